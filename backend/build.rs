@@ -0,0 +1,73 @@
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    emit_build_metadata();
+
+    // Compile the gRPC management API definitions with a vendored protoc so
+    // the build does not depend on a system-wide protobuf installation.
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/fluxdns.proto");
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc not available");
+        std::env::set_var("PROTOC", protoc);
+        tonic_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/fluxdns.proto"], &["proto"])
+            .expect("Failed to compile gRPC protos");
+    }
+}
+
+/// Embed the commit, build time and target for `crate::build_info`.
+///
+/// Builds without a git checkout (e.g. Docker) can pass the commit in
+/// `GIT_HASH`; `SOURCE_DATE_EPOCH` pins the build time for reproducible builds.
+fn emit_build_metadata() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=GIT_HASH");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let git_hash = std::env::var("GIT_HASH")
+        .ok()
+        .filter(|h| !h.is_empty())
+        .or_else(|| git(&["rev-parse", "--short=12", "HEAD"]))
+        .unwrap_or_else(|| "unknown".to_string());
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"]).is_some_and(|s| !s.is_empty());
+    let git_hash = if dirty { format!("{}-dirty", git_hash) } else { git_hash };
+
+    // Rebuild when HEAD moves to another commit
+    if let Some(git_dir) = git(&["rev-parse", "--git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        println!("cargo:rerun-if-changed={}/refs/heads", git_dir);
+    }
+
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or_default()
+        });
+
+    println!("cargo:rustc-env=FLUXDNS_GIT_HASH={}", git_hash);
+    println!("cargo:rustc-env=FLUXDNS_BUILD_TIMESTAMP={}", timestamp);
+    println!(
+        "cargo:rustc-env=FLUXDNS_BUILD_TARGET={}",
+        std::env::var("TARGET").unwrap_or_default()
+    );
+    println!(
+        "cargo:rustc-env=FLUXDNS_BUILD_PROFILE={}",
+        std::env::var("PROFILE").unwrap_or_default()
+    );
+}
+
+/// Trimmed stdout of a successful git command
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
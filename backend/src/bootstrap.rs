@@ -1,4 +1,3 @@
-use std::net::SocketAddr;
 use std::sync::Arc;
 
 use anyhow::Result;
@@ -12,19 +11,36 @@ use tokio::sync::RwLock;
 use tower_http::cors::{Any, CorsLayer};
 use tracing::info;
 
+use crate::clock::Clock;
 use crate::config::ConfigManager;
 use crate::db::Database;
-use crate::dns::{CacheConfig, CacheManager, DnsResolver, ProxyManager, RewriteEngine, UpstreamManager};
+use crate::dns::{
+    cache_backend, AdaptiveTtlSettings, CacheConfig, CacheManager, DnsResolver, DomainClassifier, ProfileRouter,
+    ProxyManager, RewriteEngine, RpzFeeds, SamplingMode, TyposquatGuard, UpstreamManager, CONFIG_KEY_ADAPTIVE_TTL,
+    CONFIG_KEY_ADAPTIVE_TTL_MAX_MULTIPLIER, CONFIG_KEY_ADAPTIVE_TTL_STABLE_REFRESHES,
+};
+use crate::dns::proxy::{
+    connection_manager, quic_transport, ConnectionLimits, ProtocolPolicy, QueryLimits, QuicTransportSettings,
+    CONFIG_KEY_UPSTREAM_PROTOCOL_RULES,
+};
 use crate::dns::server::DohDnsServer;
 use crate::log::{LogConfig, LogManager};
 use crate::state::AppState;
 use crate::services::alert_manager::AlertManager;
+use crate::services::integrity_monitor::IntegrityMonitor;
 use crate::services::listener_manager::ListenerManager;
+use crate::services::log_maintenance::LogMaintenance;
+use crate::services::reload::ReloadManager;
+use crate::services::seed::seed_first_run;
+use crate::services::update_checker::UpdateChecker;
 use crate::web::{
-    auth_middleware, cache_router, dns_query_router, fallback_handler, index_handler,
-    logs_router, records_router, rewrite_router, settings_router, static_handler, status_router,
-    strategy_router, upstreams_router, AuthService, AuthState, CacheState, DnsQueryState,
-    LogsState, RecordsState, RewriteState, SettingsState, StatusState, StrategyState, UpstreamsState,
+    access_log_middleware, auth_middleware, cache_router, categories_router, config_apply_router, dns_query_router,
+    fallback_handler, hooks_router, http_topology, index_handler, locale_middleware, logs_router,
+    not_found_handler, records_router, rewrite_router, services_router, settings_router, static_handler, status_router, strategy_router,
+    tenants_router, tokens_router, transactions_router, typosquat_router, upstreams_router, ApiAccessLog, AuthService, AuthState, CacheState,
+    CategoriesState, ConfigApplyState, DnsQueryState, HooksState, HttpServerConfig, HttpService,
+    LogsState, RecordsState, RewriteState, ServicesState, SettingsState, StatusState, StrategyState, TenantsState,
+    TokensState, TransactionsState, TyposquatState, UpstreamsState,
 };
 
 pub async fn run() -> Result<()> {
@@ -39,18 +55,41 @@ pub async fn run() -> Result<()> {
         max_size: app_config.log_max_size,
         rotation: crate::log::RotationPolicy::Daily,
         retention_days: app_config.log_retention_days,
+        format: crate::log::LogFormat::from(app_config.log_format.as_str()),
+        console_format: crate::log::LogFormat::from(app_config.log_console_format.as_str()),
+        otlp_endpoint: app_config.otlp_endpoint.clone(),
     };
     LogManager::init_with_config(log_config.clone())?;
 
-    println!("Starting FluxDNS...");
+    let build_info = crate::build_info::BuildInfo::current();
+    println!("Starting {}", build_info.summary());
+    info!("Starting {}", build_info.summary());
     info!("Configuration loaded");
 
     // Initialize database
     let db = Arc::new(Database::new(&app_config.database_url).await?);
     info!("Database initialized");
 
+    match seed_first_run(&db, &app_config.seed_profile).await {
+        Ok(Some(summary)) => info!(
+            "Applied seed profile {} ({} upstreams, {} rewrite rules)",
+            summary.profile, summary.upstreams_created, summary.rules_created
+        ),
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Failed to apply seed profile: {}", e),
+    }
+
+    // Language of API messages and alerts when not chosen per request
+    crate::i18n::load_preferred(&db).await;
+
+    // Time source for cache expiry and retention
+    let clock = Arc::new(Clock::system());
+    if app_config.debug_time_travel {
+        tracing::warn!("DEBUG_TIME_TRAVEL is enabled, the clock can be moved through /api/diagnostics/clock");
+    }
+
     // Create log manager for cleanup operations
-    let log_manager = Arc::new(LogManager::new(log_config));
+    let log_manager = Arc::new(LogManager::new(log_config).with_clock(clock.clone()));
 
     // Load cache config from database
     let cache_ttl = match db.system_config().get("cache_default_ttl").await? {
@@ -61,24 +100,67 @@ pub async fn run() -> Result<()> {
         Some(v) => v.parse().unwrap_or(10000),
         None => 10000,
     };
+    let cache_max_memory_mb = match db.system_config().get("cache_max_memory_mb").await? {
+        Some(v) => v.parse().unwrap_or(0),
+        None => 0,
+    };
 
     // Initialize DNS components
-    let cache = Arc::new(CacheManager::with_config(CacheConfig {
-        default_ttl: cache_ttl,
-        max_entries: cache_max_entries,
-    }));
-    info!("Cache manager initialized (TTL: {}s, max entries: {})", 
-          cache_ttl, cache_max_entries);
+    let cache_backend = cache_backend(&app_config.cache_backend, app_config.cache_redis_url.as_deref(), clock.clone()).await;
+    let cache = Arc::new(CacheManager::with_backend(
+        CacheConfig {
+            default_ttl: cache_ttl,
+            max_entries: cache_max_entries,
+            max_memory_mb: cache_max_memory_mb,
+        },
+        cache_backend,
+    ));
+    info!("Cache manager initialized (backend: {}, TTL: {}s, max entries: {})",
+          cache.backend_name(), cache_ttl, cache_max_entries);
+    let adaptive_defaults = AdaptiveTtlSettings::default();
+    let adaptive = AdaptiveTtlSettings {
+        enabled: db.system_config().get(CONFIG_KEY_ADAPTIVE_TTL).await?.as_deref() == Some("true"),
+        max_multiplier: match db.system_config().get(CONFIG_KEY_ADAPTIVE_TTL_MAX_MULTIPLIER).await? {
+            Some(v) => v.parse().unwrap_or(adaptive_defaults.max_multiplier),
+            None => adaptive_defaults.max_multiplier,
+        },
+        stable_refreshes: match db.system_config().get(CONFIG_KEY_ADAPTIVE_TTL_STABLE_REFRESHES).await? {
+            Some(v) => v.parse().unwrap_or(adaptive_defaults.stable_refreshes),
+            None => adaptive_defaults.stable_refreshes,
+        },
+    };
+    cache.adaptive().set_settings(adaptive);
+    if adaptive.enabled {
+        info!("Adaptive cache TTL enabled (up to {}x after {} identical refreshes)",
+              adaptive.max_multiplier, adaptive.stable_refreshes);
+    }
 
     let rewrite_engine = Arc::new(RewriteEngine::with_db(db.clone()));
     rewrite_engine.load_rules().await?;
+    rewrite_engine.load_slow_threshold().await?;
     info!("Rewrite engine initialized ({} rules loaded)", rewrite_engine.rule_count().await);
 
     let upstream_manager = Arc::new(UpstreamManager::with_db(db.clone()));
     upstream_manager.load_servers().await?;
     info!("Upstream manager initialized ({} servers loaded)", upstream_manager.server_count().await);
 
+    let connection_limits = ConnectionLimits::from_config(&app_config);
+    connection_manager().configure(connection_limits);
+    connection_manager().start_reaper();
+    info!("Upstream connections limited to {} (idle timeout: {}s)",
+          connection_limits.max_connections, connection_limits.idle_timeout.as_secs());
+
     let proxy = Arc::new(ProxyManager::new(upstream_manager.clone()));
+    let query_limits = QueryLimits::from_config(&app_config);
+    proxy.limiter().configure(query_limits);
+    info!("Upstream queries in flight limited to {} (overload action: {}, queue timeout: {}ms)",
+          query_limits.max_outstanding, query_limits.action.as_str(), query_limits.queue_timeout.as_millis());
+
+    let quic_settings = QuicTransportSettings::from_config(&app_config);
+    quic_transport().configure(quic_settings);
+    info!("QUIC transport: idle timeout {}s, keep-alive {}s, {} streams, 0-RTT {}",
+          quic_settings.idle_timeout.as_secs(), quic_settings.keep_alive_interval.as_secs(),
+          quic_settings.max_streams, if quic_settings.zero_rtt { "on" } else { "off" });
 
     // Load query strategy from database
     if let Some(strategy_str) = db.system_config().get("query_strategy").await? {
@@ -88,6 +170,19 @@ pub async fn run() -> Result<()> {
         }
     }
 
+    // Load per-domain upstream protocol rules from database
+    if let Some(stored) = db.system_config().get(CONFIG_KEY_UPSTREAM_PROTOCOL_RULES).await? {
+        match ProtocolPolicy::parse(&stored) {
+            Ok(rules) => {
+                if !rules.is_empty() {
+                    info!("Upstream protocol rules loaded ({} rules)", rules.len());
+                }
+                proxy.protocol_policy().set_rules(rules);
+            }
+            Err(e) => tracing::warn!("Ignoring upstream protocol rules: {}", e),
+        }
+    }
+
     let resolver = Arc::new(DnsResolver::with_db(
         rewrite_engine.clone(),
         cache.clone(),
@@ -96,8 +191,68 @@ pub async fn run() -> Result<()> {
     ));
     info!("DNS resolver initialized");
 
+    resolver.tenants().load().await?;
+    info!("Tenant registry initialized ({} tenants loaded)", resolver.tenants().count().await);
+
+    resolver.cookies().load().await?;
+    resolver.offline().load().await?;
+    resolver.log_sampling().load().await?;
+    let sampling = resolver.log_sampling().settings();
+    if sampling.mode != SamplingMode::All {
+        info!("Query log sampling: {} (rate 1/{})", sampling.mode.as_str(), sampling.rate);
+    }
+    resolver.shuffle().load().await?;
+    resolver.deadline().load().await?;
+    resolver.companion().load().await?;
+    resolver.private_zones().load().await?;
+    resolver.listener_modes().load().await?;
+    resolver.domain_stats().load().await?;
+    resolver.fail_policy().load().await?;
+    resolver.client_acl().load().await?;
+    let local_records = resolver.local_records().reload().await?;
+    info!("Local record index initialized ({} records loaded)", local_records);
+    if resolver.offline().is_enabled() {
+        tracing::warn!("Offline mode is enabled: upstream forwarding is disabled");
+    }
+
+    let classifier = Arc::new(DomainClassifier::new(Some(db.clone())));
+    classifier.load().await?;
+    resolver.middleware().register(classifier.clone());
+    info!("Domain classifier initialized ({} domains loaded)", classifier.domain_count());
+
+    let typosquat_guard = Arc::new(TyposquatGuard::new(Some(db.clone())));
+    typosquat_guard.load().await?;
+    resolver.middleware().register(typosquat_guard.clone());
+
+    let profile_router = Arc::new(ProfileRouter::new(
+        Some(db.clone()),
+        upstream_manager.clone(),
+        cache.clone(),
+    ));
+    profile_router.load().await?;
+    resolver.middleware().register(profile_router.clone());
+    if let Some(profile) = profile_router.active() {
+        info!("Resolution profile active: {}", profile.name);
+    }
+
+    #[cfg(feature = "scripting")]
+    let script_policy = {
+        let policy = Arc::new(crate::dns::ScriptPolicy::new(Some(db.clone())));
+        if let Err(e) = policy.load().await {
+            tracing::warn!("Failed to load policy script, continuing without it: {}", e);
+        }
+        resolver.middleware().register(policy.clone());
+        policy
+    };
+
     // Initialize ListenerManager
-    let listener_manager = Arc::new(ListenerManager::new(db.clone(), resolver.clone()));
+    let http_config = HttpServerConfig::from_config(&app_config);
+    let http_endpoints = Arc::new(http_topology(&app_config)?);
+    let listener_manager = Arc::new(ListenerManager::new(
+        db.clone(),
+        resolver.clone(),
+        http_config.clone(),
+    ));
 
 
     // Perform initial log cleanup
@@ -118,41 +273,108 @@ pub async fn run() -> Result<()> {
     // Start DNS servers based on database configuration
     let mut handles = Vec::new();
 
+    // Start resolution profile auto-switch task
+    let switch_router = profile_router.clone();
+    handles.push(tokio::spawn(async move {
+        loop {
+            if let Err(e) = switch_router.auto_switch().await {
+                tracing::warn!("Automatic profile switch failed: {}", e);
+            }
+            let interval = switch_router.settings().probe_interval_secs;
+            tokio::time::sleep(tokio::time::Duration::from_secs(interval)).await;
+        }
+    }));
+
     // Start auto cleanup task for query logs
-    let cleanup_db = db.clone();
+    handles.push(Arc::new(LogMaintenance::new(db.clone(), clock.clone())).start());
+
+    // Persist shadow rewrite rule counters
+    let shadow_engine = rewrite_engine.clone();
+    handles.push(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            if let Err(e) = shadow_engine.flush_shadow_hits().await {
+                tracing::warn!("Failed to record shadow rule hits: {}", e);
+            }
+        }
+    }));
+
+    // Snapshot the top-domains leaderboard
+    let domain_stats = resolver.domain_stats().clone();
+    handles.push(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(crate::dns::DOMAIN_STATS_SNAPSHOT_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = domain_stats.snapshot().await {
+                tracing::warn!("Failed to snapshot domain statistics: {}", e);
+            }
+        }
+    }));
+
+    // Probe upstream capabilities (EDNS, cookies, TCP, DNSSEC) when missing or stale
+    let probe_manager = upstream_manager.clone();
     handles.push(tokio::spawn(async move {
-        // Run cleanup check every hour
         let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(3600));
         loop {
             interval.tick().await;
-            
-            // Check if auto cleanup is enabled
-            let config = cleanup_db.system_config();
-            let enabled = match config.get("log_auto_cleanup_enabled").await {
-                Ok(Some(v)) => v == "true",
-                _ => false,
-            };
-            
-            if !enabled {
-                continue;
+            match probe_manager.probe_due().await {
+                Ok(0) => {}
+                Ok(n) => tracing::info!("Probed capabilities of {} upstream server(s)", n),
+                Err(e) => tracing::warn!("Upstream capability probe failed: {}", e),
             }
-            
-            // Get retention days
-            let retention_days = match config.get("log_retention_days").await {
-                Ok(Some(v)) => v.parse::<i64>().unwrap_or(30),
-                _ => 30,
-            };
-            
-            // Perform cleanup
-            match cleanup_db.query_logs().delete_old(retention_days).await {
-                Ok(deleted) => {
-                    if deleted > 0 {
-                        info!("Auto cleanup: deleted {} query logs older than {} days", deleted, retention_days);
-                    }
-                }
-                Err(e) => {
-                    tracing::warn!("Auto cleanup failed: {}", e);
-                }
+        }
+    }));
+
+    // Seed upstream latency stats at startup and whenever servers without
+    // them are loaded, before the fastest strategy relies on them
+    let warm_up_manager = upstream_manager.clone();
+    handles.push(tokio::spawn(async move {
+        loop {
+            let status = warm_up_manager.warm_up().await;
+            if status.probed > 0 {
+                tracing::info!(
+                    "Upstream warm-up: {} of {} server(s) answered",
+                    status.healthy,
+                    status.probed
+                );
+            }
+            warm_up_manager.warm_up_requested().await;
+        }
+    }));
+
+    // Health-check drained upstreams, which get no production queries
+    let drain_manager = upstream_manager.clone();
+    handles.push(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(drain_manager.health_check_interval());
+        loop {
+            interval.tick().await;
+            drain_manager.check_drained().await;
+        }
+    }));
+
+    // Start category list refresh task
+    let refresh_classifier = classifier.clone();
+    handles.push(tokio::spawn(async move {
+        // Check for stale downloaded lists every hour
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            if let Err(e) = refresh_classifier.refresh_due().await {
+                tracing::warn!("Category list refresh failed: {}", e);
+            }
+        }
+    }));
+
+    // Start RPZ feed refresh task
+    let rpz_feeds = Arc::new(RpzFeeds::new(db.clone(), rewrite_engine.clone(), cache.clone()));
+    let refresh_rpz_feeds = rpz_feeds.clone();
+    handles.push(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(crate::dns::RPZ_FEED_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = refresh_rpz_feeds.refresh_due().await {
+                tracing::warn!("RPZ feed refresh failed: {}", e);
             }
         }
     }));
@@ -167,21 +389,35 @@ pub async fn run() -> Result<()> {
     let auth_service = AuthService::new(config.clone());
     let auth_state = AuthState {
         auth_service: auth_service.clone(),
+        db: db.clone(),
     };
 
     // Create sub-routers (these have their own state types)
-    let records_routes = records_router(RecordsState { db: db.clone() });
+    let records_routes = records_router(RecordsState {
+        db: db.clone(),
+        local_records: resolver.local_records().clone(),
+        cache: cache.clone(),
+    });
+    let services_routes = services_router(ServicesState {
+        db: db.clone(),
+        local_records: resolver.local_records().clone(),
+        cache: cache.clone(),
+    });
     let rewrite_routes = rewrite_router(RewriteState {
         db: db.clone(),
         rewrite_engine: rewrite_engine.clone(),
+        cache: cache.clone(),
     });
     let upstreams_routes = upstreams_router(UpstreamsState {
         db: db.clone(),
         upstream_manager: upstream_manager.clone(),
+        proxy_manager: proxy.clone(),
     });
     let cache_routes = cache_router(CacheState {
         cache: cache.clone(),
         db: db.clone(),
+        resolver: resolver.clone(),
+        trust_proxy_headers: app_config.web_trust_proxy_headers,
     });
     let dns_query_routes = dns_query_router(DnsQueryState {
         resolver: resolver.clone(),
@@ -191,21 +427,159 @@ pub async fn run() -> Result<()> {
         proxy_manager: proxy.clone(),
     });
     let logs_routes = logs_router(LogsState { db: db.clone() });
-    let status_routes = status_router(StatusState {
+
+    // Start release update checker (idle until enabled in settings)
+    let update_checker = Arc::new(UpdateChecker::new(db.clone(), resolver.clone()));
+    update_checker.clone().start().await;
+
+    // Recent management API requests, recorded by the access log middleware
+    let api_log = Arc::new(ApiAccessLog::new().with_proxy_headers(app_config.web_trust_proxy_headers));
+
+    let status_state = StatusState {
         db: db.clone(),
         cache: cache.clone(),
         proxy_manager: proxy.clone(),
         upstream_manager: upstream_manager.clone(),
         start_time: Arc::new(RwLock::new(std::time::Instant::now())),
-    });
+        cookies: resolver.cookies().clone(),
+        update_checker: update_checker.clone(),
+        listener_manager: listener_manager.clone(),
+        http_endpoints: http_endpoints.clone(),
+        policy_stats: resolver.policy_stats().clone(),
+        deadline: resolver.deadline().clone(),
+        companion: resolver.companion().clone(),
+        fail_policy: resolver.fail_policy().clone(),
+        client_acl: resolver.client_acl().clone(),
+        rewrite_engine: rewrite_engine.clone(),
+        api_log: api_log.clone(),
+    };
+    let status_routes = status_router(status_state.clone());
+    let readiness_routes = crate::web::readiness_router(status_state);
     let listeners_routes = crate::web::listeners_router(crate::web::ListenersState {
         db: db.clone(),
         listener_manager: listener_manager.clone(),
+        profiles: profile_router.clone(),
+        resolution_modes: resolver.listener_modes().clone(),
     });
     let settings_routes = settings_router(SettingsState {
         db: db.clone(),
+        upstream_manager: upstream_manager.clone(),
+        offline: resolver.offline().clone(),
+        cookies: resolver.cookies().clone(),
+        log_sampling: resolver.log_sampling().clone(),
+        shuffle: resolver.shuffle().clone(),
+        deadline: resolver.deadline().clone(),
+        companion: resolver.companion().clone(),
+        private_zones: resolver.private_zones().clone(),
+        fail_policy: resolver.fail_policy().clone(),
+        client_acl: resolver.client_acl().clone(),
+        update_checker,
+        rewrite_engine: rewrite_engine.clone(),
+    });
+    let tenants_routes = tenants_router(TenantsState {
+        db: db.clone(),
+        tenants: resolver.tenants().clone(),
+        local_records: resolver.local_records().clone(),
+        rewrite_engine: rewrite_engine.clone(),
+        cache: cache.clone(),
+    });
+    let tokens_routes = tokens_router(TokensState { db: db.clone() });
+    let delegates_routes = crate::web::delegates_router(crate::web::DelegatesState {
+        db: db.clone(),
+        config: config.clone(),
+    });
+    let config_routes = config_apply_router(ConfigApplyState {
+        db: db.clone(),
+        rewrite_engine: rewrite_engine.clone(),
+        upstream_manager: upstream_manager.clone(),
+        listener_manager: listener_manager.clone(),
+        local_records: resolver.local_records().clone(),
+        cache: cache.clone(),
+    });
+    let notifications_routes = crate::web::notifications_router(crate::web::NotificationsState { db: db.clone() });
+    let backup_routes = crate::web::backup_router(crate::web::BackupState {
+        db: db.clone(),
+        rewrite_engine: rewrite_engine.clone(),
+        upstream_manager: upstream_manager.clone(),
+        local_records: resolver.local_records().clone(),
+        cache: cache.clone(),
+    });
+    let transactions_routes = transactions_router(TransactionsState {
+        db: db.clone(),
+        rewrite_engine: rewrite_engine.clone(),
+        upstream_manager: upstream_manager.clone(),
+        proxy_manager: proxy.clone(),
+        local_records: resolver.local_records().clone(),
+        cache: cache.clone(),
+    });
+    let hooks_routes = hooks_router(HooksState {
+        db: db.clone(),
+        cache: cache.clone(),
+        trust_proxy_headers: app_config.web_trust_proxy_headers,
+    });
+    let public_routes = crate::web::public_router(crate::web::PublicState {
+        db: db.clone(),
+        cache: cache.clone(),
+        policy_stats: resolver.policy_stats().clone(),
+        limiter: Default::default(),
+    });
+    let categories_routes = categories_router(CategoriesState {
+        db: db.clone(),
+        classifier: classifier.clone(),
+    });
+    let rpz_routes = crate::web::rpz_router(crate::web::RpzState {
+        db: db.clone(),
+        feeds: rpz_feeds.clone(),
+    });
+    let typosquat_routes = typosquat_router(TyposquatState {
+        db: db.clone(),
+        guard: typosquat_guard.clone(),
+    });
+    let profiles_routes = crate::web::profiles_router(crate::web::ProfilesState {
+        db: db.clone(),
+        router: profile_router.clone(),
+    });
+    let setup_routes = crate::web::setup_router(crate::web::SetupState {
+        db: db.clone(),
+        upstream_manager: upstream_manager.clone(),
+        rewrite_engine: rewrite_engine.clone(),
+        proxy_manager: proxy.clone(),
+        offline: resolver.offline().clone(),
+    });
+    let diagnostics_routes = crate::web::diagnostics_router(crate::web::DiagnosticsState {
+        capture: resolver.capture().clone(),
+        clock: clock.clone(),
+        time_travel: app_config.debug_time_travel,
+        upstream_manager: upstream_manager.clone(),
+        proxy_manager: proxy.clone(),
+    });
+    let analytics_routes = crate::web::analytics_router(crate::web::AnalyticsState {
+        domain_stats: resolver.domain_stats().clone(),
     });
     let doh_routes = doh_server.router();
+
+    // Start gRPC management API if configured
+    if app_config.grpc_port != 0 {
+        #[cfg(feature = "grpc")]
+        {
+            let grpc_state = crate::grpc::GrpcState {
+                db: db.clone(),
+                cache: cache.clone(),
+                resolver: resolver.clone(),
+                rewrite_engine: rewrite_engine.clone(),
+                upstream_manager: upstream_manager.clone(),
+            };
+            let grpc_config = app_config.clone();
+            let grpc_auth = auth_service.clone();
+            handles.push(tokio::spawn(async move {
+                if let Err(e) = crate::grpc::serve(grpc_state, &grpc_config, grpc_auth).await {
+                    tracing::error!("gRPC server error: {}", e);
+                }
+            }));
+        }
+        #[cfg(not(feature = "grpc"))]
+        tracing::warn!("grpc_port is set but FluxDNS was built without the `grpc` feature");
+    }
     
 
     
@@ -229,20 +603,59 @@ pub async fn run() -> Result<()> {
     let alert_manager = Arc::new(AlertManager::new(app_state.clone()));
     alert_manager.start().await;
 
+    // Reload configuration on SIGHUP
+    let reload_manager = Arc::new(ReloadManager::new(app_state.clone()));
+    reload_manager.start().await;
+
+    // Start upstream integrity monitor
+    let integrity_monitor = Arc::new(IntegrityMonitor::new(app_state.clone()));
+    integrity_monitor.clone().start().await;
+    let integrity_routes = crate::web::integrity_router(crate::web::IntegrityState {
+        db: db.clone(),
+        monitor: integrity_monitor,
+    });
+
     // Create protected API router (requires authentication)
     let protected_api = Router::new()
         .nest("/api/records", records_routes)
+        .nest("/api/services", services_routes)
         .nest("/api/rewrite", rewrite_routes)
         .nest("/api/upstreams", upstreams_routes)
         .nest("/api/cache", cache_routes)
         .nest("/api/dns", dns_query_routes)
         .nest("/api/strategy", strategy_routes)
         .nest("/api/logs", logs_routes)
+        .nest("/api/analytics", analytics_routes)
         .nest("/api/status", status_routes)
         .nest("/api/listeners", listeners_routes)
         .nest("/api/settings", settings_routes)
         .nest("/api/llm", llm_routes)
-        .layer(middleware::from_fn_with_state(auth_state.clone(), auth_middleware));
+        .nest("/api/tenants", tenants_routes)
+        .nest("/api/tokens", tokens_routes)
+        .nest("/api/delegates", delegates_routes)
+        .nest("/api/config", config_routes)
+        .nest("/api/transactions", transactions_routes)
+        .nest("/api/backup", backup_routes)
+        .nest("/api/notifications", notifications_routes)
+        .nest("/api/categories", categories_routes)
+        .nest("/api/rpz", rpz_routes)
+        .nest("/api/typosquat", typosquat_routes)
+        .nest("/api/profiles", profiles_routes)
+        .nest("/api/integrity", integrity_routes)
+        .nest("/api/setup", setup_routes)
+        .nest("/api/diagnostics", diagnostics_routes);
+
+    #[cfg(feature = "scripting")]
+    let protected_api = protected_api.nest(
+        "/api/scripting",
+        crate::web::scripting_router(crate::web::ScriptingState {
+            db: db.clone(),
+            policy: script_policy,
+        }),
+    );
+
+    let protected_api =
+        protected_api.layer(middleware::from_fn_with_state(auth_state.clone(), auth_middleware));
 
 
     // Create login router with AuthState
@@ -253,41 +666,57 @@ pub async fn run() -> Result<()> {
     // Combine all API routes
     let api_router = Router::new()
         .merge(login_router)
+        .merge(readiness_routes)  // Readiness probe doesn't require authentication
         .merge(protected_api)
-        .merge(doh_routes);  // DoH routes don't require authentication
+        .nest("/api/hooks", hooks_routes)  // Authenticated with purge tokens
+        .nest("/api/public", public_routes)  // Unauthenticated, off unless enabled in settings
+        .layer(middleware::from_fn(locale_middleware))
+        .layer(middleware::from_fn_with_state(api_log, access_log_middleware));
+
+    // Static files for the web UI
+    let ui_router = Router::new()
+        .route("/", get(index_handler))
+        .route("/*path", get(static_handler));
 
-    // Build main router with static files
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
         .allow_headers(Any);
 
-    let app = Router::new()
-        .route("/", get(index_handler))
-        .merge(api_router)
-        .route("/*path", get(static_handler))
-        .fallback(fallback_handler)
-        .layer(cors);
-
-    // Start web server
-    let web_addr: SocketAddr = format!("0.0.0.0:{}", app_config.web_port).parse()?;
-    info!("Web server listening on http://{}", web_addr);
-    info!("DoH endpoint available at http://{}/dns-query", web_addr);
-
-    let listener = tokio::net::TcpListener::bind(web_addr).await?;
-    
-    // Spawn web server with ConnectInfo for client IP extraction
-    handles.push(tokio::spawn(async move {
-        if let Err(e) = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await {
-            tracing::error!("Web server error: {}", e);
+    // Start one web server per address; the UI, API and DoH share the web
+    // port unless given their own
+    for endpoint in http_endpoints.iter() {
+        let mut app = Router::new();
+        if endpoint.serves(HttpService::Api) {
+            app = app.merge(api_router.clone());
         }
-    }));
+        if endpoint.serves(HttpService::Doh) {
+            app = app.merge(doh_routes.clone());  // DoH routes don't require authentication
+        }
+        let app = if endpoint.serves(HttpService::Ui) {
+            app.merge(ui_router.clone()).fallback(fallback_handler)
+        } else {
+            app.fallback(not_found_handler)
+        };
+        let app = app.layer(cors.clone());
+
+        let listener = tokio::net::TcpListener::bind(endpoint.address).await?;
+        info!("Web server listening on http://{} ({})", endpoint.address, endpoint.describe());
+
+        // Spawn web server; it injects ConnectInfo for client IP extraction
+        handles.push(tokio::spawn(crate::web::serve(listener, app, http_config.clone())));
+    }
 
     println!("FluxDNS started successfully");
-    println!("  - Web UI: http://0.0.0.0:{}", app_config.web_port);
-    println!("  - DoH: http://0.0.0.0:{}/dns-query", app_config.web_port);
-    
-
+    for endpoint in http_endpoints.iter() {
+        for service in &endpoint.services {
+            match service {
+                HttpService::Ui => println!("  - Web UI: http://{}", endpoint.address),
+                HttpService::Api => println!("  - API: http://{}/api", endpoint.address),
+                HttpService::Doh => println!("  - DoH: http://{}/dns-query", endpoint.address),
+            }
+        }
+    }
 
     // Wait for shutdown signal
     shutdown_signal().await;
@@ -300,6 +729,7 @@ pub async fn run() -> Result<()> {
     }
 
     info!("FluxDNS stopped");
+    LogManager::shutdown();
     Ok(())
 }
 
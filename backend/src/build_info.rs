@@ -0,0 +1,118 @@
+//! Build Metadata
+//!
+//! Version, commit and build time embedded by `build.rs`, together with the
+//! compiled-in cargo features and the protocols FluxDNS can listen on and
+//! forward to. Reported by `/api/status/version` and logged at startup so
+//! bug reports can state exactly which build is running.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Crate version from Cargo.toml
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Short commit hash, `-dirty` when built from a modified tree
+pub const GIT_HASH: &str = env!("FLUXDNS_GIT_HASH");
+const BUILD_TIMESTAMP: &str = env!("FLUXDNS_BUILD_TIMESTAMP");
+const BUILD_TARGET: &str = env!("FLUXDNS_BUILD_TARGET");
+const BUILD_PROFILE: &str = env!("FLUXDNS_BUILD_PROFILE");
+
+/// Optional cargo features and whether this build includes them
+const FEATURES: &[(&str, bool)] = &[
+    ("grpc", cfg!(feature = "grpc")),
+    ("scripting", cfg!(feature = "scripting")),
+];
+
+/// Support for one DNS transport
+#[derive(Debug, Clone, Serialize)]
+pub struct ProtocolSupport {
+    pub protocol: &'static str,
+    /// Can be served by a listener
+    pub listener: bool,
+    /// Can be used for upstream servers
+    pub upstream: bool,
+}
+
+/// Listener and upstream support per protocol
+const PROTOCOLS: &[ProtocolSupport] = &[
+    ProtocolSupport { protocol: "udp", listener: true, upstream: true },
+    ProtocolSupport { protocol: "tcp", listener: false, upstream: true },
+    ProtocolSupport { protocol: "dot", listener: true, upstream: true },
+    ProtocolSupport { protocol: "doh", listener: true, upstream: true },
+    ProtocolSupport { protocol: "doq", listener: true, upstream: true },
+    ProtocolSupport { protocol: "doh3", listener: false, upstream: true },
+];
+
+/// Metadata describing the running build
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_hash: &'static str,
+    /// None when the build time was not recorded
+    pub build_date: Option<DateTime<Utc>>,
+    /// Rust target triple
+    pub target: &'static str,
+    /// Cargo profile, `release` or `debug`
+    pub profile: &'static str,
+    /// Enabled optional features
+    pub features: Vec<&'static str>,
+    pub protocols: Vec<ProtocolSupport>,
+}
+
+impl BuildInfo {
+    /// Metadata of the running binary
+    pub fn current() -> Self {
+        Self {
+            version: VERSION,
+            git_hash: GIT_HASH,
+            build_date: BUILD_TIMESTAMP
+                .parse::<i64>()
+                .ok()
+                .filter(|&ts| ts > 0)
+                .and_then(|ts| DateTime::from_timestamp(ts, 0)),
+            target: BUILD_TARGET,
+            profile: BUILD_PROFILE,
+            features: FEATURES
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| *name)
+                .collect(),
+            protocols: PROTOCOLS.to_vec(),
+        }
+    }
+
+    /// One-line summary for the startup banner
+    pub fn summary(&self) -> String {
+        let build_date = self
+            .build_date
+            .map(|d| d.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        let features = if self.features.is_empty() {
+            "none".to_string()
+        } else {
+            self.features.join(", ")
+        };
+        format!(
+            "FluxDNS {} ({}, built {}, {} {}, features: {})",
+            self.version, self.git_hash, build_date, self.target, self.profile, features
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current_build_info() {
+        let info = BuildInfo::current();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(!info.git_hash.is_empty());
+        assert!(info.build_date.is_some());
+        assert_eq!(info.features.contains(&"grpc"), cfg!(feature = "grpc"));
+        assert!(info.protocols.iter().any(|p| p.protocol == "doh" && p.listener));
+
+        let summary = info.summary();
+        assert!(summary.starts_with(&format!("FluxDNS {} (", VERSION)));
+        assert!(summary.contains(info.git_hash));
+    }
+}
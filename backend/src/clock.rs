@@ -0,0 +1,120 @@
+//! Clock
+//!
+//! Cache expiry, the query log maintenance task and log file cleanup read
+//! the time from a shared [`Clock`] instead of `Instant::now()` and
+//! `SystemTime::now()`. The system clock follows real time plus an offset
+//! that can only be moved through [`Clock::advance`]; a manual clock stands
+//! still apart from that, so tests can step over TTLs and retention periods
+//! without sleeping.
+//!
+//! With `DEBUG_TIME_TRAVEL=true` the offset can also be moved through
+//! `/api/diagnostics/clock`, to watch entries expire on a running instance.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, Utc};
+
+/// Source of the current time
+#[derive(Debug)]
+pub struct Clock {
+    /// Start time of a manual clock, which only moves when advanced
+    frozen: Option<(Instant, SystemTime)>,
+    /// Time travelled so far, in milliseconds
+    offset_ms: AtomicU64,
+}
+
+#[allow(dead_code)]
+impl Clock {
+    /// Real time
+    pub fn system() -> Self {
+        Self {
+            frozen: None,
+            offset_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Time standing still at the moment of creation
+    pub fn manual() -> Self {
+        Self {
+            frozen: Some((Instant::now(), SystemTime::now())),
+            offset_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether time only moves through [`advance`](Self::advance)
+    pub fn is_manual(&self) -> bool {
+        self.frozen.is_some()
+    }
+
+    /// Monotonic time, for expiry and durations
+    pub fn now(&self) -> Instant {
+        let base = self.frozen.map_or_else(Instant::now, |(instant, _)| instant);
+        base + self.offset()
+    }
+
+    /// Wall-clock time, for file ages and retention
+    pub fn system_now(&self) -> SystemTime {
+        let base = self.frozen.map_or_else(SystemTime::now, |(_, system)| system);
+        base + self.offset()
+    }
+
+    /// Wall-clock time as UTC
+    pub fn utc_now(&self) -> DateTime<Utc> {
+        self.system_now().into()
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, by: Duration) {
+        let ms = u64::try_from(by.as_millis()).unwrap_or(u64::MAX);
+        let _ = self.offset_ms.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |offset| {
+            Some(offset.saturating_add(ms))
+        });
+    }
+
+    /// Time travelled so far
+    pub fn offset(&self) -> Duration {
+        Duration::from_millis(self.offset_ms.load(Ordering::Relaxed))
+    }
+
+    /// Return to the time the clock would show without travelling
+    pub fn reset(&self) {
+        self.offset_ms.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::system()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = Clock::manual();
+        let start = clock.now();
+        let start_utc = clock.utc_now();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(clock.now(), start);
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(clock.now() - start, Duration::from_secs(90));
+        assert_eq!(clock.utc_now() - start_utc, chrono::Duration::seconds(90));
+
+        clock.reset();
+        assert_eq!(clock.now(), start);
+    }
+
+    #[test]
+    fn test_system_clock_offset() {
+        let clock = Clock::system();
+        assert!(!clock.is_manual());
+        clock.advance(Duration::from_secs(3600));
+        assert!(clock.now() >= Instant::now() + Duration::from_secs(3599));
+        assert!(clock.utc_now() > Utc::now() + chrono::Duration::minutes(59));
+    }
+}
@@ -17,6 +17,7 @@
 //! - Cache settings
 //! - Query strategy
 
+use std::net::{IpAddr, Ipv4Addr};
 use std::path::{Path, PathBuf};
 use std::sync::RwLock;
 
@@ -26,8 +27,16 @@ use serde::{Deserialize, Serialize};
 /// Application configuration
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct AppConfig {
-    // Web service port
+    // Web service port and bind address
     pub web_port: u16,
+    pub web_bind_address: IpAddr,
+
+    // Separate ports for the REST API and DoH (0 = served on the web port);
+    // bind addresses default to the web bind address
+    pub api_port: u16,
+    pub api_bind_address: Option<IpAddr>,
+    pub doh_port: u16,
+    pub doh_bind_address: Option<IpAddr>,
 
     // Database configuration
     pub database_url: String,
@@ -41,12 +50,66 @@ pub struct AppConfig {
     pub log_level: String,
     pub log_max_size: u64,
     pub log_retention_days: u32,
+    /// Log file format: text or json
+    pub log_format: String,
+    /// Console log format: text or json
+    pub log_console_format: String,
+    /// OTLP collector for resolution spans (requires the `otel` feature)
+    pub otlp_endpoint: Option<String>,
+
+    // gRPC management API (requires the `grpc` feature, 0 = disabled)
+    pub grpc_port: u16,
+    pub grpc_token: Option<String>,
+    pub grpc_tls_cert: Option<PathBuf>,
+    pub grpc_tls_key: Option<PathBuf>,
+
+    // Web server limits (also applied to the HTTPS DoH listener)
+    pub web_http2: bool,
+    pub web_max_body_size: usize,
+    pub web_max_concurrent_streams: u32,
+    pub web_header_timeout_secs: u64,
+    pub web_request_timeout_secs: u64,
+    /// Take API client addresses from X-Forwarded-For / X-Real-IP
+    /// (only behind a reverse proxy that sets them)
+    pub web_trust_proxy_headers: bool,
+
+    // Pooled upstream connections (DoT, DoQ, DoH3)
+    pub upstream_idle_timeout_secs: u64,
+    pub upstream_max_connections: usize,
+
+    // Upstream queries in flight (0 = unlimited) and overload behaviour
+    pub upstream_max_outstanding: usize,
+    pub upstream_overload_action: String,
+    pub upstream_queue_timeout_ms: u64,
+
+    // QUIC transport of DoQ/DoH3 upstreams and DoQ listeners
+    // (0 disables the idle timeout or keep-alives)
+    pub quic_idle_timeout_secs: u64,
+    pub quic_keep_alive_secs: u64,
+    pub quic_max_streams: u32,
+    pub quic_zero_rtt: bool,
+    pub quic_migration: bool,
+
+    // DNS cache storage: memory or redis (requires the `redis-cache` feature)
+    pub cache_backend: String,
+    pub cache_redis_url: Option<String>,
+
+    // Seed profile applied on the first start: global, china, family or local
+    pub seed_profile: String,
+
+    // Allow moving the clock through /api/diagnostics/clock (testing only)
+    pub debug_time_travel: bool,
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
             web_port: 8080,
+            web_bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            api_port: 0,
+            api_bind_address: None,
+            doh_port: 0,
+            doh_bind_address: None,
             database_url: "sqlite:fluxdns.db?mode=rwc".to_string(),
             admin_username: "admin".to_string(),
             admin_password: "admin".to_string(),
@@ -54,6 +117,33 @@ impl Default for AppConfig {
             log_level: "warn".to_string(),
             log_max_size: 10 * 1024 * 1024, // 10MB
             log_retention_days: 30,
+            log_format: "text".to_string(),
+            log_console_format: "text".to_string(),
+            otlp_endpoint: None,
+            grpc_port: 0,
+            grpc_token: None,
+            grpc_tls_cert: None,
+            grpc_tls_key: None,
+            web_http2: true,
+            web_max_body_size: 2 * 1024 * 1024, // 2MB
+            web_max_concurrent_streams: 100,
+            web_header_timeout_secs: 10,
+            web_request_timeout_secs: 120,
+            web_trust_proxy_headers: false,
+            upstream_idle_timeout_secs: 300,
+            upstream_max_connections: 256,
+            upstream_max_outstanding: 1024,
+            upstream_overload_action: "queue".to_string(),
+            upstream_queue_timeout_ms: 100,
+            quic_idle_timeout_secs: 20,
+            quic_keep_alive_secs: 5,
+            quic_max_streams: 100,
+            quic_zero_rtt: false,
+            quic_migration: true,
+            cache_backend: "memory".to_string(),
+            cache_redis_url: None,
+            seed_profile: "china".to_string(),
+            debug_time_travel: false,
         }
     }
 }
@@ -62,6 +152,11 @@ impl Default for AppConfig {
 #[derive(Debug, Default, Clone, Deserialize)]
 pub struct PartialConfig {
     pub web_port: Option<u16>,
+    pub web_bind_address: Option<IpAddr>,
+    pub api_port: Option<u16>,
+    pub api_bind_address: Option<IpAddr>,
+    pub doh_port: Option<u16>,
+    pub doh_bind_address: Option<IpAddr>,
     pub database_url: Option<String>,
     pub admin_username: Option<String>,
     pub admin_password: Option<String>,
@@ -69,11 +164,40 @@ pub struct PartialConfig {
     pub log_level: Option<String>,
     pub log_max_size: Option<u64>,
     pub log_retention_days: Option<u32>,
+    pub log_format: Option<String>,
+    pub log_console_format: Option<String>,
+    pub otlp_endpoint: Option<String>,
+    pub grpc_port: Option<u16>,
+    pub grpc_token: Option<String>,
+    pub grpc_tls_cert: Option<PathBuf>,
+    pub grpc_tls_key: Option<PathBuf>,
+    pub web_http2: Option<bool>,
+    pub web_max_body_size: Option<usize>,
+    pub web_max_concurrent_streams: Option<u32>,
+    pub web_header_timeout_secs: Option<u64>,
+    pub web_request_timeout_secs: Option<u64>,
+    pub web_trust_proxy_headers: Option<bool>,
+    pub upstream_idle_timeout_secs: Option<u64>,
+    pub upstream_max_connections: Option<usize>,
+    pub upstream_max_outstanding: Option<usize>,
+    pub upstream_overload_action: Option<String>,
+    pub upstream_queue_timeout_ms: Option<u64>,
+    pub quic_idle_timeout_secs: Option<u64>,
+    pub quic_keep_alive_secs: Option<u64>,
+    pub quic_max_streams: Option<u32>,
+    pub quic_zero_rtt: Option<bool>,
+    pub quic_migration: Option<bool>,
+    pub cache_backend: Option<String>,
+    pub cache_redis_url: Option<String>,
+    pub seed_profile: Option<String>,
+    pub debug_time_travel: Option<bool>,
 }
 
 /// Configuration manager responsible for loading and providing access to configuration
 pub struct ConfigManager {
     config: RwLock<AppConfig>,
+    /// Config file to re-read on reload, None when built from explicit configs
+    path: Option<PathBuf>,
 }
 
 impl ConfigManager {
@@ -87,11 +211,19 @@ impl ConfigManager {
         // Load .env file if present
         let _ = dotenvy::dotenv();
 
+        Ok(Self {
+            config: RwLock::new(Self::read_config(config_path.as_ref())),
+            path: Some(config_path.as_ref().to_path_buf()),
+        })
+    }
+
+    /// Build the configuration from defaults, config file and environment
+    fn read_config(config_path: &Path) -> AppConfig {
         // Start with defaults
         let mut config = AppConfig::default();
 
         // Load from config file if exists (lower priority)
-        if let Ok(file_config) = Self::load_from_file(config_path.as_ref()) {
+        if let Ok(file_config) = Self::load_from_file(config_path) {
             Self::merge_config(&mut config, file_config);
         }
 
@@ -99,9 +231,27 @@ impl ConfigManager {
         let env_config = Self::load_from_env();
         Self::merge_config(&mut config, env_config);
 
-        Ok(Self {
-            config: RwLock::new(config),
-        })
+        config
+    }
+
+    /// Re-read the config file and environment, returning the names of
+    /// changed settings
+    ///
+    /// Environment variables already set are not overridden by `.env`.
+    pub fn reload(&self) -> Result<Vec<String>> {
+        let Some(path) = &self.path else {
+            return Ok(Vec::new());
+        };
+        // A config file that exists but no longer parses keeps the current settings
+        if path.exists() {
+            Self::load_from_file(path)?;
+        }
+
+        let new_config = Self::read_config(path);
+        let mut config = self.config.write().unwrap();
+        let changed = changed_fields(&config, &new_config);
+        *config = new_config;
+        Ok(changed)
     }
 
     /// Create ConfigManager from explicit configs for testing
@@ -122,6 +272,7 @@ impl ConfigManager {
 
         Self {
             config: RwLock::new(config),
+            path: None,
         }
     }
 
@@ -136,6 +287,21 @@ impl ConfigManager {
             web_port: std::env::var("WEB_PORT")
                 .ok()
                 .and_then(|v| v.parse().ok()),
+            web_bind_address: std::env::var("WEB_BIND_ADDRESS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            api_port: std::env::var("API_PORT")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            api_bind_address: std::env::var("API_BIND_ADDRESS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            doh_port: std::env::var("DOH_PORT")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            doh_bind_address: std::env::var("DOH_BIND_ADDRESS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
             database_url: std::env::var("DATABASE_URL").ok(),
             admin_username: std::env::var("ADMIN_USERNAME").ok(),
             admin_password: std::env::var("ADMIN_PASSWORD").ok(),
@@ -147,6 +313,67 @@ impl ConfigManager {
             log_retention_days: std::env::var("LOG_RETENTION_DAYS")
                 .ok()
                 .and_then(|v| v.parse().ok()),
+            log_format: std::env::var("LOG_FORMAT").ok(),
+            log_console_format: std::env::var("LOG_CONSOLE_FORMAT").ok(),
+            otlp_endpoint: std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok(),
+            grpc_port: std::env::var("GRPC_PORT")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            grpc_token: std::env::var("GRPC_TOKEN").ok(),
+            grpc_tls_cert: std::env::var("GRPC_TLS_CERT").ok().map(PathBuf::from),
+            grpc_tls_key: std::env::var("GRPC_TLS_KEY").ok().map(PathBuf::from),
+            web_http2: std::env::var("WEB_HTTP2")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            web_max_body_size: std::env::var("WEB_MAX_BODY_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            web_max_concurrent_streams: std::env::var("WEB_MAX_CONCURRENT_STREAMS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            web_header_timeout_secs: std::env::var("WEB_HEADER_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            web_request_timeout_secs: std::env::var("WEB_REQUEST_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            web_trust_proxy_headers: std::env::var("WEB_TRUST_PROXY_HEADERS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            upstream_idle_timeout_secs: std::env::var("UPSTREAM_IDLE_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            upstream_max_connections: std::env::var("UPSTREAM_MAX_CONNECTIONS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            upstream_max_outstanding: std::env::var("UPSTREAM_MAX_OUTSTANDING")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            upstream_overload_action: std::env::var("UPSTREAM_OVERLOAD_ACTION").ok(),
+            upstream_queue_timeout_ms: std::env::var("UPSTREAM_QUEUE_TIMEOUT_MS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            quic_idle_timeout_secs: std::env::var("QUIC_IDLE_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            quic_keep_alive_secs: std::env::var("QUIC_KEEP_ALIVE_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            quic_max_streams: std::env::var("QUIC_MAX_STREAMS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            quic_zero_rtt: std::env::var("QUIC_ZERO_RTT")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            quic_migration: std::env::var("QUIC_MIGRATION")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            cache_backend: std::env::var("CACHE_BACKEND").ok(),
+            cache_redis_url: std::env::var("CACHE_REDIS_URL").ok(),
+            seed_profile: std::env::var("SEED_PROFILE").ok(),
+            debug_time_travel: std::env::var("DEBUG_TIME_TRAVEL")
+                .ok()
+                .and_then(|v| v.parse().ok()),
         }
     }
 
@@ -164,6 +391,21 @@ impl ConfigManager {
         if let Some(v) = partial.web_port {
             config.web_port = v;
         }
+        if let Some(v) = partial.web_bind_address {
+            config.web_bind_address = v;
+        }
+        if let Some(v) = partial.api_port {
+            config.api_port = v;
+        }
+        if let Some(v) = partial.api_bind_address {
+            config.api_bind_address = Some(v);
+        }
+        if let Some(v) = partial.doh_port {
+            config.doh_port = v;
+        }
+        if let Some(v) = partial.doh_bind_address {
+            config.doh_bind_address = Some(v);
+        }
         if let Some(v) = partial.database_url {
             config.database_url = v;
         }
@@ -185,9 +427,103 @@ impl ConfigManager {
         if let Some(v) = partial.log_retention_days {
             config.log_retention_days = v;
         }
+        if let Some(v) = partial.log_format {
+            config.log_format = v;
+        }
+        if let Some(v) = partial.log_console_format {
+            config.log_console_format = v;
+        }
+        if let Some(v) = partial.otlp_endpoint {
+            config.otlp_endpoint = Some(v);
+        }
+        if let Some(v) = partial.grpc_port {
+            config.grpc_port = v;
+        }
+        if let Some(v) = partial.grpc_token {
+            config.grpc_token = Some(v);
+        }
+        if let Some(v) = partial.grpc_tls_cert {
+            config.grpc_tls_cert = Some(v);
+        }
+        if let Some(v) = partial.grpc_tls_key {
+            config.grpc_tls_key = Some(v);
+        }
+        if let Some(v) = partial.web_http2 {
+            config.web_http2 = v;
+        }
+        if let Some(v) = partial.web_max_body_size {
+            config.web_max_body_size = v;
+        }
+        if let Some(v) = partial.web_max_concurrent_streams {
+            config.web_max_concurrent_streams = v;
+        }
+        if let Some(v) = partial.web_header_timeout_secs {
+            config.web_header_timeout_secs = v;
+        }
+        if let Some(v) = partial.web_request_timeout_secs {
+            config.web_request_timeout_secs = v;
+        }
+        if let Some(v) = partial.web_trust_proxy_headers {
+            config.web_trust_proxy_headers = v;
+        }
+        if let Some(v) = partial.upstream_idle_timeout_secs {
+            config.upstream_idle_timeout_secs = v;
+        }
+        if let Some(v) = partial.upstream_max_connections {
+            config.upstream_max_connections = v;
+        }
+        if let Some(v) = partial.upstream_max_outstanding {
+            config.upstream_max_outstanding = v;
+        }
+        if let Some(v) = partial.upstream_overload_action {
+            config.upstream_overload_action = v;
+        }
+        if let Some(v) = partial.upstream_queue_timeout_ms {
+            config.upstream_queue_timeout_ms = v;
+        }
+        if let Some(v) = partial.quic_idle_timeout_secs {
+            config.quic_idle_timeout_secs = v;
+        }
+        if let Some(v) = partial.quic_keep_alive_secs {
+            config.quic_keep_alive_secs = v;
+        }
+        if let Some(v) = partial.quic_max_streams {
+            config.quic_max_streams = v;
+        }
+        if let Some(v) = partial.quic_zero_rtt {
+            config.quic_zero_rtt = v;
+        }
+        if let Some(v) = partial.quic_migration {
+            config.quic_migration = v;
+        }
+        if let Some(v) = partial.cache_backend {
+            config.cache_backend = v;
+        }
+        if let Some(v) = partial.cache_redis_url {
+            config.cache_redis_url = Some(v);
+        }
+        if let Some(v) = partial.seed_profile {
+            config.seed_profile = v;
+        }
+        if let Some(v) = partial.debug_time_travel {
+            config.debug_time_travel = v;
+        }
     }
 }
 
+/// Names of the settings that differ between two configurations
+fn changed_fields(old: &AppConfig, new: &AppConfig) -> Vec<String> {
+    let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) =
+        (serde_json::to_value(old), serde_json::to_value(new))
+    else {
+        return Vec::new();
+    };
+    old.iter()
+        .filter(|(key, value)| new.get(*key) != Some(*value))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -268,4 +604,24 @@ log_level = "debug"
         assert_eq!(config.web_port, 8080);
         assert_eq!(config.database_url, "sqlite:fluxdns.db?mode=rwc");
     }
+
+    #[test]
+    fn test_reload_reports_changed_fields() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "log_retention_days = 7").unwrap();
+        let manager = ConfigManager::load_with_path(file.path()).unwrap();
+        assert_eq!(manager.get().log_retention_days, 7);
+        assert!(manager.reload().unwrap().is_empty());
+
+        std::fs::write(file.path(), "log_retention_days = 14\nupstream_max_connections = 64\n").unwrap();
+        let mut changed = manager.reload().unwrap();
+        changed.sort();
+        assert_eq!(changed, vec!["log_retention_days", "upstream_max_connections"]);
+        assert_eq!(manager.get().upstream_max_connections, 64);
+
+        // A broken file keeps the current settings
+        std::fs::write(file.path(), "log_retention_days = [").unwrap();
+        assert!(manager.reload().is_err());
+        assert_eq!(manager.get().log_retention_days, 14);
+    }
 }
@@ -0,0 +1,168 @@
+//! Configuration table backups
+//!
+//! Rows are dumped as JSON objects keyed by column name and written back
+//! with their ids, so references into a restored table (shadow rules, rule
+//! hit counters, upstream statistics) keep pointing at the same rows.
+
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+use super::Database;
+
+/// One table row, keyed by column name
+pub type BackupRow = serde_json::Map<String, serde_json::Value>;
+
+/// Tables a backup covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupTable {
+    Records,
+    RewriteRules,
+    Upstreams,
+}
+
+impl BackupTable {
+    pub const ALL: [BackupTable; 3] = [Self::Records, Self::RewriteRules, Self::Upstreams];
+
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "records" => Some(Self::Records),
+            "rewrite_rules" => Some(Self::RewriteRules),
+            "upstreams" => Some(Self::Upstreams),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Records => "records",
+            Self::RewriteRules => "rewrite_rules",
+            Self::Upstreams => "upstreams",
+        }
+    }
+
+    /// Database table holding the rows
+    fn table_name(&self) -> &'static str {
+        match self {
+            Self::Records => "dns_records",
+            Self::RewriteRules => "rewrite_rules",
+            Self::Upstreams => "upstream_servers",
+        }
+    }
+}
+
+impl Database {
+    /// Columns of a table, in schema order
+    async fn table_columns(&self, table: &str) -> Result<Vec<String>> {
+        let columns: Vec<(String,)> = sqlx::query_as(&format!(
+            "SELECT name FROM pragma_table_info('{}') ORDER BY cid",
+            table
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(columns.into_iter().map(|(name,)| name).collect())
+    }
+
+    /// All rows of a table, ordered by id
+    pub async fn dump_table(&self, table: BackupTable) -> Result<Vec<BackupRow>> {
+        let table = table.table_name();
+        let fields = self
+            .table_columns(table)
+            .await?
+            .iter()
+            .map(|c| format!("'{0}', \"{0}\"", c))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let (json,): (String,) = sqlx::query_as(&format!(
+            "SELECT COALESCE(json_group_array(json_object({})), '[]') FROM (SELECT * FROM {} ORDER BY id)",
+            fields, table
+        ))
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Replace the rows of the given tables in one transaction
+    ///
+    /// Columns missing from the rows, e.g. in a backup taken before they
+    /// were added, get their default values; unknown keys are ignored.
+    pub async fn restore_tables(&self, tables: &[(BackupTable, &[BackupRow])]) -> Result<()> {
+        // Statements with the rows they insert, built before the write lock is taken
+        let mut statements: Vec<(String, Option<String>)> = Vec::new();
+        for (table, rows) in tables {
+            let table = table.table_name();
+            statements.push((format!("DELETE FROM {}", table), None));
+            if rows.is_empty() {
+                continue;
+            }
+            let columns: Vec<String> = self
+                .table_columns(table)
+                .await?
+                .into_iter()
+                .filter(|c| rows.iter().any(|r| r.contains_key(c)))
+                .collect();
+            ensure!(columns.iter().any(|c| c == "id"), "{} rows have no id", table);
+            let values = columns
+                .iter()
+                .map(|c| format!("json_extract(value, '$.\"{}\"')", c))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let columns = columns
+                .iter()
+                .map(|c| format!("\"{}\"", c))
+                .collect::<Vec<_>>()
+                .join(", ");
+            statements.push((
+                format!("INSERT INTO {} ({}) SELECT {} FROM json_each(?)", table, columns, values),
+                Some(serde_json::to_string(rows)?),
+            ));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        for (statement, rows) in &statements {
+            let mut query = sqlx::query(statement);
+            if let Some(rows) = rows {
+                query = query.bind(rows);
+            }
+            query.execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_dump_and_restore_table() {
+        let dir = tempdir().unwrap();
+        let db_url = format!("sqlite:{}?mode=rwc", dir.path().join("test.db").display());
+        let db = Database::new(&db_url).await.unwrap();
+        for (name, value) in [("a.example.com", "10.0.0.1"), ("b.example.com", "10.0.0.2")] {
+            sqlx::query("INSERT INTO dns_records (name, record_type, value) VALUES (?, 'A', ?)")
+                .bind(name)
+                .bind(value)
+                .execute(db.pool())
+                .await
+                .unwrap();
+        }
+        let backup = db.dump_table(BackupTable::Records).await.unwrap();
+        assert_eq!(backup.len(), 2);
+        assert_eq!(backup[0]["name"], "a.example.com");
+
+        sqlx::query("DELETE FROM dns_records WHERE name = 'a.example.com'")
+            .execute(db.pool())
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO dns_records (name, record_type, value) VALUES ('c.example.com', 'A', '10.0.0.3')")
+            .execute(db.pool())
+            .await
+            .unwrap();
+        db.restore_tables(&[(BackupTable::Records, backup.as_slice())]).await.unwrap();
+        assert_eq!(db.dump_table(BackupTable::Records).await.unwrap(), backup);
+    }
+}
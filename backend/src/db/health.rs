@@ -0,0 +1,162 @@
+//! Database write health
+//!
+//! Tracks query log write failures. After several failures in a row (disk
+//! full, read-only file) the database is marked degraded: query logging is
+//! paused and dropped entries are counted, while DNS resolution keeps
+//! running. A write is still let through periodically as a probe, and the
+//! first one that succeeds ends degraded mode.
+
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Consecutive write failures before the database is marked degraded
+const DEGRADE_AFTER_FAILURES: u32 = 3;
+/// Time between probe writes while degraded
+const PROBE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Snapshot of database write health
+#[derive(Debug, Clone, Serialize)]
+pub struct DbHealthStatus {
+    pub degraded: bool,
+    pub degraded_since: Option<DateTime<Utc>>,
+    pub consecutive_failures: u32,
+    /// Query log entries not written since startup
+    pub dropped_query_logs: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct Degraded {
+    since: Option<DateTime<Utc>>,
+    last_probe: Option<Instant>,
+    last_error: Option<String>,
+}
+
+/// Database write health tracker
+pub struct DbHealth {
+    consecutive_failures: AtomicU32,
+    dropped_query_logs: AtomicU64,
+    state: Mutex<Degraded>,
+}
+
+impl DbHealth {
+    pub fn new() -> Self {
+        Self {
+            consecutive_failures: AtomicU32::new(0),
+            dropped_query_logs: AtomicU64::new(0),
+            state: Mutex::new(Degraded::default()),
+        }
+    }
+
+    /// Whether writes are currently paused
+    pub fn is_degraded(&self) -> bool {
+        self.state.lock().unwrap().since.is_some()
+    }
+
+    /// Whether a query log write should be attempted now
+    ///
+    /// Always true while healthy; while degraded, true once per probe
+    /// interval so recovery can be detected.
+    pub fn allow_write(&self) -> bool {
+        self.allow_write_at(Instant::now())
+    }
+
+    fn allow_write_at(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.since.is_none() {
+            return true;
+        }
+        match state.last_probe {
+            Some(last) if now.duration_since(last) < PROBE_INTERVAL => false,
+            _ => {
+                state.last_probe = Some(now);
+                true
+            }
+        }
+    }
+
+    /// Record a successful write, returning true if this ended degraded mode
+    pub fn record_success(&self) -> bool {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        let mut state = self.state.lock().unwrap();
+        let recovered = state.since.is_some();
+        *state = Degraded::default();
+        recovered
+    }
+
+    /// Record a failed write, returning true if this started degraded mode
+    pub fn record_failure(&self, error: &str) -> bool {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut state = self.state.lock().unwrap();
+        state.last_error = Some(error.to_string());
+        if state.since.is_none() && failures >= DEGRADE_AFTER_FAILURES {
+            state.since = Some(Utc::now());
+            state.last_probe = Some(Instant::now());
+            return true;
+        }
+        false
+    }
+
+    /// Count a query log entry that was not written
+    pub fn record_dropped(&self) {
+        self.dropped_query_logs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Current health snapshot
+    pub fn status(&self) -> DbHealthStatus {
+        let state = self.state.lock().unwrap();
+        DbHealthStatus {
+            degraded: state.since.is_some(),
+            degraded_since: state.since,
+            consecutive_failures: self.consecutive_failures.load(Ordering::Relaxed),
+            dropped_query_logs: self.dropped_query_logs.load(Ordering::Relaxed),
+            last_error: state.last_error.clone(),
+        }
+    }
+}
+
+impl Default for DbHealth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_degrade_and_recover() {
+        let health = DbHealth::new();
+        assert!(!health.record_failure("database or disk is full"));
+        assert!(!health.record_failure("database or disk is full"));
+        assert!(!health.is_degraded());
+        assert!(health.record_failure("database or disk is full"));
+        assert!(health.is_degraded());
+        // Only the transition is reported
+        assert!(!health.record_failure("database or disk is full"));
+
+        // Writes are paused except for one probe per interval
+        let now = Instant::now();
+        assert!(!health.allow_write_at(now));
+        assert!(health.allow_write_at(now + PROBE_INTERVAL));
+        assert!(!health.allow_write_at(now + PROBE_INTERVAL));
+
+        health.record_dropped();
+        let status = health.status();
+        assert!(status.degraded);
+        assert_eq!(status.consecutive_failures, 4);
+        assert_eq!(status.dropped_query_logs, 1);
+
+        assert!(health.record_success());
+        assert!(!health.is_degraded());
+        assert!(health.allow_write());
+        assert!(!health.record_success());
+        // The dropped counter keeps counting across recoveries
+        assert_eq!(health.status().dropped_query_logs, 1);
+    }
+}
@@ -2,10 +2,14 @@
 //!
 //! Handles SQLite database connections, migrations, and CRUD operations.
 
+mod backup;
+mod health;
 mod models;
 pub mod repository;
 pub mod stats_cache;
 
+pub use backup::*;
+pub use health::*;
 pub use models::*;
 pub use repository::*;
 pub use stats_cache::*;
@@ -15,10 +19,13 @@ use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
 
 use std::sync::Arc;
 
+use crate::dns::normalize_name;
+
 /// Database wrapper providing connection pool and repositories
 pub struct Database {
     pool: SqlitePool,
     stats_cache: Arc<StatsCache>,
+    health: Arc<DbHealth>,
 }
 
 impl Database {
@@ -36,7 +43,11 @@ impl Database {
             .await?;
 
         let stats_cache = Arc::new(StatsCache::empty());
-        let db = Self { pool, stats_cache };
+        let db = Self {
+            pool,
+            stats_cache,
+            health: Arc::new(DbHealth::new()),
+        };
         db.run_migrations().await?;
         db.init_stats_cache().await?; // Initial population from DB
 
@@ -49,6 +60,11 @@ impl Database {
         &self.pool
     }
 
+    /// Get the write health tracker
+    pub fn health(&self) -> &Arc<DbHealth> {
+        &self.health
+    }
+
     /// Get DNS records repository
     pub fn dns_records(&self) -> DnsRecordRepository {
         DnsRecordRepository::new(self.pool.clone())
@@ -69,6 +85,11 @@ impl Database {
         QueryLogRepository::new(self.pool.clone(), self.stats_cache.clone())
     }
 
+    /// Get query log roll-ups repository
+    pub fn query_log_rollups(&self) -> QueryLogRollupRepository {
+        QueryLogRollupRepository::new(self.pool.clone())
+    }
+
     /// Get system config repository
     pub fn system_config(&self) -> SystemConfigRepository {
         SystemConfigRepository::new(self.pool.clone())
@@ -79,6 +100,71 @@ impl Database {
         ServerListenerRepository::new(self.pool.clone())
     }
 
+    /// Get tenants repository
+    pub fn tenants(&self) -> TenantRepository {
+        TenantRepository::new(self.pool.clone())
+    }
+
+    /// Get scoped API tokens repository
+    pub fn api_tokens(&self) -> ApiTokenRepository {
+        ApiTokenRepository::new(self.pool.clone())
+    }
+
+    /// Get delegated admins repository
+    pub fn delegated_admins(&self) -> DelegatedAdminRepository {
+        DelegatedAdminRepository::new(self.pool.clone())
+    }
+
+    /// Get cache purge audit repository
+    pub fn cache_purge_audit(&self) -> CachePurgeAuditRepository {
+        CachePurgeAuditRepository::new(self.pool.clone())
+    }
+
+    /// Get domain category lists repository
+    pub fn category_lists(&self) -> CategoryListRepository {
+        CategoryListRepository::new(self.pool.clone())
+    }
+
+    /// Get typo-squatting repository
+    pub fn typosquat(&self) -> TyposquatRepository {
+        TyposquatRepository::new(self.pool.clone())
+    }
+
+    /// Get upstream integrity alerts repository
+    pub fn integrity_alerts(&self) -> IntegrityAlertRepository {
+        IntegrityAlertRepository::new(self.pool.clone())
+    }
+
+    /// Get resolution profiles repository
+    pub fn resolution_profiles(&self) -> ResolutionProfileRepository {
+        ResolutionProfileRepository::new(self.pool.clone())
+    }
+
+    /// Get saved query log filters repository
+    pub fn saved_log_filters(&self) -> SavedLogFilterRepository {
+        SavedLogFilterRepository::new(self.pool.clone())
+    }
+
+    /// Get notifications repository
+    pub fn notifications(&self) -> NotificationRepository {
+        NotificationRepository::new(self.pool.clone())
+    }
+
+    /// Get RPZ feeds repository
+    pub fn rpz_feeds(&self) -> RpzFeedRepository {
+        RpzFeedRepository::new(self.pool.clone())
+    }
+
+    /// Get record groups repository
+    pub fn record_groups(&self) -> RecordGroupRepository {
+        RecordGroupRepository::new(self.pool.clone())
+    }
+
+    /// Get top-domain leaderboard snapshots repository
+    pub fn domain_stats(&self) -> DomainStatRepository {
+        DomainStatRepository::new(self.pool.clone())
+    }
+
     /// Force WAL checkpoint to ensure all writes are visible to readers
     pub async fn checkpoint(&self) -> Result<()> {
         sqlx::query("PRAGMA wal_checkpoint(PASSIVE)")
@@ -256,6 +342,33 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        // Optional interface binding (SO_BINDTODEVICE)
+        self.add_column_if_missing("server_listeners", "interface", "VARCHAR(15)").await?;
+
+        // Resolution profile pinned to a listener (overrides the active profile)
+        self.add_column_if_missing("server_listeners", "profile_id", "INTEGER").await?;
+        // Response rate limiting (UDP): responses per second (0 = off) and slip
+        self.add_column_if_missing("server_listeners", "rrl_responses_per_second", "INTEGER NOT NULL DEFAULT 0").await?;
+        self.add_column_if_missing("server_listeners", "rrl_slip", "INTEGER NOT NULL DEFAULT 2").await?;
+        // Recursive, authoritative-only or cache-only resolution per listener
+        self.add_column_if_missing("server_listeners", "resolution_mode", "VARCHAR(20) NOT NULL DEFAULT 'recursive'").await?;
+
+        // Outbound source address/interface per upstream
+        self.add_column_if_missing("upstream_servers", "source_ip", "VARCHAR(45)").await?;
+        self.add_column_if_missing("upstream_servers", "source_interface", "VARCHAR(15)").await?;
+        // TLS server name override and certificate verification per upstream
+        self.add_column_if_missing("upstream_servers", "tls_server_name", "VARCHAR(253)").await?;
+        self.add_column_if_missing("upstream_servers", "verify_hostname", "BOOLEAN").await?;
+        // JSON capability profile from the last probe
+        self.add_column_if_missing("upstream_servers", "capabilities", "TEXT").await?;
+        // Maintenance flag: drained servers are health-checked but get no queries
+        self.add_column_if_missing("upstream_servers", "drained", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+        // Configured position for ordered strategies (round-robin, failover)
+        self.add_column_if_missing("upstream_servers", "sort_order", "INTEGER NOT NULL DEFAULT 0").await?;
+        // DoH request method and HTTP version preference
+        self.add_column_if_missing("upstream_servers", "doh_method", "VARCHAR(8)").await?;
+        self.add_column_if_missing("upstream_servers", "doh_http_version", "VARCHAR(8)").await?;
+
         // System config table
         sqlx::query(
             r#"
@@ -349,41 +462,517 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
-        // Seed default upstream servers if none exist
-        self.seed_default_upstreams().await?;
+        // Tenants table (hosted multi-office deployments)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS tenants (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name VARCHAR(100) NOT NULL UNIQUE,
+                description TEXT,
+                api_token VARCHAR(64) NOT NULL UNIQUE,
+                client_subnets TEXT NOT NULL DEFAULT '',
+                listeners TEXT NOT NULL DEFAULT '',
+                enabled BOOLEAN DEFAULT TRUE,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Tenant ownership of records, rules and logs (NULL = global)
+        self.add_column_if_missing("dns_records", "tenant_id", "INTEGER").await?;
+        self.add_column_if_missing("rewrite_rules", "tenant_id", "INTEGER").await?;
+        self.add_column_if_missing("query_logs", "tenant_id", "INTEGER").await?;
+
+        sqlx::query(
+            r#"CREATE INDEX IF NOT EXISTS idx_query_logs_tenant ON query_logs(tenant_id)"#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Scoped API tokens (automation credentials)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS api_tokens (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name VARCHAR(100) NOT NULL UNIQUE,
+                token VARCHAR(64) NOT NULL UNIQUE,
+                scopes TEXT NOT NULL DEFAULT '',
+                enabled BOOLEAN DEFAULT TRUE,
+                expires_at DATETIME,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                last_used_at DATETIME
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Cache purge audit trail
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS cache_purge_audit (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                actor VARCHAR(100) NOT NULL,
+                client_ip VARCHAR(45) NOT NULL,
+                patterns TEXT NOT NULL,
+                purged INTEGER NOT NULL DEFAULT 0,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Domain category lists (local classification database)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS category_lists (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name VARCHAR(100) NOT NULL UNIQUE,
+                category VARCHAR(50) NOT NULL,
+                url TEXT,
+                enabled BOOLEAN DEFAULT TRUE,
+                domain_count INTEGER NOT NULL DEFAULT 0,
+                last_updated_at DATETIME,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS category_domains (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                list_id INTEGER NOT NULL,
+                domain VARCHAR(255) NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (list_id, domain),
+                FOREIGN KEY (list_id) REFERENCES category_lists(id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Category tagging of query logs
+        self.add_column_if_missing("query_logs", "category", "VARCHAR(50)").await?;
+        self.add_column_if_missing("query_logs", "answered_by", "VARCHAR(50)").await?;
+
+        sqlx::query(
+            r#"CREATE INDEX IF NOT EXISTS idx_query_logs_category ON query_logs(category, created_at)"#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Per-query trace IDs for correlating file logs with query log entries
+        self.add_column_if_missing("query_logs", "trace_id", "VARCHAR(32)").await?;
+
+        sqlx::query(
+            r#"CREATE INDEX IF NOT EXISTS idx_query_logs_trace_id ON query_logs(trace_id)"#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Queries each row stands for when query logging is sampled
+        self.add_column_if_missing("query_logs", "sample_rate", "INTEGER NOT NULL DEFAULT 1").await?;
+
+        // Typo-squatting protection: reference domains and detections
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS typosquat_domains (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                domain VARCHAR(255) NOT NULL UNIQUE,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS typosquat_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                query_name VARCHAR(255) NOT NULL,
+                suggestion VARCHAR(255) NOT NULL,
+                distance INTEGER NOT NULL,
+                client_ip VARCHAR(45),
+                blocked BOOLEAN DEFAULT FALSE,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Upstream answer discrepancies (integrity monitor)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS integrity_alerts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                domain VARCHAR(255) NOT NULL,
+                record_type VARCHAR(10) NOT NULL,
+                answers TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Query log roll-ups: hourly and daily aggregates kept after raw rows expire
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS query_log_hourly (
+                bucket DATETIME NOT NULL,
+                tenant_id INTEGER,
+                client_ip VARCHAR(45) NOT NULL,
+                query_name VARCHAR(255) NOT NULL,
+                query_type VARCHAR(10) NOT NULL,
+                response_code VARCHAR(20),
+                answered_by VARCHAR(50),
+                category VARCHAR(50),
+                queries INTEGER NOT NULL,
+                cache_hits INTEGER NOT NULL,
+                response_time_total INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"CREATE INDEX IF NOT EXISTS idx_query_log_hourly_bucket ON query_log_hourly(bucket)"#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS query_log_daily (
+                bucket DATETIME NOT NULL,
+                tenant_id INTEGER,
+                client_ip VARCHAR(45) NOT NULL,
+                query_name VARCHAR(255) NOT NULL,
+                query_type VARCHAR(10) NOT NULL,
+                response_code VARCHAR(20),
+                answered_by VARCHAR(50),
+                category VARCHAR(50),
+                queries INTEGER NOT NULL,
+                cache_hits INTEGER NOT NULL,
+                response_time_total INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"CREATE INDEX IF NOT EXISTS idx_query_log_daily_bucket ON query_log_daily(bucket)"#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Listener protocol of each query, also kept in the roll-ups
+        self.add_column_if_missing("query_logs", "protocol", "VARCHAR(10)").await?;
+        self.add_column_if_missing("query_log_hourly", "protocol", "VARCHAR(10)").await?;
+        self.add_column_if_missing("query_log_daily", "protocol", "VARCHAR(10)").await?;
+        // Extended DNS error (RFC 8914) explaining the response
+        self.add_column_if_missing("query_logs", "extended_error", "TEXT").await?;
+        // External resolver that query log entries were ingested from
+        self.add_column_if_missing("query_logs", "source", "VARCHAR(64)").await?;
+
+        // Switchable resolution profiles (split DNS)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS resolution_profiles (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name VARCHAR(100) NOT NULL UNIQUE,
+                description TEXT,
+                upstreams TEXT NOT NULL DEFAULT '',
+                rules TEXT NOT NULL DEFAULT '',
+                probe_target VARCHAR(255),
+                priority INTEGER DEFAULT 0,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Shadow (canary) rewrite rules
+        self.add_column_if_missing("rewrite_rules", "shadow", "BOOLEAN NOT NULL DEFAULT FALSE").await?;
+        self.add_column_if_missing("rewrite_rules", "shadow_of", "INTEGER").await?;
+        self.add_column_if_missing("rewrite_rules", "shadow_hits", "INTEGER NOT NULL DEFAULT 0").await?;
+        self.add_column_if_missing("rewrite_rules", "shadow_last_hit_at", "DATETIME").await?;
+
+        // Descriptions and tags for organizing records and rules
+        self.add_column_if_missing("dns_records", "description", "TEXT").await?;
+        self.add_column_if_missing("dns_records", "tags", "TEXT NOT NULL DEFAULT '[]'").await?;
+        self.add_column_if_missing("rewrite_rules", "tags", "TEXT NOT NULL DEFAULT '[]'").await?;
+
+        // Response Policy Zone feeds imported as rewrite rules
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS rpz_feeds (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name VARCHAR(100) NOT NULL UNIQUE,
+                url TEXT NOT NULL,
+                enabled BOOLEAN DEFAULT TRUE,
+                priority INTEGER NOT NULL DEFAULT 0,
+                refresh_interval_secs INTEGER NOT NULL DEFAULT 3600,
+                serial INTEGER,
+                rule_count INTEGER NOT NULL DEFAULT 0,
+                skipped_count INTEGER NOT NULL DEFAULT 0,
+                last_checked_at DATETIME,
+                last_changed_at DATETIME,
+                last_error TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Record groups (services) whose records are managed together
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS record_groups (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name VARCHAR(100) NOT NULL UNIQUE,
+                domain VARCHAR(255) NOT NULL,
+                description TEXT,
+                tenant_id INTEGER,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        self.add_column_if_missing("dns_records", "group_id", "INTEGER").await?;
+        sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_dns_records_group ON dns_records(group_id)"#)
+            .execute(&self.pool)
+            .await?;
+
+        // Hourly snapshots of the top-domains leaderboard
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS domain_stat_hours (
+                hour INTEGER NOT NULL,
+                domain VARCHAR(255) NOT NULL,
+                queries INTEGER NOT NULL,
+                blocked INTEGER NOT NULL,
+                response_time_total INTEGER NOT NULL,
+                response_time_samples INTEGER NOT NULL,
+                overcount INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (hour, domain)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Expiration dates for temporary records and rules
+        self.add_column_if_missing("dns_records", "expires_at", "DATETIME").await?;
+        self.add_column_if_missing("rewrite_rules", "expires_at", "DATETIME").await?;
+
+        // Admin accounts limited to records inside their zones
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS delegated_admins (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                username VARCHAR(100) NOT NULL UNIQUE,
+                password_hash VARCHAR(100) NOT NULL,
+                zones TEXT NOT NULL DEFAULT '',
+                enabled BOOLEAN DEFAULT TRUE,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                last_login_at DATETIME
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Whether a blocked query was rejected for its client or its name
+        self.add_column_if_missing("query_logs", "block_reason", "VARCHAR(10)").await?;
+
+        // Named query log filters offered as quick views
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS saved_log_filters (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name VARCHAR(100) NOT NULL UNIQUE,
+                description TEXT,
+                filter TEXT NOT NULL DEFAULT '{}',
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // In-app notification inbox
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS notifications (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                severity VARCHAR(10) NOT NULL,
+                source VARCHAR(50) NOT NULL,
+                message TEXT NOT NULL,
+                metadata TEXT NOT NULL DEFAULT '{}',
+                read BOOLEAN NOT NULL DEFAULT FALSE,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_notifications_read ON notifications(read, id)"#)
+            .execute(&self.pool)
+            .await?;
+
+        self.normalize_stored_names().await?;
+        self.hash_stored_tokens("tenants", "api_token").await?;
+        self.hash_stored_tokens("api_tokens", "token").await?;
+        self.migrate_purge_tokens().await?;
 
         Ok(())
     }
 
-    /// Seed default upstream DNS servers if the table is empty
-    async fn seed_default_upstreams(&self) -> Result<()> {
-        // Check if any upstream servers exist
-        let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM upstream_servers")
-            .fetch_one(&self.pool)
-            .await?;
+    /// Replace API tokens that earlier versions stored in plaintext with
+    /// their digest
+    ///
+    /// Digests are 64 hex characters; issued tokens are shorter and carry a
+    /// prefix, so anything else is a plaintext token.
+    async fn hash_stored_tokens(&self, table: &str, column: &str) -> Result<()> {
+        let tokens: Vec<(i64, String)> = sqlx::query_as(&format!(
+            "SELECT id, {column} FROM {table} WHERE length({column}) <> 64"
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        for (id, token) in &tokens {
+            sqlx::query(&format!("UPDATE {table} SET {column} = ? WHERE id = ?"))
+                .bind(hash_token(token))
+                .bind(id)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        if !tokens.is_empty() {
+            tracing::info!("Replaced {} plaintext tokens in {} with their digest", tokens.len(), table);
+        }
+        Ok(())
+    }
+
+    /// Move the cache purge webhook tokens of earlier versions into scoped
+    /// API tokens with the `cache:purge` scope
+    ///
+    /// The digests carry over, so issued `fdp_` secrets keep working. A name
+    /// already taken by an API token gets a ` (purge)` suffix.
+    async fn migrate_purge_tokens(&self) -> Result<()> {
+        let exists: Option<(String,)> =
+            sqlx::query_as("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'purge_tokens'")
+                .fetch_optional(&self.pool)
+                .await?;
+        if exists.is_none() {
+            return Ok(());
+        }
+        self.hash_stored_tokens("purge_tokens", "token").await?;
+
+        let mut tx = self.pool.begin().await?;
+        let moved = sqlx::query(
+            r#"
+            INSERT INTO api_tokens (name, token, scopes, enabled, created_at, last_used_at)
+            SELECT CASE WHEN name IN (SELECT name FROM api_tokens) THEN name || ' (purge)' ELSE name END,
+                   token, 'cache:purge', enabled, created_at, last_used_at
+            FROM purge_tokens
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        sqlx::query("DROP TABLE purge_tokens").execute(&mut *tx).await?;
+        tx.commit().await?;
+
+        if moved > 0 {
+            tracing::info!("Moved {} cache purge tokens into scoped API tokens", moved);
+        }
+        Ok(())
+    }
 
-        if count.0 == 0 {
-            // Insert default upstream servers
-            let default_servers = [
-                ("阿里云H3", "https://223.6.6.6/dns-query", "doh3"),
-                ("阿里云Quic", "223.5.5.5:853", "doq")
-            ];
-
-            for (name, address, protocol) in default_servers {
-                sqlx::query(
-                    r#"
-                    INSERT INTO upstream_servers (name, address, protocol, timeout, enabled, created_at, updated_at)
-                    VALUES (?, ?, ?, 5000, TRUE, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
-                    "#,
-                )
-                .bind(name)
-                .bind(address)
-                .bind(protocol)
+    /// Rewrite record names and exact/wildcard rewrite patterns stored in
+    /// another form (e.g. `Example.com.`) into the canonical one
+    ///
+    /// Only rows `normalize_name` would change are selected: a trailing dot,
+    /// upper case, surrounding spaces or non-ASCII characters.
+    async fn normalize_stored_names(&self) -> Result<()> {
+        const NEEDS_NORMALIZING: &str =
+            "({col} LIKE '%.' OR {col} <> lower({col}) OR {col} <> trim({col}) OR {col} GLOB '*[^ -~]*')";
+
+        let records: Vec<(i64, String)> = sqlx::query_as(&format!(
+            "SELECT id, name FROM dns_records WHERE {}",
+            NEEDS_NORMALIZING.replace("{col}", "name")
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        for (id, name) in &records {
+            sqlx::query("UPDATE dns_records SET name = ? WHERE id = ?")
+                .bind(normalize_name(name))
+                .bind(id)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        let rules: Vec<(i64, String)> = sqlx::query_as(&format!(
+            "SELECT id, pattern FROM rewrite_rules WHERE match_type IN ('exact', 'wildcard') AND {}",
+            NEEDS_NORMALIZING.replace("{col}", "pattern")
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        for (id, pattern) in &rules {
+            sqlx::query("UPDATE rewrite_rules SET pattern = ? WHERE id = ?")
+                .bind(normalize_name(pattern))
+                .bind(id)
                 .execute(&self.pool)
                 .await?;
-            }
+        }
+
+        if !records.is_empty() || !rules.is_empty() {
+            tracing::info!(
+                "Normalized {} record names and {} rewrite patterns to lowercase without a trailing dot",
+                records.len(),
+                rules.len()
+            );
+        }
+        Ok(())
+    }
 
-            tracing::info!("Seeded {} default upstream DNS servers", default_servers.len());
+    /// Add a column to an existing table unless it is already present
+    ///
+    /// SQLite has no `ADD COLUMN IF NOT EXISTS`, so the schema is inspected first.
+    async fn add_column_if_missing(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        let columns: Vec<(String,)> = sqlx::query_as(&format!(
+            "SELECT name FROM pragma_table_info('{}')",
+            table
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        if !columns.iter().any(|(name,)| name == column) {
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+                .execute(&self.pool)
+                .await?;
         }
 
         Ok(())
@@ -3,9 +3,53 @@
 //! Data structures representing database entities.
 
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use sqlx::FromRow;
 
+/// Deserialize a present field as `Some`, even when it is `null`
+///
+/// With `#[serde(default)]` this lets an update tell an omitted field
+/// (keep the value) from an explicit `null` (clear it).
+pub fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Whether an optional expiration time has passed
+pub fn is_expired(expires_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    expires_at.is_some_and(|t| t <= now)
+}
+
+/// Free-form labels on records and rules, stored as a JSON array
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Tags(pub Vec<String>);
+
+impl Tags {
+    pub fn contains(&self, tag: &str) -> bool {
+        self.0.iter().any(|t| t == tag)
+    }
+
+    /// JSON text for the `tags` column
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.0).unwrap_or_else(|_| "[]".to_string())
+    }
+}
+
+impl TryFrom<String> for Tags {
+    type Error = serde_json::Error;
+
+    fn try_from(json: String) -> Result<Self, Self::Error> {
+        if json.is_empty() {
+            return Ok(Self::default());
+        }
+        serde_json::from_str(&json).map(Self)
+    }
+}
+
 /// DNS record entity
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct DnsRecord {
@@ -18,6 +62,34 @@ pub struct DnsRecord {
     pub enabled: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Owning tenant (None = global record visible to every view)
+    pub tenant_id: Option<i64>,
+    pub description: Option<String>,
+    #[sqlx(try_from = "String")]
+    #[serde(default)]
+    pub tags: Tags,
+    /// Record group (service) the record belongs to
+    #[serde(default)]
+    pub group_id: Option<i64>,
+    /// After this time the record is treated as disabled
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Local records answering a query name
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordMatch {
+    /// Record name that owns the answer: the queried name or a wildcard
+    pub matched_name: String,
+    /// Records of the queried type under that name; empty when the name
+    /// only has records of other types
+    pub records: Vec<DnsRecord>,
+}
+
+impl RecordMatch {
+    pub fn is_wildcard(&self) -> bool {
+        self.matched_name.starts_with("*.")
+    }
 }
 
 /// Create DNS record request
@@ -32,6 +104,13 @@ pub struct CreateDnsRecord {
     pub priority: i32,
     #[serde(default = "default_enabled")]
     pub enabled: bool,
+    #[serde(default)]
+    pub tenant_id: Option<i64>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Tags,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 /// Update DNS record request
@@ -43,6 +122,11 @@ pub struct UpdateDnsRecord {
     pub ttl: Option<i32>,
     pub priority: Option<i32>,
     pub enabled: Option<bool>,
+    pub description: Option<String>,
+    pub tags: Option<Tags>,
+    /// `Some(None)` removes the expiration
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub expires_at: Option<Option<DateTime<Utc>>>,
 }
 
 /// Rewrite rule entity
@@ -58,6 +142,21 @@ pub struct RewriteRule {
     pub description: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Owning tenant (None = global rule)
+    pub tenant_id: Option<i64>,
+    /// Shadow rules are evaluated and counted but never change answers
+    pub shadow: bool,
+    /// Rule that a shadow rule replaces when promoted
+    pub shadow_of: Option<i64>,
+    /// Queries the shadow rule would have answered
+    pub shadow_hits: i64,
+    pub shadow_last_hit_at: Option<DateTime<Utc>>,
+    #[sqlx(try_from = "String")]
+    #[serde(default)]
+    pub tags: Tags,
+    /// After this time the rule is treated as disabled
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 
@@ -73,6 +172,16 @@ pub struct CreateRewriteRule {
     #[serde(default = "default_enabled")]
     pub enabled: bool,
     pub description: Option<String>,
+    #[serde(default)]
+    pub tenant_id: Option<i64>,
+    #[serde(default)]
+    pub shadow: bool,
+    #[serde(default)]
+    pub shadow_of: Option<i64>,
+    #[serde(default)]
+    pub tags: Tags,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 /// Update rewrite rule request
@@ -85,6 +194,10 @@ pub struct UpdateRewriteRule {
     pub priority: Option<i32>,
     pub enabled: Option<bool>,
     pub description: Option<String>,
+    pub tags: Option<Tags>,
+    /// `Some(None)` removes the expiration
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub expires_at: Option<Option<DateTime<Utc>>>,
 }
 
 /// Upstream server entity
@@ -98,6 +211,29 @@ pub struct UpstreamServer {
     pub enabled: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Local source IP for outbound queries (falls back to the global setting)
+    pub source_ip: Option<String>,
+    /// Local interface for outbound queries (falls back to the global setting)
+    pub source_interface: Option<String>,
+    /// TLS server name (SNI) when it differs from the address host
+    pub tls_server_name: Option<String>,
+    /// Verify the server certificate; None uses the protocol default
+    pub verify_hostname: Option<bool>,
+    /// JSON capability profile detected by the last probe
+    #[serde(skip)]
+    pub capabilities: Option<String>,
+    /// In maintenance: health-checked but receives no production queries
+    #[serde(default)]
+    pub drained: bool,
+    /// Position in the configured order; ties fall back to the ID
+    #[serde(default)]
+    pub sort_order: i64,
+    /// DoH request method (`post` or `get`); None uses POST
+    #[serde(default)]
+    pub doh_method: Option<String>,
+    /// DoH HTTP version (`auto`, `http1`, `http2`); None negotiates
+    #[serde(default)]
+    pub doh_http_version: Option<String>,
 }
 
 /// Create upstream server request
@@ -110,6 +246,18 @@ pub struct CreateUpstreamServer {
     pub timeout: i32,
     #[serde(default = "default_enabled")]
     pub enabled: bool,
+    #[serde(default)]
+    pub source_ip: Option<String>,
+    #[serde(default)]
+    pub source_interface: Option<String>,
+    #[serde(default)]
+    pub tls_server_name: Option<String>,
+    #[serde(default)]
+    pub verify_hostname: Option<bool>,
+    #[serde(default)]
+    pub doh_method: Option<String>,
+    #[serde(default)]
+    pub doh_http_version: Option<String>,
 }
 
 /// Update upstream server request
@@ -120,6 +268,17 @@ pub struct UpdateUpstreamServer {
     pub protocol: Option<String>,
     pub timeout: Option<i32>,
     pub enabled: Option<bool>,
+    /// Empty string clears the source IP
+    pub source_ip: Option<String>,
+    /// Empty string clears the source interface
+    pub source_interface: Option<String>,
+    /// Empty string clears the TLS server name
+    pub tls_server_name: Option<String>,
+    pub verify_hostname: Option<bool>,
+    /// Empty string resets to POST
+    pub doh_method: Option<String>,
+    /// Empty string resets to negotiation
+    pub doh_http_version: Option<String>,
 }
 
 /// Query log entity
@@ -134,6 +293,26 @@ pub struct QueryLog {
     pub cache_hit: bool,
     pub upstream_used: Option<String>,
     pub created_at: DateTime<Utc>,
+    pub tenant_id: Option<i64>,
+    /// Domain category, when classified
+    pub category: Option<String>,
+    /// Resolver middleware that answered the query directly (e.g. "category_filter")
+    pub answered_by: Option<String>,
+    /// Per-query trace ID, also present on the resolver's tracing log lines
+    pub trace_id: Option<String>,
+    /// Number of queries this row stands for under query log sampling
+    pub sample_rate: i64,
+    /// Listener protocol the query arrived on (udp, doh, dot, doq); None
+    /// for queries made through the API
+    pub protocol: Option<String>,
+    /// Why the query was not resolved normally, e.g.
+    /// "No Reachable Authority (22): upstream servers timed out"
+    pub extended_error: Option<String>,
+    /// External resolver the entry was ingested from; None for own queries
+    pub source: Option<String>,
+    /// "client" when the client ACL rejected the query, "domain" when a
+    /// rewrite rule or filter blocked the name
+    pub block_reason: Option<String>,
 }
 
 
@@ -148,6 +327,27 @@ pub struct CreateQueryLog {
     #[serde(default)]
     pub cache_hit: bool,
     pub upstream_used: Option<String>,
+    #[serde(default)]
+    pub tenant_id: Option<i64>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub answered_by: Option<String>,
+    #[serde(default)]
+    pub trace_id: Option<String>,
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: i64,
+    #[serde(default)]
+    pub protocol: Option<String>,
+    #[serde(default)]
+    pub extended_error: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub block_reason: Option<String>,
+    /// Time the query was made; now when not set
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
 }
 
 /// System config entity
@@ -168,6 +368,156 @@ pub struct QueryLogFilter {
     pub cache_hit: Option<bool>,
     pub start_time: Option<DateTime<Utc>>,
     pub end_time: Option<DateTime<Utc>>,
+    pub tenant_id: Option<i64>,
+    pub category: Option<String>,
+    pub trace_id: Option<String>,
+    pub protocol: Option<String>,
+    pub source: Option<String>,
+    pub block_reason: Option<String>,
+    /// Only queries taking at least this many milliseconds
+    #[serde(default)]
+    pub min_response_time: Option<i32>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+impl QueryLogFilter {
+    /// Fill the conditions left unset from a saved filter
+    pub fn or(self, saved: QueryLogFilter) -> Self {
+        Self {
+            query_name: self.query_name.or(saved.query_name),
+            query_type: self.query_type.or(saved.query_type),
+            client_ip: self.client_ip.or(saved.client_ip),
+            cache_hit: self.cache_hit.or(saved.cache_hit),
+            start_time: self.start_time.or(saved.start_time),
+            end_time: self.end_time.or(saved.end_time),
+            tenant_id: self.tenant_id.or(saved.tenant_id),
+            category: self.category.or(saved.category),
+            trace_id: self.trace_id.or(saved.trace_id),
+            protocol: self.protocol.or(saved.protocol),
+            source: self.source.or(saved.source),
+            block_reason: self.block_reason.or(saved.block_reason),
+            min_response_time: self.min_response_time.or(saved.min_response_time),
+            limit: self.limit.or(saved.limit),
+            offset: self.offset.or(saved.offset),
+        }
+    }
+}
+
+impl TryFrom<String> for QueryLogFilter {
+    type Error = serde_json::Error;
+
+    fn try_from(json: String) -> Result<Self, Self::Error> {
+        serde_json::from_str(&json)
+    }
+}
+
+/// Saved query log filter, e.g. "blocked by ACL" or "slow queries"
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SavedLogFilter {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    #[sqlx(try_from = "String")]
+    pub filter: QueryLogFilter,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Create saved log filter request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateSavedLogFilter {
+    pub name: String,
+    pub description: Option<String>,
+    pub filter: QueryLogFilter,
+}
+
+/// Update saved log filter request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateSavedLogFilter {
+    pub name: Option<String>,
+    /// Empty string clears the description
+    pub description: Option<String>,
+    pub filter: Option<QueryLogFilter>,
+}
+
+/// Severity of an in-app notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationSeverity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl NotificationSeverity {
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "info" => Some(Self::Info),
+            "warning" => Some(Self::Warning),
+            "critical" => Some(Self::Critical),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+/// Details attached to a notification, stored as a JSON object
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NotificationMetadata(pub serde_json::Map<String, serde_json::Value>);
+
+impl TryFrom<String> for NotificationMetadata {
+    type Error = serde_json::Error;
+
+    fn try_from(json: String) -> Result<Self, Self::Error> {
+        if json.is_empty() {
+            return Ok(Self::default());
+        }
+        serde_json::from_str(&json).map(Self)
+    }
+}
+
+/// Entry of the in-app notification inbox
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Notification {
+    pub id: i64,
+    /// info, warning or critical
+    pub severity: String,
+    /// Subsystem that raised it, e.g. `upstream` or `certificate`
+    pub source: String,
+    pub message: String,
+    #[sqlx(try_from = "String")]
+    pub metadata: NotificationMetadata,
+    pub read: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Create notification request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateNotification {
+    pub severity: NotificationSeverity,
+    pub source: String,
+    pub message: String,
+    pub metadata: NotificationMetadata,
+}
+
+/// Notification list filter
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NotificationFilter {
+    pub severity: Option<String>,
+    pub source: Option<String>,
+    /// Only unread notifications
+    #[serde(default)]
+    pub unread: bool,
     pub limit: Option<i64>,
     pub offset: Option<i64>,
 }
@@ -194,6 +544,10 @@ fn default_enabled() -> bool {
     true
 }
 
+fn default_sample_rate() -> i64 {
+    1
+}
+
 
 /// Server listener configuration entity
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -205,6 +559,16 @@ pub struct ServerListener {
     pub port: i32,
     pub tls_cert: Option<String>,
     pub tls_key: Option<String>,
+    /// Network interface the listener is bound to (Linux only)
+    pub interface: Option<String>,
+    /// Resolution profile used for queries on this listener
+    pub profile_id: Option<i64>,
+    /// Identical responses per second to one client network (UDP), 0 = off
+    pub rrl_responses_per_second: i32,
+    /// Every n-th rate-limited response is sent truncated, 0 = drop all
+    pub rrl_slip: i32,
+    /// recursive, authoritative_only or cache_only
+    pub resolution_mode: String,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -217,4 +581,449 @@ pub struct UpdateServerListener {
     pub port: Option<i32>,
     pub tls_cert: Option<String>,
     pub tls_key: Option<String>,
+    /// Empty string clears the interface binding
+    pub interface: Option<String>,
+    /// 0 clears the profile mapping
+    pub profile_id: Option<i64>,
+    pub rrl_responses_per_second: Option<i32>,
+    pub rrl_slip: Option<i32>,
+    pub resolution_mode: Option<String>,
+}
+
+/// Tenant entity
+///
+/// A tenant owns its own records, rewrite rules and query logs. DNS clients
+/// are mapped to a tenant by source subnet or by the listener they arrive on.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Tenant {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    /// SHA-256 digest of the API token; the token itself is only returned
+    /// when it is issued
+    #[serde(skip_serializing)]
+    pub api_token: String,
+    /// Comma-separated CIDR list, e.g. "10.1.0.0/16,192.168.5.0/24"
+    pub client_subnets: String,
+    /// Comma-separated listener protocols, e.g. "dot,doh"
+    pub listeners: String,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Tenant together with its newly issued API token
+///
+/// Returned by create and rotate, the only times the token is shown.
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantWithToken {
+    #[serde(flatten)]
+    pub tenant: Tenant,
+    pub api_token: String,
+}
+
+/// Create tenant request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateTenant {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub client_subnets: String,
+    #[serde(default)]
+    pub listeners: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// Update tenant request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateTenant {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub client_subnets: Option<String>,
+    pub listeners: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+/// Scoped API token
+///
+/// Least-privilege credential for scripts and CI systems; each request is
+/// checked against the granted scopes instead of full admin rights.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ApiToken {
+    pub id: i64,
+    pub name: String,
+    /// SHA-256 digest of the secret, which is only returned at creation
+    #[serde(skip_serializing)]
+    pub token: String,
+    /// Comma-separated scopes, e.g. "cache:purge,records:write"
+    pub scopes: String,
+    pub enabled: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    /// Granted scopes
+    pub fn scope_list(&self) -> Vec<&str> {
+        self.scopes
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+}
+
+/// API token together with its secret, returned only at creation
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiTokenWithSecret {
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+    pub token: String,
+}
+
+/// Create API token request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateApiToken {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Update API token request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateApiToken {
+    pub name: Option<String>,
+    pub scopes: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+}
+
+/// Admin account limited to DNS records inside its zones
+///
+/// A delegated admin for `web.internal` may manage `web.internal`,
+/// `app.web.internal` and `*.web.internal`, but nothing else.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DelegatedAdmin {
+    pub id: i64,
+    pub username: String,
+    #[serde(skip_serializing)]
+    pub password_hash: String,
+    /// Comma-separated zones, e.g. "web.internal,web.example.com"
+    pub zones: String,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_login_at: Option<DateTime<Utc>>,
+}
+
+impl DelegatedAdmin {
+    /// Delegated zones
+    pub fn zone_list(&self) -> Vec<&str> {
+        self.zones
+            .split(',')
+            .map(str::trim)
+            .filter(|z| !z.is_empty())
+            .collect()
+    }
+}
+
+/// Create delegated admin request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateDelegatedAdmin {
+    pub username: String,
+    pub password: String,
+    pub zones: Vec<String>,
+}
+
+/// Update delegated admin request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateDelegatedAdmin {
+    pub password: Option<String>,
+    pub zones: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+}
+
+/// Cache purge audit entry
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CachePurgeAudit {
+    pub id: i64,
+    /// Name of the purge token used, "admin" for purges through the admin
+    /// API or "grpc"
+    pub actor: String,
+    pub client_ip: String,
+    /// JSON array of the requested domains/patterns
+    pub patterns: String,
+    /// Number of cache entries removed
+    pub purged: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Create cache purge audit entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateCachePurgeAudit {
+    pub actor: String,
+    pub client_ip: String,
+    pub patterns: Vec<String>,
+    pub purged: i64,
+}
+
+/// Domain category list
+///
+/// A named set of domains sharing one category. Lists with a URL are
+/// downloaded (hosts-file or plain domain-per-line format) and refreshed
+/// periodically; lists without one are maintained by hand.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CategoryList {
+    pub id: i64,
+    pub name: String,
+    pub category: String,
+    pub url: Option<String>,
+    pub enabled: bool,
+    pub domain_count: i64,
+    pub last_updated_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Create category list request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateCategoryList {
+    pub name: String,
+    pub category: String,
+    pub url: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// Update category list request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateCategoryList {
+    pub name: Option<String>,
+    pub category: Option<String>,
+    pub url: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+/// Domain entry in a category list
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CategoryDomain {
+    pub id: i64,
+    pub list_id: i64,
+    pub domain: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Query counts for one category
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CategoryStat {
+    pub category: String,
+    pub queries: i64,
+    /// Queries answered by the category filter
+    pub blocked: i64,
+}
+
+/// Reference domain protected against typo-squatting lookalikes
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TyposquatDomain {
+    pub id: i64,
+    pub domain: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A query that looked like a typo of a reference domain
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TyposquatEvent {
+    pub id: i64,
+    pub query_name: String,
+    /// Reference domain the query most likely meant
+    pub suggestion: String,
+    /// Edit distance between the query and the suggestion
+    pub distance: i64,
+    pub client_ip: Option<String>,
+    /// Whether the query was blocked (otherwise it was only flagged)
+    pub blocked: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Create typo-squatting event request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateTyposquatEvent {
+    pub query_name: String,
+    pub suggestion: String,
+    pub distance: i64,
+    pub client_ip: Option<String>,
+    pub blocked: bool,
+}
+
+/// Upstream answer discrepancy found by the integrity monitor
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct IntegrityAlert {
+    pub id: i64,
+    pub domain: String,
+    pub record_type: String,
+    /// JSON array of per-upstream answers (server, response code, answer set)
+    pub answers: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Create integrity alert request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateIntegrityAlert {
+    pub domain: String,
+    pub record_type: String,
+    pub answers: String,
+}
+
+/// Named resolution profile (split DNS routing rules plus upstreams)
+///
+/// One profile is active at a time; it can be switched via the API or
+/// automatically based on the profile's reachability probe.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ResolutionProfile {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    /// Comma-separated upstream names in order of preference; empty uses
+    /// the global query strategy
+    pub upstreams: String,
+    /// Comma-separated `suffix=upstream` routes, e.g. "corp.example.com=corp-dns"
+    pub rules: String,
+    /// `ip:port` that must accept a TCP connection for the profile to be
+    /// selected automatically; empty means always eligible
+    pub probe_target: Option<String>,
+    /// Higher priority profiles are preferred by automatic switching
+    pub priority: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Create resolution profile request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateResolutionProfile {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub upstreams: String,
+    #[serde(default)]
+    pub rules: String,
+    pub probe_target: Option<String>,
+    #[serde(default)]
+    pub priority: i64,
+}
+
+/// Update resolution profile request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateResolutionProfile {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub upstreams: Option<String>,
+    pub rules: Option<String>,
+    /// Empty string clears the probe
+    pub probe_target: Option<String>,
+    pub priority: Option<i64>,
+}
+
+/// RPZ feed entity
+///
+/// A Response Policy Zone downloaded from a URL whose QNAME policies are
+/// kept as rewrite rules tagged with the feed.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RpzFeed {
+    pub id: i64,
+    pub name: String,
+    pub url: String,
+    pub enabled: bool,
+    /// Priority of the imported rewrite rules
+    pub priority: i32,
+    pub refresh_interval_secs: i64,
+    /// SOA serial of the last import
+    pub serial: Option<i64>,
+    pub rule_count: i64,
+    /// Records without a rewrite equivalent in the last import
+    pub skipped_count: i64,
+    pub last_checked_at: Option<DateTime<Utc>>,
+    /// When the rules were last replaced
+    pub last_changed_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Create RPZ feed request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateRpzFeed {
+    pub name: String,
+    pub url: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default = "default_rpz_refresh_interval")]
+    pub refresh_interval_secs: i64,
+}
+
+fn default_rpz_refresh_interval() -> i64 {
+    3600
+}
+
+/// Update RPZ feed request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateRpzFeed {
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub enabled: Option<bool>,
+    pub priority: Option<i32>,
+    pub refresh_interval_secs: Option<i64>,
+}
+
+/// Record group entity
+///
+/// A service whose records (A, AAAA, TXT, SRV, ...) live under one domain
+/// and are created, renamed, toggled and deleted together.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RecordGroup {
+    pub id: i64,
+    pub name: String,
+    /// Service domain the member records are named under
+    pub domain: String,
+    pub description: Option<String>,
+    /// Owning tenant of the member records (None = global)
+    pub tenant_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Create record group request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateRecordGroup {
+    pub name: String,
+    pub domain: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tenant_id: Option<i64>,
+}
+
+/// Update record group request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateRecordGroup {
+    pub name: Option<String>,
+    pub domain: Option<String>,
+    pub description: Option<String>,
+}
+
+/// One domain's leaderboard counters for one hour
+///
+/// Snapshot of an hourly top-domains sketch, reloaded on startup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct DomainStatHour {
+    /// Hours since the Unix epoch
+    pub hour: i64,
+    pub domain: String,
+    pub queries: i64,
+    pub blocked: i64,
+    pub response_time_total: i64,
+    pub response_time_samples: i64,
+    /// How much `queries` may overstate the true count
+    pub overcount: i64,
 }
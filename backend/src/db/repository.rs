@@ -5,9 +5,11 @@
 use anyhow::Result;
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use sqlx::SqlitePool;
+use sha2::{Digest, Sha256};
+use sqlx::{SqliteConnection, SqlitePool};
 
 use super::models::*;
+use crate::dns::normalize_name;
 
 /// Repository for DNS records
 pub struct DnsRecordRepository {
@@ -21,11 +23,16 @@ impl DnsRecordRepository {
 
     /// Create a new DNS record
     pub async fn create(&self, record: CreateDnsRecord) -> Result<DnsRecord> {
+        Self::create_on(&mut *self.pool.acquire().await?, record).await
+    }
+
+    /// Create a DNS record on a connection, e.g. inside a transaction
+    pub async fn create_on(conn: &mut SqliteConnection, record: CreateDnsRecord) -> Result<DnsRecord> {
         let now = Utc::now();
         let result = sqlx::query_as::<_, DnsRecord>(
             r#"
-            INSERT INTO dns_records (name, record_type, value, ttl, priority, enabled, created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO dns_records (name, record_type, value, ttl, priority, enabled, created_at, updated_at, tenant_id, description, tags, expires_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             RETURNING *
             "#,
         )
@@ -37,12 +44,53 @@ impl DnsRecordRepository {
         .bind(record.enabled)
         .bind(now)
         .bind(now)
-        .fetch_one(&self.pool)
+        .bind(record.tenant_id)
+        .bind(&record.description)
+        .bind(record.tags.to_json())
+        .bind(record.expires_at)
+        .fetch_one(&mut *conn)
         .await?;
 
         Ok(result)
     }
 
+    /// Create several DNS records in one transaction
+    ///
+    /// Either every record is created, in the given order, or none is.
+    pub async fn create_many(&self, records: Vec<CreateDnsRecord>) -> Result<Vec<DnsRecord>> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+        let mut created = Vec::with_capacity(records.len());
+
+        for record in records {
+            let row = sqlx::query_as::<_, DnsRecord>(
+                r#"
+                INSERT INTO dns_records (name, record_type, value, ttl, priority, enabled, created_at, updated_at, tenant_id, description, tags, expires_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                RETURNING *
+                "#,
+            )
+            .bind(&record.name)
+            .bind(&record.record_type)
+            .bind(&record.value)
+            .bind(record.ttl)
+            .bind(record.priority)
+            .bind(record.enabled)
+            .bind(now)
+            .bind(now)
+            .bind(record.tenant_id)
+            .bind(&record.description)
+            .bind(record.tags.to_json())
+            .bind(record.expires_at)
+            .fetch_one(&mut *tx)
+            .await?;
+            created.push(row);
+        }
+
+        tx.commit().await?;
+        Ok(created)
+    }
+
     /// Get a DNS record by ID
     pub async fn get_by_id(&self, id: i64) -> Result<Option<DnsRecord>> {
         let result = sqlx::query_as::<_, DnsRecord>(
@@ -70,6 +118,7 @@ impl DnsRecordRepository {
 
 
     /// Get DNS records by name and type
+    #[allow(dead_code)]
     pub async fn get_by_name_and_type(&self, name: &str, record_type: &str) -> Result<Vec<DnsRecord>> {
         let result = sqlx::query_as::<_, DnsRecord>(
             "SELECT * FROM dns_records WHERE name = ? AND record_type = ? AND enabled = TRUE",
@@ -83,73 +132,97 @@ impl DnsRecordRepository {
     }
 
     /// Get DNS records by name and type with wildcard support
-    /// 
-    /// This method first tries exact match, then falls back to wildcard matching.
-    /// Wildcard records are stored as `*.example.com` and match any subdomain.
-    /// 
-    /// Matching priority (highest to lowest):
-    /// 1. Exact match: `sub.example.com`
-    /// 2. Direct wildcard: `*.example.com` matches `sub.example.com`
-    /// 3. Parent wildcard: `*.example.com` matches `a.b.example.com`
+    ///
+    /// Only global records are considered; see `match_for_tenant` for the
+    /// matching rules.
     pub async fn get_by_name_and_type_with_wildcard(&self, name: &str, record_type: &str) -> Result<Vec<DnsRecord>> {
-        // Build all possible wildcard names
-        // For "a.b.example.com", generate: ["*.b.example.com", "*.example.com", "*.com"]
-        let parts: Vec<&str> = name.split('.').collect();
-        let mut wildcard_names: Vec<String> = Vec::with_capacity(parts.len());
-        
-        for i in 1..parts.len() {
-            wildcard_names.push(format!("*.{}", parts[i..].join(".")));
-        }
+        self.get_by_name_and_type_for_tenant(name, record_type, None).await
+    }
+
+    /// Get DNS records by name and type as seen from a tenant's view
+    ///
+    /// Returns the records of `match_for_tenant`, or nothing when no local
+    /// name matches.
+    pub async fn get_by_name_and_type_for_tenant(
+        &self,
+        name: &str,
+        record_type: &str,
+        tenant_id: Option<i64>,
+    ) -> Result<Vec<DnsRecord>> {
+        Ok(self
+            .match_for_tenant(name, record_type, tenant_id)
+            .await?
+            .map(|m| m.records)
+            .unwrap_or_default())
+    }
 
-        // Query exact match and all possible wildcards in one query
-        // Results are ordered: exact match first, then wildcards from most specific to least
-        if wildcard_names.is_empty() {
-            // No wildcards possible (e.g., "com"), just do exact match
-            return self.get_by_name_and_type(name, record_type).await;
+    /// Find the local records answering a query name
+    ///
+    /// Wildcard records are stored as `*.example.com` and match names at any
+    /// depth below `example.com`, but not `example.com` itself. Names compare
+    /// case-insensitively and ignore a trailing dot. The most specific name
+    /// that owns any enabled, unexpired record wins, in this order:
+    ///
+    /// 1. Exact name: `a.b.example.com`
+    /// 2. Closest wildcard: `*.b.example.com`
+    /// 3. Farther wildcards: `*.example.com`, then `*.com`
+    ///
+    /// The winning name owns the answer for every record type, so an explicit
+    /// record overrides wildcards even for types it doesn't have, and a closer
+    /// wildcard hides farther ones; the returned records may then be empty.
+    /// A tenant sees global records plus its own; for the same name the
+    /// tenant's records shadow the global ones.
+    pub async fn match_for_tenant(
+        &self,
+        name: &str,
+        record_type: &str,
+        tenant_id: Option<i64>,
+    ) -> Result<Option<RecordMatch>> {
+        let candidates = record_name_candidates(name);
+        if candidates.is_empty() {
+            return Ok(None);
         }
 
-        // Build placeholders for IN clause
-        let placeholders: Vec<&str> = wildcard_names.iter().map(|_| "?").collect();
+        // Fetch every type so names owning other types still block wildcards
+        let placeholders = vec!["?"; candidates.len()].join(", ");
         let query = format!(
             r#"
-            SELECT * FROM dns_records 
-            WHERE record_type = ? AND enabled = TRUE AND name IN (?, {})
-            ORDER BY 
-                CASE 
-                    WHEN name = ? THEN 0 
-                    ELSE 1
-                END ASC,
-                LENGTH(name) DESC
+            SELECT * FROM dns_records
+            WHERE enabled = TRUE AND RTRIM(LOWER(name), '.') IN ({})
+              AND (tenant_id IS NULL OR tenant_id = ?)
+              AND (expires_at IS NULL OR expires_at > ?)
             "#,
-            placeholders.join(", ")
+            placeholders
         );
-
-        let mut query_builder = sqlx::query_as::<_, DnsRecord>(&query)
-            .bind(record_type)
-            .bind(name);  // Exact match
-        
-        for wildcard in &wildcard_names {
-            query_builder = query_builder.bind(wildcard);
+        let mut query_builder = sqlx::query_as::<_, DnsRecord>(&query);
+        for candidate in &candidates {
+            query_builder = query_builder.bind(candidate);
         }
-        
-        query_builder = query_builder.bind(name);  // For ORDER BY CASE
-
-        let results = query_builder.fetch_all(&self.pool).await?;
+        let results = query_builder
+            .bind(tenant_id)
+            .bind(Utc::now())
+            .fetch_all(&self.pool)
+            .await?;
 
-        // Return exact match if found, otherwise return the most specific wildcard
-        if results.is_empty() {
-            return Ok(vec![]);
+        let normalized = |r: &DnsRecord| r.name.trim_end_matches('.').to_lowercase();
+        for candidate in candidates {
+            let owned: Vec<&DnsRecord> = results.iter().filter(|r| normalized(r) == candidate).collect();
+            if !owned.is_empty() {
+                return Ok(Some(owner_match(candidate, owned, record_type, tenant_id)));
+            }
         }
+        Ok(None)
+    }
 
-        // Check if first result is exact match
-        if results[0].name == name {
-            // Return only exact matches
-            return Ok(results.into_iter().filter(|r| r.name == name).collect());
-        }
+    /// List enabled DNS records
+    pub async fn list_enabled(&self) -> Result<Vec<DnsRecord>> {
+        let result = sqlx::query_as::<_, DnsRecord>(
+            "SELECT * FROM dns_records WHERE enabled = TRUE ORDER BY name, record_type",
+        )
+        .fetch_all(&self.pool)
+        .await?;
 
-        // Return the most specific wildcard matches (all with the same name)
-        let most_specific = results[0].name.clone();
-        Ok(results.into_iter().filter(|r| r.name == most_specific).collect())
+        Ok(result)
     }
 
     /// List all DNS records
@@ -163,13 +236,46 @@ impl DnsRecordRepository {
         Ok(result)
     }
 
+    /// List DNS records owned by a tenant
+    pub async fn list_by_tenant(&self, tenant_id: i64) -> Result<Vec<DnsRecord>> {
+        let result = sqlx::query_as::<_, DnsRecord>(
+            "SELECT * FROM dns_records WHERE tenant_id = ? ORDER BY name, record_type",
+        )
+        .bind(tenant_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
     /// Update a DNS record
+    #[allow(dead_code)]
     pub async fn update(&self, id: i64, update: UpdateDnsRecord) -> Result<Option<DnsRecord>> {
-        let existing = self.get_by_id(id).await?;
-        if existing.is_none() {
+        self.update_at_version(id, update, None).await
+    }
+
+    /// Update a DNS record if it is still at `version` (its `updated_at`)
+    ///
+    /// Returns None when the DNS record is gone or was modified since.
+    pub async fn update_at_version(
+        &self,
+        id: i64,
+        update: UpdateDnsRecord,
+        version: Option<chrono::DateTime<Utc>>,
+    ) -> Result<Option<DnsRecord>> {
+        Self::update_on(&mut *self.pool.acquire().await?, id, update, version).await
+    }
+
+    /// Update a DNS record on a connection, e.g. inside a transaction
+    pub async fn update_on(
+        conn: &mut SqliteConnection,
+        id: i64,
+        update: UpdateDnsRecord,
+        version: Option<chrono::DateTime<Utc>>,
+    ) -> Result<Option<DnsRecord>> {
+        let Some((existing, stored_version)) = fetch_at_version::<DnsRecord>(&mut *conn, "dns_records", id, version).await? else {
             return Ok(None);
-        }
-        let existing = existing.unwrap();
+        };
 
         let name = update.name.unwrap_or(existing.name);
         let record_type = update.record_type.unwrap_or(existing.record_type);
@@ -177,12 +283,15 @@ impl DnsRecordRepository {
         let ttl = update.ttl.unwrap_or(existing.ttl);
         let priority = update.priority.unwrap_or(existing.priority);
         let enabled = update.enabled.unwrap_or(existing.enabled);
+        let description = update.description.or(existing.description);
+        let tags = update.tags.unwrap_or(existing.tags);
+        let expires_at = update.expires_at.unwrap_or(existing.expires_at);
 
         let result = sqlx::query_as::<_, DnsRecord>(
             r#"
             UPDATE dns_records 
-            SET name = ?, record_type = ?, value = ?, ttl = ?, priority = ?, enabled = ?, updated_at = ?
-            WHERE id = ?
+            SET name = ?, record_type = ?, value = ?, ttl = ?, priority = ?, enabled = ?, description = ?, tags = ?, expires_at = ?, updated_at = ?
+            WHERE id = ? AND (? IS NULL OR updated_at = ?)
             RETURNING *
             "#,
         )
@@ -192,25 +301,204 @@ impl DnsRecordRepository {
         .bind(ttl)
         .bind(priority)
         .bind(enabled)
+        .bind(&description)
+        .bind(tags.to_json())
+        .bind(expires_at)
         .bind(Utc::now())
         .bind(id)
-        .fetch_optional(&self.pool)
+        .bind(&stored_version)
+        .bind(&stored_version)
+        .fetch_optional(&mut *conn)
         .await?;
 
         Ok(result)
     }
 
     /// Delete a DNS record
+    #[allow(dead_code)]
     pub async fn delete(&self, id: i64) -> Result<bool> {
+        Self::delete_on(&mut *self.pool.acquire().await?, id).await
+    }
+
+    /// Delete a DNS record on a connection, e.g. inside a transaction
+    pub async fn delete_on(conn: &mut SqliteConnection, id: i64) -> Result<bool> {
         let result = sqlx::query("DELETE FROM dns_records WHERE id = ?")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *conn)
             .await?;
 
         Ok(result.rows_affected() > 0)
     }
+
+    /// Delete a DNS record if it is still at `version` (its `updated_at`)
+    pub async fn delete_at_version(&self, id: i64, version: Option<chrono::DateTime<Utc>>) -> Result<bool> {
+        delete_at_version_on::<DnsRecord>(&mut *self.pool.acquire().await?, "dns_records", id, version).await
+    }
+
+    /// Enable or disable every record carrying a tag
+    ///
+    /// With a tenant, only that tenant's records are changed.
+    /// Returns the number of records changed.
+    pub async fn set_enabled_by_tag(&self, tag: &str, enabled: bool, tenant_id: Option<i64>) -> Result<u64> {
+        let result = sqlx::query(&format!(
+            "UPDATE dns_records SET enabled = ?, updated_at = ? WHERE enabled != ? AND {}",
+            TAGGED_IN_SCOPE
+        ))
+        .bind(enabled)
+        .bind(Utc::now())
+        .bind(enabled)
+        .bind(tag)
+        .bind(tenant_id)
+        .bind(tenant_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Delete every record carrying a tag
+    ///
+    /// With a tenant, only that tenant's records are deleted.
+    /// Returns the number of records deleted.
+    pub async fn delete_by_tag(&self, tag: &str, tenant_id: Option<i64>) -> Result<u64> {
+        let result = sqlx::query(&format!("DELETE FROM dns_records WHERE {}", TAGGED_IN_SCOPE))
+            .bind(tag)
+            .bind(tenant_id)
+            .bind(tenant_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Delete records whose expiration time has passed
+    ///
+    /// Returns the number of records deleted.
+    pub async fn delete_expired(&self, now: chrono::DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM dns_records WHERE expires_at IS NOT NULL AND expires_at <= ?")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// A row and, when `version` is given, its stored `updated_at` text
+///
+/// Returns None when the row is gone or was modified since `version`.
+/// Writes put the stored text in their WHERE clause, so a writer getting in
+/// between the read and the write still fails the check.
+async fn fetch_at_version<T>(
+    conn: &mut SqliteConnection,
+    table: &str,
+    id: i64,
+    version: Option<chrono::DateTime<Utc>>,
+) -> Result<Option<(T, Option<String>)>>
+where
+    T: for<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow>,
+{
+    use sqlx::Row;
+
+    let row = sqlx::query(&format!(
+        "SELECT *, CAST(updated_at AS TEXT) AS stored_version FROM {} WHERE id = ?",
+        table
+    ))
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let stored_version = match version {
+        Some(version) if row.try_get::<chrono::DateTime<Utc>, _>("updated_at")? != version => return Ok(None),
+        Some(_) => Some(row.try_get::<String, _>("stored_version")?),
+        None => None,
+    };
+    Ok(Some((T::from_row(&row)?, stored_version)))
+}
+
+/// Delete a row if it is still at `version`; see [`fetch_at_version`]
+async fn delete_at_version_on<T>(
+    conn: &mut SqliteConnection,
+    table: &str,
+    id: i64,
+    version: Option<chrono::DateTime<Utc>>,
+) -> Result<bool>
+where
+    T: for<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow>,
+{
+    let Some((_, stored_version)) = fetch_at_version::<T>(&mut *conn, table, id, version).await? else {
+        return Ok(false);
+    };
+    let result = sqlx::query(&format!(
+        "DELETE FROM {} WHERE id = ? AND (? IS NULL OR updated_at = ?)",
+        table
+    ))
+    .bind(id)
+    .bind(&stored_version)
+    .bind(&stored_version)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Row filter for tag bulk operations; binds the tag, then the tenant twice
+const TAGGED_IN_SCOPE: &str =
+    "EXISTS (SELECT 1 FROM json_each(tags) WHERE json_each.value = ?) AND (? IS NULL OR tenant_id = ?)";
+
+/// Record names that can answer a query name, most specific first
+///
+/// For `a.b.example.com`: `a.b.example.com`, `*.b.example.com`,
+/// `*.example.com`, `*.com`.
+pub(crate) fn record_name_candidates(name: &str) -> Vec<String> {
+    let name = normalize_name(name);
+    if name.is_empty() {
+        return Vec::new();
+    }
+    let labels: Vec<&str> = name.split('.').collect();
+    let mut candidates = Vec::with_capacity(labels.len());
+    for i in 1..labels.len() {
+        candidates.push(format!("*.{}", labels[i..].join(".")));
+    }
+    candidates.insert(0, name);
+    candidates
+}
+
+/// Build the match for the winning name from the enabled records it owns
+///
+/// `owned` holds the global records and the tenant's own records under the
+/// name; the tenant's records shadow the global ones when it has any.
+pub(crate) fn owner_match(
+    matched_name: String,
+    owned: Vec<&DnsRecord>,
+    record_type: &str,
+    tenant_id: Option<i64>,
+) -> RecordMatch {
+    let owner = if owned.iter().any(|r| r.tenant_id.is_some()) {
+        tenant_id
+    } else {
+        None
+    };
+    let records = owned
+        .into_iter()
+        .filter(|r| r.tenant_id == owner && r.record_type == record_type)
+        .cloned()
+        .collect();
+    RecordMatch {
+        matched_name,
+        records,
+    }
 }
 
+/// Hex SHA-256 digest under which an API token is stored
+///
+/// Tokens are long random strings, so an unsalted digest still allows lookup
+/// by token while a leaked table does not hand out working credentials.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
 
 /// Repository for rewrite rules
 pub struct RewriteRuleRepository {
@@ -224,11 +512,16 @@ impl RewriteRuleRepository {
 
     /// Create a new rewrite rule
     pub async fn create(&self, rule: CreateRewriteRule) -> Result<RewriteRule> {
+        Self::create_on(&mut *self.pool.acquire().await?, rule).await
+    }
+
+    /// Create a rewrite rule on a connection, e.g. inside a transaction
+    pub async fn create_on(conn: &mut SqliteConnection, rule: CreateRewriteRule) -> Result<RewriteRule> {
         let now = Utc::now();
         let result = sqlx::query_as::<_, RewriteRule>(
             r#"
-            INSERT INTO rewrite_rules (pattern, match_type, action_type, action_value, priority, enabled, description, created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO rewrite_rules (pattern, match_type, action_type, action_value, priority, enabled, description, created_at, updated_at, tenant_id, shadow, shadow_of, tags, expires_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             RETURNING *
             "#,
         )
@@ -241,7 +534,12 @@ impl RewriteRuleRepository {
         .bind(&rule.description)
         .bind(now)
         .bind(now)
-        .fetch_one(&self.pool)
+        .bind(rule.tenant_id)
+        .bind(rule.shadow)
+        .bind(rule.shadow_of)
+        .bind(rule.tags.to_json())
+        .bind(rule.expires_at)
+        .fetch_one(&mut *conn)
         .await?;
 
         Ok(result)
@@ -270,6 +568,18 @@ impl RewriteRuleRepository {
         Ok(result)
     }
 
+    /// List rewrite rules owned by a tenant ordered by priority
+    pub async fn list_by_tenant(&self, tenant_id: i64) -> Result<Vec<RewriteRule>> {
+        let result = sqlx::query_as::<_, RewriteRule>(
+            "SELECT * FROM rewrite_rules WHERE tenant_id = ? ORDER BY priority DESC, id ASC",
+        )
+        .bind(tenant_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
     /// List enabled rewrite rules ordered by priority
     #[allow(dead_code)]
     pub async fn list_enabled(&self) -> Result<Vec<RewriteRule>> {
@@ -284,12 +594,33 @@ impl RewriteRuleRepository {
 
 
     /// Update a rewrite rule
+    #[allow(dead_code)]
     pub async fn update(&self, id: i64, update: UpdateRewriteRule) -> Result<Option<RewriteRule>> {
-        let existing = self.get_by_id(id).await?;
-        if existing.is_none() {
+        self.update_at_version(id, update, None).await
+    }
+
+    /// Update a rewrite rule if it is still at `version` (its `updated_at`)
+    ///
+    /// Returns None when the rewrite rule is gone or was modified since.
+    pub async fn update_at_version(
+        &self,
+        id: i64,
+        update: UpdateRewriteRule,
+        version: Option<chrono::DateTime<Utc>>,
+    ) -> Result<Option<RewriteRule>> {
+        Self::update_on(&mut *self.pool.acquire().await?, id, update, version).await
+    }
+
+    /// Update a rewrite rule on a connection, e.g. inside a transaction
+    pub async fn update_on(
+        conn: &mut SqliteConnection,
+        id: i64,
+        update: UpdateRewriteRule,
+        version: Option<chrono::DateTime<Utc>>,
+    ) -> Result<Option<RewriteRule>> {
+        let Some((existing, stored_version)) = fetch_at_version::<RewriteRule>(&mut *conn, "rewrite_rules", id, version).await? else {
             return Ok(None);
-        }
-        let existing = existing.unwrap();
+        };
 
         let pattern = update.pattern.unwrap_or(existing.pattern);
         let match_type = update.match_type.unwrap_or(existing.match_type);
@@ -298,12 +629,14 @@ impl RewriteRuleRepository {
         let priority = update.priority.unwrap_or(existing.priority);
         let enabled = update.enabled.unwrap_or(existing.enabled);
         let description = update.description.or(existing.description);
+        let tags = update.tags.unwrap_or(existing.tags);
+        let expires_at = update.expires_at.unwrap_or(existing.expires_at);
 
         let result = sqlx::query_as::<_, RewriteRule>(
             r#"
             UPDATE rewrite_rules 
-            SET pattern = ?, match_type = ?, action_type = ?, action_value = ?, priority = ?, enabled = ?, description = ?, updated_at = ?
-            WHERE id = ?
+            SET pattern = ?, match_type = ?, action_type = ?, action_value = ?, priority = ?, enabled = ?, description = ?, tags = ?, expires_at = ?, updated_at = ?
+            WHERE id = ? AND (? IS NULL OR updated_at = ?)
             RETURNING *
             "#,
         )
@@ -314,24 +647,87 @@ impl RewriteRuleRepository {
         .bind(priority)
         .bind(enabled)
         .bind(&description)
+        .bind(tags.to_json())
+        .bind(expires_at)
         .bind(Utc::now())
         .bind(id)
-        .fetch_optional(&self.pool)
+        .bind(&stored_version)
+        .bind(&stored_version)
+        .fetch_optional(&mut *conn)
         .await?;
 
         Ok(result)
     }
 
     /// Delete a rewrite rule
+    #[allow(dead_code)]
     pub async fn delete(&self, id: i64) -> Result<bool> {
+        Self::delete_on(&mut *self.pool.acquire().await?, id).await
+    }
+
+    /// Delete a rewrite rule on a connection, e.g. inside a transaction
+    pub async fn delete_on(conn: &mut SqliteConnection, id: i64) -> Result<bool> {
         let result = sqlx::query("DELETE FROM rewrite_rules WHERE id = ?")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *conn)
             .await?;
 
         Ok(result.rows_affected() > 0)
     }
 
+    /// Delete a rewrite rule if it is still at `version` (its `updated_at`)
+    pub async fn delete_at_version(&self, id: i64, version: Option<chrono::DateTime<Utc>>) -> Result<bool> {
+        delete_at_version_on::<RewriteRule>(&mut *self.pool.acquire().await?, "rewrite_rules", id, version).await
+    }
+
+    /// Enable or disable every rule carrying a tag
+    ///
+    /// With a tenant, only that tenant's rules are changed.
+    /// Returns the number of rules changed.
+    pub async fn set_enabled_by_tag(&self, tag: &str, enabled: bool, tenant_id: Option<i64>) -> Result<u64> {
+        let result = sqlx::query(&format!(
+            "UPDATE rewrite_rules SET enabled = ?, updated_at = ? WHERE enabled != ? AND {}",
+            TAGGED_IN_SCOPE
+        ))
+        .bind(enabled)
+        .bind(Utc::now())
+        .bind(enabled)
+        .bind(tag)
+        .bind(tenant_id)
+        .bind(tenant_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Delete every rule carrying a tag
+    ///
+    /// With a tenant, only that tenant's rules are deleted.
+    /// Returns the number of rules deleted.
+    pub async fn delete_by_tag(&self, tag: &str, tenant_id: Option<i64>) -> Result<u64> {
+        let result = sqlx::query(&format!("DELETE FROM rewrite_rules WHERE {}", TAGGED_IN_SCOPE))
+            .bind(tag)
+            .bind(tenant_id)
+            .bind(tenant_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Delete rules whose expiration time has passed
+    ///
+    /// Returns the number of rules deleted.
+    pub async fn delete_expired(&self, now: chrono::DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM rewrite_rules WHERE expires_at IS NOT NULL AND expires_at <= ?")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
     /// Batch create rewrite rules
     /// Returns the number of rules created
     pub async fn batch_create(&self, rules: Vec<CreateRewriteRule>) -> Result<i64> {
@@ -348,8 +744,53 @@ impl RewriteRuleRepository {
         for rule in rules {
             sqlx::query(
                 r#"
-                INSERT INTO rewrite_rules (pattern, match_type, action_type, action_value, priority, enabled, description, created_at, updated_at)
-                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                INSERT INTO rewrite_rules (pattern, match_type, action_type, action_value, priority, enabled, description, created_at, updated_at, tenant_id, shadow, shadow_of, tags, expires_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&rule.pattern)
+            .bind(&rule.match_type)
+            .bind(&rule.action_type)
+            .bind(&rule.action_value)
+            .bind(rule.priority)
+            .bind(rule.enabled)
+            .bind(&rule.description)
+            .bind(now)
+            .bind(now)
+            .bind(rule.tenant_id)
+            .bind(rule.shadow)
+            .bind(rule.shadow_of)
+            .bind(rule.tags.to_json())
+            .bind(rule.expires_at)
+            .execute(&mut *tx)
+            .await?;
+            count += 1;
+        }
+
+        tx.commit().await?;
+        Ok(count)
+    }
+
+    /// Replace every rule carrying a tag with new rules in one transaction
+    ///
+    /// Returns the number of rules created.
+    pub async fn replace_by_tag(&self, tag: &str, rules: Vec<CreateRewriteRule>) -> Result<i64> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(&format!("DELETE FROM rewrite_rules WHERE {}", TAGGED_IN_SCOPE))
+            .bind(tag)
+            .bind(None::<i64>)
+            .bind(None::<i64>)
+            .execute(&mut *tx)
+            .await?;
+
+        let mut count = 0i64;
+        for rule in rules {
+            sqlx::query(
+                r#"
+                INSERT INTO rewrite_rules (pattern, match_type, action_type, action_value, priority, enabled, description, created_at, updated_at, tenant_id, shadow, shadow_of, tags, expires_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
                 "#,
             )
             .bind(&rule.pattern)
@@ -361,6 +802,11 @@ impl RewriteRuleRepository {
             .bind(&rule.description)
             .bind(now)
             .bind(now)
+            .bind(rule.tenant_id)
+            .bind(rule.shadow)
+            .bind(rule.shadow_of)
+            .bind(rule.tags.to_json())
+            .bind(rule.expires_at)
             .execute(&mut *tx)
             .await?;
             count += 1;
@@ -369,6 +815,67 @@ impl RewriteRuleRepository {
         tx.commit().await?;
         Ok(count)
     }
+
+    /// Make a shadow rule active
+    ///
+    /// The rule it shadows, if any, is deleted in the same transaction so
+    /// the edited version replaces it.
+    pub async fn promote(&self, id: i64) -> Result<Option<RewriteRule>> {
+        let mut tx = self.pool.begin().await?;
+
+        let shadow_of: Option<(Option<i64>,)> =
+            sqlx::query_as("SELECT shadow_of FROM rewrite_rules WHERE id = ? AND shadow = TRUE")
+                .bind(id)
+                .fetch_optional(&mut *tx)
+                .await?;
+        let Some((shadow_of,)) = shadow_of else {
+            return Ok(None);
+        };
+
+        if let Some(original) = shadow_of {
+            sqlx::query("DELETE FROM rewrite_rules WHERE id = ? AND shadow = FALSE")
+                .bind(original)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let rule = sqlx::query_as::<_, RewriteRule>(
+            r#"
+            UPDATE rewrite_rules
+            SET shadow = FALSE, shadow_of = NULL, updated_at = ?
+            WHERE id = ?
+            RETURNING *
+            "#,
+        )
+        .bind(Utc::now())
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(rule)
+    }
+
+    /// Add would-have-matched counts to shadow rules
+    pub async fn add_shadow_hits(&self, hits: &[(i64, u64, chrono::DateTime<Utc>)]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for (id, count, last_hit) in hits {
+            sqlx::query(
+                r#"
+                UPDATE rewrite_rules
+                SET shadow_hits = shadow_hits + ?, shadow_last_hit_at = ?
+                WHERE id = ? AND shadow = TRUE
+                "#,
+            )
+            .bind(*count as i64)
+            .bind(last_hit)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
 }
 pub struct UpstreamServerRepository {
     pool: SqlitePool,
@@ -379,13 +886,18 @@ impl UpstreamServerRepository {
         Self { pool }
     }
 
-    /// Create a new upstream server
+    /// Create a new upstream server, placed last in the configured order
     pub async fn create(&self, server: CreateUpstreamServer) -> Result<UpstreamServer> {
+        Self::create_on(&mut *self.pool.acquire().await?, server).await
+    }
+
+    /// Create an upstream server on a connection, e.g. inside a transaction
+    pub async fn create_on(conn: &mut SqliteConnection, server: CreateUpstreamServer) -> Result<UpstreamServer> {
         let now = Utc::now();
         let result = sqlx::query_as::<_, UpstreamServer>(
             r#"
-            INSERT INTO upstream_servers (name, address, protocol, timeout, enabled, created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO upstream_servers (name, address, protocol, timeout, enabled, source_ip, source_interface, tls_server_name, verify_hostname, doh_method, doh_http_version, created_at, updated_at, sort_order)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM upstream_servers))
             RETURNING *
             "#,
         )
@@ -394,9 +906,15 @@ impl UpstreamServerRepository {
         .bind(&server.protocol)
         .bind(server.timeout)
         .bind(server.enabled)
+        .bind(server.source_ip.filter(|s| !s.is_empty()))
+        .bind(server.source_interface.filter(|s| !s.is_empty()))
+        .bind(server.tls_server_name.filter(|s| !s.is_empty()))
+        .bind(server.verify_hostname)
+        .bind(server.doh_method.filter(|s| !s.is_empty()))
+        .bind(server.doh_http_version.filter(|s| !s.is_empty()))
         .bind(now)
         .bind(now)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *conn)
         .await?;
 
         Ok(result)
@@ -414,10 +932,10 @@ impl UpstreamServerRepository {
         Ok(result)
     }
 
-    /// List all upstream servers
+    /// List all upstream servers in the configured order
     pub async fn list(&self) -> Result<Vec<UpstreamServer>> {
         let result = sqlx::query_as::<_, UpstreamServer>(
-            "SELECT * FROM upstream_servers ORDER BY id",
+            "SELECT * FROM upstream_servers ORDER BY sort_order, id",
         )
         .fetch_all(&self.pool)
         .await?;
@@ -430,7 +948,7 @@ impl UpstreamServerRepository {
         let offset = (page - 1) * page_size;
         
         let result = sqlx::query_as::<_, UpstreamServer>(
-            "SELECT * FROM upstream_servers ORDER BY id LIMIT ? OFFSET ?",
+            "SELECT * FROM upstream_servers ORDER BY sort_order, id LIMIT ? OFFSET ?",
         )
         .bind(page_size)
         .bind(offset)
@@ -444,10 +962,10 @@ impl UpstreamServerRepository {
         Ok((result, total.0))
     }
 
-    /// List enabled upstream servers
+    /// List enabled upstream servers in the configured order
     pub async fn list_enabled(&self) -> Result<Vec<UpstreamServer>> {
         let result = sqlx::query_as::<_, UpstreamServer>(
-            "SELECT * FROM upstream_servers WHERE enabled = TRUE ORDER BY id",
+            "SELECT * FROM upstream_servers WHERE enabled = TRUE ORDER BY sort_order, id",
         )
         .fetch_all(&self.pool)
         .await?;
@@ -455,26 +973,91 @@ impl UpstreamServerRepository {
         Ok(result)
     }
 
+    /// Store a new order; `ids` lists every upstream server, first to last
+    pub async fn reorder(&self, ids: &[i64]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for (position, id) in ids.iter().enumerate() {
+            sqlx::query("UPDATE upstream_servers SET sort_order = ? WHERE id = ?")
+                .bind(position as i64 + 1)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
 
     /// Update an upstream server
+    #[allow(dead_code)]
     pub async fn update(&self, id: i64, update: UpdateUpstreamServer) -> Result<Option<UpstreamServer>> {
-        let existing = self.get_by_id(id).await?;
-        if existing.is_none() {
+        self.update_at_version(id, update, None).await
+    }
+
+    /// Update an upstream server if it is still at `version` (its `updated_at`)
+    ///
+    /// Returns None when the upstream server is gone or was modified since.
+    pub async fn update_at_version(
+        &self,
+        id: i64,
+        update: UpdateUpstreamServer,
+        version: Option<chrono::DateTime<Utc>>,
+    ) -> Result<Option<UpstreamServer>> {
+        Self::update_on(&mut *self.pool.acquire().await?, id, update, version).await
+    }
+
+    /// Update an upstream server on a connection, e.g. inside a transaction
+    pub async fn update_on(
+        conn: &mut SqliteConnection,
+        id: i64,
+        update: UpdateUpstreamServer,
+        version: Option<chrono::DateTime<Utc>>,
+    ) -> Result<Option<UpstreamServer>> {
+        let Some((existing, stored_version)) = fetch_at_version::<UpstreamServer>(&mut *conn, "upstream_servers", id, version).await? else {
             return Ok(None);
-        }
-        let existing = existing.unwrap();
+        };
+
+        // A profile probed at another address or over another protocol no longer applies
+        let endpoint_changed = update.address.as_ref().is_some_and(|a| *a != existing.address)
+            || update.protocol.as_ref().is_some_and(|p| *p != existing.protocol);
+        let capabilities = if endpoint_changed { None } else { existing.capabilities };
 
         let name = update.name.unwrap_or(existing.name);
         let address = update.address.unwrap_or(existing.address);
         let protocol = update.protocol.unwrap_or(existing.protocol);
         let timeout = update.timeout.unwrap_or(existing.timeout);
         let enabled = update.enabled.unwrap_or(existing.enabled);
+        let source_ip = match update.source_ip {
+            Some(s) if s.is_empty() => None,
+            Some(s) => Some(s),
+            None => existing.source_ip,
+        };
+        let source_interface = match update.source_interface {
+            Some(s) if s.is_empty() => None,
+            Some(s) => Some(s),
+            None => existing.source_interface,
+        };
+        let tls_server_name = match update.tls_server_name {
+            Some(s) if s.is_empty() => None,
+            Some(s) => Some(s),
+            None => existing.tls_server_name,
+        };
+        let verify_hostname = update.verify_hostname.or(existing.verify_hostname);
+        let doh_method = match update.doh_method {
+            Some(s) if s.is_empty() => None,
+            Some(s) => Some(s),
+            None => existing.doh_method,
+        };
+        let doh_http_version = match update.doh_http_version {
+            Some(s) if s.is_empty() => None,
+            Some(s) => Some(s),
+            None => existing.doh_http_version,
+        };
 
         let result = sqlx::query_as::<_, UpstreamServer>(
             r#"
             UPDATE upstream_servers 
-            SET name = ?, address = ?, protocol = ?, timeout = ?, enabled = ?, updated_at = ?
-            WHERE id = ?
+            SET name = ?, address = ?, protocol = ?, timeout = ?, enabled = ?, source_ip = ?, source_interface = ?, tls_server_name = ?, verify_hostname = ?, doh_method = ?, doh_http_version = ?, capabilities = ?, updated_at = ?
+            WHERE id = ? AND (? IS NULL OR updated_at = ?)
             RETURNING *
             "#,
         )
@@ -483,24 +1066,71 @@ impl UpstreamServerRepository {
         .bind(&protocol)
         .bind(timeout)
         .bind(enabled)
+        .bind(&source_ip)
+        .bind(&source_interface)
+        .bind(&tls_server_name)
+        .bind(verify_hostname)
+        .bind(&doh_method)
+        .bind(&doh_http_version)
+        .bind(&capabilities)
         .bind(Utc::now())
         .bind(id)
-        .fetch_optional(&self.pool)
+        .bind(&stored_version)
+        .bind(&stored_version)
+        .fetch_optional(&mut *conn)
         .await?;
 
         Ok(result)
     }
 
-    /// Delete an upstream server
-    pub async fn delete(&self, id: i64) -> Result<bool> {
-        let result = sqlx::query("DELETE FROM upstream_servers WHERE id = ?")
+    /// Store the probed capability profile (JSON)
+    ///
+    /// Leaves `updated_at` alone: the profile is detected, not configured.
+    pub async fn set_capabilities(&self, id: i64, capabilities: Option<&str>) -> Result<bool> {
+        let result = sqlx::query("UPDATE upstream_servers SET capabilities = ? WHERE id = ?")
+            .bind(capabilities)
             .bind(id)
             .execute(&self.pool)
             .await?;
 
         Ok(result.rows_affected() > 0)
     }
-}
+
+    /// Drain an upstream server for maintenance, or return it to service
+    pub async fn set_drained(&self, id: i64, drained: bool) -> Result<Option<UpstreamServer>> {
+        let result = sqlx::query_as::<_, UpstreamServer>(
+            "UPDATE upstream_servers SET drained = ?, updated_at = ? WHERE id = ? RETURNING *",
+        )
+        .bind(drained)
+        .bind(Utc::now())
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Delete an upstream server
+    #[allow(dead_code)]
+    pub async fn delete(&self, id: i64) -> Result<bool> {
+        Self::delete_on(&mut *self.pool.acquire().await?, id).await
+    }
+
+    /// Delete an upstream server on a connection, e.g. inside a transaction
+    pub async fn delete_on(conn: &mut SqliteConnection, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM upstream_servers WHERE id = ?")
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete an upstream server if it is still at `version` (its `updated_at`)
+    pub async fn delete_at_version(&self, id: i64, version: Option<chrono::DateTime<Utc>>) -> Result<bool> {
+        delete_at_version_on::<UpstreamServer>(&mut *self.pool.acquire().await?, "upstream_servers", id, version).await
+    }
+}
 
 
 use std::sync::Arc;
@@ -519,12 +1149,44 @@ impl QueryLogRepository {
 
     /// Create a new query log entry
     pub async fn create(&self, log: CreateQueryLog) -> Result<QueryLog> {
-        let now = Utc::now();
-        let cache_hit = log.cache_hit;
+        let result = Self::insert_on(&mut *self.pool.acquire().await?, &log).await?;
+
+        // Update memory cache
+        self.stats_cache.record_query(log.cache_hit, result.sample_rate).await;
+
+        Ok(result)
+    }
+
+    /// Create query log entries in one transaction
+    ///
+    /// Used for entries ingested from external resolvers, which carry their
+    /// own timestamps; only entries from today count towards today's total.
+    pub async fn create_many(&self, logs: Vec<CreateQueryLog>) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let mut created = Vec::with_capacity(logs.len());
+        for log in &logs {
+            created.push(Self::insert_on(&mut tx, log).await?);
+        }
+        tx.commit().await?;
+
+        let today = chrono::Local::now().date_naive();
+        for (log, row) in logs.iter().zip(&created) {
+            if row.created_at.with_timezone(&chrono::Local).date_naive() == today {
+                self.stats_cache.record_query(log.cache_hit, row.sample_rate).await;
+            } else {
+                self.stats_cache.record_earlier_query(log.cache_hit, row.sample_rate);
+            }
+        }
+        Ok(created.len() as u64)
+    }
+
+    async fn insert_on(conn: &mut SqliteConnection, log: &CreateQueryLog) -> Result<QueryLog> {
+        let created_at = log.created_at.unwrap_or_else(Utc::now);
+        let sample_rate = log.sample_rate.max(1);
         let result = sqlx::query_as::<_, QueryLog>(
             r#"
-            INSERT INTO query_logs (client_ip, query_name, query_type, response_code, response_time, cache_hit, upstream_used, created_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO query_logs (client_ip, query_name, query_type, response_code, response_time, cache_hit, upstream_used, created_at, tenant_id, category, answered_by, trace_id, sample_rate, protocol, extended_error, source, block_reason)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             RETURNING *
             "#,
         )
@@ -535,13 +1197,19 @@ impl QueryLogRepository {
         .bind(log.response_time)
         .bind(log.cache_hit)
         .bind(&log.upstream_used)
-        .bind(now)
-        .fetch_one(&self.pool)
+        .bind(created_at)
+        .bind(log.tenant_id)
+        .bind(&log.category)
+        .bind(&log.answered_by)
+        .bind(&log.trace_id)
+        .bind(sample_rate)
+        .bind(&log.protocol)
+        .bind(&log.extended_error)
+        .bind(&log.source)
+        .bind(&log.block_reason)
+        .fetch_one(conn)
         .await?;
 
-        // Update memory cache
-        self.stats_cache.record_query(cache_hit).await;
-
         Ok(result)
     }
 
@@ -596,6 +1264,55 @@ impl QueryLogRepository {
             count_builder.push_bind(cache_hit);
         }
 
+        if let Some(tenant_id) = filter.tenant_id {
+            query_builder.push(" AND tenant_id = ");
+            query_builder.push_bind(tenant_id);
+            count_builder.push(" AND tenant_id = ");
+            count_builder.push_bind(tenant_id);
+        }
+
+        if let Some(ref category) = filter.category {
+            query_builder.push(" AND category = ");
+            query_builder.push_bind(category.clone());
+            count_builder.push(" AND category = ");
+            count_builder.push_bind(category);
+        }
+
+        if let Some(ref trace_id) = filter.trace_id {
+            query_builder.push(" AND trace_id = ");
+            query_builder.push_bind(trace_id.clone());
+            count_builder.push(" AND trace_id = ");
+            count_builder.push_bind(trace_id);
+        }
+
+        if let Some(ref protocol) = filter.protocol {
+            query_builder.push(" AND protocol = ");
+            query_builder.push_bind(protocol.clone());
+            count_builder.push(" AND protocol = ");
+            count_builder.push_bind(protocol);
+        }
+
+        if let Some(ref source) = filter.source {
+            query_builder.push(" AND source = ");
+            query_builder.push_bind(source.clone());
+            count_builder.push(" AND source = ");
+            count_builder.push_bind(source);
+        }
+
+        if let Some(ref block_reason) = filter.block_reason {
+            query_builder.push(" AND block_reason = ");
+            query_builder.push_bind(block_reason.clone());
+            count_builder.push(" AND block_reason = ");
+            count_builder.push_bind(block_reason);
+        }
+
+        if let Some(min_response_time) = filter.min_response_time {
+            query_builder.push(" AND response_time >= ");
+            query_builder.push_bind(min_response_time);
+            count_builder.push(" AND response_time >= ");
+            count_builder.push_bind(min_response_time);
+        }
+
         if let Some(ref start) = filter.start_time {
             query_builder.push(" AND created_at >= ");
             query_builder.push_bind(start);
@@ -634,9 +1351,15 @@ impl QueryLogRepository {
 
     /// Delete old query logs (older than specified days)
     pub async fn delete_old(&self, days: i64) -> Result<u64> {
+        self.delete_old_at(days, Utc::now()).await
+    }
+
+    /// Delete query logs older than `days` days before `now`
+    pub async fn delete_old_at(&self, days: i64, now: chrono::DateTime<Utc>) -> Result<u64> {
         let result = sqlx::query(
-            "DELETE FROM query_logs WHERE created_at < datetime('now', ? || ' days')",
+            "DELETE FROM query_logs WHERE created_at < datetime(?, ? || ' days')",
         )
+        .bind(now.format("%Y-%m-%d %H:%M:%S").to_string())
         .bind(-days)
         .execute(&self.pool)
         .await?;
@@ -687,28 +1410,41 @@ impl QueryLogRepository {
     }
 
     /// Get query statistics (slow, from DB)
+    ///
+    /// Totals include rolled-up queries whose raw rows were deleted.
     pub async fn get_stats_db(&self) -> Result<QueryStats> {
-        let total: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM query_logs")
-            .fetch_one(&self.pool)
-            .await?;
-
-        let cache_hits: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM query_logs WHERE cache_hit = TRUE")
-            .fetch_one(&self.pool)
+        let (total, cache_hits) = QueryLogRollupRepository::new(self.pool.clone())
+            .totals(None, None, None)
             .await?;
 
         let today: (i64,) = sqlx::query_as(
-            "SELECT COUNT(*) FROM query_logs WHERE created_at >= date('now')",
+            "SELECT COALESCE(SUM(sample_rate), 0) FROM query_logs WHERE created_at >= date('now')",
         )
         .fetch_one(&self.pool)
         .await?;
 
         Ok(QueryStats {
-            total_queries: total.0,
-            cache_hits: cache_hits.0,
+            total_queries: total,
+            cache_hits,
             queries_today: today.0,
         })
     }
 
+    /// Query and block counts per category
+    ///
+    /// Only classified queries are counted; a query counts as blocked when
+    /// the category filter answered it. Reads raw logs and roll-ups alike.
+    pub async fn category_stats(
+        &self,
+        start_time: Option<chrono::DateTime<Utc>>,
+        end_time: Option<chrono::DateTime<Utc>>,
+        tenant_id: Option<i64>,
+    ) -> Result<Vec<CategoryStat>> {
+        QueryLogRollupRepository::new(self.pool.clone())
+            .category_stats(start_time, end_time, tenant_id)
+            .await
+    }
+
 }
 
 
@@ -745,6 +1481,11 @@ impl SystemConfigRepository {
 
     /// Set a config value (insert or update)
     pub async fn set(&self, key: &str, value: &str) -> Result<()> {
+        Self::set_on(&mut *self.pool.acquire().await?, key, value).await
+    }
+
+    /// Set a config value on a connection, e.g. inside a transaction
+    pub async fn set_on(conn: &mut SqliteConnection, key: &str, value: &str) -> Result<()> {
         sqlx::query(
             r#"
             INSERT INTO system_config (key, value, updated_at)
@@ -754,7 +1495,7 @@ impl SystemConfigRepository {
         )
         .bind(key)
         .bind(value)
-        .execute(&self.pool)
+        .execute(&mut *conn)
         .await?;
 
         Ok(())
@@ -790,16 +1531,18 @@ mod tests {
     use crate::db::Database;
     use tempfile::tempdir;
 
-    async fn setup_test_db() -> Database {
+    /// Test database; the directory must outlive it
+    async fn setup_test_db() -> (tempfile::TempDir, Database) {
         let dir = tempdir().unwrap();
         let db_path = dir.path().join("test.db");
         let db_url = format!("sqlite:{}?mode=rwc", db_path.display());
-        Database::new(&db_url).await.unwrap()
+        let db = Database::new(&db_url).await.unwrap();
+        (dir, db)
     }
 
     #[tokio::test]
     async fn test_dns_record_crud() {
-        let db = setup_test_db().await;
+        let (_dir, db) = setup_test_db().await;
         let repo = db.dns_records();
 
         // Create
@@ -810,10 +1553,16 @@ mod tests {
             ttl: 300,
             priority: 0,
             enabled: true,
+            tenant_id: None,
+            description: Some("Office printer".to_string()),
+            tags: Tags(vec!["office-berlin".to_string()]),
+            expires_at: None,
         }).await.unwrap();
 
         assert_eq!(record.name, "example.com");
         assert_eq!(record.record_type, "A");
+        assert_eq!(record.description.as_deref(), Some("Office printer"));
+        assert!(record.tags.contains("office-berlin"));
 
         // Read
         let fetched = repo.get_by_id(record.id).await.unwrap().unwrap();
@@ -825,6 +1574,14 @@ mod tests {
             ..Default::default()
         }).await.unwrap().unwrap();
         assert_eq!(updated.value, "192.168.1.2");
+        assert!(updated.tags.contains("office-berlin"));
+
+        // Bulk operations by tag
+        assert_eq!(repo.set_enabled_by_tag("office-berlin", false, None).await.unwrap(), 1);
+        assert_eq!(repo.set_enabled_by_tag("office-berlin", false, None).await.unwrap(), 0);
+        assert_eq!(repo.set_enabled_by_tag("office-berlin", true, Some(1)).await.unwrap(), 0);
+        assert!(!repo.get_by_id(record.id).await.unwrap().unwrap().enabled);
+        assert_eq!(repo.delete_by_tag("office-paris", None).await.unwrap(), 0);
 
         // Delete
         let deleted = repo.delete(record.id).await.unwrap();
@@ -834,10 +1591,203 @@ mod tests {
         assert!(not_found.is_none());
     }
 
+    #[tokio::test]
+    async fn test_dns_record_update_at_version() {
+        let (_dir, db) = setup_test_db().await;
+        let repo = db.dns_records();
+        let record = repo.create(CreateDnsRecord {
+            name: "example.com".to_string(),
+            record_type: "A".to_string(),
+            value: "192.168.1.1".to_string(),
+            ttl: 300,
+            priority: 0,
+            enabled: true,
+            tenant_id: None,
+            description: None,
+            tags: Tags::default(),
+            expires_at: None,
+        }).await.unwrap();
+        let update = |value: &str| UpdateDnsRecord {
+            value: Some(value.to_string()),
+            ..Default::default()
+        };
+
+        // Only the first of two writers holding the same version wins
+        let first = repo.update_at_version(record.id, update("192.168.1.2"), Some(record.updated_at)).await.unwrap();
+        assert_eq!(first.unwrap().value, "192.168.1.2");
+        let second = repo.update_at_version(record.id, update("192.168.1.3"), Some(record.updated_at)).await.unwrap();
+        assert!(second.is_none());
+        assert_eq!(repo.get_by_id(record.id).await.unwrap().unwrap().value, "192.168.1.2");
+
+        // A stale delete leaves the row in place
+        assert!(!repo.delete_at_version(record.id, Some(record.updated_at)).await.unwrap());
+        let current = repo.get_by_id(record.id).await.unwrap().unwrap();
+        assert!(repo.delete_at_version(record.id, Some(current.updated_at)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_dns_record_create_many() {
+        let (_dir, db) = setup_test_db().await;
+        let repo = db.dns_records();
+        let record = |name: &str, value: &str| CreateDnsRecord {
+            name: name.to_string(),
+            record_type: "A".to_string(),
+            value: value.to_string(),
+            ttl: 300,
+            priority: 0,
+            enabled: true,
+            tenant_id: None,
+            description: None,
+            tags: Tags::default(),
+            expires_at: None,
+        };
+
+        let created = repo
+            .create_many(vec![record("a.example.com", "192.0.2.1"), record("b.example.com", "192.0.2.2")])
+            .await
+            .unwrap();
+        let names: Vec<&str> = created.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["a.example.com", "b.example.com"]);
+        assert!(created[0].id < created[1].id);
+        assert_eq!(repo.list().await.unwrap().len(), 2);
+
+        assert!(repo.create_many(Vec::new()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dns_record_wildcard_matching() {
+        let (_dir, db) = setup_test_db().await;
+        let repo = db.dns_records();
+        for (name, record_type, value) in [
+            ("*.example.com", "A", "10.0.0.1"),
+            ("*.a.example.com", "A", "10.0.0.2"),
+            ("Host.A.Example.com", "A", "10.0.0.3"),
+            ("txt.example.com", "TXT", "explicit"),
+            ("*.b.example.com", "TXT", "wildcard"),
+        ] {
+            repo.create(CreateDnsRecord {
+                name: name.to_string(),
+                record_type: record_type.to_string(),
+                value: value.to_string(),
+                ttl: 300,
+                priority: 0,
+                enabled: true,
+                tenant_id: None,
+                description: None,
+                tags: Tags::default(),
+                expires_at: None,
+            }).await.unwrap();
+        }
+        let lookup = |name: &'static str| {
+            let repo = db.dns_records();
+            async move {
+                repo.get_by_name_and_type_with_wildcard(name, "A")
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|r| r.value)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        // Explicit names win, case-insensitively and with a trailing dot
+        assert_eq!(lookup("host.a.example.com.").await, vec!["10.0.0.3"]);
+        // The longest matching wildcard wins, at any depth
+        assert_eq!(lookup("x.a.example.com").await, vec!["10.0.0.2"]);
+        assert_eq!(lookup("y.x.a.example.com").await, vec!["10.0.0.2"]);
+        assert_eq!(lookup("x.example.com").await, vec!["10.0.0.1"]);
+        // A wildcard doesn't match its own parent name
+        assert!(lookup("example.com").await.is_empty());
+        // An explicit name of another type overrides the wildcard
+        assert!(lookup("txt.example.com").await.is_empty());
+        // So does a closer wildcard with only other types
+        assert!(lookup("x.b.example.com").await.is_empty());
+
+        let matched = repo.match_for_tenant("x.a.example.com", "AAAA", None).await.unwrap().unwrap();
+        assert_eq!(matched.matched_name, "*.a.example.com");
+        assert!(matched.is_wildcard());
+        assert!(matched.records.is_empty());
+        assert!(repo.match_for_tenant("example.org", "A", None).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stored_names_normalized() {
+        let (_dir, db) = setup_test_db().await;
+        for (name, value) in [("WWW.Example.COM.", "10.0.0.1"), ("Bücher.Example", "10.0.0.2")] {
+            sqlx::query("INSERT INTO dns_records (name, record_type, value) VALUES (?, 'A', ?)")
+                .bind(name)
+                .bind(value)
+                .execute(db.pool())
+                .await
+                .unwrap();
+        }
+        for (pattern, match_type) in [("Ads.Example.com.", "exact"), ("^Ads\\.", "regex")] {
+            sqlx::query("INSERT INTO rewrite_rules (pattern, match_type, action_type) VALUES (?, ?, 'block')")
+                .bind(pattern)
+                .bind(match_type)
+                .execute(db.pool())
+                .await
+                .unwrap();
+        }
+
+        db.normalize_stored_names().await.unwrap();
+
+        let names: Vec<String> = db.dns_records().list().await.unwrap().into_iter().map(|r| r.name).collect();
+        assert!(names.contains(&"www.example.com".to_string()));
+        assert!(names.contains(&"xn--bcher-kva.example".to_string()));
+        let patterns: Vec<String> = db.rewrite_rules().list().await.unwrap().into_iter().map(|r| r.pattern).collect();
+        assert!(patterns.contains(&"ads.example.com".to_string()));
+        // Regular expressions are left as written
+        assert!(patterns.contains(&"^Ads\\.".to_string()));
+
+        let found = db
+            .dns_records()
+            .get_by_name_and_type_with_wildcard("www.example.com.", "A")
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+    }
+
+
+    #[tokio::test]
+    async fn test_expired_records_and_rules() {
+        let (_dir, db) = setup_test_db().await;
+        let repo = db.dns_records();
+        let now = Utc::now();
+        let record = repo.create(CreateDnsRecord {
+            name: "app.example.com".to_string(),
+            record_type: "A".to_string(),
+            value: "10.0.0.1".to_string(),
+            ttl: 300,
+            priority: 0,
+            enabled: true,
+            tenant_id: None,
+            description: None,
+            tags: Tags::default(),
+            expires_at: Some(now + chrono::Duration::hours(1)),
+        }).await.unwrap();
+        assert!(repo.match_for_tenant("app.example.com", "A", None).await.unwrap().is_some());
+
+        // An omitted expiration is kept, an explicit one replaces it
+        let updated = repo.update(record.id, UpdateDnsRecord {
+            ttl: Some(60),
+            ..Default::default()
+        }).await.unwrap().unwrap();
+        assert!(updated.expires_at.is_some());
+        repo.update(record.id, UpdateDnsRecord {
+            expires_at: Some(Some(now - chrono::Duration::minutes(1))),
+            ..Default::default()
+        }).await.unwrap();
+        assert!(repo.match_for_tenant("app.example.com", "A", None).await.unwrap().is_none());
+
+        assert_eq!(repo.delete_expired(Utc::now()).await.unwrap(), 1);
+        assert!(repo.get_by_id(record.id).await.unwrap().is_none());
+        assert_eq!(db.rewrite_rules().delete_expired(Utc::now()).await.unwrap(), 0);
+    }
 
     #[tokio::test]
     async fn test_rewrite_rule_crud() {
-        let db = setup_test_db().await;
+        let (_dir, db) = setup_test_db().await;
         let repo = db.rewrite_rules();
 
         // Create
@@ -849,6 +1799,11 @@ mod tests {
             priority: 10,
             enabled: true,
             description: Some("Block ads".to_string()),
+            tenant_id: None,
+            shadow: false,
+            shadow_of: None,
+            tags: Tags(vec!["created-by-script".to_string()]),
+            expires_at: None,
         }).await.unwrap();
 
         assert_eq!(rule.pattern, "*.ads.example.com");
@@ -864,15 +1819,176 @@ mod tests {
             ..Default::default()
         }).await.unwrap().unwrap();
         assert_eq!(updated.priority, 20);
+        assert!(updated.tags.contains("created-by-script"));
 
         // Delete
         let deleted = repo.delete(rule.id).await.unwrap();
         assert!(deleted);
     }
 
+    #[tokio::test]
+    async fn test_rewrite_rule_delete_by_tag() {
+        let (_dir, db) = setup_test_db().await;
+        let repo = db.rewrite_rules();
+        let rule = |pattern: &str, tenant_id: Option<i64>, tags: &[&str]| CreateRewriteRule {
+            pattern: pattern.to_string(),
+            match_type: "exact".to_string(),
+            action_type: "block".to_string(),
+            action_value: None,
+            priority: 0,
+            enabled: true,
+            description: None,
+            tenant_id,
+            shadow: false,
+            shadow_of: None,
+            tags: Tags(tags.iter().map(|t| t.to_string()).collect()),
+            expires_at: None,
+        };
+        let tenant = db.tenants().create(CreateTenant {
+            name: "acme".to_string(),
+            description: None,
+            client_subnets: String::new(),
+            listeners: String::new(),
+            enabled: true,
+        }).await.unwrap().tenant;
+
+        let global = repo.create(rule("ads.example.com", None, &["created-by-script"])).await.unwrap();
+        let owned = repo.create(rule("ads.acme.example", Some(tenant.id), &["created-by-script"])).await.unwrap();
+        let untagged = repo.create(rule("keep.example.com", None, &[])).await.unwrap();
+
+        // A tenant only removes its own tagged rules
+        assert_eq!(repo.delete_by_tag("created-by-script", Some(tenant.id)).await.unwrap(), 1);
+        assert!(repo.get_by_id(owned.id).await.unwrap().is_none());
+        assert!(repo.get_by_id(global.id).await.unwrap().is_some());
+
+        assert_eq!(repo.delete_by_tag("created-by-script", None).await.unwrap(), 1);
+        assert_eq!(repo.delete_by_tag("created-by-script", None).await.unwrap(), 0);
+        assert!(repo.get_by_id(global.id).await.unwrap().is_none());
+        assert!(repo.get_by_id(untagged.id).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_tenant_token_stored_as_digest() {
+        let (_dir, db) = setup_test_db().await;
+        let repo = db.tenants();
+        let issued = repo.create(CreateTenant {
+            name: "acme".to_string(),
+            description: None,
+            client_subnets: String::new(),
+            listeners: String::new(),
+            enabled: true,
+        }).await.unwrap();
+
+        assert!(issued.api_token.starts_with("fdt_"));
+        assert_eq!(issued.tenant.api_token, hash_token(&issued.api_token));
+        let found = repo.get_by_token(&issued.api_token).await.unwrap().unwrap();
+        assert_eq!(found.id, issued.tenant.id);
+        // The stored digest is not a credential
+        assert!(repo.get_by_token(&issued.tenant.api_token).await.unwrap().is_none());
+
+        let rotated = repo.rotate_token(issued.tenant.id).await.unwrap().unwrap();
+        assert!(repo.get_by_token(&issued.api_token).await.unwrap().is_none());
+        assert!(repo.get_by_token(&rotated.api_token).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_api_token_stored_as_digest() {
+        let (_dir, db) = setup_test_db().await;
+        let repo = db.api_tokens();
+        let issued = repo
+            .create(CreateApiToken {
+                name: "ci".to_string(),
+                scopes: vec!["cache:purge".to_string()],
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+
+        assert!(issued.token.starts_with("fda_"));
+        assert_eq!(issued.api_token.token, hash_token(&issued.token));
+        assert!(repo.authenticate(&issued.token).await.unwrap().is_some());
+        assert!(repo.authenticate(&issued.api_token.token).await.unwrap().is_none());
+        let listed = serde_json::to_value(repo.list().await.unwrap()).unwrap();
+        assert!(listed[0].get("token").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_purge_tokens_move_into_api_tokens() {
+        let (_dir, db) = setup_test_db().await;
+        db.api_tokens()
+            .create(CreateApiToken {
+                name: "deploy".to_string(),
+                scopes: vec!["records:read".to_string()],
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+        sqlx::query(
+            "CREATE TABLE purge_tokens (id INTEGER PRIMARY KEY AUTOINCREMENT, name VARCHAR(100) NOT NULL UNIQUE, \
+             token VARCHAR(64) NOT NULL UNIQUE, enabled BOOLEAN DEFAULT TRUE, \
+             created_at DATETIME DEFAULT CURRENT_TIMESTAMP, last_used_at DATETIME)",
+        )
+        .execute(db.pool())
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO purge_tokens (name, token) VALUES ('ci', 'fdp_plain'), ('deploy', ?)")
+            .bind(hash_token("fdp_hashed"))
+            .execute(db.pool())
+            .await
+            .unwrap();
+
+        db.migrate_purge_tokens().await.unwrap();
+        // The table is gone, so a second run is a no-op
+        db.migrate_purge_tokens().await.unwrap();
+
+        let tokens = db.api_tokens().list().await.unwrap();
+        assert_eq!(tokens.len(), 3);
+        let ci = tokens.iter().find(|t| t.token == hash_token("fdp_plain")).unwrap();
+        assert_eq!(ci.name, "ci");
+        assert_eq!(ci.scope_list(), ["cache:purge"]);
+        let deploy = tokens.iter().find(|t| t.token == hash_token("fdp_hashed")).unwrap();
+        assert_eq!(deploy.name, "deploy (purge)");
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_rule_shadow_promote() {
+        let (_dir, db) = setup_test_db().await;
+        let repo = db.rewrite_rules();
+
+        let new_rule = |action_value: &str, shadow_of: Option<i64>| CreateRewriteRule {
+            pattern: "app.example.com".to_string(),
+            match_type: "exact".to_string(),
+            action_type: "map_ip".to_string(),
+            action_value: Some(action_value.to_string()),
+            priority: 0,
+            enabled: true,
+            description: None,
+            tenant_id: None,
+            shadow: shadow_of.is_some(),
+            shadow_of,
+            tags: Tags::default(),
+            expires_at: None,
+        };
+        let active = repo.create(new_rule("10.0.0.1", None)).await.unwrap();
+        let shadow = repo.create(new_rule("10.0.0.2", Some(active.id))).await.unwrap();
+        assert!(shadow.shadow);
+
+        repo.add_shadow_hits(&[(shadow.id, 3, Utc::now()), (active.id, 5, Utc::now())]).await.unwrap();
+        assert_eq!(repo.get_by_id(shadow.id).await.unwrap().unwrap().shadow_hits, 3);
+        assert_eq!(repo.get_by_id(active.id).await.unwrap().unwrap().shadow_hits, 0);
+
+        // Only shadow rules can be promoted
+        assert!(repo.promote(active.id).await.unwrap().is_none());
+
+        let promoted = repo.promote(shadow.id).await.unwrap().unwrap();
+        assert!(!promoted.shadow);
+        assert!(promoted.shadow_of.is_none());
+        assert!(repo.get_by_id(active.id).await.unwrap().is_none());
+    }
+
     #[tokio::test]
     async fn test_upstream_server_crud() {
-        let db = setup_test_db().await;
+        let (_dir, db) = setup_test_db().await;
         let repo = db.upstream_servers();
 
         // Create
@@ -882,9 +1998,18 @@ mod tests {
             protocol: "udp".to_string(),
             timeout: 5000,
             enabled: true,
+            source_ip: None,
+            source_interface: Some("eth1".to_string()),
+            tls_server_name: Some("one.one.one.one".to_string()),
+            verify_hostname: None,
+            doh_method: None,
+            doh_http_version: None,
         }).await.unwrap();
 
         assert_eq!(server.name, "Cloudflare");
+        assert_eq!(server.source_interface.as_deref(), Some("eth1"));
+        assert_eq!(server.tls_server_name.as_deref(), Some("one.one.one.one"));
+        assert!(server.verify_hostname.is_none());
 
         // Read
         let fetched = repo.get_by_id(server.id).await.unwrap().unwrap();
@@ -893,18 +2018,145 @@ mod tests {
         // Update
         let updated = repo.update(server.id, UpdateUpstreamServer {
             timeout: Some(3000),
+            source_interface: Some(String::new()),
+            verify_hostname: Some(false),
             ..Default::default()
         }).await.unwrap().unwrap();
         assert_eq!(updated.timeout, 3000);
+        assert!(updated.source_interface.is_none());
+        assert_eq!(updated.tls_server_name.as_deref(), Some("one.one.one.one"));
+        assert_eq!(updated.verify_hostname, Some(false));
+
+        // Capabilities survive edits but not a change of address
+        assert!(repo.set_capabilities(server.id, Some(r#"{"edns":true}"#)).await.unwrap());
+        let renamed = repo.update(server.id, UpdateUpstreamServer {
+            name: Some("CF".to_string()),
+            ..Default::default()
+        }).await.unwrap().unwrap();
+        assert_eq!(renamed.capabilities.as_deref(), Some(r#"{"edns":true}"#));
+        let moved = repo.update(server.id, UpdateUpstreamServer {
+            address: Some("1.0.0.1:53".to_string()),
+            ..Default::default()
+        }).await.unwrap().unwrap();
+        assert!(moved.capabilities.is_none());
+        assert!(repo.set_capabilities(server.id, Some(r#"{"edns":true}"#)).await.unwrap());
+        let same = repo.update(server.id, UpdateUpstreamServer {
+            address: Some("1.0.0.1:53".to_string()),
+            protocol: Some("udp".to_string()),
+            ..Default::default()
+        }).await.unwrap().unwrap();
+        assert_eq!(same.capabilities.as_deref(), Some(r#"{"edns":true}"#));
+        let tcp = repo.update(server.id, UpdateUpstreamServer {
+            protocol: Some("tcp".to_string()),
+            ..Default::default()
+        }).await.unwrap().unwrap();
+        assert!(tcp.capabilities.is_none());
+
+        // Drain and return to service
+        assert!(!tcp.drained);
+        let drained = repo.set_drained(server.id, true).await.unwrap().unwrap();
+        assert!(drained.drained);
+        assert!(repo.list_enabled().await.unwrap().iter().any(|s| s.id == server.id));
+        assert!(!repo.set_drained(server.id, false).await.unwrap().unwrap().drained);
+        assert!(repo.set_drained(9999, true).await.unwrap().is_none());
 
         // Delete
         let deleted = repo.delete(server.id).await.unwrap();
         assert!(deleted);
     }
 
+    #[tokio::test]
+    async fn test_upstream_server_reorder() {
+        let (_dir, db) = setup_test_db().await;
+        let repo = db.upstream_servers();
+
+        let mut ids = Vec::new();
+        for name in ["a", "b", "c"] {
+            let server = repo.create(CreateUpstreamServer {
+                name: name.to_string(),
+                address: "1.1.1.1:53".to_string(),
+                protocol: "udp".to_string(),
+                timeout: 5000,
+                enabled: true,
+                source_ip: None,
+                source_interface: None,
+                tls_server_name: None,
+                verify_hostname: None,
+                doh_method: None,
+                doh_http_version: None,
+            }).await.unwrap();
+            ids.push(server.id);
+        }
+        let names = |servers: Vec<UpstreamServer>| servers.into_iter().map(|s| s.name).collect::<Vec<_>>();
+        assert_eq!(names(repo.list().await.unwrap()), vec!["a", "b", "c"]);
+
+        repo.reorder(&[ids[2], ids[0], ids[1]]).await.unwrap();
+        assert_eq!(names(repo.list().await.unwrap()), vec!["c", "a", "b"]);
+        assert_eq!(names(repo.list_enabled().await.unwrap()), vec!["c", "a", "b"]);
+        let (page, total) = repo.list_paged(2, 2).await.unwrap();
+        assert_eq!(names(page), vec!["b"]);
+        assert_eq!(total, 3);
+    }
+
+    #[tokio::test]
+    async fn test_record_group_lifecycle() {
+        let (_dir, db) = setup_test_db().await;
+        let repo = db.record_groups();
+        let record = |name: &str, record_type: &str, value: &str| CreateDnsRecord {
+            name: name.to_string(),
+            record_type: record_type.to_string(),
+            value: value.to_string(),
+            ttl: 300,
+            priority: 0,
+            enabled: true,
+            tenant_id: None,
+            description: None,
+            tags: Tags::default(),
+            expires_at: None,
+        };
+
+        let (group, members) = repo.create(
+            CreateRecordGroup {
+                name: "web".to_string(),
+                domain: "web.lan".to_string(),
+                description: None,
+                tenant_id: None,
+            },
+            vec![
+                record("web.lan", "A", "10.0.0.5"),
+                record("web.lan", "AAAA", "fd00::5"),
+            ],
+        ).await.unwrap();
+        assert_eq!(members.len(), 2);
+        assert!(members.iter().all(|r| r.group_id == Some(group.id)));
+
+        repo.add_records(group.id, vec![record("web.lan", "TXT", "v=1")]).await.unwrap();
+        let mut members = repo.records(group.id).await.unwrap();
+        assert_eq!(members.len(), 3);
+
+        for member in &mut members {
+            member.name = "www.lan".to_string();
+        }
+        let renamed = repo.update(
+            group.id,
+            UpdateRecordGroup { domain: Some("www.lan".to_string()), ..Default::default() },
+            &members,
+        ).await.unwrap().unwrap();
+        assert_eq!(renamed.domain, "www.lan");
+        assert!(repo.records(group.id).await.unwrap().iter().all(|r| r.name == "www.lan"));
+
+        // Records outside the group are left alone
+        let other = db.dns_records().create(record("other.lan", "A", "10.0.0.6")).await.unwrap();
+        assert!(repo.delete(group.id).await.unwrap());
+        assert!(repo.get_by_id(group.id).await.unwrap().is_none());
+        let remaining = db.dns_records().list().await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].id, other.id);
+    }
+
     #[tokio::test]
     async fn test_query_log_crud() {
-        let db = setup_test_db().await;
+        let (_dir, db) = setup_test_db().await;
         let repo = db.query_logs();
 
         // Create
@@ -916,6 +2168,16 @@ mod tests {
             response_time: Some(50),
             cache_hit: false,
             upstream_used: Some("Cloudflare".to_string()),
+            tenant_id: None,
+            category: None,
+            answered_by: None,
+            trace_id: Some("00c0ffee00c0ffee".to_string()),
+            sample_rate: 1,
+            protocol: Some("udp".to_string()),
+            extended_error: None,
+            source: None,
+            block_reason: None,
+            created_at: None,
         }).await.unwrap();
 
         assert_eq!(log.query_name, "example.com");
@@ -930,11 +2192,90 @@ mod tests {
             ..Default::default()
         }).await.unwrap();
         assert_eq!(result.items.len(), 1);
+
+        // Lookup by trace ID
+        let result = repo.list(QueryLogFilter {
+            trace_id: Some("00c0ffee00c0ffee".to_string()),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(result.items.len(), 1);
+        assert_eq!(result.items[0].trace_id.as_deref(), Some("00c0ffee00c0ffee"));
+
+        // Filter by listener protocol
+        let result = repo.list(QueryLogFilter {
+            protocol: Some("udp".to_string()),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(result.items.len(), 1);
+        let result = repo.list(QueryLogFilter {
+            protocol: Some("doh".to_string()),
+            ..Default::default()
+        }).await.unwrap();
+        assert!(result.items.is_empty());
+
+        let result = repo.list(QueryLogFilter {
+            trace_id: Some("0000000000000000".to_string()),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(result.total, 0);
+    }
+
+    #[tokio::test]
+    async fn test_query_log_rollup_tiers() {
+        let (_dir, db) = setup_test_db().await;
+        let now = Utc::now();
+        let old = now - chrono::Duration::days(3);
+
+        for (name, cache_hit, created_at) in [
+            ("a.example.com", false, old),
+            ("a.example.com", true, old),
+            ("b.example.com", false, old),
+            ("c.example.com", false, now),
+        ] {
+            sqlx::query(
+                "INSERT INTO query_logs (client_ip, query_name, query_type, response_code, response_time, cache_hit, created_at) \
+                 VALUES ('10.0.0.1', ?, 'A', 'NOERROR', 10, ?, ?)",
+            )
+            .bind(name)
+            .bind(cache_hit)
+            .bind(created_at)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        }
+
+        let rollups = db.query_log_rollups();
+        let result = rollups.roll_up(now).await.unwrap();
+        assert_eq!(result.hours, 1);
+        assert_eq!(result.days, 1);
+
+        // A second run must not count anything twice
+        let result = rollups.roll_up(now).await.unwrap();
+        assert_eq!(result.hours, 0);
+
+        // Raw rows past retention are gone, hourly rows pruned to the daily tier
+        db.query_logs().delete_old(1).await.unwrap();
+        rollups.prune(now, 1, 365).await.unwrap();
+
+        assert_eq!(rollups.totals(None, None, None).await.unwrap(), (4, 1));
+
+        let domains = rollups.summary(LogDimension::Domain, None, None, None, 10).await.unwrap();
+        assert_eq!(domains.len(), 3);
+        assert_eq!(domains[0].name.as_deref(), Some("a.example.com"));
+        assert_eq!(domains[0].queries, 2);
+        assert_eq!(domains[0].avg_response_time, 10.0);
+
+        let recent = rollups
+            .summary(LogDimension::Domain, Some(now - chrono::Duration::days(1)), None, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].name.as_deref(), Some("c.example.com"));
     }
 
     #[tokio::test]
     async fn test_system_config_crud() {
-        let db = setup_test_db().await;
+        let (_dir, db) = setup_test_db().await;
         let repo = db.system_config();
 
         // Set
@@ -957,9 +2298,62 @@ mod tests {
         assert!(not_found.is_none());
     }
 
+    #[tokio::test]
+    async fn test_notification_lifecycle() {
+        let (_dir, db) = setup_test_db().await;
+        let repo = db.notifications();
+
+        let mut ids = Vec::new();
+        for (severity, source) in [
+            (NotificationSeverity::Warning, "upstream"),
+            (NotificationSeverity::Info, "backup"),
+            (NotificationSeverity::Critical, "database"),
+        ] {
+            let created = repo
+                .create(CreateNotification {
+                    severity,
+                    source: source.to_string(),
+                    message: format!("{} event", source),
+                    metadata: NotificationMetadata::default(),
+                })
+                .await
+                .unwrap();
+            ids.push(created.id);
+        }
+        assert_eq!(repo.unread_count().await.unwrap(), 3);
+
+        let warnings = repo
+            .list(NotificationFilter {
+                severity: Some("warning".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(warnings.total, 1);
+        assert_eq!(warnings.items[0].source, "upstream");
+
+        assert_eq!(repo.mark_read(Some(&ids[..2])).await.unwrap(), 2);
+        let unread = repo
+            .list(NotificationFilter {
+                unread: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(unread.items.len(), 1);
+        assert_eq!(unread.items[0].id, ids[2]);
+
+        assert_eq!(repo.mark_read(None).await.unwrap(), 1);
+        assert_eq!(repo.unread_count().await.unwrap(), 0);
+
+        assert!(repo.delete(ids[0]).await.unwrap());
+        assert!(!repo.delete(ids[0]).await.unwrap());
+        assert_eq!(repo.delete_before(Utc::now() + chrono::Duration::seconds(1)).await.unwrap(), 2);
+    }
+
     #[tokio::test]
     async fn test_stats_cache() {
-        let db = setup_test_db().await;
+        let (_dir, db) = setup_test_db().await;
         let repo = db.query_logs();
 
         // Initial stats should be empty
@@ -975,6 +2369,16 @@ mod tests {
             response_time: Some(10),
             cache_hit: true,
             upstream_used: Some("test".to_string()),
+            tenant_id: None,
+            category: None,
+            answered_by: None,
+            trace_id: None,
+            sample_rate: 1,
+            protocol: None,
+            extended_error: None,
+            source: None,
+            block_reason: None,
+            created_at: None,
         }).await.unwrap();
 
         // Stats should update immediately (from cache)
@@ -992,6 +2396,16 @@ mod tests {
             response_time: Some(20),
             cache_hit: false,
             upstream_used: Some("test".to_string()),
+            tenant_id: None,
+            category: None,
+            answered_by: None,
+            trace_id: None,
+            sample_rate: 1,
+            protocol: None,
+            extended_error: None,
+            source: None,
+            block_reason: None,
+            created_at: None,
         }).await.unwrap();
         
         let stats = repo.get_stats().await.unwrap();
@@ -1004,6 +2418,35 @@ mod tests {
         assert_eq!(db_stats.total_queries, 2);
         assert_eq!(db_stats.cache_hits, 1);
         assert_eq!(db_stats.queries_today, 2);
+
+        // A sampled row counts for the queries it stands for
+        repo.create(CreateQueryLog {
+            client_ip: "127.0.0.1".to_string(),
+            query_name: "test3.com".to_string(),
+            query_type: "A".to_string(),
+            response_code: Some("NOERROR".to_string()),
+            response_time: Some(5),
+            cache_hit: true,
+            upstream_used: None,
+            tenant_id: None,
+            category: None,
+            answered_by: None,
+            trace_id: None,
+            sample_rate: 100,
+            protocol: None,
+            extended_error: None,
+            source: None,
+            block_reason: None,
+            created_at: None,
+        }).await.unwrap();
+
+        let stats = repo.get_stats().await.unwrap();
+        assert_eq!(stats.total_queries, 102);
+        assert_eq!(stats.cache_hits, 101);
+        let db_stats = repo.get_stats_db().await.unwrap();
+        assert_eq!(db_stats.total_queries, 102);
+        assert_eq!(db_stats.cache_hits, 101);
+        assert_eq!(db_stats.queries_today, 102);
     }
 }
 
@@ -1041,7 +2484,21 @@ impl ServerListenerRepository {
 
     /// Update server listener
     pub async fn update(&self, protocol: &str, update: UpdateServerListener) -> Result<Option<ServerListener>> {
-        let existing = self.get_by_protocol(protocol).await?;
+        Self::update_on(&mut *self.pool.acquire().await?, protocol, update).await
+    }
+
+    /// Update server listener on a connection, e.g. inside a transaction
+    pub async fn update_on(
+        conn: &mut SqliteConnection,
+        protocol: &str,
+        update: UpdateServerListener,
+    ) -> Result<Option<ServerListener>> {
+        let existing = sqlx::query_as::<_, ServerListener>(
+            "SELECT * FROM server_listeners WHERE protocol = ?"
+        )
+        .bind(protocol)
+        .fetch_optional(&mut *conn)
+        .await?;
         if existing.is_none() {
             return Ok(None);
         }
@@ -1060,11 +2517,25 @@ impl ServerListenerRepository {
             Some(s) => Some(s),
             None => existing.tls_key,
         };
+        let interface = match update.interface {
+            Some(s) if s.is_empty() => None,
+            Some(s) => Some(s),
+            None => existing.interface,
+        };
+        let profile_id = match update.profile_id {
+            Some(0) => None,
+            Some(id) => Some(id),
+            None => existing.profile_id,
+        };
+        let rrl_responses_per_second = update.rrl_responses_per_second.unwrap_or(existing.rrl_responses_per_second);
+        let rrl_slip = update.rrl_slip.unwrap_or(existing.rrl_slip);
+        let resolution_mode = update.resolution_mode.unwrap_or(existing.resolution_mode);
 
         let result = sqlx::query_as::<_, ServerListener>(
             r#"
             UPDATE server_listeners 
-            SET enabled = ?, bind_address = ?, port = ?, tls_cert = ?, tls_key = ?, updated_at = CURRENT_TIMESTAMP
+            SET enabled = ?, bind_address = ?, port = ?, tls_cert = ?, tls_key = ?, interface = ?, profile_id = ?,
+                rrl_responses_per_second = ?, rrl_slip = ?, resolution_mode = ?, updated_at = CURRENT_TIMESTAMP
             WHERE protocol = ?
             RETURNING *
             "#
@@ -1074,8 +2545,13 @@ impl ServerListenerRepository {
         .bind(port)
         .bind(tls_cert)
         .bind(tls_key)
+        .bind(interface)
+        .bind(profile_id)
+        .bind(rrl_responses_per_second)
+        .bind(rrl_slip)
+        .bind(resolution_mode)
         .bind(protocol)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *conn)
         .await?;
 
         Ok(result)
@@ -1091,3 +2567,2004 @@ impl ServerListenerRepository {
         Ok(listeners)
     }
 }
+
+/// Repository for tenants
+pub struct TenantRepository {
+    pool: SqlitePool,
+}
+
+impl TenantRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Generate a new random tenant API token
+    fn generate_token() -> String {
+        format!("fdt_{}", uuid::Uuid::new_v4().simple())
+    }
+
+    /// Create a new tenant with a freshly generated API token
+    ///
+    /// The insert runs to completion before the tenant is read back, so the
+    /// token authenticates as soon as this returns.
+    pub async fn create(&self, tenant: CreateTenant) -> Result<TenantWithToken> {
+        let api_token = Self::generate_token();
+        let now = Utc::now();
+        let result = sqlx::query(
+            r#"
+            INSERT INTO tenants (name, description, api_token, client_subnets, listeners, enabled, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&tenant.name)
+        .bind(&tenant.description)
+        .bind(hash_token(&api_token))
+        .bind(&tenant.client_subnets)
+        .bind(&tenant.listeners)
+        .bind(tenant.enabled)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        let tenant = self
+            .get_by_id(result.last_insert_rowid())
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Tenant vanished after insert"))?;
+        Ok(TenantWithToken { tenant, api_token })
+    }
+
+    /// Get a tenant by ID
+    pub async fn get_by_id(&self, id: i64) -> Result<Option<Tenant>> {
+        let result = sqlx::query_as::<_, Tenant>("SELECT * FROM tenants WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Get an enabled tenant by its API token
+    pub async fn get_by_token(&self, token: &str) -> Result<Option<Tenant>> {
+        let result = sqlx::query_as::<_, Tenant>(
+            "SELECT * FROM tenants WHERE api_token = ? AND enabled = TRUE",
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// List all tenants
+    pub async fn list(&self) -> Result<Vec<Tenant>> {
+        let result = sqlx::query_as::<_, Tenant>("SELECT * FROM tenants ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// List enabled tenants
+    pub async fn list_enabled(&self) -> Result<Vec<Tenant>> {
+        let result = sqlx::query_as::<_, Tenant>(
+            "SELECT * FROM tenants WHERE enabled = TRUE ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Update a tenant
+    pub async fn update(&self, id: i64, update: UpdateTenant) -> Result<Option<Tenant>> {
+        let existing = match self.get_by_id(id).await? {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+
+        let name = update.name.unwrap_or(existing.name);
+        let description = update.description.or(existing.description);
+        let client_subnets = update.client_subnets.unwrap_or(existing.client_subnets);
+        let listeners = update.listeners.unwrap_or(existing.listeners);
+        let enabled = update.enabled.unwrap_or(existing.enabled);
+
+        let result = sqlx::query_as::<_, Tenant>(
+            r#"
+            UPDATE tenants
+            SET name = ?, description = ?, client_subnets = ?, listeners = ?, enabled = ?, updated_at = ?
+            WHERE id = ?
+            RETURNING *
+            "#,
+        )
+        .bind(&name)
+        .bind(&description)
+        .bind(&client_subnets)
+        .bind(&listeners)
+        .bind(enabled)
+        .bind(Utc::now())
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Replace a tenant's API token, invalidating the old one
+    ///
+    /// Like `create`, the update runs to completion before the tenant is read
+    /// back, so the old token stops working as soon as this returns.
+    pub async fn rotate_token(&self, id: i64) -> Result<Option<TenantWithToken>> {
+        let api_token = Self::generate_token();
+        let result = sqlx::query("UPDATE tenants SET api_token = ?, updated_at = ? WHERE id = ?")
+            .bind(hash_token(&api_token))
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        let tenant = self.get_by_id(id).await?;
+        Ok(tenant.map(|tenant| TenantWithToken { tenant, api_token }))
+    }
+
+    /// Delete a tenant together with the records and rules it owns
+    ///
+    /// Query logs are kept for auditing.
+    pub async fn delete(&self, id: i64) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM dns_records WHERE tenant_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM rewrite_rules WHERE tenant_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let result = sqlx::query("DELETE FROM tenants WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Repository for scoped API tokens
+pub struct ApiTokenRepository {
+    pool: SqlitePool,
+}
+
+impl ApiTokenRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Create a token with a freshly generated secret
+    ///
+    /// Only the digest is stored; the insert runs to completion before the
+    /// row is read back, so the secret works as soon as this returns.
+    pub async fn create(&self, token: CreateApiToken) -> Result<ApiTokenWithSecret> {
+        let secret = format!("fda_{}", uuid::Uuid::new_v4().simple());
+        let result = sqlx::query(
+            r#"
+            INSERT INTO api_tokens (name, token, scopes, enabled, expires_at, created_at)
+            VALUES (?, ?, ?, TRUE, ?, ?)
+            "#,
+        )
+        .bind(&token.name)
+        .bind(hash_token(&secret))
+        .bind(token.scopes.join(","))
+        .bind(token.expires_at)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        let api_token = self
+            .get_by_id(result.last_insert_rowid())
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("API token vanished after insert"))?;
+        Ok(ApiTokenWithSecret { api_token, token: secret })
+    }
+
+    /// Get a token by ID
+    pub async fn get_by_id(&self, id: i64) -> Result<Option<ApiToken>> {
+        let result = sqlx::query_as::<_, ApiToken>("SELECT * FROM api_tokens WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// List all tokens
+    pub async fn list(&self) -> Result<Vec<ApiToken>> {
+        let result = sqlx::query_as::<_, ApiToken>("SELECT * FROM api_tokens ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Get an enabled, unexpired token by its secret and record the use
+    pub async fn authenticate(&self, token: &str) -> Result<Option<ApiToken>> {
+        let now = Utc::now();
+        let result = sqlx::query_as::<_, ApiToken>(
+            r#"
+            UPDATE api_tokens SET last_used_at = ?
+            WHERE token = ? AND enabled = TRUE AND (expires_at IS NULL OR expires_at > ?)
+            RETURNING *
+            "#,
+        )
+        .bind(now)
+        .bind(hash_token(token))
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Update a token's name, scopes or enabled flag
+    pub async fn update(&self, id: i64, update: UpdateApiToken) -> Result<Option<ApiToken>> {
+        let existing = match self.get_by_id(id).await? {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+
+        let name = update.name.unwrap_or(existing.name);
+        let scopes = update.scopes.map(|s| s.join(",")).unwrap_or(existing.scopes);
+        let enabled = update.enabled.unwrap_or(existing.enabled);
+
+        let result = sqlx::query_as::<_, ApiToken>(
+            "UPDATE api_tokens SET name = ?, scopes = ?, enabled = ? WHERE id = ? RETURNING *",
+        )
+        .bind(&name)
+        .bind(&scopes)
+        .bind(enabled)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Delete a token
+    pub async fn delete(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM api_tokens WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Repository for delegated admin accounts
+pub struct DelegatedAdminRepository {
+    pool: SqlitePool,
+}
+
+impl DelegatedAdminRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Create an account; `password_hash` is the bcrypt hash of the password
+    pub async fn create(&self, username: &str, password_hash: &str, zones: &[String]) -> Result<DelegatedAdmin> {
+        let now = Utc::now();
+        let result = sqlx::query_as::<_, DelegatedAdmin>(
+            r#"
+            INSERT INTO delegated_admins (username, password_hash, zones, enabled, created_at, updated_at)
+            VALUES (?, ?, ?, TRUE, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(username)
+        .bind(password_hash)
+        .bind(zones.join(","))
+        .bind(now)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Get an account by ID
+    pub async fn get_by_id(&self, id: i64) -> Result<Option<DelegatedAdmin>> {
+        let result = sqlx::query_as::<_, DelegatedAdmin>("SELECT * FROM delegated_admins WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Get an account by username
+    pub async fn get_by_username(&self, username: &str) -> Result<Option<DelegatedAdmin>> {
+        let result = sqlx::query_as::<_, DelegatedAdmin>("SELECT * FROM delegated_admins WHERE username = ?")
+            .bind(username)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// List all accounts
+    pub async fn list(&self) -> Result<Vec<DelegatedAdmin>> {
+        let result = sqlx::query_as::<_, DelegatedAdmin>("SELECT * FROM delegated_admins ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Update an account's password hash, zones or enabled flag
+    pub async fn update(
+        &self,
+        id: i64,
+        password_hash: Option<String>,
+        zones: Option<Vec<String>>,
+        enabled: Option<bool>,
+    ) -> Result<Option<DelegatedAdmin>> {
+        let existing = match self.get_by_id(id).await? {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+
+        let password_hash = password_hash.unwrap_or(existing.password_hash);
+        let zones = zones.map(|z| z.join(",")).unwrap_or(existing.zones);
+        let enabled = enabled.unwrap_or(existing.enabled);
+
+        let result = sqlx::query_as::<_, DelegatedAdmin>(
+            r#"
+            UPDATE delegated_admins SET password_hash = ?, zones = ?, enabled = ?, updated_at = ?
+            WHERE id = ?
+            RETURNING *
+            "#,
+        )
+        .bind(&password_hash)
+        .bind(&zones)
+        .bind(enabled)
+        .bind(Utc::now())
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Record a successful login
+    pub async fn touch_login(&self, id: i64) -> Result<()> {
+        sqlx::query("UPDATE delegated_admins SET last_login_at = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Delete an account
+    pub async fn delete(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM delegated_admins WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Repository for the cache purge audit trail
+pub struct CachePurgeAuditRepository {
+    pool: SqlitePool,
+}
+
+impl CachePurgeAuditRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Record a purge
+    pub async fn create(&self, entry: CreateCachePurgeAudit) -> Result<CachePurgeAudit> {
+        let result = sqlx::query_as::<_, CachePurgeAudit>(
+            r#"
+            INSERT INTO cache_purge_audit (actor, client_ip, patterns, purged, created_at)
+            VALUES (?, ?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(&entry.actor)
+        .bind(&entry.client_ip)
+        .bind(serde_json::to_string(&entry.patterns)?)
+        .bind(entry.purged)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// List the most recent purges
+    pub async fn list(&self, limit: i64) -> Result<Vec<CachePurgeAudit>> {
+        let result = sqlx::query_as::<_, CachePurgeAudit>(
+            "SELECT * FROM cache_purge_audit ORDER BY id DESC LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+}
+
+/// Repository for domain category lists and their domains
+pub struct CategoryListRepository {
+    pool: SqlitePool,
+}
+
+impl CategoryListRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Create a new category list
+    pub async fn create(&self, list: CreateCategoryList) -> Result<CategoryList> {
+        let now = Utc::now();
+        let result = sqlx::query_as::<_, CategoryList>(
+            r#"
+            INSERT INTO category_lists (name, category, url, enabled, domain_count, created_at, updated_at)
+            VALUES (?, ?, ?, ?, 0, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(&list.name)
+        .bind(&list.category)
+        .bind(&list.url)
+        .bind(list.enabled)
+        .bind(now)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Get a category list by ID
+    pub async fn get_by_id(&self, id: i64) -> Result<Option<CategoryList>> {
+        let result = sqlx::query_as::<_, CategoryList>("SELECT * FROM category_lists WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// List all category lists
+    pub async fn list(&self) -> Result<Vec<CategoryList>> {
+        let result = sqlx::query_as::<_, CategoryList>("SELECT * FROM category_lists ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Update a category list
+    pub async fn update(&self, id: i64, update: UpdateCategoryList) -> Result<Option<CategoryList>> {
+        let existing = match self.get_by_id(id).await? {
+            Some(l) => l,
+            None => return Ok(None),
+        };
+
+        let name = update.name.unwrap_or(existing.name);
+        let category = update.category.unwrap_or(existing.category);
+        let url = update.url.or(existing.url);
+        let enabled = update.enabled.unwrap_or(existing.enabled);
+
+        let result = sqlx::query_as::<_, CategoryList>(
+            r#"
+            UPDATE category_lists
+            SET name = ?, category = ?, url = ?, enabled = ?, updated_at = ?
+            WHERE id = ?
+            RETURNING *
+            "#,
+        )
+        .bind(&name)
+        .bind(&category)
+        .bind(&url)
+        .bind(enabled)
+        .bind(Utc::now())
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Delete a category list together with its domains
+    pub async fn delete(&self, id: i64) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM category_domains WHERE list_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let result = sqlx::query("DELETE FROM category_lists WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// List the domains of a category list
+    pub async fn list_domains(&self, list_id: i64) -> Result<Vec<CategoryDomain>> {
+        let result = sqlx::query_as::<_, CategoryDomain>(
+            "SELECT * FROM category_domains WHERE list_id = ? ORDER BY domain",
+        )
+        .bind(list_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// All (domain, category) pairs from enabled lists
+    pub async fn enabled_entries(&self) -> Result<Vec<(String, String)>> {
+        let result: Vec<(String, String)> = sqlx::query_as(
+            r#"
+            SELECT d.domain, l.category
+            FROM category_domains d
+            JOIN category_lists l ON l.id = d.list_id
+            WHERE l.enabled = TRUE
+            ORDER BY l.id
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Add domains to a list, ignoring duplicates; returns the new domain count
+    pub async fn add_domains(&self, list_id: i64, domains: &[String]) -> Result<i64> {
+        let mut tx = self.pool.begin().await?;
+        let now = Utc::now();
+
+        for domain in domains {
+            sqlx::query(
+                "INSERT OR IGNORE INTO category_domains (list_id, domain, created_at) VALUES (?, ?, ?)",
+            )
+            .bind(list_id)
+            .bind(domain)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let count = Self::update_count(&mut tx, list_id).await?;
+        tx.commit().await?;
+        Ok(count)
+    }
+
+    /// Remove one domain from a list
+    pub async fn remove_domain(&self, list_id: i64, domain: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query("DELETE FROM category_domains WHERE list_id = ? AND domain = ?")
+            .bind(list_id)
+            .bind(domain)
+            .execute(&mut *tx)
+            .await?;
+        Self::update_count(&mut tx, list_id).await?;
+
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Replace all domains of a list (after a download) and stamp the refresh time
+    pub async fn replace_domains(&self, list_id: i64, domains: &[String]) -> Result<i64> {
+        let mut tx = self.pool.begin().await?;
+        let now = Utc::now();
+
+        sqlx::query("DELETE FROM category_domains WHERE list_id = ?")
+            .bind(list_id)
+            .execute(&mut *tx)
+            .await?;
+        for domain in domains {
+            sqlx::query(
+                "INSERT OR IGNORE INTO category_domains (list_id, domain, created_at) VALUES (?, ?, ?)",
+            )
+            .bind(list_id)
+            .bind(domain)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let count = Self::update_count(&mut tx, list_id).await?;
+        sqlx::query("UPDATE category_lists SET last_updated_at = ? WHERE id = ?")
+            .bind(now)
+            .bind(list_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(count)
+    }
+
+    async fn update_count(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>, list_id: i64) -> Result<i64> {
+        let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM category_domains WHERE list_id = ?")
+            .bind(list_id)
+            .fetch_one(&mut **tx)
+            .await?;
+        sqlx::query("UPDATE category_lists SET domain_count = ?, updated_at = ? WHERE id = ?")
+            .bind(count.0)
+            .bind(Utc::now())
+            .bind(list_id)
+            .execute(&mut **tx)
+            .await?;
+        Ok(count.0)
+    }
+}
+
+/// Repository for typo-squatting reference domains and detections
+pub struct TyposquatRepository {
+    pool: SqlitePool,
+}
+
+impl TyposquatRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// List reference domains
+    pub async fn list_domains(&self) -> Result<Vec<TyposquatDomain>> {
+        let result = sqlx::query_as::<_, TyposquatDomain>("SELECT * FROM typosquat_domains ORDER BY domain")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Add reference domains, ignoring duplicates; returns how many were new
+    pub async fn add_domains(&self, domains: &[String]) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let now = Utc::now();
+        let mut added = 0;
+
+        for domain in domains {
+            let result = sqlx::query("INSERT OR IGNORE INTO typosquat_domains (domain, created_at) VALUES (?, ?)")
+                .bind(domain)
+                .bind(now)
+                .execute(&mut *tx)
+                .await?;
+            added += result.rows_affected();
+        }
+
+        tx.commit().await?;
+        Ok(added)
+    }
+
+    /// Delete a reference domain
+    pub async fn delete_domain(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM typosquat_domains WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Record a detection
+    pub async fn create_event(&self, event: CreateTyposquatEvent) -> Result<TyposquatEvent> {
+        let result = sqlx::query_as::<_, TyposquatEvent>(
+            r#"
+            INSERT INTO typosquat_events (query_name, suggestion, distance, client_ip, blocked, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(&event.query_name)
+        .bind(&event.suggestion)
+        .bind(event.distance)
+        .bind(&event.client_ip)
+        .bind(event.blocked)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// List the most recent detections
+    pub async fn list_events(&self, limit: i64) -> Result<Vec<TyposquatEvent>> {
+        let result = sqlx::query_as::<_, TyposquatEvent>(
+            "SELECT * FROM typosquat_events ORDER BY id DESC LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Delete all detections
+    pub async fn clear_events(&self) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM typosquat_events")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// Repository for upstream integrity alerts
+pub struct IntegrityAlertRepository {
+    pool: SqlitePool,
+}
+
+impl IntegrityAlertRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Record a discrepancy
+    pub async fn create(&self, alert: CreateIntegrityAlert) -> Result<IntegrityAlert> {
+        let result = sqlx::query_as::<_, IntegrityAlert>(
+            r#"
+            INSERT INTO integrity_alerts (domain, record_type, answers, created_at)
+            VALUES (?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(&alert.domain)
+        .bind(&alert.record_type)
+        .bind(&alert.answers)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// List the most recent discrepancies
+    pub async fn list(&self, limit: i64) -> Result<Vec<IntegrityAlert>> {
+        let result = sqlx::query_as::<_, IntegrityAlert>(
+            "SELECT * FROM integrity_alerts ORDER BY id DESC LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Delete all alerts
+    pub async fn delete_all(&self) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM integrity_alerts")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// Repository for resolution profiles
+pub struct ResolutionProfileRepository {
+    pool: SqlitePool,
+}
+
+impl ResolutionProfileRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Create a profile
+    pub async fn create(&self, profile: CreateResolutionProfile) -> Result<ResolutionProfile> {
+        let now = Utc::now();
+        let result = sqlx::query_as::<_, ResolutionProfile>(
+            r#"
+            INSERT INTO resolution_profiles (name, description, upstreams, rules, probe_target, priority, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(&profile.name)
+        .bind(&profile.description)
+        .bind(&profile.upstreams)
+        .bind(&profile.rules)
+        .bind(&profile.probe_target)
+        .bind(profile.priority)
+        .bind(now)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Get a profile by ID
+    pub async fn get_by_id(&self, id: i64) -> Result<Option<ResolutionProfile>> {
+        let result = sqlx::query_as::<_, ResolutionProfile>("SELECT * FROM resolution_profiles WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// List all profiles, highest priority first
+    pub async fn list(&self) -> Result<Vec<ResolutionProfile>> {
+        let result = sqlx::query_as::<_, ResolutionProfile>(
+            "SELECT * FROM resolution_profiles ORDER BY priority DESC, id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Update a profile
+    pub async fn update(&self, id: i64, update: UpdateResolutionProfile) -> Result<Option<ResolutionProfile>> {
+        let existing = match self.get_by_id(id).await? {
+            Some(p) => p,
+            None => return Ok(None),
+        };
+
+        let name = update.name.unwrap_or(existing.name);
+        let description = update.description.or(existing.description);
+        let upstreams = update.upstreams.unwrap_or(existing.upstreams);
+        let rules = update.rules.unwrap_or(existing.rules);
+        let probe_target = match update.probe_target {
+            Some(target) if target.is_empty() => None,
+            Some(target) => Some(target),
+            None => existing.probe_target,
+        };
+        let priority = update.priority.unwrap_or(existing.priority);
+
+        let result = sqlx::query_as::<_, ResolutionProfile>(
+            r#"
+            UPDATE resolution_profiles
+            SET name = ?, description = ?, upstreams = ?, rules = ?, probe_target = ?, priority = ?, updated_at = ?
+            WHERE id = ?
+            RETURNING *
+            "#,
+        )
+        .bind(&name)
+        .bind(&description)
+        .bind(&upstreams)
+        .bind(&rules)
+        .bind(&probe_target)
+        .bind(priority)
+        .bind(Utc::now())
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Delete a profile, unpinning it from listeners
+    pub async fn delete(&self, id: i64) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE server_listeners SET profile_id = NULL WHERE profile_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let result = sqlx::query("DELETE FROM resolution_profiles WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Repository for saved query log filters
+pub struct SavedLogFilterRepository {
+    pool: SqlitePool,
+}
+
+impl SavedLogFilterRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Save a filter
+    pub async fn create(&self, filter: CreateSavedLogFilter) -> Result<SavedLogFilter> {
+        let now = Utc::now();
+        let result = sqlx::query_as::<_, SavedLogFilter>(
+            r#"
+            INSERT INTO saved_log_filters (name, description, filter, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(&filter.name)
+        .bind(&filter.description)
+        .bind(serde_json::to_string(&filter.filter)?)
+        .bind(now)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Get a saved filter by ID
+    pub async fn get_by_id(&self, id: i64) -> Result<Option<SavedLogFilter>> {
+        let result = sqlx::query_as::<_, SavedLogFilter>("SELECT * FROM saved_log_filters WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Get a saved filter by name
+    pub async fn get_by_name(&self, name: &str) -> Result<Option<SavedLogFilter>> {
+        let result = sqlx::query_as::<_, SavedLogFilter>("SELECT * FROM saved_log_filters WHERE name = ?")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// List saved filters by name
+    pub async fn list(&self) -> Result<Vec<SavedLogFilter>> {
+        let result = sqlx::query_as::<_, SavedLogFilter>("SELECT * FROM saved_log_filters ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Update a saved filter
+    pub async fn update(&self, id: i64, update: UpdateSavedLogFilter) -> Result<Option<SavedLogFilter>> {
+        let existing = match self.get_by_id(id).await? {
+            Some(f) => f,
+            None => return Ok(None),
+        };
+
+        let name = update.name.unwrap_or(existing.name);
+        let description = match update.description {
+            Some(d) if d.is_empty() => None,
+            Some(d) => Some(d),
+            None => existing.description,
+        };
+        let filter = update.filter.unwrap_or(existing.filter);
+
+        let result = sqlx::query_as::<_, SavedLogFilter>(
+            r#"
+            UPDATE saved_log_filters
+            SET name = ?, description = ?, filter = ?, updated_at = ?
+            WHERE id = ?
+            RETURNING *
+            "#,
+        )
+        .bind(&name)
+        .bind(&description)
+        .bind(serde_json::to_string(&filter)?)
+        .bind(Utc::now())
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Delete a saved filter
+    pub async fn delete(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM saved_log_filters WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Repository for in-app notifications
+pub struct NotificationRepository {
+    pool: SqlitePool,
+}
+
+impl NotificationRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Store a notification
+    pub async fn create(&self, notification: CreateNotification) -> Result<Notification> {
+        let result = sqlx::query_as::<_, Notification>(
+            r#"
+            INSERT INTO notifications (severity, source, message, metadata, read, created_at)
+            VALUES (?, ?, ?, ?, FALSE, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(notification.severity.as_str())
+        .bind(&notification.source)
+        .bind(&notification.message)
+        .bind(serde_json::to_string(&notification.metadata)?)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// List notifications, newest first
+    pub async fn list(&self, filter: NotificationFilter) -> Result<PaginatedResult<Notification>> {
+        let limit = filter.limit.unwrap_or(50).min(500);
+        let offset = filter.offset.unwrap_or(0);
+
+        let mut query_builder = sqlx::QueryBuilder::new("SELECT * FROM notifications WHERE 1=1");
+        let mut count_builder = sqlx::QueryBuilder::new("SELECT COUNT(*) FROM notifications WHERE 1=1");
+
+        if let Some(ref severity) = filter.severity {
+            query_builder.push(" AND severity = ");
+            query_builder.push_bind(severity.clone());
+            count_builder.push(" AND severity = ");
+            count_builder.push_bind(severity);
+        }
+
+        if let Some(ref source) = filter.source {
+            query_builder.push(" AND source = ");
+            query_builder.push_bind(source.clone());
+            count_builder.push(" AND source = ");
+            count_builder.push_bind(source);
+        }
+
+        if filter.unread {
+            query_builder.push(" AND read = FALSE");
+            count_builder.push(" AND read = FALSE");
+        }
+
+        let count = count_builder.build_query_as::<(i64,)>().fetch_one(&self.pool).await?.0;
+
+        query_builder.push(" ORDER BY id DESC LIMIT ");
+        query_builder.push_bind(limit);
+        query_builder.push(" OFFSET ");
+        query_builder.push_bind(offset);
+        let items = query_builder
+            .build_query_as::<Notification>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(PaginatedResult {
+            items,
+            total: count,
+            limit,
+            offset,
+        })
+    }
+
+    /// Number of unread notifications
+    pub async fn unread_count(&self) -> Result<i64> {
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM notifications WHERE read = FALSE")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Mark notifications as read, all of them when `ids` is `None`
+    pub async fn mark_read(&self, ids: Option<&[i64]>) -> Result<u64> {
+        let mut builder = sqlx::QueryBuilder::new("UPDATE notifications SET read = TRUE WHERE read = FALSE");
+        if let Some(ids) = ids {
+            if ids.is_empty() {
+                return Ok(0);
+            }
+            builder.push(" AND id IN (");
+            let mut separated = builder.separated(", ");
+            for id in ids {
+                separated.push_bind(*id);
+            }
+            builder.push(")");
+        }
+        let result = builder.build().execute(&self.pool).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Delete a notification
+    pub async fn delete(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM notifications WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete notifications created before `cutoff`
+    pub async fn delete_before(&self, cutoff: chrono::DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM notifications WHERE created_at < ?")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// Repository for RPZ feeds
+pub struct RpzFeedRepository {
+    pool: SqlitePool,
+}
+
+impl RpzFeedRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Create a feed
+    pub async fn create(&self, feed: CreateRpzFeed) -> Result<RpzFeed> {
+        let now = Utc::now();
+        let result = sqlx::query_as::<_, RpzFeed>(
+            r#"
+            INSERT INTO rpz_feeds (name, url, enabled, priority, refresh_interval_secs, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(&feed.name)
+        .bind(&feed.url)
+        .bind(feed.enabled)
+        .bind(feed.priority)
+        .bind(feed.refresh_interval_secs)
+        .bind(now)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Get a feed by ID
+    pub async fn get_by_id(&self, id: i64) -> Result<Option<RpzFeed>> {
+        let result = sqlx::query_as::<_, RpzFeed>("SELECT * FROM rpz_feeds WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// List all feeds
+    pub async fn list(&self) -> Result<Vec<RpzFeed>> {
+        let result = sqlx::query_as::<_, RpzFeed>("SELECT * FROM rpz_feeds ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Update a feed
+    pub async fn update(&self, id: i64, update: UpdateRpzFeed) -> Result<Option<RpzFeed>> {
+        let existing = match self.get_by_id(id).await? {
+            Some(f) => f,
+            None => return Ok(None),
+        };
+
+        let name = update.name.unwrap_or(existing.name);
+        let url = update.url.unwrap_or(existing.url);
+        let enabled = update.enabled.unwrap_or(existing.enabled);
+        let priority = update.priority.unwrap_or(existing.priority);
+        let refresh_interval_secs = update.refresh_interval_secs.unwrap_or(existing.refresh_interval_secs);
+
+        let result = sqlx::query_as::<_, RpzFeed>(
+            r#"
+            UPDATE rpz_feeds
+            SET name = ?, url = ?, enabled = ?, priority = ?, refresh_interval_secs = ?, updated_at = ?
+            WHERE id = ?
+            RETURNING *
+            "#,
+        )
+        .bind(&name)
+        .bind(&url)
+        .bind(enabled)
+        .bind(priority)
+        .bind(refresh_interval_secs)
+        .bind(Utc::now())
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Delete a feed
+    pub async fn delete(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM rpz_feeds WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Record a successful download; `changed` when the rules were replaced
+    pub async fn record_refresh(
+        &self,
+        id: i64,
+        serial: Option<i64>,
+        rule_count: i64,
+        skipped_count: i64,
+        changed: bool,
+    ) -> Result<()> {
+        let now = Utc::now();
+        sqlx::query(
+            r#"
+            UPDATE rpz_feeds
+            SET serial = ?, rule_count = ?, skipped_count = ?, last_checked_at = ?,
+                last_changed_at = CASE WHEN ? THEN ? ELSE last_changed_at END, last_error = NULL
+            WHERE id = ?
+            "#,
+        )
+        .bind(serial)
+        .bind(rule_count)
+        .bind(skipped_count)
+        .bind(now)
+        .bind(changed)
+        .bind(now)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a failed download, keeping the rules of the last import
+    pub async fn record_error(&self, id: i64, error: &str) -> Result<()> {
+        sqlx::query("UPDATE rpz_feeds SET last_checked_at = ?, last_error = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(error)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Config key for the end of the hourly roll-up (exclusive)
+pub const CONFIG_KEY_ROLLUP_HOURLY_UNTIL: &str = "log_rollup_hourly_until";
+/// Config key for the end of the daily roll-up (exclusive)
+pub const CONFIG_KEY_ROLLUP_DAILY_UNTIL: &str = "log_rollup_daily_until";
+/// Config key for how long hourly roll-ups are kept
+pub const CONFIG_KEY_ROLLUP_HOURLY_RETENTION: &str = "log_rollup_hourly_retention_days";
+/// Config key for how long daily roll-ups are kept
+pub const CONFIG_KEY_ROLLUP_DAILY_RETENTION: &str = "log_rollup_daily_retention_days";
+
+pub const DEFAULT_ROLLUP_HOURLY_RETENTION_DAYS: i64 = 90;
+pub const DEFAULT_ROLLUP_DAILY_RETENTION_DAYS: i64 = 730;
+
+/// Columns query logs are aggregated by
+const ROLLUP_DIMENSIONS: &str =
+    "tenant_id, client_ip, query_name, query_type, response_code, answered_by, category, protocol";
+
+/// Dimension to group query analytics by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogDimension {
+    Domain,
+    Client,
+    Type,
+    /// Answering middleware if any, otherwise the response code
+    Outcome,
+    /// Listener protocol
+    Protocol,
+}
+
+impl LogDimension {
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "domain" => Some(Self::Domain),
+            "client" => Some(Self::Client),
+            "type" => Some(Self::Type),
+            "outcome" => Some(Self::Outcome),
+            "protocol" => Some(Self::Protocol),
+            _ => None,
+        }
+    }
+
+    fn expression(&self) -> &'static str {
+        match self {
+            Self::Domain => "query_name",
+            Self::Client => "client_ip",
+            Self::Type => "query_type",
+            Self::Outcome => "COALESCE(answered_by, response_code)",
+            Self::Protocol => "protocol",
+        }
+    }
+}
+
+/// Aggregated query counts for one group
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct QueryLogGroup {
+    /// Domain, client IP, record type, outcome or protocol, depending on the dimension
+    pub name: Option<String>,
+    pub queries: i64,
+    pub cache_hits: i64,
+    pub avg_response_time: f64,
+}
+
+/// Rows written by one roll-up run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RollupResult {
+    pub hours: u64,
+    pub days: u64,
+}
+
+/// Which table serves which part of the timeline
+///
+/// Daily rows serve everything before `hourly_from`, hourly rows serve
+/// `[hourly_from, raw_from)` and raw logs serve `raw_from` onwards. `None`
+/// means the boundary is unbounded (the tier has nothing to serve yet).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RollupTiers {
+    pub hourly_from: Option<chrono::DateTime<Utc>>,
+    pub raw_from: Option<chrono::DateTime<Utc>>,
+}
+
+impl RollupTiers {
+    /// Push a subquery yielding `ts`, the dimension columns, `queries`,
+    /// `cache_hits` and `response_time_total` across all tiers
+    ///
+    /// Time bounds apply at the granularity of each tier, so roll-up rows
+    /// count when their bucket start lies within the range.
+    fn push_source<'a>(
+        &self,
+        builder: &mut sqlx::QueryBuilder<'a, sqlx::Sqlite>,
+        start: Option<chrono::DateTime<Utc>>,
+        end: Option<chrono::DateTime<Utc>>,
+        tenant_id: Option<i64>,
+    ) {
+        let filter = |builder: &mut sqlx::QueryBuilder<'a, sqlx::Sqlite>, ts: &str| {
+            if let Some(start) = start {
+                builder.push(format!(" AND {} >= ", ts)).push_bind(start);
+            }
+            if let Some(end) = end {
+                builder.push(format!(" AND {} <= ", ts)).push_bind(end);
+            }
+            if let Some(tenant_id) = tenant_id {
+                builder.push(" AND tenant_id = ").push_bind(tenant_id);
+            }
+        };
+
+        builder.push(format!(
+            "(SELECT created_at AS ts, {}, sample_rate AS queries, \
+             CASE WHEN cache_hit THEN sample_rate ELSE 0 END AS cache_hits, \
+             COALESCE(response_time, 0) * sample_rate AS response_time_total FROM query_logs WHERE 1=1",
+            ROLLUP_DIMENSIONS
+        ));
+        if let Some(raw_from) = self.raw_from {
+            builder.push(" AND created_at >= ").push_bind(raw_from);
+        }
+        filter(builder, "created_at");
+
+        if let Some(raw_from) = self.raw_from {
+            builder.push(format!(
+                " UNION ALL SELECT bucket AS ts, {}, queries, cache_hits, response_time_total \
+                 FROM query_log_hourly WHERE bucket < ",
+                ROLLUP_DIMENSIONS
+            ));
+            builder.push_bind(raw_from);
+            if let Some(hourly_from) = self.hourly_from {
+                builder.push(" AND bucket >= ").push_bind(hourly_from);
+            }
+            filter(builder, "bucket");
+        }
+
+        if let Some(hourly_from) = self.hourly_from {
+            builder.push(format!(
+                " UNION ALL SELECT bucket AS ts, {}, queries, cache_hits, response_time_total \
+                 FROM query_log_daily WHERE bucket < ",
+                ROLLUP_DIMENSIONS
+            ));
+            builder.push_bind(hourly_from);
+            filter(builder, "bucket");
+        }
+        builder.push(")");
+    }
+}
+
+/// Start of the hour containing `t`
+fn floor_hour(t: chrono::DateTime<Utc>) -> chrono::DateTime<Utc> {
+    use chrono::DurationRound;
+    t.duration_trunc(chrono::Duration::hours(1)).unwrap_or(t)
+}
+
+/// Start of the (UTC) day containing `t`
+fn floor_day(t: chrono::DateTime<Utc>) -> chrono::DateTime<Utc> {
+    use chrono::DurationRound;
+    t.duration_trunc(chrono::Duration::days(1)).unwrap_or(t)
+}
+
+/// One level of the roll-up: periods of `source` aggregated into `target`
+struct RollupTier {
+    source: &'static str,
+    /// Timestamp column of `source`
+    ts: &'static str,
+    target: &'static str,
+    watermark_key: &'static str,
+    floor: fn(chrono::DateTime<Utc>) -> chrono::DateTime<Utc>,
+    period: chrono::Duration,
+}
+
+/// Repository for query log roll-ups
+///
+/// Raw query logs are aggregated into hourly buckets once an hour is
+/// complete, and hourly buckets into daily ones once a UTC day is complete.
+/// Progress is tracked with watermarks in system config, so each period is
+/// rolled up exactly once even after the raw rows are deleted.
+pub struct QueryLogRollupRepository {
+    pool: SqlitePool,
+}
+
+impl QueryLogRollupRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    async fn watermark(&self, key: &str) -> Result<Option<chrono::DateTime<Utc>>> {
+        let value = SystemConfigRepository::new(self.pool.clone()).get(key).await?;
+        Ok(value
+            .and_then(|v| chrono::DateTime::parse_from_rfc3339(&v).ok())
+            .map(|t| t.with_timezone(&Utc)))
+    }
+
+    /// Aggregate one period of `source` into `target` and advance the watermark
+    async fn roll_period(
+        &self,
+        source: &str,
+        ts: &str,
+        target: &str,
+        watermark_key: &str,
+        (bucket, next): (chrono::DateTime<Utc>, chrono::DateTime<Utc>),
+    ) -> Result<()> {
+        let totals = if source == "query_logs" {
+            "SUM(sample_rate), SUM(CASE WHEN cache_hit THEN sample_rate ELSE 0 END), \
+             SUM(COALESCE(response_time, 0) * sample_rate)"
+        } else {
+            "SUM(queries), SUM(cache_hits), SUM(response_time_total)"
+        };
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(&format!(
+            "INSERT INTO {target} (bucket, {dims}, queries, cache_hits, response_time_total) \
+             SELECT ?, {dims}, {totals} FROM {source} WHERE {ts} >= ? AND {ts} < ? GROUP BY {dims}",
+            target = target,
+            dims = ROLLUP_DIMENSIONS,
+            totals = totals,
+            source = source,
+            ts = ts,
+        ))
+        .bind(bucket)
+        .bind(bucket)
+        .bind(next)
+        .execute(&mut *tx)
+        .await?;
+        Self::set_watermark(&mut tx, watermark_key, next).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn set_watermark(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        key: &str,
+        value: chrono::DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO system_config (key, value, updated_at)
+            VALUES (?, ?, CURRENT_TIMESTAMP)
+            ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(key)
+        .bind(value.to_rfc3339())
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Roll up every period of `source` between the watermark and `until`
+    ///
+    /// Only periods that contain rows are visited, so long idle gaps cost
+    /// nothing. Returns the number of periods written.
+    async fn roll_up_tier(&self, tier: RollupTier, until: chrono::DateTime<Utc>) -> Result<u64> {
+        let RollupTier { source, ts, target, watermark_key, floor, period } = tier;
+        let mut cursor = self.watermark(watermark_key).await?;
+        let mut periods = 0;
+
+        loop {
+            let mut next_row = sqlx::QueryBuilder::<sqlx::Sqlite>::new(format!(
+                "SELECT MIN({ts}) FROM {source} WHERE {ts} < ",
+                ts = ts,
+                source = source
+            ));
+            next_row.push_bind(until);
+            if let Some(cursor) = cursor {
+                next_row.push(format!(" AND {} >= ", ts)).push_bind(cursor);
+            }
+            let (next,): (Option<chrono::DateTime<Utc>>,) =
+                next_row.build_query_as().fetch_one(&self.pool).await?;
+
+            let Some(next) = next else {
+                break;
+            };
+            let bucket = floor(next);
+            if cursor.is_some_and(|c| bucket + period <= c) {
+                // Timestamp stored in an unexpected format; never loop on it
+                break;
+            }
+            self.roll_period(source, ts, target, watermark_key, (bucket, bucket + period))
+                .await?;
+            cursor = Some(bucket + period);
+            periods += 1;
+        }
+
+        // Nothing left before `until`: move the watermark up to it
+        if cursor.is_none_or(|c| c < until) {
+            let mut tx = self.pool.begin().await?;
+            Self::set_watermark(&mut tx, watermark_key, until).await?;
+            tx.commit().await?;
+        }
+        Ok(periods)
+    }
+
+    /// Roll up all complete hours and days before `now`
+    pub async fn roll_up(&self, now: chrono::DateTime<Utc>) -> Result<RollupResult> {
+        let hour = floor_hour(now);
+        let hours = self
+            .roll_up_tier(
+                RollupTier {
+                    source: "query_logs",
+                    ts: "created_at",
+                    target: "query_log_hourly",
+                    watermark_key: CONFIG_KEY_ROLLUP_HOURLY_UNTIL,
+                    floor: floor_hour,
+                    period: chrono::Duration::hours(1),
+                },
+                hour,
+            )
+            .await?;
+        let days = self
+            .roll_up_tier(
+                RollupTier {
+                    source: "query_log_hourly",
+                    ts: "bucket",
+                    target: "query_log_daily",
+                    watermark_key: CONFIG_KEY_ROLLUP_DAILY_UNTIL,
+                    floor: floor_day,
+                    period: chrono::Duration::days(1),
+                },
+                floor_day(hour),
+            )
+            .await?;
+
+        Ok(RollupResult { hours, days })
+    }
+
+    /// Delete roll-up rows past their retention as of `now`
+    ///
+    /// Hourly rows are only deleted once they are part of the daily tier.
+    pub async fn prune(&self, now: chrono::DateTime<Utc>, hourly_days: i64, daily_days: i64) -> Result<u64> {
+        let daily_until = self.watermark(CONFIG_KEY_ROLLUP_DAILY_UNTIL).await?;
+        let mut deleted = 0;
+
+        if let Some(daily_until) = daily_until {
+            let cutoff = (now - chrono::Duration::days(hourly_days)).min(daily_until);
+            deleted += sqlx::query("DELETE FROM query_log_hourly WHERE bucket < ?")
+                .bind(cutoff)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+        }
+
+        deleted += sqlx::query("DELETE FROM query_log_daily WHERE bucket < ?")
+            .bind(now - chrono::Duration::days(daily_days))
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        Ok(deleted)
+    }
+
+    /// Delete all roll-up rows
+    pub async fn delete_all(&self) -> Result<u64> {
+        let mut deleted = 0;
+        for table in ["query_log_hourly", "query_log_daily"] {
+            deleted += sqlx::query(&format!("DELETE FROM {}", table))
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+        }
+        Ok(deleted)
+    }
+
+    /// End of the rolled-up range; older raw logs may be deleted
+    pub async fn rolled_up_until(&self) -> Result<Option<chrono::DateTime<Utc>>> {
+        self.watermark(CONFIG_KEY_ROLLUP_HOURLY_UNTIL).await
+    }
+
+    /// Current tier boundaries
+    pub async fn tiers(&self) -> Result<RollupTiers> {
+        let Some(raw_from) = self.watermark(CONFIG_KEY_ROLLUP_HOURLY_UNTIL).await? else {
+            return Ok(RollupTiers::default());
+        };
+        let Some(daily_until) = self.watermark(CONFIG_KEY_ROLLUP_DAILY_UNTIL).await? else {
+            return Ok(RollupTiers {
+                hourly_from: None,
+                raw_from: Some(raw_from),
+            });
+        };
+
+        // Prefer hourly rows where they still exist; days whose hourly rows
+        // were pruned are served by the daily tier
+        let (oldest_hourly,): (Option<chrono::DateTime<Utc>>,) =
+            sqlx::query_as("SELECT MIN(bucket) FROM query_log_hourly")
+                .fetch_one(&self.pool)
+                .await?;
+        let hourly_from = match oldest_hourly {
+            Some(oldest) if floor_day(oldest) == oldest => oldest.min(daily_until),
+            Some(oldest) => (floor_day(oldest) + chrono::Duration::days(1)).min(daily_until),
+            None => daily_until,
+        };
+
+        Ok(RollupTiers {
+            hourly_from: Some(hourly_from),
+            raw_from: Some(raw_from),
+        })
+    }
+
+    /// Top groups by query count across all tiers
+    pub async fn summary(
+        &self,
+        dimension: LogDimension,
+        start_time: Option<chrono::DateTime<Utc>>,
+        end_time: Option<chrono::DateTime<Utc>>,
+        tenant_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<QueryLogGroup>> {
+        let tiers = self.tiers().await?;
+        let mut builder = sqlx::QueryBuilder::new(format!(
+            "SELECT {} AS name, SUM(queries) AS queries, SUM(cache_hits) AS cache_hits, \
+             CAST(SUM(response_time_total) AS REAL) / MAX(SUM(queries), 1) AS avg_response_time FROM ",
+            dimension.expression()
+        ));
+        tiers.push_source(&mut builder, start_time, end_time, tenant_id);
+        builder.push(" GROUP BY name ORDER BY queries DESC LIMIT ");
+        builder.push_bind(limit);
+
+        let result = builder
+            .build_query_as::<QueryLogGroup>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Total queries and cache hits across all tiers
+    pub async fn totals(
+        &self,
+        start_time: Option<chrono::DateTime<Utc>>,
+        end_time: Option<chrono::DateTime<Utc>>,
+        tenant_id: Option<i64>,
+    ) -> Result<(i64, i64)> {
+        let tiers = self.tiers().await?;
+        let mut builder = sqlx::QueryBuilder::new(
+            "SELECT COALESCE(SUM(queries), 0), COALESCE(SUM(cache_hits), 0) FROM ",
+        );
+        tiers.push_source(&mut builder, start_time, end_time, tenant_id);
+
+        let result = builder
+            .build_query_as::<(i64, i64)>()
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Query and block counts per category across all tiers
+    pub async fn category_stats(
+        &self,
+        start_time: Option<chrono::DateTime<Utc>>,
+        end_time: Option<chrono::DateTime<Utc>>,
+        tenant_id: Option<i64>,
+    ) -> Result<Vec<CategoryStat>> {
+        let tiers = self.tiers().await?;
+        let mut builder = sqlx::QueryBuilder::new(
+            r#"
+            SELECT category,
+                   SUM(queries) AS queries,
+                   SUM(CASE WHEN answered_by = 'category_filter' THEN queries ELSE 0 END) AS blocked
+            FROM "#,
+        );
+        tiers.push_source(&mut builder, start_time, end_time, tenant_id);
+        builder.push(" WHERE category IS NOT NULL GROUP BY category ORDER BY queries DESC");
+
+        let result = builder
+            .build_query_as::<CategoryStat>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+}
+
+/// Record group repository
+pub struct RecordGroupRepository {
+    pool: SqlitePool,
+}
+
+impl RecordGroupRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Create a group together with its member records
+    pub async fn create(
+        &self,
+        group: CreateRecordGroup,
+        records: Vec<CreateDnsRecord>,
+    ) -> Result<(RecordGroup, Vec<DnsRecord>)> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+        let created = sqlx::query_as::<_, RecordGroup>(
+            r#"
+            INSERT INTO record_groups (name, domain, description, tenant_id, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(&group.name)
+        .bind(&group.domain)
+        .bind(&group.description)
+        .bind(group.tenant_id)
+        .bind(now)
+        .bind(now)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let mut members = Vec::with_capacity(records.len());
+        for record in records {
+            members.push(insert_group_record(&mut tx, created.id, record).await?);
+        }
+        tx.commit().await?;
+
+        Ok((created, members))
+    }
+
+    /// Get a group by ID
+    pub async fn get_by_id(&self, id: i64) -> Result<Option<RecordGroup>> {
+        let result = sqlx::query_as::<_, RecordGroup>("SELECT * FROM record_groups WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// List all groups
+    pub async fn list(&self) -> Result<Vec<RecordGroup>> {
+        let result = sqlx::query_as::<_, RecordGroup>("SELECT * FROM record_groups ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Member records of a group
+    pub async fn records(&self, group_id: i64) -> Result<Vec<DnsRecord>> {
+        let result = sqlx::query_as::<_, DnsRecord>(
+            "SELECT * FROM dns_records WHERE group_id = ? ORDER BY id",
+        )
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Add member records to a group
+    pub async fn add_records(&self, group_id: i64, records: Vec<CreateDnsRecord>) -> Result<Vec<DnsRecord>> {
+        let mut tx = self.pool.begin().await?;
+        let mut members = Vec::with_capacity(records.len());
+        for record in records {
+            members.push(insert_group_record(&mut tx, group_id, record).await?);
+        }
+        sqlx::query("UPDATE record_groups SET updated_at = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(group_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(members)
+    }
+
+    /// Update a group and write back its (already rewritten) member records
+    ///
+    /// Member name, value, TTL and enabled state are stored in the same
+    /// transaction as the group, so a rename never leaves a half-moved
+    /// service behind.
+    pub async fn update(
+        &self,
+        id: i64,
+        update: UpdateRecordGroup,
+        members: &[DnsRecord],
+    ) -> Result<Option<RecordGroup>> {
+        let Some(existing) = self.get_by_id(id).await? else {
+            return Ok(None);
+        };
+
+        let name = update.name.unwrap_or(existing.name);
+        let domain = update.domain.unwrap_or(existing.domain);
+        let description = update.description.or(existing.description);
+        let now = Utc::now();
+
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query_as::<_, RecordGroup>(
+            r#"
+            UPDATE record_groups
+            SET name = ?, domain = ?, description = ?, updated_at = ?
+            WHERE id = ?
+            RETURNING *
+            "#,
+        )
+        .bind(&name)
+        .bind(&domain)
+        .bind(&description)
+        .bind(now)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        for record in members {
+            sqlx::query(
+                r#"
+                UPDATE dns_records
+                SET name = ?, value = ?, ttl = ?, enabled = ?, updated_at = ?
+                WHERE id = ? AND group_id = ?
+                "#,
+            )
+            .bind(&record.name)
+            .bind(&record.value)
+            .bind(record.ttl)
+            .bind(record.enabled)
+            .bind(now)
+            .bind(record.id)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(result)
+    }
+
+    /// Delete a group and all of its member records
+    pub async fn delete(&self, id: i64) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM dns_records WHERE group_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let result = sqlx::query("DELETE FROM record_groups WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Insert one member record of a group
+async fn insert_group_record(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    group_id: i64,
+    record: CreateDnsRecord,
+) -> Result<DnsRecord> {
+    let now = Utc::now();
+    let result = sqlx::query_as::<_, DnsRecord>(
+        r#"
+        INSERT INTO dns_records (name, record_type, value, ttl, priority, enabled, created_at, updated_at, tenant_id, description, tags, group_id, expires_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        RETURNING *
+        "#,
+    )
+    .bind(&record.name)
+    .bind(&record.record_type)
+    .bind(&record.value)
+    .bind(record.ttl)
+    .bind(record.priority)
+    .bind(record.enabled)
+    .bind(now)
+    .bind(now)
+    .bind(record.tenant_id)
+    .bind(&record.description)
+    .bind(record.tags.to_json())
+    .bind(group_id)
+    .bind(record.expires_at)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(result)
+}
+
+/// Repository for top-domain leaderboard snapshots
+pub struct DomainStatRepository {
+    pool: SqlitePool,
+}
+
+impl DomainStatRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Replace the snapshot of one hour
+    pub async fn replace_hour(&self, hour: i64, rows: &[DomainStatHour]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM domain_stat_hours WHERE hour = ?")
+            .bind(hour)
+            .execute(&mut *tx)
+            .await?;
+        for row in rows {
+            sqlx::query(
+                r#"
+                INSERT INTO domain_stat_hours
+                    (hour, domain, queries, blocked, response_time_total, response_time_samples, overcount)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(hour)
+            .bind(&row.domain)
+            .bind(row.queries)
+            .bind(row.blocked)
+            .bind(row.response_time_total)
+            .bind(row.response_time_samples)
+            .bind(row.overcount)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Snapshots from `hour` onwards
+    pub async fn list_since(&self, hour: i64) -> Result<Vec<DomainStatHour>> {
+        let rows = sqlx::query_as::<_, DomainStatHour>(
+            "SELECT * FROM domain_stat_hours WHERE hour >= ? ORDER BY hour",
+        )
+        .bind(hour)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    /// Delete snapshots before `hour`
+    pub async fn delete_before(&self, hour: i64) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM domain_stat_hours WHERE hour < ?")
+            .bind(hour)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
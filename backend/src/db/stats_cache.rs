@@ -45,11 +45,11 @@ impl StatsCache {
         *self.current_date.write().await = Local::now().date_naive();
     }
 
-    /// Record a new query
+    /// Record a new query log entry
     /// 
-    /// Increments total_queries and queries_today.
-    /// If cache_hit is true, also increments cache_hits.
-    pub async fn record_query(&self, cache_hit: bool) {
+    /// Adds `count` (the entry's sample rate) to total_queries and
+    /// queries_today. If cache_hit is true, also adds it to cache_hits.
+    pub async fn record_query(&self, cache_hit: bool, count: i64) {
         // Check if we need to reset the daily counter
         let today = Local::now().date_naive();
         {
@@ -67,11 +67,21 @@ impl StatsCache {
         }
 
         // Increment counters
-        self.total_queries.fetch_add(1, Ordering::SeqCst);
-        self.queries_today.fetch_add(1, Ordering::SeqCst);
+        self.total_queries.fetch_add(count, Ordering::SeqCst);
+        self.queries_today.fetch_add(count, Ordering::SeqCst);
         
         if cache_hit {
-            self.cache_hits.fetch_add(1, Ordering::SeqCst);
+            self.cache_hits.fetch_add(count, Ordering::SeqCst);
+        }
+    }
+
+    /// Record a query log entry from an earlier day
+    ///
+    /// Like [`record_query`](Self::record_query), but leaves queries_today alone.
+    pub fn record_earlier_query(&self, cache_hit: bool, count: i64) {
+        self.total_queries.fetch_add(count, Ordering::SeqCst);
+        if cache_hit {
+            self.cache_hits.fetch_add(count, Ordering::SeqCst);
         }
     }
 
@@ -0,0 +1,299 @@
+//! Adaptive TTL for stable answers
+//!
+//! Every upstream answer stored for a key is a refresh of that key. The
+//! answer's records (ignoring order and TTLs) are fingerprinted, and once a
+//! key has refreshed to the same answer `stable_refreshes` times in a row
+//! its cache lifetime is doubled, again after every further
+//! `stable_refreshes` identical refreshes, up to `max_multiplier`. A changed
+//! answer resets the key to the plain TTL.
+//!
+//! State is kept per instance and for at most as many keys as the cache
+//! holds; the least recently refreshed key is dropped first.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{CacheKey, NamePattern};
+use crate::dns::message::{DnsResponse, RecordType};
+
+/// Config key: whether stable answers get longer TTLs
+pub const CONFIG_KEY_ADAPTIVE_TTL: &str = "cache_adaptive_ttl";
+/// Config key: largest TTL multiplier
+pub const CONFIG_KEY_ADAPTIVE_TTL_MAX_MULTIPLIER: &str = "cache_adaptive_ttl_max_multiplier";
+/// Config key: identical refreshes per doubling
+pub const CONFIG_KEY_ADAPTIVE_TTL_STABLE_REFRESHES: &str = "cache_adaptive_ttl_stable_refreshes";
+
+/// Accepted range of the largest multiplier
+pub const ADAPTIVE_TTL_MULTIPLIER_RANGE: (u32, u32) = (2, 64);
+/// Accepted range of identical refreshes per doubling
+pub const ADAPTIVE_TTL_REFRESHES_RANGE: (u32, u32) = (1, 100);
+
+/// Adaptive TTL settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdaptiveTtlSettings {
+    pub enabled: bool,
+    /// Largest multiplier applied to the cache TTL
+    pub max_multiplier: u32,
+    /// Identical refreshes needed for each doubling
+    pub stable_refreshes: u32,
+}
+
+impl Default for AdaptiveTtlSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_multiplier: 4,
+            stable_refreshes: 3,
+        }
+    }
+}
+
+impl AdaptiveTtlSettings {
+    /// Multiplier after `stable` identical refreshes in a row
+    pub fn multiplier(&self, stable: u32) -> u32 {
+        let doublings = (stable / self.stable_refreshes.max(1)).min(31);
+        (1u32 << doublings).min(self.max_multiplier.max(1))
+    }
+}
+
+/// Adaptive state of one cached key
+#[derive(Debug, Clone, Serialize)]
+pub struct AdaptiveTtlEntry {
+    pub name: String,
+    pub record_type: RecordType,
+    pub profile_id: Option<i64>,
+    /// TTL multiplier applied to the next store
+    pub multiplier: u32,
+    /// Identical refreshes in a row
+    pub stable_refreshes: u32,
+    pub refreshes: u64,
+    /// Times the answer changed
+    pub changes: u64,
+    pub last_refresh: DateTime<Utc>,
+    pub last_change: Option<DateTime<Utc>>,
+}
+
+struct Tracked {
+    fingerprint: u64,
+    stable_refreshes: u32,
+    refreshes: u64,
+    changes: u64,
+    last_refresh: DateTime<Utc>,
+    last_change: Option<DateTime<Utc>>,
+}
+
+/// Per-key answer stability and the TTL multiplier it earns
+pub struct AdaptiveTtl {
+    settings: RwLock<AdaptiveTtlSettings>,
+    tracked: Mutex<HashMap<CacheKey, Tracked>>,
+    /// Stores made with a multiplier above 1
+    extended: AtomicU64,
+}
+
+impl AdaptiveTtl {
+    pub fn new() -> Self {
+        Self {
+            settings: RwLock::new(AdaptiveTtlSettings::default()),
+            tracked: Mutex::new(HashMap::new()),
+            extended: AtomicU64::new(0),
+        }
+    }
+
+    pub fn settings(&self) -> AdaptiveTtlSettings {
+        *self.settings.read().unwrap()
+    }
+
+    /// Apply new settings; disabling forgets all tracked keys
+    pub fn set_settings(&self, settings: AdaptiveTtlSettings) {
+        *self.settings.write().unwrap() = settings;
+        if !settings.enabled {
+            self.clear();
+        }
+    }
+
+    /// Stores made with an extended TTL since startup
+    pub fn extended(&self) -> u64 {
+        self.extended.load(Ordering::Relaxed)
+    }
+
+    /// Record a refresh of `key` and return the TTL multiplier for it
+    ///
+    /// `capacity` bounds the number of tracked keys.
+    pub fn observe(&self, key: &CacheKey, response: &DnsResponse, capacity: usize) -> u32 {
+        let settings = self.settings();
+        if !settings.enabled {
+            return 1;
+        }
+
+        let fingerprint = fingerprint(response);
+        let now = Utc::now();
+        let mut tracked = self.tracked.lock().unwrap();
+        if !tracked.contains_key(key) && tracked.len() >= capacity.max(1) {
+            let oldest = tracked
+                .iter()
+                .min_by_key(|(_, t)| t.last_refresh)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                tracked.remove(&oldest);
+            }
+        }
+
+        let entry = tracked.entry(key.clone()).or_insert(Tracked {
+            fingerprint,
+            stable_refreshes: 0,
+            refreshes: 0,
+            changes: 0,
+            last_refresh: now,
+            last_change: None,
+        });
+        if entry.refreshes > 0 {
+            if entry.fingerprint == fingerprint {
+                entry.stable_refreshes = entry.stable_refreshes.saturating_add(1);
+            } else {
+                entry.fingerprint = fingerprint;
+                entry.stable_refreshes = 0;
+                entry.changes += 1;
+                entry.last_change = Some(now);
+            }
+        }
+        entry.refreshes += 1;
+        entry.last_refresh = now;
+
+        let multiplier = settings.multiplier(entry.stable_refreshes);
+        if multiplier > 1 {
+            self.extended.fetch_add(1, Ordering::Relaxed);
+        }
+        multiplier
+    }
+
+    /// Tracked keys whose name contains `domain`, most extended first
+    pub fn entries(&self, domain: Option<&str>, limit: usize) -> Vec<AdaptiveTtlEntry> {
+        let settings = self.settings();
+        let domain = domain.map(|d| d.to_lowercase());
+        let mut entries: Vec<AdaptiveTtlEntry> = self
+            .tracked
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(key, _)| domain.as_deref().is_none_or(|d| key.name.contains(d)))
+            .map(|(key, t)| AdaptiveTtlEntry {
+                name: key.name.to_string(),
+                record_type: key.record_type,
+                profile_id: key.profile_id,
+                multiplier: settings.multiplier(t.stable_refreshes),
+                stable_refreshes: t.stable_refreshes,
+                refreshes: t.refreshes,
+                changes: t.changes,
+                last_refresh: t.last_refresh,
+                last_change: t.last_change,
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            b.stable_refreshes
+                .cmp(&a.stable_refreshes)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        entries.truncate(limit);
+        entries
+    }
+
+    /// Number of tracked keys
+    pub fn tracked(&self) -> usize {
+        self.tracked.lock().unwrap().len()
+    }
+
+    /// Forget keys whose name matches, so purged names start over
+    pub fn forget(&self, pattern: &NamePattern) {
+        self.tracked.lock().unwrap().retain(|key, _| !pattern.matches(&key.name));
+    }
+
+    pub fn clear(&self) {
+        self.tracked.lock().unwrap().clear();
+    }
+}
+
+impl Default for AdaptiveTtl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Hash of the answer records, independent of their order and TTLs
+fn fingerprint(response: &DnsResponse) -> u64 {
+    response.answers.iter().fold(0u64, |sum, record| {
+        let mut hasher = DefaultHasher::new();
+        record.record_type.hash(&mut hasher);
+        record.value.hash(&mut hasher);
+        sum.wrapping_add(hasher.finish())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::DnsRecordData;
+    use std::net::Ipv4Addr;
+
+    fn response(ips: &[[u8; 4]]) -> DnsResponse {
+        let mut response = DnsResponse::new(1);
+        for ip in ips {
+            response.add_answer(DnsRecordData::a("example.com", Ipv4Addr::from(*ip), 300));
+        }
+        response
+    }
+
+    fn enabled() -> AdaptiveTtl {
+        let adaptive = AdaptiveTtl::new();
+        adaptive.set_settings(AdaptiveTtlSettings {
+            enabled: true,
+            max_multiplier: 4,
+            stable_refreshes: 2,
+        });
+        adaptive
+    }
+
+    #[test]
+    fn test_multiplier_grows_and_resets() {
+        let adaptive = enabled();
+        let key = CacheKey::new("example.com", RecordType::A);
+        let stable = response(&[[192, 0, 2, 1], [192, 0, 2, 2]]);
+        let reordered = response(&[[192, 0, 2, 2], [192, 0, 2, 1]]);
+
+        let multipliers: Vec<u32> = [&stable, &reordered, &stable, &reordered, &stable, &stable, &stable]
+            .iter()
+            .map(|r| adaptive.observe(&key, r, 100))
+            .collect();
+        assert_eq!(multipliers, vec![1, 1, 2, 2, 4, 4, 4]);
+
+        // A changed answer starts over
+        assert_eq!(adaptive.observe(&key, &response(&[[192, 0, 2, 3]]), 100), 1);
+        let entry = &adaptive.entries(Some("example"), 10)[0];
+        assert_eq!((entry.stable_refreshes, entry.changes, entry.refreshes), (0, 1, 8));
+        assert!(entry.last_change.is_some());
+    }
+
+    #[test]
+    fn test_disabled_and_bounded() {
+        let adaptive = AdaptiveTtl::new();
+        let key = CacheKey::new("example.com", RecordType::A);
+        for _ in 0..10 {
+            assert_eq!(adaptive.observe(&key, &response(&[[192, 0, 2, 1]]), 100), 1);
+        }
+        assert_eq!(adaptive.tracked(), 0);
+
+        let adaptive = enabled();
+        for name in ["a.example.com", "b.example.com", "c.example.com"] {
+            adaptive.observe(&CacheKey::new(name, RecordType::A), &response(&[[192, 0, 2, 1]]), 2);
+        }
+        assert_eq!(adaptive.tracked(), 2);
+
+        adaptive.forget(&NamePattern::parse("*.example.com"));
+        assert_eq!(adaptive.tracked(), 0);
+    }
+}
@@ -0,0 +1,518 @@
+//! In-memory cache backend
+//!
+//! Optimized with DashMap for high concurrency. Eviction follows S3-FIFO:
+//! new entries start in a small probation segment and are only promoted to
+//! the protected segment once hit, so a burst of one-off names (random
+//! subdomains) evicts other one-off names instead of the popular entries.
+//! Names evicted from probation are remembered as ghosts and go straight
+//! to the protected segment when they come back.
+//!
+//! The estimated size of every entry is summed up; with a memory budget
+//! set, inserts evict entries the same way until the new entry fits.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::mem::size_of;
+use std::sync::atomic::{AtomicU8, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+
+use super::{CacheBackend, CacheKey, CacheLimits, CacheStats, NamePattern};
+use crate::clock::Clock;
+use crate::dns::message::{DnsRecordData, DnsResponse};
+
+/// Largest access frequency tracked per entry
+const MAX_FREQUENCY: u8 = 3;
+
+/// Approximate bytes an entry for `key` holding `response` takes
+///
+/// Counts the entry and key structs, the name and every record with its
+/// strings, the map slot and the queue slot. Allocator overhead is not
+/// included.
+pub fn estimated_size(key: &CacheKey, response: &DnsResponse) -> usize {
+    let records = response
+        .answers
+        .iter()
+        .chain(&response.authority)
+        .chain(&response.additional)
+        .map(|r| {
+            size_of::<DnsRecordData>()
+                + r.name.len()
+                + r.value.len()
+                + r.txt.as_ref().map_or(0, |strings| strings.iter().map(Vec::len).sum())
+        })
+        .sum::<usize>();
+    let extended_error = response
+        .extended_error
+        .as_ref()
+        .and_then(|e| e.text.as_ref())
+        .map_or(0, String::len);
+    size_of::<CacheEntry>() + 2 * (size_of::<CacheKey>() + size_of::<u64>()) + key.name.len() + records + extended_error
+}
+
+/// Eviction segment of a cache entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheSegment {
+    /// Newly inserted, evicted first unless hit
+    Probation,
+    /// Hit at least once (or recently evicted too early)
+    Protected,
+}
+
+/// A cached DNS response entry
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct CacheEntry {
+    /// The cached DNS response
+    pub response: DnsResponse,
+    /// When this entry expires
+    pub expires_at: Instant,
+    /// When this entry was created
+    pub created_at: Instant,
+    /// Hits since insertion or the last eviction pass, capped at 3
+    pub frequency: AtomicU8,
+    /// Segment the entry lives in
+    pub segment: CacheSegment,
+    /// Insertion generation, to skip queue slots of replaced entries
+    generation: u64,
+    /// Estimated size in bytes
+    size: usize,
+}
+
+impl CacheEntry {
+    /// Create a new cache entry stored at `now`
+    pub fn new(response: DnsResponse, ttl: Duration, now: Instant) -> Self {
+        Self {
+            response,
+            expires_at: now + ttl,
+            created_at: now,
+            frequency: AtomicU8::new(0),
+            segment: CacheSegment::Probation,
+            generation: 0,
+            size: 0,
+        }
+    }
+
+    /// Check if this entry has expired at `now`
+    pub fn is_expired(&self, now: Instant) -> bool {
+        now >= self.expires_at
+    }
+
+    /// Get the remaining TTL in seconds at `now`
+    #[allow(dead_code)]
+    pub fn remaining_ttl(&self, now: Instant) -> u64 {
+        if now >= self.expires_at {
+            0
+        } else {
+            (self.expires_at - now).as_secs()
+        }
+    }
+
+    /// Count an access
+    pub fn touch(&self) {
+        let _ = self.frequency.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |f| {
+            (f < MAX_FREQUENCY).then_some(f + 1)
+        });
+    }
+}
+
+/// Eviction queues, kept in insertion order per segment
+#[derive(Default)]
+struct Segments {
+    probation: VecDeque<(CacheKey, u64)>,
+    protected: VecDeque<(CacheKey, u64)>,
+    /// Keys recently evicted from probation without a hit
+    ghosts: VecDeque<CacheKey>,
+    ghost_set: HashSet<CacheKey>,
+    next_generation: u64,
+}
+
+impl Segments {
+    fn remember_ghost(&mut self, key: CacheKey, capacity: usize) {
+        if self.ghost_set.insert(key.clone()) {
+            self.ghosts.push_back(key);
+        }
+        while self.ghosts.len() > capacity {
+            if let Some(old) = self.ghosts.pop_front() {
+                self.ghost_set.remove(&old);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.probation.clear();
+        self.protected.clear();
+        self.ghosts.clear();
+        self.ghost_set.clear();
+    }
+}
+
+/// In-process cache, the default backend
+pub struct MemoryCache {
+    /// The cache storage
+    pub(super) cache: DashMap<CacheKey, CacheEntry>,
+    /// Eviction queues; inserts and evictions hold this lock
+    segments: Mutex<Segments>,
+    protected_hits: AtomicU64,
+    promotions: AtomicU64,
+    ghost_hits: AtomicU64,
+    probation_evictions: AtomicU64,
+    protected_evictions: AtomicU64,
+    /// Estimated bytes held by entries
+    bytes: AtomicUsize,
+    memory_evictions: AtomicU64,
+    /// Time source for expiry
+    clock: Arc<Clock>,
+}
+
+impl MemoryCache {
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(Clock::system()))
+    }
+
+    /// Create a cache that expires entries by the given clock
+    pub fn with_clock(clock: Arc<Clock>) -> Self {
+        Self {
+            cache: DashMap::new(),
+            segments: Mutex::new(Segments::default()),
+            protected_hits: AtomicU64::new(0),
+            promotions: AtomicU64::new(0),
+            ghost_hits: AtomicU64::new(0),
+            probation_evictions: AtomicU64::new(0),
+            protected_evictions: AtomicU64::new(0),
+            bytes: AtomicUsize::new(0),
+            memory_evictions: AtomicU64::new(0),
+            clock,
+        }
+    }
+
+    /// Estimated bytes held by entries
+    pub fn memory_bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Remove an entry and release its size
+    fn remove(&self, key: &CacheKey) {
+        if let Some((_, entry)) = self.cache.remove(key) {
+            self.bytes.fetch_sub(entry.size, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether one more entry of `size` bytes exceeds `limits`
+    fn over_limits(&self, limits: CacheLimits, size: usize) -> bool {
+        self.cache.len() >= limits.max_entries.max(1) || self.over_budget(limits, size)
+    }
+
+    /// Whether `size` more bytes exceed the memory budget
+    fn over_budget(&self, limits: CacheLimits, size: usize) -> bool {
+        limits.max_bytes > 0 && self.memory_bytes() + size > limits.max_bytes
+    }
+
+    /// Evict one entry, returning false when the cache is empty
+    ///
+    /// Probation is kept to about a tenth of the capacity. Its oldest
+    /// entries are promoted if hit and evicted (and remembered as ghosts)
+    /// otherwise. The protected segment is a CLOCK: hit entries get another
+    /// round with their frequency lowered.
+    fn evict_one(&self, segments: &mut Segments, max_entries: usize) -> bool {
+        let now = self.clock.now();
+        let probation_target = (max_entries / 10).max(1);
+        if segments.probation.len() > probation_target || segments.protected.is_empty() {
+            while let Some((key, generation)) = segments.probation.pop_front() {
+                let Some(mut entry) = self.cache.get_mut(&key) else {
+                    continue;
+                };
+                if entry.generation != generation {
+                    continue;
+                }
+                if entry.frequency.load(Ordering::Relaxed) > 0 && !entry.is_expired(now) {
+                    entry.frequency.store(0, Ordering::Relaxed);
+                    entry.segment = CacheSegment::Protected;
+                    drop(entry);
+                    segments.protected.push_back((key, generation));
+                    self.promotions.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                let expired = entry.is_expired(now);
+                drop(entry);
+                self.remove(&key);
+                if !expired {
+                    self.probation_evictions.fetch_add(1, Ordering::Relaxed);
+                    segments.remember_ghost(key, max_entries);
+                }
+                return true;
+            }
+        }
+
+        while let Some((key, generation)) = segments.protected.pop_front() {
+            let Some(entry) = self.cache.get(&key) else {
+                continue;
+            };
+            if entry.generation != generation {
+                continue;
+            }
+            let frequency = entry.frequency.load(Ordering::Relaxed);
+            if frequency > 0 && !entry.is_expired(now) {
+                entry.frequency.store(frequency - 1, Ordering::Relaxed);
+                drop(entry);
+                segments.protected.push_back((key, generation));
+                continue;
+            }
+            let expired = entry.is_expired(now);
+            drop(entry);
+            self.remove(&key);
+            if !expired {
+                self.protected_evictions.fetch_add(1, Ordering::Relaxed);
+            }
+            return true;
+        }
+        false
+    }
+
+    /// Drop queue slots of entries removed from the map
+    fn prune_segments(&self) {
+        let mut segments = self.segments.lock().unwrap();
+        let live = |(key, generation): &(CacheKey, u64)| {
+            self.cache.get(key).is_some_and(|e| e.generation == *generation)
+        };
+        segments.probation.retain(live);
+        segments.protected.retain(live);
+    }
+}
+
+impl Default for MemoryCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl CacheBackend for MemoryCache {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn get(&self, key: &CacheKey) -> Option<DnsResponse> {
+        let entry = self.cache.get(key)?;
+        if entry.is_expired(self.clock.now()) {
+            return None;
+        }
+        if entry.segment == CacheSegment::Protected {
+            self.protected_hits.fetch_add(1, Ordering::Relaxed);
+        }
+        entry.touch();
+        Some(entry.response.clone())
+    }
+
+    async fn set(&self, key: CacheKey, response: DnsResponse, ttl: Duration, limits: CacheLimits) {
+        let max_entries = limits.max_entries.max(1);
+        let size = estimated_size(&key, &response);
+        let mut segments = self.segments.lock().unwrap();
+        let mut entry = CacheEntry::new(response, ttl, self.clock.now());
+        entry.size = size;
+
+        // Replacing an entry keeps its segment and queue slot
+        if let Some(existing) = self.cache.get(&key) {
+            entry.segment = existing.segment;
+            entry.generation = existing.generation;
+            entry.frequency = AtomicU8::new(existing.frequency.load(Ordering::Relaxed));
+            drop(existing);
+            if let Some(old) = self.cache.insert(key, entry) {
+                self.bytes.fetch_sub(old.size, Ordering::Relaxed);
+            }
+            self.bytes.fetch_add(size, Ordering::Relaxed);
+            return;
+        }
+
+        // An entry larger than the whole budget is not stored
+        if limits.max_bytes > 0 && size > limits.max_bytes {
+            return;
+        }
+
+        while self.over_limits(limits, size) {
+            let for_memory = self.cache.len() < max_entries;
+            if !self.evict_one(&mut segments, max_entries) {
+                break;
+            }
+            if for_memory {
+                self.memory_evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        segments.next_generation += 1;
+        entry.generation = segments.next_generation;
+        if segments.ghost_set.remove(&key) {
+            self.ghost_hits.fetch_add(1, Ordering::Relaxed);
+            entry.segment = CacheSegment::Protected;
+            segments.protected.push_back((key.clone(), entry.generation));
+        } else {
+            segments.probation.push_back((key.clone(), entry.generation));
+        }
+        self.cache.insert(key, entry);
+        self.bytes.fetch_add(size, Ordering::Relaxed);
+    }
+
+    async fn clear(&self) {
+        let mut segments = self.segments.lock().unwrap();
+        self.cache.clear();
+        self.bytes.store(0, Ordering::Relaxed);
+        segments.clear();
+        drop(segments);
+        for counter in [
+            &self.protected_hits,
+            &self.promotions,
+            &self.ghost_hits,
+            &self.probation_evictions,
+            &self.protected_evictions,
+            &self.memory_evictions,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    async fn purge(&self, pattern: &NamePattern) -> usize {
+        let mut removed = 0;
+        self.cache.retain(|key, entry| {
+            let matches = pattern.matches(&key.name);
+            if matches {
+                removed += 1;
+                self.bytes.fetch_sub(entry.size, Ordering::Relaxed);
+            }
+            !matches
+        });
+        self.prune_segments();
+        removed
+    }
+
+    async fn cleanup_expired(&self) {
+        let now = self.clock.now();
+        self.cache.retain(|_, entry| {
+            let expired = entry.is_expired(now);
+            if expired {
+                self.bytes.fetch_sub(entry.size, Ordering::Relaxed);
+            }
+            !expired
+        });
+        self.prune_segments();
+    }
+
+    async fn stats(&self) -> CacheStats {
+        let protected_entries = self.segments.lock().unwrap().protected.len();
+        CacheStats {
+            entries: self.cache.len(),
+            protected_entries,
+            protected_hits: self.protected_hits.load(Ordering::Relaxed),
+            promotions: self.promotions.load(Ordering::Relaxed),
+            ghost_hits: self.ghost_hits.load(Ordering::Relaxed),
+            probation_evictions: self.probation_evictions.load(Ordering::Relaxed),
+            protected_evictions: self.protected_evictions.load(Ordering::Relaxed),
+            memory_bytes: self.memory_bytes(),
+            memory_evictions: self.memory_evictions.load(Ordering::Relaxed),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::RecordType;
+
+    fn create_test_response(id: u16) -> DnsResponse {
+        let mut response = DnsResponse::new(id);
+        response.add_answer(DnsRecordData::a(
+            "example.com",
+            "93.184.216.34".parse().unwrap(),
+            300,
+        ));
+        response
+    }
+
+    #[tokio::test]
+    async fn test_eviction_protects_hit_entries() {
+        let cache = MemoryCache::new();
+        let max_entries = 20;
+
+        // Popular names, each hit once
+        let popular: Vec<CacheKey> = (0..10)
+            .map(|i| CacheKey::new(format!("popular{}.example.com", i), RecordType::A))
+            .collect();
+        for key in &popular {
+            cache.set(key.clone(), create_test_response(1), Duration::from_secs(300), CacheLimits::entries(max_entries)).await;
+            assert!(cache.get(key).await.is_some());
+        }
+
+        // A flood of unique random subdomains
+        for i in 0..500 {
+            let key = CacheKey::new(format!("r{}.flood.example.com", i), RecordType::A);
+            cache.set(key, create_test_response(1), Duration::from_secs(300), CacheLimits::entries(max_entries)).await;
+        }
+
+        assert!(cache.cache.len() <= max_entries);
+        for key in &popular {
+            assert!(cache.get(key).await.is_some(), "{} was evicted", key.name);
+        }
+        let stats = cache.stats().await;
+        assert_eq!(stats.promotions, 10);
+        assert_eq!(stats.protected_entries, 10);
+        assert_eq!(stats.protected_evictions, 0);
+        assert!(stats.probation_evictions >= 480);
+        assert_eq!(stats.protected_hits, 10);
+    }
+
+    #[tokio::test]
+    async fn test_ghost_readmission() {
+        let cache = MemoryCache::new();
+        let max_entries = 10;
+        let first = CacheKey::new("first.example.com", RecordType::A);
+
+        cache.set(first.clone(), create_test_response(1), Duration::from_secs(300), CacheLimits::entries(max_entries)).await;
+        for i in 0..12 {
+            let key = CacheKey::new(format!("n{}.example.com", i), RecordType::A);
+            cache.set(key, create_test_response(1), Duration::from_secs(300), CacheLimits::entries(max_entries)).await;
+        }
+        assert!(!cache.cache.contains_key(&first));
+
+        // Coming back soon after eviction goes straight to the protected segment
+        cache.set(first.clone(), create_test_response(1), Duration::from_secs(300), CacheLimits::entries(max_entries)).await;
+        assert_eq!(cache.cache.get(&first).unwrap().segment, CacheSegment::Protected);
+        assert_eq!(cache.stats().await.ghost_hits, 1);
+
+        // Purged entries leave no queue slots behind
+        cache.purge(&NamePattern::parse("*.example.com")).await;
+        assert_eq!(cache.stats().await.protected_entries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_memory_budget() {
+        let cache = MemoryCache::new();
+        let key = |i: usize| CacheKey::new(format!("n{:02}.example.com", i), RecordType::A);
+        let size = estimated_size(&key(0), &create_test_response(1));
+        let limits = CacheLimits {
+            max_entries: 1000,
+            max_bytes: size * 5,
+        };
+
+        for i in 0..20 {
+            cache.set(key(i), create_test_response(1), Duration::from_secs(300), limits).await;
+        }
+        let stats = cache.stats().await;
+        assert_eq!(stats.entries, 5);
+        assert_eq!(stats.memory_bytes, size * 5);
+        assert_eq!(stats.memory_evictions, 15);
+
+        // Replacing and removing entries keeps the total in step
+        let mut larger = create_test_response(1);
+        larger.add_answer(DnsRecordData::a("example.com", "192.0.2.1".parse().unwrap(), 300));
+        let larger_size = estimated_size(&key(19), &larger);
+        cache.set(key(19), larger, Duration::from_secs(300), limits).await;
+        assert_eq!(cache.memory_bytes(), size * 4 + larger_size);
+
+        cache.purge(&NamePattern::parse("n19.example.com")).await;
+        assert_eq!(cache.memory_bytes(), size * 4);
+        cache.clear().await;
+        assert_eq!(cache.memory_bytes(), 0);
+    }
+}
@@ -0,0 +1,712 @@
+//! DNS Cache Manager
+//!
+//! Provides caching functionality for DNS responses with TTL-based expiration,
+//! cache statistics, and cache management operations.
+//!
+//! Storage is pluggable through [`CacheBackend`]. The in-memory backend is
+//! the default; instances that should share one cache can use the Redis
+//! backend (`redis-cache` feature), selected with `CACHE_BACKEND=redis`.
+//! Hit and miss counters are kept by the manager, so stats read the same
+//! whichever backend stores the entries.
+//!
+//! Answers that stay the same across refreshes can earn a longer TTL
+//! (see [`AdaptiveTtl`]).
+//!
+//! Besides the entry count, the in-memory backend can be held to a memory
+//! budget (`max_memory_mb`). Entry sizes are estimated from the key and
+//! the records they hold (see [`estimated_size`]), so the figure tracks
+//! what the cache keeps rather than what the allocator reports.
+//!
+//! The in-memory backend expires entries by a shared [`Clock`], so tests
+//! can step over TTLs instead of sleeping. Redis expires entries itself.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::clock::Clock;
+use super::message::{DnsQuery, DnsResponse, DnsResponseCode, RecordType};
+use super::name::normalize_name;
+
+mod adaptive;
+mod memory;
+#[cfg(feature = "redis-cache")]
+mod redis_cache;
+
+pub use adaptive::*;
+pub use memory::*;
+#[cfg(feature = "redis-cache")]
+pub use redis_cache::*;
+
+/// Upper bound for NODATA cache lifetimes, whatever the zone's SOA says
+const NODATA_MAX_TTL: u32 = 3600;
+
+/// Upper bound for TTLs extended by [`AdaptiveTtl`] (7 days)
+const ADAPTIVE_MAX_TTL: u64 = 86400 * 7;
+
+/// Cache lifetime for a NODATA response (NOERROR without answers)
+///
+/// Per RFC 2308 this is the lesser of the SOA record's own TTL and its
+/// MINIMUM field, taken from the authority section. Returns `None` for
+/// responses that are not NODATA or carry no SOA, which are not cached
+/// as negative answers.
+pub fn nodata_ttl(response: &DnsResponse) -> Option<u32> {
+    if response.response_code != DnsResponseCode::NoError || !response.answers.is_empty() {
+        return None;
+    }
+    let soa = response
+        .authority
+        .iter()
+        .find(|r| r.record_type == RecordType::SOA)?;
+    // SOA values are formatted "mname rname serial refresh retry expire minimum"
+    let minimum: u32 = soa.value.split_whitespace().nth(6)?.parse().ok()?;
+    let ttl = soa.ttl.min(minimum).min(NODATA_MAX_TTL);
+    (ttl > 0).then_some(ttl)
+}
+
+/// Cache key for DNS queries
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct CacheKey {
+    /// Domain name (normalized, shared string)
+    pub name: Arc<str>,
+    /// Record type
+    pub record_type: RecordType,
+    /// Listener-pinned resolution profile the answer was resolved in
+    pub profile_id: Option<i64>,
+}
+
+impl CacheKey {
+    /// Create a new cache key
+    pub fn new(name: impl AsRef<str>, record_type: RecordType) -> Self {
+        Self {
+            name: Arc::from(normalize_name(name.as_ref()).as_str()),
+            record_type,
+            profile_id: None,
+        }
+    }
+
+    /// Key the entry to a profile, so answers from different upstream sets
+    /// are not served across listeners
+    pub fn for_profile(mut self, profile_id: Option<i64>) -> Self {
+        self.profile_id = profile_id;
+        self
+    }
+
+    /// Create a cache key from a DNS query
+    pub fn from_query(query: &DnsQuery) -> Self {
+        Self::new(&query.name, query.record_type)
+    }
+}
+
+/// Cache configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// Default TTL for cached entries in seconds
+    pub default_ttl: u64,
+    /// Maximum number of entries in the cache
+    pub max_entries: usize,
+    /// Estimated memory budget in MiB, 0 for no limit
+    #[serde(default)]
+    pub max_memory_mb: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            default_ttl: 60,
+            max_entries: 10000,
+            max_memory_mb: 0,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// Capacity limits for the backend
+    pub fn limits(&self) -> CacheLimits {
+        CacheLimits {
+            max_entries: self.max_entries,
+            max_bytes: self.max_memory_mb.saturating_mul(1024 * 1024),
+        }
+    }
+}
+
+/// Capacity a backend enforces when storing an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheLimits {
+    pub max_entries: usize,
+    /// Estimated bytes held by entries, 0 for no limit
+    pub max_bytes: usize,
+}
+
+impl CacheLimits {
+    /// Limit the entry count only (for testing)
+    #[allow(dead_code)]
+    pub fn entries(max_entries: usize) -> Self {
+        Self {
+            max_entries,
+            max_bytes: 0,
+        }
+    }
+}
+
+/// Cache statistics
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheStats {
+    /// Storage backend (`memory` or `redis`)
+    pub backend: String,
+    /// Number of cache hits
+    pub hits: u64,
+    /// Number of cache misses
+    pub misses: u64,
+    /// Current number of entries in the cache
+    pub entries: usize,
+    /// Entries in the protected segment
+    pub protected_entries: usize,
+    /// Hits served from the protected segment
+    pub protected_hits: u64,
+    /// Entries promoted from probation after being hit
+    pub promotions: u64,
+    /// Re-inserted keys that had been evicted from probation too early
+    pub ghost_hits: u64,
+    /// Entries evicted from probation without ever being hit
+    pub probation_evictions: u64,
+    /// Entries evicted from the protected segment
+    pub protected_evictions: u64,
+    /// Estimated memory held by cached entries, in bytes
+    pub memory_bytes: usize,
+    /// Entries evicted to stay within the memory budget
+    pub memory_evictions: u64,
+}
+
+impl CacheStats {
+    /// Calculate the cache hit rate (0.0 to 1.0)
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// Domain pattern for purging cache entries
+///
+/// `*.example.com` matches `example.com` and all of its subdomains; any
+/// other pattern is an exact, case-insensitive name match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamePattern {
+    /// Normalized name matched exactly
+    pub exact: String,
+    /// `.example.com` for wildcard patterns
+    pub suffix: Option<String>,
+}
+
+impl NamePattern {
+    /// Parse a pattern, normalizing the name
+    pub fn parse(pattern: &str) -> Self {
+        let pattern = normalize_name(pattern);
+        match pattern.strip_prefix("*.") {
+            Some(suffix) => Self {
+                suffix: Some(format!(".{}", suffix)),
+                exact: suffix.to_string(),
+            },
+            None => Self {
+                exact: pattern,
+                suffix: None,
+            },
+        }
+    }
+
+    /// Check whether a cached name matches
+    pub fn matches(&self, name: &str) -> bool {
+        let name = name.trim_end_matches('.');
+        name == self.exact || self.suffix.as_deref().is_some_and(|s| name.ends_with(s))
+    }
+}
+
+/// Storage for cached responses
+///
+/// Backends honour the TTL given to [`CacheBackend::set`] and never return
+/// an expired entry. Backend errors are logged and treated as misses, so a
+/// failing cache never fails resolution.
+#[async_trait]
+pub trait CacheBackend: Send + Sync {
+    /// Short name reported in stats
+    fn name(&self) -> &'static str;
+
+    /// Look up a live entry
+    async fn get(&self, key: &CacheKey) -> Option<DnsResponse>;
+
+    /// Store an entry for `ttl` within `limits`, where the backend
+    /// enforces its own capacity
+    async fn set(&self, key: CacheKey, response: DnsResponse, ttl: Duration, limits: CacheLimits);
+
+    /// Remove every entry
+    async fn clear(&self);
+
+    /// Remove entries whose name matches, returning how many were removed
+    async fn purge(&self, pattern: &NamePattern) -> usize;
+
+    /// Drop expired entries where the backend does not expire them itself
+    async fn cleanup_expired(&self);
+
+    /// Entry count and backend-specific counters; hits and misses are
+    /// filled in by the manager
+    async fn stats(&self) -> CacheStats;
+}
+
+/// Build the cache backend selected in the configuration
+///
+/// Falls back to the in-memory backend, with a warning, when Redis is
+/// requested but unavailable, so DNS keeps answering either way.
+pub async fn cache_backend(kind: &str, redis_url: Option<&str>, clock: Arc<Clock>) -> Arc<dyn CacheBackend> {
+    match kind.to_lowercase().as_str() {
+        "memory" | "" => {}
+        "redis" => {
+            #[cfg(feature = "redis-cache")]
+            match redis_url {
+                Some(url) => match RedisCache::connect(url).await {
+                    Ok(backend) => return Arc::new(backend),
+                    Err(e) => tracing::warn!("Failed to connect to the Redis cache, using the in-memory cache: {}", e),
+                },
+                None => tracing::warn!("CACHE_BACKEND is redis but CACHE_REDIS_URL is not set, using the in-memory cache"),
+            }
+            #[cfg(not(feature = "redis-cache"))]
+            {
+                let _ = redis_url;
+                tracing::warn!("CACHE_BACKEND is redis but FluxDNS was built without the `redis-cache` feature, using the in-memory cache");
+            }
+        }
+        other => tracing::warn!("Unknown cache backend '{}', using the in-memory cache", other),
+    }
+    Arc::new(MemoryCache::with_clock(clock))
+}
+
+/// DNS Cache Manager
+///
+/// Thread-safe cache for DNS responses with TTL-based expiration. Applies
+/// the configured TTL and capacity, counts hits and misses, and stores
+/// entries in a [`CacheBackend`].
+pub struct CacheManager {
+    /// Entry storage
+    backend: Arc<dyn CacheBackend>,
+    /// Cache configuration
+    config: RwLock<CacheConfig>,
+    /// Cache statistics - hits
+    hits: AtomicU64,
+    /// Cache statistics - misses
+    misses: AtomicU64,
+    /// Longer TTLs for answers that do not change
+    adaptive: AdaptiveTtl,
+}
+
+impl CacheManager {
+    /// Create a new cache manager with default configuration
+    pub fn new() -> Self {
+        Self::with_config(CacheConfig::default())
+    }
+
+    /// Create a new in-memory cache manager with custom configuration
+    pub fn with_config(config: CacheConfig) -> Self {
+        Self::with_backend(config, Arc::new(MemoryCache::new()))
+    }
+
+    /// Create an in-memory cache manager expiring entries by `clock` (for testing)
+    #[allow(dead_code)]
+    pub fn with_clock(config: CacheConfig, clock: Arc<Clock>) -> Self {
+        Self::with_backend(config, Arc::new(MemoryCache::with_clock(clock)))
+    }
+
+    /// Create a cache manager storing entries in the given backend
+    pub fn with_backend(config: CacheConfig, backend: Arc<dyn CacheBackend>) -> Self {
+        Self {
+            backend,
+            config: RwLock::new(config),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            adaptive: AdaptiveTtl::new(),
+        }
+    }
+
+    /// Create a new cache manager wrapped in Arc
+    #[allow(dead_code)]
+    pub fn new_shared() -> Arc<Self> {
+        Arc::new(Self::new())
+    }
+
+    /// Adaptive TTL state and settings
+    pub fn adaptive(&self) -> &AdaptiveTtl {
+        &self.adaptive
+    }
+
+    /// Name of the storage backend
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    /// Get a cached response for the given key
+    pub async fn get(&self, key: &CacheKey) -> Option<DnsResponse> {
+        let response = self.backend.get(key).await;
+        let counter = if response.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        response
+    }
+
+    /// Whether a live entry exists, without counting a hit or miss
+    pub async fn contains(&self, key: &CacheKey) -> bool {
+        self.backend.get(key).await.is_some()
+    }
+
+    /// Store a response in the cache with the default TTL (for testing)
+    #[allow(dead_code)]
+    pub async fn set(&self, key: CacheKey, response: DnsResponse) {
+        let config = self.config.read().await;
+        let ttl = Duration::from_secs(config.default_ttl);
+        let limits = config.limits();
+        drop(config);
+
+        self.set_with_ttl(key, response, ttl, limits).await;
+    }
+
+    /// Store an upstream response, picking the TTL from the kind of answer
+    ///
+    /// NOERROR responses with answers use the default TTL, multiplied by
+    /// the [`AdaptiveTtl`] multiplier the key has earned. NODATA responses
+    /// use the SOA-derived TTL from [`nodata_ttl`] and are skipped without
+    /// an SOA. Other response codes, NXDOMAIN included, are not cached.
+    pub async fn store(&self, key: CacheKey, response: DnsResponse) {
+        if response.response_code != DnsResponseCode::NoError {
+            return;
+        }
+        if !response.answers.is_empty() {
+            let config = self.config.read().await.clone();
+            let multiplier = self.adaptive.observe(&key, &response, config.max_entries);
+            let ttl = (config.default_ttl * multiplier as u64).min(ADAPTIVE_MAX_TTL.max(config.default_ttl));
+            self.set_with_ttl(key, response, Duration::from_secs(ttl), config.limits())
+                .await;
+            return;
+        }
+        if let Some(ttl) = nodata_ttl(&response) {
+            let limits = self.config.read().await.limits();
+            self.set_with_ttl(key, response, Duration::from_secs(ttl as u64), limits)
+                .await;
+        }
+    }
+
+    /// Store a response in the cache with a specific TTL
+    pub async fn set_with_ttl(&self, key: CacheKey, response: DnsResponse, ttl: Duration, limits: CacheLimits) {
+        self.backend.set(key, response, ttl, limits).await;
+    }
+
+    /// Clear all entries from the cache
+    pub async fn clear(&self) {
+        self.backend.clear().await;
+        self.adaptive.clear();
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+
+    /// Clear cache entries for a specific domain and return how many were removed
+    pub async fn clear_domain(&self, domain: &str) -> usize {
+        self.purge_pattern(domain).await
+    }
+
+    /// Remove entries matching a domain pattern and return how many were removed
+    ///
+    /// `*.example.com` matches `example.com` and all of its subdomains; any
+    /// other pattern is an exact, case-insensitive name match.
+    pub async fn purge_pattern(&self, pattern: &str) -> usize {
+        let pattern = NamePattern::parse(pattern);
+        self.adaptive.forget(&pattern);
+        self.backend.purge(&pattern).await
+    }
+
+    /// Remove entries for the names of changed records or rules, returning
+    /// how many were removed
+    ///
+    /// Each name is a pattern as in [`purge_pattern`](Self::purge_pattern);
+    /// duplicates are purged once.
+    pub async fn purge_names<I, S>(&self, names: I) -> usize
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut patterns: Vec<NamePattern> = Vec::new();
+        for name in names {
+            let pattern = NamePattern::parse(name.as_ref());
+            if !pattern.exact.is_empty() && !patterns.contains(&pattern) {
+                patterns.push(pattern);
+            }
+        }
+
+        let mut purged = 0;
+        for pattern in &patterns {
+            self.adaptive.forget(pattern);
+            purged += self.backend.purge(pattern).await;
+        }
+        if purged > 0 {
+            tracing::debug!("Purged {} cache entries for changed names", purged);
+        }
+        purged
+    }
+
+    /// Get current cache statistics
+    pub async fn stats(&self) -> CacheStats {
+        CacheStats {
+            backend: self.backend.name().to_string(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            ..self.backend.stats().await
+        }
+    }
+
+    /// Update the default TTL
+    #[allow(dead_code)]
+    pub async fn set_ttl(&self, ttl_seconds: u64) {
+        let mut config = self.config.write().await;
+        config.default_ttl = ttl_seconds;
+    }
+
+    /// Get the current default TTL
+    #[allow(dead_code)]
+    pub async fn get_ttl(&self) -> u64 {
+        let config = self.config.read().await;
+        config.default_ttl
+    }
+
+    /// Update the maximum number of entries
+    #[allow(dead_code)]
+    pub async fn set_max_entries(&self, max_entries: usize) {
+        let mut config = self.config.write().await;
+        config.max_entries = max_entries;
+    }
+
+    /// Get the current configuration
+    pub async fn get_config(&self) -> CacheConfig {
+        self.config.read().await.clone()
+    }
+
+    /// Update the configuration
+    pub async fn update_config(&self, config: CacheConfig) {
+        let mut current = self.config.write().await;
+        *current = config;
+    }
+
+    /// Remove expired entries from the cache
+    pub async fn cleanup_expired(&self) {
+        self.backend.cleanup_expired().await;
+    }
+}
+
+impl Default for CacheManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::{DnsRecordData, DnsResponse, RecordType};
+
+    fn create_test_response(id: u16) -> DnsResponse {
+        let mut response = DnsResponse::new(id);
+        response.add_answer(DnsRecordData::a(
+            "example.com",
+            "93.184.216.34".parse().unwrap(),
+            300,
+        ));
+        response
+    }
+
+    #[tokio::test]
+    async fn test_cache_set_and_get() {
+        let cache = CacheManager::new();
+        let key = CacheKey::new("example.com", RecordType::A);
+        let response = create_test_response(12345);
+
+        cache.set(key.clone(), response.clone()).await;
+        
+        let cached = cache.get(&key).await;
+        assert!(cached.is_some());
+        assert_eq!(cached.unwrap().id, 12345);
+    }
+
+    #[test]
+    fn test_cache_key_normalized() {
+        let key = CacheKey::new("example.com", RecordType::A);
+        assert_eq!(CacheKey::new("Example.COM.", RecordType::A), key);
+        assert_eq!(
+            CacheKey::new("bücher.example", RecordType::A),
+            CacheKey::new("xn--bcher-kva.example", RecordType::A)
+        );
+    }
+
+    fn create_nodata_response(soa_ttl: u32, minimum: u32) -> DnsResponse {
+        let mut response = DnsResponse::new(1);
+        response.authority.push(DnsRecordData {
+            name: "example.com".to_string(),
+            record_type: RecordType::SOA,
+            value: format!("ns1.example.com hostmaster.example.com 1 7200 3600 1209600 {}", minimum),
+            ttl: soa_ttl,
+            priority: None,
+            txt: None,
+        });
+        response
+    }
+
+    #[test]
+    fn test_nodata_ttl() {
+        // Lesser of the SOA TTL and its MINIMUM field
+        assert_eq!(nodata_ttl(&create_nodata_response(900, 300)), Some(300));
+        assert_eq!(nodata_ttl(&create_nodata_response(120, 300)), Some(120));
+        assert_eq!(nodata_ttl(&create_nodata_response(86400, 86400)), Some(NODATA_MAX_TTL));
+        assert_eq!(nodata_ttl(&create_nodata_response(900, 0)), None);
+
+        // Not NODATA: answers present, no SOA, or NXDOMAIN
+        assert_eq!(nodata_ttl(&create_test_response(1)), None);
+        assert_eq!(nodata_ttl(&DnsResponse::new(1)), None);
+        let mut nxdomain = create_nodata_response(900, 300);
+        nxdomain.response_code = DnsResponseCode::NxDomain;
+        assert_eq!(nodata_ttl(&nxdomain), None);
+    }
+
+    #[tokio::test]
+    async fn test_store_nodata() {
+        let clock = Arc::new(Clock::manual());
+        let memory = Arc::new(MemoryCache::with_clock(clock.clone()));
+        let cache = CacheManager::with_backend(CacheConfig::default(), memory.clone());
+
+        let key = CacheKey::new("example.com", RecordType::AAAA);
+        cache.store(key.clone(), create_nodata_response(900, 300)).await;
+        let entry = memory.cache.get(&key).unwrap();
+        assert_eq!(entry.remaining_ttl(clock.now()), 300);
+        drop(entry);
+
+        // NODATA without an SOA and NXDOMAIN are not cached
+        let key = CacheKey::new("nosoa.example.com", RecordType::AAAA);
+        cache.store(key.clone(), DnsResponse::new(1)).await;
+        assert!(cache.get(&key).await.is_none());
+
+        let key = CacheKey::new("missing.example.com", RecordType::A);
+        cache.store(key.clone(), DnsResponse::nxdomain(1)).await;
+        assert!(cache.get(&key).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cache_miss() {
+        let cache = CacheManager::new();
+        let key = CacheKey::new("nonexistent.com", RecordType::A);
+        
+        let cached = cache.get(&key).await;
+        assert!(cached.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cache_expiration() {
+        let config = CacheConfig {
+            default_ttl: 0, // Immediate expiration
+            max_entries: 100,
+            max_memory_mb: 0,
+        };
+        let cache = CacheManager::with_config(config);
+        let key = CacheKey::new("example.com", RecordType::A);
+        let response = create_test_response(12345);
+
+        cache.set(key.clone(), response).await;
+        
+        // Should be expired immediately
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let cached = cache.get(&key).await;
+        assert!(cached.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cache_expiration_by_clock() {
+        let clock = Arc::new(Clock::manual());
+        let cache = CacheManager::with_clock(CacheConfig::default(), clock.clone());
+        let key = CacheKey::new("example.com", RecordType::A);
+        cache.set(key.clone(), create_test_response(1)).await;
+
+        clock.advance(Duration::from_secs(59));
+        assert!(cache.get(&key).await.is_some());
+        clock.advance(Duration::from_secs(1));
+        assert!(cache.get(&key).await.is_none());
+
+        cache.cleanup_expired().await;
+        assert_eq!(cache.stats().await.entries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_cache_clear() {
+        let cache = CacheManager::new();
+        let key1 = CacheKey::new("example1.com", RecordType::A);
+        let key2 = CacheKey::new("example2.com", RecordType::A);
+
+        cache.set(key1.clone(), create_test_response(1)).await;
+        cache.set(key2.clone(), create_test_response(2)).await;
+
+        cache.clear().await;
+
+        assert!(cache.get(&key1).await.is_none());
+        assert!(cache.get(&key2).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cache_purge_pattern() {
+        let cache = CacheManager::new();
+        for name in ["svc.internal", "api.svc.internal", "a.b.svc.internal", "othersvc.internal"] {
+            cache.set(CacheKey::new(name, RecordType::A), create_test_response(1)).await;
+        }
+        cache.set(CacheKey::new("api.svc.internal", RecordType::AAAA), create_test_response(2)).await;
+
+        assert_eq!(cache.purge_pattern("API.svc.internal.").await, 2);
+        assert_eq!(cache.purge_pattern("*.svc.internal").await, 2);
+        assert_eq!(cache.stats().await.entries, 1);
+        assert!(cache.get(&CacheKey::new("othersvc.internal", RecordType::A)).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_cache_purge_names() {
+        let cache = CacheManager::new();
+        for name in ["a.example.com", "b.example.com", "x.cdn.example.net", "example.org"] {
+            cache.set(CacheKey::new(name, RecordType::A), create_test_response(1)).await;
+        }
+
+        let purged = cache
+            .purge_names(["A.example.com", "a.example.com.", "*.cdn.example.net", ""])
+            .await;
+        assert_eq!(purged, 2);
+        assert!(cache.get(&CacheKey::new("b.example.com", RecordType::A)).await.is_some());
+        assert!(cache.get(&CacheKey::new("example.org", RecordType::A)).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_cache_stats() {
+        let cache = CacheManager::new();
+        let key = CacheKey::new("example.com", RecordType::A);
+        let response = create_test_response(12345);
+
+        // Miss
+        cache.get(&key).await;
+        
+        // Set
+        cache.set(key.clone(), response).await;
+        
+        // Hit
+        cache.get(&key).await;
+        cache.get(&key).await;
+
+        let stats = cache.stats().await;
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.entries, 1);
+        assert_eq!(stats.backend, "memory");
+        assert!((stats.hit_rate() - 0.666).abs() < 0.01);
+    }
+}
@@ -0,0 +1,199 @@
+//! Redis cache backend
+//!
+//! Stores responses as JSON under `fluxdns:cache:<type>:<name>` (or
+//! `<type>@<profile>` for listener-pinned profiles) with a millisecond
+//! expiry, so Redis drops entries when their TTL runs out and several
+//! FluxDNS instances can share one cache. Capacity is governed by
+//! the Redis `maxmemory` policy rather than `max_entries` and
+//! `max_memory_mb`. Redis errors are logged and treated as misses so
+//! resolution never depends on Redis.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use redis::RedisResult;
+
+use super::{CacheBackend, CacheKey, CacheLimits, CacheStats, NamePattern};
+use crate::dns::message::DnsResponse;
+
+/// Prefix of every key written by the cache
+const KEY_PREFIX: &str = "fluxdns:cache:";
+/// Keys requested per SCAN round trip
+const SCAN_COUNT: usize = 500;
+
+/// Cache stored in Redis
+pub struct RedisCache {
+    conn: ConnectionManager,
+}
+
+impl RedisCache {
+    /// Connect to Redis at a `redis://` or `rediss://` URL
+    pub async fn connect(url: &str) -> RedisResult<Self> {
+        let client = redis::Client::open(url)?;
+        let conn = ConnectionManager::new(client).await?;
+        Ok(Self { conn })
+    }
+
+    fn redis_key(key: &CacheKey) -> String {
+        match key.profile_id {
+            Some(profile) => format!("{}{}@{}:{}", KEY_PREFIX, key.record_type, profile, key.name),
+            None => format!("{}{}:{}", KEY_PREFIX, key.record_type, key.name),
+        }
+    }
+
+    /// Domain name part of a cache key
+    fn key_name(redis_key: &str) -> Option<&str> {
+        redis_key.strip_prefix(KEY_PREFIX)?.split_once(':').map(|(_, name)| name)
+    }
+
+    /// All cache keys, scanned in batches
+    async fn scan_keys(&self) -> RedisResult<Vec<String>> {
+        let mut conn = self.conn.clone();
+        let mut keys = Vec::new();
+        let mut cursor: u64 = 0;
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(format!("{}*", KEY_PREFIX))
+                .arg("COUNT")
+                .arg(SCAN_COUNT)
+                .query_async(&mut conn)
+                .await?;
+            keys.extend(batch);
+            if next == 0 {
+                return Ok(keys);
+            }
+            cursor = next;
+        }
+    }
+
+    /// Delete keys in batches, returning how many existed
+    async fn delete_keys(&self, keys: &[String]) -> RedisResult<usize> {
+        let mut conn = self.conn.clone();
+        let mut removed = 0;
+        for batch in keys.chunks(SCAN_COUNT) {
+            let count: usize = redis::cmd("DEL").arg(batch).query_async(&mut conn).await?;
+            removed += count;
+        }
+        Ok(removed)
+    }
+}
+
+#[async_trait]
+impl CacheBackend for RedisCache {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn get(&self, key: &CacheKey) -> Option<DnsResponse> {
+        let mut conn = self.conn.clone();
+        let value: Option<String> = match redis::cmd("GET")
+            .arg(Self::redis_key(key))
+            .query_async(&mut conn)
+            .await
+        {
+            Ok(value) => value,
+            Err(e) => {
+                tracing::warn!("Redis cache read failed: {}", e);
+                return None;
+            }
+        };
+        match serde_json::from_str(&value?) {
+            Ok(response) => Some(response),
+            Err(e) => {
+                tracing::warn!("Ignoring unreadable Redis cache entry for {}: {}", key.name, e);
+                None
+            }
+        }
+    }
+
+    async fn set(&self, key: CacheKey, response: DnsResponse, ttl: Duration, _limits: CacheLimits) {
+        // PX 0 is rejected by Redis; an entry with no lifetime is simply not stored
+        let ttl_ms = ttl.as_millis() as u64;
+        if ttl_ms == 0 {
+            return;
+        }
+        let value = match serde_json::to_string(&response) {
+            Ok(value) => value,
+            Err(e) => {
+                tracing::warn!("Failed to serialize cache entry for {}: {}", key.name, e);
+                return;
+            }
+        };
+        let mut conn = self.conn.clone();
+        let result: RedisResult<()> = redis::cmd("SET")
+            .arg(Self::redis_key(&key))
+            .arg(value)
+            .arg("PX")
+            .arg(ttl_ms)
+            .query_async(&mut conn)
+            .await;
+        if let Err(e) = result {
+            tracing::warn!("Redis cache write failed: {}", e);
+        }
+    }
+
+    async fn clear(&self) {
+        let result = match self.scan_keys().await {
+            Ok(keys) => self.delete_keys(&keys).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to clear the Redis cache: {}", e);
+        }
+    }
+
+    async fn purge(&self, pattern: &NamePattern) -> usize {
+        let keys = match self.scan_keys().await {
+            Ok(keys) => keys,
+            Err(e) => {
+                tracing::warn!("Failed to purge the Redis cache: {}", e);
+                return 0;
+            }
+        };
+        let matching: Vec<String> = keys
+            .into_iter()
+            .filter(|key| Self::key_name(key).is_some_and(|name| pattern.matches(name)))
+            .collect();
+        self.delete_keys(&matching).await.unwrap_or_else(|e| {
+            tracing::warn!("Failed to purge the Redis cache: {}", e);
+            0
+        })
+    }
+
+    async fn cleanup_expired(&self) {
+        // Redis expires keys itself
+    }
+
+    async fn stats(&self) -> CacheStats {
+        let entries = self.scan_keys().await.map(|keys| keys.len()).unwrap_or_else(|e| {
+            tracing::warn!("Failed to count Redis cache entries: {}", e);
+            0
+        });
+        CacheStats {
+            entries,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::RecordType;
+
+    #[test]
+    fn test_redis_key_round_trip() {
+        let key = CacheKey::new("WWW.Example.com.", RecordType::AAAA);
+        let redis_key = RedisCache::redis_key(&key);
+        assert_eq!(redis_key, "fluxdns:cache:AAAA:www.example.com");
+        assert_eq!(RedisCache::key_name(&redis_key), Some("www.example.com"));
+        assert_eq!(RedisCache::key_name("other:key"), None);
+
+        let redis_key = RedisCache::redis_key(&key.for_profile(Some(2)));
+        assert_eq!(redis_key, "fluxdns:cache:AAAA@2:www.example.com");
+        assert_eq!(RedisCache::key_name(&redis_key), Some("www.example.com"));
+    }
+}
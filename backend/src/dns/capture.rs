@@ -0,0 +1,349 @@
+//! Live query capture
+//!
+//! Records decoded summaries of listener traffic matching a filter for a
+//! bounded time window, so client misbehaviour can be diagnosed from the
+//! web UI without shell access to run tcpdump. Captures live only in
+//! memory and end after their duration or entry limit, whichever comes
+//! first.
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use super::cidr::IpCidr;
+use super::message::DnsQuery;
+use super::name::normalize_name;
+use super::resolver::ResolveResult;
+
+/// Longest capture window
+pub const MAX_CAPTURE_SECS: u64 = 60;
+/// Most entries a single capture may hold
+pub const MAX_CAPTURE_ENTRIES: usize = 10_000;
+/// Captures that may run at the same time
+pub const MAX_CONCURRENT_CAPTURES: usize = 4;
+
+/// Which queries a capture records
+#[derive(Debug, Clone, Default)]
+pub struct CaptureFilter {
+    /// Client address or network
+    pub client: Option<IpCidr>,
+    /// Domain pattern: `example.com` matches the name and its subdomains,
+    /// `*.example.com` only the subdomains
+    pub domain: Option<String>,
+    /// Listener protocol (udp, doh, dot, doq)
+    pub protocol: Option<String>,
+}
+
+impl CaptureFilter {
+    /// Check whether a query from `client_ip` for `name` matches
+    pub fn matches(&self, client_ip: &str, name: &str) -> bool {
+        if let Some(ref client) = self.client {
+            match client_ip.parse::<IpAddr>() {
+                Ok(ip) if client.contains(&ip) => {}
+                _ => return false,
+            }
+        }
+        if let Some(ref pattern) = self.domain {
+            let name = normalize_name(name);
+            let matched = match pattern.strip_prefix("*.") {
+                Some(suffix) => name
+                    .strip_suffix(suffix)
+                    .is_some_and(|prefix| prefix.ends_with('.')),
+                None => {
+                    name == *pattern
+                        || name
+                            .strip_suffix(pattern.as_str())
+                            .is_some_and(|prefix| prefix.ends_with('.'))
+                }
+            };
+            if !matched {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Check whether a query received on `listener` matches
+    pub fn matches_protocol(&self, listener: Option<&str>) -> bool {
+        self.protocol.is_none() || self.protocol.as_deref() == listener
+    }
+}
+
+/// Decoded summary of one query and its response
+#[derive(Debug, Clone, Serialize)]
+pub struct CapturedQuery {
+    pub timestamp: DateTime<Utc>,
+    pub trace_id: String,
+    pub client_ip: String,
+    pub listener: Option<String>,
+    pub query_name: String,
+    pub query_type: String,
+    pub response_code: String,
+    /// Answer records as "TYPE value TTL"
+    pub answers: Vec<String>,
+    pub response_time_ms: u64,
+    pub cache_hit: bool,
+    pub upstream_used: Option<String>,
+    pub answered_by: Option<String>,
+}
+
+impl CapturedQuery {
+    fn new(
+        client_ip: &str,
+        listener: Option<&str>,
+        query: &DnsQuery,
+        trace_id: &str,
+        result: &Result<ResolveResult>,
+    ) -> Self {
+        let mut entry = Self {
+            timestamp: Utc::now(),
+            trace_id: trace_id.to_string(),
+            client_ip: client_ip.to_string(),
+            listener: listener.map(str::to_string),
+            query_name: normalize_name(&query.name),
+            query_type: query.record_type.to_string(),
+            response_code: String::new(),
+            answers: Vec::new(),
+            response_time_ms: 0,
+            cache_hit: false,
+            upstream_used: None,
+            answered_by: None,
+        };
+        match result {
+            Ok(r) => {
+                entry.response_code = r.response.response_code.to_string();
+                entry.answers = r
+                    .response
+                    .answers
+                    .iter()
+                    .map(|a| format!("{} {} {}", a.record_type, a.value, a.ttl))
+                    .collect();
+                entry.response_time_ms = r.metadata.response_time_ms;
+                entry.cache_hit = r.metadata.cache_hit;
+                entry.upstream_used = r.metadata.upstream_used.clone();
+                entry.answered_by = r.metadata.answered_by.clone();
+            }
+            Err(e) => entry.response_code = format!("ERROR: {}", e),
+        }
+        entry
+    }
+}
+
+/// Why a capture ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureStop {
+    /// The capture window elapsed
+    Duration,
+    /// The entry limit was reached
+    MaxEntries,
+}
+
+/// Entries recorded by a finished capture
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureResult {
+    pub entries: Vec<CapturedQuery>,
+    pub stopped_by: CaptureStop,
+}
+
+struct CaptureSession {
+    filter: CaptureFilter,
+    max_entries: usize,
+    entries: Mutex<Vec<CapturedQuery>>,
+    full: Notify,
+}
+
+/// Running captures, fed by the resolver for every listener query
+pub struct QueryCapture {
+    sessions: RwLock<Vec<Arc<CaptureSession>>>,
+    active: AtomicUsize,
+}
+
+/// Removes a session when its capture ends or the caller goes away
+struct SessionGuard<'a> {
+    capture: &'a QueryCapture,
+    session: Arc<CaptureSession>,
+}
+
+impl Drop for SessionGuard<'_> {
+    fn drop(&mut self) {
+        let mut sessions = self.capture.sessions.write().unwrap();
+        sessions.retain(|s| !Arc::ptr_eq(s, &self.session));
+        self.capture.active.store(sessions.len(), Ordering::Relaxed);
+    }
+}
+
+impl QueryCapture {
+    pub fn new() -> Self {
+        Self {
+            sessions: RwLock::new(Vec::new()),
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    /// Record a resolved listener query in every matching capture
+    pub fn record(
+        &self,
+        client_ip: &str,
+        listener: Option<&str>,
+        query: &DnsQuery,
+        trace_id: &str,
+        result: &Result<ResolveResult>,
+    ) {
+        // Fast path: nothing to do unless a capture is running
+        if self.active.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        let sessions = self.sessions.read().unwrap();
+        let mut entry = None;
+        for session in sessions.iter() {
+            if !session.filter.matches_protocol(listener) || !session.filter.matches(client_ip, &query.name) {
+                continue;
+            }
+            let mut entries = session.entries.lock().unwrap();
+            if entries.len() >= session.max_entries {
+                continue;
+            }
+            let captured = entry
+                .get_or_insert_with(|| CapturedQuery::new(client_ip, listener, query, trace_id, result));
+            entries.push(captured.clone());
+            if entries.len() >= session.max_entries {
+                session.full.notify_one();
+            }
+        }
+    }
+
+    /// Capture matching queries for up to `duration` or `max_entries` entries
+    ///
+    /// Returns `None` when the concurrent capture limit is reached.
+    pub async fn capture(
+        &self,
+        filter: CaptureFilter,
+        duration: Duration,
+        max_entries: usize,
+    ) -> Option<CaptureResult> {
+        let session = Arc::new(CaptureSession {
+            filter,
+            max_entries,
+            entries: Mutex::new(Vec::new()),
+            full: Notify::new(),
+        });
+        {
+            let mut sessions = self.sessions.write().unwrap();
+            if sessions.len() >= MAX_CONCURRENT_CAPTURES {
+                return None;
+            }
+            sessions.push(session.clone());
+            self.active.store(sessions.len(), Ordering::Relaxed);
+        }
+        let guard = SessionGuard {
+            capture: self,
+            session,
+        };
+
+        let stopped_by = match tokio::time::timeout(duration, guard.session.full.notified()).await {
+            Ok(()) => CaptureStop::MaxEntries,
+            Err(_) => CaptureStop::Duration,
+        };
+        let entries = std::mem::take(&mut *guard.session.entries.lock().unwrap());
+        Some(CaptureResult { entries, stopped_by })
+    }
+}
+
+impl Default for QueryCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::{DnsRecordData, DnsResponse, RecordType};
+    use crate::dns::resolver::QueryMetadata;
+
+    fn resolved() -> Result<ResolveResult> {
+        let mut response = DnsResponse::new(1);
+        response.add_answer(DnsRecordData::a("www.example.com", "192.0.2.1".parse().unwrap(), 300));
+        Ok(ResolveResult {
+            response,
+            metadata: QueryMetadata::default(),
+        })
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let filter = CaptureFilter {
+            client: Some("192.168.1.0/24".parse().unwrap()),
+            domain: Some("example.com".to_string()),
+            protocol: None,
+        };
+        assert!(filter.matches("192.168.1.20", "example.com"));
+        assert!(filter.matches("192.168.1.20", "WWW.Example.com."));
+        assert!(!filter.matches("192.168.1.20", "badexample.com"));
+        assert!(!filter.matches("10.0.0.1", "example.com"));
+
+        let filter = CaptureFilter {
+            client: None,
+            domain: Some("*.example.com".to_string()),
+            protocol: Some("doh".to_string()),
+        };
+        assert!(filter.matches("10.0.0.1", "www.example.com"));
+        assert!(!filter.matches("10.0.0.1", "example.com"));
+        assert!(filter.matches_protocol(Some("doh")));
+        assert!(!filter.matches_protocol(Some("udp")));
+        assert!(!filter.matches_protocol(None));
+        assert!(CaptureFilter::default().matches_protocol(Some("udp")));
+
+        assert!(CaptureFilter::default().matches("10.0.0.1", "anything.test"));
+    }
+
+    #[tokio::test]
+    async fn test_capture_stops_at_max_entries() {
+        let capture = Arc::new(QueryCapture::new());
+        let query = DnsQuery::new("www.example.com", RecordType::A);
+
+        // Nothing is recorded while no capture runs
+        capture.record("10.0.0.1", Some("udp"), &query, "t0", &resolved());
+
+        let running = {
+            let capture = capture.clone();
+            tokio::spawn(async move {
+                capture
+                    .capture(CaptureFilter::default(), Duration::from_secs(30), 2)
+                    .await
+            })
+        };
+        while capture.active.load(Ordering::Relaxed) == 0 {
+            tokio::task::yield_now().await;
+        }
+        for trace_id in ["t1", "t2", "t3"] {
+            capture.record("10.0.0.1", Some("udp"), &query, trace_id, &resolved());
+        }
+
+        let result = running.await.unwrap().unwrap();
+        assert_eq!(result.stopped_by, CaptureStop::MaxEntries);
+        assert_eq!(result.entries.len(), 2);
+        assert_eq!(result.entries[0].trace_id, "t1");
+        assert_eq!(result.entries[0].answers, vec!["A 192.0.2.1 300".to_string()]);
+        assert_eq!(capture.active.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_capture_stops_after_duration() {
+        let capture = QueryCapture::new();
+        let result = capture
+            .capture(CaptureFilter::default(), Duration::from_millis(10), 10)
+            .await
+            .unwrap();
+        assert_eq!(result.stopped_by, CaptureStop::Duration);
+        assert!(result.entries.is_empty());
+    }
+}
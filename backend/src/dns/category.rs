@@ -0,0 +1,547 @@
+//! Domain category classification
+//!
+//! Classifies domains (e.g. "advertising", "social", "malware") so queries
+//! can be tagged in the query log, filtered by category, and counted per
+//! category. Classification sources, in order:
+//!
+//! 1. Local category lists stored in the database. A domain matches a list
+//!    entry for itself or any parent domain; the first enabled list wins.
+//! 2. An optional external classification API, queried in the background
+//!    on a miss. `{domain}` in the configured URL is replaced with the
+//!    domain and the response must be JSON like `{"category": "advertising"}`.
+//!
+//! API results (including "unclassified") are cached for a configurable
+//! TTL so each domain is looked up at most once per TTL. The classifier
+//! also runs as the `category_filter` middleware, which blocks or rewrites
+//! queries for categories with a configured action.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::db::Database;
+use super::message::{DnsQuery, DnsRecordData, DnsResponse, RecordType};
+use super::middleware::{HookOutcome, QueryContext, ResolverMiddleware};
+use super::name::normalize_name;
+use super::resolver::{DnsResolver, ResolveResult};
+
+/// Config key for the external classification API URL
+pub const CONFIG_KEY_CATEGORY_API_URL: &str = "category_api_url";
+/// Config key for per-category actions (JSON object)
+pub const CONFIG_KEY_CATEGORY_ACTIONS: &str = "category_actions";
+/// Config key for the API result cache TTL in seconds
+pub const CONFIG_KEY_CATEGORY_CACHE_TTL: &str = "category_cache_ttl";
+
+/// Middleware name recorded as `answered_by` for filtered queries
+pub const CATEGORY_FILTER: &str = "category_filter";
+
+/// Default API result cache TTL
+pub const DEFAULT_CATEGORY_CACHE_TTL: u64 = 86400;
+/// Downloaded lists older than this are refreshed
+pub const CATEGORY_LIST_REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+
+/// Timeout for a single classification API request
+const API_TIMEOUT: Duration = Duration::from_secs(3);
+/// How long an in-flight API lookup blocks duplicate lookups
+const PENDING_TTL: Duration = Duration::from_secs(30);
+/// Upper bound on cached API results
+const MAX_CACHE_ENTRIES: usize = 100_000;
+/// TTL for answers synthesized by a rewrite action
+const REWRITE_TTL: u32 = 60;
+
+/// Action applied to queries for a category
+#[derive(Debug, Clone, PartialEq)]
+pub enum CategoryAction {
+    /// Answer NXDOMAIN
+    Block,
+    /// Answer with this address
+    Rewrite(IpAddr),
+}
+
+impl CategoryAction {
+    /// Parse `"block"` or an IP address
+    pub fn parse(value: &str) -> Option<Self> {
+        if value.eq_ignore_ascii_case("block") {
+            Some(Self::Block)
+        } else {
+            value.parse().ok().map(Self::Rewrite)
+        }
+    }
+
+    /// Settings representation, the inverse of `parse`
+    pub fn as_setting(&self) -> String {
+        match self {
+            Self::Block => "block".to_string(),
+            Self::Rewrite(ip) => ip.to_string(),
+        }
+    }
+}
+
+/// Where a classification came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClassificationSource {
+    List,
+    Api,
+    Cache,
+    None,
+}
+
+/// Classification of a single domain
+#[derive(Debug, Clone, Serialize)]
+pub struct Classification {
+    pub domain: String,
+    pub category: Option<String>,
+    pub source: ClassificationSource,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    category: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct CachedCategory {
+    category: Option<String>,
+    expires_at: Instant,
+}
+
+/// Parse a downloaded list
+///
+/// Accepts hosts-file lines (`0.0.0.0 ads.example.com`), plain
+/// domain-per-line files and simple adblock rules (`||ads.example.com^`).
+/// Comments, blank lines and invalid names are skipped.
+pub fn parse_domain_list(text: &str) -> Vec<String> {
+    const IGNORED: &[&str] = &["localhost", "localhost.localdomain", "local", "broadcasthost", "0.0.0.0"];
+
+    let mut domains = Vec::new();
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() || line.starts_with('!') || line.starts_with('[') {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let first = parts.next().unwrap_or_default();
+        let candidate = if first.parse::<IpAddr>().is_ok() {
+            match parts.next() {
+                Some(name) => name,
+                None => continue,
+            }
+        } else if parts.next().is_some() {
+            // Neither a hosts entry nor a single domain
+            continue;
+        } else if let Some(rule) = first.strip_prefix("||") {
+            rule.trim_end_matches('^')
+        } else {
+            first
+        };
+
+        let domain = normalize_name(candidate);
+        if IGNORED.contains(&domain.as_str()) || !DnsResolver::is_valid_domain(&domain) {
+            continue;
+        }
+        domains.push(domain);
+    }
+
+    domains.sort();
+    domains.dedup();
+    domains
+}
+
+/// Domain classifier and category filter middleware
+pub struct DomainClassifier {
+    db: Option<Arc<Database>>,
+    /// Domain -> category from enabled local lists
+    domains: RwLock<HashMap<String, String>>,
+    /// Category -> action
+    actions: RwLock<HashMap<String, CategoryAction>>,
+    api_url: RwLock<Option<String>>,
+    cache_ttl: RwLock<Duration>,
+    cache: Arc<DashMap<String, CachedCategory>>,
+    client: reqwest::Client,
+}
+
+#[allow(dead_code)]
+impl DomainClassifier {
+    pub fn new(db: Option<Arc<Database>>) -> Self {
+        Self {
+            db,
+            domains: RwLock::new(HashMap::new()),
+            actions: RwLock::new(HashMap::new()),
+            api_url: RwLock::new(None),
+            cache_ttl: RwLock::new(Duration::from_secs(DEFAULT_CATEGORY_CACHE_TTL)),
+            cache: Arc::new(DashMap::new()),
+            client: reqwest::Client::builder()
+                .timeout(API_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Load lists and settings from database
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let mut domains = HashMap::new();
+        for (domain, category) in db.category_lists().enabled_entries().await? {
+            // Entries come ordered by list, so the first list wins
+            domains.entry(domain).or_insert(category);
+        }
+        *self.domains.write().unwrap() = domains;
+
+        let config = db.system_config();
+        let api_url = config
+            .get(CONFIG_KEY_CATEGORY_API_URL)
+            .await?
+            .filter(|u| !u.trim().is_empty());
+        let actions = config
+            .get(CONFIG_KEY_CATEGORY_ACTIONS)
+            .await?
+            .map(|v| serde_json::from_str::<HashMap<String, String>>(&v).unwrap_or_default())
+            .unwrap_or_default();
+        let cache_ttl = config
+            .get(CONFIG_KEY_CATEGORY_CACHE_TTL)
+            .await?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_CATEGORY_CACHE_TTL);
+
+        self.set_api_url(api_url);
+        self.set_actions(
+            actions
+                .iter()
+                .filter_map(|(category, action)| match CategoryAction::parse(action) {
+                    Some(action) => Some((category.clone(), action)),
+                    None => {
+                        warn!("Ignoring invalid action '{}' for category {}", action, category);
+                        None
+                    }
+                })
+                .collect(),
+        );
+        *self.cache_ttl.write().unwrap() = Duration::from_secs(cache_ttl);
+        Ok(())
+    }
+
+    /// Reload lists and settings from database
+    pub async fn reload(&self) -> Result<()> {
+        self.load().await
+    }
+
+    /// Add a local entry (in-memory only)
+    pub fn add_domain(&self, domain: &str, category: &str) {
+        self.domains
+            .write()
+            .unwrap()
+            .insert(normalize_name(domain), category.to_string());
+    }
+
+    /// Number of domains in the local database
+    pub fn domain_count(&self) -> usize {
+        self.domains.read().unwrap().len()
+    }
+
+    /// Set the external API URL, clearing cached API results
+    pub fn set_api_url(&self, url: Option<String>) {
+        let mut current = self.api_url.write().unwrap();
+        if *current != url {
+            self.cache.clear();
+        }
+        *current = url;
+    }
+
+    /// Replace the category actions
+    pub fn set_actions(&self, actions: HashMap<String, CategoryAction>) {
+        *self.actions.write().unwrap() = actions;
+    }
+
+    /// Action configured for a category
+    pub fn action_for(&self, category: &str) -> Option<CategoryAction> {
+        self.actions.read().unwrap().get(category).cloned()
+    }
+
+    /// Drop cached API results
+    pub fn clear_cache(&self) -> usize {
+        let count = self.cache.len();
+        self.cache.clear();
+        count
+    }
+
+    /// Look a domain up in the local lists, walking up parent domains
+    fn lookup_local(&self, domain: &str) -> Option<String> {
+        let domains = self.domains.read().unwrap();
+        let mut name = domain;
+        loop {
+            if let Some(category) = domains.get(name) {
+                return Some(category.clone());
+            }
+            name = name.split_once('.')?.1;
+        }
+    }
+
+    /// Unexpired cached API result (outer `None` = not cached)
+    fn lookup_cached(&self, domain: &str) -> Option<Option<String>> {
+        let entry = self.cache.get(domain)?;
+        (entry.expires_at > Instant::now()).then(|| entry.category.clone())
+    }
+
+    fn store(cache: &DashMap<String, CachedCategory>, domain: String, category: Option<String>, ttl: Duration) {
+        if cache.len() >= MAX_CACHE_ENTRIES {
+            let now = Instant::now();
+            cache.retain(|_, e| e.expires_at > now);
+            if cache.len() >= MAX_CACHE_ENTRIES {
+                cache.clear();
+            }
+        }
+        cache.insert(
+            domain,
+            CachedCategory {
+                category,
+                expires_at: Instant::now() + ttl,
+            },
+        );
+    }
+
+    async fn query_api(client: &reqwest::Client, url: &str, domain: &str) -> Result<Option<String>> {
+        let url = url.replace("{domain}", domain);
+        let response = client.get(&url).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Classification API returned {}", response.status()));
+        }
+        let body: ApiResponse = response.json().await?;
+        Ok(body.category.map(|c| c.trim().to_lowercase()).filter(|c| !c.is_empty()))
+    }
+
+    /// Classify a domain without waiting on the external API
+    ///
+    /// On a miss with an API configured, the lookup runs in the background
+    /// and later queries for the domain pick up the cached result.
+    pub fn classify(&self, domain: &str) -> Option<String> {
+        let domain = normalize_name(domain);
+        if let Some(category) = self.lookup_local(&domain) {
+            return Some(category);
+        }
+        if let Some(cached) = self.lookup_cached(&domain) {
+            return cached;
+        }
+
+        if !DnsResolver::is_valid_domain(&domain) {
+            return None;
+        }
+        let url = self.api_url.read().unwrap().clone()?;
+        // Mark the lookup as pending so concurrent queries don't repeat it
+        Self::store(&self.cache, domain.clone(), None, PENDING_TTL);
+
+        let cache = self.cache.clone();
+        let client = self.client.clone();
+        let ttl = *self.cache_ttl.read().unwrap();
+        tokio::spawn(async move {
+            match Self::query_api(&client, &url, &domain).await {
+                Ok(category) => {
+                    debug!("Classified {} as {:?}", domain, category);
+                    Self::store(&cache, domain, category, ttl);
+                }
+                Err(e) => debug!("Classification lookup for {} failed: {}", domain, e),
+            }
+        });
+        None
+    }
+
+    /// Classify a domain, waiting for the external API if needed
+    pub async fn classify_now(&self, domain: &str) -> Result<Classification> {
+        let domain = normalize_name(domain);
+        let classified = |category, source| Classification {
+            domain: domain.clone(),
+            category,
+            source,
+        };
+
+        if let Some(category) = self.lookup_local(&domain) {
+            return Ok(classified(Some(category), ClassificationSource::List));
+        }
+        if let Some(category) = self.lookup_cached(&domain) {
+            return Ok(classified(category, ClassificationSource::Cache));
+        }
+
+        let url = self.api_url.read().unwrap().clone();
+        match url {
+            Some(url) => {
+                let category = Self::query_api(&self.client, &url, &domain).await?;
+                let ttl = *self.cache_ttl.read().unwrap();
+                Self::store(&self.cache, domain.clone(), category.clone(), ttl);
+                Ok(classified(category, ClassificationSource::Api))
+            }
+            None => Ok(classified(None, ClassificationSource::None)),
+        }
+    }
+
+    /// Download a list and replace its domains; returns the domain count
+    pub async fn refresh_list(&self, list_id: i64) -> Result<i64> {
+        let db = self.db.as_ref().ok_or_else(|| anyhow!("No database configured"))?;
+        let list = db
+            .category_lists()
+            .get_by_id(list_id)
+            .await?
+            .ok_or_else(|| anyhow!("Category list {} not found", list_id))?;
+        let url = list
+            .url
+            .ok_or_else(|| anyhow!("Category list '{}' has no download URL", list.name))?;
+
+        let response = self.client.get(&url).timeout(Duration::from_secs(60)).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Download of {} returned {}", url, response.status()));
+        }
+        let domains = parse_domain_list(&response.text().await?);
+        let count = db.category_lists().replace_domains(list_id, &domains).await?;
+        info!("Category list '{}' refreshed: {} domains", list.name, count);
+
+        self.reload().await?;
+        Ok(count)
+    }
+
+    /// Refresh enabled downloadable lists that are older than the refresh interval
+    pub async fn refresh_due(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let interval = chrono::Duration::from_std(CATEGORY_LIST_REFRESH_INTERVAL)?;
+        let now = chrono::Utc::now();
+        for list in db.category_lists().list().await? {
+            let due = list.enabled
+                && list.url.is_some()
+                && list.last_updated_at.is_none_or(|t| now - t >= interval);
+            if due {
+                if let Err(e) = self.refresh_list(list.id).await {
+                    warn!("Failed to refresh category list '{}': {}", list.name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the response for a rewrite action
+    fn rewrite_response(query: &DnsQuery, ip: IpAddr) -> DnsResponse {
+        let mut response = DnsResponse::new(query.id);
+        match (ip, query.record_type) {
+            (IpAddr::V4(v4), RecordType::A) => {
+                response.add_answer(DnsRecordData::a(&query.name, v4, REWRITE_TTL));
+            }
+            (IpAddr::V6(v6), RecordType::AAAA) => {
+                response.add_answer(DnsRecordData::aaaa(&query.name, v6, REWRITE_TTL));
+            }
+            // Other types get an empty NOERROR answer, like rewrite rules
+            _ => {}
+        }
+        response
+    }
+}
+
+#[async_trait]
+impl ResolverMiddleware for DomainClassifier {
+    fn name(&self) -> &str {
+        CATEGORY_FILTER
+    }
+
+    async fn pre_rewrite(&self, ctx: &mut QueryContext) -> Result<HookOutcome> {
+        let Some(category) = self.classify(&ctx.query.name) else {
+            return Ok(HookOutcome::Continue);
+        };
+
+        Ok(match self.action_for(&category) {
+            None => HookOutcome::Continue,
+            Some(CategoryAction::Block) => HookOutcome::Respond(DnsResponse::nxdomain(ctx.query.id)),
+            Some(CategoryAction::Rewrite(ip)) => HookOutcome::Respond(Self::rewrite_response(&ctx.query, ip)),
+        })
+    }
+
+    async fn post_response(&self, ctx: &QueryContext, result: &mut ResolveResult) -> Result<()> {
+        if result.metadata.category.is_none() {
+            result.metadata.category = self.classify(&ctx.query.name);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::DnsResponseCode;
+
+    #[test]
+    fn test_parse_domain_list() {
+        let text = "\
+# hosts file
+0.0.0.0 ads.example.com
+127.0.0.1 localhost
+127.0.0.1 Tracker.Example.NET. # trailing comment
+! adblock comment
+||metrics.example.org^
+plain.example.io
+bad domain
+ads.example.com
+";
+        assert_eq!(
+            parse_domain_list(text),
+            vec!["ads.example.com", "metrics.example.org", "plain.example.io", "tracker.example.net"]
+        );
+    }
+
+    #[test]
+    fn test_category_action_parse() {
+        assert_eq!(CategoryAction::parse("BLOCK"), Some(CategoryAction::Block));
+        assert_eq!(
+            CategoryAction::parse("10.0.0.1"),
+            Some(CategoryAction::Rewrite("10.0.0.1".parse().unwrap()))
+        );
+        assert_eq!(CategoryAction::parse("allow"), None);
+    }
+
+    #[tokio::test]
+    async fn test_classify_matches_parent_domains() {
+        let classifier = DomainClassifier::new(None);
+        classifier.add_domain("doubleclick.net", "advertising");
+
+        assert_eq!(classifier.classify("ad.g.doubleclick.net.").as_deref(), Some("advertising"));
+        assert_eq!(classifier.classify("DoubleClick.net").as_deref(), Some("advertising"));
+        assert_eq!(classifier.classify("notdoubleclick.net"), None);
+
+        let result = classifier.classify_now("example.com").await.unwrap();
+        assert_eq!(result.source, ClassificationSource::None);
+    }
+
+    #[tokio::test]
+    async fn test_category_filter_middleware() {
+        let classifier = DomainClassifier::new(None);
+        classifier.add_domain("ads.example.com", "advertising");
+        classifier.add_domain("facebook.com", "social");
+        classifier.set_actions(HashMap::from([
+            ("advertising".to_string(), CategoryAction::Block),
+            ("social".to_string(), CategoryAction::Rewrite("10.0.0.1".parse().unwrap())),
+        ]));
+
+        let mut ctx = QueryContext::new(DnsQuery::new("ads.example.com", RecordType::A), None);
+        match classifier.pre_rewrite(&mut ctx).await.unwrap() {
+            HookOutcome::Respond(r) => assert_eq!(r.response_code, DnsResponseCode::NxDomain),
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+
+        let mut ctx = QueryContext::new(DnsQuery::new("www.facebook.com", RecordType::A), None);
+        match classifier.pre_rewrite(&mut ctx).await.unwrap() {
+            HookOutcome::Respond(r) => assert_eq!(r.answers[0].value, "10.0.0.1"),
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+
+        let mut ctx = QueryContext::new(DnsQuery::new("example.com", RecordType::A), None);
+        assert!(matches!(classifier.pre_rewrite(&mut ctx).await.unwrap(), HookOutcome::Continue));
+    }
+}
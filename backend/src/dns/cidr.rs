@@ -0,0 +1,135 @@
+//! CIDR matching
+//!
+//! Minimal IPv4/IPv6 network prefix parsing used to map DNS clients to
+//! subnet-scoped configuration.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// An IP network in CIDR notation (e.g. `10.0.0.0/8`, `fd00::/8`)
+///
+/// A bare address is accepted and treated as a host route (/32 or /128).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpCidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+#[allow(dead_code)]
+impl IpCidr {
+    /// Create a network from an address and prefix length
+    pub fn new(addr: IpAddr, prefix: u8) -> Option<Self> {
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix > max {
+            return None;
+        }
+        Some(Self { addr, prefix })
+    }
+
+    /// Network prefix length
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Check whether an address falls inside this network
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = if self.prefix == 0 { 0 } else { u32::MAX << (32 - self.prefix) };
+                (u32::from(net) & mask) == (u32::from(*ip) & mask)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = if self.prefix == 0 { 0 } else { u128::MAX << (128 - self.prefix) };
+                (u128::from(net) & mask) == (u128::from(*ip) & mask)
+            }
+            // IPv4-mapped IPv6 clients (dual-stack sockets) match IPv4 networks
+            (IpAddr::V4(_), IpAddr::V6(ip)) => match ip.to_ipv4_mapped() {
+                Some(v4) => self.contains(&IpAddr::V4(v4)),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Parse a comma-separated list of networks, skipping blanks
+    pub fn parse_list(list: &str) -> Result<Vec<Self>, String> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(Self::from_str)
+            .collect()
+    }
+}
+
+impl FromStr for IpCidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+
+        let addr: IpAddr = addr
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid network address: {}", s))?;
+        let prefix = match prefix {
+            Some(p) => p
+                .trim()
+                .parse::<u8>()
+                .map_err(|_| format!("Invalid prefix length: {}", s))?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+
+        Self::new(addr, prefix).ok_or_else(|| format!("Prefix length out of range: {}", s))
+    }
+}
+
+impl fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_contains_v4() {
+        let net: IpCidr = "192.168.1.0/24".parse().unwrap();
+        assert!(net.contains(&"192.168.1.77".parse().unwrap()));
+        assert!(!net.contains(&"192.168.2.1".parse().unwrap()));
+        assert!(net.contains(&"::ffff:192.168.1.5".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_parse_and_contains_v6() {
+        let net: IpCidr = "fd00::/8".parse().unwrap();
+        assert!(net.contains(&"fd12::1".parse().unwrap()));
+        assert!(!net.contains(&"2001:db8::1".parse().unwrap()));
+        assert!(!net.contains(&"10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_bare_address_is_host_route() {
+        let net: IpCidr = "10.0.0.1".parse().unwrap();
+        assert_eq!(net.prefix(), 32);
+        assert!(net.contains(&"10.0.0.1".parse().unwrap()));
+        assert!(!net.contains(&"10.0.0.2".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let nets = IpCidr::parse_list(" 10.0.0.0/8, ,172.16.0.0/12 ").unwrap();
+        assert_eq!(nets.len(), 2);
+        assert!(IpCidr::parse_list("10.0.0.0/33").is_err());
+        assert!(IpCidr::parse_list("not-an-ip").is_err());
+    }
+}
@@ -0,0 +1,279 @@
+//! Client access control
+//!
+//! Queries from blocked client networks are rejected with the configured
+//! response code by the first `pre_rewrite` middleware of the resolver, so
+//! every resolution path carrying a client address is checked before any
+//! other stage runs. Rejections are recorded
+//! with `answered_by = "client_acl"` and `block_reason = "client"`, while
+//! names blocked by rewrite rules or filters get `block_reason = "domain"`,
+//! so abuse rejections and policy hits can be told apart in the query log
+//! and the status counters.
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::db::Database;
+use super::cidr::IpCidr;
+use super::extended_error::ExtendedError;
+use super::message::DnsResponse;
+use super::middleware::{HookOutcome, QueryContext, ResolverMiddleware};
+
+/// Config key for the blocked client networks
+pub const CONFIG_KEY_BLOCKED_CLIENTS: &str = "blocked_clients";
+/// Config key for the response code sent to blocked clients
+pub const CONFIG_KEY_BLOCKED_CLIENT_RESPONSE: &str = "blocked_client_response";
+
+/// `answered_by` value for queries rejected because of the client
+pub const CLIENT_ACL_ANSWERED_BY: &str = "client_acl";
+
+/// Why a query was blocked, as stored in `query_logs.block_reason`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockReason {
+    /// The client is on the blocked list
+    Client,
+    /// The name was blocked by a rewrite rule or filter
+    Domain,
+}
+
+impl BlockReason {
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "client" => Some(Self::Client),
+            "domain" => Some(Self::Domain),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Client => "client",
+            Self::Domain => "domain",
+        }
+    }
+}
+
+/// Response code for blocked clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockedClientResponse {
+    #[default]
+    Refused,
+    NxDomain,
+}
+
+impl BlockedClientResponse {
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "refused" => Some(Self::Refused),
+            "nxdomain" => Some(Self::NxDomain),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Refused => "refused",
+            Self::NxDomain => "nxdomain",
+        }
+    }
+}
+
+/// Client access control settings
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ClientAclSettings {
+    /// Blocked addresses and networks, e.g. `203.0.113.7` or `198.51.100.0/24`
+    pub clients: Vec<String>,
+    pub response: BlockedClientResponse,
+}
+
+/// Blocked queries since startup, by reason
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct BlockStats {
+    pub clients_blocked: u64,
+    pub domains_blocked: u64,
+}
+
+struct ActiveAcl {
+    settings: ClientAclSettings,
+    networks: Vec<IpCidr>,
+}
+
+/// Blocked client list consulted by the resolver before anything else
+pub struct ClientAcl {
+    db: Option<Arc<Database>>,
+    active: RwLock<ActiveAcl>,
+    clients_blocked: AtomicU64,
+    domains_blocked: AtomicU64,
+}
+
+#[allow(dead_code)]
+impl ClientAcl {
+    pub fn new(db: Option<Arc<Database>>) -> Self {
+        Self {
+            db,
+            active: RwLock::new(ActiveAcl {
+                settings: ClientAclSettings::default(),
+                networks: Vec::new(),
+            }),
+            clients_blocked: AtomicU64::new(0),
+            domains_blocked: AtomicU64::new(0),
+        }
+    }
+
+    /// Load settings from database
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let config = db.system_config();
+        let settings = ClientAclSettings {
+            clients: config
+                .get(CONFIG_KEY_BLOCKED_CLIENTS)
+                .await?
+                .and_then(|v| serde_json::from_str(&v).ok())
+                .unwrap_or_default(),
+            response: config
+                .get(CONFIG_KEY_BLOCKED_CLIENT_RESPONSE)
+                .await?
+                .and_then(|v| BlockedClientResponse::from_str(&v))
+                .unwrap_or_default(),
+        };
+
+        self.set_settings(settings);
+        Ok(())
+    }
+
+    /// Persist and apply settings
+    pub async fn save_settings(&self, settings: ClientAclSettings) -> Result<()> {
+        if let Some(ref db) = self.db {
+            let config = db.system_config();
+            config
+                .set(CONFIG_KEY_BLOCKED_CLIENTS, &serde_json::to_string(&settings.clients)?)
+                .await?;
+            config
+                .set(CONFIG_KEY_BLOCKED_CLIENT_RESPONSE, settings.response.as_str())
+                .await?;
+        }
+        self.set_settings(settings);
+        Ok(())
+    }
+
+    pub fn settings(&self) -> ClientAclSettings {
+        self.active.read().unwrap().settings.clone()
+    }
+
+    /// Apply settings; entries that are not valid networks are skipped
+    pub fn set_settings(&self, settings: ClientAclSettings) {
+        let networks = settings
+            .clients
+            .iter()
+            .filter_map(|c| c.trim().parse::<IpCidr>().ok())
+            .collect();
+        *self.active.write().unwrap() = ActiveAcl { settings, networks };
+    }
+
+    /// Whether queries from this client are rejected
+    pub fn is_blocked(&self, client_ip: &str) -> bool {
+        let Ok(ip) = client_ip.parse::<IpAddr>() else {
+            return false;
+        };
+        self.active
+            .read()
+            .unwrap()
+            .networks
+            .iter()
+            .any(|network| network.contains(&ip))
+    }
+
+    /// Answer for a query from a blocked client
+    pub fn response(&self, id: u16) -> DnsResponse {
+        let response = match self.settings().response {
+            BlockedClientResponse::Refused => DnsResponse::refused(id),
+            BlockedClientResponse::NxDomain => DnsResponse::nxdomain(id),
+        };
+        response.with_extended_error(ExtendedError::new(ExtendedError::PROHIBITED, "blocked client"))
+    }
+
+    /// Count a blocked query
+    pub fn record(&self, reason: BlockReason) {
+        let counter = match reason {
+            BlockReason::Client => &self.clients_blocked,
+            BlockReason::Domain => &self.domains_blocked,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> BlockStats {
+        BlockStats {
+            clients_blocked: self.clients_blocked.load(Ordering::Relaxed),
+            domains_blocked: self.domains_blocked.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for ClientAcl {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[async_trait]
+impl ResolverMiddleware for ClientAcl {
+    fn name(&self) -> &str {
+        CLIENT_ACL_ANSWERED_BY
+    }
+
+    async fn pre_rewrite(&self, ctx: &mut QueryContext) -> Result<HookOutcome> {
+        match ctx.client_ip.as_deref() {
+            Some(client_ip) if self.is_blocked(client_ip) => Ok(HookOutcome::Respond(self.response(ctx.query.id))),
+            _ => Ok(HookOutcome::Continue),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::DnsResponseCode;
+
+    #[test]
+    fn test_client_acl() {
+        let acl = ClientAcl::default();
+        assert!(!acl.is_blocked("198.51.100.9"));
+
+        acl.set_settings(ClientAclSettings {
+            clients: vec!["198.51.100.0/24".to_string(), "2001:db8::1".to_string()],
+            response: BlockedClientResponse::NxDomain,
+        });
+        assert!(acl.is_blocked("198.51.100.9"));
+        assert!(acl.is_blocked("2001:db8::1"));
+        assert!(!acl.is_blocked("2001:db8::2"));
+        assert!(!acl.is_blocked("192.0.2.1"));
+        assert!(!acl.is_blocked("not-an-ip"));
+
+        let response = acl.response(7);
+        assert_eq!(response.id, 7);
+        assert_eq!(response.response_code, DnsResponseCode::NxDomain);
+        assert_eq!(response.extended_error.map(|e| e.code), Some(ExtendedError::PROHIBITED));
+
+        acl.record(BlockReason::Client);
+        acl.record(BlockReason::Domain);
+        acl.record(BlockReason::Domain);
+        assert_eq!(
+            acl.stats(),
+            BlockStats {
+                clients_blocked: 1,
+                domains_blocked: 2,
+            }
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! A/AAAA companion prefetch
+//!
+//! Dual-stack clients ask for the A and AAAA records of a name back to back,
+//! and each of the two usually misses the cache. With companion prefetch
+//! enabled, a client query for A that had to go upstream also resolves the
+//! AAAA of the same name in the background (and the other way round), so
+//! the companion query is answered from cache. Prefetched keys are tracked
+//! until their first cache hit to measure how often the client actually
+//! asked; the counters are part of `/api/status`.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::db::Database;
+use super::cache::CacheKey;
+use super::message::RecordType;
+
+/// Config key for enabling companion prefetch
+pub const CONFIG_KEY_COMPANION_PREFETCH: &str = "companion_prefetch";
+
+/// Prefetched keys tracked for hit counting
+const PENDING_LIMIT: usize = 10_000;
+
+/// A prefetch not asked for within this time no longer counts as a hit
+const PENDING_TTL: Duration = Duration::from_secs(60);
+
+/// Companion prefetch counters
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CompanionPrefetchStats {
+    pub enabled: bool,
+    /// Companion lookups sent upstream
+    pub prefetches: u64,
+    /// Prefetched answers later served to a client from cache
+    pub hits: u64,
+    /// Hits per prefetch
+    pub hit_rate: f64,
+    /// Companion lookups that failed or returned no cacheable answer
+    pub failures: u64,
+    /// Companions not fetched because they were cached or already in flight
+    pub skipped: u64,
+}
+
+/// Background resolution of the A/AAAA companion of client queries
+pub struct CompanionPrefetch {
+    db: Option<Arc<Database>>,
+    enabled: AtomicBool,
+    prefetches: AtomicU64,
+    hits: AtomicU64,
+    failures: AtomicU64,
+    skipped: AtomicU64,
+    /// Prefetched (or in-flight) keys not yet asked for, by start time
+    pending: Mutex<HashMap<CacheKey, Instant>>,
+}
+
+#[allow(dead_code)]
+impl CompanionPrefetch {
+    pub fn new(db: Option<Arc<Database>>) -> Self {
+        Self {
+            db,
+            enabled: AtomicBool::new(false),
+            prefetches: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            skipped: AtomicU64::new(0),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Load the setting from database
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let enabled = db
+            .system_config()
+            .get(CONFIG_KEY_COMPANION_PREFETCH)
+            .await?
+            .is_some_and(|v| v == "true");
+        self.set_enabled(enabled);
+        Ok(())
+    }
+
+    /// Persist and apply the setting
+    pub async fn save_enabled(&self, enabled: bool) -> Result<()> {
+        if let Some(ref db) = self.db {
+            db.system_config()
+                .set(CONFIG_KEY_COMPANION_PREFETCH, if enabled { "true" } else { "false" })
+                .await?;
+        }
+        self.set_enabled(enabled);
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.pending.lock().unwrap().clear();
+        }
+    }
+
+    /// The companion of an address query type
+    pub fn companion_type(record_type: RecordType) -> Option<RecordType> {
+        match record_type {
+            RecordType::A => Some(RecordType::AAAA),
+            RecordType::AAAA => Some(RecordType::A),
+            _ => None,
+        }
+    }
+
+    /// Claim a key for prefetching; false when it is already in flight
+    pub fn begin(&self, key: &CacheKey) -> bool {
+        let now = Instant::now();
+        let mut pending = self.pending.lock().unwrap();
+        if pending.len() >= PENDING_LIMIT {
+            pending.retain(|_, started| now.duration_since(*started) < PENDING_TTL);
+        }
+        let in_flight = pending
+            .get(key)
+            .is_some_and(|started| now.duration_since(*started) < PENDING_TTL);
+        if in_flight || pending.len() >= PENDING_LIMIT {
+            drop(pending);
+            self.record_skip();
+            return false;
+        }
+        pending.insert(key.clone(), now);
+        drop(pending);
+        self.prefetches.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Count a companion that was not fetched
+    pub fn record_skip(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a prefetch that left nothing in the cache
+    pub fn record_failure(&self, key: &CacheKey) {
+        self.pending.lock().unwrap().remove(key);
+        self.failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Note a client cache hit, counted when a prefetch stored the entry
+    pub fn record_hit(&self, key: &CacheKey) {
+        if !self.is_enabled() {
+            return;
+        }
+        let started = self.pending.lock().unwrap().remove(key);
+        if started.is_some_and(|t| t.elapsed() < PENDING_TTL) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> CompanionPrefetchStats {
+        let prefetches = self.prefetches.load(Ordering::Relaxed);
+        let hits = self.hits.load(Ordering::Relaxed);
+        CompanionPrefetchStats {
+            enabled: self.is_enabled(),
+            prefetches,
+            hits,
+            hit_rate: if prefetches == 0 { 0.0 } else { hits as f64 / prefetches as f64 },
+            failures: self.failures.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for CompanionPrefetch {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_companion_type() {
+        assert_eq!(CompanionPrefetch::companion_type(RecordType::A), Some(RecordType::AAAA));
+        assert_eq!(CompanionPrefetch::companion_type(RecordType::AAAA), Some(RecordType::A));
+        assert_eq!(CompanionPrefetch::companion_type(RecordType::MX), None);
+    }
+
+    #[test]
+    fn test_prefetch_hits_are_counted_once() {
+        let prefetch = CompanionPrefetch::new(None);
+        prefetch.set_enabled(true);
+        let key = CacheKey::new("example.com", RecordType::AAAA);
+
+        assert!(prefetch.begin(&key));
+        assert!(!prefetch.begin(&key));
+        prefetch.record_hit(&key);
+        prefetch.record_hit(&key);
+        prefetch.record_hit(&CacheKey::new("other.com", RecordType::A));
+
+        let failed = CacheKey::new("broken.com", RecordType::A);
+        assert!(prefetch.begin(&failed));
+        prefetch.record_failure(&failed);
+        assert!(prefetch.begin(&failed));
+
+        let stats = prefetch.stats();
+        assert_eq!(stats.prefetches, 3);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(stats.failures, 1);
+        assert!((stats.hit_rate - 1.0 / 3.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,392 @@
+//! DNS Cookies (RFC 7873)
+//!
+//! The UDP listener answers queries carrying a client cookie with a server
+//! cookie (RFC 9018 layout) bound to that client cookie and the client's
+//! address. A client echoing it back has shown it receives traffic at its
+//! source address. In `enforce` mode, queries with a client cookie but no
+//! valid server cookie get BADCOOKIE instead of an answer, so spoofed
+//! queries cannot be reflected at a cookie-capable victim. Queries without
+//! any cookie are always answered: most stub resolvers never send one.
+//!
+//! The upstream side lives in the UDP client, which sends cookies to the
+//! upstreams the capability probe found to support them.
+
+use std::hash::Hasher;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use siphasher::sip::SipHasher24;
+
+use crate::db::Database;
+use super::message::{append_opt_record, WireSummary, EDNS_OPTION_COOKIE};
+
+/// Config key for the cookie mode
+pub const CONFIG_KEY_DNS_COOKIES: &str = "dns_cookies";
+
+/// UDP payload size advertised in responses carrying a cookie
+const RESPONSE_UDP_PAYLOAD_SIZE: u16 = 1232;
+/// Server cookies older than this are invalid (RFC 9018, section 4.3)
+const SERVER_COOKIE_MAX_AGE_SECS: i64 = 3600;
+/// Server cookies younger than this are echoed back instead of reissued
+const SERVER_COOKIE_REFRESH_SECS: i64 = 1800;
+/// Tolerated clock skew for server cookies from the future
+const SERVER_COOKIE_MAX_SKEW_SECS: i64 = 300;
+const SERVER_COOKIE_VERSION: u8 = 1;
+/// BADCOOKIE extended RCODE (RFC 7873, section 8)
+const RCODE_BADCOOKIE: u16 = 23;
+
+/// Upstream answers whose cookie did not echo our client cookie
+static UPSTREAM_COOKIE_MISMATCHES: AtomicU64 = AtomicU64::new(0);
+
+/// Count an upstream answer rejected for a foreign client cookie
+pub fn record_upstream_cookie_mismatch() {
+    UPSTREAM_COOKIE_MISMATCHES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// How the UDP listener handles cookies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CookieMode {
+    /// Ignore cookies
+    Off,
+    /// Issue and validate cookies, answer every query
+    #[default]
+    On,
+    /// Answer BADCOOKIE to queries with a client cookie but no valid server cookie
+    Enforce,
+}
+
+impl CookieMode {
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "off" => Some(Self::Off),
+            "on" => Some(Self::On),
+            "enforce" => Some(Self::Enforce),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::On => "on",
+            Self::Enforce => "enforce",
+        }
+    }
+}
+
+/// COOKIE option of a query, once checked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CookieCheck {
+    /// No COOKIE option, or cookies are off
+    Absent,
+    /// Option of an impossible length; answered with FORMERR
+    Malformed,
+    /// Client cookie without a server cookie
+    ClientOnly([u8; 8]),
+    /// Server cookie that was not issued here, or has expired
+    Invalid([u8; 8]),
+    /// Server cookie issued here
+    Valid { client: [u8; 8], server: [u8; 16] },
+}
+
+/// Cookie counters since startup
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CookieStats {
+    pub mode: CookieMode,
+    /// Queries with a valid server cookie
+    pub valid: u64,
+    /// Queries with a client cookie only
+    pub client_only: u64,
+    /// Queries with a server cookie that failed validation
+    pub invalid: u64,
+    /// Queries with a malformed COOKIE option
+    pub malformed: u64,
+    /// BADCOOKIE answers sent in enforce mode
+    pub rejected: u64,
+    /// Upstream answers dropped because they did not echo our client cookie
+    pub upstream_mismatches: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    valid: AtomicU64,
+    client_only: AtomicU64,
+    invalid: AtomicU64,
+    malformed: AtomicU64,
+    rejected: AtomicU64,
+}
+
+/// Server cookie issuance and validation for the UDP listener
+pub struct DnsCookies {
+    db: Option<Arc<Database>>,
+    mode: RwLock<CookieMode>,
+    /// SipHash key; cookies issued before a restart become invalid
+    secret: [u8; 16],
+    counters: Counters,
+}
+
+#[allow(dead_code)]
+impl DnsCookies {
+    pub fn new(db: Option<Arc<Database>>) -> Self {
+        Self {
+            db,
+            mode: RwLock::new(CookieMode::default()),
+            secret: rand::random(),
+            counters: Counters::default(),
+        }
+    }
+
+    /// Load the mode from database
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let mode = db
+            .system_config()
+            .get(CONFIG_KEY_DNS_COOKIES)
+            .await?
+            .and_then(|v| CookieMode::from_str(&v))
+            .unwrap_or_default();
+        self.set_mode(mode);
+        Ok(())
+    }
+
+    /// Persist and apply the mode
+    pub async fn save_mode(&self, mode: CookieMode) -> Result<()> {
+        if let Some(ref db) = self.db {
+            db.system_config()
+                .set(CONFIG_KEY_DNS_COOKIES, mode.as_str())
+                .await?;
+        }
+        self.set_mode(mode);
+        Ok(())
+    }
+
+    pub fn mode(&self) -> CookieMode {
+        *self.mode.read().unwrap()
+    }
+
+    pub fn set_mode(&self, mode: CookieMode) {
+        *self.mode.write().unwrap() = mode;
+    }
+
+    pub fn stats(&self) -> CookieStats {
+        CookieStats {
+            mode: self.mode(),
+            valid: self.counters.valid.load(Ordering::Relaxed),
+            client_only: self.counters.client_only.load(Ordering::Relaxed),
+            invalid: self.counters.invalid.load(Ordering::Relaxed),
+            malformed: self.counters.malformed.load(Ordering::Relaxed),
+            rejected: self.counters.rejected.load(Ordering::Relaxed),
+            upstream_mismatches: UPSTREAM_COOKIE_MISMATCHES.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Check the COOKIE option of an encoded query and count the outcome
+    pub fn check(&self, query: &[u8], client_ip: IpAddr) -> CookieCheck {
+        if self.mode() == CookieMode::Off {
+            return CookieCheck::Absent;
+        }
+        let Some(cookie) = WireSummary::parse(query)
+            .and_then(|s| s.opt)
+            .and_then(|opt| opt.option(EDNS_OPTION_COOKIE).map(<[u8]>::to_vec))
+        else {
+            return CookieCheck::Absent;
+        };
+
+        // 8-byte client cookie, optionally followed by an 8 to 32-byte server cookie
+        if cookie.len() != 8 && !(16..=40).contains(&cookie.len()) {
+            self.counters.malformed.fetch_add(1, Ordering::Relaxed);
+            return CookieCheck::Malformed;
+        }
+        let mut client = [0u8; 8];
+        client.copy_from_slice(&cookie[..8]);
+
+        if cookie.len() == 8 {
+            self.counters.client_only.fetch_add(1, Ordering::Relaxed);
+            return CookieCheck::ClientOnly(client);
+        }
+        match <[u8; 16]>::try_from(&cookie[8..]) {
+            Ok(server) if self.verify(&client, &server, client_ip) => {
+                self.counters.valid.fetch_add(1, Ordering::Relaxed);
+                CookieCheck::Valid { client, server }
+            }
+            _ => {
+                self.counters.invalid.fetch_add(1, Ordering::Relaxed);
+                CookieCheck::Invalid(client)
+            }
+        }
+    }
+
+    /// Whether a query must be answered with BADCOOKIE instead
+    pub fn rejects(&self, check: &CookieCheck) -> bool {
+        self.mode() == CookieMode::Enforce
+            && matches!(check, CookieCheck::ClientOnly(_) | CookieCheck::Invalid(_))
+    }
+
+    /// Add a server cookie to an encoded response
+    ///
+    /// Does nothing unless the query carried a well-formed client cookie.
+    pub fn add_to_response(&self, response: &mut Vec<u8>, check: &CookieCheck, client_ip: IpAddr) {
+        let now = Utc::now().timestamp();
+        let (client, server) = match check {
+            CookieCheck::Valid { client, server }
+                if now - cookie_timestamp(server) < SERVER_COOKIE_REFRESH_SECS =>
+            {
+                (*client, *server)
+            }
+            CookieCheck::Valid { client, .. }
+            | CookieCheck::ClientOnly(client)
+            | CookieCheck::Invalid(client) => (*client, self.server_cookie(client, client_ip, now)),
+            CookieCheck::Absent | CookieCheck::Malformed => return,
+        };
+
+        let mut data = client.to_vec();
+        data.extend_from_slice(&server);
+        append_opt_record(response, RESPONSE_UDP_PAYLOAD_SIZE, false, &[(EDNS_OPTION_COOKIE, &data)]);
+    }
+
+    /// Turn an encoded response into BADCOOKIE carrying a fresh server cookie
+    ///
+    /// The response should carry no records, only the question.
+    pub fn reject(&self, response: &mut Vec<u8>, check: &CookieCheck, client_ip: IpAddr) {
+        let opt_start = response.len();
+        self.add_to_response(response, check, client_ip);
+        if response.len() == opt_start {
+            return;
+        }
+        self.counters.rejected.fetch_add(1, Ordering::Relaxed);
+        // Lower four bits in the header, upper eight in the OPT TTL field
+        response[3] = (response[3] & 0xF0) | (RCODE_BADCOOKIE & 0x0F) as u8;
+        response[opt_start + 5] = (RCODE_BADCOOKIE >> 4) as u8;
+    }
+
+    /// Server cookie: version, reserved, timestamp, hash (RFC 9018)
+    fn server_cookie(&self, client: &[u8; 8], client_ip: IpAddr, now: i64) -> [u8; 16] {
+        let mut cookie = [0u8; 16];
+        cookie[0] = SERVER_COOKIE_VERSION;
+        cookie[4..8].copy_from_slice(&(now as u32).to_be_bytes());
+        let hash = self.hash(client, &cookie[..8], client_ip);
+        cookie[8..].copy_from_slice(&hash);
+        cookie
+    }
+
+    fn verify(&self, client: &[u8; 8], server: &[u8; 16], client_ip: IpAddr) -> bool {
+        let age = Utc::now().timestamp() - cookie_timestamp(server);
+        server[0] == SERVER_COOKIE_VERSION
+            && (-SERVER_COOKIE_MAX_SKEW_SECS..=SERVER_COOKIE_MAX_AGE_SECS).contains(&age)
+            && self.hash(client, &server[..8], client_ip) == server[8..]
+    }
+
+    fn hash(&self, client: &[u8; 8], header: &[u8], client_ip: IpAddr) -> [u8; 8] {
+        let mut hasher = SipHasher24::new_with_key(&self.secret);
+        hasher.write(client);
+        hasher.write(header);
+        match client_ip {
+            IpAddr::V4(ip) => hasher.write(&ip.octets()),
+            IpAddr::V6(ip) => hasher.write(&ip.octets()),
+        }
+        hasher.finish().to_le_bytes()
+    }
+}
+
+impl Default for DnsCookies {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+/// Issue time of a server cookie, in seconds since the epoch
+///
+/// The field holds the low 32 bits; the nearest time to now is assumed.
+fn cookie_timestamp(server: &[u8; 16]) -> i64 {
+    let stamp = u32::from_be_bytes([server[4], server[5], server[6], server[7]]);
+    let now = Utc::now().timestamp();
+    now - (now as u32).wrapping_sub(stamp) as i32 as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::{DnsQuery, RecordType};
+
+    fn query_with_cookie(cookie: Option<&[u8]>) -> Vec<u8> {
+        let mut bytes = DnsQuery::with_id(7, "example.com", RecordType::A).to_bytes().unwrap();
+        let options: Vec<(u16, &[u8])> = cookie.map(|c| (EDNS_OPTION_COOKIE, c)).into_iter().collect();
+        append_opt_record(&mut bytes, 1232, false, &options);
+        bytes
+    }
+
+    fn returned_cookie(response: &[u8]) -> Vec<u8> {
+        let opt = WireSummary::parse(response).unwrap().opt.unwrap();
+        opt.option(EDNS_OPTION_COOKIE).unwrap().to_vec()
+    }
+
+    #[test]
+    fn test_cookie_roundtrip() {
+        let cookies = DnsCookies::default();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let client = [9u8; 8];
+
+        assert_eq!(cookies.check(&query_with_cookie(None), ip), CookieCheck::Absent);
+
+        let check = cookies.check(&query_with_cookie(Some(&client)), ip);
+        assert_eq!(check, CookieCheck::ClientOnly(client));
+        let mut response = DnsQuery::with_id(7, "example.com", RecordType::A).to_bytes().unwrap();
+        cookies.add_to_response(&mut response, &check, ip);
+        let cookie = returned_cookie(&response);
+        assert_eq!(cookie.len(), 24);
+        assert_eq!(cookie[..8], client);
+
+        // The issued cookie validates for this client only
+        assert!(matches!(cookies.check(&query_with_cookie(Some(&cookie)), ip), CookieCheck::Valid { .. }));
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+        assert_eq!(cookies.check(&query_with_cookie(Some(&cookie)), other), CookieCheck::Invalid(client));
+        assert!(matches!(DnsCookies::default().check(&query_with_cookie(Some(&cookie)), ip), CookieCheck::Invalid(_)));
+
+        assert_eq!(cookies.check(&query_with_cookie(Some(&[1, 2, 3])), ip), CookieCheck::Malformed);
+
+        let stats = cookies.stats();
+        assert_eq!((stats.client_only, stats.valid, stats.invalid, stats.malformed), (1, 1, 1, 1));
+    }
+
+    #[test]
+    fn test_expired_cookie_invalid() {
+        let cookies = DnsCookies::default();
+        let ip: IpAddr = "2001:db8::1".parse().unwrap();
+        let client = [3u8; 8];
+        let stale = cookies.server_cookie(&client, ip, Utc::now().timestamp() - SERVER_COOKIE_MAX_AGE_SECS - 10);
+        assert!(!cookies.verify(&client, &stale, ip));
+        let fresh = cookies.server_cookie(&client, ip, Utc::now().timestamp() - 60);
+        assert!(cookies.verify(&client, &fresh, ip));
+    }
+
+    #[test]
+    fn test_enforce_rejects_with_badcookie() {
+        let cookies = DnsCookies::default();
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let check = CookieCheck::ClientOnly([5u8; 8]);
+        assert!(!cookies.rejects(&check));
+
+        cookies.set_mode(CookieMode::Enforce);
+        assert!(cookies.rejects(&check));
+        assert!(!cookies.rejects(&CookieCheck::Absent));
+
+        let mut response = DnsQuery::with_id(7, "example.com", RecordType::A).to_bytes().unwrap();
+        cookies.reject(&mut response, &check, ip);
+        let summary = WireSummary::parse(&response).unwrap();
+        let opt = summary.opt.unwrap();
+        assert_eq!(((opt.extended_rcode as u16) << 4) | summary.rcode as u16, RCODE_BADCOOKIE);
+        assert_eq!(cookies.stats().rejected, 1);
+
+        cookies.set_mode(CookieMode::Off);
+        assert_eq!(cookies.check(&query_with_cookie(Some(&[5u8; 8])), ip), CookieCheck::Absent);
+    }
+}
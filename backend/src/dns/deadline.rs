@@ -0,0 +1,114 @@
+//! Resolution deadline
+//!
+//! One client query can try several upstreams in turn, retry, fall back to
+//! TCP and follow rewrite chains, each step with its own timeout, so the
+//! total can exceed what the client is willing to wait. The deadline bounds
+//! the whole resolution: when it passes, the pipeline is abandoned and the
+//! client gets SERVFAIL, recorded with `answered_by = "deadline"` and counted
+//! in `/api/status`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::db::Database;
+
+/// Config key for the resolution budget in milliseconds
+pub const CONFIG_KEY_RESOLUTION_TIMEOUT_MS: &str = "resolution_timeout_ms";
+
+/// `answered_by` value for queries cut off by the deadline
+pub const DEADLINE_ANSWERED_BY: &str = "deadline";
+
+/// Default resolution budget
+pub const DEFAULT_RESOLUTION_TIMEOUT_MS: u64 = 3000;
+
+/// Deadline counters
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct DeadlineStats {
+    /// Current budget in milliseconds (0 = unlimited)
+    pub timeout_ms: u64,
+    /// Queries answered with SERVFAIL because the budget ran out
+    pub timeouts: u64,
+}
+
+/// End-to-end time budget of a client query
+pub struct ResolutionDeadline {
+    db: Option<Arc<Database>>,
+    timeout_ms: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+#[allow(dead_code)]
+impl ResolutionDeadline {
+    pub fn new(db: Option<Arc<Database>>) -> Self {
+        Self {
+            db,
+            timeout_ms: AtomicU64::new(DEFAULT_RESOLUTION_TIMEOUT_MS),
+            timeouts: AtomicU64::new(0),
+        }
+    }
+
+    /// Load the budget from database
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let timeout_ms = db
+            .system_config()
+            .get(CONFIG_KEY_RESOLUTION_TIMEOUT_MS)
+            .await?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_RESOLUTION_TIMEOUT_MS);
+        self.set_timeout_ms(timeout_ms);
+        Ok(())
+    }
+
+    /// Persist and apply the budget
+    pub async fn save_timeout_ms(&self, timeout_ms: u64) -> Result<()> {
+        if let Some(ref db) = self.db {
+            db.system_config()
+                .set(CONFIG_KEY_RESOLUTION_TIMEOUT_MS, &timeout_ms.to_string())
+                .await?;
+        }
+        self.set_timeout_ms(timeout_ms);
+        Ok(())
+    }
+
+    pub fn timeout_ms(&self) -> u64 {
+        self.timeout_ms.load(Ordering::Relaxed)
+    }
+
+    pub fn set_timeout_ms(&self, timeout_ms: u64) {
+        self.timeout_ms.store(timeout_ms, Ordering::Relaxed);
+    }
+
+    /// Budget for one resolution, `None` when unlimited
+    pub fn budget(&self) -> Option<Duration> {
+        match self.timeout_ms() {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+
+    /// Count a query cut off by the deadline
+    pub fn record_timeout(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> DeadlineStats {
+        DeadlineStats {
+            timeout_ms: self.timeout_ms(),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for ResolutionDeadline {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
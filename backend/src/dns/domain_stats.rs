@@ -0,0 +1,440 @@
+//! Top-domain leaderboard
+//!
+//! Keeps per-domain query counts, block counts and latency over the last
+//! hour, day and week, so the dashboard's "top queried domains" needs no
+//! scan of the query log. Every time bucket holds a Space-Saving sketch of
+//! at most [`SKETCH_CAPACITY`] domains: once a bucket is full, a new domain
+//! takes over the least queried entry and inherits its count. Busy domains
+//! are therefore always tracked, while counts may be overstated by at most
+//! the reported `overcount`.
+//!
+//! The hour window sums twelve five-minute buckets, the day and week
+//! windows sum hourly buckets (covering 23 to 24 hours and 167 to 168 hours
+//! depending on the current hour). Hourly buckets are snapshotted to the
+//! database every few minutes and reloaded on startup; snapshots older than
+//! a week are pruned.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::db::{Database, DomainStatHour};
+use super::name::normalize_name;
+use super::policy_stats::PolicyOutcome;
+use super::resolver::ResolveResult;
+
+/// Most domains tracked per bucket
+pub const SKETCH_CAPACITY: usize = 256;
+/// Time between database snapshots of the hourly buckets
+pub const DOMAIN_STATS_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(300);
+
+const FIVE_MINUTES: i64 = 300;
+const HOUR: i64 = 3600;
+const FIVE_MINUTE_BUCKETS: usize = 12;
+const HOUR_BUCKETS: usize = 168;
+
+/// Time window of the leaderboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LeaderboardWindow {
+    #[serde(rename = "1h")]
+    Hour,
+    #[default]
+    #[serde(rename = "24h")]
+    Day,
+    #[serde(rename = "7d")]
+    Week,
+}
+
+impl LeaderboardWindow {
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "1h" | "hour" => Some(Self::Hour),
+            "24h" | "1d" | "day" => Some(Self::Day),
+            "7d" | "week" => Some(Self::Week),
+            _ => None,
+        }
+    }
+}
+
+/// Leaderboard ordering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderboardSort {
+    #[default]
+    Queries,
+    Blocked,
+    /// Slowest average response time first
+    Latency,
+}
+
+impl LeaderboardSort {
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "queries" => Some(Self::Queries),
+            "blocked" => Some(Self::Blocked),
+            "latency" => Some(Self::Latency),
+            _ => None,
+        }
+    }
+}
+
+/// One domain on the leaderboard
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DomainLeaderboardEntry {
+    pub domain: String,
+    pub queries: u64,
+    pub blocked: u64,
+    /// Average response time in milliseconds, if any query was answered
+    pub avg_response_time: Option<f64>,
+    /// How much `queries` may overstate the true count
+    pub overcount: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Counter {
+    queries: u64,
+    blocked: u64,
+    response_time_total: u64,
+    response_time_samples: u64,
+    overcount: u64,
+}
+
+impl Counter {
+    fn merge(&mut self, other: &Counter) {
+        self.queries += other.queries;
+        self.blocked += other.blocked;
+        self.response_time_total += other.response_time_total;
+        self.response_time_samples += other.response_time_samples;
+        self.overcount += other.overcount;
+    }
+}
+
+/// Space-Saving sketch of the busiest domains
+#[derive(Debug, Clone, Default)]
+struct Sketch {
+    entries: HashMap<String, Counter>,
+}
+
+impl Sketch {
+    fn add(&mut self, domain: &str, blocked: bool, response_time_ms: Option<u64>) {
+        if !self.entries.contains_key(domain) {
+            let mut counter = Counter::default();
+            if self.entries.len() >= SKETCH_CAPACITY {
+                let (victim, min) = self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, c)| c.queries)
+                    .map(|(d, c)| (d.clone(), c.queries))
+                    .expect("sketch is full");
+                self.entries.remove(&victim);
+                counter.queries = min;
+                counter.overcount = min;
+            }
+            self.entries.insert(domain.to_string(), counter);
+        }
+
+        let counter = self.entries.get_mut(domain).expect("entry was just inserted");
+        counter.queries += 1;
+        if blocked {
+            counter.blocked += 1;
+        }
+        if let Some(ms) = response_time_ms {
+            counter.response_time_total += ms;
+            counter.response_time_samples += 1;
+        }
+    }
+}
+
+/// Ring of sketches, one per time step
+#[derive(Debug)]
+struct Ring {
+    buckets: Vec<(i64, Sketch)>,
+}
+
+impl Ring {
+    fn new(len: usize) -> Self {
+        Self {
+            buckets: vec![(-1, Sketch::default()); len],
+        }
+    }
+
+    /// Sketch of step `stamp`, emptied if it still holds an older step
+    fn slot(&mut self, stamp: i64) -> &mut Sketch {
+        let i = stamp.rem_euclid(self.buckets.len() as i64) as usize;
+        let slot = &mut self.buckets[i];
+        if slot.0 != stamp {
+            *slot = (stamp, Sketch::default());
+        }
+        &mut slot.1
+    }
+
+    /// Sketch of step `stamp` if it is held
+    fn get(&self, stamp: i64) -> Option<&Sketch> {
+        let (held, sketch) = &self.buckets[stamp.rem_euclid(self.buckets.len() as i64) as usize];
+        (*held == stamp).then_some(sketch)
+    }
+
+    /// Sketches of the `count` steps up to and including `current`
+    fn recent(&self, current: i64, count: usize) -> impl Iterator<Item = &Sketch> {
+        let oldest = current - count as i64;
+        self.buckets
+            .iter()
+            .filter(move |(stamp, _)| *stamp > oldest && *stamp <= current)
+            .map(|(_, sketch)| sketch)
+    }
+}
+
+struct Buckets {
+    five_minutes: Ring,
+    hours: Ring,
+    /// Hours changed since the last snapshot
+    dirty_hours: BTreeSet<i64>,
+}
+
+/// Rolling per-domain counters for client queries
+pub struct DomainStats {
+    db: Option<Arc<Database>>,
+    buckets: Mutex<Buckets>,
+}
+
+impl DomainStats {
+    pub fn new(db: Option<Arc<Database>>) -> Self {
+        Self {
+            db,
+            buckets: Mutex::new(Buckets {
+                five_minutes: Ring::new(FIVE_MINUTE_BUCKETS),
+                hours: Ring::new(HOUR_BUCKETS),
+                dirty_hours: BTreeSet::new(),
+            }),
+        }
+    }
+
+    /// Restore the hourly buckets of the last week from database
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let current = Utc::now().timestamp().div_euclid(HOUR);
+        let rows = db.domain_stats().list_since(current - HOUR_BUCKETS as i64 + 1).await?;
+        let mut buckets = self.buckets.lock().unwrap();
+        for row in rows {
+            let counter = Counter {
+                queries: row.queries.max(0) as u64,
+                blocked: row.blocked.max(0) as u64,
+                response_time_total: row.response_time_total.max(0) as u64,
+                response_time_samples: row.response_time_samples.max(0) as u64,
+                overcount: row.overcount.max(0) as u64,
+            };
+            buckets.hours.slot(row.hour).entries.insert(row.domain, counter);
+        }
+        Ok(())
+    }
+
+    /// Count a resolved client query
+    pub fn record(&self, query_name: &str, result: &Result<ResolveResult>) {
+        self.record_at(query_name, result.as_ref().ok(), Utc::now().timestamp());
+    }
+
+    fn record_at(&self, query_name: &str, result: Option<&ResolveResult>, now: i64) {
+        let domain = normalize_name(query_name);
+        if domain.is_empty() {
+            return;
+        }
+        let blocked = result
+            .and_then(|r| r.metadata.policy.as_ref())
+            .is_some_and(|p| p.outcome == PolicyOutcome::Blocked);
+        let response_time_ms = result.map(|r| r.metadata.response_time_ms);
+
+        let hour = now.div_euclid(HOUR);
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets
+            .five_minutes
+            .slot(now.div_euclid(FIVE_MINUTES))
+            .add(&domain, blocked, response_time_ms);
+        buckets.hours.slot(hour).add(&domain, blocked, response_time_ms);
+        buckets.dirty_hours.insert(hour);
+    }
+
+    /// The top `limit` domains of a window
+    pub fn top(&self, window: LeaderboardWindow, sort: LeaderboardSort, limit: usize) -> Vec<DomainLeaderboardEntry> {
+        self.top_at(window, sort, limit, Utc::now().timestamp())
+    }
+
+    fn top_at(
+        &self,
+        window: LeaderboardWindow,
+        sort: LeaderboardSort,
+        limit: usize,
+        now: i64,
+    ) -> Vec<DomainLeaderboardEntry> {
+        let mut totals: HashMap<String, Counter> = HashMap::new();
+        {
+            let buckets = self.buckets.lock().unwrap();
+            let sketches: Vec<&Sketch> = match window {
+                LeaderboardWindow::Hour => buckets
+                    .five_minutes
+                    .recent(now.div_euclid(FIVE_MINUTES), FIVE_MINUTE_BUCKETS)
+                    .collect(),
+                LeaderboardWindow::Day => buckets.hours.recent(now.div_euclid(HOUR), 24).collect(),
+                LeaderboardWindow::Week => buckets.hours.recent(now.div_euclid(HOUR), HOUR_BUCKETS).collect(),
+            };
+            for sketch in sketches {
+                for (domain, counter) in &sketch.entries {
+                    totals.entry(domain.clone()).or_default().merge(counter);
+                }
+            }
+        }
+
+        let mut entries: Vec<DomainLeaderboardEntry> = totals
+            .into_iter()
+            .map(|(domain, c)| DomainLeaderboardEntry {
+                domain,
+                queries: c.queries,
+                blocked: c.blocked,
+                avg_response_time: (c.response_time_samples > 0)
+                    .then(|| c.response_time_total as f64 / c.response_time_samples as f64),
+                overcount: c.overcount,
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            let primary = match sort {
+                LeaderboardSort::Queries => b.queries.cmp(&a.queries),
+                LeaderboardSort::Blocked => b.blocked.cmp(&a.blocked),
+                LeaderboardSort::Latency => b
+                    .avg_response_time
+                    .unwrap_or(0.0)
+                    .total_cmp(&a.avg_response_time.unwrap_or(0.0)),
+            };
+            primary
+                .then_with(|| b.queries.cmp(&a.queries))
+                .then_with(|| a.domain.cmp(&b.domain))
+        });
+        if sort == LeaderboardSort::Blocked {
+            entries.retain(|e| e.blocked > 0);
+        }
+        entries.truncate(limit);
+        entries
+    }
+
+    /// Write changed hourly buckets to database and prune old snapshots
+    pub async fn snapshot(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let current = Utc::now().timestamp().div_euclid(HOUR);
+        let pending: Vec<(i64, Vec<DomainStatHour>)> = {
+            let mut buckets = self.buckets.lock().unwrap();
+            let dirty = std::mem::take(&mut buckets.dirty_hours);
+            dirty
+                .into_iter()
+                .filter_map(|hour| Some((hour, Self::rows(hour, buckets.hours.get(hour)?))))
+                .collect()
+        };
+
+        let repo = db.domain_stats();
+        for (i, (hour, rows)) in pending.iter().enumerate() {
+            if let Err(e) = repo.replace_hour(*hour, rows).await {
+                // Retry the unwritten hours with the next snapshot
+                let mut buckets = self.buckets.lock().unwrap();
+                buckets.dirty_hours.extend(pending[i..].iter().map(|(hour, _)| *hour));
+                return Err(e);
+            }
+        }
+        repo.delete_before(current - HOUR_BUCKETS as i64 + 1).await?;
+        Ok(())
+    }
+
+    fn rows(hour: i64, sketch: &Sketch) -> Vec<DomainStatHour> {
+        sketch
+            .entries
+            .iter()
+            .map(|(domain, c)| DomainStatHour {
+                hour,
+                domain: domain.clone(),
+                queries: c.queries as i64,
+                blocked: c.blocked as i64,
+                response_time_total: c.response_time_total as i64,
+                response_time_samples: c.response_time_samples as i64,
+                overcount: c.overcount as i64,
+            })
+            .collect()
+    }
+}
+
+impl Default for DomainStats {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::policy_stats::PolicyMatch;
+    use crate::dns::resolver::QueryMetadata;
+    use crate::dns::rewrite::RewriteAction;
+    use crate::dns::DnsResponse;
+
+    fn result(blocked: bool, response_time_ms: u64) -> ResolveResult {
+        ResolveResult {
+            response: DnsResponse::nxdomain(1),
+            metadata: QueryMetadata {
+                policy: blocked.then(|| PolicyMatch::rewrite(1, &RewriteAction::Block)),
+                response_time_ms,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_leaderboard_windows() {
+        let stats = DomainStats::default();
+        let now = 1_700_000_000;
+        for _ in 0..3 {
+            stats.record_at("Example.COM.", Some(&result(false, 10)), now);
+        }
+        stats.record_at("ads.example.net", Some(&result(true, 0)), now);
+
+        let top = stats.top_at(LeaderboardWindow::Hour, LeaderboardSort::Queries, 10, now);
+        assert_eq!(top[0].domain, "example.com");
+        assert_eq!(top[0].queries, 3);
+        assert_eq!(top[0].avg_response_time, Some(10.0));
+        assert_eq!(top[1].blocked, 1);
+
+        // Two hours later the hour window only holds the second block
+        let later = now + 2 * HOUR;
+        stats.record_at("ads.example.net", Some(&result(true, 2)), later);
+        let top = stats.top_at(LeaderboardWindow::Hour, LeaderboardSort::Queries, 10, later);
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].avg_response_time, Some(2.0));
+        let top = stats.top_at(LeaderboardWindow::Day, LeaderboardSort::Blocked, 10, later);
+        assert_eq!(top.len(), 1);
+        assert_eq!((top[0].domain.as_str(), top[0].blocked), ("ads.example.net", 2));
+
+        // A week later nothing is left
+        let top = stats.top_at(LeaderboardWindow::Week, LeaderboardSort::Queries, 10, now + 8 * 24 * HOUR);
+        assert!(top.is_empty());
+    }
+
+    #[test]
+    fn test_sketch_keeps_heavy_hitters() {
+        let mut sketch = Sketch::default();
+        for i in 0..SKETCH_CAPACITY * 4 {
+            sketch.add("busy.example.com", false, None);
+            sketch.add(&format!("rare{}.example.com", i), false, None);
+        }
+        assert_eq!(sketch.entries.len(), SKETCH_CAPACITY);
+        let busy = sketch.entries["busy.example.com"];
+        assert_eq!(busy.queries, (SKETCH_CAPACITY * 4) as u64);
+        assert_eq!(busy.overcount, 0);
+        assert!(sketch.entries.values().any(|c| c.overcount > 0));
+    }
+}
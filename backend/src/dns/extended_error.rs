@@ -0,0 +1,160 @@
+//! Extended DNS Errors (RFC 8914)
+//!
+//! Answers that are not a normal resolution carry an EDE option saying
+//! why: SERVFAIL when the upstreams failed or timed out, NXDOMAIN or
+//! REFUSED when policy blocked the name, REFUSED when failing closed or
+//! when a forwarding loop was detected. The option is only added for
+//! clients that sent EDNS. Extended errors in upstream answers, such as
+//! DNSSEC Bogus from a validating upstream, are passed on unchanged.
+//!
+//! The same reason is stored with the query log entry (`extended_error`),
+//! so the logs page shows the cause instead of a bare SERVFAIL.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::message::WireSummary;
+
+/// EDNS option code for extended errors
+pub const EDNS_OPTION_EDE: u16 = 15;
+
+/// Extended error info code with optional extra text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtendedError {
+    /// INFO-CODE
+    pub code: u16,
+    /// EXTRA-TEXT
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+impl ExtendedError {
+    pub const OTHER: u16 = 0;
+    #[allow(dead_code)]
+    pub const DNSSEC_BOGUS: u16 = 6;
+    pub const BLOCKED: u16 = 15;
+    pub const PROHIBITED: u16 = 18;
+    pub const NOT_AUTHORITATIVE: u16 = 20;
+    pub const NO_REACHABLE_AUTHORITY: u16 = 22;
+    pub const NETWORK_ERROR: u16 = 23;
+
+    pub fn new(code: u16, text: impl Into<String>) -> Self {
+        Self {
+            code,
+            text: Some(text.into()),
+        }
+    }
+
+    /// Reason for a query whose upstream resolution failed
+    pub fn for_failure(error: &anyhow::Error) -> Self {
+        let message = format!("{:#}", error).to_lowercase();
+        if message.contains("no healthy upstream") {
+            Self::new(Self::NO_REACHABLE_AUTHORITY, "no healthy upstream servers")
+        } else if message.contains("timeout") || message.contains("timed out") {
+            Self::new(Self::NO_REACHABLE_AUTHORITY, "upstream servers timed out")
+        } else if message.contains("query limit reached") {
+            Self::new(Self::OTHER, "upstream query limit reached")
+        } else if message.contains("rewrite depth") {
+            Self::new(Self::OTHER, "circular rewrite rules")
+        } else {
+            Self::new(Self::NETWORK_ERROR, "upstream servers failed")
+        }
+    }
+
+    /// Extended error of an encoded message
+    pub fn from_wire(bytes: &[u8]) -> Option<Self> {
+        Self::decode(WireSummary::parse(bytes)?.opt?.option(EDNS_OPTION_EDE)?)
+    }
+
+    /// Option data: INFO-CODE followed by EXTRA-TEXT
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = self.code.to_be_bytes().to_vec();
+        if let Some(ref text) = self.text {
+            data.extend_from_slice(text.as_bytes());
+        }
+        data
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let code = u16::from_be_bytes([*data.first()?, *data.get(1)?]);
+        let text = String::from_utf8_lossy(&data[2..]).trim_end_matches('\0').to_string();
+        Some(Self {
+            code,
+            text: (!text.is_empty()).then_some(text),
+        })
+    }
+
+    /// Purpose of the info code as named in the IANA registry
+    pub fn name(&self) -> &'static str {
+        match self.code {
+            0 => "Other Error",
+            1 => "Unsupported DNSKEY Algorithm",
+            2 => "Unsupported DS Digest Type",
+            3 => "Stale Answer",
+            4 => "Forged Answer",
+            5 => "DNSSEC Indeterminate",
+            6 => "DNSSEC Bogus",
+            7 => "Signature Expired",
+            8 => "Signature Not Yet Valid",
+            9 => "DNSKEY Missing",
+            10 => "RRSIGs Missing",
+            11 => "No Zone Key Bit Set",
+            12 => "NSEC Missing",
+            13 => "Cached Error",
+            14 => "Not Ready",
+            15 => "Blocked",
+            16 => "Censored",
+            17 => "Filtered",
+            18 => "Prohibited",
+            19 => "Stale NXDomain Answer",
+            20 => "Not Authoritative",
+            21 => "Not Supported",
+            22 => "No Reachable Authority",
+            23 => "Network Error",
+            24 => "Invalid Data",
+            _ => "Unknown",
+        }
+    }
+}
+
+impl fmt::Display for ExtendedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name(), self.code)?;
+        if let Some(ref text) = self.text {
+            write!(f, ": {}", text)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_encode_decode() {
+        let error = ExtendedError::new(ExtendedError::BLOCKED, "blocked by rewrite rule 3");
+        assert_eq!(ExtendedError::decode(&error.encode()), Some(error.clone()));
+        assert_eq!(error.to_string(), "Blocked (15): blocked by rewrite rule 3");
+
+        let bare = ExtendedError::decode(&[0, 6]).unwrap();
+        assert_eq!((bare.code, bare.text), (ExtendedError::DNSSEC_BOGUS, None));
+        assert!(ExtendedError::decode(&[0]).is_none());
+    }
+
+    #[test]
+    fn test_failure_reasons() {
+        let reason = |e: anyhow::Error| ExtendedError::for_failure(&e);
+        assert_eq!(
+            reason(anyhow!("All upstream servers failed: UDP query timeout after 5s")),
+            ExtendedError::new(ExtendedError::NO_REACHABLE_AUTHORITY, "upstream servers timed out")
+        );
+        assert_eq!(
+            reason(anyhow!("No healthy upstream servers available")).text.as_deref(),
+            Some("no healthy upstream servers")
+        );
+        assert_eq!(reason(anyhow!("connection refused")).code, ExtendedError::NETWORK_ERROR);
+    }
+}
@@ -0,0 +1,264 @@
+//! Fail-open / fail-closed policy
+//!
+//! Rewrite rules and local records come from the database. When the rewrite
+//! rules fail to load, or a local record lookup has to go to the database
+//! and the database does not answer, the resolver cannot apply the
+//! configured policy. The `fail_policy` setting decides what happens then:
+//!
+//! - `failopen` (default): resolve normally, without rewrites or local
+//!   records, so clients keep working while filtering is off.
+//! - `failclosed`: answer from the cache and refuse every other query, so
+//!   nothing resolves that the policy might have blocked.
+//!
+//! The degraded state and its counters are part of `/api/status`, and an
+//! alert is sent when it starts and ends.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::db::Database;
+
+/// Config key for the policy
+pub const CONFIG_KEY_FAIL_POLICY: &str = "fail_policy";
+
+/// `answered_by` value for queries refused by the fail-closed policy
+pub const FAIL_CLOSED_ANSWERED_BY: &str = "fail_closed";
+
+/// What to do while policy data is unavailable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FailMode {
+    /// Resolve without rewrites and local records
+    #[default]
+    #[serde(rename = "failopen")]
+    Open,
+    /// Answer from the cache, refuse everything else
+    #[serde(rename = "failclosed")]
+    Closed,
+}
+
+impl FailMode {
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "failopen" => Some(Self::Open),
+            "failclosed" => Some(Self::Closed),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Open => "failopen",
+            Self::Closed => "failclosed",
+        }
+    }
+}
+
+/// Policy data that could not be loaded
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyDataFailure {
+    /// First failure of the current outage
+    pub since: DateTime<Utc>,
+    pub error: String,
+}
+
+impl PolicyDataFailure {
+    /// Note a failure in `slot`, keeping the start of an ongoing outage;
+    /// returns true if this started one
+    pub fn record(slot: &Mutex<Option<Self>>, error: &str) -> bool {
+        let mut slot = slot.lock().unwrap();
+        let started = slot.is_none();
+        let since = slot.as_ref().map_or_else(Utc::now, |f| f.since);
+        *slot = Some(Self {
+            since,
+            error: error.to_string(),
+        });
+        started
+    }
+}
+
+/// Fail policy state
+#[derive(Debug, Clone, Serialize)]
+pub struct FailPolicyStatus {
+    pub mode: FailMode,
+    /// Whether policy data is currently unavailable
+    pub degraded: bool,
+    pub degraded_since: Option<DateTime<Utc>>,
+    /// Last failure to load the rewrite rules
+    pub rewrite_rules: Option<PolicyDataFailure>,
+    /// Last failure to look up local records in the database
+    pub local_records: Option<PolicyDataFailure>,
+    /// Queries resolved without rewrites or local records (fail open)
+    pub bypassed: u64,
+    /// Queries refused (fail closed)
+    pub refused: u64,
+    /// Queries answered from the cache while failing closed
+    pub served_from_cache: u64,
+}
+
+/// Policy applied by the resolver while policy data is unavailable
+pub struct FailPolicy {
+    db: Option<Arc<Database>>,
+    closed: AtomicBool,
+    /// Fast check for `lookup_failure`
+    lookup_failing: AtomicBool,
+    lookup_failure: Mutex<Option<PolicyDataFailure>>,
+    bypassed: AtomicU64,
+    refused: AtomicU64,
+    served_from_cache: AtomicU64,
+}
+
+#[allow(dead_code)]
+impl FailPolicy {
+    pub fn new(db: Option<Arc<Database>>) -> Self {
+        Self {
+            db,
+            closed: AtomicBool::new(false),
+            lookup_failing: AtomicBool::new(false),
+            lookup_failure: Mutex::new(None),
+            bypassed: AtomicU64::new(0),
+            refused: AtomicU64::new(0),
+            served_from_cache: AtomicU64::new(0),
+        }
+    }
+
+    /// Load the setting from database
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let mode = db
+            .system_config()
+            .get(CONFIG_KEY_FAIL_POLICY)
+            .await?
+            .and_then(|v| FailMode::from_str(&v))
+            .unwrap_or_default();
+        self.set_mode(mode);
+        Ok(())
+    }
+
+    /// Persist and apply the setting
+    pub async fn save_mode(&self, mode: FailMode) -> Result<()> {
+        if let Some(ref db) = self.db {
+            db.system_config().set(CONFIG_KEY_FAIL_POLICY, mode.as_str()).await?;
+        }
+        self.set_mode(mode);
+        Ok(())
+    }
+
+    pub fn mode(&self) -> FailMode {
+        if self.closed.load(Ordering::Relaxed) {
+            FailMode::Closed
+        } else {
+            FailMode::Open
+        }
+    }
+
+    pub fn set_mode(&self, mode: FailMode) {
+        self.closed.store(mode == FailMode::Closed, Ordering::Relaxed);
+    }
+
+    /// Note a failed local record lookup
+    pub fn record_lookup_failure(&self, error: &str) {
+        self.lookup_failing.store(true, Ordering::Relaxed);
+        if PolicyDataFailure::record(&self.lookup_failure, error) {
+            tracing::warn!(
+                "Local records are unavailable ({}); applying the {} policy",
+                error,
+                self.mode().as_str()
+            );
+        }
+    }
+
+    /// Note that local records could be looked up again
+    pub fn record_lookup_success(&self) {
+        if self.lookup_failing.swap(false, Ordering::Relaxed) {
+            self.lookup_failure.lock().unwrap().take();
+            tracing::info!("Local records are available again");
+        }
+    }
+
+    pub fn record_bypass(&self) {
+        self.bypassed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_refused(&self) {
+        self.refused.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_served_from_cache(&self) {
+        self.served_from_cache.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Current state; `rewrite_rules` is the rewrite engine's load failure
+    pub fn status(&self, rewrite_rules: Option<PolicyDataFailure>) -> FailPolicyStatus {
+        let local_records = self.lookup_failure.lock().unwrap().clone();
+        let degraded_since = [&rewrite_rules, &local_records]
+            .into_iter()
+            .flatten()
+            .map(|f| f.since)
+            .min();
+        FailPolicyStatus {
+            mode: self.mode(),
+            degraded: degraded_since.is_some(),
+            degraded_since,
+            rewrite_rules,
+            local_records,
+            bypassed: self.bypassed.load(Ordering::Relaxed),
+            refused: self.refused.load(Ordering::Relaxed),
+            served_from_cache: self.served_from_cache.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for FailPolicy {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_round_trip() {
+        for mode in [FailMode::Open, FailMode::Closed] {
+            assert_eq!(FailMode::from_str(mode.as_str()), Some(mode));
+        }
+        assert_eq!(FailMode::from_str("FailClosed"), Some(FailMode::Closed));
+        assert_eq!(FailMode::from_str("closed"), None);
+        assert_eq!(serde_json::to_string(&FailMode::Closed).unwrap(), "\"failclosed\"");
+    }
+
+    #[test]
+    fn test_degraded_state() {
+        let policy = FailPolicy::new(None);
+        assert!(!policy.status(None).degraded);
+
+        policy.record_lookup_failure("database is locked");
+        let first = policy.status(None).local_records.unwrap();
+        policy.record_lookup_failure("disk I/O error");
+        let status = policy.status(None);
+        assert!(status.degraded);
+        let failure = status.local_records.unwrap();
+        assert_eq!(failure.since, first.since);
+        assert_eq!(failure.error, "disk I/O error");
+
+        // The earliest failure starts the outage
+        let rewrite = PolicyDataFailure {
+            since: first.since - chrono::Duration::seconds(30),
+            error: "no such table".to_string(),
+        };
+        assert_eq!(policy.status(Some(rewrite.clone())).degraded_since, Some(rewrite.since));
+
+        policy.record_lookup_success();
+        assert!(!policy.status(None).degraded);
+        assert!(policy.status(Some(rewrite)).degraded);
+    }
+}
@@ -0,0 +1,186 @@
+//! In-memory index of local DNS records
+//!
+//! Local records used to be looked up with a SQL query on every resolution.
+//! The index holds every enabled record keyed by its normalized name and
+//! answers with the same rules as `DnsRecordRepository::match_for_tenant`:
+//! the exact name first, then the closest wildcard, with a tenant's own
+//! records shadowing global ones. Expired records are skipped at lookup
+//! time, so they stop answering without a rebuild. The index is rebuilt
+//! whenever records change; until it has been loaded, or after a failed
+//! reload, lookups fall back to the database.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use chrono::Utc;
+
+use crate::db::repository::{owner_match, record_name_candidates};
+use crate::db::{is_expired, Database, DnsRecord};
+use super::name::normalize_name;
+
+/// Enabled local records by normalized name
+pub struct LocalRecordIndex {
+    db: Option<Arc<Database>>,
+    /// `None` until loaded, so lookups go to the database
+    records: RwLock<Option<HashMap<String, Vec<DnsRecord>>>>,
+}
+
+#[allow(dead_code)]
+impl LocalRecordIndex {
+    pub fn new(db: Option<Arc<Database>>) -> Self {
+        Self {
+            db,
+            records: RwLock::new(None),
+        }
+    }
+
+    /// Rebuild the index from the database, returning the record count
+    ///
+    /// On failure the index is dropped so lookups fall back to the
+    /// database instead of answering from stale records.
+    pub async fn reload(&self) -> Result<usize> {
+        let Some(ref db) = self.db else {
+            return Ok(0);
+        };
+
+        match db.dns_records().list_enabled().await {
+            Ok(records) => Ok(self.set_records(records)),
+            Err(e) => {
+                *self.records.write().unwrap() = None;
+                Err(e)
+            }
+        }
+    }
+
+    /// Replace the indexed records, returning the count of active ones
+    pub fn set_records(&self, records: Vec<DnsRecord>) -> usize {
+        let now = Utc::now();
+        let mut index: HashMap<String, Vec<DnsRecord>> = HashMap::new();
+        let mut count = 0;
+        for record in records.into_iter().filter(|r| r.enabled && !is_expired(r.expires_at, now)) {
+            let name = normalize_name(&record.name);
+            index.entry(name).or_default().push(record);
+            count += 1;
+        }
+        *self.records.write().unwrap() = Some(index);
+        count
+    }
+
+    /// Whether lookups are answered from memory
+    pub fn is_loaded(&self) -> bool {
+        self.records.read().unwrap().is_some()
+    }
+
+    /// Number of indexed records
+    pub fn count(&self) -> usize {
+        self.records
+            .read()
+            .unwrap()
+            .as_ref()
+            .map_or(0, |index| index.values().map(Vec::len).sum())
+    }
+
+    /// Records of `record_type` answering `name` in a tenant's view
+    ///
+    /// Returns `None` when the index is not loaded and the caller has to
+    /// ask the database.
+    pub fn lookup(&self, name: &str, record_type: &str, tenant_id: Option<i64>) -> Option<Vec<DnsRecord>> {
+        let guard = self.records.read().unwrap();
+        let index = guard.as_ref()?;
+        let now = Utc::now();
+
+        for candidate in record_name_candidates(name) {
+            let Some(records) = index.get(&candidate) else {
+                continue;
+            };
+            let owned: Vec<&DnsRecord> = records
+                .iter()
+                .filter(|r| r.tenant_id.is_none() || r.tenant_id == tenant_id)
+                .filter(|r| !is_expired(r.expires_at, now))
+                .collect();
+            if !owned.is_empty() {
+                return Some(owner_match(candidate, owned, record_type, tenant_id).records);
+            }
+        }
+        Some(Vec::new())
+    }
+}
+
+impl Default for LocalRecordIndex {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Tags;
+    use chrono::Utc;
+
+    fn record(name: &str, record_type: &str, value: &str, tenant_id: Option<i64>) -> DnsRecord {
+        DnsRecord {
+            id: 0,
+            name: name.to_string(),
+            record_type: record_type.to_string(),
+            value: value.to_string(),
+            ttl: 300,
+            priority: 0,
+            enabled: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            tenant_id,
+            description: None,
+            tags: Tags::default(),
+            group_id: None,
+            expires_at: None,
+        }
+    }
+
+    #[test]
+    fn test_lookup_matches_like_the_database() {
+        let index = LocalRecordIndex::new(None);
+        assert!(index.lookup("host.example.com", "A", None).is_none());
+
+        let mut disabled = record("off.example.com", "A", "10.0.0.9", None);
+        disabled.enabled = false;
+        let mut expired = record("old.example.com", "A", "10.0.0.8", None);
+        expired.expires_at = Some(Utc::now() - chrono::Duration::minutes(1));
+        let mut temporary = record("tmp.example.com", "A", "10.0.0.7", None);
+        temporary.expires_at = Some(Utc::now() + chrono::Duration::hours(1));
+        let count = index.set_records(vec![
+            expired,
+            temporary,
+            record("*.example.com", "A", "10.0.0.1", None),
+            record("Host.Example.com.", "A", "10.0.0.2", None),
+            record("txt.example.com", "TXT", "explicit", None),
+            record("*.example.com", "A", "10.1.0.1", Some(7)),
+            disabled,
+        ]);
+        assert_eq!(count, 5);
+        assert_eq!(index.count(), 5);
+
+        let values = |name: &str, record_type: &str, tenant_id: Option<i64>| {
+            index
+                .lookup(name, record_type, tenant_id)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.value)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(values("host.example.com", "A", None), vec!["10.0.0.2"]);
+        assert_eq!(values("x.y.example.com", "A", None), vec!["10.0.0.1"]);
+        // An explicit name of another type overrides the wildcard
+        assert!(values("txt.example.com", "A", None).is_empty());
+        // A tenant's records shadow the global ones under the same name
+        assert_eq!(values("x.example.com", "A", Some(7)), vec!["10.1.0.1"]);
+        assert_eq!(values("x.example.com", "A", Some(8)), vec!["10.0.0.1"]);
+        // Disabled records are not indexed
+        assert_eq!(values("off.example.com", "A", None), vec!["10.0.0.1"]);
+        // Expired records are not either, unexpired ones answer as usual
+        assert_eq!(values("old.example.com", "A", None), vec!["10.0.0.1"]);
+        assert_eq!(values("tmp.example.com", "A", None), vec!["10.0.0.7"]);
+        assert!(values("example.org", "A", None).is_empty());
+    }
+}
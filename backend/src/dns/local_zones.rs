@@ -0,0 +1,189 @@
+//! Private reverse zones (RFC 6303)
+//!
+//! Reverse lookups of private addresses (10/8, 172.16/12, 192.168/16 and
+//! fd00::/8) mean nothing on the public internet, yet they are forwarded
+//! upstream and come back as slow NXDOMAINs. With the setting enabled these
+//! zones are answered locally as RFC 6303 local zones: PTR names without a
+//! local record get NXDOMAIN with the zone SOA in the authority section, and
+//! the zone apex answers its own SOA and NS. Local PTR records still take
+//! precedence since they are checked earlier in the pipeline.
+//!
+//! Off by default, because many LAN routers answer these names themselves.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::db::Database;
+use super::message::{DnsQuery, DnsRecordData, DnsResponse, RecordType};
+use super::name::normalize_name;
+
+/// Config key for answering private reverse zones locally
+pub const CONFIG_KEY_PRIVATE_REVERSE_ZONES: &str = "private_reverse_zones";
+
+/// `answered_by` value for queries answered from a local zone
+pub const LOCAL_ZONE_ANSWERED_BY: &str = "local_zone";
+
+/// TTL of the zone records and of negative answers (SOA minimum)
+const LOCAL_ZONE_TTL: u32 = 10800;
+
+/// Private reverse zone containing a name, None for any other name
+pub fn private_reverse_zone(name: &str) -> Option<String> {
+    let name = normalize_name(name);
+    let labels: Vec<&str> = name.rsplit('.').collect();
+    let zone = match labels.as_slice() {
+        ["arpa", "in-addr", "10", ..] => "10.in-addr.arpa".to_string(),
+        ["arpa", "in-addr", "172", second, ..] => {
+            let second: u8 = second.parse().ok()?;
+            if !(16..=31).contains(&second) {
+                return None;
+            }
+            format!("{}.172.in-addr.arpa", second)
+        }
+        ["arpa", "in-addr", "192", "168", ..] => "168.192.in-addr.arpa".to_string(),
+        ["arpa", "ip6", "f", "d", ..] => "d.f.ip6.arpa".to_string(),
+        _ => return None,
+    };
+    Some(zone)
+}
+
+/// Local answer for a name inside `zone`
+fn zone_response(query: &DnsQuery, zone: &str) -> DnsResponse {
+    let soa = DnsRecordData {
+        name: zone.to_string(),
+        record_type: RecordType::SOA,
+        value: format!("{} nobody.invalid 1 3600 1200 604800 {}", zone, LOCAL_ZONE_TTL),
+        ttl: LOCAL_ZONE_TTL,
+        priority: None,
+        txt: None,
+    };
+
+    let mut response = if normalize_name(&query.name) == zone {
+        let mut response = DnsResponse::new(query.id);
+        match query.record_type {
+            RecordType::SOA => response.add_answer(soa),
+            RecordType::NS => response.add_answer(DnsRecordData::ns(zone, zone, LOCAL_ZONE_TTL)),
+            // NODATA
+            _ => response.authority.push(soa),
+        }
+        response
+    } else {
+        let mut response = DnsResponse::nxdomain(query.id);
+        response.authority.push(soa);
+        response
+    };
+    response.authoritative = true;
+    response
+}
+
+/// Local answers for private reverse zones
+pub struct PrivateReverseZones {
+    db: Option<Arc<Database>>,
+    enabled: AtomicBool,
+}
+
+#[allow(dead_code)]
+impl PrivateReverseZones {
+    pub fn new(db: Option<Arc<Database>>) -> Self {
+        Self {
+            db,
+            enabled: AtomicBool::new(false),
+        }
+    }
+
+    /// Load the setting from database
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let enabled = db
+            .system_config()
+            .get(CONFIG_KEY_PRIVATE_REVERSE_ZONES)
+            .await?
+            .is_some_and(|v| v == "true");
+        self.set_enabled(enabled);
+        Ok(())
+    }
+
+    /// Persist and apply the setting
+    pub async fn save_enabled(&self, enabled: bool) -> Result<()> {
+        if let Some(ref db) = self.db {
+            db.system_config()
+                .set(CONFIG_KEY_PRIVATE_REVERSE_ZONES, if enabled { "true" } else { "false" })
+                .await?;
+        }
+        self.set_enabled(enabled);
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Local answer for a query, None when disabled or outside the zones
+    pub fn answer(&self, query: &DnsQuery) -> Option<DnsResponse> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let zone = private_reverse_zone(&query.name)?;
+        Some(zone_response(query, &zone))
+    }
+}
+
+impl Default for PrivateReverseZones {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::DnsResponseCode;
+
+    #[test]
+    fn test_private_reverse_zone() {
+        assert_eq!(private_reverse_zone("1.0.0.10.in-addr.arpa.").as_deref(), Some("10.in-addr.arpa"));
+        assert_eq!(private_reverse_zone("5.4.20.172.IN-ADDR.ARPA").as_deref(), Some("20.172.in-addr.arpa"));
+        assert_eq!(private_reverse_zone("1.1.168.192.in-addr.arpa").as_deref(), Some("168.192.in-addr.arpa"));
+        assert_eq!(private_reverse_zone("d.f.ip6.arpa").as_deref(), Some("d.f.ip6.arpa"));
+        assert!(private_reverse_zone("1.0.32.172.in-addr.arpa").is_none());
+        assert!(private_reverse_zone("1.1.1.1.in-addr.arpa").is_none());
+        assert!(private_reverse_zone("c.f.ip6.arpa").is_none());
+        assert!(private_reverse_zone("in-addr.arpa").is_none());
+        assert!(private_reverse_zone("10.example.com").is_none());
+    }
+
+    #[test]
+    fn test_answers() {
+        let zones = PrivateReverseZones::default();
+        let ptr = DnsQuery::with_id(7, "4.3.2.10.in-addr.arpa", RecordType::PTR);
+        assert!(zones.answer(&ptr).is_none());
+        zones.set_enabled(true);
+
+        let response = zones.answer(&ptr).unwrap();
+        assert_eq!(response.id, 7);
+        assert_eq!(response.response_code, DnsResponseCode::NxDomain);
+        assert!(response.authoritative);
+        assert_eq!(response.authority[0].name, "10.in-addr.arpa");
+        assert_eq!(response.authority[0].record_type, RecordType::SOA);
+
+        let apex = DnsQuery::with_id(1, "168.192.in-addr.arpa", RecordType::NS);
+        let response = zones.answer(&apex).unwrap();
+        assert_eq!(response.response_code, DnsResponseCode::NoError);
+        assert_eq!(response.answers[0].value, "168.192.in-addr.arpa");
+
+        let nodata = DnsQuery::with_id(1, "168.192.in-addr.arpa", RecordType::A);
+        let response = zones.answer(&nodata).unwrap();
+        assert!(response.answers.is_empty());
+        assert_eq!(response.authority.len(), 1);
+
+        assert!(zones.answer(&DnsQuery::new("8.8.8.8.in-addr.arpa", RecordType::PTR)).is_none());
+    }
+}
@@ -0,0 +1,249 @@
+//! Query log sampling
+//!
+//! At thousands of queries per second, writing every query to the log costs
+//! more than it is worth. In `sample` mode one query in N is logged; in
+//! `notable` mode only errors, blocked queries and slow queries are. Notable
+//! queries are logged in both modes. Each row records the rate it was
+//! sampled at (`sample_rate`), so summing it instead of counting rows
+//! estimates the real query counts.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::db::Database;
+use super::message::DnsResponseCode;
+use super::resolver::ResolveResult;
+
+/// Config key for the sampling mode
+pub const CONFIG_KEY_QUERY_LOG_SAMPLING: &str = "query_log_sampling";
+/// Config key for N in "log 1 in N"
+pub const CONFIG_KEY_QUERY_LOG_SAMPLE_RATE: &str = "query_log_sample_rate";
+/// Config key for the latency at which a query is always logged
+pub const CONFIG_KEY_QUERY_LOG_SLOW_MS: &str = "query_log_slow_ms";
+
+/// Which queries are written to the query log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplingMode {
+    /// Every query
+    #[default]
+    All,
+    /// One query in N, plus every notable query
+    Sample,
+    /// Only errors, blocked and slow queries
+    Notable,
+}
+
+impl SamplingMode {
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "all" => Some(Self::All),
+            "sample" => Some(Self::Sample),
+            "notable" => Some(Self::Notable),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Sample => "sample",
+            Self::Notable => "notable",
+        }
+    }
+}
+
+/// Query log sampling settings
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SamplingSettings {
+    pub mode: SamplingMode,
+    /// Log one query in this many in `sample` mode
+    pub rate: u32,
+    /// Queries taking at least this long are always logged (0 = never)
+    pub slow_ms: u64,
+}
+
+impl Default for SamplingSettings {
+    fn default() -> Self {
+        Self {
+            mode: SamplingMode::All,
+            rate: 100,
+            slow_ms: 500,
+        }
+    }
+}
+
+/// Decides which resolved queries are logged
+pub struct QueryLogSampler {
+    db: Option<Arc<Database>>,
+    settings: RwLock<SamplingSettings>,
+    /// Queries seen in `sample` mode, to pick every Nth
+    seen: AtomicU64,
+}
+
+#[allow(dead_code)]
+impl QueryLogSampler {
+    pub fn new(db: Option<Arc<Database>>) -> Self {
+        Self {
+            db,
+            settings: RwLock::new(SamplingSettings::default()),
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    /// Load settings from database
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let config = db.system_config();
+        let defaults = SamplingSettings::default();
+        let settings = SamplingSettings {
+            mode: config
+                .get(CONFIG_KEY_QUERY_LOG_SAMPLING)
+                .await?
+                .and_then(|v| SamplingMode::from_str(&v))
+                .unwrap_or_default(),
+            rate: config
+                .get(CONFIG_KEY_QUERY_LOG_SAMPLE_RATE)
+                .await?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.rate),
+            slow_ms: config
+                .get(CONFIG_KEY_QUERY_LOG_SLOW_MS)
+                .await?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.slow_ms),
+        };
+
+        self.set_settings(settings);
+        Ok(())
+    }
+
+    /// Persist and apply settings
+    pub async fn save_settings(&self, settings: SamplingSettings) -> Result<()> {
+        if let Some(ref db) = self.db {
+            let config = db.system_config();
+            config
+                .set(CONFIG_KEY_QUERY_LOG_SAMPLING, settings.mode.as_str())
+                .await?;
+            config
+                .set(CONFIG_KEY_QUERY_LOG_SAMPLE_RATE, &settings.rate.to_string())
+                .await?;
+            config
+                .set(CONFIG_KEY_QUERY_LOG_SLOW_MS, &settings.slow_ms.to_string())
+                .await?;
+        }
+        self.set_settings(settings);
+        Ok(())
+    }
+
+    pub fn settings(&self) -> SamplingSettings {
+        *self.settings.read().unwrap()
+    }
+
+    pub fn set_settings(&self, settings: SamplingSettings) {
+        *self.settings.write().unwrap() = settings;
+    }
+
+    /// Sample rate to log a result with, or `None` to skip logging it
+    ///
+    /// Notable queries are logged at rate 1; sampled ones at the
+    /// configured rate, so each row stands for that many queries.
+    pub fn sample(&self, result: &Result<ResolveResult>) -> Option<u32> {
+        let settings = self.settings();
+        if settings.mode == SamplingMode::All || is_notable(result, settings.slow_ms) {
+            return Some(1);
+        }
+        match settings.mode {
+            SamplingMode::Sample => {
+                let rate = settings.rate.max(1);
+                let seen = self.seen.fetch_add(1, Ordering::Relaxed);
+                seen.is_multiple_of(rate as u64).then_some(rate)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for QueryLogSampler {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+/// Whether a query is an error, blocked (by policy or client ACL) or slow
+fn is_notable(result: &Result<ResolveResult>, slow_ms: u64) -> bool {
+    let Ok(r) = result else {
+        return true;
+    };
+    let error = !matches!(
+        r.response.response_code,
+        DnsResponseCode::NoError | DnsResponseCode::NxDomain
+    );
+    let blocked = r.metadata.block_reason().is_some();
+    let slow = slow_ms > 0 && r.metadata.response_time_ms >= slow_ms;
+    error || blocked || slow
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::DnsResponse;
+    use crate::dns::policy_stats::PolicyMatch;
+    use crate::dns::resolver::QueryMetadata;
+    use crate::dns::rewrite::RewriteAction;
+
+    fn result(response: DnsResponse, response_time_ms: u64) -> Result<ResolveResult> {
+        Ok(ResolveResult {
+            response,
+            metadata: QueryMetadata {
+                response_time_ms,
+                ..Default::default()
+            },
+        })
+    }
+
+    #[test]
+    fn test_sample_mode() {
+        let sampler = QueryLogSampler::new(None);
+        assert_eq!(sampler.sample(&result(DnsResponse::new(1), 1)), Some(1));
+
+        sampler.set_settings(SamplingSettings {
+            mode: SamplingMode::Sample,
+            rate: 10,
+            slow_ms: 500,
+        });
+        let logged: Vec<u32> = (0..100)
+            .filter_map(|_| sampler.sample(&result(DnsResponse::new(1), 1)))
+            .collect();
+        assert_eq!(logged, vec![10; 10]);
+
+        // Notable queries are always logged, at rate 1
+        assert_eq!(sampler.sample(&result(DnsResponse::servfail(1), 1)), Some(1));
+        assert_eq!(sampler.sample(&result(DnsResponse::new(1), 800)), Some(1));
+        assert_eq!(sampler.sample(&Err(anyhow::anyhow!("timeout"))), Some(1));
+    }
+
+    #[test]
+    fn test_notable_mode() {
+        let sampler = QueryLogSampler::new(None);
+        sampler.set_settings(SamplingSettings {
+            mode: SamplingMode::Notable,
+            rate: 10,
+            slow_ms: 0,
+        });
+        assert_eq!(sampler.sample(&result(DnsResponse::new(1), 800)), None);
+        assert_eq!(sampler.sample(&result(DnsResponse::nxdomain(1), 1)), None);
+        assert_eq!(sampler.sample(&result(DnsResponse::refused(1), 1)), Some(1));
+
+        let mut blocked = result(DnsResponse::nxdomain(1), 1).unwrap();
+        blocked.metadata.policy = Some(PolicyMatch::rewrite(1, &RewriteAction::Block));
+        assert_eq!(sampler.sample(&Ok(blocked)), Some(1));
+    }
+}
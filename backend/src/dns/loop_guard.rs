@@ -0,0 +1,237 @@
+//! Forwarding loop detection
+//!
+//! An instance that forwards to itself, directly or through another
+//! forwarder, sends each query round in circles until the timeouts
+//! cascade. Two guards catch this:
+//!
+//! - Upstreams whose address is one of our enabled listeners are refused
+//!   when they are added or changed.
+//! - Queries sent upstream carry an EDNS option with an identifier of this
+//!   instance. A query reaching a listener with our own identifier has come
+//!   back round; it is answered REFUSED instead of being forwarded again,
+//!   and the loop is logged, counted in `/api/status` and alerted on.
+//!   Forwarders that strip unknown EDNS options hide the marker, so loops
+//!   through them still end in timeouts.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::extended_error::ExtendedError;
+use super::message::{DnsQuery, DnsResponse, WireSummary};
+use super::proxy::{parse_host_port, UpstreamProtocol};
+
+/// EDNS option code of the loop marker (local/experimental range, RFC 6891)
+pub const EDNS_OPTION_LOOP_MARKER: u16 = 65001;
+
+/// Minimum seconds between two loop warnings in the log
+const LOG_INTERVAL_SECS: i64 = 60;
+
+/// A query that came back to this instance
+#[derive(Debug, Clone, Serialize)]
+pub struct LoopEvent {
+    pub at: DateTime<Utc>,
+    pub name: String,
+    pub record_type: String,
+    /// Address the looped query arrived from
+    pub client_ip: String,
+    pub listener: String,
+}
+
+/// Loops detected since startup
+#[derive(Debug, Clone, Serialize)]
+pub struct LoopGuardStatus {
+    pub detected: u64,
+    pub last: Option<LoopEvent>,
+}
+
+/// Marks outgoing queries and recognises them when they come back
+pub struct LoopGuard {
+    instance_id: [u8; 8],
+    detected: AtomicU64,
+    last: Mutex<Option<LoopEvent>>,
+    last_logged: Mutex<Option<DateTime<Utc>>>,
+}
+
+static LOOP_GUARD: OnceLock<LoopGuard> = OnceLock::new();
+
+/// Process-wide loop guard
+pub fn loop_guard() -> &'static LoopGuard {
+    LOOP_GUARD.get_or_init(LoopGuard::new)
+}
+
+impl LoopGuard {
+    pub fn new() -> Self {
+        Self {
+            instance_id: rand::random(),
+            detected: AtomicU64::new(0),
+            last: Mutex::new(None),
+            last_logged: Mutex::new(None),
+        }
+    }
+
+    /// EDNS option to add to queries sent upstream
+    pub fn marker(&self) -> (u16, &[u8]) {
+        (EDNS_OPTION_LOOP_MARKER, &self.instance_id)
+    }
+
+    /// Whether an encoded query carries our own marker
+    pub fn is_looped(&self, query: &[u8]) -> bool {
+        WireSummary::parse(query)
+            .and_then(|s| s.opt)
+            .is_some_and(|opt| opt.option(EDNS_OPTION_LOOP_MARKER) == Some(&self.instance_id[..]))
+    }
+
+    /// REFUSED response for a query that came back to us, `None` otherwise
+    ///
+    /// Listeners call this with the raw query before resolving it.
+    pub fn check(&self, data: &[u8], query: &DnsQuery, client_ip: &str, listener: &str) -> Option<DnsResponse> {
+        if !self.is_looped(data) {
+            return None;
+        }
+        self.record(query, client_ip, listener);
+        Some(DnsResponse::refused(query.id).with_extended_error(ExtendedError::new(
+            ExtendedError::OTHER,
+            "forwarding loop detected",
+        )))
+    }
+
+    /// Count a looped query, logging at most once a minute
+    pub fn record(&self, query: &DnsQuery, client_ip: &str, listener: &str) {
+        let now = Utc::now();
+        self.detected.fetch_add(1, Ordering::Relaxed);
+        *self.last.lock().unwrap() = Some(LoopEvent {
+            at: now,
+            name: query.name.clone(),
+            record_type: query.record_type.to_string(),
+            client_ip: client_ip.to_string(),
+            listener: listener.to_string(),
+        });
+
+        let mut last_logged = self.last_logged.lock().unwrap();
+        if last_logged.is_none_or(|t| (now - t).num_seconds() >= LOG_INTERVAL_SECS) {
+            *last_logged = Some(now);
+            tracing::warn!(
+                "Forwarding loop detected: {} {} came back from {} on the {} listener; check that no upstream forwards to this server",
+                query.name,
+                query.record_type,
+                client_ip,
+                listener
+            );
+        }
+    }
+
+    pub fn status(&self) -> LoopGuardStatus {
+        LoopGuardStatus {
+            detected: self.detected.load(Ordering::Relaxed),
+            last: self.last.lock().unwrap().clone(),
+        }
+    }
+}
+
+impl Default for LoopGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Listener protocol an upstream of the given protocol would reach
+pub fn listener_protocol(protocol: UpstreamProtocol) -> Option<&'static str> {
+    match protocol {
+        UpstreamProtocol::Udp => Some("udp"),
+        UpstreamProtocol::Dot => Some("dot"),
+        UpstreamProtocol::Doh => Some("doh"),
+        UpstreamProtocol::Doq => Some("doq"),
+        // No listener serves plain TCP or HTTP/3
+        UpstreamProtocol::Tcp | UpstreamProtocol::Doh3 => None,
+    }
+}
+
+/// Socket address an upstream sends to, when it is given as an IP address
+///
+/// Host names other than `localhost` are not resolved.
+pub fn upstream_target(address: &str, protocol: UpstreamProtocol) -> Option<SocketAddr> {
+    let (host, port) = match protocol {
+        UpstreamProtocol::Doh | UpstreamProtocol::Doh3 => {
+            let url = reqwest::Url::parse(address).ok()?;
+            let host = url.host_str()?.trim_start_matches('[').trim_end_matches(']').to_string();
+            (host, url.port_or_known_default()?)
+        }
+        _ => parse_host_port(address, protocol.default_port()).ok()?,
+    };
+    let ip = if host.eq_ignore_ascii_case("localhost") {
+        IpAddr::V4(Ipv4Addr::LOCALHOST)
+    } else {
+        host.parse().ok()?
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Whether sending to `target` reaches a listener bound to `bind`
+///
+/// A wildcard bind answers on every local address; an address counts as
+/// local when it is loopback, unspecified or can be bound to.
+pub fn reaches_listener(target: SocketAddr, bind: SocketAddr) -> bool {
+    if target.port() != bind.port() {
+        return false;
+    }
+    if target.ip() == bind.ip() {
+        return true;
+    }
+    bind.ip().is_unspecified() && is_local_ip(target.ip())
+}
+
+fn is_local_ip(ip: IpAddr) -> bool {
+    ip.is_loopback() || ip.is_unspecified() || UdpSocket::bind(SocketAddr::new(ip, 0)).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::{append_opt_record, DnsResponseCode, RecordType};
+
+    #[test]
+    fn test_marker_round_trip() {
+        let guard = LoopGuard::new();
+        let query = DnsQuery::with_id(7, "loop.example.com", RecordType::A);
+        let plain = query.to_bytes().unwrap();
+        assert!(guard.check(&plain, &query, "127.0.0.1", "udp").is_none());
+
+        // Another instance's marker is not ours
+        let mut foreign = plain.clone();
+        append_opt_record(&mut foreign, 1232, false, &[LoopGuard::new().marker()]);
+        assert!(!guard.is_looped(&foreign));
+
+        let mut marked = plain;
+        append_opt_record(&mut marked, 1232, false, &[guard.marker()]);
+        let response = guard.check(&marked, &query, "127.0.0.1", "udp").unwrap();
+        assert_eq!(response.response_code, DnsResponseCode::Refused);
+        assert_eq!(response.id, 7);
+
+        let status = guard.status();
+        assert_eq!(status.detected, 1);
+        let last = status.last.unwrap();
+        assert_eq!((last.name.as_str(), last.listener.as_str()), ("loop.example.com", "udp"));
+    }
+
+    #[test]
+    fn test_upstream_reaches_listener() {
+        let target = |address, protocol| upstream_target(address, protocol).unwrap();
+        let wildcard: SocketAddr = "0.0.0.0:53".parse().unwrap();
+
+        assert!(reaches_listener(target("127.0.0.1", UpstreamProtocol::Udp), wildcard));
+        assert!(reaches_listener(target("localhost:53", UpstreamProtocol::Udp), wildcard));
+        assert!(reaches_listener(target("[::1]:53", UpstreamProtocol::Udp), "[::]:53".parse().unwrap()));
+        assert!(!reaches_listener(target("127.0.0.1:5353", UpstreamProtocol::Udp), wildcard));
+        assert!(!reaches_listener(target("8.8.8.8", UpstreamProtocol::Udp), wildcard));
+        assert!(!reaches_listener(target("127.0.0.1", UpstreamProtocol::Udp), "192.0.2.1:53".parse().unwrap()));
+
+        let doh = target("https://127.0.0.1/dns-query", UpstreamProtocol::Doh);
+        assert_eq!(doh, "127.0.0.1:443".parse().unwrap());
+        assert_eq!(target("https://[::1]:8443/dns-query", UpstreamProtocol::Doh).port(), 8443);
+        assert!(upstream_target("https://dns.google/dns-query", UpstreamProtocol::Doh).is_none());
+    }
+}
@@ -13,6 +13,9 @@ use hickory_proto::op::{Message, MessageType, OpCode, ResponseCode};
 use hickory_proto::rr::{Name, RData, Record, RecordType as TrustRecordType};
 use hickory_proto::serialize::binary::{BinDecodable, BinEncodable};
 
+use super::extended_error::{ExtendedError, EDNS_OPTION_EDE};
+use super::txt::{format_txt, txt_strings};
+
 /// DNS-specific errors
 #[derive(Error, Debug)]
 pub enum DnsError {
@@ -144,6 +147,9 @@ pub struct DnsQuery {
     pub record_type: RecordType,
     /// Whether recursion is desired
     pub recursion_desired: bool,
+    /// Whether the query carried an OPT record
+    #[serde(default)]
+    pub edns: bool,
 }
 
 impl DnsQuery {
@@ -154,6 +160,7 @@ impl DnsQuery {
             name: name.into(),
             record_type,
             recursion_desired: true,
+            edns: false,
         }
     }
 
@@ -164,6 +171,7 @@ impl DnsQuery {
             name: name.into(),
             record_type,
             recursion_desired: true,
+            edns: false,
         }
     }
 
@@ -185,6 +193,7 @@ impl DnsQuery {
             name: query.name().to_string().trim_end_matches('.').to_string(),
             record_type,
             recursion_desired: message.recursion_desired(),
+            edns: message.extensions().is_some(),
         })
     }
 
@@ -219,6 +228,218 @@ fn rand_id() -> u16 {
     (nanos % 65536) as u16
 }
 
+/// Append an EDNS(0) OPT record to an encoded message
+///
+/// `options` are (code, data) pairs. When the message already ends with an
+/// OPT record, e.g. one carrying an extended error from
+/// `DnsResponse::to_bytes`, the options are added to it instead and its
+/// payload size is kept. Returns false, leaving the message untouched, when
+/// it is too short to be a DNS message or its additional count is exhausted.
+pub fn append_opt_record(
+    bytes: &mut Vec<u8>,
+    udp_payload_size: u16,
+    dnssec_ok: bool,
+    options: &[(u16, &[u8])],
+) -> bool {
+    if bytes.len() < 12 {
+        return false;
+    }
+    let rdlen: usize = options.iter().map(|(_, data)| 4 + data.len()).sum();
+
+    if let Some(start) = trailing_opt(bytes) {
+        let rdlen_pos = start + 9;
+        let current = u16::from_be_bytes([bytes[rdlen_pos], bytes[rdlen_pos + 1]]) as usize;
+        let Ok(merged) = u16::try_from(current + rdlen) else {
+            return false;
+        };
+        bytes[rdlen_pos..rdlen_pos + 2].copy_from_slice(&merged.to_be_bytes());
+        if dnssec_ok {
+            bytes[start + 7] |= 0x80;
+        }
+        push_options(bytes, options);
+        return true;
+    }
+
+    let Some(arcount) = u16::from_be_bytes([bytes[10], bytes[11]]).checked_add(1) else {
+        return false;
+    };
+    bytes[10..12].copy_from_slice(&arcount.to_be_bytes());
+    bytes.push(0); // root owner name
+    bytes.extend_from_slice(&41u16.to_be_bytes()); // TYPE OPT
+    bytes.extend_from_slice(&udp_payload_size.to_be_bytes());
+    bytes.extend_from_slice(&[0, 0]); // extended RCODE, version
+    bytes.extend_from_slice(&(if dnssec_ok { 0x8000u16 } else { 0 }).to_be_bytes());
+    bytes.extend_from_slice(&(rdlen as u16).to_be_bytes());
+    push_options(bytes, options);
+    true
+}
+
+fn push_options(bytes: &mut Vec<u8>, options: &[(u16, &[u8])]) {
+    for (code, data) in options {
+        bytes.extend_from_slice(&code.to_be_bytes());
+        bytes.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(data);
+    }
+}
+
+/// Start of the OPT record when it is the last record of the message
+fn trailing_opt(bytes: &[u8]) -> Option<usize> {
+    let header = bytes.get(..12)?;
+    let count = |i: usize| u16::from_be_bytes([header[i], header[i + 1]]) as usize;
+    if count(10) == 0 {
+        return None;
+    }
+
+    let mut pos = 12;
+    for _ in 0..count(4) {
+        pos = skip_name(bytes, pos)? + 4;
+    }
+    let mut last = None;
+    for _ in 0..count(6) + count(8) + count(10) {
+        let start = pos;
+        pos = skip_name(bytes, pos)?;
+        let fixed = bytes.get(pos..pos + 10)?;
+        last = Some((start, u16::from_be_bytes([fixed[0], fixed[1]])));
+        pos += 10 + u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+    }
+    match last {
+        Some((start, TYPE_OPT)) if pos == bytes.len() && bytes[start] == 0 => Some(start),
+        _ => None,
+    }
+}
+
+/// EDNS option code for DNS cookies (RFC 7873)
+pub const EDNS_OPTION_COOKIE: u16 = 10;
+
+/// UDP payload size advertised in responses carrying an extended error
+const EDE_UDP_PAYLOAD_SIZE: u16 = 1232;
+
+const TYPE_OPT: u16 = 41;
+const TYPE_RRSIG: u16 = 46;
+
+/// OPT record of an encoded message
+#[derive(Debug, Clone)]
+pub struct OptRecord {
+    pub udp_payload_size: u16,
+    /// Upper eight bits of the extended RCODE
+    pub extended_rcode: u8,
+    #[allow(dead_code)]
+    pub dnssec_ok: bool,
+    /// (code, data) pairs
+    pub options: Vec<(u16, Vec<u8>)>,
+}
+
+impl OptRecord {
+    /// Data of the first option with the given code
+    pub fn option(&self, code: u16) -> Option<&[u8]> {
+        self.options
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|(_, data)| data.as_slice())
+    }
+}
+
+/// Header fields and EDNS data of an encoded message
+///
+/// Walks the wire format directly because the parsed message types do not
+/// keep unknown EDNS options or RRSIG records.
+#[derive(Debug, Clone)]
+pub struct WireSummary {
+    /// Lower four bits of the RCODE
+    pub rcode: u8,
+    pub opt: Option<OptRecord>,
+    /// An RRSIG record is in the answer section
+    pub rrsig: bool,
+}
+
+impl WireSummary {
+    /// Returns `None` for messages that are cut short
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let header = bytes.get(..12)?;
+        let count = |i: usize| u16::from_be_bytes([header[i], header[i + 1]]) as usize;
+        let (questions, answers, authority, additional) = (count(4), count(6), count(8), count(10));
+
+        let mut summary = WireSummary {
+            rcode: header[3] & 0x0F,
+            opt: None,
+            rrsig: false,
+        };
+        let mut pos = 12;
+        for _ in 0..questions {
+            pos = skip_name(bytes, pos)? + 4;
+        }
+        for i in 0..answers + authority + additional {
+            pos = skip_name(bytes, pos)?;
+            let fixed = bytes.get(pos..pos + 10)?;
+            let rtype = u16::from_be_bytes([fixed[0], fixed[1]]);
+            let rdlen = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+            let rdata = bytes.get(pos + 10..pos + 10 + rdlen)?;
+
+            if rtype == TYPE_RRSIG && i < answers {
+                summary.rrsig = true;
+            }
+            if rtype == TYPE_OPT && i >= answers + authority {
+                summary.opt = Some(OptRecord {
+                    udp_payload_size: u16::from_be_bytes([fixed[2], fixed[3]]),
+                    extended_rcode: fixed[4],
+                    dnssec_ok: fixed[6] & 0x80 != 0,
+                    options: parse_options(rdata),
+                });
+            }
+            pos += 10 + rdlen;
+        }
+        Some(summary)
+    }
+}
+
+/// First question of an encoded message: its raw name and record type
+pub fn wire_question(bytes: &[u8]) -> Option<(&[u8], u16)> {
+    if bytes.len() < 12 || u16::from_be_bytes([bytes[4], bytes[5]]) == 0 {
+        return None;
+    }
+    let end = skip_name(bytes, 12)?;
+    let qtype = bytes.get(end..end + 2)?;
+    Some((&bytes[12..end], u16::from_be_bytes([qtype[0], qtype[1]])))
+}
+
+/// Header and first question of an encoded response, with TC set and no
+/// records, telling the client to retry over TCP
+pub fn truncated_response(bytes: &[u8]) -> Option<Vec<u8>> {
+    let (name, _) = wire_question(bytes)?;
+    let mut truncated = bytes[..12 + name.len() + 4].to_vec();
+    truncated[2] |= 0x02;
+    truncated[4..6].copy_from_slice(&1u16.to_be_bytes());
+    truncated[6..12].fill(0);
+    Some(truncated)
+}
+
+/// Position just past the (possibly compressed) name starting at `pos`
+fn skip_name(bytes: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *bytes.get(pos)? as usize;
+        match len & 0xC0 {
+            0x00 if len == 0 => return Some(pos + 1),
+            0x00 => pos += 1 + len,
+            0xC0 => return Some(pos + 2),
+            _ => return None,
+        }
+    }
+}
+
+fn parse_options(mut rdata: &[u8]) -> Vec<(u16, Vec<u8>)> {
+    let mut options = Vec::new();
+    while rdata.len() >= 4 {
+        let code = u16::from_be_bytes([rdata[0], rdata[1]]);
+        let len = u16::from_be_bytes([rdata[2], rdata[3]]) as usize;
+        let Some(data) = rdata.get(4..4 + len) else {
+            break;
+        };
+        options.push((code, data.to_vec()));
+        rdata = &rdata[4 + len..];
+    }
+    options
+}
+
 /// A single DNS record in a response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DnsRecordData {
@@ -232,6 +453,9 @@ pub struct DnsRecordData {
     pub ttl: u32,
     /// Priority (for MX and SRV records)
     pub priority: Option<u16>,
+    /// Character-strings of a TXT record, segmented as on the wire
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub txt: Option<Vec<Vec<u8>>>,
 }
 
 impl DnsRecordData {
@@ -243,6 +467,7 @@ impl DnsRecordData {
             value: ip.to_string(),
             ttl,
             priority: None,
+            txt: None,
         }
     }
 
@@ -254,6 +479,7 @@ impl DnsRecordData {
             value: ip.to_string(),
             ttl,
             priority: None,
+            txt: None,
         }
     }
 
@@ -265,6 +491,7 @@ impl DnsRecordData {
             value: target.into(),
             ttl,
             priority: None,
+            txt: None,
         }
     }
 
@@ -276,17 +503,26 @@ impl DnsRecordData {
             value: exchange.into(),
             ttl,
             priority: Some(priority),
+            txt: None,
         }
     }
 
     /// Create a new TXT record
+    ///
+    /// `text` is a TXT value: plain text or quoted character-strings.
     pub fn txt(name: impl Into<String>, text: impl Into<String>, ttl: u32) -> Self {
+        Self::txt_strings(name, txt_strings(&text.into()), ttl)
+    }
+
+    /// Create a new TXT record from its character-strings
+    pub fn txt_strings(name: impl Into<String>, strings: Vec<Vec<u8>>, ttl: u32) -> Self {
         Self {
             name: name.into(),
             record_type: RecordType::TXT,
-            value: text.into(),
+            value: format_txt(&strings),
             ttl,
             priority: None,
+            txt: Some(strings),
         }
     }
 
@@ -298,6 +534,7 @@ impl DnsRecordData {
             value: target.into(),
             ttl,
             priority: None,
+            txt: None,
         }
     }
 
@@ -309,6 +546,7 @@ impl DnsRecordData {
             value: nameserver.into(),
             ttl,
             priority: None,
+            txt: None,
         }
     }
 }
@@ -389,6 +627,9 @@ pub struct DnsResponse {
     pub authority: Vec<DnsRecordData>,
     /// Additional records
     pub additional: Vec<DnsRecordData>,
+    /// Why the query was not resolved normally (RFC 8914)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extended_error: Option<ExtendedError>,
 }
 
 impl DnsResponse {
@@ -402,6 +643,7 @@ impl DnsResponse {
             answers: Vec::new(),
             authority: Vec::new(),
             additional: Vec::new(),
+            extended_error: None,
         }
     }
 
@@ -415,6 +657,7 @@ impl DnsResponse {
             answers: Vec::new(),
             authority: Vec::new(),
             additional: Vec::new(),
+            extended_error: None,
         }
     }
 
@@ -428,6 +671,7 @@ impl DnsResponse {
             answers: Vec::new(),
             authority: Vec::new(),
             additional: Vec::new(),
+            extended_error: None,
         }
     }
 
@@ -441,9 +685,16 @@ impl DnsResponse {
             answers: Vec::new(),
             authority: Vec::new(),
             additional: Vec::new(),
+            extended_error: None,
         }
     }
 
+    /// Attach the reason for an unusual answer
+    pub fn with_extended_error(mut self, error: ExtendedError) -> Self {
+        self.extended_error = Some(error);
+        self
+    }
+
     /// Add an answer record
     pub fn add_answer(&mut self, record: DnsRecordData) {
         self.answers.push(record);
@@ -482,6 +733,7 @@ impl DnsResponse {
             answers,
             authority,
             additional,
+            extended_error: ExtendedError::from_wire(data),
         })
     }
 
@@ -525,9 +777,14 @@ impl DnsResponse {
             }
         }
 
-        message
+        let mut bytes = message
             .to_bytes()
-            .map_err(|e| DnsError::EncodeError(e.to_string()))
+            .map_err(|e| DnsError::EncodeError(e.to_string()))?;
+        // Only clients that sent EDNS may get an OPT record back
+        if let (Some(error), true) = (&self.extended_error, query.edns) {
+            append_opt_record(&mut bytes, EDE_UDP_PAYLOAD_SIZE, false, &[(EDNS_OPTION_EDE, &error.encode())]);
+        }
+        Ok(bytes)
     }
 }
 
@@ -544,6 +801,7 @@ fn record_to_data(record: &Record) -> Option<DnsRecordData> {
             value: ip.to_string(),
             ttl,
             priority: None,
+            txt: None,
         }),
         RData::AAAA(ip) => Some(DnsRecordData {
             name,
@@ -551,6 +809,7 @@ fn record_to_data(record: &Record) -> Option<DnsRecordData> {
             value: ip.to_string(),
             ttl,
             priority: None,
+            txt: None,
         }),
         RData::CNAME(cname) => Some(DnsRecordData {
             name,
@@ -558,6 +817,7 @@ fn record_to_data(record: &Record) -> Option<DnsRecordData> {
             value: cname.to_string().trim_end_matches('.').to_string(),
             ttl,
             priority: None,
+            txt: None,
         }),
         RData::MX(mx) => Some(DnsRecordData {
             name,
@@ -565,21 +825,11 @@ fn record_to_data(record: &Record) -> Option<DnsRecordData> {
             value: mx.exchange().to_string().trim_end_matches('.').to_string(),
             ttl,
             priority: Some(mx.preference()),
+            txt: None,
         }),
         RData::TXT(txt) => {
-            let text: String = txt
-                .txt_data()
-                .iter()
-                .map(|b| String::from_utf8_lossy(b).to_string())
-                .collect::<Vec<_>>()
-                .join("");
-            Some(DnsRecordData {
-                name,
-                record_type: RecordType::TXT,
-                value: text,
-                ttl,
-                priority: None,
-            })
+            let strings = txt.txt_data().iter().map(|s| s.to_vec()).collect();
+            Some(DnsRecordData::txt_strings(name, strings, ttl))
         }
         RData::PTR(ptr) => Some(DnsRecordData {
             name,
@@ -587,6 +837,7 @@ fn record_to_data(record: &Record) -> Option<DnsRecordData> {
             value: ptr.to_string().trim_end_matches('.').to_string(),
             ttl,
             priority: None,
+            txt: None,
         }),
         RData::NS(ns) => Some(DnsRecordData {
             name,
@@ -594,6 +845,7 @@ fn record_to_data(record: &Record) -> Option<DnsRecordData> {
             value: ns.to_string().trim_end_matches('.').to_string(),
             ttl,
             priority: None,
+            txt: None,
         }),
         RData::SOA(soa) => {
             let value = format!(
@@ -612,6 +864,7 @@ fn record_to_data(record: &Record) -> Option<DnsRecordData> {
                 value,
                 ttl,
                 priority: None,
+                txt: None,
             })
         }
         RData::SRV(srv) => {
@@ -627,6 +880,7 @@ fn record_to_data(record: &Record) -> Option<DnsRecordData> {
                 value,
                 ttl,
                 priority: Some(srv.priority()),
+                txt: None,
             })
         }
         _ => None,
@@ -656,7 +910,17 @@ fn data_to_record(data: &DnsRecordData) -> Option<Record> {
             RData::MX(hickory_proto::rr::rdata::MX::new(priority, exchange))
         }
         RecordType::TXT => {
-            RData::TXT(hickory_proto::rr::rdata::TXT::new(vec![data.value.clone()]))
+            let parsed;
+            let strings = match data.txt {
+                Some(ref strings) => strings,
+                None => {
+                    parsed = txt_strings(&data.value);
+                    &parsed
+                }
+            };
+            RData::TXT(hickory_proto::rr::rdata::TXT::from_bytes(
+                strings.iter().map(Vec::as_slice).collect(),
+            ))
         }
         RecordType::PTR => {
             let target = Name::from_str(&data.value).ok()?;
@@ -722,6 +986,44 @@ mod tests {
         assert_eq!(RecordType::CNAME.to_string(), "CNAME");
     }
 
+    #[test]
+    fn test_wire_summary_reads_opt_record() {
+        let query = DnsQuery::with_id(1, "example.com", RecordType::A).to_bytes().unwrap();
+        let mut bytes = query.clone();
+        assert!(append_opt_record(&mut bytes, 1400, true, &[(EDNS_OPTION_COOKIE, &[1, 2, 3, 4, 5, 6, 7, 8])]));
+
+        let summary = WireSummary::parse(&bytes).unwrap();
+        let opt = summary.opt.unwrap();
+        assert_eq!(opt.udp_payload_size, 1400);
+        assert!(opt.dnssec_ok);
+        assert_eq!(opt.option(EDNS_OPTION_COOKIE), Some(&[1, 2, 3, 4, 5, 6, 7, 8][..]));
+        assert!(!summary.rrsig);
+
+        // The OPT record leaves the message parseable
+        assert_eq!(DnsQuery::from_bytes(&bytes).unwrap().name, "example.com");
+        assert!(WireSummary::parse(&bytes[..bytes.len() - 3]).is_none());
+        assert!(WireSummary::parse(&query).unwrap().opt.is_none());
+    }
+
+    #[test]
+    fn test_truncated_response() {
+        let query = DnsQuery::with_id(9, "example.com", RecordType::AAAA);
+        let mut response = DnsResponse::new(9);
+        response.add_answer(DnsRecordData::aaaa("example.com", "2001:db8::1".parse().unwrap(), 60));
+        let bytes = response.to_bytes(&query).unwrap();
+
+        let (name, qtype) = wire_question(&bytes).unwrap();
+        assert_eq!(name, b"\x07example\x03com\x00");
+        assert_eq!(qtype, 28);
+
+        let truncated = Message::from_vec(&truncated_response(&bytes).unwrap()).unwrap();
+        assert!(truncated.truncated());
+        assert_eq!(truncated.id(), 9);
+        assert_eq!(truncated.queries().len(), 1);
+        assert!(truncated.answers().is_empty());
+        assert!(wire_question(&bytes[..5]).is_none());
+    }
+
     #[test]
     fn test_dns_query_creation() {
         let query = DnsQuery::new("example.com", RecordType::A);
@@ -758,6 +1060,30 @@ mod tests {
         assert!(response.answers.is_empty());
     }
 
+    #[test]
+    fn test_extended_error_on_the_wire() {
+        let error = ExtendedError::new(ExtendedError::NO_REACHABLE_AUTHORITY, "upstream servers timed out");
+        let response = DnsResponse::servfail(7).with_extended_error(error.clone());
+
+        // Clients without EDNS get a plain SERVFAIL
+        let mut query = DnsQuery::with_id(7, "example.com", RecordType::A);
+        let plain = response.to_bytes(&query).unwrap();
+        assert!(WireSummary::parse(&plain).unwrap().opt.is_none());
+
+        query.edns = true;
+        let mut bytes = response.to_bytes(&query).unwrap();
+        assert_eq!(ExtendedError::from_wire(&bytes), Some(error.clone()));
+        let parsed = DnsResponse::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.response_code, DnsResponseCode::ServFail);
+        assert_eq!(parsed.extended_error, Some(error.clone()));
+
+        // Further options join the same OPT record
+        append_opt_record(&mut bytes, 1232, false, &[(12, &[0, 0])]);
+        let opt = WireSummary::parse(&bytes).unwrap().opt.unwrap();
+        assert_eq!(opt.option(12), Some(&[0u8, 0][..]));
+        assert_eq!(ExtendedError::from_wire(&bytes), Some(error));
+    }
+
     #[test]
     fn test_dns_record_data_a() {
         let record = DnsRecordData::a("example.com", "93.184.216.34".parse().unwrap(), 300);
@@ -774,6 +1100,23 @@ mod tests {
         assert_eq!(record.priority, Some(10));
     }
 
+    #[test]
+    fn test_txt_strings_on_the_wire() {
+        let query = DnsQuery::with_id(5, "dkim.example.com", RecordType::TXT);
+        let strings = vec![b"v=DKIM1; k=rsa; p=MIIB".to_vec(), "x".repeat(255).into_bytes(), vec![0, 0xff]];
+        let mut response = DnsResponse::new(5);
+        response.add_answer(DnsRecordData::txt_strings("dkim.example.com", strings.clone(), 300));
+
+        let parsed = DnsResponse::from_bytes(&response.to_bytes(&query).unwrap()).unwrap();
+        let answer = &parsed.answers[0];
+        assert_eq!(answer.txt.as_deref(), Some(&strings[..]));
+        assert_eq!(answer.value, response.answers[0].value);
+
+        // A value alone is enough to restore the strings
+        let from_value = DnsRecordData::txt("dkim.example.com", answer.value.clone(), 300);
+        assert_eq!(from_value.txt.as_deref(), Some(&strings[..]));
+    }
+
     #[test]
     fn test_response_code_display() {
         assert_eq!(DnsResponseCode::NoError.to_string(), "NOERROR");
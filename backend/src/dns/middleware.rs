@@ -0,0 +1,290 @@
+//! Resolver middleware hooks
+//!
+//! Lets custom logic plug into the resolution pipeline without forking the
+//! resolver. A middleware implements any of three stage callbacks, which the
+//! resolver calls in order:
+//!
+//! 1. `pre_rewrite` - before rewrite rules and local records; may alter the
+//!    query or answer it directly
+//! 2. `pre_upstream` - after a cache miss, before forwarding upstream; may
+//!    answer directly or pick the upstream via `QueryContext::upstream`
+//! 3. `post_response` - on every final result, including short-circuited ones
+//!
+//! Within a stage, middleware runs in registration order. A middleware that
+//! fails is logged and skipped so a broken hook cannot take resolution down.
+
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tracing::warn;
+
+use crate::db::Database;
+use super::message::{DnsQuery, DnsResponse};
+use super::resolution_mode::ResolutionMode;
+use super::resolver::{DnsResolver, ResolveResult};
+
+/// Per-query context shared by all stages
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct QueryContext {
+    /// The query being resolved; `pre_rewrite` hooks may modify it
+    pub query: DnsQuery,
+    /// Client address, when the query arrived on a listener
+    pub client_ip: Option<String>,
+    /// Listener protocol the query arrived on
+    pub listener: Option<String>,
+    /// Tenant view the query resolves in
+    pub tenant_id: Option<i64>,
+    /// Resolution profile pinned by the listener, overriding the active profile
+    pub profile_id: Option<i64>,
+    /// Upstream server name to forward to instead of using the query strategy
+    pub upstream: Option<String>,
+    /// Trace ID tying log lines, the query log entry and the upstream path together
+    pub trace_id: String,
+    /// Resolution mode of the listener; limits what may answer the query
+    pub resolution_mode: ResolutionMode,
+}
+
+impl QueryContext {
+    /// Context for a query without client information (API, internal lookups)
+    pub fn new(query: DnsQuery, tenant_id: Option<i64>) -> Self {
+        Self {
+            query,
+            client_ip: None,
+            listener: None,
+            tenant_id,
+            profile_id: None,
+            upstream: None,
+            trace_id: new_trace_id(),
+            resolution_mode: ResolutionMode::Recursive,
+        }
+    }
+}
+
+/// Generate a random trace ID (16 lowercase hex characters)
+pub fn new_trace_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+/// Outcome of a pre-resolution hook
+#[derive(Debug, Clone)]
+pub enum HookOutcome {
+    /// Continue with the next hook / pipeline step
+    Continue,
+    /// Stop resolution and answer with this response
+    Respond(DnsResponse),
+}
+
+/// A resolver middleware
+///
+/// All stage callbacks default to no-ops, so implementations only override
+/// the stages they care about.
+#[async_trait]
+pub trait ResolverMiddleware: Send + Sync {
+    /// Name used in logs and query metadata
+    fn name(&self) -> &str;
+
+    /// Called before rewrite rules are evaluated
+    async fn pre_rewrite(&self, _ctx: &mut QueryContext) -> Result<HookOutcome> {
+        Ok(HookOutcome::Continue)
+    }
+
+    /// Called after a cache miss, before the query is forwarded upstream
+    async fn pre_upstream(&self, _ctx: &mut QueryContext) -> Result<HookOutcome> {
+        Ok(HookOutcome::Continue)
+    }
+
+    /// Called with the final result before it is returned
+    async fn post_response(&self, _ctx: &QueryContext, _result: &mut ResolveResult) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Ordered list of middleware attached to a resolver
+pub struct MiddlewareChain {
+    hooks: RwLock<Vec<Arc<dyn ResolverMiddleware>>>,
+}
+
+impl Default for MiddlewareChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[allow(dead_code)]
+impl MiddlewareChain {
+    /// Create an empty chain
+    pub fn new() -> Self {
+        Self {
+            hooks: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Append a middleware; it runs after those already registered
+    pub fn register(&self, hook: Arc<dyn ResolverMiddleware>) {
+        self.hooks.write().unwrap().push(hook);
+    }
+
+    /// Remove all middleware with the given name
+    pub fn unregister(&self, name: &str) -> bool {
+        let mut hooks = self.hooks.write().unwrap();
+        let before = hooks.len();
+        hooks.retain(|h| h.name() != name);
+        hooks.len() != before
+    }
+
+    /// Names of registered middleware, in execution order
+    pub fn names(&self) -> Vec<String> {
+        self.hooks.read().unwrap().iter().map(|h| h.name().to_string()).collect()
+    }
+
+    fn snapshot(&self) -> Vec<Arc<dyn ResolverMiddleware>> {
+        self.hooks.read().unwrap().clone()
+    }
+
+    /// Run the pre-rewrite stage; returns the answering hook and its response
+    pub async fn run_pre_rewrite(&self, ctx: &mut QueryContext) -> Option<(String, DnsResponse)> {
+        for hook in self.snapshot() {
+            match hook.pre_rewrite(ctx).await {
+                Ok(HookOutcome::Continue) => {}
+                Ok(HookOutcome::Respond(response)) => return Some((hook.name().to_string(), response)),
+                Err(e) => warn!("Middleware '{}' failed in pre-rewrite: {}", hook.name(), e),
+            }
+        }
+        None
+    }
+
+    /// Run the pre-upstream stage; returns the answering hook and its response
+    pub async fn run_pre_upstream(&self, ctx: &mut QueryContext) -> Option<(String, DnsResponse)> {
+        for hook in self.snapshot() {
+            match hook.pre_upstream(ctx).await {
+                Ok(HookOutcome::Continue) => {}
+                Ok(HookOutcome::Respond(response)) => return Some((hook.name().to_string(), response)),
+                Err(e) => warn!("Middleware '{}' failed in pre-upstream: {}", hook.name(), e),
+            }
+        }
+        None
+    }
+
+    /// Run the post-response stage
+    pub async fn run_post_response(&self, ctx: &QueryContext, result: &mut ResolveResult) {
+        for hook in self.snapshot() {
+            if let Err(e) = hook.post_response(ctx, result).await {
+                warn!("Middleware '{}' failed in post-response: {}", hook.name(), e);
+            }
+        }
+    }
+}
+
+/// Refuses queries for syntactically invalid domain names
+pub struct DomainValidation;
+
+#[async_trait]
+impl ResolverMiddleware for DomainValidation {
+    fn name(&self) -> &str {
+        "domain_validation"
+    }
+
+    async fn pre_rewrite(&self, ctx: &mut QueryContext) -> Result<HookOutcome> {
+        if DnsResolver::is_valid_domain(&ctx.query.name) {
+            Ok(HookOutcome::Continue)
+        } else {
+            Ok(HookOutcome::Respond(DnsResponse::refused(ctx.query.id)))
+        }
+    }
+}
+
+/// Answers NXDOMAIN for record types disabled in settings
+pub struct DisabledRecordTypes {
+    db: Arc<Database>,
+}
+
+impl DisabledRecordTypes {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl ResolverMiddleware for DisabledRecordTypes {
+    fn name(&self) -> &str {
+        "disabled_record_types"
+    }
+
+    async fn pre_rewrite(&self, ctx: &mut QueryContext) -> Result<HookOutcome> {
+        let Some(value) = self.db.system_config().get("disabled_record_types").await? else {
+            return Ok(HookOutcome::Continue);
+        };
+        let disabled: Vec<String> = serde_json::from_str(&value).unwrap_or_default();
+        let record_type = ctx.query.record_type.to_string();
+
+        if disabled.iter().any(|t| t.eq_ignore_ascii_case(&record_type)) {
+            Ok(HookOutcome::Respond(DnsResponse::nxdomain(ctx.query.id)))
+        } else {
+            Ok(HookOutcome::Continue)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::{DnsResponseCode, RecordType};
+
+    struct Tagger(&'static str);
+
+    #[async_trait]
+    impl ResolverMiddleware for Tagger {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        async fn pre_rewrite(&self, ctx: &mut QueryContext) -> Result<HookOutcome> {
+            ctx.query.name = format!("{}.{}", self.0, ctx.query.name);
+            Ok(HookOutcome::Continue)
+        }
+    }
+
+    struct Failing;
+
+    #[async_trait]
+    impl ResolverMiddleware for Failing {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        async fn pre_rewrite(&self, _ctx: &mut QueryContext) -> Result<HookOutcome> {
+            Err(anyhow::anyhow!("boom"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chain_runs_in_registration_order() {
+        let chain = MiddlewareChain::new();
+        chain.register(Arc::new(Tagger("a")));
+        chain.register(Arc::new(Failing));
+        chain.register(Arc::new(Tagger("b")));
+        assert_eq!(chain.names(), vec!["a", "failing", "b"]);
+
+        let mut ctx = QueryContext::new(DnsQuery::new("example.com", RecordType::A), None);
+        assert!(chain.run_pre_rewrite(&mut ctx).await.is_none());
+        assert_eq!(ctx.query.name, "b.a.example.com");
+
+        assert!(chain.unregister("failing"));
+        assert_eq!(chain.names(), vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_domain_validation_short_circuits() {
+        let chain = MiddlewareChain::new();
+        chain.register(Arc::new(DomainValidation));
+        chain.register(Arc::new(Tagger("never")));
+
+        let mut ctx = QueryContext::new(DnsQuery::new("bad domain.com", RecordType::A), None);
+        let (name, response) = chain.run_pre_rewrite(&mut ctx).await.unwrap();
+        assert_eq!(name, "domain_validation");
+        assert_eq!(response.response_code, DnsResponseCode::Refused);
+        assert_eq!(ctx.query.name, "bad domain.com");
+    }
+}
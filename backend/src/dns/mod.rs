@@ -3,14 +3,79 @@
 //! Contains DNS server implementations and related functionality.
 
 mod cache;
+mod capture;
+mod category;
+mod cidr;
+mod client_acl;
+mod companion;
+mod cookie;
+mod deadline;
+mod domain_stats;
+mod extended_error;
+mod fail_policy;
+mod local_records;
+mod local_zones;
+mod log_sampling;
+mod loop_guard;
 mod message;
+mod middleware;
+mod name;
+mod offline;
+mod policy_stats;
+mod profile;
 pub mod proxy;
+mod resolution_mode;
 mod resolver;
 mod rewrite;
+mod rewrite_metrics;
+mod rpz;
+mod rrl;
+#[cfg(feature = "scripting")]
+mod script;
 pub mod server;
+mod shuffle;
+mod socket;
+mod tenant;
+mod txt;
+mod typosquat;
+
+#[cfg(test)]
+mod test_support;
+#[cfg(test)]
+mod pipeline_tests;
 
 pub use cache::*;
+pub use capture::*;
+pub use category::*;
+pub use cidr::*;
+pub use client_acl::*;
+pub use companion::*;
+pub use cookie::*;
+pub use deadline::*;
+pub use domain_stats::*;
+pub use fail_policy::*;
+pub use local_records::*;
+pub use local_zones::*;
+pub use log_sampling::*;
+pub use loop_guard::*;
 pub use message::*;
+#[allow(unused_imports)]
+pub use middleware::*;
+pub use name::*;
+pub use offline::*;
+pub use policy_stats::*;
+pub use profile::*;
 pub use proxy::*;
+pub use resolution_mode::*;
 pub use resolver::*;
 pub use rewrite::*;
+pub use rewrite_metrics::*;
+pub use rpz::*;
+pub use rrl::*;
+#[cfg(feature = "scripting")]
+pub use script::*;
+pub use shuffle::*;
+pub use socket::*;
+pub use tenant::*;
+pub use txt::*;
+pub use typosquat::*;
@@ -0,0 +1,86 @@
+//! Domain Name Normalization
+//!
+//! Names reach FluxDNS from the wire, the records and rewrite APIs and the
+//! config file, in any case, with or without a trailing dot and sometimes in
+//! Unicode. Everything that stores or compares names (records, rewrite
+//! patterns, cache keys, query logs) uses the same form: lowercase, no
+//! trailing dot, internationalized labels in punycode. APIs convert stored
+//! names back to Unicode for display.
+
+/// Prefix of punycode-encoded labels
+const ACE_PREFIX: &str = "xn--";
+
+/// Normalize a name for storage and comparison
+///
+/// Names that are not valid IDNs are only lowercased; use [`name_to_ascii`]
+/// where invalid input should be rejected.
+pub fn normalize_name(name: &str) -> String {
+    let lower = name.trim().trim_end_matches('.').to_lowercase();
+    if lower.is_ascii() {
+        return lower;
+    }
+    idna::domain_to_ascii(&lower).unwrap_or(lower)
+}
+
+/// Normalize a name, failing when it is not a valid internationalized name
+pub fn name_to_ascii(name: &str) -> Result<String, String> {
+    let name = name.trim().trim_end_matches('.');
+    if name.is_ascii() {
+        return Ok(name.to_lowercase());
+    }
+    idna::domain_to_ascii(name)
+        .map_err(|_| format!("Invalid internationalized domain name: {}", name))
+}
+
+/// Convert punycode labels back to Unicode for display
+///
+/// Returns the name unchanged when it has no punycode labels or cannot be
+/// decoded.
+pub fn name_to_unicode(name: &str) -> String {
+    let encoded = name.split('.').any(|label| {
+        label
+            .get(..ACE_PREFIX.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(ACE_PREFIX))
+    });
+    if !encoded {
+        return name.to_string();
+    }
+    match idna::domain_to_unicode(name) {
+        (unicode, Ok(())) => unicode,
+        _ => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name("Example.COM."), "example.com");
+        assert_eq!(normalize_name(" www.example.com "), "www.example.com");
+        assert_eq!(normalize_name("Bücher.Example"), "xn--bcher-kva.example");
+        assert_eq!(normalize_name("*.münchen.de."), "*.xn--mnchen-3ya.de");
+        assert_eq!(normalize_name("_dmarc.example.com"), "_dmarc.example.com");
+        // Already encoded names are left alone
+        assert_eq!(normalize_name("XN--BCHER-KVA.example"), "xn--bcher-kva.example");
+    }
+
+    #[test]
+    fn test_name_to_ascii() {
+        assert_eq!(name_to_ascii("bücher.example.").unwrap(), "xn--bcher-kva.example");
+        assert_eq!(name_to_ascii("Example.com").unwrap(), "example.com");
+        // Labels may not start with a combining mark
+        assert!(name_to_ascii("\u{0301}bücher.example").is_err());
+    }
+
+    #[test]
+    fn test_name_to_unicode() {
+        assert_eq!(name_to_unicode("xn--bcher-kva.example"), "bücher.example");
+        assert_eq!(name_to_unicode("*.xn--mnchen-3ya.de"), "*.münchen.de");
+        assert_eq!(name_to_unicode("example.com"), "example.com");
+        // Undecodable labels are shown as stored
+        assert_eq!(name_to_unicode("xn--a.example"), "xn--a.example");
+        assert_eq!(name_to_unicode(&normalize_name("例子.测试")), "例子.测试");
+    }
+}
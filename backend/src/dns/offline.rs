@@ -0,0 +1,139 @@
+//! Offline (air-gapped) mode
+//!
+//! When enabled, nothing is forwarded upstream: rewrite rules, local records
+//! and the cache answer what they can and every remaining query gets the
+//! configured response code. Such answers are recorded with
+//! `answered_by = "offline"` so they stand apart from real NXDOMAINs in the
+//! query log.
+
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::db::Database;
+use super::extended_error::ExtendedError;
+use super::message::DnsResponse;
+
+/// Config key for the feature switch
+pub const CONFIG_KEY_OFFLINE_MODE: &str = "offline_mode";
+/// Config key for the response code of unanswerable queries
+pub const CONFIG_KEY_OFFLINE_RESPONSE: &str = "offline_response";
+
+/// `answered_by` value for queries refused because of offline mode
+pub const OFFLINE_ANSWERED_BY: &str = "offline";
+
+/// Response code for queries that would need an upstream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OfflineResponse {
+    #[default]
+    NxDomain,
+    Refused,
+}
+
+impl OfflineResponse {
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "nxdomain" => Some(Self::NxDomain),
+            "refused" => Some(Self::Refused),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NxDomain => "nxdomain",
+            Self::Refused => "refused",
+        }
+    }
+}
+
+/// Offline mode settings
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct OfflineSettings {
+    pub enabled: bool,
+    pub response: OfflineResponse,
+}
+
+/// Offline mode switch consulted by the resolver before forwarding
+pub struct OfflineMode {
+    db: Option<Arc<Database>>,
+    settings: RwLock<OfflineSettings>,
+}
+
+#[allow(dead_code)]
+impl OfflineMode {
+    pub fn new(db: Option<Arc<Database>>) -> Self {
+        Self {
+            db,
+            settings: RwLock::new(OfflineSettings::default()),
+        }
+    }
+
+    /// Load settings from database
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let config = db.system_config();
+        let settings = OfflineSettings {
+            enabled: config
+                .get(CONFIG_KEY_OFFLINE_MODE)
+                .await?
+                .is_some_and(|v| v == "true"),
+            response: config
+                .get(CONFIG_KEY_OFFLINE_RESPONSE)
+                .await?
+                .and_then(|v| OfflineResponse::from_str(&v))
+                .unwrap_or_default(),
+        };
+
+        self.set_settings(settings);
+        Ok(())
+    }
+
+    /// Persist and apply settings
+    pub async fn save_settings(&self, settings: OfflineSettings) -> Result<()> {
+        if let Some(ref db) = self.db {
+            let config = db.system_config();
+            config
+                .set(CONFIG_KEY_OFFLINE_MODE, &settings.enabled.to_string())
+                .await?;
+            config
+                .set(CONFIG_KEY_OFFLINE_RESPONSE, settings.response.as_str())
+                .await?;
+        }
+        self.set_settings(settings);
+        Ok(())
+    }
+
+    pub fn settings(&self) -> OfflineSettings {
+        *self.settings.read().unwrap()
+    }
+
+    pub fn set_settings(&self, settings: OfflineSettings) {
+        *self.settings.write().unwrap() = settings;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.settings().enabled
+    }
+
+    /// Answer for a query that cannot be resolved locally
+    pub fn response(&self, id: u16) -> DnsResponse {
+        let response = match self.settings().response {
+            OfflineResponse::NxDomain => DnsResponse::nxdomain(id),
+            OfflineResponse::Refused => DnsResponse::refused(id),
+        };
+        response.with_extended_error(ExtendedError::new(ExtendedError::OTHER, "offline mode"))
+    }
+}
+
+impl Default for OfflineMode {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
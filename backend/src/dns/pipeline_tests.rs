@@ -0,0 +1,265 @@
+//! End-to-end pipeline tests
+//!
+//! Queries go over real UDP sockets through the listener, resolver and
+//! proxy to in-process mock upstreams, covering strategy fallback and
+//! cache behavior without network access.
+
+use std::time::Duration;
+
+use super::fail_policy::FailMode;
+use super::message::{DnsRecordData, DnsResponseCode, RecordType};
+use super::proxy::QueryStrategy;
+use super::test_support::{MockAnswer, MockUpstream, TestPipeline};
+
+#[tokio::test]
+async fn test_listener_forwards_and_caches() {
+    let upstream = MockUpstream::start().await;
+    upstream.answer_a("www.example.com", "192.0.2.10", 300);
+    let pipeline = TestPipeline::start(vec![upstream.upstream(1, "mock")], QueryStrategy::Concurrent).await;
+
+    let response = pipeline.query("www.example.com", RecordType::A).await;
+    assert_eq!(response.response_code, DnsResponseCode::NoError);
+    assert_eq!(response.answers.len(), 1);
+    assert_eq!(response.answers[0].value, "192.0.2.10");
+    assert_eq!(upstream.queries_for("www.example.com"), 1);
+
+    // The second query is answered from the cache
+    let response = pipeline.query("www.example.com", RecordType::A).await;
+    assert_eq!(response.answers[0].value, "192.0.2.10");
+    assert_eq!(upstream.queries_for("www.example.com"), 1);
+}
+
+#[tokio::test]
+async fn test_nxdomain_is_not_cached() {
+    let upstream = MockUpstream::start().await;
+    let pipeline = TestPipeline::start(vec![upstream.upstream(1, "mock")], QueryStrategy::Concurrent).await;
+
+    for _ in 0..2 {
+        let response = pipeline.query("missing.example.com", RecordType::A).await;
+        assert_eq!(response.response_code, DnsResponseCode::NxDomain);
+    }
+    assert_eq!(upstream.queries_for("missing.example.com"), 2);
+}
+
+#[tokio::test]
+async fn test_concurrent_skips_failing_upstream() {
+    let failing = MockUpstream::start().await;
+    failing.respond_to_all(MockAnswer::Rcode(DnsResponseCode::ServFail));
+    let working = MockUpstream::start().await;
+    // Answer after the failure so it is seen before the winner cancels the rest
+    working.answer_a("www.example.com", "192.0.2.20", 300)
+        .set_delay(Duration::from_millis(50));
+    let pipeline = TestPipeline::start(
+        vec![failing.upstream(1, "failing"), working.upstream(2, "working")],
+        QueryStrategy::Concurrent,
+    )
+    .await;
+
+    let response = pipeline.query("www.example.com", RecordType::A).await;
+    assert_eq!(response.response_code, DnsResponseCode::NoError);
+    assert_eq!(response.answers[0].value, "192.0.2.20");
+    assert_eq!(failing.queries(), 1);
+    assert_eq!(pipeline.upstream_manager.get_stats(1).await.unwrap().failures, 1);
+}
+
+#[tokio::test]
+async fn test_concurrent_prefers_faster_upstream() {
+    let slow = MockUpstream::start().await;
+    slow.answer_a("www.example.com", "192.0.2.1", 300)
+        .set_delay(Duration::from_millis(200));
+    let fast = MockUpstream::start().await;
+    fast.answer_a("www.example.com", "192.0.2.2", 300);
+    let pipeline = TestPipeline::start(
+        vec![slow.upstream(1, "slow"), fast.upstream(2, "fast")],
+        QueryStrategy::Concurrent,
+    )
+    .await;
+
+    let response = pipeline.query("www.example.com", RecordType::A).await;
+    assert_eq!(response.answers[0].value, "192.0.2.2");
+}
+
+#[tokio::test]
+async fn test_round_robin_fails_over_on_timeout() {
+    let silent = MockUpstream::start().await;
+    silent.respond_to_all(MockAnswer::Drop);
+    let backup = MockUpstream::start().await;
+    backup.respond(
+        "mail.example.com",
+        MockAnswer::Records(vec![DnsRecordData::mx("mail.example.com", "mx.example.com", 10, 300)]),
+    );
+    let pipeline = TestPipeline::start(
+        vec![silent.upstream(1, "silent"), backup.upstream(2, "backup")],
+        QueryStrategy::RoundRobin,
+    )
+    .await;
+
+    // The first round-robin pick is the silent server; the query fails over
+    let response = pipeline.query("mail.example.com", RecordType::MX).await;
+    assert_eq!(response.response_code, DnsResponseCode::NoError);
+    assert_eq!(response.answers.len(), 1);
+    assert_eq!(silent.queries(), 1);
+    assert_eq!(backup.queries(), 1);
+    assert_eq!(pipeline.upstream_manager.get_stats(1).await.unwrap().failures, 1);
+}
+
+#[tokio::test]
+async fn test_all_upstreams_failing_returns_servfail() {
+    let upstream = MockUpstream::start().await;
+    upstream.respond_to_all(MockAnswer::Drop);
+    let pipeline = TestPipeline::start(vec![upstream.upstream(1, "silent")], QueryStrategy::Concurrent).await;
+
+    let response = pipeline.query("www.example.com", RecordType::A).await;
+    assert_eq!(response.response_code, DnsResponseCode::ServFail);
+}
+
+#[tokio::test]
+async fn test_resolution_deadline_answers_servfail() {
+    let upstream = MockUpstream::start().await;
+    // Slower than the deadline but within the upstream timeout
+    upstream.answer_a("www.example.com", "192.0.2.40", 300)
+        .set_delay(Duration::from_millis(250));
+    let pipeline = TestPipeline::start(vec![upstream.upstream(1, "slow")], QueryStrategy::Concurrent).await;
+    pipeline.resolver.deadline().set_timeout_ms(100);
+
+    let response = pipeline.query("www.example.com", RecordType::A).await;
+    assert_eq!(response.response_code, DnsResponseCode::ServFail);
+    assert_eq!(pipeline.resolver.deadline().stats().timeouts, 1);
+
+    // Without a deadline the slow answer gets through
+    pipeline.resolver.deadline().set_timeout_ms(0);
+    let response = pipeline.query("www.example.com", RecordType::A).await;
+    assert_eq!(response.answers[0].value, "192.0.2.40");
+    assert_eq!(pipeline.resolver.deadline().stats().timeouts, 1);
+}
+
+#[tokio::test]
+async fn test_fastest_probes_all_upstreams_without_stats() {
+    let first = MockUpstream::start().await;
+    first.answer_a("www.example.com", "192.0.2.1", 300)
+        .set_delay(Duration::from_millis(50));
+    let second = MockUpstream::start().await;
+    second.answer_a("www.example.com", "192.0.2.1", 300)
+        .set_delay(Duration::from_millis(50));
+    let pipeline = TestPipeline::start(
+        vec![first.upstream(1, "first"), second.upstream(2, "second")],
+        QueryStrategy::Fastest,
+    )
+    .await;
+
+    // Without response time history the fastest strategy queries every server
+    let response = pipeline.query("www.example.com", RecordType::A).await;
+    assert_eq!(response.answers[0].value, "192.0.2.1");
+    assert_eq!(first.queries() + second.queries(), 2);
+}
+
+#[tokio::test]
+async fn test_drained_upstream_gets_no_queries() {
+    let drained = MockUpstream::start().await;
+    let active = MockUpstream::start().await;
+    active.answer_a("www.example.com", "192.0.2.30", 300);
+    let mut maintenance = drained.upstream(1, "drained");
+    maintenance.drained = true;
+    let pipeline = TestPipeline::start(
+        vec![maintenance, active.upstream(2, "active")],
+        QueryStrategy::Concurrent,
+    )
+    .await;
+
+    let response = pipeline.query("www.example.com", RecordType::A).await;
+    assert_eq!(response.answers[0].value, "192.0.2.30");
+    assert_eq!(drained.queries(), 0);
+}
+
+#[tokio::test]
+async fn test_companion_prefetch_fills_cache() {
+    let upstream = MockUpstream::start().await;
+    upstream.answer_a("www.example.com", "192.0.2.50", 300);
+    let pipeline = TestPipeline::start(vec![upstream.upstream(1, "mock")], QueryStrategy::Concurrent).await;
+    pipeline.resolver.companion().set_enabled(true);
+
+    pipeline.query("www.example.com", RecordType::A).await;
+    // The AAAA lookup runs in the background
+    for _ in 0..50 {
+        if upstream.queries_for("www.example.com") == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(upstream.queries_for("www.example.com"), 2);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let response = pipeline.query("www.example.com", RecordType::AAAA).await;
+    assert_eq!(response.response_code, DnsResponseCode::NoError);
+    assert_eq!(upstream.queries_for("www.example.com"), 2);
+
+    let stats = pipeline.resolver.companion().stats();
+    assert_eq!(stats.prefetches, 1);
+    assert_eq!(stats.hits, 1);
+}
+
+#[tokio::test]
+async fn test_fail_policy_while_rewrite_rules_are_unavailable() {
+    let upstream = MockUpstream::start().await;
+    upstream.answer_a("www.example.com", "192.0.2.10", 300);
+    let pipeline = TestPipeline::start(vec![upstream.upstream(1, "mock")], QueryStrategy::Concurrent).await;
+    pipeline.resolver.rewrite_engine().record_load_failure("database is locked");
+
+    // Fail closed: nothing cached yet, so the query is refused
+    pipeline.resolver.fail_policy().set_mode(FailMode::Closed);
+    let response = pipeline.query("www.example.com", RecordType::A).await;
+    assert_eq!(response.response_code, DnsResponseCode::Refused);
+    assert_eq!(upstream.queries_for("www.example.com"), 0);
+
+    // Fail open: resolved normally
+    pipeline.resolver.fail_policy().set_mode(FailMode::Open);
+    let response = pipeline.query("www.example.com", RecordType::A).await;
+    assert_eq!(response.answers[0].value, "192.0.2.10");
+    assert_eq!(upstream.queries_for("www.example.com"), 1);
+
+    // Fail closed again: the cached answer is still served
+    pipeline.resolver.fail_policy().set_mode(FailMode::Closed);
+    let response = pipeline.query("www.example.com", RecordType::A).await;
+    assert_eq!(response.answers[0].value, "192.0.2.10");
+    assert_eq!(upstream.queries_for("www.example.com"), 1);
+
+    let status = pipeline.resolver.fail_policy_status();
+    assert!(status.degraded);
+    assert_eq!((status.refused, status.bypassed, status.served_from_cache), (1, 1, 1));
+}
+
+#[tokio::test]
+async fn test_listener_resolution_modes() {
+    use std::collections::HashMap;
+    use super::resolution_mode::ResolutionMode;
+
+    let upstream = MockUpstream::start().await;
+    upstream.answer_a("www.example.com", "192.0.2.10", 300);
+    upstream.answer_a("other.example.com", "192.0.2.20", 300);
+    let pipeline = TestPipeline::start(vec![upstream.upstream(1, "mock")], QueryStrategy::Concurrent).await;
+    let set_mode = |mode| {
+        pipeline
+            .resolver
+            .listener_modes()
+            .set_modes(HashMap::from([("udp".to_string(), mode)]));
+    };
+
+    let response = pipeline.query("www.example.com", RecordType::A).await;
+    assert_eq!(response.answers[0].value, "192.0.2.10");
+
+    // Cache-only: cached answers are served, misses are refused
+    set_mode(ResolutionMode::CacheOnly);
+    let response = pipeline.query("www.example.com", RecordType::A).await;
+    assert_eq!(response.answers[0].value, "192.0.2.10");
+    let response = pipeline.query("other.example.com", RecordType::A).await;
+    assert_eq!(response.response_code, DnsResponseCode::Refused);
+    assert!(response.recursion_available);
+    assert_eq!(upstream.queries_for("other.example.com"), 0);
+
+    // Authoritative-only: the cache is not consulted and RA is cleared
+    set_mode(ResolutionMode::AuthoritativeOnly);
+    let response = pipeline.query("www.example.com", RecordType::A).await;
+    assert_eq!(response.response_code, DnsResponseCode::Refused);
+    assert!(!response.recursion_available);
+    assert_eq!(upstream.queries_for("www.example.com"), 1);
+}
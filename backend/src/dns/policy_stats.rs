@@ -0,0 +1,495 @@
+//! Policy statistics
+//!
+//! Counts client queries that were answered by policy instead of being
+//! resolved normally: blocked (NXDOMAIN from a block rule or a filtering
+//! middleware), remapped (answered with a rewrite target) or answered from
+//! local records. Counts are kept per rule, per domain category and per
+//! client group (tenant) over the last hour and the last day, so the
+//! dashboard can show "12,304 ads blocked today" without scanning the
+//! query log.
+//!
+//! Each series is a ring of one-minute buckets for the hour and one-hour
+//! buckets for the day, updated on every query. The day window therefore
+//! covers the last 23 to 24 hours depending on the current hour.
+//!
+//! Blocks are also counted since startup per rule and per category, for
+//! export as Prometheus counters.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+use chrono::Utc;
+use serde::Serialize;
+
+use super::client_acl::CLIENT_ACL_ANSWERED_BY;
+use super::extended_error::ExtendedError;
+use super::message::{DnsResponse, DnsResponseCode};
+use super::resolver::ResolveResult;
+use super::rewrite::RewriteAction;
+
+const MINUTE_BUCKETS: usize = 60;
+const HOUR_BUCKETS: usize = 24;
+
+/// Most rules or categories with their own block counter; blocks beyond
+/// are only counted as "other"
+const MAX_BLOCK_COUNTERS: usize = 10_000;
+
+/// Middleware whose answers are not policy decisions
+const NON_POLICY_MIDDLEWARE: &[&str] = &["domain_validation", CLIENT_ACL_ANSWERED_BY];
+
+/// How policy answered a query
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyOutcome {
+    Blocked,
+    Remapped,
+    Local,
+}
+
+/// What answered a query by policy
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PolicySource {
+    /// Rewrite rule by ID
+    Rewrite(i64),
+    /// Local DNS records
+    LocalRecord,
+    /// Middleware by name, e.g. `category_filter`
+    Middleware(String),
+}
+
+impl PolicySource {
+    /// Source kind for the API
+    pub fn kind(&self) -> &str {
+        match self {
+            PolicySource::Rewrite(_) => "rewrite",
+            PolicySource::LocalRecord => "local_record",
+            PolicySource::Middleware(name) => name,
+        }
+    }
+}
+
+/// A policy decision recorded in the query metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyMatch {
+    pub outcome: PolicyOutcome,
+    pub source: PolicySource,
+}
+
+impl PolicyMatch {
+    pub fn rewrite(rule_id: i64, action: &RewriteAction) -> Self {
+        let outcome = match action {
+            RewriteAction::Block | RewriteAction::NoData => PolicyOutcome::Blocked,
+            // The engine never answers with a passthru rule; they only exempt names
+            RewriteAction::MapToIp(_) | RewriteAction::MapToDomain(_) | RewriteAction::Passthru => {
+                PolicyOutcome::Remapped
+            }
+        };
+        Self {
+            outcome,
+            source: PolicySource::Rewrite(rule_id),
+        }
+    }
+
+    pub fn local_record() -> Self {
+        Self {
+            outcome: PolicyOutcome::Local,
+            source: PolicySource::LocalRecord,
+        }
+    }
+
+    /// Extended error for blocked answers (RFC 8914 Blocked)
+    pub fn extended_error(&self) -> Option<ExtendedError> {
+        if self.outcome != PolicyOutcome::Blocked {
+            return None;
+        }
+        let text = match &self.source {
+            PolicySource::Rewrite(id) => format!("blocked by rewrite rule {}", id),
+            source => format!("blocked by {}", source.kind()),
+        };
+        Some(ExtendedError::new(ExtendedError::BLOCKED, text))
+    }
+
+    /// A middleware answer; answers with records are remaps, the rest blocks
+    pub fn middleware(name: &str, response: &DnsResponse) -> Option<Self> {
+        if NON_POLICY_MIDDLEWARE.contains(&name) {
+            return None;
+        }
+        let outcome = if !response.answers.is_empty() {
+            PolicyOutcome::Remapped
+        } else if matches!(
+            response.response_code,
+            DnsResponseCode::NxDomain | DnsResponseCode::Refused
+        ) {
+            PolicyOutcome::Blocked
+        } else {
+            return None;
+        };
+        Some(Self {
+            outcome,
+            source: PolicySource::Middleware(name.to_string()),
+        })
+    }
+}
+
+/// Query counts by outcome
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PolicyCounts {
+    pub blocked: u64,
+    pub remapped: u64,
+    pub local: u64,
+}
+
+impl PolicyCounts {
+    fn add(&mut self, outcome: PolicyOutcome) {
+        match outcome {
+            PolicyOutcome::Blocked => self.blocked += 1,
+            PolicyOutcome::Remapped => self.remapped += 1,
+            PolicyOutcome::Local => self.local += 1,
+        }
+    }
+
+    fn merge(&mut self, other: &PolicyCounts) {
+        self.blocked += other.blocked;
+        self.remapped += other.remapped;
+        self.local += other.local;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.blocked + self.remapped + self.local
+    }
+}
+
+/// Counts over the last hour and the last day
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PolicyWindows {
+    pub last_hour: PolicyCounts,
+    pub last_day: PolicyCounts,
+}
+
+/// Ring buffers of per-minute and per-hour counts
+#[derive(Debug, Clone)]
+struct Series {
+    minutes: [(i64, PolicyCounts); MINUTE_BUCKETS],
+    hours: [(i64, PolicyCounts); HOUR_BUCKETS],
+}
+
+impl Default for Series {
+    fn default() -> Self {
+        Self {
+            minutes: [(-1, PolicyCounts::default()); MINUTE_BUCKETS],
+            hours: [(-1, PolicyCounts::default()); HOUR_BUCKETS],
+        }
+    }
+}
+
+impl Series {
+    fn add(&mut self, outcome: PolicyOutcome, now: i64) {
+        let minute = now.div_euclid(60);
+        let hour = now.div_euclid(3600);
+        for (slot, stamp) in [
+            (&mut self.minutes[minute.rem_euclid(MINUTE_BUCKETS as i64) as usize], minute),
+            (&mut self.hours[hour.rem_euclid(HOUR_BUCKETS as i64) as usize], hour),
+        ] {
+            if slot.0 != stamp {
+                *slot = (stamp, PolicyCounts::default());
+            }
+            slot.1.add(outcome);
+        }
+    }
+
+    fn windows(&self, now: i64) -> PolicyWindows {
+        let minute = now.div_euclid(60);
+        let hour = now.div_euclid(3600);
+        let sum = |buckets: &[(i64, PolicyCounts)], current: i64| {
+            let oldest = current - buckets.len() as i64;
+            let mut counts = PolicyCounts::default();
+            for (_, c) in buckets.iter().filter(|(stamp, _)| *stamp > oldest && *stamp <= current) {
+                counts.merge(c);
+            }
+            counts
+        };
+        PolicyWindows {
+            last_hour: sum(&self.minutes, minute),
+            last_day: sum(&self.hours, hour),
+        }
+    }
+}
+
+/// Series keyed by rule, category or client group
+#[derive(Debug)]
+struct Breakdown<K> {
+    series: HashMap<K, Series>,
+}
+
+impl<K: Clone + Eq + Hash> Breakdown<K> {
+    fn new() -> Self {
+        Self {
+            series: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, key: K, outcome: PolicyOutcome, now: i64) {
+        self.series.entry(key).or_default().add(outcome, now);
+    }
+
+    /// Windows for every key seen in the last day; idle keys are dropped
+    fn windows(&mut self, now: i64) -> Vec<(K, PolicyWindows)> {
+        let mut windows = Vec::new();
+        self.series.retain(|key, series| {
+            let w = series.windows(now);
+            if w.last_day.total() == 0 {
+                return false;
+            }
+            windows.push((key.clone(), w));
+            true
+        });
+        windows
+    }
+}
+
+/// Blocks since startup by key, with a bounded number of keys
+#[derive(Debug)]
+struct BlockCounter<K> {
+    counts: HashMap<K, u64>,
+    /// Blocks of keys past [`MAX_BLOCK_COUNTERS`]
+    overflow: u64,
+}
+
+impl<K: Clone + Eq + Hash> BlockCounter<K> {
+    fn new() -> Self {
+        Self {
+            counts: HashMap::new(),
+            overflow: 0,
+        }
+    }
+
+    fn add(&mut self, key: &K) {
+        if let Some(count) = self.counts.get_mut(key) {
+            *count += 1;
+        } else if self.counts.len() < MAX_BLOCK_COUNTERS {
+            self.counts.insert(key.clone(), 1);
+        } else {
+            self.overflow += 1;
+        }
+    }
+
+    fn top(&self, n: usize) -> TopBlocks<K> {
+        let mut top: Vec<(K, u64)> = self.counts.iter().map(|(k, c)| (k.clone(), *c)).collect();
+        top.sort_by_key(|(_, c)| Reverse(*c));
+        let other = top.iter().skip(n).map(|(_, c)| c).sum::<u64>() + self.overflow;
+        top.truncate(n);
+        TopBlocks { top, other }
+    }
+}
+
+/// Busiest keys by blocks since startup, the rest summed up
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TopBlocks<K> {
+    pub top: Vec<(K, u64)>,
+    pub other: u64,
+}
+
+struct Counters {
+    total: Series,
+    by_rule: Breakdown<PolicySource>,
+    by_category: Breakdown<String>,
+    by_tenant: Breakdown<Option<i64>>,
+    blocks_by_rule: BlockCounter<PolicySource>,
+    blocks_by_category: BlockCounter<String>,
+}
+
+/// Policy counters at one point in time
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PolicySnapshot {
+    pub total: PolicyWindows,
+    pub by_rule: Vec<(PolicySource, PolicyWindows)>,
+    pub by_category: Vec<(String, PolicyWindows)>,
+    pub by_tenant: Vec<(Option<i64>, PolicyWindows)>,
+}
+
+/// Rolling policy counters for client queries
+pub struct PolicyStats {
+    counters: Mutex<Counters>,
+}
+
+impl Default for PolicyStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PolicyStats {
+    pub fn new() -> Self {
+        Self {
+            counters: Mutex::new(Counters {
+                total: Series::default(),
+                by_rule: Breakdown::new(),
+                by_category: Breakdown::new(),
+                by_tenant: Breakdown::new(),
+                blocks_by_rule: BlockCounter::new(),
+                blocks_by_category: BlockCounter::new(),
+            }),
+        }
+    }
+
+    /// Count a resolved client query; queries not answered by policy are ignored
+    pub fn record(&self, result: &ResolveResult, tenant_id: Option<i64>) {
+        self.record_at(result, tenant_id, Utc::now().timestamp());
+    }
+
+    fn record_at(&self, result: &ResolveResult, tenant_id: Option<i64>, now: i64) {
+        let Some(ref policy) = result.metadata.policy else {
+            return;
+        };
+        let outcome = policy.outcome;
+        let mut counters = self.counters.lock().unwrap();
+        counters.total.add(outcome, now);
+        counters.by_rule.add(policy.source.clone(), outcome, now);
+        if let Some(ref category) = result.metadata.category {
+            counters.by_category.add(category.clone(), outcome, now);
+        }
+        counters.by_tenant.add(tenant_id, outcome, now);
+        if outcome == PolicyOutcome::Blocked {
+            counters.blocks_by_rule.add(&policy.source);
+            if let Some(ref category) = result.metadata.category {
+                counters.blocks_by_category.add(category);
+            }
+        }
+    }
+
+    /// Blocks since startup of the `n` busiest rules and categories
+    pub fn top_blocks(&self, n: usize) -> (TopBlocks<PolicySource>, TopBlocks<String>) {
+        let counters = self.counters.lock().unwrap();
+        (counters.blocks_by_rule.top(n), counters.blocks_by_category.top(n))
+    }
+
+    /// Current counts, busiest rules, categories and groups first
+    pub fn snapshot(&self) -> PolicySnapshot {
+        self.snapshot_at(Utc::now().timestamp())
+    }
+
+    fn snapshot_at(&self, now: i64) -> PolicySnapshot {
+        fn busiest_first<K>(mut entries: Vec<(K, PolicyWindows)>) -> Vec<(K, PolicyWindows)> {
+            entries.sort_by_key(|e| Reverse(e.1.last_day.total()));
+            entries
+        }
+
+        let mut counters = self.counters.lock().unwrap();
+        PolicySnapshot {
+            total: counters.total.windows(now),
+            by_rule: busiest_first(counters.by_rule.windows(now)),
+            by_category: busiest_first(counters.by_category.windows(now)),
+            by_tenant: busiest_first(counters.by_tenant.windows(now)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::resolver::QueryMetadata;
+
+    fn result(policy: Option<PolicyMatch>, category: Option<&str>) -> ResolveResult {
+        ResolveResult {
+            response: DnsResponse::nxdomain(1),
+            metadata: QueryMetadata {
+                policy,
+                category: category.map(str::to_string),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn blocked(rule_id: i64) -> Option<PolicyMatch> {
+        Some(PolicyMatch::rewrite(rule_id, &RewriteAction::Block))
+    }
+
+    #[test]
+    fn test_rewrite_outcomes() {
+        assert_eq!(PolicyMatch::rewrite(1, &RewriteAction::Block).outcome, PolicyOutcome::Blocked);
+        let remap = PolicyMatch::rewrite(1, &RewriteAction::MapToIp("10.0.0.1".parse().unwrap()));
+        assert_eq!(remap.outcome, PolicyOutcome::Remapped);
+        assert_eq!(remap.source.kind(), "rewrite");
+    }
+
+    #[test]
+    fn test_middleware_outcomes() {
+        let nxdomain = DnsResponse::nxdomain(1);
+        let policy = PolicyMatch::middleware("category_filter", &nxdomain).unwrap();
+        assert_eq!(policy.outcome, PolicyOutcome::Blocked);
+        assert_eq!(policy.source.kind(), "category_filter");
+
+        // Malformed names are refused, but not by policy
+        assert!(PolicyMatch::middleware("domain_validation", &nxdomain).is_none());
+    }
+
+    #[test]
+    fn test_breakdowns() {
+        let stats = PolicyStats::new();
+        let now = 1_700_000_000;
+        stats.record_at(&result(blocked(1), Some("advertising")), None, now);
+        stats.record_at(&result(blocked(1), Some("advertising")), Some(2), now);
+        stats.record_at(&result(blocked(2), None), Some(2), now);
+        stats.record_at(&result(Some(PolicyMatch::local_record()), None), Some(2), now);
+        // Normal resolution is not counted
+        stats.record_at(&result(None, Some("advertising")), None, now);
+
+        let snapshot = stats.snapshot_at(now);
+        assert_eq!(
+            snapshot.total.last_hour,
+            PolicyCounts { blocked: 3, remapped: 0, local: 1 }
+        );
+        assert_eq!(snapshot.by_rule[0].0, PolicySource::Rewrite(1));
+        assert_eq!(snapshot.by_rule[0].1.last_day.blocked, 2);
+        assert_eq!(snapshot.by_category, vec![(
+            "advertising".to_string(),
+            PolicyWindows {
+                last_hour: PolicyCounts { blocked: 2, ..Default::default() },
+                last_day: PolicyCounts { blocked: 2, ..Default::default() },
+            }
+        )]);
+        assert_eq!(snapshot.by_tenant[0].0, Some(2));
+        assert_eq!(snapshot.by_tenant[0].1.last_day.total(), 3);
+    }
+
+    #[test]
+    fn test_windows_expire() {
+        let stats = PolicyStats::new();
+        let now = 1_700_000_000;
+        stats.record_at(&result(blocked(1), None), None, now);
+        stats.record_at(&result(blocked(1), None), None, now + 1800);
+
+        // Half an hour later both count; after an hour only the second
+        let snapshot = stats.snapshot_at(now + 1800);
+        assert_eq!(snapshot.total.last_hour.blocked, 2);
+        let snapshot = stats.snapshot_at(now + 3600);
+        assert_eq!(snapshot.total.last_hour.blocked, 1);
+        assert_eq!(snapshot.total.last_day.blocked, 2);
+
+        // A day later nothing is left and idle rules are dropped
+        let snapshot = stats.snapshot_at(now + 2 * 86400);
+        assert_eq!(snapshot.total, PolicyWindows::default());
+        assert!(snapshot.by_rule.is_empty());
+    }
+
+    #[test]
+    fn test_top_blocks() {
+        let stats = PolicyStats::new();
+        let now = 1_700_000_000;
+        for _ in 0..3 {
+            stats.record_at(&result(blocked(1), Some("malware")), None, now);
+        }
+        stats.record_at(&result(blocked(2), Some("advertising")), None, now);
+        stats.record_at(&result(blocked(3), None), None, now);
+        stats.record_at(&result(Some(PolicyMatch::local_record()), Some("malware")), None, now);
+
+        // Block counters never expire
+        let snapshot = stats.snapshot_at(now + 2 * 86400);
+        assert!(snapshot.by_rule.is_empty());
+        let (rules, categories) = stats.top_blocks(1);
+        assert_eq!(rules, TopBlocks { top: vec![(PolicySource::Rewrite(1), 3)], other: 2 });
+        assert_eq!(categories, TopBlocks { top: vec![("malware".to_string(), 3)], other: 1 });
+    }
+}
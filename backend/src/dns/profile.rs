@@ -0,0 +1,607 @@
+//! Resolution profiles
+//!
+//! A profile bundles split DNS routes (`suffix=upstream`) with a preferred
+//! upstream set, e.g. an "office" profile that sends `corp.example.com` to
+//! the VPN resolver and a "home" profile that uses public upstreams only.
+//! At most one profile is active; without one the resolver behaves as if
+//! the feature did not exist.
+//!
+//! Profiles can be activated through the API, or automatically: each probe
+//! round connects to every profile's `probe_target` and activates the
+//! highest priority profile that is reachable (profiles without a probe are
+//! always reachable, which makes them a natural fallback). The DNS cache is
+//! cleared on every switch so answers from the previous network do not leak
+//! into the new one.
+//!
+//! A listener can also be pinned to a profile, e.g. DoH for guests through
+//! filtering upstreams while UDP on the LAN stays unfiltered. Pinned
+//! profiles apply whatever profile is active, and their answers are cached
+//! separately.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::db::{Database, ResolutionProfile};
+use super::cache::CacheManager;
+use super::middleware::{HookOutcome, QueryContext, ResolverMiddleware};
+use super::name::normalize_name;
+use super::proxy::UpstreamManager;
+
+/// Config key for the active profile ID (empty when none is active)
+pub const CONFIG_KEY_ACTIVE_PROFILE: &str = "active_profile_id";
+/// Config key for automatic switching
+pub const CONFIG_KEY_PROFILE_AUTO_SWITCH: &str = "profile_auto_switch";
+/// Config key for the probe interval
+pub const CONFIG_KEY_PROFILE_PROBE_INTERVAL: &str = "profile_probe_interval_secs";
+
+/// Shortest accepted probe interval
+pub const MIN_PROBE_INTERVAL_SECS: u64 = 5;
+
+const DEFAULT_PROBE_INTERVAL_SECS: u64 = 30;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A split DNS route: names under `suffix` go to `upstream`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProfileRule {
+    pub suffix: String,
+    pub upstream: String,
+}
+
+impl ProfileRule {
+    /// Whether `name` is the suffix itself or a subdomain of it
+    pub fn matches(&self, name: &str) -> bool {
+        name == self.suffix
+            || name
+                .strip_suffix(self.suffix.as_str())
+                .is_some_and(|prefix| prefix.ends_with('.'))
+    }
+}
+
+/// Parse comma-separated `suffix=upstream` routes
+pub fn parse_rules(rules: &str) -> Result<Vec<ProfileRule>> {
+    rules
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|entry| {
+            let (suffix, upstream) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid route '{}', expected suffix=upstream", entry))?;
+            let suffix = normalize_name(suffix.trim().trim_start_matches("*."));
+            let upstream = upstream.trim().to_string();
+            if suffix.is_empty() || upstream.is_empty() {
+                return Err(anyhow!("Invalid route '{}', expected suffix=upstream", entry));
+            }
+            Ok(ProfileRule { suffix, upstream })
+        })
+        .collect()
+}
+
+/// Parse a probe target, which must be a literal `ip:port`
+///
+/// Host names are rejected on purpose: resolving them could go through this
+/// very resolver and make the probe depend on the profile it selects.
+pub fn parse_probe_target(target: &str) -> Result<SocketAddr> {
+    target
+        .trim()
+        .parse::<SocketAddr>()
+        .map_err(|_| anyhow!("Invalid probe target '{}', expected ip:port", target))
+}
+
+/// In-memory form of a profile
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileView {
+    pub id: i64,
+    pub name: String,
+    /// Preferred upstreams, in order
+    pub upstreams: Vec<String>,
+    /// Routes, most specific suffix first
+    pub rules: Vec<ProfileRule>,
+    pub probe_target: Option<SocketAddr>,
+    pub priority: i64,
+}
+
+impl ProfileView {
+    /// Build from database model, skipping malformed routes and probes
+    pub fn from_db(profile: &ResolutionProfile) -> Self {
+        let upstreams = profile
+            .upstreams
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        let mut rules = parse_rules(&profile.rules).unwrap_or_else(|e| {
+            warn!("Ignoring routes of profile {}: {}", profile.name, e);
+            Vec::new()
+        });
+        rules.sort_by_key(|r| std::cmp::Reverse(r.suffix.len()));
+
+        let probe_target = profile
+            .probe_target
+            .as_deref()
+            .filter(|t| !t.trim().is_empty())
+            .and_then(|t| match parse_probe_target(t) {
+                Ok(addr) => Some(addr),
+                Err(e) => {
+                    warn!("Ignoring probe of profile {}: {}", profile.name, e);
+                    None
+                }
+            });
+
+        Self {
+            id: profile.id,
+            name: profile.name.clone(),
+            upstreams,
+            rules,
+            probe_target,
+            priority: profile.priority,
+        }
+    }
+
+    /// Upstream a query name is routed to by this profile's rules
+    pub fn route(&self, name: &str) -> Option<&str> {
+        let name = normalize_name(name);
+        self.rules
+            .iter()
+            .find(|r| r.matches(&name))
+            .map(|r| r.upstream.as_str())
+    }
+}
+
+/// Automatic switching settings
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ProfileSettings {
+    pub auto_switch: bool,
+    pub probe_interval_secs: u64,
+}
+
+impl Default for ProfileSettings {
+    fn default() -> Self {
+        Self {
+            auto_switch: false,
+            probe_interval_secs: DEFAULT_PROBE_INTERVAL_SECS,
+        }
+    }
+}
+
+/// Outcome of probing one profile
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    pub profile_id: i64,
+    pub profile: String,
+    pub target: Option<String>,
+    pub reachable: bool,
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Pick the profile to activate from probe results
+///
+/// `profiles` must be ordered by priority (highest first); the first
+/// reachable one wins.
+pub fn choose_profile(profiles: &[ProfileView], results: &[ProbeResult]) -> Option<i64> {
+    profiles
+        .iter()
+        .find(|p| results.iter().any(|r| r.profile_id == p.id && r.reachable))
+        .map(|p| p.id)
+}
+
+/// Profile registry, switcher and resolver middleware
+pub struct ProfileRouter {
+    db: Option<Arc<Database>>,
+    upstream_manager: Arc<UpstreamManager>,
+    cache: Arc<CacheManager>,
+    settings: RwLock<ProfileSettings>,
+    /// All profiles, highest priority first
+    profiles: RwLock<Vec<ProfileView>>,
+    active: RwLock<Option<i64>>,
+    /// Profile pinned per listener protocol
+    listener_profiles: RwLock<HashMap<String, i64>>,
+    last_probe: RwLock<Vec<ProbeResult>>,
+}
+
+#[allow(dead_code)]
+impl ProfileRouter {
+    pub fn new(db: Option<Arc<Database>>, upstream_manager: Arc<UpstreamManager>, cache: Arc<CacheManager>) -> Self {
+        Self {
+            db,
+            upstream_manager,
+            cache,
+            settings: RwLock::new(ProfileSettings::default()),
+            profiles: RwLock::new(Vec::new()),
+            active: RwLock::new(None),
+            listener_profiles: RwLock::new(HashMap::new()),
+            last_probe: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Load profiles, the active profile and settings from database
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let config = db.system_config();
+        let settings = ProfileSettings {
+            auto_switch: config
+                .get(CONFIG_KEY_PROFILE_AUTO_SWITCH)
+                .await?
+                .is_some_and(|v| v == "true"),
+            probe_interval_secs: config
+                .get(CONFIG_KEY_PROFILE_PROBE_INTERVAL)
+                .await?
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(DEFAULT_PROBE_INTERVAL_SECS)
+                .max(MIN_PROBE_INTERVAL_SECS),
+        };
+        let active = config
+            .get(CONFIG_KEY_ACTIVE_PROFILE)
+            .await?
+            .and_then(|v| v.parse::<i64>().ok());
+        let profiles: Vec<ProfileView> = db
+            .resolution_profiles()
+            .list()
+            .await?
+            .iter()
+            .map(ProfileView::from_db)
+            .collect();
+
+        // A deleted profile cannot stay active
+        let active = active.filter(|id| profiles.iter().any(|p| p.id == *id));
+        let listener_profiles = db
+            .server_listeners()
+            .list()
+            .await?
+            .into_iter()
+            .filter_map(|l| Some((l.protocol, l.profile_id?)))
+            .filter(|(_, id)| profiles.iter().any(|p| p.id == *id))
+            .collect();
+
+        *self.settings.write().unwrap() = settings;
+        *self.profiles.write().unwrap() = profiles;
+        *self.active.write().unwrap() = active;
+        *self.listener_profiles.write().unwrap() = listener_profiles;
+        Ok(())
+    }
+
+    /// Reload profiles and settings from database
+    pub async fn reload(&self) -> Result<()> {
+        self.load().await
+    }
+
+    pub fn settings(&self) -> ProfileSettings {
+        *self.settings.read().unwrap()
+    }
+
+    /// Persist and apply automatic switching settings
+    pub async fn save_settings(&self, settings: ProfileSettings) -> Result<()> {
+        if let Some(ref db) = self.db {
+            let config = db.system_config();
+            config
+                .set(CONFIG_KEY_PROFILE_AUTO_SWITCH, &settings.auto_switch.to_string())
+                .await?;
+            config
+                .set(CONFIG_KEY_PROFILE_PROBE_INTERVAL, &settings.probe_interval_secs.to_string())
+                .await?;
+        }
+        *self.settings.write().unwrap() = settings;
+        Ok(())
+    }
+
+    /// Replace the profiles (in-memory only)
+    pub fn set_profiles(&self, mut profiles: Vec<ProfileView>) {
+        profiles.sort_by_key(|p| std::cmp::Reverse(p.priority));
+        *self.profiles.write().unwrap() = profiles;
+    }
+
+    pub fn profiles(&self) -> Vec<ProfileView> {
+        self.profiles.read().unwrap().clone()
+    }
+
+    /// The active profile, if any
+    pub fn active(&self) -> Option<ProfileView> {
+        let active = (*self.active.read().unwrap())?;
+        self.profile(active)
+    }
+
+    /// Pin profiles to listener protocols (in-memory only)
+    pub fn set_listener_profiles(&self, listener_profiles: HashMap<String, i64>) {
+        *self.listener_profiles.write().unwrap() = listener_profiles;
+    }
+
+    /// Profile pinned to a listener protocol, if any
+    pub fn listener_profile(&self, listener: &str) -> Option<i64> {
+        self.listener_profiles.read().unwrap().get(listener).copied()
+    }
+
+    fn profile(&self, id: i64) -> Option<ProfileView> {
+        self.profiles.read().unwrap().iter().find(|p| p.id == id).cloned()
+    }
+
+    /// Results of the most recent probe round
+    pub fn last_probe(&self) -> Vec<ProbeResult> {
+        self.last_probe.read().unwrap().clone()
+    }
+
+    /// Activate a profile (`None` deactivates profiles)
+    ///
+    /// Returns whether the active profile changed. The DNS cache is cleared
+    /// on change.
+    pub async fn activate(&self, id: Option<i64>) -> Result<bool> {
+        let name = match id {
+            Some(id) => Some(
+                self.profiles
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .find(|p| p.id == id)
+                    .map(|p| p.name.clone())
+                    .ok_or_else(|| anyhow!("Profile {} not found", id))?,
+            ),
+            None => None,
+        };
+
+        if *self.active.read().unwrap() == id {
+            return Ok(false);
+        }
+
+        if let Some(ref db) = self.db {
+            let value = id.map(|id| id.to_string()).unwrap_or_default();
+            db.system_config().set(CONFIG_KEY_ACTIVE_PROFILE, &value).await?;
+        }
+        *self.active.write().unwrap() = id;
+        self.cache.clear().await;
+
+        info!(
+            "[Profile] Active resolution profile: {}",
+            name.as_deref().unwrap_or("none")
+        );
+        Ok(true)
+    }
+
+    /// Probe every profile once
+    pub async fn probe_all(&self) -> Vec<ProbeResult> {
+        let profiles = self.profiles();
+        let probes = profiles.iter().map(|profile| async move {
+            let Some(target) = profile.probe_target else {
+                return ProbeResult {
+                    profile_id: profile.id,
+                    profile: profile.name.clone(),
+                    target: None,
+                    reachable: true,
+                    latency_ms: None,
+                    error: None,
+                };
+            };
+
+            let start = Instant::now();
+            let outcome = tokio::time::timeout(PROBE_TIMEOUT, tokio::net::TcpStream::connect(target)).await;
+            let error = match outcome {
+                Ok(Ok(_)) => None,
+                Ok(Err(e)) => Some(e.to_string()),
+                Err(_) => Some("timed out".to_string()),
+            };
+            ProbeResult {
+                profile_id: profile.id,
+                profile: profile.name.clone(),
+                target: Some(target.to_string()),
+                reachable: error.is_none(),
+                latency_ms: error.is_none().then(|| start.elapsed().as_millis() as u64),
+                error,
+            }
+        });
+
+        let results = futures::future::join_all(probes).await;
+        *self.last_probe.write().unwrap() = results.clone();
+        results
+    }
+
+    /// Run one probe round and switch profiles if needed
+    ///
+    /// Does nothing unless automatic switching is enabled. When no profile
+    /// is reachable the current one is kept.
+    pub async fn auto_switch(&self) -> Result<()> {
+        if !self.settings().auto_switch {
+            return Ok(());
+        }
+
+        let results = self.probe_all().await;
+        let Some(id) = choose_profile(&self.profiles(), &results) else {
+            return Ok(());
+        };
+        self.activate(Some(id)).await?;
+        Ok(())
+    }
+
+    /// First healthy upstream of the preferred set
+    async fn preferred_upstream(&self, preferred: &[String]) -> Option<String> {
+        let healthy = self.upstream_manager.get_healthy_servers().await;
+        preferred
+            .iter()
+            .find(|name| healthy.iter().any(|s| &s.name == *name))
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl ResolverMiddleware for ProfileRouter {
+    fn name(&self) -> &str {
+        "profile"
+    }
+
+    /// Pin the listener's profile before the cache is consulted
+    async fn pre_rewrite(&self, ctx: &mut QueryContext) -> Result<HookOutcome> {
+        if ctx.profile_id.is_none() {
+            ctx.profile_id = ctx.listener.as_deref().and_then(|l| self.listener_profile(l));
+        }
+        Ok(HookOutcome::Continue)
+    }
+
+    async fn pre_upstream(&self, ctx: &mut QueryContext) -> Result<HookOutcome> {
+        if ctx.upstream.is_some() {
+            return Ok(HookOutcome::Continue);
+        }
+        let profile = match ctx.profile_id {
+            Some(id) => self.profile(id),
+            None => self.active(),
+        };
+        let Some(profile) = profile else {
+            return Ok(HookOutcome::Continue);
+        };
+
+        ctx.upstream = match profile.route(&ctx.query.name) {
+            Some(upstream) => Some(upstream.to_string()),
+            None => self.preferred_upstream(&profile.upstreams).await,
+        };
+        Ok(HookOutcome::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use crate::dns::message::{DnsQuery, RecordType};
+    use crate::dns::proxy::{UpstreamProtocol, UpstreamServer};
+
+    fn profile(id: i64, name: &str, upstreams: &str, rules: &str, probe: Option<&str>, priority: i64) -> ProfileView {
+        ProfileView::from_db(&ResolutionProfile {
+            id,
+            name: name.to_string(),
+            description: None,
+            upstreams: upstreams.to_string(),
+            rules: rules.to_string(),
+            probe_target: probe.map(str::to_string),
+            priority,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        })
+    }
+
+    fn probe(profile_id: i64, reachable: bool) -> ProbeResult {
+        ProbeResult {
+            profile_id,
+            profile: String::new(),
+            target: None,
+            reachable,
+            latency_ms: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = parse_rules(" *.Corp.Example.com. = corp-dns , internal=corp-dns,").unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].suffix, "corp.example.com");
+        assert_eq!(rules[0].upstream, "corp-dns");
+        assert!(parse_rules("corp.example.com").is_err());
+        assert!(parse_rules("=corp-dns").is_err());
+        assert!(parse_rules("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_route_most_specific_suffix() {
+        let view = profile(1, "office", "", "example.com=public,corp.example.com=corp", None, 0);
+        assert_eq!(view.route("git.corp.example.com."), Some("corp"));
+        assert_eq!(view.route("corp.example.com"), Some("corp"));
+        assert_eq!(view.route("www.example.com"), Some("public"));
+        assert_eq!(view.route("notexample.com"), None);
+    }
+
+    #[test]
+    fn test_route_idn_suffix() {
+        let view = profile(1, "office", "", "München.de.=corp", None, 0);
+        assert_eq!(view.route("www.xn--mnchen-3ya.de"), Some("corp"));
+        assert_eq!(view.route("WWW.München.DE."), Some("corp"));
+    }
+
+    #[test]
+    fn test_parse_probe_target() {
+        assert!(parse_probe_target("10.0.0.1:443").is_ok());
+        assert!(parse_probe_target("[fd00::1]:22").is_ok());
+        assert!(parse_probe_target("vpn.corp.example.com:443").is_err());
+        assert!(profile(1, "office", "", "", Some("bogus"), 0).probe_target.is_none());
+    }
+
+    #[test]
+    fn test_choose_profile_by_priority() {
+        let profiles = vec![
+            profile(1, "office", "", "", Some("10.0.0.1:443"), 10),
+            profile(2, "home", "", "", None, 0),
+        ];
+        assert_eq!(choose_profile(&profiles, &[probe(1, true), probe(2, true)]), Some(1));
+        assert_eq!(choose_profile(&profiles, &[probe(1, false), probe(2, true)]), Some(2));
+        assert_eq!(choose_profile(&profiles, &[probe(1, false), probe(2, false)]), None);
+    }
+
+    #[tokio::test]
+    async fn test_middleware_routes_active_profile() {
+        let upstreams = Arc::new(UpstreamManager::new());
+        upstreams
+            .add_server(UpstreamServer::new(1, "corp", "10.0.0.53:53", UpstreamProtocol::Udp, 5000))
+            .await;
+        upstreams
+            .add_server(UpstreamServer::new(2, "public", "1.1.1.1:53", UpstreamProtocol::Udp, 5000))
+            .await;
+        let router = ProfileRouter::new(None, upstreams, CacheManager::new_shared());
+        router.set_profiles(vec![profile(1, "office", "missing,public", "corp.example.com=corp", None, 0)]);
+
+        let mut ctx = QueryContext::new(DnsQuery::new("git.corp.example.com", RecordType::A), None);
+        router.pre_upstream(&mut ctx).await.unwrap();
+        assert!(ctx.upstream.is_none(), "no profile is active yet");
+
+        assert!(router.activate(Some(1)).await.unwrap());
+        assert!(!router.activate(Some(1)).await.unwrap());
+        assert!(router.activate(Some(7)).await.is_err());
+
+        router.pre_upstream(&mut ctx).await.unwrap();
+        assert_eq!(ctx.upstream.as_deref(), Some("corp"));
+
+        let mut ctx = QueryContext::new(DnsQuery::new("example.org", RecordType::A), None);
+        router.pre_upstream(&mut ctx).await.unwrap();
+        assert_eq!(ctx.upstream.as_deref(), Some("public"));
+    }
+
+    #[tokio::test]
+    async fn test_listener_pinned_profile() {
+        let upstreams = Arc::new(UpstreamManager::new());
+        upstreams
+            .add_server(UpstreamServer::new(1, "filtered", "10.0.0.53:53", UpstreamProtocol::Udp, 5000))
+            .await;
+        upstreams
+            .add_server(UpstreamServer::new(2, "open", "1.1.1.1:53", UpstreamProtocol::Udp, 5000))
+            .await;
+        let router = ProfileRouter::new(None, upstreams, CacheManager::new_shared());
+        router.set_profiles(vec![
+            profile(1, "guest", "filtered", "", None, 0),
+            profile(2, "lan", "open", "", None, 0),
+        ]);
+        router.set_listener_profiles(HashMap::from([("doh".to_string(), 1)]));
+        router.activate(Some(2)).await.unwrap();
+
+        // The pinned profile wins over the active one on its listener
+        let mut ctx = QueryContext::new(DnsQuery::new("example.org", RecordType::A), None);
+        ctx.listener = Some("doh".to_string());
+        router.pre_rewrite(&mut ctx).await.unwrap();
+        assert_eq!(ctx.profile_id, Some(1));
+        router.pre_upstream(&mut ctx).await.unwrap();
+        assert_eq!(ctx.upstream.as_deref(), Some("filtered"));
+
+        let mut ctx = QueryContext::new(DnsQuery::new("example.org", RecordType::A), None);
+        ctx.listener = Some("udp".to_string());
+        router.pre_rewrite(&mut ctx).await.unwrap();
+        assert_eq!(ctx.profile_id, None);
+        router.pre_upstream(&mut ctx).await.unwrap();
+        assert_eq!(ctx.upstream.as_deref(), Some("open"));
+    }
+}
@@ -1,25 +1,44 @@
 //! DNS Upstream Clients
 //!
 //! Provides client implementations for querying upstream DNS servers
-//! using different protocols (UDP, DoT, DoH, DoQ).
+//! using different protocols (UDP, TCP, DoT, DoH, DoQ).
+//!
+//! Each upstream may carry a [`SourceBinding`] that pins its outbound
+//! traffic to a local address and/or interface: it is applied to the UDP
+//! socket bind, the TCP and DoT connect and the QUIC endpoint (DoQ/DoH3).
+//! DoH honours the source address only.
+//!
+//! Encrypted upstreams send `tls_server_name` as SNI when set, e.g. to reach
+//! a provider by IP address, and verify the certificate against that name
+//! unless verification is turned off. DoH pins the server name to the IP
+//! address in the URL instead, so the override requires an IP-based URL.
 
-use std::net::SocketAddr;
-use std::sync::Arc;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Weak};
 use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use tokio::net::UdpSocket;
-use tokio::sync::OnceCell;
 use tokio::time::timeout;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use h3::client::SendRequest;
 use h3_quinn::OpenStreams;
 use bytes::Bytes;
 
 type H3SendRequest = SendRequest<OpenStreams, Bytes>;
 
-use crate::dns::message::{DnsQuery, DnsResponse};
+use crate::dns::cookie::record_upstream_cookie_mismatch;
+use crate::dns::loop_guard::loop_guard;
+use crate::dns::message::{append_opt_record, DnsQuery, DnsResponse, WireSummary, EDNS_OPTION_COOKIE};
+use crate::dns::socket::{bind_udp, SourceBinding};
+use super::connections::{
+    connection_manager, ConnectionKind, ConnectionSlot, IdleSlot, Pooled, UpstreamConnection,
+};
+use super::probe::DEFAULT_EDNS_PAYLOAD_SIZE;
+use super::quic_transport::{quic_transport, QuicConnectionStats};
+use super::doh_options::{doh_stats, get_query_url, rejects_get, DohHttpVersion, DohMethod};
+use super::traffic::upstream_traffic;
 use super::upstream::{UpstreamServer, UpstreamProtocol};
 
 /// Parse an address string that may contain IPv6 in bracket notation.
@@ -28,7 +47,7 @@ use super::upstream::{UpstreamServer, UpstreamProtocol};
 /// - IPv6: "[2001:4860:4860::8888]:53" or "[::1]:853"
 /// - Hostname: "dns.google:853" or "dns.google"
 /// Returns (host, port) tuple where host has brackets stripped for IPv6
-fn parse_host_port(address: &str, default_port: u16) -> Result<(String, u16)> {
+pub(crate) fn parse_host_port(address: &str, default_port: u16) -> Result<(String, u16)> {
     // Check for IPv6 in brackets: [::1]:port or [2001:db8::1]:port
     if address.starts_with('[') {
         if let Some(bracket_end) = address.find(']') {
@@ -63,28 +82,26 @@ fn parse_host_port(address: &str, default_port: u16) -> Result<(String, u16)> {
     Ok((address.to_string(), default_port))
 }
 
-/// Global QUIC endpoint cache for DoQ clients
+/// Encode a query for a TCP, TLS, HTTPS or QUIC upstream
+///
+/// These servers all handle EDNS, so an OPT record with the loop marker is
+/// always added; the advertised UDP size does not apply to them.
+pub(super) fn encode_marked(query: &DnsQuery) -> Result<Vec<u8>> {
+    let mut bytes = query.to_bytes()
+        .map_err(|e| anyhow!("Failed to encode query: {}", e))?;
+    append_opt_record(&mut bytes, DEFAULT_EDNS_PAYLOAD_SIZE, false, &[loop_guard().marker()]);
+    Ok(bytes)
+}
+
+/// Global QUIC endpoint cache for DoQ and DoH3 clients
 /// Reusing endpoints significantly improves performance by avoiding
 /// repeated socket binding and configuration overhead.
 ///
 /// We use a pool of endpoints to distribute load and avoid contention.
 const ENDPOINT_POOL_SIZE: usize = 20;
 
-static DOQ_ENDPOINTS_V4: OnceCell<Vec<quinn::Endpoint>> = OnceCell::const_new();
-static DOQ_INDEX_V4: AtomicUsize = AtomicUsize::new(0);
-
-static DOQ_ENDPOINTS_V6: OnceCell<Vec<quinn::Endpoint>> = OnceCell::const_new();
-static DOQ_INDEX_V6: AtomicUsize = AtomicUsize::new(0);
-
-/// Global QUIC endpoint cache for DoH3 clients
-static DOH3_ENDPOINTS_V4: OnceCell<Vec<quinn::Endpoint>> = OnceCell::const_new();
-static DOH3_INDEX_V4: AtomicUsize = AtomicUsize::new(0);
-
-static DOH3_ENDPOINTS_V6: OnceCell<Vec<quinn::Endpoint>> = OnceCell::const_new();
-static DOH3_INDEX_V6: AtomicUsize = AtomicUsize::new(0);
-
-/// Global DoT connection pool
-/// Key: "host:port", Value: TLS stream
+/// Global DoT and TCP connection pools
+/// Key: "host:port", Value: stream
 use std::collections::HashMap;
 use std::sync::OnceLock;
 use tokio::sync::Mutex;
@@ -92,85 +109,266 @@ use tokio_rustls::client::TlsStream;
 use tokio::net::TcpStream;
 
 type DotConnection = TlsStream<TcpStream>;
-static DOT_POOL: OnceLock<Mutex<HashMap<String, DotConnection>>> = OnceLock::new();
 
-fn get_dot_pool() -> &'static Mutex<HashMap<String, DotConnection>> {
-    DOT_POOL.get_or_init(|| Mutex::new(HashMap::new()))
+type StreamConnections<T> = Mutex<HashMap<String, Pooled<T>>>;
+
+struct StreamPool<T> {
+    connections: StreamConnections<T>,
+}
+
+impl<T: Send> IdleSlot for StreamPool<T> {
+    fn close_idle(&self, idle_timeout: Duration) -> usize {
+        let Ok(mut connections) = self.connections.try_lock() else {
+            return 0;
+        };
+        // Dropping the stream closes the socket
+        let before = connections.len();
+        connections.retain(|_, conn| conn.idle_for() < idle_timeout);
+        before - connections.len()
+    }
+}
+
+static DOT_POOL: OnceLock<Arc<StreamPool<DotConnection>>> = OnceLock::new();
+static TCP_POOL: OnceLock<Arc<StreamPool<TcpStream>>> = OnceLock::new();
+
+fn stream_pool<T: Send + 'static>(cell: &'static OnceLock<Arc<StreamPool<T>>>) -> &'static StreamConnections<T> {
+    &cell
+        .get_or_init(|| {
+            let pool = Arc::new(StreamPool { connections: Mutex::new(HashMap::new()) });
+            let weak = Arc::downgrade(&pool) as Weak<dyn IdleSlot>;
+            connection_manager().register(weak);
+            pool
+        })
+        .connections
+}
+
+fn get_dot_pool() -> &'static StreamConnections<DotConnection> {
+    stream_pool(&DOT_POOL)
+}
+
+fn get_tcp_pool() -> &'static StreamConnections<TcpStream> {
+    stream_pool(&TCP_POOL)
+}
+
+/// Create connection slots swept by the connection manager
+fn new_connection_slots<T: UpstreamConnection + 'static>(count: usize) -> Vec<Arc<ConnectionSlot<T>>> {
+    (0..count)
+        .map(|_| {
+            let slot = Arc::new(ConnectionSlot::<T>::new(None));
+            let weak = Arc::downgrade(&slot) as Weak<dyn IdleSlot>;
+            connection_manager().register(weak);
+            slot
+        })
+        .collect()
 }
 
 /// QUIC protocol type for endpoint caching
-#[derive(Clone, Copy)]
-enum QuicProtocol {
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) enum QuicProtocol {
     Doq,
     Doh3,
 }
 
-/// Get or create a cached QUIC endpoint for the given protocol and address family
-fn get_quic_endpoint(protocol: QuicProtocol, is_ipv6: bool) -> Result<&'static quinn::Endpoint> {
-    let (cell, index) = match (protocol, is_ipv6) {
-        (QuicProtocol::Doq, false) => (&DOQ_ENDPOINTS_V4, &DOQ_INDEX_V4),
-        (QuicProtocol::Doq, true) => (&DOQ_ENDPOINTS_V6, &DOQ_INDEX_V6),
-        (QuicProtocol::Doh3, false) => (&DOH3_ENDPOINTS_V4, &DOH3_INDEX_V4),
-        (QuicProtocol::Doh3, true) => (&DOH3_ENDPOINTS_V6, &DOH3_INDEX_V6),
-    };
-    
-    // Try to get existing endpoint pool
-    if let Some(endpoints) = cell.get() {
-        let idx = index.fetch_add(1, Ordering::Relaxed) % endpoints.len();
-        return Ok(&endpoints[idx]);
+/// Key: (protocol, source, is_ipv6)
+type EndpointKey = (QuicProtocol, SourceBinding, bool);
+
+/// Endpoints for one key, handed out round-robin
+///
+/// Unbound clients share a pool of [`ENDPOINT_POOL_SIZE`] endpoints. Bound
+/// sources are rare, so a single endpoint per source is kept instead.
+struct EndpointSet {
+    endpoints: Vec<quinn::Endpoint>,
+    next: usize,
+    last_used: Instant,
+    /// Transport settings generation of the endpoints' client config
+    generation: u64,
+}
+
+struct QuicEndpointCache {
+    sets: std::sync::Mutex<HashMap<EndpointKey, EndpointSet>>,
+}
+
+impl IdleSlot for QuicEndpointCache {
+    /// Drop endpoint sets that are idle and carry no connection
+    ///
+    /// Endpoints are sockets rather than connections, so they do not count
+    /// towards the returned number.
+    fn close_idle(&self, idle_timeout: Duration) -> usize {
+        let mut sets = self.sets.lock().unwrap();
+        let mut closed = 0;
+        sets.retain(|_, set| {
+            let idle = set.last_used.elapsed() >= idle_timeout
+                && set.endpoints.iter().all(|e| e.open_connections() == 0);
+            if idle {
+                closed += set.endpoints.len();
+            }
+            !idle
+        });
+        if closed > 0 {
+            connection_manager().endpoints_closed(closed);
+            tracing::debug!("Closed {} idle QUIC endpoints", closed);
+        }
+        0
     }
-    
-    // Create new endpoint pool
-    let mut eps = Vec::with_capacity(ENDPOINT_POOL_SIZE);
-    
-    for _ in 0..ENDPOINT_POOL_SIZE {
-        // Create new endpoint with appropriate config
-        let bind_addr: SocketAddr = if is_ipv6 {
-            "[::]:0".parse()?
-        } else {
-            "0.0.0.0:0".parse()?
-        };
-        
-        // Create TLS config with certificate verification disabled (for IP-based connections)
-        let mut crypto = rustls::ClientConfig::builder()
+}
+
+static QUIC_ENDPOINTS: OnceLock<Arc<QuicEndpointCache>> = OnceLock::new();
+
+fn get_quic_endpoint_cache() -> &'static QuicEndpointCache {
+    QUIC_ENDPOINTS.get_or_init(|| {
+        let cache = Arc::new(QuicEndpointCache { sets: Default::default() });
+        let weak = Arc::downgrade(&cache) as Weak<dyn IdleSlot>;
+        connection_manager().register(weak);
+        cache
+    })
+}
+
+/// TLS client config that verifies server certificates against the web PKI
+/// roots, or accepts any certificate
+pub(super) fn tls_client_config(verify: bool) -> rustls::ClientConfig {
+    if verify {
+        let mut root_store = rustls::RootCertStore::empty();
+        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        rustls::ClientConfig::builder()
+            .with_root_certificates(root_store)
+            .with_no_client_auth()
+    } else {
+        rustls::ClientConfig::builder()
             .dangerous()
             .with_custom_certificate_verifier(Arc::new(NoVerifier))
-            .with_no_client_auth();
-        
-        // Set ALPN protocol based on QUIC protocol type
-        crypto.alpn_protocols = match protocol {
-            QuicProtocol::Doq => vec![b"doq".to_vec()],
-            QuicProtocol::Doh3 => vec![b"h3".to_vec()],
-        };
-        
-        let quic_crypto = quinn::crypto::rustls::QuicClientConfig::try_from(crypto)
-            .map_err(|e| anyhow!("Failed to create QUIC client config: {}", e))?;
-            
-        // Configure Transport (Keep-Alive)
-        let mut transport = quinn::TransportConfig::default();
-        // Send keep-alive every 5 seconds to maintain NAT mappings
-        transport.keep_alive_interval(Some(Duration::from_secs(5)));
-        // Set max idle timeout to 20 seconds
-        transport.max_idle_timeout(Some(quinn::VarInt::from_u32(20_000).into()));
-        
-        let mut client_config = quinn::ClientConfig::new(Arc::new(quic_crypto));
-        client_config.transport_config(Arc::new(transport));
-        
-        let mut endpoint = quinn::Endpoint::client(bind_addr)?;
-        endpoint.set_default_client_config(client_config);
-        
-        eps.push(endpoint);
+            .with_no_client_auth()
     }
+}
+
+/// QUIC client config for DoQ or DoH3
+fn quic_client_config(protocol: QuicProtocol, verify: bool) -> Result<quinn::ClientConfig> {
+    let mut crypto = tls_client_config(verify);
     
-    // Try to store it
-    // If another thread beat us to it, the result will be Err(value), but we just discard our created value
-    // and use the one in the cell.
-    let _ = cell.set(eps);
+    // Set ALPN protocol based on QUIC protocol type
+    crypto.alpn_protocols = match protocol {
+        QuicProtocol::Doq => vec![b"doq".to_vec()],
+        QuicProtocol::Doh3 => vec![b"h3".to_vec()],
+    };
     
-    // Get from cell (guaranteed to be Some now)
-    let endpoints = cell.get().unwrap();
-    let idx = index.fetch_add(1, Ordering::Relaxed) % endpoints.len();
-    Ok(&endpoints[idx])
+    let settings = quic_transport().settings();
+    crypto.enable_early_data = settings.zero_rtt;
+    crypto.resumption = quic_transport().resumption(verify);
+
+    let quic_crypto = quinn::crypto::rustls::QuicClientConfig::try_from(crypto)
+        .map_err(|e| anyhow!("Failed to create QUIC client config: {}", e))?;
+
+    // Keep-alives maintain NAT mappings between queries
+    let mut client_config = quinn::ClientConfig::new(Arc::new(quic_crypto));
+    client_config.transport_config(Arc::new(settings.transport_config()));
+    Ok(client_config)
+}
+
+/// Create a client QUIC endpoint on the given local address
+///
+/// The endpoint's default config accepts any certificate (for IP-based
+/// connections); see [`connect_quic`] for verified connections.
+fn create_quic_endpoint(
+    protocol: QuicProtocol,
+    bind_addr: SocketAddr,
+    interface: Option<&str>,
+) -> Result<quinn::Endpoint> {
+    let client_config = quic_client_config(protocol, false)?;
+    
+    let socket = bind_udp(bind_addr, interface)?;
+    let runtime = quinn::default_runtime()
+        .ok_or_else(|| anyhow!("No async runtime found for QUIC endpoint"))?;
+    let mut endpoint = quinn::Endpoint::new(quinn::EndpointConfig::default(), None, socket, runtime)?;
+    endpoint.set_default_client_config(client_config);
+    
+    Ok(endpoint)
+}
+
+/// Get or create a cached QUIC endpoint for reaching `target` from `source`
+pub(super) fn get_quic_endpoint(
+    protocol: QuicProtocol,
+    target: SocketAddr,
+    source: &SourceBinding,
+) -> Result<quinn::Endpoint> {
+    let is_ipv6 = target.is_ipv6();
+    let key = (protocol, source.clone(), is_ipv6);
+    let generation = quic_transport().generation();
+    let mut sets = get_quic_endpoint_cache().sets.lock().unwrap();
+
+    let set = match sets.entry(key) {
+        std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+        std::collections::hash_map::Entry::Vacant(entry) => {
+            let endpoints = if source.is_default() {
+                let bind_addr: SocketAddr = if is_ipv6 {
+                    "[::]:0".parse()?
+                } else {
+                    "0.0.0.0:0".parse()?
+                };
+                (0..ENDPOINT_POOL_SIZE)
+                    .map(|_| create_quic_endpoint(protocol, bind_addr, None))
+                    .collect::<Result<Vec<_>>>()?
+            } else {
+                vec![create_quic_endpoint(protocol, source.local_addr(target)?, source.interface.as_deref())?]
+            };
+            connection_manager().endpoints_opened(endpoints.len());
+            entry.insert(EndpointSet {
+                endpoints,
+                next: 0,
+                last_used: Instant::now(),
+                generation,
+            })
+        }
+    };
+
+    if set.generation != generation {
+        let client_config = quic_client_config(protocol, false)?;
+        for endpoint in &mut set.endpoints {
+            endpoint.set_default_client_config(client_config.clone());
+        }
+        set.generation = generation;
+    }
+    set.last_used = Instant::now();
+    let endpoint = set.endpoints[set.next % set.endpoints.len()].clone();
+    set.next = set.next.wrapping_add(1);
+    Ok(endpoint)
+}
+
+/// Start a QUIC connection, verifying the certificate if the server asks for it
+pub(super) fn connect_quic(
+    endpoint: &quinn::Endpoint,
+    protocol: QuicProtocol,
+    server: &UpstreamServer,
+    addr: SocketAddr,
+    server_name: &str,
+) -> Result<quinn::Connecting> {
+    let connecting = if server.verify_hostname {
+        endpoint.connect_with(quic_client_config(protocol, true)?, addr, server_name)?
+    } else {
+        endpoint.connect(addr, server_name)?
+    };
+    Ok(connecting)
+}
+
+/// Finish a QUIC connection
+///
+/// With 0-RTT enabled and a session ticket cached for the server, the
+/// connection is returned at once and queries go out as early data.
+pub(super) async fn establish_quic(connecting: quinn::Connecting, limit: Duration) -> Result<quinn::Connection> {
+    let connecting = if quic_transport().settings().zero_rtt {
+        match connecting.into_0rtt() {
+            Ok((connection, accepted)) => {
+                tokio::spawn(async move { quic_transport().record_zero_rtt(accepted.await) });
+                return Ok(connection);
+            }
+            Err(connecting) => connecting,
+        }
+    } else {
+        connecting
+    };
+    match timeout(limit, connecting).await {
+        Ok(Ok(connection)) => Ok(connection),
+        Ok(Err(e)) => Err(anyhow!("Connection failed: {}", e)),
+        Err(_) => Err(anyhow!("Connection timeout")),
+    }
 }
 
 /// Result of a DNS query to an upstream server
@@ -199,6 +397,11 @@ pub trait DnsClient: Send + Sync {
     /// Check if the server is reachable (health check)
     #[allow(dead_code)]
     async fn health_check(&self) -> Result<Duration>;
+
+    /// Transport state of the pooled QUIC connections (DoQ/DoH3 only)
+    fn quic_connections(&self) -> Vec<QuicConnectionStats> {
+        Vec::new()
+    }
 }
 
 /// UDP DNS Client
@@ -208,6 +411,10 @@ pub struct UdpDnsClient {
     server: UpstreamServer,
     #[allow(dead_code)]
     socket: Option<UdpSocket>,
+    /// Client cookie sent to servers that support cookies (RFC 7873)
+    client_cookie: [u8; 8],
+    /// Server cookie from the last answer, echoed in the next query
+    server_cookie: std::sync::Mutex<Option<Vec<u8>>>,
 }
 
 impl UdpDnsClient {
@@ -216,12 +423,14 @@ impl UdpDnsClient {
         Self {
             server,
             socket: None,
+            client_cookie: rand::random(),
+            server_cookie: std::sync::Mutex::new(None),
         }
     }
 
     /// Parse the server address with IPv6 support
     /// Supports formats: "1.1.1.1:53", "[2001:4860:4860::8888]:53", "dns.google:53"
-    fn parse_address(&self) -> Result<SocketAddr> {
+    pub(super) fn parse_address(&self) -> Result<SocketAddr> {
         let (host, port) = parse_host_port(&self.server.address, UpstreamProtocol::Udp.default_port())?;
         
         // Try to parse as IP address directly
@@ -236,22 +445,17 @@ impl UdpDnsClient {
     }
 
     /// Send a query and receive response
-    async fn send_query(&self, query_bytes: &[u8], server_addr: SocketAddr) -> Result<Vec<u8>> {
+    pub(super) async fn send_query(&self, query_bytes: &[u8], server_addr: SocketAddr) -> Result<Vec<u8>> {
         use tracing::debug;
         
-        // Bind to appropriate address family based on target
-        let bind_addr = if server_addr.is_ipv6() {
-            "[::]:0"
-        } else {
-            "0.0.0.0:0"
-        };
-        let socket = UdpSocket::bind(bind_addr).await
-            .map_err(|e| anyhow!("Failed to bind UDP socket to {}: {}", bind_addr, e))?;
+        // Bind to the configured source, or the target's address family
+        let socket = UdpSocket::from_std(self.server.source.bind_udp(server_addr)?)?;
         
         debug!("Sending UDP query to {} ({} bytes)", server_addr, query_bytes.len());
         
         // Send query
         let sent = socket.send_to(query_bytes, server_addr).await?;
+        upstream_traffic().record_sent(self.server.id, sent);
         debug!("Sent {} bytes to {}", sent, server_addr);
         
         // Receive response
@@ -260,10 +464,93 @@ impl UdpDnsClient {
         let (len, from) = timeout(self.server.timeout, socket.recv_from(&mut buf)).await
             .map_err(|_| anyhow!("Query timeout after {:?}", self.server.timeout))??;
         
+        upstream_traffic().record_received(self.server.id, len);
         debug!("Received {} bytes from {}", len, from);
         buf.truncate(len);
         Ok(buf)
     }
+
+    /// Send a query over TCP (RFC 7766) and receive the response
+    pub(super) async fn send_query_tcp(&self, query_bytes: &[u8], server_addr: SocketAddr) -> Result<Vec<u8>> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let exchange = async {
+            let mut stream = self.server.source.connect_tcp(server_addr).await?;
+            let mut framed = Vec::with_capacity(query_bytes.len() + 2);
+            framed.extend_from_slice(&(query_bytes.len() as u16).to_be_bytes());
+            framed.extend_from_slice(query_bytes);
+            stream.write_all(&framed).await?;
+            upstream_traffic().record_sent(self.server.id, framed.len());
+
+            let mut len_buf = [0u8; 2];
+            stream.read_exact(&mut len_buf).await?;
+            let mut buf = vec![0u8; u16::from_be_bytes(len_buf) as usize];
+            stream.read_exact(&mut buf).await?;
+            upstream_traffic().record_received(self.server.id, len_buf.len() + buf.len());
+            Ok::<_, anyhow::Error>(buf)
+        };
+
+        timeout(self.server.timeout, exchange).await
+            .map_err(|_| anyhow!("TCP query timeout after {:?}", self.server.timeout))?
+    }
+
+    /// Encode a query, adding an OPT record for servers known to handle EDNS
+    ///
+    /// The OPT record carries the loop marker. The DO bit is never set:
+    /// answers are not validated here, and servers that strip it would only
+    /// waste the extra bytes.
+    fn encode_query(&self, query: &DnsQuery) -> Result<Vec<u8>> {
+        let mut bytes = query.to_bytes()
+            .map_err(|e| anyhow!("Failed to encode query: {}", e))?;
+        if let Some(size) = self.server.edns_payload_size() {
+            let marker = loop_guard().marker();
+            if self.sends_cookies() {
+                let mut cookie = self.client_cookie.to_vec();
+                if let Some(ref server) = *self.server_cookie.lock().unwrap() {
+                    cookie.extend_from_slice(server);
+                }
+                append_opt_record(&mut bytes, size, false, &[(EDNS_OPTION_COOKIE, &cookie), marker]);
+            } else {
+                append_opt_record(&mut bytes, size, false, &[marker]);
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Whether queries carry a client cookie
+    fn sends_cookies(&self) -> bool {
+        self.server.probed().is_some_and(|c| c.edns && c.cookies)
+    }
+
+    /// Check the cookie of an answer and remember the server cookie
+    ///
+    /// Answers that do not echo our client cookie are rejected as spoofed;
+    /// answers without any cookie are accepted, as some server farms only
+    /// support cookies on part of their nodes. Returns true when the server
+    /// answered BADCOOKIE and the query should be repeated.
+    fn accept_cookie(&self, response: &[u8]) -> Result<bool> {
+        if !self.sends_cookies() {
+            return Ok(false);
+        }
+        let Some(summary) = WireSummary::parse(response) else {
+            return Ok(false);
+        };
+        let Some(opt) = summary.opt else {
+            return Ok(false);
+        };
+        let Some(cookie) = opt.option(EDNS_OPTION_COOKIE) else {
+            return Ok(false);
+        };
+
+        if cookie.len() < 16 || cookie[..8] != self.client_cookie {
+            record_upstream_cookie_mismatch();
+            return Err(anyhow!("Answer from {} does not echo our DNS cookie", self.server.address));
+        }
+        *self.server_cookie.lock().unwrap() = Some(cookie[8..].to_vec());
+
+        // BADCOOKIE is extended RCODE 23: 7 in the header, 1 in the OPT record
+        Ok(summary.rcode == 7 && opt.extended_rcode == 1)
+    }
 }
 
 #[async_trait]
@@ -277,18 +564,35 @@ impl DnsClient for UdpDnsClient {
         let server_addr = self.parse_address()?;
         debug!("Parsed server address: {}", server_addr);
         
-        let query_bytes = query.to_bytes()
-            .map_err(|e| anyhow!("Failed to encode query: {}", e))?;
+        let mut query_bytes = self.encode_query(query)?;
         debug!("Encoded query: {} bytes", query_bytes.len());
         
         let start = Instant::now();
-        let response_bytes = match self.send_query(&query_bytes, server_addr).await {
+        let mut response_bytes = match self.send_query(&query_bytes, server_addr).await {
             Ok(bytes) => bytes,
             Err(e) => {
                 warn!("UDP query to {} failed: {}", server_addr, e);
                 return Err(e);
             }
         };
+
+        // Repeat once with the fresh server cookie handed out in a BADCOOKIE answer
+        if self.accept_cookie(&response_bytes)? {
+            debug!("BADCOOKIE from {}, retrying with the new server cookie", server_addr);
+            query_bytes = self.encode_query(query)?;
+            response_bytes = self.send_query(&query_bytes, server_addr).await?;
+            self.accept_cookie(&response_bytes)?;
+        }
+
+        // Retry truncated answers over TCP unless the server is known not to serve it
+        let truncated = response_bytes.get(2).is_some_and(|flags| flags & 0x02 != 0);
+        if truncated && self.server.tcp_fallback() {
+            debug!("Response from {} truncated, retrying over TCP", server_addr);
+            match self.send_query_tcp(&query_bytes, server_addr).await {
+                Ok(bytes) => response_bytes = bytes,
+                Err(e) => warn!("TCP fallback to {} failed, using truncated answer: {}", server_addr, e),
+            }
+        }
         let response_time = start.elapsed();
         
         debug!("Received response: {} bytes in {:?}", response_bytes.len(), response_time);
@@ -322,6 +626,146 @@ impl DnsClient for UdpDnsClient {
 }
 
 
+/// Send a length-prefixed query over a stream and read the answer (RFC 7766)
+async fn send_framed<S>(server: &UpstreamServer, conn: &mut S, query: &DnsQuery) -> Result<Vec<u8>>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Encode query with length prefix (TCP DNS format)
+    let query_bytes = encode_marked(query)?;
+    let len = (query_bytes.len() as u16).to_be_bytes();
+
+    conn.write_all(&len).await?;
+    conn.write_all(&query_bytes).await?;
+    conn.flush().await?;
+    upstream_traffic().record_sent(server.id, len.len() + query_bytes.len());
+
+    // Read response length
+    let mut len_buf = [0u8; 2];
+    timeout(server.timeout, conn.read_exact(&mut len_buf)).await
+        .map_err(|_| anyhow!("Read timeout"))??;
+    let response_len = u16::from_be_bytes(len_buf) as usize;
+
+    // Read response
+    let mut response_bytes = vec![0u8; response_len];
+    timeout(server.timeout, conn.read_exact(&mut response_bytes)).await
+        .map_err(|_| anyhow!("Read timeout"))??;
+    upstream_traffic().record_received(server.id, len_buf.len() + response_len);
+
+    Ok(response_bytes)
+}
+
+/// TCP DNS Client
+///
+/// Queries upstream DNS servers over plain TCP (RFC 7766), for networks
+/// that block UDP port 53 and for answers too large for UDP. Connections
+/// are pooled and reused like DoT connections.
+pub struct TcpDnsClient {
+    server: UpstreamServer,
+}
+
+impl TcpDnsClient {
+    /// Create a new TCP DNS client
+    pub fn new(server: UpstreamServer) -> Self {
+        Self { server }
+    }
+
+    /// Parse the server address with IPv6 support
+    /// Supports formats: "1.1.1.1:53", "[2001:4860:4860::8888]:53", "dns.google:53"
+    fn parse_address(&self) -> Result<(String, u16)> {
+        parse_host_port(&self.server.address, UpstreamProtocol::Tcp.default_port())
+    }
+
+    /// Open a new connection, from the configured source address if any
+    async fn create_connection(&self, host: &str, port: u16) -> Result<Pooled<TcpStream>> {
+        let permit = connection_manager().acquire(ConnectionKind::Tcp)?;
+
+        let addrs: Vec<SocketAddr> = match host.parse::<IpAddr>() {
+            Ok(ip) => vec![SocketAddr::new(ip, port)],
+            Err(_) => tokio::net::lookup_host((host, port)).await
+                .map_err(|e| anyhow!("Failed to resolve {}: {}", host, e))?
+                .collect(),
+        };
+        let target = self.server.source.select_target(&addrs)
+            .ok_or_else(|| anyhow!("No usable addresses found for {}", host))?;
+
+        let stream = timeout(self.server.timeout, self.server.source.connect_tcp(target)).await
+            .map_err(|_| anyhow!("Connection timeout to {}", target))??;
+        stream.set_nodelay(true)?;
+
+        Ok(Pooled::new(stream, permit))
+    }
+}
+
+#[async_trait]
+impl DnsClient for TcpDnsClient {
+    async fn query(&self, query: &DnsQuery) -> Result<QueryResult> {
+        use tracing::debug;
+
+        let (host, port) = self.parse_address()?;
+        let pool_key = if self.server.source.is_default() {
+            format!("{}:{}", host, port)
+        } else {
+            format!("{}:{}@{}", host, port, self.server.source)
+        };
+
+        let start = Instant::now();
+
+        // Try to reuse an existing connection; servers close idle ones early
+        let pool = get_tcp_pool();
+        let conn_opt = pool.lock().await.remove(&pool_key);
+
+        let (response_bytes, conn) = match conn_opt {
+            Some(mut conn) => match send_framed(&self.server, &mut conn.conn, query).await {
+                Ok(bytes) => (bytes, conn),
+                Err(e) => {
+                    debug!("TCP reused connection to {} failed: {}, creating new connection", pool_key, e);
+                    drop(conn);
+                    let mut new_conn = self.create_connection(&host, port).await?;
+                    let bytes = send_framed(&self.server, &mut new_conn.conn, query).await?;
+                    (bytes, new_conn)
+                }
+            },
+            None => {
+                debug!("TCP creating new connection to {}", pool_key);
+                let mut new_conn = self.create_connection(&host, port).await?;
+                let bytes = send_framed(&self.server, &mut new_conn.conn, query).await?;
+                (bytes, new_conn)
+            }
+        };
+
+        // Put connection back to pool
+        conn.touch();
+        pool.lock().await.insert(pool_key, conn);
+
+        let response_time = start.elapsed();
+
+        let response = DnsResponse::from_bytes(&response_bytes)
+            .map_err(|e| anyhow!("Failed to parse response: {}", e))?;
+
+        Ok(QueryResult {
+            response,
+            response_time_ms: response_time.as_millis() as u64,
+            server_id: self.server.id,
+            server_name: self.server.name.clone(),
+        })
+    }
+
+    fn server(&self) -> &UpstreamServer {
+        &self.server
+    }
+
+    async fn health_check(&self) -> Result<Duration> {
+        let query = DnsQuery::new("dns.google", crate::dns::message::RecordType::A);
+        let start = Instant::now();
+        let _ = self.query(&query).await?;
+        Ok(start.elapsed())
+    }
+}
+
+
 /// DoT (DNS over TLS) Client
 ///
 /// Queries upstream DNS servers using DNS over TLS protocol.
@@ -342,12 +786,27 @@ impl DotDnsClient {
         parse_host_port(&self.server.address, UpstreamProtocol::Dot.default_port())
     }
 
+    /// Open a TCP connection, from the configured source address if any
+    async fn connect_tcp(&self, addr: &str) -> Result<TcpStream> {
+        if self.server.source.is_default() {
+            return Ok(TcpStream::connect(addr).await?);
+        }
+
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host(addr).await
+            .map_err(|e| anyhow!("Failed to resolve {}: {}", addr, e))?
+            .collect();
+        let target = self.server.source.select_target(&addrs)
+            .ok_or_else(|| anyhow!("No usable addresses found for {}", addr))?;
+        self.server.source.connect_tcp(target).await
+    }
+
     /// Create a new TLS connection with IPv6 support
-    async fn create_connection(&self, host: &str, port: u16) -> Result<DotConnection> {
+    async fn create_connection(&self, host: &str, port: u16) -> Result<Pooled<DotConnection>> {
         use tokio_rustls::TlsConnector;
-        use rustls::{ClientConfig, RootCertStore};
         use rustls::pki_types::ServerName;
 
+        let permit = connection_manager().acquire(ConnectionKind::Dot)?;
+
         // Format address for connection - use brackets for IPv6
         let addr = if host.contains(':') {
             format!("[{}]:{}", host, port)
@@ -355,29 +814,22 @@ impl DotDnsClient {
             format!("{}:{}", host, port)
         };
         
-        // Create TLS config with system root certificates
-        let mut root_store = RootCertStore::empty();
-        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-        
-        let config = ClientConfig::builder()
-            .with_root_certificates(root_store)
-            .with_no_client_auth();
-        
+        let config = tls_client_config(self.server.verify_hostname);
         let connector = TlsConnector::from(Arc::new(config));
         
-        // For SNI, use the host directly (without brackets) if it's a hostname
-        // For IP addresses, we still need a ServerName but TLS doesn't verify it
-        let server_name = ServerName::try_from(host.to_string())
-            .map_err(|_| anyhow!("Invalid server name: {}", host))?;
+        // SNI is the configured server name, or the host without brackets
+        let name = self.server.tls_name(host);
+        let server_name = ServerName::try_from(name.to_string())
+            .map_err(|_| anyhow!("Invalid server name: {}", name))?;
         
         // Connect with timeout
-        let stream = timeout(self.server.timeout, TcpStream::connect(&addr)).await
+        let stream = timeout(self.server.timeout, self.connect_tcp(&addr)).await
             .map_err(|_| anyhow!("Connection timeout to {}", addr))??;
         
         let tls_stream = timeout(self.server.timeout, connector.connect(server_name, stream)).await
             .map_err(|_| anyhow!("TLS handshake timeout"))??;
         
-        Ok(tls_stream)
+        Ok(Pooled::new(tls_stream, permit))
     }
 
     /// Send query over an existing connection, returns None if connection is broken
@@ -386,29 +838,7 @@ impl DotDnsClient {
         conn: &mut DotConnection,
         query: &DnsQuery,
     ) -> Result<Vec<u8>> {
-        use tokio::io::{AsyncReadExt, AsyncWriteExt};
-
-        // Encode query with length prefix (TCP DNS format)
-        let query_bytes = query.to_bytes()
-            .map_err(|e| anyhow!("Failed to encode query: {}", e))?;
-        let len = (query_bytes.len() as u16).to_be_bytes();
-        
-        conn.write_all(&len).await?;
-        conn.write_all(&query_bytes).await?;
-        conn.flush().await?;
-        
-        // Read response length
-        let mut len_buf = [0u8; 2];
-        timeout(self.server.timeout, conn.read_exact(&mut len_buf)).await
-            .map_err(|_| anyhow!("Read timeout"))??;
-        let response_len = u16::from_be_bytes(len_buf) as usize;
-        
-        // Read response
-        let mut response_bytes = vec![0u8; response_len];
-        timeout(self.server.timeout, conn.read_exact(&mut response_bytes)).await
-            .map_err(|_| anyhow!("Read timeout"))??;
-        
-        Ok(response_bytes)
+        send_framed(&self.server, conn, query).await
     }
 }
 
@@ -418,48 +848,51 @@ impl DnsClient for DotDnsClient {
         use tracing::debug;
 
         let (host, port) = self.parse_address()?;
-        let pool_key = format!("{}:{}", host, port);
+        let mut pool_key = if self.server.source.is_default() {
+            format!("{}:{}", host, port)
+        } else {
+            format!("{}:{}@{}", host, port, self.server.source)
+        };
+        if let Some(ref name) = self.server.tls_server_name {
+            pool_key = format!("{}#{}", pool_key, name);
+        }
         
         let start = Instant::now();
         
         // Try to reuse existing connection
         let pool = get_dot_pool();
-        let mut conn_opt = {
+        let conn_opt = {
             let mut pool_guard = pool.lock().await;
             pool_guard.remove(&pool_key)
         };
         
-        let response_bytes = if let Some(ref mut conn) = conn_opt {
+        let (response_bytes, conn) = if let Some(mut conn) = conn_opt {
             // Try to use existing connection
-            match self.send_query_on_conn(conn, query).await {
+            match self.send_query_on_conn(&mut conn.conn, query).await {
                 Ok(bytes) => {
                     debug!("DoT query succeeded on reused connection to {}", pool_key);
-                    // Put connection back to pool
-                    let mut pool_guard = pool.lock().await;
-                    pool_guard.insert(pool_key.clone(), conn_opt.take().unwrap());
-                    bytes
+                    (bytes, conn)
                 }
                 Err(e) => {
                     debug!("DoT reused connection failed: {}, creating new connection", e);
-                    // Connection broken, create new one
+                    // Connection broken, release it before creating a new one
+                    drop(conn);
                     let mut new_conn = self.create_connection(&host, port).await?;
-                    let bytes = self.send_query_on_conn(&mut new_conn, query).await?;
-                    // Put new connection to pool
-                    let mut pool_guard = pool.lock().await;
-                    pool_guard.insert(pool_key.clone(), new_conn);
-                    bytes
+                    let bytes = self.send_query_on_conn(&mut new_conn.conn, query).await?;
+                    (bytes, new_conn)
                 }
             }
         } else {
             // No existing connection, create new one
             debug!("DoT creating new connection to {}", pool_key);
             let mut new_conn = self.create_connection(&host, port).await?;
-            let bytes = self.send_query_on_conn(&mut new_conn, query).await?;
-            // Put connection to pool
-            let mut pool_guard = pool.lock().await;
-            pool_guard.insert(pool_key.clone(), new_conn);
-            bytes
+            let bytes = self.send_query_on_conn(&mut new_conn.conn, query).await?;
+            (bytes, new_conn)
         };
+
+        // Put connection back to pool
+        conn.touch();
+        pool.lock().await.insert(pool_key, conn);
         
         let response_time = start.elapsed();
         
@@ -489,64 +922,208 @@ impl DnsClient for DotDnsClient {
 
 /// DoH (DNS over HTTPS) Client
 ///
-/// Queries upstream DNS servers using DNS over HTTPS protocol.
+/// Queries upstream DNS servers using DNS over HTTPS protocol, with the
+/// HTTP method and version configured on the upstream.
 pub struct DohDnsClient {
     server: UpstreamServer,
     client: reqwest::Client,
+    /// Version-negotiating client, used once pinned HTTP/2 failed
+    negotiating_client: Option<reqwest::Client>,
+    url: String,
+    /// The server rejected GET; queries go out as POST
+    get_rejected: AtomicBool,
+    /// The server failed over pinned HTTP/2
+    http2_failed: AtomicBool,
 }
 
 impl DohDnsClient {
     /// Create a new DoH DNS client
     pub fn new(server: UpstreamServer) -> Self {
-        let client = reqwest::Client::builder()
+        if server.source.interface.is_some() {
+            tracing::warn!(
+                "DoH upstream {} ignores source interface binding; only the source IP is applied",
+                server.name
+            );
+        }
+        let mut url = Self::base_url(&server.address);
+        let mut pinned = None;
+
+        // Request the server name and pin it to the IP address in the URL
+        if let Some(ref name) = server.tls_server_name {
+            match Self::pin_server_name(&url, name) {
+                Some((pinned_url, addr)) => {
+                    pinned = Some((name.as_str(), addr));
+                    url = pinned_url;
+                }
+                None => tracing::warn!(
+                    "DoH upstream {} ignores TLS server name; the URL must use an IP address",
+                    server.name
+                ),
+            }
+        }
+        let client = Self::build_client(&server, pinned, server.doh_http_version);
+        let negotiating_client = (server.doh_http_version == DohHttpVersion::Http2)
+            .then(|| Self::build_client(&server, pinned, DohHttpVersion::Auto));
+
+        Self {
+            server,
+            client,
+            negotiating_client,
+            url,
+            get_rejected: AtomicBool::new(false),
+            http2_failed: AtomicBool::new(false),
+        }
+    }
+
+    /// Build an HTTP client speaking `http_version`
+    fn build_client(
+        server: &UpstreamServer,
+        pinned: Option<(&str, SocketAddr)>,
+        http_version: DohHttpVersion,
+    ) -> reqwest::Client {
+        let mut builder = reqwest::Client::builder()
             .timeout(server.timeout)
-            .build()
-            .unwrap_or_default();
-        
-        Self { server, client }
+            .local_address(server.source.ip)
+            .danger_accept_invalid_certs(!server.verify_hostname);
+        if let Some((name, addr)) = pinned {
+            builder = builder.resolve(name, addr);
+        }
+        builder = match http_version {
+            DohHttpVersion::Auto => builder,
+            DohHttpVersion::Http1 => builder.http1_only(),
+            DohHttpVersion::Http2 => builder.http2_prior_knowledge(),
+        };
+        builder.build().unwrap_or_default()
     }
 
-    /// Get the DoH URL
-    fn get_url(&self) -> String {
-        if self.server.address.starts_with("http://") || self.server.address.starts_with("https://") {
-            self.server.address.clone()
+    /// Get the DoH URL for an address
+    fn base_url(address: &str) -> String {
+        if address.starts_with("http://") || address.starts_with("https://") {
+            address.to_string()
         } else {
-            format!("https://{}/dns-query", self.server.address)
+            format!("https://{}/dns-query", address)
         }
     }
+
+    /// Replace the IP address host of `url` with `name`
+    ///
+    /// Returns the new URL and the address `name` should resolve to, or
+    /// None when the URL host is not an IP address.
+    fn pin_server_name(url: &str, name: &str) -> Option<(String, SocketAddr)> {
+        let mut parsed = reqwest::Url::parse(url).ok()?;
+        let ip: IpAddr = parsed.host_str()?.trim_matches(['[', ']']).parse().ok()?;
+        let port = parsed.port_or_known_default()?;
+        parsed.set_host(Some(name)).ok()?;
+        Some((parsed.to_string(), SocketAddr::new(ip, port)))
+    }
+
+    /// Method of the next query: POST once the server rejected GET
+    fn method(&self) -> DohMethod {
+        if self.get_rejected.load(Ordering::Relaxed) {
+            DohMethod::Post
+        } else {
+            self.server.doh_method
+        }
+    }
+
+    /// HTTP client of the next query
+    fn http_client(&self) -> &reqwest::Client {
+        match self.negotiating_client {
+            Some(ref client) if self.http2_failed.load(Ordering::Relaxed) => client,
+            _ => &self.client,
+        }
+    }
+
+    fn request(&self, query_bytes: &[u8], method: DohMethod) -> reqwest::RequestBuilder {
+        let request = match method {
+            DohMethod::Post => self.http_client()
+                .post(&self.url)
+                .header("Content-Type", "application/dns-message")
+                .body(query_bytes.to_vec()),
+            DohMethod::Get => self.http_client().get(get_query_url(&self.url, query_bytes)),
+        };
+        request.header("Accept", "application/dns-message")
+    }
+
+    /// Switch to the negotiating client after pinned HTTP/2 failed
+    ///
+    /// Returns whether the request should be retried.
+    fn fall_back_from_http2(&self, error: &reqwest::Error) -> bool {
+        if self.negotiating_client.is_none() || self.http2_failed.swap(true, Ordering::Relaxed) {
+            return false;
+        }
+        tracing::warn!(
+            "DoH upstream {} failed over HTTP/2 ({}); negotiating the HTTP version from now on",
+            self.server.name, error
+        );
+        doh_stats().record_http2_fallback(self.server.id);
+        true
+    }
+
+    /// Send one query with `method` and parse the answer
+    async fn exchange(&self, query_bytes: &[u8], method: DohMethod) -> Result<DnsResponse> {
+        let response = match self.request(query_bytes, method).send().await {
+            Err(e) if !e.is_timeout() && self.fall_back_from_http2(&e) => {
+                self.request(query_bytes, method).send().await?
+            }
+            result => result?,
+        };
+        upstream_traffic().record_sent(self.server.id, query_bytes.len());
+
+        let status = response.status();
+        if !status.is_success() {
+            if method == DohMethod::Get
+                && rejects_get(status.as_u16())
+                && !self.get_rejected.swap(true, Ordering::Relaxed)
+            {
+                tracing::warn!(
+                    "DoH upstream {} rejected GET with status {}; using POST from now on",
+                    self.server.name, status
+                );
+                doh_stats().record_get_fallback(self.server.id);
+            }
+            return Err(anyhow!("DoH query failed with status: {}", status));
+        }
+
+        let response_bytes = response.bytes().await?;
+        upstream_traffic().record_received(self.server.id, response_bytes.len());
+        DnsResponse::from_bytes(&response_bytes)
+            .map_err(|e| anyhow!("Failed to parse response: {}", e))
+    }
 }
 
 #[async_trait]
 impl DnsClient for DohDnsClient {
     async fn query(&self, query: &DnsQuery) -> Result<QueryResult> {
-        let url = self.get_url();
-        let query_bytes = query.to_bytes()
-            .map_err(|e| anyhow!("Failed to encode query: {}", e))?;
-        
+        let mut query_bytes = encode_marked(query)?;
+        let mut method = self.method();
+        let id_zeroed = method == DohMethod::Get;
+        if id_zeroed {
+            // RFC 8484 §4.1: a zero ID keeps GET answers cacheable
+            query_bytes[..2].fill(0);
+        }
+
         let start = Instant::now();
-        
-        // Use POST method with application/dns-message content type
-        let response = self.client
-            .post(&url)
-            .header("Content-Type", "application/dns-message")
-            .header("Accept", "application/dns-message")
-            .body(query_bytes)
-            .send()
-            .await?;
-        
-        if !response.status().is_success() {
-            return Err(anyhow!("DoH query failed with status: {}", response.status()));
+        let mut attempt_start = start;
+        let mut result = self.exchange(&query_bytes, method).await;
+        if result.is_err() && method != self.method() {
+            // The server rejected GET; retry the query as POST
+            doh_stats().record(self.server.id, method, None);
+            method = self.method();
+            attempt_start = Instant::now();
+            result = self.exchange(&query_bytes, method).await;
         }
-        
-        let response_bytes = response.bytes().await?;
-        let response_time = start.elapsed();
-        
-        let dns_response = DnsResponse::from_bytes(&response_bytes)
-            .map_err(|e| anyhow!("Failed to parse response: {}", e))?;
-        
+        let attempt_ms = attempt_start.elapsed().as_millis() as u64;
+        doh_stats().record(self.server.id, method, result.as_ref().ok().map(|_| attempt_ms));
+
+        let mut dns_response = result?;
+        if id_zeroed {
+            dns_response.id = query.id;
+        }
+
         Ok(QueryResult {
             response: dns_response,
-            response_time_ms: response_time.as_millis() as u64,
+            response_time_ms: start.elapsed().as_millis() as u64,
             server_id: self.server.id,
             server_name: self.server.name.clone(),
         })
@@ -569,7 +1146,7 @@ impl DnsClient for DohDnsClient {
 /// Queries upstream DNS servers using DNS over QUIC protocol.
 pub struct DoqDnsClient {
     server: UpstreamServer,
-    connections: Vec<Arc<tokio::sync::RwLock<Option<quinn::Connection>>>>,
+    connections: Vec<Arc<ConnectionSlot<quinn::Connection>>>,
     connect_locks: Vec<Arc<tokio::sync::Mutex<()>>>,
     index: AtomicUsize,
 }
@@ -577,17 +1154,15 @@ pub struct DoqDnsClient {
 impl DoqDnsClient {
     /// Create a new DoQ DNS client
     pub fn new(server: UpstreamServer) -> Self {
-        let mut connections = Vec::with_capacity(ENDPOINT_POOL_SIZE);
         let mut connect_locks = Vec::with_capacity(ENDPOINT_POOL_SIZE);
         
         for _ in 0..ENDPOINT_POOL_SIZE {
-            connections.push(Arc::new(tokio::sync::RwLock::new(None)));
             connect_locks.push(Arc::new(tokio::sync::Mutex::new(())));
         }
 
         Self { 
             server,
-            connections,
+            connections: new_connection_slots(ENDPOINT_POOL_SIZE),
             connect_locks,
             index: AtomicUsize::new(0),
         }
@@ -650,12 +1225,9 @@ impl DoqDnsClient {
             return Err(anyhow!("No addresses found for {}", host));
         }
 
-        // Prefer IPv4 addresses
-        let addr = addrs.iter()
-            .find(|a| a.is_ipv4())
-            .or_else(|| addrs.first())
-            .cloned()
-            .ok_or_else(|| anyhow!("No addresses found for {}", host))?;
+        // Prefer IPv4 addresses (or the source address family)
+        let addr = self.server.source.select_target(&addrs)
+            .ok_or_else(|| anyhow!("No usable addresses found for {}", host))?;
         
         Ok((addr, host))
     }
@@ -667,6 +1239,7 @@ impl DnsClient for DoqDnsClient {
         use tracing::debug;
 
         let (addr, sni_host) = self.resolve_address().await?;
+        let sni_host = self.server.tls_name(&sni_host).to_string();
         
         // Loop to allow one retry if cached connection fails
         let mut attempts = 0;
@@ -690,7 +1263,7 @@ impl DnsClient for DoqDnsClient {
                 None
             } else {
                 let guard = connection_slot.read().await;
-                guard.clone().filter(|c| is_healthy(c))
+                guard.as_ref().filter(|p| is_healthy(&p.conn)).map(|p| p.touch().clone())
             };
             
             let connection = if let Some(conn) = connection {
@@ -702,7 +1275,7 @@ impl DnsClient for DoqDnsClient {
 
                 // 3. Double check
                 let guard = connection_slot.read().await;
-                if let Some(conn) = guard.clone().filter(|c| is_healthy(c)) {
+                if let Some(conn) = guard.as_ref().filter(|p| is_healthy(&p.conn)).map(|p| p.touch().clone()) {
                     drop(guard);
                     debug!("DoQ reused connection created by another thread to {} (slot {})", addr, idx);
                     conn
@@ -712,20 +1285,16 @@ impl DnsClient for DoqDnsClient {
                     debug!("DoQ creating new connection to {} (SNI: {}, slot {})", addr, sni_host, idx);
                     
                     // Get or create cached endpoint
-                    let endpoint = get_quic_endpoint(QuicProtocol::Doq, addr.is_ipv6())?;
+                    let endpoint = get_quic_endpoint(QuicProtocol::Doq, addr, &self.server.source)?;
                     let connect_sni = sni_host.as_str();
-                    
-                    match timeout(self.server.timeout, endpoint.connect(addr, connect_sni)?).await {
-                        Ok(Ok(conn)) => {
-                            debug!("DoQ connection established to {} (slot {})", addr, idx);
-                            // Update cache
-                            let mut guard = connection_slot.write().await;
-                            *guard = Some(conn.clone());
-                            conn
-                        },
-                        Ok(Err(e)) => return Err(anyhow!("Connection failed: {}", e)),
-                        Err(_) => return Err(anyhow!("Connection timeout")),
-                    }
+                    let permit = connection_manager().acquire(ConnectionKind::Doq)?;
+                    let connecting = connect_quic(&endpoint, QuicProtocol::Doq, &self.server, addr, connect_sni)?;
+                    let conn = establish_quic(connecting, self.server.timeout).await?;
+                    debug!("DoQ connection established to {} (slot {})", addr, idx);
+                    // Update cache
+                    let mut guard = connection_slot.write().await;
+                    *guard = Some(Pooled::new(conn.clone(), permit));
+                    conn
                 }
             };
 
@@ -737,8 +1306,7 @@ impl DnsClient for DoqDnsClient {
                 
                 // Encode query
                 let doq_query = DnsQuery::with_id(0, &query.name, query.record_type.clone());
-                let query_bytes = doq_query.to_bytes()
-                    .map_err(|e| anyhow!("Failed to encode query: {}", e))?;
+                let query_bytes = encode_marked(&doq_query)?;
                 let len = (query_bytes.len() as u16).to_be_bytes();
                 
                 let start = Instant::now();
@@ -747,6 +1315,7 @@ impl DnsClient for DoqDnsClient {
                 send.write_all(&len).await?;
                 send.write_all(&query_bytes).await?;
                 send.finish().map_err(|e| anyhow!("Failed to finish stream: {}", e))?;
+                upstream_traffic().record_sent(self.server.id, len.len() + query_bytes.len());
                 
                 // Read response
                 let mut len_buf = [0u8; 2];
@@ -762,6 +1331,7 @@ impl DnsClient for DoqDnsClient {
                 let mut response_bytes = vec![0u8; response_len];
                 recv.read_exact(&mut response_bytes).await
                     .map_err(|e| anyhow!("Failed to read response body: {}", e))?;
+                upstream_traffic().record_received(self.server.id, len_buf.len() + response_len);
                     
                 let response_time = start.elapsed();
                 let response = DnsResponse::from_bytes(&response_bytes)
@@ -805,6 +1375,16 @@ impl DnsClient for DoqDnsClient {
         let _ = self.query(&query).await?;
         Ok(start.elapsed())
     }
+
+    fn quic_connections(&self) -> Vec<QuicConnectionStats> {
+        self.connections
+            .iter()
+            .filter_map(|slot| {
+                let guard = slot.try_read().ok()?;
+                guard.as_ref().map(|p| QuicConnectionStats::from_connection(&p.conn))
+            })
+            .collect()
+    }
 }
 
 /// Certificate verifier that accepts any certificate (for IP-based connections)
@@ -865,25 +1445,35 @@ impl rustls::client::danger::ServerCertVerifier for NoVerifier {
 /// The QUIC endpoint is reused for better performance.
 pub struct Doh3DnsClient {
     server: UpstreamServer,
-    connections: Vec<Arc<tokio::sync::RwLock<Option<H3SendRequest>>>>,
+    connections: Vec<Arc<ConnectionSlot<Doh3Connection>>>,
     connect_locks: Vec<Arc<tokio::sync::Mutex<()>>>,
     index: AtomicUsize,
 }
 
+/// Cached HTTP/3 session and the QUIC connection carrying it
+struct Doh3Connection {
+    sender: H3SendRequest,
+    connection: quinn::Connection,
+}
+
+impl UpstreamConnection for Doh3Connection {
+    fn shutdown(&self) {
+        self.connection.shutdown();
+    }
+}
+
 impl Doh3DnsClient {
     /// Create a new DoH3 DNS client
     pub fn new(server: UpstreamServer) -> Self {
-        let mut connections = Vec::with_capacity(ENDPOINT_POOL_SIZE);
         let mut connect_locks = Vec::with_capacity(ENDPOINT_POOL_SIZE);
         
         for _ in 0..ENDPOINT_POOL_SIZE {
-            connections.push(Arc::new(tokio::sync::RwLock::new(None)));
             connect_locks.push(Arc::new(tokio::sync::Mutex::new(())));
         }
 
         Self {
             server,
-            connections,
+            connections: new_connection_slots(ENDPOINT_POOL_SIZE),
             connect_locks,
             index: AtomicUsize::new(0),
         }
@@ -967,12 +1557,9 @@ impl Doh3DnsClient {
             .map_err(|e| anyhow!("Failed to resolve hostname {}: {}", host, e))?
             .collect();
         
-        // Prefer IPv4 for better compatibility, but accept IPv6
-        addrs.iter()
-            .find(|a| a.is_ipv4())
-            .or_else(|| addrs.first())
-            .cloned()
-            .ok_or_else(|| anyhow!("No addresses found for {}", host))
+        // Prefer IPv4 for better compatibility (or the source address family)
+        self.server.source.select_target(&addrs)
+            .ok_or_else(|| anyhow!("No usable addresses found for {}", host))
     }
 }
 
@@ -984,6 +1571,7 @@ impl DnsClient for Doh3DnsClient {
         use tracing::debug;
 
         let (sni_host, host, port, path) = self.parse_url()?;
+        let sni_host = self.server.tls_name(&sni_host).to_string();
         let addr = self.resolve_address(&host, port).await?;
         
         debug!("DoH3 connecting to {} (SNI: {}, path: {})", addr, sni_host, path);
@@ -998,8 +1586,7 @@ impl DnsClient for Doh3DnsClient {
         let start = std::time::Instant::now();
 
         // ENCODE QUERY
-        let query_bytes = query.to_bytes()
-            .map_err(|e| anyhow!("Failed to encode query: {}", e))?;
+        let query_bytes = encode_marked(query)?;
         
         let mut attempts = 0;
         
@@ -1009,11 +1596,12 @@ impl DnsClient for Doh3DnsClient {
             let is_retry = attempts > 1;
 
             // 1. Get connection (cached)
+            let is_healthy = |c: &Doh3Connection| c.connection.close_reason().is_none();
             let mut sender = if is_retry {
                 None
             } else {
                 let guard = connection_slot.read().await;
-                guard.clone()
+                guard.as_ref().filter(|p| is_healthy(&p.conn)).map(|p| p.touch().sender.clone())
             };
 
             // 2. If no cached connection, create new one
@@ -1022,28 +1610,28 @@ impl DnsClient for Doh3DnsClient {
                 
                 // Double check
                 let guard = connection_slot.read().await;
-                if let Some(s) = guard.clone() {
+                if let Some(s) = guard.as_ref().filter(|p| is_healthy(&p.conn)).map(|p| p.touch().sender.clone()) {
                     sender = Some(s);
                 } else {
                     drop(guard);
                     
                     // Get or create cached endpoint
-                    let endpoint = get_quic_endpoint(QuicProtocol::Doh3, addr.is_ipv6())?;
+                    let endpoint = get_quic_endpoint(QuicProtocol::Doh3, addr, &self.server.source)?;
                     let connect_sni = sni_host.as_str();
+                    let permit = connection_manager().acquire(ConnectionKind::Doh3)?;
 
                     debug!("DoH3 creating new connection to {} (slot {})", addr, idx);
 
                     // Create new QUIC connection
-                    let connection = timeout(
+                    let connection = establish_quic(
+                        connect_quic(&endpoint, QuicProtocol::Doh3, &self.server, addr, connect_sni)?,
                         self.server.timeout,
-                        endpoint.connect(addr, connect_sni)?
-                    ).await
-                        .map_err(|_| anyhow!("Connection timeout"))??;
+                    ).await?;
 
                     debug!("DoH3 QUIC connection established (slot {})", idx);
 
                     // Create HTTP/3 session
-                    let quinn_conn = h3_quinn::Connection::new(connection);
+                    let quinn_conn = h3_quinn::Connection::new(connection.clone());
                     let (mut driver, new_sender) = h3::client::new(quinn_conn).await
                         .map_err(|e| anyhow!("Failed to create H3 connection: {}", e))?;
 
@@ -1054,7 +1642,10 @@ impl DnsClient for Doh3DnsClient {
                     
                     // Update cache
                     let mut guard = connection_slot.write().await;
-                    *guard = Some(new_sender.clone());
+                    *guard = Some(Pooled::new(
+                        Doh3Connection { sender: new_sender.clone(), connection },
+                        permit,
+                    ));
                     sender = Some(new_sender);
                 }
             }
@@ -1093,11 +1684,13 @@ impl DnsClient for Doh3DnsClient {
         };
 
         // Send body
+        let query_len = query_bytes.len();
         request_stream.send_data(Bytes::from(query_bytes)).await
             .map_err(|e| anyhow!("Failed to send body: {}", e))?;
             
         request_stream.finish().await
             .map_err(|e| anyhow!("Failed to finish request: {}", e))?;
+        upstream_traffic().record_sent(self.server.id, query_len);
 
         // Receive response
         let response = request_stream.recv_response().await
@@ -1122,6 +1715,7 @@ impl DnsClient for Doh3DnsClient {
             }
         }
 
+        upstream_traffic().record_received(self.server.id, response_bytes.len());
         let response_time = start.elapsed();
         debug!("DoH3 received {} bytes in {:?}", response_bytes.len(), response_time);
 
@@ -1146,6 +1740,16 @@ impl DnsClient for Doh3DnsClient {
         let _ = self.query(&query).await?;
         Ok(start.elapsed())
     }
+
+    fn quic_connections(&self) -> Vec<QuicConnectionStats> {
+        self.connections
+            .iter()
+            .filter_map(|slot| {
+                let guard = slot.try_read().ok()?;
+                guard.as_ref().map(|p| QuicConnectionStats::from_connection(&p.conn.connection))
+            })
+            .collect()
+    }
 }
 
 
@@ -1153,6 +1757,7 @@ impl DnsClient for Doh3DnsClient {
 pub fn create_client(server: UpstreamServer) -> Box<dyn DnsClient> {
     match server.protocol {
         UpstreamProtocol::Udp => Box::new(UdpDnsClient::new(server)),
+        UpstreamProtocol::Tcp => Box::new(TcpDnsClient::new(server)),
         UpstreamProtocol::Dot => Box::new(DotDnsClient::new(server)),
         UpstreamProtocol::Doh => Box::new(DohDnsClient::new(server)),
         UpstreamProtocol::Doq => Box::new(DoqDnsClient::new(server)),
@@ -1209,18 +1814,153 @@ mod tests {
         assert_eq!(client.server().protocol, UpstreamProtocol::Doh3);
     }
 
+    #[test]
+    fn test_tls_server_name_override() {
+        let server = UpstreamServer::new(
+            1, "Test", "1.1.1.1:853", UpstreamProtocol::Dot, 5000,
+        );
+        assert!(server.verify_hostname);
+        assert_eq!(server.tls_name("1.1.1.1"), "1.1.1.1");
+        let server = server.with_tls(Some("one.one.one.one".to_string()), true);
+        assert_eq!(server.tls_name("1.1.1.1"), "one.one.one.one");
+
+        // QUIC upstreams only verify on request
+        let server = UpstreamServer::new(
+            1, "Test", "94.140.14.14:853", UpstreamProtocol::Doq, 5000,
+        );
+        assert!(!server.verify_hostname);
+    }
+
+    #[test]
+    fn test_doh_pin_server_name() {
+        let (url, addr) = DohDnsClient::pin_server_name(
+            "https://1.1.1.1/dns-query", "cloudflare-dns.com",
+        ).unwrap();
+        assert_eq!(url, "https://cloudflare-dns.com/dns-query");
+        assert_eq!(addr, "1.1.1.1:443".parse().unwrap());
+
+        let (url, addr) = DohDnsClient::pin_server_name(
+            "https://[2606:4700:4700::1111]:8443/dns-query", "cloudflare-dns.com",
+        ).unwrap();
+        assert_eq!(url, "https://cloudflare-dns.com:8443/dns-query");
+        assert_eq!(addr, "[2606:4700:4700::1111]:8443".parse().unwrap());
+
+        assert!(DohDnsClient::pin_server_name("https://dns.google/dns-query", "dns.google").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_udp_source_family_mismatch() {
+        let server = UpstreamServer::new(
+            1, "Test", "[::1]:53", UpstreamProtocol::Udp, 1000,
+        ).with_source(SourceBinding::new(Some("127.0.0.1".parse().unwrap()), None));
+        let client = create_client(server);
+        let query = DnsQuery::new("example.com", crate::dns::message::RecordType::A);
+        let err = client.query(&query).await.unwrap_err();
+        assert!(err.to_string().contains("address family mismatch"));
+    }
+
+    #[tokio::test]
+    async fn test_tcp_client_reuses_connection() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    // Echo each query back as an empty answer
+                    let mut len = [0u8; 2];
+                    while stream.read_exact(&mut len).await.is_ok() {
+                        let mut message = vec![0u8; u16::from_be_bytes(len) as usize];
+                        stream.read_exact(&mut message).await.unwrap();
+                        message[2] |= 0x80;
+                        stream.write_all(&len).await.unwrap();
+                        stream.write_all(&message).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        let server = UpstreamServer::new(1, "Mock", addr.to_string(), UpstreamProtocol::Tcp, 500);
+        let client = create_client(server);
+        assert_eq!(client.server().protocol, UpstreamProtocol::Tcp);
+        let query = DnsQuery::new("example.com", crate::dns::message::RecordType::A);
+        for _ in 0..2 {
+            let result = client.query(&query).await.unwrap();
+            assert!(result.response.answers.is_empty());
+        }
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+
+    /// UDP server answering with a server cookie, echoing the client cookie
+    /// unless `spoof` is set
+    async fn spawn_cookie_server(spoof: bool) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            while let Ok((len, from)) = socket.recv_from(&mut buf).await {
+                let opt = WireSummary::parse(&buf[..len]).unwrap().opt.unwrap();
+                let request_cookie = opt.option(EDNS_OPTION_COOKIE).unwrap();
+                // Strip the query's OPT record, which ends the message
+                let opt_len = 11 + opt.options.iter().map(|(_, data)| 4 + data.len()).sum::<usize>();
+                let mut response = buf[..len - opt_len].to_vec();
+                response[2] |= 0x80;
+                response[11] -= 1;
+                let mut cookie = if spoof { vec![0u8; 8] } else { request_cookie[..8].to_vec() };
+                cookie.extend_from_slice(&[7u8; 8]);
+                append_opt_record(&mut response, 1232, false, &[(EDNS_OPTION_COOKIE, &cookie)]);
+                let _ = socket.send_to(&response, from).await;
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_udp_client_cookies() {
+        use crate::dns::{DnsCookies, UpstreamCapabilities};
+
+        let capabilities = UpstreamCapabilities {
+            edns: true,
+            cookies: true,
+            probed_at: chrono::Utc::now(),
+            ..Default::default()
+        };
+        let query = DnsQuery::new("example.com", crate::dns::message::RecordType::A);
+
+        let addr = spawn_cookie_server(false).await;
+        let server = UpstreamServer::new(1, "Mock", addr.to_string(), UpstreamProtocol::Udp, 500)
+            .with_capabilities(capabilities.clone());
+        let client = UdpDnsClient::new(server);
+        client.query(&query).await.unwrap();
+        assert_eq!(client.server_cookie.lock().unwrap().as_deref(), Some(&[7u8; 8][..]));
+        // The next query echoes the server cookie
+        client.query(&query).await.unwrap();
+
+        // Answers with someone else's client cookie are dropped and counted
+        let before = DnsCookies::default().stats().upstream_mismatches;
+        let addr = spawn_cookie_server(true).await;
+        let server = UpstreamServer::new(2, "Spoofed", addr.to_string(), UpstreamProtocol::Udp, 500)
+            .with_capabilities(capabilities);
+        assert!(UdpDnsClient::new(server).query(&query).await.is_err());
+        assert!(DnsCookies::default().stats().upstream_mismatches > before);
+    }
+
     #[test]
     fn test_doh_url_generation() {
         let server = UpstreamServer::new(
             1, "Test", "dns.google", UpstreamProtocol::Doh, 5000,
         );
         let client = DohDnsClient::new(server);
-        assert_eq!(client.get_url(), "https://dns.google/dns-query");
+        assert_eq!(client.url, "https://dns.google/dns-query");
 
         let server2 = UpstreamServer::new(
             2, "Test2", "https://cloudflare-dns.com/dns-query", UpstreamProtocol::Doh, 5000,
         );
         let client2 = DohDnsClient::new(server2);
-        assert_eq!(client2.get_url(), "https://cloudflare-dns.com/dns-query");
+        assert_eq!(client2.url, "https://cloudflare-dns.com/dns-query");
     }
 }
@@ -0,0 +1,368 @@
+//! Upstream Connection Manager
+//!
+//! DoT, DoQ and DoH3 clients keep their upstream connections open between
+//! queries, and QUIC endpoints are shared by all clients. Without limits
+//! these caches only ever grow, so the manager:
+//!
+//! - tracks when each pooled connection was last used and closes it once
+//!   it has been idle for longer than the configured period
+//! - drops cached QUIC endpoints (one UDP socket each) that are idle and
+//!   carry no connection
+//! - caps the number of open upstream connections; a connection beyond the
+//!   ceiling is refused and the query fails over like any upstream error
+//!
+//! Current counts are reported in `/api/status`.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock, Weak};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::config::AppConfig;
+
+/// Longest pause between two idle sweeps
+const MAX_REAP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Protocols with pooled upstream connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionKind {
+    Dot,
+    Doq,
+    Doh3,
+    Tcp,
+}
+
+impl ConnectionKind {
+    fn index(self) -> usize {
+        match self {
+            Self::Dot => 0,
+            Self::Doq => 1,
+            Self::Doh3 => 2,
+            Self::Tcp => 3,
+        }
+    }
+}
+
+/// Idle timeout and connection ceiling
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    /// Close connections unused for this long, zero keeps them open
+    pub idle_timeout: Duration,
+    /// Open connections allowed across all upstreams, zero for no ceiling
+    pub max_connections: usize,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(300),
+            max_connections: 256,
+        }
+    }
+}
+
+impl ConnectionLimits {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(config.upstream_idle_timeout_secs),
+            max_connections: config.upstream_max_connections,
+        }
+    }
+}
+
+/// Upstream connection counters
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ConnectionStats {
+    pub dot: usize,
+    pub doq: usize,
+    pub doh3: usize,
+    pub tcp: usize,
+    /// Open connections of all protocols
+    pub total: usize,
+    /// Ceiling on `total`, zero when unlimited
+    pub max_connections: usize,
+    /// Cached QUIC endpoints, each holding one UDP socket
+    pub quic_endpoints: usize,
+    pub idle_timeout_secs: u64,
+    /// Connections closed for being idle since startup
+    pub reaped: u64,
+    /// Connections refused at the ceiling since startup
+    pub refused: u64,
+}
+
+/// A cache of upstream connections the manager can sweep
+pub trait IdleSlot: Send + Sync {
+    /// Close connections unused for at least `idle_timeout`, returning how many
+    ///
+    /// Caches that are busy are skipped until the next sweep.
+    fn close_idle(&self, idle_timeout: Duration) -> usize;
+}
+
+/// A connection that must be closed explicitly, not just dropped
+pub trait UpstreamConnection: Send + Sync {
+    fn shutdown(&self);
+}
+
+impl UpstreamConnection for quinn::Connection {
+    fn shutdown(&self) {
+        self.close(0u32.into(), b"idle");
+    }
+}
+
+/// Slot caching at most one connection
+pub type ConnectionSlot<T> = tokio::sync::RwLock<Option<Pooled<T>>>;
+
+impl<T: UpstreamConnection> IdleSlot for ConnectionSlot<T> {
+    fn close_idle(&self, idle_timeout: Duration) -> usize {
+        let Ok(mut slot) = self.try_write() else {
+            return 0;
+        };
+        match slot.take_if(|pooled| pooled.idle_for() >= idle_timeout) {
+            Some(pooled) => {
+                pooled.conn.shutdown();
+                1
+            }
+            None => 0,
+        }
+    }
+}
+
+/// A pooled connection with its last-use time and its share of the ceiling
+pub struct Pooled<T> {
+    pub conn: T,
+    last_used: Mutex<Instant>,
+    _permit: ConnectionPermit,
+}
+
+impl<T> Pooled<T> {
+    pub fn new(conn: T, permit: ConnectionPermit) -> Self {
+        Self {
+            conn,
+            last_used: Mutex::new(Instant::now()),
+            _permit: permit,
+        }
+    }
+
+    /// Mark the connection as used and return it
+    pub fn touch(&self) -> &T {
+        *self.last_used.lock().unwrap() = Instant::now();
+        &self.conn
+    }
+
+    pub fn idle_for(&self) -> Duration {
+        self.last_used.lock().unwrap().elapsed()
+    }
+}
+
+/// Counts one open connection until dropped
+pub struct ConnectionPermit {
+    manager: &'static ConnectionManager,
+    kind: ConnectionKind,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.manager.open[self.kind.index()].fetch_sub(1, Ordering::Relaxed);
+        self.manager.total.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Tracks and limits upstream connections across all clients
+pub struct ConnectionManager {
+    limits: RwLock<ConnectionLimits>,
+    open: [AtomicUsize; 4],
+    total: AtomicUsize,
+    quic_endpoints: AtomicUsize,
+    reaped: AtomicU64,
+    refused: AtomicU64,
+    slots: Mutex<Vec<Weak<dyn IdleSlot>>>,
+}
+
+static CONNECTION_MANAGER: OnceLock<ConnectionManager> = OnceLock::new();
+
+/// The process-wide connection manager
+pub fn connection_manager() -> &'static ConnectionManager {
+    CONNECTION_MANAGER.get_or_init(|| ConnectionManager::new(ConnectionLimits::default()))
+}
+
+impl ConnectionManager {
+    pub fn new(limits: ConnectionLimits) -> Self {
+        Self {
+            limits: RwLock::new(limits),
+            open: Default::default(),
+            total: AtomicUsize::new(0),
+            quic_endpoints: AtomicUsize::new(0),
+            reaped: AtomicU64::new(0),
+            refused: AtomicU64::new(0),
+            slots: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn limits(&self) -> ConnectionLimits {
+        *self.limits.read().unwrap()
+    }
+
+    /// Replace the limits; open connections above a lowered ceiling are
+    /// closed as they go idle
+    pub fn configure(&self, limits: ConnectionLimits) {
+        *self.limits.write().unwrap() = limits;
+    }
+
+    /// Reserve room for a new connection, failing at the ceiling
+    pub fn acquire(&'static self, kind: ConnectionKind) -> Result<ConnectionPermit> {
+        let max = self.limits().max_connections;
+        let reserved = self
+            .total
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |total| {
+                (max == 0 || total < max).then_some(total + 1)
+            });
+        if reserved.is_err() {
+            self.refused.fetch_add(1, Ordering::Relaxed);
+            return Err(anyhow!("Upstream connection limit reached ({} open)", max));
+        }
+        self.open[kind.index()].fetch_add(1, Ordering::Relaxed);
+        Ok(ConnectionPermit { manager: self, kind })
+    }
+
+    /// Include a connection cache in idle sweeps for as long as it lives
+    pub fn register(&self, slot: Weak<dyn IdleSlot>) {
+        self.slots.lock().unwrap().push(slot);
+    }
+
+    pub fn endpoints_opened(&self, count: usize) {
+        self.quic_endpoints.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn endpoints_closed(&self, count: usize) {
+        self.quic_endpoints.fetch_sub(count, Ordering::Relaxed);
+    }
+
+    /// Sweep every registered cache once, returning the connections closed
+    pub fn close_idle(&self, idle_timeout: Duration) -> usize {
+        let slots: Vec<_> = {
+            let mut slots = self.slots.lock().unwrap();
+            slots.retain(|slot| slot.strong_count() > 0);
+            slots.iter().filter_map(Weak::upgrade).collect()
+        };
+        let closed: usize = slots.iter().map(|slot| slot.close_idle(idle_timeout)).sum();
+        self.reaped.fetch_add(closed as u64, Ordering::Relaxed);
+        closed
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        let limits = self.limits();
+        ConnectionStats {
+            dot: self.open[ConnectionKind::Dot.index()].load(Ordering::Relaxed),
+            doq: self.open[ConnectionKind::Doq.index()].load(Ordering::Relaxed),
+            doh3: self.open[ConnectionKind::Doh3.index()].load(Ordering::Relaxed),
+            tcp: self.open[ConnectionKind::Tcp.index()].load(Ordering::Relaxed),
+            total: self.total.load(Ordering::Relaxed),
+            max_connections: limits.max_connections,
+            quic_endpoints: self.quic_endpoints.load(Ordering::Relaxed),
+            idle_timeout_secs: limits.idle_timeout.as_secs(),
+            reaped: self.reaped.load(Ordering::Relaxed),
+            refused: self.refused.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Close idle connections in the background
+    pub fn start_reaper(&'static self) {
+        tracing::info!("Upstream connection reaper started");
+        tokio::spawn(async move {
+            loop {
+                let idle_timeout = self.limits().idle_timeout;
+                if idle_timeout.is_zero() {
+                    tokio::time::sleep(MAX_REAP_INTERVAL).await;
+                    continue;
+                }
+                let closed = self.close_idle(idle_timeout);
+                if closed > 0 {
+                    tracing::debug!("Closed {} idle upstream connections", closed);
+                }
+                let interval = (idle_timeout / 4).clamp(Duration::from_secs(1), MAX_REAP_INTERVAL);
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct TestConnection {
+        closed: AtomicBool,
+    }
+
+    impl UpstreamConnection for Arc<TestConnection> {
+        fn shutdown(&self) {
+            self.closed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn manager(max_connections: usize) -> &'static ConnectionManager {
+        Box::leak(Box::new(ConnectionManager::new(ConnectionLimits {
+            idle_timeout: Duration::from_secs(300),
+            max_connections,
+        })))
+    }
+
+    #[test]
+    fn test_connection_ceiling() {
+        let limited = manager(2);
+        let first = limited.acquire(ConnectionKind::Dot).unwrap();
+        let _second = limited.acquire(ConnectionKind::Doq).unwrap();
+        assert!(limited.acquire(ConnectionKind::Doh3).is_err());
+
+        let stats = limited.stats();
+        assert_eq!((stats.dot, stats.doq, stats.doh3, stats.total), (1, 1, 0, 2));
+        assert_eq!(stats.refused, 1);
+
+        // Dropping a connection frees its place
+        drop(first);
+        assert!(limited.acquire(ConnectionKind::Doh3).is_ok());
+        assert_eq!(limited.stats().dot, 0);
+
+        // Zero disables the ceiling
+        let unlimited = manager(0);
+        let permits: Vec<_> = (0..10).map(|_| unlimited.acquire(ConnectionKind::Dot).unwrap()).collect();
+        assert_eq!(unlimited.stats().total, permits.len());
+    }
+
+    #[tokio::test]
+    async fn test_close_idle_connections() {
+        let manager = manager(0);
+        let conn = Arc::new(TestConnection::default());
+        let slot: Arc<ConnectionSlot<Arc<TestConnection>>> = Arc::new(tokio::sync::RwLock::new(Some(
+            Pooled::new(conn.clone(), manager.acquire(ConnectionKind::Doq).unwrap()),
+        )));
+        let weak = Arc::downgrade(&slot) as Weak<dyn IdleSlot>;
+        manager.register(weak);
+
+        // Recently used connections stay open
+        assert_eq!(manager.close_idle(Duration::from_secs(60)), 0);
+        assert!(slot.read().await.is_some());
+
+        // Busy slots are skipped
+        {
+            let _busy = slot.write().await;
+            assert_eq!(manager.close_idle(Duration::ZERO), 0);
+        }
+
+        assert_eq!(manager.close_idle(Duration::ZERO), 1);
+        assert!(slot.read().await.is_none());
+        assert!(conn.closed.load(Ordering::Relaxed));
+        let stats = manager.stats();
+        assert_eq!((stats.doq, stats.total, stats.reaped), (0, 0, 1));
+
+        // Dropped caches are forgotten
+        drop(slot);
+        manager.close_idle(Duration::ZERO);
+        assert!(manager.slots.lock().unwrap().is_empty());
+    }
+}
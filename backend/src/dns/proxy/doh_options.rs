@@ -0,0 +1,260 @@
+//! DoH Request Options
+//!
+//! DoH upstreams send queries as POST requests by default. With
+//! `doh_method = get` they are sent as `GET ?dns=<base64url>` (RFC 8484
+//! §4.1) with the message ID set to zero, which some providers answer
+//! faster and HTTP caches can serve. `doh_http_version` pins HTTP/1.1 or
+//! HTTP/2 instead of negotiating the version during the TLS handshake.
+//!
+//! A server that rejects GET (405, 414, 415 or 501) is queried with POST
+//! from then on; a server that fails over pinned HTTP/2 is queried with a
+//! negotiated version from then on. Latency per method and the fallbacks
+//! are kept per upstream and reported in `/api/upstreams/status`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+
+/// HTTP method used for DoH queries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DohMethod {
+    #[default]
+    Post,
+    Get,
+}
+
+impl DohMethod {
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "post" => Some(Self::Post),
+            "get" => Some(Self::Get),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Post => "post",
+            Self::Get => "get",
+        }
+    }
+}
+
+/// HTTP version used for DoH queries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DohHttpVersion {
+    /// Negotiated with the server (ALPN)
+    #[default]
+    Auto,
+    Http1,
+    Http2,
+}
+
+impl DohHttpVersion {
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "auto" => Some(Self::Auto),
+            "http1" | "http/1.1" | "http1.1" => Some(Self::Http1),
+            "http2" | "http/2" | "h2" => Some(Self::Http2),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Http1 => "http1",
+            Self::Http2 => "http2",
+        }
+    }
+}
+
+/// Whether an HTTP status means the server does not accept GET queries
+pub fn rejects_get(status: u16) -> bool {
+    matches!(status, 405 | 414 | 415 | 501)
+}
+
+/// URL of a GET query: `dns=` with the message in unpadded base64url
+pub fn get_query_url(url: &str, message: &[u8]) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}dns={}", url, separator, URL_SAFE_NO_PAD.encode(message))
+}
+
+/// Queries sent with one method
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct DohMethodStats {
+    pub queries: u64,
+    pub failures: u64,
+    /// Average response time of successful queries
+    pub avg_response_time_ms: f64,
+}
+
+/// DoH request counters of one upstream
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct DohStats {
+    pub post: DohMethodStats,
+    pub get: DohMethodStats,
+    /// Server rejected GET; queries go out as POST
+    pub get_fallback: bool,
+    /// Server failed over pinned HTTP/2; the version is negotiated
+    pub http2_fallback: bool,
+}
+
+#[derive(Default)]
+struct MethodCounters {
+    queries: u64,
+    failures: u64,
+    total_ms: u64,
+}
+
+impl MethodCounters {
+    fn stats(&self) -> DohMethodStats {
+        let successes = self.queries - self.failures;
+        DohMethodStats {
+            queries: self.queries,
+            failures: self.failures,
+            avg_response_time_ms: if successes == 0 {
+                0.0
+            } else {
+                self.total_ms as f64 / successes as f64
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+struct DohCounters {
+    post: MethodCounters,
+    get: MethodCounters,
+    get_fallback: bool,
+    http2_fallback: bool,
+}
+
+/// DoH counters of all upstreams, keyed by server ID
+pub struct DohRequestStats {
+    servers: RwLock<HashMap<i64, Arc<Mutex<DohCounters>>>>,
+}
+
+static DOH_STATS: OnceLock<DohRequestStats> = OnceLock::new();
+
+/// The process-wide DoH request counters
+pub fn doh_stats() -> &'static DohRequestStats {
+    DOH_STATS.get_or_init(DohRequestStats::new)
+}
+
+#[allow(dead_code)]
+impl DohRequestStats {
+    pub fn new() -> Self {
+        Self {
+            servers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn counters(&self, id: i64) -> Arc<Mutex<DohCounters>> {
+        if let Some(counters) = self.servers.read().unwrap().get(&id) {
+            return counters.clone();
+        }
+        self.servers.write().unwrap().entry(id).or_default().clone()
+    }
+
+    /// Count a query; `response_time_ms` is None when it failed
+    pub fn record(&self, id: i64, method: DohMethod, response_time_ms: Option<u64>) {
+        let counters = self.counters(id);
+        let mut counters = counters.lock().unwrap();
+        let method = match method {
+            DohMethod::Post => &mut counters.post,
+            DohMethod::Get => &mut counters.get,
+        };
+        method.queries += 1;
+        match response_time_ms {
+            Some(ms) => method.total_ms += ms,
+            None => method.failures += 1,
+        }
+    }
+
+    /// Note that a server rejected GET
+    pub fn record_get_fallback(&self, id: i64) {
+        self.counters(id).lock().unwrap().get_fallback = true;
+    }
+
+    /// Note that a server failed over pinned HTTP/2
+    pub fn record_http2_fallback(&self, id: i64) {
+        self.counters(id).lock().unwrap().http2_fallback = true;
+    }
+
+    /// Counters of one server, None when it never sent a DoH query
+    pub fn stats(&self, id: i64) -> Option<DohStats> {
+        let counters = self.servers.read().unwrap().get(&id)?.clone();
+        let counters = counters.lock().unwrap();
+        Some(DohStats {
+            post: counters.post.stats(),
+            get: counters.get.stats(),
+            get_fallback: counters.get_fallback,
+            http2_fallback: counters.http2_fallback,
+        })
+    }
+
+    /// Drop the counters of a deleted or reconfigured server
+    pub fn remove(&self, id: i64) {
+        self.servers.write().unwrap().remove(&id);
+    }
+}
+
+impl Default for DohRequestStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(DohMethod::from_str("GET"), Some(DohMethod::Get));
+        assert_eq!(DohMethod::from_str("put"), None);
+        assert_eq!(DohHttpVersion::from_str("HTTP/1.1"), Some(DohHttpVersion::Http1));
+        assert_eq!(DohHttpVersion::from_str("h2"), Some(DohHttpVersion::Http2));
+        for version in [DohHttpVersion::Auto, DohHttpVersion::Http1, DohHttpVersion::Http2] {
+            assert_eq!(DohHttpVersion::from_str(version.as_str()), Some(version));
+        }
+    }
+
+    #[test]
+    fn test_get_query_url() {
+        assert_eq!(
+            get_query_url("https://dns.google/dns-query", &[0, 0, 1, 0]),
+            "https://dns.google/dns-query?dns=AAABAA"
+        );
+        assert_eq!(
+            get_query_url("https://doh.example/q?ecs=0", &[0xfb, 0xff]),
+            "https://doh.example/q?ecs=0&dns=-_8"
+        );
+        assert!(rejects_get(405));
+        assert!(!rejects_get(503));
+    }
+
+    #[test]
+    fn test_method_stats() {
+        let stats = DohRequestStats::new();
+        assert!(stats.stats(1).is_none());
+        stats.record(1, DohMethod::Get, Some(10));
+        stats.record(1, DohMethod::Get, Some(30));
+        stats.record(1, DohMethod::Get, None);
+        stats.record(1, DohMethod::Post, Some(50));
+        stats.record_get_fallback(1);
+
+        let server = stats.stats(1).unwrap();
+        assert_eq!((server.get.queries, server.get.failures), (3, 1));
+        assert_eq!(server.get.avg_response_time_ms, 20.0);
+        assert_eq!(server.post.avg_response_time_ms, 50.0);
+        assert!(server.get_fallback && !server.http2_fallback);
+    }
+}
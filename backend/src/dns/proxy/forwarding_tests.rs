@@ -28,6 +28,7 @@ mod property_tests {
         let cache = Arc::new(CacheManager::with_config(CacheConfig {
             default_ttl: 60,
             max_entries: 1000,
+            max_memory_mb: 0,
         }));
         let upstream_manager = Arc::new(UpstreamManager::new());
         let proxy = Arc::new(ProxyManager::new(upstream_manager));
@@ -41,6 +42,7 @@ mod property_tests {
         let cache = Arc::new(CacheManager::with_config(CacheConfig {
             default_ttl: 60,
             max_entries: 1000,
+            max_memory_mb: 0,
         }));
         let upstream_manager = Arc::new(UpstreamManager::new());
         let proxy = Arc::new(ProxyManager::new(upstream_manager));
@@ -0,0 +1,573 @@
+//! Handshake Speedtest
+//!
+//! Measures what a protocol costs on the network between us and one
+//! upstream: the time to set up a connection (TCP connect and TLS
+//! handshake, or the QUIC handshake), to answer the first query on the new
+//! connection, and to answer further queries once the connection is warm.
+//! The connection is opened next to the client pools, so pooled connections
+//! neither help nor skew the numbers, and closed afterwards. DoH is measured
+//! over HTTP/1.1 with keep-alive; UDP has no connection to set up and serves
+//! as the baseline.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{Buf, Bytes};
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use crate::dns::message::{DnsQuery, DnsResponse, RecordType};
+use super::client::{
+    connect_quic, encode_marked, get_quic_endpoint, parse_host_port, tls_client_config, DnsClient,
+    QuicProtocol, UdpDnsClient,
+};
+use super::connections::{connection_manager, ConnectionKind};
+use super::upstream::{UpstreamProtocol, UpstreamServer};
+
+/// Warm queries measured by default
+pub const DEFAULT_WARM_SAMPLES: usize = 5;
+/// Most warm queries measured per upstream
+pub const MAX_WARM_SAMPLES: usize = 20;
+
+/// Name queried during the speedtest, as in health checks
+const SPEEDTEST_NAME: &str = "dns.google";
+/// Largest HTTP/1.1 response head accepted from a DoH server
+const MAX_HTTP_HEAD_LEN: usize = 16 * 1024;
+
+/// Connection and query timings of one upstream, in milliseconds
+#[derive(Debug, Clone, Serialize)]
+pub struct HandshakeTiming {
+    pub upstream_id: i64,
+    pub name: String,
+    pub protocol: UpstreamProtocol,
+    pub address: String,
+    /// TCP connect (TCP, DoT, DoH)
+    pub tcp_connect_ms: Option<f64>,
+    /// TLS handshake over TCP (DoT, DoH) or QUIC handshake (DoQ, DoH3)
+    pub handshake_ms: Option<f64>,
+    /// Whole connection setup; none for UDP
+    pub connect_ms: Option<f64>,
+    /// First query on the new connection
+    pub first_query_ms: Option<f64>,
+    /// Median of the queries after the first
+    pub warm_query_ms: Option<f64>,
+    pub warm_samples: usize,
+    /// Why the test stopped early; timings measured until then are kept
+    pub error: Option<String>,
+}
+
+impl HandshakeTiming {
+    fn new(server: &UpstreamServer) -> Self {
+        Self {
+            upstream_id: server.id,
+            name: server.name.clone(),
+            protocol: server.protocol,
+            address: server.address.clone(),
+            tcp_connect_ms: None,
+            handshake_ms: None,
+            connect_ms: None,
+            first_query_ms: None,
+            warm_query_ms: None,
+            warm_samples: 0,
+            error: None,
+        }
+    }
+}
+
+/// Query timings collected so far
+struct QueryTimes<'a> {
+    timing: &'a mut HandshakeTiming,
+    warm: Vec<f64>,
+}
+
+impl<'a> QueryTimes<'a> {
+    fn new(timing: &'a mut HandshakeTiming) -> Self {
+        Self { timing, warm: Vec::new() }
+    }
+
+    /// Record a query; the first one counts as the first query
+    fn record(&mut self, elapsed: Duration) {
+        if self.timing.first_query_ms.is_none() {
+            self.timing.first_query_ms = Some(ms(elapsed));
+            return;
+        }
+        self.warm.push(ms(elapsed));
+        self.timing.warm_query_ms = median(&mut self.warm);
+        self.timing.warm_samples = self.warm.len();
+    }
+}
+
+/// Measure connection setup, first query and `samples` warm queries
+///
+/// Never fails: an unreachable upstream yields timings with `error` set.
+pub async fn measure_handshake(server: &UpstreamServer, samples: usize) -> HandshakeTiming {
+    let mut timing = HandshakeTiming::new(server);
+    let samples = samples.clamp(1, MAX_WARM_SAMPLES);
+    let result = match server.protocol {
+        UpstreamProtocol::Udp => measure_udp(server, &mut timing, samples).await,
+        UpstreamProtocol::Tcp => measure_tcp(server, &mut timing, samples).await,
+        UpstreamProtocol::Dot => measure_dot(server, &mut timing, samples).await,
+        UpstreamProtocol::Doh => measure_doh(server, &mut timing, samples).await,
+        UpstreamProtocol::Doq => measure_doq(server, &mut timing, samples).await,
+        UpstreamProtocol::Doh3 => measure_doh3(server, &mut timing, samples).await,
+    };
+    if let Err(e) = result {
+        timing.error = Some(e.to_string());
+    }
+    timing
+}
+
+async fn measure_udp(server: &UpstreamServer, timing: &mut HandshakeTiming, samples: usize) -> Result<()> {
+    let client = UdpDnsClient::new(server.clone());
+    let query = DnsQuery::new(SPEEDTEST_NAME, RecordType::A);
+    let mut times = QueryTimes::new(timing);
+    for _ in 0..=samples {
+        let start = Instant::now();
+        client.query(&query).await?;
+        times.record(start.elapsed());
+    }
+    Ok(())
+}
+
+async fn measure_tcp(server: &UpstreamServer, timing: &mut HandshakeTiming, samples: usize) -> Result<()> {
+    let (host, port) = parse_host_port(&server.address, UpstreamProtocol::Tcp.default_port())?;
+    let target = resolve(server, &host, port).await?;
+    let _permit = connection_manager().acquire(ConnectionKind::Tcp)?;
+
+    let start = Instant::now();
+    let mut stream = connect_tcp(server, target).await?;
+    timing.tcp_connect_ms = Some(ms(start.elapsed()));
+    timing.connect_ms = timing.tcp_connect_ms;
+
+    let query = encode_marked(&DnsQuery::new(SPEEDTEST_NAME, RecordType::A))?;
+    let mut times = QueryTimes::new(timing);
+    for _ in 0..=samples {
+        let start = Instant::now();
+        exchange_framed(&mut stream, &query, server.timeout).await?;
+        times.record(start.elapsed());
+    }
+    Ok(())
+}
+
+async fn measure_dot(server: &UpstreamServer, timing: &mut HandshakeTiming, samples: usize) -> Result<()> {
+    let (host, port) = parse_host_port(&server.address, UpstreamProtocol::Dot.default_port())?;
+    let target = resolve(server, &host, port).await?;
+    let _permit = connection_manager().acquire(ConnectionKind::Dot)?;
+
+    let start = Instant::now();
+    let tcp = connect_tcp(server, target).await?;
+    timing.tcp_connect_ms = Some(ms(start.elapsed()));
+    let tls_start = Instant::now();
+    let mut stream = connect_tls(server, server.tls_name(&host), tcp, Vec::new()).await?;
+    timing.handshake_ms = Some(ms(tls_start.elapsed()));
+    timing.connect_ms = Some(ms(start.elapsed()));
+
+    let query = encode_marked(&DnsQuery::new(SPEEDTEST_NAME, RecordType::A))?;
+    let mut times = QueryTimes::new(timing);
+    for _ in 0..=samples {
+        let start = Instant::now();
+        exchange_framed(&mut stream, &query, server.timeout).await?;
+        times.record(start.elapsed());
+    }
+    Ok(())
+}
+
+async fn measure_doh(server: &UpstreamServer, timing: &mut HandshakeTiming, samples: usize) -> Result<()> {
+    let (host, port, path) = https_target(&server.address)?;
+    let target = resolve(server, &host, port).await?;
+
+    let start = Instant::now();
+    let tcp = connect_tcp(server, target).await?;
+    timing.tcp_connect_ms = Some(ms(start.elapsed()));
+    let tls_start = Instant::now();
+    let mut stream = connect_tls(server, server.tls_name(&host), tcp, vec![b"http/1.1".to_vec()]).await?;
+    timing.handshake_ms = Some(ms(tls_start.elapsed()));
+    timing.connect_ms = Some(ms(start.elapsed()));
+
+    let authority = match host.contains(':') {
+        true => format!("[{}]:{}", host, port),
+        false => format!("{}:{}", host, port),
+    };
+    let mut query = encode_marked(&DnsQuery::new(SPEEDTEST_NAME, RecordType::A))?;
+    query[..2].fill(0);
+    let request_head = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/dns-message\r\nAccept: application/dns-message\r\nContent-Length: {}\r\n\r\n",
+        path,
+        server.tls_server_name.as_deref().unwrap_or(&authority),
+        query.len()
+    );
+
+    let mut times = QueryTimes::new(timing);
+    for _ in 0..=samples {
+        let start = Instant::now();
+        stream.write_all(request_head.as_bytes()).await?;
+        stream.write_all(&query).await?;
+        stream.flush().await?;
+        let body = timeout(server.timeout, read_http1_body(&mut stream))
+            .await
+            .map_err(|_| anyhow!("Read timeout"))??;
+        DnsResponse::from_bytes(&body).map_err(|e| anyhow!("Failed to parse response: {}", e))?;
+        times.record(start.elapsed());
+    }
+    Ok(())
+}
+
+async fn measure_doq(server: &UpstreamServer, timing: &mut HandshakeTiming, samples: usize) -> Result<()> {
+    let (host, port) = parse_host_port(&server.address, UpstreamProtocol::Doq.default_port())?;
+    let target = resolve(server, &host, port).await?;
+    let endpoint = get_quic_endpoint(QuicProtocol::Doq, target, &server.source)?;
+    let _permit = connection_manager().acquire(ConnectionKind::Doq)?;
+
+    let start = Instant::now();
+    let connecting = connect_quic(&endpoint, QuicProtocol::Doq, server, target, server.tls_name(&host))?;
+    let connection = timeout(server.timeout, connecting)
+        .await
+        .map_err(|_| anyhow!("Connection timeout"))??;
+    timing.handshake_ms = Some(ms(start.elapsed()));
+    timing.connect_ms = timing.handshake_ms;
+
+    // RFC 9250 §4.2.1: DoQ queries carry ID 0
+    let query = encode_marked(&DnsQuery::with_id(0, SPEEDTEST_NAME, RecordType::A))?;
+    let mut times = QueryTimes::new(timing);
+    let result = async {
+        for _ in 0..=samples {
+            let start = Instant::now();
+            let (mut send, mut recv) = timeout(server.timeout, connection.open_bi())
+                .await
+                .map_err(|_| anyhow!("Stream open timeout"))??;
+            send.write_all(&(query.len() as u16).to_be_bytes()).await?;
+            send.write_all(&query).await?;
+            send.finish().map_err(|e| anyhow!("Failed to finish stream: {}", e))?;
+            timeout(server.timeout, read_framed(&mut recv))
+                .await
+                .map_err(|_| anyhow!("Read timeout"))??;
+            times.record(start.elapsed());
+        }
+        Ok(())
+    }
+    .await;
+    connection.close(0u32.into(), b"");
+    result
+}
+
+async fn measure_doh3(server: &UpstreamServer, timing: &mut HandshakeTiming, samples: usize) -> Result<()> {
+    let (host, port, path) = https_target(&server.address)?;
+    let target = resolve(server, &host, port).await?;
+    let endpoint = get_quic_endpoint(QuicProtocol::Doh3, target, &server.source)?;
+    let _permit = connection_manager().acquire(ConnectionKind::Doh3)?;
+    let server_name = server.tls_name(&host).to_string();
+
+    // The HTTP/3 session is part of the connection setup
+    let start = Instant::now();
+    let connecting = connect_quic(&endpoint, QuicProtocol::Doh3, server, target, &server_name)?;
+    let connection = timeout(server.timeout, connecting)
+        .await
+        .map_err(|_| anyhow!("Connection timeout"))??;
+    timing.handshake_ms = Some(ms(start.elapsed()));
+    let (mut driver, mut sender) = h3::client::new(h3_quinn::Connection::new(connection.clone()))
+        .await
+        .map_err(|e| anyhow!("Failed to create H3 connection: {}", e))?;
+    tokio::spawn(async move {
+        let _ = futures::future::poll_fn(|cx| driver.poll_close(cx)).await;
+    });
+    timing.connect_ms = Some(ms(start.elapsed()));
+
+    let query = Bytes::from(encode_marked(&DnsQuery::new(SPEEDTEST_NAME, RecordType::A))?);
+    let uri = format!("https://{}:{}{}", server_name, port, path);
+    let mut times = QueryTimes::new(timing);
+    let result = async {
+        for _ in 0..=samples {
+            let start = Instant::now();
+            let request = http::Request::post(&uri)
+                .header("content-type", "application/dns-message")
+                .header("accept", "application/dns-message")
+                .header("content-length", query.len().to_string())
+                .body(())
+                .map_err(|e| anyhow!("Failed to build request: {}", e))?;
+            let exchange = async {
+                let mut stream = sender
+                    .send_request(request)
+                    .await
+                    .map_err(|e| anyhow!("Failed to send request: {}", e))?;
+                stream
+                    .send_data(query.clone())
+                    .await
+                    .map_err(|e| anyhow!("Failed to send body: {}", e))?;
+                stream.finish().await.map_err(|e| anyhow!("Failed to finish request: {}", e))?;
+                let response = stream
+                    .recv_response()
+                    .await
+                    .map_err(|e| anyhow!("Failed to receive response: {}", e))?;
+                if !response.status().is_success() {
+                    bail!("DoH3 query failed with status: {}", response.status());
+                }
+                let mut body = Vec::new();
+                while let Some(mut chunk) = stream
+                    .recv_data()
+                    .await
+                    .map_err(|e| anyhow!("Failed to read response body: {}", e))?
+                {
+                    while chunk.has_remaining() {
+                        let bytes = chunk.chunk();
+                        body.extend_from_slice(bytes);
+                        let len = bytes.len();
+                        chunk.advance(len);
+                    }
+                }
+                DnsResponse::from_bytes(&body).map_err(|e| anyhow!("Failed to parse response: {}", e))
+            };
+            timeout(server.timeout, exchange)
+                .await
+                .map_err(|_| anyhow!("Query timeout"))??;
+            times.record(start.elapsed());
+        }
+        Ok(())
+    }
+    .await;
+    connection.close(0u32.into(), b"");
+    result
+}
+
+/// Resolve an upstream host outside the timed part
+async fn resolve(server: &UpstreamServer, host: &str, port: u16) -> Result<SocketAddr> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| anyhow!("Failed to resolve {}: {}", host, e))?
+        .collect();
+    server
+        .source
+        .select_target(&addrs)
+        .ok_or_else(|| anyhow!("No usable addresses found for {}", host))
+}
+
+async fn connect_tcp(server: &UpstreamServer, target: SocketAddr) -> Result<TcpStream> {
+    let connect = async {
+        if server.source.is_default() {
+            Ok(TcpStream::connect(target).await?)
+        } else {
+            server.source.connect_tcp(target).await
+        }
+    };
+    timeout(server.timeout, connect)
+        .await
+        .map_err(|_| anyhow!("Connection timeout to {}", target))?
+}
+
+async fn connect_tls(
+    server: &UpstreamServer,
+    name: &str,
+    tcp: TcpStream,
+    alpn: Vec<Vec<u8>>,
+) -> Result<tokio_rustls::client::TlsStream<TcpStream>> {
+    let mut config = tls_client_config(server.verify_hostname);
+    config.alpn_protocols = alpn;
+    let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+    let server_name = rustls::pki_types::ServerName::try_from(name.to_string())
+        .map_err(|_| anyhow!("Invalid server name: {}", name))?;
+    timeout(server.timeout, connector.connect(server_name, tcp))
+        .await
+        .map_err(|_| anyhow!("TLS handshake timeout"))?
+        .map_err(Into::into)
+}
+
+/// Host, port and path of a DoH or DoH3 upstream address
+fn https_target(address: &str) -> Result<(String, u16, String)> {
+    if !address.contains("://") {
+        let (host, port) = parse_host_port(address, 443)?;
+        return Ok((host, port, "/dns-query".to_string()));
+    }
+    let url = reqwest::Url::parse(address).map_err(|e| anyhow!("Invalid URL '{}': {}", address, e))?;
+    if url.scheme() != "https" {
+        bail!("The speedtest needs an https URL, not '{}'", address);
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("URL '{}' has no host", address))?
+        .trim_matches(['[', ']'])
+        .to_string();
+    let mut path = url.path().to_string();
+    if let Some(query) = url.query() {
+        path = format!("{}?{}", path, query);
+    }
+    Ok((host, url.port_or_known_default().unwrap_or(443), path))
+}
+
+/// Send a length-prefixed query and read the answer (DoT)
+async fn exchange_framed<S>(stream: &mut S, query: &[u8], limit: Duration) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut framed = Vec::with_capacity(query.len() + 2);
+    framed.extend_from_slice(&(query.len() as u16).to_be_bytes());
+    framed.extend_from_slice(query);
+    stream.write_all(&framed).await?;
+    stream.flush().await?;
+    timeout(limit, read_framed(stream))
+        .await
+        .map_err(|_| anyhow!("Read timeout"))?
+}
+
+/// Read a length-prefixed answer and check that it parses
+async fn read_framed<S: AsyncRead + Unpin>(stream: &mut S) -> Result<()> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len).await?;
+    let mut answer = vec![0u8; usize::from(u16::from_be_bytes(len))];
+    stream.read_exact(&mut answer).await?;
+    DnsResponse::from_bytes(&answer).map_err(|e| anyhow!("Failed to parse response: {}", e))?;
+    Ok(())
+}
+
+/// Read one HTTP/1.1 response and return its body
+async fn read_http1_body<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        if let Some(body) = http1_body(&buf)? {
+            return Ok(body);
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            bail!("Connection closed by the server");
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+/// Body of a buffered HTTP/1.1 response, `None` while it is incomplete
+fn http1_body(buf: &[u8]) -> Result<Option<Vec<u8>>> {
+    let Some(head_len) = find(buf, b"\r\n\r\n").map(|pos| pos + 4) else {
+        if buf.len() > MAX_HTTP_HEAD_LEN {
+            bail!("HTTP response head too large");
+        }
+        return Ok(None);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_len]);
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| anyhow!("Invalid HTTP response"))?;
+    if !(200..300).contains(&status) {
+        bail!("DoH query failed with status: {}", status);
+    }
+
+    let mut content_length = None;
+    let mut chunked = false;
+    for (name, value) in lines.filter_map(|line| line.split_once(':')) {
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = Some(value.parse::<usize>().map_err(|_| anyhow!("Invalid Content-Length"))?);
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.to_ascii_lowercase().contains("chunked");
+        }
+    }
+
+    let body = &buf[head_len..];
+    if chunked {
+        return dechunk(body);
+    }
+    let len = content_length.ok_or_else(|| anyhow!("DoH response has no Content-Length"))?;
+    Ok((body.len() >= len).then(|| body[..len].to_vec()))
+}
+
+/// Decode a chunked body, `None` while it is incomplete
+fn dechunk(mut data: &[u8]) -> Result<Option<Vec<u8>>> {
+    let mut body = Vec::new();
+    loop {
+        let Some(line_len) = find(data, b"\r\n") else {
+            return Ok(None);
+        };
+        let size = std::str::from_utf8(&data[..line_len])
+            .ok()
+            .and_then(|line| usize::from_str_radix(line.split(';').next()?.trim(), 16).ok())
+            .ok_or_else(|| anyhow!("Invalid chunk size"))?;
+        data = &data[line_len + 2..];
+        if size == 0 {
+            return Ok(Some(body));
+        }
+        if data.len() < size + 2 {
+            return Ok(None);
+        }
+        body.extend_from_slice(&data[..size]);
+        data = &data[size + 2..];
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn ms(elapsed: Duration) -> f64 {
+    (elapsed.as_secs_f64() * 100_000.0).round() / 100.0
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http1_body() {
+        let head = b"HTTP/1.1 200 OK\r\nContent-Type: application/dns-message\r\ncontent-length: 4\r\n\r\n";
+        assert_eq!(http1_body(&head[..20]).unwrap(), None);
+        let mut response = head.to_vec();
+        response.extend_from_slice(b"ab");
+        assert_eq!(http1_body(&response).unwrap(), None);
+        response.extend_from_slice(b"cd");
+        assert_eq!(http1_body(&response).unwrap(), Some(b"abcd".to_vec()));
+
+        let chunked = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2;x=y\r\nde\r\n0\r\n\r\n";
+        assert_eq!(http1_body(chunked).unwrap(), Some(b"abcde".to_vec()));
+        assert_eq!(http1_body(&chunked[..chunked.len() - 7]).unwrap(), None);
+
+        assert!(http1_body(b"HTTP/1.1 415 Unsupported Media Type\r\nContent-Length: 0\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn test_https_target() {
+        assert_eq!(
+            https_target("https://dns.google/dns-query").unwrap(),
+            ("dns.google".to_string(), 443, "/dns-query".to_string())
+        );
+        assert_eq!(
+            https_target("https://[2606:4700::1111]:8443/q?x=1").unwrap(),
+            ("2606:4700::1111".to_string(), 8443, "/q?x=1".to_string())
+        );
+        assert_eq!(
+            https_target("1.1.1.1").unwrap(),
+            ("1.1.1.1".to_string(), 443, "/dns-query".to_string())
+        );
+        assert!(https_target("http://1.1.1.1/dns-query").is_err());
+    }
+
+    #[test]
+    fn test_query_times() {
+        let server = UpstreamServer::new(1, "t".to_string(), "1.1.1.1:53".to_string(), UpstreamProtocol::Udp, 5000);
+        let mut timing = HandshakeTiming::new(&server);
+        let mut times = QueryTimes::new(&mut timing);
+        for ms in [40, 9, 12, 10] {
+            times.record(Duration::from_millis(ms));
+        }
+        assert_eq!(timing.first_query_ms, Some(40.0));
+        assert_eq!(timing.warm_query_ms, Some(10.0));
+        assert_eq!(timing.warm_samples, 3);
+    }
+}
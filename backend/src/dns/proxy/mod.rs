@@ -2,13 +2,29 @@
 //!
 //! Provides DNS proxy functionality including:
 //! - Upstream server management
-//! - Multiple protocol support (UDP, DoT, DoH, DoQ)
+//! - Multiple protocol support (UDP, TCP, DoT, DoH, DoQ)
 //! - Query strategies (concurrent, fastest, round-robin, random)
 //! - Failover handling
+//! - Upstream capability probing (EDNS, cookies, TCP, DNSSEC)
+//! - Idle connection reaping and a ceiling on open upstream connections
+//! - A ceiling on upstream queries in flight with overload shedding
+//! - Per-upstream byte counters and bandwidth rates
+//! - Per-domain restrictions on the upstream protocols used
+//! - DoH request method and HTTP version options
+//! - Per-upstream handshake speedtest
+//! - QUIC transport tuning (timeouts, stream limit, 0-RTT, migration)
 
 mod upstream;
 mod client;
+mod connections;
+mod overload;
 mod strategy;
+mod probe;
+mod protocol_policy;
+mod traffic;
+mod doh_options;
+mod handshake;
+mod quic_transport;
 
 #[cfg(test)]
 mod forwarding_tests;
@@ -16,4 +32,12 @@ mod forwarding_tests;
 pub use upstream::*;
 #[allow(unused_imports)]
 pub use client::*;
+pub use connections::*;
+pub use overload::*;
 pub use strategy::*;
+pub use probe::*;
+pub use protocol_policy::*;
+pub use traffic::*;
+pub use doh_options::*;
+pub use handshake::*;
+pub use quic_transport::*;
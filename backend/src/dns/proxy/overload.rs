@@ -0,0 +1,321 @@
+//! Upstream Query Limiter
+//!
+//! Every cache miss becomes at least one upstream query, and under a flood
+//! the number of queries in flight is otherwise unbounded: each one holds a
+//! socket and buffers until it completes. The limiter caps upstream queries
+//! in flight across all clients. A query beyond the cap either waits briefly
+//! for a free slot or is shed at once; shed queries are answered SERVFAIL.
+//!
+//! Saturation counters are reported in `/api/status` so the cap can be
+//! sized from the peak load actually seen.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::AppConfig;
+
+/// What to do with a query when the limit is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverloadAction {
+    /// Wait up to the queue timeout for a free slot, then answer SERVFAIL
+    #[default]
+    Queue,
+    /// Answer SERVFAIL immediately
+    Servfail,
+}
+
+impl OverloadAction {
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "queue" => Some(OverloadAction::Queue),
+            "servfail" => Some(OverloadAction::Servfail),
+            _ => None,
+        }
+    }
+
+    /// Convert to string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OverloadAction::Queue => "queue",
+            OverloadAction::Servfail => "servfail",
+        }
+    }
+}
+
+/// Cap on upstream queries in flight and the overload behaviour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryLimits {
+    /// Upstream queries allowed in flight, zero for no limit
+    pub max_outstanding: usize,
+    pub action: OverloadAction,
+    /// Longest wait for a free slot with [`OverloadAction::Queue`]
+    pub queue_timeout: Duration,
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        Self {
+            max_outstanding: 1024,
+            action: OverloadAction::Queue,
+            queue_timeout: Duration::from_millis(100),
+        }
+    }
+}
+
+impl QueryLimits {
+    pub fn from_config(config: &AppConfig) -> Self {
+        let action = OverloadAction::from_str(&config.upstream_overload_action).unwrap_or_else(|| {
+            tracing::warn!(
+                "Unknown upstream overload action '{}', using queue",
+                config.upstream_overload_action
+            );
+            OverloadAction::Queue
+        });
+        Self {
+            max_outstanding: config.upstream_max_outstanding,
+            action,
+            queue_timeout: Duration::from_millis(config.upstream_queue_timeout_ms),
+        }
+    }
+}
+
+/// Upstream query counters
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueryLimiterStats {
+    /// Upstream queries currently in flight
+    pub in_flight: usize,
+    /// Highest `in_flight` since startup
+    pub peak_in_flight: usize,
+    /// Ceiling on `in_flight`, zero when unlimited
+    pub max_outstanding: usize,
+    /// `in_flight` as a share of the ceiling, zero when unlimited
+    pub utilization: f64,
+    /// Queries currently waiting for a free slot
+    pub waiting: usize,
+    pub action: OverloadAction,
+    pub queue_timeout_ms: u64,
+    /// Queries admitted since startup
+    pub admitted: u64,
+    /// Admitted queries that had to wait for a slot
+    pub queued: u64,
+    /// Queries answered SERVFAIL at the limit since startup
+    pub shed: u64,
+}
+
+/// Holds one upstream query slot until dropped
+pub struct QueryPermit<'a> {
+    limiter: &'a QueryLimiter,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for QueryPermit<'_> {
+    fn drop(&mut self) {
+        self.limiter.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts a query waiting for a slot, also when the wait is cancelled
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Limits upstream queries in flight
+pub struct QueryLimiter {
+    limits: RwLock<QueryLimits>,
+    semaphore: RwLock<Arc<Semaphore>>,
+    in_flight: AtomicUsize,
+    peak_in_flight: AtomicUsize,
+    waiting: AtomicUsize,
+    admitted: AtomicU64,
+    queued: AtomicU64,
+    shed: AtomicU64,
+}
+
+impl QueryLimiter {
+    pub fn new(limits: QueryLimits) -> Self {
+        Self {
+            limits: RwLock::new(limits),
+            semaphore: RwLock::new(Arc::new(Semaphore::new(limits.max_outstanding))),
+            in_flight: AtomicUsize::new(0),
+            peak_in_flight: AtomicUsize::new(0),
+            waiting: AtomicUsize::new(0),
+            admitted: AtomicU64::new(0),
+            queued: AtomicU64::new(0),
+            shed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn limits(&self) -> QueryLimits {
+        *self.limits.read().unwrap()
+    }
+
+    /// Replace the limits
+    ///
+    /// A new ceiling applies to queries started afterwards; queries already
+    /// in flight finish normally, so the count can briefly exceed a lowered
+    /// ceiling.
+    pub fn configure(&self, limits: QueryLimits) {
+        let mut current = self.limits.write().unwrap();
+        if current.max_outstanding != limits.max_outstanding {
+            *self.semaphore.write().unwrap() = Arc::new(Semaphore::new(limits.max_outstanding));
+        }
+        *current = limits;
+    }
+
+    /// Reserve a slot for an upstream query, failing when overloaded
+    pub async fn acquire(&self) -> Result<QueryPermit<'_>> {
+        let limits = self.limits();
+        let permit = if limits.max_outstanding == 0 {
+            None
+        } else {
+            let semaphore = self.semaphore.read().unwrap().clone();
+            match semaphore.clone().try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) if limits.action == OverloadAction::Servfail => {
+                    return Err(self.shed(&limits));
+                }
+                Err(_) => {
+                    self.waiting.fetch_add(1, Ordering::Relaxed);
+                    let _waiting = Waiting(&self.waiting);
+                    match tokio::time::timeout(limits.queue_timeout, semaphore.acquire_owned()).await {
+                        Ok(Ok(permit)) => {
+                            self.queued.fetch_add(1, Ordering::Relaxed);
+                            Some(permit)
+                        }
+                        _ => return Err(self.shed(&limits)),
+                    }
+                }
+            }
+        };
+
+        self.admitted.fetch_add(1, Ordering::Relaxed);
+        let in_flight = self.in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+        self.peak_in_flight.fetch_max(in_flight, Ordering::Relaxed);
+        Ok(QueryPermit {
+            limiter: self,
+            _permit: permit,
+        })
+    }
+
+    fn shed(&self, limits: &QueryLimits) -> anyhow::Error {
+        self.shed.fetch_add(1, Ordering::Relaxed);
+        tracing::debug!("Upstream query limit reached, shedding query");
+        anyhow!("Upstream query limit reached ({} in flight)", limits.max_outstanding)
+    }
+
+    pub fn stats(&self) -> QueryLimiterStats {
+        let limits = self.limits();
+        let in_flight = self.in_flight.load(Ordering::Relaxed);
+        let utilization = if limits.max_outstanding > 0 {
+            in_flight as f64 / limits.max_outstanding as f64
+        } else {
+            0.0
+        };
+        QueryLimiterStats {
+            in_flight,
+            peak_in_flight: self.peak_in_flight.load(Ordering::Relaxed),
+            max_outstanding: limits.max_outstanding,
+            utilization,
+            waiting: self.waiting.load(Ordering::Relaxed),
+            action: limits.action,
+            queue_timeout_ms: limits.queue_timeout.as_millis() as u64,
+            admitted: self.admitted.load(Ordering::Relaxed),
+            queued: self.queued.load(Ordering::Relaxed),
+            shed: self.shed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for QueryLimiter {
+    fn default() -> Self {
+        Self::new(QueryLimits::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(max_outstanding: usize, action: OverloadAction) -> QueryLimiter {
+        QueryLimiter::new(QueryLimits {
+            max_outstanding,
+            action,
+            queue_timeout: Duration::from_millis(50),
+        })
+    }
+
+    #[test]
+    fn test_overload_action_from_str() {
+        assert_eq!(OverloadAction::from_str("queue"), Some(OverloadAction::Queue));
+        assert_eq!(OverloadAction::from_str("SERVFAIL"), Some(OverloadAction::Servfail));
+        assert_eq!(OverloadAction::from_str("drop"), None);
+        assert_eq!(OverloadAction::Servfail.as_str(), "servfail");
+    }
+
+    #[tokio::test]
+    async fn test_servfail_sheds_at_limit() {
+        let limiter = limiter(2, OverloadAction::Servfail);
+        let first = limiter.acquire().await.unwrap();
+        let _second = limiter.acquire().await.unwrap();
+        assert!(limiter.acquire().await.is_err());
+
+        let stats = limiter.stats();
+        assert_eq!((stats.in_flight, stats.admitted, stats.shed), (2, 2, 1));
+        assert_eq!(stats.utilization, 1.0);
+
+        // Finished queries free their slot
+        drop(first);
+        assert!(limiter.acquire().await.is_ok());
+        assert_eq!(limiter.stats().peak_in_flight, 2);
+    }
+
+    #[tokio::test]
+    async fn test_queue_waits_for_slot() {
+        let limiter = Arc::new(limiter(1, OverloadAction::Queue));
+        let held = limiter.acquire().await.unwrap();
+
+        // Nothing frees up within the queue timeout
+        assert!(limiter.acquire().await.is_err());
+        assert_eq!(limiter.stats().shed, 1);
+
+        let waiter = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire().await.is_ok() })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(limiter.stats().waiting, 1);
+        drop(held);
+        assert!(waiter.await.unwrap());
+
+        let stats = limiter.stats();
+        assert_eq!((stats.waiting, stats.queued, stats.in_flight), (0, 1, 0));
+    }
+
+    #[tokio::test]
+    async fn test_configure_limits() {
+        let limiter = limiter(1, OverloadAction::Servfail);
+        let _held = limiter.acquire().await.unwrap();
+        assert!(limiter.acquire().await.is_err());
+
+        // Zero disables the limit
+        limiter.configure(QueryLimits {
+            max_outstanding: 0,
+            ..limiter.limits()
+        });
+        let permits: Vec<_> = futures::future::join_all((0..10).map(|_| limiter.acquire())).await;
+        assert!(permits.iter().all(Result::is_ok));
+        assert_eq!(limiter.stats().in_flight, 11);
+        assert_eq!(limiter.stats().utilization, 0.0);
+    }
+}
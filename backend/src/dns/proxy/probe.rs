@@ -0,0 +1,249 @@
+//! Upstream Capability Probing
+//!
+//! Detects what a plain DNS upstream supports so the UDP client can adapt
+//! to it: whether it answers EDNS(0) queries and with which UDP payload
+//! size, whether it returns DNS cookies (RFC 7873), whether it serves
+//! queries over TCP and whether it returns DNSSEC records when asked with
+//! the DO bit. Encrypted transports have their own framing and are not
+//! probed.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::dns::message::{append_opt_record, DnsQuery, RecordType, WireSummary, EDNS_OPTION_COOKIE};
+use super::client::UdpDnsClient;
+use super::upstream::{UpstreamProtocol, UpstreamServer};
+
+/// Largest UDP payload size advertised to upstreams (DNS flag day 2020)
+pub const DEFAULT_EDNS_PAYLOAD_SIZE: u16 = 1232;
+
+/// Hours after which a successful probe is repeated
+const PROBE_MAX_AGE_HOURS: i64 = 24;
+/// Hours after which a failed probe is retried
+const FAILED_PROBE_MAX_AGE_HOURS: i64 = 1;
+
+const RCODE_FORMERR: u8 = 1;
+
+/// Features detected on an upstream server
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct UpstreamCapabilities {
+    /// Answers EDNS(0) queries with an OPT record
+    pub edns: bool,
+    /// UDP payload size the server advertises
+    pub udp_payload_size: Option<u16>,
+    /// Returns a server cookie
+    pub cookies: bool,
+    /// Serves queries over TCP, so truncated answers can be retried there
+    pub tcp: bool,
+    /// Returns RRSIG records for a signed zone when the DO bit is set
+    pub dnssec: bool,
+    pub probed_at: DateTime<Utc>,
+    /// Why the probe failed; the other fields are meaningless when set
+    pub error: Option<String>,
+}
+
+impl UpstreamCapabilities {
+    /// Parse the JSON stored on an upstream record
+    pub fn from_json(json: Option<&str>) -> Option<Self> {
+        serde_json::from_str(json?).ok()
+    }
+
+    fn failed(error: impl std::fmt::Display) -> Self {
+        Self {
+            probed_at: Utc::now(),
+            error: Some(error.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Whether the probe is due to be repeated
+    pub fn is_stale(&self) -> bool {
+        let max_age = if self.error.is_some() {
+            FAILED_PROBE_MAX_AGE_HOURS
+        } else {
+            PROBE_MAX_AGE_HOURS
+        };
+        Utc::now() - self.probed_at > Duration::hours(max_age)
+    }
+}
+
+/// Probe an upstream server
+///
+/// Never fails: an unreachable server yields capabilities with `error` set.
+pub async fn probe_upstream(server: &UpstreamServer) -> UpstreamCapabilities {
+    if server.protocol != UpstreamProtocol::Udp {
+        return UpstreamCapabilities::failed(format!(
+            "Capability probing does not apply to {} upstreams",
+            server.protocol
+        ));
+    }
+    run_probe(server).await.unwrap_or_else(UpstreamCapabilities::failed)
+}
+
+async fn run_probe(server: &UpstreamServer) -> Result<UpstreamCapabilities> {
+    let client = UdpDnsClient::new(server.clone());
+    let addr = client.parse_address()?;
+    let mut capabilities = UpstreamCapabilities {
+        probed_at: Utc::now(),
+        ..Default::default()
+    };
+
+    // The root zone is signed, so a DNSSEC-aware server returns its RRSIG
+    let cookie: [u8; 8] = rand::random();
+    let mut edns_query = probe_query()?;
+    append_opt_record(
+        &mut edns_query,
+        DEFAULT_EDNS_PAYLOAD_SIZE,
+        true,
+        &[(EDNS_OPTION_COOKIE, &cookie)],
+    );
+    let edns_answer = client
+        .send_query(&edns_query, addr)
+        .await
+        .ok()
+        .and_then(|bytes| WireSummary::parse(&bytes));
+
+    match edns_answer {
+        Some(WireSummary { rcode, opt: Some(opt), rrsig, .. }) if rcode != RCODE_FORMERR => {
+            capabilities.edns = true;
+            capabilities.udp_payload_size = Some(opt.udp_payload_size);
+            capabilities.cookies = opt
+                .option(EDNS_OPTION_COOKIE)
+                .is_some_and(|c| c.len() >= 16 && c.starts_with(&cookie));
+            capabilities.dnssec = rrsig;
+        }
+        _ => {
+            // Servers that drop or reject EDNS must still answer plain queries
+            client
+                .send_query(&probe_query()?, addr)
+                .await
+                .map_err(|e| anyhow!("No answer from {}: {}", addr, e))?;
+        }
+    }
+
+    capabilities.tcp = client.send_query_tcp(&probe_query()?, addr).await.is_ok();
+    Ok(capabilities)
+}
+
+/// Encoded `. SOA` query without EDNS
+fn probe_query() -> Result<Vec<u8>> {
+    DnsQuery::new(".", RecordType::SOA)
+        .to_bytes()
+        .map_err(|e| anyhow!("Failed to encode probe query: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::proxy::{create_client, DnsClient};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, UdpSocket};
+
+    /// Turn a query into a response by setting QR, optionally truncated
+    fn echo(query: &[u8], truncated: bool) -> Vec<u8> {
+        let mut response = query.to_vec();
+        response[2] |= 0x80;
+        if truncated {
+            response[2] |= 0x02;
+        }
+        response
+    }
+
+    /// UDP server answering EDNS queries with a 4096-byte payload size and a
+    /// server cookie, and plain queries with a truncated echo
+    async fn spawn_udp_server() -> std::net::SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            loop {
+                let Ok((len, from)) = socket.recv_from(&mut buf).await else {
+                    return;
+                };
+                let request = &buf[..len];
+                let response = match WireSummary::parse(request).and_then(|s| s.opt) {
+                    Some(opt) => {
+                        // Strip the client's OPT record (11 bytes plus options)
+                        let opt_len = 11 + opt.options.iter().map(|(_, d)| 4 + d.len()).sum::<usize>();
+                        let mut response = echo(&request[..len - opt_len], false);
+                        response[11] -= 1;
+                        let mut cookie = opt.option(EDNS_OPTION_COOKIE).unwrap().to_vec();
+                        cookie.extend_from_slice(&[7u8; 8]);
+                        append_opt_record(&mut response, 4096, true, &[(EDNS_OPTION_COOKIE, &cookie)]);
+                        response
+                    }
+                    None => echo(request, true),
+                };
+                let _ = socket.send_to(&response, from).await;
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_probe_detects_edns_and_cookies() {
+        let addr = spawn_udp_server().await;
+        let server = UpstreamServer::new(1, "Mock", addr.to_string(), UpstreamProtocol::Udp, 500);
+
+        let capabilities = probe_upstream(&server).await;
+        assert_eq!(capabilities.error, None);
+        assert!(capabilities.edns);
+        assert_eq!(capabilities.udp_payload_size, Some(4096));
+        assert!(capabilities.cookies);
+        assert!(!capabilities.dnssec);
+        assert!(!capabilities.tcp);
+
+        // Advertise no more than the default size, and keep truncated answers
+        let server = server.with_capabilities(capabilities);
+        assert_eq!(server.edns_payload_size(), Some(DEFAULT_EDNS_PAYLOAD_SIZE));
+        assert!(!server.tcp_fallback());
+    }
+
+    #[tokio::test]
+    async fn test_probe_failure_keeps_defaults() {
+        let server = UpstreamServer::new(1, "Closed", "127.0.0.1:1", UpstreamProtocol::Udp, 200);
+        let capabilities = probe_upstream(&server).await;
+        assert!(capabilities.error.is_some());
+        assert!(!capabilities.is_stale());
+
+        let server = server.with_capabilities(capabilities);
+        assert_eq!(server.edns_payload_size(), None);
+        assert!(server.tcp_fallback());
+
+        let doh = UpstreamServer::new(2, "DoH", "https://dns.google/dns-query", UpstreamProtocol::Doh, 200);
+        assert!(probe_upstream(&doh).await.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_answer_retried_over_tcp() {
+        let addr = spawn_udp_server().await;
+        let Ok(listener) = TcpListener::bind(addr).await else {
+            return; // TCP port taken by another process
+        };
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut len = [0u8; 2];
+            stream.read_exact(&mut len).await.unwrap();
+            let mut request = vec![0u8; u16::from_be_bytes(len) as usize];
+            stream.read_exact(&mut request).await.unwrap();
+            let mut response = echo(&request, false);
+            response[3] = (response[3] & 0xF0) | 3; // NXDOMAIN marks the TCP answer
+            stream.write_all(&(response.len() as u16).to_be_bytes()).await.unwrap();
+            stream.write_all(&response).await.unwrap();
+        });
+
+        let server = UpstreamServer::new(1, "Mock", addr.to_string(), UpstreamProtocol::Udp, 500);
+        let query = DnsQuery::new("example.com", RecordType::A);
+        let result = create_client(server.clone()).query(&query).await.unwrap();
+        assert_eq!(result.response.response_code.to_string(), "NXDOMAIN");
+
+        // Servers known not to serve TCP keep the truncated UDP answer
+        let server = server.with_capabilities(UpstreamCapabilities {
+            probed_at: Utc::now(),
+            ..Default::default()
+        });
+        let result = UdpDnsClient::new(server).query(&query).await.unwrap();
+        assert_eq!(result.response.response_code.to_string(), "NOERROR");
+    }
+}
@@ -0,0 +1,207 @@
+//! Per-domain upstream protocol policy
+//!
+//! Some networks interfere with plain UDP DNS for particular names while
+//! encrypted transports get through. A protocol rule restricts the names it
+//! matches to upstreams of the listed protocols, e.g. `*.example.org` only
+//! via DoH or DoQ. The proxy manager applies the rules when it selects
+//! servers: the query strategy runs over the allowed upstreams only, and a
+//! query fails with an explicit error instead of silently falling back to a
+//! forbidden protocol when none of them is enabled or healthy.
+
+use std::sync::RwLock;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::dns::cache::NamePattern;
+use crate::dns::name::normalize_name;
+use super::upstream::{UpstreamProtocol, UpstreamServer};
+
+/// Config key for the protocol rules (JSON array of [`ProtocolRule`])
+pub const CONFIG_KEY_UPSTREAM_PROTOCOL_RULES: &str = "upstream_protocol_rules";
+
+/// Names matching `pattern` may only be forwarded over `protocols`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProtocolRule {
+    /// `example.org` matches the name only, `*.example.org` also subdomains
+    pub pattern: String,
+    pub protocols: Vec<UpstreamProtocol>,
+}
+
+impl ProtocolRule {
+    /// Check the pattern and protocol list
+    pub fn validate(&self) -> Result<()> {
+        let pattern = normalize_name(self.pattern.trim_start_matches("*."));
+        if pattern.is_empty() {
+            return Err(anyhow!("Protocol rule pattern must not be empty"));
+        }
+        if self.protocols.is_empty() {
+            return Err(anyhow!("Protocol rule '{}' allows no protocol", self.pattern));
+        }
+        Ok(())
+    }
+
+    /// Protocol list for messages, e.g. `doh/doq`
+    pub fn protocol_list(&self) -> String {
+        self.protocols.iter().map(|p| p.as_str()).collect::<Vec<_>>().join("/")
+    }
+
+    /// Whether a server may answer names matching this rule
+    pub fn allows(&self, server: &UpstreamServer) -> bool {
+        self.protocols.contains(&server.protocol)
+    }
+}
+
+struct ActiveRules {
+    rules: Vec<ProtocolRule>,
+    /// Parsed patterns, in the same order as `rules`
+    patterns: Vec<NamePattern>,
+}
+
+/// Protocol rules consulted by the proxy manager
+pub struct ProtocolPolicy {
+    active: RwLock<ActiveRules>,
+}
+
+#[allow(dead_code)]
+impl ProtocolPolicy {
+    pub fn new() -> Self {
+        Self {
+            active: RwLock::new(ActiveRules {
+                rules: Vec::new(),
+                patterns: Vec::new(),
+            }),
+        }
+    }
+
+    /// Parse rules stored under [`CONFIG_KEY_UPSTREAM_PROTOCOL_RULES`]
+    pub fn parse(stored: &str) -> Result<Vec<ProtocolRule>> {
+        let rules: Vec<ProtocolRule> = serde_json::from_str(stored)?;
+        for rule in &rules {
+            rule.validate()?;
+        }
+        Ok(rules)
+    }
+
+    pub fn rules(&self) -> Vec<ProtocolRule> {
+        self.active.read().unwrap().rules.clone()
+    }
+
+    /// Replace the rules (in-memory only)
+    pub fn set_rules(&self, rules: Vec<ProtocolRule>) {
+        let patterns = rules.iter().map(|r| NamePattern::parse(&r.pattern)).collect();
+        *self.active.write().unwrap() = ActiveRules { rules, patterns };
+    }
+
+    /// Rule restricting a query name, if any
+    ///
+    /// An exact pattern wins over a wildcard; otherwise the longest
+    /// matching pattern wins, so `*.cdn.example.org` can relax or tighten
+    /// `*.example.org`.
+    pub fn rule_for(&self, name: &str) -> Option<ProtocolRule> {
+        let active = self.active.read().unwrap();
+        if active.rules.is_empty() {
+            return None;
+        }
+        let name = normalize_name(name);
+        active
+            .rules
+            .iter()
+            .zip(&active.patterns)
+            .filter(|(_, pattern)| pattern.matches(&name))
+            .max_by_key(|(_, pattern)| (pattern.suffix.is_none(), pattern.exact.len()))
+            .map(|(rule, _)| rule.clone())
+    }
+}
+
+impl Default for ProtocolPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Servers a rule allows, with a clear error when there are none
+///
+/// `enabled` are all enabled upstreams and `healthy` those currently
+/// taking queries; the two cases get different messages so a missing
+/// upstream is not mistaken for an outage.
+pub fn allowed_servers(
+    name: &str,
+    rule: &ProtocolRule,
+    enabled: &[UpstreamServer],
+    healthy: Vec<UpstreamServer>,
+) -> Result<Vec<UpstreamServer>> {
+    if !enabled.iter().any(|s| rule.allows(s)) {
+        return Err(anyhow!(
+            "No enabled {} upstream for {} (required by protocol rule '{}')",
+            rule.protocol_list(),
+            name,
+            rule.pattern
+        ));
+    }
+    let allowed: Vec<UpstreamServer> = healthy.into_iter().filter(|s| rule.allows(s)).collect();
+    if allowed.is_empty() {
+        return Err(anyhow!(
+            "No healthy {} upstream for {} (required by protocol rule '{}')",
+            rule.protocol_list(),
+            name,
+            rule.pattern
+        ));
+    }
+    Ok(allowed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, protocols: &[UpstreamProtocol]) -> ProtocolRule {
+        ProtocolRule {
+            pattern: pattern.to_string(),
+            protocols: protocols.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = ProtocolPolicy::parse(r#"[{"pattern":"*.example.org","protocols":["doh","doq"]}]"#).unwrap();
+        assert_eq!(rules, vec![rule("*.example.org", &[UpstreamProtocol::Doh, UpstreamProtocol::Doq])]);
+        assert!(ProtocolPolicy::parse(r#"[{"pattern":"example.org","protocols":[]}]"#).is_err());
+        assert!(ProtocolPolicy::parse(r#"[{"pattern":"*.","protocols":["doh"]}]"#).is_err());
+        assert!(ProtocolPolicy::parse(r#"[{"pattern":"example.org","protocols":["smtp"]}]"#).is_err());
+    }
+
+    #[test]
+    fn test_rule_for_most_specific() {
+        let policy = ProtocolPolicy::new();
+        policy.set_rules(vec![
+            rule("*.example.org", &[UpstreamProtocol::Doh]),
+            rule("*.cdn.example.org", &[UpstreamProtocol::Doq]),
+            rule("cdn.example.org", &[UpstreamProtocol::Dot]),
+        ]);
+
+        let protocols = |name: &str| policy.rule_for(name).map(|r| r.protocols);
+        assert_eq!(protocols("Www.Example.org."), Some(vec![UpstreamProtocol::Doh]));
+        assert_eq!(protocols("example.org"), Some(vec![UpstreamProtocol::Doh]));
+        assert_eq!(protocols("img.cdn.example.org"), Some(vec![UpstreamProtocol::Doq]));
+        assert_eq!(protocols("cdn.example.org"), Some(vec![UpstreamProtocol::Dot]));
+        assert_eq!(protocols("example.com"), None);
+    }
+
+    #[test]
+    fn test_allowed_servers_errors() {
+        let udp = UpstreamServer::new(1, "udp", "1.1.1.1:53", UpstreamProtocol::Udp, 5000);
+        let doh = UpstreamServer::new(2, "doh", "https://1.1.1.1/dns-query", UpstreamProtocol::Doh, 5000);
+        let doh_only = rule("*.example.org", &[UpstreamProtocol::Doh]);
+
+        let enabled = vec![udp.clone(), doh.clone()];
+        let allowed = allowed_servers("example.org", &doh_only, &enabled, enabled.clone()).unwrap();
+        assert_eq!(allowed, vec![doh.clone()]);
+
+        let err = allowed_servers("example.org", &doh_only, &enabled, vec![udp.clone()]).unwrap_err();
+        assert!(err.to_string().starts_with("No healthy doh upstream"));
+
+        let err = allowed_servers("example.org", &doh_only, std::slice::from_ref(&udp), vec![udp.clone()]).unwrap_err();
+        assert!(err.to_string().starts_with("No enabled doh upstream"));
+    }
+}
@@ -0,0 +1,257 @@
+//! QUIC Transport Tuning
+//!
+//! Idle timeout, keep-alive interval, stream limit, 0-RTT and connection
+//! migration of the DoQ/DoH3 upstream clients and the DoQ listener, set with
+//! the `quic_*` configuration options. Quinn's defaults drop connections on
+//! lossy mobile and router links, so these can be loosened there.
+//!
+//! With 0-RTT enabled, a client that holds a session ticket for a server
+//! sends its first query with the handshake. A rejected attempt fails that
+//! query, which is retried on a fresh connection. Upstream clients pick up
+//! changes on their next connection; listeners when they are restarted.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::config::AppConfig;
+
+/// Session tickets kept per resumption store
+const MAX_SESSION_TICKETS: usize = 256;
+
+/// QUIC transport parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuicTransportSettings {
+    /// Close connections without traffic for this long, zero never
+    pub idle_timeout: Duration,
+    /// Send keep-alives this often, zero disables them
+    pub keep_alive_interval: Duration,
+    /// Concurrent bidirectional streams the peer may open
+    pub max_streams: u32,
+    /// Send (client) or accept (listener) early data on resumed sessions
+    pub zero_rtt: bool,
+    /// Let clients of the listener keep their connection across address changes
+    pub migration: bool,
+}
+
+impl Default for QuicTransportSettings {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(20),
+            keep_alive_interval: Duration::from_secs(5),
+            max_streams: 100,
+            zero_rtt: false,
+            migration: true,
+        }
+    }
+}
+
+impl QuicTransportSettings {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(config.quic_idle_timeout_secs),
+            keep_alive_interval: Duration::from_secs(config.quic_keep_alive_secs),
+            max_streams: config.quic_max_streams,
+            zero_rtt: config.quic_zero_rtt,
+            migration: config.quic_migration,
+        }
+    }
+
+    /// Quinn transport config for these settings
+    pub fn transport_config(&self) -> quinn::TransportConfig {
+        let mut transport = quinn::TransportConfig::default();
+        transport.max_idle_timeout(if self.idle_timeout.is_zero() {
+            None
+        } else {
+            quinn::IdleTimeout::try_from(self.idle_timeout).ok()
+        });
+        transport.keep_alive_interval(Some(self.keep_alive_interval).filter(|i| !i.is_zero()));
+        transport.max_concurrent_bidi_streams(self.max_streams.into());
+        transport
+    }
+}
+
+/// Transport settings and 0-RTT counters
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct QuicTransportStats {
+    pub idle_timeout_secs: u64,
+    pub keep_alive_secs: u64,
+    pub max_streams: u32,
+    pub zero_rtt: bool,
+    pub migration: bool,
+    /// Upstream connections that sent early data since startup
+    pub zero_rtt_attempts: u64,
+    /// Of those, the ones whose early data the server accepted
+    pub zero_rtt_accepted: u64,
+    /// Connections accepted by DoQ listeners since startup
+    pub listener_connections: u64,
+}
+
+/// Live state of one pooled upstream QUIC connection
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QuicConnectionStats {
+    pub remote_address: String,
+    pub rtt_ms: f64,
+    pub congestion_window: u64,
+    pub sent_packets: u64,
+    pub lost_packets: u64,
+    /// Current MTU of the path
+    pub mtu: u16,
+}
+
+impl QuicConnectionStats {
+    pub fn from_connection(connection: &quinn::Connection) -> Self {
+        let stats = connection.stats();
+        Self {
+            remote_address: connection.remote_address().to_string(),
+            rtt_ms: stats.path.rtt.as_secs_f64() * 1000.0,
+            congestion_window: stats.path.cwnd,
+            sent_packets: stats.path.sent_packets,
+            lost_packets: stats.path.lost_packets,
+            mtu: stats.path.current_mtu,
+        }
+    }
+}
+
+/// Pooled QUIC connections of one upstream
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UpstreamQuicConnections {
+    pub server_id: i64,
+    pub server_name: String,
+    pub protocol: String,
+    pub connections: Vec<QuicConnectionStats>,
+}
+
+/// Process-wide QUIC transport settings
+pub struct QuicTransport {
+    settings: RwLock<QuicTransportSettings>,
+    /// Bumped on every change so cached endpoints refresh their client config
+    generation: AtomicU64,
+    /// Session tickets of verified and unverified connections, kept apart so
+    /// a session set up without verification is never resumed as verified
+    verified_sessions: rustls::client::Resumption,
+    unverified_sessions: rustls::client::Resumption,
+    zero_rtt_attempts: AtomicU64,
+    zero_rtt_accepted: AtomicU64,
+    listener_connections: AtomicU64,
+}
+
+static QUIC_TRANSPORT: OnceLock<QuicTransport> = OnceLock::new();
+
+/// The process-wide QUIC transport settings
+pub fn quic_transport() -> &'static QuicTransport {
+    QUIC_TRANSPORT.get_or_init(|| QuicTransport::new(QuicTransportSettings::default()))
+}
+
+impl QuicTransport {
+    pub fn new(settings: QuicTransportSettings) -> Self {
+        Self {
+            settings: RwLock::new(settings),
+            generation: AtomicU64::new(0),
+            verified_sessions: rustls::client::Resumption::in_memory_sessions(MAX_SESSION_TICKETS),
+            unverified_sessions: rustls::client::Resumption::in_memory_sessions(MAX_SESSION_TICKETS),
+            zero_rtt_attempts: AtomicU64::new(0),
+            zero_rtt_accepted: AtomicU64::new(0),
+            listener_connections: AtomicU64::new(0),
+        }
+    }
+
+    pub fn settings(&self) -> QuicTransportSettings {
+        *self.settings.read().unwrap()
+    }
+
+    /// Replace the settings
+    pub fn configure(&self, settings: QuicTransportSettings) {
+        let mut current = self.settings.write().unwrap();
+        if *current != settings {
+            *current = settings;
+            self.generation.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    /// Changes with every settings update
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Session ticket store for client connections
+    pub fn resumption(&self, verify: bool) -> rustls::client::Resumption {
+        if verify {
+            self.verified_sessions.clone()
+        } else {
+            self.unverified_sessions.clone()
+        }
+    }
+
+    /// Count an upstream connection that sent early data
+    pub fn record_zero_rtt(&self, accepted: bool) {
+        self.zero_rtt_attempts.fetch_add(1, Ordering::Relaxed);
+        if accepted {
+            self.zero_rtt_accepted.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Count a connection accepted by a DoQ listener
+    pub fn record_listener_connection(&self) {
+        self.listener_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> QuicTransportStats {
+        let settings = self.settings();
+        QuicTransportStats {
+            idle_timeout_secs: settings.idle_timeout.as_secs(),
+            keep_alive_secs: settings.keep_alive_interval.as_secs(),
+            max_streams: settings.max_streams,
+            zero_rtt: settings.zero_rtt,
+            migration: settings.migration,
+            zero_rtt_attempts: self.zero_rtt_attempts.load(Ordering::Relaxed),
+            zero_rtt_accepted: self.zero_rtt_accepted.load(Ordering::Relaxed),
+            listener_connections: self.listener_connections.load(Ordering::Relaxed),
+        }
+    }
+
+    /// QUIC config of a listener with the given TLS settings
+    pub fn server_config(&self, crypto: rustls::ServerConfig) -> anyhow::Result<quinn::ServerConfig> {
+        let settings = self.settings();
+        let mut crypto = crypto;
+        if settings.zero_rtt {
+            // quinn only accepts 0 or u32::MAX here
+            crypto.max_early_data_size = u32::MAX;
+        }
+        let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(crypto)
+            .map_err(|e| anyhow::anyhow!("Failed to create QUIC server config: {}", e))?;
+        let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+        server_config.transport_config(Arc::new(settings.transport_config()));
+        server_config.migration(settings.migration);
+        Ok(server_config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_configure_bumps_generation() {
+        let transport = QuicTransport::new(QuicTransportSettings::default());
+        transport.configure(QuicTransportSettings::default());
+        assert_eq!(transport.generation(), 0);
+
+        transport.configure(QuicTransportSettings {
+            zero_rtt: true,
+            keep_alive_interval: Duration::ZERO,
+            ..Default::default()
+        });
+        assert_eq!(transport.generation(), 1);
+        let stats = transport.stats();
+        assert!(stats.zero_rtt);
+        assert_eq!(stats.keep_alive_secs, 0);
+
+        transport.record_zero_rtt(true);
+        transport.record_zero_rtt(false);
+        let stats = transport.stats();
+        assert_eq!((stats.zero_rtt_attempts, stats.zero_rtt_accepted), (2, 1));
+    }
+}
@@ -3,8 +3,15 @@
 //! Provides different strategies for querying upstream DNS servers:
 //! - Concurrent: Query all servers simultaneously, return first response
 //! - Fastest: Use the server with the best historical response time
-//! - RoundRobin: Rotate through servers sequentially
+//! - RoundRobin: Rotate through servers in the configured order
 //! - Random: Select a random server for each query
+//!
+//! Failover after an error tries the remaining servers in the configured
+//! order (`sort_order`, set through `PUT /api/upstreams/order`).
+//!
+//! Queries pass the [`QueryLimiter`] before any upstream is contacted.
+//! Names covered by a [`ProtocolRule`] only go to upstreams of the allowed
+//! protocols.
 
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -13,10 +20,15 @@ use anyhow::{anyhow, Result};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
+use tracing::field::{display, Empty};
+use tracing::Instrument;
 use uuid::Uuid;
 
 use crate::dns::message::DnsQuery;
 use super::client::{create_client, DnsClient, QueryResult};
+use super::overload::QueryLimiter;
+use super::protocol_policy::{allowed_servers, ProtocolPolicy, ProtocolRule};
+use super::quic_transport::UpstreamQuicConnections;
 use super::upstream::{UpstreamManager, UpstreamServer};
 use std::collections::HashMap;
 use tokio::sync::Mutex;
@@ -24,6 +36,30 @@ use tokio::sync::Mutex;
 /// Global counter for query failures
 static TOTAL_FAILURE_COUNT: AtomicUsize = AtomicUsize::new(0);
 
+/// Query one upstream inside an `upstream_attempt` span
+async fn attempt(client: &dyn DnsClient, server: &UpstreamServer, query: &DnsQuery) -> Result<QueryResult> {
+    let span = tracing::debug_span!(
+        "upstream_attempt",
+        upstream = %server.name,
+        protocol = %server.protocol,
+        address = %server.address,
+        rcode = Empty,
+        response_time_ms = Empty,
+        error = Empty,
+    );
+    let result = client.query(query).instrument(span.clone()).await;
+    match &result {
+        Ok(r) => {
+            span.record("rcode", display(&r.response.response_code));
+            span.record("response_time_ms", r.response_time_ms);
+        }
+        Err(e) => {
+            span.record("error", display(e));
+        }
+    }
+    result
+}
+
 /// Query strategy types
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -86,6 +122,10 @@ pub struct ProxyManager {
     round_robin_counter: AtomicUsize,
     /// Upstream client cache (keyed by UpstreamServer)
     client_cache: Mutex<HashMap<UpstreamServer, Arc<dyn DnsClient>>>,
+    /// Cap on upstream queries in flight
+    limiter: QueryLimiter,
+    /// Per-domain upstream protocol restrictions
+    protocol_policy: ProtocolPolicy,
 }
 
 #[allow(dead_code)]
@@ -97,6 +137,8 @@ impl ProxyManager {
             strategy: RwLock::new(QueryStrategy::default()),
             round_robin_counter: AtomicUsize::new(0),
             client_cache: Mutex::new(HashMap::new()),
+            limiter: QueryLimiter::default(),
+            protocol_policy: ProtocolPolicy::new(),
         }
     }
 
@@ -121,6 +163,16 @@ impl ProxyManager {
         &self.upstream_manager
     }
 
+    /// Limiter for upstream queries in flight
+    pub fn limiter(&self) -> &QueryLimiter {
+        &self.limiter
+    }
+
+    /// Per-domain upstream protocol restrictions
+    pub fn protocol_policy(&self) -> &ProtocolPolicy {
+        &self.protocol_policy
+    }
+
     /// Get or create a client for the given server
     async fn get_client(&self, server: &UpstreamServer) -> Arc<dyn DnsClient> {
         let mut cache = self.client_cache.lock().await;
@@ -134,19 +186,52 @@ impl ProxyManager {
         client
     }
 
+    /// Transport state of the pooled QUIC connections, per upstream
+    pub async fn quic_connections(&self) -> Vec<UpstreamQuicConnections> {
+        let cache = self.client_cache.lock().await;
+        let mut upstreams: Vec<_> = cache
+            .values()
+            .filter_map(|client| {
+                let connections = client.quic_connections();
+                if connections.is_empty() {
+                    return None;
+                }
+                let server = client.server();
+                Some(UpstreamQuicConnections {
+                    server_id: server.id,
+                    server_name: server.name.clone(),
+                    protocol: server.protocol.as_str().to_string(),
+                    connections,
+                })
+            })
+            .collect();
+        upstreams.sort_by_key(|u| u.server_id);
+        upstreams
+    }
+
     /// Query upstream servers using the configured strategy
+    ///
+    /// Fails without contacting any upstream when the query limit is reached.
     pub async fn query(&self, query: &DnsQuery) -> Result<QueryResult> {
+        let _permit = self.limiter.acquire().await?;
+        self.query_with_strategy(query).await
+    }
+
+    async fn query_with_strategy(&self, query: &DnsQuery) -> Result<QueryResult> {
         use tracing::info;
         
         let trace_id = Uuid::new_v4().to_string();
         let strategy = self.get_strategy().await;
         info!("[{}] Query start: {} {} using {}", trace_id, query.name, query.record_type, strategy);
         
-        let result = match strategy {
-            QueryStrategy::Concurrent => self.query_concurrent(query, &trace_id).await,
-            QueryStrategy::Fastest => self.query_fastest(query, &trace_id).await,
-            QueryStrategy::RoundRobin => self.query_round_robin(query, &trace_id).await,
-            QueryStrategy::Random => self.query_random(query, &trace_id).await,
+        let result = match self.protocol_policy.rule_for(&query.name) {
+            Some(rule) => self.query_restricted(query, &rule, strategy, &trace_id).await,
+            None => match strategy {
+                QueryStrategy::Concurrent => self.query_concurrent(query, &trace_id).await,
+                QueryStrategy::Fastest => self.query_fastest(query, &trace_id).await,
+                QueryStrategy::RoundRobin => self.query_round_robin(query, &trace_id).await,
+                QueryStrategy::Random => self.query_random(query, &trace_id).await,
+            },
         };
         
         match &result {
@@ -161,6 +246,181 @@ impl ProxyManager {
         result
     }
 
+    /// Query a named upstream server, bypassing the configured strategy
+    ///
+    /// Falls back to the normal strategy if the server is unknown or
+    /// unhealthy, and fails over like any other single-server query. A
+    /// server whose protocol a protocol rule forbids for the name is not
+    /// used either.
+    pub async fn query_via(&self, query: &DnsQuery, server_name: &str) -> Result<QueryResult> {
+        use tracing::{info, warn};
+
+        let _permit = self.limiter.acquire().await?;
+        let trace_id = Uuid::new_v4().to_string();
+        let server = self
+            .upstream_manager
+            .get_healthy_servers()
+            .await
+            .into_iter()
+            .find(|s| s.name == server_name);
+
+        let rule = self.protocol_policy.rule_for(&query.name);
+        if let (Some(server), Some(rule)) = (&server, &rule) {
+            if !rule.allows(server) {
+                warn!(
+                    "[{}] [Routed] Upstream '{}' speaks {}, but protocol rule '{}' requires {} for {}",
+                    trace_id, server.name, server.protocol, rule.pattern, rule.protocol_list(), query.name
+                );
+                return self.query_with_strategy(query).await;
+            }
+        }
+
+        match server {
+            Some(server) => {
+                info!(
+                    "[{}] [Routed] {} {} via {}, addr: {}, protocol: {}",
+                    trace_id, query.name, query.record_type, server.name, server.address, server.protocol
+                );
+                self.query_server(server, query, &trace_id).await
+            }
+            None => {
+                warn!(
+                    "[{}] [Routed] Upstream '{}' not available, using strategy for {}",
+                    trace_id, server_name, query.name
+                );
+                self.query_with_strategy(query).await
+            }
+        }
+    }
+
+    /// Query each of the given servers concurrently and collect every result
+    ///
+    /// Unlike the strategies there is no failover and no early return, so
+    /// the answers of different upstreams can be compared.
+    pub async fn query_each(
+        &self,
+        query: &DnsQuery,
+        servers: &[UpstreamServer],
+    ) -> Vec<(UpstreamServer, Result<QueryResult>)> {
+        let queries = servers.iter().map(|server| async move {
+            let client = self.get_client(server).await;
+            let result = attempt(client.as_ref(), server, query).await;
+            match &result {
+                Ok(r) => self.upstream_manager.record_success(r.server_id, r.response_time_ms).await,
+                Err(_) => self.upstream_manager.record_failure(server.id).await,
+            }
+            (server.clone(), result)
+        });
+        futures::future::join_all(queries).await
+    }
+
+    /// Query the upstreams a protocol rule allows, using the strategy
+    ///
+    /// Concurrent races all allowed servers; the other strategies order
+    /// them and fail over within the allowed set only, so a rule is never
+    /// bypassed by failover.
+    async fn query_restricted(
+        &self,
+        query: &DnsQuery,
+        rule: &ProtocolRule,
+        strategy: QueryStrategy,
+        trace_id: &str,
+    ) -> Result<QueryResult> {
+        use rand::seq::SliceRandom;
+        use tracing::info;
+
+        let enabled = self.upstream_manager.get_servers().await;
+        let healthy = self.upstream_manager.get_healthy_servers().await;
+        let mut servers = allowed_servers(&query.name, rule, &enabled, healthy)?;
+
+        info!(
+            "[{}] [Protocol] {} limited to {} by rule '{}', {} candidate(s)",
+            trace_id, query.name, rule.protocol_list(), rule.pattern, servers.len()
+        );
+
+        match strategy {
+            QueryStrategy::Concurrent => {
+                let attempts = servers
+                    .iter()
+                    .map(|server| Box::pin(self.try_server(server, query, trace_id)));
+                return futures::future::select_ok(attempts)
+                    .await
+                    .map(|(result, _)| result)
+                    .map_err(|e| anyhow!("All {} upstream servers failed: {}", rule.protocol_list(), e));
+            }
+            QueryStrategy::Fastest => {
+                let stats = self.upstream_manager.get_all_stats().await;
+                servers.sort_by_key(|s| {
+                    stats.get(&s.id).map(|st| st.avg_response_time_for_sorting()).unwrap_or(0)
+                });
+            }
+            QueryStrategy::RoundRobin => {
+                let index = self.round_robin_counter.fetch_add(1, Ordering::Relaxed) % servers.len();
+                servers.rotate_left(index);
+            }
+            QueryStrategy::Random => servers.shuffle(&mut rand::thread_rng()),
+        }
+
+        let mut last_error = None;
+        let mut first_failed: Option<&str> = None;
+        for server in &servers {
+            match self.try_server(server, query, trace_id).await {
+                Ok(result) => {
+                    if let Some(failed) = first_failed {
+                        self.upstream_manager.record_failover(failed, Some(&result.server_name)).await;
+                    }
+                    return Ok(result);
+                }
+                Err(e) => {
+                    first_failed.get_or_insert(&server.name);
+                    last_error = Some(e);
+                }
+            }
+        }
+        if let Some(failed) = first_failed.filter(|_| servers.len() > 1) {
+            self.upstream_manager.record_failover(failed, None).await;
+        }
+        Err(anyhow!(
+            "All {} upstream servers failed: {}",
+            rule.protocol_list(),
+            last_error.map(|e| e.to_string()).unwrap_or_else(|| "unknown error".to_string())
+        ))
+    }
+
+    /// Query one server without failover, recording the outcome
+    ///
+    /// Only NOERROR and NXDOMAIN count as answers.
+    async fn try_server(&self, server: &UpstreamServer, query: &DnsQuery, trace_id: &str) -> Result<QueryResult> {
+        use crate::dns::message::DnsResponseCode;
+        use tracing::{info, warn};
+
+        let client = self.get_client(server).await;
+        let result = attempt(client.as_ref(), server, query).await.and_then(|result| {
+            let code = result.response.response_code;
+            if code == DnsResponseCode::NoError || code == DnsResponseCode::NxDomain {
+                Ok(result)
+            } else {
+                Err(anyhow!("{} returned {}", result.server_name, code))
+            }
+        });
+
+        match &result {
+            Ok(r) => {
+                info!(
+                    "[{}] Server {} responded: {} in {}ms",
+                    trace_id, r.server_name, r.response.response_code, r.response_time_ms
+                );
+                self.upstream_manager.record_success(r.server_id, r.response_time_ms).await;
+            }
+            Err(e) => {
+                let fail_count = TOTAL_FAILURE_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
+                warn!("[{}] Server {} failed: {}, 当前失败总数: {}", trace_id, server.name, e, fail_count);
+                self.upstream_manager.record_failure(server.id).await;
+            }
+        }
+        result
+    }
+
     /// Query all servers concurrently, return first successful response and cancel others
     async fn query_concurrent(&self, query: &DnsQuery, trace_id: &str) -> Result<QueryResult> {
         use tracing::{debug, info, warn};
@@ -205,9 +465,7 @@ impl ProxyManager {
                         // Return None to indicate cancelled (not a failure)
                         None
                     }
-                    result = async {
-                        client.query(&q).await
-                    } => {
+                    result = attempt(client.as_ref(), &server, &q) => {
                         // Log individual server result
                         match &result {
                             Ok(r) => info!(
@@ -222,7 +480,7 @@ impl ProxyManager {
                         Some((server_id, result))
                     }
                 }
-            });
+            }.in_current_span());
             handles.push(handle);
         }
 
@@ -386,7 +644,7 @@ impl ProxyManager {
         
         let client = self.get_client(&server).await;
         
-        match client.query(query).await {
+        match attempt(client.as_ref(), &server, query).await {
             Ok(result) => {
                 info!(
                     "[{}] Server {} responded: {} in {}ms",
@@ -403,8 +661,10 @@ impl ProxyManager {
                 self.upstream_manager.record_failure(server.id).await;
                 
                 // Try failover to another server
-                self.failover_query(query, server.id, trace_id).await
-                    .map_err(|_| anyhow!("Query failed and failover exhausted: {}", e))
+                let failover = self.failover_query(query, server.id, trace_id).await;
+                let to = failover.as_ref().ok().map(|r| r.server_name.as_str());
+                self.upstream_manager.record_failover(&server.name, to).await;
+                failover.map_err(|_| anyhow!("Query failed and failover exhausted: {}", e))
             }
         }
     }
@@ -427,7 +687,7 @@ impl ProxyManager {
             );
             
             let client = self.get_client(&server).await;
-            match client.query(query).await {
+            match attempt(client.as_ref(), &server, query).await {
                 Ok(result) => {
                     info!(
                         "[{}] [Failover] Server {} succeeded: {} in {}ms",
@@ -503,6 +763,28 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_protocol_rule_without_allowed_upstream() {
+        let upstream_manager = Arc::new(UpstreamManager::new());
+        upstream_manager.add_server(UpstreamServer::new(
+            1, "Server1", "127.0.0.1:1", UpstreamProtocol::Udp, 200,
+        )).await;
+
+        let proxy_manager = ProxyManager::new(upstream_manager);
+        proxy_manager.protocol_policy().set_rules(vec![ProtocolRule {
+            pattern: "*.example.org".to_string(),
+            protocols: vec![UpstreamProtocol::Doh, UpstreamProtocol::Doq],
+        }]);
+
+        let query = DnsQuery::new("www.example.org", crate::dns::message::RecordType::A);
+        let err = proxy_manager.query(&query).await.unwrap_err();
+        assert!(err.to_string().starts_with("No enabled doh/doq upstream for www.example.org"), "{}", err);
+
+        // Routing to a forbidden upstream by name does not bypass the rule
+        let err = proxy_manager.query_via(&query, "Server1").await.unwrap_err();
+        assert!(err.to_string().starts_with("No enabled doh/doq upstream"), "{}", err);
+    }
+
     #[tokio::test]
     async fn test_round_robin_counter() {
         let upstream_manager = Arc::new(UpstreamManager::new());
@@ -0,0 +1,192 @@
+//! Upstream Traffic Counters
+//!
+//! Every upstream client counts the DNS message bytes it sends and
+//! receives, including retries, TCP fallbacks and exchanges that fail
+//! half-way. Counts cover the DNS payload and its TCP length prefix, not
+//! TLS, QUIC or HTTP framing. Each server keeps cumulative totals and the
+//! bytes of the last minute in one-second buckets for current rates; they
+//! are reported in `/api/upstreams/status` and `/api/upstreams/metrics`.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+/// Seconds averaged for the current rates
+pub const TRAFFIC_RATE_WINDOW_SECS: u64 = 60;
+
+/// Bytes exchanged with one upstream
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct TrafficStats {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Average over the last minute
+    pub sent_bytes_per_sec: f64,
+    pub received_bytes_per_sec: f64,
+}
+
+/// One-second bucket: (second, sent, received)
+type Bucket = (u64, u64, u64);
+
+struct TrafficCounters {
+    sent: AtomicU64,
+    received: AtomicU64,
+    recent: Mutex<[Bucket; TRAFFIC_RATE_WINDOW_SECS as usize]>,
+}
+
+impl TrafficCounters {
+    fn new() -> Self {
+        Self {
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+            recent: Mutex::new([(0, 0, 0); TRAFFIC_RATE_WINDOW_SECS as usize]),
+        }
+    }
+
+    fn record(&self, now: u64, sent: u64, received: u64) {
+        self.sent.fetch_add(sent, Ordering::Relaxed);
+        self.received.fetch_add(received, Ordering::Relaxed);
+
+        let mut recent = self.recent.lock().unwrap();
+        let bucket = &mut recent[(now % TRAFFIC_RATE_WINDOW_SECS) as usize];
+        if bucket.0 != now {
+            *bucket = (now, 0, 0);
+        }
+        bucket.1 += sent;
+        bucket.2 += received;
+    }
+
+    fn stats(&self, now: u64) -> TrafficStats {
+        let (sent, received) = self
+            .recent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(second, _, _)| *second <= now && now - second < TRAFFIC_RATE_WINDOW_SECS)
+            .fold((0, 0), |(s, r), (_, sent, received)| (s + sent, r + received));
+        let window = TRAFFIC_RATE_WINDOW_SECS as f64;
+        TrafficStats {
+            bytes_sent: self.sent.load(Ordering::Relaxed),
+            bytes_received: self.received.load(Ordering::Relaxed),
+            sent_bytes_per_sec: sent as f64 / window,
+            received_bytes_per_sec: received as f64 / window,
+        }
+    }
+}
+
+/// Byte counters of all upstreams, keyed by server ID
+pub struct UpstreamTraffic {
+    servers: RwLock<HashMap<i64, Arc<TrafficCounters>>>,
+}
+
+static UPSTREAM_TRAFFIC: OnceLock<UpstreamTraffic> = OnceLock::new();
+
+/// The process-wide upstream traffic counters
+pub fn upstream_traffic() -> &'static UpstreamTraffic {
+    UPSTREAM_TRAFFIC.get_or_init(UpstreamTraffic::new)
+}
+
+#[allow(dead_code)]
+impl UpstreamTraffic {
+    pub fn new() -> Self {
+        Self {
+            servers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn counters(&self, id: i64) -> Arc<TrafficCounters> {
+        if let Some(counters) = self.servers.read().unwrap().get(&id) {
+            return counters.clone();
+        }
+        self.servers
+            .write()
+            .unwrap()
+            .entry(id)
+            .or_insert_with(|| Arc::new(TrafficCounters::new()))
+            .clone()
+    }
+
+    /// Count bytes sent to a server
+    pub fn record_sent(&self, id: i64, bytes: usize) {
+        self.counters(id).record(now_secs(), bytes as u64, 0);
+    }
+
+    /// Count bytes received from a server
+    pub fn record_received(&self, id: i64, bytes: usize) {
+        self.counters(id).record(now_secs(), 0, bytes as u64);
+    }
+
+    /// Traffic of one server; zero when it never exchanged a message
+    pub fn stats(&self, id: i64) -> TrafficStats {
+        self.servers
+            .read()
+            .unwrap()
+            .get(&id)
+            .map(|c| c.stats(now_secs()))
+            .unwrap_or_default()
+    }
+
+    /// Drop the counters of a deleted server
+    pub fn remove(&self, id: i64) {
+        self.servers.write().unwrap().remove(&id);
+    }
+}
+
+impl Default for UpstreamTraffic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traffic_totals_and_rates() {
+        let counters = TrafficCounters::new();
+        counters.record(1_000, 60, 0);
+        counters.record(1_000, 0, 120);
+        counters.record(1_030, 60, 240);
+
+        let stats = counters.stats(1_030);
+        assert_eq!(stats.bytes_sent, 120);
+        assert_eq!(stats.bytes_received, 360);
+        assert_eq!(stats.sent_bytes_per_sec, 2.0);
+        assert_eq!(stats.received_bytes_per_sec, 6.0);
+
+        // Only the last minute counts towards the rates
+        let stats = counters.stats(1_065);
+        assert_eq!(stats.bytes_sent, 120);
+        assert_eq!(stats.sent_bytes_per_sec, 1.0);
+        assert_eq!(stats.received_bytes_per_sec, 4.0);
+
+        // A bucket reused a minute later starts from zero
+        counters.record(1_090, 30, 0);
+        assert_eq!(counters.stats(1_090).sent_bytes_per_sec, 0.5);
+    }
+
+    #[test]
+    fn test_traffic_per_server() {
+        let traffic = UpstreamTraffic::new();
+        traffic.record_sent(1, 40);
+        traffic.record_received(1, 100);
+        traffic.record_sent(2, 50);
+        assert_eq!(traffic.stats(1).bytes_sent, 40);
+        assert_eq!(traffic.stats(1).bytes_received, 100);
+        assert_eq!(traffic.stats(3), TrafficStats::default());
+
+        traffic.remove(1);
+        assert_eq!(traffic.stats(1), TrafficStats::default());
+        assert_eq!(traffic.stats(2).bytes_sent, 50);
+    }
+}
@@ -7,10 +7,20 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use tokio::sync::RwLock;
+use tokio::sync::{Notify, RwLock};
 
 use crate::db::{Database, UpstreamServer as DbUpstreamServer};
+use crate::dns::SourceBinding;
+use super::client::create_client;
+use super::doh_options::{DohHttpVersion, DohMethod};
+use super::probe::{probe_upstream, UpstreamCapabilities, DEFAULT_EDNS_PAYLOAD_SIZE};
+
+/// Config key for the global outbound source IP
+pub const CONFIG_KEY_UPSTREAM_SOURCE_IP: &str = "upstream_source_ip";
+/// Config key for the global outbound source interface
+pub const CONFIG_KEY_UPSTREAM_SOURCE_INTERFACE: &str = "upstream_source_interface";
 
 /// Supported upstream DNS protocols
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -18,6 +28,8 @@ use crate::db::{Database, UpstreamServer as DbUpstreamServer};
 pub enum UpstreamProtocol {
     /// Standard UDP DNS (port 53)
     Udp,
+    /// Plain DNS over TCP (port 53)
+    Tcp,
     /// DNS over TLS (port 853)
     Dot,
     /// DNS over HTTPS (port 443)
@@ -33,6 +45,7 @@ impl UpstreamProtocol {
     pub fn from_str(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
             "udp" => Some(UpstreamProtocol::Udp),
+            "tcp" => Some(UpstreamProtocol::Tcp),
             "dot" => Some(UpstreamProtocol::Dot),
             "doh" => Some(UpstreamProtocol::Doh),
             "doq" => Some(UpstreamProtocol::Doq),
@@ -45,6 +58,7 @@ impl UpstreamProtocol {
     pub fn as_str(&self) -> &'static str {
         match self {
             UpstreamProtocol::Udp => "udp",
+            UpstreamProtocol::Tcp => "tcp",
             UpstreamProtocol::Dot => "dot",
             UpstreamProtocol::Doh => "doh",
             UpstreamProtocol::Doq => "doq",
@@ -55,13 +69,26 @@ impl UpstreamProtocol {
     /// Get default port for this protocol
     pub fn default_port(&self) -> u16 {
         match self {
-            UpstreamProtocol::Udp => 53,
+            UpstreamProtocol::Udp | UpstreamProtocol::Tcp => 53,
             UpstreamProtocol::Dot => 853,
             UpstreamProtocol::Doh => 443,
             UpstreamProtocol::Doq => 853,  // RFC 9250: DoQ uses UDP port 853
             UpstreamProtocol::Doh3 => 443, // DoH3 uses UDP port 443
         }
     }
+
+    /// Whether queries are sent over TLS or QUIC
+    pub fn is_encrypted(&self) -> bool {
+        !matches!(self, UpstreamProtocol::Udp | UpstreamProtocol::Tcp)
+    }
+
+    /// Whether the server certificate is verified unless configured otherwise
+    ///
+    /// QUIC upstreams are commonly configured by IP address, so their
+    /// certificates are only verified on request.
+    pub fn verifies_by_default(&self) -> bool {
+        matches!(self, UpstreamProtocol::Dot | UpstreamProtocol::Doh)
+    }
 }
 
 impl std::fmt::Display for UpstreamProtocol {
@@ -86,6 +113,20 @@ pub struct UpstreamServer {
     pub timeout: Duration,
     /// Whether this server is enabled
     pub enabled: bool,
+    /// Local source address/interface for queries to this server
+    pub source: SourceBinding,
+    /// TLS server name (SNI) when it differs from the address host
+    pub tls_server_name: Option<String>,
+    /// Whether the server certificate is verified against the server name
+    pub verify_hostname: bool,
+    /// HTTP method of DoH queries
+    pub doh_method: DohMethod,
+    /// HTTP version of DoH queries
+    pub doh_http_version: DohHttpVersion,
+    /// Features detected by the last capability probe
+    pub capabilities: Option<UpstreamCapabilities>,
+    /// In maintenance: health-checked but not used for queries
+    pub drained: bool,
 }
 
 #[allow(dead_code)]
@@ -105,12 +146,77 @@ impl UpstreamServer {
             protocol,
             timeout: Duration::from_millis(timeout_ms as u64),
             enabled: true,
+            source: SourceBinding::default(),
+            tls_server_name: None,
+            verify_hostname: protocol.verifies_by_default(),
+            doh_method: DohMethod::default(),
+            doh_http_version: DohHttpVersion::default(),
+            capabilities: None,
+            drained: false,
         }
     }
 
+    /// Set the outbound source binding
+    pub fn with_source(mut self, source: SourceBinding) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Set the TLS server name and certificate verification
+    pub fn with_tls(mut self, server_name: Option<String>, verify_hostname: bool) -> Self {
+        self.tls_server_name = server_name;
+        self.verify_hostname = verify_hostname;
+        self
+    }
+
+    /// Set the DoH request method and HTTP version
+    pub fn with_doh(mut self, method: DohMethod, http_version: DohHttpVersion) -> Self {
+        self.doh_method = method;
+        self.doh_http_version = http_version;
+        self
+    }
+
+    /// Name to send as SNI and verify the certificate against
+    pub fn tls_name<'a>(&'a self, host: &'a str) -> &'a str {
+        self.tls_server_name.as_deref().unwrap_or(host)
+    }
+
+    /// Set the probed capabilities
+    pub fn with_capabilities(mut self, capabilities: UpstreamCapabilities) -> Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
+    /// Capabilities from the last probe, unless it failed
+    pub fn probed(&self) -> Option<&UpstreamCapabilities> {
+        self.capabilities.as_ref().filter(|c| c.error.is_none())
+    }
+
+    /// UDP payload size to advertise in queries, `None` to send no OPT record
+    ///
+    /// EDNS is only used once a probe has shown the server handles it.
+    pub fn edns_payload_size(&self) -> Option<u16> {
+        self.probed()
+            .filter(|c| c.edns)
+            .map(|c| c.udp_payload_size.unwrap_or(DEFAULT_EDNS_PAYLOAD_SIZE).clamp(512, DEFAULT_EDNS_PAYLOAD_SIZE))
+    }
+
+    /// Whether truncated UDP answers should be retried over TCP
+    pub fn tcp_fallback(&self) -> bool {
+        self.probed().is_none_or(|c| c.tcp)
+    }
+
     /// Create from database model
     pub fn from_db(db_server: &DbUpstreamServer) -> Option<Self> {
         let protocol = UpstreamProtocol::from_str(&db_server.protocol)?;
+        let source = SourceBinding::parse(
+            db_server.source_ip.as_deref(),
+            db_server.source_interface.as_deref(),
+        )
+        .unwrap_or_else(|e| {
+            tracing::warn!("Ignoring source binding of upstream {}: {}", db_server.name, e);
+            SourceBinding::default()
+        });
         Some(Self {
             id: db_server.id,
             name: db_server.name.clone(),
@@ -118,6 +224,17 @@ impl UpstreamServer {
             protocol,
             timeout: Duration::from_millis(db_server.timeout as u64),
             enabled: db_server.enabled,
+            source,
+            tls_server_name: db_server.tls_server_name.clone().filter(|s| !s.is_empty()),
+            verify_hostname: db_server.verify_hostname.unwrap_or(protocol.verifies_by_default()),
+            doh_method: db_server.doh_method.as_deref()
+                .and_then(DohMethod::from_str)
+                .unwrap_or_default(),
+            doh_http_version: db_server.doh_http_version.as_deref()
+                .and_then(DohHttpVersion::from_str)
+                .unwrap_or_default(),
+            capabilities: UpstreamCapabilities::from_json(db_server.capabilities.as_deref()),
+            drained: db_server.drained,
         })
     }
 
@@ -365,18 +482,43 @@ pub struct HealthCheckResult {
 
 /// Upstream Server Manager
 ///
+/// A query moving on from a failed upstream to the next one
+#[derive(Debug, Clone, Serialize)]
+pub struct FailoverEvent {
+    pub at: DateTime<Utc>,
+    /// Server that failed the query
+    pub from: String,
+    /// Server that answered instead, None when every server failed
+    pub to: Option<String>,
+}
+
+/// Outcome of the last latency warm-up
+#[derive(Debug, Clone, Serialize)]
+pub struct WarmUpStatus {
+    pub finished_at: DateTime<Utc>,
+    /// Servers health-checked because they had no latency data
+    pub probed: usize,
+    /// Probed servers that answered
+    pub healthy: usize,
+}
+
 /// Manages a collection of upstream DNS servers with health checking
 /// and statistics tracking.
 pub struct UpstreamManager {
-    /// Loaded servers
+    /// Loaded servers, in the configured order
     servers: RwLock<Vec<UpstreamServer>>,
     /// Statistics per server (keyed by server ID)
     stats: RwLock<HashMap<i64, UpstreamStats>>,
     /// Database connection for persistence
     db: Option<Arc<Database>>,
-    /// Health check interval
-    #[allow(dead_code)]
+    /// Health check interval for drained servers
     health_check_interval: Duration,
+    /// Most recent failover
+    last_failover: RwLock<Option<FailoverEvent>>,
+    /// Signalled when servers without latency data were loaded
+    warm_up_needed: Notify,
+    /// Most recent warm-up
+    last_warm_up: RwLock<Option<WarmUpStatus>>,
 }
 
 #[allow(dead_code)]
@@ -388,6 +530,9 @@ impl UpstreamManager {
             stats: RwLock::new(HashMap::new()),
             db: None,
             health_check_interval: Duration::from_secs(30),
+            last_failover: RwLock::new(None),
+            warm_up_needed: Notify::new(),
+            last_warm_up: RwLock::new(None),
         }
     }
 
@@ -398,6 +543,9 @@ impl UpstreamManager {
             stats: RwLock::new(HashMap::new()),
             db: Some(db),
             health_check_interval: Duration::from_secs(30),
+            last_failover: RwLock::new(None),
+            warm_up_needed: Notify::new(),
+            last_warm_up: RwLock::new(None),
         }
     }
 
@@ -406,25 +554,67 @@ impl UpstreamManager {
         Arc::new(Self::new())
     }
 
+    /// Load the global outbound source binding from database
+    async fn global_source(db: &Database) -> anyhow::Result<SourceBinding> {
+        let config = db.system_config();
+        let ip = config.get(CONFIG_KEY_UPSTREAM_SOURCE_IP).await?;
+        let interface = config.get(CONFIG_KEY_UPSTREAM_SOURCE_INTERFACE).await?;
+        Ok(SourceBinding::parse(ip.as_deref(), interface.as_deref()).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring global upstream source binding: {}", e);
+            SourceBinding::default()
+        }))
+    }
+
+    /// Build runtime servers, applying the global source binding where unset
+    async fn servers_from_db(db: &Database) -> anyhow::Result<Vec<UpstreamServer>> {
+        let db_servers = db.upstream_servers().list_enabled().await?;
+        let global = Self::global_source(db).await?;
+        Ok(db_servers
+            .iter()
+            .filter_map(UpstreamServer::from_db)
+            .map(|s| {
+                let source = s.source.clone().or(&global);
+                s.with_source(source)
+            })
+            .collect())
+    }
+
     /// Load servers from database
     pub async fn load_servers(&self) -> anyhow::Result<()> {
         if let Some(ref db) = self.db {
-            let db_servers = db.upstream_servers().list_enabled().await?;
-            let servers: Vec<UpstreamServer> = db_servers
-                .iter()
-                .filter_map(|s| UpstreamServer::from_db(s))
-                .collect();
+            let servers = Self::servers_from_db(db).await?;
+            self.apply_servers(servers).await;
+        }
+        Ok(())
+    }
 
-            // Initialize stats for each server
-            let mut stats = self.stats.write().await;
-            for server in &servers {
+    /// Replace the loaded servers
+    ///
+    /// Stats are kept for unchanged servers and reset for servers whose
+    /// address or protocol changed, since their latency no longer applies.
+    /// A warm-up is requested when any server is left without latency data.
+    async fn apply_servers(&self, servers: Vec<UpstreamServer>) {
+        let mut current_servers = self.servers.write().await;
+        let mut stats = self.stats.write().await;
+        for server in &servers {
+            let changed = current_servers
+                .iter()
+                .find(|s| s.id == server.id)
+                .is_some_and(|s| s.address != server.address || s.protocol != server.protocol);
+            if changed {
+                stats.insert(server.id, UpstreamStats::new());
+            } else {
                 stats.entry(server.id).or_insert_with(UpstreamStats::new);
             }
+        }
 
-            let mut current_servers = self.servers.write().await;
-            *current_servers = servers;
+        let needs_warm_up = servers
+            .iter()
+            .any(|s| !s.drained && stats.get(&s.id).is_none_or(|st| st.successes == 0));
+        *current_servers = servers;
+        if needs_warm_up {
+            self.warm_up_needed.notify_one();
         }
-        Ok(())
     }
 
     /// Reload servers from database
@@ -435,21 +625,99 @@ impl UpstreamManager {
     /// Reload servers from a provided database reference
     pub async fn reload_from_db(&self, db: &Database) -> anyhow::Result<()> {
         db.checkpoint().await?;
-        let db_servers = db.upstream_servers().list_enabled().await?;
-        let servers: Vec<UpstreamServer> = db_servers
-            .iter()
-            .filter_map(|s| UpstreamServer::from_db(s))
-            .collect();
+        let servers = Self::servers_from_db(db).await?;
+        self.apply_servers(servers).await;
+        Ok(())
+    }
 
-        // Initialize stats for each server
-        let mut stats = self.stats.write().await;
-        for server in &servers {
-            stats.entry(server.id).or_insert_with(UpstreamStats::new);
+    /// Health-check servers without latency data, all at once
+    ///
+    /// Seeds the stats the fastest strategy ranks by, so the first queries
+    /// after startup or an upstream change are not sent to a slow server.
+    pub async fn warm_up(&self) -> WarmUpStatus {
+        let pending: Vec<UpstreamServer> = {
+            let servers = self.servers.read().await;
+            let stats = self.stats.read().await;
+            servers
+                .iter()
+                .filter(|s| !s.drained && stats.get(&s.id).is_none_or(|st| st.successes == 0))
+                .cloned()
+                .collect()
+        };
+
+        let results = futures::future::join_all(
+            pending.iter().map(|server| async move { create_client(server.clone()).health_check().await }),
+        )
+        .await;
+
+        let mut healthy = 0;
+        for (server, result) in pending.iter().zip(results) {
+            match result {
+                Ok(elapsed) => {
+                    self.record_success(server.id, elapsed.as_millis() as u64).await;
+                    healthy += 1;
+                }
+                Err(e) => {
+                    tracing::debug!("Warm-up of upstream {} failed: {}", server.name, e);
+                    self.record_failure(server.id).await;
+                }
+            }
         }
 
-        let mut current_servers = self.servers.write().await;
-        *current_servers = servers;
-        Ok(())
+        let status = WarmUpStatus {
+            finished_at: Utc::now(),
+            probed: pending.len(),
+            healthy,
+        };
+        *self.last_warm_up.write().await = Some(status.clone());
+        status
+    }
+
+    /// Wait until servers without latency data are loaded
+    pub async fn warm_up_requested(&self) {
+        self.warm_up_needed.notified().await
+    }
+
+    /// Most recent warm-up, None before the first one finished
+    pub async fn last_warm_up(&self) -> Option<WarmUpStatus> {
+        self.last_warm_up.read().await.clone()
+    }
+
+    /// Probe an upstream's capabilities and store them on its record
+    pub async fn probe_capabilities(
+        &self,
+        db: &Database,
+        db_server: &DbUpstreamServer,
+    ) -> anyhow::Result<UpstreamCapabilities> {
+        let server = UpstreamServer::from_db(db_server)
+            .ok_or_else(|| anyhow::anyhow!("Unknown protocol '{}'", db_server.protocol))?;
+        let source = server.source.clone().or(&Self::global_source(db).await?);
+        let capabilities = probe_upstream(&server.with_source(source)).await;
+
+        db.upstream_servers()
+            .set_capabilities(db_server.id, Some(&serde_json::to_string(&capabilities)?))
+            .await?;
+        self.reload_from_db(db).await?;
+        Ok(capabilities)
+    }
+
+    /// Probe enabled UDP upstreams that were never probed or are due again
+    pub async fn probe_due(&self) -> anyhow::Result<usize> {
+        let Some(ref db) = self.db else {
+            return Ok(0);
+        };
+
+        let mut probed = 0;
+        for db_server in db.upstream_servers().list_enabled().await? {
+            let due = UpstreamProtocol::from_str(&db_server.protocol) == Some(UpstreamProtocol::Udp)
+                && UpstreamCapabilities::from_json(db_server.capabilities.as_deref())
+                    .is_none_or(|c| c.is_stale());
+            if due {
+                self.probe_capabilities(db, &db_server).await?;
+                probed += 1;
+            }
+        }
+        Ok(probed)
     }
 
     /// Get all enabled servers
@@ -457,7 +725,9 @@ impl UpstreamManager {
         self.servers.read().await.clone()
     }
 
-    /// Get healthy servers only (excludes suspended servers)
+    /// Get servers that may take queries (excludes suspended and drained servers)
+    ///
+    /// Servers keep the configured order, which round-robin and failover follow.
     pub async fn get_healthy_servers(&self) -> Vec<UpstreamServer> {
         let servers = self.servers.read().await;
         let stats = self.stats.read().await;
@@ -465,7 +735,9 @@ impl UpstreamManager {
         servers
             .iter()
             .filter(|s| {
-                s.enabled && stats.get(&s.id).map(|st| st.is_healthy()).unwrap_or(true)
+                s.enabled
+                    && !s.drained
+                    && stats.get(&s.id).map(|st| st.is_healthy()).unwrap_or(true)
             })
             .cloned()
             .collect()
@@ -520,6 +792,53 @@ impl UpstreamManager {
         }
     }
 
+    /// Record that a query failed over from `from` to `to`
+    pub async fn record_failover(&self, from: &str, to: Option<&str>) {
+        *self.last_failover.write().await = Some(FailoverEvent {
+            at: Utc::now(),
+            from: from.to_string(),
+            to: to.map(str::to_string),
+        });
+    }
+
+    /// Most recent failover, None since startup if there was none
+    pub async fn last_failover(&self) -> Option<FailoverEvent> {
+        self.last_failover.read().await.clone()
+    }
+
+    /// Health-check drained servers
+    ///
+    /// Drained servers get no production queries, so their stats would go
+    /// stale; an explicit check keeps them current for the status views and
+    /// shows whether a server is fit to return to service.
+    pub async fn check_drained(&self) -> usize {
+        let drained: Vec<UpstreamServer> = self
+            .servers
+            .read()
+            .await
+            .iter()
+            .filter(|s| s.drained)
+            .cloned()
+            .collect();
+
+        for server in &drained {
+            let id = server.id;
+            match create_client(server.clone()).health_check().await {
+                Ok(elapsed) => self.record_success(id, elapsed.as_millis() as u64).await,
+                Err(e) => {
+                    tracing::debug!("Health check of drained upstream {} failed: {}", server.name, e);
+                    self.record_failure(id).await;
+                }
+            }
+        }
+        drained.len()
+    }
+
+    /// Interval between health checks of drained servers
+    pub fn health_check_interval(&self) -> Duration {
+        self.health_check_interval
+    }
+
     /// Reset health status for a server
     pub async fn reset_health(&self, id: i64) {
         let mut stats = self.stats.write().await;
@@ -621,6 +940,7 @@ mod tests {
     fn test_protocol_from_str() {
         assert_eq!(UpstreamProtocol::from_str("udp"), Some(UpstreamProtocol::Udp));
         assert_eq!(UpstreamProtocol::from_str("UDP"), Some(UpstreamProtocol::Udp));
+        assert_eq!(UpstreamProtocol::from_str("tcp"), Some(UpstreamProtocol::Tcp));
         assert_eq!(UpstreamProtocol::from_str("dot"), Some(UpstreamProtocol::Dot));
         assert_eq!(UpstreamProtocol::from_str("doh"), Some(UpstreamProtocol::Doh));
         assert_eq!(UpstreamProtocol::from_str("doq"), Some(UpstreamProtocol::Doq));
@@ -632,6 +952,7 @@ mod tests {
     #[test]
     fn test_protocol_default_port() {
         assert_eq!(UpstreamProtocol::Udp.default_port(), 53);
+        assert_eq!(UpstreamProtocol::Tcp.default_port(), 53);
         assert_eq!(UpstreamProtocol::Dot.default_port(), 853);
         assert_eq!(UpstreamProtocol::Doh.default_port(), 443);
         assert_eq!(UpstreamProtocol::Doq.default_port(), 853);  // RFC 9250: DoQ uses UDP port 853
@@ -784,6 +1105,25 @@ mod tests {
         assert_eq!(healthy[0].id, 1);
     }
 
+    #[tokio::test]
+    async fn test_upstream_manager_drained_servers() {
+        let manager = UpstreamManager::new();
+
+        manager.add_server(UpstreamServer::new(
+            1, "Active", "8.8.8.8:53", UpstreamProtocol::Udp, 5000,
+        )).await;
+        let mut drained = UpstreamServer::new(2, "Drained", "8.8.4.4:53", UpstreamProtocol::Udp, 5000);
+        drained.drained = true;
+        manager.add_server(drained).await;
+
+        // Drained servers stay loaded but take no queries
+        assert_eq!(manager.server_count().await, 2);
+        let healthy = manager.get_healthy_servers().await;
+        assert_eq!(healthy.len(), 1);
+        assert_eq!(healthy[0].id, 1);
+        assert_eq!(manager.get_fastest_server().await.unwrap().id, 1);
+    }
+
     #[tokio::test]
     async fn test_upstream_manager_fastest_server() {
         let manager = UpstreamManager::new();
@@ -814,4 +1154,23 @@ mod tests {
         
         assert_eq!(manager.server_count().await, 0);
     }
+
+    #[tokio::test]
+    async fn test_upstream_manager_apply_servers_keeps_latency() {
+        let manager = UpstreamManager::new();
+        let server = UpstreamServer::new(1, "Server1", "1.1.1.1:53", UpstreamProtocol::Udp, 5000);
+        manager.apply_servers(vec![server.clone()]).await;
+        manager.record_success(1, 20).await;
+
+        // Unchanged servers keep their latency, so no warm-up is needed
+        manager.apply_servers(vec![server.clone()]).await;
+        assert_eq!(manager.get_stats(1).await.unwrap().successes, 1);
+        assert_eq!(manager.warm_up().await.probed, 0);
+        assert_eq!(manager.last_warm_up().await.map(|w| w.probed), Some(0));
+
+        // A new address starts over
+        let moved = UpstreamServer::new(1, "Server1", "1.0.0.1:53", UpstreamProtocol::Udp, 5000);
+        manager.apply_servers(vec![moved]).await;
+        assert_eq!(manager.get_stats(1).await.unwrap().successes, 0);
+    }
 }
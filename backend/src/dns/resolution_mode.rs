@@ -0,0 +1,160 @@
+//! Listener resolution modes
+//!
+//! Each listener resolves in one of three modes:
+//! - `recursive` (default): the full pipeline, forwarding cache misses upstream
+//! - `authoritative_only`: answers come from rewrite rules, local records and
+//!   local zones only; the cache is not consulted, every response has RA=0
+//!   and names outside the local data are REFUSED
+//! - `cache_only`: like recursive, but cache misses are REFUSED instead of
+//!   being forwarded
+//!
+//! The mode applies to rewrite targets too, so a `MapToDomain` rule on an
+//! authoritative-only listener cannot reach an upstream either.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::db::Database;
+use super::extended_error::ExtendedError;
+use super::message::DnsResponse;
+
+/// `answered_by` value for queries refused by the listener's resolution mode
+pub const RESOLUTION_MODE_ANSWERED_BY: &str = "resolution_mode";
+
+/// How queries arriving on a listener are resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolutionMode {
+    #[default]
+    Recursive,
+    AuthoritativeOnly,
+    CacheOnly,
+}
+
+impl ResolutionMode {
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().replace('-', "_").as_str() {
+            "recursive" => Some(Self::Recursive),
+            "authoritative_only" => Some(Self::AuthoritativeOnly),
+            "cache_only" => Some(Self::CacheOnly),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Recursive => "recursive",
+            Self::AuthoritativeOnly => "authoritative_only",
+            Self::CacheOnly => "cache_only",
+        }
+    }
+
+    /// Whether cache misses may be forwarded upstream
+    pub fn forwards(&self) -> bool {
+        *self == Self::Recursive
+    }
+
+    /// Whether cached upstream answers may be served
+    pub fn uses_cache(&self) -> bool {
+        *self != Self::AuthoritativeOnly
+    }
+
+    /// Whether responses advertise recursion (RA)
+    pub fn recursion_available(&self) -> bool {
+        *self != Self::AuthoritativeOnly
+    }
+
+    /// Answer for a query this mode will not forward
+    pub fn refusal(&self, id: u16) -> DnsResponse {
+        let error = match self {
+            Self::AuthoritativeOnly => ExtendedError::new(ExtendedError::NOT_AUTHORITATIVE, "authoritative-only listener"),
+            _ => ExtendedError::new(ExtendedError::OTHER, "cache-only listener"),
+        };
+        let mut response = DnsResponse::refused(id).with_extended_error(error);
+        response.recursion_available = self.recursion_available();
+        response
+    }
+}
+
+/// Resolution mode of each listener protocol
+pub struct ListenerResolutionModes {
+    db: Option<Arc<Database>>,
+    modes: RwLock<HashMap<String, ResolutionMode>>,
+}
+
+impl ListenerResolutionModes {
+    pub fn new(db: Option<Arc<Database>>) -> Self {
+        Self {
+            db,
+            modes: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Load the modes of all listeners from database
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let modes = db
+            .server_listeners()
+            .list()
+            .await?
+            .into_iter()
+            .filter_map(|l| Some((l.protocol, ResolutionMode::from_str(&l.resolution_mode)?)))
+            .collect();
+        self.set_modes(modes);
+        Ok(())
+    }
+
+    /// Set the modes by listener protocol (in-memory only)
+    pub fn set_modes(&self, modes: HashMap<String, ResolutionMode>) {
+        *self.modes.write().unwrap() = modes;
+    }
+
+    /// Mode of a listener; queries without one resolve recursively
+    pub fn mode(&self, listener: Option<&str>) -> ResolutionMode {
+        listener
+            .and_then(|l| self.modes.read().unwrap().get(l).copied())
+            .unwrap_or_default()
+    }
+}
+
+impl Default for ListenerResolutionModes {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(ResolutionMode::from_str("Authoritative-Only"), Some(ResolutionMode::AuthoritativeOnly));
+        assert_eq!(ResolutionMode::from_str("cache_only"), Some(ResolutionMode::CacheOnly));
+        assert_eq!(ResolutionMode::from_str("forward"), None);
+        for mode in [ResolutionMode::Recursive, ResolutionMode::AuthoritativeOnly, ResolutionMode::CacheOnly] {
+            assert_eq!(ResolutionMode::from_str(mode.as_str()), Some(mode));
+        }
+    }
+
+    #[test]
+    fn test_listener_modes() {
+        let modes = ListenerResolutionModes::default();
+        modes.set_modes(HashMap::from([("udp".to_string(), ResolutionMode::AuthoritativeOnly)]));
+        assert_eq!(modes.mode(Some("udp")), ResolutionMode::AuthoritativeOnly);
+        assert_eq!(modes.mode(Some("doh")), ResolutionMode::Recursive);
+        assert_eq!(modes.mode(None), ResolutionMode::Recursive);
+
+        let refusal = ResolutionMode::AuthoritativeOnly.refusal(7);
+        assert_eq!(refusal.id, 7);
+        assert!(!refusal.recursion_available);
+        assert_eq!(refusal.extended_error.map(|e| e.code), Some(ExtendedError::NOT_AUTHORITATIVE));
+    }
+}
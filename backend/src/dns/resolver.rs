@@ -5,16 +5,34 @@
 
 use std::net::IpAddr;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use tracing::debug;
+use tracing::{debug, Instrument};
 
 use crate::db::{Database, CreateQueryLog};
 use super::cache::{CacheKey, CacheManager};
+use super::capture::QueryCapture;
+use super::client_acl::{BlockReason, ClientAcl, CLIENT_ACL_ANSWERED_BY};
+use super::companion::CompanionPrefetch;
+use super::cookie::DnsCookies;
+use super::deadline::{ResolutionDeadline, DEADLINE_ANSWERED_BY};
+use super::domain_stats::DomainStats;
+use super::extended_error::ExtendedError;
+use super::fail_policy::{FailMode, FailPolicy, FailPolicyStatus, FAIL_CLOSED_ANSWERED_BY};
+use super::local_records::LocalRecordIndex;
+use super::local_zones::{PrivateReverseZones, LOCAL_ZONE_ANSWERED_BY};
+use super::log_sampling::QueryLogSampler;
+use super::middleware::{new_trace_id, DisabledRecordTypes, DomainValidation, MiddlewareChain, QueryContext};
 use super::message::{DnsQuery, DnsRecordData, DnsResponse, DnsResponseCode, RecordType};
+use super::name::normalize_name;
+use super::offline::{OfflineMode, OFFLINE_ANSWERED_BY};
+use super::policy_stats::{PolicyMatch, PolicyOutcome, PolicyStats};
 use super::proxy::ProxyManager;
+use super::resolution_mode::{ListenerResolutionModes, RESOLUTION_MODE_ANSWERED_BY};
 use super::rewrite::{RewriteAction, RewriteEngine};
+use super::shuffle::AnswerShuffle;
+use super::tenant::TenantRegistry;
 
 /// Query metadata returned alongside the DNS response
 #[derive(Debug, Clone)]
@@ -29,6 +47,12 @@ pub struct QueryMetadata {
     pub rewrite_applied: bool,
     /// The rewrite rule ID that was applied (if any)
     pub rewrite_rule_id: Option<i64>,
+    /// Middleware that answered the query directly (if any)
+    pub answered_by: Option<String>,
+    /// Domain category, when a classifier is registered
+    pub category: Option<String>,
+    /// Policy that answered the query instead of normal resolution
+    pub policy: Option<PolicyMatch>,
 }
 
 impl Default for QueryMetadata {
@@ -39,6 +63,37 @@ impl Default for QueryMetadata {
             upstream_used: None,
             rewrite_applied: false,
             rewrite_rule_id: None,
+            answered_by: None,
+            category: None,
+            policy: None,
+        }
+    }
+}
+
+impl QueryMetadata {
+    /// Pipeline stage that answered, as recorded on the `dns_query` span
+    pub fn outcome(&self) -> &str {
+        if self.cache_hit {
+            "cache"
+        } else if self.rewrite_applied {
+            "rewrite"
+        } else if let Some(ref answered_by) = self.answered_by {
+            answered_by
+        } else if self.upstream_used.is_some() {
+            "upstream"
+        } else {
+            "local_record"
+        }
+    }
+
+    /// Why the query was blocked: the client by the ACL, or the name by policy
+    pub fn block_reason(&self) -> Option<BlockReason> {
+        if self.answered_by.as_deref() == Some(CLIENT_ACL_ANSWERED_BY) {
+            Some(BlockReason::Client)
+        } else if self.policy.as_ref().is_some_and(|p| p.outcome == PolicyOutcome::Blocked) {
+            Some(BlockReason::Domain)
+        } else {
+            None
         }
     }
 }
@@ -65,6 +120,38 @@ pub struct DnsResolver {
     proxy: Arc<ProxyManager>,
     /// Database for query logging (optional)
     db: Option<Arc<Database>>,
+    /// Tenant views selected per client
+    tenants: Arc<TenantRegistry>,
+    /// Middleware hooks run at each pipeline stage
+    middleware: Arc<MiddlewareChain>,
+    /// Offline mode switch; blocks all upstream forwarding when enabled
+    offline: Arc<OfflineMode>,
+    /// DNS cookie issuance and validation for the UDP listener
+    cookies: Arc<DnsCookies>,
+    /// Blocked, remapped and locally answered client queries
+    policy_stats: Arc<PolicyStats>,
+    /// Top-domain leaderboard of client queries
+    domain_stats: Arc<DomainStats>,
+    /// Live query captures for diagnostics
+    capture: Arc<QueryCapture>,
+    /// Which queries are written to the query log
+    log_sampling: Arc<QueryLogSampler>,
+    /// Local records held in memory
+    local_records: Arc<LocalRecordIndex>,
+    /// Random ordering of address records in upstream and cached answers
+    shuffle: Arc<AnswerShuffle>,
+    /// End-to-end time budget of each client query
+    deadline: Arc<ResolutionDeadline>,
+    /// Background resolution of the A/AAAA companion of upstream misses
+    companion: Arc<CompanionPrefetch>,
+    /// What to do while rewrite rules or local records are unavailable
+    fail_policy: Arc<FailPolicy>,
+    /// Local answers for private reverse zones (RFC 6303)
+    private_zones: Arc<PrivateReverseZones>,
+    /// Recursive, authoritative-only or cache-only resolution per listener
+    listener_modes: Arc<ListenerResolutionModes>,
+    /// Client networks whose queries are rejected
+    client_acl: Arc<ClientAcl>,
 }
 
 
@@ -76,11 +163,28 @@ impl DnsResolver {
         cache: Arc<CacheManager>,
         proxy: Arc<ProxyManager>,
     ) -> Self {
+        let client_acl = Arc::new(ClientAcl::new(None));
         Self {
             rewrite_engine,
             cache,
             proxy,
             db: None,
+            tenants: Arc::new(TenantRegistry::new()),
+            middleware: Arc::new(Self::builtin_middleware(None, client_acl.clone())),
+            offline: Arc::new(OfflineMode::new(None)),
+            cookies: Arc::new(DnsCookies::new(None)),
+            policy_stats: Arc::new(PolicyStats::new()),
+            domain_stats: Arc::new(DomainStats::new(None)),
+            capture: Arc::new(QueryCapture::new()),
+            log_sampling: Arc::new(QueryLogSampler::new(None)),
+            local_records: Arc::new(LocalRecordIndex::new(None)),
+            shuffle: Arc::new(AnswerShuffle::new(None)),
+            deadline: Arc::new(ResolutionDeadline::new(None)),
+            companion: Arc::new(CompanionPrefetch::new(None)),
+            fail_policy: Arc::new(FailPolicy::new(None)),
+            private_zones: Arc::new(PrivateReverseZones::new(None)),
+            listener_modes: Arc::new(ListenerResolutionModes::new(None)),
+            client_acl,
         }
     }
 
@@ -91,14 +195,44 @@ impl DnsResolver {
         proxy: Arc<ProxyManager>,
         db: Arc<Database>,
     ) -> Self {
+        let client_acl = Arc::new(ClientAcl::new(Some(db.clone())));
         Self {
             rewrite_engine,
             cache,
             proxy,
+            tenants: Arc::new(TenantRegistry::with_db(db.clone())),
+            middleware: Arc::new(Self::builtin_middleware(Some(db.clone()), client_acl.clone())),
+            offline: Arc::new(OfflineMode::new(Some(db.clone()))),
+            cookies: Arc::new(DnsCookies::new(Some(db.clone()))),
+            policy_stats: Arc::new(PolicyStats::new()),
+            domain_stats: Arc::new(DomainStats::new(Some(db.clone()))),
+            capture: Arc::new(QueryCapture::new()),
+            log_sampling: Arc::new(QueryLogSampler::new(Some(db.clone()))),
+            local_records: Arc::new(LocalRecordIndex::new(Some(db.clone()))),
+            shuffle: Arc::new(AnswerShuffle::new(Some(db.clone()))),
+            deadline: Arc::new(ResolutionDeadline::new(Some(db.clone()))),
+            companion: Arc::new(CompanionPrefetch::new(Some(db.clone()))),
+            fail_policy: Arc::new(FailPolicy::new(Some(db.clone()))),
+            private_zones: Arc::new(PrivateReverseZones::new(Some(db.clone()))),
+            listener_modes: Arc::new(ListenerResolutionModes::new(Some(db.clone()))),
+            client_acl,
             db: Some(db),
         }
     }
 
+    /// Middleware chain with the built-in pre-rewrite checks
+    ///
+    /// The client ACL comes first, so blocked clients learn nothing else.
+    fn builtin_middleware(db: Option<Arc<Database>>, client_acl: Arc<ClientAcl>) -> MiddlewareChain {
+        let chain = MiddlewareChain::new();
+        chain.register(client_acl);
+        chain.register(Arc::new(DomainValidation));
+        if let Some(db) = db {
+            chain.register(Arc::new(DisabledRecordTypes::new(db)));
+        }
+        chain
+    }
+
     /// Create a new DNS resolver wrapped in Arc
     pub fn new_shared(
         rewrite_engine: Arc<RewriteEngine>,
@@ -123,61 +257,240 @@ impl DnsResolver {
         &self.proxy
     }
 
+    /// Get the tenant registry
+    pub fn tenants(&self) -> &Arc<TenantRegistry> {
+        &self.tenants
+    }
+
+    /// Get the middleware chain, to register custom hooks
+    pub fn middleware(&self) -> &Arc<MiddlewareChain> {
+        &self.middleware
+    }
+
+    /// Get the offline mode switch
+    pub fn offline(&self) -> &Arc<OfflineMode> {
+        &self.offline
+    }
+
+    /// Get the client access list
+    pub fn client_acl(&self) -> &Arc<ClientAcl> {
+        &self.client_acl
+    }
+
+    /// Get the DNS cookie state
+    pub fn cookies(&self) -> &Arc<DnsCookies> {
+        &self.cookies
+    }
+
+    /// Get the policy counters
+    pub fn policy_stats(&self) -> &Arc<PolicyStats> {
+        &self.policy_stats
+    }
+
+    /// Get the top-domain leaderboard
+    pub fn domain_stats(&self) -> &Arc<DomainStats> {
+        &self.domain_stats
+    }
+
+    /// Get the live query capture
+    pub fn capture(&self) -> &Arc<QueryCapture> {
+        &self.capture
+    }
+
+    /// Get the query log sampler
+    pub fn log_sampling(&self) -> &Arc<QueryLogSampler> {
+        &self.log_sampling
+    }
+
+    /// Get the in-memory local record index
+    pub fn local_records(&self) -> &Arc<LocalRecordIndex> {
+        &self.local_records
+    }
+
+    /// Get the answer shuffling settings
+    pub fn shuffle(&self) -> &Arc<AnswerShuffle> {
+        &self.shuffle
+    }
+
+    /// Get the resolution deadline
+    pub fn deadline(&self) -> &Arc<ResolutionDeadline> {
+        &self.deadline
+    }
+
+    /// Get the companion prefetch
+    pub fn companion(&self) -> &Arc<CompanionPrefetch> {
+        &self.companion
+    }
+
+    /// Get the private reverse zones
+    pub fn private_zones(&self) -> &Arc<PrivateReverseZones> {
+        &self.private_zones
+    }
+
+    /// Get the resolution modes of the listeners
+    pub fn listener_modes(&self) -> &Arc<ListenerResolutionModes> {
+        &self.listener_modes
+    }
+
+    /// Get the fail-open / fail-closed policy
+    pub fn fail_policy(&self) -> &Arc<FailPolicy> {
+        &self.fail_policy
+    }
+
+    /// Fail policy state, including rewrite rule load failures
+    pub fn fail_policy_status(&self) -> FailPolicyStatus {
+        self.fail_policy.status(self.rewrite_engine.load_failure())
+    }
+
+    /// Resolve a query upstream and cache the answer with a custom TTL
+    ///
+    /// Used to preload the cache before switching to offline mode, so
+    /// skips rewrite rules, local records and middleware.
+    pub async fn preload(&self, query: &DnsQuery, ttl: Duration) -> Result<DnsResponseCode> {
+        if self.offline.is_enabled() {
+            return Err(anyhow::anyhow!("Cannot preload the cache while offline mode is enabled"));
+        }
+
+        let result = self.proxy.query(query).await?;
+        let response_code = result.response.response_code;
+        if response_code == DnsResponseCode::NoError {
+            let limits = self.cache.get_config().await.limits();
+            self.cache
+                .set_with_ttl(CacheKey::from_query(query), result.response, ttl, limits)
+                .await;
+        }
+        Ok(response_code)
+    }
+
     /// Resolve a DNS query
     ///
     /// This is the main entry point for DNS resolution. It follows this flow:
-    /// 1. Validate domain name (reject invalid domains)
-    /// 2. Check if record type is disabled
-    /// 3. Check rewrite rules
-    /// 4. If rewrite matches, apply the action
-    /// 5. Check local DNS records from database
-    /// 6. Otherwise, check cache
-    /// 7. If cache miss, query upstream via proxy
-    /// 8. Cache the response
+    /// 1. Run pre-rewrite middleware (domain validation, disabled record types, ...)
+    /// 2. Check rewrite rules
+    /// 3. If rewrite matches, apply the action
+    /// 4. Check local DNS records from database
+    /// 5. Otherwise, check cache
+    /// 6. If cache miss, run pre-upstream middleware, then query upstream via proxy
+    ///    (private reverse zones are answered locally, and in offline mode the
+    ///    configured response code is returned instead; listeners that do not
+    ///    forward skip the cache or refuse here, see `ResolutionMode`)
+    /// 7. Cache the response
+    /// 8. Run post-response middleware on the final result
     pub async fn resolve(&self, query: &DnsQuery) -> Result<ResolveResult> {
+        self.resolve_for_tenant(query, None).await
+    }
+
+    /// Resolve a DNS query within a tenant's view
+    ///
+    /// `None` resolves against global records and rules only. Upstream
+    /// answers are shared between views, so the cache is not partitioned.
+    pub async fn resolve_for_tenant(&self, query: &DnsQuery, tenant_id: Option<i64>) -> Result<ResolveResult> {
+        self.resolve_with_context(QueryContext::new(query.clone(), tenant_id)).await
+    }
+
+    /// Resolve a DNS query with full client context available to middleware
+    ///
+    /// Runs inside a `dns_query` span carrying the trace ID, so resolver and
+    /// proxy log lines for this query can be matched to its query log entry.
+    /// The span records the outcome, upstream and cache hit once answered;
+    /// each pipeline stage and upstream attempt gets a child span.
+    /// The pipeline is bounded by the resolution deadline; past it the query
+    /// is answered with SERVFAIL.
+    pub async fn resolve_with_context(&self, mut ctx: QueryContext) -> Result<ResolveResult> {
+        let span = tracing::info_span!(
+            "dns_query",
+            trace_id = %ctx.trace_id,
+            qname = %ctx.query.name,
+            qtype = %ctx.query.record_type,
+            client = ctx.client_ip.as_deref().unwrap_or("-"),
+            outcome = tracing::field::Empty,
+            upstream = tracing::field::Empty,
+            cache_hit = tracing::field::Empty,
+            rcode = tracing::field::Empty,
+        );
+        async move {
+            let mut result = match self.deadline.budget() {
+                Some(budget) => {
+                    match tokio::time::timeout(budget, self.run_pipeline(&mut ctx)).await {
+                        Ok(result) => result?,
+                        Err(_) => self.deadline_exceeded(&ctx, budget),
+                    }
+                }
+                None => self.run_pipeline(&mut ctx).await?,
+            };
+            if result.response.extended_error.is_none() {
+                result.response.extended_error = result.metadata.policy.as_ref().and_then(PolicyMatch::extended_error);
+            }
+            if !ctx.resolution_mode.recursion_available() {
+                result.response.recursion_available = false;
+            }
+            self.middleware.run_post_response(&ctx, &mut result).await;
+
+            let span = tracing::Span::current();
+            span.record("outcome", result.metadata.outcome());
+            span.record("cache_hit", result.metadata.cache_hit);
+            span.record("rcode", tracing::field::display(&result.response.response_code));
+            if let Some(ref upstream) = result.metadata.upstream_used {
+                span.record("upstream", upstream.as_str());
+            }
+            Ok(result)
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Resolution pipeline between the pre-rewrite and post-response stages
+    async fn run_pipeline(&self, ctx: &mut QueryContext) -> Result<ResolveResult> {
         let start = Instant::now();
         let mut metadata = QueryMetadata::default();
+        let original_id = ctx.query.id;
 
-        // Step 0: Validate domain name - reject invalid domains
-        if !Self::is_valid_domain(&query.name) {
+        // Step 1: Pre-rewrite middleware
+        if let Some((hook, mut response)) = self.middleware.run_pre_rewrite(ctx).await {
+            response.id = original_id;
+            metadata.policy = PolicyMatch::middleware(&hook, &response);
+            metadata.answered_by = Some(hook);
+            metadata.response_time_ms = start.elapsed().as_millis() as u64;
             debug!(
-                "[DNS Result] {} {} | Invalid domain (rejected) | {}ms",
-                query.name, query.record_type, start.elapsed().as_millis()
+                "[DNS Result] {} {} | Middleware({}) {} | {}ms",
+                ctx.query.name,
+                ctx.query.record_type,
+                metadata.answered_by.as_deref().unwrap_or_default(),
+                response.response_code,
+                metadata.response_time_ms
             );
-            metadata.response_time_ms = start.elapsed().as_millis() as u64;
-            return Ok(ResolveResult {
-                response: DnsResponse::refused(query.id),
-                metadata,
-            });
+            return Ok(ResolveResult { response, metadata });
         }
 
+        let query = &ctx.query.clone();
+        let tenant_id = ctx.tenant_id;
+
         debug!("[DNS Query] {} {} (ID: {})", query.name, query.record_type, query.id);
 
-        // Step 1: Check if record type is disabled
-        if let Some(ref db) = self.db {
-            if self.is_record_type_disabled(db, &query.record_type.to_string()).await {
-                debug!(
-                    "[DNS Result] {} {} | Disabled record type | {}ms",
-                    query.name, query.record_type, start.elapsed().as_millis()
-                );
-                metadata.response_time_ms = start.elapsed().as_millis() as u64;
-                return Ok(ResolveResult {
-                    response: DnsResponse::nxdomain(query.id),
-                    metadata,
-                });
+        // Step 2: Check rewrite rules, unless they could not be loaded
+        let mut degraded = false;
+        if self.rewrite_engine.rules_unavailable() {
+            degraded = true;
+            if let Some(result) = self.fail_closed(query, ctx.profile_id, start).await {
+                return Ok(result);
             }
-        }
-
-        // Step 2: Check rewrite rules
-        if let Some(rewrite_result) = self.rewrite_engine.check(&query.name).await {
+        } else if let Some(rewrite_result) = self
+            .rewrite_engine
+            .check_for_tenant(&query.name, tenant_id)
+            .instrument(tracing::debug_span!("rewrite_check"))
+            .await
+        {
             metadata.rewrite_applied = true;
             metadata.rewrite_rule_id = Some(rewrite_result.rule_id);
+            metadata.policy = Some(PolicyMatch::rewrite(rewrite_result.rule_id, &rewrite_result.action));
 
-            let response = self.apply_rewrite_action(query, &rewrite_result.action).await?;
+            let response = self.apply_rewrite_action(query, &rewrite_result.action, ctx).await?;
             metadata.response_time_ms = start.elapsed().as_millis() as u64;
 
             let action_desc = match &rewrite_result.action {
                 RewriteAction::Block => "BLOCKED".to_string(),
+                RewriteAction::NoData => "NODATA".to_string(),
+                RewriteAction::Passthru => "PASSTHRU".to_string(),
                 RewriteAction::MapToIp(ip) => format!("-> {}", ip),
                 RewriteAction::MapToDomain(domain) => format!("-> {}", domain),
             };
@@ -191,26 +504,53 @@ impl DnsResolver {
 
         // Step 2: Check local DNS records from database
         if let Some(ref db) = self.db {
-            if let Some(response) = self.check_local_records(db, query).await? {
-                metadata.response_time_ms = start.elapsed().as_millis() as u64;
-                let answers: Vec<String> = response.answers.iter().map(|a| a.value.clone()).collect();
-                debug!(
-                    "[DNS Result] {} {} | LocalRecord | {} | {}ms",
-                    query.name, query.record_type, answers.join(", "), metadata.response_time_ms
-                );
-                return Ok(ResolveResult { response, metadata });
+            let lookup = self
+                .check_local_records(db, query, tenant_id)
+                .instrument(tracing::debug_span!("local_records"))
+                .await;
+            match lookup {
+                Ok(Some(response)) => {
+                    metadata.response_time_ms = start.elapsed().as_millis() as u64;
+                    metadata.policy = Some(PolicyMatch::local_record());
+                    let answers: Vec<String> = response.answers.iter().map(|a| a.value.clone()).collect();
+                    debug!(
+                        "[DNS Result] {} {} | LocalRecord | {} | {}ms",
+                        query.name, query.record_type, answers.join(", "), metadata.response_time_ms
+                    );
+                    return Ok(ResolveResult { response, metadata });
+                }
+                Ok(None) => {}
+                Err(_) => {
+                    degraded = true;
+                    if let Some(result) = self.fail_closed(query, ctx.profile_id, start).await {
+                        return Ok(result);
+                    }
+                }
             }
         }
+        if degraded {
+            self.fail_policy.record_bypass();
+        }
 
-        // Step 3: Check cache
-        let cache_key = CacheKey::from_query(query);
-        if let Some(cached_response) = self.cache.get(&cache_key).await {
+        // Step 3: Check cache (kept apart per listener-pinned profile), unless
+        // the listener answers from local data only
+        let cache_key = CacheKey::from_query(query).for_profile(ctx.profile_id);
+        let cache_span = tracing::debug_span!("cache_lookup", hit = tracing::field::Empty);
+        let cached = match ctx.resolution_mode.uses_cache() {
+            true => self.cache.get(&cache_key).instrument(cache_span.clone()).await,
+            false => None,
+        };
+        cache_span.record("hit", cached.is_some());
+        if let Some(cached_response) = cached {
             metadata.cache_hit = true;
             metadata.response_time_ms = start.elapsed().as_millis() as u64;
 
+            self.companion.record_hit(&cache_key);
+
             // Update response ID to match query
             let mut response = cached_response;
             response.id = query.id;
+            self.shuffle.apply(&query.name, &mut response);
 
             let answers: Vec<String> = response.answers.iter().map(|a| a.value.clone()).collect();
             debug!(
@@ -223,8 +563,67 @@ impl DnsResolver {
 
         debug!("Cache miss for {} {}", query.name, query.record_type);
 
-        // Step 4: Query upstream via proxy
-        let query_result = self.proxy.query(query).await?;
+        // Step 4: Pre-upstream middleware
+        if let Some((hook, mut response)) = self.middleware.run_pre_upstream(ctx).await {
+            response.id = query.id;
+            metadata.policy = PolicyMatch::middleware(&hook, &response);
+            metadata.answered_by = Some(hook);
+            metadata.response_time_ms = start.elapsed().as_millis() as u64;
+            debug!(
+                "[DNS Result] {} {} | Middleware({}) {} | {}ms",
+                query.name,
+                query.record_type,
+                metadata.answered_by.as_deref().unwrap_or_default(),
+                response.response_code,
+                metadata.response_time_ms
+            );
+            return Ok(ResolveResult { response, metadata });
+        }
+
+        // Step 5: Private reverse zones are answered without forwarding,
+        // unless middleware picked an upstream for the query
+        if ctx.upstream.is_none() {
+            if let Some(response) = self.private_zones.answer(query) {
+                metadata.answered_by = Some(LOCAL_ZONE_ANSWERED_BY.to_string());
+                metadata.response_time_ms = start.elapsed().as_millis() as u64;
+                debug!(
+                    "[DNS Result] {} {} | LocalZone {} | {}ms",
+                    query.name, query.record_type, response.response_code, metadata.response_time_ms
+                );
+                return Ok(ResolveResult { response, metadata });
+            }
+        }
+
+        // Step 6: Listeners that do not forward, and offline mode, answer
+        // instead of forwarding
+        if !ctx.resolution_mode.forwards() {
+            let response = ctx.resolution_mode.refusal(query.id);
+            metadata.answered_by = Some(RESOLUTION_MODE_ANSWERED_BY.to_string());
+            metadata.response_time_ms = start.elapsed().as_millis() as u64;
+            debug!(
+                "[DNS Result] {} {} | ResolutionMode({}) {} | {}ms",
+                query.name, query.record_type, ctx.resolution_mode.as_str(), response.response_code, metadata.response_time_ms
+            );
+            return Ok(ResolveResult { response, metadata });
+        }
+        if self.offline.is_enabled() {
+            let response = self.offline.response(query.id);
+            metadata.answered_by = Some(OFFLINE_ANSWERED_BY.to_string());
+            metadata.response_time_ms = start.elapsed().as_millis() as u64;
+            debug!(
+                "[DNS Result] {} {} | Offline {} | {}ms",
+                query.name, query.record_type, response.response_code, metadata.response_time_ms
+            );
+            return Ok(ResolveResult { response, metadata });
+        }
+
+        // Step 7: Query upstream via proxy (or the upstream chosen by middleware)
+        let upstream_span = tracing::debug_span!("upstream", upstream = tracing::field::Empty);
+        let query_result = match ctx.upstream.as_deref() {
+            Some(upstream) => self.proxy.query_via(&ctx.query, upstream).instrument(upstream_span.clone()).await?,
+            None => self.proxy.query(&ctx.query).instrument(upstream_span.clone()).await?,
+        };
+        upstream_span.record("upstream", query_result.server_name.as_str());
         
         metadata.upstream_used = Some(query_result.server_name.clone());
         metadata.response_time_ms = start.elapsed().as_millis() as u64;
@@ -232,10 +631,12 @@ impl DnsResolver {
         // Restore original query ID in response (important for DoQ which uses ID=0)
         let mut response = query_result.response;
         response.id = query.id;
+        self.shuffle.apply(&query.name, &mut response);
 
-        // Step 5: Cache the response (only if successful)
+        // Step 8: Cache the response (answers and NODATA only)
+        self.cache.store(cache_key, response.clone()).await;
         if response.response_code == DnsResponseCode::NoError {
-            self.cache.set(cache_key, response.clone()).await;
+            self.prefetch_companion(ctx).await;
         }
 
         let answers: Vec<String> = response.answers.iter().map(|a| a.value.clone()).collect();
@@ -255,6 +656,96 @@ impl DnsResolver {
         })
     }
 
+    /// Resolve the A/AAAA companion of an upstream-resolved query in the
+    /// background, so the client's follow-up query hits the cache
+    ///
+    /// The companion passes the pre-rewrite middleware (disabled record
+    /// types, scripts, ...) and goes to the same upstream override.
+    async fn prefetch_companion(&self, ctx: &QueryContext) {
+        if !self.companion.is_enabled() {
+            return;
+        }
+        let Some(record_type) = CompanionPrefetch::companion_type(ctx.query.record_type) else {
+            return;
+        };
+
+        let mut companion_ctx = QueryContext {
+            query: DnsQuery {
+                record_type,
+                ..ctx.query.clone()
+            },
+            ..ctx.clone()
+        };
+        let key = CacheKey::from_query(&companion_ctx.query).for_profile(ctx.profile_id);
+        if self.cache.contains(&key).await {
+            self.companion.record_skip();
+            return;
+        }
+        if !self.companion.begin(&key) {
+            return;
+        }
+
+        let cache = self.cache.clone();
+        let proxy = self.proxy.clone();
+        let shuffle = self.shuffle.clone();
+        let middleware = self.middleware.clone();
+        let companion = self.companion.clone();
+        let span = tracing::debug_span!("companion_prefetch", qtype = %record_type);
+        tokio::spawn(
+            async move {
+                if middleware.run_pre_rewrite(&mut companion_ctx).await.is_some() {
+                    companion.record_failure(&key);
+                    return;
+                }
+                let result = match companion_ctx.upstream.as_deref() {
+                    Some(upstream) => proxy.query_via(&companion_ctx.query, upstream).await,
+                    None => proxy.query(&companion_ctx.query).await,
+                };
+                match result {
+                    Ok(result) if result.response.response_code == DnsResponseCode::NoError => {
+                        let mut response = result.response;
+                        shuffle.apply(&companion_ctx.query.name, &mut response);
+                        cache.store(key, response).await;
+                    }
+                    Ok(result) => {
+                        debug!(
+                            "Companion prefetch of {} {} returned {}",
+                            companion_ctx.query.name, record_type, result.response.response_code
+                        );
+                        companion.record_failure(&key);
+                    }
+                    Err(e) => {
+                        debug!("Companion prefetch of {} {} failed: {}", companion_ctx.query.name, record_type, e);
+                        companion.record_failure(&key);
+                    }
+                }
+            }
+            .instrument(span),
+        );
+    }
+
+    /// SERVFAIL answer for a query whose resolution ran out of time
+    fn deadline_exceeded(&self, ctx: &QueryContext, budget: Duration) -> ResolveResult {
+        self.deadline.record_timeout();
+        debug!(
+            "[DNS Result] {} {} | Deadline exceeded | {}ms",
+            ctx.query.name,
+            ctx.query.record_type,
+            budget.as_millis()
+        );
+        ResolveResult {
+            response: DnsResponse::servfail(ctx.query.id).with_extended_error(ExtendedError::new(
+                ExtendedError::NO_REACHABLE_AUTHORITY,
+                format!("resolution timed out after {}ms", budget.as_millis()),
+            )),
+            metadata: QueryMetadata {
+                answered_by: Some(DEADLINE_ANSWERED_BY.to_string()),
+                response_time_ms: budget.as_millis() as u64,
+                ..Default::default()
+            },
+        }
+    }
+
     /// Check if domain name is valid according to DNS standards
     /// 
     /// Valid domain names must:
@@ -263,7 +754,7 @@ impl DnsResolver {
     /// - Not contain special characters or Unicode (browsers convert IDN to Punycode)
     /// - Be between 1-253 characters total
     /// - Have labels (parts between dots) of 1-63 characters each
-    fn is_valid_domain(name: &str) -> bool {
+    pub(crate) fn is_valid_domain(name: &str) -> bool {
         // Check length
         if name.is_empty() || name.len() > 253 {
             return false;
@@ -320,53 +811,122 @@ impl DnsResolver {
         true
     }
 
-    /// Check if a record type is disabled in settings
-    async fn is_record_type_disabled(&self, db: &Database, record_type: &str) -> bool {
-        match db.system_config().get("disabled_record_types").await {
-            Ok(Some(value)) => {
-                if let Ok(disabled_types) = serde_json::from_str::<Vec<String>>(&value) {
-                    let upper = record_type.to_uppercase();
-                    return disabled_types.iter().any(|t| t.to_uppercase() == upper);
-                }
-                false
-            }
-            _ => false,
-        }
-    }
-
     /// Resolve a DNS query with client IP for logging
     ///
     /// This method wraps resolve() and saves the query log to database.
     pub async fn resolve_with_client(&self, query: &DnsQuery, client_ip: &str) -> Result<ResolveResult> {
-        let result = self.resolve(query).await;
+        self.resolve_from_listener(query, client_ip, None).await
+    }
+
+    /// Resolve a DNS query received on a specific listener
+    ///
+    /// The client IP and listener protocol select the tenant view; the
+    /// query log is tagged with the selected tenant.
+    pub async fn resolve_from_listener(
+        &self,
+        query: &DnsQuery,
+        client_ip: &str,
+        listener: Option<&str>,
+    ) -> Result<ResolveResult> {
+        let tenant_id = self.tenants.select(client_ip, listener).await;
+        let resolution_mode = self.listener_modes.mode(listener);
+        let trace_id = new_trace_id();
+        let ctx = QueryContext {
+            query: query.clone(),
+            client_ip: Some(client_ip.to_string()),
+            listener: listener.map(str::to_string),
+            tenant_id,
+            profile_id: None,
+            upstream: None,
+            trace_id: trace_id.clone(),
+            resolution_mode,
+        };
+        let result = self.resolve_with_context(ctx).await;
+        if let Ok(ref r) = result {
+            self.policy_stats.record(r, tenant_id);
+            if let Some(reason) = r.metadata.block_reason() {
+                self.client_acl.record(reason);
+            }
+        }
+        self.domain_stats.record(&query.name, &result);
+        self.capture.record(client_ip, listener, query, &trace_id, &result);
         
         // Save query log to database (fire and forget)
         if let Some(ref db) = self.db {
+            // Skip queries left out by the sampling mode
+            let Some(sample_rate) = self.log_sampling.sample(&result) else {
+                return result;
+            };
+            // While the database is degraded, logging is paused apart from
+            // periodic probe writes
+            let health = db.health().clone();
+            if !health.allow_write() {
+                health.record_dropped();
+                return result;
+            }
             let log = match &result {
                 Ok(r) => CreateQueryLog {
                     client_ip: client_ip.to_string(),
-                    query_name: query.name.clone(),
+                    query_name: normalize_name(&query.name),
                     query_type: query.record_type.to_string(),
                     response_code: Some(r.response.response_code.to_string()),
                     response_time: Some(r.metadata.response_time_ms as i32),
                     cache_hit: r.metadata.cache_hit,
                     upstream_used: r.metadata.upstream_used.clone(),
+                    tenant_id,
+                    category: r.metadata.category.clone(),
+                    answered_by: r.metadata.answered_by.clone(),
+                    trace_id: Some(trace_id.clone()),
+                    sample_rate: sample_rate as i64,
+                    protocol: listener.map(str::to_string),
+                    extended_error: r.response.extended_error.as_ref().map(ToString::to_string),
+                    source: None,
+                    block_reason: r.metadata.block_reason().map(|b| b.as_str().to_string()),
+                    created_at: None,
                 },
                 Err(e) => CreateQueryLog {
                     client_ip: client_ip.to_string(),
-                    query_name: query.name.clone(),
+                    query_name: normalize_name(&query.name),
                     query_type: query.record_type.to_string(),
                     response_code: Some(format!("ERROR: {}", e)),
                     response_time: None,
                     cache_hit: false,
                     upstream_used: None,
+                    tenant_id,
+                    category: None,
+                    answered_by: None,
+                    trace_id: Some(trace_id.clone()),
+                    sample_rate: sample_rate as i64,
+                    protocol: listener.map(str::to_string),
+                    extended_error: Some(ExtendedError::for_failure(e).to_string()),
+                    source: None,
+                    block_reason: None,
+                    created_at: None,
                 },
             };
             
             let db = db.clone();
             tokio::spawn(async move {
-                if let Err(e) = db.query_logs().create(log).await {
-                    tracing::warn!("Failed to save query log: {}", e);
+                match db.query_logs().create(log).await {
+                    Ok(_) => {
+                        if health.record_success() {
+                            tracing::info!(
+                                "Database writes succeeded again, query logging resumed ({} entries dropped so far)",
+                                health.status().dropped_query_logs
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        health.record_dropped();
+                        if health.record_failure(&e.to_string()) {
+                            tracing::error!(
+                                "Database writes keep failing ({}), pausing query logging until they succeed again",
+                                e
+                            );
+                        } else if !health.is_degraded() {
+                            tracing::warn!(%trace_id, "Failed to save query log: {}", e);
+                        }
+                    }
                 }
             });
         }
@@ -374,13 +934,63 @@ impl DnsResolver {
         result
     }
 
-    /// Check local DNS records from database
-    async fn check_local_records(&self, db: &Database, query: &DnsQuery) -> Result<Option<DnsResponse>> {
+    /// Answer while policy data is unavailable: None when failing open,
+    /// otherwise the cached answer or REFUSED
+    async fn fail_closed(&self, query: &DnsQuery, profile_id: Option<i64>, start: Instant) -> Option<ResolveResult> {
+        if self.fail_policy.mode() != FailMode::Closed {
+            return None;
+        }
+
+        let mut metadata = QueryMetadata::default();
+        let cache_key = CacheKey::from_query(query).for_profile(profile_id);
+        let response = match self.cache.get(&cache_key).await {
+            Some(mut response) => {
+                response.id = query.id;
+                metadata.cache_hit = true;
+                self.fail_policy.record_served_from_cache();
+                response
+            }
+            None => {
+                metadata.answered_by = Some(FAIL_CLOSED_ANSWERED_BY.to_string());
+                self.fail_policy.record_refused();
+                DnsResponse::refused(query.id)
+                    .with_extended_error(ExtendedError::new(ExtendedError::OTHER, "policy data unavailable"))
+            }
+        };
+        metadata.response_time_ms = start.elapsed().as_millis() as u64;
+        debug!(
+            "[DNS Result] {} {} | FailClosed {} | {}ms",
+            query.name, query.record_type, response.response_code, metadata.response_time_ms
+        );
+        Some(ResolveResult { response, metadata })
+    }
+
+    /// Check local DNS records, from the in-memory index when loaded
+    async fn check_local_records(
+        &self,
+        db: &Database,
+        query: &DnsQuery,
+        tenant_id: Option<i64>,
+    ) -> Result<Option<DnsResponse>> {
         use std::net::{Ipv4Addr, Ipv6Addr};
         use std::str::FromStr;
 
         let record_type_str = query.record_type.to_string();
-        let records = db.dns_records().get_by_name_and_type_with_wildcard(&query.name, &record_type_str).await?;
+        let records = match self.local_records.lookup(&query.name, &record_type_str, tenant_id) {
+            Some(records) => records,
+            None => match db
+                .dns_records()
+                .get_by_name_and_type_for_tenant(&query.name, &record_type_str, tenant_id)
+                .await
+            {
+                Ok(records) => records,
+                Err(e) => {
+                    self.fail_policy.record_lookup_failure(&e.to_string());
+                    return Err(e);
+                }
+            },
+        };
+        self.fail_policy.record_lookup_success();
         if records.is_empty() {
             return Ok(None);
         }
@@ -391,18 +1001,19 @@ impl DnsResolver {
             if !record.enabled {
                 continue;
             }
+            // Clients must not cache a temporary record past its expiration
+            let ttl = record.expires_at.map_or(record.ttl, |t| {
+                record.ttl.min((t - chrono::Utc::now()).num_seconds().clamp(0, i32::MAX as i64) as i32)
+            }) as u32;
 
-            // For wildcard records, use the queried name instead of the record name
-            let response_name = if record.name.starts_with("*.") {
-                &query.name
-            } else {
-                &record.name
-            };
+            // Answer with the queried name: wildcard records synthesize it, and
+            // explicit names match regardless of case or a trailing dot
+            let response_name = &query.name;
 
             let dns_record = match query.record_type {
                 RecordType::A => {
                     if let Ok(ip) = Ipv4Addr::from_str(&record.value) {
-                        Some(DnsRecordData::a(response_name, ip, record.ttl as u32))
+                        Some(DnsRecordData::a(response_name, ip, ttl))
                     } else {
                         debug!("Invalid IPv4 address in DNS record: {}", record.value);
                         None
@@ -410,26 +1021,26 @@ impl DnsResolver {
                 }
                 RecordType::AAAA => {
                     if let Ok(ip) = Ipv6Addr::from_str(&record.value) {
-                        Some(DnsRecordData::aaaa(response_name, ip, record.ttl as u32))
+                        Some(DnsRecordData::aaaa(response_name, ip, ttl))
                     } else {
                         debug!("Invalid IPv6 address in DNS record: {}", record.value);
                         None
                     }
                 }
                 RecordType::CNAME => {
-                    Some(DnsRecordData::cname(response_name, &record.value, record.ttl as u32))
+                    Some(DnsRecordData::cname(response_name, &record.value, ttl))
                 }
                 RecordType::MX => {
-                    Some(DnsRecordData::mx(response_name, &record.value, record.priority as u16, record.ttl as u32))
+                    Some(DnsRecordData::mx(response_name, &record.value, record.priority as u16, ttl))
                 }
                 RecordType::TXT => {
-                    Some(DnsRecordData::txt(response_name, &record.value, record.ttl as u32))
+                    Some(DnsRecordData::txt(response_name, &record.value, ttl))
                 }
                 RecordType::PTR => {
-                    Some(DnsRecordData::ptr(response_name, &record.value, record.ttl as u32))
+                    Some(DnsRecordData::ptr(response_name, &record.value, ttl))
                 }
                 RecordType::NS => {
-                    Some(DnsRecordData::ns(response_name, &record.value, record.ttl as u32))
+                    Some(DnsRecordData::ns(response_name, &record.value, ttl))
                 }
                 _ => None,
             };
@@ -461,6 +1072,7 @@ impl DnsResolver {
         &self,
         query: &DnsQuery,
         action: &RewriteAction,
+        ctx: &QueryContext,
     ) -> Result<DnsResponse> {
         match action {
             RewriteAction::MapToIp(ip) => {
@@ -469,7 +1081,7 @@ impl DnsResolver {
             RewriteAction::MapToDomain(target_domain) => {
                 // Resolve the target domain
                 let target_query = DnsQuery::new(target_domain, query.record_type);
-                let result = self.resolve_without_rewrite(&target_query).await?;
+                let result = self.resolve_without_rewrite(&target_query, ctx).await?;
                 
                 // Return response with original query ID
                 let mut response = result.response;
@@ -479,6 +1091,14 @@ impl DnsResolver {
             RewriteAction::Block => {
                 Ok(DnsResponse::nxdomain(query.id))
             }
+            RewriteAction::NoData => {
+                Ok(DnsResponse::new(query.id))
+            }
+            RewriteAction::Passthru => {
+                let mut response = self.resolve_without_rewrite(query, ctx).await?.response;
+                response.id = query.id;
+                Ok(response)
+            }
         }
     }
 
@@ -521,9 +1141,9 @@ impl DnsResolver {
 
     /// Resolve without checking rewrite rules (to avoid infinite loops)
     /// This is kept for backward compatibility but now delegates to resolve_with_depth
-    async fn resolve_without_rewrite(&self, query: &DnsQuery) -> Result<ResolveResult> {
+    async fn resolve_without_rewrite(&self, query: &DnsQuery, ctx: &QueryContext) -> Result<ResolveResult> {
         // Start with depth 1 since we're already in a rewrite
-        self.resolve_with_depth(query, 1).await
+        self.resolve_with_depth(query, 1, ctx).await
     }
 
     /// Resolve with depth tracking to prevent infinite loops
@@ -532,6 +1152,7 @@ impl DnsResolver {
         &'a self,
         query: &'a DnsQuery,
         depth: u32,
+        ctx: &'a QueryContext,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ResolveResult>> + Send + 'a>> {
         Box::pin(async move {
             const MAX_DEPTH: u32 = 10;
@@ -543,14 +1164,21 @@ impl DnsResolver {
 
             let start = Instant::now();
             let mut metadata = QueryMetadata::default();
+            let tenant_id = ctx.tenant_id;
 
             debug!(
                 "Resolving DNS query (depth {}): {} {} (ID: {})",
                 depth, query.name, query.record_type, query.id
             );
 
-            // Step 1: Check rewrite rules (allow chaining)
-            if let Some(rewrite_result) = self.rewrite_engine.check(&query.name).await {
+            // Step 1: Check rewrite rules (allow chaining), unless they could not be loaded
+            let mut degraded = false;
+            if self.rewrite_engine.rules_unavailable() {
+                degraded = true;
+                if let Some(result) = self.fail_closed(query, ctx.profile_id, start).await {
+                    return Ok(result);
+                }
+            } else if let Some(rewrite_result) = self.rewrite_engine.check_for_tenant(&query.name, tenant_id).await {
                 debug!(
                     "Rewrite rule {} matched for {} (depth {})",
                     rewrite_result.rule_id, query.name, depth
@@ -558,7 +1186,7 @@ impl DnsResolver {
                 metadata.rewrite_applied = true;
                 metadata.rewrite_rule_id = Some(rewrite_result.rule_id);
 
-                let response = self.apply_rewrite_action_with_depth(query, &rewrite_result.action, depth).await?;
+                let response = self.apply_rewrite_action_with_depth(query, &rewrite_result.action, depth, ctx).await?;
                 metadata.response_time_ms = start.elapsed().as_millis() as u64;
 
                 return Ok(ResolveResult { response, metadata });
@@ -566,27 +1194,76 @@ impl DnsResolver {
 
             // Step 2: Check local DNS records from database
             if let Some(ref db) = self.db {
-                if let Some(response) = self.check_local_records(db, query).await? {
-                    debug!("Local DNS record found for {} {} (depth {})", query.name, query.record_type, depth);
-                    metadata.response_time_ms = start.elapsed().as_millis() as u64;
-                    return Ok(ResolveResult { response, metadata });
+                match self.check_local_records(db, query, tenant_id).await {
+                    Ok(Some(response)) => {
+                        debug!("Local DNS record found for {} {} (depth {})", query.name, query.record_type, depth);
+                        metadata.response_time_ms = start.elapsed().as_millis() as u64;
+                        return Ok(ResolveResult { response, metadata });
+                    }
+                    Ok(None) => {}
+                    Err(_) => {
+                        degraded = true;
+                        if let Some(result) = self.fail_closed(query, ctx.profile_id, start).await {
+                            return Ok(result);
+                        }
+                    }
                 }
             }
+            if degraded {
+                self.fail_policy.record_bypass();
+            }
 
             // Step 3: Check cache
-            let cache_key = CacheKey::from_query(query);
-            if let Some(cached_response) = self.cache.get(&cache_key).await {
+            let cache_key = CacheKey::from_query(query).for_profile(ctx.profile_id);
+            let cached = match ctx.resolution_mode.uses_cache() {
+                true => self.cache.get(&cache_key).await,
+                false => None,
+            };
+            if let Some(cached_response) = cached {
                 metadata.cache_hit = true;
                 metadata.response_time_ms = start.elapsed().as_millis() as u64;
 
                 let mut response = cached_response;
                 response.id = query.id;
+                self.shuffle.apply(&query.name, &mut response);
 
                 return Ok(ResolveResult { response, metadata });
             }
 
-            // Step 4: Query upstream
-            let query_result = self.proxy.query(query).await?;
+            // Step 4: Pre-upstream middleware, with the rewrite target as the query
+            let mut hook_ctx = QueryContext {
+                query: query.clone(),
+                ..ctx.clone()
+            };
+            if let Some((hook, mut response)) = self.middleware.run_pre_upstream(&mut hook_ctx).await {
+                response.id = query.id;
+                metadata.answered_by = Some(hook);
+                metadata.response_time_ms = start.elapsed().as_millis() as u64;
+                return Ok(ResolveResult { response, metadata });
+            }
+
+            if !ctx.resolution_mode.forwards() {
+                metadata.answered_by = Some(RESOLUTION_MODE_ANSWERED_BY.to_string());
+                metadata.response_time_ms = start.elapsed().as_millis() as u64;
+                return Ok(ResolveResult {
+                    response: ctx.resolution_mode.refusal(query.id),
+                    metadata,
+                });
+            }
+            if self.offline.is_enabled() {
+                metadata.answered_by = Some(OFFLINE_ANSWERED_BY.to_string());
+                metadata.response_time_ms = start.elapsed().as_millis() as u64;
+                return Ok(ResolveResult {
+                    response: self.offline.response(query.id),
+                    metadata,
+                });
+            }
+
+            // Step 5: Query upstream
+            let query_result = match hook_ctx.upstream.as_deref() {
+                Some(upstream) => self.proxy.query_via(&hook_ctx.query, upstream).await?,
+                None => self.proxy.query(&hook_ctx.query).await?,
+            };
             
             metadata.upstream_used = Some(query_result.server_name);
             metadata.response_time_ms = start.elapsed().as_millis() as u64;
@@ -594,11 +1271,10 @@ impl DnsResolver {
             // Restore original query ID in response (important for DoQ which uses ID=0)
             let mut response = query_result.response;
             response.id = query.id;
+            self.shuffle.apply(&query.name, &mut response);
 
             // Cache the response
-            if response.response_code == DnsResponseCode::NoError {
-                self.cache.set(cache_key, response.clone()).await;
-            }
+            self.cache.store(cache_key, response.clone()).await;
 
             Ok(ResolveResult {
                 response,
@@ -613,6 +1289,7 @@ impl DnsResolver {
         query: &'a DnsQuery,
         action: &'a RewriteAction,
         depth: u32,
+        ctx: &'a QueryContext,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<DnsResponse>> + Send + 'a>> {
         Box::pin(async move {
             match action {
@@ -622,7 +1299,7 @@ impl DnsResolver {
                 RewriteAction::MapToDomain(target_domain) => {
                     // Resolve the target domain with increased depth
                     let target_query = DnsQuery::new(target_domain, query.record_type);
-                    let result = self.resolve_with_depth(&target_query, depth + 1).await?;
+                    let result = self.resolve_with_depth(&target_query, depth + 1, ctx).await?;
                     
                     // Return response with original query ID
                     let mut response = result.response;
@@ -632,6 +1309,14 @@ impl DnsResolver {
                 RewriteAction::Block => {
                     Ok(DnsResponse::nxdomain(query.id))
                 }
+                RewriteAction::NoData => {
+                    Ok(DnsResponse::new(query.id))
+                }
+                RewriteAction::Passthru => {
+                    let mut response = self.resolve_with_depth(query, depth + 1, ctx).await?.response;
+                    response.id = query.id;
+                    Ok(response)
+                }
             }
         })
     }
@@ -642,7 +1327,7 @@ impl DnsResolver {
 mod tests {
     use super::*;
     use crate::dns::cache::CacheConfig;
-    use crate::dns::proxy::{UpstreamManager, UpstreamProtocol, UpstreamServer};
+    use crate::dns::proxy::UpstreamManager;
     use crate::dns::rewrite::{MatchType, RewriteRule};
     use std::net::Ipv4Addr;
 
@@ -651,6 +1336,7 @@ mod tests {
         let cache = Arc::new(CacheManager::with_config(CacheConfig {
             default_ttl: 60,
             max_entries: 1000,
+            max_memory_mb: 0,
         }));
         let upstream_manager = Arc::new(UpstreamManager::new());
         let proxy = Arc::new(ProxyManager::new(upstream_manager));
@@ -743,6 +1429,61 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_resolver_offline_mode() {
+        use crate::dns::offline::{OfflineResponse, OfflineSettings};
+
+        let resolver = create_test_resolver();
+        resolver.offline().set_settings(OfflineSettings {
+            enabled: true,
+            response: OfflineResponse::Refused,
+        });
+
+        // Cached answers are still served
+        let mut response = DnsResponse::new(1);
+        response.add_answer(DnsRecordData::a("cached.com", Ipv4Addr::new(1, 2, 3, 4), 300));
+        resolver.cache.set(CacheKey::new("cached.com", RecordType::A), response).await;
+        let result = resolver.resolve(&DnsQuery::new("cached.com", RecordType::A)).await.unwrap();
+        assert!(result.metadata.cache_hit);
+
+        // Everything else is refused without touching upstreams
+        let query = DnsQuery::new("example.com", RecordType::A);
+        let result = resolver.resolve(&query).await.unwrap();
+        assert_eq!(result.response.response_code, DnsResponseCode::Refused);
+        assert_eq!(result.response.id, query.id);
+        assert_eq!(result.metadata.answered_by.as_deref(), Some(OFFLINE_ANSWERED_BY));
+
+        assert!(resolver.preload(&query, Duration::from_secs(60)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolver_blocked_client() {
+        use crate::dns::client_acl::ClientAclSettings;
+
+        let resolver = create_test_resolver();
+        resolver.client_acl().set_settings(ClientAclSettings {
+            clients: vec!["198.51.100.0/24".to_string()],
+            ..Default::default()
+        });
+
+        let query = DnsQuery::new("example.com", RecordType::A);
+        let result = resolver.resolve_from_listener(&query, "198.51.100.7", Some("udp")).await.unwrap();
+        assert_eq!(result.response.response_code, DnsResponseCode::Refused);
+        assert_eq!(result.response.id, query.id);
+        assert_eq!(result.metadata.answered_by.as_deref(), Some(CLIENT_ACL_ANSWERED_BY));
+        assert_eq!(result.metadata.block_reason(), Some(BlockReason::Client));
+        assert!(result.metadata.policy.is_none());
+        assert_eq!(resolver.client_acl().stats().clients_blocked, 1);
+
+        // The check is a middleware, so callers building their own context are covered too
+        let mut ctx = QueryContext::new(query.clone(), None);
+        ctx.client_ip = Some("198.51.100.8".to_string());
+        let result = resolver.resolve_with_context(ctx).await.unwrap();
+        assert_eq!(result.response.response_code, DnsResponseCode::Refused);
+        assert_eq!(result.metadata.answered_by.as_deref(), Some(CLIENT_ACL_ANSWERED_BY));
+        assert_eq!(resolver.middleware().names()[0], CLIENT_ACL_ANSWERED_BY);
+    }
+
     #[tokio::test]
     async fn test_create_ip_response_a_record() {
         let resolver = create_test_resolver();
@@ -9,15 +9,42 @@
 //! - Map to IP address
 //! - Map to another domain
 //! - Block (return NXDOMAIN)
+//! - No data (return an empty NOERROR answer)
+//! - Passthru (exempt the name from the rules after it)
+//!
+//! Rules in shadow state are evaluated like any other rule but never change
+//! an answer; each query they would have answered is counted so the rule can
+//! be observed before it is promoted.
+//!
+//! Rules past their expiration time are skipped as if disabled.
+//!
+//! Exact and wildcard patterns are normalized like query names (lowercase,
+//! no trailing dot, punycode); regular expressions are matched against the
+//! normalized name.
+//!
+//! Each evaluation is timed and counted (see [`RewriteMetrics`]).
+//!
+//! A failed load is remembered until the next successful one; while it
+//! lasts the resolver applies the fail policy (see [`super::FailPolicy`]).
 
+use std::collections::HashMap;
 use std::net::IpAddr;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Utc};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
-use crate::db::{Database, RewriteRule as DbRewriteRule};
+use crate::db::{is_expired, Database, RewriteRule as DbRewriteRule};
+
+use super::fail_policy::PolicyDataFailure;
+use super::name::normalize_name;
+use super::rewrite_metrics::{
+    RewriteEvalStats, RewriteMetrics, CONFIG_KEY_REWRITE_SLOW_EVAL_US, DEFAULT_REWRITE_SLOW_EVAL_US,
+};
 
 /// Match type for rewrite rules
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -63,6 +90,10 @@ pub enum RewriteAction {
     MapToDomain(String),
     /// Block the request (return NXDOMAIN)
     Block,
+    /// Answer with no records (NOERROR, NODATA)
+    NoData,
+    /// Resolve normally, skipping lower-priority rules
+    Passthru,
 }
 
 #[allow(dead_code)]
@@ -78,6 +109,8 @@ impl RewriteAction {
                 Some(RewriteAction::MapToDomain(action_value?.to_string()))
             }
             "block" => Some(RewriteAction::Block),
+            "nodata" => Some(RewriteAction::NoData),
+            "passthru" => Some(RewriteAction::Passthru),
             _ => None,
         }
     }
@@ -88,6 +121,8 @@ impl RewriteAction {
             RewriteAction::MapToIp(_) => "map_ip",
             RewriteAction::MapToDomain(_) => "map_domain",
             RewriteAction::Block => "block",
+            RewriteAction::NoData => "nodata",
+            RewriteAction::Passthru => "passthru",
         }
     }
 
@@ -96,7 +131,7 @@ impl RewriteAction {
         match self {
             RewriteAction::MapToIp(ip) => Some(ip.to_string()),
             RewriteAction::MapToDomain(domain) => Some(domain.clone()),
-            RewriteAction::Block => None,
+            RewriteAction::Block | RewriteAction::NoData | RewriteAction::Passthru => None,
         }
     }
 }
@@ -117,6 +152,12 @@ pub struct RewriteRule {
     pub enabled: bool,
     /// Priority (higher = checked first)
     pub priority: i32,
+    /// Owning tenant (None = applies to every view)
+    pub tenant_id: Option<i64>,
+    /// Shadow rules are only counted, never applied
+    pub shadow: bool,
+    /// After this time the rule is skipped
+    pub expires_at: Option<DateTime<Utc>>,
     /// Compiled regex (for regex match type)
     compiled_regex: Option<Regex>,
 }
@@ -136,6 +177,7 @@ impl RewriteRule {
         } else {
             None
         };
+        let pattern = normalize_pattern(pattern, match_type);
 
         Self {
             id,
@@ -144,6 +186,9 @@ impl RewriteRule {
             action,
             enabled: true,
             priority,
+            tenant_id: None,
+            shadow: false,
+            expires_at: None,
             compiled_regex,
         }
     }
@@ -164,11 +209,14 @@ impl RewriteRule {
 
         Some(Self {
             id: db_rule.id,
-            pattern: db_rule.pattern.clone(),
+            pattern: normalize_pattern(db_rule.pattern.clone(), match_type),
             match_type,
             action,
             enabled: db_rule.enabled,
             priority: db_rule.priority,
+            tenant_id: db_rule.tenant_id,
+            shadow: db_rule.shadow,
+            expires_at: db_rule.expires_at,
             compiled_regex,
         })
     }
@@ -179,13 +227,12 @@ impl RewriteRule {
             return false;
         }
 
-        let domain_lower = domain.to_lowercase();
-        let pattern_lower = self.pattern.to_lowercase();
+        let domain = normalize_name(domain);
 
         match self.match_type {
-            MatchType::Exact => domain_lower == pattern_lower,
-            MatchType::Wildcard => self.wildcard_matches(&domain_lower, &pattern_lower),
-            MatchType::Regex => self.regex_matches(&domain_lower),
+            MatchType::Exact => domain == self.pattern,
+            MatchType::Wildcard => self.wildcard_matches(&domain, &self.pattern),
+            MatchType::Regex => self.regex_matches(&domain),
         }
     }
 
@@ -228,6 +275,14 @@ impl RewriteRule {
     }
 }
 
+/// Normalize exact and wildcard patterns; regular expressions are kept as written
+pub fn normalize_pattern(pattern: String, match_type: MatchType) -> String {
+    match match_type {
+        MatchType::Regex => pattern,
+        MatchType::Exact | MatchType::Wildcard => normalize_name(&pattern),
+    }
+}
+
 /// Result of a rewrite operation
 #[derive(Debug, Clone)]
 pub struct RewriteResult {
@@ -246,6 +301,14 @@ pub struct RewriteEngine {
     rules: RwLock<Vec<RewriteRule>>,
     /// Database connection for persistence
     db: Option<Arc<Database>>,
+    /// Shadow rule hits not yet written to the database
+    shadow_hits: Mutex<HashMap<i64, (u64, DateTime<Utc>)>>,
+    /// Evaluation time and rule counts
+    metrics: RewriteMetrics,
+    /// Fast check for `load_failure`
+    load_failed: AtomicBool,
+    /// Last failed load, cleared by the next successful one
+    load_failure: Mutex<Option<PolicyDataFailure>>,
 }
 
 #[allow(dead_code)]
@@ -255,6 +318,10 @@ impl RewriteEngine {
         Self {
             rules: RwLock::new(Vec::new()),
             db: None,
+            shadow_hits: Mutex::new(HashMap::new()),
+            metrics: RewriteMetrics::new(),
+            load_failed: AtomicBool::new(false),
+            load_failure: Mutex::new(None),
         }
     }
 
@@ -263,6 +330,10 @@ impl RewriteEngine {
         Self {
             rules: RwLock::new(Vec::new()),
             db: Some(db),
+            shadow_hits: Mutex::new(HashMap::new()),
+            metrics: RewriteMetrics::new(),
+            load_failed: AtomicBool::new(false),
+            load_failure: Mutex::new(None),
         }
     }
 
@@ -272,9 +343,18 @@ impl RewriteEngine {
     }
 
     /// Load rules from database
+    ///
+    /// A failure is remembered (see [`Self::load_failure`]) and the
+    /// previously loaded rules are kept but no longer applied.
     pub async fn load_rules(&self) -> anyhow::Result<()> {
         if let Some(ref db) = self.db {
-            let db_rules = db.rewrite_rules().list().await?;
+            let db_rules = match db.rewrite_rules().list().await {
+                Ok(rules) => rules,
+                Err(e) => {
+                    self.record_load_failure(&e.to_string());
+                    return Err(e);
+                }
+            };
             let mut rules: Vec<RewriteRule> = db_rules
                 .iter()
                 .filter_map(|r| RewriteRule::from_db(r))
@@ -285,21 +365,94 @@ impl RewriteEngine {
             
             let mut current_rules = self.rules.write().await;
             *current_rules = rules;
+            drop(current_rules);
+            if self.load_failed.swap(false, Ordering::Relaxed) {
+                self.load_failure.lock().unwrap().take();
+                tracing::info!("Rewrite rules loaded again");
+            }
         }
         Ok(())
     }
 
+    /// Note that the rules could not be loaded
+    pub fn record_load_failure(&self, error: &str) {
+        self.load_failed.store(true, Ordering::Relaxed);
+        if PolicyDataFailure::record(&self.load_failure, error) {
+            tracing::warn!("Rewrite rules could not be loaded ({}); applying the fail policy", error);
+        }
+    }
+
+    /// Whether the last load failed, so the rules must not be applied
+    pub fn rules_unavailable(&self) -> bool {
+        self.load_failed.load(Ordering::Relaxed)
+    }
+
+    /// The ongoing load failure, if any
+    pub fn load_failure(&self) -> Option<PolicyDataFailure> {
+        self.load_failure.lock().unwrap().clone()
+    }
+
     /// Reload rules from database
     pub async fn reload_rules(&self) -> anyhow::Result<()> {
         self.load_rules().await
     }
 
-    /// Check if a domain matches any rewrite rule
+    /// Check if a domain matches any global rewrite rule
     pub async fn check(&self, domain: &str) -> Option<RewriteResult> {
+        self.check_for_tenant(domain, None).await
+    }
+
+    /// Check if a domain matches any rewrite rule visible to a tenant
+    ///
+    /// Global rules apply to every tenant; tenant-owned rules only apply
+    /// to their own tenant. Matching shadow rules ahead of the first active
+    /// match are counted and skipped. When the first active match is a
+    /// passthru rule, no rewrite applies.
+    pub async fn check_for_tenant(&self, domain: &str, tenant_id: Option<i64>) -> Option<RewriteResult> {
         let rules = self.rules.read().await;
-        
+
+        let started = Instant::now();
+        let mut evaluated = 0;
+        let mut regex_timings = Vec::new();
+        let result = self.evaluate(&rules, domain, tenant_id, &mut evaluated, &mut regex_timings);
+        self.metrics.record(domain, started.elapsed(), evaluated, &regex_timings);
+        result
+    }
+
+    /// Walk the rules, counting the rules tested and timing regex matches
+    fn evaluate(
+        &self,
+        rules: &[RewriteRule],
+        domain: &str,
+        tenant_id: Option<i64>,
+        evaluated: &mut u64,
+        regex_timings: &mut Vec<(i64, Duration)>,
+    ) -> Option<RewriteResult> {
+        let now = Utc::now();
         for rule in rules.iter() {
-            if rule.matches(domain) {
+            if rule.tenant_id.is_some() && rule.tenant_id != tenant_id {
+                continue;
+            }
+            if is_expired(rule.expires_at, now) {
+                continue;
+            }
+            *evaluated += 1;
+            let matched = if rule.match_type == MatchType::Regex && rule.enabled {
+                let started = Instant::now();
+                let matched = rule.matches(domain);
+                regex_timings.push((rule.id, started.elapsed()));
+                matched
+            } else {
+                rule.matches(domain)
+            };
+            if matched {
+                if rule.shadow {
+                    self.record_shadow_hit(rule.id);
+                    continue;
+                }
+                if rule.action == RewriteAction::Passthru {
+                    return None;
+                }
                 return Some(RewriteResult {
                     rule_id: rule.id,
                     action: rule.action.clone(),
@@ -310,6 +463,54 @@ impl RewriteEngine {
         None
     }
 
+    fn record_shadow_hit(&self, id: i64) {
+        let mut hits = self.shadow_hits.lock().unwrap();
+        let entry = hits.entry(id).or_insert((0, Utc::now()));
+        entry.0 += 1;
+        entry.1 = Utc::now();
+    }
+
+    /// Shadow rule hits not yet written to the database, by rule ID
+    pub fn pending_shadow_hits(&self) -> HashMap<i64, u64> {
+        self.shadow_hits
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, (count, _))| (*id, *count))
+            .collect()
+    }
+
+    /// Write pending shadow rule hits to the database
+    ///
+    /// Hits are kept in memory if the write fails and retried next time.
+    pub async fn flush_shadow_hits(&self) -> anyhow::Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let pending: Vec<(i64, u64, DateTime<Utc>)> = self
+            .shadow_hits
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(id, (count, last_hit))| (id, count, last_hit))
+            .collect();
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        if let Err(e) = db.rewrite_rules().add_shadow_hits(&pending).await {
+            let mut hits = self.shadow_hits.lock().unwrap();
+            for (id, count, last_hit) in pending {
+                let entry = hits.entry(id).or_insert((0, last_hit));
+                entry.0 += count;
+                entry.1 = entry.1.max(last_hit);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
     /// Add a rule (in-memory only, use database for persistence)
     pub async fn add_rule(&self, rule: RewriteRule) {
         let mut rules = self.rules.write().await;
@@ -338,6 +539,47 @@ impl RewriteEngine {
     pub async fn rule_count(&self) -> usize {
         self.rules.read().await.len()
     }
+
+    /// Evaluation counters
+    pub fn metrics(&self) -> &RewriteMetrics {
+        &self.metrics
+    }
+
+    /// Evaluation metrics, with the patterns of the slowest regex rules
+    pub async fn eval_stats(&self) -> RewriteEvalStats {
+        let rules = self.rules.read().await;
+        let patterns: HashMap<i64, String> = rules
+            .iter()
+            .filter(|r| r.match_type == MatchType::Regex)
+            .map(|r| (r.id, r.pattern.clone()))
+            .collect();
+        self.metrics.snapshot(rules.len(), &patterns)
+    }
+
+    /// Load the slow evaluation threshold from database
+    pub async fn load_slow_threshold(&self) -> anyhow::Result<()> {
+        if let Some(ref db) = self.db {
+            let threshold_us = db
+                .system_config()
+                .get(CONFIG_KEY_REWRITE_SLOW_EVAL_US)
+                .await?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_REWRITE_SLOW_EVAL_US);
+            self.metrics.set_slow_threshold_us(threshold_us);
+        }
+        Ok(())
+    }
+
+    /// Persist and apply the slow evaluation threshold (0 = never warn)
+    pub async fn save_slow_threshold_us(&self, threshold_us: u64) -> anyhow::Result<()> {
+        if let Some(ref db) = self.db {
+            db.system_config()
+                .set(CONFIG_KEY_REWRITE_SLOW_EVAL_US, &threshold_us.to_string())
+                .await?;
+        }
+        self.metrics.set_slow_threshold_us(threshold_us);
+        Ok(())
+    }
 }
 
 impl Default for RewriteEngine {
@@ -407,6 +649,21 @@ mod tests {
         assert!(!rule.matches("example.org"));
     }
 
+    #[test]
+    fn test_idn_match() {
+        let rule = RewriteRule::new(
+            1,
+            "*.München.de.".to_string(),
+            MatchType::Wildcard,
+            RewriteAction::Block,
+            0,
+        );
+        assert_eq!(rule.pattern, "*.xn--mnchen-3ya.de");
+        assert!(rule.matches("www.xn--mnchen-3ya.de"));
+        assert!(rule.matches("WWW.XN--MNCHEN-3YA.DE."));
+        assert!(!rule.matches("www.munchen.de"));
+    }
+
     #[test]
     fn test_regex_match() {
         let rule = RewriteRule::new(
@@ -512,6 +769,156 @@ mod tests {
         assert_eq!(result.unwrap().rule_id, 2);
     }
 
+    #[tokio::test]
+    async fn test_rewrite_engine_passthru() {
+        let engine = RewriteEngine::new();
+        engine.add_rule(RewriteRule::new(
+            1,
+            "*.example.com".to_string(),
+            MatchType::Wildcard,
+            RewriteAction::Block,
+            0,
+        )).await;
+        engine.add_rule(RewriteRule::new(
+            2,
+            "good.example.com".to_string(),
+            MatchType::Exact,
+            RewriteAction::Passthru,
+            1,
+        )).await;
+
+        assert!(engine.check("good.example.com").await.is_none());
+        assert_eq!(engine.check("bad.example.com").await.unwrap().rule_id, 1);
+        assert_eq!(RewriteAction::from_parts("passthru", None), Some(RewriteAction::Passthru));
+        assert_eq!(RewriteAction::from_parts("NODATA", None), Some(RewriteAction::NoData));
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_engine_tenant_rules() {
+        let engine = RewriteEngine::new();
+
+        engine.add_rule(RewriteRule::new(
+            1,
+            "*.example.com".to_string(),
+            MatchType::Wildcard,
+            RewriteAction::Block,
+            1,
+        )).await;
+
+        let mut tenant_rule = RewriteRule::new(
+            2,
+            "app.example.com".to_string(),
+            MatchType::Exact,
+            RewriteAction::MapToDomain("tenant.internal".to_string()),
+            10,
+        );
+        tenant_rule.tenant_id = Some(7);
+        engine.add_rule(tenant_rule).await;
+
+        // Owning tenant sees its own rule first
+        let result = engine.check_for_tenant("app.example.com", Some(7)).await;
+        assert_eq!(result.unwrap().rule_id, 2);
+
+        // Other tenants and untenanted clients only see global rules
+        let result = engine.check_for_tenant("app.example.com", Some(8)).await;
+        assert_eq!(result.unwrap().rule_id, 1);
+        let result = engine.check("app.example.com").await;
+        assert_eq!(result.unwrap().rule_id, 1);
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_engine_eval_stats() {
+        let engine = RewriteEngine::new();
+        engine.metrics().set_slow_threshold_us(0);
+
+        engine.add_rule(RewriteRule::new(
+            1,
+            r"^ads\.".to_string(),
+            MatchType::Regex,
+            RewriteAction::Block,
+            10,
+        )).await;
+        engine.add_rule(RewriteRule::new(
+            2,
+            "app.example.com".to_string(),
+            MatchType::Exact,
+            RewriteAction::NoData,
+            1,
+        )).await;
+
+        assert_eq!(engine.check("ads.example.com").await.unwrap().rule_id, 1);
+        assert!(engine.check("www.example.com").await.is_none());
+
+        let stats = engine.eval_stats().await;
+        assert_eq!(stats.rules, 2);
+        assert_eq!(stats.evaluations, 2);
+        assert_eq!(stats.rules_evaluated, 3);
+        assert_eq!(stats.max_rules_evaluated, 2);
+        assert_eq!(stats.histogram.iter().map(|b| b.count).sum::<u64>(), 2);
+        assert_eq!(stats.slowest_regex_rules.len(), 1);
+        assert_eq!(stats.slowest_regex_rules[0].rule_id, 1);
+        assert_eq!(stats.slowest_regex_rules[0].evaluations, 2);
+        assert_eq!(stats.slowest_regex_rules[0].pattern.as_deref(), Some(r"^ads\."));
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_engine_shadow_rules() {
+        let engine = RewriteEngine::new();
+
+        let mut shadow = RewriteRule::new(
+            1,
+            "*.example.com".to_string(),
+            MatchType::Wildcard,
+            RewriteAction::Block,
+            10,
+        );
+        shadow.shadow = true;
+        engine.add_rule(shadow).await;
+
+        engine.add_rule(RewriteRule::new(
+            2,
+            "app.example.com".to_string(),
+            MatchType::Exact,
+            RewriteAction::MapToDomain("app.internal".to_string()),
+            5,
+        )).await;
+
+        // The shadow rule never answers, the active rule below it still does
+        assert!(engine.check("www.example.com").await.is_none());
+        assert_eq!(engine.check("app.example.com").await.unwrap().rule_id, 2);
+        assert!(engine.check("example.org").await.is_none());
+
+        assert_eq!(engine.pending_shadow_hits().get(&1), Some(&2));
+        assert!(!engine.pending_shadow_hits().contains_key(&2));
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_engine_skips_expired_rules() {
+        let engine = RewriteEngine::new();
+
+        let mut expired = RewriteRule::new(
+            1,
+            "app.example.com".to_string(),
+            MatchType::Exact,
+            RewriteAction::Block,
+            10,
+        );
+        expired.expires_at = Some(Utc::now() - chrono::Duration::seconds(1));
+        engine.add_rule(expired).await;
+
+        let mut temporary = RewriteRule::new(
+            2,
+            "app.example.com".to_string(),
+            MatchType::Exact,
+            RewriteAction::MapToDomain("app-new.internal".to_string()),
+            5,
+        );
+        temporary.expires_at = Some(Utc::now() + chrono::Duration::hours(1));
+        engine.add_rule(temporary).await;
+
+        assert_eq!(engine.check("app.example.com").await.unwrap().rule_id, 2);
+    }
+
     #[tokio::test]
     async fn test_rewrite_engine_remove_rule() {
         let engine = RewriteEngine::new();
@@ -0,0 +1,298 @@
+//! Rewrite engine evaluation metrics
+//!
+//! Every client query walks the rewrite rules until one matches, so the
+//! cost of a lookup grows with the rule count and with expensive regular
+//! expressions. The engine records how long each evaluation took (as a
+//! histogram), how many rules it tested and how long every regex rule took
+//! to match, and warns when an evaluation exceeds the configured threshold.
+//! Counters live in memory only and are served at `/api/status/rewrite`.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// Config key for the slow evaluation threshold in microseconds
+pub const CONFIG_KEY_REWRITE_SLOW_EVAL_US: &str = "rewrite_slow_eval_us";
+
+/// Default slow evaluation threshold
+pub const DEFAULT_REWRITE_SLOW_EVAL_US: u64 = 5000;
+
+/// Upper bounds of the histogram buckets; a last bucket takes the rest
+const BUCKET_BOUNDS_US: [u64; 7] = [10, 50, 100, 500, 1_000, 5_000, 10_000];
+
+/// Regex rules reported by [`RewriteMetrics::snapshot`]
+const SLOWEST_REGEX_RULES: usize = 10;
+
+/// Shortest interval between two slow evaluation warnings
+const SLOW_WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// One histogram bucket
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalHistogramBucket {
+    /// Inclusive upper bound in microseconds, None for the overflow bucket
+    pub le_us: Option<u64>,
+    pub count: u64,
+}
+
+/// Match time of one regex rule
+#[derive(Debug, Clone, Serialize)]
+pub struct RegexRuleTiming {
+    pub rule_id: i64,
+    /// Rule pattern, None once the rule is deleted
+    pub pattern: Option<String>,
+    pub evaluations: u64,
+    pub avg_us: f64,
+    pub max_us: u64,
+}
+
+/// Rewrite evaluation metrics
+#[derive(Debug, Clone, Serialize)]
+pub struct RewriteEvalStats {
+    /// Rules currently loaded
+    pub rules: usize,
+    pub evaluations: u64,
+    pub avg_us: f64,
+    pub max_us: u64,
+    pub histogram: Vec<EvalHistogramBucket>,
+    /// Rules tested across all evaluations
+    pub rules_evaluated: u64,
+    pub avg_rules_evaluated: f64,
+    pub max_rules_evaluated: u64,
+    /// Warning threshold in microseconds (0 = never warn)
+    pub slow_threshold_us: u64,
+    /// Evaluations at or above the threshold
+    pub slow_evaluations: u64,
+    /// Regex rules by descending average match time
+    pub slowest_regex_rules: Vec<RegexRuleTiming>,
+}
+
+#[derive(Default)]
+struct RegexTiming {
+    evaluations: u64,
+    total_ns: u64,
+    max_ns: u64,
+}
+
+#[derive(Default)]
+struct SlowWarning {
+    last: Option<Instant>,
+    suppressed: u64,
+}
+
+/// Evaluation counters kept by the rewrite engine
+pub struct RewriteMetrics {
+    evaluations: AtomicU64,
+    total_us: AtomicU64,
+    max_us: AtomicU64,
+    buckets: [AtomicU64; BUCKET_BOUNDS_US.len() + 1],
+    rules_evaluated: AtomicU64,
+    max_rules_evaluated: AtomicU64,
+    slow_threshold_us: AtomicU64,
+    slow_evaluations: AtomicU64,
+    regex: Mutex<HashMap<i64, RegexTiming>>,
+    slow_warning: Mutex<SlowWarning>,
+}
+
+#[allow(dead_code)]
+impl RewriteMetrics {
+    pub fn new() -> Self {
+        Self {
+            evaluations: AtomicU64::new(0),
+            total_us: AtomicU64::new(0),
+            max_us: AtomicU64::new(0),
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            rules_evaluated: AtomicU64::new(0),
+            max_rules_evaluated: AtomicU64::new(0),
+            slow_threshold_us: AtomicU64::new(DEFAULT_REWRITE_SLOW_EVAL_US),
+            slow_evaluations: AtomicU64::new(0),
+            regex: Mutex::new(HashMap::new()),
+            slow_warning: Mutex::new(SlowWarning::default()),
+        }
+    }
+
+    pub fn slow_threshold_us(&self) -> u64 {
+        self.slow_threshold_us.load(Ordering::Relaxed)
+    }
+
+    pub fn set_slow_threshold_us(&self, threshold_us: u64) {
+        self.slow_threshold_us.store(threshold_us, Ordering::Relaxed);
+    }
+
+    /// Record one evaluation of the rules for `domain`
+    ///
+    /// `regex_timings` holds the match time of every regex rule tested.
+    pub fn record(
+        &self,
+        domain: &str,
+        elapsed: Duration,
+        rules_evaluated: u64,
+        regex_timings: &[(i64, Duration)],
+    ) {
+        let us = elapsed.as_micros() as u64;
+        self.evaluations.fetch_add(1, Ordering::Relaxed);
+        self.total_us.fetch_add(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+        self.buckets[bucket_index(us)].fetch_add(1, Ordering::Relaxed);
+        self.rules_evaluated.fetch_add(rules_evaluated, Ordering::Relaxed);
+        self.max_rules_evaluated.fetch_max(rules_evaluated, Ordering::Relaxed);
+
+        if !regex_timings.is_empty() {
+            let mut regex = self.regex.lock().unwrap();
+            for (rule_id, took) in regex_timings {
+                let ns = took.as_nanos() as u64;
+                let timing = regex.entry(*rule_id).or_default();
+                timing.evaluations += 1;
+                timing.total_ns += ns;
+                timing.max_ns = timing.max_ns.max(ns);
+            }
+        }
+
+        let threshold = self.slow_threshold_us();
+        if threshold > 0 && us >= threshold {
+            self.slow_evaluations.fetch_add(1, Ordering::Relaxed);
+            self.warn_slow(domain, us, rules_evaluated, threshold);
+        }
+    }
+
+    /// Log a slow evaluation, at most once per [`SLOW_WARNING_INTERVAL`]
+    fn warn_slow(&self, domain: &str, us: u64, rules_evaluated: u64, threshold: u64) {
+        let mut warning = self.slow_warning.lock().unwrap();
+        if warning.last.is_some_and(|t| t.elapsed() < SLOW_WARNING_INTERVAL) {
+            warning.suppressed += 1;
+            return;
+        }
+        let suppressed = std::mem::take(&mut warning.suppressed);
+        warning.last = Some(Instant::now());
+        drop(warning);
+
+        tracing::warn!(
+            "Rewrite evaluation for {} took {}us over {} rules (threshold {}us, {} more slow evaluations since the last warning)",
+            domain,
+            us,
+            rules_evaluated,
+            threshold,
+            suppressed
+        );
+    }
+
+    /// Current counters; `patterns` maps loaded rule IDs to their pattern
+    pub fn snapshot(&self, rules: usize, patterns: &HashMap<i64, String>) -> RewriteEvalStats {
+        let evaluations = self.evaluations.load(Ordering::Relaxed);
+        let rules_evaluated = self.rules_evaluated.load(Ordering::Relaxed);
+        let per_evaluation = |total: u64| {
+            if evaluations == 0 {
+                0.0
+            } else {
+                total as f64 / evaluations as f64
+            }
+        };
+
+        let histogram = self
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(i, count)| EvalHistogramBucket {
+                le_us: BUCKET_BOUNDS_US.get(i).copied(),
+                count: count.load(Ordering::Relaxed),
+            })
+            .collect();
+
+        let mut slowest_regex_rules: Vec<RegexRuleTiming> = self
+            .regex
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(rule_id, timing)| RegexRuleTiming {
+                rule_id: *rule_id,
+                pattern: patterns.get(rule_id).cloned(),
+                evaluations: timing.evaluations,
+                avg_us: timing.total_ns as f64 / timing.evaluations.max(1) as f64 / 1000.0,
+                max_us: timing.max_ns / 1000,
+            })
+            .collect();
+        slowest_regex_rules.sort_by(|a, b| b.avg_us.total_cmp(&a.avg_us));
+        slowest_regex_rules.truncate(SLOWEST_REGEX_RULES);
+
+        RewriteEvalStats {
+            rules,
+            evaluations,
+            avg_us: per_evaluation(self.total_us.load(Ordering::Relaxed)),
+            max_us: self.max_us.load(Ordering::Relaxed),
+            histogram,
+            rules_evaluated,
+            avg_rules_evaluated: per_evaluation(rules_evaluated),
+            max_rules_evaluated: self.max_rules_evaluated.load(Ordering::Relaxed),
+            slow_threshold_us: self.slow_threshold_us(),
+            slow_evaluations: self.slow_evaluations.load(Ordering::Relaxed),
+            slowest_regex_rules,
+        }
+    }
+}
+
+impl Default for RewriteMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn bucket_index(us: u64) -> usize {
+    BUCKET_BOUNDS_US
+        .iter()
+        .position(|bound| us <= *bound)
+        .unwrap_or(BUCKET_BOUNDS_US.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets() {
+        let metrics = RewriteMetrics::new();
+        metrics.set_slow_threshold_us(0);
+        for us in [3, 10, 11, 700, 50_000] {
+            metrics.record("example.com", Duration::from_micros(us), 2, &[]);
+        }
+
+        let stats = metrics.snapshot(2, &HashMap::new());
+        let counts: Vec<u64> = stats.histogram.iter().map(|b| b.count).collect();
+        assert_eq!(counts, vec![2, 1, 0, 0, 1, 0, 0, 1]);
+        assert_eq!(stats.histogram.last().unwrap().le_us, None);
+        assert_eq!(stats.evaluations, 5);
+        assert_eq!(stats.max_us, 50_000);
+        assert_eq!(stats.rules_evaluated, 10);
+        assert_eq!(stats.avg_rules_evaluated, 2.0);
+        assert_eq!(stats.slow_evaluations, 0);
+    }
+
+    #[test]
+    fn test_slow_evaluations_and_regex_rules() {
+        let metrics = RewriteMetrics::new();
+        metrics.set_slow_threshold_us(100);
+        metrics.record(
+            "a.example.com",
+            Duration::from_micros(150),
+            3,
+            &[(1, Duration::from_micros(20)), (2, Duration::from_micros(120))],
+        );
+        metrics.record("b.example.com", Duration::from_micros(40), 3, &[(1, Duration::from_micros(40))]);
+
+        let patterns = HashMap::from([(2, "^ads\\.".to_string())]);
+        let stats = metrics.snapshot(3, &patterns);
+        assert_eq!(stats.slow_evaluations, 1);
+        assert_eq!(stats.max_rules_evaluated, 3);
+
+        let slowest = &stats.slowest_regex_rules;
+        assert_eq!(slowest.len(), 2);
+        assert_eq!(slowest[0].rule_id, 2);
+        assert_eq!(slowest[0].pattern.as_deref(), Some("^ads\\."));
+        assert_eq!(slowest[0].max_us, 120);
+        assert_eq!(slowest[1].rule_id, 1);
+        assert_eq!(slowest[1].pattern, None);
+        assert_eq!(slowest[1].evaluations, 2);
+        assert_eq!(slowest[1].avg_us, 30.0);
+    }
+}
@@ -0,0 +1,604 @@
+//! Response Policy Zone import
+//!
+//! Security feeds are often published as RPZ zone files. Their QNAME
+//! policies are converted into rewrite rules:
+//!
+//! | RPZ record                     | Rewrite action |
+//! |--------------------------------|----------------|
+//! | `CNAME .`                      | `block`        |
+//! | `CNAME *.`                     | `nodata`       |
+//! | `CNAME rpz-passthru.`          | `passthru`     |
+//! | `CNAME rpz-drop.`              | `block`        |
+//! | `A` / `AAAA` (Local-Data)      | `map_ip`       |
+//! | `CNAME target.` (Local-Data)   | `map_domain`   |
+//!
+//! `*.name` owners become wildcard rules. Exact names are emitted before
+//! wildcards, and longer wildcards before shorter ones, so the most
+//! specific policy wins as it does in RPZ. IP, NSDNAME, NSIP and client IP
+//! triggers, other Local-Data types and all but the first address of an
+//! owner have no rewrite equivalent and are counted as skipped.
+//!
+//! Feeds are zone files downloaded from a URL. Each refresh compares the
+//! SOA serial with the one last imported and only replaces the feed's rules
+//! when it changed; the rules carry the `rpz` tag and the feed's own tag.
+
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::db::{CreateRewriteRule, Database, RpzFeed, Tags};
+
+use super::cache::CacheManager;
+use super::name::normalize_name;
+use super::resolver::DnsResolver;
+use super::rewrite::{MatchType, RewriteAction, RewriteEngine};
+
+/// Tag on every rewrite rule imported from RPZ
+pub const RPZ_TAG: &str = "rpz";
+
+/// How often feeds are checked for a due refresh
+pub const RPZ_FEED_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Download timeout of a feed
+const RPZ_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Trigger labels of policies other than QNAME
+const NON_QNAME_TRIGGERS: [&str; 4] = ["rpz-ip", "rpz-nsdname", "rpz-nsip", "rpz-client-ip"];
+
+/// One QNAME policy
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpzRule {
+    /// Trigger name without the zone origin, `*.` prefixed for wildcards
+    pub name: String,
+    pub action: RewriteAction,
+}
+
+impl RpzRule {
+    pub fn match_type(&self) -> MatchType {
+        if self.name.starts_with("*.") {
+            MatchType::Wildcard
+        } else {
+            MatchType::Exact
+        }
+    }
+}
+
+/// A parsed RPZ zone
+#[derive(Debug, Clone, Default)]
+pub struct RpzZone {
+    pub origin: Option<String>,
+    /// SOA serial
+    pub serial: Option<u32>,
+    /// Policies, most specific first
+    pub rules: Vec<RpzRule>,
+    /// Records without a rewrite equivalent
+    pub skipped: usize,
+}
+
+impl RpzZone {
+    /// Parse zone file text
+    pub fn parse(text: &str) -> Self {
+        let mut zone = RpzZone::default();
+        let mut owner: Option<String> = None;
+        let mut seen = HashSet::new();
+
+        for (blank_owner, tokens) in records(text) {
+            if tokens[0].starts_with('$') {
+                if tokens[0].eq_ignore_ascii_case("$ORIGIN") {
+                    zone.origin = tokens.get(1).map(|o| normalize_name(o));
+                }
+                continue;
+            }
+
+            let mut rest = tokens.as_slice();
+            if !blank_owner {
+                owner = Some(rest[0].clone());
+                rest = &rest[1..];
+            }
+            while rest.first().is_some_and(|t| is_ttl(t) || is_class(t)) {
+                rest = &rest[1..];
+            }
+            let (Some(owner), Some((record_type, rdata))) = (owner.as_deref(), rest.split_first())
+            else {
+                zone.skipped += 1;
+                continue;
+            };
+
+            let record_type = record_type.to_ascii_uppercase();
+            match record_type.as_str() {
+                "SOA" => {
+                    if zone.origin.is_none() && owner.ends_with('.') {
+                        zone.origin = Some(normalize_name(owner));
+                    }
+                    zone.serial = rdata.get(2).and_then(|s| s.parse().ok());
+                    continue;
+                }
+                "NS" => continue,
+                _ => {}
+            }
+
+            let action = match (record_type.as_str(), rdata.first()) {
+                ("CNAME", Some(target)) => cname_action(target, zone.origin.as_deref()),
+                ("A" | "AAAA", Some(address)) => address.parse::<IpAddr>().ok().map(RewriteAction::MapToIp),
+                _ => None,
+            };
+            let (Some(name), Some(action)) = (zone.trigger_name(owner), action) else {
+                zone.skipped += 1;
+                continue;
+            };
+            if !seen.insert(name.clone()) {
+                zone.skipped += 1;
+                continue;
+            }
+            zone.rules.push(RpzRule { name, action });
+        }
+
+        // Exact names first, then wildcards from the longest suffix down
+        zone.rules
+            .sort_by_key(|r| r.name.strip_prefix("*.").map(|base| Reverse(base.split('.').count())));
+        zone
+    }
+
+    /// QNAME trigger of an owner name, `None` for the apex, names outside
+    /// the zone and other trigger types
+    fn trigger_name(&self, owner: &str) -> Option<String> {
+        if owner == "@" {
+            return None;
+        }
+        let name = match (owner.strip_suffix('.'), self.origin.as_deref()) {
+            (Some(absolute), Some(origin)) => normalize_name(absolute)
+                .strip_suffix(origin)?
+                .strip_suffix('.')?
+                .to_string(),
+            (Some(absolute), None) => normalize_name(absolute),
+            (None, _) => normalize_name(owner),
+        };
+
+        let last_label = name.rsplit('.').next().unwrap_or_default();
+        if NON_QNAME_TRIGGERS.contains(&last_label) {
+            return None;
+        }
+        let base = name.strip_prefix("*.").unwrap_or(&name);
+        DnsResolver::is_valid_domain(base).then_some(name)
+    }
+
+    /// Rewrite rules for the policies
+    pub fn to_rewrite_rules(
+        &self,
+        priority: i32,
+        enabled: bool,
+        description: &str,
+        tags: &Tags,
+    ) -> Vec<CreateRewriteRule> {
+        self.rules
+            .iter()
+            .map(|rule| CreateRewriteRule {
+                pattern: rule.name.clone(),
+                match_type: rule.match_type().as_str().to_string(),
+                action_type: rule.action.action_type().to_string(),
+                action_value: rule.action.action_value(),
+                priority,
+                enabled,
+                description: Some(description.to_string()),
+                tenant_id: None,
+                shadow: false,
+                shadow_of: None,
+                tags: tags.clone(),
+                expires_at: None,
+            })
+            .collect()
+    }
+}
+
+/// Action of a CNAME policy
+fn cname_action(target: &str, origin: Option<&str>) -> Option<RewriteAction> {
+    let target = target.to_ascii_lowercase();
+    match target.as_str() {
+        // rpz-drop answers nothing at all; NXDOMAIN is the closest we have
+        "." | "rpz-drop." => return Some(RewriteAction::Block),
+        "*." => return Some(RewriteAction::NoData),
+        "rpz-passthru." => return Some(RewriteAction::Passthru),
+        _ => {}
+    }
+    // rpz-tcp-only and wildcard Local-Data (`CNAME *.target.`) have no
+    // rewrite equivalent
+    if target.starts_with("rpz-") || target.starts_with("*.") {
+        return None;
+    }
+
+    let domain = match (target.strip_suffix('.'), origin) {
+        (Some(absolute), _) => absolute.to_string(),
+        (None, Some(origin)) => format!("{}.{}", target, origin),
+        (None, None) => target.clone(),
+    };
+    DnsResolver::is_valid_domain(&domain).then(|| RewriteAction::MapToDomain(normalize_name(&domain)))
+}
+
+/// Logical records of a zone file: (owner left blank, tokens)
+///
+/// Comments are removed and parenthesised records joined into one.
+fn records(text: &str) -> Vec<(bool, Vec<String>)> {
+    let mut records = Vec::new();
+    let mut current: Option<(bool, Vec<String>)> = None;
+    let mut depth = 0usize;
+
+    for line in text.lines() {
+        let line = strip_comment(line);
+        if current.is_none() && line.trim().is_empty() {
+            continue;
+        }
+        let (_, tokens) = current.get_or_insert_with(|| (line.starts_with([' ', '\t']), Vec::new()));
+        for ch in line.chars() {
+            match ch {
+                '(' => depth += 1,
+                ')' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        tokens.extend(line.replace(['(', ')'], " ").split_whitespace().map(str::to_string));
+
+        if depth == 0 {
+            records.extend(current.take().filter(|(_, tokens)| !tokens.is_empty()));
+        }
+    }
+    records.extend(current.filter(|(_, tokens)| !tokens.is_empty()));
+    records
+}
+
+/// Line without its `;` comment
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, ch) in line.char_indices() {
+        match ch {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// TTL field such as `300` or `1h30m`
+fn is_ttl(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_ascii_digit())
+        && token.chars().all(|c| c.is_ascii_digit() || "smhdwSMHDW".contains(c))
+}
+
+fn is_class(token: &str) -> bool {
+    ["IN", "CH", "HS", "CS"].iter().any(|c| token.eq_ignore_ascii_case(c))
+}
+
+/// Tag of the rules imported from a feed
+pub fn rpz_feed_tag(feed_id: i64) -> String {
+    format!("rpz-feed:{}", feed_id)
+}
+
+/// Tag of the rules imported by hand under a name
+pub fn rpz_import_tag(name: &str) -> String {
+    format!("rpz-import:{}", name)
+}
+
+/// Outcome of a feed refresh or import
+#[derive(Debug, Clone, Serialize)]
+pub struct RpzRefreshResult {
+    /// Whether the rules were replaced
+    pub changed: bool,
+    pub serial: Option<u32>,
+    pub rule_count: usize,
+    pub skipped_count: usize,
+}
+
+/// Downloads RPZ feeds into rewrite rules
+pub struct RpzFeeds {
+    db: Arc<Database>,
+    rewrite_engine: Arc<RewriteEngine>,
+    cache: Arc<CacheManager>,
+    client: reqwest::Client,
+}
+
+impl RpzFeeds {
+    pub fn new(db: Arc<Database>, rewrite_engine: Arc<RewriteEngine>, cache: Arc<CacheManager>) -> Self {
+        Self {
+            db,
+            rewrite_engine,
+            cache,
+            client: reqwest::Client::builder()
+                .timeout(RPZ_DOWNLOAD_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Download a feed and replace its rules
+    ///
+    /// Unless forced, a zone whose serial matches the last import leaves the
+    /// rules untouched. Failures are recorded on the feed.
+    pub async fn refresh(&self, feed_id: i64, force: bool) -> Result<RpzRefreshResult> {
+        let feed = self
+            .db
+            .rpz_feeds()
+            .get_by_id(feed_id)
+            .await?
+            .ok_or_else(|| anyhow!("RPZ feed {} not found", feed_id))?;
+
+        match self.refresh_feed(&feed, force).await {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                self.db.rpz_feeds().record_error(feed.id, &e.to_string()).await?;
+                Err(e)
+            }
+        }
+    }
+
+    async fn refresh_feed(&self, feed: &RpzFeed, force: bool) -> Result<RpzRefreshResult> {
+        let response = self.client.get(&feed.url).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Download of {} returned {}", feed.url, response.status()));
+        }
+        let zone = RpzZone::parse(&response.text().await?);
+        if zone.serial.is_none() && zone.rules.is_empty() {
+            return Err(anyhow!("{} is not an RPZ zone file", feed.url));
+        }
+
+        let serial = zone.serial.map(i64::from);
+        if !force && serial.is_some() && serial == feed.serial {
+            self.db
+                .rpz_feeds()
+                .record_refresh(feed.id, serial, feed.rule_count, feed.skipped_count, false)
+                .await?;
+            return Ok(RpzRefreshResult {
+                changed: false,
+                serial: zone.serial,
+                rule_count: feed.rule_count as usize,
+                skipped_count: feed.skipped_count as usize,
+            });
+        }
+
+        let rules = zone.to_rewrite_rules(
+            feed.priority,
+            feed.enabled,
+            &format!("RPZ feed {}", feed.name),
+            &Tags(vec![RPZ_TAG.to_string(), rpz_feed_tag(feed.id)]),
+        );
+        let mut changed_names = self.tagged_names(&rpz_feed_tag(feed.id)).await?;
+        changed_names.extend(rules.iter().map(|r| r.pattern.clone()));
+        let rule_count = self
+            .db
+            .rewrite_rules()
+            .replace_by_tag(&rpz_feed_tag(feed.id), rules)
+            .await?;
+        self.db
+            .rpz_feeds()
+            .record_refresh(feed.id, serial, rule_count, zone.skipped as i64, true)
+            .await?;
+        self.rewrite_engine.reload_rules().await?;
+        self.cache.purge_names(changed_names).await;
+        info!(
+            "RPZ feed '{}' imported: serial {:?}, {} rules, {} records skipped",
+            feed.name, zone.serial, rule_count, zone.skipped
+        );
+
+        Ok(RpzRefreshResult {
+            changed: true,
+            serial: zone.serial,
+            rule_count: rule_count as usize,
+            skipped_count: zone.skipped,
+        })
+    }
+
+    /// Refresh enabled feeds whose refresh interval has passed
+    pub async fn refresh_due(&self) -> Result<()> {
+        let now = chrono::Utc::now();
+        for feed in self.db.rpz_feeds().list().await? {
+            let interval = chrono::Duration::seconds(feed.refresh_interval_secs);
+            let due = feed.enabled && feed.last_checked_at.is_none_or(|t| now - t >= interval);
+            if due {
+                if let Err(e) = self.refresh(feed.id, false).await {
+                    warn!("Failed to refresh RPZ feed '{}': {}", feed.name, e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Import a zone under a name, replacing an earlier import of the same name
+    pub async fn import(&self, name: &str, zone: &RpzZone, priority: i32) -> Result<RpzRefreshResult> {
+        let tag = rpz_import_tag(name);
+        let rules = zone.to_rewrite_rules(
+            priority,
+            true,
+            &format!("RPZ import {}", name),
+            &Tags(vec![RPZ_TAG.to_string(), tag.clone()]),
+        );
+        let mut changed_names = self.tagged_names(&tag).await?;
+        changed_names.extend(rules.iter().map(|r| r.pattern.clone()));
+        let rule_count = self.db.rewrite_rules().replace_by_tag(&tag, rules).await?;
+        self.rewrite_engine.reload_rules().await?;
+        self.cache.purge_names(changed_names).await;
+        info!("RPZ zone '{}' imported: {} rules, {} records skipped", name, rule_count, zone.skipped);
+
+        Ok(RpzRefreshResult {
+            changed: true,
+            serial: zone.serial,
+            rule_count: rule_count as usize,
+            skipped_count: zone.skipped,
+        })
+    }
+
+    /// Enable or disable the rules of a feed
+    pub async fn set_enabled(&self, feed_id: i64, enabled: bool) -> Result<()> {
+        let changed_names = self.tagged_names(&rpz_feed_tag(feed_id)).await?;
+        self.db
+            .rewrite_rules()
+            .set_enabled_by_tag(&rpz_feed_tag(feed_id), enabled, None)
+            .await?;
+        self.rewrite_engine.reload_rules().await?;
+        self.cache.purge_names(changed_names).await;
+        Ok(())
+    }
+
+    /// Delete the rules carrying a feed or import tag
+    pub async fn remove_rules(&self, tag: &str) -> Result<u64> {
+        let changed_names = self.tagged_names(tag).await?;
+        let deleted = self.db.rewrite_rules().delete_by_tag(tag, None).await?;
+        self.rewrite_engine.reload_rules().await?;
+        self.cache.purge_names(changed_names).await;
+        Ok(deleted)
+    }
+
+    /// Names answered by the rules carrying a tag, as cache purge patterns
+    ///
+    /// Regex and shadow rules are left out, as they never answer for a
+    /// fixed name.
+    async fn tagged_names(&self, tag: &str) -> Result<Vec<String>> {
+        Ok(self
+            .db
+            .rewrite_rules()
+            .list()
+            .await?
+            .into_iter()
+            .filter(|r| r.tags.contains(tag) && !r.shadow && !r.match_type.eq_ignore_ascii_case("regex"))
+            .map(|r| r.pattern)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    use crate::dns::cache::CacheKey;
+    use crate::dns::message::{DnsRecordData, DnsResponse, RecordType};
+
+    const ZONE: &str = r#"
+$TTL 300
+$ORIGIN rpz.example.net.
+@   IN  SOA ns.example.net. admin.example.net. (
+        2024061501 ; serial
+        3600 600 86400 60 )
+    IN  NS  ns.example.net.
+
+; QNAME policies
+malware.test            CNAME   .
+*.malware.test          CNAME   .
+tracker.test    60 IN   CNAME   *.
+ok.malware.test         CNAME   rpz-passthru.
+dropped.test            CNAME   rpz-drop.
+slow.test               CNAME   rpz-tcp-only.
+portal.test             A       192.0.2.10
+                        AAAA    2001:db8::10
+cdn.test                CNAME   safe.example.com.
+*.ads.malware.test      CNAME   .
+Relative.Test.rpz.example.net. CNAME .
+other.zone.             CNAME   .
+txt.test                TXT     "blocked; see policy"
+
+; other triggers
+32.10.2.0.192.rpz-ip    CNAME   .
+ns.evil.rpz-nsdname     CNAME   .
+"#;
+
+    #[test]
+    fn test_parse_rpz_zone() {
+        let zone = RpzZone::parse(ZONE);
+        assert_eq!(zone.origin.as_deref(), Some("rpz.example.net"));
+        assert_eq!(zone.serial, Some(2024061501));
+
+        let rule = |name: &str| zone.rules.iter().find(|r| r.name == name).map(|r| r.action.clone());
+        assert_eq!(rule("malware.test"), Some(RewriteAction::Block));
+        assert_eq!(rule("tracker.test"), Some(RewriteAction::NoData));
+        assert_eq!(rule("ok.malware.test"), Some(RewriteAction::Passthru));
+        assert_eq!(rule("dropped.test"), Some(RewriteAction::Block));
+        assert_eq!(
+            rule("portal.test"),
+            Some(RewriteAction::MapToIp(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 10))))
+        );
+        assert_eq!(rule("cdn.test"), Some(RewriteAction::MapToDomain("safe.example.com".to_string())));
+        assert_eq!(rule("relative.test"), Some(RewriteAction::Block));
+        assert_eq!(rule("slow.test"), None);
+        assert_eq!(rule("other.zone"), None);
+
+        // tcp-only, second address, out-of-zone name, TXT, IP and NSDNAME triggers
+        assert_eq!(zone.skipped, 6);
+        assert_eq!(zone.rules.len(), 9);
+    }
+
+    #[test]
+    fn test_rpz_rules_most_specific_first() {
+        let zone = RpzZone::parse(ZONE);
+        let names: Vec<&str> = zone.rules.iter().map(|r| r.name.as_str()).collect();
+        let position = |name: &str| names.iter().position(|n| *n == name).unwrap();
+
+        // The passthru exception must be checked before the wildcard block
+        assert!(position("ok.malware.test") < position("*.malware.test"));
+        assert!(position("*.ads.malware.test") < position("*.malware.test"));
+        assert_eq!(zone.rules[names.len() - 1].match_type(), MatchType::Wildcard);
+        assert_eq!(zone.rules[0].match_type(), MatchType::Exact);
+    }
+
+    #[test]
+    fn test_rpz_to_rewrite_rules() {
+        let zone = RpzZone::parse(ZONE);
+        let tags = Tags(vec![RPZ_TAG.to_string(), rpz_feed_tag(7)]);
+        let rules = zone.to_rewrite_rules(50, true, "RPZ feed test", &tags);
+        assert_eq!(rules.len(), zone.rules.len());
+
+        let portal = rules.iter().find(|r| r.pattern == "portal.test").unwrap();
+        assert_eq!(portal.match_type, "exact");
+        assert_eq!(portal.action_type, "map_ip");
+        assert_eq!(portal.action_value.as_deref(), Some("192.0.2.10"));
+        assert_eq!(portal.priority, 50);
+        assert!(portal.tags.contains("rpz-feed:7"));
+
+        let wildcard = rules.iter().find(|r| r.pattern == "*.malware.test").unwrap();
+        assert_eq!(wildcard.match_type, "wildcard");
+        assert_eq!(wildcard.action_type, "block");
+        assert_eq!(wildcard.action_value, None);
+    }
+
+    #[test]
+    fn test_rpz_without_origin_uses_soa_owner() {
+        let zone = RpzZone::parse(
+            "feed.rpz. 300 IN SOA ns. admin. 7 3600 600 86400 60\n\
+             bad.example.com.feed.rpz. 300 IN CNAME .\n",
+        );
+        assert_eq!(zone.origin.as_deref(), Some("feed.rpz"));
+        assert_eq!(zone.serial, Some(7));
+        assert_eq!(zone.rules.len(), 1);
+        assert_eq!(zone.rules[0].name, "bad.example.com");
+    }
+
+    #[tokio::test]
+    async fn test_rpz_import_purges_cached_answers() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_url = format!("sqlite:{}?mode=rwc", dir.path().join("test.db").display());
+        let db = Arc::new(Database::new(&db_url).await.unwrap());
+        let cache = Arc::new(CacheManager::new());
+        let feeds = RpzFeeds::new(db.clone(), Arc::new(RewriteEngine::with_db(db)), cache.clone());
+        let cached = |name: &str| {
+            let mut response = DnsResponse::new(1);
+            response.add_answer(DnsRecordData::a(name, Ipv4Addr::new(192, 0, 2, 1), 300));
+            (CacheKey::new(name, RecordType::A), response)
+        };
+
+        feeds.import("test", &RpzZone::parse(ZONE), 0).await.unwrap();
+        for name in ["malware.test", "fresh.test", "unrelated.test"] {
+            let (key, response) = cached(name);
+            cache.set(key, response).await;
+        }
+
+        // The replacement drops malware.test and adds fresh.test
+        let zone = RpzZone::parse("$ORIGIN rpz.example.net.\nfresh.test CNAME .\n");
+        feeds.import("test", &zone, 0).await.unwrap();
+        assert!(cache.get(&cached("malware.test").0).await.is_none());
+        assert!(cache.get(&cached("fresh.test").0).await.is_none());
+        assert!(cache.get(&cached("unrelated.test").0).await.is_some());
+    }
+}
@@ -0,0 +1,448 @@
+//! Response Rate Limiting (RRL)
+//!
+//! A public UDP listener can be used to reflect and amplify traffic at a
+//! spoofed source address. RRL caps how fast identical responses go to one
+//! client network: each (client prefix, response) pair gets a token bucket
+//! refilled at `responses_per_second`. Answers are keyed by name and type;
+//! NXDOMAIN and error responses to a network share one bucket each, so
+//! random-subdomain floods are limited too. Clients are grouped by /24
+//! (IPv4) and /56 (IPv6) prefixes.
+//!
+//! Responses over the limit are dropped, except every `slip`-th one, which
+//! is sent truncated (TC bit, no records). A real client retries over TCP;
+//! a spoofed victim receives only a small packet. `slip = 0` drops every
+//! limited response, `slip = 1` truncates every one.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use super::message::wire_question;
+
+/// Client IPv4 prefix sharing a bucket
+pub const RRL_IPV4_PREFIX_LEN: u32 = 24;
+/// Client IPv6 prefix sharing a bucket
+pub const RRL_IPV6_PREFIX_LEN: u32 = 56;
+/// Default truncated response rate among limited responses
+pub const DEFAULT_RRL_SLIP: u32 = 2;
+/// Upper bound of `responses_per_second`
+pub const MAX_RRL_RESPONSES_PER_SECOND: u32 = 10_000;
+/// Upper bound of `slip`
+pub const MAX_RRL_SLIP: u32 = 10;
+/// Buckets tracked at most; beyond this the least recently used is evicted
+const MAX_BUCKETS: usize = 100_000;
+
+/// Rate limit settings of a listener
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RrlConfig {
+    /// Identical responses per second to one client network, 0 = off
+    pub responses_per_second: u32,
+    /// Every n-th limited response is sent truncated instead of dropped
+    pub slip: u32,
+}
+
+impl Default for RrlConfig {
+    fn default() -> Self {
+        Self {
+            responses_per_second: 0,
+            slip: DEFAULT_RRL_SLIP,
+        }
+    }
+}
+
+impl RrlConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.responses_per_second > 0
+    }
+}
+
+/// What to do with a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RrlAction {
+    Send,
+    /// Send a truncated copy
+    Slip,
+    Drop,
+}
+
+/// Limiter state and counters
+#[derive(Debug, Clone, Serialize)]
+pub struct RrlStatus {
+    #[serde(flatten)]
+    pub config: RrlConfig,
+    /// Responses checked while limiting was on
+    pub responses: u64,
+    /// Limited responses sent truncated
+    pub slipped: u64,
+    /// Limited responses not sent
+    pub dropped: u64,
+    /// Buckets currently tracked
+    pub tracked: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ResponseClass {
+    Answer,
+    NxDomain,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct BucketKey {
+    network: IpAddr,
+    class: ResponseClass,
+    /// Lowercased wire name and type; empty for NXDOMAIN and errors
+    name: Vec<u8>,
+    qtype: u16,
+}
+
+impl BucketKey {
+    /// Key of an encoded response, None when it has no question
+    fn new(client: IpAddr, response: &[u8]) -> Option<Self> {
+        let (name, qtype) = wire_question(response)?;
+        let class = match response[3] & 0x0F {
+            0 => ResponseClass::Answer,
+            3 => ResponseClass::NxDomain,
+            _ => ResponseClass::Error,
+        };
+        let (name, qtype) = match class {
+            ResponseClass::Answer => (name.to_ascii_lowercase(), qtype),
+            _ => (Vec::new(), 0),
+        };
+        Some(Self {
+            network: client_network(client),
+            class,
+            name,
+            qtype,
+        })
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Responses limited so far, to pick the slipped ones
+    limited: u64,
+}
+
+struct BucketNode {
+    key: BucketKey,
+    bucket: Bucket,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+/// Buckets in least recently used order
+///
+/// Nodes live in a slab and are linked by index, so a lookup, moving a
+/// bucket to the back and evicting the front are all O(1). An evicted
+/// bucket belongs to the network idle the longest; it starts full when
+/// that network returns, as any new bucket does.
+struct BucketTable {
+    index: HashMap<BucketKey, usize>,
+    nodes: Vec<BucketNode>,
+    /// Least recently used
+    head: Option<usize>,
+    /// Most recently used
+    tail: Option<usize>,
+    capacity: usize,
+}
+
+impl BucketTable {
+    fn new(capacity: usize) -> Self {
+        Self {
+            index: HashMap::new(),
+            nodes: Vec::new(),
+            head: None,
+            tail: None,
+            capacity,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn clear(&mut self) {
+        self.index.clear();
+        self.nodes.clear();
+        self.head = None;
+        self.tail = None;
+    }
+
+    /// Bucket of `key` marked most recently used, created by `new` when
+    /// missing in place of the least recently used one once full
+    fn get_or_insert(&mut self, key: BucketKey, new: impl FnOnce() -> Bucket) -> &mut Bucket {
+        let i = match self.index.get(&key) {
+            Some(&i) => {
+                self.unlink(i);
+                i
+            }
+            None => match self.head.filter(|_| self.nodes.len() >= self.capacity) {
+                Some(i) => {
+                    self.unlink(i);
+                    let node = &mut self.nodes[i];
+                    let evicted = std::mem::replace(&mut node.key, key.clone());
+                    node.bucket = new();
+                    self.index.remove(&evicted);
+                    self.index.insert(key, i);
+                    i
+                }
+                None => {
+                    self.nodes.push(BucketNode {
+                        key: key.clone(),
+                        bucket: new(),
+                        prev: None,
+                        next: None,
+                    });
+                    self.index.insert(key, self.nodes.len() - 1);
+                    self.nodes.len() - 1
+                }
+            },
+        };
+        self.push_back(i);
+        &mut self.nodes[i].bucket
+    }
+
+    fn unlink(&mut self, i: usize) {
+        let (prev, next) = (self.nodes[i].prev, self.nodes[i].next);
+        match prev {
+            Some(p) => self.nodes[p].next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(n) => self.nodes[n].prev = prev,
+            None => self.tail = prev,
+        }
+    }
+
+    fn push_back(&mut self, i: usize) {
+        self.nodes[i].prev = self.tail;
+        self.nodes[i].next = None;
+        match self.tail {
+            Some(t) => self.nodes[t].next = Some(i),
+            None => self.head = Some(i),
+        }
+        self.tail = Some(i);
+    }
+}
+
+/// Token-bucket response rate limiter of one listener
+pub struct ResponseRateLimiter {
+    config: RwLock<RrlConfig>,
+    buckets: Mutex<BucketTable>,
+    responses: AtomicU64,
+    slipped: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl ResponseRateLimiter {
+    pub fn new(config: RrlConfig) -> Self {
+        Self {
+            config: RwLock::new(config),
+            buckets: Mutex::new(BucketTable::new(MAX_BUCKETS)),
+            responses: AtomicU64::new(0),
+            slipped: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Apply new settings; buckets start over when they change
+    pub fn configure(&self, config: RrlConfig) {
+        let mut current = self.config.write().unwrap();
+        if *current != config {
+            *current = config;
+            self.buckets.lock().unwrap().clear();
+        }
+    }
+
+    pub fn config(&self) -> RrlConfig {
+        *self.config.read().unwrap()
+    }
+
+    /// Decide whether an encoded response may go to `client`
+    pub fn check(&self, client: IpAddr, response: &[u8]) -> RrlAction {
+        self.check_at(client, response, Instant::now())
+    }
+
+    fn check_at(&self, client: IpAddr, response: &[u8], now: Instant) -> RrlAction {
+        let config = self.config();
+        if !config.is_enabled() {
+            return RrlAction::Send;
+        }
+        self.responses.fetch_add(1, Ordering::Relaxed);
+        let Some(key) = BucketKey::new(client, response) else {
+            return RrlAction::Send;
+        };
+        let rate = config.responses_per_second as f64;
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.get_or_insert(key, || Bucket {
+            tokens: rate,
+            updated: now,
+            limited: 0,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return RrlAction::Send;
+        }
+        bucket.limited += 1;
+        if config.slip > 0 && bucket.limited.is_multiple_of(config.slip as u64) {
+            self.slipped.fetch_add(1, Ordering::Relaxed);
+            RrlAction::Slip
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            RrlAction::Drop
+        }
+    }
+
+    pub fn status(&self) -> RrlStatus {
+        RrlStatus {
+            config: self.config(),
+            responses: self.responses.load(Ordering::Relaxed),
+            slipped: self.slipped.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            tracked: self.buckets.lock().unwrap().len(),
+        }
+    }
+}
+
+impl Default for ResponseRateLimiter {
+    fn default() -> Self {
+        Self::new(RrlConfig::default())
+    }
+}
+
+/// Network of a client address that shares its buckets
+fn client_network(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let mask = u32::MAX << (32 - RRL_IPV4_PREFIX_LEN);
+            IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+        }
+        IpAddr::V6(v6) => {
+            let mask = u128::MAX << (128 - RRL_IPV6_PREFIX_LEN);
+            IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::{DnsQuery, DnsResponse, RecordType};
+    use std::time::Duration;
+
+    fn response(name: &str, nxdomain: bool) -> Vec<u8> {
+        let query = DnsQuery::with_id(1, name, RecordType::A);
+        let response = if nxdomain { DnsResponse::nxdomain(1) } else { DnsResponse::new(1) };
+        response.to_bytes(&query).unwrap()
+    }
+
+    fn limiter(responses_per_second: u32, slip: u32) -> ResponseRateLimiter {
+        ResponseRateLimiter::new(RrlConfig { responses_per_second, slip })
+    }
+
+    #[test]
+    fn test_disabled_sends_everything() {
+        let rrl = ResponseRateLimiter::default();
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        for _ in 0..100 {
+            assert_eq!(rrl.check(client, &response("example.com", false)), RrlAction::Send);
+        }
+        assert_eq!(rrl.status().responses, 0);
+    }
+
+    #[test]
+    fn test_bucket_limits_and_refills() {
+        let rrl = limiter(2, 0);
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let answer = response("example.com", false);
+        let now = Instant::now();
+
+        assert_eq!(rrl.check_at(client, &answer, now), RrlAction::Send);
+        assert_eq!(rrl.check_at(client, &answer, now), RrlAction::Send);
+        assert_eq!(rrl.check_at(client, &answer, now), RrlAction::Drop);
+        // Another name, and another network, have their own buckets
+        assert_eq!(rrl.check_at(client, &response("example.org", false), now), RrlAction::Send);
+        assert_eq!(rrl.check_at("198.51.100.1".parse().unwrap(), &answer, now), RrlAction::Send);
+        // Half a second refills one token
+        assert_eq!(rrl.check_at(client, &answer, now + Duration::from_millis(500)), RrlAction::Send);
+
+        let status = rrl.status();
+        assert_eq!((status.responses, status.dropped, status.slipped), (6, 1, 0));
+    }
+
+    #[test]
+    fn test_slip_and_shared_nxdomain_bucket() {
+        let rrl = limiter(1, 2);
+        let client: IpAddr = "2001:db8:0:1::1".parse().unwrap();
+        let neighbour: IpAddr = "2001:db8:0:2::1".parse().unwrap();
+        let now = Instant::now();
+
+        // Random names under NXDOMAIN from one /56 all count against one bucket
+        let actions: Vec<_> = ["a.example", "b.example", "c.example", "d.example", "e.example"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let ip = if i % 2 == 0 { client } else { neighbour };
+                rrl.check_at(ip, &response(name, true), now)
+            })
+            .collect();
+        assert_eq!(
+            actions,
+            vec![RrlAction::Send, RrlAction::Drop, RrlAction::Slip, RrlAction::Drop, RrlAction::Slip]
+        );
+    }
+
+    #[test]
+    fn test_configure_resets_buckets() {
+        let rrl = limiter(1, 0);
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let answer = response("example.com", false);
+        assert_eq!(rrl.check(client, &answer), RrlAction::Send);
+        assert_eq!(rrl.status().tracked, 1);
+
+        rrl.configure(RrlConfig { responses_per_second: 5, slip: 0 });
+        assert_eq!(rrl.status().tracked, 0);
+        assert_eq!(rrl.check(client, &answer), RrlAction::Send);
+    }
+
+    #[test]
+    fn test_full_table_evicts_least_recently_used() {
+        let rrl = limiter(1, 0);
+        rrl.buckets.lock().unwrap().capacity = 2;
+        let answer = response("example.com", false);
+        let now = Instant::now();
+        let [a, b, c]: [IpAddr; 3] = ["192.0.2.1", "198.51.100.1", "203.0.113.1"].map(|ip| ip.parse().unwrap());
+
+        assert_eq!(rrl.check_at(a, &answer, now), RrlAction::Send);
+        assert_eq!(rrl.check_at(b, &answer, now), RrlAction::Send);
+        assert_eq!(rrl.check_at(a, &answer, now), RrlAction::Drop);
+        // A new network takes the place of b, used least recently
+        assert_eq!(rrl.check_at(c, &answer, now), RrlAction::Send);
+        assert_eq!(rrl.status().tracked, 2);
+        // Limiting goes on for the tracked networks
+        assert_eq!(rrl.check_at(a, &answer, now), RrlAction::Drop);
+        assert_eq!(rrl.check_at(c, &answer, now), RrlAction::Drop);
+        assert_eq!(rrl.check_at(b, &answer, now), RrlAction::Send);
+        assert_eq!(rrl.check_at(b, &answer, now), RrlAction::Drop);
+    }
+
+    #[test]
+    fn test_client_network() {
+        assert_eq!(client_network("192.0.2.77".parse().unwrap()), "192.0.2.0".parse::<IpAddr>().unwrap());
+        assert_eq!(
+            client_network("2001:db8:aa:bbcc::1".parse().unwrap()),
+            "2001:db8:aa:bb00::".parse::<IpAddr>().unwrap()
+        );
+    }
+}
@@ -0,0 +1,435 @@
+//! Scripted query policy
+//!
+//! Runs an admin-provided Lua script as resolver middleware. The script must
+//! define a global `policy(q)` function, called once per query with a table:
+//!
+//! ```lua
+//! -- q.name, q.type, q.client_ip, q.listener, q.tenant_id
+//! function policy(q)
+//!   if q.name:match("%.internal$") then
+//!     return { action = "route", upstream = "corp-dns" }
+//!   end
+//!   if q.name == "ads.example.com" then return "block" end
+//!   return "allow"
+//! end
+//! ```
+//!
+//! Return values: `nil` / `"allow"`, `"block"`,
+//! `{ action = "rewrite", value = "<ip>" }` or
+//! `{ action = "route", upstream = "<upstream name>" }`.
+//!
+//! The interpreter is sandboxed (table, string and math libraries only) and
+//! each call is bounded by a memory cap and an execution deadline. Scripts
+//! that error or exceed a limit let the query through.
+//!
+//! Calls run on tokio's blocking pool so a slow script never stalls the
+//! resolver. Each blocking thread compiles its own interpreter on first use
+//! and recompiles after the script changes, so globals a script sets are
+//! per thread rather than shared between queries.
+
+use std::cell::RefCell;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use mlua::{HookTriggers, Lua, LuaOptions, StdLib, Value};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::db::Database;
+use super::message::{DnsQuery, DnsRecordData, DnsResponse, RecordType};
+use super::middleware::{HookOutcome, QueryContext, ResolverMiddleware};
+
+/// Config key for the policy script source
+pub const CONFIG_KEY_POLICY_SCRIPT: &str = "policy_script";
+/// Config key for the policy script switch
+pub const CONFIG_KEY_POLICY_SCRIPT_ENABLED: &str = "policy_script_enabled";
+
+/// Maximum memory a script may allocate
+pub const SCRIPT_MEMORY_LIMIT: usize = 8 * 1024 * 1024;
+/// Maximum wall time for a single `policy` call
+pub const SCRIPT_TIME_LIMIT: Duration = Duration::from_millis(20);
+
+/// TTL for answers synthesized by a rewrite decision
+const REWRITE_TTL: u32 = 60;
+
+/// Query fields exposed to the script
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyInput {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub record_type: String,
+    #[serde(default)]
+    pub client_ip: Option<String>,
+    #[serde(default)]
+    pub listener: Option<String>,
+    #[serde(default)]
+    pub tenant_id: Option<i64>,
+}
+
+impl PolicyInput {
+    pub fn from_context(ctx: &QueryContext) -> Self {
+        Self {
+            name: ctx.query.name.clone(),
+            record_type: ctx.query.record_type.to_string(),
+            client_ip: ctx.client_ip.clone(),
+            listener: ctx.listener.clone(),
+            tenant_id: ctx.tenant_id,
+        }
+    }
+}
+
+/// Decision returned by the script
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum PolicyDecision {
+    Allow,
+    Block,
+    Rewrite { value: IpAddr },
+    Route { upstream: String },
+}
+
+/// A compiled policy script
+pub struct ScriptEngine {
+    lua: Lua,
+}
+
+impl ScriptEngine {
+    /// Compile a script and check that it defines `policy`
+    pub fn compile(source: &str) -> Result<Self> {
+        let lua = Lua::new_with(StdLib::TABLE | StdLib::STRING | StdLib::MATH, LuaOptions::default())
+            .map_err(|e| anyhow!("Failed to create script runtime: {}", e))?;
+        lua.set_memory_limit(SCRIPT_MEMORY_LIMIT)
+            .map_err(|e| anyhow!("Failed to set script memory limit: {}", e))?;
+
+        let engine = Self { lua };
+        engine.with_deadline(|lua| lua.load(source).set_name("policy").exec())?;
+
+        let has_policy = matches!(engine.lua.globals().get::<_, Value>("policy"), Ok(Value::Function(_)));
+        if !has_policy {
+            return Err(anyhow!("Script must define a global function `policy(q)`"));
+        }
+        Ok(engine)
+    }
+
+    /// Run `f` with the execution deadline armed
+    fn with_deadline<T>(&self, f: impl FnOnce(&Lua) -> mlua::Result<T>) -> Result<T> {
+        let deadline = Instant::now() + SCRIPT_TIME_LIMIT;
+        self.lua.set_hook(HookTriggers::new().every_nth_instruction(1000), move |_, _| {
+            if Instant::now() > deadline {
+                Err(mlua::Error::runtime("script exceeded its time limit"))
+            } else {
+                Ok(())
+            }
+        });
+        let result = f(&self.lua);
+        self.lua.remove_hook();
+        result.map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Evaluate the policy for a query
+    pub fn evaluate(&self, input: &PolicyInput) -> Result<PolicyDecision> {
+        let value = self.with_deadline(|lua| {
+            let q = lua.create_table()?;
+            q.set("name", input.name.as_str())?;
+            q.set("type", input.record_type.as_str())?;
+            q.set("client_ip", input.client_ip.as_deref())?;
+            q.set("listener", input.listener.as_deref())?;
+            q.set("tenant_id", input.tenant_id)?;
+
+            let policy: mlua::Function = lua.globals().get("policy")?;
+            let value: Value = policy.call(q)?;
+            Ok(decode_decision(value))
+        })?;
+        value
+    }
+}
+
+/// Convert a script return value into a decision
+fn decode_decision(value: Value) -> Result<PolicyDecision> {
+    let (action, table) = match value {
+        Value::Nil => return Ok(PolicyDecision::Allow),
+        Value::String(s) => (s.to_str()?.to_lowercase(), None),
+        Value::Table(t) => {
+            let action: String = t
+                .get("action")
+                .map_err(|_| anyhow!("Decision table must have a string `action`"))?;
+            (action.to_lowercase(), Some(t))
+        }
+        other => return Err(anyhow!("Unsupported policy return type: {}", other.type_name())),
+    };
+
+    let field = |name: &str| -> Result<String> {
+        table
+            .as_ref()
+            .and_then(|t| t.get::<_, String>(name).ok())
+            .ok_or_else(|| anyhow!("`{}` decision requires a `{}` field", action, name))
+    };
+
+    match action.as_str() {
+        "allow" => Ok(PolicyDecision::Allow),
+        "block" => Ok(PolicyDecision::Block),
+        "rewrite" => {
+            let value = field("value")?;
+            let ip = value
+                .parse()
+                .map_err(|_| anyhow!("Invalid rewrite IP address: {}", value))?;
+            Ok(PolicyDecision::Rewrite { value: ip })
+        }
+        "route" => Ok(PolicyDecision::Route {
+            upstream: field("upstream")?,
+        }),
+        other => Err(anyhow!("Unknown policy action: {}", other)),
+    }
+}
+
+/// Build the response for a rewrite decision
+fn rewrite_response(query: &DnsQuery, ip: IpAddr) -> DnsResponse {
+    let mut response = DnsResponse::new(query.id);
+    match (ip, query.record_type) {
+        (IpAddr::V4(v4), RecordType::A) => {
+            response.add_answer(DnsRecordData::a(&query.name, v4, REWRITE_TTL));
+        }
+        (IpAddr::V6(v6), RecordType::AAAA) => {
+            response.add_answer(DnsRecordData::aaaa(&query.name, v6, REWRITE_TTL));
+        }
+        // Other types get an empty NOERROR answer, like rewrite rules
+        _ => {}
+    }
+    response
+}
+
+/// Source of every installed script gets a new generation
+static SCRIPT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// This thread's interpreter and the generation it was compiled from
+    static THREAD_ENGINE: RefCell<Option<(u64, ScriptEngine)>> = const { RefCell::new(None) };
+}
+
+/// An installed script
+struct ActiveScript {
+    generation: u64,
+    source: String,
+}
+
+impl ActiveScript {
+    /// Evaluate on the calling thread's interpreter, compiling it if missing or stale
+    fn evaluate(&self, input: &PolicyInput) -> Result<PolicyDecision> {
+        THREAD_ENGINE.with(|slot| {
+            let mut slot = slot.borrow_mut();
+            let engine = match slot.take() {
+                Some((generation, engine)) if generation == self.generation => engine,
+                _ => ScriptEngine::compile(&self.source)?,
+            };
+            let decision = engine.evaluate(input);
+            *slot = Some((self.generation, engine));
+            decision
+        })
+    }
+}
+
+/// Resolver middleware running the active policy script
+pub struct ScriptPolicy {
+    db: Option<Arc<Database>>,
+    script: RwLock<Option<Arc<ActiveScript>>>,
+}
+
+impl ScriptPolicy {
+    pub fn new(db: Option<Arc<Database>>) -> Self {
+        Self {
+            db,
+            script: RwLock::new(None),
+        }
+    }
+
+    /// Install (or clear) the active script
+    pub fn set_script(&self, source: Option<&str>) -> Result<()> {
+        let script = match source {
+            Some(source) => {
+                ScriptEngine::compile(source)?;
+                Some(Arc::new(ActiveScript {
+                    generation: SCRIPT_GENERATION.fetch_add(1, Ordering::Relaxed),
+                    source: source.to_string(),
+                }))
+            }
+            None => None,
+        };
+        *self.script.write().unwrap() = script;
+        Ok(())
+    }
+
+    /// Whether a script is active
+    pub fn is_active(&self) -> bool {
+        self.script.read().unwrap().is_some()
+    }
+
+    /// Load the saved script from settings
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+        let config = db.system_config();
+        let enabled = config
+            .get(CONFIG_KEY_POLICY_SCRIPT_ENABLED)
+            .await?
+            .is_some_and(|v| v == "true");
+        let source = config.get(CONFIG_KEY_POLICY_SCRIPT).await?;
+
+        match source.filter(|s| enabled && !s.trim().is_empty()) {
+            Some(source) => {
+                self.set_script(Some(&source))?;
+                info!("Policy script loaded");
+            }
+            None => self.set_script(None)?,
+        }
+        Ok(())
+    }
+
+    async fn evaluate(&self, input: PolicyInput) -> Option<Result<PolicyDecision>> {
+        let script = self.script.read().unwrap().clone()?;
+        let result = tokio::task::spawn_blocking(move || script.evaluate(&input))
+            .await
+            .unwrap_or_else(|e| Err(anyhow!("Policy script task failed: {}", e)));
+        Some(result)
+    }
+}
+
+#[async_trait]
+impl ResolverMiddleware for ScriptPolicy {
+    fn name(&self) -> &str {
+        "policy_script"
+    }
+
+    async fn pre_rewrite(&self, ctx: &mut QueryContext) -> Result<HookOutcome> {
+        let decision = match self.evaluate(PolicyInput::from_context(ctx)).await {
+            None => return Ok(HookOutcome::Continue),
+            Some(Ok(decision)) => decision,
+            Some(Err(e)) => {
+                warn!("Policy script failed for {}: {}", ctx.query.name, e);
+                return Ok(HookOutcome::Continue);
+            }
+        };
+
+        Ok(match decision {
+            PolicyDecision::Allow => HookOutcome::Continue,
+            PolicyDecision::Block => HookOutcome::Respond(DnsResponse::nxdomain(ctx.query.id)),
+            PolicyDecision::Rewrite { value } => HookOutcome::Respond(rewrite_response(&ctx.query, value)),
+            PolicyDecision::Route { upstream } => {
+                ctx.upstream = Some(upstream);
+                HookOutcome::Continue
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::DnsResponseCode;
+
+    fn input(name: &str) -> PolicyInput {
+        PolicyInput {
+            name: name.to_string(),
+            record_type: "A".to_string(),
+            client_ip: Some("10.0.0.5".to_string()),
+            listener: Some("udp".to_string()),
+            tenant_id: None,
+        }
+    }
+
+    const SCRIPT: &str = r#"
+        function policy(q)
+          if q.name == "ads.example.com" then return "block" end
+          if q.name == "nas.home" then return { action = "rewrite", value = "192.168.1.10" } end
+          if q.name:match("%.corp$") then return { action = "route", upstream = "corp-dns" } end
+          if q.client_ip == "10.0.0.9" then return "block" end
+          return nil
+        end
+    "#;
+
+    #[test]
+    fn test_script_decisions() {
+        let engine = ScriptEngine::compile(SCRIPT).unwrap();
+        assert_eq!(engine.evaluate(&input("ads.example.com")).unwrap(), PolicyDecision::Block);
+        assert_eq!(
+            engine.evaluate(&input("nas.home")).unwrap(),
+            PolicyDecision::Rewrite { value: "192.168.1.10".parse().unwrap() }
+        );
+        assert_eq!(
+            engine.evaluate(&input("git.corp")).unwrap(),
+            PolicyDecision::Route { upstream: "corp-dns".to_string() }
+        );
+        assert_eq!(engine.evaluate(&input("example.com")).unwrap(), PolicyDecision::Allow);
+    }
+
+    #[test]
+    fn test_script_compile_errors() {
+        assert!(ScriptEngine::compile("function policy(q) return").is_err());
+        assert!(ScriptEngine::compile("x = 1").is_err());
+        // Sandboxed: no os/io libraries
+        assert!(ScriptEngine::compile("os.execute('true') function policy(q) end").is_err());
+    }
+
+    #[test]
+    fn test_script_limits() {
+        let engine = ScriptEngine::compile(
+            "function policy(q) if q.name == 'loop.test' then while true do end end return 'block' end",
+        )
+        .unwrap();
+        assert!(engine.evaluate(&input("loop.test")).is_err());
+        // Still usable after hitting the deadline
+        assert_eq!(engine.evaluate(&input("example.com")).unwrap(), PolicyDecision::Block);
+
+        let engine = ScriptEngine::compile(
+            "function policy(q) local t = {} for i = 1, 1e8 do t[i] = string.rep('x', 64) .. i end end",
+        )
+        .unwrap();
+        assert!(engine.evaluate(&input("example.com")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_script_policy_middleware() {
+        let policy = ScriptPolicy::new(None);
+        let mut ctx = QueryContext::new(DnsQuery::new("ads.example.com", RecordType::A), None);
+        assert!(matches!(policy.pre_rewrite(&mut ctx).await.unwrap(), HookOutcome::Continue));
+
+        policy.set_script(Some(SCRIPT)).unwrap();
+        match policy.pre_rewrite(&mut ctx).await.unwrap() {
+            HookOutcome::Respond(r) => assert_eq!(r.response_code, DnsResponseCode::NxDomain),
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+
+        let mut ctx = QueryContext::new(DnsQuery::new("git.corp", RecordType::A), None);
+        assert!(matches!(policy.pre_rewrite(&mut ctx).await.unwrap(), HookOutcome::Continue));
+        assert_eq!(ctx.upstream.as_deref(), Some("corp-dns"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_script_policy_swap() {
+        let policy = Arc::new(ScriptPolicy::new(None));
+        policy.set_script(Some(SCRIPT)).unwrap();
+
+        let tasks: Vec<_> = (0..16)
+            .map(|_| {
+                let policy = policy.clone();
+                tokio::spawn(async move { policy.evaluate(input("ads.example.com")).await.unwrap().unwrap() })
+            })
+            .collect();
+        for task in tasks {
+            assert_eq!(task.await.unwrap(), PolicyDecision::Block);
+        }
+
+        // Threads that already compiled the old script pick up the new one
+        policy.set_script(Some("function policy(q) return 'allow' end")).unwrap();
+        for _ in 0..16 {
+            assert_eq!(policy.evaluate(input("ads.example.com")).await.unwrap().unwrap(), PolicyDecision::Allow);
+        }
+
+        policy.set_script(None).unwrap();
+        assert!(policy.evaluate(input("ads.example.com")).await.is_none());
+    }
+}
@@ -2,6 +2,12 @@
 //!
 //! Implements a DNS server over HTTPS protocol (port 443).
 //! Supports both GET and POST methods as per RFC 8484.
+//!
+//! Requests that are not DNS queries at all (bad base64, undecodable
+//! messages, wrong media type) are rejected with an HTTP error. Anything
+//! that decodes as a query gets `200 OK` with a DNS answer, and resolution
+//! problems are reported through the DNS response code. Responses to EDNS(0)
+//! queries are padded to 468 byte blocks as recommended by RFC 8467.
 
 #![allow(dead_code)]
 
@@ -16,12 +22,31 @@ use axum::{
     Router,
 };
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hickory_proto::op::{Message, MessageType, OpCode, ResponseCode};
+use hickory_proto::serialize::binary::{BinDecodable, BinEncodable};
 use serde::Deserialize;
 use tracing::{debug, warn};
 
-use crate::dns::message::{DnsQuery, DnsResponse};
+use crate::dns::loop_guard::loop_guard;
+use crate::dns::extended_error::ExtendedError;
+use crate::dns::message::{append_opt_record, DnsError, DnsQuery, DnsResponse, WireSummary};
 use crate::dns::resolver::DnsResolver;
 
+/// Media type of DNS wire format messages (RFC 8484)
+pub const DNS_MESSAGE_CONTENT_TYPE: &str = "application/dns-message";
+
+/// Block size responses are padded to (RFC 8467, section 4.1)
+pub const RESPONSE_PADDING_BLOCK: usize = 468;
+
+/// EDNS(0) padding option code (RFC 7830)
+const EDNS_OPTION_PADDING: u16 = 12;
+/// UDP payload size advertised in the OPT record
+const EDNS_UDP_PAYLOAD_SIZE: u16 = 1232;
+/// Length of an OPT record with an empty padding option
+const PADDING_OVERHEAD: usize = 11 + PADDING_OPTION_OVERHEAD;
+/// Length of an empty padding option
+const PADDING_OPTION_OVERHEAD: usize = 4;
+
 /// DoH server state
 #[derive(Clone)]
 pub struct DohState {
@@ -109,6 +134,20 @@ async fn handle_post_query(
 ) -> Response {
     debug!("DoH POST request received");
 
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(str::trim);
+    if !content_type.is_some_and(|t| t.eq_ignore_ascii_case(DNS_MESSAGE_CONTENT_TYPE)) {
+        return (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Content-Type must be application/dns-message",
+        )
+            .into_response();
+    }
+
     // Get client IP from request headers or connection
     let client_ip = get_client_ip(&request, Some(addr));
 
@@ -154,13 +193,31 @@ fn get_client_ip(request: &axum::http::Request<axum::body::Body>, conn_addr: Opt
 
 /// Process a DNS query and return an HTTP response
 async fn process_dns_query(resolver: &DnsResolver, query_bytes: &[u8], client_ip: &str) -> Response {
-    // Parse the DNS query
+    // Only requests that are not DNS queries at all are HTTP errors
+    let request = match Message::from_bytes(query_bytes) {
+        Ok(message) if message.message_type() == MessageType::Query => message,
+        Ok(_) => {
+            return (StatusCode::BAD_REQUEST, "DNS message is not a query").into_response();
+        }
+        Err(e) => {
+            warn!("Failed to parse DNS query: {}", e);
+            return (StatusCode::BAD_REQUEST, "Malformed DNS message").into_response();
+        }
+    };
+    let pad = request.extensions().is_some();
+
+    if request.op_code() != OpCode::Query {
+        return create_dns_response(error_message(&request, ResponseCode::NotImp), pad);
+    }
+    if request.queries().is_empty() {
+        return create_dns_response(error_message(&request, ResponseCode::FormErr), pad);
+    }
+
     let query = match DnsQuery::from_bytes(query_bytes) {
         Ok(q) => q,
         Err(e) => {
-            warn!("Failed to parse DNS query: {}", e);
-            let response = DnsResponse::servfail(0);
-            return create_dns_response(&response, &DnsQuery::new(".", crate::dns::message::RecordType::A));
+            debug!("Unsupported DNS query: {}", e);
+            return create_dns_response(error_message(&request, ResponseCode::ServFail), pad);
         }
     };
 
@@ -169,13 +226,17 @@ async fn process_dns_query(resolver: &DnsResolver, query_bytes: &[u8], client_ip
         query.name, query.record_type, query.id
     );
 
+    if let Some(response) = loop_guard().check(query_bytes, &query, client_ip, "doh") {
+        return create_dns_response(response.to_bytes(&query), pad);
+    }
+
     // Resolve the query with client IP for logging
-    let result = match resolver.resolve_with_client(&query, client_ip).await {
+    let result = match resolver.resolve_from_listener(&query, client_ip, Some("doh")).await {
         Ok(r) => r,
         Err(e) => {
             warn!("Failed to resolve query for {}: {}", query.name, e);
-            let response = DnsResponse::servfail(query.id);
-            return create_dns_response(&response, &query);
+            let response = DnsResponse::servfail(query.id).with_extended_error(ExtendedError::for_failure(&e));
+            return create_dns_response(response.to_bytes(&query), pad);
         }
     };
 
@@ -188,18 +249,65 @@ async fn process_dns_query(resolver: &DnsResolver, query_bytes: &[u8], client_ip
         result.metadata.response_time_ms
     );
 
-    create_dns_response(&result.response, &query)
+    create_dns_response(result.response.to_bytes(&query), pad)
+}
+
+/// Encode an error response echoing the request's questions
+fn error_message(request: &Message, code: ResponseCode) -> Result<Vec<u8>, DnsError> {
+    let mut message = Message::new();
+    message.set_id(request.id());
+    message.set_message_type(MessageType::Response);
+    message.set_op_code(request.op_code());
+    message.set_recursion_desired(request.recursion_desired());
+    message.set_recursion_available(true);
+    message.set_response_code(code);
+    for query in request.queries() {
+        message.add_query(query.clone());
+    }
+    message
+        .to_bytes()
+        .map_err(|e| DnsError::EncodeError(e.to_string()))
+}
+
+/// Append a padding option (RFC 7830) so the message length becomes a
+/// multiple of `block`
+///
+/// The option goes into the message's OPT record when it already ends with
+/// one, e.g. for an extended error, and into a new OPT record otherwise.
+pub fn pad_message(bytes: &mut Vec<u8>, block: usize) {
+    if block == 0 {
+        return;
+    }
+    let overhead = if WireSummary::parse(bytes).is_some_and(|s| s.opt.is_some()) {
+        PADDING_OPTION_OVERHEAD
+    } else {
+        PADDING_OVERHEAD
+    };
+    let padding = (block - (bytes.len() + overhead) % block) % block;
+    append_opt_record(
+        bytes,
+        EDNS_UDP_PAYLOAD_SIZE,
+        false,
+        &[(EDNS_OPTION_PADDING, &vec![0u8; padding])],
+    );
 }
 
 /// Create an HTTP response with DNS message content
-fn create_dns_response(response: &DnsResponse, query: &DnsQuery) -> Response {
-    match response.to_bytes(query) {
-        Ok(bytes) => (
-            StatusCode::OK,
-            [(header::CONTENT_TYPE, "application/dns-message")],
-            bytes,
-        )
-            .into_response(),
+///
+/// DoH never truncates, so the TC bit stays clear whatever the size.
+fn create_dns_response<E: std::fmt::Display>(encoded: Result<Vec<u8>, E>, pad: bool) -> Response {
+    match encoded {
+        Ok(mut bytes) => {
+            if pad {
+                pad_message(&mut bytes, RESPONSE_PADDING_BLOCK);
+            }
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, DNS_MESSAGE_CONTENT_TYPE)],
+                bytes,
+            )
+                .into_response()
+        }
         Err(e) => {
             warn!("Failed to encode DNS response: {}", e);
             (StatusCode::INTERNAL_SERVER_ERROR, "Failed to encode DNS response").into_response()
@@ -270,6 +378,7 @@ mod tests {
     use crate::dns::rewrite::RewriteEngine;
     use crate::dns::CacheKey;
     use axum::body::Body;
+    use axum::extract::connect_info::MockConnectInfo;
     use axum::http::Request;
     use std::net::Ipv4Addr;
     use tower::ServiceExt;
@@ -279,6 +388,7 @@ mod tests {
         let cache = Arc::new(CacheManager::with_config(CacheConfig {
             default_ttl: 60,
             max_entries: 1000,
+            max_memory_mb: 0,
         }));
         let upstream_manager = Arc::new(UpstreamManager::new());
         let proxy = Arc::new(ProxyManager::new(upstream_manager));
@@ -286,6 +396,29 @@ mod tests {
         Arc::new(DnsResolver::new(rewrite_engine, cache, proxy))
     }
 
+    /// DoH router as served, with a fake peer address
+    fn test_router(resolver: Arc<DnsResolver>) -> Router {
+        DohDnsServer::new(resolver)
+            .router()
+            .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))))
+    }
+
+    fn post_request(body: Vec<u8>) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/dns-query")
+            .header("Content-Type", DNS_MESSAGE_CONTENT_TYPE)
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn body_bytes(response: Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
     #[tokio::test]
     async fn test_doh_server_creation() {
         let resolver = create_test_resolver();
@@ -307,8 +440,7 @@ mod tests {
         ));
         resolver.cache().set(cache_key, response).await;
 
-        let server = DohDnsServer::new(resolver);
-        let router = server.router();
+        let router = test_router(resolver);
 
         // Create a DNS query
         let query = DnsQuery::with_id(54321, "doh.example.com", RecordType::A);
@@ -344,8 +476,7 @@ mod tests {
         ));
         resolver.cache().set(cache_key, response).await;
 
-        let server = DohDnsServer::new(resolver);
-        let router = server.router();
+        let router = test_router(resolver);
 
         // Create a DNS query and encode it
         let query = DnsQuery::with_id(11111, "get.example.com", RecordType::A);
@@ -366,8 +497,7 @@ mod tests {
     #[tokio::test]
     async fn test_doh_invalid_base64() {
         let resolver = create_test_resolver();
-        let server = DohDnsServer::new(resolver);
-        let router = server.router();
+        let router = test_router(resolver);
 
         // Make GET request with invalid base64
         let request = Request::builder()
@@ -379,4 +509,121 @@ mod tests {
         let response = router.oneshot(request).await.unwrap();
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
+
+    #[test]
+    fn test_pad_message_block_length() {
+        let query = DnsQuery::with_id(7, "pad.example.com", RecordType::A);
+        let mut response = DnsResponse::new(7);
+        response.add_answer(DnsRecordData::a("pad.example.com", Ipv4Addr::new(10, 0, 0, 1), 300));
+        let mut bytes = response.to_bytes(&query).unwrap();
+        pad_message(&mut bytes, RESPONSE_PADDING_BLOCK);
+        assert_eq!(bytes.len() % RESPONSE_PADDING_BLOCK, 0);
+
+        // Still a valid message, with the OPT record in the EDNS section
+        let message = Message::from_bytes(&bytes).unwrap();
+        assert!(message.extensions().is_some());
+        assert_eq!(message.answers().len(), 1);
+
+        // A message already ending on a block boundary gets no padding bytes
+        let mut exact = vec![0u8; RESPONSE_PADDING_BLOCK - PADDING_OVERHEAD];
+        pad_message(&mut exact, RESPONSE_PADDING_BLOCK);
+        assert_eq!(exact.len(), RESPONSE_PADDING_BLOCK);
+    }
+
+    #[tokio::test]
+    async fn test_doh_pads_only_edns_queries() {
+        let resolver = create_test_resolver();
+        let mut response = DnsResponse::new(0);
+        response.add_answer(DnsRecordData::a("edns.example.com", Ipv4Addr::new(10, 0, 0, 3), 300));
+        resolver.cache().set(CacheKey::new("edns.example.com", RecordType::A), response).await;
+        let router = test_router(resolver);
+
+        let plain = DnsQuery::with_id(1, "edns.example.com", RecordType::A).to_bytes().unwrap();
+        let mut padded = plain.clone();
+        pad_message(&mut padded, 128);
+
+        let response = router.clone().oneshot(post_request(plain)).await.unwrap();
+        let message = Message::from_bytes(&body_bytes(response).await).unwrap();
+        assert!(message.extensions().is_none());
+
+        let response = router.oneshot(post_request(padded)).await.unwrap();
+        let bytes = body_bytes(response).await;
+        assert_eq!(bytes.len() % RESPONSE_PADDING_BLOCK, 0);
+        assert_eq!(DnsResponse::from_bytes(&bytes).unwrap().answers.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_doh_large_response_not_truncated() {
+        let resolver = create_test_resolver();
+        let mut response = DnsResponse::new(0);
+        for i in 0..60u8 {
+            response.add_answer(DnsRecordData::a("big.example.com", Ipv4Addr::new(10, 0, 1, i), 300));
+        }
+        resolver.cache().set(CacheKey::new("big.example.com", RecordType::A), response).await;
+        let router = test_router(resolver);
+
+        let query = DnsQuery::with_id(2, "big.example.com", RecordType::A).to_bytes().unwrap();
+        let response = router.oneshot(post_request(query)).await.unwrap();
+        let bytes = body_bytes(response).await;
+        assert!(bytes.len() > 512);
+
+        let message = Message::from_bytes(&bytes).unwrap();
+        assert!(!message.truncated());
+        assert_eq!(message.answers().len(), 60);
+    }
+
+    #[tokio::test]
+    async fn test_doh_http_errors() {
+        let router = test_router(create_test_resolver());
+        let query = DnsQuery::with_id(3, "example.com", RecordType::A).to_bytes().unwrap();
+
+        // Wrong media type
+        let request = Request::builder()
+            .method("POST")
+            .uri("/dns-query")
+            .header("Content-Type", "application/json")
+            .body(Body::from(query.clone()))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        // Not a DNS message
+        let response = router.clone().oneshot(post_request(vec![1, 2, 3])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // A response instead of a query
+        let answer = DnsResponse::new(3)
+            .to_bytes(&DnsQuery::with_id(3, "example.com", RecordType::A))
+            .unwrap();
+        let response = router.oneshot(post_request(answer)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_doh_dns_errors_use_rcode() {
+        let router = test_router(create_test_resolver());
+
+        // Unsupported query type is answered, not rejected
+        let mut message = Message::new();
+        message.set_id(4);
+        message.set_message_type(MessageType::Query);
+        message.set_op_code(OpCode::Query);
+        message.add_query(hickory_proto::op::Query::query(
+            hickory_proto::rr::Name::from_ascii("example.com.").unwrap(),
+            hickory_proto::rr::RecordType::CAA,
+        ));
+        let response = router.clone().oneshot(post_request(message.to_bytes().unwrap())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let reply = Message::from_bytes(&body_bytes(response).await).unwrap();
+        assert_eq!(reply.id(), 4);
+        assert_eq!(reply.response_code(), ResponseCode::ServFail);
+        assert_eq!(reply.queries().len(), 1);
+
+        // Other opcodes are not implemented
+        message.set_op_code(OpCode::Status);
+        let response = router.oneshot(post_request(message.to_bytes().unwrap())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let reply = Message::from_bytes(&body_bytes(response).await).unwrap();
+        assert_eq!(reply.response_code(), ResponseCode::NotImp);
+    }
 }
@@ -11,14 +11,19 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
-use quinn::{Endpoint, ServerConfig};
+use quinn::{Endpoint, EndpointConfig, ServerConfig};
 use rustls::pki_types::CertificateDer;
 use rustls_pemfile::{certs, private_key};
 use tracing::{debug, info, warn};
 
+use crate::dns::extended_error::ExtendedError;
+use crate::dns::loop_guard::loop_guard;
+use crate::dns::proxy::quic_transport;
 use crate::dns::message::{DnsQuery, DnsResponse};
 use crate::dns::resolver::DnsResolver;
+use crate::dns::socket::bind_udp;
 use super::dot::TlsConfig;
+use super::interface_suffix;
 
 /// DNS over QUIC Server
 ///
@@ -38,13 +43,26 @@ impl DoqDnsServer {
         bind_addr: SocketAddr,
         tls_config: TlsConfig,
         resolver: Arc<DnsResolver>,
+    ) -> Result<Self> {
+        Self::with_interface(bind_addr, None, tls_config, resolver).await
+    }
+
+    /// Create a new DoQ DNS server, optionally bound to a network interface
+    pub async fn with_interface(
+        bind_addr: SocketAddr,
+        interface: Option<&str>,
+        tls_config: TlsConfig,
+        resolver: Arc<DnsResolver>,
     ) -> Result<Self> {
         let server_config = Self::create_server_config(&tls_config)?;
-        
-        let endpoint = Endpoint::server(server_config, bind_addr)
+
+        let socket = bind_udp(bind_addr, interface)?;
+        let runtime = quinn::default_runtime()
+            .ok_or_else(|| anyhow!("No async runtime found for QUIC endpoint"))?;
+        let endpoint = Endpoint::new(EndpointConfig::default(), Some(server_config), socket, runtime)
             .map_err(|e| anyhow!("Failed to create QUIC endpoint: {}", e))?;
 
-        info!("DoQ DNS server bound to {}", bind_addr);
+        info!("DoQ DNS server bound to {}{}", bind_addr, interface_suffix(interface));
 
         Ok(Self {
             endpoint,
@@ -82,13 +100,8 @@ impl DoqDnsServer {
             .with_single_cert(certs, key)
             .map_err(|e| anyhow!("Failed to build TLS config: {}", e))?;
 
-        // Build QUIC server config using quinn's crypto wrapper
-        let server_config = ServerConfig::with_crypto(Arc::new(
-            quinn::crypto::rustls::QuicServerConfig::try_from(crypto)
-                .map_err(|e| anyhow!("Failed to create QUIC server config: {}", e))?
-        ));
-
-        Ok(server_config)
+        // Build QUIC server config with the configured transport settings
+        quic_transport().server_config(crypto)
     }
 
     /// Create a new DoQ DNS server on the default port (853)
@@ -114,12 +127,22 @@ impl DoqDnsServer {
     pub async fn run(&self) -> Result<()> {
         info!("DoQ DNS server starting on {}", self.bind_addr);
 
-        while let Some(connecting) = self.endpoint.accept().await {
+        while let Some(incoming) = self.endpoint.accept().await {
             let resolver = self.resolver.clone();
 
             tokio::spawn(async move {
-                match connecting.await {
+                // Queries may arrive as early data; DNS over QUIC allows it (RFC 9250 §4.5)
+                let connection = match incoming.accept() {
+                    Ok(connecting) if quic_transport().settings().zero_rtt => match connecting.into_0rtt() {
+                        Ok((connection, _)) => Ok(connection),
+                        Err(connecting) => connecting.await,
+                    },
+                    Ok(connecting) => connecting.await,
+                    Err(e) => Err(e),
+                };
+                match connection {
                     Ok(connection) => {
+                        quic_transport().record_listener_connection();
                         let peer_addr = connection.remote_address();
                         debug!("New DoQ connection from {}", peer_addr);
 
@@ -214,7 +237,7 @@ impl DoqDnsServer {
     }
 
     /// Handle a DNS query and return the response bytes
-    async fn handle_query(resolver: &DnsResolver, data: &[u8], client_ip: &str) -> Result<Vec<u8>> {
+    pub(super) async fn handle_query(resolver: &DnsResolver, data: &[u8], client_ip: &str) -> Result<Vec<u8>> {
         // Parse the query
         let query = match DnsQuery::from_bytes(data) {
             Ok(q) => q,
@@ -231,12 +254,17 @@ impl DoqDnsServer {
             query.name, query.record_type, query.id
         );
 
+        if let Some(response) = loop_guard().check(data, &query, client_ip, "doq") {
+            return response.to_bytes(&query)
+                .map_err(|e| anyhow!("Failed to encode error response: {}", e));
+        }
+
         // Resolve the query with client IP for logging
-        let result = match resolver.resolve_with_client(&query, client_ip).await {
+        let result = match resolver.resolve_from_listener(&query, client_ip, Some("doq")).await {
             Ok(r) => r,
             Err(e) => {
                 warn!("Failed to resolve query for {}: {}", query.name, e);
-                let response = DnsResponse::servfail(query.id);
+                let response = DnsResponse::servfail(query.id).with_extended_error(ExtendedError::for_failure(&e));
                 return response.to_bytes(&query)
                     .map_err(|e| anyhow!("Failed to encode error response: {}", e));
             }
@@ -18,8 +18,12 @@ use tokio::net::{TcpListener, TcpStream};
 use tokio_rustls::TlsAcceptor;
 use tracing::{debug, error, info, warn};
 
+use crate::dns::extended_error::ExtendedError;
+use crate::dns::loop_guard::loop_guard;
 use crate::dns::message::{DnsQuery, DnsResponse};
 use crate::dns::resolver::DnsResolver;
+use crate::dns::socket::bind_tcp;
+use super::interface_suffix;
 
 /// TLS configuration for the DoT server
 #[derive(Clone)]
@@ -93,14 +97,23 @@ impl DotDnsServer {
         bind_addr: SocketAddr,
         tls_config: TlsConfig,
         resolver: Arc<DnsResolver>,
+    ) -> Result<Self> {
+        Self::with_interface(bind_addr, None, tls_config, resolver).await
+    }
+
+    /// Create a new DoT DNS server, optionally bound to a network interface
+    pub async fn with_interface(
+        bind_addr: SocketAddr,
+        interface: Option<&str>,
+        tls_config: TlsConfig,
+        resolver: Arc<DnsResolver>,
     ) -> Result<Self> {
         let server_config = tls_config.load()?;
         let acceptor = TlsAcceptor::from(Arc::new(server_config));
 
-        let listener = TcpListener::bind(bind_addr).await
-            .map_err(|e| anyhow!("Failed to bind TCP listener to {}: {}", bind_addr, e))?;
+        let listener = bind_tcp(bind_addr, interface)?;
 
-        info!("DoT DNS server bound to {}", bind_addr);
+        info!("DoT DNS server bound to {}{}", bind_addr, interface_suffix(interface));
 
         Ok(Self {
             listener,
@@ -212,7 +225,7 @@ impl DotDnsServer {
     }
 
     /// Handle a DNS query and return the response bytes
-    async fn handle_query(resolver: &DnsResolver, data: &[u8], client_ip: &str) -> Result<Vec<u8>> {
+    pub(super) async fn handle_query(resolver: &DnsResolver, data: &[u8], client_ip: &str) -> Result<Vec<u8>> {
         // Parse the query
         let query = match DnsQuery::from_bytes(data) {
             Ok(q) => q,
@@ -229,12 +242,17 @@ impl DotDnsServer {
             query.name, query.record_type, query.id
         );
 
+        if let Some(response) = loop_guard().check(data, &query, client_ip, "dot") {
+            return response.to_bytes(&query)
+                .map_err(|e| anyhow!("Failed to encode error response: {}", e));
+        }
+
         // Resolve the query with client IP for logging
-        let result = match resolver.resolve_with_client(&query, client_ip).await {
+        let result = match resolver.resolve_from_listener(&query, client_ip, Some("dot")).await {
             Ok(r) => r,
             Err(e) => {
                 warn!("Failed to resolve query for {}: {}", query.name, e);
-                let response = DnsResponse::servfail(query.id);
+                let response = DnsResponse::servfail(query.id).with_extended_error(ExtendedError::for_failure(&e));
                 return response.to_bytes(&query)
                     .map_err(|e| anyhow!("Failed to encode error response: {}", e));
             }
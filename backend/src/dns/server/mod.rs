@@ -20,3 +20,8 @@ pub use dot::*;
 pub use doh::*;
 #[allow(unused_imports)]
 pub use doq::*;
+
+/// Log suffix naming the interface a listener is bound to
+fn interface_suffix(interface: Option<&str>) -> String {
+    interface.map(|i| format!(" (interface {})", i)).unwrap_or_default()
+}
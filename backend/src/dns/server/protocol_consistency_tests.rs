@@ -9,17 +9,22 @@
 
 #[cfg(test)]
 mod property_tests {
-    use std::net::Ipv4Addr;
+    use std::net::{Ipv4Addr, SocketAddr};
     use std::sync::Arc;
 
+    use axum::body::Body;
+    use axum::extract::connect_info::MockConnectInfo;
+    use axum::http::{Request, StatusCode};
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
     use proptest::prelude::*;
+    use tower::ServiceExt;
 
     use crate::dns::cache::{CacheConfig, CacheManager, CacheKey};
     use crate::dns::message::{DnsQuery, DnsRecordData, DnsResponse, DnsResponseCode, RecordType};
     use crate::dns::proxy::{ProxyManager, UpstreamManager};
     use crate::dns::resolver::DnsResolver;
     use crate::dns::rewrite::RewriteEngine;
-    use crate::dns::server::UdpDnsServer;
+    use crate::dns::server::{DohDnsServer, DoqDnsServer, DotDnsServer, UdpDnsServer};
 
     /// Create a test resolver with pre-populated cache
     fn create_test_resolver() -> Arc<DnsResolver> {
@@ -27,6 +32,7 @@ mod property_tests {
         let cache = Arc::new(CacheManager::with_config(CacheConfig {
             default_ttl: 60,
             max_entries: 1000,
+            max_memory_mb: 0,
         }));
         let upstream_manager = Arc::new(UpstreamManager::new());
         let proxy = Arc::new(ProxyManager::new(upstream_manager));
@@ -34,6 +40,65 @@ mod property_tests {
         Arc::new(DnsResolver::new(rewrite_engine, cache, proxy))
     }
 
+    /// Send a request to the DoH endpoint and return the DNS message body
+    async fn doh_exchange(resolver: Arc<DnsResolver>, request: Request<Body>) -> Vec<u8> {
+        let router = DohDnsServer::new(resolver)
+            .router()
+            .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+        let response = router.oneshot(request).await.expect("DoH request should succeed");
+        assert_eq!(response.status(), StatusCode::OK);
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("DoH body should be readable")
+            .to_vec()
+    }
+
+    /// Answer the same query over every protocol, labelled by protocol
+    async fn exchange_all(resolver: &Arc<DnsResolver>, query_bytes: &[u8]) -> Vec<(&'static str, DnsResponse)> {
+        let udp_server = UdpDnsServer::new("127.0.0.1:0".parse().unwrap(), resolver.clone())
+            .await
+            .expect("UDP server creation should succeed");
+        let udp = udp_server
+            .handle_query(query_bytes, "127.0.0.1:1234".parse().unwrap())
+            .await
+            .expect("UDP query handling should succeed");
+
+        let doh_get = doh_exchange(
+            resolver.clone(),
+            Request::builder()
+                .method("GET")
+                .uri(format!("/dns-query?dns={}", URL_SAFE_NO_PAD.encode(query_bytes)))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        let doh_post = doh_exchange(
+            resolver.clone(),
+            Request::builder()
+                .method("POST")
+                .uri("/dns-query")
+                .header("Content-Type", "application/dns-message")
+                .body(Body::from(query_bytes.to_vec()))
+                .unwrap(),
+        )
+        .await;
+
+        let dot = DotDnsServer::handle_query(resolver, query_bytes, "127.0.0.1")
+            .await
+            .expect("DoT query handling should succeed");
+        let doq = DoqDnsServer::handle_query(resolver, query_bytes, "127.0.0.1")
+            .await
+            .expect("DoQ query handling should succeed");
+
+        [("udp", udp), ("doh-get", doh_get), ("doh-post", doh_post), ("dot", dot), ("doq", doq)]
+            .into_iter()
+            .map(|(protocol, bytes)| {
+                let response = DnsResponse::from_bytes(&bytes).expect("Response parsing should succeed");
+                (protocol, response)
+            })
+            .collect()
+    }
+
     /// Strategy to generate valid domain names
     fn domain_strategy() -> impl Strategy<Value = String> {
         // Generate valid domain labels (alphanumeric, 1-10 chars each)
@@ -324,5 +389,57 @@ mod property_tests {
                 Ok(())
             })?;
         }
+
+        // Feature: dns-proxy-service, Property 1: DNS 协议处理一致性
+        // For any valid DNS query, UDP, DoH GET, DoH POST, DoT and DoQ must return
+        // the same ID, response code and answer section.
+        // **Validates: Requirements 1.1, 1.2, 1.3, 1.4**
+        #[test]
+        fn prop_all_protocols_identical_answers(
+            domain in domain_strategy(),
+            query_id in query_id_strategy(),
+            ips in prop::collection::vec(ipv4_strategy(), 1..8),
+            ttl in ttl_strategy()
+        ) {
+            let resolver = create_test_resolver();
+
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                let mut cached_response = DnsResponse::new(0);
+                for ip in &ips {
+                    cached_response.add_answer(DnsRecordData::a(&domain, *ip, ttl));
+                }
+                resolver.cache().set(CacheKey::new(&domain, RecordType::A), cached_response).await;
+
+                let query = DnsQuery::with_id(query_id, &domain, RecordType::A);
+                let query_bytes = query.to_bytes().expect("Query encoding should succeed");
+
+                let responses = exchange_all(&resolver, &query_bytes).await;
+                let answers = |response: &DnsResponse| {
+                    // TTLs count down between requests, so they are not compared
+                    response
+                        .answers
+                        .iter()
+                        .map(|a| (a.name.clone(), a.record_type, a.value.clone()))
+                        .collect::<Vec<_>>()
+                };
+
+                let (_, reference) = &responses[0];
+                prop_assert_eq!(reference.answers.len(), ips.len());
+                for (protocol, response) in &responses[1..] {
+                    prop_assert_eq!(response.id, query_id, "{} response ID should match query ID", protocol);
+                    prop_assert_eq!(
+                        response.response_code, reference.response_code,
+                        "{} response code should match UDP", protocol
+                    );
+                    prop_assert_eq!(
+                        answers(response), answers(reference),
+                        "{} answers should match UDP", protocol
+                    );
+                }
+
+                Ok(())
+            })?;
+        }
     }
 }
@@ -1,18 +1,27 @@
 //! UDP DNS Server
 //!
 //! Implements a standard DNS server over UDP protocol (port 53).
+//! Queries carrying a DNS cookie (RFC 7873) get a server cookie back; see
+//! [`crate::dns::DnsCookies`]. Responses pass the listener's response rate
+//! limiter before they are sent; see [`crate::dns::ResponseRateLimiter`].
 
 #![allow(dead_code)]
 
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
 use tokio::net::UdpSocket;
 use tracing::{debug, error, info, warn};
 
-use crate::dns::message::{DnsQuery, DnsResponse};
+use crate::dns::cookie::CookieCheck;
+use crate::dns::extended_error::ExtendedError;
+use crate::dns::loop_guard::loop_guard;
+use crate::dns::message::{truncated_response, DnsQuery, DnsResponse, DnsResponseCode};
 use crate::dns::resolver::DnsResolver;
+use crate::dns::rrl::{ResponseRateLimiter, RrlAction};
+use crate::dns::socket::bind_udp;
+use super::interface_suffix;
 
 /// UDP DNS Server
 ///
@@ -24,23 +33,41 @@ pub struct UdpDnsServer {
     resolver: Arc<DnsResolver>,
     /// Server bind address
     bind_addr: SocketAddr,
+    /// Response rate limiter, off unless configured
+    rrl: Arc<ResponseRateLimiter>,
 }
 
 impl UdpDnsServer {
     /// Create a new UDP DNS server
     pub async fn new(bind_addr: SocketAddr, resolver: Arc<DnsResolver>) -> Result<Self> {
-        let socket = UdpSocket::bind(bind_addr).await
+        Self::with_interface(bind_addr, None, resolver).await
+    }
+
+    /// Create a new UDP DNS server, optionally bound to a network interface
+    pub async fn with_interface(
+        bind_addr: SocketAddr,
+        interface: Option<&str>,
+        resolver: Arc<DnsResolver>,
+    ) -> Result<Self> {
+        let socket = UdpSocket::from_std(bind_udp(bind_addr, interface)?)
             .map_err(|e| anyhow!("Failed to bind UDP socket to {}: {}", bind_addr, e))?;
 
-        info!("UDP DNS server bound to {}", bind_addr);
+        info!("UDP DNS server bound to {}{}", bind_addr, interface_suffix(interface));
 
         Ok(Self {
             socket,
             resolver,
             bind_addr,
+            rrl: Arc::new(ResponseRateLimiter::default()),
         })
     }
 
+    /// Use a shared response rate limiter
+    pub fn with_rate_limiter(mut self, rrl: Arc<ResponseRateLimiter>) -> Self {
+        self.rrl = rrl;
+        self
+    }
+
     /// Create a new UDP DNS server on the default port (53)
     pub async fn new_default(resolver: Arc<DnsResolver>) -> Result<Self> {
         Self::new("0.0.0.0:53".parse()?, resolver).await
@@ -93,8 +120,10 @@ impl UdpDnsServer {
         src: SocketAddr,
     ) -> Result<()> {
         debug!("Processing query from {}", src);
-        let client_ip = src.ip().to_string();
-        let response_bytes = Self::handle_query_internal(&self.resolver, &data, &client_ip).await?;
+        let Some(response_bytes) = self.respond(&data, src).await? else {
+            debug!("Rate limited response to {} dropped", src);
+            return Ok(());
+        };
         
         debug!("Sending {} byte response to {}", response_bytes.len(), src);
         self.socket.send_to(&response_bytes, src).await
@@ -104,11 +133,21 @@ impl UdpDnsServer {
         Ok(())
     }
 
+    /// Response to send for a query, None when rate limiting drops it
+    async fn respond(&self, data: &[u8], src: SocketAddr) -> Result<Option<Vec<u8>>> {
+        let response_bytes = Self::handle_query_internal(&self.resolver, data, src.ip()).await?;
+        Ok(match self.rrl.check(src.ip(), &response_bytes) {
+            RrlAction::Send => Some(response_bytes),
+            RrlAction::Slip => truncated_response(&response_bytes),
+            RrlAction::Drop => None,
+        })
+    }
+
     /// Handle a DNS query and return the response bytes
     async fn handle_query_internal(
         resolver: &DnsResolver,
         data: &[u8],
-        client_ip: &str,
+        client_ip: IpAddr,
     ) -> Result<Vec<u8>> {
         // Parse the query
         let query = match DnsQuery::from_bytes(data) {
@@ -127,14 +166,39 @@ impl UdpDnsServer {
             query.name, query.record_type, query.id
         );
 
+        if let Some(response) = loop_guard().check(data, &query, &client_ip.to_string(), "udp") {
+            return response.to_bytes(&query)
+                .map_err(|e| anyhow!("Failed to encode error response: {}", e));
+        }
+
+        let cookies = resolver.cookies();
+        let cookie = cookies.check(data, client_ip);
+        if cookie == CookieCheck::Malformed {
+            debug!("Malformed DNS cookie from {}", client_ip);
+            let mut response = DnsResponse::new(query.id);
+            response.response_code = DnsResponseCode::FormErr;
+            return response.to_bytes(&query)
+                .map_err(|e| anyhow!("Failed to encode error response: {}", e));
+        }
+        if cookies.rejects(&cookie) {
+            debug!("Rejecting query from {} without a valid server cookie", client_ip);
+            let mut response = DnsResponse::new(query.id).to_bytes(&query)
+                .map_err(|e| anyhow!("Failed to encode error response: {}", e))?;
+            cookies.reject(&mut response, &cookie, client_ip);
+            return Ok(response);
+        }
+
         // Resolve the query with client IP for logging
-        let result = match resolver.resolve_with_client(&query, client_ip).await {
+        let result = match resolver.resolve_from_listener(&query, &client_ip.to_string(), Some("udp")).await {
             Ok(r) => r,
             Err(e) => {
                 warn!("Failed to resolve query for {}: {}", query.name, e);
-                let response = DnsResponse::servfail(query.id);
-                return response.to_bytes(&query)
-                    .map_err(|e| anyhow!("Failed to encode error response: {}", e));
+                let mut response = DnsResponse::servfail(query.id)
+                    .with_extended_error(ExtendedError::for_failure(&e))
+                    .to_bytes(&query)
+                    .map_err(|e| anyhow!("Failed to encode error response: {}", e))?;
+                cookies.add_to_response(&mut response, &cookie, client_ip);
+                return Ok(response);
             }
         };
 
@@ -148,14 +212,15 @@ impl UdpDnsServer {
         );
 
         // Encode the response
-        result.response.to_bytes(&query)
-            .map_err(|e| anyhow!("Failed to encode response: {}", e))
+        let mut response = result.response.to_bytes(&query)
+            .map_err(|e| anyhow!("Failed to encode response: {}", e))?;
+        cookies.add_to_response(&mut response, &cookie, client_ip);
+        Ok(response)
     }
 
     /// Handle a single DNS query (for testing)
     pub async fn handle_query(&self, data: &[u8], src: SocketAddr) -> Result<Vec<u8>> {
-        let client_ip = src.ip().to_string();
-        Self::handle_query_internal(&self.resolver, data, &client_ip).await
+        Self::handle_query_internal(&self.resolver, data, src.ip()).await
     }
 }
 
@@ -175,6 +240,7 @@ mod tests {
         let cache = Arc::new(CacheManager::with_config(CacheConfig {
             default_ttl: 60,
             max_entries: 1000,
+            max_memory_mb: 0,
         }));
         let upstream_manager = Arc::new(UpstreamManager::new());
         let proxy = Arc::new(ProxyManager::new(upstream_manager));
@@ -225,6 +291,108 @@ mod tests {
         assert_eq!(response.answers[0].value, "192.168.1.100");
     }
 
+    #[tokio::test]
+    async fn test_handle_query_with_cookie() {
+        use crate::dns::message::{append_opt_record, WireSummary, EDNS_OPTION_COOKIE};
+        use crate::dns::CookieMode;
+
+        let resolver = create_test_resolver();
+        let mut response = DnsResponse::new(0);
+        response.add_answer(DnsRecordData::a("cookie.example.com", Ipv4Addr::new(10, 0, 0, 9), 300));
+        resolver.cache().set(CacheKey::new("cookie.example.com", RecordType::A), response).await;
+        let server = UdpDnsServer::new("127.0.0.1:0".parse().unwrap(), resolver.clone()).await.unwrap();
+        let src = "127.0.0.1:1234".parse().unwrap();
+
+        let query_with = |cookie: &[u8]| {
+            let mut bytes = DnsQuery::with_id(1, "cookie.example.com", RecordType::A).to_bytes().unwrap();
+            append_opt_record(&mut bytes, 1232, false, &[(EDNS_OPTION_COOKIE, cookie)]);
+            bytes
+        };
+
+        // A client cookie is answered with a server cookie
+        let response_bytes = server.handle_query(&query_with(&[1u8; 8]), src).await.unwrap();
+        assert_eq!(DnsResponse::from_bytes(&response_bytes).unwrap().answers.len(), 1);
+        let opt = WireSummary::parse(&response_bytes).unwrap().opt.unwrap();
+        let cookie = opt.option(EDNS_OPTION_COOKIE).unwrap().to_vec();
+        assert_eq!(cookie.len(), 24);
+
+        // Enforced: only the echoed server cookie gets an answer
+        resolver.cookies().set_mode(CookieMode::Enforce);
+        let response_bytes = server.handle_query(&query_with(&[1u8; 8]), src).await.unwrap();
+        let summary = WireSummary::parse(&response_bytes).unwrap();
+        assert_eq!((summary.rcode, summary.opt.unwrap().extended_rcode), (7, 1));
+
+        let response_bytes = server.handle_query(&query_with(&cookie), src).await.unwrap();
+        assert_eq!(DnsResponse::from_bytes(&response_bytes).unwrap().answers.len(), 1);
+
+        // Queries without cookies are still answered
+        let plain = DnsQuery::with_id(2, "cookie.example.com", RecordType::A).to_bytes().unwrap();
+        let response_bytes = server.handle_query(&plain, src).await.unwrap();
+        assert_eq!(DnsResponse::from_bytes(&response_bytes).unwrap().answers.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_servfail_carries_cookie() {
+        use crate::dns::message::{append_opt_record, WireSummary, EDNS_OPTION_COOKIE};
+
+        // No upstreams, so an uncached name fails to resolve
+        let server = UdpDnsServer::new("127.0.0.1:0".parse().unwrap(), create_test_resolver()).await.unwrap();
+        let mut query = DnsQuery::with_id(4, "unreachable.example.com", RecordType::A).to_bytes().unwrap();
+        append_opt_record(&mut query, 1232, false, &[(EDNS_OPTION_COOKIE, &[1u8; 8])]);
+
+        let response_bytes = server.handle_query(&query, "127.0.0.1:1234".parse().unwrap()).await.unwrap();
+        let summary = WireSummary::parse(&response_bytes).unwrap();
+        assert_eq!(summary.rcode, 2);
+        let opt = summary.opt.unwrap();
+        assert_eq!(opt.option(EDNS_OPTION_COOKIE).unwrap().len(), 24);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_responses() {
+        use crate::dns::message::WireSummary;
+        use crate::dns::rrl::RrlConfig;
+
+        let resolver = create_test_resolver();
+        let mut response = DnsResponse::new(0);
+        response.add_answer(DnsRecordData::a("rrl.example.com", Ipv4Addr::new(10, 0, 0, 7), 300));
+        resolver.cache().set(CacheKey::new("rrl.example.com", RecordType::A), response).await;
+        let rrl = Arc::new(ResponseRateLimiter::new(RrlConfig { responses_per_second: 1, slip: 2 }));
+        let server = UdpDnsServer::new("127.0.0.1:0".parse().unwrap(), resolver)
+            .await
+            .unwrap()
+            .with_rate_limiter(rrl.clone());
+        let src = "192.0.2.10:5353".parse().unwrap();
+        let query = DnsQuery::with_id(4, "rrl.example.com", RecordType::A).to_bytes().unwrap();
+
+        // One answer a second; beyond that every second response slips
+        let first = server.respond(&query, src).await.unwrap().unwrap();
+        assert_eq!(DnsResponse::from_bytes(&first).unwrap().answers.len(), 1);
+        assert!(server.respond(&query, src).await.unwrap().is_none());
+        let slipped = server.respond(&query, src).await.unwrap().unwrap();
+        assert_eq!(slipped[2] & 0x02, 0x02);
+        assert_eq!(WireSummary::parse(&slipped).unwrap().rcode, 0);
+        assert!(DnsResponse::from_bytes(&slipped).unwrap().answers.is_empty());
+
+        let status = rrl.status();
+        assert_eq!((status.responses, status.dropped, status.slipped), (3, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_handle_looped_query() {
+        use crate::dns::message::{append_opt_record, WireSummary};
+
+        let resolver = create_test_resolver();
+        let server = UdpDnsServer::new("127.0.0.1:0".parse().unwrap(), resolver).await.unwrap();
+        let detected = loop_guard().status().detected;
+
+        // A query carrying our own marker came back round and is refused
+        let mut query_bytes = DnsQuery::with_id(3, "loop.example.com", RecordType::A).to_bytes().unwrap();
+        append_opt_record(&mut query_bytes, 1232, false, &[loop_guard().marker()]);
+        let response_bytes = server.handle_query(&query_bytes, "127.0.0.1:1234".parse().unwrap()).await.unwrap();
+        assert_eq!(WireSummary::parse(&response_bytes).unwrap().rcode, 5);
+        assert!(loop_guard().status().detected > detected);
+    }
+
     #[tokio::test]
     async fn test_handle_invalid_query() {
         let resolver = create_test_resolver();
@@ -0,0 +1,217 @@
+//! Answer shuffling
+//!
+//! Some upstreams always return their address records in the same order,
+//! so clients that pick the first address all land on the same host. With
+//! shuffling enabled, globally or for matching names, the A and AAAA records
+//! of upstream answers are put in random order (RFC 1794 round-robin) before
+//! they are cached, and again on every cache hit. Other records, such as a
+//! CNAME chain in front of the addresses, keep their position.
+
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::db::Database;
+use super::cache::NamePattern;
+use super::message::{DnsResponse, RecordType};
+use super::name::normalize_name;
+
+/// Config key for shuffling every answer
+pub const CONFIG_KEY_SHUFFLE_ANSWERS: &str = "shuffle_answers";
+/// Config key for the names shuffled when not enabled globally
+pub const CONFIG_KEY_SHUFFLE_ANSWER_DOMAINS: &str = "shuffle_answer_domains";
+
+/// Answer shuffling settings
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ShuffleSettings {
+    /// Shuffle answers for every name
+    pub enabled: bool,
+    /// Names to shuffle when not enabled globally; `*.example.com` also
+    /// matches every subdomain
+    pub domains: Vec<String>,
+}
+
+struct ActiveShuffle {
+    settings: ShuffleSettings,
+    patterns: Vec<NamePattern>,
+}
+
+/// Shuffles address records of upstream and cached answers
+pub struct AnswerShuffle {
+    db: Option<Arc<Database>>,
+    active: RwLock<ActiveShuffle>,
+}
+
+#[allow(dead_code)]
+impl AnswerShuffle {
+    pub fn new(db: Option<Arc<Database>>) -> Self {
+        Self {
+            db,
+            active: RwLock::new(ActiveShuffle {
+                settings: ShuffleSettings::default(),
+                patterns: Vec::new(),
+            }),
+        }
+    }
+
+    /// Load settings from database
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let config = db.system_config();
+        let settings = ShuffleSettings {
+            enabled: config
+                .get(CONFIG_KEY_SHUFFLE_ANSWERS)
+                .await?
+                .is_some_and(|v| v == "true"),
+            domains: config
+                .get(CONFIG_KEY_SHUFFLE_ANSWER_DOMAINS)
+                .await?
+                .and_then(|v| serde_json::from_str(&v).ok())
+                .unwrap_or_default(),
+        };
+
+        self.set_settings(settings);
+        Ok(())
+    }
+
+    /// Persist and apply settings
+    pub async fn save_settings(&self, settings: ShuffleSettings) -> Result<()> {
+        if let Some(ref db) = self.db {
+            let config = db.system_config();
+            config
+                .set(CONFIG_KEY_SHUFFLE_ANSWERS, if settings.enabled { "true" } else { "false" })
+                .await?;
+            config
+                .set(CONFIG_KEY_SHUFFLE_ANSWER_DOMAINS, &serde_json::to_string(&settings.domains)?)
+                .await?;
+        }
+        self.set_settings(settings);
+        Ok(())
+    }
+
+    pub fn settings(&self) -> ShuffleSettings {
+        self.active.read().unwrap().settings.clone()
+    }
+
+    pub fn set_settings(&self, settings: ShuffleSettings) {
+        let patterns = settings.domains.iter().map(|d| NamePattern::parse(d)).collect();
+        *self.active.write().unwrap() = ActiveShuffle { settings, patterns };
+    }
+
+    /// Whether answers for a name are shuffled
+    pub fn applies_to(&self, name: &str) -> bool {
+        let active = self.active.read().unwrap();
+        if active.settings.enabled {
+            return true;
+        }
+        if active.patterns.is_empty() {
+            return false;
+        }
+        let name = normalize_name(name);
+        active.patterns.iter().any(|p| p.matches(&name))
+    }
+
+    /// Shuffle the address records of a response for `name` if enabled
+    pub fn apply(&self, name: &str, response: &mut DnsResponse) {
+        if self.applies_to(name) {
+            shuffle_addresses(response);
+        }
+    }
+}
+
+impl Default for AnswerShuffle {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+/// Shuffle the A and AAAA answers among their own positions
+fn shuffle_addresses(response: &mut DnsResponse) {
+    let mut rng = rand::thread_rng();
+    for record_type in [RecordType::A, RecordType::AAAA] {
+        let positions: Vec<usize> = response
+            .answers
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.record_type == record_type)
+            .map(|(i, _)| i)
+            .collect();
+        if positions.len() < 2 {
+            continue;
+        }
+        let mut order = positions.clone();
+        order.shuffle(&mut rng);
+        let records: Vec<_> = order.iter().map(|&i| response.answers[i].clone()).collect();
+        for (&position, record) in positions.iter().zip(records) {
+            response.answers[position] = record;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::DnsRecordData;
+    use std::collections::HashSet;
+    use std::net::Ipv4Addr;
+
+    fn response() -> DnsResponse {
+        let mut response = DnsResponse::new(1);
+        response.add_answer(DnsRecordData::cname("www.example.com", "cdn.example.net", 300));
+        for i in 1..=8 {
+            response.add_answer(DnsRecordData::a("cdn.example.net", Ipv4Addr::new(192, 0, 2, i), 300));
+        }
+        response
+    }
+
+    #[test]
+    fn test_shuffle_keeps_records_and_cname_first() {
+        let shuffle = AnswerShuffle::new(None);
+        shuffle.set_settings(ShuffleSettings {
+            enabled: true,
+            domains: Vec::new(),
+        });
+
+        let original = response();
+        let mut orders = HashSet::new();
+        for _ in 0..50 {
+            let mut shuffled = original.clone();
+            shuffle.apply("www.example.com", &mut shuffled);
+            assert_eq!(shuffled.answers[0].record_type, RecordType::CNAME);
+            let mut values: Vec<String> = shuffled.answers.iter().map(|a| a.value.clone()).collect();
+            orders.insert(values.clone());
+            values.sort();
+            let mut expected: Vec<String> = original.answers.iter().map(|a| a.value.clone()).collect();
+            expected.sort();
+            assert_eq!(values, expected);
+        }
+        assert!(orders.len() > 1);
+    }
+
+    #[test]
+    fn test_shuffle_per_domain() {
+        let shuffle = AnswerShuffle::new(None);
+        assert!(!shuffle.applies_to("www.example.com"));
+
+        shuffle.set_settings(ShuffleSettings {
+            enabled: false,
+            domains: vec!["*.Example.com".to_string(), "cdn.example.net".to_string()],
+        });
+        assert!(shuffle.applies_to("WWW.example.com."));
+        assert!(shuffle.applies_to("example.com"));
+        assert!(shuffle.applies_to("cdn.example.net"));
+        assert!(!shuffle.applies_to("img.cdn.example.net"));
+        assert!(!shuffle.applies_to("example.org"));
+
+        let values = |r: &DnsResponse| r.answers.iter().map(|a| a.value.clone()).collect::<Vec<_>>();
+        let original = response();
+        let mut unchanged = original.clone();
+        shuffle.apply("example.org", &mut unchanged);
+        assert_eq!(values(&unchanged), values(&original));
+    }
+}
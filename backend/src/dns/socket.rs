@@ -0,0 +1,260 @@
+//! Socket helpers
+//!
+//! Creates listener sockets that can optionally be bound to a network
+//! interface with `SO_BINDTODEVICE`, so that on multi-homed hosts a
+//! listener only accepts traffic arriving on that interface regardless of
+//! which addresses the interface holds. Interface binding is only
+//! available on Linux; elsewhere it fails with a descriptive error.
+//!
+//! Outbound upstream sockets use the same mechanism through
+//! [`SourceBinding`], which pins queries to a local source address and/or
+//! interface (e.g. a specific WAN or VPN link).
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpSocket, TcpStream};
+
+/// Maximum interface name length (IFNAMSIZ minus the trailing NUL)
+const MAX_INTERFACE_NAME_LEN: usize = 15;
+
+/// Whether sockets can be bound to an interface on this platform
+pub fn interface_binding_supported() -> bool {
+    cfg!(any(target_os = "linux", target_os = "android"))
+}
+
+/// Validate an interface name for use with [`bind_udp`] and [`bind_tcp`]
+///
+/// Checks the name format and platform support, and on Linux that the
+/// interface exists.
+pub fn validate_interface(name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > MAX_INTERFACE_NAME_LEN {
+        return Err(anyhow!(
+            "Interface name must be 1-{} characters",
+            MAX_INTERFACE_NAME_LEN
+        ));
+    }
+    if name == "." || name == ".." || name.chars().any(|c| c == '/' || c.is_whitespace()) {
+        return Err(anyhow!("Invalid interface name '{}'", name));
+    }
+    if !interface_binding_supported() {
+        return Err(anyhow!(
+            "Binding to a network interface is only supported on Linux"
+        ));
+    }
+    #[cfg(target_os = "linux")]
+    {
+        if !std::path::Path::new("/sys/class/net").join(name).exists() {
+            return Err(anyhow!("Network interface '{}' does not exist", name));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bind_device(socket: &Socket, interface: &str) -> Result<()> {
+    socket
+        .bind_device(Some(interface.as_bytes()))
+        .map_err(|e| anyhow!("Failed to bind socket to interface {}: {}", interface, e))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn bind_device(_socket: &Socket, interface: &str) -> Result<()> {
+    Err(anyhow!(
+        "Cannot bind to interface {}: only supported on Linux",
+        interface
+    ))
+}
+
+fn new_socket(addr: SocketAddr, ty: Type, protocol: Protocol, interface: Option<&str>) -> Result<Socket> {
+    let socket = Socket::new(Domain::for_address(addr), ty, Some(protocol))
+        .map_err(|e| anyhow!("Failed to create socket: {}", e))?;
+    if let Some(interface) = interface {
+        bind_device(&socket, interface)?;
+    }
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+/// Bind a non-blocking UDP socket, optionally restricted to an interface
+///
+/// Returns a std socket so it can be handed to tokio or quinn.
+pub fn bind_udp(addr: SocketAddr, interface: Option<&str>) -> Result<std::net::UdpSocket> {
+    let socket = new_socket(addr, Type::DGRAM, Protocol::UDP, interface)?;
+    socket
+        .bind(&addr.into())
+        .map_err(|e| anyhow!("Failed to bind UDP socket to {}: {}", addr, e))?;
+    Ok(socket.into())
+}
+
+/// Bind a TCP listener, optionally restricted to an interface
+pub fn bind_tcp(addr: SocketAddr, interface: Option<&str>) -> Result<tokio::net::TcpListener> {
+    let socket = new_socket(addr, Type::STREAM, Protocol::TCP, interface)?;
+    socket.set_reuse_address(true)?;
+    socket
+        .bind(&addr.into())
+        .map_err(|e| anyhow!("Failed to bind TCP listener to {}: {}", addr, e))?;
+    socket.listen(1024)?;
+    Ok(tokio::net::TcpListener::from_std(socket.into())?)
+}
+
+/// Local source address and interface for outbound queries
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SourceBinding {
+    pub ip: Option<IpAddr>,
+    pub interface: Option<String>,
+}
+
+impl SourceBinding {
+    #[allow(dead_code)]
+    pub fn new(ip: Option<IpAddr>, interface: Option<String>) -> Self {
+        Self { ip, interface }
+    }
+
+    /// Parse stored settings, treating empty strings as unset
+    pub fn parse(ip: Option<&str>, interface: Option<&str>) -> Result<Self> {
+        let ip = match ip.map(str::trim).filter(|s| !s.is_empty()) {
+            Some(s) => Some(
+                s.parse::<IpAddr>()
+                    .map_err(|_| anyhow!("Invalid source IP address '{}'", s))?,
+            ),
+            None => None,
+        };
+        let interface = interface
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string);
+        Ok(Self { ip, interface })
+    }
+
+    /// Whether the OS picks the source (no address or interface configured)
+    pub fn is_default(&self) -> bool {
+        self.ip.is_none() && self.interface.is_none()
+    }
+
+    /// Fill unset fields from another binding (e.g. the global default)
+    pub fn or(self, fallback: &SourceBinding) -> Self {
+        Self {
+            ip: self.ip.or(fallback.ip),
+            interface: self.interface.or_else(|| fallback.interface.clone()),
+        }
+    }
+
+    /// Local address to bind for reaching `target`
+    pub fn local_addr(&self, target: SocketAddr) -> Result<SocketAddr> {
+        match self.ip {
+            Some(ip) if ip.is_ipv6() != target.is_ipv6() => Err(anyhow!(
+                "Source address {} cannot reach {} (address family mismatch)",
+                ip,
+                target
+            )),
+            Some(ip) => Ok(SocketAddr::new(ip, 0)),
+            None if target.is_ipv6() => Ok(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0)),
+            None => Ok(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0)),
+        }
+    }
+
+    /// Pick the resolved address that this binding can reach
+    pub fn select_target(&self, addrs: &[SocketAddr]) -> Option<SocketAddr> {
+        match self.ip {
+            Some(ip) => addrs.iter().find(|a| a.is_ipv6() == ip.is_ipv6()).copied(),
+            None => addrs.iter().find(|a| a.is_ipv4()).or_else(|| addrs.first()).copied(),
+        }
+    }
+
+    /// Bind a UDP socket for sending to `target`
+    pub fn bind_udp(&self, target: SocketAddr) -> Result<std::net::UdpSocket> {
+        bind_udp(self.local_addr(target)?, self.interface.as_deref())
+    }
+
+    /// Open a TCP connection to `target` from this source
+    pub async fn connect_tcp(&self, target: SocketAddr) -> Result<TcpStream> {
+        let local = self.local_addr(target)?;
+        let socket = new_socket(local, Type::STREAM, Protocol::TCP, self.interface.as_deref())?;
+        if self.ip.is_some() {
+            socket
+                .bind(&local.into())
+                .map_err(|e| anyhow!("Failed to bind source address {}: {}", local, e))?;
+        }
+        let socket = TcpSocket::from_std_stream(socket.into());
+        socket
+            .connect(target)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to {}: {}", target, e))
+    }
+}
+
+impl std::fmt::Display for SourceBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.ip, &self.interface) {
+            (Some(ip), Some(iface)) => write!(f, "{} via {}", ip, iface),
+            (Some(ip), None) => write!(f, "{}", ip),
+            (None, Some(iface)) => write!(f, "via {}", iface),
+            (None, None) => write!(f, "default"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_interface_name() {
+        assert!(validate_interface("").is_err());
+        assert!(validate_interface("averyveryverylongname").is_err());
+        assert!(validate_interface("eth0/1").is_err());
+        assert!(validate_interface("eth 0").is_err());
+        assert!(validate_interface("..").is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_validate_interface_exists() {
+        assert!(validate_interface("lo").is_ok());
+        assert!(validate_interface("nosuchif0").is_err());
+    }
+
+    #[test]
+    fn test_source_binding_parse() {
+        let source = SourceBinding::parse(Some(" 192.0.2.1 "), Some("")).unwrap();
+        assert_eq!(source.ip, Some("192.0.2.1".parse().unwrap()));
+        assert!(source.interface.is_none());
+        assert!(SourceBinding::parse(Some(""), None).unwrap().is_default());
+        assert!(SourceBinding::parse(Some("not-an-ip"), None).is_err());
+    }
+
+    #[test]
+    fn test_source_binding_local_addr() {
+        let v4: SocketAddr = "1.1.1.1:53".parse().unwrap();
+        let v6: SocketAddr = "[2606:4700::1111]:53".parse().unwrap();
+
+        let default = SourceBinding::default();
+        assert_eq!(default.local_addr(v4).unwrap(), "0.0.0.0:0".parse().unwrap());
+        assert_eq!(default.local_addr(v6).unwrap(), "[::]:0".parse().unwrap());
+
+        let source = SourceBinding::new(Some("192.0.2.1".parse().unwrap()), None);
+        assert_eq!(source.local_addr(v4).unwrap(), "192.0.2.1:0".parse().unwrap());
+        assert!(source.local_addr(v6).is_err());
+        assert_eq!(source.select_target(&[v6, v4]), Some(v4));
+    }
+
+    #[test]
+    fn test_source_binding_fallback() {
+        let global = SourceBinding::new(Some("192.0.2.1".parse().unwrap()), Some("wg0".to_string()));
+        let own = SourceBinding::new(None, Some("eth1".to_string()));
+        let merged = own.or(&global);
+        assert_eq!(merged.ip, global.ip);
+        assert_eq!(merged.interface.as_deref(), Some("eth1"));
+    }
+
+    #[tokio::test]
+    async fn test_bind_without_interface() {
+        let udp = bind_udp("127.0.0.1:0".parse().unwrap(), None).unwrap();
+        assert!(udp.local_addr().unwrap().port() > 0);
+        let tcp = bind_tcp("127.0.0.1:0".parse().unwrap(), None).unwrap();
+        assert!(tcp.local_addr().unwrap().port() > 0);
+    }
+}
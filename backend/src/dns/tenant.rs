@@ -0,0 +1,186 @@
+//! Tenant resolution views
+//!
+//! Maps incoming DNS clients to a tenant so the resolver can apply the
+//! tenant's own records and rewrite rules on top of the global ones.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::db::{Database, Tenant};
+use super::cidr::IpCidr;
+
+/// In-memory selector for a single tenant
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct TenantView {
+    /// Tenant ID from database
+    pub id: i64,
+    /// Tenant name
+    pub name: String,
+    /// Client networks mapped to this tenant
+    pub subnets: Vec<IpCidr>,
+    /// Listener protocols mapped to this tenant (lowercase)
+    pub listeners: Vec<String>,
+}
+
+impl TenantView {
+    /// Build from database model, skipping malformed subnets
+    pub fn from_db(tenant: &Tenant) -> Self {
+        let subnets = tenant
+            .client_subnets
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| match s.parse::<IpCidr>() {
+                Ok(net) => Some(net),
+                Err(e) => {
+                    warn!("Ignoring subnet for tenant {}: {}", tenant.name, e);
+                    None
+                }
+            })
+            .collect();
+
+        let listeners = tenant
+            .listeners
+            .split(',')
+            .map(|s| s.trim().to_lowercase())
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        Self {
+            id: tenant.id,
+            name: tenant.name.clone(),
+            subnets,
+            listeners,
+        }
+    }
+}
+
+/// Tenant registry
+///
+/// Selection order: the most specific matching client subnet wins; if no
+/// subnet matches, the first tenant bound to the listener is used.
+pub struct TenantRegistry {
+    views: RwLock<Vec<TenantView>>,
+    db: Option<Arc<Database>>,
+}
+
+#[allow(dead_code)]
+impl TenantRegistry {
+    /// Create an empty registry without database
+    pub fn new() -> Self {
+        Self {
+            views: RwLock::new(Vec::new()),
+            db: None,
+        }
+    }
+
+    /// Create a registry backed by the database
+    pub fn with_db(db: Arc<Database>) -> Self {
+        Self {
+            views: RwLock::new(Vec::new()),
+            db: Some(db),
+        }
+    }
+
+    /// Load enabled tenants from database
+    pub async fn load(&self) -> anyhow::Result<()> {
+        if let Some(ref db) = self.db {
+            let tenants = db.tenants().list_enabled().await?;
+            let views = tenants.iter().map(TenantView::from_db).collect();
+            *self.views.write().await = views;
+        }
+        Ok(())
+    }
+
+    /// Reload tenants from database
+    pub async fn reload(&self) -> anyhow::Result<()> {
+        self.load().await
+    }
+
+    /// Add a view (in-memory only)
+    pub async fn add_view(&self, view: TenantView) {
+        self.views.write().await.push(view);
+    }
+
+    /// Number of active tenants
+    pub async fn count(&self) -> usize {
+        self.views.read().await.len()
+    }
+
+    /// Select the tenant for a client
+    pub async fn select(&self, client_ip: &str, listener: Option<&str>) -> Option<i64> {
+        let views = self.views.read().await;
+        if views.is_empty() {
+            return None;
+        }
+
+        if let Ok(ip) = client_ip.parse::<IpAddr>() {
+            let best = views
+                .iter()
+                .filter_map(|v| {
+                    v.subnets
+                        .iter()
+                        .filter(|net| net.contains(&ip))
+                        .map(|net| net.prefix())
+                        .max()
+                        .map(|prefix| (prefix, v.id))
+                })
+                .max_by_key(|(prefix, _)| *prefix);
+            if let Some((_, id)) = best {
+                return Some(id);
+            }
+        }
+
+        let listener = listener?.to_lowercase();
+        views
+            .iter()
+            .find(|v| v.listeners.contains(&listener))
+            .map(|v| v.id)
+    }
+}
+
+impl Default for TenantRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(id: i64, subnets: &str, listeners: &[&str]) -> TenantView {
+        TenantView {
+            id,
+            name: format!("tenant-{}", id),
+            subnets: IpCidr::parse_list(subnets).unwrap(),
+            listeners: listeners.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_select_by_most_specific_subnet() {
+        let registry = TenantRegistry::new();
+        registry.add_view(view(1, "10.0.0.0/8", &[])).await;
+        registry.add_view(view(2, "10.1.0.0/16", &[])).await;
+
+        assert_eq!(registry.select("10.1.2.3", None).await, Some(2));
+        assert_eq!(registry.select("10.2.0.1", None).await, Some(1));
+        assert_eq!(registry.select("192.168.0.1", None).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_select_by_listener_fallback() {
+        let registry = TenantRegistry::new();
+        registry.add_view(view(1, "10.0.0.0/8", &[])).await;
+        registry.add_view(view(2, "", &["dot"])).await;
+
+        assert_eq!(registry.select("192.168.0.1", Some("DoT")).await, Some(2));
+        assert_eq!(registry.select("10.0.0.1", Some("dot")).await, Some(1));
+        assert_eq!(registry.select("192.168.0.1", Some("udp")).await, None);
+    }
+}
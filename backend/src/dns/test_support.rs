@@ -0,0 +1,252 @@
+//! Test support for end-to-end pipeline tests
+//!
+//! Provides an in-process mock upstream DNS server with programmable
+//! answers, delays and failures, and a pipeline harness that runs a real
+//! UDP listener in front of a resolver forwarding to mock upstreams. Both
+//! bind to ephemeral loopback ports, so tests need no network access.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+
+use super::cache::{CacheConfig, CacheManager};
+use super::message::{DnsQuery, DnsRecordData, DnsResponse, DnsResponseCode, RecordType};
+use super::name::normalize_name;
+use super::proxy::{ProxyManager, QueryStrategy, UpstreamManager, UpstreamProtocol, UpstreamServer};
+use super::resolver::DnsResolver;
+use super::rewrite::RewriteEngine;
+use super::server::UdpDnsServer;
+
+/// Upstream timeout used by the harness, short enough to keep failover tests quick
+pub const MOCK_UPSTREAM_TIMEOUT_MS: u32 = 300;
+
+/// How the mock upstream answers a name
+#[derive(Debug, Clone)]
+pub enum MockAnswer {
+    /// NOERROR with these answer records
+    Records(Vec<DnsRecordData>),
+    /// An empty response with this response code
+    Rcode(DnsResponseCode),
+    /// No response at all, so the client times out
+    Drop,
+}
+
+#[derive(Debug, Clone)]
+struct MockRule {
+    answer: MockAnswer,
+    delay: Duration,
+}
+
+#[derive(Default)]
+struct MockState {
+    rules: HashMap<String, MockRule>,
+    /// Answer for names without a rule (NXDOMAIN when unset)
+    fallback: Option<MockRule>,
+    /// Delay added to every answer
+    delay: Duration,
+    queries: HashMap<String, usize>,
+}
+
+/// In-process mock upstream DNS server on a loopback UDP port
+pub struct MockUpstream {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    total: Arc<AtomicUsize>,
+    handle: JoinHandle<()>,
+}
+
+impl MockUpstream {
+    /// Bind to an ephemeral loopback port and start answering
+    pub async fn start() -> Self {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.expect("mock upstream should bind"));
+        let addr = socket.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let total = Arc::new(AtomicUsize::new(0));
+
+        let handle = {
+            let state = state.clone();
+            let total = total.clone();
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; 4096];
+                loop {
+                    let Ok((len, src)) = socket.recv_from(&mut buf).await else {
+                        continue;
+                    };
+                    let Ok(query) = DnsQuery::from_bytes(&buf[..len]) else {
+                        continue;
+                    };
+                    total.fetch_add(1, Ordering::SeqCst);
+                    let rule = Self::rule_for(&state, &query);
+
+                    // Answer from a separate task so a slow name does not hold up others
+                    let socket = socket.clone();
+                    tokio::spawn(async move {
+                        if !rule.delay.is_zero() {
+                            tokio::time::sleep(rule.delay).await;
+                        }
+                        let mut response = DnsResponse::new(query.id);
+                        match rule.answer {
+                            MockAnswer::Records(records) => response.answers = records,
+                            MockAnswer::Rcode(code) => response.response_code = code,
+                            MockAnswer::Drop => return,
+                        }
+                        if let Ok(bytes) = response.to_bytes(&query) {
+                            let _ = socket.send_to(&bytes, src).await;
+                        }
+                    });
+                }
+            })
+        };
+
+        Self {
+            addr,
+            state,
+            total,
+            handle,
+        }
+    }
+
+    /// Count the query and pick the rule that answers it
+    fn rule_for(state: &Mutex<MockState>, query: &DnsQuery) -> MockRule {
+        let name = normalize_name(&query.name);
+        let mut state = state.lock().unwrap();
+        *state.queries.entry(name.clone()).or_default() += 1;
+        let mut rule = state.rules.get(&name).cloned().or_else(|| state.fallback.clone()).unwrap_or(MockRule {
+            answer: MockAnswer::Rcode(DnsResponseCode::NxDomain),
+            delay: Duration::ZERO,
+        });
+        rule.delay += state.delay;
+        rule
+    }
+
+    /// Answer `name` as programmed
+    pub fn respond(&self, name: &str, answer: MockAnswer) -> &Self {
+        self.respond_after(name, answer, Duration::ZERO)
+    }
+
+    /// Answer `name` as programmed after a delay
+    pub fn respond_after(&self, name: &str, answer: MockAnswer, delay: Duration) -> &Self {
+        self.state
+            .lock()
+            .unwrap()
+            .rules
+            .insert(normalize_name(name), MockRule { answer, delay });
+        self
+    }
+
+    /// Answer `name` with a single A record
+    pub fn answer_a(&self, name: &str, ip: &str, ttl: u32) -> &Self {
+        let record = DnsRecordData::a(normalize_name(name), ip.parse().expect("valid IPv4 address"), ttl);
+        self.respond(name, MockAnswer::Records(vec![record]))
+    }
+
+    /// Answer every name without its own rule this way
+    pub fn respond_to_all(&self, answer: MockAnswer) -> &Self {
+        self.state.lock().unwrap().fallback = Some(MockRule {
+            answer,
+            delay: Duration::ZERO,
+        });
+        self
+    }
+
+    /// Delay every answer
+    pub fn set_delay(&self, delay: Duration) -> &Self {
+        self.state.lock().unwrap().delay = delay;
+        self
+    }
+
+    /// Queries received in total
+    pub fn queries(&self) -> usize {
+        self.total.load(Ordering::SeqCst)
+    }
+
+    /// Queries received for one name
+    pub fn queries_for(&self, name: &str) -> usize {
+        let state = self.state.lock().unwrap();
+        state.queries.get(&normalize_name(name)).copied().unwrap_or(0)
+    }
+
+    /// UDP upstream server pointing at this mock
+    pub fn upstream(&self, id: i64, name: &str) -> UpstreamServer {
+        UpstreamServer::new(id, name, self.addr.to_string(), UpstreamProtocol::Udp, MOCK_UPSTREAM_TIMEOUT_MS)
+    }
+}
+
+impl Drop for MockUpstream {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// A UDP listener, resolver and proxy forwarding to the given upstreams
+pub struct TestPipeline {
+    pub upstream_manager: Arc<UpstreamManager>,
+    pub resolver: Arc<DnsResolver>,
+    listener_addr: SocketAddr,
+    handle: JoinHandle<()>,
+}
+
+impl TestPipeline {
+    /// Start a listener on an ephemeral loopback port
+    pub async fn start(upstreams: Vec<UpstreamServer>, strategy: QueryStrategy) -> Self {
+        let upstream_manager = Arc::new(UpstreamManager::new());
+        for server in upstreams {
+            upstream_manager.add_server(server).await;
+        }
+        let proxy = Arc::new(ProxyManager::new(upstream_manager.clone()));
+        proxy.set_strategy(strategy).await;
+        let cache = Arc::new(CacheManager::with_config(CacheConfig {
+            default_ttl: 60,
+            max_entries: 1000,
+            max_memory_mb: 0,
+        }));
+        let resolver = Arc::new(DnsResolver::new(Arc::new(RewriteEngine::new()), cache, proxy));
+
+        let server = Arc::new(
+            UdpDnsServer::new("127.0.0.1:0".parse().unwrap(), resolver.clone())
+                .await
+                .expect("listener should bind"),
+        );
+        let listener_addr = server.local_addr().unwrap();
+        let handle = tokio::spawn(async move {
+            let _ = server.run().await;
+        });
+
+        Self {
+            upstream_manager,
+            resolver,
+            listener_addr,
+            handle,
+        }
+    }
+
+    /// Send a query to the listener over UDP and decode the answer
+    pub async fn query(&self, name: &str, record_type: RecordType) -> DnsResponse {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let query = DnsQuery::new(name, record_type);
+        socket
+            .send_to(&query.to_bytes().unwrap(), self.listener_addr)
+            .await
+            .unwrap();
+
+        let mut buf = vec![0u8; 4096];
+        let (len, _) = tokio::time::timeout(Duration::from_secs(5), socket.recv_from(&mut buf))
+            .await
+            .expect("listener should answer")
+            .unwrap();
+        let response = DnsResponse::from_bytes(&buf[..len]).expect("answer should parse");
+        assert_eq!(response.id, query.id);
+        response
+    }
+}
+
+impl Drop for TestPipeline {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
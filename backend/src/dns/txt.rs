@@ -0,0 +1,173 @@
+//! TXT character-strings
+//!
+//! A TXT record holds one or more character-strings of up to 255 bytes
+//! each; DKIM keys and long SPF records rely on the split. Record values
+//! use the zone file presentation format: a value starting with `"` is a
+//! sequence of quoted strings (`"v=DKIM1; p=MIIB..." "IDAQAB"`) with `\"`,
+//! `\\` and `\DDD` escapes, anything else is plain text cut into 255-byte
+//! strings. Answers decoded from the wire are formatted the same way, so
+//! the segmentation and binary content survive a round trip.
+
+/// Longest character-string in a TXT record
+pub const MAX_TXT_STRING_LEN: usize = 255;
+
+/// Character-strings of a TXT value, rejecting malformed quoting
+pub fn parse_txt(value: &str) -> Result<Vec<Vec<u8>>, String> {
+    if !value.trim_start().starts_with('"') {
+        return Ok(split_txt(value.as_bytes()));
+    }
+
+    let mut strings = Vec::new();
+    let mut bytes = value.trim().bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b' ' | b'\t' => continue,
+            b'"' => {}
+            _ => return Err("TXT strings must be quoted when the value starts with a quote".to_string()),
+        }
+
+        let mut string = Vec::new();
+        loop {
+            match bytes.next() {
+                None => return Err("Unterminated quoted string in TXT value".to_string()),
+                Some(b'"') => break,
+                Some(b'\\') => match bytes.next() {
+                    None => return Err("Unterminated quoted string in TXT value".to_string()),
+                    Some(d) if d.is_ascii_digit() => {
+                        let digits = [Some(d), bytes.next(), bytes.next()];
+                        let code = digits
+                            .iter()
+                            .try_fold(0u32, |acc, d| match d {
+                                Some(d) if d.is_ascii_digit() => Some(acc * 10 + u32::from(d - b'0')),
+                                _ => None,
+                            })
+                            .and_then(|code| u8::try_from(code).ok())
+                            .ok_or_else(|| "Invalid \\DDD escape in TXT value".to_string())?;
+                        string.push(code);
+                    }
+                    Some(c) => string.push(c),
+                },
+                Some(c) => string.push(c),
+            }
+        }
+        if string.len() > MAX_TXT_STRING_LEN {
+            return Err(format!("TXT character-string exceeds {} bytes", MAX_TXT_STRING_LEN));
+        }
+        strings.push(string);
+    }
+    Ok(strings)
+}
+
+/// Character-strings of a TXT value, treating malformed quoting as plain text
+pub fn txt_strings(value: &str) -> Vec<Vec<u8>> {
+    parse_txt(value).unwrap_or_else(|_| split_txt(value.as_bytes()))
+}
+
+/// Plain text cut into character-strings
+fn split_txt(text: &[u8]) -> Vec<Vec<u8>> {
+    if text.is_empty() {
+        return vec![Vec::new()];
+    }
+    text.chunks(MAX_TXT_STRING_LEN).map(<[u8]>::to_vec).collect()
+}
+
+/// Presentation form of TXT character-strings
+///
+/// A single printable string is shown as is; anything else is quoted.
+pub fn format_txt(strings: &[Vec<u8>]) -> String {
+    if let [single] = strings {
+        if let Ok(text) = std::str::from_utf8(single) {
+            if text.trim() == text && !text.starts_with('"') && !text.chars().any(char::is_control) {
+                return text.to_string();
+            }
+        }
+    }
+    strings
+        .iter()
+        .map(|s| format!("\"{}\"", escape_txt(s)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// One character-string with quotes, backslashes and unprintable bytes escaped
+pub fn escape_txt(string: &[u8]) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    match std::str::from_utf8(string) {
+        Ok(text) => {
+            for c in text.chars() {
+                match c {
+                    '"' | '\\' => {
+                        escaped.push('\\');
+                        escaped.push(c);
+                    }
+                    c if c.is_control() => {
+                        let mut buf = [0u8; 4];
+                        for b in c.encode_utf8(&mut buf).bytes() {
+                            escaped.push_str(&format!("\\{:03}", b));
+                        }
+                    }
+                    c => escaped.push(c),
+                }
+            }
+        }
+        Err(_) => {
+            for &b in string {
+                match b {
+                    b'"' | b'\\' => {
+                        escaped.push('\\');
+                        escaped.push(char::from(b));
+                    }
+                    0x20..=0x7e => escaped.push(char::from(b)),
+                    _ => escaped.push_str(&format!("\\{:03}", b)),
+                }
+            }
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(parse_txt("v=spf1 -all").unwrap(), vec![b"v=spf1 -all".to_vec()]);
+        assert_eq!(parse_txt("").unwrap(), vec![Vec::<u8>::new()]);
+
+        let long = "k".repeat(300);
+        let strings = parse_txt(&long).unwrap();
+        assert_eq!(strings.iter().map(Vec::len).collect::<Vec<_>>(), vec![255, 45]);
+        assert_eq!(format_txt(&strings), format!("\"{}\" \"{}\"", "k".repeat(255), "k".repeat(45)));
+    }
+
+    #[test]
+    fn test_quoted_strings() {
+        let strings = parse_txt(r#""v=DKIM1; p=MIIB" "IDAQAB""#).unwrap();
+        assert_eq!(strings, vec![b"v=DKIM1; p=MIIB".to_vec(), b"IDAQAB".to_vec()]);
+
+        let strings = parse_txt(r#""say \"hi\"" "a\\b" "\000\255""#).unwrap();
+        assert_eq!(strings, vec![b"say \"hi\"".to_vec(), b"a\\b".to_vec(), vec![0, 255]]);
+
+        assert!(parse_txt(r#""open"#).is_err());
+        assert!(parse_txt(r#""a" b"#).is_err());
+        assert!(parse_txt(r#""\256""#).is_err());
+        assert!(parse_txt(&format!("\"{}\"", "x".repeat(256))).is_err());
+    }
+
+    #[test]
+    fn test_format_round_trip() {
+        let cases = vec![
+            vec![b"hello world".to_vec()],
+            vec![b"first".to_vec(), b"second".to_vec()],
+            vec![vec![0xff, 0x00, b'"', b'\\'], Vec::new()],
+            vec!["\"quoted\" start".as_bytes().to_vec()],
+            vec!["日本語".as_bytes().to_vec()],
+        ];
+        for strings in cases {
+            assert_eq!(parse_txt(&format_txt(&strings)).unwrap(), strings);
+        }
+        assert_eq!(format_txt(&[b"hello world".to_vec()]), "hello world");
+        assert_eq!(format_txt(&[vec![0xff, b'a']]), r#""\255a""#);
+    }
+}
@@ -0,0 +1,311 @@
+//! Typo-squatting protection
+//!
+//! Flags queries that look like misspellings of reference domains (popular
+//! or allowlisted sites), which is how phishing lookalikes are usually
+//! found. A query matches when its trailing labels are within a small edit
+//! distance of a reference domain, counting transpositions as one edit:
+//! `www.gooogle.com` and `goolge.com` both match `google.com`, while
+//! `mail.google.com` does not.
+//!
+//! In flag mode, lookalikes that resolve to NXDOMAIN are logged and
+//! recorded as detections. In block mode, every lookalike query is answered
+//! with NXDOMAIN before it reaches rewrite rules or upstreams.
+
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::db::{CreateTyposquatEvent, Database};
+use super::message::{DnsResponse, DnsResponseCode};
+use super::middleware::{HookOutcome, QueryContext, ResolverMiddleware};
+use super::name::normalize_name;
+use super::resolver::ResolveResult;
+
+/// Config key for the feature switch
+pub const CONFIG_KEY_TYPOSQUAT_ENABLED: &str = "typosquat_enabled";
+/// Config key for block mode
+pub const CONFIG_KEY_TYPOSQUAT_BLOCK: &str = "typosquat_block";
+/// Config key for the maximum edit distance
+pub const CONFIG_KEY_TYPOSQUAT_MAX_DISTANCE: &str = "typosquat_max_distance";
+
+/// Largest accepted edit distance; beyond this nearly everything matches
+pub const MAX_TYPOSQUAT_DISTANCE: usize = 3;
+
+/// References shorter than this are skipped, since one edit away from a
+/// very short name is mostly unrelated domains
+const MIN_REFERENCE_LEN: usize = 6;
+
+/// Typo-squatting settings
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TyposquatSettings {
+    pub enabled: bool,
+    /// Block lookalikes instead of only flagging NXDOMAIN results
+    pub block: bool,
+    pub max_distance: usize,
+}
+
+impl Default for TyposquatSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            block: false,
+            max_distance: 1,
+        }
+    }
+}
+
+/// A query that looks like a typo of a reference domain
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Lookalike {
+    pub suggestion: String,
+    pub distance: usize,
+}
+
+/// Optimal string alignment distance (Levenshtein plus adjacent transpositions)
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.as_bytes();
+    let b = b.as_bytes();
+    let mut prev2 = vec![0; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        cur[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            cur[j] = (prev[j] + 1).min(cur[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                cur[j] = cur[j].min(prev2[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut prev2, &mut prev);
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+/// Typo-squatting detector and resolver middleware
+pub struct TyposquatGuard {
+    db: Option<Arc<Database>>,
+    settings: RwLock<TyposquatSettings>,
+    /// Normalized reference domains
+    references: RwLock<Vec<String>>,
+}
+
+#[allow(dead_code)]
+impl TyposquatGuard {
+    pub fn new(db: Option<Arc<Database>>) -> Self {
+        Self {
+            db,
+            settings: RwLock::new(TyposquatSettings::default()),
+            references: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Load settings and reference domains from database
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let config = db.system_config();
+        let flag = |v: Option<String>| v.is_some_and(|v| v == "true");
+        let settings = TyposquatSettings {
+            enabled: flag(config.get(CONFIG_KEY_TYPOSQUAT_ENABLED).await?),
+            block: flag(config.get(CONFIG_KEY_TYPOSQUAT_BLOCK).await?),
+            max_distance: config
+                .get(CONFIG_KEY_TYPOSQUAT_MAX_DISTANCE)
+                .await?
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(1)
+                .clamp(1, MAX_TYPOSQUAT_DISTANCE),
+        };
+        let domains = db.typosquat().list_domains().await?;
+
+        self.set_settings(settings);
+        self.set_references(domains.into_iter().map(|d| d.domain).collect());
+        Ok(())
+    }
+
+    /// Reload settings and reference domains from database
+    pub async fn reload(&self) -> Result<()> {
+        self.load().await
+    }
+
+    pub fn settings(&self) -> TyposquatSettings {
+        *self.settings.read().unwrap()
+    }
+
+    pub fn set_settings(&self, settings: TyposquatSettings) {
+        *self.settings.write().unwrap() = settings;
+    }
+
+    /// Replace the reference domains (in-memory only)
+    pub fn set_references(&self, domains: Vec<String>) {
+        let domains = domains
+            .iter()
+            .map(|d| normalize_name(d))
+            .filter(|d| d.len() >= MIN_REFERENCE_LEN)
+            .collect();
+        *self.references.write().unwrap() = domains;
+    }
+
+    /// Number of active reference domains
+    pub fn reference_count(&self) -> usize {
+        self.references.read().unwrap().len()
+    }
+
+    /// Find the reference domain a name is most likely a typo of
+    ///
+    /// Returns `None` for reference domains themselves and their subdomains.
+    pub fn check(&self, name: &str, max_distance: usize) -> Option<Lookalike> {
+        let name = normalize_name(name);
+        let labels: Vec<&str> = name.split('.').collect();
+        let references = self.references.read().unwrap();
+        let mut best: Option<Lookalike> = None;
+
+        for reference in references.iter() {
+            let count = reference.split('.').count();
+            if labels.len() < count {
+                continue;
+            }
+            let tail = labels[labels.len() - count..].join(".");
+            if tail == *reference {
+                return None;
+            }
+            if tail.len().abs_diff(reference.len()) > max_distance {
+                continue;
+            }
+
+            let distance = edit_distance(&tail, reference);
+            if distance <= max_distance && best.as_ref().is_none_or(|b| distance < b.distance) {
+                best = Some(Lookalike {
+                    suggestion: reference.clone(),
+                    distance,
+                });
+            }
+        }
+        best
+    }
+
+    /// Log a detection and store it (fire and forget)
+    fn record(&self, ctx: &QueryContext, lookalike: &Lookalike, blocked: bool) {
+        warn!(
+            "[Typosquat] {} looks like {} (distance {}, client {}){}",
+            ctx.query.name,
+            lookalike.suggestion,
+            lookalike.distance,
+            ctx.client_ip.as_deref().unwrap_or("-"),
+            if blocked { ", blocked" } else { "" }
+        );
+
+        if let Some(ref db) = self.db {
+            let db = db.clone();
+            let event = CreateTyposquatEvent {
+                query_name: ctx.query.name.clone(),
+                suggestion: lookalike.suggestion.clone(),
+                distance: lookalike.distance as i64,
+                client_ip: ctx.client_ip.clone(),
+                blocked,
+            };
+            tokio::spawn(async move {
+                if let Err(e) = db.typosquat().create_event(event).await {
+                    warn!("Failed to save typo-squatting event: {}", e);
+                }
+            });
+        }
+    }
+}
+
+#[async_trait]
+impl ResolverMiddleware for TyposquatGuard {
+    fn name(&self) -> &str {
+        "typosquat_guard"
+    }
+
+    async fn pre_rewrite(&self, ctx: &mut QueryContext) -> Result<HookOutcome> {
+        let settings = self.settings();
+        if !settings.enabled || !settings.block {
+            return Ok(HookOutcome::Continue);
+        }
+
+        match self.check(&ctx.query.name, settings.max_distance) {
+            Some(lookalike) => {
+                self.record(ctx, &lookalike, true);
+                Ok(HookOutcome::Respond(DnsResponse::nxdomain(ctx.query.id)))
+            }
+            None => Ok(HookOutcome::Continue),
+        }
+    }
+
+    async fn post_response(&self, ctx: &QueryContext, result: &mut ResolveResult) -> Result<()> {
+        let settings = self.settings();
+        let flaggable = settings.enabled
+            && !settings.block
+            && result.metadata.answered_by.is_none()
+            && !result.metadata.rewrite_applied
+            && result.response.response_code == DnsResponseCode::NxDomain;
+
+        if flaggable {
+            if let Some(lookalike) = self.check(&ctx.query.name, settings.max_distance) {
+                self.record(ctx, &lookalike, false);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::message::{DnsQuery, RecordType};
+
+    fn guard() -> TyposquatGuard {
+        let guard = TyposquatGuard::new(None);
+        guard.set_references(vec!["google.com".to_string(), "paypal.com".to_string(), "x.com".to_string()]);
+        guard.set_settings(TyposquatSettings {
+            enabled: true,
+            block: true,
+            max_distance: 1,
+        });
+        guard
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("google.com", "google.com"), 0);
+        assert_eq!(edit_distance("gooogle.com", "google.com"), 1);
+        assert_eq!(edit_distance("goolge.com", "google.com"), 1);
+        assert_eq!(edit_distance("paypa1.com", "paypal.com"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_check_lookalikes() {
+        let guard = guard();
+        assert_eq!(guard.check("www.gooogle.com", 1).unwrap().suggestion, "google.com");
+        assert_eq!(guard.check("paypa1.com.", 1).unwrap().suggestion, "paypal.com");
+        assert!(guard.check("mail.google.com", 1).is_none());
+        assert!(guard.check("example.com", 1).is_none());
+        // Short references are ignored
+        assert_eq!(guard.reference_count(), 2);
+        assert!(guard.check("y.com", 1).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_block_mode_answers_nxdomain() {
+        let guard = guard();
+        let mut ctx = QueryContext::new(DnsQuery::new("goolge.com", RecordType::A), None);
+        match guard.pre_rewrite(&mut ctx).await.unwrap() {
+            HookOutcome::Respond(r) => assert_eq!(r.response_code, DnsResponseCode::NxDomain),
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+
+        let mut ctx = QueryContext::new(DnsQuery::new("google.com", RecordType::A), None);
+        assert!(matches!(guard.pre_rewrite(&mut ctx).await.unwrap(), HookOutcome::Continue));
+    }
+}
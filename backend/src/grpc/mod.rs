@@ -0,0 +1,780 @@
+//! gRPC management API module
+//!
+//! Exposes the core management operations (records, rewrite rules, upstreams,
+//! cache purge and the query test tool) as a tonic service, for automation
+//! that prefers typed RPC over REST. Handlers share the repository and DNS
+//! component layer with the REST API and reuse its request validation, so
+//! both surfaces behave the same.
+//!
+//! Only compiled with the `grpc` feature; the server starts when `grpc_port`
+//! is non-zero. Every call must carry `authorization: Bearer <token>` with
+//! either the configured `grpc_token` or an admin JWT.
+
+// tonic::Status is large, but it is the error type tonic's service traits require
+#![allow(clippy::result_large_err)]
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tonic::metadata::MetadataMap;
+use tonic::transport::{Identity, Server, ServerTlsConfig};
+use tonic::{Request, Response, Status};
+
+use crate::config::AppConfig;
+use crate::db::{CreateCachePurgeAudit, Database, DnsRecord, RewriteRule, UpstreamServer};
+use crate::dns::{CacheManager, DnsResolver, RewriteEngine, UpstreamManager};
+use crate::web::dns_query::DnsQueryRequest;
+use crate::web::hooks::{validate_pattern, MAX_PURGE_PATTERNS};
+use crate::web::records::{
+    ensure_tenant_exists, reload_local_records, ttl_bounds, CreateRecordRequest, UpdateRecordRequest,
+};
+use crate::web::rewrite::{rule_cache_name, CreateRewriteRuleRequest, UpdateRewriteRuleRequest};
+use crate::web::upstreams::{own_listener_error, CreateUpstreamServerRequest, UpdateUpstreamServerRequest};
+use crate::web::{ApiError, AuthService};
+
+#[allow(clippy::all)]
+pub mod pb {
+    tonic::include_proto!("fluxdns.v1");
+}
+
+use pb::management_server::{Management, ManagementServer};
+
+/// Shared state for the gRPC service
+#[derive(Clone)]
+pub struct GrpcState {
+    pub db: Arc<Database>,
+    pub cache: Arc<CacheManager>,
+    pub resolver: Arc<DnsResolver>,
+    pub rewrite_engine: Arc<RewriteEngine>,
+    pub upstream_manager: Arc<UpstreamManager>,
+}
+
+/// Management service implementation
+pub struct ManagementService {
+    state: GrpcState,
+}
+
+/// Map a REST API error onto the equivalent gRPC status
+fn api_status(e: ApiError) -> Status {
+    match e.code.as_str() {
+        "BAD_REQUEST" => Status::invalid_argument(e.message),
+        "NOT_FOUND" => Status::not_found(e.message),
+        "CONFLICT" => Status::already_exists(e.message),
+        "UNAUTHORIZED" => Status::unauthenticated(e.message),
+        "FORBIDDEN" => Status::permission_denied(e.message),
+        _ => Status::internal(e.message),
+    }
+}
+
+/// Build an INVALID_ARGUMENT status carrying the validation error details
+fn validation_status<T: Serialize>(errors: T) -> Status {
+    let details = serde_json::to_string(&errors).unwrap_or_default();
+    Status::invalid_argument(format!("Validation failed: {}", details))
+}
+
+fn internal(context: &str, e: anyhow::Error) -> Status {
+    Status::internal(format!("{}: {}", context, e))
+}
+
+impl From<DnsRecord> for pb::Record {
+    fn from(r: DnsRecord) -> Self {
+        Self {
+            id: r.id,
+            name: r.name,
+            record_type: r.record_type,
+            value: r.value,
+            ttl: r.ttl,
+            priority: r.priority,
+            enabled: r.enabled,
+            tenant_id: r.tenant_id,
+            created_at: r.created_at.to_rfc3339(),
+            updated_at: r.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+impl From<RewriteRule> for pb::RewriteRule {
+    fn from(r: RewriteRule) -> Self {
+        Self {
+            id: r.id,
+            pattern: r.pattern,
+            match_type: r.match_type,
+            action_type: r.action_type,
+            action_value: r.action_value,
+            priority: r.priority,
+            enabled: r.enabled,
+            description: r.description,
+            tenant_id: r.tenant_id,
+            created_at: r.created_at.to_rfc3339(),
+            updated_at: r.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+impl From<UpstreamServer> for pb::Upstream {
+    fn from(s: UpstreamServer) -> Self {
+        Self {
+            id: s.id,
+            name: s.name,
+            address: s.address,
+            protocol: s.protocol,
+            timeout: s.timeout,
+            enabled: s.enabled,
+            created_at: s.created_at.to_rfc3339(),
+            updated_at: s.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+impl From<pb::CreateRecordRequest> for CreateRecordRequest {
+    fn from(r: pb::CreateRecordRequest) -> Self {
+        Self {
+            name: r.name,
+            record_type: r.record_type,
+            value: r.value,
+            ttl: r.ttl.unwrap_or(300),
+            priority: r.priority,
+            enabled: r.enabled.unwrap_or(true),
+            tenant_id: r.tenant_id,
+            description: None,
+            tags: Vec::new(),
+            expires_at: None,
+        }
+    }
+}
+
+impl From<pb::UpdateRecordRequest> for UpdateRecordRequest {
+    fn from(r: pb::UpdateRecordRequest) -> Self {
+        Self {
+            name: r.name,
+            record_type: r.record_type,
+            value: r.value,
+            ttl: r.ttl,
+            priority: r.priority,
+            enabled: r.enabled,
+            description: None,
+            tags: None,
+            expires_at: None,
+        }
+    }
+}
+
+impl From<pb::CreateRewriteRuleRequest> for CreateRewriteRuleRequest {
+    fn from(r: pb::CreateRewriteRuleRequest) -> Self {
+        Self {
+            pattern: r.pattern,
+            match_type: r.match_type,
+            action_type: r.action_type,
+            action_value: r.action_value,
+            priority: r.priority,
+            enabled: r.enabled.unwrap_or(true),
+            description: r.description,
+            tenant_id: r.tenant_id,
+            shadow: false,
+            tags: Vec::new(),
+            expires_at: None,
+        }
+    }
+}
+
+impl From<pb::UpdateRewriteRuleRequest> for UpdateRewriteRuleRequest {
+    fn from(r: pb::UpdateRewriteRuleRequest) -> Self {
+        Self {
+            pattern: r.pattern,
+            match_type: r.match_type,
+            action_type: r.action_type,
+            action_value: r.action_value,
+            priority: r.priority,
+            enabled: r.enabled,
+            description: r.description,
+            tags: None,
+            expires_at: None,
+        }
+    }
+}
+
+impl From<pb::CreateUpstreamRequest> for CreateUpstreamServerRequest {
+    fn from(r: pb::CreateUpstreamRequest) -> Self {
+        Self {
+            name: r.name,
+            address: r.address,
+            protocol: r.protocol,
+            timeout: r.timeout.unwrap_or(5000),
+            enabled: r.enabled.unwrap_or(true),
+            source_ip: None,
+            source_interface: None,
+            tls_server_name: None,
+            verify_hostname: None,
+            doh_method: None,
+            doh_http_version: None,
+        }
+    }
+}
+
+impl From<pb::UpdateUpstreamRequest> for UpdateUpstreamServerRequest {
+    fn from(r: pb::UpdateUpstreamRequest) -> Self {
+        Self {
+            name: r.name,
+            address: r.address,
+            protocol: r.protocol,
+            timeout: r.timeout,
+            enabled: r.enabled,
+            source_ip: None,
+            source_interface: None,
+            tls_server_name: None,
+            verify_hostname: None,
+            doh_method: None,
+            doh_http_version: None,
+        }
+    }
+}
+
+impl ManagementService {
+    pub fn new(state: GrpcState) -> Self {
+        Self { state }
+    }
+
+    /// Reload rules and drop cached answers for the changed rules' names
+    async fn reload_rewrite_rules(&self, changed: &[&RewriteRule]) {
+        if let Err(e) = self.state.rewrite_engine.reload_rules().await {
+            tracing::warn!("Failed to reload rewrite rules: {}", e);
+        }
+        let names = changed
+            .iter()
+            .filter_map(|r| rule_cache_name(&r.pattern, &r.match_type, r.shadow));
+        self.state.cache.purge_names(names).await;
+    }
+
+    /// Reload records and drop cached answers for the changed names
+    async fn reload_local_records(&self, changed_names: &[&str]) {
+        reload_local_records(self.state.resolver.local_records()).await;
+        self.state.cache.purge_names(changed_names).await;
+    }
+
+    async fn reload_upstreams(&self) {
+        if let Err(e) = self.state.upstream_manager.reload_from_db(&self.state.db).await {
+            tracing::warn!("Failed to reload upstream servers: {}", e);
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Management for ManagementService {
+    async fn list_records(
+        &self,
+        request: Request<pb::ListRecordsRequest>,
+    ) -> Result<Response<pb::ListRecordsResponse>, Status> {
+        let repo = self.state.db.dns_records();
+        let records = match request.into_inner().tenant_id {
+            Some(tenant_id) => repo.list_by_tenant(tenant_id).await,
+            None => repo.list().await,
+        }
+        .map_err(|e| internal("Failed to list records", e))?;
+
+        Ok(Response::new(pb::ListRecordsResponse {
+            records: records.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn get_record(
+        &self,
+        request: Request<pb::IdRequest>,
+    ) -> Result<Response<pb::Record>, Status> {
+        let id = request.into_inner().id;
+        let record = self
+            .state
+            .db
+            .dns_records()
+            .get_by_id(id)
+            .await
+            .map_err(|e| internal("Failed to get record", e))?
+            .ok_or_else(|| Status::not_found(format!("Record with id {} not found", id)))?;
+
+        Ok(Response::new(record.into()))
+    }
+
+    async fn create_record(
+        &self,
+        request: Request<pb::CreateRecordRequest>,
+    ) -> Result<Response<pb::Record>, Status> {
+        let request: CreateRecordRequest = request.into_inner().into();
+        ensure_tenant_exists(&self.state.db, request.tenant_id)
+            .await
+            .map_err(api_status)?;
+        let ttl_bounds = ttl_bounds(&self.state.db).await;
+        request.validate(&ttl_bounds).map_err(validation_status)?;
+
+        let record = self
+            .state
+            .db
+            .dns_records()
+            .create(request.into_create_dns_record())
+            .await
+            .map_err(|e| internal("Failed to create record", e))?;
+        self.reload_local_records(&[record.name.as_str()]).await;
+
+        Ok(Response::new(record.into()))
+    }
+
+    async fn update_record(
+        &self,
+        request: Request<pb::UpdateRecordRequest>,
+    ) -> Result<Response<pb::Record>, Status> {
+        let request = request.into_inner();
+        let id = request.id;
+        let repo = self.state.db.dns_records();
+
+        let existing = repo
+            .get_by_id(id)
+            .await
+            .map_err(|e| internal("Failed to get record", e))?
+            .ok_or_else(|| Status::not_found(format!("Record with id {} not found", id)))?;
+
+        let request: UpdateRecordRequest = request.into();
+        let ttl_bounds = ttl_bounds(&self.state.db).await;
+        request
+            .validate(&existing.record_type, &ttl_bounds)
+            .map_err(validation_status)?;
+
+        let record = repo
+            .update(id, request.into_update_dns_record())
+            .await
+            .map_err(|e| internal("Failed to update record", e))?
+            .ok_or_else(|| Status::not_found(format!("Record with id {} not found", id)))?;
+        self.reload_local_records(&[existing.name.as_str(), record.name.as_str()]).await;
+
+        Ok(Response::new(record.into()))
+    }
+
+    async fn delete_record(
+        &self,
+        request: Request<pb::IdRequest>,
+    ) -> Result<Response<pb::Empty>, Status> {
+        let id = request.into_inner().id;
+        let repo = self.state.db.dns_records();
+        let existing = repo
+            .get_by_id(id)
+            .await
+            .map_err(|e| internal("Failed to get record", e))?;
+        let deleted = repo
+            .delete(id)
+            .await
+            .map_err(|e| internal("Failed to delete record", e))?;
+
+        if deleted {
+            let names: Vec<&str> = existing.iter().map(|r| r.name.as_str()).collect();
+            self.reload_local_records(&names).await;
+            Ok(Response::new(pb::Empty {}))
+        } else {
+            Err(Status::not_found(format!("Record with id {} not found", id)))
+        }
+    }
+
+    async fn list_rewrite_rules(
+        &self,
+        request: Request<pb::ListRewriteRulesRequest>,
+    ) -> Result<Response<pb::ListRewriteRulesResponse>, Status> {
+        let repo = self.state.db.rewrite_rules();
+        let rules = match request.into_inner().tenant_id {
+            Some(tenant_id) => repo.list_by_tenant(tenant_id).await,
+            None => repo.list().await,
+        }
+        .map_err(|e| internal("Failed to list rewrite rules", e))?;
+
+        Ok(Response::new(pb::ListRewriteRulesResponse {
+            rules: rules.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn create_rewrite_rule(
+        &self,
+        request: Request<pb::CreateRewriteRuleRequest>,
+    ) -> Result<Response<pb::RewriteRule>, Status> {
+        let request: CreateRewriteRuleRequest = request.into_inner().into();
+        ensure_tenant_exists(&self.state.db, request.tenant_id)
+            .await
+            .map_err(api_status)?;
+        request.validate().map_err(validation_status)?;
+
+        let rule = self
+            .state
+            .db
+            .rewrite_rules()
+            .create(request.into_create_rewrite_rule())
+            .await
+            .map_err(|e| internal("Failed to create rewrite rule", e))?;
+
+        self.reload_rewrite_rules(&[&rule]).await;
+        Ok(Response::new(rule.into()))
+    }
+
+    async fn update_rewrite_rule(
+        &self,
+        request: Request<pb::UpdateRewriteRuleRequest>,
+    ) -> Result<Response<pb::RewriteRule>, Status> {
+        let request = request.into_inner();
+        let id = request.id;
+        let repo = self.state.db.rewrite_rules();
+
+        let existing = repo
+            .get_by_id(id)
+            .await
+            .map_err(|e| internal("Failed to get rewrite rule", e))?
+            .ok_or_else(|| Status::not_found(format!("Rewrite rule with id {} not found", id)))?;
+
+        let request: UpdateRewriteRuleRequest = request.into();
+        request.validate(&existing).map_err(validation_status)?;
+
+        let rule = repo
+            .update(id, request.into_update_rewrite_rule(&existing))
+            .await
+            .map_err(|e| internal("Failed to update rewrite rule", e))?
+            .ok_or_else(|| Status::not_found(format!("Rewrite rule with id {} not found", id)))?;
+
+        self.reload_rewrite_rules(&[&existing, &rule]).await;
+        Ok(Response::new(rule.into()))
+    }
+
+    async fn delete_rewrite_rule(
+        &self,
+        request: Request<pb::IdRequest>,
+    ) -> Result<Response<pb::Empty>, Status> {
+        let id = request.into_inner().id;
+        let repo = self.state.db.rewrite_rules();
+        let existing = repo
+            .get_by_id(id)
+            .await
+            .map_err(|e| internal("Failed to get rewrite rule", e))?;
+        let deleted = repo
+            .delete(id)
+            .await
+            .map_err(|e| internal("Failed to delete rewrite rule", e))?;
+
+        if !deleted {
+            return Err(Status::not_found(format!("Rewrite rule with id {} not found", id)));
+        }
+
+        let changed: Vec<&RewriteRule> = existing.iter().collect();
+        self.reload_rewrite_rules(&changed).await;
+        Ok(Response::new(pb::Empty {}))
+    }
+
+    async fn list_upstreams(
+        &self,
+        _request: Request<pb::Empty>,
+    ) -> Result<Response<pb::ListUpstreamsResponse>, Status> {
+        let servers = self
+            .state
+            .db
+            .upstream_servers()
+            .list()
+            .await
+            .map_err(|e| internal("Failed to list upstream servers", e))?;
+
+        Ok(Response::new(pb::ListUpstreamsResponse {
+            upstreams: servers.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn create_upstream(
+        &self,
+        request: Request<pb::CreateUpstreamRequest>,
+    ) -> Result<Response<pb::Upstream>, Status> {
+        let request: CreateUpstreamServerRequest = request.into_inner().into();
+        request.validate().map_err(validation_status)?;
+        if let Some(errors) = own_listener_error(&self.state.db, &request.address, &request.protocol)
+            .await
+            .map_err(|e| internal("Failed to get listeners", e))?
+        {
+            return Err(validation_status(errors));
+        }
+
+        let server = self
+            .state
+            .db
+            .upstream_servers()
+            .create(request.into_create_upstream_server())
+            .await
+            .map_err(|e| internal("Failed to create upstream server", e))?;
+
+        self.reload_upstreams().await;
+        Ok(Response::new(server.into()))
+    }
+
+    async fn update_upstream(
+        &self,
+        request: Request<pb::UpdateUpstreamRequest>,
+    ) -> Result<Response<pb::Upstream>, Status> {
+        let request = request.into_inner();
+        let id = request.id;
+        let repo = self.state.db.upstream_servers();
+
+        let existing = repo
+            .get_by_id(id)
+            .await
+            .map_err(|e| internal("Failed to get upstream server", e))?
+            .ok_or_else(|| Status::not_found(format!("Upstream server with id {} not found", id)))?;
+
+        let request: UpdateUpstreamServerRequest = request.into();
+        request.validate(&existing).map_err(validation_status)?;
+        if request.address.is_some() || request.protocol.is_some() {
+            let address = request.address.as_deref().unwrap_or(&existing.address);
+            let protocol = request.protocol.as_deref().unwrap_or(&existing.protocol);
+            if let Some(errors) = own_listener_error(&self.state.db, address, protocol)
+                .await
+                .map_err(|e| internal("Failed to get listeners", e))?
+            {
+                return Err(validation_status(errors));
+            }
+        }
+
+        let server = repo
+            .update(id, request.into_update_upstream_server())
+            .await
+            .map_err(|e| internal("Failed to update upstream server", e))?
+            .ok_or_else(|| Status::not_found(format!("Upstream server with id {} not found", id)))?;
+
+        self.reload_upstreams().await;
+        Ok(Response::new(server.into()))
+    }
+
+    async fn delete_upstream(
+        &self,
+        request: Request<pb::IdRequest>,
+    ) -> Result<Response<pb::Empty>, Status> {
+        let id = request.into_inner().id;
+        let deleted = self
+            .state
+            .db
+            .upstream_servers()
+            .delete(id)
+            .await
+            .map_err(|e| internal("Failed to delete upstream server", e))?;
+
+        if !deleted {
+            return Err(Status::not_found(format!("Upstream server with id {} not found", id)));
+        }
+
+        self.reload_upstreams().await;
+        Ok(Response::new(pb::Empty {}))
+    }
+
+    async fn purge_cache(
+        &self,
+        request: Request<pb::PurgeCacheRequest>,
+    ) -> Result<Response<pb::PurgeCacheResponse>, Status> {
+        let client_ip = request
+            .remote_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default();
+        let request = request.into_inner();
+
+        if request.domains.is_empty() || request.domains.len() > MAX_PURGE_PATTERNS {
+            return Err(Status::invalid_argument(format!(
+                "Provide between 1 and {} domains",
+                MAX_PURGE_PATTERNS
+            )));
+        }
+        let patterns: Vec<String> = request.domains.iter().map(|d| d.trim().to_string()).collect();
+        for pattern in &patterns {
+            validate_pattern(pattern).map_err(Status::invalid_argument)?;
+        }
+
+        let mut purged = 0;
+        for pattern in &patterns {
+            purged += self.state.cache.purge_pattern(pattern).await;
+        }
+
+        tracing::info!(
+            "Cache purge via gRPC from {}: {} entries for {} patterns",
+            client_ip,
+            purged,
+            patterns.len()
+        );
+
+        let audit = CreateCachePurgeAudit {
+            actor: "grpc".to_string(),
+            client_ip,
+            patterns: patterns.clone(),
+            purged: purged as i64,
+        };
+        if let Err(e) = self.state.db.cache_purge_audit().create(audit).await {
+            tracing::warn!("Failed to write cache purge audit entry: {}", e);
+        }
+
+        Ok(Response::new(pb::PurgeCacheResponse {
+            purged: purged as u64,
+            domains: patterns.len() as u64,
+        }))
+    }
+
+    async fn test_query(
+        &self,
+        request: Request<pb::TestQueryRequest>,
+    ) -> Result<Response<pb::TestQueryResponse>, Status> {
+        let request = request.into_inner();
+        let query = DnsQueryRequest {
+            domain: request.domain,
+            record_type: request.record_type,
+        };
+        query.validate().map_err(validation_status)?;
+
+        let record_type = query
+            .get_record_type()
+            .ok_or_else(|| Status::invalid_argument("Invalid record type"))?;
+
+        let result = self
+            .state
+            .resolver
+            .resolve_with_type(&query.domain, record_type)
+            .await
+            .map_err(|e| Status::unavailable(format!("DNS query failed: {}", e)))?;
+
+        let records = result
+            .response
+            .answers
+            .iter()
+            .map(|r| pb::AnswerRecord {
+                name: r.name.clone(),
+                record_type: r.record_type.to_string(),
+                value: r.value.clone(),
+                ttl: r.ttl,
+            })
+            .collect();
+
+        Ok(Response::new(pb::TestQueryResponse {
+            record_type: query.record_type.to_uppercase(),
+            domain: query.domain,
+            records,
+            response_time_ms: result.metadata.response_time_ms,
+            cache_hit: result.metadata.cache_hit,
+            upstream_used: result.metadata.upstream_used,
+            rewrite_applied: result.metadata.rewrite_applied,
+            response_code: result.response.response_code.to_string(),
+        }))
+    }
+}
+
+/// Check the bearer token in request metadata
+///
+/// Accepts the static `grpc_token` when configured, otherwise falls back to
+/// verifying an admin JWT. Delegated admin JWTs are rejected.
+fn authorize(
+    metadata: &MetadataMap,
+    static_token: Option<&str>,
+    auth_service: &AuthService,
+) -> Result<(), Status> {
+    let token = metadata
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(AuthService::extract_token_from_header)
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .ok_or_else(|| Status::unauthenticated("Missing bearer token"))?;
+
+    if static_token.is_some_and(|expected| expected == token) {
+        return Ok(());
+    }
+
+    auth_service
+        .verify_token(token)
+        .ok()
+        .filter(|claims| claims.delegate_id.is_none())
+        .map(|_| ())
+        .ok_or_else(|| Status::unauthenticated("Invalid token"))
+}
+
+/// Run the gRPC management server until it fails
+pub async fn serve(state: GrpcState, config: &AppConfig, auth_service: AuthService) -> Result<()> {
+    let addr: SocketAddr = format!("0.0.0.0:{}", config.grpc_port).parse()?;
+
+    let static_token = config.grpc_token.clone().filter(|t| !t.is_empty());
+    let interceptor = move |request: Request<()>| {
+        authorize(request.metadata(), static_token.as_deref(), &auth_service)?;
+        Ok(request)
+    };
+    let service = ManagementServer::with_interceptor(ManagementService::new(state), interceptor);
+
+    let mut builder = Server::builder();
+    match (&config.grpc_tls_cert, &config.grpc_tls_key) {
+        (Some(cert_path), Some(key_path)) => {
+            let cert = std::fs::read(cert_path)
+                .with_context(|| format!("Failed to read gRPC TLS certificate: {}", cert_path.display()))?;
+            let key = std::fs::read(key_path)
+                .with_context(|| format!("Failed to read gRPC TLS key: {}", key_path.display()))?;
+            builder = builder
+                .tls_config(ServerTlsConfig::new().identity(Identity::from_pem(cert, key)))
+                .context("Invalid gRPC TLS configuration")?;
+            tracing::info!("gRPC management API listening on https://{}", addr);
+        }
+        (None, None) => {
+            tracing::info!("gRPC management API listening on http://{}", addr);
+        }
+        _ => anyhow::bail!("Both grpc_tls_cert and grpc_tls_key must be set to enable gRPC TLS"),
+    }
+
+    builder.add_service(service).serve(addr).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ConfigManager, PartialConfig};
+    use crate::web::auth::LoginRequest;
+
+    fn auth_service() -> AuthService {
+        let config = PartialConfig {
+            admin_username: Some("admin".to_string()),
+            admin_password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        AuthService::new(Arc::new(ConfigManager::from_configs(Some(config), None)))
+    }
+
+    fn metadata_with(token: &str) -> MetadataMap {
+        let mut metadata = MetadataMap::new();
+        metadata.insert("authorization", format!("Bearer {}", token).parse().unwrap());
+        metadata
+    }
+
+    #[test]
+    fn test_authorize_static_token() {
+        let auth = auth_service();
+        assert!(authorize(&metadata_with("grpc-secret"), Some("grpc-secret"), &auth).is_ok());
+        assert!(authorize(&metadata_with("wrong"), Some("grpc-secret"), &auth).is_err());
+        assert!(authorize(&MetadataMap::new(), Some("grpc-secret"), &auth).is_err());
+    }
+
+    #[test]
+    fn test_authorize_admin_jwt() {
+        let auth = auth_service();
+        let login = auth
+            .login(&LoginRequest {
+                username: "admin".to_string(),
+                password: "secret".to_string(),
+            })
+            .unwrap();
+        assert!(authorize(&metadata_with(&login.token), None, &auth).is_ok());
+        assert!(authorize(&metadata_with("not-a-jwt"), None, &auth).is_err());
+    }
+
+    #[test]
+    fn test_api_status_mapping() {
+        let status = api_status(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: "Tenant with id 9 not found".to_string(),
+            details: None,
+        });
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        let status = api_status(ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: "boom".to_string(),
+            details: None,
+        });
+        assert_eq!(status.code(), tonic::Code::Internal);
+    }
+}
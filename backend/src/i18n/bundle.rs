@@ -0,0 +1,423 @@
+//! en / zh-CN message bundle
+//!
+//! One `(en, zh-CN)` pair per message. `{}` stands for text filled in at
+//! runtime (ids, names, error causes); both sides must use the same number
+//! of placeholders in the same order. Prefixes such as
+//! `"Failed to save settings: {}"` also cover messages the code formats
+//! with a cause.
+
+pub(super) static MESSAGES: &[(&str, &str)] = &[
+    // General API errors
+    ("Validation failed", "验证失败"),
+    ("Not found", "未找到"),
+    ("Missing or invalid Authorization header", "缺少或无效的 Authorization 请求头"),
+    ("Invalid username or password", "用户名或密码错误"),
+    ("Authentication error", "认证失败"),
+    ("Invalid token", "令牌无效"),
+    ("Tenant tokens cannot access this endpoint", "租户令牌无权访问此接口"),
+    ("API token '{}' lacks the scope for this endpoint", "API 令牌 '{}' 缺少此接口的权限范围"),
+    ("Delegated admins cannot access this endpoint", "委派管理员无权访问此接口"),
+    ("Delegated admin account is disabled or deleted", "委派管理员账号已被禁用或删除"),
+    ("Name {} is outside your delegated zones", "域名 {} 不在您的委派区域内"),
+    ("Name is outside your delegated zones", "域名不在您的委派区域内"),
+    ("Delegated admins cannot apply tag operations", "委派管理员不能执行标签批量操作"),
+    ("Too many requests, try again later", "请求过多，请稍后重试"),
+    ("Too many captures running, try again later", "正在运行的抓包过多，请稍后重试"),
+    ("If-Match header is required for this request", "此请求需要 If-Match 请求头"),
+    ("Resource has been modified; reload it and retry", "资源已被修改，请刷新后重试"),
+    ("Invalid settings: {}", "设置无效: {}"),
+    ("Invalid record TTL bounds: {}", "记录 TTL 范围无效: {}"),
+    ("Invalid strategy", "无效的查询策略"),
+    ("Invalid record type", "无效的记录类型"),
+    ("Invalid window: {}", "无效的时间窗口: {}"),
+    ("Invalid sort: {}", "无效的排序方式: {}"),
+    ("Invalid profile. Must be one of: {}", "无效的配置方案，必须是以下之一: {}"),
+    ("DNS query failed: {}", "DNS 查询失败: {}"),
+    // Not found
+    ("Upstream server with id {} not found", "ID 为 {} 的上游服务器不存在"),
+    ("samples must be between 1 and {}", "samples 必须在 1 到 {} 之间"),
+    ("Upstream server {} is listed more than once", "上游服务器 {} 在排序中重复出现"),
+    ("Upstream server {} is missing from the order", "排序中缺少上游服务器 {}"),
+    ("Rewrite rule with id {} not found", "ID 为 {} 的重写规则不存在"),
+    ("Record with id {} not found", "ID 为 {} 的记录不存在"),
+    ("Service with id {} not found", "ID 为 {} 的服务不存在"),
+    ("Tenant with id {} not found", "ID 为 {} 的租户不存在"),
+    ("API token with id {} not found", "ID 为 {} 的 API 令牌不存在"),
+    ("Delegated admin with id {} not found", "ID 为 {} 的委派管理员不存在"),
+    ("Profile with id {} not found", "ID 为 {} 的解析配置不存在"),
+    ("Category list with id {} not found", "ID 为 {} 的分类列表不存在"),
+    ("RPZ feed with id {} not found", "ID 为 {} 的 RPZ 订阅不存在"),
+    ("Reference domain with id {} not found", "ID 为 {} 的参考域名不存在"),
+    ("Notification with id {} not found", "ID 为 {} 的通知不存在"),
+    ("Listener '{}' not found", "监听器 '{}' 不存在"),
+    ("Unknown listener '{}'", "未知的监听器 '{}'"),
+    ("Profile {} not found", "解析配置 {} 不存在"),
+    // Conflicts
+    ("Duplicate upstream name", "上游服务器名称重复"),
+    ("Duplicate rule", "规则重复"),
+    ("Duplicate record", "记录重复"),
+    ("Duplicate listener", "监听器重复"),
+    ("Service '{}' already exists", "服务 '{}' 已存在"),
+    ("Username '{}' is already taken", "用户名 '{}' 已被占用"),
+    // Upstream validation
+    ("Name cannot be empty", "名称不能为空"),
+    ("Name cannot exceed 100 characters", "名称不能超过 100 个字符"),
+    ("Name cannot exceed 255 characters", "名称不能超过 255 个字符"),
+    ("Name must be between 1 and 100 characters", "名称长度必须在 1 到 100 个字符之间"),
+    ("Address cannot be empty", "地址不能为空"),
+    ("Address cannot exceed 255 characters", "地址不能超过 255 个字符"),
+    ("Address should be in host:port format (e.g., 8.8.8.8:53)", "地址应为 host:port 格式（例如 8.8.8.8:53）"),
+    (
+        "DoH/DoH3 address should be a URL (e.g., https://dns.google/dns-query)",
+        "DoH/DoH3 地址应为 URL（例如 https://dns.google/dns-query）",
+    ),
+    ("Timeout must be at least 100ms", "超时时间不能小于 100ms"),
+    ("Timeout cannot exceed 60000ms (60 seconds)", "超时时间不能超过 60000ms（60 秒）"),
+    ("Invalid source IP address '{}'", "源 IP 地址 '{}' 无效"),
+    ("Invalid TLS server name '{}': must be a DNS name", "TLS 服务器名称 '{}' 无效: 必须是域名"),
+    ("Only applies to encrypted protocols, not {}", "仅适用于加密协议，不适用于 {}"),
+    ("Only applies to DoH upstreams, not {}", "仅适用于 DoH 上游，不适用于 {}"),
+    ("Invalid DoH method '{}': must be get or post", "DoH 请求方法 '{}' 无效: 必须是 get 或 post"),
+    ("Invalid HTTP version '{}': must be auto, http1 or http2", "HTTP 版本 '{}' 无效: 必须是 auto、http1 或 http2"),
+    (
+        "DoH upstreams only accept a TLS server name with an IP address URL (e.g., https://1.1.1.1/dns-query)",
+        "DoH 上游仅在地址为 IP URL 时接受 TLS 服务器名称（例如 https://1.1.1.1/dns-query）",
+    ),
+    ("Capability probing only applies to UDP upstreams", "能力探测仅适用于 UDP 上游"),
+    (
+        "Address reaches this server's own {} listener on {}; forwarding to it would loop",
+        "该地址指向本服务自身的 {} 监听器 {}，转发到此地址会形成循环",
+    ),
+    // Rewrite rule validation
+    ("Pattern cannot be empty", "匹配模式不能为空"),
+    ("Pattern cannot exceed 255 characters", "匹配模式不能超过 255 个字符"),
+    ("Invalid regular expression pattern", "无效的正则表达式"),
+    ("Wildcard pattern must contain at least one '*'", "通配符模式必须包含至少一个 '*'"),
+    ("Exact pattern contains invalid characters", "精确匹配模式包含无效字符"),
+    ("Invalid IP address for map_ip action", "map_ip 动作的 IP 地址无效"),
+    ("action_value cannot be empty for map_domain action", "map_domain 动作的 action_value 不能为空"),
+    ("No valid patterns provided", "没有提供有效的匹配模式"),
+    ("Invalid pattern '{}': {}", "匹配模式 '{}' 无效: {}"),
+    ("Successfully created {} rewrite rules", "成功创建 {} 条重写规则"),
+    ("Rule is already a shadow rule; edit it directly", "该规则已是影子规则，请直接编辑"),
+    ("Rewrite rule {} is not a shadow rule", "重写规则 {} 不是影子规则"),
+    ("Tag cannot be empty", "标签不能为空"),
+    // Record validation
+    ("Name contains invalid characters", "名称包含无效字符"),
+    (
+        "Wildcard '*' is only allowed as the leftmost label, e.g. *.example.com",
+        "通配符 '*' 只能作为最左侧的标签，例如 *.example.com",
+    ),
+    ("Value cannot be empty", "值不能为空"),
+    ("Invalid IPv4 address for A record", "A 记录的 IPv4 地址无效"),
+    ("Invalid IPv6 address for AAAA record", "AAAA 记录的 IPv6 地址无效"),
+    ("MX record value cannot be empty", "MX 记录的值不能为空"),
+    ("TXT record value too long", "TXT 记录的值过长"),
+    (
+        "TXT strings must be quoted when the value starts with a quote",
+        "TXT 值以引号开头时，每个字符串都必须加引号",
+    ),
+    ("Unterminated quoted string in TXT value", "TXT 值中的引号字符串未闭合"),
+    ("Invalid \\DDD escape in TXT value", "TXT 值中的 \\DDD 转义无效"),
+    ("TXT character-string exceeds {} bytes", "TXT 字符串超过 {} 字节"),
+    ("Tags cannot be empty", "标签不能为空"),
+    ("Tag '{}' exceeds {} characters", "标签 '{}' 超过 {} 个字符"),
+    ("At most {} tags are allowed", "最多允许 {} 个标签"),
+    ("TTL cannot be negative", "TTL 不能为负数"),
+    ("TTL must be greater than 0", "TTL 必须大于 0"),
+    ("TTL cannot exceed 7 days (604800 seconds)", "TTL 不能超过 7 天（604800 秒）"),
+    ("Minimum TTL cannot be negative", "最小 TTL 不能为负数"),
+    ("Priority cannot be negative", "优先级不能为负数"),
+    // Service validation
+    ("Service name must be 1-100 characters", "服务名称长度必须在 1 到 100 个字符之间"),
+    ("Service domain cannot be a wildcard", "服务域名不能是通配符"),
+    ("A service needs at least one record", "服务至少需要一条记录"),
+    // Other validation
+    ("Domain cannot be empty", "域名不能为空"),
+    ("Domain cannot exceed 255 characters", "域名不能超过 255 个字符"),
+    ("No valid domains given", "没有提供有效的域名"),
+    ("Provide between 1 and {} domains", "请提供 1 到 {} 个域名"),
+    ("Provide between 1 and {} records", "请提供 1 到 {} 条记录"),
+    ("Provide between 1 and {} operations", "请提供 1 到 {} 个操作"),
+    (
+        "Operation {} ({}) failed, no changes were applied: {}",
+        "操作 {}（{}）执行失败，所有变更均未生效: {}",
+    ),
+    (
+        "Failed to apply change to {} '{}', no changes were applied: {}",
+        "应用 {} '{}' 的变更失败，所有变更均未生效: {}",
+    ),
+    ("Retention days must be at least 1", "保留天数不能小于 1"),
+    ("Days must be at least 1", "天数不能小于 1"),
+    ("Max entries must be greater than 0", "最大条目数必须大于 0"),
+    ("Max entries cannot exceed 1,000,000", "最大条目数不能超过 1,000,000"),
+    ("Max memory cannot exceed {} MiB", "最大内存不能超过 {} MiB"),
+    ("Port must be between 1 and 65535", "端口必须在 1-65535 之间"),
+    ("Webhook URL is not configured", "未配置 Webhook URL"),
+    ("Missing purge token", "缺少清除令牌"),
+    ("Invalid purge token", "清除令牌无效"),
+    ("No script to test", "没有可测试的脚本"),
+    ("Cannot enable an empty policy script", "不能启用空的策略脚本"),
+    // Delegated admin validation
+    ("Username must be between 1 and 100 characters", "用户名长度必须在 1 到 100 个字符之间"),
+    ("Username may only contain letters, digits, '.', '-' and '_'", "用户名只能包含字母、数字、'.'、'-' 和 '_'"),
+    ("Password must be at least {} characters", "密码长度不能少于 {} 个字符"),
+    ("Zone '{}' cannot be a wildcard", "区域 '{}' 不能是通配符"),
+    ("Invalid zone '{}'", "无效的区域 '{}'"),
+    ("Provide between 1 and {} zones", "请提供 1 到 {} 个区域"),
+    ("Saved log filter with id {} not found", "ID 为 {} 的已保存日志筛选器不存在"),
+    ("A saved log filter named '{}' already exists", "名为 '{}' 的已保存日志筛选器已存在"),
+    ("Invalid block reason: {}", "无效的拦截原因: {}"),
+    ("min_response_time cannot be negative", "min_response_time 不能为负数"),
+    ("Unknown backup table '{}'. Must be one of: records, rewrite_rules, upstreams", "未知的备份表 '{}'，必须是 records、rewrite_rules、upstreams 之一"),
+    ("Backup has no {} section", "备份中没有 {} 部分"),
+    ("Backup contains no tables to restore", "备份中没有可恢复的表"),
+    ("Unsupported backup version {}", "不支持的备份版本 {}"),
+    ("Invalid backup: {}[{}] has no id", "备份无效: {}[{}] 缺少 id"),
+    ("Invalid backup: {} id {} is listed more than once", "备份无效: {} 的 id {} 重复出现"),
+    ("Tenant {} does not exist", "租户 {} 不存在"),
+    ("Record group {} does not exist", "记录组 {} 不存在"),
+    ("Rewrite rule {} is not in the backup", "重写规则 {} 不在备份中"),
+    ("Invalid severity: {}", "无效的严重级别: {}"),
+    // Settings registry
+    ("unknown setting", "未知设置"),
+    ("must be a boolean", "必须是布尔值"),
+    ("must be a string", "必须是字符串"),
+    ("must be a list of strings", "必须是字符串列表"),
+    ("must be an integer between {} and {}", "必须是 {} 到 {} 之间的整数"),
+    ("must be one of: {}", "必须是以下之一: {}"),
+    ("must be an http:// or https:// URL", "必须是 http:// 或 https:// URL"),
+    ("invalid record type: {}", "无效的记录类型: {}"),
+    ("invalid IP address: {}", "无效的 IP 地址: {}"),
+    ("invalid client network: {}", "无效的客户端网络: {}"),
+    // Failures with a cause
+    ("Failed to get settings", "获取设置失败"),
+    ("Failed to save settings", "保存设置失败"),
+    ("Failed to save alert settings", "保存告警设置失败"),
+    ("Failed to serialize settings", "序列化设置失败"),
+    ("Failed to get config", "获取配置失败"),
+    ("Failed to save config", "保存配置失败"),
+    ("Failed to get webhook URL", "获取 Webhook URL 失败"),
+    ("Failed to send test alert", "发送测试告警失败"),
+    ("Failed to list upstream servers", "获取上游服务器列表失败"),
+    ("Failed to get upstream servers", "获取上游服务器失败"),
+    ("Failed to get upstream server", "获取上游服务器失败"),
+    ("Failed to create upstream server", "创建上游服务器失败"),
+    ("Failed to update upstream server", "更新上游服务器失败"),
+    ("Failed to delete upstream server", "删除上游服务器失败"),
+    ("Failed to reorder upstream servers", "调整上游服务器顺序失败"),
+    ("Failed to probe upstream server", "探测上游服务器失败"),
+    ("Failed to save protocol rules", "保存协议约束失败"),
+    ("Failed to list rewrite rules", "获取重写规则列表失败"),
+    ("Failed to get rewrite rules", "获取重写规则失败"),
+    ("Failed to get rewrite rule", "获取重写规则失败"),
+    ("Failed to create rewrite rule", "创建重写规则失败"),
+    ("Failed to create rewrite rules", "创建重写规则失败"),
+    ("Failed to update rewrite rule", "更新重写规则失败"),
+    ("Failed to update rewrite rules", "更新重写规则失败"),
+    ("Failed to delete rewrite rule", "删除重写规则失败"),
+    ("Failed to reload rewrite rules", "重新加载重写规则失败"),
+    ("Failed to list records", "获取记录列表失败"),
+    ("Failed to get record", "获取记录失败"),
+    ("Failed to create record", "创建记录失败"),
+    ("Failed to update record", "更新记录失败"),
+    ("Failed to update records", "更新记录失败"),
+    ("Failed to delete record", "删除记录失败"),
+    ("Failed to list services", "获取服务列表失败"),
+    ("Failed to get service", "获取服务失败"),
+    ("Failed to create service", "创建服务失败"),
+    ("Failed to update service", "更新服务失败"),
+    ("Failed to delete service", "删除服务失败"),
+    ("Failed to list listeners", "获取监听器列表失败"),
+    ("Failed to get listener", "获取监听器失败"),
+    ("Failed to list query logs", "获取查询日志失败"),
+    ("Failed to save query logs", "保存查询日志失败"),
+    ("Query logging is paused while database writes fail, try again later", "数据库写入失败，查询日志已暂停记录，请稍后重试"),
+    ("Source must be between 1 and {} characters", "来源名称长度必须在 1 到 {} 个字符之间"),
+    ("Source may only contain letters, digits, '.', '-' and '_'", "来源名称只能包含字母、数字、'.'、'-' 和 '_'"),
+    ("Provide between 1 and {} entries", "请提供 1 到 {} 条日志"),
+    ("Invalid IP address", "无效的 IP 地址"),
+    ("Invalid domain name", "无效的域名"),
+    ("Invalid response code", "无效的响应码"),
+    ("Response time must be between 0 and {} ms", "响应时间必须在 0 到 {} 毫秒之间"),
+    ("Upstream must be at most 255 characters", "上游长度不能超过 255 个字符"),
+    ("Timestamp lies in the future", "时间戳不能晚于当前时间"),
+    ("Failed to get query stats", "获取查询统计失败"),
+    ("Failed to get query statistics", "获取查询统计失败"),
+    ("Failed to cleanup query logs", "清理查询日志失败"),
+    ("Failed to delete all query logs", "清空查询日志失败"),
+    ("Failed to list saved log filters", "获取已保存日志筛选器列表失败"),
+    ("Failed to get saved log filter", "获取已保存日志筛选器失败"),
+    ("Failed to save log filter", "保存日志筛选器失败"),
+    ("Failed to update saved log filter", "更新已保存日志筛选器失败"),
+    ("Failed to delete saved log filter", "删除已保存日志筛选器失败"),
+    ("Failed to apply seed profile", "应用初始配置方案失败"),
+    ("Failed to create backup", "创建备份失败"),
+    ("Failed to restore backup", "恢复备份失败"),
+    ("Failed to read current configuration", "读取当前配置失败"),
+    ("Failed to list notifications", "获取通知列表失败"),
+    ("Failed to count unread notifications", "统计未读通知失败"),
+    ("Failed to mark notifications as read", "标记通知为已读失败"),
+    ("Failed to delete notification", "删除通知失败"),
+    ("配置读取失败", "Failed to read configuration"),
+    // Listener API (Chinese source)
+    ("端口必须在 1-65535 之间", "Port must be between 1 and 65535"),
+    ("该监听器未配置证书", "No certificate is configured for this listener"),
+    ("证书格式无效，请提供 PEM 格式的证书", "Invalid certificate, please provide a PEM certificate"),
+    ("私钥格式无效，请提供 PEM 格式的私钥", "Invalid private key, please provide a PEM private key"),
+    ("无法解析证书内容", "Unable to parse the certificate"),
+    ("证书解析失败", "Failed to parse certificate"),
+    ("网络接口无效", "Invalid network interface"),
+    ("解析模式必须是 recursive、authoritative_only 或 cache_only", "Resolution mode must be recursive, authoritative_only or cache_only"),
+    ("更新失败", "Update failed"),
+    ("启动失败", "Failed to start"),
+    // LLM assistant
+    ("未配置 LLM，请先在设置中配置", "No LLM is configured, please configure one in the settings first"),
+    ("未配置 LLM", "No LLM is configured"),
+    (
+        "你是 FluxDNS 的 AI 助手，帮助用户管理 DNS 服务。",
+        "You are the FluxDNS AI assistant and help the user manage the DNS service. Reply in English.",
+    ),
+    ("FluxDNS AI 助手可以帮助你管理 DNS 服务", "The FluxDNS AI assistant helps you manage the DNS service"),
+    ("你可以用自然语言告诉我你想做什么", "Tell me in plain language what you want to do"),
+    (
+        "例如：'帮我添加一条 A 记录，将 test.com 指向 1.2.3.4'",
+        "For example: 'Add an A record pointing test.com to 1.2.3.4'",
+    ),
+    ("或者：'分析一下最近的查询日志，有没有异常'", "Or: 'Analyze the recent query logs for anomalies'"),
+    ("DNS 记录管理", "DNS records"),
+    ("管理本地 DNS 解析记录", "Manage local DNS records"),
+    ("批量添加 DNS 记录", "Add DNS records in bulk"),
+    ("编辑单条 DNS 记录", "Edit a DNS record"),
+    ("删除 DNS 记录", "Delete a DNS record"),
+    ("列出所有 DNS 记录", "List all DNS records"),
+    ("创建包含多条记录的服务", "Create a service made of several records"),
+    ("列出服务及其记录", "List services and their records"),
+    ("重命名或启停整个服务", "Rename, enable or disable a whole service"),
+    ("删除服务及其所有记录", "Delete a service and all of its records"),
+    ("重写规则管理", "Rewrite rules"),
+    (
+        "管理 DNS 查询重写规则，支持精确匹配、通配符和正则表达式",
+        "Manage DNS rewrite rules with exact, wildcard and regex patterns",
+    ),
+    ("批量添加重写规则", "Add rewrite rules in bulk"),
+    ("编辑单条规则", "Edit a rule"),
+    ("删除规则", "Delete a rule"),
+    ("列出所有规则", "List all rules"),
+    ("上游服务器管理", "Upstream servers"),
+    (
+        "管理上游 DNS 服务器，支持多种协议（UDP/TCP/DoT/DoH/DoQ/DoH3）",
+        "Manage upstream DNS servers over UDP/TCP/DoT/DoH/DoQ/DoH3",
+    ),
+    ("批量导入上游服务器", "Import upstream servers in bulk"),
+    ("编辑上游服务器", "Edit an upstream server"),
+    ("删除上游服务器", "Delete an upstream server"),
+    ("检测单个上游健康状态", "Check the health of one upstream"),
+    ("批量检测所有上游", "Check all upstreams"),
+    ("恢复不健康的上游", "Restore unhealthy upstreams"),
+    ("分析上游性能", "Analyze upstream performance"),
+    ("DNS 查询", "DNS query"),
+    ("执行 DNS 查询，支持所有记录类型", "Run DNS queries of any record type"),
+    (
+        "查询域名解析，支持 A/AAAA/CNAME/MX/TXT/PTR/NS/SOA/SRV/CAA 等类型",
+        "Resolve a name as A/AAAA/CNAME/MX/TXT/PTR/NS/SOA/SRV/CAA and more",
+    ),
+    ("使用 PTR 类型可以进行反向解析（IP → 域名）", "Use the PTR type for reverse lookups (IP → name)"),
+    ("系统会自动将 IP 地址转换为 PTR 查询格式", "IP addresses are converted to PTR query names automatically"),
+    ("日志分析", "Log analysis"),
+    ("分析 DNS 查询日志", "Analyze DNS query logs"),
+    ("综合分析查询日志", "Analyze the query logs"),
+    ("获取高频查询排行", "Top frequent queries"),
+    ("检测异常流量", "Detect abnormal traffic"),
+    ("统计指定域名的查询次数", "Count queries for a domain"),
+    ("获取域名查询排名", "Domain query ranking"),
+    ("系统设置", "Settings"),
+    ("管理系统配置和日志清理", "Manage system settings and log cleanup"),
+    ("获取系统运行状态", "Get the system status"),
+    ("更新查询策略", "Update the query strategy"),
+    ("切换记录类型开关", "Toggle record types"),
+    ("清空缓存", "Clear the cache"),
+    ("获取日志保留设置", "Get log retention settings"),
+    ("更新日志保留设置", "Update log retention settings"),
+    ("清理指定日期前的日志", "Delete logs before a date"),
+    ("清空所有日志", "Delete all logs"),
+    ("缓存管理", "Cache"),
+    ("管理 DNS 缓存", "Manage the DNS cache"),
+    ("获取缓存统计信息", "Get cache statistics"),
+    ("查询缓存中的条目", "Look up a cache entry"),
+    ("删除指定缓存条目", "Delete a cache entry"),
+    ("监听器管理", "Listeners"),
+    ("管理 DNS 服务监听器", "Manage DNS listeners"),
+    ("列出所有监听器", "List all listeners"),
+    ("添加新监听器", "Add a listener"),
+    ("编辑监听器", "Edit a listener"),
+    ("删除监听器", "Delete a listener"),
+    ("诊断工具", "Diagnostics"),
+    ("DNS 解析诊断和测试", "Diagnose and test DNS resolution"),
+    ("追踪 DNS 解析全过程", "Trace a DNS resolution end to end"),
+    ("测试上游服务器连通性", "Test upstream connectivity"),
+    ("对比多个上游的解析结果", "Compare answers from several upstreams"),
+    ("智能分析", "Analytics"),
+    ("高级数据分析和安全检测", "Advanced analytics and security checks"),
+    ("获取客户端查询统计", "Per-client query statistics"),
+    ("检测 DNS 隧道攻击", "Detect DNS tunneling"),
+    ("智能推荐阻止规则", "Suggest blocking rules"),
+    ("配置管理", "Configuration"),
+    ("导入导出配置和备份", "Import, export and back up the configuration"),
+    ("导出当前配置", "Export the current configuration"),
+    ("导入配置", "Import a configuration"),
+    ("备份数据库", "Back up the database"),
+    // Notifications
+    ("Average upstream latency {}ms exceeds the {}ms threshold", "上游平均延迟 {}ms 超过阈值 {}ms"),
+    ("Database writes are failing, query logging is paused: {}", "数据库写入失败，查询日志已暂停: {}"),
+    ("Database writes succeed again, {} query log entries were dropped", "数据库写入已恢复，期间丢弃 {} 条查询日志"),
+    ("Rewrite rules or local records cannot be loaded: {}", "无法加载重写规则或本地记录: {}"),
+    ("Rewrite rules and local records are available again", "重写规则和本地记录已重新可用"),
+    ("Forwarding loop detected: {} {} from {} came back on the {} listener", "检测到转发循环: {} {}（来自 {}）经由 {} 监听器回到了本服务"),
+    ("Upstream {} is unhealthy", "上游 {} 状态异常"),
+    ("Upstream {} is healthy again", "上游 {} 已恢复正常"),
+    ("Certificate of the {} listener has expired", "{} 监听器的证书已过期"),
+    ("Certificate of the {} listener expires in {} days", "{} 监听器的证书将在 {} 天后过期"),
+    ("Upstreams disagree on {} {}", "上游对 {} {} 的解析结果不一致"),
+    ("Backup from {} restored: {}", "已恢复 {} 的备份: {}"),
+    ("Configuration reload failed: {}", "配置重新加载失败: {}"),
+    ("Restart required for changed settings: {}", "以下已更改的设置需要重启后生效: {}"),
+    ("{} traffic anomalies detected in the last {}", "检测到 {} 个流量异常（最近 {}）"),
+    // Alerts
+    (
+        "🚨 **High Latency Alert**\n\nCurrent Average Latency: **{}ms**\nThreshold: {}ms\n\nPlease check your upstream servers.",
+        "🚨 **高延迟告警**\n\n当前平均延迟: **{}ms**\n阈值: {}ms\n\n请检查上游服务器。",
+    ),
+    (
+        "🚨 **Database Degraded**\n\nDatabase writes are failing: {}\nQuery logging is paused; DNS resolution continues.",
+        "🚨 **数据库降级**\n\n数据库写入失败: {}\n查询日志已暂停，DNS 解析不受影响。",
+    ),
+    (
+        "✅ **Database Recovered**\n\nDatabase writes succeed again and query logging has resumed.\nQuery log entries dropped so far: {}",
+        "✅ **数据库已恢复**\n\n数据库写入已恢复，查询日志继续记录。\n期间丢弃的查询日志条数: {}",
+    ),
+    (
+        "🚨 **Policy Data Unavailable**\n\nRewrite rules or local records cannot be loaded: {}\nFail-open policy: queries are resolved without rewrite rules and local records.",
+        "🚨 **策略数据不可用**\n\n无法加载重写规则或本地记录: {}\n失败放行策略: 查询将在不应用重写规则和本地记录的情况下正常解析。",
+    ),
+    (
+        "🚨 **Policy Data Unavailable**\n\nRewrite rules or local records cannot be loaded: {}\nFail-closed policy: queries not in the cache are refused.",
+        "🚨 **策略数据不可用**\n\n无法加载重写规则或本地记录: {}\n失败拒绝策略: 缓存中没有的查询将被拒绝。",
+    ),
+    (
+        "✅ **Policy Data Restored**\n\nRewrite rules and local records are available again.\nQueries bypassed: {}, refused: {}",
+        "✅ **策略数据已恢复**\n\n重写规则和本地记录已重新可用。\n期间放行查询: {}，拒绝查询: {}",
+    ),
+    (
+        "🚨 **Forwarding Loop Detected**\n\nA query sent upstream came back to this server: {} {} from {} on the {} listener.\nLooped queries so far: {}\nCheck that no upstream forwards to this server.",
+        "🚨 **检测到转发循环**\n\n发往上游的查询又回到了本服务: {} {}，来自 {}，经由 {} 监听器。\n累计循环查询: {}\n请检查是否有上游将查询转发回本服务。",
+    ),
+    (
+        "🚨 **Upstream Integrity Alert**\n\nUpstreams disagree on **{}** ({}):\n{}",
+        "🚨 **上游一致性告警**\n\n上游对 **{}**（{}）的解析结果不一致:\n{}",
+    ),
+    (
+        "🔔 **Test Alert**\n\nThis is a test notification from FluxDNS.",
+        "🔔 **测试告警**\n\n这是一条来自 FluxDNS 的测试通知。",
+    ),
+];
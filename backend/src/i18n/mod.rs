@@ -0,0 +1,265 @@
+//! Localization of server-originated text
+//!
+//! API error messages, validation errors, LLM help output and alert text are
+//! written in one language in the code (mostly English, some Chinese). Before
+//! they leave the server they are looked up in the [`bundle`] of message
+//! pairs and rendered in the caller's language, so handlers keep formatting
+//! plain strings and only the response edge knows about languages.
+//!
+//! The language of an API request comes from the `ui_language` setting when
+//! it names one, otherwise from the `Accept-Language` header, otherwise
+//! English. Work outside a request (alerts, background jobs) uses the
+//! setting.
+
+mod bundle;
+
+use std::future::Future;
+use std::sync::RwLock;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::db::Database;
+
+/// Config key for the preferred language: `auto`, `en` or `zh-CN`
+pub const CONFIG_KEY_UI_LANGUAGE: &str = "ui_language";
+
+/// Supported languages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Lang {
+    #[serde(rename = "en")]
+    En,
+    #[serde(rename = "zh-CN")]
+    ZhCn,
+}
+
+impl Lang {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::ZhCn => "zh-CN",
+        }
+    }
+
+    /// Parse a language tag such as `en-US`, `zh`, `zh-Hans-CN`
+    ///
+    /// Every Chinese tag maps to zh-CN, the only Chinese bundle.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let primary = tag.trim().split(['-', '_']).next()?.to_ascii_lowercase();
+        match primary.as_str() {
+            "en" => Some(Lang::En),
+            "zh" => Some(Lang::ZhCn),
+            _ => None,
+        }
+    }
+
+    /// Best supported language of an `Accept-Language` header
+    ///
+    /// Tags are tried by descending quality; ties keep header order.
+    pub fn from_accept_language(header: &str) -> Option<Self> {
+        let mut tags: Vec<(&str, f32)> = header
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .and_then(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                (!tag.is_empty() && quality > 0.0).then_some((tag, quality))
+            })
+            .collect();
+        tags.sort_by(|a, b| b.1.total_cmp(&a.1));
+        tags.into_iter().find_map(|(tag, _)| Self::parse(tag))
+    }
+}
+
+impl std::fmt::Display for Lang {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Language chosen in the `ui_language` setting; `None` follows the client
+static PREFERRED: RwLock<Option<Lang>> = RwLock::new(None);
+
+tokio::task_local! {
+    static REQUEST_LANG: Lang;
+}
+
+/// Set the preferred language from a `ui_language` value (`auto` clears it)
+pub fn set_preferred(value: &str) {
+    *PREFERRED.write().unwrap() = Lang::parse(value);
+}
+
+/// Load the `ui_language` setting
+pub async fn load_preferred(db: &Database) {
+    let value = db
+        .system_config()
+        .get(CONFIG_KEY_UI_LANGUAGE)
+        .await
+        .unwrap_or(None)
+        .unwrap_or_default();
+    set_preferred(&value);
+}
+
+/// Language of a request with the given `Accept-Language` header
+pub fn resolve(accept_language: Option<&str>) -> Lang {
+    (*PREFERRED.read().unwrap())
+        .or_else(|| accept_language.and_then(Lang::from_accept_language))
+        .unwrap_or(Lang::En)
+}
+
+/// Run a future with `lang` as the current language
+pub async fn scope<F: Future>(lang: Lang, f: F) -> F::Output {
+    REQUEST_LANG.scope(lang, f).await
+}
+
+/// Current language: the request's, else the preferred one, else English
+pub fn current() -> Lang {
+    REQUEST_LANG
+        .try_with(|lang| *lang)
+        .ok()
+        .unwrap_or_else(|| resolve(None))
+}
+
+/// Render a message in `lang`
+///
+/// The message is matched against both sides of the bundle, so English and
+/// Chinese source strings work alike. `{}` in a bundle entry matches any
+/// text, which is carried over to the translation. A message without a
+/// whole match is split at the first `": "` and both halves are localized,
+/// so `"Failed to save settings: disk full"` and `"ttl: must be a boolean"`
+/// are covered by their parts. Anything unknown is returned unchanged.
+pub fn localize(message: &str, lang: Lang) -> String {
+    if let Some(localized) = localize_whole(message, lang) {
+        return localized;
+    }
+    match message.split_once(": ") {
+        Some((head, tail)) => format!(
+            "{}: {}",
+            localize_whole(head, lang).unwrap_or_else(|| head.to_string()),
+            localize(tail, lang)
+        ),
+        None => message.to_string(),
+    }
+}
+
+/// Localize the string leaves of a JSON value in place
+///
+/// Identifiers under `name`, `field` and `code` keys are left alone.
+pub fn localize_value(value: &mut Value, lang: Lang) {
+    match value {
+        Value::String(s) => *s = localize(s, lang),
+        Value::Array(items) => items.iter_mut().for_each(|v| localize_value(v, lang)),
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                if !matches!(key.as_str(), "name" | "field" | "code") {
+                    localize_value(v, lang);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn localize_whole(message: &str, lang: Lang) -> Option<String> {
+    bundle::MESSAGES.iter().find_map(|&(en, zh)| {
+        let target = match lang {
+            Lang::En => en,
+            Lang::ZhCn => zh,
+        };
+        [en, zh]
+            .into_iter()
+            .find_map(|source| match_template(source, message))
+            .map(|captures| fill_template(target, &captures))
+    })
+}
+
+/// Match `text` against a template where `{}` stands for any text
+fn match_template<'a>(template: &str, text: &'a str) -> Option<Vec<&'a str>> {
+    let parts: Vec<&str> = template.split("{}").collect();
+    let mut rest = text.strip_prefix(parts[0])?;
+    let mut captures = Vec::new();
+    for (i, &part) in parts.iter().enumerate().skip(1) {
+        let end = if i == parts.len() - 1 {
+            rest.strip_suffix(part)?.len()
+        } else {
+            rest.find(part)?
+        };
+        captures.push(&rest[..end]);
+        rest = &rest[end + part.len()..];
+    }
+    (parts.len() > 1 || rest.is_empty()).then_some(captures)
+}
+
+fn fill_template(template: &str, captures: &[&str]) -> String {
+    let mut out = String::with_capacity(template.len());
+    for (i, part) in template.split("{}").enumerate() {
+        if i > 0 {
+            out.push_str(captures.get(i - 1).copied().unwrap_or_default());
+        }
+        out.push_str(part);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_accept_language() {
+        assert_eq!(Lang::from_accept_language("zh-CN,zh;q=0.9,en;q=0.8"), Some(Lang::ZhCn));
+        assert_eq!(Lang::from_accept_language("fr-FR, en-US;q=0.7, zh;q=0.5"), Some(Lang::En));
+        assert_eq!(Lang::from_accept_language("en;q=0.2, zh-Hans;q=0.8"), Some(Lang::ZhCn));
+        assert_eq!(Lang::from_accept_language("zh;q=0, de"), None);
+        assert_eq!(Lang::from_accept_language(""), None);
+    }
+
+    #[test]
+    fn test_localize_exact_and_template() {
+        assert_eq!(localize("Validation failed", Lang::ZhCn), "验证失败");
+        assert_eq!(localize("验证失败", Lang::En), "Validation failed");
+        assert_eq!(
+            localize("Upstream server with id 7 not found", Lang::ZhCn),
+            "ID 为 7 的上游服务器不存在"
+        );
+        assert_eq!(localize("端口必须在 1-65535 之间", Lang::En), "Port must be between 1 and 65535");
+        assert_eq!(localize("Validation failed", Lang::En), "Validation failed");
+        assert_eq!(localize("something new", Lang::ZhCn), "something new");
+    }
+
+    #[test]
+    fn test_localize_split() {
+        assert_eq!(
+            localize("Failed to save settings: disk full", Lang::ZhCn),
+            "保存设置失败: disk full"
+        );
+        assert_eq!(
+            localize("dns_cookies: must be one of: off, on, enforce", Lang::ZhCn),
+            "dns_cookies: 必须是以下之一: off, on, enforce"
+        );
+    }
+
+    #[test]
+    fn test_localize_value_skips_identifiers() {
+        let mut details = json!({
+            "errors": [{"field": "name", "message": "Name cannot be empty"}],
+            "name": "Validation failed",
+        });
+        localize_value(&mut details, Lang::ZhCn);
+        assert_eq!(details["errors"][0]["field"], "name");
+        assert_eq!(details["errors"][0]["message"], "名称不能为空");
+        assert_eq!(details["name"], "Validation failed");
+    }
+
+    #[test]
+    fn test_match_template() {
+        assert_eq!(match_template("a {} b {}", "a 1 b 2 3"), Some(vec!["1", "2 3"]));
+        assert_eq!(match_template("plain", "plain"), Some(vec![]));
+        assert_eq!(match_template("plain", "plain text"), None);
+        assert_eq!(match_template("{} not found", "x not found!"), None);
+    }
+}
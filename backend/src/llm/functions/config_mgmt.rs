@@ -6,6 +6,7 @@ use serde_json::{json, Value};
 use super::LlmFunction;
 use crate::llm::types::{FunctionDefinition, FunctionResult};
 use crate::state::AppState;
+use crate::web::backup::backup_document;
 
 pub struct ExportConfigFunction;
 
@@ -94,19 +95,28 @@ impl LlmFunction for BackupDatabaseFunction {
     fn definition(&self) -> FunctionDefinition {
         FunctionDefinition {
             name: "backup_database".to_string(),
-            description: "创建数据库备份".to_string(),
+            description: "创建配置备份 (DNS 记录、重写规则和上游服务器)，返回可直接用于恢复的备份文档".to_string(),
             parameters: json!({"type": "object", "properties": {}, "required": []}),
         }
     }
 
-    async fn execute(&self, _args: Value, _state: &AppState) -> FunctionResult {
-        // TODO: Implement database backup
-        let backup_name = format!("fluxdns_backup_{}.db", chrono::Utc::now().format("%Y%m%d_%H%M%S"));
-        
-        FunctionResult::success(json!({
-            "success": true,
-            "backup_name": backup_name,
-            "message": "数据库备份功能待实现"
-        }))
+    async fn execute(&self, _args: Value, state: &AppState) -> FunctionResult {
+        // Same document as GET /api/backup, so it can be restored as is
+        match backup_document(&state.db).await {
+            Ok(backup) => {
+                let count = |rows: &Option<Vec<_>>| rows.as_ref().map_or(0, Vec::len);
+                FunctionResult::success(json!({
+                    "success": true,
+                    "tables": {
+                        "records": count(&backup.records),
+                        "rewrite_rules": count(&backup.rewrite_rules),
+                        "upstreams": count(&backup.upstreams)
+                    },
+                    "backup": backup,
+                    "message": "备份已创建，可通过 POST /api/backup/restore 恢复"
+                }))
+            }
+            Err(e) => FunctionResult::error(format!("创建备份失败: {}", e)),
+        }
     }
 }
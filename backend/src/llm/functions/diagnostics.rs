@@ -10,8 +10,9 @@ use crate::llm::types::{FunctionDefinition, FunctionResult};
 use crate::state::AppState;
 use crate::dns::{DnsQuery, RecordType, CacheKey};
 use crate::dns::proxy::{UpstreamServer, UpstreamProtocol};
+use crate::dns::proxy::{measure_handshake, DEFAULT_WARM_SAMPLES, MAX_WARM_SAMPLES};
 use crate::dns::proxy::{
-    UdpDnsClient, DotDnsClient, DohDnsClient, DoqDnsClient, Doh3DnsClient, DnsClient
+    UdpDnsClient, TcpDnsClient, DotDnsClient, DohDnsClient, DoqDnsClient, Doh3DnsClient, DnsClient
 };
 
 pub struct TraceDnsResolutionFunction;
@@ -161,6 +162,7 @@ impl LlmFunction for TraceDnsResolutionFunction {
 
             let client: Box<dyn DnsClient> = match protocol {
                 UpstreamProtocol::Udp => Box::new(UdpDnsClient::new(server_config)),
+                UpstreamProtocol::Tcp => Box::new(TcpDnsClient::new(server_config)),
                 UpstreamProtocol::Dot => Box::new(DotDnsClient::new(server_config)),
                 UpstreamProtocol::Doh => Box::new(DohDnsClient::new(server_config)),
                 UpstreamProtocol::Doq => Box::new(DoqDnsClient::new(server_config)),
@@ -257,6 +259,7 @@ impl LlmFunction for TestUpstreamConnectivityFunction {
         // 2. Instantiate Client
         let client: Box<dyn DnsClient> = match protocol {
             UpstreamProtocol::Udp => Box::new(UdpDnsClient::new(server_config)),
+            UpstreamProtocol::Tcp => Box::new(TcpDnsClient::new(server_config)),
             UpstreamProtocol::Dot => Box::new(DotDnsClient::new(server_config)),
             UpstreamProtocol::Doh => Box::new(DohDnsClient::new(server_config)),
             UpstreamProtocol::Doq => Box::new(DoqDnsClient::new(server_config)),
@@ -341,6 +344,7 @@ impl LlmFunction for CompareUpstreamResponsesFunction {
                     
                     let client: Box<dyn DnsClient> = match protocol {
                         UpstreamProtocol::Udp => Box::new(UdpDnsClient::new(server_config)),
+                        UpstreamProtocol::Tcp => Box::new(TcpDnsClient::new(server_config)),
                         UpstreamProtocol::Dot => Box::new(DotDnsClient::new(server_config)),
                         UpstreamProtocol::Doh => Box::new(DohDnsClient::new(server_config)),
                         UpstreamProtocol::Doq => Box::new(DoqDnsClient::new(server_config)),
@@ -503,6 +507,7 @@ impl LlmFunction for QuerySingleUpstreamFunction {
 
         let client: Box<dyn DnsClient> = match protocol {
             UpstreamProtocol::Udp => Box::new(UdpDnsClient::new(server_config)),
+            UpstreamProtocol::Tcp => Box::new(TcpDnsClient::new(server_config)),
             UpstreamProtocol::Dot => Box::new(DotDnsClient::new(server_config)),
             UpstreamProtocol::Doh => Box::new(DohDnsClient::new(server_config)),
             UpstreamProtocol::Doq => Box::new(DoqDnsClient::new(server_config)),
@@ -546,3 +551,50 @@ impl LlmFunction for QuerySingleUpstreamFunction {
         }
     }
 }
+
+/// Measure connection setup and query latency per upstream protocol
+pub struct MeasureUpstreamHandshakeFunction;
+
+#[async_trait]
+impl LlmFunction for MeasureUpstreamHandshakeFunction {
+    fn definition(&self) -> FunctionDefinition {
+        FunctionDefinition {
+            name: "measure_upstream_handshake".to_string(),
+            description: "测量上游服务器的建连开销：TCP 连接、TLS/QUIC 握手、首次查询和连接复用后的查询耗时，用于判断 DoH/DoT/DoQ 是否值得".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "upstream_id": {"type": "integer", "description": "上游服务器 ID（可选，不填则测量全部）"},
+                    "samples": {"type": "integer", "description": "连接复用后的查询次数，1-20，默认 5"}
+                },
+                "required": []
+            }),
+        }
+    }
+
+    async fn execute(&self, args: Value, state: &AppState) -> FunctionResult {
+        let samples = args
+            .get("samples")
+            .and_then(|v| v.as_u64())
+            .map(|n| n as usize)
+            .unwrap_or(DEFAULT_WARM_SAMPLES)
+            .clamp(1, MAX_WARM_SAMPLES);
+
+        let servers = match args.get("upstream_id").and_then(|v| v.as_i64()) {
+            Some(id) => match state.upstream_manager.get_server(id).await {
+                Some(s) => vec![s],
+                None => return FunctionResult::error(format!("未找到 ID 为 {} 的上游服务器", id)),
+            },
+            None => state.upstream_manager.get_servers().await,
+        };
+        if servers.is_empty() {
+            return FunctionResult::error("没有启用的上游服务器");
+        }
+
+        let timings = futures::future::join_all(servers.iter().map(|s| measure_handshake(s, samples))).await;
+        FunctionResult::success(json!({
+            "samples": samples,
+            "results": timings
+        }))
+    }
+}
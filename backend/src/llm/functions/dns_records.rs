@@ -6,6 +6,17 @@ use serde_json::{json, Value};
 use super::LlmFunction;
 use crate::llm::types::{FunctionDefinition, FunctionResult};
 use crate::state::AppState;
+use crate::web::records::reload_local_records;
+use crate::web::DomainScope;
+
+/// Check that an existing record lies inside a delegated admin's zones
+async fn ensure_record_in_zones(id: i64, state: &AppState, domains: &DomainScope) -> Result<(), FunctionResult> {
+    match state.db.dns_records().get_by_id(id).await {
+        Ok(Some(record)) if domains.covers(&record.name) => Ok(()),
+        Ok(_) => Err(FunctionResult::error(format!("未找到 ID 为 {} 的记录", id))),
+        Err(e) => Err(FunctionResult::error(format!("查询失败: {}", e))),
+    }
+}
 
 /// Batch add DNS records
 pub struct BatchAddDnsRecordsFunction;
@@ -60,6 +71,25 @@ impl LlmFunction for BatchAddDnsRecordsFunction {
         }
     }
 
+    fn delegable(&self) -> bool {
+        true
+    }
+
+    async fn execute_scoped(&self, args: Value, state: &AppState, domains: &DomainScope) -> FunctionResult {
+        let outside: Vec<&str> = args
+            .get("records")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|r| r.get("name").and_then(|v| v.as_str()))
+            .filter(|name| !domains.covers(name))
+            .collect();
+        if !outside.is_empty() {
+            return FunctionResult::error(format!("域名不在委派区域内: {}", outside.join(", ")));
+        }
+        self.execute(args, state).await
+    }
+
     async fn execute(&self, args: Value, state: &AppState) -> FunctionResult {
         let records = match args.get("records").and_then(|v| v.as_array()) {
             Some(r) => r,
@@ -103,6 +133,14 @@ impl LlmFunction for BatchAddDnsRecordsFunction {
             }
         }
 
+        if !added.is_empty() {
+            reload_local_records(state.resolver.local_records()).await;
+            state
+                .cache
+                .purge_names(added.iter().filter_map(|r| r["name"].as_str()))
+                .await;
+        }
+
         FunctionResult::success(json!({
             "added_count": added.len(),
             "error_count": errors.len(),
@@ -146,6 +184,25 @@ impl LlmFunction for EditDnsRecordFunction {
         }
     }
 
+    fn delegable(&self) -> bool {
+        true
+    }
+
+    async fn execute_scoped(&self, args: Value, state: &AppState, domains: &DomainScope) -> FunctionResult {
+        let Some(id) = args.get("id").and_then(|v| v.as_i64()) else {
+            return FunctionResult::error("Missing required parameter: id");
+        };
+        if let Err(result) = ensure_record_in_zones(id, state, domains).await {
+            return result;
+        }
+        if let Some(name) = args["updates"].get("name").and_then(|v| v.as_str()) {
+            if !domains.covers(name) {
+                return FunctionResult::error(format!("域名不在委派区域内: {}", name));
+            }
+        }
+        self.execute(args, state).await
+    }
+
     async fn execute(&self, args: Value, state: &AppState) -> FunctionResult {
         let id = match args.get("id").and_then(|v| v.as_i64()) {
             Some(id) => id,
@@ -189,9 +246,21 @@ impl LlmFunction for EditDnsRecordFunction {
             id
         );
 
+        let mut changed_names: Vec<String> = updates
+            .get("name")
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .into_iter()
+            .collect();
+        if let Ok(Some(existing)) = state.db.dns_records().get_by_id(id).await {
+            changed_names.push(existing.name);
+        }
+
         match sqlx::query(&query).execute(state.db.pool()).await {
             Ok(result) => {
                 if result.rows_affected() > 0 {
+                    reload_local_records(state.resolver.local_records()).await;
+                    state.cache.purge_names(changed_names).await;
                     FunctionResult::success(json!({"success": true, "id": id, "message": "记录已更新"}))
                 } else {
                     FunctionResult::error(format!("未找到 ID 为 {} 的记录", id))
@@ -224,12 +293,28 @@ impl LlmFunction for DeleteDnsRecordFunction {
         }
     }
 
+    fn delegable(&self) -> bool {
+        true
+    }
+
+    async fn execute_scoped(&self, args: Value, state: &AppState, domains: &DomainScope) -> FunctionResult {
+        let Some(id) = args.get("id").and_then(|v| v.as_i64()) else {
+            return FunctionResult::error("Missing required parameter: id");
+        };
+        if let Err(result) = ensure_record_in_zones(id, state, domains).await {
+            return result;
+        }
+        self.execute(args, state).await
+    }
+
     async fn execute(&self, args: Value, state: &AppState) -> FunctionResult {
         let id = match args.get("id").and_then(|v| v.as_i64()) {
             Some(id) => id,
             None => return FunctionResult::error("Missing required parameter: id"),
         };
 
+        let existing = state.db.dns_records().get_by_id(id).await.ok().flatten();
+
         match sqlx::query("DELETE FROM dns_records WHERE id = ?")
             .bind(id)
             .execute(state.db.pool())
@@ -237,6 +322,8 @@ impl LlmFunction for DeleteDnsRecordFunction {
         {
             Ok(result) => {
                 if result.rows_affected() > 0 {
+                    reload_local_records(state.resolver.local_records()).await;
+                    state.cache.purge_names(existing.map(|r| r.name)).await;
                     FunctionResult::success(json!({"success": true, "id": id, "message": "记录已删除"}))
                 } else {
                     FunctionResult::error(format!("未找到 ID 为 {} 的记录", id))
@@ -277,6 +364,35 @@ impl LlmFunction for ListDnsRecordsFunction {
         }
     }
 
+    fn delegable(&self) -> bool {
+        true
+    }
+
+    async fn execute_scoped(&self, mut args: Value, state: &AppState, domains: &DomainScope) -> FunctionResult {
+        // Filter before applying the limit, so it counts only visible records
+        let limit = args.get("limit").and_then(|v| v.as_i64()).unwrap_or(50).max(0) as usize;
+        if let Some(args) = args.as_object_mut() {
+            args.insert("limit".to_string(), json!(-1));
+        }
+
+        let mut result = self.execute(args, state).await;
+        if let Some(data) = result.data.as_mut() {
+            let records: Vec<Value> = data["records"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|r| r["name"].as_str().is_some_and(|name| domains.covers(name)))
+                .take(limit)
+                .cloned()
+                .collect();
+            *data = json!({
+                "count": records.len(),
+                "records": records
+            });
+        }
+        result
+    }
+
     async fn execute(&self, args: Value, state: &AppState) -> FunctionResult {
         let name_filter = args.get("name").and_then(|v| v.as_str());
         let type_filter = args.get("record_type").and_then(|v| v.as_str());
@@ -4,8 +4,10 @@ use async_trait::async_trait;
 use serde_json::{json, Value};
 
 use super::LlmFunction;
+use crate::i18n;
 use crate::llm::types::{FunctionDefinition, FunctionResult};
 use crate::state::AppState;
+use crate::web::DomainScope;
 
 /// Get help information about available commands
 pub struct GetHelpFunction;
@@ -30,10 +32,18 @@ impl LlmFunction for GetHelpFunction {
         }
     }
 
+    fn delegable(&self) -> bool {
+        true
+    }
+
+    async fn execute_scoped(&self, args: Value, state: &AppState, _domains: &DomainScope) -> FunctionResult {
+        self.execute(args, state).await
+    }
+
     async fn execute(&self, args: Value, _state: &AppState) -> FunctionResult {
         let topic = args.get("topic").and_then(|v| v.as_str());
 
-        let help_content = match topic {
+        let mut help_content = match topic {
             Some("dns_records") => json!({
                 "topic": "DNS 记录管理",
                 "description": "管理本地 DNS 解析记录",
@@ -41,7 +51,11 @@ impl LlmFunction for GetHelpFunction {
                     {"name": "batch_add_dns_records", "description": "批量添加 DNS 记录"},
                     {"name": "edit_dns_record", "description": "编辑单条 DNS 记录"},
                     {"name": "delete_dns_record", "description": "删除 DNS 记录"},
-                    {"name": "list_dns_records", "description": "列出所有 DNS 记录"}
+                    {"name": "list_dns_records", "description": "列出所有 DNS 记录"},
+                    {"name": "create_service", "description": "创建包含多条记录的服务"},
+                    {"name": "list_services", "description": "列出服务及其记录"},
+                    {"name": "update_service", "description": "重命名或启停整个服务"},
+                    {"name": "delete_service", "description": "删除服务及其所有记录"}
                 ]
             }),
             Some("rewrite_rules") => json!({
@@ -171,6 +185,7 @@ impl LlmFunction for GetHelpFunction {
                 ]
             }),
         };
+        i18n::localize_value(&mut help_content, i18n::current());
 
         FunctionResult::success(help_content)
     }
@@ -199,6 +214,14 @@ impl LlmFunction for ExplainRecordTypeFunction {
         }
     }
 
+    fn delegable(&self) -> bool {
+        true
+    }
+
+    async fn execute_scoped(&self, args: Value, state: &AppState, _domains: &DomainScope) -> FunctionResult {
+        self.execute(args, state).await
+    }
+
     async fn execute(&self, args: Value, _state: &AppState) -> FunctionResult {
         let record_type = match args.get("type").and_then(|v| v.as_str()) {
             Some(t) => t.to_uppercase(),
@@ -4,7 +4,9 @@ use async_trait::async_trait;
 use serde_json::{json, Value};
 
 use super::LlmFunction;
+use crate::db::NotificationSeverity;
 use crate::llm::types::{FunctionDefinition, FunctionResult};
+use crate::services::notifications::notify;
 use crate::state::AppState;
 
 /// Analyze query logs
@@ -189,6 +191,17 @@ impl LlmFunction for DetectAnomaliesFunction {
             }));
         }
 
+        if !anomalies.is_empty() {
+            notify(
+                &state.db,
+                NotificationSeverity::Warning,
+                "anomaly",
+                format!("{} traffic anomalies detected in the last {}", anomalies.len(), time_range),
+                json!({ "time_range": time_range, "anomalies": anomalies }),
+            )
+            .await;
+        }
+
         FunctionResult::success(json!({
             "time_range": time_range,
             "anomaly_count": anomalies.len(),
@@ -4,6 +4,7 @@
 // functions that can be called by the LLM.
 
 pub mod dns_records;
+pub mod services;
 pub mod rewrite_rules;
 pub mod upstreams;
 pub mod dns_query;
@@ -23,6 +24,7 @@ use serde_json::Value;
 
 use super::types::{FunctionDefinition, FunctionResult, ToolDefinition};
 use crate::state::AppState;
+use crate::web::DomainScope;
 
 /// Trait for implementing callable functions
 #[async_trait]
@@ -32,12 +34,24 @@ pub trait LlmFunction: Send + Sync {
     
     /// Execute the function with the given arguments
     async fn execute(&self, args: Value, state: &AppState) -> FunctionResult;
+
+    /// Whether delegated admins may call the function
+    fn delegable(&self) -> bool {
+        false
+    }
+
+    /// Execute on behalf of a delegated admin, limited to its zones
+    async fn execute_scoped(&self, _args: Value, _state: &AppState, _domains: &DomainScope) -> FunctionResult {
+        FunctionResult::error("This function is not available to delegated admins")
+    }
 }
 
 /// Central registry for all LLM-callable functions
 pub struct FunctionRegistry {
     functions: HashMap<String, Arc<dyn LlmFunction>>,
     state: Arc<AppState>,
+    /// Set for delegated admins, who only get delegable functions
+    domains: Option<DomainScope>,
 }
 
 impl FunctionRegistry {
@@ -46,6 +60,7 @@ impl FunctionRegistry {
         let mut registry = Self {
             functions: HashMap::new(),
             state,
+            domains: None,
         };
         
         // Register all functions
@@ -54,6 +69,15 @@ impl FunctionRegistry {
         registry
     }
 
+    /// Limit the registry to a delegated admin's zones
+    pub fn with_domains(mut self, domains: Option<DomainScope>) -> Self {
+        if domains.is_some() {
+            self.functions.retain(|_, f| f.delegable());
+        }
+        self.domains = domains;
+        self
+    }
+
     /// Register all available functions
     fn register_all(&mut self) {
         // Help functions (always available)
@@ -65,6 +89,12 @@ impl FunctionRegistry {
         self.register(Arc::new(dns_records::EditDnsRecordFunction));
         self.register(Arc::new(dns_records::DeleteDnsRecordFunction));
         self.register(Arc::new(dns_records::ListDnsRecordsFunction));
+
+        // Service (record group) functions
+        self.register(Arc::new(services::CreateServiceFunction));
+        self.register(Arc::new(services::ListServicesFunction));
+        self.register(Arc::new(services::UpdateServiceFunction));
+        self.register(Arc::new(services::DeleteServiceFunction));
         
         // Rewrite Rules functions
         self.register(Arc::new(rewrite_rules::BatchAddRewriteRulesFunction));
@@ -92,6 +122,7 @@ impl FunctionRegistry {
         self.register(Arc::new(logs::GetQueryRankingFunction));
         
         // System settings functions
+        self.register(Arc::new(settings::ListSettingsFunction));
         self.register(Arc::new(settings::GetSystemStatusFunction));
         self.register(Arc::new(settings::UpdateQueryStrategyFunction));
         self.register(Arc::new(settings::ToggleRecordTypesFunction));
@@ -118,6 +149,7 @@ impl FunctionRegistry {
         self.register(Arc::new(diagnostics::CompareUpstreamResponsesFunction));
         self.register(Arc::new(diagnostics::ListUpstreamServersFunction));
         self.register(Arc::new(diagnostics::QuerySingleUpstreamFunction));
+        self.register(Arc::new(diagnostics::MeasureUpstreamHandshakeFunction));
         
         // Analytics functions
         self.register(Arc::new(analytics::GetClientStatsFunction));
@@ -162,7 +194,10 @@ impl FunctionRegistry {
             Err(e) => return FunctionResult::error(format!("Invalid arguments: {}", e)),
         };
 
-        func.execute(args, &self.state).await
+        match self.domains {
+            Some(ref domains) => func.execute_scoped(args, &self.state, domains).await,
+            None => func.execute(args, &self.state).await,
+        }
     }
 
     /// Get the count of registered functions
@@ -6,6 +6,15 @@ use serde_json::{json, Value};
 use super::LlmFunction;
 use crate::llm::types::{FunctionDefinition, FunctionResult};
 use crate::state::AppState;
+use crate::web::rewrite::rule_cache_name;
+
+/// Reload the rewrite engine and drop cached answers for changed names
+async fn apply_rule_changes(state: &AppState, changed_names: Vec<String>) {
+    if let Err(e) = state.rewrite_engine.reload_rules().await {
+        tracing::warn!("Failed to reload rewrite rules: {}", e);
+    }
+    state.cache.purge_names(changed_names).await;
+}
 
 /// Batch add rewrite rules
 pub struct BatchAddRewriteRulesFunction;
@@ -37,7 +46,7 @@ impl LlmFunction for BatchAddRewriteRulesFunction {
                                 "action_type": {
                                     "type": "string",
                                     "description": "动作类型",
-                                    "enum": ["block", "map_ip", "map_domain"]
+                                    "enum": ["block", "map_ip", "map_domain", "nodata", "passthru"]
                                 },
                                 "action_value": {
                                     "type": "string",
@@ -114,6 +123,17 @@ impl LlmFunction for BatchAddRewriteRulesFunction {
             }
         }
 
+        if !added.is_empty() {
+            let changed_names = added
+                .iter()
+                .filter_map(|r| {
+                    rule_cache_name(r["pattern"].as_str()?, r["match_type"].as_str()?, false)
+                })
+                .map(str::to_string)
+                .collect();
+            apply_rule_changes(state, changed_names).await;
+        }
+
         FunctionResult::success(json!({
             "added_count": added.len(),
             "error_count": errors.len(),
@@ -145,7 +165,7 @@ impl LlmFunction for EditRewriteRuleFunction {
                         "properties": {
                             "pattern": {"type": "string"},
                             "match_type": {"type": "string", "enum": ["exact", "wildcard", "regex"]},
-                            "action_type": {"type": "string", "enum": ["block", "map_ip", "map_domain"]},
+                            "action_type": {"type": "string", "enum": ["block", "map_ip", "map_domain", "nodata", "passthru"]},
                             "action_value": {"type": "string"},
                             "priority": {"type": "integer"},
                             "enabled": {"type": "boolean"}
@@ -199,9 +219,29 @@ impl LlmFunction for EditRewriteRuleFunction {
             id
         );
 
+        let existing = state.db.rewrite_rules().get_by_id(id).await.ok().flatten();
+
         match sqlx::query(&query).execute(state.db.pool()).await {
             Ok(result) => {
                 if result.rows_affected() > 0 {
+                    let mut changed_names = Vec::new();
+                    if let Some(ref existing) = existing {
+                        let pattern = updates.get("pattern").and_then(|v| v.as_str());
+                        let match_type = updates
+                            .get("match_type")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or(existing.match_type.as_str());
+                        changed_names.extend(
+                            pattern.and_then(|p| rule_cache_name(p, match_type, existing.shadow)),
+                        );
+                        changed_names.extend(rule_cache_name(
+                            &existing.pattern,
+                            &existing.match_type,
+                            existing.shadow,
+                        ));
+                    }
+                    let changed_names = changed_names.into_iter().map(str::to_string).collect();
+                    apply_rule_changes(state, changed_names).await;
                     FunctionResult::success(json!({"success": true, "id": id, "message": "规则已更新"}))
                 } else {
                     FunctionResult::error(format!("未找到 ID 为 {} 的规则", id))
@@ -240,6 +280,8 @@ impl LlmFunction for DeleteRewriteRuleFunction {
             None => return FunctionResult::error("Missing required parameter: id"),
         };
 
+        let existing = state.db.rewrite_rules().get_by_id(id).await.ok().flatten();
+
         match sqlx::query("DELETE FROM rewrite_rules WHERE id = ?")
             .bind(id)
             .execute(state.db.pool())
@@ -247,6 +289,12 @@ impl LlmFunction for DeleteRewriteRuleFunction {
         {
             Ok(result) => {
                 if result.rows_affected() > 0 {
+                    let changed_names = existing
+                        .iter()
+                        .filter_map(|r| rule_cache_name(&r.pattern, &r.match_type, r.shadow))
+                        .map(str::to_string)
+                        .collect();
+                    apply_rule_changes(state, changed_names).await;
                     FunctionResult::success(json!({"success": true, "id": id, "message": "规则已删除"}))
                 } else {
                     FunctionResult::error(format!("未找到 ID 为 {} 的规则", id))
@@ -276,7 +324,7 @@ impl LlmFunction for ListRewriteRulesFunction {
                     "action_type": {
                         "type": "string",
                         "description": "按动作类型筛选",
-                        "enum": ["block", "map_ip", "map_domain"]
+                        "enum": ["block", "map_ip", "map_domain", "nodata", "passthru"]
                     },
                     "limit": {
                         "type": "integer",
@@ -0,0 +1,243 @@
+// Service Functions - Manage record groups (multi-record services)
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use super::LlmFunction;
+use crate::llm::types::{FunctionDefinition, FunctionResult};
+use crate::state::AppState;
+use crate::web::services::{
+    create_service, delete_service, list_service_views, load_service, update_service, CreateServiceRequest,
+    ServiceView, ServicesState, UpdateServiceRequest,
+};
+use crate::web::ApiError;
+
+fn services_state(state: &AppState) -> ServicesState {
+    ServicesState {
+        db: state.db.clone(),
+        local_records: state.resolver.local_records().clone(),
+        cache: state.cache.clone(),
+    }
+}
+
+fn api_error(e: ApiError) -> FunctionResult {
+    match e.details {
+        Some(details) => FunctionResult::error(format!("{}: {}", e.message, details)),
+        None => FunctionResult::error(e.message),
+    }
+}
+
+fn service_json(service: &ServiceView) -> Value {
+    json!({
+        "id": service.group.id,
+        "name": service.group.name,
+        "domain": service.group.domain,
+        "description": service.group.description,
+        "records": service.records.iter().map(|r| json!({
+            "id": r.record.id,
+            "name": r.record.name,
+            "type": r.record.record_type,
+            "value": r.record.value,
+            "ttl": r.record.ttl,
+            "enabled": r.record.enabled
+        })).collect::<Vec<_>>()
+    })
+}
+
+/// Create a service with its records
+pub struct CreateServiceFunction;
+
+#[async_trait]
+impl LlmFunction for CreateServiceFunction {
+    fn definition(&self) -> FunctionDefinition {
+        FunctionDefinition {
+            name: "create_service".to_string(),
+            description: "创建服务（记录组）：一次性创建同一域名下的多条记录（如 A+AAAA+TXT+SRV），之后可整体重命名、启停和删除".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "name": {
+                        "type": "string",
+                        "description": "服务名称，如 web"
+                    },
+                    "domain": {
+                        "type": "string",
+                        "description": "服务域名，如 web.lan"
+                    },
+                    "description": {
+                        "type": "string",
+                        "description": "服务说明"
+                    },
+                    "ttl": {
+                        "type": "integer",
+                        "description": "记录默认 TTL，单位秒，默认 300"
+                    },
+                    "records": {
+                        "type": "array",
+                        "description": "服务包含的记录模板",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "name": {
+                                    "type": "string",
+                                    "description": "相对名称，空或 @ 表示服务域名本身，如 _http._tcp 表示 _http._tcp.<域名>"
+                                },
+                                "record_type": {
+                                    "type": "string",
+                                    "description": "记录类型",
+                                    "enum": ["A", "AAAA", "CNAME", "MX", "TXT", "PTR", "NS", "SRV"]
+                                },
+                                "value": {
+                                    "type": "string",
+                                    "description": "记录值，{domain} 会替换为服务域名"
+                                },
+                                "ttl": {
+                                    "type": "integer",
+                                    "description": "单条记录 TTL，默认使用服务 TTL"
+                                },
+                                "priority": {
+                                    "type": "integer",
+                                    "description": "优先级（仅 MX/SRV 记录需要）"
+                                }
+                            },
+                            "required": ["record_type", "value"]
+                        }
+                    }
+                },
+                "required": ["name", "domain", "records"]
+            }),
+        }
+    }
+
+    async fn execute(&self, args: Value, state: &AppState) -> FunctionResult {
+        let request: CreateServiceRequest = match serde_json::from_value(args) {
+            Ok(r) => r,
+            Err(e) => return FunctionResult::error(format!("参数无效: {}", e)),
+        };
+
+        match create_service(&services_state(state), request).await {
+            Ok(service) => FunctionResult::success(service_json(&service)),
+            Err(e) => api_error(e),
+        }
+    }
+}
+
+/// List services
+pub struct ListServicesFunction;
+
+#[async_trait]
+impl LlmFunction for ListServicesFunction {
+    fn definition(&self) -> FunctionDefinition {
+        FunctionDefinition {
+            name: "list_services".to_string(),
+            description: "列出所有服务（记录组）及其包含的记录".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        }
+    }
+
+    async fn execute(&self, _args: Value, state: &AppState) -> FunctionResult {
+        match list_service_views(&services_state(state)).await {
+            Ok(services) => FunctionResult::success(json!({
+                "count": services.len(),
+                "services": services.iter().map(service_json).collect::<Vec<_>>()
+            })),
+            Err(e) => api_error(e),
+        }
+    }
+}
+
+/// Rename, move or toggle a service
+pub struct UpdateServiceFunction;
+
+#[async_trait]
+impl LlmFunction for UpdateServiceFunction {
+    fn definition(&self) -> FunctionDefinition {
+        FunctionDefinition {
+            name: "update_service".to_string(),
+            description: "修改服务：更换域名时同步重命名所有记录并更新指向该域名的 CNAME/MX/SRV 等目标，也可整体启停或修改 TTL".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "id": {
+                        "type": "integer",
+                        "description": "服务 ID"
+                    },
+                    "name": {"type": "string", "description": "新服务名称"},
+                    "domain": {"type": "string", "description": "新服务域名"},
+                    "description": {"type": "string", "description": "新服务说明"},
+                    "enabled": {"type": "boolean", "description": "启用或停用所有记录"},
+                    "ttl": {"type": "integer", "description": "所有记录的新 TTL"}
+                },
+                "required": ["id"]
+            }),
+        }
+    }
+
+    async fn execute(&self, args: Value, state: &AppState) -> FunctionResult {
+        let id = match args.get("id").and_then(|v| v.as_i64()) {
+            Some(id) => id,
+            None => return FunctionResult::error("Missing required parameter: id"),
+        };
+        let request: UpdateServiceRequest = match serde_json::from_value(args) {
+            Ok(r) => r,
+            Err(e) => return FunctionResult::error(format!("参数无效: {}", e)),
+        };
+
+        let state = services_state(state);
+        let group = match load_service(&state.db, id).await {
+            Ok(group) => group,
+            Err(e) => return api_error(e),
+        };
+        match update_service(&state, group, request).await {
+            Ok(service) => FunctionResult::success(service_json(&service)),
+            Err(e) => api_error(e),
+        }
+    }
+}
+
+/// Delete a service with all of its records
+pub struct DeleteServiceFunction;
+
+#[async_trait]
+impl LlmFunction for DeleteServiceFunction {
+    fn definition(&self) -> FunctionDefinition {
+        FunctionDefinition {
+            name: "delete_service".to_string(),
+            description: "删除服务及其包含的所有记录".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "id": {
+                        "type": "integer",
+                        "description": "要删除的服务 ID"
+                    }
+                },
+                "required": ["id"]
+            }),
+        }
+    }
+
+    async fn execute(&self, args: Value, state: &AppState) -> FunctionResult {
+        let id = match args.get("id").and_then(|v| v.as_i64()) {
+            Some(id) => id,
+            None => return FunctionResult::error("Missing required parameter: id"),
+        };
+
+        let state = services_state(state);
+        let group = match load_service(&state.db, id).await {
+            Ok(group) => group,
+            Err(e) => return api_error(e),
+        };
+        match delete_service(&state, &group).await {
+            Ok(deleted_records) => FunctionResult::success(json!({
+                "deleted": group.name,
+                "deleted_records": deleted_records
+            })),
+            Err(e) => api_error(e),
+        }
+    }
+}
@@ -6,6 +6,44 @@ use serde_json::{json, Value};
 use super::LlmFunction;
 use crate::llm::types::{FunctionDefinition, FunctionResult};
 use crate::state::AppState;
+use crate::web::settings_registry::{find_setting, SettingSchema, SETTINGS};
+
+pub struct ListSettingsFunction;
+
+#[async_trait]
+impl LlmFunction for ListSettingsFunction {
+    fn definition(&self) -> FunctionDefinition {
+        FunctionDefinition {
+            name: "list_settings".to_string(),
+            description: "列出可通过 /api/settings 修改的系统设置，包括类型、默认值、当前值和说明".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string", "description": "只查看指定设置 (可选)"}
+                },
+                "required": []
+            }),
+        }
+    }
+
+    async fn execute(&self, args: Value, state: &AppState) -> FunctionResult {
+        let defs: Vec<_> = match args.get("name").and_then(|v| v.as_str()) {
+            Some(name) => match find_setting(name) {
+                Some(def) => vec![def],
+                None => return FunctionResult::error(format!("未知设置: {}", name)),
+            },
+            None => SETTINGS.iter().collect(),
+        };
+
+        let mut settings = Vec::with_capacity(defs.len());
+        for def in defs {
+            let mut entry = serde_json::to_value(SettingSchema::from(def)).unwrap_or_default();
+            entry["current"] = def.current_value(&state.db).await;
+            settings.push(entry);
+        }
+        FunctionResult::success(json!({ "settings": settings }))
+    }
+}
 
 pub struct GetSystemStatusFunction;
 
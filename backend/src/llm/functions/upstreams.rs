@@ -60,7 +60,7 @@ impl LlmFunction for BatchImportUpstreamsFunction {
             }
 
             let result = sqlx::query(
-                "INSERT INTO upstream_servers (name, address, protocol, timeout, enabled) VALUES (?, ?, ?, ?, 1)"
+                "INSERT INTO upstream_servers (name, address, protocol, timeout, enabled, sort_order) VALUES (?, ?, ?, ?, 1, (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM upstream_servers))"
             )
             .bind(name)
             .bind(address)
@@ -0,0 +1,176 @@
+//! JSON log lines
+//!
+//! With `LOG_FORMAT=json` every event is written as one JSON object with
+//! fixed top-level fields, so Loki or ELK can ingest the log without regex
+//! parsing:
+//!
+//! ```json
+//! {"timestamp":"2024-01-01T12:00:00.000+08:00","level":"INFO","target":"fluxdns::dns","message":"...","trace_id":"...","fields":{...}}
+//! ```
+//!
+//! `trace_id` comes from the enclosing `dns_query` span and is left out for
+//! events outside a query; other event fields go under `fields`.
+
+use std::fmt;
+
+use chrono::{Local, SecondsFormat};
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Trace ID of a span, kept in its extensions for the formatter
+struct TraceId(String);
+
+/// Remembers the `trace_id` field of each span that has one
+pub struct TraceIdLayer;
+
+impl<S> Layer<S> for TraceIdLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = JsonFields::default();
+        attrs.record(&mut fields);
+        if let Some(Value::String(trace_id)) = fields.0.remove("trace_id") {
+            if let Some(span) = ctx.span(id) {
+                span.extensions_mut().insert(TraceId(trace_id));
+            }
+        }
+    }
+}
+
+/// Formats events as single-line JSON objects
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let trace_id = ctx.event_scope().and_then(|mut scope| {
+            scope.find_map(|span| {
+                let extensions = span.extensions();
+                extensions.get::<TraceId>().map(|t| t.0.clone())
+            })
+        });
+        writeln!(writer, "{}", event_json(event, trace_id))
+    }
+}
+
+/// JSON object for one event
+fn event_json(event: &Event<'_>, trace_id: Option<String>) -> Value {
+    let mut fields = JsonFields::default();
+    event.record(&mut fields);
+    let mut fields = fields.0;
+    let metadata = event.metadata();
+
+    let mut line = Map::new();
+    line.insert(
+        "timestamp".to_string(),
+        Value::String(Local::now().to_rfc3339_opts(SecondsFormat::Millis, false)),
+    );
+    line.insert("level".to_string(), Value::String(metadata.level().to_string()));
+    line.insert("target".to_string(), Value::String(metadata.target().to_string()));
+    line.insert(
+        "message".to_string(),
+        fields.remove("message").unwrap_or_else(|| Value::String(String::new())),
+    );
+    if let Some(trace_id) = trace_id {
+        line.insert("trace_id".to_string(), Value::String(trace_id));
+    }
+    if !fields.is_empty() {
+        line.insert("fields".to_string(), Value::Object(fields));
+    }
+    Value::Object(line)
+}
+
+/// Collects recorded fields as JSON values
+#[derive(Default)]
+struct JsonFields(Map<String, Value>);
+
+impl Visit for JsonFields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_string(), Value::String(format!("{:?}", value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_lines_carry_trace_id() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry().with(TraceIdLayer).with(
+            tracing_subscriber::fmt::layer()
+                .event_format(JsonFormat)
+                .with_writer(move || writer.clone()),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(port = 53, "listener started");
+            let span = tracing::info_span!("dns_query", trace_id = %"abc123", name = "example.com");
+            span.in_scope(|| tracing::warn!("upstream timeout"));
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<Value> = output.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[0]["message"], "listener started");
+        assert_eq!(lines[0]["fields"]["port"], 53);
+        assert!(lines[0].get("trace_id").is_none());
+        assert!(lines[0]["timestamp"].is_string());
+        assert!(lines[0]["target"].as_str().unwrap().contains("log::json"));
+
+        assert_eq!(lines[1]["level"], "WARN");
+        assert_eq!(lines[1]["message"], "upstream timeout");
+        assert_eq!(lines[1]["trace_id"], "abc123");
+        assert!(lines[1].get("fields").is_none());
+    }
+}
@@ -10,10 +10,15 @@
 //! - Automatic cleanup of expired logs (Requirements 7.4)
 //! - Environment variable configuration (Requirements 7.5, 7.6, 7.7)
 //! - Config file fallback (Requirements 7.8)
+//! - Optional JSON lines for log ingestion (`LOG_FORMAT=json`)
+
+mod json;
+#[cfg(feature = "otel")]
+mod otel;
 
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, SystemTime};
 
 use anyhow::{Context, Result};
@@ -24,9 +29,13 @@ use tracing_subscriber::fmt::format::FmtSpan;
 use tracing_subscriber::fmt::time::FormatTime;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
 use tracing_subscriber::EnvFilter;
 use chrono::Local;
 
+use crate::clock::Clock;
+use json::{JsonFormat, TraceIdLayer};
+
 /// Custom time formatter for logs (yyyy-MM-dd HH:mm:ss)
 #[derive(Clone, Copy, Debug)]
 struct LocalTimeFormatter;
@@ -55,6 +64,12 @@ pub struct LogConfig {
     pub rotation: RotationPolicy,
     /// Number of days to retain log files
     pub retention_days: u32,
+    /// Format of the log files
+    pub format: LogFormat,
+    /// Format of the console output
+    pub console_format: LogFormat,
+    /// OTLP collector receiving resolution spans (`otel` feature)
+    pub otlp_endpoint: Option<String>,
 }
 
 impl Default for LogConfig {
@@ -65,6 +80,27 @@ impl Default for LogConfig {
             max_size: 10 * 1024 * 1024, // 10MB
             rotation: RotationPolicy::Daily,
             retention_days: 30,
+            format: LogFormat::Text,
+            console_format: LogFormat::Text,
+            otlp_endpoint: None,
+        }
+    }
+}
+
+/// Log line format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl From<&str> for LogFormat {
+    fn from(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "json" => LogFormat::Json,
+            _ => LogFormat::Text,
         }
     }
 }
@@ -114,13 +150,24 @@ impl From<&str> for RotationPolicy {
 /// - 7.8: Fall back to config file when environment variables not set
 pub struct LogManager {
     config: LogConfig,
+    /// Time source for file ages
+    clock: Arc<Clock>,
 }
 
 #[allow(dead_code)]
 impl LogManager {
     /// Create a new LogManager with the given configuration
     pub fn new(config: LogConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            clock: Arc::new(Clock::system()),
+        }
+    }
+
+    /// Measure file ages by the given clock
+    pub fn with_clock(mut self, clock: Arc<Clock>) -> Self {
+        self.clock = clock;
+        self
     }
 
     /// Initialize the logging system with default configuration
@@ -152,13 +199,15 @@ impl LogManager {
         // Parse log level
         let level_filter = Self::parse_level_filter(&config.level);
 
-        // Build the subscriber with both console and file output
-        let env_filter = EnvFilter::try_from_default_env()
+        // Build the subscriber with both console and file output; the
+        // level applies per layer so spans can still be exported over OTLP
+        let env_filter = || EnvFilter::try_from_default_env()
             .unwrap_or_else(|_| EnvFilter::new(level_filter));
 
         // File layer - writes to rolling log files
+        let file_json = config.format == LogFormat::Json;
         let file_layer = tracing_subscriber::fmt::layer()
-            .with_writer(non_blocking)
+            .with_writer(non_blocking.clone())
             .with_ansi(false)
             .with_target(true)
             .with_thread_ids(false)
@@ -166,23 +215,54 @@ impl LogManager {
             .with_line_number(false)
             .with_timer(LocalTimeFormatter)
             .with_span_events(FmtSpan::CLOSE);
+        let json_file_layer = tracing_subscriber::fmt::layer()
+            .with_writer(non_blocking)
+            .with_span_events(FmtSpan::CLOSE)
+            .event_format(JsonFormat);
 
         // Console layer - writes to stdout
+        let console_json = config.console_format == LogFormat::Json;
         let console_layer = tracing_subscriber::fmt::layer()
             .with_target(true)
             .with_thread_ids(false)
             .with_timer(LocalTimeFormatter);
+        let json_console_layer = tracing_subscriber::fmt::layer().event_format(JsonFormat);
+
+        #[cfg(feature = "otel")]
+        let otel_layer = match config.otlp_endpoint.as_deref() {
+            Some(endpoint) => Some(otel::layer(endpoint)?),
+            None => None,
+        };
+        #[cfg(not(feature = "otel"))]
+        let otel_layer: Option<tracing_subscriber::layer::Identity> = None;
 
         // Initialize the subscriber
         tracing_subscriber::registry()
-            .with(env_filter)
-            .with(file_layer)
-            .with(console_layer)
+            .with((file_json || console_json).then(|| TraceIdLayer.with_filter(env_filter())))
+            .with((!file_json).then(|| file_layer.with_filter(env_filter())))
+            .with(file_json.then(|| json_file_layer.with_filter(env_filter())))
+            .with((!console_json).then(|| console_layer.with_filter(env_filter())))
+            .with(console_json.then(|| json_console_layer.with_filter(env_filter())))
+            .with(otel_layer)
             .init();
 
+        if let Some(endpoint) = &config.otlp_endpoint {
+            if cfg!(feature = "otel") {
+                tracing::info!("Exporting resolution spans to {}", endpoint);
+            } else {
+                tracing::warn!("OTEL_EXPORTER_OTLP_ENDPOINT is set but FluxDNS was built without the `otel` feature, spans are not exported");
+            }
+        }
+
         Ok(())
     }
 
+    /// Flush spans queued for OTLP export before exiting
+    pub fn shutdown() {
+        #[cfg(feature = "otel")]
+        otel::shutdown();
+    }
+
     /// Load configuration from environment variables
     /// Falls back to defaults when environment variables are not set
     pub fn load_config_from_env() -> LogConfig {
@@ -207,12 +287,25 @@ impl LogManager {
             .and_then(|v| v.parse().ok())
             .unwrap_or(30);
 
+        let format = std::env::var("LOG_FORMAT")
+            .map(|v| LogFormat::from(v.as_str()))
+            .unwrap_or(LogFormat::Text);
+
+        let console_format = std::env::var("LOG_CONSOLE_FORMAT")
+            .map(|v| LogFormat::from(v.as_str()))
+            .unwrap_or(LogFormat::Text);
+
+        let otlp_endpoint = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok();
+
         LogConfig {
             path,
             level,
             max_size,
             rotation,
             retention_days,
+            format,
+            console_format,
+            otlp_endpoint,
         }
     }
 
@@ -259,7 +352,7 @@ impl LogManager {
         }
 
         let retention_duration = Duration::from_secs(retention_days as u64 * 24 * 60 * 60);
-        let now = SystemTime::now();
+        let now = self.clock.system_now();
 
         let entries = fs::read_dir(dir)
             .with_context(|| format!("Failed to read log directory: {:?}", dir))?;
@@ -414,6 +507,16 @@ mod tests {
         assert_eq!(config.max_size, 10 * 1024 * 1024);
         assert_eq!(config.rotation, RotationPolicy::Daily);
         assert_eq!(config.retention_days, 30);
+        assert_eq!(config.format, LogFormat::Text);
+        assert_eq!(config.console_format, LogFormat::Text);
+    }
+
+    #[test]
+    fn test_log_format_from_str() {
+        assert_eq!(LogFormat::from("json"), LogFormat::Json);
+        assert_eq!(LogFormat::from("JSON"), LogFormat::Json);
+        assert_eq!(LogFormat::from("text"), LogFormat::Text);
+        assert_eq!(LogFormat::from("unknown"), LogFormat::Text);
     }
 
     #[test]
@@ -498,6 +601,28 @@ mod tests {
         assert!(log_path.exists());
     }
 
+    #[test]
+    fn test_cleanup_removes_expired_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("dns-proxy.log.2024-01-01");
+        File::create(&log_path).unwrap().write_all(b"old").unwrap();
+
+        let config = LogConfig {
+            path: temp_dir.path().to_path_buf(),
+            retention_days: 7,
+            ..Default::default()
+        };
+        let clock = Arc::new(Clock::manual());
+        let manager = LogManager::new(config).with_clock(clock.clone());
+
+        clock.advance(Duration::from_secs(6 * 24 * 60 * 60));
+        assert_eq!(manager.cleanup_old_logs().unwrap().deleted_files, 0);
+        clock.advance(Duration::from_secs(2 * 24 * 60 * 60));
+        let result = manager.cleanup_old_logs().unwrap();
+        assert_eq!((result.deleted_files, result.deleted_bytes), (1, 3));
+        assert!(!log_path.exists());
+    }
+
     #[test]
     fn test_list_log_files() {
         let temp_dir = TempDir::new().unwrap();
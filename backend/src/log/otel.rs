@@ -0,0 +1,49 @@
+//! OTLP span export
+//!
+//! With the `otel` feature and `OTEL_EXPORTER_OTLP_ENDPOINT` set, the
+//! resolution spans (`dns_query` and its `rewrite_check`, `local_records`,
+//! `cache_lookup`, `upstream` and `upstream_attempt` children) are sent to
+//! an OpenTelemetry collector over OTLP/gRPC, so a tracing backend such as
+//! Jaeger or Tempo shows where slow queries spend their time. Spans are
+//! exported at debug level whatever `LOG_LEVEL` is; log events are not.
+
+use anyhow::Result;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tracing::{Level, Subscriber};
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Service name reported with every span
+const SERVICE_NAME: &str = "fluxdns";
+
+/// Layer exporting spans to the OTLP collector at `endpoint`
+pub fn layer<S>(endpoint: &str) -> Result<impl Layer<S>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+        .with_trace_config(
+            trace::Config::default().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                SERVICE_NAME,
+            )])),
+        )
+        .install_batch(runtime::Tokio)?;
+    let tracer = provider.tracer(SERVICE_NAME);
+    opentelemetry::global::set_tracer_provider(provider);
+
+    Ok(tracing_opentelemetry::layer()
+        .with_tracer(tracer)
+        .with_filter(filter_fn(|meta| meta.is_span() && *meta.level() <= Level::DEBUG)))
+}
+
+/// Flush spans still queued for export
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
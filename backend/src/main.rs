@@ -4,10 +4,15 @@
 //! with a web management interface.
 
 mod bootstrap;
+mod build_info;
+mod clock;
 mod config;
 mod db;
 mod dns;
 mod error;
+mod i18n;
+#[cfg(feature = "grpc")]
+mod grpc;
 mod llm;
 mod log;
 mod state;
@@ -1,12 +1,32 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use chrono::{DateTime, Utc};
 use tokio::time::{interval, Duration, Instant};
 use tokio::sync::Mutex;
+use crate::db::NotificationSeverity;
+use crate::dns::{loop_guard, FailMode};
+use crate::i18n;
+use crate::services::notifications::notify;
 use crate::state::AppState;
 use serde_json::json;
 
+/// Warn about listener certificates expiring within this many days
+const CERT_EXPIRY_WARNING_DAYS: i64 = 14;
+
 pub struct AlertManager {
     state: Arc<AppState>,
     last_alert_time: Mutex<Option<Instant>>,
+    /// Whether the current database degradation has been alerted
+    db_degraded_alerted: AtomicBool,
+    /// Whether the current policy data outage has been alerted
+    policy_degraded_alerted: AtomicBool,
+    /// Looped queries already covered by an alert
+    loops_alerted: AtomicU64,
+    /// Upstreams last seen unhealthy
+    unhealthy_upstreams: Mutex<HashSet<i64>>,
+    /// Expiry of the listener certificates already notified, by protocol
+    certs_notified: Mutex<HashMap<String, DateTime<Utc>>>,
 }
 
 impl AlertManager {
@@ -14,6 +34,11 @@ impl AlertManager {
         Self {
             state,
             last_alert_time: Mutex::new(None),
+            db_degraded_alerted: AtomicBool::new(false),
+            policy_degraded_alerted: AtomicBool::new(false),
+            loops_alerted: AtomicU64::new(0),
+            unhealthy_upstreams: Mutex::new(HashSet::new()),
+            certs_notified: Mutex::new(HashMap::new()),
         }
     }
 
@@ -26,20 +51,36 @@ impl AlertManager {
                 if let Err(e) = self.check_alerts().await {
                     tracing::error!("Failed to check alerts: {}", e);
                 }
+                if let Err(e) = self.check_database().await {
+                    tracing::error!("Failed to send database alert: {}", e);
+                }
+                if let Err(e) = self.check_fail_policy().await {
+                    tracing::error!("Failed to send fail policy alert: {}", e);
+                }
+                if let Err(e) = self.check_loops().await {
+                    tracing::error!("Failed to send forwarding loop alert: {}", e);
+                }
+                self.check_upstream_health().await;
+                if let Err(e) = self.check_certificates().await {
+                    tracing::error!("Failed to check listener certificates: {}", e);
+                }
             }
         });
     }
 
-    async fn check_alerts(&self) -> anyhow::Result<()> {
+    /// Alert webhook, if alerts are enabled and one is configured
+    async fn webhook(&self) -> anyhow::Result<Option<String>> {
         let config = self.state.db.system_config();
-        
-        // 1. Check if enabled
-        let enabled = config.get("alert_enabled").await?.unwrap_or_default() == "true";
-        if !enabled {
-            return Ok(());
+        if config.get("alert_enabled").await?.unwrap_or_default() != "true" {
+            return Ok(None);
         }
+        Ok(config.get("alert_webhook_url").await?.filter(|w| !w.is_empty()))
+    }
 
-        // 2. Cooldown check (5 minutes)
+    async fn check_alerts(&self) -> anyhow::Result<()> {
+        let config = self.state.db.system_config();
+
+        // 1. Cooldown check (5 minutes)
         let mut last_alert = self.last_alert_time.lock().await;
         if let Some(last) = *last_alert {
             if last.elapsed() < Duration::from_secs(300) {
@@ -47,18 +88,12 @@ impl AlertManager {
             }
         }
 
-        // 3. Get thresholds
-        let webhook = config.get("alert_webhook_url").await?;
-        if webhook.is_none() || webhook.as_ref().unwrap().is_empty() {
-            return Ok(());
-        }
-        let webhook = webhook.unwrap();
-        
+        // 2. Get thresholds
         let latency_threshold: f64 = config.get("alert_latency_threshold_ms").await?
             .and_then(|v| v.parse().ok())
             .unwrap_or(200.0);
 
-        // 4. Check current stats
+        // 3. Check current stats
         let stats = self.state.upstream_manager.get_all_stats().await;
         
         // Calculate weighted average latency based on query count
@@ -79,32 +114,274 @@ impl AlertManager {
         };
         
         if avg_latency > latency_threshold && total_queries_count > 0 {
-            let message = format!(
-                "🚨 **High Latency Alert**\n\nCurrent Average Latency: **{:.2}ms**\nThreshold: {}ms\n\nPlease check your upstream servers.",
-                avg_latency, latency_threshold
-            );
-            
-            self.send_alert(&webhook, &message).await?;
+            notify(
+                &self.state.db,
+                NotificationSeverity::Warning,
+                "upstream",
+                format!(
+                    "Average upstream latency {:.2}ms exceeds the {}ms threshold",
+                    avg_latency, latency_threshold
+                ),
+                json!({ "avg_latency_ms": avg_latency, "threshold_ms": latency_threshold }),
+            )
+            .await;
             *last_alert = Some(Instant::now());
+
+            // 4. Post to the webhook if enabled
+            if let Some(webhook) = self.webhook().await? {
+                let message = format!(
+                    "🚨 **High Latency Alert**\n\nCurrent Average Latency: **{:.2}ms**\nThreshold: {}ms\n\nPlease check your upstream servers.",
+                    avg_latency, latency_threshold
+                );
+                self.send_alert(&webhook, &message).await?;
+            }
         }
 
         Ok(())
     }
 
-    async fn send_alert(&self, webhook: &str, message: &str) -> anyhow::Result<()> {
-        let client = reqwest::Client::new();
-        // Webhook payload compatible with Slack, Discord, etc.
-        let payload = json!({
-            "text": message,
-            "content": message 
-        });
+    /// Alert once when the database enters degraded mode and once when it recovers
+    async fn check_database(&self) -> anyhow::Result<()> {
+        let status = self.state.db.health().status();
+        if status.degraded == self.db_degraded_alerted.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        self.db_degraded_alerted.store(status.degraded, Ordering::Relaxed);
 
-        client.post(webhook)
-            .json(&payload)
-            .send()
-            .await?;
-            
-        tracing::info!("Alert sent to webhook: {}", webhook);
+        let error = status.last_error.as_deref().unwrap_or("unknown error");
+        // Likely lost while writes fail; the recovery notice tells what happened
+        if status.degraded {
+            notify(
+                &self.state.db,
+                NotificationSeverity::Critical,
+                "database",
+                format!("Database writes are failing, query logging is paused: {}", error),
+                json!({ "error": error }),
+            )
+            .await;
+        } else {
+            notify(
+                &self.state.db,
+                NotificationSeverity::Info,
+                "database",
+                format!(
+                    "Database writes succeed again, {} query log entries were dropped",
+                    status.dropped_query_logs
+                ),
+                json!({ "dropped_query_logs": status.dropped_query_logs }),
+            )
+            .await;
+        }
+
+        let Some(webhook) = self.webhook().await? else {
+            return Ok(());
+        };
+        let message = if status.degraded {
+            format!(
+                "🚨 **Database Degraded**\n\nDatabase writes are failing: {}\nQuery logging is paused; DNS resolution continues.",
+                error
+            )
+        } else {
+            format!(
+                "✅ **Database Recovered**\n\nDatabase writes succeed again and query logging has resumed.\nQuery log entries dropped so far: {}",
+                status.dropped_query_logs
+            )
+        };
+        self.send_alert(&webhook, &message).await
+    }
+
+    /// Alert once when rewrite rules or local records become unavailable and
+    /// once when they are back
+    async fn check_fail_policy(&self) -> anyhow::Result<()> {
+        let status = self.state.resolver.fail_policy_status();
+        if status.degraded == self.policy_degraded_alerted.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        self.policy_degraded_alerted.store(status.degraded, Ordering::Relaxed);
+
+        let error = status
+            .rewrite_rules
+            .as_ref()
+            .or(status.local_records.as_ref())
+            .map_or("unknown error", |f| f.error.as_str());
+        if status.degraded {
+            notify(
+                &self.state.db,
+                NotificationSeverity::Critical,
+                "policy",
+                format!("Rewrite rules or local records cannot be loaded: {}", error),
+                json!({ "error": error, "mode": status.mode }),
+            )
+            .await;
+        } else {
+            notify(
+                &self.state.db,
+                NotificationSeverity::Info,
+                "policy",
+                "Rewrite rules and local records are available again",
+                json!({ "bypassed": status.bypassed, "refused": status.refused }),
+            )
+            .await;
+        }
+
+        let Some(webhook) = self.webhook().await? else {
+            return Ok(());
+        };
+        let message = if status.degraded {
+            match status.mode {
+                FailMode::Open => format!(
+                    "🚨 **Policy Data Unavailable**\n\nRewrite rules or local records cannot be loaded: {}\nFail-open policy: queries are resolved without rewrite rules and local records.",
+                    error
+                ),
+                FailMode::Closed => format!(
+                    "🚨 **Policy Data Unavailable**\n\nRewrite rules or local records cannot be loaded: {}\nFail-closed policy: queries not in the cache are refused.",
+                    error
+                ),
+            }
+        } else {
+            format!(
+                "✅ **Policy Data Restored**\n\nRewrite rules and local records are available again.\nQueries bypassed: {}, refused: {}",
+                status.bypassed, status.refused
+            )
+        };
+        self.send_alert(&webhook, &message).await
+    }
+
+    /// Alert when queries came back to this server since the last check
+    async fn check_loops(&self) -> anyhow::Result<()> {
+        let status = loop_guard().status();
+        let Some(last) = status.last else {
+            return Ok(());
+        };
+        if status.detected <= self.loops_alerted.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        self.loops_alerted.store(status.detected, Ordering::Relaxed);
+
+        notify(
+            &self.state.db,
+            NotificationSeverity::Warning,
+            "loop",
+            format!(
+                "Forwarding loop detected: {} {} from {} came back on the {} listener",
+                last.name, last.record_type, last.client_ip, last.listener
+            ),
+            json!({
+                "name": last.name,
+                "record_type": last.record_type,
+                "client_ip": last.client_ip,
+                "listener": last.listener,
+                "detected": status.detected,
+            }),
+        )
+        .await;
+
+        let Some(webhook) = self.webhook().await? else {
+            return Ok(());
+        };
+        let message = format!(
+            "🚨 **Forwarding Loop Detected**\n\nA query sent upstream came back to this server: {} {} from {} on the {} listener.\nLooped queries so far: {}\nCheck that no upstream forwards to this server.",
+            last.name, last.record_type, last.client_ip, last.listener, status.detected
+        );
+        self.send_alert(&webhook, &message).await
+    }
+
+    /// Notify when an upstream turns unhealthy and when it recovers
+    async fn check_upstream_health(&self) {
+        let servers = self.state.upstream_manager.get_servers().await;
+        let stats = self.state.upstream_manager.get_all_stats().await;
+        let unhealthy: HashSet<i64> = servers
+            .iter()
+            .filter(|s| stats.get(&s.id).is_some_and(|st| !st.is_healthy()))
+            .map(|s| s.id)
+            .collect();
+
+        let mut previous = self.unhealthy_upstreams.lock().await;
+        for server in &servers {
+            let (severity, message) = match (previous.contains(&server.id), unhealthy.contains(&server.id)) {
+                (false, true) => (NotificationSeverity::Warning, format!("Upstream {} is unhealthy", server.name)),
+                (true, false) => (NotificationSeverity::Info, format!("Upstream {} is healthy again", server.name)),
+                _ => continue,
+            };
+            let success_rate = stats.get(&server.id).map(|st| st.success_rate());
+            notify(
+                &self.state.db,
+                severity,
+                "upstream",
+                message,
+                json!({ "server_id": server.id, "server_name": server.name, "success_rate": success_rate }),
+            )
+            .await;
+        }
+        *previous = unhealthy;
+    }
+
+    /// Notify once per certificate when a TLS listener's certificate is
+    /// about to expire or has expired
+    async fn check_certificates(&self) -> anyhow::Result<()> {
+        let listeners = self.state.db.server_listeners().list().await?;
+        let now = Utc::now();
+        let mut notified = self.certs_notified.lock().await;
+        for listener in listeners.iter().filter(|l| l.enabled) {
+            let Some(not_after) = listener.tls_cert.as_deref().and_then(certificate_expiry) else {
+                continue;
+            };
+            let days = (not_after - now).num_days();
+            if days > CERT_EXPIRY_WARNING_DAYS || notified.get(&listener.protocol) == Some(&not_after) {
+                continue;
+            }
+            let (severity, message) = if not_after <= now {
+                (
+                    NotificationSeverity::Critical,
+                    format!("Certificate of the {} listener has expired", listener.protocol),
+                )
+            } else {
+                (
+                    NotificationSeverity::Warning,
+                    format!("Certificate of the {} listener expires in {} days", listener.protocol, days),
+                )
+            };
+            notify(
+                &self.state.db,
+                severity,
+                "certificate",
+                message,
+                json!({ "listener": listener.protocol, "not_after": not_after, "days_until_expiry": days }),
+            )
+            .await;
+            notified.insert(listener.protocol.clone(), not_after);
+        }
         Ok(())
     }
+
+    async fn send_alert(&self, webhook: &str, message: &str) -> anyhow::Result<()> {
+        send_webhook(webhook, message).await
+    }
+}
+
+/// Post an alert message to a webhook
+///
+/// The payload is compatible with Slack, Discord, etc.
+pub async fn send_webhook(webhook: &str, message: &str) -> anyhow::Result<()> {
+    let message = i18n::localize(message, i18n::current());
+    let client = reqwest::Client::new();
+    let payload = json!({
+        "text": message,
+        "content": message 
+    });
+
+    client.post(webhook)
+        .json(&payload)
+        .send()
+        .await?;
+        
+    tracing::info!("Alert sent to webhook: {}", webhook);
+    Ok(())
+}
+
+/// Expiry of the first certificate in a PEM bundle
+fn certificate_expiry(pem: &str) -> Option<DateTime<Utc>> {
+    let der = rustls_pemfile::certs(&mut pem.as_bytes()).next()?.ok()?;
+    let (_, cert) = x509_parser::parse_x509_certificate(&der).ok()?;
+    DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0)
 }
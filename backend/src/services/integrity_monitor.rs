@@ -0,0 +1,450 @@
+//! Upstream response integrity monitor
+//!
+//! Periodically resolves a configured set of domains against several
+//! upstreams at once and compares the answers. An upstream that returns a
+//! different response code or an unrelated answer set for the same name is
+//! a sign of hijacking, censorship or a poisoned resolver, so every
+//! discrepancy is stored as an integrity alert and optionally posted to the
+//! alert webhook.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+use crate::db::{CreateIntegrityAlert, NotificationSeverity};
+use crate::dns::{DnsQuery, QueryResult, RecordType};
+use crate::services::alert_manager::send_webhook;
+use crate::services::notifications::notify;
+use crate::state::AppState;
+
+/// Config key for the feature switch
+pub const CONFIG_KEY_INTEGRITY_ENABLED: &str = "integrity_enabled";
+/// Config key for the monitored domains (JSON array)
+pub const CONFIG_KEY_INTEGRITY_DOMAINS: &str = "integrity_domains";
+/// Config key for the compared upstream names (JSON array, empty = all healthy)
+pub const CONFIG_KEY_INTEGRITY_UPSTREAMS: &str = "integrity_upstreams";
+/// Config key for the compared record types (JSON array)
+pub const CONFIG_KEY_INTEGRITY_RECORD_TYPES: &str = "integrity_record_types";
+/// Config key for the check interval in seconds
+pub const CONFIG_KEY_INTEGRITY_INTERVAL: &str = "integrity_interval_secs";
+/// Config key for the comparison mode
+pub const CONFIG_KEY_INTEGRITY_MODE: &str = "integrity_mode";
+
+/// Default check interval
+pub const DEFAULT_INTEGRITY_INTERVAL_SECS: u64 = 300;
+/// Shortest accepted check interval
+pub const MIN_INTEGRITY_INTERVAL_SECS: u64 = 30;
+
+/// Minimum time between webhook alerts for the same domain and type
+const WEBHOOK_COOLDOWN: Duration = Duration::from_secs(3600);
+
+/// How answer sets are compared
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegrityMode {
+    /// Flag only answer sets with no address in common (tolerates CDN rotation)
+    #[default]
+    Disjoint,
+    /// Flag any difference in the answer sets
+    Exact,
+}
+
+impl IntegrityMode {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "disjoint" => Some(Self::Disjoint),
+            "exact" => Some(Self::Exact),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Disjoint => "disjoint",
+            Self::Exact => "exact",
+        }
+    }
+}
+
+/// Integrity monitor settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IntegritySettings {
+    pub enabled: bool,
+    pub domains: Vec<String>,
+    /// Upstream names to compare; empty compares all healthy upstreams
+    pub upstreams: Vec<String>,
+    pub record_types: Vec<String>,
+    pub interval_secs: u64,
+    pub mode: IntegrityMode,
+}
+
+impl Default for IntegritySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            domains: Vec::new(),
+            upstreams: Vec::new(),
+            record_types: vec!["A".to_string(), "AAAA".to_string()],
+            interval_secs: DEFAULT_INTEGRITY_INTERVAL_SECS,
+            mode: IntegrityMode::Disjoint,
+        }
+    }
+}
+
+/// One upstream's answer to a compared query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UpstreamAnswer {
+    pub server: String,
+    pub response_code: Option<String>,
+    /// Sorted, de-duplicated record values of the queried type
+    pub answers: Vec<String>,
+    /// Set when the upstream failed; failed upstreams are not compared
+    pub error: Option<String>,
+}
+
+impl UpstreamAnswer {
+    fn from_result(server: &str, record_type: RecordType, result: Result<QueryResult>) -> Self {
+        match result {
+            Ok(r) => {
+                let answers: BTreeSet<String> = r
+                    .response
+                    .answers
+                    .iter()
+                    .filter(|a| a.record_type == record_type)
+                    .map(|a| a.value.trim_end_matches('.').to_lowercase())
+                    .collect();
+                Self {
+                    server: server.to_string(),
+                    response_code: Some(r.response.response_code.to_string()),
+                    answers: answers.into_iter().collect(),
+                    error: None,
+                }
+            }
+            Err(e) => Self {
+                server: server.to_string(),
+                response_code: None,
+                answers: Vec::new(),
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Result of comparing one domain across upstreams
+#[derive(Debug, Clone, Serialize)]
+pub struct IntegrityCheck {
+    pub domain: String,
+    pub record_type: String,
+    pub discrepancy: bool,
+    pub answers: Vec<UpstreamAnswer>,
+}
+
+/// Whether the successful answers disagree
+pub fn is_discrepancy(answers: &[UpstreamAnswer], mode: IntegrityMode) -> bool {
+    let ok: Vec<&UpstreamAnswer> = answers.iter().filter(|a| a.error.is_none()).collect();
+
+    for (i, a) in ok.iter().enumerate() {
+        for b in &ok[i + 1..] {
+            if a.response_code != b.response_code {
+                return true;
+            }
+            let differs = match mode {
+                IntegrityMode::Exact => a.answers != b.answers,
+                IntegrityMode::Disjoint => {
+                    a.answers.is_empty() != b.answers.is_empty()
+                        || (!a.answers.is_empty() && !a.answers.iter().any(|x| b.answers.contains(x)))
+                }
+            };
+            if differs {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Background service comparing upstream answers
+pub struct IntegrityMonitor {
+    state: Arc<AppState>,
+    last_webhook: Mutex<HashMap<String, Instant>>,
+}
+
+impl IntegrityMonitor {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            state,
+            last_webhook: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn start(self: Arc<Self>) {
+        tracing::info!("IntegrityMonitor background task started");
+        tokio::spawn(async move {
+            loop {
+                let settings = match self.settings().await {
+                    Ok(s) => s,
+                    Err(e) => {
+                        tracing::error!("Failed to load integrity settings: {}", e);
+                        IntegritySettings::default()
+                    }
+                };
+                // Offline mode disables all upstream traffic, probes included
+                if settings.enabled && !self.state.resolver.offline().is_enabled() {
+                    self.run_once(&settings).await;
+                }
+                let secs = settings.interval_secs.max(MIN_INTEGRITY_INTERVAL_SECS);
+                tokio::time::sleep(Duration::from_secs(secs)).await;
+            }
+        });
+    }
+
+    /// Load settings from database
+    pub async fn settings(&self) -> Result<IntegritySettings> {
+        let config = self.state.db.system_config();
+        let defaults = IntegritySettings::default();
+        let list = |v: Option<String>| -> Option<Vec<String>> {
+            v.and_then(|v| serde_json::from_str(&v).ok())
+        };
+
+        Ok(IntegritySettings {
+            enabled: config.get(CONFIG_KEY_INTEGRITY_ENABLED).await?.is_some_and(|v| v == "true"),
+            domains: list(config.get(CONFIG_KEY_INTEGRITY_DOMAINS).await?).unwrap_or_default(),
+            upstreams: list(config.get(CONFIG_KEY_INTEGRITY_UPSTREAMS).await?).unwrap_or_default(),
+            record_types: list(config.get(CONFIG_KEY_INTEGRITY_RECORD_TYPES).await?)
+                .filter(|t| !t.is_empty())
+                .unwrap_or(defaults.record_types),
+            interval_secs: config
+                .get(CONFIG_KEY_INTEGRITY_INTERVAL)
+                .await?
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(defaults.interval_secs),
+            mode: config
+                .get(CONFIG_KEY_INTEGRITY_MODE)
+                .await?
+                .and_then(|v| IntegrityMode::from_str(&v))
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Save settings to database
+    pub async fn save_settings(&self, settings: &IntegritySettings) -> Result<()> {
+        let config = self.state.db.system_config();
+        let saves = [
+            (CONFIG_KEY_INTEGRITY_ENABLED, settings.enabled.to_string()),
+            (CONFIG_KEY_INTEGRITY_DOMAINS, serde_json::to_string(&settings.domains)?),
+            (CONFIG_KEY_INTEGRITY_UPSTREAMS, serde_json::to_string(&settings.upstreams)?),
+            (CONFIG_KEY_INTEGRITY_RECORD_TYPES, serde_json::to_string(&settings.record_types)?),
+            (CONFIG_KEY_INTEGRITY_INTERVAL, settings.interval_secs.to_string()),
+            (CONFIG_KEY_INTEGRITY_MODE, settings.mode.as_str().to_string()),
+        ];
+        for (key, value) in saves {
+            config.set(key, &value).await?;
+        }
+        Ok(())
+    }
+
+    /// Resolve a domain against the compared upstreams and compare the answers
+    pub async fn check(
+        &self,
+        domain: &str,
+        record_type: RecordType,
+        settings: &IntegritySettings,
+    ) -> Result<IntegrityCheck> {
+        let upstream_manager = &self.state.upstream_manager;
+        let servers = if settings.upstreams.is_empty() {
+            upstream_manager.get_healthy_servers().await
+        } else {
+            upstream_manager
+                .get_servers()
+                .await
+                .into_iter()
+                .filter(|s| settings.upstreams.contains(&s.name))
+                .collect()
+        };
+        if servers.len() < 2 {
+            return Err(anyhow!(
+                "At least 2 upstream servers are required, {} available",
+                servers.len()
+            ));
+        }
+
+        let query = DnsQuery::new(domain, record_type);
+        let answers: Vec<UpstreamAnswer> = self
+            .state
+            .proxy
+            .query_each(&query, &servers)
+            .await
+            .into_iter()
+            .map(|(server, result)| UpstreamAnswer::from_result(&server.name, record_type, result))
+            .collect();
+
+        Ok(IntegrityCheck {
+            domain: domain.to_string(),
+            record_type: record_type.to_string(),
+            discrepancy: is_discrepancy(&answers, settings.mode),
+            answers,
+        })
+    }
+
+    /// Check every configured domain once
+    pub async fn run_once(&self, settings: &IntegritySettings) {
+        for domain in &settings.domains {
+            for type_str in &settings.record_types {
+                let Ok(record_type) = type_str.parse::<RecordType>() else {
+                    tracing::warn!("Skipping invalid integrity record type: {}", type_str);
+                    continue;
+                };
+                match self.check(domain, record_type, settings).await {
+                    Ok(check) if check.discrepancy => self.report(&check).await,
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::warn!("Integrity check for {} {} failed: {}", domain, type_str, e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Store a discrepancy, add it to the notifications and post it to the
+    /// alert webhook
+    async fn report(&self, check: &IntegrityCheck) {
+        let summary: Vec<String> = check
+            .answers
+            .iter()
+            .map(|a| match (&a.error, &a.response_code) {
+                (Some(e), _) => format!("{}: error ({})", a.server, e),
+                (None, rcode) => format!(
+                    "{}: {} [{}]",
+                    a.server,
+                    rcode.as_deref().unwrap_or("-"),
+                    a.answers.join(", ")
+                ),
+            })
+            .collect();
+        tracing::warn!(
+            "[Integrity] Upstream answers differ for {} {}: {}",
+            check.domain,
+            check.record_type,
+            summary.join("; ")
+        );
+
+        let answers = serde_json::to_string(&check.answers).unwrap_or_default();
+        if let Err(e) = self
+            .state
+            .db
+            .integrity_alerts()
+            .create(CreateIntegrityAlert {
+                domain: check.domain.clone(),
+                record_type: check.record_type.clone(),
+                answers,
+            })
+            .await
+        {
+            tracing::error!("Failed to save integrity alert: {}", e);
+        }
+
+        notify(
+            &self.state.db,
+            NotificationSeverity::Warning,
+            "integrity",
+            format!("Upstreams disagree on {} {}", check.domain, check.record_type),
+            serde_json::json!({ "domain": check.domain, "record_type": check.record_type, "answers": summary }),
+        )
+        .await;
+
+        if let Err(e) = self.post_webhook(check, &summary).await {
+            tracing::error!("Failed to send integrity alert: {}", e);
+        }
+    }
+
+    async fn post_webhook(&self, check: &IntegrityCheck, summary: &[String]) -> Result<()> {
+        let config = self.state.db.system_config();
+        if config.get("alert_enabled").await?.unwrap_or_default() != "true" {
+            return Ok(());
+        }
+        let Some(webhook) = config.get("alert_webhook_url").await?.filter(|w| !w.is_empty()) else {
+            return Ok(());
+        };
+
+        let key = format!("{} {}", check.domain, check.record_type);
+        let mut last_webhook = self.last_webhook.lock().await;
+        if last_webhook.get(&key).is_some_and(|t| t.elapsed() < WEBHOOK_COOLDOWN) {
+            return Ok(());
+        }
+
+        let message = format!(
+            "🚨 **Upstream Integrity Alert**\n\nUpstreams disagree on **{}** ({}):\n{}",
+            check.domain,
+            check.record_type,
+            summary.iter().map(|s| format!("- {}", s)).collect::<Vec<_>>().join("\n")
+        );
+        send_webhook(&webhook, &message).await?;
+        last_webhook.insert(key, Instant::now());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(server: &str, rcode: &str, answers: &[&str]) -> UpstreamAnswer {
+        UpstreamAnswer {
+            server: server.to_string(),
+            response_code: Some(rcode.to_string()),
+            answers: answers.iter().map(|a| a.to_string()).collect(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_matching_answers() {
+        let answers = vec![
+            answer("a", "NOERROR", &["1.1.1.1", "2.2.2.2"]),
+            answer("b", "NOERROR", &["1.1.1.1", "2.2.2.2"]),
+        ];
+        assert!(!is_discrepancy(&answers, IntegrityMode::Exact));
+        assert!(!is_discrepancy(&answers, IntegrityMode::Disjoint));
+    }
+
+    #[test]
+    fn test_overlapping_answers() {
+        let answers = vec![
+            answer("a", "NOERROR", &["1.1.1.1", "2.2.2.2"]),
+            answer("b", "NOERROR", &["2.2.2.2", "3.3.3.3"]),
+        ];
+        assert!(is_discrepancy(&answers, IntegrityMode::Exact));
+        assert!(!is_discrepancy(&answers, IntegrityMode::Disjoint));
+    }
+
+    #[test]
+    fn test_disjoint_and_rcode_mismatch() {
+        let disjoint = vec![
+            answer("a", "NOERROR", &["1.1.1.1"]),
+            answer("b", "NOERROR", &["10.0.0.1"]),
+        ];
+        assert!(is_discrepancy(&disjoint, IntegrityMode::Disjoint));
+
+        let rcode = vec![
+            answer("a", "NOERROR", &["1.1.1.1"]),
+            answer("b", "NXDOMAIN", &[]),
+        ];
+        assert!(is_discrepancy(&rcode, IntegrityMode::Disjoint));
+
+        let empty = vec![answer("a", "NOERROR", &[]), answer("b", "NOERROR", &[])];
+        assert!(!is_discrepancy(&empty, IntegrityMode::Disjoint));
+    }
+
+    #[test]
+    fn test_failed_upstreams_are_ignored() {
+        let mut failed = answer("b", "NOERROR", &[]);
+        failed.response_code = None;
+        failed.error = Some("timeout".to_string());
+        let answers = vec![answer("a", "NOERROR", &["1.1.1.1"]), failed];
+        assert!(!is_discrepancy(&answers, IntegrityMode::Exact));
+    }
+}
@@ -2,18 +2,31 @@
 //!
 //! Manages the lifecycle of DNS server listeners (UDP, DoT, DoH, DoQ).
 //! Supports dynamic starting, stopping, and restarting of listeners without application restart.
+//!
+//! Listener tasks are supervised: a task that panics or exits on its own is
+//! restarted with exponential backoff. After repeated failures the listener
+//! is given up on, reported by the readiness probe and announced to the
+//! alert webhook until it is started again.
+//!
+//! The UDP listener's response rate limiter outlives its tasks, so its
+//! counters survive restarts and setting changes apply without one.
 
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
-use tokio::task::AbortHandle;
+use tokio::task::{AbortHandle, JoinHandle};
 use tracing::{info, error, warn};
-use chrono::Local;
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
 
-use crate::db::Database;
-use crate::dns::DnsResolver;
+use crate::db::{Database, ServerListener};
+use crate::services::alert_manager::send_webhook;
+use crate::dns::{bind_tcp, DnsResolver, ResponseRateLimiter, RrlConfig};
 use crate::dns::server::{UdpDnsServer, DohDnsServer, DotDnsServer, DoqDnsServer, TlsConfig};
+use crate::web::HttpServerConfig;
 
 /// Listener Manager
 ///
@@ -22,20 +35,105 @@ use crate::dns::server::{UdpDnsServer, DohDnsServer, DotDnsServer, DoqDnsServer,
 pub struct ListenerManager {
     db: Arc<Database>,
     resolver: Arc<DnsResolver>,
+    /// Limits for the HTTPS DoH listener
+    http_config: HttpServerConfig,
     /// Running tasks by protocol name
-    tasks: Arc<RwLock<HashMap<String, AbortHandle>>>,
+    tasks: Arc<RwLock<HashMap<String, RunningListener>>>,
+    /// Listeners given up on after repeated crashes, by protocol name
+    failed: Arc<RwLock<HashMap<String, ListenerFailure>>>,
+    /// Source of task generations, to tell restarts of a protocol apart
+    generations: Arc<AtomicU64>,
+    /// Response rate limiter of the UDP listener
+    rrl: Arc<ResponseRateLimiter>,
+}
+
+/// Restarts attempted before a crashed listener is given up on
+const MAX_RESTART_ATTEMPTS: u32 = 5;
+/// Delay before the first restart, doubled for each further attempt
+const INITIAL_RESTART_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+/// A listener that ran this long before crashing starts over with a fresh
+/// restart budget
+const STABLE_RUN_PERIOD: Duration = Duration::from_secs(300);
+
+/// Delay before restart attempt `attempt` (1-based)
+fn restart_backoff(attempt: u32) -> Duration {
+    INITIAL_RESTART_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_RESTART_BACKOFF)
+}
+
+/// A running listener task and the configuration it was started with
+struct RunningListener {
+    handle: AbortHandle,
+    config: ServerListener,
+    generation: u64,
+    started_at: Instant,
+    /// Crash restarts since the listener last ran stably
+    restarts: u32,
+}
+
+/// A listener that kept crashing and is no longer restarted
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ListenerFailure {
+    pub protocol: String,
+    /// Why the last attempt failed
+    pub reason: String,
+    pub restarts: u32,
+    pub failed_at: DateTime<Utc>,
+}
+
+/// Changes made by [`ListenerManager::reconcile`], by protocol
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ReconcileSummary {
+    pub started: Vec<String>,
+    pub restarted: Vec<String>,
+    pub stopped: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+impl ReconcileSummary {
+    pub fn is_empty(&self) -> bool {
+        self.started.is_empty() && self.restarted.is_empty() && self.stopped.is_empty() && self.failed.is_empty()
+    }
+}
+
+/// Response rate limit settings of a listener
+pub fn rrl_config(listener: &ServerListener) -> RrlConfig {
+    RrlConfig {
+        responses_per_second: listener.rrl_responses_per_second.max(0) as u32,
+        slip: listener.rrl_slip.max(0) as u32,
+    }
+}
+
+/// Whether a running listener must be restarted to apply `new`
+fn needs_restart(running: &ServerListener, new: &ServerListener) -> bool {
+    running.bind_address != new.bind_address
+        || running.port != new.port
+        || running.interface != new.interface
+        || running.tls_cert != new.tls_cert
+        || running.tls_key != new.tls_key
 }
 
 impl ListenerManager {
     /// Create a new ListenerManager
-    pub fn new(db: Arc<Database>, resolver: Arc<DnsResolver>) -> Self {
+    pub fn new(db: Arc<Database>, resolver: Arc<DnsResolver>, http_config: HttpServerConfig) -> Self {
         Self {
             db,
             resolver,
+            http_config,
             tasks: Arc::new(RwLock::new(HashMap::new())),
+            failed: Arc::new(RwLock::new(HashMap::new())),
+            generations: Arc::new(AtomicU64::new(0)),
+            rrl: Arc::new(ResponseRateLimiter::default()),
         }
     }
 
+    /// Response rate limiter of the UDP listener
+    pub fn response_rate_limiter(&self) -> &Arc<ResponseRateLimiter> {
+        &self.rrl
+    }
+
     /// Start all enabled listeners from database
     pub async fn start_all_enabled(&self) {
         info!("Starting all enabled listeners...");
@@ -78,6 +176,8 @@ impl ListenerManager {
         };
 
 
+        let config = listener.clone();
+
         // NOTE: Removed enabled check here because the caller (listeners.rs)
         // has already verified the enabled state from the database update response.
         // Re-reading from DB here could get stale data due to transaction timing.
@@ -94,14 +194,20 @@ impl ListenerManager {
 
         let resolver = self.resolver.clone();
         let _task_protocol = protocol.to_string();
+        let interface = listener.interface.as_deref();
 
-        info!("Starting {} listener on {}", protocol, addr);
+        match interface {
+            Some(iface) => info!("Starting {} listener on {} (interface {})", protocol, addr, iface),
+            None => info!("Starting {} listener on {}", protocol, addr),
+        }
 
-        let handle = match protocol {
+        let task: JoinHandle<()> = match protocol {
             "udp" => {
                 // Try to bind first
-                match UdpDnsServer::new(addr, resolver).await {
+                match UdpDnsServer::with_interface(addr, interface, resolver).await {
                     Ok(server) => {
+                        self.rrl.configure(rrl_config(&listener));
+                        let server = server.with_rate_limiter(self.rrl.clone());
                         let msg = format!("✅ UDP listener started on {}", addr);
                         info!("{}", msg);
                         let time = Local::now().format("%Y-%m-%d %H:%M:%S");
@@ -114,7 +220,7 @@ impl ListenerManager {
                             }
                             info!("UDP listener stopped");
                         });
-                        task.abort_handle()
+                        task
                     }
                     Err(e) => {
                         error!("Failed to bind UDP server: {}", e);
@@ -138,7 +244,7 @@ impl ListenerManager {
                      
                      let tls_config = TlsConfig::new(cert_path, key_path);
 
-                    match DotDnsServer::new(addr, tls_config, resolver).await {
+                    match DotDnsServer::with_interface(addr, interface, tls_config, resolver).await {
                         Ok(server) => {
                             let msg = format!("✅ DoT listener started on {}", addr);
                             info!("{}", msg);
@@ -151,7 +257,7 @@ impl ListenerManager {
                                 }
                                 info!("DoT listener stopped");
                             });
-                            task.abort_handle()
+                            task
                         }
                         Err(e) => {
                             error!("Failed to start DoT server: {}", e);
@@ -200,24 +306,26 @@ impl ListenerManager {
                      .ok_or_else(|| anyhow::anyhow!("No private key found in PEM"))?;
                  
                  // Build rustls config
-                 let tls_config = rustls::ServerConfig::builder()
+                 let mut tls_config = rustls::ServerConfig::builder()
                      .with_no_client_auth()
                      .with_single_cert(certs, key)
                      .map_err(|e| anyhow::anyhow!("Failed to build TLS config: {}", e))?;
+                 tls_config.alpn_protocols = self.http_config.alpn_protocols();
                  
                  let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(tls_config));
                  
                  // Bind TCP listener first
-                 let tcp_listener = match tokio::net::TcpListener::bind(addr).await {
+                 let tcp_listener = match bind_tcp(addr, interface) {
                      Ok(l) => l,
                      Err(e) => {
                          error!("Failed to bind DoH address {}: {}", addr, e);
-                         return Err(anyhow::anyhow!(e));
+                         return Err(e);
                      }
                  };
                  
                  let server = DohDnsServer::new(resolver.clone());
-                 let app = server.router();
+                 let http_config = self.http_config.clone();
+                 let app = http_config.apply(server.router());
                  
                  let msg = format!("✅ DoH listener (HTTPS) started on {}", addr);
                  info!("{}", msg);
@@ -225,12 +333,6 @@ impl ListenerManager {
                  println!("{} {}", time, msg);
                  
                  let task = tokio::spawn(async move {
-                     use tower::ServiceExt;
-                     use hyper::server::conn::http1;
-                     use hyper::service::service_fn;
-                     use hyper_util::rt::TokioIo;
-                     use axum::extract::ConnectInfo;
-                     
                      loop {
                          let (stream, peer_addr) = match tcp_listener.accept().await {
                              Ok(s) => s,
@@ -242,32 +344,12 @@ impl ListenerManager {
                          
                          let acceptor = acceptor.clone();
                          let app = app.clone();
+                         let http_config = http_config.clone();
                          
                          tokio::spawn(async move {
                              match acceptor.accept(stream).await {
                                  Ok(tls_stream) => {
-                                     let io = TokioIo::new(tls_stream);
-                                     let svc = app.clone();
-                                     let addr = peer_addr;
-                                     
-                                     let service = service_fn(move |mut req: hyper::Request<hyper::body::Incoming>| {
-                                         let svc = svc.clone();
-                                         // Inject ConnectInfo extension for client IP extraction
-                                         req.extensions_mut().insert(ConnectInfo(addr));
-                                         async move {
-                                             svc.oneshot(req).await
-                                         }
-                                     });
-                                     
-                                     if let Err(e) = http1::Builder::new()
-                                         .serve_connection(io, service)
-                                         .await
-                                     {
-                                         // Don't log connection reset errors as they're common
-                                         if !e.to_string().contains("connection reset") {
-                                             tracing::debug!("HTTP connection error from {}: {}", peer_addr, e);
-                                         }
-                                     }
+                                     http_config.serve_connection(tls_stream, peer_addr, app).await;
                                  }
                                  Err(e) => {
                                      tracing::debug!("TLS handshake failed from {}: {}", peer_addr, e);
@@ -276,7 +358,7 @@ impl ListenerManager {
                          });
                      }
                  });
-                 task.abort_handle()
+                 task
             }
             "doq" => {
                if let (Some(cert), Some(key)) = (listener.tls_cert, listener.tls_key) {
@@ -286,7 +368,7 @@ impl ListenerManager {
                    std::fs::write(&key_path, key).unwrap_or(());
                    let tls_config = TlsConfig::new(cert_path, key_path);
 
-                   match DoqDnsServer::new(addr, tls_config, resolver).await {
+                   match DoqDnsServer::with_interface(addr, interface, tls_config, resolver).await {
                         Ok(server) => {
                             let msg = format!("✅ DoQ listener started on {}", addr);
                             info!("{}", msg);
@@ -298,7 +380,7 @@ impl ListenerManager {
                                     error!("DoQ server error: {}", e);
                                 }
                             });
-                            task.abort_handle()
+                            task
                         }
                         Err(e) => {
                             error!("Failed to start DoQ server: {}", e);
@@ -326,15 +408,155 @@ impl ListenerManager {
             }
         };
 
-        self.tasks.write().await.insert(protocol.to_string(), handle);
+        let generation = self.generations.fetch_add(1, Ordering::Relaxed);
+        self.tasks.write().await.insert(
+            protocol.to_string(),
+            RunningListener {
+                handle: task.abort_handle(),
+                config,
+                generation,
+                started_at: Instant::now(),
+                restarts: 0,
+            },
+        );
+        self.failed.write().await.remove(protocol);
+        self.supervise(protocol.to_string(), generation, task);
         Ok(())
     }
 
+    /// Watch a listener task and restart it if it ends without being stopped
+    fn supervise(&self, protocol: String, generation: u64, task: JoinHandle<()>) {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let reason = match task.await {
+                // Aborted by stop_listener or a restart
+                Err(e) if e.is_cancelled() => return,
+                Err(e) => format!("task panicked: {}", e),
+                Ok(()) => "task exited".to_string(),
+            };
+            manager.recover(&protocol, generation, reason).await;
+        });
+    }
+
+    /// Restart a crashed listener with backoff, giving up after
+    /// [`MAX_RESTART_ATTEMPTS`]
+    async fn recover(&self, protocol: &str, generation: u64, mut reason: String) {
+        let mut attempts = {
+            let mut tasks = self.tasks.write().await;
+            match tasks.get(protocol) {
+                Some(running) if running.generation == generation => {
+                    let restarts = if running.started_at.elapsed() >= STABLE_RUN_PERIOD {
+                        0
+                    } else {
+                        running.restarts
+                    };
+                    tasks.remove(protocol);
+                    restarts
+                }
+                // Replaced in the meantime
+                _ => return,
+            }
+        };
+        error!("{} listener crashed: {}", protocol.to_uppercase(), reason);
+
+        while attempts < MAX_RESTART_ATTEMPTS {
+            attempts += 1;
+            let backoff = restart_backoff(attempts);
+            warn!(
+                "Restarting {} listener in {:?} (attempt {}/{})",
+                protocol.to_uppercase(),
+                backoff,
+                attempts,
+                MAX_RESTART_ATTEMPTS
+            );
+            tokio::time::sleep(backoff).await;
+
+            // Started, stopped or disabled by someone else while waiting
+            if self.is_running(protocol).await {
+                return;
+            }
+            match self.db.server_listeners().get_by_protocol(protocol).await {
+                Ok(Some(listener)) if listener.enabled => {}
+                Ok(_) => {
+                    info!("{} listener no longer enabled, not restarting", protocol.to_uppercase());
+                    return;
+                }
+                Err(e) => {
+                    reason = format!("failed to load listener config: {}", e);
+                    continue;
+                }
+            }
+
+            match self.start_listener(protocol).await {
+                Ok(()) => {
+                    if let Some(running) = self.tasks.write().await.get_mut(protocol) {
+                        running.restarts = attempts;
+                    }
+                    info!("{} listener restarted after crash", protocol.to_uppercase());
+                    return;
+                }
+                Err(e) => reason = e.to_string(),
+            }
+        }
+
+        self.give_up(protocol, reason, attempts).await;
+    }
+
+    /// Record a listener as failed and raise an alert
+    async fn give_up(&self, protocol: &str, reason: String, restarts: u32) {
+        let msg = format!(
+            "❌ {} listener failed after {} restart attempts: {}",
+            protocol.to_uppercase(),
+            restarts,
+            reason
+        );
+        error!("{}", msg);
+        let time = Local::now().format("%Y-%m-%d %H:%M:%S");
+        println!("{} {}", time, msg);
+
+        let failure = ListenerFailure {
+            protocol: protocol.to_string(),
+            reason,
+            restarts,
+            failed_at: Utc::now(),
+        };
+        if let Err(e) = self.notify_failure(&failure).await {
+            error!("Failed to send listener failure alert: {}", e);
+        }
+        self.failed.write().await.insert(protocol.to_string(), failure);
+    }
+
+    async fn notify_failure(&self, failure: &ListenerFailure) -> anyhow::Result<()> {
+        let config = self.db.system_config();
+        if config.get("alert_enabled").await?.unwrap_or_default() != "true" {
+            return Ok(());
+        }
+        let Some(webhook) = config.get("alert_webhook_url").await?.filter(|w| !w.is_empty()) else {
+            return Ok(());
+        };
+
+        let message = format!(
+            "🚨 **FluxDNS Listener Down**\n\nThe **{}** listener crashed and could not be restarted after {} attempts.\nLast error: {}",
+            failure.protocol.to_uppercase(),
+            failure.restarts,
+            failure.reason
+        );
+        send_webhook(&webhook, &message).await
+    }
+
+    /// Listeners given up on after repeated crashes
+    pub async fn failed_listeners(&self) -> Vec<ListenerFailure> {
+        let mut failed: Vec<_> = self.failed.read().await.values().cloned().collect();
+        failed.sort_by(|a, b| a.protocol.cmp(&b.protocol));
+        failed
+    }
+
     /// Stop a specific listener
     pub async fn stop_listener(&self, protocol: &str) {
+        self.failed.write().await.remove(protocol);
         let mut tasks = self.tasks.write().await;
-        if let Some(handle) = tasks.remove(protocol) {
-            handle.abort();
+        if let Some(running) = tasks.remove(protocol) {
+            running.handle.abort();
             let msg = format!("🛑 {} listener stopped", protocol.to_uppercase());
             info!("{}", msg);
             let time = Local::now().format("%Y-%m-%d %H:%M:%S");
@@ -342,10 +564,100 @@ impl ListenerManager {
         }
     }
 
+    /// Bring the running listeners in line with the database
+    ///
+    /// Enabled listeners that are not running are started, disabled ones
+    /// stopped, and running ones whose address, interface or certificate
+    /// changed are restarted.
+    pub async fn reconcile(&self) -> anyhow::Result<ReconcileSummary> {
+        let listeners = self.db.server_listeners().list().await?;
+        let running: HashMap<String, ServerListener> = self
+            .tasks
+            .read()
+            .await
+            .iter()
+            .map(|(protocol, r)| (protocol.clone(), r.config.clone()))
+            .collect();
 
+        let mut summary = ReconcileSummary::default();
+        for listener in &listeners {
+            let protocol = listener.protocol.clone();
+            let current = running.get(&protocol);
+            if protocol == "udp" {
+                self.rrl.configure(rrl_config(listener));
+            }
+            if !listener.enabled {
+                if current.is_some() {
+                    self.stop_listener(&protocol).await;
+                    summary.stopped.push(protocol);
+                }
+                continue;
+            }
+            if current.is_some_and(|c| !needs_restart(c, listener)) {
+                continue;
+            }
+            match self.start_listener(&protocol).await {
+                Ok(()) if current.is_some() => summary.restarted.push(protocol),
+                Ok(()) => summary.started.push(protocol),
+                Err(_) => summary.failed.push(protocol),
+            }
+        }
+
+        // Listeners removed from the database
+        for protocol in running.keys() {
+            if !listeners.iter().any(|l| &l.protocol == protocol) {
+                self.stop_listener(protocol).await;
+                summary.stopped.push(protocol.clone());
+            }
+        }
+        Ok(summary)
+    }
 
     /// Check if a listener is running
     pub async fn is_running(&self, protocol: &str) -> bool {
         self.tasks.read().await.contains_key(protocol)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listener(port: i32) -> ServerListener {
+        ServerListener {
+            id: 1,
+            protocol: "udp".to_string(),
+            enabled: true,
+            bind_address: "0.0.0.0".to_string(),
+            port,
+            tls_cert: None,
+            tls_key: None,
+            interface: None,
+            profile_id: None,
+            rrl_responses_per_second: 0,
+            rrl_slip: 2,
+            resolution_mode: "recursive".to_string(),
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_needs_restart() {
+        let running = listener(53);
+        assert!(!needs_restart(&running, &listener(53)));
+        assert!(!needs_restart(&running, &ServerListener { enabled: false, updated_at: "now".to_string(), ..listener(53) }));
+        assert!(needs_restart(&running, &listener(5353)));
+        assert!(needs_restart(&running, &ServerListener { interface: Some("eth0".to_string()), ..listener(53) }));
+        assert!(needs_restart(&running, &ServerListener { tls_cert: Some("pem".to_string()), ..listener(53) }));
+    }
+
+    #[test]
+    fn test_restart_backoff() {
+        assert_eq!(restart_backoff(1), Duration::from_secs(1));
+        assert_eq!(restart_backoff(2), Duration::from_secs(2));
+        assert_eq!(restart_backoff(5), Duration::from_secs(16));
+        assert_eq!(restart_backoff(7), MAX_RESTART_BACKOFF);
+        assert_eq!(restart_backoff(u32::MAX), MAX_RESTART_BACKOFF);
+    }
+}
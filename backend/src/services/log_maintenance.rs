@@ -0,0 +1,231 @@
+//! Query log maintenance
+//!
+//! Hourly task that rolls raw query logs up into the hourly and daily
+//! tiers, prunes roll-ups past their retention, deletes notifications
+//! older than 30 days and, with auto cleanup
+//! enabled, deletes raw logs older than `log_retention_days`. With
+//! `purge_expired_entries` set it also deletes DNS records and rewrite
+//! rules past their expiration time; until then they are only treated as
+//! disabled. The current
+//! time comes from the shared [`Clock`], so a pass can be run at any
+//! point in (artificial) time.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Duration};
+use tracing::info;
+
+use crate::clock::Clock;
+use crate::services::notifications::NOTIFICATION_RETENTION_DAYS;
+use crate::db::{
+    Database, CONFIG_KEY_ROLLUP_DAILY_RETENTION, CONFIG_KEY_ROLLUP_HOURLY_RETENTION,
+    DEFAULT_ROLLUP_DAILY_RETENTION_DAYS, DEFAULT_ROLLUP_HOURLY_RETENTION_DAYS,
+};
+
+/// Time between maintenance passes
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Config key enabling the purge of expired records and rules
+pub const CONFIG_KEY_PURGE_EXPIRED_ENTRIES: &str = "purge_expired_entries";
+
+/// What one maintenance pass did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MaintenanceResult {
+    /// Hours and days rolled up
+    pub rolled_up_hours: u64,
+    pub rolled_up_days: u64,
+    /// Raw query logs deleted by auto cleanup
+    pub deleted_logs: u64,
+    /// Expired DNS records and rewrite rules deleted
+    pub purged_records: u64,
+    pub purged_rules: u64,
+}
+
+pub struct LogMaintenance {
+    db: Arc<Database>,
+    clock: Arc<Clock>,
+}
+
+impl LogMaintenance {
+    pub fn new(db: Arc<Database>, clock: Arc<Clock>) -> Self {
+        Self { db, clock }
+    }
+
+    /// Run a pass every hour, starting now
+    pub fn start(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = interval(MAINTENANCE_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.run_once().await {
+                    // Keep raw logs until they are rolled up
+                    tracing::warn!("Query log roll-up failed: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Roll up, prune and clean up as of the clock's current time
+    ///
+    /// Fails only when the roll-up fails, in which case nothing is deleted.
+    pub async fn run_once(&self) -> Result<MaintenanceResult> {
+        let now = self.clock.utc_now();
+        let config = self.db.system_config();
+        let rollups = self.db.query_log_rollups();
+
+        // Roll up before deleting so no raw log is lost to analytics
+        let rolled_up = rollups.roll_up(now).await?;
+        if rolled_up.hours > 0 || rolled_up.days > 0 {
+            info!("Query log roll-up: {} hours, {} days", rolled_up.hours, rolled_up.days);
+        }
+        let mut result = MaintenanceResult {
+            rolled_up_hours: rolled_up.hours,
+            rolled_up_days: rolled_up.days,
+            ..Default::default()
+        };
+
+        let hourly_days = match config.get(CONFIG_KEY_ROLLUP_HOURLY_RETENTION).await {
+            Ok(Some(v)) => v.parse::<i64>().unwrap_or(DEFAULT_ROLLUP_HOURLY_RETENTION_DAYS),
+            _ => DEFAULT_ROLLUP_HOURLY_RETENTION_DAYS,
+        };
+        let daily_days = match config.get(CONFIG_KEY_ROLLUP_DAILY_RETENTION).await {
+            Ok(Some(v)) => v.parse::<i64>().unwrap_or(DEFAULT_ROLLUP_DAILY_RETENTION_DAYS),
+            _ => DEFAULT_ROLLUP_DAILY_RETENTION_DAYS,
+        };
+        if let Err(e) = rollups.prune(now, hourly_days, daily_days).await {
+            tracing::warn!("Query log roll-up pruning failed: {}", e);
+        }
+
+        let cutoff = now - chrono::Duration::days(NOTIFICATION_RETENTION_DAYS);
+        match self.db.notifications().delete_before(cutoff).await {
+            Ok(deleted) if deleted > 0 => info!("Deleted {} notifications older than {} days", deleted, NOTIFICATION_RETENTION_DAYS),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Notification cleanup failed: {}", e),
+        }
+
+        // Expired entries no longer answer, so dropping them changes no
+        // response and the loaded rules and records need no reload
+        if matches!(config.get(CONFIG_KEY_PURGE_EXPIRED_ENTRIES).await, Ok(Some(ref v)) if v == "true") {
+            match self.db.dns_records().delete_expired(now).await {
+                Ok(purged) => result.purged_records = purged,
+                Err(e) => tracing::warn!("Purging expired DNS records failed: {}", e),
+            }
+            match self.db.rewrite_rules().delete_expired(now).await {
+                Ok(purged) => result.purged_rules = purged,
+                Err(e) => tracing::warn!("Purging expired rewrite rules failed: {}", e),
+            }
+            if result.purged_records > 0 || result.purged_rules > 0 {
+                info!(
+                    "Purged {} expired DNS records and {} expired rewrite rules",
+                    result.purged_records, result.purged_rules
+                );
+            }
+        }
+
+        // Check if auto cleanup is enabled
+        let enabled = match config.get("log_auto_cleanup_enabled").await {
+            Ok(Some(v)) => v == "true",
+            _ => false,
+        };
+        if !enabled {
+            return Ok(result);
+        }
+
+        let retention_days = match config.get("log_retention_days").await {
+            Ok(Some(v)) => v.parse::<i64>().unwrap_or(30),
+            _ => 30,
+        };
+        match self.db.query_logs().delete_old_at(retention_days, now).await {
+            Ok(deleted) => {
+                if deleted > 0 {
+                    info!("Auto cleanup: deleted {} query logs older than {} days", deleted, retention_days);
+                }
+                result.deleted_logs = deleted;
+            }
+            Err(e) => {
+                tracing::warn!("Auto cleanup failed: {}", e);
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{CreateDnsRecord, CreateQueryLog};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_retention_with_advanced_clock() {
+        let dir = tempdir().unwrap();
+        let db_url = format!("sqlite:{}?mode=rwc", dir.path().join("test.db").display());
+        let db = Arc::new(Database::new(&db_url).await.unwrap());
+        db.system_config().set("log_auto_cleanup_enabled", "true").await.unwrap();
+        db.system_config().set("log_retention_days", "7").await.unwrap();
+        db.query_logs()
+            .create(CreateQueryLog {
+                client_ip: "10.0.0.1".to_string(),
+                query_name: "example.com".to_string(),
+                query_type: "A".to_string(),
+                response_code: Some("NOERROR".to_string()),
+                response_time: Some(10),
+                cache_hit: false,
+                upstream_used: None,
+                tenant_id: None,
+                category: None,
+                answered_by: None,
+                trace_id: None,
+                sample_rate: 1,
+                protocol: Some("udp".to_string()),
+                extended_error: None,
+                source: None,
+                block_reason: None,
+                created_at: None,
+            })
+            .await
+            .unwrap();
+
+        let clock = Arc::new(Clock::manual());
+        let maintenance = LogMaintenance::new(db.clone(), clock.clone());
+        assert_eq!(maintenance.run_once().await.unwrap().deleted_logs, 0);
+
+        // Past the retention period, once rolled up
+        clock.advance(Duration::from_secs(8 * 24 * 3600));
+        assert_eq!(maintenance.run_once().await.unwrap().deleted_logs, 1);
+        assert_eq!(db.query_log_rollups().totals(None, None, None).await.unwrap(), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn test_purge_expired_entries() {
+        let dir = tempdir().unwrap();
+        let db_url = format!("sqlite:{}?mode=rwc", dir.path().join("test.db").display());
+        let db = Arc::new(Database::new(&db_url).await.unwrap());
+        let clock = Arc::new(Clock::manual());
+        db.dns_records()
+            .create(CreateDnsRecord {
+                name: "migrating.example.com".to_string(),
+                record_type: "A".to_string(),
+                value: "10.0.0.1".to_string(),
+                ttl: 300,
+                priority: 0,
+                enabled: true,
+                tenant_id: None,
+                description: None,
+                tags: Default::default(),
+                expires_at: Some(clock.utc_now() + chrono::Duration::hours(2)),
+            })
+            .await
+            .unwrap();
+
+        let maintenance = LogMaintenance::new(db.clone(), clock.clone());
+        clock.advance(Duration::from_secs(3 * 3600));
+        // Expired entries are only purged when enabled
+        assert_eq!(maintenance.run_once().await.unwrap().purged_records, 0);
+        db.system_config().set(CONFIG_KEY_PURGE_EXPIRED_ENTRIES, "true").await.unwrap();
+        assert_eq!(maintenance.run_once().await.unwrap().purged_records, 1);
+        assert!(db.dns_records().list().await.unwrap().is_empty());
+    }
+}
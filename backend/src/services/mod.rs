@@ -1,3 +1,9 @@
 pub mod alert_manager;
+pub mod integrity_monitor;
 pub mod listener_manager;
+pub mod log_maintenance;
+pub mod notifications;
+pub mod reload;
+pub mod seed;
+pub mod update_checker;
 
@@ -0,0 +1,43 @@
+//! In-app notifications
+//!
+//! Upstream health changes, database and policy outages, forwarding loops,
+//! expiring listener certificates, integrity discrepancies, backup restores
+//! and configuration reload problems are stored in the `notifications`
+//! table, so the web UI can show them in its inbox (`/api/notifications`)
+//! whether or not an alert webhook is configured. Messages are stored in
+//! English and localized when listed.
+
+use serde_json::Value;
+
+use crate::db::{CreateNotification, Database, NotificationMetadata, NotificationSeverity};
+
+/// Days a notification is kept before log maintenance deletes it
+pub const NOTIFICATION_RETENTION_DAYS: i64 = 30;
+
+/// Store a notification; failures are logged, never returned
+///
+/// `metadata` should be a JSON object; anything else is dropped.
+pub async fn notify(
+    db: &Database,
+    severity: NotificationSeverity,
+    source: &str,
+    message: impl Into<String>,
+    metadata: Value,
+) {
+    let metadata = match metadata {
+        Value::Object(map) => NotificationMetadata(map),
+        _ => NotificationMetadata::default(),
+    };
+    let result = db
+        .notifications()
+        .create(CreateNotification {
+            severity,
+            source: source.to_string(),
+            message: message.into(),
+            metadata,
+        })
+        .await;
+    if let Err(e) = result {
+        tracing::warn!("Failed to store {} notification: {}", source, e);
+    }
+}
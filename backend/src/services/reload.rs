@@ -0,0 +1,240 @@
+//! Configuration Reload
+//!
+//! Operators used to BIND or dnsmasq expect `kill -HUP` to reload the
+//! configuration. On SIGHUP the config file and environment are re-read,
+//! local records, rewrite rules and upstream servers are reloaded from the
+//! database and the listeners are reconciled with their stored settings. A
+//! one-line summary of what changed is logged.
+
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::db::NotificationSeverity;
+use crate::dns::proxy::{
+    connection_manager, quic_transport, ConnectionLimits, QueryLimits, QuicTransportSettings,
+};
+use crate::services::listener_manager::ReconcileSummary;
+use crate::services::notifications::notify;
+use crate::state::AppState;
+
+/// Settings that apply without a restart
+const LIVE_SETTINGS: &[&str] = &[
+    "admin_username",
+    "admin_password",
+    "upstream_idle_timeout_secs",
+    "upstream_max_connections",
+    "upstream_max_outstanding",
+    "upstream_overload_action",
+    "upstream_queue_timeout_ms",
+    "quic_idle_timeout_secs",
+    "quic_keep_alive_secs",
+    "quic_max_streams",
+    "quic_zero_rtt",
+    "quic_migration",
+];
+
+/// What a reload changed
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ReloadSummary {
+    /// Settings changed in the config file or environment
+    pub config_changed: Vec<String>,
+    /// Changed settings that only take effect after a restart
+    pub restart_required: Vec<String>,
+    pub rewrite_rules_before: usize,
+    pub rewrite_rules: usize,
+    pub upstreams_before: usize,
+    pub upstreams: usize,
+    pub listeners: ReconcileSummary,
+    /// Steps that failed; the rest of the reload still ran
+    pub errors: Vec<String>,
+}
+
+impl ReloadSummary {
+    /// One-line description for the log
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.config_changed.is_empty() {
+            parts.push("config unchanged".to_string());
+        } else {
+            parts.push(format!("config changed: {}", self.config_changed.join(", ")));
+        }
+        if !self.restart_required.is_empty() {
+            parts.push(format!("restart required for: {}", self.restart_required.join(", ")));
+        }
+        parts.push(format!(
+            "{} rewrite rules (was {})",
+            self.rewrite_rules, self.rewrite_rules_before
+        ));
+        parts.push(format!("{} upstreams (was {})", self.upstreams, self.upstreams_before));
+
+        let listeners = &self.listeners;
+        if listeners.is_empty() {
+            parts.push("listeners unchanged".to_string());
+        }
+        for (label, protocols) in [
+            ("started", &listeners.started),
+            ("restarted", &listeners.restarted),
+            ("stopped", &listeners.stopped),
+            ("failed to start", &listeners.failed),
+        ] {
+            if !protocols.is_empty() {
+                parts.push(format!("listeners {}: {}", label, protocols.join(", ")));
+            }
+        }
+        if !self.errors.is_empty() {
+            parts.push(format!("errors: {}", self.errors.join("; ")));
+        }
+        parts.join("; ")
+    }
+}
+
+/// Reloads configuration on SIGHUP
+pub struct ReloadManager {
+    state: Arc<AppState>,
+}
+
+impl ReloadManager {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+
+    /// Listen for SIGHUP in the background; a no-op on other platforms
+    pub async fn start(self: Arc<Self>) {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+
+            let mut hangup = match signal(SignalKind::hangup()) {
+                Ok(s) => s,
+                Err(e) => {
+                    tracing::error!("Failed to install SIGHUP handler: {}", e);
+                    return;
+                }
+            };
+            tracing::info!("Reload on SIGHUP enabled");
+            tokio::spawn(async move {
+                while hangup.recv().await.is_some() {
+                    tracing::info!("Received SIGHUP signal, reloading configuration");
+                    let summary = self.reload().await;
+                    if summary.errors.is_empty() {
+                        tracing::info!("Reload complete: {}", summary.describe());
+                    } else {
+                        tracing::warn!("Reload completed with errors: {}", summary.describe());
+                    }
+                    self.record_notifications(&summary).await;
+                }
+            });
+        }
+    }
+
+    /// Add reload errors and settings waiting for a restart to the notifications
+    async fn record_notifications(&self, summary: &ReloadSummary) {
+        if !summary.errors.is_empty() {
+            notify(
+                &self.state.db,
+                NotificationSeverity::Warning,
+                "config",
+                format!("Configuration reload failed: {}", summary.errors.join("; ")),
+                json!({ "errors": summary.errors }),
+            )
+            .await;
+        }
+        if !summary.restart_required.is_empty() {
+            notify(
+                &self.state.db,
+                NotificationSeverity::Info,
+                "config",
+                format!("Restart required for changed settings: {}", summary.restart_required.join(", ")),
+                json!({ "settings": summary.restart_required }),
+            )
+            .await;
+        }
+    }
+
+    /// Reload config, records, rewrite rules and upstreams, then reconcile listeners
+    pub async fn reload(&self) -> ReloadSummary {
+        let state = &self.state;
+        let mut summary = ReloadSummary::default();
+
+        match state.config.reload() {
+            Ok(changed) => {
+                summary.restart_required = changed
+                    .iter()
+                    .filter(|field| !LIVE_SETTINGS.contains(&field.as_str()))
+                    .cloned()
+                    .collect();
+                summary.config_changed = changed;
+            }
+            Err(e) => summary.errors.push(format!("config file: {}", e)),
+        }
+        let config = state.config.get();
+        connection_manager().configure(ConnectionLimits::from_config(&config));
+        state.proxy.limiter().configure(QueryLimits::from_config(&config));
+        quic_transport().configure(QuicTransportSettings::from_config(&config));
+
+        if let Err(e) = state.resolver.local_records().reload().await {
+            summary.errors.push(format!("local records: {}", e));
+        }
+
+        summary.rewrite_rules_before = state.rewrite_engine.rule_count().await;
+        if let Err(e) = state.rewrite_engine.reload_rules().await {
+            summary.errors.push(format!("rewrite rules: {}", e));
+        }
+        summary.rewrite_rules = state.rewrite_engine.rule_count().await;
+
+        summary.upstreams_before = state.upstream_manager.server_count().await;
+        if let Err(e) = state.upstream_manager.reload_from_db(&state.db).await {
+            summary.errors.push(format!("upstreams: {}", e));
+        }
+        summary.upstreams = state.upstream_manager.server_count().await;
+
+        match state.listener_manager.reconcile().await {
+            Ok(listeners) => summary.listeners = listeners,
+            Err(e) => summary.errors.push(format!("listeners: {}", e)),
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_summary() {
+        let summary = ReloadSummary {
+            rewrite_rules_before: 3,
+            rewrite_rules: 3,
+            upstreams_before: 2,
+            upstreams: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            summary.describe(),
+            "config unchanged; 3 rewrite rules (was 3); 2 upstreams (was 2); listeners unchanged"
+        );
+
+        let summary = ReloadSummary {
+            config_changed: vec!["web_port".to_string(), "admin_password".to_string()],
+            restart_required: vec!["web_port".to_string()],
+            rewrite_rules_before: 3,
+            rewrite_rules: 5,
+            upstreams_before: 2,
+            upstreams: 1,
+            listeners: ReconcileSummary {
+                restarted: vec!["dot".to_string()],
+                failed: vec!["doq".to_string()],
+                ..Default::default()
+            },
+            errors: vec!["upstreams: database is locked".to_string()],
+        };
+        assert_eq!(
+            summary.describe(),
+            "config changed: web_port, admin_password; restart required for: web_port; \
+             5 rewrite rules (was 3); 1 upstreams (was 2); listeners restarted: dot; \
+             listeners failed to start: doq; errors: upstreams: database is locked"
+        );
+    }
+}
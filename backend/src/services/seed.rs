@@ -0,0 +1,393 @@
+//! Seed profiles
+//!
+//! A fresh installation needs upstreams before it can resolve anything. A
+//! seed profile bundles upstreams, a query strategy and a few example
+//! rewrite rules for a common deployment:
+//!
+//! - `global` - privacy-focused public resolvers over DoH/DoT/DoQ
+//! - `china` - resolvers hosted in mainland China (the historical default)
+//! - `family` - filtering resolvers plus safe search enforcement
+//! - `local` - no upstreams; offline mode answers from local data only
+//!
+//! The profile named by `SEED_PROFILE` / `seed_profile` is applied once, on
+//! the first start with an empty upstream table. Later profiles can be
+//! applied through `POST /api/setup/seed`; applying is additive and skips
+//! upstreams and rules that already exist.
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::db::{CreateRewriteRule, CreateUpstreamServer, Database, Tags};
+use crate::dns::proxy::QueryStrategy;
+use crate::dns::CONFIG_KEY_OFFLINE_MODE;
+
+/// Config key recording the profile applied on first start
+pub const CONFIG_KEY_SEED_PROFILE: &str = "seed_profile";
+
+/// Tag put on seeded rewrite rules so they are easy to find and remove
+const SEED_TAG: &str = "seed";
+
+/// A deployment scenario
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeedProfile {
+    Global,
+    China,
+    Family,
+    Local,
+}
+
+struct SeedUpstream {
+    name: &'static str,
+    address: &'static str,
+    protocol: &'static str,
+    tls_server_name: Option<&'static str>,
+}
+
+struct SeedRule {
+    pattern: &'static str,
+    match_type: &'static str,
+    action_type: &'static str,
+    action_value: Option<&'static str>,
+    enabled: bool,
+    description: &'static str,
+}
+
+const fn upstream(
+    name: &'static str,
+    address: &'static str,
+    protocol: &'static str,
+    tls_server_name: Option<&'static str>,
+) -> SeedUpstream {
+    SeedUpstream { name, address, protocol, tls_server_name }
+}
+
+const fn rule(
+    pattern: &'static str,
+    action_type: &'static str,
+    action_value: Option<&'static str>,
+    description: &'static str,
+) -> SeedRule {
+    SeedRule {
+        pattern,
+        match_type: if pattern.as_bytes()[0] == b'*' { "wildcard" } else { "exact" },
+        action_type,
+        action_value,
+        enabled: true,
+        description,
+    }
+}
+
+/// Stops browsers from switching to their own DoH resolver behind our back
+const DOH_CANARY: SeedRule = rule(
+    "use-application-dns.net",
+    "block",
+    None,
+    "Keep browsers on this resolver instead of their built-in DoH",
+);
+
+const GLOBAL_UPSTREAMS: &[SeedUpstream] = &[
+    upstream("Cloudflare DoH", "https://1.1.1.1/dns-query", "doh", None),
+    upstream("Quad9 DoT", "9.9.9.9:853", "dot", Some("dns.quad9.net")),
+    upstream("AdGuard DoQ", "94.140.14.140:853", "doq", Some("unfiltered.adguard-dns.com")),
+];
+
+const CHINA_UPSTREAMS: &[SeedUpstream] = &[
+    upstream("阿里云H3", "https://223.6.6.6/dns-query", "doh3", None),
+    upstream("阿里云Quic", "223.5.5.5:853", "doq", None),
+    upstream("DNSPod DoH", "https://1.12.12.12/dns-query", "doh", None),
+];
+
+const FAMILY_UPSTREAMS: &[SeedUpstream] = &[
+    upstream("Cloudflare Family DoH", "https://1.1.1.3/dns-query", "doh", None),
+    upstream("CleanBrowsing Family DoT", "185.228.168.168:853", "dot", Some("family-filter-dns.cleanbrowsing.org")),
+    upstream("AdGuard Family DoQ", "94.140.14.15:853", "doq", Some("family.adguard-dns.com")),
+];
+
+const GLOBAL_RULES: &[SeedRule] = &[
+    rule("*.doubleclick.net", "block", None, "Ad tracking"),
+    DOH_CANARY,
+];
+
+const CHINA_RULES: &[SeedRule] = &[DOH_CANARY];
+
+const FAMILY_RULES: &[SeedRule] = &[
+    rule("www.google.com", "map_domain", Some("forcesafesearch.google.com"), "Enforce Google SafeSearch"),
+    rule("www.bing.com", "map_domain", Some("strict.bing.com"), "Enforce Bing SafeSearch"),
+    rule("www.youtube.com", "map_domain", Some("restrict.youtube.com"), "Enforce YouTube Restricted Mode"),
+    rule("duckduckgo.com", "map_domain", Some("safe.duckduckgo.com"), "Enforce DuckDuckGo safe search"),
+    DOH_CANARY,
+];
+
+const LOCAL_RULES: &[SeedRule] = &[SeedRule {
+    pattern: "router.lan",
+    match_type: "exact",
+    action_type: "map_ip",
+    action_value: Some("192.168.1.1"),
+    enabled: false,
+    description: "Example local name; adjust the address and enable",
+}];
+
+impl SeedProfile {
+    pub const ALL: [SeedProfile; 4] = [Self::Global, Self::China, Self::Family, Self::Local];
+
+    /// Parse from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "global" | "privacy" => Some(Self::Global),
+            "china" | "cn" => Some(Self::China),
+            "family" => Some(Self::Family),
+            "local" | "local_only" | "local-only" => Some(Self::Local),
+            _ => None,
+        }
+    }
+
+    /// Convert to string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::China => "china",
+            Self::Family => "family",
+            Self::Local => "local",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Global => "Privacy-focused public resolvers (Cloudflare, Quad9, AdGuard) over encrypted transports",
+            Self::China => "Resolvers hosted in mainland China (AliDNS, DNSPod) over encrypted transports",
+            Self::Family => "Family-filtering resolvers with safe search enforced for common search engines",
+            Self::Local => "No upstreams: answer from local records and rewrite rules only (offline mode)",
+        }
+    }
+
+    fn upstreams(&self) -> &'static [SeedUpstream] {
+        match self {
+            Self::Global => GLOBAL_UPSTREAMS,
+            Self::China => CHINA_UPSTREAMS,
+            Self::Family => FAMILY_UPSTREAMS,
+            Self::Local => &[],
+        }
+    }
+
+    fn rules(&self) -> &'static [SeedRule] {
+        match self {
+            Self::Global => GLOBAL_RULES,
+            Self::China => CHINA_RULES,
+            Self::Family => FAMILY_RULES,
+            Self::Local => LOCAL_RULES,
+        }
+    }
+
+    /// Query strategy set by the profile
+    pub fn strategy(&self) -> QueryStrategy {
+        match self {
+            Self::Global | Self::Family => QueryStrategy::Fastest,
+            Self::China | Self::Local => QueryStrategy::Concurrent,
+        }
+    }
+}
+
+impl std::fmt::Display for SeedProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Profile description served to clients
+#[derive(Debug, Serialize)]
+pub struct SeedProfileInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub strategy: &'static str,
+    pub upstreams: Vec<&'static str>,
+    pub rules: Vec<&'static str>,
+}
+
+impl From<SeedProfile> for SeedProfileInfo {
+    fn from(profile: SeedProfile) -> Self {
+        Self {
+            name: profile.as_str(),
+            description: profile.description(),
+            strategy: profile.strategy().as_str(),
+            upstreams: profile.upstreams().iter().map(|u| u.name).collect(),
+            rules: profile.rules().iter().map(|r| r.pattern).collect(),
+        }
+    }
+}
+
+/// What applying a profile created
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SeedSummary {
+    pub profile: String,
+    pub upstreams_created: usize,
+    pub upstreams_skipped: usize,
+    pub rules_created: usize,
+    pub rules_skipped: usize,
+    pub strategy: String,
+    pub offline_mode: bool,
+}
+
+/// Apply a profile: create missing upstreams and rules, set the strategy
+pub async fn apply_seed_profile(db: &Database, profile: SeedProfile) -> Result<SeedSummary> {
+    let mut summary = SeedSummary {
+        profile: profile.as_str().to_string(),
+        strategy: profile.strategy().as_str().to_string(),
+        offline_mode: profile == SeedProfile::Local,
+        ..Default::default()
+    };
+
+    let upstream_repo = db.upstream_servers();
+    let existing = upstream_repo.list().await?;
+    for seed in profile.upstreams() {
+        if existing.iter().any(|s| s.name == seed.name || s.address == seed.address) {
+            summary.upstreams_skipped += 1;
+            continue;
+        }
+        upstream_repo
+            .create(CreateUpstreamServer {
+                name: seed.name.to_string(),
+                address: seed.address.to_string(),
+                protocol: seed.protocol.to_string(),
+                timeout: 5000,
+                enabled: true,
+                source_ip: None,
+                source_interface: None,
+                tls_server_name: seed.tls_server_name.map(str::to_string),
+                verify_hostname: None,
+                doh_method: None,
+                doh_http_version: None,
+            })
+            .await?;
+        summary.upstreams_created += 1;
+    }
+
+    let rule_repo = db.rewrite_rules();
+    let existing = rule_repo.list().await?;
+    for seed in profile.rules() {
+        if existing.iter().any(|r| r.pattern == seed.pattern && r.tenant_id.is_none()) {
+            summary.rules_skipped += 1;
+            continue;
+        }
+        rule_repo
+            .create(CreateRewriteRule {
+                pattern: seed.pattern.to_string(),
+                match_type: seed.match_type.to_string(),
+                action_type: seed.action_type.to_string(),
+                action_value: seed.action_value.map(str::to_string),
+                priority: 0,
+                enabled: seed.enabled,
+                description: Some(seed.description.to_string()),
+                tenant_id: None,
+                shadow: false,
+                shadow_of: None,
+                tags: Tags(vec![SEED_TAG.to_string()]),
+                expires_at: None,
+            })
+            .await?;
+        summary.rules_created += 1;
+    }
+
+    let config = db.system_config();
+    config.set("query_strategy", profile.strategy().as_str()).await?;
+    if summary.offline_mode {
+        config.set(CONFIG_KEY_OFFLINE_MODE, "true").await?;
+    }
+    config.set(CONFIG_KEY_SEED_PROFILE, profile.as_str()).await?;
+
+    Ok(summary)
+}
+
+/// Apply the configured profile on the first start
+///
+/// Runs only when no profile was recorded yet and no upstream exists, so
+/// upgrading an existing installation or deleting every upstream later
+/// never seeds again. Returns `None` when nothing was applied.
+pub async fn seed_first_run(db: &Database, profile: &str) -> Result<Option<SeedSummary>> {
+    let config = db.system_config();
+    if config.get(CONFIG_KEY_SEED_PROFILE).await?.is_some() {
+        return Ok(None);
+    }
+
+    if !db.upstream_servers().list().await?.is_empty() {
+        // Installed before seed profiles existed
+        config.set(CONFIG_KEY_SEED_PROFILE, "").await?;
+        return Ok(None);
+    }
+
+    let profile = SeedProfile::from_str(profile).ok_or_else(|| {
+        anyhow!(
+            "Unknown seed profile '{}', expected one of: {}",
+            profile,
+            SeedProfile::ALL.map(|p| p.as_str()).join(", ")
+        )
+    })?;
+    apply_seed_profile(db, profile).await.map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    async fn setup_test_db() -> (tempfile::TempDir, Database) {
+        let dir = tempdir().unwrap();
+        let db_url = format!("sqlite:{}?mode=rwc", dir.path().join("test.db").display());
+        let db = Database::new(&db_url).await.unwrap();
+        (dir, db)
+    }
+
+    #[test]
+    fn test_profile_from_str() {
+        for profile in SeedProfile::ALL {
+            assert_eq!(SeedProfile::from_str(profile.as_str()), Some(profile));
+        }
+        assert_eq!(SeedProfile::from_str("Local-Only"), Some(SeedProfile::Local));
+        assert_eq!(SeedProfile::from_str("corporate"), None);
+    }
+
+    #[test]
+    fn test_profiles_are_well_formed() {
+        use crate::dns::RewriteAction;
+        use crate::dns::proxy::UpstreamProtocol;
+
+        for profile in SeedProfile::ALL {
+            for u in profile.upstreams() {
+                assert!(UpstreamProtocol::from_str(u.protocol).is_some(), "{}", u.name);
+            }
+            for r in profile.rules() {
+                assert!(RewriteAction::from_parts(r.action_type, r.action_value).is_some(), "{}", r.pattern);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_seed_first_run_once() {
+        let (_dir, db) = setup_test_db().await;
+
+        let summary = seed_first_run(&db, "family").await.unwrap().unwrap();
+        assert_eq!(summary.upstreams_created, FAMILY_UPSTREAMS.len());
+        assert_eq!(summary.rules_created, FAMILY_RULES.len());
+        assert_eq!(db.system_config().get("query_strategy").await.unwrap().as_deref(), Some("fastest"));
+
+        // Already seeded: a second start changes nothing
+        assert!(seed_first_run(&db, "global").await.unwrap().is_none());
+        assert_eq!(db.upstream_servers().list().await.unwrap().len(), FAMILY_UPSTREAMS.len());
+
+        // Applying again through the API skips what exists
+        let summary = apply_seed_profile(&db, SeedProfile::Family).await.unwrap();
+        assert_eq!(summary.upstreams_created, 0);
+        assert_eq!(summary.rules_skipped, FAMILY_RULES.len());
+    }
+
+    #[tokio::test]
+    async fn test_seed_local_only() {
+        let (_dir, db) = setup_test_db().await;
+        assert!(seed_first_run(&db, "bogus").await.is_err());
+
+        let summary = seed_first_run(&db, "local").await.unwrap().unwrap();
+        assert!(summary.offline_mode);
+        assert!(db.upstream_servers().list().await.unwrap().is_empty());
+        assert_eq!(db.system_config().get(CONFIG_KEY_OFFLINE_MODE).await.unwrap().as_deref(), Some("true"));
+    }
+}
@@ -0,0 +1,431 @@
+//! Release update checker
+//!
+//! Optionally polls the GitHub releases of FluxDNS for a version newer than
+//! the running build on the configured channel: `stable` considers full
+//! releases only, `beta` also pre-releases. A newer release is reported in
+//! `/api/status`, logged and posted once to the alert webhook. Nothing is
+//! ever downloaded or installed; upgrading stays an operator decision.
+
+use std::cmp::Ordering;
+use std::sync::{Arc, Mutex, RwLock};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+use crate::build_info::VERSION;
+use crate::db::Database;
+use crate::dns::DnsResolver;
+use crate::services::alert_manager::send_webhook;
+
+/// Config key for the feature switch
+pub const CONFIG_KEY_UPDATE_CHECK_ENABLED: &str = "update_check_enabled";
+/// Config key for the release channel
+pub const CONFIG_KEY_UPDATE_CHANNEL: &str = "update_channel";
+
+/// Release feed, newest first
+const RELEASES_URL: &str = "https://api.github.com/repos/lhstack/fluxdns/releases?per_page=30";
+const API_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How often settings are re-read to see whether a check is due
+const POLL_INTERVAL: Duration = Duration::from_secs(600);
+/// Hours between successful checks
+const CHECK_INTERVAL_HOURS: i64 = 12;
+/// Hours before a failed check is retried
+const FAILED_CHECK_INTERVAL_HOURS: i64 = 1;
+
+/// Which releases are offered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReleaseChannel {
+    /// Full releases only
+    #[default]
+    Stable,
+    /// Full releases and pre-releases
+    Beta,
+}
+
+impl ReleaseChannel {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "stable" => Some(Self::Stable),
+            "beta" => Some(Self::Beta),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Stable => "stable",
+            Self::Beta => "beta",
+        }
+    }
+}
+
+/// Update checker settings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateSettings {
+    pub enabled: bool,
+    pub channel: ReleaseChannel,
+}
+
+/// Release as returned by the GitHub API
+#[derive(Debug, Clone, Deserialize)]
+pub struct GithubRelease {
+    pub tag_name: String,
+    pub html_url: String,
+    #[serde(default)]
+    pub prerelease: bool,
+    #[serde(default)]
+    pub draft: bool,
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+/// Newest release on the configured channel
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReleaseInfo {
+    pub version: String,
+    pub url: String,
+    pub prerelease: bool,
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+/// Outcome of the last update check
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UpdateStatus {
+    pub current_version: String,
+    pub channel: ReleaseChannel,
+    pub latest: Option<ReleaseInfo>,
+    pub update_available: bool,
+    pub checked_at: DateTime<Utc>,
+    /// Why the check failed
+    pub error: Option<String>,
+}
+
+/// Semantic version with an optional pre-release suffix
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Version {
+    core: (u64, u64, u64),
+    pre: Option<String>,
+}
+
+impl Version {
+    /// Parse `1.2.3`, `v1.2.3` or `1.2.3-beta.1`; build metadata is ignored
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.trim().trim_start_matches(['v', 'V']);
+        let s = s.split('+').next()?;
+        let (core, pre) = match s.split_once('-') {
+            Some((core, pre)) => (core, Some(pre.to_string())),
+            None => (s, None),
+        };
+        let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+        let major = parts.next()??;
+        let minor = parts.next().unwrap_or(Some(0))?;
+        let patch = parts.next().unwrap_or(Some(0))?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self { core: (major, minor, patch), pre })
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.core.cmp(&other.core).then_with(|| match (&self.pre, &other.pre) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(a), Some(b)) => compare_pre_release(a, b),
+        })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compare dot-separated pre-release identifiers as semver does
+fn compare_pre_release(a: &str, b: &str) -> Ordering {
+    let mut a_ids = a.split('.');
+    let mut b_ids = b.split('.');
+    loop {
+        let ordering = match (a_ids.next(), b_ids.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => match (x.parse::<u64>(), y.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => x.cmp(y),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Newest published release on a channel
+pub fn latest_release(releases: &[GithubRelease], channel: ReleaseChannel) -> Option<ReleaseInfo> {
+    releases
+        .iter()
+        .filter(|r| !r.draft && (channel == ReleaseChannel::Beta || !r.prerelease))
+        .filter_map(|r| Version::parse(&r.tag_name).map(|v| (v, r)))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, r)| ReleaseInfo {
+            version: r.tag_name.trim_start_matches(['v', 'V']).to_string(),
+            url: r.html_url.clone(),
+            prerelease: r.prerelease,
+            published_at: r.published_at,
+        })
+}
+
+/// Whether `latest` is newer than `current`
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    match (Version::parse(latest), Version::parse(current)) {
+        (Some(latest), Some(current)) => latest > current,
+        _ => false,
+    }
+}
+
+/// Background service checking for new releases
+pub struct UpdateChecker {
+    db: Arc<Database>,
+    resolver: Arc<DnsResolver>,
+    client: reqwest::Client,
+    status: RwLock<Option<UpdateStatus>>,
+    /// Last version announced to the webhook
+    notified: Mutex<Option<String>>,
+}
+
+impl UpdateChecker {
+    pub fn new(db: Arc<Database>, resolver: Arc<DnsResolver>) -> Self {
+        Self {
+            db,
+            resolver,
+            client: reqwest::Client::builder()
+                .timeout(API_TIMEOUT)
+                .user_agent(format!("FluxDNS/{}", VERSION))
+                .build()
+                .unwrap_or_default(),
+            status: RwLock::new(None),
+            notified: Mutex::new(None),
+        }
+    }
+
+    pub async fn start(self: Arc<Self>) {
+        tracing::info!("UpdateChecker background task started");
+        tokio::spawn(async move {
+            loop {
+                let settings = match self.settings().await {
+                    Ok(s) => s,
+                    Err(e) => {
+                        tracing::error!("Failed to load update check settings: {}", e);
+                        UpdateSettings::default()
+                    }
+                };
+                // Offline mode disables all outbound traffic
+                if settings.enabled && !self.resolver.offline().is_enabled() && self.is_due(settings.channel) {
+                    self.check(settings.channel).await;
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        });
+    }
+
+    /// Load settings from database
+    pub async fn settings(&self) -> Result<UpdateSettings> {
+        let config = self.db.system_config();
+        Ok(UpdateSettings {
+            enabled: config
+                .get(CONFIG_KEY_UPDATE_CHECK_ENABLED)
+                .await?
+                .is_some_and(|v| v == "true"),
+            channel: config
+                .get(CONFIG_KEY_UPDATE_CHANNEL)
+                .await?
+                .and_then(|v| ReleaseChannel::from_str(&v))
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Save settings to database, dropping a result that no longer applies
+    pub async fn save_settings(&self, settings: UpdateSettings) -> Result<()> {
+        let config = self.db.system_config();
+        config
+            .set(CONFIG_KEY_UPDATE_CHECK_ENABLED, &settings.enabled.to_string())
+            .await?;
+        config
+            .set(CONFIG_KEY_UPDATE_CHANNEL, settings.channel.as_str())
+            .await?;
+
+        let mut status = self.status.write().unwrap();
+        if !settings.enabled || status.as_ref().is_some_and(|s| s.channel != settings.channel) {
+            *status = None;
+        }
+        Ok(())
+    }
+
+    /// Result of the last check, None when disabled or not yet checked
+    pub fn status(&self) -> Option<UpdateStatus> {
+        self.status.read().unwrap().clone()
+    }
+
+    fn is_due(&self, channel: ReleaseChannel) -> bool {
+        match self.status.read().unwrap().as_ref() {
+            Some(status) if status.channel == channel => {
+                let hours = if status.error.is_some() {
+                    FAILED_CHECK_INTERVAL_HOURS
+                } else {
+                    CHECK_INTERVAL_HOURS
+                };
+                Utc::now() - status.checked_at > chrono::Duration::hours(hours)
+            }
+            _ => true,
+        }
+    }
+
+    /// Query the release feed and record the outcome
+    pub async fn check(&self, channel: ReleaseChannel) -> UpdateStatus {
+        let mut status = UpdateStatus {
+            current_version: VERSION.to_string(),
+            channel,
+            latest: None,
+            update_available: false,
+            checked_at: Utc::now(),
+            error: None,
+        };
+        match self.fetch_releases().await {
+            Ok(releases) => {
+                status.latest = latest_release(&releases, channel);
+                status.update_available = status
+                    .latest
+                    .as_ref()
+                    .is_some_and(|r| is_newer(&r.version, VERSION));
+            }
+            Err(e) => {
+                tracing::warn!("Update check failed: {}", e);
+                status.error = Some(e.to_string());
+            }
+        }
+
+        *self.status.write().unwrap() = Some(status.clone());
+        if let Some(release) = status.latest.as_ref().filter(|_| status.update_available) {
+            self.announce(release).await;
+        }
+        status
+    }
+
+    async fn fetch_releases(&self) -> Result<Vec<GithubRelease>> {
+        let response = self
+            .client
+            .get(RELEASES_URL)
+            .header("Accept", "application/vnd.github+json")
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Release feed returned HTTP {}", response.status()));
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Log a new release and post it to the alert webhook once
+    async fn announce(&self, release: &ReleaseInfo) {
+        {
+            let mut notified = self.notified.lock().unwrap();
+            if notified.as_deref() == Some(release.version.as_str()) {
+                return;
+            }
+            *notified = Some(release.version.clone());
+        }
+        tracing::info!(
+            "FluxDNS {} is available (running {}): {}",
+            release.version,
+            VERSION,
+            release.url
+        );
+
+        if let Err(e) = self.notify(release).await {
+            tracing::error!("Failed to send update notification: {}", e);
+        }
+    }
+
+    async fn notify(&self, release: &ReleaseInfo) -> Result<()> {
+        let config = self.db.system_config();
+        if config.get("alert_enabled").await?.unwrap_or_default() != "true" {
+            return Ok(());
+        }
+        let Some(webhook) = config.get("alert_webhook_url").await?.filter(|w| !w.is_empty()) else {
+            return Ok(());
+        };
+
+        let message = format!(
+            "📦 **FluxDNS Update Available**\n\nVersion **{}**{} is available, running {}.\n{}",
+            release.version,
+            if release.prerelease { " (pre-release)" } else { "" },
+            VERSION,
+            release.url
+        );
+        send_webhook(&webhook, &message).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(tag: &str, prerelease: bool, draft: bool) -> GithubRelease {
+        GithubRelease {
+            tag_name: tag.to_string(),
+            html_url: format!("https://github.com/lhstack/fluxdns/releases/tag/{}", tag),
+            prerelease,
+            draft,
+            published_at: None,
+        }
+    }
+
+    #[test]
+    fn test_version_ordering() {
+        assert!(is_newer("1.2.0", "1.1.6"));
+        assert!(is_newer("v1.10.0", "1.9.9"));
+        assert!(is_newer("1.2.0", "1.2.0-beta.3"));
+        assert!(is_newer("1.2.0-beta.10", "1.2.0-beta.9"));
+        assert!(is_newer("1.2.0-rc.1", "1.2.0-beta.2"));
+        assert!(!is_newer("1.1.6", "1.1.6"));
+        assert!(!is_newer("1.2.0-beta.1", "1.2.0"));
+        assert!(!is_newer("nightly", "1.1.6"));
+    }
+
+    #[test]
+    fn test_latest_release_by_channel() {
+        let releases = vec![
+            release("v1.3.0-beta.1", true, false),
+            release("v1.4.0", false, true),
+            release("v1.2.0", false, false),
+            release("v1.1.0", false, false),
+            release("nightly", true, false),
+        ];
+
+        let stable = latest_release(&releases, ReleaseChannel::Stable).unwrap();
+        assert_eq!(stable.version, "1.2.0");
+        assert!(!stable.prerelease);
+
+        let beta = latest_release(&releases, ReleaseChannel::Beta).unwrap();
+        assert_eq!(beta.version, "1.3.0-beta.1");
+        assert!(beta.prerelease);
+
+        assert!(latest_release(&[], ReleaseChannel::Beta).is_none());
+    }
+
+    #[test]
+    fn test_release_channel_round_trip() {
+        for channel in [ReleaseChannel::Stable, ReleaseChannel::Beta] {
+            assert_eq!(ReleaseChannel::from_str(channel.as_str()), Some(channel));
+        }
+        assert_eq!(ReleaseChannel::from_str("nightly"), None);
+    }
+}
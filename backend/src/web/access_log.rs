@@ -0,0 +1,224 @@
+//! Management API access log
+//!
+//! Every API request is recorded with its method, path, status, latency,
+//! authenticated caller and client address. The latest entries are kept in
+//! a ring buffer served at `/api/status/api-log`, and each request is also
+//! written to the tracing log under the `api_access` target (successful
+//! reads at debug level, everything else at info), which helps diagnose odd
+//! UI behaviour and misbehaving automation.
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{Method, Request},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::web::auth::ApiUser;
+use crate::web::hooks::client_ip;
+
+/// Entries kept in the ring buffer
+pub const API_LOG_CAPACITY: usize = 1000;
+
+/// One API request
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiAccessEntry {
+    pub timestamp: DateTime<Utc>,
+    pub method: String,
+    /// Request path without the query string, which may carry a token
+    pub path: String,
+    pub status: u16,
+    pub latency_ms: f64,
+    /// Authenticated caller, None for unauthenticated endpoints and rejections
+    pub user: Option<String>,
+    pub client_ip: Option<String>,
+}
+
+/// Filter for [`ApiAccessLog::entries`]
+#[derive(Debug, Default)]
+pub struct ApiAccessFilter {
+    pub user: Option<String>,
+    /// Only paths starting with this prefix
+    pub path: Option<String>,
+    /// Only responses with at least this status
+    pub min_status: Option<u16>,
+}
+
+impl ApiAccessFilter {
+    fn matches(&self, entry: &ApiAccessEntry) -> bool {
+        self.user.as_ref().is_none_or(|u| entry.user.as_ref() == Some(u))
+            && self.path.as_ref().is_none_or(|p| entry.path.starts_with(p.as_str()))
+            && self.min_status.is_none_or(|s| entry.status >= s)
+    }
+}
+
+/// Ring buffer of recent API requests
+pub struct ApiAccessLog {
+    entries: Mutex<VecDeque<ApiAccessEntry>>,
+    capacity: usize,
+    recorded: AtomicU64,
+    trust_proxy_headers: bool,
+}
+
+impl ApiAccessLog {
+    pub fn new() -> Self {
+        Self::with_capacity(API_LOG_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            recorded: AtomicU64::new(0),
+            trust_proxy_headers: false,
+        }
+    }
+
+    /// Record client addresses from reverse proxy headers
+    pub fn with_proxy_headers(mut self, trust: bool) -> Self {
+        self.trust_proxy_headers = trust;
+        self
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Requests recorded since startup, including those dropped from the buffer
+    pub fn recorded(&self) -> u64 {
+        self.recorded.load(Ordering::Relaxed)
+    }
+
+    pub fn record(&self, entry: ApiAccessEntry) {
+        self.recorded.fetch_add(1, Ordering::Relaxed);
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Matching entries, newest first
+    pub fn entries(&self, filter: &ApiAccessFilter, limit: usize) -> Vec<ApiAccessEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|e| filter.matches(e))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Default for ApiAccessLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Record each API request in the access log
+pub async fn access_log_middleware(
+    State(log): State<Arc<ApiAccessLog>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| client_ip(request.headers(), *addr, log.trust_proxy_headers));
+
+    let response = next.run(request).await;
+
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+    let status = response.status();
+    let user = response.extensions().get::<ApiUser>().map(|u| u.0.clone());
+    let caller = user.as_deref().unwrap_or("-");
+    let ip = client_ip.as_deref().unwrap_or("-");
+    if method == Method::GET && status.is_success() {
+        tracing::debug!(target: "api_access", "{} {} {} {:.1}ms user={} ip={}", method, path, status.as_u16(), latency_ms, caller, ip);
+    } else {
+        tracing::info!(target: "api_access", "{} {} {} {:.1}ms user={} ip={}", method, path, status.as_u16(), latency_ms, caller, ip);
+    }
+
+    log.record(ApiAccessEntry {
+        timestamp: Utc::now(),
+        method: method.to_string(),
+        path,
+        status: status.as_u16(),
+        latency_ms,
+        user,
+        client_ip,
+    });
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, status: u16, user: Option<&str>) -> ApiAccessEntry {
+        ApiAccessEntry {
+            timestamp: Utc::now(),
+            method: "GET".to_string(),
+            path: path.to_string(),
+            status,
+            latency_ms: 1.0,
+            user: user.map(str::to_string),
+            client_ip: None,
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer_drops_oldest() {
+        let log = ApiAccessLog::with_capacity(2);
+        log.record(entry("/api/records", 200, Some("admin")));
+        log.record(entry("/api/status", 200, Some("admin")));
+        log.record(entry("/api/cache", 500, None));
+
+        let paths: Vec<String> = log
+            .entries(&ApiAccessFilter::default(), 10)
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+        assert_eq!(paths, vec!["/api/cache", "/api/status"]);
+        assert_eq!(log.recorded(), 3);
+    }
+
+    #[test]
+    fn test_filter() {
+        let log = ApiAccessLog::new();
+        log.record(entry("/api/records", 200, Some("admin")));
+        log.record(entry("/api/records/7", 404, Some("token:ci")));
+        log.record(entry("/api/status", 401, None));
+
+        let filter = ApiAccessFilter {
+            min_status: Some(400),
+            ..Default::default()
+        };
+        assert_eq!(log.entries(&filter, 10).len(), 2);
+
+        let filter = ApiAccessFilter {
+            user: Some("token:ci".to_string()),
+            path: Some("/api/records".to_string()),
+            ..Default::default()
+        };
+        let entries = log.entries(&filter, 10);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].status, 404);
+        assert_eq!(log.entries(&ApiAccessFilter::default(), 1)[0].path, "/api/status");
+    }
+}
@@ -0,0 +1,82 @@
+//! Query analytics API module
+//!
+//! Serves the top-domains leaderboard from the resolver's in-memory
+//! sketches, so the dashboard needs no scan of the query log. Counts are
+//! approximate: each entry reports how much its query count may be
+//! overstated.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::dns::{DomainLeaderboardEntry, DomainStats, LeaderboardSort, LeaderboardWindow};
+use crate::web::{bad_request, ApiError};
+
+/// Default number of leaderboard entries
+const DEFAULT_LIMIT: usize = 20;
+/// Most leaderboard entries returned
+const MAX_LIMIT: usize = 100;
+
+/// Application state for analytics API
+#[derive(Clone)]
+pub struct AnalyticsState {
+    pub domain_stats: Arc<DomainStats>,
+}
+
+/// Leaderboard query parameters
+#[derive(Debug, Deserialize)]
+pub struct DomainsParams {
+    /// 1h, 24h (default) or 7d
+    pub window: Option<String>,
+    /// queries (default), blocked or latency
+    pub sort: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Leaderboard response
+#[derive(Debug, Serialize)]
+pub struct DomainsResponse {
+    pub window: LeaderboardWindow,
+    pub sort: LeaderboardSort,
+    pub data: Vec<DomainLeaderboardEntry>,
+}
+
+/// Top domains over a sliding window
+///
+/// GET /api/analytics/domains?window=24h&sort=queries&limit=20
+pub async fn top_domains(
+    State(state): State<AnalyticsState>,
+    Query(params): Query<DomainsParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let window = match params.window.as_deref() {
+        Some(w) => LeaderboardWindow::from_str(w)
+            .ok_or_else(|| bad_request(format!("Invalid window: {}", w)))?,
+        None => LeaderboardWindow::default(),
+    };
+    let sort = match params.sort.as_deref() {
+        Some(s) => LeaderboardSort::from_str(s)
+            .ok_or_else(|| bad_request(format!("Invalid sort: {}", s)))?,
+        None => LeaderboardSort::default(),
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    Ok(Json(DomainsResponse {
+        window,
+        sort,
+        data: state.domain_stats.top(window, sort, limit),
+    }))
+}
+
+/// Build the analytics API router
+pub fn analytics_router(state: AnalyticsState) -> axum::Router {
+    use axum::routing::get;
+
+    axum::Router::new()
+        .route("/domains", get(top_domains))
+        .with_state(state)
+}
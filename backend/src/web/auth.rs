@@ -10,13 +10,17 @@
 //! - 5.4: Read username/password from environment variables
 //! - 5.5: Fall back to config file if env vars not set
 //! - 5.6: Environment variables take priority over config file
+//!
+//! Besides the configured admin, delegated admins stored in the database can
+//! log in; their JWTs carry the account ID and only reach the records API and
+//! the AI assistant, limited to names inside their zones.
 
 use std::sync::Arc;
 
 use axum::{
     body::Body,
     extract::State,
-    http::{Request, StatusCode},
+    http::{Method, Request, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
     Json,
@@ -26,7 +30,11 @@ use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData,
 use serde::{Deserialize, Serialize};
 
 use crate::config::ConfigManager;
+use crate::db::Database;
+use crate::dns::normalize_name;
 use crate::error::AppError;
+use crate::i18n;
+use crate::web::tokens::scopes_allow;
 
 /// JWT secret key - in production, this should be loaded from configuration
 const JWT_SECRET: &str = "dns-proxy-service-secret-key-change-in-production";
@@ -58,6 +66,9 @@ pub struct Claims {
     pub exp: i64,
     /// Issued at time (Unix timestamp)
     pub iat: i64,
+    /// Delegated admin account ID; absent for the configured admin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delegate_id: Option<i64>,
 }
 
 /// Authentication service
@@ -107,14 +118,19 @@ impl AuthService {
             return Err(AppError::Auth("Invalid username or password".to_string()));
         }
 
-        // Generate JWT token
+        self.issue_token(&request.username, None)
+    }
+
+    /// Generate a JWT token for a user, optionally a delegated admin
+    pub fn issue_token(&self, username: &str, delegate_id: Option<i64>) -> Result<LoginResponse, AppError> {
         let now = Utc::now();
         let expires_at = now + Duration::hours(TOKEN_EXPIRATION_HOURS);
 
         let claims = Claims {
-            sub: request.username.clone(),
+            sub: username.to_string(),
             exp: expires_at.timestamp(),
             iat: now.timestamp(),
+            delegate_id,
         };
 
         let token = encode(&Header::default(), &claims, &self.encoding_key)
@@ -161,15 +177,26 @@ pub struct ApiError {
 }
 
 impl IntoResponse for ApiError {
-    fn into_response(self) -> Response {
+    fn into_response(mut self) -> Response {
         let status = match self.code.as_str() {
             "UNAUTHORIZED" => StatusCode::UNAUTHORIZED,
             "FORBIDDEN" => StatusCode::FORBIDDEN,
             "BAD_REQUEST" => StatusCode::BAD_REQUEST,
             "NOT_FOUND" => StatusCode::NOT_FOUND,
+            "CONFLICT" => StatusCode::CONFLICT,
+            "PRECONDITION_REQUIRED" => StatusCode::PRECONDITION_REQUIRED,
+            "TOO_MANY_REQUESTS" => StatusCode::TOO_MANY_REQUESTS,
+            "SERVICE_UNAVAILABLE" => StatusCode::SERVICE_UNAVAILABLE,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
+        // Render the message and validation details in the request language
+        let lang = i18n::current();
+        self.message = i18n::localize(&self.message, lang);
+        if let Some(details) = self.details.as_mut() {
+            i18n::localize_value(details, lang);
+        }
+
         let body = Json(self);
         (status, body).into_response()
     }
@@ -179,34 +206,171 @@ impl IntoResponse for ApiError {
 #[derive(Clone)]
 pub struct AuthState {
     pub auth_service: AuthService,
+    /// Used to look up scoped and tenant API tokens
+    pub db: Arc<Database>,
+}
+
+/// Tenant scope attached to requests authenticated with a tenant API token
+///
+/// Handlers that support tenants restrict reads and writes to resources owned
+/// by `tenant_id`. Requests authenticated as the admin carry no scope.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct TenantScope {
+    pub tenant_id: i64,
+    pub name: String,
+}
+
+/// Domain scope attached to requests authenticated as a delegated admin
+///
+/// The records handlers and LLM functions only let the caller see and change
+/// records whose names lie inside `zones`.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct DomainScope {
+    pub admin_id: i64,
+    pub username: String,
+    /// Normalized zones, e.g. "web.internal"
+    pub zones: Vec<String>,
+}
+
+impl DomainScope {
+    /// Whether a name is one of the zones or lies below one
+    pub fn covers(&self, name: &str) -> bool {
+        let name = normalize_name(name);
+        self.zones
+            .iter()
+            .any(|zone| name == *zone || name.ends_with(&format!(".{}", zone)))
+    }
+}
+
+/// Caller authenticated by [`auth_middleware`], attached to the response
+///
+/// `admin` users appear by name, scoped API tokens as `token:<name>`, tenant
+/// tokens as `tenant:<name>` and delegated admins as `delegate:<name>`; read
+/// by the API access log.
+#[derive(Debug, Clone)]
+pub struct ApiUser(pub String);
+
+/// Endpoints open to tenant tokens, as method and path; `:id` matches a
+/// numeric path segment
+///
+/// Only handlers that confine reads and writes to the tenant's own records,
+/// services, rewrite rules and query logs are listed; global operations such
+/// as `/api/records/refresh` and `/api/rewrite/reload` stay admin-only.
+const TENANT_ROUTES: &[(&str, &str)] = &[
+    ("GET", "/api/records"),
+    ("POST", "/api/records"),
+    ("GET", "/api/records/match"),
+    ("POST", "/api/records/bulk"),
+    ("GET", "/api/records/:id"),
+    ("PUT", "/api/records/:id"),
+    ("DELETE", "/api/records/:id"),
+    ("GET", "/api/services"),
+    ("POST", "/api/services"),
+    ("GET", "/api/services/:id"),
+    ("PUT", "/api/services/:id"),
+    ("DELETE", "/api/services/:id"),
+    ("POST", "/api/services/:id/records"),
+    ("GET", "/api/rewrite"),
+    ("POST", "/api/rewrite"),
+    ("POST", "/api/rewrite/batch"),
+    ("POST", "/api/rewrite/bulk"),
+    ("GET", "/api/rewrite/:id"),
+    ("PUT", "/api/rewrite/:id"),
+    ("DELETE", "/api/rewrite/:id"),
+    ("POST", "/api/rewrite/:id/shadow"),
+    ("POST", "/api/rewrite/:id/promote"),
+    ("GET", "/api/logs"),
+    ("GET", "/api/logs/export"),
+    ("GET", "/api/logs/summary"),
+];
+
+/// Check whether a tenant token may call an endpoint
+fn tenant_may_access(method: &Method, path: &str) -> bool {
+    let path = path.strip_suffix('/').filter(|p| !p.is_empty()).unwrap_or(path);
+    let segments: Vec<&str> = path.split('/').collect();
+
+    TENANT_ROUTES.iter().any(|(route_method, route)| {
+        let pattern: Vec<&str> = route.split('/').collect();
+        *route_method == method.as_str()
+            && pattern.len() == segments.len()
+            && pattern.iter().zip(&segments).all(|(p, s)| {
+                p == s || (*p == ":id" && !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()))
+            })
+    })
+}
+
+/// Check whether a delegated admin may call an endpoint
+///
+/// Delegated admins can manage records and use the AI assistant; the
+/// handlers limit both to their zones.
+fn delegate_may_access(path: &str) -> bool {
+    const DELEGATE_PATHS: &[&str] = &["/api/llm/chat", "/api/llm/chat/stream", "/api/llm/tools"];
+
+    ((path == "/api/records" || path.starts_with("/api/records/")) && path != "/api/records/refresh")
+        || DELEGATE_PATHS.contains(&path)
 }
 
 /// Login handler for the /api/auth/login endpoint
+///
+/// The configured admin is tried first, then delegated admin accounts.
 pub async fn login_handler(
     State(state): State<AuthState>,
     Json(request): Json<LoginRequest>,
 ) -> Result<Json<LoginResponse>, ApiError> {
-    state
-        .auth_service
-        .login(&request)
-        .map(Json)
-        .map_err(|e| ApiError {
-            code: "UNAUTHORIZED".to_string(),
-            message: e.to_string(),
-            details: None,
-        })
+    let result = match state.auth_service.login(&request) {
+        Ok(response) => Ok(response),
+        Err(e) => login_delegate(&state, &request).await.unwrap_or(Err(e)),
+    };
+
+    result.map(Json).map_err(|e| ApiError {
+        code: "UNAUTHORIZED".to_string(),
+        message: e.to_string(),
+        details: None,
+    })
+}
+
+/// Log in as a delegated admin, `None` unless an enabled account matches
+async fn login_delegate(state: &AuthState, request: &LoginRequest) -> Option<Result<LoginResponse, AppError>> {
+    let repo = state.db.delegated_admins();
+    let admin = repo
+        .get_by_username(&request.username)
+        .await
+        .ok()
+        .flatten()
+        .filter(|a| a.enabled)?;
+
+    // bcrypt is deliberately slow, keep it off the async workers
+    let password = request.password.clone();
+    let hash = admin.password_hash.clone();
+    let valid = tokio::task::spawn_blocking(move || bcrypt::verify(password, &hash).unwrap_or(false))
+        .await
+        .unwrap_or(false);
+    if !valid {
+        return None;
+    }
+
+    if let Err(e) = repo.touch_login(admin.id).await {
+        tracing::warn!("Failed to record login of delegated admin {}: {}", admin.username, e);
+    }
+    Some(state.auth_service.issue_token(&admin.username, Some(admin.id)))
 }
 
 /// Authentication middleware
 ///
 /// Validates JWT token from Authorization header.
+/// Tokens that are not admin JWTs are checked against scoped API tokens,
+/// which may only call endpoints covered by their scopes, and then against
+/// tenant API tokens; a tenant match attaches a `TenantScope` to the request.
+/// JWTs of delegated admins attach a `DomainScope` instead.
 /// Returns 401 Unauthorized if token is missing or invalid.
 ///
 /// # Requirements
 /// - 5.1: Require user login to access management interface
 pub async fn auth_middleware(
     State(state): State<AuthState>,
-    request: Request<Body>,
+    mut request: Request<Body>,
     next: Next,
 ) -> Result<Response, ApiError> {
     // Skip auth for login endpoint
@@ -245,13 +409,88 @@ pub async fn auth_middleware(
     })?;
 
     // Verify token
-    state.auth_service.verify_token(&token).map_err(|e| ApiError {
+    let user = match state.auth_service.verify_token(&token) {
+        Ok(Claims { delegate_id: Some(id), .. }) => authenticate_delegate(&state, id, &mut request).await?,
+        Ok(claims) => claims.sub,
+        Err(e) => authenticate_api_token(&state, &token, &mut request, e).await?,
+    };
+
+    let mut response = next.run(request).await;
+    response.extensions_mut().insert(ApiUser(user));
+    Ok(response)
+}
+
+/// Check a delegated admin's JWT against the stored account
+///
+/// Disabling or deleting the account revokes its tokens at once, and zone
+/// changes apply from the next request.
+async fn authenticate_delegate(state: &AuthState, id: i64, request: &mut Request<Body>) -> Result<String, ApiError> {
+    let admin = state.db.delegated_admins().get_by_id(id).await.ok().flatten();
+    let admin = admin.filter(|a| a.enabled).ok_or_else(|| ApiError {
+        code: "UNAUTHORIZED".to_string(),
+        message: "Delegated admin account is disabled or deleted".to_string(),
+        details: None,
+    })?;
+
+    if !delegate_may_access(request.uri().path()) {
+        return Err(ApiError {
+            code: "FORBIDDEN".to_string(),
+            message: "Delegated admins cannot access this endpoint".to_string(),
+            details: None,
+        });
+    }
+
+    let user = format!("delegate:{}", admin.username);
+    request.extensions_mut().insert(DomainScope {
+        admin_id: admin.id,
+        zones: admin.zone_list().into_iter().map(normalize_name).collect(),
+        username: admin.username,
+    });
+    Ok(user)
+}
+
+/// Authenticate a token that is not an admin JWT, returning the caller name
+///
+/// Scoped API tokens are tried first, then tenant tokens.
+async fn authenticate_api_token(
+    state: &AuthState,
+    token: &str,
+    request: &mut Request<Body>,
+    e: AppError,
+) -> Result<String, ApiError> {
+    if let Some(api_token) = state.db.api_tokens().authenticate(token).await.ok().flatten() {
+        if !scopes_allow(api_token.scope_list(), request.method(), request.uri().path()) {
+            return Err(ApiError {
+                code: "FORBIDDEN".to_string(),
+                message: format!("API token '{}' lacks the scope for this endpoint", api_token.name),
+                details: None,
+            });
+        }
+        return Ok(format!("token:{}", api_token.name));
+    }
+
+    // Then tenant API tokens
+    let tenant = state.db.tenants().get_by_token(token).await.ok().flatten();
+    let tenant = tenant.ok_or_else(|| ApiError {
         code: "UNAUTHORIZED".to_string(),
         message: e.to_string(),
         details: None,
     })?;
 
-    Ok(next.run(request).await)
+    if !tenant_may_access(request.method(), request.uri().path()) {
+        return Err(ApiError {
+            code: "FORBIDDEN".to_string(),
+            message: "Tenant tokens cannot access this endpoint".to_string(),
+            details: None,
+        });
+    }
+
+    let user = format!("tenant:{}", tenant.name);
+    request.extensions_mut().insert(TenantScope {
+        tenant_id: tenant.id,
+        name: tenant.name,
+    });
+    Ok(user)
 }
 
 #[cfg(test)]
@@ -614,6 +853,56 @@ mod tests {
         assert!(auth_service1.verify_token(&token2).is_err());
     }
 
+    #[test]
+    fn test_tenant_may_access() {
+        assert!(tenant_may_access(&Method::GET, "/api/records"));
+        assert!(tenant_may_access(&Method::PUT, "/api/records/12"));
+        assert!(tenant_may_access(&Method::POST, "/api/services/4/records"));
+        assert!(tenant_may_access(&Method::POST, "/api/rewrite/batch"));
+        assert!(tenant_may_access(&Method::GET, "/api/logs"));
+        assert!(tenant_may_access(&Method::GET, "/api/logs/"));
+        assert!(tenant_may_access(&Method::GET, "/api/logs/export"));
+        assert!(tenant_may_access(&Method::GET, "/api/logs/summary"));
+        assert!(!tenant_may_access(&Method::DELETE, "/api/logs/cleanup/all"));
+        assert!(!tenant_may_access(&Method::GET, "/api/tenants"));
+        assert!(!tenant_may_access(&Method::GET, "/api/recordsx"));
+        assert!(!tenant_may_access(&Method::GET, "/api/upstreams"));
+        // Global reloads are admin-only
+        assert!(!tenant_may_access(&Method::POST, "/api/records/refresh"));
+        assert!(!tenant_may_access(&Method::POST, "/api/rewrite/reload"));
+        assert!(!tenant_may_access(&Method::POST, "/api/records/12"));
+        assert!(!tenant_may_access(&Method::GET, "/api/records/12/extra"));
+    }
+
+    #[test]
+    fn test_delegate_access() {
+        assert!(delegate_may_access("/api/records"));
+        assert!(delegate_may_access("/api/records/12"));
+        assert!(delegate_may_access("/api/records/bulk"));
+        assert!(delegate_may_access("/api/llm/chat/stream"));
+        assert!(!delegate_may_access("/api/records/refresh"));
+        assert!(!delegate_may_access("/api/llm/config"));
+        assert!(!delegate_may_access("/api/services"));
+        assert!(!delegate_may_access("/api/delegates"));
+
+        let scope = DomainScope {
+            admin_id: 1,
+            username: "web".to_string(),
+            zones: vec!["web.internal".to_string()],
+        };
+        assert!(scope.covers("web.internal"));
+        assert!(scope.covers("App.Web.Internal."));
+        assert!(scope.covers("*.web.internal"));
+        assert!(!scope.covers("otherweb.internal"));
+        assert!(!scope.covers("internal"));
+
+        let auth_service = AuthService::new(create_test_config());
+        let token = auth_service.issue_token("web", Some(1)).unwrap().token;
+        assert_eq!(auth_service.verify_token(&token).unwrap().delegate_id, Some(1));
+        let token = auth_service.issue_token("testuser", None).unwrap().token;
+        assert_eq!(auth_service.verify_token(&token).unwrap().delegate_id, None);
+    }
+
     #[test]
     fn test_credentials_from_config() {
         // Test that credentials are read from config
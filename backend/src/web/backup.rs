@@ -0,0 +1,611 @@
+//! Backup API module
+//!
+//! `GET /api/backup` downloads the DNS records, rewrite rules and upstream
+//! servers as one JSON document. `POST /api/backup/restore` takes such a
+//! document back; each restored table is replaced as a whole and its rows
+//! keep their ids, tenant rows included.
+//!
+//! - `?dry_run=true` only reports what would change: per table the rows
+//!   created, updated, deleted and unchanged, and the ids whose contents
+//!   differ from the backup
+//! - `?tables=rewrite_rules` restores only the listed tables (`records`,
+//!   `rewrite_rules`, `upstreams`) and leaves the others as they are, e.g.
+//!   to pull the rules from last night's backup without touching records
+//!
+//! Restored rows pass the same validation and normalization as rows created
+//! through the API, and rows referring to a tenant, record group or shadowed
+//! rule that does not exist reject the whole restore.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{BackupRow, BackupTable, Database, NotificationSeverity, Tags};
+use crate::dns::proxy::UpstreamManager;
+use crate::dns::{CacheManager, LocalRecordIndex, RewriteEngine};
+use crate::services::notifications::notify;
+use crate::web::records::{reload_local_records, ttl_bounds, CreateRecordRequest, TtlBounds};
+use crate::web::rewrite::CreateRewriteRuleRequest;
+use crate::web::upstreams::CreateUpstreamServerRequest;
+use crate::web::{bad_request, internal_error, ApiError};
+
+/// Backup document version written by this release
+const BACKUP_VERSION: u32 = 1;
+
+/// Application state for backup API
+#[derive(Clone)]
+pub struct BackupState {
+    pub db: Arc<Database>,
+    pub rewrite_engine: Arc<RewriteEngine>,
+    pub upstream_manager: Arc<UpstreamManager>,
+    pub local_records: Arc<LocalRecordIndex>,
+    pub cache: Arc<CacheManager>,
+}
+
+/// Backup document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupDocument {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub records: Option<Vec<BackupRow>>,
+    pub rewrite_rules: Option<Vec<BackupRow>>,
+    pub upstreams: Option<Vec<BackupRow>>,
+}
+
+impl BackupDocument {
+    fn rows(&self, table: BackupTable) -> Option<&[BackupRow]> {
+        match table {
+            BackupTable::Records => self.records.as_deref(),
+            BackupTable::RewriteRules => self.rewrite_rules.as_deref(),
+            BackupTable::Upstreams => self.upstreams.as_deref(),
+        }
+    }
+}
+
+/// Query parameters for restore
+#[derive(Debug, Deserialize)]
+pub struct RestoreParams {
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Comma-separated tables to restore, all in the document by default
+    pub tables: Option<String>,
+}
+
+/// What restoring one table changes
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TableDiff {
+    pub table: BackupTable,
+    pub create: usize,
+    pub update: usize,
+    pub delete: usize,
+    pub unchanged: usize,
+    /// Ids present in both whose contents differ
+    pub conflicting_ids: Vec<i64>,
+}
+
+impl TableDiff {
+    fn has_changes(&self) -> bool {
+        self.create + self.update + self.delete > 0
+    }
+}
+
+/// Validation error details
+#[derive(Debug, Serialize)]
+pub struct ValidationErrors {
+    pub errors: Vec<ValidationError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidationError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Rows other tables may point at
+struct References {
+    tenants: HashSet<i64>,
+    groups: HashSet<i64>,
+}
+
+/// Restore/dry-run response
+#[derive(Debug, Serialize)]
+pub struct RestoreResponse {
+    pub dry_run: bool,
+    pub applied: bool,
+    pub tables: Vec<TableDiff>,
+}
+
+fn row_id(row: &BackupRow) -> Option<i64> {
+    row.get("id").and_then(|id| id.as_i64())
+}
+
+/// Tables to restore: the requested ones, or all in the document
+fn select_tables(doc: &BackupDocument, tables: Option<&str>) -> Result<Vec<BackupTable>, ApiError> {
+    let Some(tables) = tables.filter(|t| !t.trim().is_empty()) else {
+        return Ok(BackupTable::ALL.into_iter().filter(|t| doc.rows(*t).is_some()).collect());
+    };
+
+    let mut selected = Vec::new();
+    for name in tables.split(',') {
+        let table = BackupTable::from_str(name).ok_or_else(|| {
+            bad_request(format!(
+                "Unknown backup table '{}'. Must be one of: records, rewrite_rules, upstreams",
+                name.trim()
+            ))
+        })?;
+        if doc.rows(table).is_none() {
+            return Err(bad_request(format!("Backup has no {} section", table.as_str())));
+        }
+        if !selected.contains(&table) {
+            selected.push(table);
+        }
+    }
+    Ok(selected)
+}
+
+/// Check that every row has a unique id
+fn validate_rows(table: BackupTable, rows: &[BackupRow]) -> Result<(), ApiError> {
+    let mut seen = HashSet::new();
+    for (i, row) in rows.iter().enumerate() {
+        let Some(id) = row_id(row) else {
+            return Err(bad_request(format!("Invalid backup: {}[{}] has no id", table.as_str(), i)));
+        };
+        if !seen.insert(id) {
+            return Err(bad_request(format!("Invalid backup: {} id {} is listed more than once", table.as_str(), id)));
+        }
+    }
+    Ok(())
+}
+
+fn text(row: &BackupRow, key: &str) -> Option<String> {
+    row.get(key).and_then(|v| v.as_str()).map(str::to_string)
+}
+
+fn int(row: &BackupRow, key: &str) -> Option<i64> {
+    row.get(key).and_then(|v| v.as_i64())
+}
+
+/// SQLite stores booleans as 0 and 1
+fn flag(row: &BackupRow, key: &str) -> Option<bool> {
+    row.get(key).and_then(|v| v.as_bool().or_else(|| v.as_i64().map(|n| n != 0)))
+}
+
+fn tags(row: &BackupRow) -> Vec<String> {
+    text(row, "tags").and_then(|t| Tags::try_from(t).ok()).unwrap_or_default().0
+}
+
+fn optional_text(value: Option<String>) -> serde_json::Value {
+    value.filter(|s| !s.is_empty()).into()
+}
+
+/// Validate and normalize one row as the create endpoint of its table would
+fn prepare_row(
+    table: BackupTable,
+    row: &BackupRow,
+    ttl_bounds: &TtlBounds,
+) -> Result<BackupRow, Vec<(String, String)>> {
+    let mut row = row.clone();
+    let errors: Vec<(String, String)> = match table {
+        BackupTable::Records => {
+            let request = CreateRecordRequest {
+                name: text(&row, "name").unwrap_or_default(),
+                record_type: text(&row, "record_type").unwrap_or_default(),
+                value: text(&row, "value").unwrap_or_default(),
+                ttl: int(&row, "ttl").unwrap_or(300) as i32,
+                priority: int(&row, "priority").unwrap_or(0) as i32,
+                enabled: flag(&row, "enabled").unwrap_or(true),
+                tenant_id: int(&row, "tenant_id"),
+                description: text(&row, "description"),
+                tags: tags(&row),
+                expires_at: None,
+            };
+            match request.validate(ttl_bounds) {
+                Ok(()) => {
+                    let record = request.into_create_dns_record();
+                    row.insert("name".to_string(), record.name.into());
+                    row.insert("record_type".to_string(), record.record_type.into());
+                    row.insert("tags".to_string(), record.tags.to_json().into());
+                    Vec::new()
+                }
+                Err(e) => e.errors.into_iter().map(|e| (e.field, e.message)).collect(),
+            }
+        }
+        BackupTable::RewriteRules => {
+            let request = CreateRewriteRuleRequest {
+                pattern: text(&row, "pattern").unwrap_or_default(),
+                match_type: text(&row, "match_type").unwrap_or_default(),
+                action_type: text(&row, "action_type").unwrap_or_default(),
+                action_value: text(&row, "action_value"),
+                priority: int(&row, "priority").unwrap_or(0) as i32,
+                enabled: flag(&row, "enabled").unwrap_or(true),
+                description: text(&row, "description"),
+                tenant_id: int(&row, "tenant_id"),
+                shadow: flag(&row, "shadow").unwrap_or(false),
+                tags: tags(&row),
+                expires_at: None,
+            };
+            match request.validate() {
+                Ok(()) => {
+                    let rule = request.into_create_rewrite_rule();
+                    row.insert("pattern".to_string(), rule.pattern.into());
+                    row.insert("match_type".to_string(), rule.match_type.into());
+                    row.insert("action_type".to_string(), rule.action_type.into());
+                    row.insert("tags".to_string(), rule.tags.to_json().into());
+                    Vec::new()
+                }
+                Err(e) => e.errors.into_iter().map(|e| (e.field, e.message)).collect(),
+            }
+        }
+        BackupTable::Upstreams => {
+            let request = CreateUpstreamServerRequest {
+                name: text(&row, "name").unwrap_or_default(),
+                address: text(&row, "address").unwrap_or_default(),
+                protocol: text(&row, "protocol").unwrap_or_default(),
+                timeout: int(&row, "timeout").unwrap_or(5000) as i32,
+                enabled: flag(&row, "enabled").unwrap_or(true),
+                source_ip: text(&row, "source_ip"),
+                source_interface: text(&row, "source_interface"),
+                tls_server_name: text(&row, "tls_server_name"),
+                verify_hostname: flag(&row, "verify_hostname"),
+                doh_method: text(&row, "doh_method"),
+                doh_http_version: text(&row, "doh_http_version"),
+            };
+            match request.validate() {
+                Ok(()) => {
+                    let server = request.into_create_upstream_server();
+                    row.insert("protocol".to_string(), server.protocol.into());
+                    for (key, value) in [
+                        ("source_ip", server.source_ip),
+                        ("source_interface", server.source_interface),
+                        ("tls_server_name", server.tls_server_name),
+                        ("doh_method", server.doh_method),
+                        ("doh_http_version", server.doh_http_version),
+                    ] {
+                        if row.contains_key(key) {
+                            row.insert(key.to_string(), optional_text(value));
+                        }
+                    }
+                    Vec::new()
+                }
+                Err(e) => e.errors.into_iter().map(|e| (e.field, e.message)).collect(),
+            }
+        }
+    };
+    if errors.is_empty() {
+        Ok(row)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Validate and normalize the rows of a table and check their references
+///
+/// A tenant or record group must exist in the database; a shadowed rule
+/// must be among the restored rules.
+fn prepare_rows(
+    table: BackupTable,
+    rows: &[BackupRow],
+    ttl_bounds: &TtlBounds,
+    references: &References,
+    errors: &mut Vec<ValidationError>,
+) -> Vec<BackupRow> {
+    let ids: HashSet<i64> = rows.iter().filter_map(row_id).collect();
+    let mut prepared = Vec::with_capacity(rows.len());
+    for (i, row) in rows.iter().enumerate() {
+        let mut error = |field: &str, message: String| {
+            errors.push(ValidationError {
+                field: format!("{}[{}].{}", table.as_str(), i, field),
+                message,
+            })
+        };
+        match prepare_row(table, row, ttl_bounds) {
+            Ok(row) => prepared.push(row),
+            Err(row_errors) => {
+                for (field, message) in row_errors {
+                    error(&field, message);
+                }
+            }
+        }
+        if let Some(id) = int(row, "tenant_id").filter(|id| !references.tenants.contains(id)) {
+            error("tenant_id", format!("Tenant {} does not exist", id));
+        }
+        if table == BackupTable::Records {
+            if let Some(id) = int(row, "group_id").filter(|id| !references.groups.contains(id)) {
+                error("group_id", format!("Record group {} does not exist", id));
+            }
+        }
+        if table == BackupTable::RewriteRules {
+            if let Some(id) = int(row, "shadow_of").filter(|id| !ids.contains(id)) {
+                error("shadow_of", format!("Rewrite rule {} is not in the backup", id));
+            }
+        }
+    }
+    prepared
+}
+
+/// Compare the current rows of a table with the backup
+///
+/// Only columns present in the backup are compared, so columns added
+/// since it was taken don't make every row a conflict.
+fn diff_table(table: BackupTable, current: &[BackupRow], backup: &[BackupRow]) -> TableDiff {
+    let current: HashMap<i64, &BackupRow> = current.iter().filter_map(|r| Some((row_id(r)?, r))).collect();
+    let mut diff = TableDiff {
+        table,
+        create: 0,
+        update: 0,
+        delete: 0,
+        unchanged: 0,
+        conflicting_ids: Vec::new(),
+    };
+
+    let mut restored = HashSet::new();
+    for row in backup {
+        let Some(id) = row_id(row) else { continue };
+        restored.insert(id);
+        match current.get(&id) {
+            None => diff.create += 1,
+            Some(existing) if row.iter().all(|(k, v)| existing.get(k) == Some(v)) => diff.unchanged += 1,
+            Some(_) => {
+                diff.update += 1;
+                diff.conflicting_ids.push(id);
+            }
+        }
+    }
+    diff.delete = current.keys().filter(|id| !restored.contains(id)).count();
+    diff.conflicting_ids.sort_unstable();
+    diff
+}
+
+/// Backup of every table
+pub(crate) async fn backup_document(db: &Database) -> anyhow::Result<BackupDocument> {
+    Ok(BackupDocument {
+        version: BACKUP_VERSION,
+        created_at: Utc::now(),
+        records: Some(db.dump_table(BackupTable::Records).await?),
+        rewrite_rules: Some(db.dump_table(BackupTable::RewriteRules).await?),
+        upstreams: Some(db.dump_table(BackupTable::Upstreams).await?),
+    })
+}
+
+/// Download a backup
+///
+/// GET /api/backup
+pub async fn create_backup(State(state): State<BackupState>) -> Result<impl IntoResponse, ApiError> {
+    let doc = backup_document(&state.db)
+        .await
+        .map_err(|e| internal_error("Failed to create backup", e))?;
+    Ok(Json(doc))
+}
+
+/// Restore a backup, or preview the restore
+///
+/// POST /api/backup/restore[?dry_run=true][&tables=records,rewrite_rules,upstreams]
+pub async fn restore_backup(
+    State(state): State<BackupState>,
+    Query(params): Query<RestoreParams>,
+    Json(doc): Json<BackupDocument>,
+) -> Result<impl IntoResponse, ApiError> {
+    if doc.version > BACKUP_VERSION {
+        return Err(bad_request(format!("Unsupported backup version {}", doc.version)));
+    }
+    let tables = select_tables(&doc, params.tables.as_deref())?;
+    if tables.is_empty() {
+        return Err(bad_request("Backup contains no tables to restore".to_string()));
+    }
+
+    let read_failed = |e| internal_error("Failed to read current configuration", e);
+    let tenants = state.db.tenants().list().await.map_err(read_failed)?;
+    let groups = state.db.record_groups().list().await.map_err(read_failed)?;
+    let references = References {
+        tenants: tenants.into_iter().map(|t| t.id).collect(),
+        groups: groups.into_iter().map(|g| g.id).collect(),
+    };
+    let ttl_bounds = ttl_bounds(&state.db).await;
+
+    let mut prepared = HashMap::with_capacity(tables.len());
+    let mut errors = Vec::new();
+    for &table in &tables {
+        let rows = doc.rows(table).unwrap_or_default();
+        validate_rows(table, rows)?;
+        prepared.insert(table, prepare_rows(table, rows, &ttl_bounds, &references, &mut errors));
+    }
+    if !errors.is_empty() {
+        return Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: "Validation failed".to_string(),
+            details: Some(serde_json::to_value(ValidationErrors { errors }).unwrap()),
+        });
+    }
+
+    let mut diffs = Vec::with_capacity(tables.len());
+    for &table in &tables {
+        let current = state.db.dump_table(table).await.map_err(read_failed)?;
+        diffs.push(diff_table(table, &current, &prepared[&table]));
+    }
+
+    let changed: Vec<BackupTable> = diffs.iter().filter(|d| d.has_changes()).map(|d| d.table).collect();
+    if params.dry_run || changed.is_empty() {
+        return Ok(Json(RestoreResponse {
+            dry_run: params.dry_run,
+            applied: false,
+            tables: diffs,
+        }));
+    }
+
+    let restore: Vec<(BackupTable, &[BackupRow])> = changed
+        .iter()
+        .map(|&table| (table, prepared[&table].as_slice()))
+        .collect();
+    state
+        .db
+        .restore_tables(&restore)
+        .await
+        .map_err(|e| internal_error("Failed to restore backup", e))?;
+
+    let restored = changed.iter().map(|t| t.as_str()).collect::<Vec<_>>().join(", ");
+    tracing::info!("Backup from {} restored: {}", doc.created_at, restored);
+    notify(
+        &state.db,
+        NotificationSeverity::Info,
+        "backup",
+        format!("Backup from {} restored: {}", doc.created_at.format("%Y-%m-%d %H:%M UTC"), restored),
+        serde_json::json!({ "created_at": doc.created_at, "tables": diffs }),
+    )
+    .await;
+
+    // Hot reload the affected components
+    if changed.contains(&BackupTable::Records) {
+        reload_local_records(&state.local_records).await;
+    }
+    if changed.contains(&BackupTable::RewriteRules) {
+        if let Err(e) = state.rewrite_engine.reload_rules().await {
+            tracing::warn!("Failed to reload rewrite rules: {}", e);
+        }
+    }
+    if changed.contains(&BackupTable::Upstreams) {
+        if let Err(e) = state.upstream_manager.reload_from_db(&state.db).await {
+            tracing::warn!("Failed to reload upstream servers: {}", e);
+        }
+    }
+    // Cached answers may come from the replaced records and rules
+    if changed.iter().any(|t| *t != BackupTable::Upstreams) {
+        state.cache.clear().await;
+    }
+
+    Ok(Json(RestoreResponse {
+        dry_run: false,
+        applied: true,
+        tables: diffs,
+    }))
+}
+
+/// Build the backup API router
+pub fn backup_router(state: BackupState) -> axum::Router {
+    use axum::routing::{get, post};
+
+    axum::Router::new()
+        .route("/", get(create_backup))
+        .route("/restore", post(restore_backup))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn row(value: serde_json::Value) -> BackupRow {
+        value.as_object().unwrap().clone()
+    }
+
+    fn document(records: Option<Vec<BackupRow>>, rewrite_rules: Option<Vec<BackupRow>>) -> BackupDocument {
+        BackupDocument {
+            version: BACKUP_VERSION,
+            created_at: Utc::now(),
+            records,
+            rewrite_rules,
+            upstreams: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_table() {
+        let current = vec![
+            row(json!({"id": 1, "name": "a.com", "ttl": 300, "group_id": null})),
+            row(json!({"id": 2, "name": "b.com", "ttl": 300, "group_id": null})),
+            row(json!({"id": 3, "name": "c.com", "ttl": 300, "group_id": null})),
+        ];
+        // Taken before group_id existed
+        let backup = vec![
+            row(json!({"id": 1, "name": "a.com", "ttl": 300})),
+            row(json!({"id": 2, "name": "b.com", "ttl": 60})),
+            row(json!({"id": 4, "name": "d.com", "ttl": 300})),
+        ];
+
+        let diff = diff_table(BackupTable::Records, &current, &backup);
+        assert_eq!((diff.create, diff.update, diff.delete, diff.unchanged), (1, 1, 1, 1));
+        assert_eq!(diff.conflicting_ids, vec![2]);
+        assert!(diff.has_changes());
+        assert!(!diff_table(BackupTable::Records, &current, &current).has_changes());
+    }
+
+    #[test]
+    fn test_select_tables() {
+        let doc = document(Some(Vec::new()), Some(Vec::new()));
+        assert_eq!(
+            select_tables(&doc, None).unwrap(),
+            vec![BackupTable::Records, BackupTable::RewriteRules]
+        );
+        assert_eq!(
+            select_tables(&doc, Some("rewrite_rules, rewrite_rules")).unwrap(),
+            vec![BackupTable::RewriteRules]
+        );
+        assert!(select_tables(&doc, Some("upstreams")).is_err());
+        assert!(select_tables(&doc, Some("listeners")).is_err());
+    }
+
+    #[test]
+    fn test_validate_rows() {
+        let rows = vec![row(json!({"id": 1})), row(json!({"id": 2}))];
+        assert!(validate_rows(BackupTable::Records, &rows).is_ok());
+        assert!(validate_rows(BackupTable::Records, &[row(json!({"name": "a.com"}))]).is_err());
+        assert!(validate_rows(BackupTable::Records, &[row(json!({"id": 1})), row(json!({"id": 1}))]).is_err());
+    }
+
+    #[test]
+    fn test_prepare_rows() {
+        let references = References {
+            tenants: HashSet::from([1]),
+            groups: HashSet::from([7]),
+        };
+        let prepare = |table, rows: &[BackupRow]| {
+            let mut errors = Vec::new();
+            let rows = prepare_rows(table, rows, &TtlBounds::default(), &references, &mut errors);
+            (rows, errors.into_iter().map(|e| e.field).collect::<Vec<_>>())
+        };
+
+        // Normalized as the create endpoints do
+        let (rows, errors) = prepare(
+            BackupTable::Records,
+            &[row(json!({
+                "id": 1, "name": "App.Example.COM.", "record_type": "a", "value": "10.0.0.1",
+                "ttl": 300, "enabled": 1, "tags": "[\"Prod\"]", "tenant_id": 1, "group_id": 7
+            }))],
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(rows[0]["name"], "app.example.com");
+        assert_eq!(rows[0]["record_type"], "A");
+        assert_eq!(rows[0]["tags"], "[\"prod\"]");
+
+        let (_, errors) = prepare(
+            BackupTable::Records,
+            &[
+                row(json!({"id": 1, "name": "a.com", "record_type": "A", "value": "not-an-ip"})),
+                row(json!({"id": 2, "name": "b.com", "record_type": "A", "value": "10.0.0.2", "tenant_id": 2})),
+                row(json!({"id": 3, "name": "c.com", "record_type": "A", "value": "10.0.0.3", "group_id": 8})),
+            ],
+        );
+        assert_eq!(errors, ["records[0].value", "records[1].tenant_id", "records[2].group_id"]);
+
+        let (rows, errors) = prepare(
+            BackupTable::RewriteRules,
+            &[
+                row(json!({"id": 1, "pattern": "Ads.Example.", "match_type": "Exact", "action_type": "block"})),
+                row(json!({
+                    "id": 2, "pattern": "x.com", "match_type": "exact", "action_type": "block",
+                    "shadow": 1, "shadow_of": 9
+                })),
+            ],
+        );
+        assert_eq!(rows[0]["pattern"], "ads.example");
+        assert_eq!(rows[0]["match_type"], "exact");
+        assert_eq!(errors, ["rewrite_rules[1].shadow_of"]);
+    }
+}
@@ -10,17 +10,25 @@
 //! - 3.20: Provide clearing all cache
 //! - 3.21: Display cache statistics
 
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{
-    extract::{Path, State},
+    extract::{ConnectInfo, Path, Query, State},
+    http::HeaderMap,
     response::IntoResponse,
     Json,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::db::Database;
-use crate::dns::{CacheConfig, CacheManager, CacheStats};
+use crate::db::{CreateCachePurgeAudit, Database};
+use crate::dns::{
+    AdaptiveTtlSettings, CacheConfig, CacheManager, CacheStats, DnsQuery, DnsResolver, RecordType,
+    ADAPTIVE_TTL_MULTIPLIER_RANGE, ADAPTIVE_TTL_REFRESHES_RANGE, CONFIG_KEY_ADAPTIVE_TTL,
+    CONFIG_KEY_ADAPTIVE_TTL_MAX_MULTIPLIER, CONFIG_KEY_ADAPTIVE_TTL_STABLE_REFRESHES,
+};
+use crate::web::hooks::client_ip;
 use crate::web::ApiError;
 
 /// Application state for cache API
@@ -28,24 +36,58 @@ use crate::web::ApiError;
 pub struct CacheState {
     pub cache: Arc<CacheManager>,
     pub db: Arc<Database>,
+    pub resolver: Arc<DnsResolver>,
+    /// Take the client address from reverse proxy headers
+    pub trust_proxy_headers: bool,
 }
 
+/// Most domains accepted by one preload request
+const MAX_PRELOAD_DOMAINS: usize = 1000;
+/// Default TTL of preloaded entries (7 days)
+const DEFAULT_PRELOAD_TTL: u64 = 86400 * 7;
+/// Longest TTL of preloaded entries (1 year)
+const MAX_PRELOAD_TTL: u64 = 86400 * 365;
+/// Largest cache memory budget (64 GiB)
+const MAX_CACHE_MEMORY_MB: usize = 65536;
+
 /// Cache statistics response
 #[derive(Debug, Serialize)]
 pub struct CacheStatsResponse {
+    /// Storage backend: memory or redis
+    pub backend: String,
     pub hits: u64,
     pub misses: u64,
     pub entries: usize,
     pub hit_rate: f64,
+    /// Eviction protection: segment sizes, promotions and ghost re-admissions
+    pub protected_entries: usize,
+    pub protected_hits: u64,
+    pub promotions: u64,
+    pub ghost_hits: u64,
+    pub probation_evictions: u64,
+    pub protected_evictions: u64,
+    /// Estimated memory held by entries (0 for the Redis backend)
+    pub memory_bytes: usize,
+    /// Entries evicted to stay within `max_memory_mb`
+    pub memory_evictions: u64,
 }
 
 impl From<CacheStats> for CacheStatsResponse {
     fn from(stats: CacheStats) -> Self {
         Self {
+            hit_rate: stats.hit_rate(),
+            backend: stats.backend,
             hits: stats.hits,
             misses: stats.misses,
             entries: stats.entries,
-            hit_rate: stats.hit_rate(),
+            protected_entries: stats.protected_entries,
+            protected_hits: stats.protected_hits,
+            promotions: stats.promotions,
+            ghost_hits: stats.ghost_hits,
+            probation_evictions: stats.probation_evictions,
+            protected_evictions: stats.protected_evictions,
+            memory_bytes: stats.memory_bytes,
+            memory_evictions: stats.memory_evictions,
         }
     }
 }
@@ -55,22 +97,36 @@ impl From<CacheStats> for CacheStatsResponse {
 pub struct CacheConfigResponse {
     pub default_ttl: u64,
     pub max_entries: usize,
+    /// Estimated memory budget in MiB, 0 for no limit
+    pub max_memory_mb: usize,
+    /// Extend the TTL of answers that stay the same across refreshes
+    pub adaptive_ttl: bool,
+    pub adaptive_ttl_max_multiplier: u32,
+    pub adaptive_ttl_stable_refreshes: u32,
 }
 
-impl From<CacheConfig> for CacheConfigResponse {
-    fn from(config: CacheConfig) -> Self {
+impl CacheConfigResponse {
+    pub fn new(config: CacheConfig, adaptive: AdaptiveTtlSettings) -> Self {
         Self {
             default_ttl: config.default_ttl,
             max_entries: config.max_entries,
+            max_memory_mb: config.max_memory_mb,
+            adaptive_ttl: adaptive.enabled,
+            adaptive_ttl_max_multiplier: adaptive.max_multiplier,
+            adaptive_ttl_stable_refreshes: adaptive.stable_refreshes,
         }
     }
 }
 
 /// Update cache configuration request
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct UpdateCacheConfigRequest {
     pub default_ttl: Option<u64>,
     pub max_entries: Option<usize>,
+    pub max_memory_mb: Option<usize>,
+    pub adaptive_ttl: Option<bool>,
+    pub adaptive_ttl_max_multiplier: Option<u32>,
+    pub adaptive_ttl_stable_refreshes: Option<u32>,
 }
 
 /// Validation error details
@@ -120,6 +176,28 @@ impl UpdateCacheConfigRequest {
             }
         }
 
+        if self.max_memory_mb.is_some_and(|mb| mb > MAX_CACHE_MEMORY_MB) {
+            errors.push(ValidationError {
+                field: "max_memory_mb".to_string(),
+                message: format!("Max memory cannot exceed {} MiB", MAX_CACHE_MEMORY_MB),
+            });
+        }
+
+        let (min, max) = ADAPTIVE_TTL_MULTIPLIER_RANGE;
+        if self.adaptive_ttl_max_multiplier.is_some_and(|m| !(min..=max).contains(&m)) {
+            errors.push(ValidationError {
+                field: "adaptive_ttl_max_multiplier".to_string(),
+                message: format!("Max multiplier must be between {} and {}", min, max),
+            });
+        }
+        let (min, max) = ADAPTIVE_TTL_REFRESHES_RANGE;
+        if self.adaptive_ttl_stable_refreshes.is_some_and(|n| !(min..=max).contains(&n)) {
+            errors.push(ValidationError {
+                field: "adaptive_ttl_stable_refreshes".to_string(),
+                message: format!("Stable refreshes must be between {} and {}", min, max),
+            });
+        }
+
         if errors.is_empty() {
             Ok(())
         } else {
@@ -152,7 +230,7 @@ pub async fn get_cache_config(
     State(state): State<CacheState>,
 ) -> Result<impl IntoResponse, ApiError> {
     let config = state.cache.get_config().await;
-    Ok(Json(CacheConfigResponse::from(config)))
+    Ok(Json(CacheConfigResponse::new(config, state.cache.adaptive().settings())))
 }
 
 /// Update cache configuration
@@ -179,9 +257,24 @@ pub async fn update_cache_config(
     if let Some(max_entries) = request.max_entries {
         config.max_entries = max_entries;
     }
+    if let Some(max_memory_mb) = request.max_memory_mb {
+        config.max_memory_mb = max_memory_mb;
+    }
 
     state.cache.update_config(config.clone()).await;
 
+    let mut adaptive = state.cache.adaptive().settings();
+    if let Some(enabled) = request.adaptive_ttl {
+        adaptive.enabled = enabled;
+    }
+    if let Some(max_multiplier) = request.adaptive_ttl_max_multiplier {
+        adaptive.max_multiplier = max_multiplier;
+    }
+    if let Some(stable_refreshes) = request.adaptive_ttl_stable_refreshes {
+        adaptive.stable_refreshes = stable_refreshes;
+    }
+    state.cache.adaptive().set_settings(adaptive);
+
     // Persist to database
     let sys_config = state.db.system_config();
     if let Err(e) = sys_config.set("cache_default_ttl", &config.default_ttl.to_string()).await {
@@ -190,10 +283,66 @@ pub async fn update_cache_config(
     if let Err(e) = sys_config.set("cache_max_entries", &config.max_entries.to_string()).await {
         tracing::warn!("Failed to persist cache_max_entries: {}", e);
     }
+    if let Err(e) = sys_config.set("cache_max_memory_mb", &config.max_memory_mb.to_string()).await {
+        tracing::warn!("Failed to persist cache_max_memory_mb: {}", e);
+    }
+    for (key, value) in [
+        (CONFIG_KEY_ADAPTIVE_TTL, adaptive.enabled.to_string()),
+        (CONFIG_KEY_ADAPTIVE_TTL_MAX_MULTIPLIER, adaptive.max_multiplier.to_string()),
+        (CONFIG_KEY_ADAPTIVE_TTL_STABLE_REFRESHES, adaptive.stable_refreshes.to_string()),
+    ] {
+        if let Err(e) = sys_config.set(key, &value).await {
+            tracing::warn!("Failed to persist {}: {}", key, e);
+        }
+    }
 
-    tracing::info!("Cache config updated: ttl={}, max_entries={}", config.default_ttl, config.max_entries);
+    tracing::info!(
+        "Cache config updated: ttl={}, max_entries={}, max_memory_mb={}",
+        config.default_ttl,
+        config.max_entries,
+        config.max_memory_mb
+    );
 
-    Ok(Json(CacheConfigResponse::from(config)))
+    Ok(Json(CacheConfigResponse::new(config, adaptive)))
+}
+
+/// Adaptive TTL query parameters
+#[derive(Debug, Deserialize)]
+pub struct AdaptiveTtlParams {
+    /// Only names containing this text
+    pub domain: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Per-name adaptive TTL state: how often each answer changed and the
+/// multiplier it has earned
+///
+/// GET /api/cache/adaptive?domain=example&limit=100
+pub async fn adaptive_ttl_state(
+    State(state): State<CacheState>,
+    Query(params): Query<AdaptiveTtlParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let adaptive = state.cache.adaptive();
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    Ok(Json(serde_json::json!({
+        "settings": adaptive.settings(),
+        "tracked": adaptive.tracked(),
+        "extended_stores": adaptive.extended(),
+        "data": adaptive.entries(params.domain.as_deref(), limit),
+    })))
+}
+
+/// Write a purge from the admin API to the purge audit trail
+async fn audit_purge(state: &CacheState, addr: SocketAddr, headers: &HeaderMap, patterns: Vec<String>, purged: usize) {
+    let audit = CreateCachePurgeAudit {
+        actor: "admin".to_string(),
+        client_ip: client_ip(headers, addr, state.trust_proxy_headers),
+        patterns,
+        purged: purged as i64,
+    };
+    if let Err(e) = state.db.cache_purge_audit().create(audit).await {
+        tracing::warn!("Failed to write cache purge audit entry: {}", e);
+    }
 }
 
 /// Clear all cache entries
@@ -201,8 +350,12 @@ pub async fn update_cache_config(
 /// POST /api/cache/clear
 pub async fn clear_cache(
     State(state): State<CacheState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, ApiError> {
+    let purged = state.cache.stats().await.entries;
     state.cache.clear().await;
+    audit_purge(&state, addr, &headers, vec!["*".to_string()], purged).await;
 
     Ok(Json(serde_json::json!({
         "message": "Cache cleared successfully"
@@ -214,6 +367,8 @@ pub async fn clear_cache(
 /// POST /api/cache/clear/:domain
 pub async fn clear_domain_cache(
     State(state): State<CacheState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Path(domain): Path<String>,
 ) -> Result<impl IntoResponse, ApiError> {
     if domain.is_empty() {
@@ -224,7 +379,8 @@ pub async fn clear_domain_cache(
         });
     }
 
-    state.cache.clear_domain(&domain).await;
+    let purged = state.cache.clear_domain(&domain).await;
+    audit_purge(&state, addr, &headers, vec![domain.clone()], purged).await;
 
     Ok(Json(serde_json::json!({
         "message": format!("Cache cleared for domain: {}", domain)
@@ -247,6 +403,123 @@ pub async fn cleanup_cache(
     })))
 }
 
+/// Cache preload request
+#[derive(Debug, Deserialize)]
+pub struct PreloadRequest {
+    pub domains: Vec<String>,
+    /// Defaults to A and AAAA
+    pub record_types: Option<Vec<String>>,
+    /// TTL of the preloaded entries in seconds; defaults to 7 days
+    pub ttl: Option<u64>,
+}
+
+/// Outcome of preloading one name
+#[derive(Debug, Serialize)]
+pub struct PreloadResult {
+    pub domain: String,
+    pub record_type: String,
+    /// Response code, or "ERROR" when the upstream query failed
+    pub status: String,
+}
+
+/// Preload the cache, e.g. before switching to offline mode
+///
+/// Each name is resolved upstream and answers are cached with the given
+/// TTL, so they keep being served while upstream forwarding is disabled.
+///
+/// POST /api/cache/preload
+pub async fn preload_cache(
+    State(state): State<CacheState>,
+    Json(request): Json<PreloadRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let bad_request = |message: String| ApiError {
+        code: "BAD_REQUEST".to_string(),
+        message,
+        details: None,
+    };
+
+    if state.resolver.offline().is_enabled() {
+        return Err(bad_request(
+            "Cannot preload the cache while offline mode is enabled".to_string(),
+        ));
+    }
+    if request.domains.is_empty() || request.domains.len() > MAX_PRELOAD_DOMAINS {
+        return Err(bad_request(format!(
+            "domains must contain between 1 and {} entries",
+            MAX_PRELOAD_DOMAINS
+        )));
+    }
+    let ttl = request.ttl.unwrap_or(DEFAULT_PRELOAD_TTL);
+    if ttl == 0 || ttl > MAX_PRELOAD_TTL {
+        return Err(bad_request(format!(
+            "ttl must be between 1 and {} seconds",
+            MAX_PRELOAD_TTL
+        )));
+    }
+    let record_types = request
+        .record_types
+        .unwrap_or_else(|| vec!["A".to_string(), "AAAA".to_string()])
+        .iter()
+        .map(|t| {
+            t.parse::<RecordType>()
+                .map_err(|_| bad_request(format!("Invalid record type: {}", t)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut results = Vec::new();
+    for domain in &request.domains {
+        let domain = domain.trim().trim_end_matches('.').to_lowercase();
+        if domain.is_empty() {
+            continue;
+        }
+        for record_type in &record_types {
+            let query = DnsQuery::new(&domain, *record_type);
+            let status = match state.resolver.preload(&query, Duration::from_secs(ttl)).await {
+                Ok(code) => code.to_string(),
+                Err(e) => {
+                    tracing::warn!("Failed to preload {} {}: {}", domain, record_type, e);
+                    "ERROR".to_string()
+                }
+            };
+            results.push(PreloadResult {
+                domain: domain.clone(),
+                record_type: record_type.to_string(),
+                status,
+            });
+        }
+    }
+
+    let cached = results.iter().filter(|r| r.status == "NOERROR").count();
+    Ok(Json(serde_json::json!({
+        "cached": cached,
+        "total": results.len(),
+        "results": results,
+    })))
+}
+
+/// Purge audit query parameters
+#[derive(Debug, Deserialize)]
+pub struct PurgeAuditParams {
+    pub limit: Option<i64>,
+}
+
+/// List recent cache purges from the admin API, webhooks and gRPC
+///
+/// GET /api/cache/purge-audit?limit=100
+pub async fn list_purge_audit(
+    State(state): State<CacheState>,
+    Query(params): Query<PurgeAuditParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    let entries = state.db.cache_purge_audit().list(limit).await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to list purge audit entries: {}", e),
+        details: None,
+    })?;
+
+    Ok(Json(serde_json::json!({ "data": entries })))
+}
+
 /// Build the cache API router
 pub fn cache_router(state: CacheState) -> axum::Router {
     use axum::routing::{get, post};
@@ -254,9 +527,12 @@ pub fn cache_router(state: CacheState) -> axum::Router {
     axum::Router::new()
         .route("/stats", get(cache_stats))
         .route("/config", get(get_cache_config).put(update_cache_config))
+        .route("/adaptive", get(adaptive_ttl_state))
         .route("/clear", post(clear_cache))
         .route("/clear/:domain", post(clear_domain_cache))
         .route("/cleanup", post(cleanup_cache))
+        .route("/preload", post(preload_cache))
+        .route("/purge-audit", get(list_purge_audit))
         .with_state(state)
 }
 
@@ -270,6 +546,7 @@ mod tests {
             hits: 100,
             misses: 50,
             entries: 25,
+            ..Default::default()
         };
         let response = CacheStatsResponse::from(stats);
         assert_eq!(response.hits, 100);
@@ -283,10 +560,12 @@ mod tests {
         let config = CacheConfig {
             default_ttl: 60,
             max_entries: 10000,
+            max_memory_mb: 0,
         };
-        let response = CacheConfigResponse::from(config);
+        let response = CacheConfigResponse::new(config, AdaptiveTtlSettings::default());
         assert_eq!(response.default_ttl, 60);
         assert_eq!(response.max_entries, 10000);
+        assert!(!response.adaptive_ttl);
     }
 
     #[test]
@@ -294,6 +573,10 @@ mod tests {
         let request = UpdateCacheConfigRequest {
             default_ttl: Some(300),
             max_entries: Some(5000),
+            max_memory_mb: Some(256),
+            adaptive_ttl: Some(true),
+            adaptive_ttl_max_multiplier: Some(8),
+            adaptive_ttl_stable_refreshes: Some(3),
         };
         assert!(request.validate().is_ok());
     }
@@ -302,13 +585,13 @@ mod tests {
     fn test_update_cache_config_validation_invalid_ttl() {
         let request = UpdateCacheConfigRequest {
             default_ttl: Some(0),
-            max_entries: None,
+            ..Default::default()
         };
         assert!(request.validate().is_err());
 
         let request = UpdateCacheConfigRequest {
             default_ttl: Some(86400 * 8), // More than 7 days
-            max_entries: None,
+            ..Default::default()
         };
         assert!(request.validate().is_err());
     }
@@ -316,14 +599,35 @@ mod tests {
     #[test]
     fn test_update_cache_config_validation_invalid_max_entries() {
         let request = UpdateCacheConfigRequest {
-            default_ttl: None,
             max_entries: Some(0),
+            ..Default::default()
         };
         assert!(request.validate().is_err());
 
         let request = UpdateCacheConfigRequest {
-            default_ttl: None,
             max_entries: Some(1_000_001),
+            ..Default::default()
+        };
+        assert!(request.validate().is_err());
+
+        let request = UpdateCacheConfigRequest {
+            max_memory_mb: Some(MAX_CACHE_MEMORY_MB + 1),
+            ..Default::default()
+        };
+        assert!(request.validate().is_err());
+    }
+
+    #[test]
+    fn test_update_cache_config_validation_invalid_adaptive_ttl() {
+        let request = UpdateCacheConfigRequest {
+            adaptive_ttl_max_multiplier: Some(1),
+            ..Default::default()
+        };
+        assert!(request.validate().is_err());
+
+        let request = UpdateCacheConfigRequest {
+            adaptive_ttl_stable_refreshes: Some(0),
+            ..Default::default()
         };
         assert!(request.validate().is_err());
     }
@@ -0,0 +1,567 @@
+//! Domain categories API module
+//!
+//! Manage the local category database (downloadable or hand-maintained
+//! domain lists), the external classification API and per-category
+//! block/rewrite actions, and report query volume per category.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{
+    CategoryDomain, CategoryList, CategoryStat, CreateCategoryList, Database, UpdateCategoryList,
+};
+use crate::dns::{
+    parse_domain_list, CategoryAction, DnsResolver, DomainClassifier, CONFIG_KEY_CATEGORY_ACTIONS,
+    CONFIG_KEY_CATEGORY_API_URL, CONFIG_KEY_CATEGORY_CACHE_TTL, DEFAULT_CATEGORY_CACHE_TTL,
+};
+use crate::web::{bad_request, internal_error, not_found, ApiError};
+
+/// Application state for categories API
+#[derive(Clone)]
+pub struct CategoriesState {
+    pub db: Arc<Database>,
+    pub classifier: Arc<DomainClassifier>,
+}
+
+/// API response wrapper for single list
+#[derive(Debug, Serialize)]
+pub struct CategoryListResponse {
+    pub data: CategoryList,
+}
+
+/// API response wrapper for multiple lists
+#[derive(Debug, Serialize)]
+pub struct CategoryListsResponse {
+    pub data: Vec<CategoryList>,
+    pub total: usize,
+}
+
+/// Domains of a list
+#[derive(Debug, Serialize)]
+pub struct CategoryDomainsResponse {
+    pub data: Vec<CategoryDomain>,
+    pub total: usize,
+}
+
+/// Add domains request (one domain per entry, hosts-file lines accepted)
+#[derive(Debug, Deserialize)]
+pub struct AddDomainsRequest {
+    pub domains: Vec<String>,
+}
+
+/// Domain count after a change
+#[derive(Debug, Serialize)]
+pub struct DomainCountResponse {
+    pub domain_count: i64,
+}
+
+/// Classification query parameters
+#[derive(Debug, Deserialize)]
+pub struct ClassifyParams {
+    pub domain: String,
+}
+
+/// Category statistics query parameters
+#[derive(Debug, Deserialize)]
+pub struct CategoryStatsParams {
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    pub tenant_id: Option<i64>,
+}
+
+/// Category statistics response
+#[derive(Debug, Serialize)]
+pub struct CategoryStatsResponse {
+    pub data: Vec<CategoryStat>,
+    pub total_queries: i64,
+    pub total_blocked: i64,
+}
+
+/// Category settings
+#[derive(Debug, Serialize)]
+pub struct CategorySettings {
+    /// External classification API URL (`{domain}` is substituted)
+    pub api_url: Option<String>,
+    /// Category -> "block" or an IP address to answer with
+    pub actions: HashMap<String, String>,
+    /// Cache TTL for API classifications, in seconds
+    pub cache_ttl: u64,
+    /// Domains currently loaded from enabled lists
+    pub loaded_domains: usize,
+}
+
+/// Update category settings request
+#[derive(Debug, Deserialize)]
+pub struct UpdateCategorySettingsRequest {
+    pub api_url: Option<String>,
+    pub actions: Option<HashMap<String, String>>,
+    pub cache_ttl: Option<u64>,
+}
+
+fn save_error(e: anyhow::Error) -> ApiError {
+    internal_error("Failed to save category settings", e)
+}
+
+/// Validate list name and category
+fn validate_list(name: Option<&str>, category: Option<&str>) -> Result<(), ApiError> {
+    if let Some(name) = name {
+        if name.trim().is_empty() || name.len() > 100 {
+            return Err(bad_request("Name must be 1-100 characters".to_string()));
+        }
+    }
+    if let Some(category) = category {
+        validate_category(category)?;
+    }
+    Ok(())
+}
+
+/// Categories are lowercase identifiers like "advertising" or "adult-content"
+fn validate_category(category: &str) -> Result<(), ApiError> {
+    let valid = !category.is_empty()
+        && category.len() <= 50
+        && category
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(bad_request(format!(
+            "Invalid category '{}': use 1-50 lowercase letters, digits, '-' or '_'",
+            category
+        )))
+    }
+}
+
+fn validate_url(url: &str) -> Result<(), ApiError> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        Ok(())
+    } else {
+        Err(bad_request("URL must start with http:// or https://".to_string()))
+    }
+}
+
+/// Refresh the resolver's category database after a change
+async fn reload_classifier(state: &CategoriesState) {
+    if let Err(e) = state.classifier.reload().await {
+        tracing::warn!("Failed to reload category lists: {}", e);
+    }
+}
+
+/// List all category lists
+///
+/// GET /api/categories/lists
+pub async fn list_lists(
+    State(state): State<CategoriesState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let lists = state
+        .db
+        .category_lists()
+        .list()
+        .await
+        .map_err(|e| internal_error("Failed to list category lists", e))?;
+
+    Ok(Json(CategoryListsResponse {
+        total: lists.len(),
+        data: lists,
+    }))
+}
+
+/// Get a category list by ID
+///
+/// GET /api/categories/lists/:id
+pub async fn get_list(
+    State(state): State<CategoriesState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let list = state
+        .db
+        .category_lists()
+        .get_by_id(id)
+        .await
+        .map_err(|e| internal_error("Failed to get category list", e))?;
+
+    list.map(|l| Json(CategoryListResponse { data: l }))
+        .ok_or_else(|| not_found("Category list", id))
+}
+
+/// Create a category list
+///
+/// POST /api/categories/lists
+///
+/// Lists with a URL are downloaded immediately; a failed download leaves
+/// the list empty until the next refresh.
+pub async fn create_list(
+    State(state): State<CategoriesState>,
+    Json(mut request): Json<CreateCategoryList>,
+) -> Result<impl IntoResponse, ApiError> {
+    request.name = request.name.trim().to_string();
+    request.category = request.category.trim().to_lowercase();
+    request.url = request.url.map(|u| u.trim().to_string()).filter(|u| !u.is_empty());
+    validate_list(Some(&request.name), Some(&request.category))?;
+    if let Some(ref url) = request.url {
+        validate_url(url)?;
+    }
+
+    let list = state
+        .db
+        .category_lists()
+        .create(request)
+        .await
+        .map_err(|e| internal_error("Failed to create category list", e))?;
+
+    let list = if list.url.is_some() {
+        if let Err(e) = state.classifier.refresh_list(list.id).await {
+            tracing::warn!("Initial download of category list '{}' failed: {}", list.name, e);
+        }
+        state
+            .db
+            .category_lists()
+            .get_by_id(list.id)
+            .await
+            .map_err(|e| internal_error("Failed to get category list", e))?
+            .unwrap_or(list)
+    } else {
+        list
+    };
+
+    Ok((StatusCode::CREATED, Json(CategoryListResponse { data: list })))
+}
+
+/// Update a category list
+///
+/// PUT /api/categories/lists/:id
+pub async fn update_list(
+    State(state): State<CategoriesState>,
+    Path(id): Path<i64>,
+    Json(mut request): Json<UpdateCategoryList>,
+) -> Result<impl IntoResponse, ApiError> {
+    request.name = request.name.map(|n| n.trim().to_string());
+    request.category = request.category.map(|c| c.trim().to_lowercase());
+    validate_list(request.name.as_deref(), request.category.as_deref())?;
+    if let Some(ref url) = request.url {
+        validate_url(url)?;
+    }
+
+    let list = state
+        .db
+        .category_lists()
+        .update(id, request)
+        .await
+        .map_err(|e| internal_error("Failed to update category list", e))?
+        .ok_or_else(|| not_found("Category list", id))?;
+
+    reload_classifier(&state).await;
+
+    Ok(Json(CategoryListResponse { data: list }))
+}
+
+/// Delete a category list and its domains
+///
+/// DELETE /api/categories/lists/:id
+pub async fn delete_list(
+    State(state): State<CategoriesState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let deleted = state
+        .db
+        .category_lists()
+        .delete(id)
+        .await
+        .map_err(|e| internal_error("Failed to delete category list", e))?;
+
+    if !deleted {
+        return Err(not_found("Category list", id));
+    }
+
+    reload_classifier(&state).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Download a list now
+///
+/// POST /api/categories/lists/:id/refresh
+pub async fn refresh_list(
+    State(state): State<CategoriesState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let list = state
+        .db
+        .category_lists()
+        .get_by_id(id)
+        .await
+        .map_err(|e| internal_error("Failed to get category list", e))?
+        .ok_or_else(|| not_found("Category list", id))?;
+    if list.url.is_none() {
+        return Err(bad_request(format!("Category list '{}' has no download URL", list.name)));
+    }
+
+    let domain_count = state
+        .classifier
+        .refresh_list(id)
+        .await
+        .map_err(|e| internal_error("Failed to refresh category list", e))?;
+
+    Ok(Json(DomainCountResponse { domain_count }))
+}
+
+/// List the domains of a list
+///
+/// GET /api/categories/lists/:id/domains
+pub async fn list_domains(
+    State(state): State<CategoriesState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let domains = state
+        .db
+        .category_lists()
+        .list_domains(id)
+        .await
+        .map_err(|e| internal_error("Failed to list category domains", e))?;
+
+    Ok(Json(CategoryDomainsResponse {
+        total: domains.len(),
+        data: domains,
+    }))
+}
+
+/// Add domains to a list
+///
+/// POST /api/categories/lists/:id/domains
+pub async fn add_domains(
+    State(state): State<CategoriesState>,
+    Path(id): Path<i64>,
+    Json(request): Json<AddDomainsRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let domains = parse_domain_list(&request.domains.join("\n"));
+    if domains.is_empty() {
+        return Err(bad_request("No valid domains given".to_string()));
+    }
+
+    let repo = state.db.category_lists();
+    repo.get_by_id(id)
+        .await
+        .map_err(|e| internal_error("Failed to get category list", e))?
+        .ok_or_else(|| not_found("Category list", id))?;
+
+    let domain_count = repo
+        .add_domains(id, &domains)
+        .await
+        .map_err(|e| internal_error("Failed to add category domains", e))?;
+
+    reload_classifier(&state).await;
+
+    Ok(Json(DomainCountResponse { domain_count }))
+}
+
+/// Remove a domain from a list
+///
+/// DELETE /api/categories/lists/:id/domains/:domain
+pub async fn remove_domain(
+    State(state): State<CategoriesState>,
+    Path((id, domain)): Path<(i64, String)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let removed = state
+        .db
+        .category_lists()
+        .remove_domain(id, &domain.to_lowercase())
+        .await
+        .map_err(|e| internal_error("Failed to remove category domain", e))?;
+
+    if !removed {
+        return Err(ApiError {
+            code: "NOT_FOUND".to_string(),
+            message: format!("Domain {} not found in category list {}", domain, id),
+            details: None,
+        });
+    }
+
+    reload_classifier(&state).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Classify a domain
+///
+/// GET /api/categories/classify?domain=
+pub async fn classify(
+    State(state): State<CategoriesState>,
+    Query(params): Query<ClassifyParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !DnsResolver::is_valid_domain(params.domain.trim()) {
+        return Err(bad_request(format!("Invalid domain: {}", params.domain)));
+    }
+
+    let classification = state
+        .classifier
+        .classify_now(&params.domain)
+        .await
+        .map_err(|e| internal_error("Classification failed", e))?;
+
+    Ok(Json(classification))
+}
+
+/// Query and block counts per category
+///
+/// GET /api/categories/stats
+pub async fn category_stats(
+    State(state): State<CategoriesState>,
+    Query(params): Query<CategoryStatsParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let parse_time = |t: Option<String>| {
+        t.and_then(|t| chrono::DateTime::parse_from_rfc3339(&t).ok().map(|dt| dt.with_timezone(&chrono::Utc)))
+    };
+
+    let stats = state
+        .db
+        .query_logs()
+        .category_stats(parse_time(params.start_time), parse_time(params.end_time), params.tenant_id)
+        .await
+        .map_err(|e| internal_error("Failed to get category statistics", e))?;
+
+    Ok(Json(CategoryStatsResponse {
+        total_queries: stats.iter().map(|s| s.queries).sum(),
+        total_blocked: stats.iter().map(|s| s.blocked).sum(),
+        data: stats,
+    }))
+}
+
+async fn get_setting(db: &Database, key: &str) -> Result<Option<String>, ApiError> {
+    db.system_config()
+        .get(key)
+        .await
+        .map_err(|e| internal_error("Failed to get category settings", e))
+}
+
+async fn load_settings(state: &CategoriesState) -> Result<CategorySettings, ApiError> {
+    let api_url = get_setting(&state.db, CONFIG_KEY_CATEGORY_API_URL)
+        .await?
+        .filter(|u| !u.is_empty());
+    let actions = get_setting(&state.db, CONFIG_KEY_CATEGORY_ACTIONS)
+        .await?
+        .map(|v| serde_json::from_str(&v).unwrap_or_default())
+        .unwrap_or_default();
+    let cache_ttl = get_setting(&state.db, CONFIG_KEY_CATEGORY_CACHE_TTL)
+        .await?
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_CATEGORY_CACHE_TTL);
+
+    Ok(CategorySettings {
+        api_url,
+        actions,
+        cache_ttl,
+        loaded_domains: state.classifier.domain_count(),
+    })
+}
+
+/// Get category settings
+///
+/// GET /api/categories/settings
+pub async fn get_settings(
+    State(state): State<CategoriesState>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(load_settings(&state).await?))
+}
+
+/// Update category settings
+///
+/// PUT /api/categories/settings
+///
+/// An empty `api_url` disables the external API.
+pub async fn update_settings(
+    State(state): State<CategoriesState>,
+    Json(request): Json<UpdateCategorySettingsRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let repo = state.db.system_config();
+
+    if let Some(url) = request.api_url {
+        let url = url.trim();
+        if !url.is_empty() {
+            validate_url(url)?;
+            if !url.contains("{domain}") {
+                return Err(bad_request("API URL must contain a {domain} placeholder".to_string()));
+            }
+        }
+        repo.set(CONFIG_KEY_CATEGORY_API_URL, url).await.map_err(save_error)?;
+    }
+
+    if let Some(actions) = request.actions {
+        let mut normalized = HashMap::new();
+        for (category, action) in actions {
+            let category = category.trim().to_lowercase();
+            validate_category(&category)?;
+            let action = CategoryAction::parse(action.trim()).ok_or_else(|| {
+                bad_request(format!(
+                    "Invalid action '{}' for category {}: use \"block\" or an IP address",
+                    action, category
+                ))
+            })?;
+            normalized.insert(category, action.as_setting());
+        }
+        let value = serde_json::to_string(&normalized).map_err(|e| save_error(e.into()))?;
+        repo.set(CONFIG_KEY_CATEGORY_ACTIONS, &value).await.map_err(save_error)?;
+    }
+
+    if let Some(ttl) = request.cache_ttl {
+        repo.set(CONFIG_KEY_CATEGORY_CACHE_TTL, &ttl.to_string()).await.map_err(save_error)?;
+    }
+
+    reload_classifier(&state).await;
+
+    Ok(Json(load_settings(&state).await?))
+}
+
+/// Drop cached API classifications
+///
+/// DELETE /api/categories/cache
+pub async fn clear_cache(
+    State(state): State<CategoriesState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let cleared = state.classifier.clear_cache();
+    Ok(Json(serde_json::json!({ "cleared": cleared })))
+}
+
+/// Build the categories API router
+pub fn categories_router(state: CategoriesState) -> axum::Router {
+    use axum::routing::{delete, get, post};
+
+    axum::Router::new()
+        .route("/lists", get(list_lists).post(create_list))
+        .route("/lists/:id", get(get_list).put(update_list).delete(delete_list))
+        .route("/lists/:id/refresh", post(refresh_list))
+        .route("/lists/:id/domains", get(list_domains).post(add_domains))
+        .route("/lists/:id/domains/:domain", delete(remove_domain))
+        .route("/classify", get(classify))
+        .route("/stats", get(category_stats))
+        .route("/settings", get(get_settings).put(update_settings))
+        .route("/cache", delete(clear_cache))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_category() {
+        assert!(validate_category("advertising").is_ok());
+        assert!(validate_category("adult-content_2").is_ok());
+        assert!(validate_category("").is_err());
+        assert!(validate_category("Ads").is_err());
+        assert!(validate_category("social media").is_err());
+    }
+
+    #[test]
+    fn test_validate_url() {
+        assert!(validate_url("https://example.com/hosts.txt").is_ok());
+        assert!(validate_url("ftp://example.com/hosts.txt").is_err());
+    }
+}
@@ -0,0 +1,935 @@
+//! Config-as-code API module
+//!
+//! Accepts a declarative YAML document describing the desired DNS records,
+//! rewrite rules, upstream servers and listeners, diffs it against the
+//! current database state and applies the difference.
+//!
+//! Only sections present in the document are managed. With `prune: true`,
+//! records, rules and upstreams that exist in the database but not in a
+//! managed section are deleted. Tenant-owned records and rules are never
+//! touched. Optional fields omitted from an entry are left as they are.
+//! Applying the same document twice produces no changes.
+//!
+//! ```yaml
+//! prune: true
+//! records:
+//!   - { name: app.internal, record_type: A, value: 10.0.0.5, ttl: 60 }
+//! rewrite_rules:
+//!   - { pattern: "*.ads.example", match_type: wildcard, action_type: block }
+//! upstreams:
+//!   - { name: Cloudflare, address: 1.1.1.1:53, protocol: udp }
+//! listeners:
+//!   - { protocol: dot, enabled: true, port: 853 }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+
+use crate::db::{
+    CreateDnsRecord, CreateRewriteRule, CreateUpstreamServer, Database, DnsRecord, DnsRecordRepository,
+    RewriteRule, RewriteRuleRepository, ServerListener, ServerListenerRepository, UpdateDnsRecord,
+    UpdateRewriteRule, UpdateServerListener, UpdateUpstreamServer, UpstreamServer, UpstreamServerRepository,
+};
+use crate::dns::proxy::UpstreamManager;
+use crate::dns::{normalize_name, validate_interface, CacheManager, LocalRecordIndex, RewriteEngine};
+use crate::services::listener_manager::ListenerManager;
+use crate::web::records::{reload_local_records, ttl_bounds, CreateRecordRequest, TtlBounds};
+use crate::web::rewrite::{rule_cache_name, store_pattern, CreateRewriteRuleRequest};
+use crate::web::upstreams::CreateUpstreamServerRequest;
+use crate::web::ApiError;
+
+/// Application state for config-as-code API
+#[derive(Clone)]
+pub struct ConfigApplyState {
+    pub db: Arc<Database>,
+    pub rewrite_engine: Arc<RewriteEngine>,
+    pub upstream_manager: Arc<UpstreamManager>,
+    pub listener_manager: Arc<ListenerManager>,
+    pub local_records: Arc<LocalRecordIndex>,
+    pub cache: Arc<CacheManager>,
+}
+
+/// Desired configuration document
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigDocument {
+    /// Delete unlisted resources in managed sections
+    #[serde(default)]
+    pub prune: bool,
+    pub records: Option<Vec<RecordSpec>>,
+    pub rewrite_rules: Option<Vec<RewriteRuleSpec>>,
+    pub upstreams: Option<Vec<UpstreamSpec>>,
+    pub listeners: Option<Vec<ListenerSpec>>,
+}
+
+/// Desired DNS record, identified by name, type and value
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecordSpec {
+    pub name: String,
+    pub record_type: String,
+    pub value: String,
+    #[serde(default = "default_ttl")]
+    pub ttl: i32,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// Desired rewrite rule, identified by pattern and match type
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RewriteRuleSpec {
+    pub pattern: String,
+    pub match_type: String,
+    pub action_type: String,
+    pub action_value: Option<String>,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub description: Option<String>,
+}
+
+/// Desired upstream server, identified by name
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpstreamSpec {
+    pub name: String,
+    pub address: String,
+    pub protocol: String,
+    #[serde(default = "default_timeout")]
+    pub timeout: i32,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// Desired listener settings, identified by protocol
+///
+/// Listeners are a fixed set, so they can only be updated. Omitted fields
+/// are left unchanged; TLS material is managed through the listeners API.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenerSpec {
+    pub protocol: String,
+    pub enabled: Option<bool>,
+    pub bind_address: Option<String>,
+    pub port: Option<i32>,
+    /// Network interface to bind to; empty string removes the binding
+    pub interface: Option<String>,
+}
+
+fn default_ttl() -> i32 {
+    300
+}
+
+fn default_timeout() -> i32 {
+    5000
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Query parameters for apply
+#[derive(Debug, Deserialize)]
+pub struct ApplyParams {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Validation error details
+#[derive(Debug, Serialize)]
+pub struct ValidationErrors {
+    pub errors: Vec<ValidationError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidationError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Kind of change in a plan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeAction {
+    Create,
+    Update,
+    Delete,
+}
+
+/// Single field difference
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub from: serde_json::Value,
+    pub to: serde_json::Value,
+}
+
+/// Single planned change
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    pub kind: &'static str,
+    pub action: ChangeAction,
+    pub key: String,
+    pub id: Option<i64>,
+    pub fields: Vec<FieldChange>,
+}
+
+/// Change counts
+#[derive(Debug, Default, Serialize)]
+pub struct PlanSummary {
+    pub create: usize,
+    pub update: usize,
+    pub delete: usize,
+    pub unchanged: usize,
+}
+
+/// Apply/dry-run response
+#[derive(Debug, Serialize)]
+pub struct ApplyResponse {
+    pub dry_run: bool,
+    pub applied: bool,
+    pub summary: PlanSummary,
+    pub changes: Vec<Change>,
+}
+
+/// Database operation backing a planned change
+#[derive(Debug, Clone)]
+enum Operation {
+    CreateRecord(CreateDnsRecord),
+    UpdateRecord(i64, UpdateDnsRecord),
+    DeleteRecord(i64),
+    CreateRule(CreateRewriteRule),
+    UpdateRule(i64, UpdateRewriteRule),
+    DeleteRule(i64),
+    CreateUpstream(CreateUpstreamServer),
+    UpdateUpstream(i64, UpdateUpstreamServer),
+    DeleteUpstream(i64),
+    UpdateListener(String, UpdateServerListener),
+}
+
+/// Current state the document is diffed against
+#[derive(Debug, Default)]
+struct CurrentState {
+    records: Vec<DnsRecord>,
+    rules: Vec<RewriteRule>,
+    upstreams: Vec<UpstreamServer>,
+    listeners: Vec<ServerListener>,
+    ttl_bounds: TtlBounds,
+}
+
+/// Computed plan
+#[derive(Debug, Default)]
+struct Plan {
+    changes: Vec<(Change, Operation)>,
+    unchanged: usize,
+}
+
+impl Plan {
+    fn push(&mut self, kind: &'static str, action: ChangeAction, key: String, id: Option<i64>, fields: Vec<FieldChange>, op: Operation) {
+        self.changes.push((Change { kind, action, key, id, fields }, op));
+    }
+
+    fn summary(&self) -> PlanSummary {
+        let count = |action| self.changes.iter().filter(|(c, _)| c.action == action).count();
+        PlanSummary {
+            create: count(ChangeAction::Create),
+            update: count(ChangeAction::Update),
+            delete: count(ChangeAction::Delete),
+            unchanged: self.unchanged,
+        }
+    }
+}
+
+/// Compare a field and record the difference
+///
+/// Returns the desired value when it differs from the current one.
+fn diff_field<T>(fields: &mut Vec<FieldChange>, field: &str, current: &T, desired: &T) -> Option<T>
+where
+    T: PartialEq + Serialize + Clone,
+{
+    if current == desired {
+        return None;
+    }
+    fields.push(FieldChange {
+        field: field.to_string(),
+        from: serde_json::to_value(current).unwrap_or_default(),
+        to: serde_json::to_value(desired).unwrap_or_default(),
+    });
+    Some(desired.clone())
+}
+
+fn record_key(name: &str, record_type: &str, value: &str) -> String {
+    format!("{} {} {}", normalize_name(name), record_type.to_uppercase(), value)
+}
+
+fn rule_key(pattern: &str, match_type: &str) -> String {
+    format!("{} ({})", store_pattern(pattern.to_string(), match_type), match_type.to_lowercase())
+}
+
+/// Validate the document against the same rules as the REST API
+fn validate_document(doc: &ConfigDocument, current: &CurrentState) -> Result<(), ValidationErrors> {
+    let mut errors = Vec::new();
+
+    if let Some(ref records) = doc.records {
+        let mut seen = HashSet::new();
+        for (i, spec) in records.iter().enumerate() {
+            let request = CreateRecordRequest {
+                name: spec.name.clone(),
+                record_type: spec.record_type.clone(),
+                value: spec.value.clone(),
+                ttl: spec.ttl,
+                priority: spec.priority,
+                enabled: spec.enabled,
+                tenant_id: None,
+                description: None,
+                tags: Vec::new(),
+                expires_at: None,
+            };
+            if let Err(e) = request.validate(&current.ttl_bounds) {
+                errors.extend(e.errors.into_iter().map(|e| ValidationError {
+                    field: format!("records[{}].{}", i, e.field),
+                    message: e.message,
+                }));
+            }
+            if !seen.insert(record_key(&spec.name, &spec.record_type, &spec.value)) {
+                errors.push(ValidationError {
+                    field: format!("records[{}]", i),
+                    message: "Duplicate record".to_string(),
+                });
+            }
+        }
+    }
+
+    if let Some(ref rules) = doc.rewrite_rules {
+        let mut seen = HashSet::new();
+        for (i, spec) in rules.iter().enumerate() {
+            let request = CreateRewriteRuleRequest {
+                pattern: spec.pattern.clone(),
+                match_type: spec.match_type.clone(),
+                action_type: spec.action_type.clone(),
+                action_value: spec.action_value.clone(),
+                priority: spec.priority,
+                enabled: spec.enabled,
+                description: spec.description.clone(),
+                tenant_id: None,
+                shadow: false,
+                tags: Vec::new(),
+                expires_at: None,
+            };
+            if let Err(e) = request.validate() {
+                errors.extend(e.errors.into_iter().map(|e| ValidationError {
+                    field: format!("rewrite_rules[{}].{}", i, e.field),
+                    message: e.message,
+                }));
+            }
+            if !seen.insert(rule_key(&spec.pattern, &spec.match_type)) {
+                errors.push(ValidationError {
+                    field: format!("rewrite_rules[{}]", i),
+                    message: "Duplicate rule".to_string(),
+                });
+            }
+        }
+    }
+
+    if let Some(ref upstreams) = doc.upstreams {
+        let mut seen = HashSet::new();
+        for (i, spec) in upstreams.iter().enumerate() {
+            let request = CreateUpstreamServerRequest {
+                name: spec.name.clone(),
+                address: spec.address.clone(),
+                protocol: spec.protocol.clone(),
+                timeout: spec.timeout,
+                enabled: spec.enabled,
+                source_ip: None,
+                source_interface: None,
+                tls_server_name: None,
+                verify_hostname: None,
+                doh_method: None,
+                doh_http_version: None,
+            };
+            if let Err(e) = request.validate() {
+                errors.extend(e.errors.into_iter().map(|e| ValidationError {
+                    field: format!("upstreams[{}].{}", i, e.field),
+                    message: e.message,
+                }));
+            }
+            if !seen.insert(spec.name.clone()) {
+                errors.push(ValidationError {
+                    field: format!("upstreams[{}]", i),
+                    message: "Duplicate upstream name".to_string(),
+                });
+            }
+        }
+    }
+
+    if let Some(ref listeners) = doc.listeners {
+        let mut seen = HashSet::new();
+        for (i, spec) in listeners.iter().enumerate() {
+            let protocol = spec.protocol.to_lowercase();
+            if !current.listeners.iter().any(|l| l.protocol == protocol) {
+                errors.push(ValidationError {
+                    field: format!("listeners[{}].protocol", i),
+                    message: format!("Unknown listener '{}'", spec.protocol),
+                });
+            }
+            if let Some(port) = spec.port {
+                if !(1..=65535).contains(&port) {
+                    errors.push(ValidationError {
+                        field: format!("listeners[{}].port", i),
+                        message: "Port must be between 1 and 65535".to_string(),
+                    });
+                }
+            }
+            if let Some(iface) = spec.interface.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+                if let Err(e) = validate_interface(iface) {
+                    errors.push(ValidationError {
+                        field: format!("listeners[{}].interface", i),
+                        message: e.to_string(),
+                    });
+                }
+            }
+            if !seen.insert(protocol) {
+                errors.push(ValidationError {
+                    field: format!("listeners[{}]", i),
+                    message: "Duplicate listener".to_string(),
+                });
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationErrors { errors })
+    }
+}
+
+fn plan_records(plan: &mut Plan, specs: &[RecordSpec], current: &[DnsRecord], prune: bool) {
+    let mut existing: HashMap<String, &DnsRecord> = HashMap::new();
+    for record in current.iter().filter(|r| r.tenant_id.is_none()) {
+        existing
+            .entry(record_key(&record.name, &record.record_type, &record.value))
+            .or_insert(record);
+    }
+
+    let mut matched = HashSet::new();
+    for spec in specs {
+        let key = record_key(&spec.name, &spec.record_type, &spec.value);
+        match existing.get(&key) {
+            Some(record) => {
+                matched.insert(record.id);
+                let mut fields = Vec::new();
+                let update = UpdateDnsRecord {
+                    ttl: diff_field(&mut fields, "ttl", &record.ttl, &spec.ttl),
+                    priority: diff_field(&mut fields, "priority", &record.priority, &spec.priority),
+                    enabled: diff_field(&mut fields, "enabled", &record.enabled, &spec.enabled),
+                    ..Default::default()
+                };
+                if fields.is_empty() {
+                    plan.unchanged += 1;
+                } else {
+                    plan.push("record", ChangeAction::Update, key, Some(record.id), fields, Operation::UpdateRecord(record.id, update));
+                }
+            }
+            None => {
+                let create = CreateDnsRecord {
+                    name: normalize_name(&spec.name),
+                    record_type: spec.record_type.to_uppercase(),
+                    value: spec.value.clone(),
+                    ttl: spec.ttl,
+                    priority: spec.priority,
+                    enabled: spec.enabled,
+                    tenant_id: None,
+                    description: None,
+                    tags: Default::default(),
+                    expires_at: None,
+                };
+                plan.push("record", ChangeAction::Create, key, None, Vec::new(), Operation::CreateRecord(create));
+            }
+        }
+    }
+
+    if prune {
+        for record in current.iter().filter(|r| r.tenant_id.is_none() && !matched.contains(&r.id)) {
+            let key = record_key(&record.name, &record.record_type, &record.value);
+            plan.push("record", ChangeAction::Delete, key, Some(record.id), Vec::new(), Operation::DeleteRecord(record.id));
+        }
+    }
+}
+
+fn plan_rules(plan: &mut Plan, specs: &[RewriteRuleSpec], current: &[RewriteRule], prune: bool) {
+    let mut existing: HashMap<String, &RewriteRule> = HashMap::new();
+    // Shadow rules are canaries managed through the rewrite API
+    for rule in current.iter().filter(|r| r.tenant_id.is_none() && !r.shadow) {
+        existing
+            .entry(rule_key(&rule.pattern, &rule.match_type))
+            .or_insert(rule);
+    }
+
+    let mut matched = HashSet::new();
+    for spec in specs {
+        let key = rule_key(&spec.pattern, &spec.match_type);
+        let action_type = spec.action_type.to_lowercase();
+        match existing.get(&key) {
+            Some(rule) => {
+                matched.insert(rule.id);
+                let mut fields = Vec::new();
+                let update = UpdateRewriteRule {
+                    action_type: diff_field(&mut fields, "action_type", &rule.action_type, &action_type),
+                    action_value: spec
+                        .action_value
+                        .as_ref()
+                        .and_then(|v| diff_field(&mut fields, "action_value", &rule.action_value, &Some(v.clone())))
+                        .flatten(),
+                    priority: diff_field(&mut fields, "priority", &rule.priority, &spec.priority),
+                    enabled: diff_field(&mut fields, "enabled", &rule.enabled, &spec.enabled),
+                    description: spec
+                        .description
+                        .as_ref()
+                        .and_then(|v| diff_field(&mut fields, "description", &rule.description, &Some(v.clone())))
+                        .flatten(),
+                    ..Default::default()
+                };
+                if fields.is_empty() {
+                    plan.unchanged += 1;
+                } else {
+                    plan.push("rewrite_rule", ChangeAction::Update, key, Some(rule.id), fields, Operation::UpdateRule(rule.id, update));
+                }
+            }
+            None => {
+                let create = CreateRewriteRule {
+                    pattern: store_pattern(spec.pattern.clone(), &spec.match_type),
+                    match_type: spec.match_type.to_lowercase(),
+                    action_type,
+                    action_value: spec.action_value.clone(),
+                    priority: spec.priority,
+                    enabled: spec.enabled,
+                    description: spec.description.clone(),
+                    tenant_id: None,
+                    shadow: false,
+                    shadow_of: None,
+                    tags: Default::default(),
+                    expires_at: None,
+                };
+                plan.push("rewrite_rule", ChangeAction::Create, key, None, Vec::new(), Operation::CreateRule(create));
+            }
+        }
+    }
+
+    if prune {
+        for rule in current.iter().filter(|r| r.tenant_id.is_none() && !r.shadow && !matched.contains(&r.id)) {
+            let key = rule_key(&rule.pattern, &rule.match_type);
+            plan.push("rewrite_rule", ChangeAction::Delete, key, Some(rule.id), Vec::new(), Operation::DeleteRule(rule.id));
+        }
+    }
+}
+
+fn plan_upstreams(plan: &mut Plan, specs: &[UpstreamSpec], current: &[UpstreamServer], prune: bool) {
+    let mut existing: HashMap<&str, &UpstreamServer> = HashMap::new();
+    for server in current {
+        existing.entry(server.name.as_str()).or_insert(server);
+    }
+
+    let mut matched = HashSet::new();
+    for spec in specs {
+        let protocol = spec.protocol.to_lowercase();
+        match existing.get(spec.name.as_str()) {
+            Some(server) => {
+                matched.insert(server.id);
+                let mut fields = Vec::new();
+                let update = UpdateUpstreamServer {
+                    address: diff_field(&mut fields, "address", &server.address, &spec.address),
+                    protocol: diff_field(&mut fields, "protocol", &server.protocol, &protocol),
+                    timeout: diff_field(&mut fields, "timeout", &server.timeout, &spec.timeout),
+                    enabled: diff_field(&mut fields, "enabled", &server.enabled, &spec.enabled),
+                    ..Default::default()
+                };
+                if fields.is_empty() {
+                    plan.unchanged += 1;
+                } else {
+                    plan.push("upstream", ChangeAction::Update, spec.name.clone(), Some(server.id), fields, Operation::UpdateUpstream(server.id, update));
+                }
+            }
+            None => {
+                let create = CreateUpstreamServer {
+                    name: spec.name.clone(),
+                    address: spec.address.clone(),
+                    protocol,
+                    timeout: spec.timeout,
+                    enabled: spec.enabled,
+                    source_ip: None,
+                    source_interface: None,
+                    tls_server_name: None,
+                    verify_hostname: None,
+                    doh_method: None,
+                    doh_http_version: None,
+                };
+                plan.push("upstream", ChangeAction::Create, spec.name.clone(), None, Vec::new(), Operation::CreateUpstream(create));
+            }
+        }
+    }
+
+    if prune {
+        for server in current.iter().filter(|s| !matched.contains(&s.id)) {
+            plan.push("upstream", ChangeAction::Delete, server.name.clone(), Some(server.id), Vec::new(), Operation::DeleteUpstream(server.id));
+        }
+    }
+}
+
+fn plan_listeners(plan: &mut Plan, specs: &[ListenerSpec], current: &[ServerListener]) {
+    for spec in specs {
+        let protocol = spec.protocol.to_lowercase();
+        let Some(listener) = current.iter().find(|l| l.protocol == protocol) else {
+            continue;
+        };
+
+        let mut fields = Vec::new();
+        let update = UpdateServerListener {
+            enabled: spec.enabled.and_then(|v| diff_field(&mut fields, "enabled", &listener.enabled, &v)),
+            bind_address: spec
+                .bind_address
+                .as_ref()
+                .and_then(|v| diff_field(&mut fields, "bind_address", &listener.bind_address, v)),
+            port: spec.port.and_then(|v| diff_field(&mut fields, "port", &listener.port, &v)),
+            interface: spec
+                .interface
+                .as_ref()
+                .map(|v| Some(v.trim().to_string()).filter(|v| !v.is_empty()))
+                .and_then(|v| diff_field(&mut fields, "interface", &listener.interface, &v))
+                .map(Option::unwrap_or_default),
+            ..Default::default()
+        };
+        if fields.is_empty() {
+            plan.unchanged += 1;
+        } else {
+            plan.push("listener", ChangeAction::Update, protocol.clone(), Some(listener.id), fields, Operation::UpdateListener(protocol, update));
+        }
+    }
+}
+
+/// Diff a document against the current state
+fn build_plan(doc: &ConfigDocument, current: &CurrentState) -> Plan {
+    let mut plan = Plan::default();
+    if let Some(ref records) = doc.records {
+        plan_records(&mut plan, records, &current.records, doc.prune);
+    }
+    if let Some(ref rules) = doc.rewrite_rules {
+        plan_rules(&mut plan, rules, &current.rules, doc.prune);
+    }
+    if let Some(ref upstreams) = doc.upstreams {
+        plan_upstreams(&mut plan, upstreams, &current.upstreams, doc.prune);
+    }
+    if let Some(ref listeners) = doc.listeners {
+        plan_listeners(&mut plan, listeners, &current.listeners);
+    }
+    plan
+}
+
+async fn load_current_state(db: &Database) -> anyhow::Result<CurrentState> {
+    Ok(CurrentState {
+        records: db.dns_records().list().await?,
+        rules: db.rewrite_rules().list().await?,
+        upstreams: db.upstream_servers().list().await?,
+        listeners: db.server_listeners().list().await?,
+        ttl_bounds: ttl_bounds(db).await,
+    })
+}
+
+/// Execute a single planned operation on the apply transaction
+///
+/// Names whose cached answers the operation invalidates are pushed onto
+/// `names`, both before and after the change.
+async fn execute(
+    conn: &mut SqliteConnection,
+    op: Operation,
+    current: &CurrentState,
+    names: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    let existing_record = |id: i64| current.records.iter().find(|r| r.id == id).map(|r| r.name.clone());
+    let existing_rule = |id: i64| {
+        current
+            .rules
+            .iter()
+            .find(|r| r.id == id)
+            .and_then(|r| rule_cache_name(&r.pattern, &r.match_type, r.shadow))
+            .map(String::from)
+    };
+    match op {
+        Operation::CreateRecord(r) => {
+            let record = DnsRecordRepository::create_on(conn, r).await?;
+            names.push(record.name);
+        }
+        Operation::UpdateRecord(id, u) => {
+            let record = DnsRecordRepository::update_on(conn, id, u, None).await?;
+            names.extend(existing_record(id));
+            names.extend(record.map(|r| r.name));
+        }
+        Operation::DeleteRecord(id) => {
+            DnsRecordRepository::delete_on(conn, id).await?;
+            names.extend(existing_record(id));
+        }
+        Operation::CreateRule(r) => {
+            let rule = RewriteRuleRepository::create_on(conn, r).await?;
+            names.extend(rule_cache_name(&rule.pattern, &rule.match_type, rule.shadow).map(String::from));
+        }
+        Operation::UpdateRule(id, u) => {
+            let rule = RewriteRuleRepository::update_on(conn, id, u, None).await?;
+            names.extend(existing_rule(id));
+            if let Some(rule) = rule {
+                names.extend(rule_cache_name(&rule.pattern, &rule.match_type, rule.shadow).map(String::from));
+            }
+        }
+        Operation::DeleteRule(id) => {
+            RewriteRuleRepository::delete_on(conn, id).await?;
+            names.extend(existing_rule(id));
+        }
+        Operation::CreateUpstream(s) => {
+            UpstreamServerRepository::create_on(conn, s).await?;
+        }
+        Operation::UpdateUpstream(id, u) => {
+            UpstreamServerRepository::update_on(conn, id, u, None).await?;
+        }
+        Operation::DeleteUpstream(id) => {
+            UpstreamServerRepository::delete_on(conn, id).await?;
+        }
+        Operation::UpdateListener(protocol, u) => {
+            ServerListenerRepository::update_on(conn, &protocol, u).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Apply a declarative configuration document
+///
+/// POST /api/config/apply[?dry_run=true]
+///
+/// The request body is the YAML document. With `dry_run` the computed diff
+/// is returned without touching the database.
+pub async fn apply_config(
+    State(state): State<ConfigApplyState>,
+    Query(params): Query<ApplyParams>,
+    body: String,
+) -> Result<impl IntoResponse, ApiError> {
+    let doc: ConfigDocument = serde_yaml::from_str(&body).map_err(|e| ApiError {
+        code: "BAD_REQUEST".to_string(),
+        message: format!("Invalid YAML document: {}", e),
+        details: None,
+    })?;
+
+    let current = load_current_state(&state.db).await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to load current configuration: {}", e),
+        details: None,
+    })?;
+
+    if let Err(validation_errors) = validate_document(&doc, &current) {
+        return Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: "Validation failed".to_string(),
+            details: Some(serde_json::to_value(validation_errors).unwrap()),
+        });
+    }
+
+    let plan = build_plan(&doc, &current);
+    let summary = plan.summary();
+
+    if params.dry_run || plan.changes.is_empty() {
+        return Ok(Json(ApplyResponse {
+            dry_run: params.dry_run,
+            applied: false,
+            summary,
+            changes: plan.changes.into_iter().map(|(c, _)| c).collect(),
+        }));
+    }
+
+    // All changes land together or not at all, so a failure part-way never
+    // leaves the database half applied behind the running components
+    let mut tx = state.db.pool().begin().await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to start transaction: {}", e),
+        details: None,
+    })?;
+    let mut changes = Vec::with_capacity(plan.changes.len());
+    let mut listeners_changed = Vec::new();
+    let mut changed_names = Vec::new();
+    for (change, op) in plan.changes {
+        if let Operation::UpdateListener(ref protocol, _) = op {
+            listeners_changed.push(protocol.clone());
+        }
+        if let Err(e) = execute(&mut tx, op, &current, &mut changed_names).await {
+            if let Err(e) = tx.rollback().await {
+                tracing::warn!("Failed to roll back config apply: {}", e);
+            }
+            return Err(ApiError {
+                code: "INTERNAL_ERROR".to_string(),
+                message: format!(
+                    "Failed to apply change to {} '{}', no changes were applied: {}",
+                    change.kind, change.key, e
+                ),
+                details: None,
+            });
+        }
+        changes.push(change);
+    }
+    tx.commit().await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to commit config apply: {}", e),
+        details: None,
+    })?;
+
+    tracing::info!(
+        "Config applied: {} created, {} updated, {} deleted",
+        summary.create,
+        summary.update,
+        summary.delete
+    );
+
+    // Hot reload the affected components
+    if changes.iter().any(|c| c.kind == "record") {
+        reload_local_records(&state.local_records).await;
+    }
+    if changes.iter().any(|c| c.kind == "rewrite_rule") {
+        if let Err(e) = state.rewrite_engine.reload_rules().await {
+            tracing::warn!("Failed to reload rewrite rules: {}", e);
+        }
+    }
+    if !changed_names.is_empty() {
+        state.cache.purge_names(changed_names).await;
+    }
+    if changes.iter().any(|c| c.kind == "upstream") {
+        if let Err(e) = state.upstream_manager.reload_from_db(&state.db).await {
+            tracing::warn!("Failed to reload upstream servers: {}", e);
+        }
+    }
+    for protocol in listeners_changed {
+        match state.db.server_listeners().get_by_protocol(&protocol).await {
+            Ok(Some(l)) if l.enabled => {
+                if let Err(e) = state.listener_manager.start_listener(&protocol).await {
+                    tracing::error!("Failed to start {} listener: {}", protocol, e);
+                }
+            }
+            Ok(Some(_)) => state.listener_manager.stop_listener(&protocol).await,
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to reload {} listener: {}", protocol, e),
+        }
+    }
+
+    Ok(Json(ApplyResponse {
+        dry_run: false,
+        applied: true,
+        summary,
+        changes,
+    }))
+}
+
+/// Build the config-as-code API router
+pub fn config_apply_router(state: ConfigApplyState) -> axum::Router {
+    use axum::routing::post;
+
+    axum::Router::new()
+        .route("/apply", post(apply_config))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn record(id: i64, name: &str, value: &str, ttl: i32) -> DnsRecord {
+        DnsRecord {
+            id,
+            name: name.to_string(),
+            record_type: "A".to_string(),
+            value: value.to_string(),
+            ttl,
+            priority: 0,
+            enabled: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            tenant_id: None,
+            description: None,
+            tags: Default::default(),
+            group_id: None,
+            expires_at: None,
+        }
+    }
+
+    fn parse(yaml: &str) -> ConfigDocument {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_fields() {
+        assert!(serde_yaml::from_str::<ConfigDocument>("recods: []").is_err());
+        let doc = parse("records:\n  - { name: a.com, record_type: A, value: 1.1.1.1 }");
+        assert_eq!(doc.records.unwrap()[0].ttl, 300);
+    }
+
+    #[test]
+    fn test_plan_create_update_unchanged() {
+        let current = CurrentState {
+            records: vec![record(1, "a.com", "1.1.1.1", 300), record(2, "b.com", "2.2.2.2", 300)],
+            ..Default::default()
+        };
+        let doc = parse(
+            "records:\n  - { name: a.com, record_type: A, value: 1.1.1.1 }\n  - { name: B.com, record_type: a, value: 2.2.2.2, ttl: 60 }\n  - { name: c.com, record_type: A, value: 3.3.3.3 }",
+        );
+
+        let plan = build_plan(&doc, &current);
+        let summary = plan.summary();
+        assert_eq!(summary.create, 1);
+        assert_eq!(summary.update, 1);
+        assert_eq!(summary.delete, 0);
+        assert_eq!(summary.unchanged, 1);
+
+        let (update, _) = plan.changes.iter().find(|(c, _)| c.action == ChangeAction::Update).unwrap();
+        assert_eq!(update.id, Some(2));
+        assert_eq!(update.fields[0].field, "ttl");
+    }
+
+    #[test]
+    fn test_plan_prune_skips_tenant_records() {
+        let mut tenant_record = record(2, "t.com", "9.9.9.9", 300);
+        tenant_record.tenant_id = Some(1);
+        let current = CurrentState {
+            records: vec![record(1, "old.com", "1.1.1.1", 300), tenant_record],
+            ..Default::default()
+        };
+
+        let plan = build_plan(&parse("prune: true\nrecords: []"), &current);
+        assert_eq!(plan.changes.len(), 1);
+        assert_eq!(plan.changes[0].0.action, ChangeAction::Delete);
+        assert_eq!(plan.changes[0].0.id, Some(1));
+
+        // Sections not present in the document are not managed
+        let plan = build_plan(&parse("prune: true\nupstreams: []"), &current);
+        assert!(plan.changes.is_empty());
+    }
+
+    #[test]
+    fn test_validate_document() {
+        let current = CurrentState::default();
+        let doc = parse(
+            "records:\n  - { name: a.com, record_type: A, value: not-an-ip }\n  - { name: a.com, record_type: A, value: not-an-ip }\nlisteners:\n  - { protocol: udp, port: 70000 }",
+        );
+        let errors = validate_document(&doc, &current).unwrap_err().errors;
+        let fields: Vec<_> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert!(fields.contains(&"records[0].value"));
+        assert!(fields.contains(&"records[1]"));
+        assert!(fields.contains(&"listeners[0].protocol"));
+        assert!(fields.contains(&"listeners[0].port"));
+    }
+}
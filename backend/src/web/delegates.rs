@@ -0,0 +1,208 @@
+//! Delegated admins module
+//!
+//! Delegated admins log in like the configured admin but may only manage
+//! DNS records whose names lie inside their zones, e.g. the web team editing
+//! `*.web.internal` and nothing else. They reach the records API and the AI
+//! assistant only; managing the accounts themselves is admin-only.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+
+use crate::config::ConfigManager;
+use crate::db::{CreateDelegatedAdmin, Database, UpdateDelegatedAdmin};
+use crate::dns::normalize_name;
+use crate::web::records::validate_name;
+use crate::web::{bad_request, internal_error, not_found, ApiError};
+
+/// Shortest accepted password
+const MIN_PASSWORD_LEN: usize = 8;
+/// Most zones per account
+const MAX_ZONES: usize = 32;
+
+/// Application state for delegated admins API
+#[derive(Clone)]
+pub struct DelegatesState {
+    pub db: Arc<Database>,
+    /// Used to keep delegated usernames apart from the configured admin
+    pub config: Arc<ConfigManager>,
+}
+
+fn validate_username(username: &str) -> Result<(), ApiError> {
+    if username.is_empty() || username.len() > 100 {
+        return Err(bad_request("Username must be between 1 and 100 characters".to_string()));
+    }
+    if !username.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_')) {
+        return Err(bad_request("Username may only contain letters, digits, '.', '-' and '_'".to_string()));
+    }
+    Ok(())
+}
+
+fn validate_password(password: &str) -> Result<(), ApiError> {
+    if password.len() < MIN_PASSWORD_LEN {
+        return Err(bad_request(format!("Password must be at least {} characters", MIN_PASSWORD_LEN)));
+    }
+    Ok(())
+}
+
+/// Normalize, deduplicate and check zones
+fn normalize_zones(zones: Vec<String>) -> Result<Vec<String>, ApiError> {
+    let mut normalized: Vec<String> = Vec::new();
+    for zone in zones {
+        let zone = normalize_name(&zone);
+        if zone.starts_with('*') {
+            return Err(bad_request(format!("Zone '{}' cannot be a wildcard", zone)));
+        }
+        validate_name(&zone).map_err(|e| bad_request(format!("Invalid zone '{}': {}", zone, e)))?;
+        if !normalized.contains(&zone) {
+            normalized.push(zone);
+        }
+    }
+    if normalized.is_empty() || normalized.len() > MAX_ZONES {
+        return Err(bad_request(format!("Provide between 1 and {} zones", MAX_ZONES)));
+    }
+    Ok(normalized)
+}
+
+/// Hash a password off the async workers
+async fn hash_password(password: String) -> Result<String, ApiError> {
+    tokio::task::spawn_blocking(move || bcrypt::hash(password, bcrypt::DEFAULT_COST))
+        .await
+        .map_err(|e| internal_error("Failed to hash password", e))?
+        .map_err(|e| internal_error("Failed to hash password", e))
+}
+
+/// List delegated admins
+///
+/// GET /api/delegates
+pub async fn list_delegates(State(state): State<DelegatesState>) -> Result<impl IntoResponse, ApiError> {
+    let admins = state
+        .db
+        .delegated_admins()
+        .list()
+        .await
+        .map_err(|e| internal_error("Failed to list delegated admins", e))?;
+
+    Ok(Json(serde_json::json!({ "data": admins })))
+}
+
+/// Create a delegated admin
+///
+/// POST /api/delegates
+pub async fn create_delegate(
+    State(state): State<DelegatesState>,
+    Json(request): Json<CreateDelegatedAdmin>,
+) -> Result<impl IntoResponse, ApiError> {
+    let username = request.username.trim().to_string();
+    validate_username(&username)?;
+    validate_password(&request.password)?;
+    let zones = normalize_zones(request.zones)?;
+
+    let repo = state.db.delegated_admins();
+    let taken = username == state.config.get().admin_username
+        || repo
+            .get_by_username(&username)
+            .await
+            .map_err(|e| internal_error("Failed to get delegated admin", e))?
+            .is_some();
+    if taken {
+        return Err(ApiError {
+            code: "CONFLICT".to_string(),
+            message: format!("Username '{}' is already taken", username),
+            details: None,
+        });
+    }
+
+    let password_hash = hash_password(request.password).await?;
+    let admin = repo
+        .create(&username, &password_hash, &zones)
+        .await
+        .map_err(|e| internal_error("Failed to create delegated admin", e))?;
+
+    Ok((StatusCode::CREATED, Json(serde_json::json!({ "data": admin }))))
+}
+
+/// Change a delegated admin's password, zones or enabled flag
+///
+/// PUT /api/delegates/:id
+pub async fn update_delegate(
+    State(state): State<DelegatesState>,
+    Path(id): Path<i64>,
+    Json(request): Json<UpdateDelegatedAdmin>,
+) -> Result<impl IntoResponse, ApiError> {
+    let zones = request.zones.map(normalize_zones).transpose()?;
+    let password_hash = match request.password {
+        Some(password) => {
+            validate_password(&password)?;
+            Some(hash_password(password).await?)
+        }
+        None => None,
+    };
+
+    let admin = state
+        .db
+        .delegated_admins()
+        .update(id, password_hash, zones, request.enabled)
+        .await
+        .map_err(|e| internal_error("Failed to update delegated admin", e))?
+        .ok_or_else(|| not_found("Delegated admin", id))?;
+
+    Ok(Json(serde_json::json!({ "data": admin })))
+}
+
+/// Delete a delegated admin, revoking its tokens
+///
+/// DELETE /api/delegates/:id
+pub async fn delete_delegate(
+    State(state): State<DelegatesState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let deleted = state
+        .db
+        .delegated_admins()
+        .delete(id)
+        .await
+        .map_err(|e| internal_error("Failed to delete delegated admin", e))?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(not_found("Delegated admin", id))
+    }
+}
+
+/// Create delegated admins router
+pub fn delegates_router(state: DelegatesState) -> axum::Router {
+    use axum::routing::{get, put};
+
+    axum::Router::new()
+        .route("/", get(list_delegates).post(create_delegate))
+        .route("/:id", put(update_delegate).delete(delete_delegate))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_zones() {
+        let zones = normalize_zones(vec!["Web.Internal.".to_string(), "web.internal".to_string()]).unwrap();
+        assert_eq!(zones, vec!["web.internal"]);
+        assert!(normalize_zones(vec![]).is_err());
+        assert!(normalize_zones(vec!["*.web.internal".to_string()]).is_err());
+        assert!(normalize_zones(vec!["bad zone".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_validate_username() {
+        assert!(validate_username("web-team").is_ok());
+        assert!(validate_username("").is_err());
+        assert!(validate_username("web team").is_err());
+    }
+}
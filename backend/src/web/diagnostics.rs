@@ -0,0 +1,311 @@
+//! Diagnostics API module
+//!
+//! Live capture of decoded query/response summaries for debugging client
+//! behaviour without shell access, and the clock used for cache expiry and
+//! log retention, which can be moved forward when `DEBUG_TIME_TRAVEL` is set.
+//! Also measures the connection setup cost of each upstream's protocol and
+//! reports the QUIC transport settings and pooled QUIC connections.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Clock;
+use crate::dns::proxy::{
+    measure_handshake, quic_transport, HandshakeTiming, ProxyManager, QuicTransportStats, UpstreamManager,
+    UpstreamQuicConnections, DEFAULT_WARM_SAMPLES, MAX_WARM_SAMPLES,
+};
+use crate::dns::{
+    normalize_name, CaptureFilter, CaptureStop, CapturedQuery, IpCidr, QueryCapture,
+    MAX_CAPTURE_ENTRIES, MAX_CAPTURE_SECS,
+};
+use crate::web::{bad_request, not_found, ApiError};
+
+/// Default capture window in seconds
+const DEFAULT_CAPTURE_SECS: u64 = 10;
+/// Default entry limit
+const DEFAULT_CAPTURE_ENTRIES: usize = 1000;
+/// Largest single clock step (one year)
+const MAX_CLOCK_ADVANCE_SECS: u64 = 366 * 24 * 3600;
+
+/// Application state for diagnostics API
+#[derive(Clone)]
+pub struct DiagnosticsState {
+    pub capture: Arc<QueryCapture>,
+    pub clock: Arc<Clock>,
+    /// Whether the clock may be moved (`DEBUG_TIME_TRAVEL`)
+    pub time_travel: bool,
+    pub upstream_manager: Arc<UpstreamManager>,
+    pub proxy_manager: Arc<ProxyManager>,
+}
+
+/// Capture request
+#[derive(Debug, Deserialize)]
+pub struct CaptureRequest {
+    /// Client address or CIDR network
+    pub client: Option<String>,
+    /// `example.com` (name and subdomains) or `*.example.com` (subdomains only)
+    pub domain: Option<String>,
+    /// Listener protocol: udp, doh, dot or doq
+    pub protocol: Option<String>,
+    /// Capture window, 1-60 seconds (default 10)
+    pub duration_secs: Option<u64>,
+    /// Entry limit, 1-10000 (default 1000)
+    pub max_entries: Option<usize>,
+}
+
+impl CaptureRequest {
+    fn filter(&self) -> Result<CaptureFilter, String> {
+        let client = match self.client.as_deref().map(str::trim) {
+            Some(c) if !c.is_empty() => Some(c.parse::<IpCidr>()?),
+            _ => None,
+        };
+        let domain = match self.domain.as_deref().map(str::trim) {
+            Some(d) if !d.is_empty() => Some(match d.strip_prefix("*.") {
+                Some(rest) => format!("*.{}", normalize_name(rest)),
+                None => normalize_name(d),
+            }),
+            _ => None,
+        };
+        let protocol = self
+            .protocol
+            .as_deref()
+            .map(|p| p.trim().to_ascii_lowercase())
+            .filter(|p| !p.is_empty());
+        Ok(CaptureFilter { client, domain, protocol })
+    }
+}
+
+/// Capture response
+#[derive(Debug, Serialize)]
+pub struct CaptureResponse {
+    pub data: Vec<CapturedQuery>,
+    pub total: usize,
+    pub stopped_by: CaptureStop,
+}
+
+/// Capture matching listener traffic for a time window
+///
+/// POST /api/diagnostics/capture
+///
+/// Holds the request open until the window elapses or the entry limit is
+/// reached, then returns the captured queries.
+pub async fn start_capture(
+    State(state): State<DiagnosticsState>,
+    Json(request): Json<CaptureRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let duration_secs = request.duration_secs.unwrap_or(DEFAULT_CAPTURE_SECS);
+    if !(1..=MAX_CAPTURE_SECS).contains(&duration_secs) {
+        return Err(bad_request(format!(
+            "duration_secs must be between 1 and {}",
+            MAX_CAPTURE_SECS
+        )));
+    }
+    let max_entries = request.max_entries.unwrap_or(DEFAULT_CAPTURE_ENTRIES);
+    if !(1..=MAX_CAPTURE_ENTRIES).contains(&max_entries) {
+        return Err(bad_request(format!(
+            "max_entries must be between 1 and {}",
+            MAX_CAPTURE_ENTRIES
+        )));
+    }
+    let filter = request.filter().map_err(bad_request)?;
+
+    let result = state
+        .capture
+        .capture(filter, Duration::from_secs(duration_secs), max_entries)
+        .await
+        .ok_or_else(|| ApiError {
+            code: "TOO_MANY_REQUESTS".to_string(),
+            message: "Too many captures running, try again later".to_string(),
+            details: None,
+        })?;
+
+    Ok(Json(CaptureResponse {
+        total: result.entries.len(),
+        data: result.entries,
+        stopped_by: result.stopped_by,
+    }))
+}
+
+/// Clock request
+#[derive(Debug, Deserialize)]
+pub struct ClockRequest {
+    /// Seconds to move the clock forward
+    pub advance_secs: Option<u64>,
+    /// Return to real time first
+    #[serde(default)]
+    pub reset: bool,
+}
+
+/// Clock state
+#[derive(Debug, Serialize)]
+pub struct ClockResponse {
+    /// Time the server currently works with
+    pub now: chrono::DateTime<chrono::Utc>,
+    /// Seconds travelled ahead of real time
+    pub offset_secs: u64,
+    pub time_travel: bool,
+}
+
+fn clock_response(state: &DiagnosticsState) -> ClockResponse {
+    ClockResponse {
+        now: state.clock.utc_now(),
+        offset_secs: state.clock.offset().as_secs(),
+        time_travel: state.time_travel,
+    }
+}
+
+/// Get the server clock
+///
+/// GET /api/diagnostics/clock
+pub async fn get_clock(State(state): State<DiagnosticsState>) -> Json<ClockResponse> {
+    Json(clock_response(&state))
+}
+
+/// Move the server clock forward, or back to real time
+///
+/// POST /api/diagnostics/clock
+///
+/// Cached entries expire and retention applies as of the moved clock;
+/// only allowed with `DEBUG_TIME_TRAVEL=true`.
+pub async fn set_clock(
+    State(state): State<DiagnosticsState>,
+    Json(request): Json<ClockRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !state.time_travel {
+        return Err(ApiError {
+            code: "FORBIDDEN".to_string(),
+            message: "Time travel is disabled, set DEBUG_TIME_TRAVEL=true to enable it".to_string(),
+            details: None,
+        });
+    }
+    let advance_secs = request.advance_secs.unwrap_or(0);
+    if advance_secs > MAX_CLOCK_ADVANCE_SECS {
+        return Err(bad_request(format!(
+            "advance_secs must be at most {}",
+            MAX_CLOCK_ADVANCE_SECS
+        )));
+    }
+
+    if request.reset {
+        state.clock.reset();
+    }
+    state.clock.advance(Duration::from_secs(advance_secs));
+    tracing::warn!("Server clock moved to {}s ahead of real time", state.clock.offset().as_secs());
+    Ok(Json(clock_response(&state)))
+}
+
+/// Handshake speedtest request
+#[derive(Debug, Default, Deserialize)]
+pub struct HandshakeRequest {
+    /// Upstreams to measure (default: all)
+    pub upstream_ids: Option<Vec<i64>>,
+    /// Warm queries per upstream, 1-20 (default 5)
+    pub samples: Option<usize>,
+}
+
+/// Handshake speedtest response
+#[derive(Debug, Serialize)]
+pub struct HandshakeResponse {
+    pub data: Vec<HandshakeTiming>,
+}
+
+/// Measure connection setup and query latency of upstreams
+///
+/// POST /api/diagnostics/handshake
+///
+/// Opens a fresh connection to each upstream, next to the pooled ones, and
+/// reports the TCP, TLS or QUIC handshake time, the first query and the
+/// median of the warm queries. Upstreams are measured concurrently.
+pub async fn measure_handshakes(
+    State(state): State<DiagnosticsState>,
+    Json(request): Json<HandshakeRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let samples = request.samples.unwrap_or(DEFAULT_WARM_SAMPLES);
+    if !(1..=MAX_WARM_SAMPLES).contains(&samples) {
+        return Err(bad_request(format!(
+            "samples must be between 1 and {}",
+            MAX_WARM_SAMPLES
+        )));
+    }
+
+    let servers = match request.upstream_ids {
+        Some(ids) => {
+            let mut servers = Vec::with_capacity(ids.len());
+            for id in ids {
+                let server = state
+                    .upstream_manager
+                    .get_server(id)
+                    .await
+                    .ok_or_else(|| not_found("Upstream server", id))?;
+                servers.push(server);
+            }
+            servers
+        }
+        None => state.upstream_manager.get_servers().await,
+    };
+
+    let data = futures::future::join_all(servers.iter().map(|server| measure_handshake(server, samples))).await;
+    Ok(Json(HandshakeResponse { data }))
+}
+
+/// QUIC transport response
+#[derive(Debug, Serialize)]
+pub struct QuicDiagnostics {
+    pub transport: QuicTransportStats,
+    /// Pooled DoQ/DoH3 upstream connections with their path statistics
+    pub upstreams: Vec<UpstreamQuicConnections>,
+}
+
+/// Get the QUIC transport settings and pooled upstream connections
+///
+/// GET /api/diagnostics/quic
+pub async fn get_quic(State(state): State<DiagnosticsState>) -> Json<QuicDiagnostics> {
+    Json(QuicDiagnostics {
+        transport: quic_transport().stats(),
+        upstreams: state.proxy_manager.quic_connections().await,
+    })
+}
+
+/// Build the diagnostics router
+pub fn diagnostics_router(state: DiagnosticsState) -> axum::Router {
+    use axum::routing::{get, post};
+
+    axum::Router::new()
+        .route("/capture", post(start_capture))
+        .route("/clock", get(get_clock).post(set_clock))
+        .route("/handshake", post(measure_handshakes))
+        .route("/quic", get(get_quic))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_request_filter() {
+        let request = CaptureRequest {
+            client: Some("192.168.1.0/24".to_string()),
+            domain: Some("*.Example.COM.".to_string()),
+            protocol: Some("DoH".to_string()),
+            duration_secs: None,
+            max_entries: None,
+        };
+        let filter = request.filter().unwrap();
+        assert!(filter.matches("192.168.1.5", "www.example.com"));
+        assert_eq!(filter.domain.as_deref(), Some("*.example.com"));
+        assert_eq!(filter.protocol.as_deref(), Some("doh"));
+
+        let request = CaptureRequest {
+            client: Some("not-an-ip".to_string()),
+            domain: None,
+            protocol: None,
+            duration_secs: None,
+            max_entries: None,
+        };
+        assert!(request.filter().is_err());
+    }
+}
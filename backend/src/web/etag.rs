@@ -0,0 +1,111 @@
+//! Optimistic concurrency control
+//!
+//! Resources with an `updated_at` timestamp expose it as an `ETag` header on
+//! GET. Clients send it back in `If-Match` on PUT/DELETE; if the resource
+//! changed in the meantime the request fails with 409 Conflict instead of
+//! silently overwriting the other edit.
+//!
+//! `If-Match` is optional unless the `require_if_match` setting is enabled,
+//! in which case writes without it are rejected with 428.
+//!
+//! A matching `If-Match` also makes the write itself conditional on the
+//! version, so of two concurrent writers sending the same tag only the
+//! first succeeds and the other gets 409.
+
+use axum::http::{header, HeaderMap, HeaderValue};
+use chrono::{DateTime, Utc};
+
+use crate::db::Database;
+use crate::web::ApiError;
+
+/// Config key for If-Match strictness
+pub const CONFIG_KEY_REQUIRE_IF_MATCH: &str = "require_if_match";
+
+/// Build the entity tag for a resource version
+pub fn etag_for(id: i64, updated_at: &DateTime<Utc>) -> String {
+    format!("\"{}-{}\"", id, updated_at.timestamp_micros())
+}
+
+/// `ETag` response header for a resource version
+pub fn etag_header(id: i64, updated_at: &DateTime<Utc>) -> [(header::HeaderName, HeaderValue); 1] {
+    let value = HeaderValue::from_str(&etag_for(id, updated_at))
+        .unwrap_or_else(|_| HeaderValue::from_static("\"\""));
+    [(header::ETAG, value)]
+}
+
+/// Whether writes must carry an If-Match header
+pub async fn if_match_required(db: &Database) -> bool {
+    db.system_config()
+        .get(CONFIG_KEY_REQUIRE_IF_MATCH)
+        .await
+        .unwrap_or(None)
+        .is_some_and(|v| v == "true")
+}
+
+/// Check whether an If-Match header value matches the current tag
+///
+/// Accepts `*`, comma-separated lists and weak (`W/`) validators.
+fn if_match_matches(if_match: &str, current: &str) -> bool {
+    if_match.split(',').map(str::trim).any(|tag| {
+        tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == current
+    })
+}
+
+/// 409 for a write whose resource changed since the client read it
+pub fn modified_error(current: Option<String>) -> ApiError {
+    ApiError {
+        code: "CONFLICT".to_string(),
+        message: "Resource has been modified; reload it and retry".to_string(),
+        details: current.map(|etag| serde_json::json!({ "etag": etag })),
+    }
+}
+
+/// Verify the request's If-Match precondition against the current version
+///
+/// Returns the version the write must still find, unless the request has
+/// no If-Match or matches any version with `*`.
+pub async fn check_if_match(
+    db: &Database,
+    headers: &HeaderMap,
+    id: i64,
+    updated_at: &DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, ApiError> {
+    let current = etag_for(id, updated_at);
+
+    match headers.get(header::IF_MATCH).map(|v| v.to_str()) {
+        Some(Ok(if_match)) if if_match_matches(if_match, &current) => {
+            Ok((if_match.trim() != "*").then_some(*updated_at))
+        }
+        Some(_) => Err(modified_error(Some(current))),
+        None if if_match_required(db).await => Err(ApiError {
+            code: "PRECONDITION_REQUIRED".to_string(),
+            message: "If-Match header is required for this request".to_string(),
+            details: Some(serde_json::json!({ "etag": current })),
+        }),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_etag_changes_with_updated_at() {
+        let t1 = Utc::now();
+        let t2 = t1 + chrono::Duration::microseconds(1);
+        assert_eq!(etag_for(1, &t1), etag_for(1, &t1));
+        assert_ne!(etag_for(1, &t1), etag_for(1, &t2));
+        assert_ne!(etag_for(1, &t1), etag_for(2, &t1));
+    }
+
+    #[test]
+    fn test_if_match_matches() {
+        let current = "\"7-100\"";
+        assert!(if_match_matches("\"7-100\"", current));
+        assert!(if_match_matches("W/\"7-100\"", current));
+        assert!(if_match_matches("\"7-99\", \"7-100\"", current));
+        assert!(if_match_matches("*", current));
+        assert!(!if_match_matches("\"7-99\"", current));
+    }
+}
@@ -0,0 +1,230 @@
+//! Webhook API module
+//!
+//! Endpoints meant to be called by automation (CI/CD pipelines) rather than
+//! the admin UI. They are authenticated with scoped API tokens granted
+//! `cache:purge` instead of the admin JWT, and every call is written to an
+//! audit trail.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, Method},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{CreateCachePurgeAudit, Database};
+use crate::dns::CacheManager;
+use crate::web::tokens::scopes_allow;
+use crate::web::{internal_error, ApiError};
+
+/// Application state for webhook API
+#[derive(Clone)]
+pub struct HooksState {
+    pub db: Arc<Database>,
+    pub cache: Arc<CacheManager>,
+    /// Take the client address from reverse proxy headers
+    pub trust_proxy_headers: bool,
+}
+
+/// Maximum number of patterns accepted per purge request
+pub(crate) const MAX_PURGE_PATTERNS: usize = 1000;
+
+/// Cache purge request
+#[derive(Debug, Clone, Deserialize)]
+pub struct PurgeRequest {
+    /// Exact domains or `*.suffix` patterns
+    pub domains: Vec<String>,
+}
+
+/// Cache purge response
+#[derive(Debug, Serialize)]
+pub struct PurgeResponse {
+    pub purged: usize,
+    pub domains: usize,
+}
+
+/// Validate a purge pattern
+pub(crate) fn validate_pattern(pattern: &str) -> Result<(), String> {
+    let name = pattern.strip_prefix("*.").unwrap_or(pattern);
+    if name.is_empty() {
+        return Err("Pattern cannot be empty".to_string());
+    }
+    if name.len() > 255 {
+        return Err(format!("Pattern too long: {}", pattern));
+    }
+    let valid_chars = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == '_');
+    if !valid_chars {
+        return Err(format!("Invalid pattern: {}", pattern));
+    }
+    Ok(())
+}
+
+/// Extract the bearer token from the Authorization or X-Purge-Token header
+fn extract_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-purge-token").and_then(|v| v.to_str().ok()))
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+/// Client IP of a request
+///
+/// The forwarding headers are set by the client unless a reverse proxy
+/// overwrites them, so they are only honoured with `trust_proxy_headers`.
+pub(crate) fn client_ip(headers: &HeaderMap, addr: SocketAddr, trust_proxy_headers: bool) -> String {
+    let forwarded = || {
+        headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .or_else(|| headers.get("x-real-ip").and_then(|v| v.to_str().ok()))
+            .map(|ip| ip.trim().to_string())
+            .filter(|ip| !ip.is_empty())
+    };
+    trust_proxy_headers
+        .then(forwarded)
+        .flatten()
+        .unwrap_or_else(|| addr.ip().to_string())
+}
+
+/// Purge cache entries for a list of domains
+///
+/// POST /api/hooks/cache/purge
+pub async fn purge_cache(
+    State(state): State<HooksState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<PurgeRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let token = extract_token(&headers).ok_or_else(|| ApiError {
+        code: "UNAUTHORIZED".to_string(),
+        message: "Missing purge token".to_string(),
+        details: None,
+    })?;
+
+    let purge_token = state
+        .db
+        .api_tokens()
+        .authenticate(&token)
+        .await
+        .map_err(|e| internal_error("Failed to verify purge token", e))?
+        .ok_or_else(|| ApiError {
+            code: "UNAUTHORIZED".to_string(),
+            message: "Invalid purge token".to_string(),
+            details: None,
+        })?;
+    if !scopes_allow(purge_token.scope_list(), &Method::POST, "/api/hooks/cache/purge") {
+        return Err(ApiError {
+            code: "FORBIDDEN".to_string(),
+            message: format!("API token '{}' lacks the scope for this endpoint", purge_token.name),
+            details: None,
+        });
+    }
+
+    if request.domains.is_empty() || request.domains.len() > MAX_PURGE_PATTERNS {
+        return Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: format!("Provide between 1 and {} domains", MAX_PURGE_PATTERNS),
+            details: None,
+        });
+    }
+    let patterns: Vec<String> = request.domains.iter().map(|d| d.trim().to_string()).collect();
+    for pattern in &patterns {
+        validate_pattern(pattern).map_err(|e| ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: e,
+            details: None,
+        })?;
+    }
+
+    let mut purged = 0;
+    for pattern in &patterns {
+        purged += state.cache.purge_pattern(pattern).await;
+    }
+
+    let ip = client_ip(&headers, addr, state.trust_proxy_headers);
+    tracing::info!(
+        "Cache purge by token '{}' from {}: {} entries for {} patterns",
+        purge_token.name,
+        ip,
+        purged,
+        patterns.len()
+    );
+
+    let audit = CreateCachePurgeAudit {
+        actor: purge_token.name,
+        client_ip: ip,
+        patterns: patterns.clone(),
+        purged: purged as i64,
+    };
+    if let Err(e) = state.db.cache_purge_audit().create(audit).await {
+        tracing::warn!("Failed to write cache purge audit entry: {}", e);
+    }
+
+    Ok(Json(PurgeResponse {
+        purged,
+        domains: patterns.len(),
+    }))
+}
+
+/// Build the webhook API router
+///
+/// Mounted outside the admin auth middleware; each handler authenticates
+/// its own token.
+pub fn hooks_router(state: HooksState) -> axum::Router {
+    use axum::routing::post;
+
+    axum::Router::new()
+        .route("/cache/purge", post(purge_cache))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_pattern() {
+        assert!(validate_pattern("api.svc.internal").is_ok());
+        assert!(validate_pattern("*.svc.internal").is_ok());
+        assert!(validate_pattern("*").is_err());
+        assert!(validate_pattern("*.").is_err());
+        assert!(validate_pattern("a b.com").is_err());
+    }
+
+    #[test]
+    fn test_extract_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(extract_token(&headers), None);
+
+        headers.insert("x-purge-token", "fdp_abc".parse().unwrap());
+        assert_eq!(extract_token(&headers).as_deref(), Some("fdp_abc"));
+
+        headers.insert(header::AUTHORIZATION, "Bearer fdp_xyz".parse().unwrap());
+        assert_eq!(extract_token(&headers).as_deref(), Some("fdp_xyz"));
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let addr: SocketAddr = "192.0.2.7:40000".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        assert_eq!(client_ip(&headers, addr, true), "192.0.2.7");
+
+        headers.insert("x-forwarded-for", "203.0.113.9, 10.0.0.1".parse().unwrap());
+        assert_eq!(client_ip(&headers, addr, false), "192.0.2.7");
+        assert_eq!(client_ip(&headers, addr, true), "203.0.113.9");
+
+        headers.remove("x-forwarded-for");
+        headers.insert("x-real-ip", "203.0.113.10".parse().unwrap());
+        assert_eq!(client_ip(&headers, addr, true), "203.0.113.10");
+    }
+}
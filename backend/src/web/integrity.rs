@@ -0,0 +1,215 @@
+//! Upstream integrity API module
+//!
+//! Configure which domains are compared across upstreams, run an on-demand
+//! comparison and review recorded discrepancies.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{Database, IntegrityAlert};
+use crate::dns::RecordType;
+use crate::services::integrity_monitor::{
+    IntegrityMode, IntegrityMonitor, IntegritySettings, MIN_INTEGRITY_INTERVAL_SECS,
+};
+use crate::web::{bad_request, internal_error, ApiError};
+
+/// Application state for integrity API
+#[derive(Clone)]
+pub struct IntegrityState {
+    pub db: Arc<Database>,
+    pub monitor: Arc<IntegrityMonitor>,
+}
+
+/// Update settings request
+#[derive(Debug, Deserialize)]
+pub struct UpdateIntegritySettingsRequest {
+    pub enabled: Option<bool>,
+    pub domains: Option<Vec<String>>,
+    pub upstreams: Option<Vec<String>>,
+    pub record_types: Option<Vec<String>>,
+    pub interval_secs: Option<u64>,
+    pub mode: Option<IntegrityMode>,
+}
+
+/// On-demand check request
+#[derive(Debug, Deserialize)]
+pub struct CheckRequest {
+    pub domain: String,
+    /// Defaults to A
+    pub record_type: Option<String>,
+}
+
+/// Alerts query parameters
+#[derive(Debug, Deserialize)]
+pub struct AlertsParams {
+    pub limit: Option<i64>,
+}
+
+/// Alerts response
+#[derive(Debug, Serialize)]
+pub struct IntegrityAlertsResponse {
+    pub data: Vec<IntegrityAlert>,
+    pub total: usize,
+}
+
+fn normalize_domains(domains: Vec<String>) -> Vec<String> {
+    let mut domains: Vec<String> = domains
+        .iter()
+        .map(|d| d.trim().trim_end_matches('.').to_lowercase())
+        .filter(|d| !d.is_empty())
+        .collect();
+    domains.sort();
+    domains.dedup();
+    domains
+}
+
+fn parse_record_type(s: &str) -> Result<RecordType, ApiError> {
+    s.parse::<RecordType>()
+        .map_err(|_| bad_request(format!("Invalid record type: {}", s)))
+}
+
+/// Get integrity monitor settings
+///
+/// GET /api/integrity/settings
+pub async fn get_settings(
+    State(state): State<IntegrityState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let settings = state
+        .monitor
+        .settings()
+        .await
+        .map_err(|e| internal_error("Failed to load integrity settings", e))?;
+
+    Ok(Json(settings))
+}
+
+/// Update integrity monitor settings
+///
+/// PUT /api/integrity/settings
+pub async fn update_settings(
+    State(state): State<IntegrityState>,
+    Json(request): Json<UpdateIntegritySettingsRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    if let Some(secs) = request.interval_secs {
+        if secs < MIN_INTEGRITY_INTERVAL_SECS {
+            return Err(bad_request(format!(
+                "interval_secs must be at least {}",
+                MIN_INTEGRITY_INTERVAL_SECS
+            )));
+        }
+    }
+    if let Some(ref types) = request.record_types {
+        if types.is_empty() {
+            return Err(bad_request("record_types must not be empty".to_string()));
+        }
+        for t in types {
+            parse_record_type(t)?;
+        }
+    }
+
+    let current = state
+        .monitor
+        .settings()
+        .await
+        .map_err(|e| internal_error("Failed to load integrity settings", e))?;
+    let settings = IntegritySettings {
+        enabled: request.enabled.unwrap_or(current.enabled),
+        domains: request.domains.map(normalize_domains).unwrap_or(current.domains),
+        upstreams: request.upstreams.unwrap_or(current.upstreams),
+        record_types: request
+            .record_types
+            .map(|t| t.iter().map(|t| t.to_uppercase()).collect())
+            .unwrap_or(current.record_types),
+        interval_secs: request.interval_secs.unwrap_or(current.interval_secs),
+        mode: request.mode.unwrap_or(current.mode),
+    };
+
+    state
+        .monitor
+        .save_settings(&settings)
+        .await
+        .map_err(|e| internal_error("Failed to save integrity settings", e))?;
+
+    Ok(Json(settings))
+}
+
+/// Compare one domain across upstreams now
+///
+/// POST /api/integrity/check
+pub async fn check_domain(
+    State(state): State<IntegrityState>,
+    Json(request): Json<CheckRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let domain = request.domain.trim().trim_end_matches('.').to_lowercase();
+    if domain.is_empty() {
+        return Err(bad_request("Domain is required".to_string()));
+    }
+    let record_type = parse_record_type(request.record_type.as_deref().unwrap_or("A"))?;
+
+    let settings = state
+        .monitor
+        .settings()
+        .await
+        .map_err(|e| internal_error("Failed to load integrity settings", e))?;
+    let check = state
+        .monitor
+        .check(&domain, record_type, &settings)
+        .await
+        .map_err(|e| bad_request(e.to_string()))?;
+
+    Ok(Json(check))
+}
+
+/// List recent discrepancies
+///
+/// GET /api/integrity/alerts
+pub async fn list_alerts(
+    State(state): State<IntegrityState>,
+    Query(params): Query<AlertsParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    let alerts = state
+        .db
+        .integrity_alerts()
+        .list(limit)
+        .await
+        .map_err(|e| internal_error("Failed to list integrity alerts", e))?;
+
+    Ok(Json(IntegrityAlertsResponse {
+        total: alerts.len(),
+        data: alerts,
+    }))
+}
+
+/// Delete all discrepancies
+///
+/// DELETE /api/integrity/alerts
+pub async fn clear_alerts(
+    State(state): State<IntegrityState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let deleted = state
+        .db
+        .integrity_alerts()
+        .delete_all()
+        .await
+        .map_err(|e| internal_error("Failed to delete integrity alerts", e))?;
+
+    Ok(Json(serde_json::json!({ "deleted_count": deleted })))
+}
+
+/// Build the integrity API router
+pub fn integrity_router(state: IntegrityState) -> axum::Router {
+    use axum::routing::{get, post};
+
+    axum::Router::new()
+        .route("/settings", get(get_settings).put(update_settings))
+        .route("/check", post(check_domain))
+        .route("/alerts", get(list_alerts).delete(clear_alerts))
+        .with_state(state)
+}
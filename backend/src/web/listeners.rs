@@ -14,15 +14,21 @@ use axum::{
 use serde::{Deserialize, Serialize};
 
 use crate::db::{Database, ServerListener, UpdateServerListener};
+use crate::dns::{
+    interface_binding_supported, validate_interface, ListenerResolutionModes, ProfileRouter, ResolutionMode, RrlStatus,
+    MAX_RRL_RESPONSES_PER_SECOND, MAX_RRL_SLIP,
+};
 use super::ApiError;
 
-use crate::services::listener_manager::ListenerManager;
+use crate::services::listener_manager::{rrl_config, ListenerManager};
 
 /// Listeners API state
 #[derive(Clone)]
 pub struct ListenersState {
     pub db: Arc<Database>,
     pub listener_manager: Arc<ListenerManager>,
+    pub profiles: Arc<ProfileRouter>,
+    pub resolution_modes: Arc<ListenerResolutionModes>,
 }
 
 /// Listener response
@@ -38,6 +44,28 @@ pub struct ListenerResponse {
     pub description: String,
     pub tls_cert: Option<String>,
     pub tls_key: Option<String>,
+    pub interface: Option<String>,
+    /// Resolution profile used for queries on this listener
+    pub profile_id: Option<i64>,
+    /// Response rate limit: responses per second per client network, 0 = off
+    pub rrl_responses_per_second: i32,
+    /// Every n-th rate-limited response is sent truncated
+    pub rrl_slip: i32,
+    /// recursive, authoritative_only or cache_only
+    pub resolution_mode: String,
+    /// Rate limiter counters (UDP listener only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RrlStatus>,
+}
+
+impl ListenerResponse {
+    /// Add the rate limiter counters of the UDP listener
+    fn with_rate_limit(mut self, manager: &ListenerManager) -> Self {
+        if self.protocol == "udp" {
+            self.rate_limit = Some(manager.response_rate_limiter().status());
+        }
+        self
+    }
 }
 
 impl From<ServerListener> for ListenerResponse {
@@ -62,6 +90,12 @@ impl From<ServerListener> for ListenerResponse {
             description,
             tls_cert: l.tls_cert,
             tls_key: l.tls_key,
+            interface: l.interface,
+            profile_id: l.profile_id,
+            rrl_responses_per_second: l.rrl_responses_per_second,
+            rrl_slip: l.rrl_slip,
+            resolution_mode: l.resolution_mode,
+            rate_limit: None,
         }
     }
 }
@@ -70,6 +104,8 @@ impl From<ServerListener> for ListenerResponse {
 #[derive(Debug, Serialize)]
 pub struct ListListenersResponse {
     pub data: Vec<ListenerResponse>,
+    /// Whether listeners can be bound to a network interface on this host
+    pub interface_binding_supported: bool,
 }
 
 /// Update listener request
@@ -80,6 +116,17 @@ pub struct UpdateListenerRequest {
     pub port: Option<i32>,
     pub tls_cert: Option<String>,
     pub tls_key: Option<String>,
+    /// Network interface to bind to; empty string removes the binding
+    pub interface: Option<String>,
+    /// Resolution profile for queries on this listener; 0 removes the mapping
+    pub profile_id: Option<i64>,
+    /// Response rate limit (UDP only): responses per second, 0 turns it off
+    pub rrl_responses_per_second: Option<i32>,
+    /// Every n-th rate-limited response is sent truncated, 0 drops them all
+    pub rrl_slip: Option<i32>,
+    /// recursive, authoritative_only (local data only, RA=0, REFUSED
+    /// otherwise) or cache_only (REFUSED on cache misses)
+    pub resolution_mode: Option<String>,
 }
 
 /// Certificate information response
@@ -113,9 +160,15 @@ async fn list_listeners(
         details: None,
     })?;
 
-    let response: Vec<ListenerResponse> = listeners.into_iter().map(|l| l.into()).collect();
+    let response: Vec<ListenerResponse> = listeners
+        .into_iter()
+        .map(|l| ListenerResponse::from(l).with_rate_limit(&state.listener_manager))
+        .collect();
 
-    Ok(Json(ListListenersResponse { data: response }))
+    Ok(Json(ListListenersResponse {
+        data: response,
+        interface_binding_supported: interface_binding_supported(),
+    }))
 }
 
 /// Get a specific listener by protocol
@@ -130,7 +183,7 @@ async fn get_listener(
     })?;
 
     match listener {
-        Some(l) => Ok(Json(ListenerResponse::from(l))),
+        Some(l) => Ok(Json(ListenerResponse::from(l).with_rate_limit(&state.listener_manager))),
         None => Err(ApiError {
             code: "NOT_FOUND".to_string(),
             message: format!("Listener '{}' not found", protocol),
@@ -178,6 +231,79 @@ async fn update_listener(
         }
     }
 
+    // Validate interface binding if provided
+    let interface = request.interface.map(|s| s.trim().to_string());
+    if let Some(ref iface) = interface {
+        if !iface.is_empty() {
+            if let Err(e) = validate_interface(iface) {
+                return Err(ApiError {
+                    code: "VALIDATION_ERROR".to_string(),
+                    message: format!("网络接口无效: {}", e),
+                    details: None,
+                });
+            }
+        }
+    }
+
+    // Validate the resolution profile if provided
+    if let Some(profile_id) = request.profile_id {
+        if profile_id != 0 && !state.profiles.profiles().iter().any(|p| p.id == profile_id) {
+            return Err(ApiError {
+                code: "VALIDATION_ERROR".to_string(),
+                message: format!("解析配置 {} 不存在", profile_id),
+                details: None,
+            });
+        }
+    }
+
+    // Validate response rate limiting, which only the UDP listener applies
+    if request.rrl_responses_per_second.is_some() || request.rrl_slip.is_some() {
+        if protocol != "udp" {
+            return Err(ApiError {
+                code: "VALIDATION_ERROR".to_string(),
+                message: "响应限速仅适用于 UDP 监听器".to_string(),
+                details: None,
+            });
+        }
+        if let Some(rate) = request.rrl_responses_per_second {
+            if rate < 0 || rate as u32 > MAX_RRL_RESPONSES_PER_SECOND {
+                return Err(ApiError {
+                    code: "VALIDATION_ERROR".to_string(),
+                    message: format!("每秒响应数必须在 0-{} 之间", MAX_RRL_RESPONSES_PER_SECOND),
+                    details: None,
+                });
+            }
+        }
+        if let Some(slip) = request.rrl_slip {
+            if slip < 0 || slip as u32 > MAX_RRL_SLIP {
+                return Err(ApiError {
+                    code: "VALIDATION_ERROR".to_string(),
+                    message: format!("slip 必须在 0-{} 之间", MAX_RRL_SLIP),
+                    details: None,
+                });
+            }
+        }
+    }
+
+    // Validate the resolution mode if provided
+    let resolution_mode = match request.resolution_mode.as_deref() {
+        Some(mode) => Some(ResolutionMode::from_str(mode).ok_or_else(|| ApiError {
+            code: "VALIDATION_ERROR".to_string(),
+            message: "解析模式必须是 recursive、authoritative_only 或 cache_only".to_string(),
+            details: None,
+        })?),
+        None => None,
+    };
+
+    // Profile, rate limit and resolution mode changes apply without
+    // restarting the listener
+    let profile_only = request.enabled.is_none()
+        && request.bind_address.is_none()
+        && request.port.is_none()
+        && request.tls_cert.is_none()
+        && request.tls_key.is_none()
+        && interface.is_none();
+
     let update = UpdateServerListener {
         enabled: request.enabled,
         bind_address: request.bind_address,
@@ -185,6 +311,11 @@ async fn update_listener(
         // Don't flatten/filter empty strings here. Passes Some("") to repository to indicate truncation.
         tls_cert: request.tls_cert.map(|s| s.trim().to_string()),
         tls_key: request.tls_key.map(|s| s.trim().to_string()),
+        interface,
+        profile_id: request.profile_id,
+        rrl_responses_per_second: request.rrl_responses_per_second,
+        rrl_slip: request.rrl_slip,
+        resolution_mode: resolution_mode.map(|m| m.as_str().to_string()),
     };
 
     let listener = state.db.server_listeners().update(&protocol, update).await.map_err(|e| ApiError {
@@ -193,7 +324,26 @@ async fn update_listener(
         details: None,
     })?;
 
+    if request.profile_id.is_some() {
+        if let Err(e) = state.profiles.reload().await {
+            tracing::warn!("Failed to reload resolution profiles: {}", e);
+        }
+    }
+
+    if resolution_mode.is_some() {
+        if let Err(e) = state.resolution_modes.load().await {
+            tracing::warn!("Failed to reload listener resolution modes: {}", e);
+        }
+    }
+
+    if let Some(ref l) = listener {
+        if l.protocol == "udp" {
+            state.listener_manager.response_rate_limiter().configure(rrl_config(l));
+        }
+    }
+
     match listener {
+        Some(l) if profile_only => Ok((StatusCode::OK, Json(ListenerResponse::from(l).with_rate_limit(&state.listener_manager)))),
         Some(l) => {
             // Manage lifecycle via ListenerManager
             if l.enabled {
@@ -226,7 +376,7 @@ async fn update_listener(
                 );
             }
             tracing::info!("Listener {} updated: enabled={}, port={}", protocol, l.enabled, l.port);
-            Ok((StatusCode::OK, Json(ListenerResponse::from(l))))
+            Ok((StatusCode::OK, Json(ListenerResponse::from(l).with_rate_limit(&state.listener_manager))))
         }
         None => Err(ApiError {
             code: "NOT_FOUND".to_string(),
@@ -6,7 +6,7 @@ use axum::{
     http::header,
     response::Response,
     routing::{delete, get, patch, post, put},
-    Json, Router,
+    Extension, Json, Router,
 };
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
@@ -18,8 +18,9 @@ use crate::llm::{
     types::{get_provider_presets, ChatCompletionChunk, ChatMessage, ProviderPreset, Role, StreamEvent},
     FunctionRegistry, LlmClient,
 };
+use crate::i18n;
 use crate::state::AppState;
-use crate::web::auth::ApiError;
+use crate::web::auth::{ApiError, DomainScope};
 
 /// LLM API State
 #[derive(Clone)]
@@ -269,6 +270,7 @@ pub struct ChatResponse {
 /// Send a chat message
 async fn chat(
     State(state): State<LlmState>,
+    domains: Option<Extension<DomainScope>>,
     Json(req): Json<ChatRequest>,
 ) -> Result<Json<ChatResponse>, ApiError> {
     // Get enabled config
@@ -294,15 +296,16 @@ async fn chat(
         None => return Err(bad_request("未配置 LLM，请先在设置中配置")),
     };
 
-    let registry = Arc::new(FunctionRegistry::new(state.app_state.clone()));
-    let client = LlmClient::new(config, registry);
+    let registry = FunctionRegistry::new(state.app_state.clone()).with_domains(domains.map(|Extension(d)| d));
+    let client = LlmClient::new(config, Arc::new(registry));
 
     // Build messages with optional context
     let mut messages = vec![
         ChatMessage {
             role: Role::System,
             content: Some(format!(
-                "你是 FluxDNS 的 AI 助手，帮助用户管理 DNS 服务。{}",
+                "{}{}",
+                i18n::localize("你是 FluxDNS 的 AI 助手，帮助用户管理 DNS 服务。", i18n::current()),
                 req.context.as_deref().unwrap_or("")
             )),
             name: None,
@@ -330,8 +333,12 @@ async fn chat(
 }
 
 /// Streaming chat endpoint using SSE
+///
+/// Delegated admins only get record functions limited to their zones, and
+/// their chats are not saved to sessions.
 async fn chat_stream(
     State(state): State<LlmState>,
+    domains: Option<Extension<DomainScope>>,
     Json(req): Json<ChatRequest>,
 ) -> Response {
     // Quick check if LLM is configured
@@ -348,7 +355,10 @@ async fn chat_stream(
         return Response::builder()
             .status(400)
             .header(header::CONTENT_TYPE, "text/event-stream")
-            .body(Body::from("data: {\"type\":\"error\",\"message\":\"未配置 LLM\"}\n\n"))
+            .body(Body::from(format!(
+                "data: {}\n\n",
+                serde_json::json!({"type": "error", "message": i18n::localize("未配置 LLM", i18n::current())})
+            )))
             .unwrap();
     }
 
@@ -357,7 +367,8 @@ async fn chat_stream(
         ChatMessage {
             role: Role::System,
             content: Some(format!(
-                "你是 FluxDNS 的 AI 助手，帮助用户管理 DNS 服务。{}",
+                "{}{}",
+                i18n::localize("你是 FluxDNS 的 AI 助手，帮助用户管理 DNS 服务。", i18n::current()),
                 req.context.as_deref().unwrap_or("")
             )),
             name: None,
@@ -381,13 +392,16 @@ async fn chat_stream(
     // Clone what we need for the async task
     let app_state = state.app_state.clone();
     let initial_messages = messages;
-    let session_id = req.session_id.clone();
+    let domains = domains.map(|Extension(d)| d);
+    let session_id = req.session_id.clone().filter(|_| domains.is_none());
     let user_message = req.message;
 
-    // Spawn a task to process the streaming response with tool call loop
-    tokio::spawn(async move {
+    // Spawn a task to process the streaming response with tool call loop,
+    // keeping the request language for tool output
+    let lang = i18n::current();
+    tokio::spawn(i18n::scope(lang, async move {
         let mut current_messages = initial_messages;
-        let registry = FunctionRegistry::new(app_state.clone());
+        let registry = FunctionRegistry::new(app_state.clone()).with_domains(domains.clone());
         
         // Save user message to database if session_id provided
         if let Some(ref sid) = session_id {
@@ -435,7 +449,8 @@ async fn chat_stream(
                         return;
                     }
                 };
-                LlmClient::new(config, Arc::new(FunctionRegistry::new(app_state.clone())))
+                let registry = FunctionRegistry::new(app_state.clone()).with_domains(domains.clone());
+                LlmClient::new(config, Arc::new(registry))
             };
 
             let llm_response = match client.send_stream_request(current_messages.clone()).await {
@@ -603,7 +618,7 @@ async fn chat_stream(
         let done_event = StreamEvent::Done;
         let json = serde_json::to_string(&done_event).unwrap_or_default();
         let _ = tx.send(Ok(format!("data: {}\n\n", json))).await;
-    });
+    }));
 
     // Create SSE response stream
     let stream = ReceiverStream::new(rx);
@@ -621,8 +636,9 @@ async fn chat_stream(
 /// Get all available tools (functions)
 async fn get_tools(
     State(state): State<LlmState>,
+    domains: Option<Extension<DomainScope>>,
 ) -> Result<Json<Vec<crate::llm::types::FunctionDefinition>>, ApiError> {
-    let registry = FunctionRegistry::new(state.app_state.clone());
+    let registry = FunctionRegistry::new(state.app_state.clone()).with_domains(domains.map(|Extension(d)| d));
     let definitions = registry.get_tool_definitions()
         .into_iter()
         .map(|t| t.function)
@@ -0,0 +1,32 @@
+//! Request language middleware
+//!
+//! Resolves the language of each API request from the `ui_language` setting
+//! and the `Accept-Language` header, runs the handler with it as the current
+//! language (see [`crate::i18n`]) and reports it in `Content-Language`.
+
+use axum::{
+    body::Body,
+    http::{
+        header::{ACCEPT_LANGUAGE, CONTENT_LANGUAGE},
+        HeaderValue, Request,
+    },
+    middleware::Next,
+    response::Response,
+};
+
+use crate::i18n;
+
+/// Run the request in its resolved language
+pub async fn locale_middleware(request: Request<Body>, next: Next) -> Response {
+    let accept_language = request
+        .headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok());
+    let lang = i18n::resolve(accept_language);
+
+    let mut response = i18n::scope(lang, next.run(request)).await;
+    response
+        .headers_mut()
+        .insert(CONTENT_LANGUAGE, HeaderValue::from_static(lang.as_str()));
+    response
+}
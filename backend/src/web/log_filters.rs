@@ -0,0 +1,252 @@
+//! Saved query log filters
+//!
+//! Named filters the UI offers as one-click views, e.g. "blocked clients"
+//! (`{"block_reason": "client"}`) or "slow queries"
+//! (`{"min_response_time": 500}`). Apply one with
+//! `GET /api/logs?filter_id=...`; conditions given in the request take
+//! precedence over the saved ones, so a view can be narrowed to a time range.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+
+use crate::db::{CreateSavedLogFilter, Database, QueryLogFilter, UpdateSavedLogFilter};
+use crate::dns::{normalize_name, BlockReason};
+use crate::web::logs::LogsState;
+use crate::web::{bad_request, internal_error, not_found, ApiError};
+
+fn validate_name(name: &str) -> Result<String, ApiError> {
+    let name = name.trim();
+    if name.is_empty() || name.len() > 100 {
+        return Err(bad_request("Name must be between 1 and 100 characters".to_string()));
+    }
+    Ok(name.to_string())
+}
+
+/// Normalize the conditions of a filter; paging is left to each request
+fn normalize_filter(filter: QueryLogFilter) -> Result<QueryLogFilter, ApiError> {
+    let block_reason = match filter.block_reason.as_deref().map(str::trim) {
+        Some(reason) => Some(
+            BlockReason::from_str(reason)
+                .ok_or_else(|| bad_request(format!("Invalid block reason: {}", reason)))?
+                .as_str()
+                .to_string(),
+        ),
+        None => None,
+    };
+    if filter.min_response_time.is_some_and(|t| t < 0) {
+        return Err(bad_request("min_response_time cannot be negative".to_string()));
+    }
+
+    Ok(QueryLogFilter {
+        query_name: filter.query_name.map(|n| normalize_name(&n)),
+        trace_id: filter.trace_id.map(|t| t.trim().to_ascii_lowercase()),
+        protocol: filter.protocol.map(|p| p.trim().to_ascii_lowercase()),
+        source: filter.source.map(|s| s.trim().to_string()),
+        block_reason,
+        limit: None,
+        offset: None,
+        ..filter
+    })
+}
+
+/// Fill the conditions a request left unset from a saved filter
+pub(crate) async fn with_saved_filter(
+    db: &Database,
+    filter_id: Option<i64>,
+    filter: QueryLogFilter,
+) -> Result<QueryLogFilter, ApiError> {
+    let Some(id) = filter_id else {
+        return Ok(filter);
+    };
+    let saved = db
+        .saved_log_filters()
+        .get_by_id(id)
+        .await
+        .map_err(|e| internal_error("Failed to get saved log filter", e))?
+        .ok_or_else(|| not_found("Saved log filter", id))?;
+    Ok(filter.or(saved.filter))
+}
+
+async fn ensure_name_free(db: &Database, name: &str, id: Option<i64>) -> Result<(), ApiError> {
+    let existing = db
+        .saved_log_filters()
+        .get_by_name(name)
+        .await
+        .map_err(|e| internal_error("Failed to get saved log filter", e))?;
+    if existing.is_some_and(|f| Some(f.id) != id) {
+        return Err(ApiError {
+            code: "CONFLICT".to_string(),
+            message: format!("A saved log filter named '{}' already exists", name),
+            details: None,
+        });
+    }
+    Ok(())
+}
+
+/// List saved log filters
+///
+/// GET /api/logs/filters
+pub async fn list_filters(State(state): State<LogsState>) -> Result<impl IntoResponse, ApiError> {
+    let filters = state
+        .db
+        .saved_log_filters()
+        .list()
+        .await
+        .map_err(|e| internal_error("Failed to list saved log filters", e))?;
+
+    Ok(Json(serde_json::json!({ "data": filters })))
+}
+
+/// Get a saved log filter
+///
+/// GET /api/logs/filters/:id
+pub async fn get_filter(
+    State(state): State<LogsState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let filter = state
+        .db
+        .saved_log_filters()
+        .get_by_id(id)
+        .await
+        .map_err(|e| internal_error("Failed to get saved log filter", e))?
+        .ok_or_else(|| not_found("Saved log filter", id))?;
+
+    Ok(Json(serde_json::json!({ "data": filter })))
+}
+
+/// Save a log filter
+///
+/// POST /api/logs/filters
+pub async fn create_filter(
+    State(state): State<LogsState>,
+    Json(request): Json<CreateSavedLogFilter>,
+) -> Result<impl IntoResponse, ApiError> {
+    let name = validate_name(&request.name)?;
+    let filter = normalize_filter(request.filter)?;
+    ensure_name_free(&state.db, &name, None).await?;
+
+    let saved = state
+        .db
+        .saved_log_filters()
+        .create(CreateSavedLogFilter {
+            name,
+            description: request.description.filter(|d| !d.is_empty()),
+            filter,
+        })
+        .await
+        .map_err(|e| internal_error("Failed to save log filter", e))?;
+
+    Ok((StatusCode::CREATED, Json(serde_json::json!({ "data": saved }))))
+}
+
+/// Rename a saved log filter or change its conditions
+///
+/// PUT /api/logs/filters/:id
+pub async fn update_filter(
+    State(state): State<LogsState>,
+    Path(id): Path<i64>,
+    Json(request): Json<UpdateSavedLogFilter>,
+) -> Result<impl IntoResponse, ApiError> {
+    let name = request.name.as_deref().map(validate_name).transpose()?;
+    if let Some(ref name) = name {
+        ensure_name_free(&state.db, name, Some(id)).await?;
+    }
+    let filter = request.filter.map(normalize_filter).transpose()?;
+
+    let saved = state
+        .db
+        .saved_log_filters()
+        .update(
+            id,
+            UpdateSavedLogFilter {
+                name,
+                description: request.description,
+                filter,
+            },
+        )
+        .await
+        .map_err(|e| internal_error("Failed to update saved log filter", e))?
+        .ok_or_else(|| not_found("Saved log filter", id))?;
+
+    Ok(Json(serde_json::json!({ "data": saved })))
+}
+
+/// Delete a saved log filter
+///
+/// DELETE /api/logs/filters/:id
+pub async fn delete_filter(
+    State(state): State<LogsState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let deleted = state
+        .db
+        .saved_log_filters()
+        .delete(id)
+        .await
+        .map_err(|e| internal_error("Failed to delete saved log filter", e))?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(not_found("Saved log filter", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_filter() {
+        let filter = normalize_filter(QueryLogFilter {
+            query_name: Some("Example.COM.".to_string()),
+            protocol: Some(" DoH".to_string()),
+            block_reason: Some("Client".to_string()),
+            min_response_time: Some(500),
+            limit: Some(10),
+            offset: Some(20),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(filter.query_name.as_deref(), Some("example.com"));
+        assert_eq!(filter.protocol.as_deref(), Some("doh"));
+        assert_eq!(filter.block_reason.as_deref(), Some("client"));
+        assert_eq!(filter.min_response_time, Some(500));
+        assert_eq!(filter.limit, None);
+        assert_eq!(filter.offset, None);
+
+        assert!(normalize_filter(QueryLogFilter {
+            block_reason: Some("abuse".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(normalize_filter(QueryLogFilter {
+            min_response_time: Some(-1),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_request_conditions_take_precedence() {
+        let saved = QueryLogFilter {
+            block_reason: Some("domain".to_string()),
+            protocol: Some("udp".to_string()),
+            ..Default::default()
+        };
+        let filter = QueryLogFilter {
+            protocol: Some("doh".to_string()),
+            limit: Some(50),
+            ..Default::default()
+        }
+        .or(saved);
+        assert_eq!(filter.block_reason.as_deref(), Some("domain"));
+        assert_eq!(filter.protocol.as_deref(), Some("doh"));
+        assert_eq!(filter.limit, Some(50));
+    }
+}
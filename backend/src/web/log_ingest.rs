@@ -0,0 +1,277 @@
+//! Query log ingestion API
+//!
+//! Lets external resolvers (e.g. edge dnsmasq instances) push their query
+//! logs into FluxDNS, so all DNS analytics live in one place. Each batch
+//! carries a source tag that is stored with the entries and can be used to
+//! filter the query log (`GET /api/logs?source=...`). A batch is validated
+//! as a whole and written in one transaction; API tokens need the
+//! `logs:ingest` scope.
+
+use std::net::IpAddr;
+
+use axum::{extract::State, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::db::CreateQueryLog;
+use crate::dns::{normalize_name, DnsResolver};
+use crate::web::logs::LogsState;
+use crate::web::ApiError;
+
+/// Most entries accepted in one batch
+pub const MAX_INGEST_ENTRIES: usize = 5000;
+/// Longest accepted source tag
+const MAX_SOURCE_LEN: usize = 64;
+/// Longest accepted response time (ten minutes)
+const MAX_RESPONSE_TIME_MS: i32 = 600_000;
+/// How far entry timestamps may lie in the future (clock skew)
+const MAX_CLOCK_SKEW_SECS: i64 = 300;
+
+/// A batch of query log entries from one external resolver
+#[derive(Debug, Deserialize)]
+pub struct IngestRequest {
+    /// Name of the sending resolver, e.g. "edge-dnsmasq-1"
+    pub source: String,
+    pub entries: Vec<IngestEntry>,
+}
+
+/// One query as seen by the external resolver
+#[derive(Debug, Deserialize)]
+pub struct IngestEntry {
+    pub client_ip: String,
+    pub query_name: String,
+    /// Record type, e.g. A, AAAA or TYPE65
+    pub query_type: String,
+    /// Response code, e.g. NOERROR or NXDOMAIN
+    pub response_code: Option<String>,
+    /// Response time in milliseconds
+    pub response_time: Option<i32>,
+    #[serde(default)]
+    pub cache_hit: bool,
+    /// Upstream the external resolver forwarded to
+    pub upstream_used: Option<String>,
+    /// When the query was made (default: now)
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// Ingestion response
+#[derive(Debug, Serialize)]
+pub struct IngestResponse {
+    pub source: String,
+    pub accepted: u64,
+}
+
+/// Validation error details
+#[derive(Debug, Serialize)]
+pub struct ValidationErrors {
+    pub errors: Vec<ValidationError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidationError {
+    pub field: String,
+    pub message: String,
+}
+
+fn validate_source(source: &str) -> Result<String, String> {
+    let source = source.trim();
+    if source.is_empty() || source.len() > MAX_SOURCE_LEN {
+        return Err(format!("Source must be between 1 and {} characters", MAX_SOURCE_LEN));
+    }
+    if !source.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_')) {
+        return Err("Source may only contain letters, digits, '.', '-' and '_'".to_string());
+    }
+    Ok(source.to_string())
+}
+
+/// Validate an entry, returning the log row or (field, message) pairs
+fn validate_entry(
+    entry: IngestEntry,
+    source: &str,
+    now: DateTime<Utc>,
+) -> Result<CreateQueryLog, Vec<(&'static str, String)>> {
+    let mut errors = Vec::new();
+
+    let client_ip = entry.client_ip.trim();
+    if client_ip.parse::<IpAddr>().is_err() {
+        errors.push(("client_ip", "Invalid IP address".to_string()));
+    }
+    let query_name = normalize_name(&entry.query_name);
+    if !DnsResolver::is_valid_domain(&query_name) {
+        errors.push(("query_name", "Invalid domain name".to_string()));
+    }
+    let query_type = entry.query_type.trim().to_ascii_uppercase();
+    if query_type.is_empty() || query_type.len() > 10 || !query_type.chars().all(|c| c.is_ascii_alphanumeric()) {
+        errors.push(("query_type", "Invalid record type".to_string()));
+    }
+    let response_code = entry.response_code.map(|c| c.trim().to_ascii_uppercase()).filter(|c| !c.is_empty());
+    if response_code
+        .as_ref()
+        .is_some_and(|c| c.len() > 16 || !c.chars().all(|c| c.is_ascii_alphanumeric()))
+    {
+        errors.push(("response_code", "Invalid response code".to_string()));
+    }
+    if entry.response_time.is_some_and(|t| !(0..=MAX_RESPONSE_TIME_MS).contains(&t)) {
+        errors.push(("response_time", format!("Response time must be between 0 and {} ms", MAX_RESPONSE_TIME_MS)));
+    }
+    let upstream_used = entry.upstream_used.map(|u| u.trim().to_string()).filter(|u| !u.is_empty());
+    if upstream_used.as_ref().is_some_and(|u| u.len() > 255) {
+        errors.push(("upstream_used", "Upstream must be at most 255 characters".to_string()));
+    }
+    if entry
+        .timestamp
+        .is_some_and(|t| (t - now).num_seconds() > MAX_CLOCK_SKEW_SECS)
+    {
+        errors.push(("timestamp", "Timestamp lies in the future".to_string()));
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(CreateQueryLog {
+        client_ip: client_ip.to_string(),
+        query_name,
+        query_type,
+        response_code,
+        response_time: entry.response_time,
+        cache_hit: entry.cache_hit,
+        upstream_used,
+        tenant_id: None,
+        category: None,
+        answered_by: None,
+        trace_id: None,
+        sample_rate: 1,
+        protocol: None,
+        extended_error: None,
+        source: Some(source.to_string()),
+        block_reason: None,
+        created_at: Some(entry.timestamp.unwrap_or(now)),
+    })
+}
+
+/// Validate a batch, returning the rows to write
+fn validate_request(request: IngestRequest, now: DateTime<Utc>) -> Result<(String, Vec<CreateQueryLog>), ValidationErrors> {
+    let mut errors = Vec::new();
+    let source = validate_source(&request.source).unwrap_or_else(|message| {
+        errors.push(ValidationError {
+            field: "source".to_string(),
+            message,
+        });
+        String::new()
+    });
+    if request.entries.is_empty() || request.entries.len() > MAX_INGEST_ENTRIES {
+        errors.push(ValidationError {
+            field: "entries".to_string(),
+            message: format!("Provide between 1 and {} entries", MAX_INGEST_ENTRIES),
+        });
+    }
+
+    let mut logs = Vec::with_capacity(request.entries.len());
+    for (i, entry) in request.entries.into_iter().enumerate() {
+        match validate_entry(entry, &source, now) {
+            Ok(log) => logs.push(log),
+            Err(entry_errors) => errors.extend(entry_errors.into_iter().map(|(field, message)| ValidationError {
+                field: format!("entries[{}].{}", i, field),
+                message,
+            })),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok((source, logs))
+    } else {
+        Err(ValidationErrors { errors })
+    }
+}
+
+/// Ingest a batch of query log entries
+///
+/// POST /api/logs/ingest
+///
+/// All entries are written or none. While the database is degraded the
+/// batch is rejected, so the sender can retry it later.
+pub async fn ingest_logs(
+    State(state): State<LogsState>,
+    Json(request): Json<IngestRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (source, logs) = validate_request(request, Utc::now()).map_err(|validation_errors| ApiError {
+        code: "BAD_REQUEST".to_string(),
+        message: "Validation failed".to_string(),
+        details: Some(serde_json::to_value(validation_errors).unwrap()),
+    })?;
+
+    let health = state.db.health().clone();
+    if !health.allow_write() {
+        return Err(ApiError {
+            code: "SERVICE_UNAVAILABLE".to_string(),
+            message: "Query logging is paused while database writes fail, try again later".to_string(),
+            details: None,
+        });
+    }
+
+    let accepted = match state.db.query_logs().create_many(logs).await {
+        Ok(accepted) => {
+            health.record_success();
+            accepted
+        }
+        Err(e) => {
+            health.record_failure(&e.to_string());
+            return Err(ApiError {
+                code: "INTERNAL_ERROR".to_string(),
+                message: format!("Failed to save query logs: {}", e),
+                details: None,
+            });
+        }
+    };
+
+    tracing::debug!("Ingested {} query log entries from {}", accepted, source);
+    Ok(Json(IngestResponse { source, accepted }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(client_ip: &str, query_name: &str) -> IngestEntry {
+        IngestEntry {
+            client_ip: client_ip.to_string(),
+            query_name: query_name.to_string(),
+            query_type: "a".to_string(),
+            response_code: Some("noerror".to_string()),
+            response_time: Some(3),
+            cache_hit: true,
+            upstream_used: None,
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_request() {
+        let now = Utc::now();
+        let request = IngestRequest {
+            source: " edge-1 ".to_string(),
+            entries: vec![entry("192.168.1.5", "WWW.Example.COM.")],
+        };
+        let (source, logs) = validate_request(request, now).unwrap();
+        assert_eq!(source, "edge-1");
+        assert_eq!(logs[0].query_name, "www.example.com");
+        assert_eq!(logs[0].query_type, "A");
+        assert_eq!(logs[0].response_code.as_deref(), Some("NOERROR"));
+        assert_eq!(logs[0].source.as_deref(), Some("edge-1"));
+        assert_eq!(logs[0].created_at, Some(now));
+
+        let mut future = entry("10.0.0.1", "example.com");
+        future.timestamp = Some(now + chrono::Duration::hours(1));
+        let request = IngestRequest {
+            source: "edge 1".to_string(),
+            entries: vec![entry("not-an-ip", "example.com"), future],
+        };
+        let fields: Vec<String> = validate_request(request, now)
+            .unwrap_err()
+            .errors
+            .into_iter()
+            .map(|e| e.field)
+            .collect();
+        assert_eq!(fields, vec!["source", "entries[0].client_ip", "entries[1].timestamp"]);
+    }
+}
@@ -11,12 +11,20 @@ use std::sync::Arc;
 use axum::{
     extract::{Query, State},
     response::IntoResponse,
-    Json,
+    Extension, Json,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::db::{Database, PaginatedResult, QueryLog, QueryLogFilter, QueryStats};
-use crate::web::ApiError;
+use crate::db::{
+    Database, LogDimension, PaginatedResult, QueryLog, QueryLogFilter, QueryLogGroup, QueryStats,
+    CONFIG_KEY_ROLLUP_DAILY_RETENTION, CONFIG_KEY_ROLLUP_HOURLY_RETENTION,
+    DEFAULT_ROLLUP_DAILY_RETENTION_DAYS, DEFAULT_ROLLUP_HOURLY_RETENTION_DAYS,
+};
+use crate::dns::{name_to_unicode, normalize_name};
+use crate::web::log_filters::{
+    create_filter, delete_filter, get_filter, list_filters, update_filter, with_saved_filter,
+};
+use crate::web::{ApiError, TenantScope};
 
 /// Application state for logs API
 #[derive(Clone)]
@@ -33,6 +41,20 @@ pub struct LogsQueryParams {
     pub cache_hit: Option<bool>,
     pub start_time: Option<String>,
     pub end_time: Option<String>,
+    pub tenant_id: Option<i64>,
+    pub category: Option<String>,
+    pub trace_id: Option<String>,
+    /// Listener protocol: udp, doh, dot or doq
+    pub protocol: Option<String>,
+    /// External resolver the entries were ingested from
+    pub source: Option<String>,
+    /// Blocked queries only: client (rejected by the client ACL) or domain
+    /// (blocked by a rewrite rule or filter)
+    pub block_reason: Option<String>,
+    /// Only queries taking at least this many milliseconds
+    pub min_response_time: Option<i32>,
+    /// Saved filter filling the conditions not given here
+    pub filter_id: Option<i64>,
     pub limit: Option<i64>,
     pub offset: Option<i64>,
     pub format: Option<String>,
@@ -41,22 +63,47 @@ pub struct LogsQueryParams {
 impl From<LogsQueryParams> for QueryLogFilter {
     fn from(params: LogsQueryParams) -> Self {
         Self {
-            query_name: params.query_name,
+            query_name: params.query_name.map(|n| normalize_name(&n)),
             query_type: params.query_type,
             client_ip: params.client_ip,
             cache_hit: params.cache_hit,
             start_time: params.start_time.and_then(|t| chrono::DateTime::parse_from_rfc3339(&t).ok().map(|dt| dt.with_timezone(&chrono::Utc))),
             end_time: params.end_time.and_then(|t| chrono::DateTime::parse_from_rfc3339(&t).ok().map(|dt| dt.with_timezone(&chrono::Utc))),
+            tenant_id: params.tenant_id,
+            category: params.category,
+            trace_id: params.trace_id.map(|t| t.trim().to_ascii_lowercase()),
+            protocol: params.protocol.map(|p| p.trim().to_ascii_lowercase()),
+            source: params.source.map(|s| s.trim().to_string()),
+            block_reason: params.block_reason.map(|b| b.trim().to_ascii_lowercase()),
+            min_response_time: params.min_response_time,
             limit: params.limit,
             offset: params.offset,
         }
     }
 }
 
+/// A query log entry as returned by the API
+#[derive(Debug, Serialize)]
+pub struct QueryLogView {
+    #[serde(flatten)]
+    pub log: QueryLog,
+    /// Query name with punycode labels shown in Unicode
+    pub display_name: String,
+}
+
+impl From<QueryLog> for QueryLogView {
+    fn from(log: QueryLog) -> Self {
+        Self {
+            display_name: name_to_unicode(&log.query_name),
+            log,
+        }
+    }
+}
+
 /// Paginated logs response
 #[derive(Debug, Serialize)]
 pub struct LogsListResponse {
-    pub data: Vec<QueryLog>,
+    pub data: Vec<QueryLogView>,
     pub total: i64,
     pub limit: i64,
     pub offset: i64,
@@ -67,7 +114,7 @@ impl From<PaginatedResult<QueryLog>> for LogsListResponse {
     fn from(result: PaginatedResult<QueryLog>) -> Self {
         let has_more = result.offset + (result.items.len() as i64) < result.total;
         Self {
-            data: result.items,
+            data: result.items.into_iter().map(QueryLogView::from).collect(),
             total: result.total,
             limit: result.limit,
             offset: result.offset,
@@ -101,15 +148,36 @@ impl From<QueryStats> for QueryStatsResponse {
     }
 }
 
+/// Query parameters for grouped analytics
+#[derive(Debug, Clone, Deserialize)]
+pub struct SummaryParams {
+    pub group_by: String,
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    pub tenant_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Grouped analytics response
+#[derive(Debug, Serialize)]
+pub struct SummaryResponse {
+    pub group_by: LogDimension,
+    pub data: Vec<QueryLogGroup>,
+}
+
 /// List query logs with pagination and filtering
 ///
 /// GET /api/logs
 pub async fn list_logs(
     State(state): State<LogsState>,
+    scope: Option<Extension<TenantScope>>,
     Query(params): Query<LogsQueryParams>,
 ) -> Result<impl IntoResponse, ApiError> {
     let repo = state.db.query_logs();
-    let filter = QueryLogFilter::from(params);
+    let mut filter = with_saved_filter(&state.db, params.filter_id, QueryLogFilter::from(params)).await?;
+    if let Some(Extension(scope)) = scope {
+        filter.tenant_id = Some(scope.tenant_id);
+    }
 
     let result = repo.list(filter).await.map_err(|e| ApiError {
         code: "INTERNAL_ERROR".to_string(),
@@ -137,6 +205,53 @@ pub async fn get_stats(
     Ok(Json(QueryStatsResponse::from(stats)))
 }
 
+/// Top domains, clients, record types, outcomes or listener protocols
+///
+/// Reads raw logs and hourly/daily roll-ups transparently, so ranges older
+/// than the raw log retention are still covered.
+///
+/// GET /api/logs/summary
+pub async fn get_summary(
+    State(state): State<LogsState>,
+    scope: Option<Extension<TenantScope>>,
+    Query(params): Query<SummaryParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let dimension = LogDimension::from_str(&params.group_by).ok_or_else(|| ApiError {
+        code: "BAD_REQUEST".to_string(),
+        message: "group_by must be one of: domain, client, type, outcome, protocol".to_string(),
+        details: None,
+    })?;
+    let parse_time = |t: Option<String>| {
+        t.and_then(|t| chrono::DateTime::parse_from_rfc3339(&t).ok().map(|dt| dt.with_timezone(&chrono::Utc)))
+    };
+    let tenant_id = match scope {
+        Some(Extension(scope)) => Some(scope.tenant_id),
+        None => params.tenant_id,
+    };
+
+    let data = state
+        .db
+        .query_log_rollups()
+        .summary(
+            dimension,
+            parse_time(params.start_time),
+            parse_time(params.end_time),
+            tenant_id,
+            params.limit.unwrap_or(20).clamp(1, 1000),
+        )
+        .await
+        .map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to summarize query logs: {}", e),
+            details: None,
+        })?;
+
+    Ok(Json(SummaryResponse {
+        group_by: dimension,
+        data,
+    }))
+}
+
 /// Delete old query logs
 ///
 /// DELETE /api/logs/cleanup
@@ -202,7 +317,7 @@ pub async fn cleanup_logs_before_date(
     })))
 }
 
-/// Delete all query logs
+/// Delete all query logs, including roll-ups
 ///
 /// DELETE /api/logs/cleanup/all
 pub async fn cleanup_all_logs(
@@ -215,6 +330,11 @@ pub async fn cleanup_all_logs(
         message: format!("Failed to delete all query logs: {}", e),
         details: None,
     })?;
+    state.db.query_log_rollups().delete_all().await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to delete query log roll-ups: {}", e),
+        details: None,
+    })?;
 
     Ok(Json(serde_json::json!({
         "message": format!("Deleted all {} log entries", deleted),
@@ -227,11 +347,15 @@ pub async fn cleanup_all_logs(
 /// GET /api/logs/export
 pub async fn export_logs(
     State(state): State<LogsState>,
+    scope: Option<Extension<TenantScope>>,
     Query(params): Query<LogsQueryParams>,
 ) -> Result<impl IntoResponse, ApiError> {
     let repo = state.db.query_logs();
     // Increase limit for export, or set to a large number
-    let mut filter = QueryLogFilter::from(params.clone());
+    let mut filter = with_saved_filter(&state.db, params.filter_id, QueryLogFilter::from(params.clone())).await?;
+    if let Some(Extension(scope)) = scope {
+        filter.tenant_id = Some(scope.tenant_id);
+    }
     filter.limit = Some(10000); // Limit export to 10k rows for now to prevent OOM
     filter.offset = Some(0);
 
@@ -249,11 +373,11 @@ pub async fn export_logs(
 
     // Default to CSV
     let mut csv = String::new();
-    csv.push_str("Time,Client IP,Domain,Type,Response Code,Response Time(ms),Cache Hit,Upstream\n");
+    csv.push_str("Time,Client IP,Domain,Type,Response Code,Response Time(ms),Cache Hit,Upstream,Category,Trace ID,Sample Rate,Protocol,Extended Error,Block Reason\n");
 
     for log in result.items {
         csv.push_str(&format!(
-            "{},{},{},{},{},{},{},{}\n",
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
             log.created_at.to_rfc3339(),
             log.client_ip,
             log.query_name,
@@ -261,7 +385,13 @@ pub async fn export_logs(
             log.response_code.unwrap_or_default(),
             log.response_time.unwrap_or(0),
             log.cache_hit,
-            log.upstream_used.unwrap_or_default()
+            log.upstream_used.unwrap_or_default(),
+            log.category.unwrap_or_default(),
+            log.trace_id.unwrap_or_default(),
+            log.sample_rate,
+            log.protocol.unwrap_or_default(),
+            csv_field(&log.extended_error.unwrap_or_default()),
+            log.block_reason.unwrap_or_default()
         ));
     }
 
@@ -274,6 +404,27 @@ pub async fn export_logs(
     ).into_response())
 }
 
+/// Quote a CSV field that contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Read a day count setting
+async fn get_days(db: &Database, key: &str, default: i64) -> Result<i64, ApiError> {
+    let value = db.system_config().get(key).await
+        .map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to get config: {}", e),
+            details: None,
+        })?;
+
+    Ok(value.and_then(|v| v.parse::<i64>().ok()).unwrap_or(default))
+}
+
 /// Get log retention settings
 ///
 /// GET /api/logs/retention
@@ -300,6 +451,18 @@ pub async fn get_retention_settings(
         .and_then(|v| v.parse::<i64>().ok())
         .unwrap_or(30);
 
+    let hourly_retention_days =
+        get_days(&state.db, CONFIG_KEY_ROLLUP_HOURLY_RETENTION, DEFAULT_ROLLUP_HOURLY_RETENTION_DAYS).await?;
+    let daily_retention_days =
+        get_days(&state.db, CONFIG_KEY_ROLLUP_DAILY_RETENTION, DEFAULT_ROLLUP_DAILY_RETENTION_DAYS).await?;
+
+    let rolled_up_until = state.db.query_log_rollups().rolled_up_until().await
+        .map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to get roll-up progress: {}", e),
+            details: None,
+        })?;
+
     let oldest_date = state.db.query_logs().get_oldest_date().await
         .map_err(|e| ApiError {
             code: "INTERNAL_ERROR".to_string(),
@@ -310,6 +473,9 @@ pub async fn get_retention_settings(
     Ok(Json(serde_json::json!({
         "auto_cleanup_enabled": auto_cleanup_enabled,
         "retention_days": retention_days,
+        "hourly_retention_days": hourly_retention_days,
+        "daily_retention_days": daily_retention_days,
+        "rolled_up_until": rolled_up_until,
         "oldest_log_date": oldest_date
     })))
 }
@@ -321,6 +487,10 @@ pub async fn get_retention_settings(
 pub struct UpdateRetentionParams {
     pub auto_cleanup_enabled: Option<bool>,
     pub retention_days: Option<i64>,
+    /// Days to keep hourly roll-ups
+    pub hourly_retention_days: Option<i64>,
+    /// Days to keep daily roll-ups
+    pub daily_retention_days: Option<i64>,
 }
 
 pub async fn update_retention_settings(
@@ -354,18 +524,43 @@ pub async fn update_retention_settings(
             })?;
     }
 
+    let rollup_retention = [
+        (CONFIG_KEY_ROLLUP_HOURLY_RETENTION, params.hourly_retention_days),
+        (CONFIG_KEY_ROLLUP_DAILY_RETENTION, params.daily_retention_days),
+    ];
+    for (key, days) in rollup_retention {
+        let Some(days) = days else { continue };
+        if days < 1 {
+            return Err(ApiError {
+                code: "BAD_REQUEST".to_string(),
+                message: "Retention days must be at least 1".to_string(),
+                details: None,
+            });
+        }
+        config.set(key, &days.to_string()).await
+            .map_err(|e| ApiError {
+                code: "INTERNAL_ERROR".to_string(),
+                message: format!("Failed to save config: {}", e),
+                details: None,
+            })?;
+    }
+
     // Return updated settings
     get_retention_settings(State(state)).await
 }
 
 /// Build the logs API router
 pub fn logs_router(state: LogsState) -> axum::Router {
-    use axum::routing::{delete, get, put};
+    use axum::routing::{delete, get, post, put};
 
     axum::Router::new()
         .route("/", get(list_logs))
         .route("/export", get(export_logs))
+        .route("/ingest", post(super::log_ingest::ingest_logs))
         .route("/stats", get(get_stats))
+        .route("/summary", get(get_summary))
+        .route("/filters", get(list_filters).post(create_filter))
+        .route("/filters/:id", get(get_filter).put(update_filter).delete(delete_filter))
         .route("/cleanup", delete(cleanup_logs))
         .route("/cleanup/before", delete(cleanup_logs_before_date))
         .route("/cleanup/all", delete(cleanup_all_logs))
@@ -387,6 +582,14 @@ mod tests {
             cache_hit: Some(true),
             start_time: None,
             end_time: None,
+            tenant_id: None,
+            category: None,
+            trace_id: None,
+            protocol: Some(" DoH".to_string()),
+            source: None,
+            block_reason: Some("Client".to_string()),
+            min_response_time: None,
+            filter_id: None,
             limit: Some(50),
             offset: Some(0),
             format: None,
@@ -395,6 +598,8 @@ mod tests {
         assert_eq!(filter.query_name, Some("example.com".to_string()));
         assert_eq!(filter.query_type, Some("A".to_string()));
         assert_eq!(filter.cache_hit, Some(true));
+        assert_eq!(filter.protocol.as_deref(), Some("doh"));
+        assert_eq!(filter.block_reason.as_deref(), Some("client"));
         assert_eq!(filter.limit, Some(50));
         assert_eq!(filter.offset, Some(0));
     }
@@ -439,6 +644,15 @@ mod tests {
             cache_hit: false,
             upstream_used: None,
             created_at: Utc::now(),
+            tenant_id: None,
+            category: None,
+            answered_by: None,
+            trace_id: None,
+            sample_rate: 1,
+            protocol: None,
+            extended_error: None,
+            source: None,
+            block_reason: None,
         };
 
         // Case 1: 50 items returned, total is 100, so has_more should be true
@@ -461,4 +675,53 @@ mod tests {
         let response = LogsListResponse::from(result);
         assert!(!response.has_more);
     }
+
+    #[test]
+    fn test_log_display_name() {
+        let params = LogsQueryParams {
+            query_name: Some("Bücher.example.".to_string()),
+            query_type: None,
+            client_ip: None,
+            cache_hit: None,
+            start_time: None,
+            end_time: None,
+            tenant_id: None,
+            category: None,
+            trace_id: None,
+            protocol: None,
+            source: None,
+            block_reason: None,
+            min_response_time: None,
+            filter_id: None,
+            limit: None,
+            offset: None,
+            format: None,
+        };
+        let filter = QueryLogFilter::from(params);
+        assert_eq!(filter.query_name.as_deref(), Some("xn--bcher-kva.example"));
+
+        let log = QueryLog {
+            id: 1,
+            query_name: "xn--bcher-kva.example".to_string(),
+            query_type: "A".to_string(),
+            client_ip: "127.0.0.1".to_string(),
+            response_code: None,
+            response_time: None,
+            cache_hit: false,
+            upstream_used: None,
+            created_at: chrono::Utc::now(),
+            tenant_id: None,
+            category: None,
+            answered_by: None,
+            trace_id: None,
+            sample_rate: 1,
+            protocol: None,
+            extended_error: None,
+            source: None,
+            block_reason: None,
+        };
+        let value = serde_json::to_value(QueryLogView::from(log)).unwrap();
+        assert_eq!(value["query_name"], "xn--bcher-kva.example");
+        assert_eq!(value["display_name"], "bücher.example");
+    }
 }
@@ -2,36 +2,115 @@
 //!
 //! Contains the Axum web server and REST API implementations.
 
+pub mod access_log;
+pub mod analytics;
 pub mod auth;
+pub mod backup;
 pub mod cache;
+pub mod categories;
+pub mod config_apply;
+pub mod delegates;
+pub mod diagnostics;
 pub mod dns_query;
+pub mod etag;
+pub mod hooks;
+pub mod integrity;
 pub mod listeners;
 pub mod llm;
+pub mod locale;
+pub mod log_filters;
+pub mod log_ingest;
 pub mod logs;
+pub mod notifications;
+pub mod profiles;
+pub mod public;
 pub mod records;
 pub mod rewrite;
+pub mod rpz;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod server;
+pub mod services;
 pub mod settings;
+pub mod settings_registry;
+pub mod setup;
 pub mod static_files;
 pub mod status;
 pub mod strategy;
+pub mod tenants;
+pub mod tokens;
+pub mod topology;
+pub mod transactions;
+pub mod typosquat;
 pub mod upstreams;
 
 
+pub use access_log::{access_log_middleware, ApiAccessLog};
+pub use analytics::{analytics_router, AnalyticsState};
 pub use auth::{
-    auth_middleware, ApiError, AuthService, AuthState,
+    auth_middleware, ApiError, AuthService, AuthState, DomainScope, TenantScope,
 };
+pub use backup::{backup_router, BackupState};
 pub use cache::{cache_router, CacheState};
+pub use categories::{categories_router, CategoriesState};
+pub use config_apply::{config_apply_router, ConfigApplyState};
+pub use delegates::{delegates_router, DelegatesState};
+pub use diagnostics::{diagnostics_router, DiagnosticsState};
 pub use dns_query::{dns_query_router, DnsQueryState};
+pub use hooks::{hooks_router, HooksState};
+pub use integrity::{integrity_router, IntegrityState};
 pub use listeners::{listeners_router, ListenersState};
+pub use locale::locale_middleware;
 pub use logs::{logs_router, LogsState};
+pub use notifications::{notifications_router, NotificationsState};
+pub use profiles::{profiles_router, ProfilesState};
+pub use public::{public_router, PublicState};
 pub use records::{
     records_router, RecordsState,
 };
 pub use rewrite::{rewrite_router, RewriteState};
+pub use rpz::{rpz_router, RpzState};
+#[cfg(feature = "scripting")]
+pub use scripting::{scripting_router, ScriptingState};
+pub use server::{serve, HttpServerConfig};
+pub use services::{services_router, ServicesState};
 pub use settings::{settings_router, SettingsState};
+pub use setup::{setup_router, SetupState};
 pub use static_files::{fallback_handler, index_handler, static_handler};
-pub use status::{status_router, StatusState};
+pub use status::{readiness_router, status_router, StatusState};
 pub use strategy::{strategy_router, StrategyState};
+pub use tenants::{tenants_router, TenantsState};
+pub use tokens::{tokens_router, TokensState};
+pub use topology::{http_topology, not_found_handler, HttpService};
+pub use transactions::{transactions_router, TransactionsState};
+pub use typosquat::{typosquat_router, TyposquatState};
 pub use upstreams::{upstreams_router, UpstreamsState};
 pub use llm::{llm_router, LlmState};
 
+/// Error for an operation that failed on the server side, e.g. a database
+/// error: `"Failed to list feeds: <cause>"`
+pub(crate) fn internal_error(message: &str, e: impl std::fmt::Display) -> ApiError {
+    ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("{}: {}", message, e),
+        details: None,
+    }
+}
+
+/// Error for a request the caller has to correct
+pub(crate) fn bad_request(message: impl Into<String>) -> ApiError {
+    ApiError {
+        code: "BAD_REQUEST".to_string(),
+        message: message.into(),
+        details: None,
+    }
+}
+
+/// Error for a missing entity: `"Service with id 7 not found"`
+pub(crate) fn not_found(entity: &str, id: i64) -> ApiError {
+    ApiError {
+        code: "NOT_FOUND".to_string(),
+        message: format!("{} with id {} not found", entity, id),
+        details: None,
+    }
+}
@@ -0,0 +1,163 @@
+//! Notifications API module
+//!
+//! Inbox of the events recorded by [`crate::services::notifications`], for
+//! the web UI's bell icon. Messages are localized to the request language.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{Database, Notification, NotificationFilter, NotificationSeverity, PaginatedResult};
+use crate::i18n;
+use crate::web::{bad_request, internal_error, not_found, ApiError};
+
+/// Application state for notifications API
+#[derive(Clone)]
+pub struct NotificationsState {
+    pub db: Arc<Database>,
+}
+
+/// Notification list response
+#[derive(Debug, Serialize)]
+pub struct NotificationsListResponse {
+    pub data: Vec<Notification>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    pub has_more: bool,
+    /// Unread notifications, regardless of the filter
+    pub unread: i64,
+}
+
+impl NotificationsListResponse {
+    fn new(result: PaginatedResult<Notification>, unread: i64) -> Self {
+        let has_more = result.offset + (result.items.len() as i64) < result.total;
+        let lang = i18n::current();
+        Self {
+            data: result
+                .items
+                .into_iter()
+                .map(|n| Notification {
+                    message: i18n::localize(&n.message, lang),
+                    ..n
+                })
+                .collect(),
+            total: result.total,
+            limit: result.limit,
+            offset: result.offset,
+            has_more,
+            unread,
+        }
+    }
+}
+
+/// Mark-read request; without ids every notification is marked read
+#[derive(Debug, Default, Deserialize)]
+pub struct MarkReadRequest {
+    pub ids: Option<Vec<i64>>,
+}
+
+async fn unread_count(db: &Database) -> Result<i64, ApiError> {
+    db.notifications()
+        .unread_count()
+        .await
+        .map_err(|e| internal_error("Failed to count unread notifications", e))
+}
+
+/// List notifications, newest first
+///
+/// GET /api/notifications[?severity=warning][&source=upstream][&unread=true][&limit=50][&offset=0]
+pub async fn list_notifications(
+    State(state): State<NotificationsState>,
+    Query(filter): Query<NotificationFilter>,
+) -> Result<impl IntoResponse, ApiError> {
+    let severity = match filter.severity.as_deref() {
+        Some(s) => Some(
+            NotificationSeverity::from_str(s)
+                .ok_or_else(|| bad_request(format!("Invalid severity: {}", s)))?
+                .as_str()
+                .to_string(),
+        ),
+        None => None,
+    };
+    let filter = NotificationFilter {
+        severity,
+        source: filter.source.map(|s| s.trim().to_lowercase()),
+        ..filter
+    };
+
+    let result = state
+        .db
+        .notifications()
+        .list(filter)
+        .await
+        .map_err(|e| internal_error("Failed to list notifications", e))?;
+    let unread = unread_count(&state.db).await?;
+
+    Ok(Json(NotificationsListResponse::new(result, unread)))
+}
+
+/// Number of unread notifications, for the bell badge
+///
+/// GET /api/notifications/unread-count
+pub async fn get_unread_count(State(state): State<NotificationsState>) -> Result<impl IntoResponse, ApiError> {
+    let unread = unread_count(&state.db).await?;
+    Ok(Json(serde_json::json!({ "data": { "unread": unread } })))
+}
+
+/// Mark notifications as read
+///
+/// POST /api/notifications/read with `{"ids": [1, 2]}`, or `{}` for all
+pub async fn mark_read(
+    State(state): State<NotificationsState>,
+    Json(request): Json<MarkReadRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let marked = state
+        .db
+        .notifications()
+        .mark_read(request.ids.as_deref())
+        .await
+        .map_err(|e| internal_error("Failed to mark notifications as read", e))?;
+    let unread = unread_count(&state.db).await?;
+
+    Ok(Json(serde_json::json!({ "data": { "marked": marked, "unread": unread } })))
+}
+
+/// Delete a notification
+///
+/// DELETE /api/notifications/:id
+pub async fn delete_notification(
+    State(state): State<NotificationsState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let deleted = state
+        .db
+        .notifications()
+        .delete(id)
+        .await
+        .map_err(|e| internal_error("Failed to delete notification", e))?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(not_found("Notification", id))
+    }
+}
+
+/// Build the notifications API router
+pub fn notifications_router(state: NotificationsState) -> axum::Router {
+    use axum::routing::{delete, get, post};
+
+    axum::Router::new()
+        .route("/", get(list_notifications))
+        .route("/unread-count", get(get_unread_count))
+        .route("/read", post(mark_read))
+        .route("/:id", delete(delete_notification))
+        .with_state(state)
+}
@@ -0,0 +1,502 @@
+//! Resolution profiles API module
+//!
+//! Manage split DNS profiles, switch the active profile manually and
+//! configure probe-based automatic switching.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{
+    CreateResolutionProfile, Database, ResolutionProfile, UpdateResolutionProfile,
+};
+use crate::dns::{
+    parse_probe_target, parse_rules, ProbeResult, ProfileRouter, ProfileSettings,
+    MIN_PROBE_INTERVAL_SECS,
+};
+use crate::web::{bad_request, internal_error, not_found, ApiError};
+
+/// Application state for profiles API
+#[derive(Clone)]
+pub struct ProfilesState {
+    pub db: Arc<Database>,
+    pub router: Arc<ProfileRouter>,
+}
+
+/// Validation error details
+#[derive(Debug, Serialize)]
+pub struct ValidationErrors {
+    pub errors: Vec<ValidationError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidationError {
+    pub field: String,
+    pub message: String,
+}
+
+/// API response wrapper for single profile
+#[derive(Debug, Serialize)]
+pub struct ProfileResponse {
+    pub data: ResolutionProfile,
+}
+
+/// API response wrapper for multiple profiles
+#[derive(Debug, Serialize)]
+pub struct ProfilesListResponse {
+    pub data: Vec<ResolutionProfile>,
+    pub total: usize,
+    pub active_profile_id: Option<i64>,
+}
+
+/// Switching status response
+#[derive(Debug, Serialize)]
+pub struct ProfileStatusResponse {
+    pub active_profile_id: Option<i64>,
+    pub active_profile: Option<String>,
+    pub settings: ProfileSettings,
+    pub last_probe: Vec<ProbeResult>,
+}
+
+/// Manual activation request
+#[derive(Debug, Deserialize)]
+pub struct ActivateRequest {
+    /// Profile to activate; `null` deactivates profiles
+    pub id: Option<i64>,
+}
+
+/// Update switching settings request
+#[derive(Debug, Deserialize)]
+pub struct UpdateProfileSettingsRequest {
+    pub auto_switch: Option<bool>,
+    pub probe_interval_secs: Option<u64>,
+}
+
+/// Validate profile name
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Name cannot be empty".to_string());
+    }
+    if name.len() > 100 {
+        return Err("Name cannot exceed 100 characters".to_string());
+    }
+    Ok(())
+}
+
+/// Check that every named upstream is configured
+fn validate_upstream_names<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+    known: &[String],
+) -> Result<(), String> {
+    for name in names {
+        if !known.iter().any(|k| k == name) {
+            return Err(format!("Unknown upstream '{}'", name));
+        }
+    }
+    Ok(())
+}
+
+/// Normalize a comma-separated list (trim entries, drop blanks)
+fn normalize_list(list: &str) -> String {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn validate_fields(
+    name: Option<&str>,
+    upstreams: Option<&str>,
+    rules: Option<&str>,
+    probe_target: Option<&str>,
+    known_upstreams: &[String],
+) -> Result<(), ValidationErrors> {
+    let mut errors = Vec::new();
+    let mut push = |field: &str, message: String| {
+        errors.push(ValidationError {
+            field: field.to_string(),
+            message,
+        })
+    };
+
+    if let Some(name) = name {
+        if let Err(e) = validate_name(name) {
+            push("name", e);
+        }
+    }
+
+    if let Some(upstreams) = upstreams {
+        let names = upstreams.split(',').map(str::trim).filter(|s| !s.is_empty());
+        if let Err(e) = validate_upstream_names(names, known_upstreams) {
+            push("upstreams", e);
+        }
+    }
+
+    if let Some(rules) = rules {
+        match parse_rules(rules) {
+            Ok(rules) => {
+                let names = rules.iter().map(|r| r.upstream.as_str());
+                if let Err(e) = validate_upstream_names(names, known_upstreams) {
+                    push("rules", e);
+                }
+            }
+            Err(e) => push("rules", e.to_string()),
+        }
+    }
+
+    if let Some(target) = probe_target.filter(|t| !t.trim().is_empty()) {
+        if let Err(e) = parse_probe_target(target) {
+            push("probe_target", e.to_string());
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationErrors { errors })
+    }
+}
+
+fn validation_failed(errors: ValidationErrors) -> ApiError {
+    ApiError {
+        code: "BAD_REQUEST".to_string(),
+        message: "Validation failed".to_string(),
+        details: Some(serde_json::to_value(errors).unwrap()),
+    }
+}
+
+/// Names of all configured upstream servers
+async fn upstream_names(state: &ProfilesState) -> Result<Vec<String>, ApiError> {
+    let servers = state
+        .db
+        .upstream_servers()
+        .list()
+        .await
+        .map_err(|e| internal_error("Failed to list upstream servers", e))?;
+
+    Ok(servers.into_iter().map(|s| s.name).collect())
+}
+
+/// Refresh the resolver's profiles after a change
+async fn reload_router(state: &ProfilesState) {
+    if let Err(e) = state.router.reload().await {
+        tracing::warn!("Failed to reload resolution profiles: {}", e);
+    }
+}
+
+/// List all profiles
+///
+/// GET /api/profiles
+pub async fn list_profiles(
+    State(state): State<ProfilesState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let profiles = state
+        .db
+        .resolution_profiles()
+        .list()
+        .await
+        .map_err(|e| internal_error("Failed to list profiles", e))?;
+
+    Ok(Json(ProfilesListResponse {
+        total: profiles.len(),
+        data: profiles,
+        active_profile_id: state.router.active().map(|p| p.id),
+    }))
+}
+
+/// Get a profile by ID
+///
+/// GET /api/profiles/:id
+pub async fn get_profile(
+    State(state): State<ProfilesState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let profile = state
+        .db
+        .resolution_profiles()
+        .get_by_id(id)
+        .await
+        .map_err(|e| internal_error("Failed to get profile", e))?;
+
+    profile
+        .map(|p| Json(ProfileResponse { data: p }))
+        .ok_or_else(|| not_found("Profile", id))
+}
+
+/// Create a new profile
+///
+/// POST /api/profiles
+pub async fn create_profile(
+    State(state): State<ProfilesState>,
+    Json(mut request): Json<CreateResolutionProfile>,
+) -> Result<impl IntoResponse, ApiError> {
+    let known = upstream_names(&state).await?;
+    validate_fields(
+        Some(&request.name),
+        Some(&request.upstreams),
+        Some(&request.rules),
+        request.probe_target.as_deref(),
+        &known,
+    )
+    .map_err(validation_failed)?;
+
+    request.name = request.name.trim().to_string();
+    request.upstreams = normalize_list(&request.upstreams);
+    request.rules = normalize_list(&request.rules);
+    request.probe_target = request
+        .probe_target
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty());
+
+    let profile = state
+        .db
+        .resolution_profiles()
+        .create(request)
+        .await
+        .map_err(|e| internal_error("Failed to create profile", e))?;
+
+    reload_router(&state).await;
+
+    Ok((StatusCode::CREATED, Json(ProfileResponse { data: profile })))
+}
+
+/// Update a profile
+///
+/// PUT /api/profiles/:id
+pub async fn update_profile(
+    State(state): State<ProfilesState>,
+    Path(id): Path<i64>,
+    Json(mut request): Json<UpdateResolutionProfile>,
+) -> Result<impl IntoResponse, ApiError> {
+    let known = upstream_names(&state).await?;
+    validate_fields(
+        request.name.as_deref(),
+        request.upstreams.as_deref(),
+        request.rules.as_deref(),
+        request.probe_target.as_deref(),
+        &known,
+    )
+    .map_err(validation_failed)?;
+
+    request.name = request.name.map(|n| n.trim().to_string());
+    request.upstreams = request.upstreams.map(|s| normalize_list(&s));
+    request.rules = request.rules.map(|s| normalize_list(&s));
+    request.probe_target = request.probe_target.map(|t| t.trim().to_string());
+
+    let profile = state
+        .db
+        .resolution_profiles()
+        .update(id, request)
+        .await
+        .map_err(|e| internal_error("Failed to update profile", e))?;
+    let profile = profile.ok_or_else(|| not_found("Profile", id))?;
+
+    reload_router(&state).await;
+
+    Ok(Json(ProfileResponse { data: profile }))
+}
+
+/// Delete a profile; deleting the active profile deactivates profiles
+///
+/// DELETE /api/profiles/:id
+pub async fn delete_profile(
+    State(state): State<ProfilesState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    if state.router.active().is_some_and(|p| p.id == id) {
+        state
+            .router
+            .activate(None)
+            .await
+            .map_err(|e| internal_error("Failed to deactivate profile", e))?;
+    }
+
+    let deleted = state
+        .db
+        .resolution_profiles()
+        .delete(id)
+        .await
+        .map_err(|e| internal_error("Failed to delete profile", e))?;
+
+    if !deleted {
+        return Err(not_found("Profile", id));
+    }
+
+    reload_router(&state).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get the active profile, switching settings and last probe results
+///
+/// GET /api/profiles/status
+pub async fn get_status(
+    State(state): State<ProfilesState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let active = state.router.active();
+
+    Ok(Json(ProfileStatusResponse {
+        active_profile_id: active.as_ref().map(|p| p.id),
+        active_profile: active.map(|p| p.name),
+        settings: state.router.settings(),
+        last_probe: state.router.last_probe(),
+    }))
+}
+
+/// Activate a profile manually
+///
+/// Manual activation turns automatic switching off so the next probe round
+/// does not immediately undo it.
+///
+/// POST /api/profiles/activate
+pub async fn activate_profile(
+    State(state): State<ProfilesState>,
+    Json(request): Json<ActivateRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    if let Some(id) = request.id {
+        if !state.router.profiles().iter().any(|p| p.id == id) {
+            return Err(not_found("Profile", id));
+        }
+    }
+
+    let settings = state.router.settings();
+    if settings.auto_switch {
+        state
+            .router
+            .save_settings(ProfileSettings {
+                auto_switch: false,
+                ..settings
+            })
+            .await
+            .map_err(|e| internal_error("Failed to save profile settings", e))?;
+    }
+
+    let changed = state
+        .router
+        .activate(request.id)
+        .await
+        .map_err(|e| internal_error("Failed to activate profile", e))?;
+
+    Ok(Json(serde_json::json!({
+        "active_profile_id": request.id,
+        "changed": changed,
+    })))
+}
+
+/// Update automatic switching settings
+///
+/// PUT /api/profiles/settings
+pub async fn update_settings(
+    State(state): State<ProfilesState>,
+    Json(request): Json<UpdateProfileSettingsRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    if let Some(secs) = request.probe_interval_secs {
+        if secs < MIN_PROBE_INTERVAL_SECS {
+            return Err(bad_request(format!(
+                "probe_interval_secs must be at least {}",
+                MIN_PROBE_INTERVAL_SECS
+            )));
+        }
+    }
+
+    let current = state.router.settings();
+    let settings = ProfileSettings {
+        auto_switch: request.auto_switch.unwrap_or(current.auto_switch),
+        probe_interval_secs: request.probe_interval_secs.unwrap_or(current.probe_interval_secs),
+    };
+
+    state
+        .router
+        .save_settings(settings)
+        .await
+        .map_err(|e| internal_error("Failed to save profile settings", e))?;
+
+    // Switch right away instead of waiting for the next probe round
+    if settings.auto_switch && !current.auto_switch {
+        if let Err(e) = state.router.auto_switch().await {
+            tracing::warn!("Automatic profile switch failed: {}", e);
+        }
+    }
+
+    Ok(Json(settings))
+}
+
+/// Probe every profile now, switching if automatic switching is enabled
+///
+/// POST /api/profiles/probe
+pub async fn probe_profiles(
+    State(state): State<ProfilesState>,
+) -> Result<impl IntoResponse, ApiError> {
+    if state.router.settings().auto_switch {
+        state
+            .router
+            .auto_switch()
+            .await
+            .map_err(|e| internal_error("Automatic profile switch failed", e))?;
+    } else {
+        state.router.probe_all().await;
+    }
+
+    Ok(Json(serde_json::json!({
+        "active_profile_id": state.router.active().map(|p| p.id),
+        "results": state.router.last_probe(),
+    })))
+}
+
+/// Build the profiles API router
+pub fn profiles_router(state: ProfilesState) -> axum::Router {
+    use axum::routing::{get, post, put};
+
+    axum::Router::new()
+        .route("/", get(list_profiles).post(create_profile))
+        .route("/status", get(get_status))
+        .route("/settings", put(update_settings))
+        .route("/activate", post(activate_profile))
+        .route("/probe", post(probe_profiles))
+        .route("/:id", get(get_profile).put(update_profile).delete(delete_profile))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_fields_collects_errors() {
+        let known = vec!["corp-dns".to_string(), "cloudflare".to_string()];
+
+        let result = validate_fields(
+            Some(""),
+            Some("cloudflare,quad9"),
+            Some("corp.example.com"),
+            Some("vpn.example.com:443"),
+            &known,
+        );
+        assert_eq!(result.unwrap_err().errors.len(), 4);
+
+        let result = validate_fields(None, None, Some("corp.example.com=other"), None, &known);
+        assert_eq!(result.unwrap_err().errors[0].field, "rules");
+
+        assert!(validate_fields(
+            Some("office"),
+            Some("cloudflare"),
+            Some("corp.example.com=corp-dns"),
+            Some("10.0.0.1:443"),
+            &known,
+        )
+        .is_ok());
+        assert!(validate_fields(None, None, None, Some(""), &known).is_ok());
+    }
+
+    #[test]
+    fn test_normalize_list() {
+        assert_eq!(normalize_list(" a=b , ,c=d "), "a=b,c=d");
+    }
+}
@@ -0,0 +1,174 @@
+//! Public Stats API module
+//!
+//! An optional, unauthenticated summary for a shared status page: queries
+//! today, ads and trackers blocked today and the cache hit rate. Nothing
+//! that identifies domains or clients is exposed. The endpoint is off by
+//! default, switched on in settings, and answers 404 while disabled.
+//! Requests are rate limited per client address.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{ConnectInfo, State},
+    response::IntoResponse,
+    Json,
+};
+use serde::Serialize;
+
+use crate::db::Database;
+use crate::dns::{CacheManager, PolicyStats};
+use crate::web::ApiError;
+
+/// Config key for the public stats switch
+pub const CONFIG_KEY_PUBLIC_STATS_ENABLED: &str = "public_stats_enabled";
+
+/// Requests allowed per client address in each window
+const RATE_LIMIT_REQUESTS: u32 = 30;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+/// Client count above which expired windows are swept
+const RATE_LIMIT_SWEEP_THRESHOLD: usize = 1024;
+
+/// Whether the public stats endpoint is enabled
+pub async fn public_stats_enabled(db: &Database) -> bool {
+    db.system_config()
+        .get(CONFIG_KEY_PUBLIC_STATS_ENABLED)
+        .await
+        .unwrap_or(None)
+        .is_some_and(|v| v == "true")
+}
+
+/// Fixed-window request limiter keyed by client address
+///
+/// Keyed by the connecting address rather than forwarding headers, which
+/// clients could set freely to dodge the limit.
+pub struct RateLimiter {
+    max_requests: u32,
+    window: Duration,
+    clients: Mutex<HashMap<IpAddr, (Instant, u32)>>,
+}
+
+impl RateLimiter {
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        Self {
+            max_requests,
+            window,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count a request, returning false when the client is over the limit
+    pub fn check(&self, client: IpAddr) -> bool {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: IpAddr, now: Instant) -> bool {
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() > RATE_LIMIT_SWEEP_THRESHOLD {
+            clients.retain(|_, (start, _)| now.duration_since(*start) < self.window);
+        }
+        let (start, count) = clients.entry(client).or_insert((now, 0));
+        if now.duration_since(*start) >= self.window {
+            *start = now;
+            *count = 0;
+        }
+        if *count >= self.max_requests {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(RATE_LIMIT_REQUESTS, RATE_LIMIT_WINDOW)
+    }
+}
+
+/// Application state for the public stats API
+#[derive(Clone)]
+pub struct PublicState {
+    pub db: Arc<Database>,
+    pub cache: Arc<CacheManager>,
+    pub policy_stats: Arc<PolicyStats>,
+    pub limiter: Arc<RateLimiter>,
+}
+
+/// Coarse statistics safe to show without logging in
+#[derive(Debug, Serialize)]
+pub struct PublicStats {
+    pub queries_today: i64,
+    /// Queries blocked by rules or filters in the last day
+    pub blocked_today: u64,
+    /// Cache hit rate since startup, rounded to a whole percent
+    pub cache_hit_rate: f64,
+}
+
+/// Public stats summary
+///
+/// GET /api/public/stats
+pub async fn public_stats(
+    State(state): State<PublicState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !public_stats_enabled(&state.db).await {
+        return Err(ApiError {
+            code: "NOT_FOUND".to_string(),
+            message: "Not found".to_string(),
+            details: None,
+        });
+    }
+    if !state.limiter.check(addr.ip()) {
+        return Err(ApiError {
+            code: "TOO_MANY_REQUESTS".to_string(),
+            message: "Too many requests, try again later".to_string(),
+            details: None,
+        });
+    }
+
+    let query_stats = state.db.query_logs().get_stats().await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to get query stats: {}", e),
+        details: None,
+    })?;
+    let hit_rate = state.cache.stats().await.hit_rate();
+
+    Ok(Json(PublicStats {
+        queries_today: query_stats.queries_today,
+        blocked_today: state.policy_stats.snapshot().total.last_day.blocked,
+        cache_hit_rate: (hit_rate * 100.0).round() / 100.0,
+    }))
+}
+
+/// Build the public stats router
+pub fn public_router(state: PublicState) -> axum::Router {
+    use axum::routing::get;
+
+    axum::Router::new()
+        .route("/stats", get(public_stats))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let client: IpAddr = "192.168.1.10".parse().unwrap();
+        let other: IpAddr = "192.168.1.11".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(limiter.check_at(client, now));
+        assert!(limiter.check_at(client, now));
+        assert!(!limiter.check_at(client, now + Duration::from_secs(1)));
+        // Limits are per client
+        assert!(limiter.check_at(other, now));
+        // A new window starts once the old one has passed
+        assert!(limiter.check_at(client, now + Duration::from_secs(60)));
+    }
+}
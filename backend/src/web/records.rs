@@ -9,25 +9,90 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
-    Json,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
 };
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::db::{CreateDnsRecord, Database, DnsRecord, UpdateDnsRecord};
-use crate::web::ApiError;
+use crate::db::{deserialize_some, CreateDnsRecord, Database, DnsRecord, RecordMatch, Tags, UpdateDnsRecord};
+use crate::dns::{escape_txt, name_to_ascii, name_to_unicode, normalize_name, parse_txt, CacheManager, LocalRecordIndex};
+use crate::web::etag::{check_if_match, etag_header, modified_error};
+use crate::web::{ApiError, DomainScope, TenantScope};
 
 /// Application state for DNS records API
 #[derive(Clone)]
 pub struct RecordsState {
     pub db: Arc<Database>,
+    pub local_records: Arc<LocalRecordIndex>,
+    pub cache: Arc<CacheManager>,
 }
 
 /// Supported DNS record types
 const VALID_RECORD_TYPES: &[&str] = &["A", "AAAA", "CNAME", "MX", "TXT", "PTR", "NS", "SOA", "SRV"];
 
+/// Maximum number of tags on a record or rule
+const MAX_TAGS: usize = 16;
+/// Maximum length of a single tag
+const MAX_TAG_LEN: usize = 64;
+/// Most records accepted by one bulk create request
+pub const MAX_BULK_RECORDS: usize = 1000;
+
+/// Config keys for the allowed record TTL range
+pub const CONFIG_KEY_RECORD_TTL_MIN: &str = "record_ttl_min";
+pub const CONFIG_KEY_RECORD_TTL_MAX: &str = "record_ttl_max";
+
+/// Allowed record TTL range in seconds, adjustable in settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TtlBounds {
+    pub min: i32,
+    pub max: i32,
+}
+
+impl Default for TtlBounds {
+    fn default() -> Self {
+        Self {
+            min: 1,
+            max: 604800, // 1 week
+        }
+    }
+}
+
+impl TtlBounds {
+    /// Check that the range itself is usable
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min < 0 {
+            return Err("Minimum TTL cannot be negative".to_string());
+        }
+        if self.max < self.min {
+            return Err(format!(
+                "Maximum TTL ({}) cannot be lower than minimum TTL ({})",
+                self.max, self.min
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Current record TTL range, defaults for missing or invalid settings
+pub async fn ttl_bounds(db: &Database) -> TtlBounds {
+    let repo = db.system_config();
+    let defaults = TtlBounds::default();
+    let min = repo.get(CONFIG_KEY_RECORD_TTL_MIN).await.unwrap_or(None);
+    let max = repo.get(CONFIG_KEY_RECORD_TTL_MAX).await.unwrap_or(None);
+    let bounds = TtlBounds {
+        min: min.and_then(|v| v.parse().ok()).unwrap_or(defaults.min),
+        max: max.and_then(|v| v.parse().ok()).unwrap_or(defaults.max),
+    };
+    if bounds.validate().is_ok() {
+        bounds
+    } else {
+        defaults
+    }
+}
+
 /// Validation error details
 #[derive(Debug, Serialize)]
 pub struct ValidationErrors {
@@ -52,6 +117,15 @@ pub struct CreateRecordRequest {
     pub priority: i32,
     #[serde(default = "default_enabled")]
     pub enabled: bool,
+    /// Owning tenant (ignored for tenant tokens, which always use their own)
+    #[serde(default)]
+    pub tenant_id: Option<i64>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// After this time the record is treated as disabled
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 fn default_ttl() -> i32 {
@@ -71,23 +145,160 @@ pub struct UpdateRecordRequest {
     pub ttl: Option<i32>,
     pub priority: Option<i32>,
     pub enabled: Option<bool>,
+    pub description: Option<String>,
+    /// Replaces all tags
+    pub tags: Option<Vec<String>>,
+    /// `null` removes the expiration
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub expires_at: Option<Option<DateTime<Utc>>>,
+}
+
+/// Query parameters for list endpoints
+#[derive(Debug, Default, Deserialize)]
+pub struct TagFilter {
+    /// Comma-separated tags an entry must all carry
+    pub tag: Option<String>,
+    /// Only entries expiring within this many hours (not yet expired)
+    pub expiring_within_hours: Option<i64>,
+}
+
+impl TagFilter {
+    /// Whether an entry's tags satisfy the filter
+    pub fn matches(&self, tags: &Tags) -> bool {
+        self.tag.as_deref().is_none_or(|filter| {
+            filter
+                .split(',')
+                .map(|t| t.trim().to_lowercase())
+                .filter(|t| !t.is_empty())
+                .all(|t| tags.contains(&t))
+        })
+    }
+
+    /// Whether an entry's expiration satisfies the filter
+    pub fn matches_expiry(&self, expires_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+        self.expiring_within_hours.is_none_or(|hours| {
+            expires_at.is_some_and(|t| t > now && t <= now + Duration::hours(hours))
+        })
+    }
+}
+
+/// Operation applied to every entry carrying a tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagAction {
+    Enable,
+    Disable,
+    Delete,
+}
+
+/// Bulk operation by tag request
+#[derive(Debug, Deserialize)]
+pub struct BulkTagRequest {
+    pub tag: String,
+    pub action: TagAction,
+}
+
+/// Bulk operation by tag response
+#[derive(Debug, Serialize)]
+pub struct BulkTagResponse {
+    /// Entries changed or deleted
+    pub affected: u64,
+}
+
+/// Bulk create request
+#[derive(Debug, Deserialize)]
+pub struct BulkCreateRequest {
+    pub records: Vec<CreateRecordRequest>,
+}
+
+/// Bulk create response
+#[derive(Debug, Serialize)]
+pub struct BulkCreateResponse {
+    pub created: usize,
+    /// Ids of the new records, in request order
+    pub ids: Vec<i64>,
+}
+
+/// Body of `POST /api/records/bulk`: records to create, or a tag operation
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum BulkRecordsRequest {
+    Create(BulkCreateRequest),
+    Tag(BulkTagRequest),
+}
+
+/// A record as returned by the API
+#[derive(Debug, Serialize)]
+pub struct RecordView {
+    #[serde(flatten)]
+    pub record: DnsRecord,
+    /// Name with punycode labels shown in Unicode
+    pub display_name: String,
+    /// Character-strings a TXT value is sent as, unprintable bytes escaped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub txt_strings: Option<Vec<String>>,
+}
+
+impl From<DnsRecord> for RecordView {
+    fn from(record: DnsRecord) -> Self {
+        let txt_strings = if record.record_type.eq_ignore_ascii_case("TXT") {
+            parse_txt(&record.value)
+                .ok()
+                .map(|strings| strings.iter().map(|s| escape_txt(s)).collect())
+        } else {
+            None
+        };
+        Self {
+            display_name: name_to_unicode(&record.name),
+            txt_strings,
+            record,
+        }
+    }
 }
 
 /// API response wrapper for single record
 #[derive(Debug, Serialize)]
 pub struct RecordResponse {
-    pub data: DnsRecord,
+    pub data: RecordView,
 }
 
 /// API response wrapper for multiple records
 #[derive(Debug, Serialize)]
 pub struct RecordsListResponse {
-    pub data: Vec<DnsRecord>,
+    pub data: Vec<RecordView>,
     pub total: usize,
 }
 
+/// Query parameters for record matching
+#[derive(Debug, Deserialize)]
+pub struct MatchRecordsQuery {
+    pub name: String,
+    #[serde(rename = "type", default = "default_match_type")]
+    pub record_type: String,
+    /// Tenant view to match in (ignored for tenant tokens)
+    pub tenant_id: Option<i64>,
+}
+
+fn default_match_type() -> String {
+    "A".to_string()
+}
+
+/// Local records that would answer a query
+#[derive(Debug, Serialize)]
+pub struct RecordMatchResponse {
+    pub name: String,
+    pub record_type: String,
+    /// None when no local name matches and the query goes upstream
+    #[serde(rename = "match")]
+    pub matched: Option<RecordMatch>,
+    pub wildcard: bool,
+}
+
 /// Validate a DNS record name
-fn validate_name(name: &str) -> Result<(), String> {
+///
+/// Internationalized names are checked in their punycode form.
+pub(crate) fn validate_name(name: &str) -> Result<(), String> {
+    let name = name_to_ascii(name)?;
     if name.is_empty() {
         return Err("Name cannot be empty".to_string());
     }
@@ -101,6 +312,10 @@ fn validate_name(name: &str) -> Result<(), String> {
     if !valid_chars {
         return Err("Name contains invalid characters".to_string());
     }
+    // A wildcard must be the whole leftmost label of a non-empty parent
+    if name.contains('*') && !(name.starts_with("*.") && name.len() > 2 && !name[1..].contains('*')) {
+        return Err("Wildcard '*' is only allowed as the leftmost label, e.g. *.example.com".to_string());
+    }
     Ok(())
 }
 
@@ -143,10 +358,11 @@ fn validate_value(value: &str, record_type: &str) -> Result<(), String> {
             }
         }
         "TXT" => {
-            // TXT records can contain any text
+            // Plain text of any length, or quoted character-strings
             if value.len() > 65535 {
                 return Err("TXT record value too long".to_string());
             }
+            parse_txt(value)?;
         }
         _ => {
             // For other types, just ensure non-empty
@@ -158,14 +374,43 @@ fn validate_value(value: &str, record_type: &str) -> Result<(), String> {
     Ok(())
 }
 
-/// Validate TTL value
-#[allow(unused_comparisons)]
-fn validate_ttl(ttl: i32) -> Result<(), String> {
+/// Normalize tags: trimmed, lowercased, de-duplicated, in the given order
+pub(crate) fn normalize_tags(tags: &[String]) -> Result<Tags, String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() {
+            return Err("Tags cannot be empty".to_string());
+        }
+        if tag.len() > MAX_TAG_LEN {
+            return Err(format!("Tag '{}' exceeds {} characters", tag, MAX_TAG_LEN));
+        }
+        if !tag.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':')) {
+            return Err(format!(
+                "Tag '{}' may only contain letters, digits, '-', '_', '.' and ':'",
+                tag
+            ));
+        }
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    if normalized.len() > MAX_TAGS {
+        return Err(format!("At most {} tags are allowed", MAX_TAGS));
+    }
+    Ok(Tags(normalized))
+}
+
+/// Validate TTL value against the configured range
+pub(crate) fn validate_ttl(ttl: i32, bounds: &TtlBounds) -> Result<(), String> {
     if ttl < 0 {
         return Err("TTL cannot be negative".to_string());
     }
-    if ttl > 2147483647 {
-        return Err("TTL value too large".to_string());
+    if ttl < bounds.min || ttl > bounds.max {
+        return Err(format!(
+            "TTL must be between {} and {} seconds, got {}",
+            bounds.min, bounds.max, ttl
+        ));
     }
     Ok(())
 }
@@ -180,7 +425,7 @@ fn validate_priority(priority: i32) -> Result<(), String> {
 
 impl CreateRecordRequest {
     /// Validate the create request
-    pub fn validate(&self) -> Result<(), ValidationErrors> {
+    pub fn validate(&self, ttl_bounds: &TtlBounds) -> Result<(), ValidationErrors> {
         let mut errors = Vec::new();
 
         if let Err(e) = validate_name(&self.name) {
@@ -212,7 +457,7 @@ impl CreateRecordRequest {
             });
         }
 
-        if let Err(e) = validate_ttl(self.ttl) {
+        if let Err(e) = validate_ttl(self.ttl, ttl_bounds) {
             errors.push(ValidationError {
                 field: "ttl".to_string(),
                 message: e,
@@ -226,6 +471,13 @@ impl CreateRecordRequest {
             });
         }
 
+        if let Err(e) = normalize_tags(&self.tags) {
+            errors.push(ValidationError {
+                field: "tags".to_string(),
+                message: e,
+            });
+        }
+
         if errors.is_empty() {
             Ok(())
         } else {
@@ -233,22 +485,26 @@ impl CreateRecordRequest {
         }
     }
 
-    /// Convert to CreateDnsRecord with normalized record type
+    /// Convert to CreateDnsRecord with normalized name and record type
     pub fn into_create_dns_record(self) -> CreateDnsRecord {
         CreateDnsRecord {
-            name: self.name,
+            name: normalize_name(&self.name),
             record_type: self.record_type.to_uppercase(),
             value: self.value,
             ttl: self.ttl,
             priority: self.priority,
             enabled: self.enabled,
+            tenant_id: self.tenant_id,
+            description: self.description,
+            tags: normalize_tags(&self.tags).unwrap_or_default(),
+            expires_at: self.expires_at,
         }
     }
 }
 
 impl UpdateRecordRequest {
     /// Validate the update request
-    pub fn validate(&self, existing_record_type: &str) -> Result<(), ValidationErrors> {
+    pub fn validate(&self, existing_record_type: &str, ttl_bounds: &TtlBounds) -> Result<(), ValidationErrors> {
         let mut errors = Vec::new();
 
         if let Some(ref name) = self.name {
@@ -283,7 +539,7 @@ impl UpdateRecordRequest {
         }
 
         if let Some(ttl) = self.ttl {
-            if let Err(e) = validate_ttl(ttl) {
+            if let Err(e) = validate_ttl(ttl, ttl_bounds) {
                 errors.push(ValidationError {
                     field: "ttl".to_string(),
                     message: e,
@@ -300,6 +556,15 @@ impl UpdateRecordRequest {
             }
         }
 
+        if let Some(ref tags) = self.tags {
+            if let Err(e) = normalize_tags(tags) {
+                errors.push(ValidationError {
+                    field: "tags".to_string(),
+                    message: e,
+                });
+            }
+        }
+
         if errors.is_empty() {
             Ok(())
         } else {
@@ -307,15 +572,18 @@ impl UpdateRecordRequest {
         }
     }
 
-    /// Convert to UpdateDnsRecord with normalized record type
+    /// Convert to UpdateDnsRecord with normalized name and record type
     pub fn into_update_dns_record(self) -> UpdateDnsRecord {
         UpdateDnsRecord {
-            name: self.name,
+            name: self.name.map(|n| normalize_name(&n)),
             record_type: self.record_type.map(|t| t.to_uppercase()),
             value: self.value,
             ttl: self.ttl,
             priority: self.priority,
             enabled: self.enabled,
+            description: self.description,
+            tags: self.tags.map(|t| normalize_tags(&t).unwrap_or_default()),
+            expires_at: self.expires_at,
         }
     }
 }
@@ -323,21 +591,81 @@ impl UpdateRecordRequest {
 
 /// List all DNS records
 ///
-/// GET /api/records
+/// GET /api/records?tag=office-berlin&expiring_within_hours=24
 pub async fn list_records(
     State(state): State<RecordsState>,
+    scope: Option<Extension<TenantScope>>,
+    domains: Option<Extension<DomainScope>>,
+    Query(filter): Query<TagFilter>,
 ) -> Result<impl IntoResponse, ApiError> {
     let repo = state.db.dns_records();
     
-    let records = repo.list().await.map_err(|e| ApiError {
+    let records = match scope {
+        Some(Extension(scope)) => repo.list_by_tenant(scope.tenant_id).await,
+        None => repo.list().await,
+    };
+    let mut records = records.map_err(|e| ApiError {
         code: "INTERNAL_ERROR".to_string(),
         message: format!("Failed to list records: {}", e),
         details: None,
     })?;
+    let now = Utc::now();
+    records.retain(|r| {
+        in_zones(&domains, &r.name) && filter.matches(&r.tags) && filter.matches_expiry(r.expires_at, now)
+    });
 
     Ok(Json(RecordsListResponse {
         total: records.len(),
-        data: records,
+        data: records.into_iter().map(RecordView::from).collect(),
+    }))
+}
+
+/// Show which local records answer a name
+///
+/// GET /api/records/match?name=host.example.com&type=A
+///
+/// Follows the resolver's order: the exact name, then the closest
+/// wildcard. The winning name answers for every type, so `match.records`
+/// can be empty when it only has records of other types.
+pub async fn match_records(
+    State(state): State<RecordsState>,
+    scope: Option<Extension<TenantScope>>,
+    domains: Option<Extension<DomainScope>>,
+    Query(query): Query<MatchRecordsQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let tenant_id = match scope {
+        Some(Extension(scope)) => Some(scope.tenant_id),
+        None => query.tenant_id,
+    };
+    let record_type = query.record_type.to_uppercase();
+    if let Err(e) = validate_record_type(&record_type) {
+        return Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: e,
+            details: None,
+        });
+    }
+
+    let name = normalize_name(&query.name);
+    if !in_zones(&domains, &name) {
+        return Err(outside_zones(&name));
+    }
+    let matched = state
+        .db
+        .dns_records()
+        .match_for_tenant(&name, &record_type, tenant_id)
+        .await
+        .map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to match records: {}", e),
+            details: None,
+        })?;
+
+    Ok(Json(RecordMatchResponse {
+        name,
+        record_type,
+        wildcard: matched.as_ref().is_some_and(|m| m.is_wildcard()),
+        matched,
     }))
 }
 
@@ -346,6 +674,8 @@ pub async fn list_records(
 /// GET /api/records/:id
 pub async fn get_record(
     State(state): State<RecordsState>,
+    scope: Option<Extension<TenantScope>>,
+    domains: Option<Extension<DomainScope>>,
     Path(id): Path<i64>,
 ) -> Result<impl IntoResponse, ApiError> {
     let repo = state.db.dns_records();
@@ -355,9 +685,10 @@ pub async fn get_record(
         message: format!("Failed to get record: {}", e),
         details: None,
     })?;
+    let record = record.filter(|r| visible_to(&scope, r.tenant_id) && in_zones(&domains, &r.name));
 
     match record {
-        Some(r) => Ok(Json(RecordResponse { data: r })),
+        Some(r) => Ok((etag_header(r.id, &r.updated_at), Json(RecordResponse { data: r.into() }))),
         None => Err(ApiError {
             code: "NOT_FOUND".to_string(),
             message: format!("Record with id {} not found", id),
@@ -371,16 +702,28 @@ pub async fn get_record(
 /// POST /api/records
 pub async fn create_record(
     State(state): State<RecordsState>,
-    Json(request): Json<CreateRecordRequest>,
+    scope: Option<Extension<TenantScope>>,
+    domains: Option<Extension<DomainScope>>,
+    Json(mut request): Json<CreateRecordRequest>,
 ) -> Result<impl IntoResponse, ApiError> {
+    // Tenant tokens can only create records in their own tenant
+    match scope {
+        Some(Extension(scope)) => request.tenant_id = Some(scope.tenant_id),
+        None => ensure_tenant_exists(&state.db, request.tenant_id).await?,
+    }
+
     // Validate request
-    if let Err(validation_errors) = request.validate() {
+    let ttl_bounds = ttl_bounds(&state.db).await;
+    if let Err(validation_errors) = request.validate(&ttl_bounds) {
         return Err(ApiError {
             code: "BAD_REQUEST".to_string(),
             message: "Validation failed".to_string(),
             details: Some(serde_json::to_value(validation_errors).unwrap()),
         });
     }
+    if !in_zones(&domains, &request.name) {
+        return Err(outside_zones(&request.name));
+    }
 
     let repo = state.db.dns_records();
     let create_record = request.into_create_dns_record();
@@ -390,8 +733,10 @@ pub async fn create_record(
         message: format!("Failed to create record: {}", e),
         details: None,
     })?;
+    reload_local_records(&state.local_records).await;
+    state.cache.purge_names([&record.name]).await;
 
-    Ok((StatusCode::CREATED, Json(RecordResponse { data: record })))
+    Ok((StatusCode::CREATED, Json(RecordResponse { data: record.into() })))
 }
 
 /// Update a DNS record
@@ -399,7 +744,10 @@ pub async fn create_record(
 /// PUT /api/records/:id
 pub async fn update_record(
     State(state): State<RecordsState>,
+    scope: Option<Extension<TenantScope>>,
+    domains: Option<Extension<DomainScope>>,
     Path(id): Path<i64>,
+    headers: HeaderMap,
     Json(request): Json<UpdateRecordRequest>,
 ) -> Result<impl IntoResponse, ApiError> {
     let repo = state.db.dns_records();
@@ -410,6 +758,7 @@ pub async fn update_record(
         message: format!("Failed to get record: {}", e),
         details: None,
     })?;
+    let existing = existing.filter(|r| visible_to(&scope, r.tenant_id) && in_zones(&domains, &r.name));
 
     let existing = existing.ok_or_else(|| ApiError {
         code: "NOT_FOUND".to_string(),
@@ -417,25 +766,36 @@ pub async fn update_record(
         details: None,
     })?;
 
+    let version = check_if_match(&state.db, &headers, existing.id, &existing.updated_at).await?;
+
     // Validate request against existing record type
-    if let Err(validation_errors) = request.validate(&existing.record_type) {
+    let ttl_bounds = ttl_bounds(&state.db).await;
+    if let Err(validation_errors) = request.validate(&existing.record_type, &ttl_bounds) {
         return Err(ApiError {
             code: "BAD_REQUEST".to_string(),
             message: "Validation failed".to_string(),
             details: Some(serde_json::to_value(validation_errors).unwrap()),
         });
     }
+    if let Some(name) = request.name.as_deref().filter(|n| !in_zones(&domains, n)) {
+        return Err(outside_zones(name));
+    }
 
     let update_record = request.into_update_dns_record();
     
-    let record = repo.update(id, update_record).await.map_err(|e| ApiError {
+    let record = repo.update_at_version(id, update_record, version).await.map_err(|e| ApiError {
         code: "INTERNAL_ERROR".to_string(),
         message: format!("Failed to update record: {}", e),
         details: None,
     })?;
+    reload_local_records(&state.local_records).await;
+    let mut changed_names = vec![existing.name];
+    changed_names.extend(record.as_ref().map(|r| r.name.clone()));
+    state.cache.purge_names(changed_names).await;
 
     match record {
-        Some(r) => Ok(Json(RecordResponse { data: r })),
+        Some(r) => Ok((etag_header(r.id, &r.updated_at), Json(RecordResponse { data: r.into() }))),
+        None if version.is_some() => Err(modified_error(None)),
         None => Err(ApiError {
             code: "NOT_FOUND".to_string(),
             message: format!("Record with id {} not found", id),
@@ -449,18 +809,40 @@ pub async fn update_record(
 /// DELETE /api/records/:id
 pub async fn delete_record(
     State(state): State<RecordsState>,
+    scope: Option<Extension<TenantScope>>,
+    domains: Option<Extension<DomainScope>>,
     Path(id): Path<i64>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, ApiError> {
     let repo = state.db.dns_records();
+
+    let existing = repo.get_by_id(id).await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to get record: {}", e),
+        details: None,
+    })?;
+    let existing = existing
+        .filter(|r| visible_to(&scope, r.tenant_id) && in_zones(&domains, &r.name))
+        .ok_or_else(|| ApiError {
+            code: "NOT_FOUND".to_string(),
+            message: format!("Record with id {} not found", id),
+            details: None,
+        })?;
+
+    let version = check_if_match(&state.db, &headers, existing.id, &existing.updated_at).await?;
     
-    let deleted = repo.delete(id).await.map_err(|e| ApiError {
+    let deleted = repo.delete_at_version(id, version).await.map_err(|e| ApiError {
         code: "INTERNAL_ERROR".to_string(),
         message: format!("Failed to delete record: {}", e),
         details: None,
     })?;
 
     if deleted {
+        reload_local_records(&state.local_records).await;
+        state.cache.purge_names([&existing.name]).await;
         Ok(StatusCode::NO_CONTENT)
+    } else if version.is_some() {
+        Err(modified_error(None))
     } else {
         Err(ApiError {
             code: "NOT_FOUND".to_string(),
@@ -470,12 +852,253 @@ pub async fn delete_record(
     }
 }
 
+/// Create records in one transaction, or apply a tag operation
+///
+/// POST /api/records/bulk
+///
+/// `{"records": [...]}` creates up to [`MAX_BULK_RECORDS`] records. All of
+/// them are validated first; if any fails, nothing is created and the
+/// errors of every item come back together, with fields such as
+/// `records[3].value`. `{"tag": ..., "action": ...}` is handled by
+/// [`bulk_records_by_tag`].
+pub async fn bulk_records(
+    State(state): State<RecordsState>,
+    scope: Option<Extension<TenantScope>>,
+    domains: Option<Extension<DomainScope>>,
+    Json(request): Json<BulkRecordsRequest>,
+) -> Result<Response, ApiError> {
+    match request {
+        BulkRecordsRequest::Create(request) => bulk_create_records(&state, scope, domains, request)
+            .await
+            .map(|created| (StatusCode::CREATED, Json(created)).into_response()),
+        BulkRecordsRequest::Tag(request) => bulk_records_by_tag(State(state), scope, domains, Json(request))
+            .await
+            .map(IntoResponse::into_response),
+    }
+}
+
+async fn bulk_create_records(
+    state: &RecordsState,
+    scope: Option<Extension<TenantScope>>,
+    domains: Option<Extension<DomainScope>>,
+    request: BulkCreateRequest,
+) -> Result<BulkCreateResponse, ApiError> {
+    let mut records = request.records;
+    if records.is_empty() || records.len() > MAX_BULK_RECORDS {
+        return Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: format!("Provide between 1 and {} records", MAX_BULK_RECORDS),
+            details: None,
+        });
+    }
+
+    // Tenant tokens can only create records in their own tenant
+    if let Some(Extension(ref scope)) = scope {
+        for record in &mut records {
+            record.tenant_id = Some(scope.tenant_id);
+        }
+    }
+
+    let ttl_bounds = ttl_bounds(&state.db).await;
+    let mut errors = Vec::new();
+    let mut known_tenants = Vec::new();
+    for (i, record) in records.iter().enumerate() {
+        if let Err(item_errors) = record.validate(&ttl_bounds) {
+            errors.extend(item_errors.errors.into_iter().map(|e| ValidationError {
+                field: format!("records[{}].{}", i, e.field),
+                message: e.message,
+            }));
+        }
+        if !in_zones(&domains, &record.name) {
+            errors.push(ValidationError {
+                field: format!("records[{}].name", i),
+                message: "Name is outside your delegated zones".to_string(),
+            });
+        }
+        if let (None, Some(tenant_id)) = (&scope, record.tenant_id) {
+            if !known_tenants.contains(&tenant_id) {
+                match ensure_tenant_exists(&state.db, Some(tenant_id)).await {
+                    Ok(()) => known_tenants.push(tenant_id),
+                    Err(e) if e.code == "BAD_REQUEST" => errors.push(ValidationError {
+                        field: format!("records[{}].tenant_id", i),
+                        message: e.message,
+                    }),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: "Validation failed".to_string(),
+            details: Some(serde_json::to_value(ValidationErrors { errors }).unwrap()),
+        });
+    }
+
+    let created = state
+        .db
+        .dns_records()
+        .create_many(records.into_iter().map(CreateRecordRequest::into_create_dns_record).collect())
+        .await
+        .map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to create records: {}", e),
+            details: None,
+        })?;
+    reload_local_records(&state.local_records).await;
+    state.cache.purge_names(created.iter().map(|r| r.name.as_str())).await;
+
+    Ok(BulkCreateResponse {
+        created: created.len(),
+        ids: created.iter().map(|r| r.id).collect(),
+    })
+}
+
+/// Enable, disable or delete every record carrying a tag
+///
+/// POST /api/records/bulk
+///
+/// Tags span zones, so delegated admins cannot use tag operations.
+pub async fn bulk_records_by_tag(
+    State(state): State<RecordsState>,
+    scope: Option<Extension<TenantScope>>,
+    domains: Option<Extension<DomainScope>>,
+    Json(request): Json<BulkTagRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    if domains.is_some() {
+        return Err(ApiError {
+            code: "FORBIDDEN".to_string(),
+            message: "Delegated admins cannot apply tag operations".to_string(),
+            details: None,
+        });
+    }
+    let tag = request.tag.trim().to_lowercase();
+    if tag.is_empty() {
+        return Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: "Tag cannot be empty".to_string(),
+            details: None,
+        });
+    }
+    let tenant_id = scope.map(|Extension(scope)| scope.tenant_id);
+
+    let repo = state.db.dns_records();
+    let tagged_names: Vec<String> = repo
+        .list()
+        .await
+        .map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to list records: {}", e),
+            details: None,
+        })?
+        .into_iter()
+        .filter(|r| r.tags.contains(&tag) && (tenant_id.is_none() || r.tenant_id == tenant_id))
+        .map(|r| r.name)
+        .collect();
+    let affected = match request.action {
+        TagAction::Enable => repo.set_enabled_by_tag(&tag, true, tenant_id).await,
+        TagAction::Disable => repo.set_enabled_by_tag(&tag, false, tenant_id).await,
+        TagAction::Delete => repo.delete_by_tag(&tag, tenant_id).await,
+    }
+    .map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to update records: {}", e),
+        details: None,
+    })?;
+    if affected > 0 {
+        reload_local_records(&state.local_records).await;
+        state.cache.purge_names(tagged_names).await;
+    }
+
+    Ok(Json(BulkTagResponse { affected }))
+}
+
+/// Rebuild the in-memory record index from the database
+///
+/// POST /api/records/refresh
+pub async fn refresh_records(
+    State(state): State<RecordsState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let records = state.local_records.reload().await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to refresh local records: {}", e),
+        details: None,
+    })?;
+
+    Ok(Json(serde_json::json!({
+        "message": "Local records refreshed successfully",
+        "records": records
+    })))
+}
+
+/// Rebuild the record index after a change; on failure the resolver
+/// falls back to the database until the next successful reload
+pub(crate) async fn reload_local_records(index: &LocalRecordIndex) {
+    if let Err(e) = index.reload().await {
+        tracing::warn!("Failed to reload local records: {}", e);
+    }
+}
+
+/// Whether a resource owned by `owner` is visible to the caller
+///
+/// Admin requests (no tenant scope) see everything.
+pub(crate) fn visible_to(scope: &Option<Extension<TenantScope>>, owner: Option<i64>) -> bool {
+    match scope {
+        Some(Extension(scope)) => owner == Some(scope.tenant_id),
+        None => true,
+    }
+}
+
+/// Whether a record name lies inside the caller's delegated zones
+///
+/// Requests without a domain scope may manage every name.
+pub(crate) fn in_zones(domains: &Option<Extension<DomainScope>>, name: &str) -> bool {
+    match domains {
+        Some(Extension(domains)) => domains.covers(name),
+        None => true,
+    }
+}
+
+fn outside_zones(name: &str) -> ApiError {
+    ApiError {
+        code: "FORBIDDEN".to_string(),
+        message: format!("Name {} is outside your delegated zones", name),
+        details: None,
+    }
+}
+
+/// Reject admin requests that reference an unknown tenant
+pub(crate) async fn ensure_tenant_exists(db: &Database, tenant_id: Option<i64>) -> Result<(), ApiError> {
+    let Some(tenant_id) = tenant_id else {
+        return Ok(());
+    };
+
+    let tenant = db.tenants().get_by_id(tenant_id).await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to get tenant: {}", e),
+        details: None,
+    })?;
+
+    match tenant {
+        Some(_) => Ok(()),
+        None => Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: format!("Tenant with id {} not found", tenant_id),
+            details: None,
+        }),
+    }
+}
+
 /// Build the records API router
 pub fn records_router(state: RecordsState) -> axum::Router {
     use axum::routing::get;
     
     axum::Router::new()
         .route("/", get(list_records).post(create_record))
+        .route("/match", get(match_records))
+        .route("/bulk", axum::routing::post(bulk_records))
+        .route("/refresh", axum::routing::post(refresh_records))
         .route("/:id", get(get_record).put(update_record).delete(delete_record))
         .with_state(state)
 }
@@ -491,6 +1114,8 @@ mod tests {
         assert!(validate_name("*.example.com").is_ok());
         assert!(validate_name("test-domain.com").is_ok());
         assert!(validate_name("_dmarc.example.com").is_ok());
+        assert!(validate_name("bücher.example").is_ok());
+        assert!(validate_name("*.münchen.de.").is_ok());
     }
 
     #[test]
@@ -498,6 +1123,83 @@ mod tests {
         assert!(validate_name("").is_err());
         assert!(validate_name(&"a".repeat(256)).is_err());
         assert!(validate_name("example.com!").is_err());
+        assert!(validate_name("*").is_err());
+        assert!(validate_name("*.").is_err());
+        assert!(validate_name("a*.example.com").is_err());
+        assert!(validate_name("a.*.example.com").is_err());
+        assert!(validate_name("*.*.example.com").is_err());
+    }
+
+    #[test]
+    fn test_normalize_tags() {
+        let tags = normalize_tags(&[
+            " Office-Berlin ".to_string(),
+            "created-by-script".to_string(),
+            "office-berlin".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(tags.0, vec!["office-berlin", "created-by-script"]);
+
+        assert!(normalize_tags(&["".to_string()]).is_err());
+        assert!(normalize_tags(&["has space".to_string()]).is_err());
+        assert!(normalize_tags(&["x".repeat(MAX_TAG_LEN + 1)]).is_err());
+        let too_many: Vec<String> = (0..=MAX_TAGS).map(|i| format!("t{}", i)).collect();
+        assert!(normalize_tags(&too_many).is_err());
+    }
+
+    #[test]
+    fn test_bulk_request_forms() {
+        let request: BulkRecordsRequest = serde_json::from_value(serde_json::json!({
+            "records": [{"name": "a.example.com", "record_type": "A", "value": "192.0.2.1"}]
+        }))
+        .unwrap();
+        match request {
+            BulkRecordsRequest::Create(create) => assert_eq!(create.records[0].ttl, 300),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let request: BulkRecordsRequest =
+            serde_json::from_value(serde_json::json!({"tag": "vpn", "action": "disable"})).unwrap();
+        assert!(matches!(request, BulkRecordsRequest::Tag(BulkTagRequest { action: TagAction::Disable, .. })));
+    }
+
+    #[test]
+    fn test_tag_filter() {
+        let tags = Tags(vec!["office-berlin".to_string(), "vpn".to_string()]);
+        assert!(TagFilter::default().matches(&tags));
+        let filter = |tag: &str| TagFilter {
+            tag: Some(tag.to_string()),
+            ..Default::default()
+        };
+        assert!(filter("VPN").matches(&tags));
+        assert!(filter("vpn, office-berlin").matches(&tags));
+        assert!(!filter("vpn,office-paris").matches(&tags));
+    }
+
+    #[test]
+    fn test_expiring_filter() {
+        let now = Utc::now();
+        assert!(TagFilter::default().matches_expiry(None, now));
+
+        let filter = TagFilter {
+            expiring_within_hours: Some(24),
+            ..Default::default()
+        };
+        assert!(filter.matches_expiry(Some(now + Duration::hours(2)), now));
+        assert!(!filter.matches_expiry(Some(now + Duration::days(3)), now));
+        assert!(!filter.matches_expiry(Some(now - Duration::hours(1)), now));
+        assert!(!filter.matches_expiry(None, now));
+    }
+
+    #[test]
+    fn test_update_request_expiration() {
+        let update: UpdateRecordRequest = serde_json::from_value(serde_json::json!({"ttl": 60})).unwrap();
+        assert_eq!(update.expires_at, None);
+        let update: UpdateRecordRequest = serde_json::from_value(serde_json::json!({"expires_at": null})).unwrap();
+        assert_eq!(update.expires_at, Some(None));
+        let update: UpdateRecordRequest =
+            serde_json::from_value(serde_json::json!({"expires_at": "2030-01-01T00:00:00Z"})).unwrap();
+        assert!(matches!(update.expires_at, Some(Some(_))));
     }
 
     #[test]
@@ -528,6 +1230,18 @@ mod tests {
         assert!(validate_value("", "A").is_err());
     }
 
+    #[test]
+    fn test_validate_value_txt_record() {
+        assert!(validate_value("v=spf1 -all", "TXT").is_ok());
+        assert!(validate_value(&"p".repeat(400), "TXT").is_ok());
+        assert!(validate_value(r#""v=DKIM1; p=MIIB" "IDAQAB""#, "TXT").is_ok());
+        assert_eq!(
+            validate_value(r#""unterminated"#, "TXT").unwrap_err(),
+            "Unterminated quoted string in TXT value"
+        );
+        assert!(validate_value(&format!("\"{}\"", "p".repeat(256)), "TXT").is_err());
+    }
+
     #[test]
     fn test_validate_value_aaaa_record() {
         assert!(validate_value("::1", "AAAA").is_ok());
@@ -537,10 +1251,28 @@ mod tests {
 
     #[test]
     fn test_validate_ttl() {
-        assert!(validate_ttl(0).is_ok());
-        assert!(validate_ttl(300).is_ok());
-        assert!(validate_ttl(86400).is_ok());
-        assert!(validate_ttl(-1).is_err());
+        let bounds = TtlBounds::default();
+        assert!(validate_ttl(1, &bounds).is_ok());
+        assert!(validate_ttl(300, &bounds).is_ok());
+        assert!(validate_ttl(86400, &bounds).is_ok());
+        assert!(validate_ttl(0, &bounds).is_err());
+        assert!(validate_ttl(-1, &bounds).is_err());
+        assert!(validate_ttl(i32::MAX, &bounds).is_err());
+
+        let bounds = TtlBounds { min: 0, max: 60 };
+        assert!(validate_ttl(0, &bounds).is_ok());
+        assert_eq!(
+            validate_ttl(61, &bounds).unwrap_err(),
+            "TTL must be between 0 and 60 seconds, got 61"
+        );
+    }
+
+    #[test]
+    fn test_ttl_bounds_validate() {
+        assert!(TtlBounds::default().validate().is_ok());
+        assert!(TtlBounds { min: 60, max: 60 }.validate().is_ok());
+        assert!(TtlBounds { min: -1, max: 60 }.validate().is_err());
+        assert!(TtlBounds { min: 300, max: 60 }.validate().is_err());
     }
 
     #[test]
@@ -559,8 +1291,12 @@ mod tests {
             ttl: 300,
             priority: 0,
             enabled: true,
+            tenant_id: None,
+            description: None,
+            tags: Vec::new(),
+            expires_at: None,
         };
-        assert!(valid_request.validate().is_ok());
+        assert!(valid_request.validate(&TtlBounds::default()).is_ok());
 
         let invalid_request = CreateRecordRequest {
             name: "".to_string(),
@@ -569,8 +1305,12 @@ mod tests {
             ttl: -1,
             priority: -1,
             enabled: true,
+            tenant_id: None,
+            description: None,
+            tags: Vec::new(),
+            expires_at: None,
         };
-        let result = invalid_request.validate();
+        let result = invalid_request.validate(&TtlBounds::default());
         assert!(result.is_err());
         let errors = result.unwrap_err();
         assert!(errors.errors.len() >= 3);
@@ -585,8 +1325,100 @@ mod tests {
             ttl: 300,
             priority: 0,
             enabled: true,
+            tenant_id: None,
+            description: None,
+            tags: Vec::new(),
+            expires_at: None,
         };
         let create_record = request.into_create_dns_record();
         assert_eq!(create_record.record_type, "A"); // Should be uppercase
     }
+
+    #[test]
+    fn test_record_names_normalized() {
+        let request = CreateRecordRequest {
+            name: "Bücher.Example.".to_string(),
+            record_type: "A".to_string(),
+            value: "192.168.1.1".to_string(),
+            ttl: 300,
+            priority: 0,
+            enabled: true,
+            tenant_id: None,
+            description: None,
+            tags: Vec::new(),
+            expires_at: None,
+        };
+        assert!(request.validate(&TtlBounds::default()).is_ok());
+        let create_record = request.into_create_dns_record();
+        assert_eq!(create_record.name, "xn--bcher-kva.example");
+
+        let update = UpdateRecordRequest {
+            name: Some("WWW.Example.COM.".to_string()),
+            record_type: None,
+            value: None,
+            ttl: None,
+            priority: None,
+            enabled: None,
+            description: None,
+            tags: None,
+            expires_at: None,
+        };
+        assert_eq!(update.into_update_dns_record().name.as_deref(), Some("www.example.com"));
+    }
+
+    #[test]
+    fn test_in_zones() {
+        assert!(in_zones(&None, "db.internal"));
+
+        let domains = Some(Extension(DomainScope {
+            admin_id: 1,
+            username: "web".to_string(),
+            zones: vec!["web.internal".to_string()],
+        }));
+        assert!(in_zones(&domains, "*.web.internal"));
+        assert!(in_zones(&domains, "WWW.web.internal."));
+        assert!(!in_zones(&domains, "db.internal"));
+    }
+
+    #[tokio::test]
+    async fn test_update_with_stale_if_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_url = format!("sqlite:{}?mode=rwc", dir.path().join("test.db").display());
+        let db = Arc::new(Database::new(&db_url).await.unwrap());
+        let state = RecordsState {
+            local_records: Arc::new(LocalRecordIndex::new(Some(db.clone()))),
+            cache: Arc::new(CacheManager::new()),
+            db,
+        };
+        let record = state.db.dns_records().create(CreateDnsRecord {
+            name: "www.example.com".to_string(),
+            record_type: "A".to_string(),
+            value: "192.0.2.1".to_string(),
+            ttl: 300,
+            priority: 0,
+            enabled: true,
+            tenant_id: None,
+            description: None,
+            tags: Tags::default(),
+            expires_at: None,
+        }).await.unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            axum::http::header::IF_MATCH,
+            crate::web::etag::etag_for(record.id, &record.updated_at).parse().unwrap(),
+        );
+        let update = |value: &str| {
+            Json(serde_json::from_value::<UpdateRecordRequest>(serde_json::json!({ "value": value })).unwrap())
+        };
+
+        // Both writers read the same version; only the first one lands
+        let first = update_record(State(state.clone()), None, None, Path(record.id), headers.clone(), update("192.0.2.2")).await;
+        assert!(first.is_ok(), "{:?}", first.err().map(|e| e.message));
+        let second = update_record(State(state.clone()), None, None, Path(record.id), headers, update("192.0.2.3")).await;
+        assert_eq!(second.err().unwrap().code, "CONFLICT");
+
+        let stored = state.db.dns_records().get_by_id(record.id).await.unwrap().unwrap();
+        assert_eq!(stored.value, "192.0.2.2");
+    }
 }
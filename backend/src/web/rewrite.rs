@@ -6,33 +6,44 @@
 //!
 //! - 8.8: Provide rewrite rule management interface
 //! - 8.9: Store rewrite rule configuration in database
+//!
+//! Rules can be dark-launched: a rule created with `shadow: true`, or a
+//! shadow copy of an existing rule with edits applied, is evaluated and
+//! counted without affecting answers until it is promoted.
 
 use std::sync::Arc;
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
-    Json,
+    Extension, Json,
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::db::{CreateRewriteRule, Database, RewriteRule, UpdateRewriteRule};
-use crate::dns::RewriteEngine;
-use crate::web::ApiError;
+use crate::db::{deserialize_some, CreateRewriteRule, Database, RewriteRule, UpdateRewriteRule};
+use crate::dns::{name_to_ascii, name_to_unicode, normalize_pattern, CacheManager, MatchType, RewriteEngine};
+use crate::web::etag::{check_if_match, etag_header, modified_error};
+use crate::web::records::{
+    ensure_tenant_exists, normalize_tags, visible_to, BulkTagRequest, BulkTagResponse, TagAction,
+    TagFilter,
+};
+use crate::web::{ApiError, TenantScope};
 
 /// Application state for rewrite rules API
 #[derive(Clone)]
 pub struct RewriteState {
     pub db: Arc<Database>,
     pub rewrite_engine: Arc<RewriteEngine>,
+    pub cache: Arc<CacheManager>,
 }
 
 /// Valid match types
 const VALID_MATCH_TYPES: &[&str] = &["exact", "wildcard", "regex"];
 
 /// Valid action types
-const VALID_ACTION_TYPES: &[&str] = &["map_ip", "map_domain", "block"];
+const VALID_ACTION_TYPES: &[&str] = &["map_ip", "map_domain", "block", "nodata", "passthru"];
 
 /// Validation error details
 #[derive(Debug, Serialize)]
@@ -58,6 +69,17 @@ pub struct CreateRewriteRuleRequest {
     #[serde(default = "default_enabled")]
     pub enabled: bool,
     pub description: Option<String>,
+    /// Owning tenant (ignored for tenant tokens, which always use their own)
+    #[serde(default)]
+    pub tenant_id: Option<i64>,
+    /// Create in shadow state: counted but not applied
+    #[serde(default)]
+    pub shadow: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// After this time the rule is treated as disabled
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 fn default_enabled() -> bool {
@@ -74,18 +96,42 @@ pub struct UpdateRewriteRuleRequest {
     pub priority: Option<i32>,
     pub enabled: Option<bool>,
     pub description: Option<String>,
+    /// Replaces all tags
+    pub tags: Option<Vec<String>>,
+    /// `null` removes the expiration
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub expires_at: Option<Option<DateTime<Utc>>>,
+}
+
+/// A rule as returned by the API
+#[derive(Debug, Serialize)]
+pub struct RewriteRuleView {
+    #[serde(flatten)]
+    pub rule: RewriteRule,
+    /// Pattern with punycode labels shown in Unicode
+    pub display_pattern: String,
+}
+
+impl From<RewriteRule> for RewriteRuleView {
+    fn from(rule: RewriteRule) -> Self {
+        let display_pattern = match MatchType::from_str(&rule.match_type) {
+            Some(MatchType::Regex) | None => rule.pattern.clone(),
+            Some(_) => name_to_unicode(&rule.pattern),
+        };
+        Self { rule, display_pattern }
+    }
 }
 
 /// API response wrapper for single rule
 #[derive(Debug, Serialize)]
 pub struct RewriteRuleResponse {
-    pub data: RewriteRule,
+    pub data: RewriteRuleView,
 }
 
 /// API response wrapper for multiple rules
 #[derive(Debug, Serialize)]
 pub struct RewriteRulesListResponse {
-    pub data: Vec<RewriteRule>,
+    pub data: Vec<RewriteRuleView>,
     pub total: usize,
 }
 
@@ -110,9 +156,11 @@ fn validate_pattern(pattern: &str, match_type: &str) -> Result<(), String> {
             if !pattern.contains('*') {
                 return Err("Wildcard pattern must contain at least one '*'".to_string());
             }
+            name_to_ascii(pattern)?;
         }
         "exact" => {
             // Exact patterns should be valid domain names
+            let pattern = name_to_ascii(pattern)?;
             let valid_chars = pattern.chars().all(|c| {
                 c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == '_'
             });
@@ -125,6 +173,14 @@ fn validate_pattern(pattern: &str, match_type: &str) -> Result<(), String> {
     Ok(())
 }
 
+/// Normalize a pattern for storage; unknown match types are left to validation
+pub(crate) fn store_pattern(pattern: String, match_type: &str) -> String {
+    match MatchType::from_str(match_type) {
+        Some(match_type) => normalize_pattern(pattern, match_type),
+        None => pattern,
+    }
+}
+
 /// Validate match type
 fn validate_match_type(match_type: &str) -> Result<(), String> {
     let lower = match_type.to_lowercase();
@@ -162,8 +218,8 @@ fn validate_action(action_type: &str, action_value: &Option<String>) -> Result<(
                 return Err("action_value cannot be empty for map_domain action".to_string());
             }
         }
-        "block" => {
-            // Block action doesn't require a value
+        "block" | "nodata" | "passthru" => {
+            // These actions don't take a value
         }
         _ => {}
     }
@@ -204,6 +260,13 @@ impl CreateRewriteRuleRequest {
             });
         }
 
+        if let Err(e) = normalize_tags(&self.tags) {
+            errors.push(ValidationError {
+                field: "tags".to_string(),
+                message: e,
+            });
+        }
+
         if errors.is_empty() {
             Ok(())
         } else {
@@ -214,13 +277,18 @@ impl CreateRewriteRuleRequest {
     /// Convert to CreateRewriteRule with normalized values
     pub fn into_create_rewrite_rule(self) -> CreateRewriteRule {
         CreateRewriteRule {
-            pattern: self.pattern,
+            pattern: store_pattern(self.pattern, &self.match_type),
             match_type: self.match_type.to_lowercase(),
             action_type: self.action_type.to_lowercase(),
             action_value: self.action_value,
             priority: self.priority,
             enabled: self.enabled,
             description: self.description,
+            tenant_id: self.tenant_id,
+            shadow: self.shadow,
+            shadow_of: None,
+            tags: normalize_tags(&self.tags).unwrap_or_default(),
+            expires_at: self.expires_at,
         }
     }
 }
@@ -268,6 +336,15 @@ impl UpdateRewriteRuleRequest {
             }
         }
 
+        if let Some(ref tags) = self.tags {
+            if let Err(e) = normalize_tags(tags) {
+                errors.push(ValidationError {
+                    field: "tags".to_string(),
+                    message: e,
+                });
+            }
+        }
+
         if errors.is_empty() {
             Ok(())
         } else {
@@ -276,36 +353,83 @@ impl UpdateRewriteRuleRequest {
     }
 
     /// Convert to UpdateRewriteRule with normalized values
-    pub fn into_update_rewrite_rule(self) -> UpdateRewriteRule {
+    pub fn into_update_rewrite_rule(self, existing: &RewriteRule) -> UpdateRewriteRule {
+        let match_type = self.match_type.as_deref().unwrap_or(&existing.match_type);
         UpdateRewriteRule {
-            pattern: self.pattern,
+            pattern: self.pattern.map(|p| store_pattern(p, match_type)),
             match_type: self.match_type.map(|t| t.to_lowercase()),
             action_type: self.action_type.map(|t| t.to_lowercase()),
             action_value: self.action_value,
             priority: self.priority,
             enabled: self.enabled,
             description: self.description,
+            tags: self.tags.map(|t| normalize_tags(&t).unwrap_or_default()),
+            expires_at: self.expires_at,
         }
     }
 }
 
+/// Write pending shadow hits so responses show current counts
+async fn flush_shadow_hits(state: &RewriteState) {
+    if let Err(e) = state.rewrite_engine.flush_shadow_hits().await {
+        tracing::warn!("Failed to record shadow rule hits: {}", e);
+    }
+}
+
+/// Name a rule answers for, as a cache purge pattern
+///
+/// Regex rules match no fixed name and shadow rules never answer, so
+/// neither purges cached answers.
+pub(crate) fn rule_cache_name<'a>(pattern: &'a str, match_type: &str, shadow: bool) -> Option<&'a str> {
+    (!shadow && !match_type.eq_ignore_ascii_case("regex")).then_some(pattern)
+}
+
+/// Look up a rule visible to the caller
+async fn find_rule(
+    state: &RewriteState,
+    scope: &Option<Extension<TenantScope>>,
+    id: i64,
+) -> Result<RewriteRule, ApiError> {
+    let rule = state.db.rewrite_rules().get_by_id(id).await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to get rewrite rule: {}", e),
+        details: None,
+    })?;
+
+    rule.filter(|r| visible_to(scope, r.tenant_id))
+        .ok_or_else(|| ApiError {
+            code: "NOT_FOUND".to_string(),
+            message: format!("Rewrite rule with id {} not found", id),
+            details: None,
+        })
+}
+
 /// List all rewrite rules
 ///
-/// GET /api/rewrite
+/// GET /api/rewrite?tag=created-by-script&expiring_within_hours=24
 pub async fn list_rules(
     State(state): State<RewriteState>,
+    scope: Option<Extension<TenantScope>>,
+    Query(filter): Query<TagFilter>,
 ) -> Result<impl IntoResponse, ApiError> {
+    flush_shadow_hits(&state).await;
     let repo = state.db.rewrite_rules();
 
-    let rules = repo.list().await.map_err(|e| ApiError {
+    let rules = match scope {
+        Some(Extension(scope)) => repo.list_by_tenant(scope.tenant_id).await,
+        None => repo.list().await,
+    };
+    let mut rules = rules.map_err(|e| ApiError {
         code: "INTERNAL_ERROR".to_string(),
         message: format!("Failed to list rewrite rules: {}", e),
         details: None,
     })?;
+    let now = Utc::now();
+    rules.retain(|r| filter.matches(&r.tags) && filter.matches_expiry(r.expires_at, now));
 
     Ok(Json(RewriteRulesListResponse {
         total: rules.len(),
-        data: rules,
+        data: rules.into_iter().map(RewriteRuleView::from).collect(),
     }))
 }
 
@@ -314,8 +438,10 @@ pub async fn list_rules(
 /// GET /api/rewrite/:id
 pub async fn get_rule(
     State(state): State<RewriteState>,
+    scope: Option<Extension<TenantScope>>,
     Path(id): Path<i64>,
 ) -> Result<impl IntoResponse, ApiError> {
+    flush_shadow_hits(&state).await;
     let repo = state.db.rewrite_rules();
 
     let rule = repo.get_by_id(id).await.map_err(|e| ApiError {
@@ -323,9 +449,10 @@ pub async fn get_rule(
         message: format!("Failed to get rewrite rule: {}", e),
         details: None,
     })?;
+    let rule = rule.filter(|r| visible_to(&scope, r.tenant_id));
 
     match rule {
-        Some(r) => Ok(Json(RewriteRuleResponse { data: r })),
+        Some(r) => Ok((etag_header(r.id, &r.updated_at), Json(RewriteRuleResponse { data: r.into() }))),
         None => Err(ApiError {
             code: "NOT_FOUND".to_string(),
             message: format!("Rewrite rule with id {} not found", id),
@@ -339,8 +466,15 @@ pub async fn get_rule(
 /// POST /api/rewrite
 pub async fn create_rule(
     State(state): State<RewriteState>,
-    Json(request): Json<CreateRewriteRuleRequest>,
+    scope: Option<Extension<TenantScope>>,
+    Json(mut request): Json<CreateRewriteRuleRequest>,
 ) -> Result<impl IntoResponse, ApiError> {
+    // Tenant tokens can only create rules in their own tenant
+    match scope {
+        Some(Extension(scope)) => request.tenant_id = Some(scope.tenant_id),
+        None => ensure_tenant_exists(&state.db, request.tenant_id).await?,
+    }
+
     // Validate request
     if let Err(validation_errors) = request.validate() {
         return Err(ApiError {
@@ -363,8 +497,12 @@ pub async fn create_rule(
     if let Err(e) = state.rewrite_engine.reload_rules().await {
         tracing::warn!("Failed to reload rewrite rules: {}", e);
     }
+    state
+        .cache
+        .purge_names(rule_cache_name(&rule.pattern, &rule.match_type, rule.shadow))
+        .await;
 
-    Ok((StatusCode::CREATED, Json(RewriteRuleResponse { data: rule })))
+    Ok((StatusCode::CREATED, Json(RewriteRuleResponse { data: rule.into() })))
 }
 
 /// Update a rewrite rule
@@ -372,7 +510,9 @@ pub async fn create_rule(
 /// PUT /api/rewrite/:id
 pub async fn update_rule(
     State(state): State<RewriteState>,
+    scope: Option<Extension<TenantScope>>,
     Path(id): Path<i64>,
+    headers: HeaderMap,
     Json(request): Json<UpdateRewriteRuleRequest>,
 ) -> Result<impl IntoResponse, ApiError> {
     let repo = state.db.rewrite_rules();
@@ -383,6 +523,7 @@ pub async fn update_rule(
         message: format!("Failed to get rewrite rule: {}", e),
         details: None,
     })?;
+    let existing = existing.filter(|r| visible_to(&scope, r.tenant_id));
 
     let existing = existing.ok_or_else(|| ApiError {
         code: "NOT_FOUND".to_string(),
@@ -390,6 +531,8 @@ pub async fn update_rule(
         details: None,
     })?;
 
+    let version = check_if_match(&state.db, &headers, existing.id, &existing.updated_at).await?;
+
     // Validate request against existing rule
     if let Err(validation_errors) = request.validate(&existing) {
         return Err(ApiError {
@@ -399,9 +542,9 @@ pub async fn update_rule(
         });
     }
 
-    let update_rule = request.into_update_rewrite_rule();
+    let update_rule = request.into_update_rewrite_rule(&existing);
 
-    let rule = repo.update(id, update_rule).await.map_err(|e| ApiError {
+    let rule = repo.update_at_version(id, update_rule, version).await.map_err(|e| ApiError {
         code: "INTERNAL_ERROR".to_string(),
         message: format!("Failed to update rewrite rule: {}", e),
         details: None,
@@ -411,9 +554,16 @@ pub async fn update_rule(
     if let Err(e) = state.rewrite_engine.reload_rules().await {
         tracing::warn!("Failed to reload rewrite rules: {}", e);
     }
+    let mut changed_names: Vec<&str> = Vec::new();
+    changed_names.extend(rule_cache_name(&existing.pattern, &existing.match_type, existing.shadow));
+    if let Some(ref r) = rule {
+        changed_names.extend(rule_cache_name(&r.pattern, &r.match_type, r.shadow));
+    }
+    state.cache.purge_names(changed_names).await;
 
     match rule {
-        Some(r) => Ok(Json(RewriteRuleResponse { data: r })),
+        Some(r) => Ok((etag_header(r.id, &r.updated_at), Json(RewriteRuleResponse { data: r.into() }))),
+        None if version.is_some() => Err(modified_error(None)),
         None => Err(ApiError {
             code: "NOT_FOUND".to_string(),
             message: format!("Rewrite rule with id {} not found", id),
@@ -427,11 +577,28 @@ pub async fn update_rule(
 /// DELETE /api/rewrite/:id
 pub async fn delete_rule(
     State(state): State<RewriteState>,
+    scope: Option<Extension<TenantScope>>,
     Path(id): Path<i64>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, ApiError> {
     let repo = state.db.rewrite_rules();
 
-    let deleted = repo.delete(id).await.map_err(|e| ApiError {
+    let existing = repo.get_by_id(id).await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to get rewrite rule: {}", e),
+        details: None,
+    })?;
+    let existing = existing
+        .filter(|r| visible_to(&scope, r.tenant_id))
+        .ok_or_else(|| ApiError {
+            code: "NOT_FOUND".to_string(),
+            message: format!("Rewrite rule with id {} not found", id),
+            details: None,
+        })?;
+
+    let version = check_if_match(&state.db, &headers, existing.id, &existing.updated_at).await?;
+
+    let deleted = repo.delete_at_version(id, version).await.map_err(|e| ApiError {
         code: "INTERNAL_ERROR".to_string(),
         message: format!("Failed to delete rewrite rule: {}", e),
         details: None,
@@ -442,7 +609,13 @@ pub async fn delete_rule(
         if let Err(e) = state.rewrite_engine.reload_rules().await {
             tracing::warn!("Failed to reload rewrite rules: {}", e);
         }
+        state
+            .cache
+            .purge_names(rule_cache_name(&existing.pattern, &existing.match_type, existing.shadow))
+            .await;
         Ok(StatusCode::NO_CONTENT)
+    } else if version.is_some() {
+        Err(modified_error(None))
     } else {
         Err(ApiError {
             code: "NOT_FOUND".to_string(),
@@ -452,6 +625,115 @@ pub async fn delete_rule(
     }
 }
 
+/// Create a shadow copy of a rule with edits applied
+///
+/// The copy runs next to the original without affecting answers; promoting
+/// it replaces the original.
+///
+/// POST /api/rewrite/:id/shadow
+pub async fn create_shadow_rule(
+    State(state): State<RewriteState>,
+    scope: Option<Extension<TenantScope>>,
+    Path(id): Path<i64>,
+    Json(request): Json<UpdateRewriteRuleRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let existing = find_rule(&state, &scope, id).await?;
+    if existing.shadow {
+        return Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: "Rule is already a shadow rule; edit it directly".to_string(),
+            details: None,
+        });
+    }
+
+    if let Err(validation_errors) = request.validate(&existing) {
+        return Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: "Validation failed".to_string(),
+            details: Some(serde_json::to_value(validation_errors).unwrap()),
+        });
+    }
+
+    let update = request.into_update_rewrite_rule(&existing);
+    let create_rule = CreateRewriteRule {
+        pattern: update.pattern.unwrap_or(existing.pattern),
+        match_type: update.match_type.unwrap_or(existing.match_type),
+        action_type: update.action_type.unwrap_or(existing.action_type),
+        action_value: update.action_value.or(existing.action_value),
+        priority: update.priority.unwrap_or(existing.priority),
+        enabled: update.enabled.unwrap_or(existing.enabled),
+        description: update.description.or(existing.description),
+        tenant_id: existing.tenant_id,
+        shadow: true,
+        shadow_of: Some(existing.id),
+        tags: update.tags.unwrap_or(existing.tags),
+        expires_at: update.expires_at.unwrap_or(existing.expires_at),
+    };
+
+    let rule = state.db.rewrite_rules().create(create_rule).await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to create shadow rule: {}", e),
+        details: None,
+    })?;
+
+    if let Err(e) = state.rewrite_engine.reload_rules().await {
+        tracing::warn!("Failed to reload rewrite rules: {}", e);
+    }
+
+    Ok((StatusCode::CREATED, Json(RewriteRuleResponse { data: rule.into() })))
+}
+
+/// Promote a shadow rule to active
+///
+/// POST /api/rewrite/:id/promote
+pub async fn promote_rule(
+    State(state): State<RewriteState>,
+    scope: Option<Extension<TenantScope>>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let existing = find_rule(&state, &scope, id).await?;
+    if !existing.shadow {
+        return Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: format!("Rewrite rule {} is not a shadow rule", id),
+            details: None,
+        });
+    }
+
+    let original = match existing.shadow_of {
+        Some(original_id) => state.db.rewrite_rules().get_by_id(original_id).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to get rewrite rule: {}", e),
+            details: None,
+        })?,
+        None => None,
+    };
+
+    flush_shadow_hits(&state).await;
+    let rule = state.db.rewrite_rules().promote(id).await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to promote rewrite rule: {}", e),
+        details: None,
+    })?;
+    let rule = rule.ok_or_else(|| ApiError {
+        code: "NOT_FOUND".to_string(),
+        message: format!("Rewrite rule with id {} not found", id),
+        details: None,
+    })?;
+
+    if let Err(e) = state.rewrite_engine.reload_rules().await {
+        tracing::warn!("Failed to reload rewrite rules: {}", e);
+    }
+    let mut changed_names: Vec<&str> = Vec::new();
+    changed_names.extend(rule_cache_name(&rule.pattern, &rule.match_type, rule.shadow));
+    if let Some(ref o) = original {
+        changed_names.extend(rule_cache_name(&o.pattern, &o.match_type, o.shadow));
+    }
+    state.cache.purge_names(changed_names).await;
+
+    Ok((etag_header(rule.id, &rule.updated_at), Json(RewriteRuleResponse { data: rule.into() })))
+}
+
 /// Reload rewrite rules from database
 ///
 /// POST /api/rewrite/reload
@@ -490,6 +772,15 @@ pub struct BatchCreateRequest {
     pub enabled: bool,
     /// Description for all rules
     pub description: Option<String>,
+    /// Tags for all rules
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Owning tenant (ignored for tenant tokens, which always use their own)
+    #[serde(default)]
+    pub tenant_id: Option<i64>,
+    /// Expiration for all rules
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 fn default_match_type() -> String {
@@ -515,8 +806,14 @@ pub struct BatchCreateResponse {
 /// rewrite rules for each one with the same action.
 pub async fn batch_create_rules(
     State(state): State<RewriteState>,
-    Json(request): Json<BatchCreateRequest>,
+    scope: Option<Extension<TenantScope>>,
+    Json(mut request): Json<BatchCreateRequest>,
 ) -> Result<impl IntoResponse, ApiError> {
+    match scope {
+        Some(Extension(scope)) => request.tenant_id = Some(scope.tenant_id),
+        None => ensure_tenant_exists(&state.db, request.tenant_id).await?,
+    }
+
     // Validate match_type
     if let Err(e) = validate_match_type(&request.match_type) {
         return Err(ApiError {
@@ -535,6 +832,12 @@ pub async fn batch_create_rules(
         });
     }
 
+    let tags = normalize_tags(&request.tags).map_err(|e| ApiError {
+        code: "BAD_REQUEST".to_string(),
+        message: e,
+        details: None,
+    })?;
+
     // Parse patterns (split by newline, comma, or semicolon)
     let patterns: Vec<String> = request.patterns
         .split(|c| c == '\n' || c == ',' || c == ';')
@@ -565,16 +868,27 @@ pub async fn batch_create_rules(
     let rules: Vec<crate::db::CreateRewriteRule> = patterns
         .into_iter()
         .map(|pattern| crate::db::CreateRewriteRule {
-            pattern,
+            pattern: store_pattern(pattern, &request.match_type),
             match_type: request.match_type.to_lowercase(),
             action_type: request.action_type.to_lowercase(),
             action_value: request.action_value.clone(),
             priority: request.priority,
             enabled: request.enabled,
             description: request.description.clone(),
+            tenant_id: request.tenant_id,
+            shadow: false,
+            shadow_of: None,
+            tags: tags.clone(),
+            expires_at: request.expires_at,
         })
         .collect();
 
+    let changed_names: Vec<String> = rules
+        .iter()
+        .filter_map(|r| rule_cache_name(&r.pattern, &r.match_type, r.shadow))
+        .map(str::to_string)
+        .collect();
+
     let repo = state.db.rewrite_rules();
     let created = repo.batch_create(rules).await.map_err(|e| ApiError {
         code: "INTERNAL_ERROR".to_string(),
@@ -586,6 +900,7 @@ pub async fn batch_create_rules(
     if let Err(e) = state.rewrite_engine.reload_rules().await {
         tracing::warn!("Failed to reload rewrite rules: {}", e);
     }
+    state.cache.purge_names(changed_names).await;
 
     Ok((StatusCode::CREATED, Json(BatchCreateResponse {
         created,
@@ -593,6 +908,58 @@ pub async fn batch_create_rules(
     })))
 }
 
+/// Enable, disable or delete every rule carrying a tag
+///
+/// POST /api/rewrite/bulk
+pub async fn bulk_rules_by_tag(
+    State(state): State<RewriteState>,
+    scope: Option<Extension<TenantScope>>,
+    Json(request): Json<BulkTagRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let tag = request.tag.trim().to_lowercase();
+    if tag.is_empty() {
+        return Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: "Tag cannot be empty".to_string(),
+            details: None,
+        });
+    }
+    let tenant_id = scope.map(|Extension(scope)| scope.tenant_id);
+
+    let repo = state.db.rewrite_rules();
+    let changed_names: Vec<String> = repo
+        .list()
+        .await
+        .map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to list rewrite rules: {}", e),
+            details: None,
+        })?
+        .into_iter()
+        .filter(|r| r.tags.contains(&tag) && (tenant_id.is_none() || r.tenant_id == tenant_id))
+        .filter_map(|r| rule_cache_name(&r.pattern, &r.match_type, r.shadow).map(str::to_string))
+        .collect();
+    let affected = match request.action {
+        TagAction::Enable => repo.set_enabled_by_tag(&tag, true, tenant_id).await,
+        TagAction::Disable => repo.set_enabled_by_tag(&tag, false, tenant_id).await,
+        TagAction::Delete => repo.delete_by_tag(&tag, tenant_id).await,
+    }
+    .map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to update rewrite rules: {}", e),
+        details: None,
+    })?;
+
+    if affected > 0 {
+        if let Err(e) = state.rewrite_engine.reload_rules().await {
+            tracing::warn!("Failed to reload rewrite rules: {}", e);
+        }
+        state.cache.purge_names(changed_names).await;
+    }
+
+    Ok(Json(BulkTagResponse { affected }))
+}
+
 /// Build the rewrite rules API router
 pub fn rewrite_router(state: RewriteState) -> axum::Router {
     use axum::routing::{get, post};
@@ -601,7 +968,10 @@ pub fn rewrite_router(state: RewriteState) -> axum::Router {
         .route("/", get(list_rules).post(create_rule))
         .route("/reload", post(reload_rules))
         .route("/batch", post(batch_create_rules))
+        .route("/bulk", post(bulk_rules_by_tag))
         .route("/:id", get(get_rule).put(update_rule).delete(delete_rule))
+        .route("/:id/shadow", post(create_shadow_rule))
+        .route("/:id/promote", post(promote_rule))
         .with_state(state)
 }
 
@@ -674,6 +1044,10 @@ mod tests {
             priority: 10,
             enabled: true,
             description: Some("Block ads".to_string()),
+            tenant_id: None,
+            shadow: false,
+            tags: Vec::new(),
+            expires_at: None,
         };
         assert!(valid_request.validate().is_ok());
 
@@ -685,6 +1059,10 @@ mod tests {
             priority: 0,
             enabled: true,
             description: None,
+            tenant_id: None,
+            shadow: false,
+            tags: Vec::new(),
+            expires_at: None,
         };
         let result = invalid_request.validate();
         assert!(result.is_err());
@@ -700,9 +1078,24 @@ mod tests {
             priority: 10,
             enabled: true,
             description: None,
+            tenant_id: None,
+            shadow: false,
+            tags: Vec::new(),
+            expires_at: None,
         };
         let create_rule = request.into_create_rewrite_rule();
         assert_eq!(create_rule.match_type, "wildcard");
         assert_eq!(create_rule.action_type, "block");
     }
+
+    #[test]
+    fn test_patterns_normalized() {
+        assert!(validate_pattern("Bücher.example", "exact").is_ok());
+        assert!(validate_pattern("*.münchen.de", "wildcard").is_ok());
+
+        assert_eq!(store_pattern("Bücher.Example.".to_string(), "exact"), "xn--bcher-kva.example");
+        assert_eq!(store_pattern("*.München.de".to_string(), "WILDCARD"), "*.xn--mnchen-3ya.de");
+        // Regular expressions are stored as written
+        assert_eq!(store_pattern("^Ads\\.".to_string(), "regex"), "^Ads\\.");
+    }
 }
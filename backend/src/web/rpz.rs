@@ -0,0 +1,320 @@
+//! RPZ API module
+//!
+//! Manage Response Policy Zone feeds, whose QNAME policies are imported as
+//! tagged rewrite rules and refreshed on a schedule, and import RPZ zone
+//! text by hand.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{CreateRpzFeed, Database, RpzFeed, UpdateRpzFeed};
+use crate::dns::{rpz_feed_tag, rpz_import_tag, RpzFeeds, RpzZone};
+use crate::web::{bad_request, internal_error, not_found, ApiError};
+
+/// Shortest allowed refresh interval
+const MIN_REFRESH_INTERVAL_SECS: i64 = 300;
+
+/// Application state for RPZ API
+#[derive(Clone)]
+pub struct RpzState {
+    pub db: Arc<Database>,
+    pub feeds: Arc<RpzFeeds>,
+}
+
+/// API response wrapper for single feed
+#[derive(Debug, Serialize)]
+pub struct RpzFeedResponse {
+    pub data: RpzFeed,
+}
+
+/// API response wrapper for multiple feeds
+#[derive(Debug, Serialize)]
+pub struct RpzFeedsResponse {
+    pub data: Vec<RpzFeed>,
+    pub total: usize,
+}
+
+/// Import zone text request
+#[derive(Debug, Deserialize)]
+pub struct ImportRpzRequest {
+    /// Replaces the rules of an earlier import with the same name
+    pub name: String,
+    /// Zone file text
+    pub content: String,
+    #[serde(default)]
+    pub priority: i32,
+}
+
+/// Number of rules removed
+#[derive(Debug, Serialize)]
+pub struct RemovedRulesResponse {
+    pub removed: u64,
+}
+
+/// Validate feed fields
+fn validate_feed(
+    name: Option<&str>,
+    url: Option<&str>,
+    refresh_interval_secs: Option<i64>,
+) -> Result<(), ApiError> {
+    if let Some(name) = name {
+        validate_name(name)?;
+    }
+    if let Some(url) = url {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(bad_request("URL must start with http:// or https://".to_string()));
+        }
+    }
+    if let Some(interval) = refresh_interval_secs {
+        if interval < MIN_REFRESH_INTERVAL_SECS {
+            return Err(bad_request(format!(
+                "Refresh interval must be at least {} seconds",
+                MIN_REFRESH_INTERVAL_SECS
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn validate_name(name: &str) -> Result<(), ApiError> {
+    if name.trim().is_empty() || name.len() > 100 {
+        return Err(bad_request("Name must be 1-100 characters".to_string()));
+    }
+    Ok(())
+}
+
+async fn load_feed(state: &RpzState, id: i64) -> Result<RpzFeed, ApiError> {
+    state
+        .db
+        .rpz_feeds()
+        .get_by_id(id)
+        .await
+        .map_err(|e| internal_error("Failed to get RPZ feed", e))?
+        .ok_or_else(|| not_found("RPZ feed", id))
+}
+
+/// List all RPZ feeds
+///
+/// GET /api/rpz/feeds
+pub async fn list_feeds(State(state): State<RpzState>) -> Result<impl IntoResponse, ApiError> {
+    let feeds = state
+        .db
+        .rpz_feeds()
+        .list()
+        .await
+        .map_err(|e| internal_error("Failed to list RPZ feeds", e))?;
+
+    Ok(Json(RpzFeedsResponse {
+        total: feeds.len(),
+        data: feeds,
+    }))
+}
+
+/// Get an RPZ feed by ID
+///
+/// GET /api/rpz/feeds/:id
+pub async fn get_feed(
+    State(state): State<RpzState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let feed = load_feed(&state, id).await?;
+    Ok(Json(RpzFeedResponse { data: feed }))
+}
+
+/// Create an RPZ feed
+///
+/// POST /api/rpz/feeds
+///
+/// The zone is downloaded immediately; a failed download is recorded in
+/// `last_error` and retried at the next refresh.
+pub async fn create_feed(
+    State(state): State<RpzState>,
+    Json(mut request): Json<CreateRpzFeed>,
+) -> Result<impl IntoResponse, ApiError> {
+    request.name = request.name.trim().to_string();
+    request.url = request.url.trim().to_string();
+    validate_feed(
+        Some(&request.name),
+        Some(&request.url),
+        Some(request.refresh_interval_secs),
+    )?;
+
+    let feed = state
+        .db
+        .rpz_feeds()
+        .create(request)
+        .await
+        .map_err(|e| internal_error("Failed to create RPZ feed", e))?;
+
+    if feed.enabled {
+        if let Err(e) = state.feeds.refresh(feed.id, true).await {
+            tracing::warn!("Initial download of RPZ feed '{}' failed: {}", feed.name, e);
+        }
+    }
+    let feed = load_feed(&state, feed.id).await?;
+
+    Ok((StatusCode::CREATED, Json(RpzFeedResponse { data: feed })))
+}
+
+/// Update an RPZ feed
+///
+/// PUT /api/rpz/feeds/:id
+///
+/// A new URL or priority re-imports the zone; enabling or disabling the
+/// feed switches its rules.
+pub async fn update_feed(
+    State(state): State<RpzState>,
+    Path(id): Path<i64>,
+    Json(mut request): Json<UpdateRpzFeed>,
+) -> Result<impl IntoResponse, ApiError> {
+    request.name = request.name.map(|n| n.trim().to_string());
+    request.url = request.url.map(|u| u.trim().to_string());
+    validate_feed(
+        request.name.as_deref(),
+        request.url.as_deref(),
+        request.refresh_interval_secs,
+    )?;
+
+    let existing = load_feed(&state, id).await?;
+    let feed = state
+        .db
+        .rpz_feeds()
+        .update(id, request)
+        .await
+        .map_err(|e| internal_error("Failed to update RPZ feed", e))?
+        .ok_or_else(|| not_found("RPZ feed", id))?;
+
+    if feed.url != existing.url || feed.priority != existing.priority {
+        if let Err(e) = state.feeds.refresh(id, true).await {
+            tracing::warn!("Re-import of RPZ feed '{}' failed: {}", feed.name, e);
+        }
+    }
+    if feed.enabled != existing.enabled {
+        state
+            .feeds
+            .set_enabled(id, feed.enabled)
+            .await
+            .map_err(|e| internal_error("Failed to switch RPZ feed rules", e))?;
+    }
+    let feed = load_feed(&state, id).await?;
+
+    Ok(Json(RpzFeedResponse { data: feed }))
+}
+
+/// Delete an RPZ feed and its rules
+///
+/// DELETE /api/rpz/feeds/:id
+pub async fn delete_feed(
+    State(state): State<RpzState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let deleted = state
+        .db
+        .rpz_feeds()
+        .delete(id)
+        .await
+        .map_err(|e| internal_error("Failed to delete RPZ feed", e))?;
+
+    if !deleted {
+        return Err(not_found("RPZ feed", id));
+    }
+
+    state
+        .feeds
+        .remove_rules(&rpz_feed_tag(id))
+        .await
+        .map_err(|e| internal_error("Failed to delete RPZ feed rules", e))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Download a feed now, replacing its rules even if the serial is unchanged
+///
+/// POST /api/rpz/feeds/:id/refresh
+pub async fn refresh_feed(
+    State(state): State<RpzState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    load_feed(&state, id).await?;
+
+    let result = state
+        .feeds
+        .refresh(id, true)
+        .await
+        .map_err(|e| internal_error("Failed to refresh RPZ feed", e))?;
+
+    Ok(Json(result))
+}
+
+/// Import RPZ zone text as rewrite rules
+///
+/// POST /api/rpz/import
+pub async fn import_zone(
+    State(state): State<RpzState>,
+    Json(request): Json<ImportRpzRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let name = request.name.trim();
+    validate_name(name)?;
+    let zone = RpzZone::parse(&request.content);
+    if zone.serial.is_none() && zone.rules.is_empty() {
+        return Err(bad_request("Content is not an RPZ zone file".to_string()));
+    }
+
+    let result = state
+        .feeds
+        .import(name, &zone, request.priority)
+        .await
+        .map_err(|e| internal_error("Failed to import RPZ zone", e))?;
+
+    Ok(Json(result))
+}
+
+/// Remove the rules of a hand import
+///
+/// DELETE /api/rpz/import/:name
+pub async fn remove_import(
+    State(state): State<RpzState>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let removed = state
+        .feeds
+        .remove_rules(&rpz_import_tag(name.trim()))
+        .await
+        .map_err(|e| internal_error("Failed to remove imported RPZ rules", e))?;
+
+    Ok(Json(RemovedRulesResponse { removed }))
+}
+
+/// Create RPZ router
+pub fn rpz_router(state: RpzState) -> axum::Router {
+    use axum::routing::{delete, get, post};
+
+    axum::Router::new()
+        .route("/feeds", get(list_feeds).post(create_feed))
+        .route("/feeds/:id", get(get_feed).put(update_feed).delete(delete_feed))
+        .route("/feeds/:id/refresh", post(refresh_feed))
+        .route("/import", post(import_zone))
+        .route("/import/:name", delete(remove_import))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_feed() {
+        assert!(validate_feed(Some("abuse.ch"), Some("https://example.com/rpz.zone"), Some(3600)).is_ok());
+        assert!(validate_feed(None, None, None).is_ok());
+        assert!(validate_feed(Some(" "), None, None).is_err());
+        assert!(validate_feed(None, Some("ftp://example.com/rpz.zone"), None).is_err());
+        assert!(validate_feed(None, None, Some(60)).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! Policy scripting API module
+//!
+//! Manage the per-query policy script and evaluate scripts against sample
+//! queries before activating them. Saving a script hot-reloads it into the
+//! resolver.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::db::Database;
+use crate::dns::{
+    PolicyDecision, PolicyInput, ScriptEngine, ScriptPolicy, CONFIG_KEY_POLICY_SCRIPT,
+    CONFIG_KEY_POLICY_SCRIPT_ENABLED, SCRIPT_MEMORY_LIMIT, SCRIPT_TIME_LIMIT,
+};
+use crate::web::{bad_request, internal_error, ApiError};
+
+/// Application state for scripting API
+#[derive(Clone)]
+pub struct ScriptingState {
+    pub db: Arc<Database>,
+    pub policy: Arc<ScriptPolicy>,
+}
+
+/// Policy script response
+#[derive(Debug, Serialize)]
+pub struct PolicyScriptResponse {
+    pub enabled: bool,
+    pub active: bool,
+    pub script: String,
+    pub memory_limit_bytes: usize,
+    pub time_limit_ms: u128,
+}
+
+/// Update policy script request
+#[derive(Debug, Deserialize)]
+pub struct UpdatePolicyScriptRequest {
+    pub script: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+/// Script test request
+#[derive(Debug, Deserialize)]
+pub struct TestScriptRequest {
+    /// Script to evaluate; defaults to the saved script
+    pub script: Option<String>,
+    /// Sample query
+    #[serde(flatten)]
+    pub query: PolicyInput,
+}
+
+/// Script test response
+#[derive(Debug, Serialize)]
+pub struct TestScriptResponse {
+    pub decision: PolicyDecision,
+    pub elapsed_us: u128,
+}
+
+fn settings_error(e: anyhow::Error) -> ApiError {
+    internal_error("Failed to access policy script settings", e)
+}
+
+fn script_error(message: &str, e: anyhow::Error) -> ApiError {
+    bad_request(format!("{}: {}", message, e))
+}
+
+async fn saved_script(db: &Database) -> Result<(bool, String), ApiError> {
+    let config = db.system_config();
+    let enabled = config
+        .get(CONFIG_KEY_POLICY_SCRIPT_ENABLED)
+        .await
+        .map_err(settings_error)?
+        .is_some_and(|v| v == "true");
+    let script = config
+        .get(CONFIG_KEY_POLICY_SCRIPT)
+        .await
+        .map_err(settings_error)?
+        .unwrap_or_default();
+    Ok((enabled, script))
+}
+
+/// Get the policy script
+///
+/// GET /api/scripting/policy
+pub async fn get_policy(
+    State(state): State<ScriptingState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (enabled, script) = saved_script(&state.db).await?;
+
+    Ok(Json(PolicyScriptResponse {
+        enabled,
+        active: state.policy.is_active(),
+        script,
+        memory_limit_bytes: SCRIPT_MEMORY_LIMIT,
+        time_limit_ms: SCRIPT_TIME_LIMIT.as_millis(),
+    }))
+}
+
+/// Update the policy script and hot-reload it
+///
+/// PUT /api/scripting/policy
+pub async fn update_policy(
+    State(state): State<ScriptingState>,
+    Json(request): Json<UpdatePolicyScriptRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (current_enabled, current_script) = saved_script(&state.db).await?;
+    let enabled = request.enabled.unwrap_or(current_enabled);
+    let script = request.script.unwrap_or(current_script);
+
+    // Compile before saving so a broken script never reaches the resolver
+    if enabled && script.trim().is_empty() {
+        return Err(bad_request("Cannot enable an empty policy script"));
+    }
+    if !script.trim().is_empty() {
+        ScriptEngine::compile(&script).map_err(|e| script_error("Invalid policy script", e))?;
+    }
+
+    let config = state.db.system_config();
+    config
+        .set(CONFIG_KEY_POLICY_SCRIPT, &script)
+        .await
+        .map_err(settings_error)?;
+    config
+        .set(CONFIG_KEY_POLICY_SCRIPT_ENABLED, if enabled { "true" } else { "false" })
+        .await
+        .map_err(settings_error)?;
+
+    state
+        .policy
+        .set_script(enabled.then_some(script.as_str()))
+        .map_err(|e| script_error("Invalid policy script", e))?;
+    tracing::info!("Policy script {}", if enabled { "activated" } else { "deactivated" });
+
+    Ok(Json(PolicyScriptResponse {
+        enabled,
+        active: state.policy.is_active(),
+        script,
+        memory_limit_bytes: SCRIPT_MEMORY_LIMIT,
+        time_limit_ms: SCRIPT_TIME_LIMIT.as_millis(),
+    }))
+}
+
+/// Evaluate a script against a sample query
+///
+/// POST /api/scripting/test
+pub async fn test_script(
+    State(state): State<ScriptingState>,
+    Json(request): Json<TestScriptRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let script = match request.script {
+        Some(script) => script,
+        None => saved_script(&state.db).await?.1,
+    };
+    if script.trim().is_empty() {
+        return Err(bad_request("No script to test"));
+    }
+
+    let query = request.query;
+    let result = tokio::task::spawn_blocking(move || {
+        let engine = ScriptEngine::compile(&script)?;
+        let start = Instant::now();
+        let decision = engine.evaluate(&query)?;
+        Ok::<_, anyhow::Error>((decision, start.elapsed().as_micros()))
+    })
+    .await
+    .map_err(|e| settings_error(e.into()))?;
+
+    let (decision, elapsed_us) = result.map_err(|e| script_error("Script evaluation failed", e))?;
+    Ok(Json(TestScriptResponse { decision, elapsed_us }))
+}
+
+/// Build the scripting API router
+pub fn scripting_router(state: ScriptingState) -> axum::Router {
+    use axum::routing::{get, post};
+
+    axum::Router::new()
+        .route("/policy", get(get_policy).put(update_policy))
+        .route("/test", post(test_script))
+        .with_state(state)
+}
@@ -0,0 +1,224 @@
+//! HTTP server
+//!
+//! Serves the web port and the HTTPS DoH listener with the connection limits
+//! from `AppConfig`, so a slow or misbehaving client cannot tie up the
+//! management plane: optional HTTP/2, a cap on concurrent streams per
+//! HTTP/2 connection, header and whole-request timeouts, and a request body
+//! limit.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::extract::{ConnectInfo, DefaultBodyLimit};
+use axum::Router;
+use hyper::body::Incoming;
+use hyper::service::service_fn;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tower::ServiceExt;
+use tower_http::timeout::TimeoutLayer;
+
+use crate::config::AppConfig;
+
+/// Pause after a failed accept, e.g. when out of file descriptors
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Connection and request limits for an HTTP listener
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpServerConfig {
+    /// Accept HTTP/2: prior-knowledge h2c on plain listeners, ALPN `h2` behind TLS
+    pub http2: bool,
+    /// Largest request body accepted, in bytes
+    pub max_body_size: usize,
+    /// Concurrent streams allowed on one HTTP/2 connection
+    pub max_concurrent_streams: u32,
+    /// Time allowed for an HTTP/1 client to send the request headers
+    pub header_timeout: Duration,
+    /// Time allowed for a request to be answered; 408 after that
+    pub request_timeout: Duration,
+}
+
+impl HttpServerConfig {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            http2: config.web_http2,
+            max_body_size: config.web_max_body_size,
+            max_concurrent_streams: config.web_max_concurrent_streams.max(1),
+            header_timeout: Duration::from_secs(config.web_header_timeout_secs.max(1)),
+            request_timeout: Duration::from_secs(config.web_request_timeout_secs.max(1)),
+        }
+    }
+
+    /// ALPN protocols to offer on a TLS listener, most preferred first
+    pub fn alpn_protocols(&self) -> Vec<Vec<u8>> {
+        let mut protocols = Vec::new();
+        if self.http2 {
+            protocols.push(b"h2".to_vec());
+        }
+        protocols.push(b"http/1.1".to_vec());
+        protocols
+    }
+
+    /// Apply the body limit and request timeout to a router
+    pub fn apply(&self, router: Router) -> Router {
+        router
+            .layer(DefaultBodyLimit::max(self.max_body_size))
+            .layer(TimeoutLayer::new(self.request_timeout))
+    }
+
+    fn connection_builder(&self) -> auto::Builder<TokioExecutor> {
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .header_read_timeout(self.header_timeout);
+        builder
+            .http2()
+            .timer(TokioTimer::new())
+            .max_concurrent_streams(self.max_concurrent_streams);
+        if self.http2 {
+            builder
+        } else {
+            builder.http1_only()
+        }
+    }
+
+    /// Serve one accepted connection until the client closes it
+    ///
+    /// `app` should already have [`apply`](Self::apply) applied.
+    pub async fn serve_connection<I>(&self, io: I, peer_addr: SocketAddr, app: Router)
+    where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let service = service_fn(move |mut req: hyper::Request<Incoming>| {
+            // Inject ConnectInfo extension for client IP extraction
+            req.extensions_mut().insert(ConnectInfo(peer_addr));
+            app.clone().oneshot(req)
+        });
+
+        if let Err(e) = self
+            .connection_builder()
+            .serve_connection(TokioIo::new(io), service)
+            .await
+        {
+            // Don't log connection reset errors as they're common
+            if !e.to_string().contains("connection reset") {
+                tracing::debug!("HTTP connection error from {}: {}", peer_addr, e);
+            }
+        }
+    }
+}
+
+/// Accept and serve plain HTTP connections forever
+pub async fn serve(listener: TcpListener, app: Router, config: HttpServerConfig) {
+    let app = config.apply(app);
+    loop {
+        let (stream, peer_addr) = match listener.accept().await {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::error!("TCP accept error: {}", e);
+                tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                continue;
+            }
+        };
+
+        let app = app.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            config.serve_connection(stream, peer_addr, app).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::post;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn test_config() -> HttpServerConfig {
+        HttpServerConfig {
+            http2: true,
+            max_body_size: 16,
+            max_concurrent_streams: 8,
+            header_timeout: Duration::from_secs(1),
+            request_timeout: Duration::from_millis(200),
+        }
+    }
+
+    async fn spawn_server(config: HttpServerConfig) -> SocketAddr {
+        let app = Router::new()
+            .route("/echo", post(|body: String| async move { body }))
+            .route(
+                "/slow",
+                post(|| async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    "done"
+                }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, app, config));
+        addr
+    }
+
+    async fn post_raw(addr: SocketAddr, path: &str, body: &str) -> String {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: test\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            path,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[test]
+    fn test_alpn_protocols() {
+        let mut config = test_config();
+        assert_eq!(config.alpn_protocols(), vec![b"h2".to_vec(), b"http/1.1".to_vec()]);
+        config.http2 = false;
+        assert_eq!(config.alpn_protocols(), vec![b"http/1.1".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_limits_enforced() {
+        let addr = spawn_server(test_config()).await;
+
+        let response = post_raw(addr, "/echo", "hello").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("hello"));
+
+        let response = post_raw(addr, "/echo", &"x".repeat(64)).await;
+        assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+
+        let response = post_raw(addr, "/slow", "").await;
+        assert!(response.starts_with("HTTP/1.1 408"), "{}", response);
+    }
+
+    #[tokio::test]
+    async fn test_http2_prior_knowledge() {
+        const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+        // An HTTP/2 server answers the preface with a SETTINGS frame (type 4)
+        let addr = spawn_server(test_config()).await;
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(PREFACE).await.unwrap();
+        let mut header = [0u8; 9];
+        stream.read_exact(&mut header).await.unwrap();
+        assert_eq!(header[3], 4);
+
+        // With HTTP/2 disabled the preface is rejected as a bad HTTP/1 request
+        let addr = spawn_server(HttpServerConfig { http2: false, ..test_config() }).await;
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(PREFACE).await.unwrap();
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response).await;
+        assert!(!response.starts_with(&[0, 0]));
+    }
+}
@@ -0,0 +1,675 @@
+//! Services API module
+//!
+//! A service is a record group: the A, AAAA, TXT, SRV, ... records of one
+//! application under a common domain. Services are created from record
+//! templates in a single transaction, and renaming, toggling or deleting a
+//! service applies to every member record.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{CreateDnsRecord, CreateRecordGroup, Database, DnsRecord, RecordGroup, UpdateRecordGroup};
+use crate::dns::{normalize_name, CacheManager, LocalRecordIndex};
+use crate::web::records::{
+    ensure_tenant_exists, reload_local_records, ttl_bounds, validate_name, validate_ttl, visible_to,
+    CreateRecordRequest, RecordView, TtlBounds, ValidationError, ValidationErrors,
+};
+use crate::web::{bad_request, internal_error, not_found, ApiError, TenantScope};
+
+/// Placeholder for the service domain in template values
+const DOMAIN_PLACEHOLDER: &str = "{domain}";
+
+/// Record types whose value ends with a host name
+const HOST_VALUE_TYPES: &[&str] = &["CNAME", "MX", "NS", "PTR", "SRV"];
+
+/// Application state for services API
+#[derive(Clone)]
+pub struct ServicesState {
+    pub db: Arc<Database>,
+    pub local_records: Arc<LocalRecordIndex>,
+    pub cache: Arc<CacheManager>,
+}
+
+/// One record of a service, relative to the service domain
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServiceRecordTemplate {
+    /// Label(s) in front of the domain; empty or "@" for the domain itself
+    #[serde(default)]
+    pub name: String,
+    pub record_type: String,
+    /// Record value; `{domain}` is replaced by the service domain
+    pub value: String,
+    /// Defaults to the service TTL
+    pub ttl: Option<i32>,
+    #[serde(default)]
+    pub priority: i32,
+}
+
+/// Create service request
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateServiceRequest {
+    pub name: String,
+    pub domain: String,
+    pub description: Option<String>,
+    /// Owning tenant (ignored for tenant tokens, which always use their own)
+    #[serde(default)]
+    pub tenant_id: Option<i64>,
+    #[serde(default = "default_ttl")]
+    pub ttl: i32,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub records: Vec<ServiceRecordTemplate>,
+}
+
+/// Add records to a service request
+#[derive(Debug, Clone, Deserialize)]
+pub struct AddServiceRecordsRequest {
+    #[serde(default = "default_ttl")]
+    pub ttl: i32,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub records: Vec<ServiceRecordTemplate>,
+}
+
+/// Update service request
+///
+/// A new domain renames every member record under the old domain and
+/// rewrites host values (CNAME, MX, NS, PTR, SRV targets) pointing into it.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UpdateServiceRequest {
+    pub name: Option<String>,
+    pub domain: Option<String>,
+    pub description: Option<String>,
+    /// Enables or disables every member record
+    pub enabled: Option<bool>,
+    /// Sets the TTL of every member record
+    pub ttl: Option<i32>,
+}
+
+fn default_ttl() -> i32 {
+    300
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// A service with its member records
+#[derive(Debug, Serialize)]
+pub struct ServiceView {
+    #[serde(flatten)]
+    pub group: RecordGroup,
+    pub records: Vec<RecordView>,
+}
+
+impl ServiceView {
+    fn new(group: RecordGroup, records: Vec<DnsRecord>) -> Self {
+        Self {
+            group,
+            records: records.into_iter().map(RecordView::from).collect(),
+        }
+    }
+}
+
+/// API response wrapper for single service
+#[derive(Debug, Serialize)]
+pub struct ServiceResponse {
+    pub data: ServiceView,
+}
+
+/// API response wrapper for multiple services
+#[derive(Debug, Serialize)]
+pub struct ServicesListResponse {
+    pub data: Vec<ServiceView>,
+    pub total: usize,
+}
+
+fn validation_failed(errors: Vec<ValidationError>) -> ApiError {
+    ApiError {
+        code: "BAD_REQUEST".to_string(),
+        message: "Validation failed".to_string(),
+        details: Some(serde_json::to_value(ValidationErrors { errors }).unwrap()),
+    }
+}
+
+/// Full record name of a template name under `domain`
+pub(crate) fn member_name(name: &str, domain: &str) -> String {
+    let name = name.trim().trim_end_matches('.');
+    if name.is_empty() || name == "@" {
+        domain.to_string()
+    } else {
+        format!("{}.{}", name, domain)
+    }
+}
+
+/// Expand templates into validated records under `domain`
+///
+/// Errors name the offending field as `records[i].<field>`.
+pub(crate) fn expand_templates(
+    domain: &str,
+    templates: &[ServiceRecordTemplate],
+    ttl: i32,
+    enabled: bool,
+    tenant_id: Option<i64>,
+    bounds: &TtlBounds,
+) -> Result<Vec<CreateDnsRecord>, Vec<ValidationError>> {
+    let mut records = Vec::with_capacity(templates.len());
+    let mut errors = Vec::new();
+
+    for (i, template) in templates.iter().enumerate() {
+        let request = CreateRecordRequest {
+            name: member_name(&template.name, domain),
+            record_type: template.record_type.clone(),
+            value: template.value.replace(DOMAIN_PLACEHOLDER, domain),
+            ttl: template.ttl.unwrap_or(ttl),
+            priority: template.priority,
+            enabled,
+            tenant_id,
+            description: None,
+            tags: Vec::new(),
+            expires_at: None,
+        };
+        match request.validate(bounds) {
+            Ok(()) => records.push(request.into_create_dns_record()),
+            Err(e) => errors.extend(e.errors.into_iter().map(|e| ValidationError {
+                field: format!("records[{}].{}", i, e.field),
+                message: e.message,
+            })),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(records)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Move a name under `old_domain` to `new_domain`; other names are kept
+pub(crate) fn rebase_name(name: &str, old_domain: &str, new_domain: &str) -> Option<String> {
+    let bare = name.trim_end_matches('.');
+    let lower = bare.to_ascii_lowercase();
+    let old_domain = old_domain.to_ascii_lowercase();
+    let renamed = if lower == old_domain {
+        new_domain.to_string()
+    } else {
+        let prefix = lower.strip_suffix(old_domain.as_str())?.strip_suffix('.')?;
+        // Lowercasing keeps byte offsets, so the original labels keep their case
+        format!("{}.{}", &bare[..prefix.len()], new_domain)
+    };
+    // Keep an absolute name absolute
+    Some(if name.ends_with('.') { format!("{}.", renamed) } else { renamed })
+}
+
+/// Rewrite the host name at the end of a value pointing into `old_domain`
+pub(crate) fn rebase_value(record_type: &str, value: &str, old_domain: &str, new_domain: &str) -> Option<String> {
+    if !HOST_VALUE_TYPES.contains(&record_type.to_uppercase().as_str()) {
+        return None;
+    }
+    let (head, host) = match value.trim_end().rsplit_once(char::is_whitespace) {
+        Some((head, host)) => (Some(head), host),
+        None => (None, value.trim()),
+    };
+    let host = rebase_name(host, old_domain, new_domain)?;
+    Some(match head {
+        Some(head) => format!("{} {}", head, host),
+        None => host,
+    })
+}
+
+/// Look up a service
+pub(crate) async fn load_service(db: &Database, id: i64) -> Result<RecordGroup, ApiError> {
+    db.record_groups()
+        .get_by_id(id)
+        .await
+        .map_err(|e| internal_error("Failed to get service", e))?
+        .ok_or_else(|| not_found("Service", id))
+}
+
+/// Reject a service name used by another service
+async fn ensure_name_free(db: &Database, name: &str, except: Option<i64>) -> Result<(), ApiError> {
+    let groups = db
+        .record_groups()
+        .list()
+        .await
+        .map_err(|e| internal_error("Failed to list services", e))?;
+    if groups.iter().any(|g| g.name == name && Some(g.id) != except) {
+        return Err(ApiError {
+            code: "CONFLICT".to_string(),
+            message: format!("Service '{}' already exists", name),
+            details: None,
+        });
+    }
+    Ok(())
+}
+
+fn validate_service_name(name: &str) -> Result<(), ApiError> {
+    if name.is_empty() || name.len() > 100 {
+        return Err(bad_request("Service name must be 1-100 characters".to_string()));
+    }
+    Ok(())
+}
+
+fn validate_domain(domain: &str) -> Result<(), ApiError> {
+    validate_name(domain).map_err(bad_request)?;
+    if domain.contains('*') {
+        return Err(bad_request("Service domain cannot be a wildcard".to_string()));
+    }
+    Ok(())
+}
+
+/// Apply a record change: rebuild the index and drop cached answers
+async fn apply_changes(state: &ServicesState, names: Vec<String>) {
+    reload_local_records(&state.local_records).await;
+    state.cache.purge_names(names).await;
+}
+
+/// Create a service and its records
+pub(crate) async fn create_service(
+    state: &ServicesState,
+    mut request: CreateServiceRequest,
+) -> Result<ServiceView, ApiError> {
+    request.name = request.name.trim().to_string();
+    validate_service_name(&request.name)?;
+    validate_domain(&request.domain)?;
+    let domain = normalize_name(&request.domain);
+    if request.records.is_empty() {
+        return Err(bad_request("A service needs at least one record".to_string()));
+    }
+    ensure_name_free(&state.db, &request.name, None).await?;
+
+    let bounds = ttl_bounds(&state.db).await;
+    let mut errors = Vec::new();
+    if let Err(e) = validate_ttl(request.ttl, &bounds) {
+        errors.push(ValidationError {
+            field: "ttl".to_string(),
+            message: e,
+        });
+    }
+    let records = match expand_templates(
+        &domain,
+        &request.records,
+        request.ttl,
+        request.enabled,
+        request.tenant_id,
+        &bounds,
+    ) {
+        Ok(records) if errors.is_empty() => records,
+        Ok(_) => return Err(validation_failed(errors)),
+        Err(e) => {
+            errors.extend(e);
+            return Err(validation_failed(errors));
+        }
+    };
+
+    let (group, members) = state
+        .db
+        .record_groups()
+        .create(
+            CreateRecordGroup {
+                name: request.name,
+                domain,
+                description: request.description,
+                tenant_id: request.tenant_id,
+            },
+            records,
+        )
+        .await
+        .map_err(|e| internal_error("Failed to create service", e))?;
+    apply_changes(state, members.iter().map(|r| r.name.clone()).collect()).await;
+
+    Ok(ServiceView::new(group, members))
+}
+
+/// Add templated records to a service
+pub(crate) async fn add_service_records(
+    state: &ServicesState,
+    group: &RecordGroup,
+    request: AddServiceRecordsRequest,
+) -> Result<ServiceView, ApiError> {
+    if request.records.is_empty() {
+        return Err(bad_request("A service needs at least one record".to_string()));
+    }
+    let bounds = ttl_bounds(&state.db).await;
+    let records = expand_templates(
+        &group.domain,
+        &request.records,
+        request.ttl,
+        request.enabled,
+        group.tenant_id,
+        &bounds,
+    )
+    .map_err(validation_failed)?;
+
+    let added = state
+        .db
+        .record_groups()
+        .add_records(group.id, records)
+        .await
+        .map_err(|e| internal_error("Failed to update service", e))?;
+    apply_changes(state, added.into_iter().map(|r| r.name).collect()).await;
+
+    service_view(state, group.id).await
+}
+
+/// Rename, retarget or toggle a service and its records
+pub(crate) async fn update_service(
+    state: &ServicesState,
+    group: RecordGroup,
+    request: UpdateServiceRequest,
+) -> Result<ServiceView, ApiError> {
+    let name = request.name.map(|n| n.trim().to_string());
+    if let Some(ref name) = name {
+        validate_service_name(name)?;
+        ensure_name_free(&state.db, name, Some(group.id)).await?;
+    }
+    let domain = match request.domain {
+        Some(ref domain) => {
+            validate_domain(domain)?;
+            Some(normalize_name(domain))
+        }
+        None => None,
+    };
+    if let Some(ttl) = request.ttl {
+        let bounds = ttl_bounds(&state.db).await;
+        validate_ttl(ttl, &bounds).map_err(|e| {
+            validation_failed(vec![ValidationError {
+                field: "ttl".to_string(),
+                message: e,
+            }])
+        })?;
+    }
+
+    let repo = state.db.record_groups();
+    let mut members = repo
+        .records(group.id)
+        .await
+        .map_err(|e| internal_error("Failed to get service", e))?;
+    let mut changed_names: Vec<String> = members.iter().map(|r| r.name.clone()).collect();
+    for record in &mut members {
+        if let Some(ref domain) = domain {
+            if let Some(renamed) = rebase_name(&record.name, &group.domain, domain) {
+                record.name = renamed;
+            }
+            if let Some(value) = rebase_value(&record.record_type, &record.value, &group.domain, domain) {
+                record.value = value;
+            }
+        }
+        if let Some(ttl) = request.ttl {
+            record.ttl = ttl;
+        }
+        if let Some(enabled) = request.enabled {
+            record.enabled = enabled;
+        }
+    }
+    changed_names.extend(members.iter().map(|r| r.name.clone()));
+
+    let id = group.id;
+    repo.update(
+        id,
+        UpdateRecordGroup {
+            name,
+            domain,
+            description: request.description,
+        },
+        &members,
+    )
+    .await
+    .map_err(|e| internal_error("Failed to update service", e))?
+    .ok_or_else(|| not_found("Service", id))?;
+    apply_changes(state, changed_names).await;
+
+    service_view(state, id).await
+}
+
+/// Delete a service and all of its records
+pub(crate) async fn delete_service(state: &ServicesState, group: &RecordGroup) -> Result<usize, ApiError> {
+    let repo = state.db.record_groups();
+    let members = repo
+        .records(group.id)
+        .await
+        .map_err(|e| internal_error("Failed to get service", e))?;
+    let deleted = repo
+        .delete(group.id)
+        .await
+        .map_err(|e| internal_error("Failed to delete service", e))?;
+    if !deleted {
+        return Err(not_found("Service", group.id));
+    }
+    let count = members.len();
+    apply_changes(state, members.into_iter().map(|r| r.name).collect()).await;
+
+    Ok(count)
+}
+
+/// A service with its current records
+pub(crate) async fn service_view(state: &ServicesState, id: i64) -> Result<ServiceView, ApiError> {
+    let group = load_service(&state.db, id).await?;
+    let records = state
+        .db
+        .record_groups()
+        .records(id)
+        .await
+        .map_err(|e| internal_error("Failed to get service", e))?;
+    Ok(ServiceView::new(group, records))
+}
+
+/// All services with their records
+pub(crate) async fn list_service_views(state: &ServicesState) -> Result<Vec<ServiceView>, ApiError> {
+    let groups = state
+        .db
+        .record_groups()
+        .list()
+        .await
+        .map_err(|e| internal_error("Failed to list services", e))?;
+    let mut members: HashMap<i64, Vec<DnsRecord>> = HashMap::new();
+    for record in state
+        .db
+        .dns_records()
+        .list()
+        .await
+        .map_err(|e| internal_error("Failed to list services", e))?
+    {
+        if let Some(group_id) = record.group_id {
+            members.entry(group_id).or_default().push(record);
+        }
+    }
+
+    Ok(groups
+        .into_iter()
+        .map(|g| {
+            let records = members.remove(&g.id).unwrap_or_default();
+            ServiceView::new(g, records)
+        })
+        .collect())
+}
+
+/// Look up a service visible to the caller
+async fn visible_service(
+    state: &ServicesState,
+    scope: &Option<Extension<TenantScope>>,
+    id: i64,
+) -> Result<RecordGroup, ApiError> {
+    let group = load_service(&state.db, id).await?;
+    if !visible_to(scope, group.tenant_id) {
+        return Err(not_found("Service", id));
+    }
+    Ok(group)
+}
+
+/// List all services
+///
+/// GET /api/services
+pub async fn list_services(
+    State(state): State<ServicesState>,
+    scope: Option<Extension<TenantScope>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let services: Vec<ServiceView> = list_service_views(&state)
+        .await?
+        .into_iter()
+        .filter(|s| visible_to(&scope, s.group.tenant_id))
+        .collect();
+
+    Ok(Json(ServicesListResponse {
+        total: services.len(),
+        data: services,
+    }))
+}
+
+/// Get a service
+///
+/// GET /api/services/:id
+pub async fn get_service(
+    State(state): State<ServicesState>,
+    scope: Option<Extension<TenantScope>>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    visible_service(&state, &scope, id).await?;
+    let service = service_view(&state, id).await?;
+
+    Ok(Json(ServiceResponse { data: service }))
+}
+
+/// Create a service from record templates
+///
+/// POST /api/services
+pub async fn create_service_handler(
+    State(state): State<ServicesState>,
+    scope: Option<Extension<TenantScope>>,
+    Json(mut request): Json<CreateServiceRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    // Tenant tokens can only create services in their own tenant
+    match scope {
+        Some(Extension(scope)) => request.tenant_id = Some(scope.tenant_id),
+        None => ensure_tenant_exists(&state.db, request.tenant_id).await?,
+    }
+    let service = create_service(&state, request).await?;
+
+    Ok((StatusCode::CREATED, Json(ServiceResponse { data: service })))
+}
+
+/// Update a service and its records
+///
+/// PUT /api/services/:id
+pub async fn update_service_handler(
+    State(state): State<ServicesState>,
+    scope: Option<Extension<TenantScope>>,
+    Path(id): Path<i64>,
+    Json(request): Json<UpdateServiceRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let group = visible_service(&state, &scope, id).await?;
+    let service = update_service(&state, group, request).await?;
+
+    Ok(Json(ServiceResponse { data: service }))
+}
+
+/// Add records to a service
+///
+/// POST /api/services/:id/records
+pub async fn add_service_records_handler(
+    State(state): State<ServicesState>,
+    scope: Option<Extension<TenantScope>>,
+    Path(id): Path<i64>,
+    Json(request): Json<AddServiceRecordsRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let group = visible_service(&state, &scope, id).await?;
+    let service = add_service_records(&state, &group, request).await?;
+
+    Ok(Json(ServiceResponse { data: service }))
+}
+
+/// Delete a service and all of its records
+///
+/// DELETE /api/services/:id
+pub async fn delete_service_handler(
+    State(state): State<ServicesState>,
+    scope: Option<Extension<TenantScope>>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let group = visible_service(&state, &scope, id).await?;
+    delete_service(&state, &group).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Build the services API router
+pub fn services_router(state: ServicesState) -> axum::Router {
+    use axum::routing::{get, post};
+
+    axum::Router::new()
+        .route("/", get(list_services).post(create_service_handler))
+        .route(
+            "/:id",
+            get(get_service).put(update_service_handler).delete(delete_service_handler),
+        )
+        .route("/:id/records", post(add_service_records_handler))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(name: &str, record_type: &str, value: &str) -> ServiceRecordTemplate {
+        ServiceRecordTemplate {
+            name: name.to_string(),
+            record_type: record_type.to_string(),
+            value: value.to_string(),
+            ttl: None,
+            priority: 0,
+        }
+    }
+
+    #[test]
+    fn test_expand_templates() {
+        let templates = vec![
+            template("@", "A", "10.0.0.5"),
+            template("", "TXT", "v=spf1 a:{domain} -all"),
+            template("_http._tcp", "SRV", "0 80 {domain}"),
+        ];
+        let records = expand_templates("web.lan", &templates, 600, true, Some(3), &TtlBounds::default()).unwrap();
+        let names: Vec<&str> = records.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["web.lan", "web.lan", "_http._tcp.web.lan"]);
+        assert_eq!(records[1].value, "v=spf1 a:web.lan -all");
+        assert_eq!(records[2].value, "0 80 web.lan");
+        assert!(records.iter().all(|r| r.ttl == 600 && r.tenant_id == Some(3)));
+        assert!(records[0].tags.0.is_empty());
+
+        let errors = expand_templates(
+            "web.lan",
+            &[template("@", "A", "10.0.0.5"), template("api", "AAAA", "10.0.0.6")],
+            300,
+            true,
+            None,
+            &TtlBounds::default(),
+        )
+        .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "records[1].value");
+    }
+
+    #[test]
+    fn test_rebase() {
+        assert_eq!(rebase_name("web.lan", "web.lan", "www.lan").as_deref(), Some("www.lan"));
+        assert_eq!(rebase_name("api.Web.lan", "web.lan", "www.lan").as_deref(), Some("api.www.lan"));
+        assert_eq!(rebase_name("web.lan.", "web.lan", "www.lan").as_deref(), Some("www.lan."));
+        assert_eq!(rebase_name("myweb.lan", "web.lan", "www.lan"), None);
+        assert_eq!(rebase_name("lan", "web.lan", "www.lan"), None);
+
+        assert_eq!(
+            rebase_value("SRV", "5 443 api.web.lan", "web.lan", "www.lan").as_deref(),
+            Some("5 443 api.www.lan")
+        );
+        assert_eq!(rebase_value("CNAME", "web.lan", "web.lan", "www.lan").as_deref(), Some("www.lan"));
+        assert_eq!(rebase_value("CNAME", "cdn.example.com", "web.lan", "www.lan"), None);
+        assert_eq!(rebase_value("TXT", "web.lan", "web.lan", "www.lan"), None);
+    }
+}
@@ -10,14 +10,46 @@ use axum::{
     Json,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 use crate::db::Database;
+use crate::dns::proxy::{
+    UpstreamManager, CONFIG_KEY_UPSTREAM_SOURCE_INTERFACE, CONFIG_KEY_UPSTREAM_SOURCE_IP,
+};
+use crate::dns::{
+    AnswerShuffle, BlockedClientResponse, ClientAcl, ClientAclSettings, CompanionPrefetch, CookieMode, DnsCookies, FailMode, FailPolicy, OfflineMode, OfflineResponse, OfflineSettings,
+    PrivateReverseZones, QueryLogSampler, ResolutionDeadline, RewriteEngine, SamplingMode, SamplingSettings,
+    ShuffleSettings,
+};
+use crate::i18n::{self, CONFIG_KEY_UI_LANGUAGE};
+use crate::services::log_maintenance::CONFIG_KEY_PURGE_EXPIRED_ENTRIES;
+use crate::services::update_checker::{ReleaseChannel, UpdateChecker, UpdateSettings};
+use crate::web::etag::{if_match_required, CONFIG_KEY_REQUIRE_IF_MATCH};
+use crate::web::public::{public_stats_enabled, CONFIG_KEY_PUBLIC_STATS_ENABLED};
+use crate::web::records::{
+    ttl_bounds, TtlBounds, CONFIG_KEY_RECORD_TTL_MAX, CONFIG_KEY_RECORD_TTL_MIN,
+};
+use crate::web::settings_registry::{
+    validate_update, SettingSchema, CONFIG_KEY_DISABLED_RECORD_TYPES, SETTINGS,
+};
 use crate::web::ApiError;
 
 /// Application state for settings API
 #[derive(Clone)]
 pub struct SettingsState {
     pub db: Arc<Database>,
+    pub upstream_manager: Arc<UpstreamManager>,
+    pub offline: Arc<OfflineMode>,
+    pub cookies: Arc<DnsCookies>,
+    pub log_sampling: Arc<QueryLogSampler>,
+    pub shuffle: Arc<AnswerShuffle>,
+    pub deadline: Arc<ResolutionDeadline>,
+    pub companion: Arc<CompanionPrefetch>,
+    pub private_zones: Arc<PrivateReverseZones>,
+    pub fail_policy: Arc<FailPolicy>,
+    pub client_acl: Arc<ClientAcl>,
+    pub update_checker: Arc<UpdateChecker>,
+    pub rewrite_engine: Arc<RewriteEngine>,
 }
 
 /// System settings response
@@ -29,10 +61,58 @@ pub struct SystemSettings {
     pub alert_enabled: bool,
     pub alert_webhook_url: Option<String>,
     pub alert_latency_threshold_ms: i64,
+    /// Reject record/rule/upstream writes without an If-Match header
+    pub require_if_match: bool,
+    /// Allowed TTL range for DNS records, in seconds
+    pub record_ttl_min: i32,
+    pub record_ttl_max: i32,
+    /// Default source IP for upstream queries
+    pub upstream_source_ip: Option<String>,
+    /// Default source interface for upstream queries
+    pub upstream_source_interface: Option<String>,
+    /// Offline mode: never forward upstream
+    pub offline_mode: bool,
+    /// Answer for queries that would need an upstream in offline mode
+    pub offline_response: OfflineResponse,
+    /// DNS cookie handling on the UDP listener
+    pub dns_cookies: CookieMode,
+    /// Query log sampling: mode, 1-in-N rate and always-logged latency
+    pub query_log_sampling: SamplingMode,
+    pub query_log_sample_rate: u32,
+    pub query_log_slow_ms: u64,
+    /// Shuffle address records for every name, or only for these names
+    pub shuffle_answers: bool,
+    pub shuffle_answer_domains: Vec<String>,
+    /// End-to-end budget of a client query in milliseconds (0 = unlimited)
+    pub resolution_timeout_ms: u64,
+    /// Resolve the AAAA of A misses (and vice versa) in the background
+    pub companion_prefetch: bool,
+    /// Answer reverse lookups of private addresses locally (RFC 6303)
+    pub private_reverse_zones: bool,
+    /// While rewrite rules or local records are unavailable: failopen or failclosed
+    pub fail_policy: FailMode,
+    /// Client addresses and networks whose queries are rejected
+    pub blocked_clients: Vec<String>,
+    /// Answer sent to blocked clients: refused or nxdomain
+    pub blocked_client_response: BlockedClientResponse,
+    /// Check GitHub releases for newer versions (never installs them)
+    pub update_check_enabled: bool,
+    pub update_channel: ReleaseChannel,
+    /// Serve coarse stats at /api/public/stats without authentication
+    pub public_stats_enabled: bool,
+    /// Language of API messages and alerts: auto, en or zh-CN
+    pub ui_language: String,
+    /// Rewrite evaluations taking this long are logged (0 = never)
+    pub rewrite_slow_eval_us: u64,
+    /// Delete expired records and rewrite rules in the hourly maintenance pass
+    pub purge_expired_entries: bool,
 }
 
 /// Update settings request
+///
+/// Fields mirror the settings registry, which validates them first.
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct UpdateSettingsRequest {
     /// Disabled record types
     pub disabled_record_types: Option<Vec<String>>,
@@ -40,11 +120,35 @@ pub struct UpdateSettingsRequest {
     pub alert_enabled: Option<bool>,
     pub alert_webhook_url: Option<String>,
     pub alert_latency_threshold_ms: Option<i64>,
+    pub require_if_match: Option<bool>,
+    pub record_ttl_min: Option<i32>,
+    pub record_ttl_max: Option<i32>,
+    /// Empty string clears the default source IP
+    pub upstream_source_ip: Option<String>,
+    /// Empty string clears the default source interface
+    pub upstream_source_interface: Option<String>,
+    pub offline_mode: Option<bool>,
+    pub offline_response: Option<OfflineResponse>,
+    pub dns_cookies: Option<CookieMode>,
+    pub query_log_sampling: Option<SamplingMode>,
+    pub query_log_sample_rate: Option<u32>,
+    pub query_log_slow_ms: Option<u64>,
+    pub shuffle_answers: Option<bool>,
+    pub shuffle_answer_domains: Option<Vec<String>>,
+    pub resolution_timeout_ms: Option<u64>,
+    pub companion_prefetch: Option<bool>,
+    pub private_reverse_zones: Option<bool>,
+    pub fail_policy: Option<FailMode>,
+    pub blocked_clients: Option<Vec<String>>,
+    pub blocked_client_response: Option<BlockedClientResponse>,
+    pub update_check_enabled: Option<bool>,
+    pub update_channel: Option<ReleaseChannel>,
+    pub public_stats_enabled: Option<bool>,
+    pub ui_language: Option<String>,
+    pub rewrite_slow_eval_us: Option<u64>,
+    pub purge_expired_entries: Option<bool>,
 }
 
-/// Config key for disabled record types
-const CONFIG_KEY_DISABLED_RECORD_TYPES: &str = "disabled_record_types";
-
 /// Get current system settings
 ///
 /// GET /api/settings
@@ -74,37 +178,103 @@ pub async fn get_settings(
         .and_then(|v| v.parse().ok())
         .unwrap_or(200);
 
+    let require_if_match = if_match_required(&state.db).await;
+    let ttl_bounds = ttl_bounds(&state.db).await;
+
+    let upstream_source_ip = repo.get(CONFIG_KEY_UPSTREAM_SOURCE_IP).await
+        .unwrap_or(None)
+        .filter(|v| !v.is_empty());
+    let upstream_source_interface = repo.get(CONFIG_KEY_UPSTREAM_SOURCE_INTERFACE).await
+        .unwrap_or(None)
+        .filter(|v| !v.is_empty());
+
+    let offline = state.offline.settings();
+    let sampling = state.log_sampling.settings();
+    let shuffle = state.shuffle.settings();
+    let client_acl = state.client_acl.settings();
+
+    let update = state.update_checker.settings().await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to get settings: {}", e),
+        details: None,
+    })?;
+
     Ok(Json(SystemSettings {
         disabled_record_types,
         alert_enabled,
         alert_webhook_url,
         alert_latency_threshold_ms,
+        require_if_match,
+        record_ttl_min: ttl_bounds.min,
+        record_ttl_max: ttl_bounds.max,
+        upstream_source_ip,
+        upstream_source_interface,
+        offline_mode: offline.enabled,
+        offline_response: offline.response,
+        dns_cookies: state.cookies.mode(),
+        query_log_sampling: sampling.mode,
+        query_log_sample_rate: sampling.rate,
+        query_log_slow_ms: sampling.slow_ms,
+        shuffle_answers: shuffle.enabled,
+        shuffle_answer_domains: shuffle.domains,
+        resolution_timeout_ms: state.deadline.timeout_ms(),
+        companion_prefetch: state.companion.is_enabled(),
+        private_reverse_zones: state.private_zones.is_enabled(),
+        fail_policy: state.fail_policy.mode(),
+        blocked_clients: client_acl.clients,
+        blocked_client_response: client_acl.response,
+        update_check_enabled: update.enabled,
+        update_channel: update.channel,
+        public_stats_enabled: public_stats_enabled(&state.db).await,
+        ui_language: repo
+            .get(CONFIG_KEY_UI_LANGUAGE)
+            .await
+            .unwrap_or(None)
+            .unwrap_or_else(|| "auto".to_string()),
+        rewrite_slow_eval_us: state.rewrite_engine.metrics().slow_threshold_us(),
+        purge_expired_entries: repo
+            .get(CONFIG_KEY_PURGE_EXPIRED_ENTRIES)
+            .await
+            .unwrap_or(None)
+            .unwrap_or_default()
+            == "true",
     }))
 }
 
+/// Settings schema: name, type, default and description of each setting
+///
+/// GET /api/settings/schema
+pub async fn get_settings_schema() -> Json<Vec<SettingSchema>> {
+    Json(SETTINGS.iter().map(SettingSchema::from).collect())
+}
+
 /// Update system settings
 ///
 /// PUT /api/settings
+///
+/// Unknown names and invalid values are rejected with one message per
+/// problem in `details`; nothing is saved in that case.
 pub async fn update_settings(
     State(state): State<SettingsState>,
-    Json(request): Json<UpdateSettingsRequest>,
+    Json(body): Json<Map<String, Value>>,
 ) -> Result<impl IntoResponse, ApiError> {
+    if let Err(errors) = validate_update(&body) {
+        return Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: "Validation failed".to_string(),
+            details: Some(serde_json::json!(errors)),
+        });
+    }
+    let request: UpdateSettingsRequest =
+        serde_json::from_value(Value::Object(body)).map_err(|e| ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: format!("Invalid settings: {}", e),
+            details: None,
+        })?;
+
     let repo = state.db.system_config();
 
     if let Some(disabled_types) = request.disabled_record_types {
-        // Validate record types
-        let valid_types = ["A", "AAAA", "CNAME", "MX", "TXT", "PTR", "NS", "SOA", "SRV"];
-        for t in &disabled_types {
-            let upper = t.to_uppercase();
-            if !valid_types.contains(&upper.as_str()) {
-                return Err(ApiError {
-                    code: "BAD_REQUEST".to_string(),
-                    message: format!("Invalid record type: {}", t),
-                    details: None,
-                });
-            }
-        }
-
         let value = serde_json::to_string(&disabled_types).map_err(|e| ApiError {
             code: "INTERNAL_ERROR".to_string(),
             message: format!("Failed to serialize settings: {}", e),
@@ -142,6 +312,231 @@ pub async fn update_settings(
         })?;
     }
 
+    if let Some(required) = request.require_if_match {
+        repo.set(CONFIG_KEY_REQUIRE_IF_MATCH, if required { "true" } else { "false" }).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+    }
+
+    if let Some(enabled) = request.public_stats_enabled {
+        repo.set(CONFIG_KEY_PUBLIC_STATS_ENABLED, if enabled { "true" } else { "false" }).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+    }
+
+    if let Some(enabled) = request.purge_expired_entries {
+        repo.set(CONFIG_KEY_PURGE_EXPIRED_ENTRIES, if enabled { "true" } else { "false" }).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+    }
+
+    if let Some(language) = request.ui_language {
+        repo.set(CONFIG_KEY_UI_LANGUAGE, &language).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+        i18n::set_preferred(&language);
+    }
+
+    if request.record_ttl_min.is_some() || request.record_ttl_max.is_some() {
+        let current = ttl_bounds(&state.db).await;
+        let bounds = TtlBounds {
+            min: request.record_ttl_min.unwrap_or(current.min),
+            max: request.record_ttl_max.unwrap_or(current.max),
+        };
+        bounds.validate().map_err(|e| ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: format!("Invalid record TTL bounds: {}", e),
+            details: None,
+        })?;
+        for (key, value) in [
+            (CONFIG_KEY_RECORD_TTL_MIN, bounds.min),
+            (CONFIG_KEY_RECORD_TTL_MAX, bounds.max),
+        ] {
+            repo.set(key, &value.to_string()).await.map_err(|e| ApiError {
+                code: "INTERNAL_ERROR".to_string(),
+                message: format!("Failed to save settings: {}", e),
+                details: None,
+            })?;
+        }
+    }
+
+    let source_changed = request.upstream_source_ip.is_some() || request.upstream_source_interface.is_some();
+
+    if let Some(ip) = request.upstream_source_ip {
+        let ip = ip.trim();
+        repo.set(CONFIG_KEY_UPSTREAM_SOURCE_IP, ip).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+    }
+
+    if let Some(interface) = request.upstream_source_interface {
+        let interface = interface.trim();
+        repo.set(CONFIG_KEY_UPSTREAM_SOURCE_INTERFACE, interface).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+    }
+
+    if request.offline_mode.is_some() || request.offline_response.is_some() {
+        let current = state.offline.settings();
+        let settings = OfflineSettings {
+            enabled: request.offline_mode.unwrap_or(current.enabled),
+            response: request.offline_response.unwrap_or(current.response),
+        };
+        state.offline.save_settings(settings).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+        if settings.enabled != current.enabled {
+            tracing::info!(
+                "Offline mode {}",
+                if settings.enabled { "enabled" } else { "disabled" }
+            );
+        }
+    }
+
+    if let Some(mode) = request.dns_cookies {
+        state.cookies.save_mode(mode).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+    }
+
+    if request.query_log_sampling.is_some()
+        || request.query_log_sample_rate.is_some()
+        || request.query_log_slow_ms.is_some()
+    {
+        let current = state.log_sampling.settings();
+        let settings = SamplingSettings {
+            mode: request.query_log_sampling.unwrap_or(current.mode),
+            rate: request.query_log_sample_rate.unwrap_or(current.rate),
+            slow_ms: request.query_log_slow_ms.unwrap_or(current.slow_ms),
+        };
+        state.log_sampling.save_settings(settings).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+    }
+
+    if request.shuffle_answers.is_some() || request.shuffle_answer_domains.is_some() {
+        let current = state.shuffle.settings();
+        let settings = ShuffleSettings {
+            enabled: request.shuffle_answers.unwrap_or(current.enabled),
+            domains: request
+                .shuffle_answer_domains
+                .map(|domains| {
+                    domains
+                        .iter()
+                        .map(|d| d.trim().to_string())
+                        .filter(|d| !d.is_empty())
+                        .collect()
+                })
+                .unwrap_or(current.domains),
+        };
+        state.shuffle.save_settings(settings).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+    }
+
+    if request.blocked_clients.is_some() || request.blocked_client_response.is_some() {
+        let current = state.client_acl.settings();
+        let settings = ClientAclSettings {
+            clients: request
+                .blocked_clients
+                .map(|clients| {
+                    clients
+                        .iter()
+                        .map(|c| c.trim().to_string())
+                        .filter(|c| !c.is_empty())
+                        .collect()
+                })
+                .unwrap_or(current.clients),
+            response: request.blocked_client_response.unwrap_or(current.response),
+        };
+        state.client_acl.save_settings(settings).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+    }
+
+    if let Some(timeout_ms) = request.resolution_timeout_ms {
+        state.deadline.save_timeout_ms(timeout_ms).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+    }
+
+    if let Some(enabled) = request.companion_prefetch {
+        state.companion.save_enabled(enabled).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+    }
+
+    if let Some(enabled) = request.private_reverse_zones {
+        state.private_zones.save_enabled(enabled).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+    }
+
+    if let Some(mode) = request.fail_policy {
+        state.fail_policy.save_mode(mode).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+    }
+
+    if let Some(threshold_us) = request.rewrite_slow_eval_us {
+        state.rewrite_engine.save_slow_threshold_us(threshold_us).await.map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        })?;
+    }
+
+    if request.update_check_enabled.is_some() || request.update_channel.is_some() {
+        let save_err = |e: anyhow::Error| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save settings: {}", e),
+            details: None,
+        };
+        let current = state.update_checker.settings().await.map_err(save_err)?;
+        let settings = UpdateSettings {
+            enabled: request.update_check_enabled.unwrap_or(current.enabled),
+            channel: request.update_channel.unwrap_or(current.channel),
+        };
+        state.update_checker.save_settings(settings).await.map_err(save_err)?;
+    }
+
+    // Rebuild upstream clients with the new default source
+    if source_changed {
+        if let Err(e) = state.upstream_manager.reload_from_db(&state.db).await {
+            tracing::warn!("Failed to reload upstream servers: {}", e);
+        }
+    }
+
     // Return updated settings
     get_settings(State(state)).await
 }
@@ -152,6 +547,7 @@ pub fn settings_router(state: SettingsState) -> axum::Router {
 
     axum::Router::new()
         .route("/", get(get_settings).put(update_settings))
+        .route("/schema", get(get_settings_schema))
         .route("/test-alert", axum::routing::post(test_alert))
         .with_state(state)
 }
@@ -180,9 +576,13 @@ async fn test_alert(
         }
         
         let client = reqwest::Client::new();
+        let text = i18n::localize(
+            "🔔 **Test Alert**\n\nThis is a test notification from FluxDNS.",
+            i18n::current(),
+        );
         let payload = serde_json::json!({
-            "text": "🔔 **Test Alert**\n\nThis is a test notification from FluxDNS.",
-            "content": "Test notification from FluxDNS"
+            "text": text,
+            "content": text
         });
 
         client.post(&url)
@@ -204,3 +604,20 @@ async fn test_alert(
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_matches_update_request() {
+        // Every registered setting must be accepted by the update request
+        let update: Map<String, Value> = SETTINGS
+            .iter()
+            .map(|def| (def.name.to_string(), def.default_value()))
+            .collect();
+        let request: UpdateSettingsRequest = serde_json::from_value(Value::Object(update)).unwrap();
+        assert_eq!(request.alert_latency_threshold_ms, Some(200));
+        assert_eq!(request.dns_cookies, Some(CookieMode::On));
+    }
+}
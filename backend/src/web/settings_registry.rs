@@ -0,0 +1,475 @@
+//! Typed settings registry
+//!
+//! Every setting exposed by `/api/settings` is declared here with its type,
+//! default, validator and description. Updates are checked against the
+//! registry, so a misspelled name is rejected instead of silently ignored,
+//! and the schema is served to the UI and the LLM assistant. Each setting
+//! is stored in `system_config` under its own name and applied immediately.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::db::Database;
+use crate::i18n::CONFIG_KEY_UI_LANGUAGE;
+use crate::dns::proxy::{CONFIG_KEY_UPSTREAM_SOURCE_INTERFACE, CONFIG_KEY_UPSTREAM_SOURCE_IP};
+use crate::dns::{
+    validate_interface, IpCidr, RecordType, CONFIG_KEY_BLOCKED_CLIENTS, CONFIG_KEY_BLOCKED_CLIENT_RESPONSE,
+    CONFIG_KEY_COMPANION_PREFETCH, CONFIG_KEY_DNS_COOKIES, CONFIG_KEY_FAIL_POLICY,
+    CONFIG_KEY_OFFLINE_MODE, CONFIG_KEY_OFFLINE_RESPONSE, CONFIG_KEY_PRIVATE_REVERSE_ZONES, CONFIG_KEY_QUERY_LOG_SAMPLE_RATE,
+    CONFIG_KEY_QUERY_LOG_SAMPLING, CONFIG_KEY_QUERY_LOG_SLOW_MS, CONFIG_KEY_RESOLUTION_TIMEOUT_MS,
+    CONFIG_KEY_REWRITE_SLOW_EVAL_US, CONFIG_KEY_SHUFFLE_ANSWERS, CONFIG_KEY_SHUFFLE_ANSWER_DOMAINS,
+};
+use crate::services::log_maintenance::CONFIG_KEY_PURGE_EXPIRED_ENTRIES;
+use crate::services::update_checker::{CONFIG_KEY_UPDATE_CHANNEL, CONFIG_KEY_UPDATE_CHECK_ENABLED};
+use crate::web::etag::CONFIG_KEY_REQUIRE_IF_MATCH;
+use crate::web::hooks::validate_pattern;
+use crate::web::public::CONFIG_KEY_PUBLIC_STATS_ENABLED;
+use crate::web::records::{CONFIG_KEY_RECORD_TTL_MAX, CONFIG_KEY_RECORD_TTL_MIN};
+
+/// Config key for disabled record types
+pub const CONFIG_KEY_DISABLED_RECORD_TYPES: &str = "disabled_record_types";
+
+/// Value type of a setting
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SettingType {
+    Bool,
+    Integer { min: i64, max: i64 },
+    String,
+    StringList,
+    Enum { values: &'static [&'static str] },
+}
+
+impl SettingType {
+    /// Check the JSON type (and range or allowed values) of a value
+    fn check(&self, value: &Value) -> Result<(), String> {
+        match (self, value) {
+            (Self::Bool, Value::Bool(_)) => Ok(()),
+            (Self::Integer { min, max }, Value::Number(n)) => match n.as_i64() {
+                Some(v) if (*min..=*max).contains(&v) => Ok(()),
+                _ => Err(format!("must be an integer between {} and {}", min, max)),
+            },
+            (Self::String, Value::String(_)) => Ok(()),
+            (Self::StringList, Value::Array(items)) if items.iter().all(Value::is_string) => Ok(()),
+            (Self::Enum { values }, Value::String(s)) if values.contains(&s.as_str()) => Ok(()),
+            (Self::Enum { values }, _) => Err(format!("must be one of: {}", values.join(", "))),
+            (Self::Bool, _) => Err("must be a boolean".to_string()),
+            (Self::Integer { min, max }, _) => {
+                Err(format!("must be an integer between {} and {}", min, max))
+            }
+            (Self::String, _) => Err("must be a string".to_string()),
+            (Self::StringList, _) => Err("must be a list of strings".to_string()),
+        }
+    }
+
+    /// Decode a stored `system_config` value
+    fn decode(&self, stored: &str) -> Option<Value> {
+        match self {
+            Self::Bool => Some(Value::Bool(stored == "true")),
+            Self::Integer { .. } => stored.parse::<i64>().ok().map(Value::from),
+            Self::String | Self::Enum { .. } => Some(Value::String(stored.to_string())),
+            Self::StringList => serde_json::from_str(stored).ok(),
+        }
+    }
+}
+
+/// Check of a setting value, returning the reason it is invalid
+pub type Validator = fn(&Value) -> Result<(), String>;
+
+/// A registered setting
+#[derive(Debug)]
+pub struct SettingDef {
+    /// API field name and `system_config` key
+    pub name: &'static str,
+    pub kind: SettingType,
+    /// Default value as JSON text
+    pub default: &'static str,
+    pub description: &'static str,
+    /// Checks beyond the value type
+    pub validate: Option<Validator>,
+}
+
+impl SettingDef {
+    /// Default value
+    pub fn default_value(&self) -> Value {
+        serde_json::from_str(self.default).unwrap_or(Value::Null)
+    }
+
+    /// Validate a value for this setting
+    pub fn check(&self, value: &Value) -> Result<(), String> {
+        self.kind.check(value)?;
+        match self.validate {
+            Some(validate) => validate(value),
+            None => Ok(()),
+        }
+    }
+
+    /// Current value from the database, or the default when unset
+    pub async fn current_value(&self, db: &Database) -> Value {
+        db.system_config()
+            .get(self.name)
+            .await
+            .unwrap_or(None)
+            .and_then(|v| self.kind.decode(&v))
+            .unwrap_or_else(|| self.default_value())
+    }
+}
+
+/// Schema entry served to clients
+#[derive(Debug, Serialize)]
+pub struct SettingSchema {
+    pub name: &'static str,
+    #[serde(flatten)]
+    pub kind: SettingType,
+    pub default: Value,
+    pub description: &'static str,
+}
+
+impl From<&SettingDef> for SettingSchema {
+    fn from(def: &SettingDef) -> Self {
+        Self {
+            name: def.name,
+            kind: def.kind,
+            default: def.default_value(),
+            description: def.description,
+        }
+    }
+}
+
+fn validate_record_types(value: &Value) -> Result<(), String> {
+    for t in value.as_array().into_iter().flatten().filter_map(Value::as_str) {
+        if t.parse::<RecordType>().is_err() {
+            return Err(format!("invalid record type: {}", t));
+        }
+    }
+    Ok(())
+}
+
+fn validate_patterns(value: &Value) -> Result<(), String> {
+    for pattern in value.as_array().into_iter().flatten().filter_map(Value::as_str) {
+        validate_pattern(pattern.trim())?;
+    }
+    Ok(())
+}
+
+fn validate_networks(value: &Value) -> Result<(), String> {
+    for network in value.as_array().into_iter().flatten().filter_map(Value::as_str) {
+        let network = network.trim();
+        if !network.is_empty() && network.parse::<IpCidr>().is_err() {
+            return Err(format!("invalid client network: {}", network));
+        }
+    }
+    Ok(())
+}
+
+fn validate_webhook_url(value: &Value) -> Result<(), String> {
+    let url = value.as_str().unwrap_or_default().trim();
+    if url.is_empty() || url.starts_with("http://") || url.starts_with("https://") {
+        Ok(())
+    } else {
+        Err("must be an http:// or https:// URL".to_string())
+    }
+}
+
+fn validate_source_ip(value: &Value) -> Result<(), String> {
+    let ip = value.as_str().unwrap_or_default().trim();
+    if ip.is_empty() || ip.parse::<std::net::IpAddr>().is_ok() {
+        Ok(())
+    } else {
+        Err(format!("invalid IP address: {}", ip))
+    }
+}
+
+fn validate_source_interface(value: &Value) -> Result<(), String> {
+    let interface = value.as_str().unwrap_or_default().trim();
+    if interface.is_empty() {
+        return Ok(());
+    }
+    validate_interface(interface).map_err(|e| e.to_string())
+}
+
+/// All settings accepted by `/api/settings`
+pub static SETTINGS: &[SettingDef] = &[
+    SettingDef {
+        name: CONFIG_KEY_DISABLED_RECORD_TYPES,
+        kind: SettingType::StringList,
+        default: "[]",
+        description: "Record types answered with an empty response (e.g. [\"AAAA\"] to disable IPv6)",
+        validate: Some(validate_record_types),
+    },
+    SettingDef {
+        name: "alert_enabled",
+        kind: SettingType::Bool,
+        default: "false",
+        description: "Post alerts to the webhook",
+        validate: None,
+    },
+    SettingDef {
+        name: "alert_webhook_url",
+        kind: SettingType::String,
+        default: "\"\"",
+        description: "Slack/Discord compatible webhook for alerts",
+        validate: Some(validate_webhook_url),
+    },
+    SettingDef {
+        name: "alert_latency_threshold_ms",
+        kind: SettingType::Integer { min: 1, max: 600_000 },
+        default: "200",
+        description: "Average upstream latency that raises an alert, in milliseconds",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_REQUIRE_IF_MATCH,
+        kind: SettingType::Bool,
+        default: "false",
+        description: "Reject record, rule and upstream writes without an If-Match header",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_RECORD_TTL_MIN,
+        kind: SettingType::Integer { min: 0, max: i32::MAX as i64 },
+        default: "1",
+        description: "Lowest TTL accepted for DNS records, in seconds",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_RECORD_TTL_MAX,
+        kind: SettingType::Integer { min: 0, max: i32::MAX as i64 },
+        default: "604800",
+        description: "Highest TTL accepted for DNS records, in seconds",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_UPSTREAM_SOURCE_IP,
+        kind: SettingType::String,
+        default: "\"\"",
+        description: "Default source IP for upstream queries; empty uses the system default",
+        validate: Some(validate_source_ip),
+    },
+    SettingDef {
+        name: CONFIG_KEY_UPSTREAM_SOURCE_INTERFACE,
+        kind: SettingType::String,
+        default: "\"\"",
+        description: "Default source interface for upstream queries; empty uses the system default",
+        validate: Some(validate_source_interface),
+    },
+    SettingDef {
+        name: CONFIG_KEY_OFFLINE_MODE,
+        kind: SettingType::Bool,
+        default: "false",
+        description: "Never forward upstream; answer from local records and the cache only",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_OFFLINE_RESPONSE,
+        kind: SettingType::Enum { values: &["nxdomain", "refused"] },
+        default: "\"nxdomain\"",
+        description: "Answer for queries that would need an upstream in offline mode",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_DNS_COOKIES,
+        kind: SettingType::Enum { values: &["off", "on", "enforce"] },
+        default: "\"on\"",
+        description: "DNS cookie handling on the UDP listener",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_QUERY_LOG_SAMPLING,
+        kind: SettingType::Enum { values: &["all", "sample", "notable"] },
+        default: "\"all\"",
+        description: "Queries written to the query log: all, 1 in N plus notable ones, or only errors, blocked and slow queries",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_QUERY_LOG_SAMPLE_RATE,
+        kind: SettingType::Integer { min: 1, max: 1_000_000 },
+        default: "100",
+        description: "N for query log sampling: one query in N is logged",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_QUERY_LOG_SLOW_MS,
+        kind: SettingType::Integer { min: 0, max: 600_000 },
+        default: "500",
+        description: "Queries taking at least this long are always logged when sampling, in milliseconds (0 = never)",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_SHUFFLE_ANSWERS,
+        kind: SettingType::Bool,
+        default: "false",
+        description: "Shuffle the A/AAAA records of upstream and cached answers for every name",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_SHUFFLE_ANSWER_DOMAINS,
+        kind: SettingType::StringList,
+        default: "[]",
+        description: "Names whose answers are shuffled when shuffle_answers is off (*.example.com includes subdomains)",
+        validate: Some(validate_patterns),
+    },
+    SettingDef {
+        name: CONFIG_KEY_RESOLUTION_TIMEOUT_MS,
+        kind: SettingType::Integer { min: 0, max: 60_000 },
+        default: "3000",
+        description: "Longest a client query may take across retries and fallbacks before it is answered with SERVFAIL, in milliseconds (0 = unlimited)",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_COMPANION_PREFETCH,
+        kind: SettingType::Bool,
+        default: "false",
+        description: "When an A query goes upstream, also resolve the AAAA of the name in the background (and vice versa) so the follow-up query hits the cache",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_PRIVATE_REVERSE_ZONES,
+        kind: SettingType::Bool,
+        default: "false",
+        description: "Answer reverse lookups of private addresses (10/8, 172.16/12, 192.168/16, fd00::/8) locally instead of forwarding them (RFC 6303)",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_REWRITE_SLOW_EVAL_US,
+        kind: SettingType::Integer { min: 0, max: 10_000_000 },
+        default: "5000",
+        description: "Rewrite rule evaluations taking at least this long are logged as warnings, in microseconds (0 = never)",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_FAIL_POLICY,
+        kind: SettingType::Enum { values: &["failopen", "failclosed"] },
+        default: "\"failopen\"",
+        description: "While rewrite rules or local records cannot be loaded: resolve without them (failopen) or refuse queries not in the cache (failclosed)",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_BLOCKED_CLIENTS,
+        kind: SettingType::StringList,
+        default: "[]",
+        description: "Client addresses or networks (e.g. 198.51.100.0/24) whose queries are rejected and logged with block_reason \"client\"",
+        validate: Some(validate_networks),
+    },
+    SettingDef {
+        name: CONFIG_KEY_BLOCKED_CLIENT_RESPONSE,
+        kind: SettingType::Enum { values: &["refused", "nxdomain"] },
+        default: "\"refused\"",
+        description: "Answer for queries from blocked clients",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_UPDATE_CHECK_ENABLED,
+        kind: SettingType::Bool,
+        default: "false",
+        description: "Check GitHub releases for newer versions (never installs them)",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_UPDATE_CHANNEL,
+        kind: SettingType::Enum { values: &["stable", "beta"] },
+        default: "\"stable\"",
+        description: "Release channel for update checks",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_PUBLIC_STATS_ENABLED,
+        kind: SettingType::Bool,
+        default: "false",
+        description: "Serve coarse stats at /api/public/stats without authentication",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_UI_LANGUAGE,
+        kind: SettingType::Enum { values: &["auto", "en", "zh-CN"] },
+        default: "\"auto\"",
+        description: "Language of API messages, assistant help and alerts; auto follows the Accept-Language header",
+        validate: None,
+    },
+    SettingDef {
+        name: CONFIG_KEY_PURGE_EXPIRED_ENTRIES,
+        kind: SettingType::Bool,
+        default: "false",
+        description: "Delete expired DNS records and rewrite rules in the hourly maintenance pass",
+        validate: None,
+    },
+];
+
+/// Look up a setting by name
+pub fn find_setting(name: &str) -> Option<&'static SettingDef> {
+    SETTINGS.iter().find(|def| def.name == name)
+}
+
+/// Validate a settings update, returning one message per problem
+///
+/// Null values mean "leave unchanged" and are accepted.
+pub fn validate_update(update: &Map<String, Value>) -> Result<(), Vec<String>> {
+    let errors: Vec<String> = update
+        .iter()
+        .filter(|(_, value)| !value.is_null())
+        .filter_map(|(name, value)| match find_setting(name) {
+            Some(def) => def.check(value).err().map(|e| format!("{}: {}", name, e)),
+            None => Some(format!("{}: unknown setting", name)),
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web::records::TtlBounds;
+    use serde_json::json;
+
+    #[test]
+    fn test_defaults_are_valid() {
+        for def in SETTINGS {
+            assert!(def.check(&def.default_value()).is_ok(), "{}", def.name);
+        }
+        let ttl = TtlBounds::default();
+        assert_eq!(find_setting(CONFIG_KEY_RECORD_TTL_MIN).unwrap().default_value(), json!(ttl.min));
+        assert_eq!(find_setting(CONFIG_KEY_RECORD_TTL_MAX).unwrap().default_value(), json!(ttl.max));
+        assert_eq!(
+            find_setting(CONFIG_KEY_RESOLUTION_TIMEOUT_MS).unwrap().default_value(),
+            json!(crate::dns::DEFAULT_RESOLUTION_TIMEOUT_MS)
+        );
+        assert_eq!(
+            find_setting(CONFIG_KEY_REWRITE_SLOW_EVAL_US).unwrap().default_value(),
+            json!(crate::dns::DEFAULT_REWRITE_SLOW_EVAL_US)
+        );
+    }
+
+    #[test]
+    fn test_validate_update() {
+        let update = json!({
+            "offline_mode": true,
+            "dns_cookies": "enforce",
+            "disabled_record_types": ["aaaa"],
+            "blocked_clients": ["198.51.100.0/24", "2001:db8::1"],
+            "upstream_source_ip": null,
+        });
+        assert!(validate_update(update.as_object().unwrap()).is_ok());
+
+        let update = json!({
+            "ofline_mode": true,
+            "dns_cookies": "always",
+            "alert_latency_threshold_ms": "200",
+            "disabled_record_types": ["AAAA", "BOGUS"],
+            "blocked_clients": ["198.51.100.0/33"],
+            "upstream_source_ip": "10.0.0.300",
+        });
+        let mut errors = validate_update(update.as_object().unwrap()).unwrap_err();
+        errors.sort();
+        assert_eq!(errors.len(), 6);
+        assert_eq!(errors[0], "alert_latency_threshold_ms: must be an integer between 1 and 600000");
+        assert_eq!(errors[1], "blocked_clients: invalid client network: 198.51.100.0/33");
+        assert_eq!(errors[2], "disabled_record_types: invalid record type: BOGUS");
+        assert_eq!(errors[3], "dns_cookies: must be one of: off, on, enforce");
+        assert_eq!(errors[4], "ofline_mode: unknown setting");
+        assert!(errors[5].starts_with("upstream_source_ip:"));
+    }
+}
@@ -0,0 +1,102 @@
+//! Setup API module
+//!
+//! Lists the seed profiles and applies one on demand, e.g. from a setup
+//! wizard after the first start. Applying is additive, so it can also be
+//! used to pull another profile's upstreams into an existing installation.
+
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::Deserialize;
+
+use crate::db::Database;
+use crate::dns::{OfflineMode, ProxyManager, RewriteEngine, UpstreamManager};
+use crate::services::seed::{apply_seed_profile, SeedProfile, SeedProfileInfo, CONFIG_KEY_SEED_PROFILE};
+use crate::web::ApiError;
+
+/// Application state for setup API
+#[derive(Clone)]
+pub struct SetupState {
+    pub db: Arc<Database>,
+    pub upstream_manager: Arc<UpstreamManager>,
+    pub rewrite_engine: Arc<RewriteEngine>,
+    pub proxy_manager: Arc<ProxyManager>,
+    pub offline: Arc<OfflineMode>,
+}
+
+/// Apply seed profile request
+#[derive(Debug, Deserialize)]
+pub struct ApplySeedRequest {
+    pub profile: String,
+}
+
+/// List seed profiles and the one applied on first start
+///
+/// GET /api/setup/profiles
+pub async fn list_seed_profiles(
+    State(state): State<SetupState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let applied = state
+        .db
+        .system_config()
+        .get(CONFIG_KEY_SEED_PROFILE)
+        .await
+        .unwrap_or(None)
+        .filter(|v| !v.is_empty());
+    let profiles: Vec<SeedProfileInfo> = SeedProfile::ALL.into_iter().map(SeedProfileInfo::from).collect();
+
+    Ok(Json(serde_json::json!({ "data": profiles, "applied": applied })))
+}
+
+/// Apply a seed profile
+///
+/// POST /api/setup/seed
+pub async fn apply_seed(
+    State(state): State<SetupState>,
+    Json(request): Json<ApplySeedRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let profile = SeedProfile::from_str(&request.profile).ok_or_else(|| ApiError {
+        code: "BAD_REQUEST".to_string(),
+        message: format!(
+            "Invalid profile. Must be one of: {}",
+            SeedProfile::ALL.map(|p| p.as_str()).join(", ")
+        ),
+        details: None,
+    })?;
+
+    let summary = apply_seed_profile(&state.db, profile).await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to apply seed profile: {}", e),
+        details: None,
+    })?;
+
+    // Hot reload what the profile touched
+    if let Err(e) = state.upstream_manager.reload_from_db(&state.db).await {
+        tracing::warn!("Failed to reload upstream servers: {}", e);
+    }
+    if let Err(e) = state.rewrite_engine.reload_rules().await {
+        tracing::warn!("Failed to reload rewrite rules: {}", e);
+    }
+    if let Err(e) = state.offline.load().await {
+        tracing::warn!("Failed to reload offline mode: {}", e);
+    }
+    state.proxy_manager.set_strategy(profile.strategy()).await;
+
+    tracing::info!(
+        "Applied seed profile {} ({} upstreams, {} rewrite rules created)",
+        profile,
+        summary.upstreams_created,
+        summary.rules_created
+    );
+    Ok(Json(serde_json::json!({ "data": summary })))
+}
+
+/// Build the setup API router
+pub fn setup_router(state: SetupState) -> axum::Router {
+    use axum::routing::{get, post};
+
+    axum::Router::new()
+        .route("/profiles", get(list_seed_profiles))
+        .route("/seed", post(apply_seed))
+        .with_state(state)
+}
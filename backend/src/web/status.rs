@@ -6,20 +6,35 @@
 //!
 //! - 4.6: Provide service status monitoring functionality
 
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use std::time::Instant;
 
 use axum::{
-    extract::State,
+    extract::{Query, State},
+    http::StatusCode,
     response::IntoResponse,
     Json,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
-use crate::db::Database;
-use crate::dns::CacheManager;
-use crate::dns::proxy::{ProxyManager, UpstreamManager};
+use crate::build_info::BuildInfo;
+use crate::db::{Database, DbHealthStatus};
+use crate::dns::{
+    name_to_unicode, BlockStats, CacheManager, ClientAcl, CompanionPrefetch, CompanionPrefetchStats, CookieStats, DeadlineStats, DnsCookies,
+    loop_guard, FailPolicy, FailPolicyStatus, LoopGuardStatus, PolicyCounts,
+    PolicySource, PolicyStats, PolicyWindows, ResolutionDeadline, RewriteEngine,
+};
+use crate::dns::proxy::{
+    connection_manager, ConnectionStats, FailoverEvent, ProxyManager, QueryLimiterStats, UpstreamManager, UpstreamServer,
+    UpstreamStats, WarmUpStatus,
+};
+use crate::services::listener_manager::{ListenerFailure, ListenerManager};
+use crate::services::update_checker::{UpdateChecker, UpdateStatus};
+use crate::web::access_log::{ApiAccessEntry, ApiAccessFilter, ApiAccessLog};
+use crate::web::topology::HttpEndpoint;
+use crate::web::upstreams::escape_label;
 use crate::web::ApiError;
 
 /// Application state for status API
@@ -30,6 +45,17 @@ pub struct StatusState {
     pub proxy_manager: Arc<ProxyManager>,
     pub upstream_manager: Arc<UpstreamManager>,
     pub start_time: Arc<RwLock<Instant>>,
+    pub cookies: Arc<DnsCookies>,
+    pub update_checker: Arc<UpdateChecker>,
+    pub listener_manager: Arc<ListenerManager>,
+    pub http_endpoints: Arc<Vec<HttpEndpoint>>,
+    pub policy_stats: Arc<PolicyStats>,
+    pub deadline: Arc<ResolutionDeadline>,
+    pub companion: Arc<CompanionPrefetch>,
+    pub fail_policy: Arc<FailPolicy>,
+    pub client_acl: Arc<ClientAcl>,
+    pub rewrite_engine: Arc<RewriteEngine>,
+    pub api_log: Arc<ApiAccessLog>,
 }
 
 /// System status response
@@ -41,17 +67,55 @@ pub struct SystemStatusResponse {
     pub query: QueryStatusInfo,
     pub upstreams: UpstreamsStatusInfo,
     pub strategy: String,
+    /// HTTP listeners serving the UI, API and DoH
+    pub http_endpoints: Vec<HttpEndpoint>,
+    /// DNS cookie counters, including validation failures
+    pub cookies: CookieStats,
+    /// Resolution budget and queries that ran out of it
+    pub deadline: DeadlineStats,
+    /// A/AAAA companion prefetches and how many were used
+    pub companion_prefetch: CompanionPrefetchStats,
+    /// Queries rejected for their client versus blocked for their name
+    pub blocks: BlockStats,
+    /// Pooled upstream connections and QUIC endpoints
+    pub connections: ConnectionStats,
+    /// Upstream queries in flight and overload shedding
+    pub upstream_queries: QueryLimiterStats,
+    /// Last release check, None when update checks are disabled
+    pub update: Option<UpdateStatus>,
+    /// Database write health; query logging pauses while degraded
+    pub database: DbHealthStatus,
+    /// Fail-open / fail-closed policy and whether it is in effect
+    pub fail_policy: FailPolicyStatus,
+    /// Queries that came back to this server through a forwarding loop
+    pub forwarding_loops: LoopGuardStatus,
+    /// Estimated cache memory and the process resident size
+    pub memory: MemoryStatusInfo,
 }
 
 /// Cache status information
 #[derive(Debug, Serialize)]
 pub struct CacheStatusInfo {
+    pub backend: String,
     pub entries: usize,
     pub hits: u64,
     pub misses: u64,
     pub hit_rate: f64,
     pub default_ttl: u64,
     pub max_entries: usize,
+    /// Estimated memory held by cached entries
+    pub memory_bytes: usize,
+    /// Memory budget in MiB, 0 for no limit
+    pub max_memory_mb: usize,
+}
+
+/// Memory usage
+#[derive(Debug, Serialize)]
+pub struct MemoryStatusInfo {
+    /// Estimated bytes held by the DNS cache
+    pub cache_bytes: usize,
+    /// Resident set size of the process, where the OS reports it
+    pub resident_bytes: Option<u64>,
 }
 
 /// Query status information
@@ -66,8 +130,13 @@ pub struct QueryStatusInfo {
 #[derive(Debug, Serialize)]
 pub struct UpstreamsStatusInfo {
     pub total: usize,
+    /// Healthy servers taking queries
     pub healthy: usize,
+    /// Servers in maintenance
+    pub drained: usize,
     pub servers: Vec<UpstreamStatusInfo>,
+    /// Last latency warm-up of servers without stats
+    pub warm_up: Option<WarmUpStatus>,
 }
 
 /// Individual upstream server status
@@ -78,12 +147,89 @@ pub struct UpstreamStatusInfo {
     pub address: String,
     pub protocol: String,
     pub enabled: bool,
+    pub drained: bool,
     pub healthy: bool,
     pub queries: u64,
     pub failures: u64,
     pub avg_response_time_ms: u64,
 }
 
+/// Compact upstream health for the dashboard header
+#[derive(Debug, Serialize)]
+pub struct UpstreamSummaryResponse {
+    pub strategy: String,
+    pub total: usize,
+    pub healthy: usize,
+    pub unhealthy: usize,
+    /// Servers in maintenance, counted apart from healthy and unhealthy
+    pub drained: usize,
+    /// Counts per protocol
+    pub groups: Vec<UpstreamGroupSummary>,
+    /// Most recent failover since startup
+    pub last_failover: Option<FailoverEvent>,
+    /// Slowest server by average response time, None before any query
+    pub worst_latency: Option<UpstreamLatency>,
+}
+
+/// Upstream health counts of one protocol
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct UpstreamGroupSummary {
+    pub group: String,
+    pub total: usize,
+    pub healthy: usize,
+    pub unhealthy: usize,
+    pub drained: usize,
+}
+
+/// Average response time of one server
+#[derive(Debug, Serialize)]
+pub struct UpstreamLatency {
+    pub id: i64,
+    pub name: String,
+    pub protocol: String,
+    pub avg_response_time_ms: u64,
+}
+
+/// Blocked, remapped and locally answered queries
+#[derive(Debug, Serialize)]
+pub struct PolicyStatusResponse {
+    pub last_hour: PolicyCounts,
+    pub last_day: PolicyCounts,
+    pub by_rule: Vec<PolicyRuleStats>,
+    pub by_category: Vec<PolicyCategoryStats>,
+    pub by_group: Vec<PolicyGroupStats>,
+}
+
+/// Policy counts for one rule or filtering middleware
+#[derive(Debug, Serialize)]
+pub struct PolicyRuleStats {
+    /// `rewrite`, `local_record` or the middleware name
+    pub source: String,
+    pub rule_id: Option<i64>,
+    /// Rewrite rule pattern, None once the rule is deleted
+    pub pattern: Option<String>,
+    #[serde(flatten)]
+    pub counts: PolicyWindows,
+}
+
+/// Policy counts for one domain category
+#[derive(Debug, Serialize)]
+pub struct PolicyCategoryStats {
+    pub category: String,
+    #[serde(flatten)]
+    pub counts: PolicyWindows,
+}
+
+/// Policy counts for one client group
+#[derive(Debug, Serialize)]
+pub struct PolicyGroupStats {
+    /// None for clients outside every tenant
+    pub tenant_id: Option<i64>,
+    pub tenant_name: Option<String>,
+    #[serde(flatten)]
+    pub counts: PolicyWindows,
+}
+
 /// Health check response
 #[derive(Debug, Serialize)]
 pub struct HealthCheckResponse {
@@ -93,6 +239,15 @@ pub struct HealthCheckResponse {
     pub upstreams: bool,
 }
 
+/// Readiness probe response
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    pub database: bool,
+    /// Listeners that crashed and could not be restarted
+    pub failed_listeners: Vec<ListenerFailure>,
+}
+
 /// Get system status
 ///
 /// GET /api/status
@@ -123,8 +278,9 @@ pub async fn system_status(
 
     let upstream_stats = state.upstream_manager.get_all_stats().await;
     let healthy_count = servers.iter().filter(|s| {
-        s.enabled && upstream_stats.get(&s.id).map(|st| st.is_healthy()).unwrap_or(true)
+        s.enabled && !s.drained && upstream_stats.get(&s.id).map(|st| st.is_healthy()).unwrap_or(true)
     }).count();
+    let drained_count = servers.iter().filter(|s| s.enabled && s.drained).count();
 
     let upstream_servers: Vec<UpstreamStatusInfo> = servers
         .into_iter()
@@ -136,6 +292,7 @@ pub async fn system_status(
                 address: s.address,
                 protocol: s.protocol,
                 enabled: s.enabled,
+                drained: s.drained,
                 healthy: stats.map(|st| st.is_healthy()).unwrap_or(s.enabled),
                 queries: stats.map(|st| st.queries).unwrap_or(0),
                 failures: stats.map(|st| st.failures).unwrap_or(0),
@@ -147,16 +304,23 @@ pub async fn system_status(
     // Get current strategy
     let strategy = state.proxy_manager.get_strategy().await;
 
+    let database = state.db.health().status();
+    let fail_policy = state.fail_policy.status(state.rewrite_engine.load_failure());
+    let degraded = database.degraded || fail_policy.degraded;
+
     Ok(Json(SystemStatusResponse {
-        status: "running".to_string(),
+        status: if degraded { "degraded" } else { "running" }.to_string(),
         uptime_seconds,
         cache: CacheStatusInfo {
+            hit_rate: cache_stats.hit_rate(),
+            backend: cache_stats.backend,
             entries: cache_stats.entries,
             hits: cache_stats.hits,
             misses: cache_stats.misses,
-            hit_rate: cache_stats.hit_rate(),
             default_ttl: cache_config.default_ttl,
             max_entries: cache_config.max_entries,
+            memory_bytes: cache_stats.memory_bytes,
+            max_memory_mb: cache_config.max_memory_mb,
         },
         query: QueryStatusInfo {
             total_queries: query_stats.total_queries,
@@ -166,12 +330,307 @@ pub async fn system_status(
         upstreams: UpstreamsStatusInfo {
             total: upstream_servers.len(),
             healthy: healthy_count,
+            drained: drained_count,
             servers: upstream_servers,
+            warm_up: state.upstream_manager.last_warm_up().await,
         },
         strategy: strategy.as_str().to_string(),
+        http_endpoints: state.http_endpoints.as_ref().clone(),
+        cookies: state.cookies.stats(),
+        deadline: state.deadline.stats(),
+        companion_prefetch: state.companion.stats(),
+        blocks: state.client_acl.stats(),
+        connections: connection_manager().stats(),
+        upstream_queries: state.proxy_manager.limiter().stats(),
+        update: state.update_checker.status(),
+        database,
+        fail_policy,
+        forwarding_loops: loop_guard().status(),
+        memory: MemoryStatusInfo {
+            cache_bytes: cache_stats.memory_bytes,
+            resident_bytes: resident_memory_bytes(),
+        },
     }))
 }
 
+/// Upstream health overview
+///
+/// GET /api/status/upstream-summary
+///
+/// Built from the in-memory servers and health stats only, so it is cheap
+/// enough to poll from every page.
+pub async fn upstream_summary(State(state): State<StatusState>) -> Json<UpstreamSummaryResponse> {
+    let servers = state.upstream_manager.get_servers().await;
+    let stats = state.upstream_manager.get_all_stats().await;
+    let mut summary = summarize_upstreams(&servers, &stats);
+    summary.strategy = state.proxy_manager.get_strategy().await.as_str().to_string();
+    summary.last_failover = state.upstream_manager.last_failover().await;
+    Json(summary)
+}
+
+/// Health counts and the slowest server of the loaded upstreams
+fn summarize_upstreams(servers: &[UpstreamServer], stats: &HashMap<i64, UpstreamStats>) -> UpstreamSummaryResponse {
+    let mut groups: BTreeMap<&str, UpstreamGroupSummary> = BTreeMap::new();
+    let mut worst_latency: Option<UpstreamLatency> = None;
+
+    for server in servers {
+        let group = groups.entry(server.protocol.as_str()).or_insert_with(|| UpstreamGroupSummary {
+            group: server.protocol.as_str().to_string(),
+            ..Default::default()
+        });
+        group.total += 1;
+        let server_stats = stats.get(&server.id);
+        if server.drained {
+            group.drained += 1;
+        } else if server_stats.map(|st| st.is_healthy()).unwrap_or(true) {
+            group.healthy += 1;
+        } else {
+            group.unhealthy += 1;
+        }
+
+        let Some(server_stats) = server_stats.filter(|st| st.queries > 0) else {
+            continue;
+        };
+        let avg = server_stats.avg_response_time_ms();
+        let slower = match worst_latency {
+            Some(ref worst) => avg > worst.avg_response_time_ms,
+            None => true,
+        };
+        if slower {
+            worst_latency = Some(UpstreamLatency {
+                id: server.id,
+                name: server.name.clone(),
+                protocol: server.protocol.as_str().to_string(),
+                avg_response_time_ms: avg,
+            });
+        }
+    }
+
+    let groups: Vec<UpstreamGroupSummary> = groups.into_values().collect();
+    UpstreamSummaryResponse {
+        strategy: String::new(),
+        total: servers.len(),
+        healthy: groups.iter().map(|g| g.healthy).sum(),
+        unhealthy: groups.iter().map(|g| g.unhealthy).sum(),
+        drained: groups.iter().map(|g| g.drained).sum(),
+        groups,
+        last_failover: None,
+        worst_latency,
+    }
+}
+
+/// Resident set size from `/proc/self/status` (Linux only)
+fn resident_memory_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    parse_vm_rss(&status)
+}
+
+/// `VmRSS` line of a proc status file, in bytes
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    let kb = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+/// Policy counters for the last hour and day
+///
+/// GET /api/status/policy
+pub async fn policy_status(
+    State(state): State<StatusState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let snapshot = state.policy_stats.snapshot();
+
+    let rules = state.db.rewrite_rules().list().await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to get rewrite rules: {}", e),
+        details: None,
+    })?;
+    let tenants = state.db.tenants().list().await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to get tenants: {}", e),
+        details: None,
+    })?;
+
+    let by_rule = snapshot
+        .by_rule
+        .into_iter()
+        .map(|(source, counts)| {
+            let rule_id = match source {
+                PolicySource::Rewrite(id) => Some(id),
+                _ => None,
+            };
+            let pattern = rule_id
+                .and_then(|id| rules.iter().find(|r| r.id == id))
+                .map(|r| name_to_unicode(&r.pattern));
+            PolicyRuleStats {
+                source: source.kind().to_string(),
+                rule_id,
+                pattern,
+                counts,
+            }
+        })
+        .collect();
+
+    let by_category = snapshot
+        .by_category
+        .into_iter()
+        .map(|(category, counts)| PolicyCategoryStats { category, counts })
+        .collect();
+
+    let by_group = snapshot
+        .by_tenant
+        .into_iter()
+        .map(|(tenant_id, counts)| PolicyGroupStats {
+            tenant_id,
+            tenant_name: tenant_id
+                .and_then(|id| tenants.iter().find(|t| t.id == id))
+                .map(|t| t.name.clone()),
+            counts,
+        })
+        .collect();
+
+    Ok(Json(PolicyStatusResponse {
+        last_hour: snapshot.total.last_hour,
+        last_day: snapshot.total.last_day,
+        by_rule,
+        by_category,
+        by_group,
+    }))
+}
+
+/// Default number of rules and categories with their own block counter
+const DEFAULT_METRICS_TOP: usize = 20;
+/// Most rules and categories with their own block counter
+const MAX_METRICS_TOP: usize = 200;
+
+/// Policy metrics query parameters
+#[derive(Debug, Deserialize)]
+pub struct PolicyMetricsParams {
+    /// Rules and categories with their own series; the rest are summed up
+    /// as "other" (default 20)
+    pub top: Option<usize>,
+}
+
+/// Block counters since startup in the Prometheus text format
+///
+/// Only the busiest rules and categories get their own series, keeping
+/// the label cardinality bounded however many rules are loaded.
+///
+/// GET /api/status/metrics?top=20
+pub async fn policy_metrics(
+    State(state): State<StatusState>,
+    Query(params): Query<PolicyMetricsParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let top = params.top.unwrap_or(DEFAULT_METRICS_TOP).clamp(1, MAX_METRICS_TOP);
+    let (rules, categories) = state.policy_stats.top_blocks(top);
+    let patterns: HashMap<i64, String> = state
+        .db
+        .rewrite_rules()
+        .list()
+        .await
+        .map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to get rewrite rules: {}", e),
+            details: None,
+        })?
+        .into_iter()
+        .map(|r| (r.id, r.pattern))
+        .collect();
+
+    let mut out = String::new();
+    out.push_str("# HELP fluxdns_rule_blocks_total Queries blocked by a rule since startup\n");
+    out.push_str("# TYPE fluxdns_rule_blocks_total counter\n");
+    for (source, count) in &rules.top {
+        let (rule, pattern) = match source {
+            PolicySource::Rewrite(id) => (id.to_string(), patterns.get(id).cloned().unwrap_or_default()),
+            other => (other.kind().to_string(), String::new()),
+        };
+        out.push_str(&format!(
+            "fluxdns_rule_blocks_total{{source=\"{}\",rule=\"{}\",pattern=\"{}\"}} {}\n",
+            escape_label(source.kind()),
+            escape_label(&rule),
+            escape_label(&pattern),
+            count
+        ));
+    }
+    out.push_str(&format!(
+        "fluxdns_rule_blocks_total{{source=\"other\",rule=\"other\",pattern=\"\"}} {}\n",
+        rules.other
+    ));
+
+    out.push_str("# HELP fluxdns_category_blocks_total Queries blocked by domain category since startup\n");
+    out.push_str("# TYPE fluxdns_category_blocks_total counter\n");
+    for (category, count) in &categories.top {
+        out.push_str(&format!(
+            "fluxdns_category_blocks_total{{category=\"{}\"}} {}\n",
+            escape_label(category),
+            count
+        ));
+    }
+    out.push_str(&format!(
+        "fluxdns_category_blocks_total{{category=\"other\"}} {}\n",
+        categories.other
+    ));
+
+    Ok(([(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")], out))
+}
+
+/// Rewrite engine evaluation metrics
+///
+/// GET /api/status/rewrite
+pub async fn rewrite_status(State(state): State<StatusState>) -> impl IntoResponse {
+    Json(state.rewrite_engine.eval_stats().await)
+}
+
+/// API access log query parameters
+#[derive(Debug, Deserialize)]
+pub struct ApiLogParams {
+    /// Entries to return, newest first (default 100)
+    pub limit: Option<usize>,
+    /// Caller: admin user name, `token:<name>` or `tenant:<name>`
+    pub user: Option<String>,
+    /// Path prefix, e.g. `/api/records`
+    pub path: Option<String>,
+    /// Lowest status code, e.g. 400 for failed requests only
+    pub min_status: Option<u16>,
+}
+
+/// API access log response
+#[derive(Debug, Serialize)]
+pub struct ApiLogResponse {
+    pub data: Vec<ApiAccessEntry>,
+    /// Entries kept in memory
+    pub capacity: usize,
+    /// Requests recorded since startup
+    pub recorded: u64,
+}
+
+/// Recent management API requests
+///
+/// GET /api/status/api-log?limit=100&user=admin&path=/api/records&min_status=400
+pub async fn api_log(
+    State(state): State<StatusState>,
+    Query(params): Query<ApiLogParams>,
+) -> impl IntoResponse {
+    let filter = ApiAccessFilter {
+        user: params.user.filter(|u| !u.is_empty()),
+        path: params.path.filter(|p| !p.is_empty()),
+        min_status: params.min_status,
+    };
+    let limit = params.limit.unwrap_or(100).clamp(1, state.api_log.capacity());
+    Json(ApiLogResponse {
+        data: state.api_log.entries(&filter, limit),
+        capacity: state.api_log.capacity(),
+        recorded: state.api_log.recorded(),
+    })
+}
+
 /// Health check endpoint
 ///
 /// GET /api/health
@@ -184,11 +643,13 @@ pub async fn health_check(
     // Check cache (always healthy if it exists)
     let cache_healthy = true;
 
-    // Check if we have any healthy upstreams
+    // Check if we have any upstreams taking queries
     let servers = state.db.upstream_servers().list_enabled().await.unwrap_or_default();
-    let upstreams_healthy = !servers.is_empty();
+    let upstreams_healthy = servers.iter().any(|s| !s.drained);
 
-    let overall_status = if db_healthy && cache_healthy {
+    let listeners_healthy = state.listener_manager.failed_listeners().await.is_empty();
+
+    let overall_status = if db_healthy && cache_healthy && listeners_healthy {
         "healthy"
     } else {
         "degraded"
@@ -202,6 +663,39 @@ pub async fn health_check(
     }))
 }
 
+/// Readiness probe for orchestrators, available without authentication
+///
+/// Answers 503 while the database is unreachable or a listener has been
+/// given up on after repeated crashes.
+///
+/// GET /api/ready
+pub async fn readiness(State(state): State<StatusState>) -> impl IntoResponse {
+    let database = state.db.query_logs().get_stats().await.is_ok();
+    let failed_listeners = state.listener_manager.failed_listeners().await;
+    let ready = database && failed_listeners.is_empty();
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(ReadinessResponse {
+            ready,
+            database,
+            failed_listeners,
+        }),
+    )
+}
+
+/// Build metadata endpoint
+///
+/// GET /api/status/version
+pub async fn version_info() -> Json<BuildInfo> {
+    Json(BuildInfo::current())
+}
+
 /// Build the status API router
 pub fn status_router(state: StatusState) -> axum::Router {
     use axum::routing::get;
@@ -209,6 +703,21 @@ pub fn status_router(state: StatusState) -> axum::Router {
     axum::Router::new()
         .route("/", get(system_status))
         .route("/health", get(health_check))
+        .route("/version", get(version_info))
+        .route("/upstream-summary", get(upstream_summary))
+        .route("/policy", get(policy_status))
+        .route("/rewrite", get(rewrite_status))
+        .route("/metrics", get(policy_metrics))
+        .route("/api-log", get(api_log))
+        .with_state(state)
+}
+
+/// Build the unauthenticated readiness probe router
+pub fn readiness_router(state: StatusState) -> axum::Router {
+    use axum::routing::get;
+
+    axum::Router::new()
+        .route("/api/ready", get(readiness))
         .with_state(state)
 }
 
@@ -219,17 +728,66 @@ mod tests {
     #[test]
     fn test_cache_status_info() {
         let info = CacheStatusInfo {
+            backend: "memory".to_string(),
             entries: 100,
             hits: 80,
             misses: 20,
             hit_rate: 0.8,
             default_ttl: 60,
             max_entries: 10000,
+            memory_bytes: 25_600,
+            max_memory_mb: 0,
         };
         assert_eq!(info.entries, 100);
         assert_eq!(info.hit_rate, 0.8);
     }
 
+    #[test]
+    fn test_summarize_upstreams() {
+        use crate::dns::proxy::UpstreamProtocol;
+
+        let server = |id, name: &str, protocol| UpstreamServer::new(id, name, "192.0.2.1:53", protocol, 5000);
+        let mut drained = server(3, "maintenance", UpstreamProtocol::Udp);
+        drained.drained = true;
+        let servers = vec![
+            server(1, "fast", UpstreamProtocol::Udp),
+            server(2, "slow", UpstreamProtocol::Doh),
+            drained,
+            server(4, "down", UpstreamProtocol::Doh),
+        ];
+
+        let mut stats = HashMap::new();
+        let mut fast = UpstreamStats::new();
+        fast.record_success(10);
+        let mut slow = UpstreamStats::new();
+        slow.record_success(250);
+        let mut down = UpstreamStats::new();
+        for _ in 0..5 {
+            down.record_failure();
+        }
+        stats.insert(1, fast);
+        stats.insert(2, slow);
+        stats.insert(4, down);
+
+        let summary = summarize_upstreams(&servers, &stats);
+        assert_eq!((summary.total, summary.healthy, summary.unhealthy, summary.drained), (4, 2, 1, 1));
+        assert_eq!(
+            summary.groups,
+            vec![
+                UpstreamGroupSummary { group: "doh".to_string(), total: 2, healthy: 1, unhealthy: 1, drained: 0 },
+                UpstreamGroupSummary { group: "udp".to_string(), total: 2, healthy: 1, unhealthy: 0, drained: 1 },
+            ]
+        );
+        assert_eq!(summary.worst_latency.unwrap().name, "slow");
+    }
+
+    #[test]
+    fn test_parse_vm_rss() {
+        let status = "Name:\tfluxdns\nVmPeak:\t  90000 kB\nVmRSS:\t   12345 kB\nThreads:\t8\n";
+        assert_eq!(parse_vm_rss(status), Some(12345 * 1024));
+        assert_eq!(parse_vm_rss("Name:\tfluxdns\n"), None);
+    }
+
     #[test]
     fn test_query_status_info() {
         let info = QueryStatusInfo {
@@ -0,0 +1,366 @@
+//! Tenants API module
+//!
+//! Implements REST API endpoints for managing tenants in hosted deployments.
+//! Each tenant gets its own API token, which is scoped to the tenant's
+//! records, rewrite rules and query logs, and is mapped to DNS clients by
+//! client subnet and/or listener. Only a digest of the token is stored; the
+//! token itself is returned once, when the tenant is created or the token
+//! rotated.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::Serialize;
+
+use crate::db::{CreateTenant, Database, Tenant, TenantWithToken, UpdateTenant};
+use crate::dns::{CacheManager, IpCidr, LocalRecordIndex, RewriteEngine, TenantRegistry};
+use crate::web::records::reload_local_records;
+use crate::web::rewrite::rule_cache_name;
+use crate::web::{internal_error, not_found, ApiError};
+
+/// Application state for tenants API
+#[derive(Clone)]
+pub struct TenantsState {
+    pub db: Arc<Database>,
+    pub tenants: Arc<TenantRegistry>,
+    pub local_records: Arc<LocalRecordIndex>,
+    pub rewrite_engine: Arc<RewriteEngine>,
+    pub cache: Arc<CacheManager>,
+}
+
+/// Listener protocols a tenant can be bound to
+const VALID_LISTENERS: &[&str] = &["udp", "dot", "doh", "doq", "doh3"];
+
+/// Validation error details
+#[derive(Debug, Serialize)]
+pub struct ValidationErrors {
+    pub errors: Vec<ValidationError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidationError {
+    pub field: String,
+    pub message: String,
+}
+
+/// API response wrapper for single tenant
+#[derive(Debug, Serialize)]
+pub struct TenantResponse {
+    pub data: Tenant,
+}
+
+/// API response wrapper for a tenant with its newly issued token
+#[derive(Debug, Serialize)]
+pub struct IssuedTenantResponse {
+    pub data: TenantWithToken,
+}
+
+/// API response wrapper for multiple tenants
+#[derive(Debug, Serialize)]
+pub struct TenantsListResponse {
+    pub data: Vec<Tenant>,
+    pub total: usize,
+}
+
+/// Validate tenant name
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Name cannot be empty".to_string());
+    }
+    if name.len() > 100 {
+        return Err("Name cannot exceed 100 characters".to_string());
+    }
+    Ok(())
+}
+
+/// Validate comma-separated client subnets
+fn validate_subnets(subnets: &str) -> Result<(), String> {
+    IpCidr::parse_list(subnets).map(|_| ())
+}
+
+/// Validate comma-separated listener protocols
+fn validate_listeners(listeners: &str) -> Result<(), String> {
+    for listener in listeners.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        if !VALID_LISTENERS.contains(&listener.to_lowercase().as_str()) {
+            return Err(format!(
+                "Invalid listener '{}'. Must be one of: {}",
+                listener,
+                VALID_LISTENERS.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Normalize a comma-separated list (trim entries, drop blanks)
+fn normalize_list(list: &str, lowercase: bool) -> String {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| if lowercase { s.to_lowercase() } else { s.to_string() })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn validate_fields(
+    name: Option<&str>,
+    subnets: Option<&str>,
+    listeners: Option<&str>,
+) -> Result<(), ValidationErrors> {
+    let mut errors = Vec::new();
+
+    if let Some(name) = name {
+        if let Err(e) = validate_name(name) {
+            errors.push(ValidationError {
+                field: "name".to_string(),
+                message: e,
+            });
+        }
+    }
+
+    if let Some(subnets) = subnets {
+        if let Err(e) = validate_subnets(subnets) {
+            errors.push(ValidationError {
+                field: "client_subnets".to_string(),
+                message: e,
+            });
+        }
+    }
+
+    if let Some(listeners) = listeners {
+        if let Err(e) = validate_listeners(listeners) {
+            errors.push(ValidationError {
+                field: "listeners".to_string(),
+                message: e,
+            });
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationErrors { errors })
+    }
+}
+
+fn validation_failed(errors: ValidationErrors) -> ApiError {
+    ApiError {
+        code: "BAD_REQUEST".to_string(),
+        message: "Validation failed".to_string(),
+        details: Some(serde_json::to_value(errors).unwrap()),
+    }
+}
+
+/// Refresh the resolver's tenant views after a change
+async fn reload_registry(state: &TenantsState) {
+    if let Err(e) = state.tenants.reload().await {
+        tracing::warn!("Failed to reload tenants: {}", e);
+    }
+}
+
+/// List all tenants
+///
+/// GET /api/tenants
+pub async fn list_tenants(
+    State(state): State<TenantsState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let tenants = state
+        .db
+        .tenants()
+        .list()
+        .await
+        .map_err(|e| internal_error("Failed to list tenants", e))?;
+
+    Ok(Json(TenantsListResponse {
+        total: tenants.len(),
+        data: tenants,
+    }))
+}
+
+/// Get a tenant by ID
+///
+/// GET /api/tenants/:id
+pub async fn get_tenant(
+    State(state): State<TenantsState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let tenant = state
+        .db
+        .tenants()
+        .get_by_id(id)
+        .await
+        .map_err(|e| internal_error("Failed to get tenant", e))?;
+
+    tenant
+        .map(|t| Json(TenantResponse { data: t }))
+        .ok_or_else(|| not_found("Tenant", id))
+}
+
+/// Create a new tenant
+///
+/// POST /api/tenants
+pub async fn create_tenant(
+    State(state): State<TenantsState>,
+    Json(mut request): Json<CreateTenant>,
+) -> Result<impl IntoResponse, ApiError> {
+    validate_fields(
+        Some(&request.name),
+        Some(&request.client_subnets),
+        Some(&request.listeners),
+    )
+    .map_err(validation_failed)?;
+
+    request.name = request.name.trim().to_string();
+    request.client_subnets = normalize_list(&request.client_subnets, false);
+    request.listeners = normalize_list(&request.listeners, true);
+
+    let tenant = state
+        .db
+        .tenants()
+        .create(request)
+        .await
+        .map_err(|e| internal_error("Failed to create tenant", e))?;
+
+    reload_registry(&state).await;
+
+    Ok((StatusCode::CREATED, Json(IssuedTenantResponse { data: tenant })))
+}
+
+/// Update a tenant
+///
+/// PUT /api/tenants/:id
+pub async fn update_tenant(
+    State(state): State<TenantsState>,
+    Path(id): Path<i64>,
+    Json(mut request): Json<UpdateTenant>,
+) -> Result<impl IntoResponse, ApiError> {
+    validate_fields(
+        request.name.as_deref(),
+        request.client_subnets.as_deref(),
+        request.listeners.as_deref(),
+    )
+    .map_err(validation_failed)?;
+
+    request.name = request.name.map(|n| n.trim().to_string());
+    request.client_subnets = request.client_subnets.map(|s| normalize_list(&s, false));
+    request.listeners = request.listeners.map(|s| normalize_list(&s, true));
+
+    let tenant = state
+        .db
+        .tenants()
+        .update(id, request)
+        .await
+        .map_err(|e| internal_error("Failed to update tenant", e))?;
+    let tenant = tenant.ok_or_else(|| not_found("Tenant", id))?;
+
+    reload_registry(&state).await;
+
+    Ok(Json(TenantResponse { data: tenant }))
+}
+
+/// Delete a tenant together with its records and rewrite rules
+///
+/// DELETE /api/tenants/:id
+pub async fn delete_tenant(
+    State(state): State<TenantsState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    // Names answered by the tenant's records and rules, purged once they go
+    let records = state
+        .db
+        .dns_records()
+        .list_by_tenant(id)
+        .await
+        .map_err(|e| internal_error("Failed to list records", e))?;
+    let rules = state
+        .db
+        .rewrite_rules()
+        .list_by_tenant(id)
+        .await
+        .map_err(|e| internal_error("Failed to list rewrite rules", e))?;
+    let mut changed_names: Vec<&str> = records.iter().map(|r| r.name.as_str()).collect();
+    changed_names.extend(rules.iter().filter_map(|r| rule_cache_name(&r.pattern, &r.match_type, r.shadow)));
+
+    let deleted = state
+        .db
+        .tenants()
+        .delete(id)
+        .await
+        .map_err(|e| internal_error("Failed to delete tenant", e))?;
+
+    if !deleted {
+        return Err(not_found("Tenant", id));
+    }
+
+    reload_registry(&state).await;
+    reload_local_records(&state.local_records).await;
+    if !rules.is_empty() {
+        if let Err(e) = state.rewrite_engine.reload_rules().await {
+            tracing::warn!("Failed to reload rewrite rules: {}", e);
+        }
+    }
+    state.cache.purge_names(changed_names).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Issue a new API token, invalidating the previous one
+///
+/// POST /api/tenants/:id/rotate-token
+pub async fn rotate_token(
+    State(state): State<TenantsState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let tenant = state
+        .db
+        .tenants()
+        .rotate_token(id)
+        .await
+        .map_err(|e| internal_error("Failed to rotate tenant token", e))?;
+
+    tenant
+        .map(|t| Json(IssuedTenantResponse { data: t }))
+        .ok_or_else(|| not_found("Tenant", id))
+}
+
+/// Build the tenants API router
+pub fn tenants_router(state: TenantsState) -> axum::Router {
+    use axum::routing::{get, post};
+
+    axum::Router::new()
+        .route("/", get(list_tenants).post(create_tenant))
+        .route("/:id", get(get_tenant).put(update_tenant).delete(delete_tenant))
+        .route("/:id/rotate-token", post(rotate_token))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_listeners() {
+        assert!(validate_listeners("").is_ok());
+        assert!(validate_listeners("dot, DoH").is_ok());
+        assert!(validate_listeners("udp,tcp").is_err());
+    }
+
+    #[test]
+    fn test_validate_fields_collects_errors() {
+        let result = validate_fields(Some(""), Some("10.0.0.0/40"), Some("smtp"));
+        assert_eq!(result.unwrap_err().errors.len(), 3);
+
+        assert!(validate_fields(Some("acme"), Some("10.0.0.0/8"), None).is_ok());
+    }
+
+    #[test]
+    fn test_normalize_list() {
+        assert_eq!(normalize_list(" DoT , ,doh ", true), "dot,doh");
+        assert_eq!(normalize_list("10.0.0.0/8 ,", false), "10.0.0.0/8");
+    }
+}
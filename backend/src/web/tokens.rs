@@ -0,0 +1,287 @@
+//! API tokens module
+//!
+//! Scoped API tokens give scripts and CI systems least-privilege access to
+//! the management API. Each scope covers a set of path prefixes; `:read`
+//! scopes allow only GET requests, the others allow every method. Token
+//! management itself is admin-only, so a token can never widen its own
+//! scopes.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::{Method, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Serialize;
+
+use crate::db::{CreateApiToken, Database, UpdateApiToken};
+use crate::web::{bad_request, internal_error, not_found, ApiError};
+
+/// A permission that can be granted to an API token
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ApiScope {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Path prefixes the scope covers
+    #[serde(skip)]
+    prefixes: &'static [&'static str],
+    /// Whether methods other than GET are allowed
+    #[serde(skip)]
+    write: bool,
+}
+
+impl ApiScope {
+    const fn read_only(name: &'static str, description: &'static str, prefixes: &'static [&'static str]) -> Self {
+        Self { name, description, prefixes, write: false }
+    }
+
+    const fn read_write(name: &'static str, description: &'static str, prefixes: &'static [&'static str]) -> Self {
+        Self { name, description, prefixes, write: true }
+    }
+
+    /// Check whether the scope allows a request
+    fn allows(&self, method: &Method, path: &str) -> bool {
+        if !self.write && method != Method::GET && method != Method::HEAD {
+            return false;
+        }
+        self.prefixes
+            .iter()
+            .any(|p| path == *p || path.starts_with(&format!("{}/", p)))
+    }
+}
+
+/// All scopes an API token can be granted
+pub const API_SCOPES: &[ApiScope] = &[
+    ApiScope::read_only(
+        "records:read",
+        "List local DNS records and services",
+        &["/api/records", "/api/services"],
+    ),
+    ApiScope::read_write(
+        "records:write",
+        "Create, update and delete local DNS records and services",
+        &["/api/records", "/api/services"],
+    ),
+    ApiScope::read_only("rewrite:read", "List rewrite rules", &["/api/rewrite"]),
+    ApiScope::read_write("rewrite:write", "Create, update and delete rewrite rules", &["/api/rewrite"]),
+    ApiScope::read_only("upstreams:read", "List upstream servers", &["/api/upstreams"]),
+    ApiScope::read_write("upstreams:write", "Manage upstream servers", &["/api/upstreams"]),
+    ApiScope::read_only(
+        "cache:read",
+        "Read cache statistics, configuration and purge audit",
+        &["/api/cache/stats", "/api/cache/config", "/api/cache/purge-audit"],
+    ),
+    ApiScope::read_write(
+        "cache:purge",
+        "Clear the whole cache or single domains, and call the purge webhook",
+        &["/api/cache/clear", "/api/hooks/cache/purge"],
+    ),
+    ApiScope::read_write(
+        "cache:write",
+        "Change cache configuration, clean up and preload",
+        &["/api/cache/config", "/api/cache/cleanup", "/api/cache/preload"],
+    ),
+    ApiScope::read_only("logs:read", "Query, export and summarize query logs", &["/api/logs"]),
+    ApiScope::read_write(
+        "logs:ingest",
+        "Submit query logs from external resolvers",
+        &["/api/logs/ingest"],
+    ),
+    ApiScope::read_only("analytics:read", "Read the top-domains leaderboard", &["/api/analytics"]),
+    ApiScope::read_only("status:read", "Read system status and health", &["/api/status"]),
+    ApiScope::read_write("dns:query", "Run DNS queries through the proxy", &["/api/dns/query"]),
+    ApiScope::read_only("backup:read", "Download configuration backups", &["/api/backup"]),
+    ApiScope::read_only("rpz:read", "List RPZ feeds", &["/api/rpz"]),
+    ApiScope::read_write("rpz:write", "Manage RPZ feeds and imports", &["/api/rpz"]),
+];
+
+/// Look up a scope by name
+pub fn find_scope(name: &str) -> Option<&'static ApiScope> {
+    API_SCOPES.iter().find(|s| s.name == name)
+}
+
+/// Check whether any of the granted scopes allows a request
+pub fn scopes_allow<'a>(scopes: impl IntoIterator<Item = &'a str>, method: &Method, path: &str) -> bool {
+    scopes
+        .into_iter()
+        .filter_map(find_scope)
+        .any(|s| s.allows(method, path))
+}
+
+/// Application state for API tokens API
+#[derive(Clone)]
+pub struct TokensState {
+    pub db: Arc<Database>,
+}
+
+fn validate_name(name: &str) -> Result<(), ApiError> {
+    if name.is_empty() || name.len() > 100 {
+        return Err(bad_request("Name must be between 1 and 100 characters".to_string()));
+    }
+    Ok(())
+}
+
+/// Trim, deduplicate and check scope names
+fn normalize_scopes(scopes: Vec<String>) -> Result<Vec<String>, ApiError> {
+    let mut normalized: Vec<String> = Vec::new();
+    for scope in scopes {
+        let scope = scope.trim().to_string();
+        if find_scope(&scope).is_none() {
+            return Err(bad_request(format!("Unknown scope '{}'", scope)));
+        }
+        if !normalized.contains(&scope) {
+            normalized.push(scope);
+        }
+    }
+    if normalized.is_empty() {
+        return Err(bad_request("At least one scope is required".to_string()));
+    }
+    Ok(normalized)
+}
+
+/// List available scopes
+///
+/// GET /api/tokens/scopes
+pub async fn list_scopes() -> impl IntoResponse {
+    Json(serde_json::json!({ "data": API_SCOPES }))
+}
+
+/// List API tokens
+///
+/// Only metadata is listed; secrets are shown once, at creation.
+///
+/// GET /api/tokens
+pub async fn list_tokens(State(state): State<TokensState>) -> Result<impl IntoResponse, ApiError> {
+    let tokens = state
+        .db
+        .api_tokens()
+        .list()
+        .await
+        .map_err(|e| internal_error("Failed to list API tokens", e))?;
+
+    Ok(Json(serde_json::json!({ "data": tokens })))
+}
+
+/// Create an API token
+///
+/// The secret is only returned here; the server keeps just its digest.
+///
+/// POST /api/tokens
+pub async fn create_token(
+    State(state): State<TokensState>,
+    Json(request): Json<CreateApiToken>,
+) -> Result<impl IntoResponse, ApiError> {
+    let name = request.name.trim().to_string();
+    validate_name(&name)?;
+    let scopes = normalize_scopes(request.scopes)?;
+
+    let token = state
+        .db
+        .api_tokens()
+        .create(CreateApiToken {
+            name,
+            scopes,
+            expires_at: request.expires_at,
+        })
+        .await
+        .map_err(|e| internal_error("Failed to create API token", e))?;
+
+    Ok((StatusCode::CREATED, Json(serde_json::json!({ "data": token }))))
+}
+
+/// Update an API token's name, scopes or enabled flag
+///
+/// PUT /api/tokens/:id
+pub async fn update_token(
+    State(state): State<TokensState>,
+    Path(id): Path<i64>,
+    Json(mut request): Json<UpdateApiToken>,
+) -> Result<impl IntoResponse, ApiError> {
+    request.name = request.name.map(|n| n.trim().to_string());
+    if let Some(ref name) = request.name {
+        validate_name(name)?;
+    }
+    if let Some(scopes) = request.scopes.take() {
+        request.scopes = Some(normalize_scopes(scopes)?);
+    }
+
+    let token = state
+        .db
+        .api_tokens()
+        .update(id, request)
+        .await
+        .map_err(|e| internal_error("Failed to update API token", e))?
+        .ok_or_else(|| not_found("API token", id))?;
+
+    Ok(Json(serde_json::json!({ "data": token })))
+}
+
+/// Delete an API token
+///
+/// DELETE /api/tokens/:id
+pub async fn delete_token(
+    State(state): State<TokensState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let deleted = state
+        .db
+        .api_tokens()
+        .delete(id)
+        .await
+        .map_err(|e| internal_error("Failed to delete API token", e))?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(not_found("API token", id))
+    }
+}
+
+/// Create API tokens router
+pub fn tokens_router(state: TokensState) -> axum::Router {
+    use axum::routing::{get, put};
+
+    axum::Router::new()
+        .route("/", get(list_tokens).post(create_token))
+        .route("/scopes", get(list_scopes))
+        .route("/:id", put(update_token).delete(delete_token))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scopes_allow() {
+        let purge = ["cache:purge"];
+        assert!(scopes_allow(purge, &Method::POST, "/api/cache/clear"));
+        assert!(scopes_allow(purge, &Method::POST, "/api/cache/clear/example.com"));
+        assert!(scopes_allow(purge, &Method::POST, "/api/hooks/cache/purge"));
+        assert!(!scopes_allow(purge, &Method::GET, "/api/cache/purge-audit"));
+        assert!(!scopes_allow(purge, &Method::PUT, "/api/cache/config"));
+
+        let read = ["records:read", "logs:read"];
+        assert!(scopes_allow(read, &Method::GET, "/api/records/3"));
+        assert!(scopes_allow(read, &Method::GET, "/api/logs/export"));
+        assert!(!scopes_allow(read, &Method::POST, "/api/records"));
+        assert!(!scopes_allow(read, &Method::DELETE, "/api/logs/cleanup/all"));
+        assert!(!scopes_allow(read, &Method::GET, "/api/recordsx"));
+        assert!(!scopes_allow(read, &Method::POST, "/api/logs/ingest"));
+        assert!(scopes_allow(["logs:ingest"], &Method::POST, "/api/logs/ingest"));
+
+        assert!(scopes_allow(["records:write"], &Method::DELETE, "/api/records/3"));
+        assert!(!scopes_allow(["records:write"], &Method::GET, "/api/tokens"));
+        assert!(!scopes_allow(["admin"], &Method::GET, "/api/records"));
+    }
+
+    #[test]
+    fn test_normalize_scopes() {
+        let scopes = normalize_scopes(vec![" cache:purge".to_string(), "cache:purge".to_string()]).unwrap();
+        assert_eq!(scopes, vec!["cache:purge"]);
+        assert!(normalize_scopes(vec![]).is_err());
+        assert!(normalize_scopes(vec!["cache:everything".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! HTTP Topology
+//!
+//! The web UI, the REST API and the plain-HTTP DoH endpoint share the web
+//! port by default. The API and DoH can each be moved to their own port and
+//! bind address, e.g. UI and API on a LAN address with DoH public. Services
+//! that end up on the same address share one listener. The resulting
+//! endpoints are reported in `/api/status`.
+
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::{bail, Result};
+use axum::response::IntoResponse;
+use serde::Serialize;
+
+use crate::config::AppConfig;
+use crate::web::ApiError;
+
+/// A part of the HTTP server that can be given its own port
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpService {
+    /// Web management interface
+    Ui,
+    /// REST API under `/api`
+    Api,
+    /// DNS over HTTPS at `/dns-query`
+    Doh,
+}
+
+impl HttpService {
+    /// Convert to string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpService::Ui => "ui",
+            HttpService::Api => "api",
+            HttpService::Doh => "doh",
+        }
+    }
+}
+
+/// One HTTP listener and the services it serves
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HttpEndpoint {
+    pub address: SocketAddr,
+    pub services: Vec<HttpService>,
+}
+
+impl HttpEndpoint {
+    pub fn serves(&self, service: HttpService) -> bool {
+        self.services.contains(&service)
+    }
+
+    /// Comma-separated service names for logging
+    pub fn describe(&self) -> String {
+        self.services
+            .iter()
+            .map(HttpService::as_str)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Group the UI, API and DoH services by listen address
+///
+/// A port of zero keeps a service on the web port. Fails when two
+/// listeners would claim the same port with overlapping addresses.
+pub fn http_topology(config: &AppConfig) -> Result<Vec<HttpEndpoint>> {
+    let web = SocketAddr::new(config.web_bind_address, config.web_port);
+    let placed = |port: u16, bind: Option<IpAddr>| match port {
+        0 => web,
+        port => SocketAddr::new(bind.unwrap_or(config.web_bind_address), port),
+    };
+    let placements = [
+        (HttpService::Ui, web),
+        (HttpService::Api, placed(config.api_port, config.api_bind_address)),
+        (HttpService::Doh, placed(config.doh_port, config.doh_bind_address)),
+    ];
+
+    let mut endpoints: Vec<HttpEndpoint> = Vec::new();
+    for (service, address) in placements {
+        if let Some(endpoint) = endpoints.iter_mut().find(|e| e.address == address) {
+            endpoint.services.push(service);
+            continue;
+        }
+        let conflict = endpoints.iter().find(|e| {
+            e.address.port() == address.port()
+                && (e.address.ip().is_unspecified() || address.ip().is_unspecified())
+        });
+        if let Some(endpoint) = conflict {
+            bail!(
+                "{} on {} conflicts with {} on {}",
+                service.as_str(),
+                address,
+                endpoint.describe(),
+                endpoint.address
+            );
+        }
+        endpoints.push(HttpEndpoint {
+            address,
+            services: vec![service],
+        });
+    }
+    Ok(endpoints)
+}
+
+/// Fallback for listeners without the web UI
+pub async fn not_found_handler() -> impl IntoResponse {
+    ApiError {
+        code: "NOT_FOUND".to_string(),
+        message: "Not found".to_string(),
+        details: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn services(endpoints: &[HttpEndpoint]) -> Vec<(String, Vec<HttpService>)> {
+        endpoints
+            .iter()
+            .map(|e| (e.address.to_string(), e.services.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_shared_port_by_default() {
+        let endpoints = http_topology(&AppConfig::default()).unwrap();
+        assert_eq!(
+            services(&endpoints),
+            vec![(
+                "0.0.0.0:8080".to_string(),
+                vec![HttpService::Ui, HttpService::Api, HttpService::Doh]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_split_ports() {
+        let config = AppConfig {
+            web_bind_address: "192.168.1.2".parse().unwrap(),
+            doh_port: 443,
+            doh_bind_address: Some("0.0.0.0".parse().unwrap()),
+            ..Default::default()
+        };
+        let endpoints = http_topology(&config).unwrap();
+        assert_eq!(
+            services(&endpoints),
+            vec![
+                ("192.168.1.2:8080".to_string(), vec![HttpService::Ui, HttpService::Api]),
+                ("0.0.0.0:443".to_string(), vec![HttpService::Doh]),
+            ]
+        );
+
+        // A separate port inherits the web bind address
+        let config = AppConfig {
+            api_port: 9090,
+            ..Default::default()
+        };
+        let endpoints = http_topology(&config).unwrap();
+        assert_eq!(endpoints[1].address.to_string(), "0.0.0.0:9090");
+        assert!(endpoints[1].serves(HttpService::Api));
+        assert!(endpoints[0].serves(HttpService::Doh));
+    }
+
+    #[test]
+    fn test_conflicting_ports() {
+        // Same port, one listener on every address
+        let config = AppConfig {
+            doh_port: 8080,
+            doh_bind_address: Some("127.0.0.1".parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(http_topology(&config).is_err());
+
+        // Same port on two distinct addresses is fine
+        let config = AppConfig {
+            web_bind_address: "192.168.1.2".parse().unwrap(),
+            doh_port: 8080,
+            doh_bind_address: Some("127.0.0.1".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(http_topology(&config).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,630 @@
+//! Configuration transactions API module
+//!
+//! Applies a list of typed operations across records, rewrite rules,
+//! upstream servers and the upstream protocol rules in one database
+//! transaction: either every operation takes effect or none does. All
+//! operations are validated first against the same rules as the REST API,
+//! with errors on fields such as `operations[2].address`; an operation that
+//! still fails while running (e.g. its entity was deleted meanwhile) rolls
+//! the whole transaction back. Live components are reloaded once, after
+//! the commit.
+//!
+//! ```json
+//! {"operations": [
+//!   {"op": "set_protocol_rules", "rules": [{"pattern": "*.corp.example", "protocols": ["dot"]}]},
+//!   {"op": "create_upstream", "name": "Corp DoT 1", "address": "10.0.0.53:853", "protocol": "dot"},
+//!   {"op": "create_upstream", "name": "Corp DoT 2", "address": "10.0.1.53:853", "protocol": "dot"},
+//!   {"op": "create_rewrite_rule", "pattern": "vpn.corp.example", "match_type": "exact", "action_type": "passthru"}
+//! ]}
+//! ```
+
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+
+use crate::db::{
+    CreateDnsRecord, CreateRewriteRule, CreateUpstreamServer, Database, DnsRecord, DnsRecordRepository,
+    RewriteRule, RewriteRuleRepository, SystemConfigRepository, UpdateDnsRecord, UpdateRewriteRule,
+    UpdateUpstreamServer, UpstreamServer, UpstreamServerRepository,
+};
+use crate::dns::proxy::{ProtocolRule, ProxyManager, UpstreamManager, CONFIG_KEY_UPSTREAM_PROTOCOL_RULES};
+use crate::dns::{CacheManager, LocalRecordIndex, RewriteEngine};
+use crate::web::config_apply::ChangeAction;
+use crate::web::records::{
+    ensure_tenant_exists, reload_local_records, ttl_bounds, CreateRecordRequest, TtlBounds,
+    UpdateRecordRequest,
+};
+use crate::web::rewrite::{rule_cache_name, CreateRewriteRuleRequest, UpdateRewriteRuleRequest};
+use crate::web::upstreams::{
+    own_listener_error, validate_protocol_rules, CreateUpstreamServerRequest, UpdateUpstreamServerRequest,
+};
+use crate::web::ApiError;
+
+/// Maximum operations in one transaction
+pub const MAX_TRANSACTION_OPERATIONS: usize = 500;
+
+/// Application state for configuration transactions API
+#[derive(Clone)]
+pub struct TransactionsState {
+    pub db: Arc<Database>,
+    pub rewrite_engine: Arc<RewriteEngine>,
+    pub upstream_manager: Arc<UpstreamManager>,
+    pub proxy_manager: Arc<ProxyManager>,
+    pub local_records: Arc<LocalRecordIndex>,
+    pub cache: Arc<CacheManager>,
+}
+
+/// Transaction request
+#[derive(Debug, Deserialize)]
+pub struct TransactionRequest {
+    pub operations: Vec<TransactionOperation>,
+}
+
+/// Single operation, tagged by `op`
+///
+/// Create and update operations take the same fields as the matching REST
+/// endpoints; updates and deletes name the entity by `id`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum TransactionOperation {
+    CreateRecord(CreateRecordRequest),
+    UpdateRecord {
+        id: i64,
+        #[serde(flatten)]
+        changes: UpdateRecordRequest,
+    },
+    DeleteRecord {
+        id: i64,
+    },
+    CreateRewriteRule(CreateRewriteRuleRequest),
+    UpdateRewriteRule {
+        id: i64,
+        #[serde(flatten)]
+        changes: UpdateRewriteRuleRequest,
+    },
+    DeleteRewriteRule {
+        id: i64,
+    },
+    CreateUpstream(CreateUpstreamServerRequest),
+    UpdateUpstream {
+        id: i64,
+        #[serde(flatten)]
+        changes: UpdateUpstreamServerRequest,
+    },
+    DeleteUpstream {
+        id: i64,
+    },
+    /// Replace all upstream protocol rules
+    SetProtocolRules {
+        rules: Vec<ProtocolRule>,
+    },
+}
+
+impl TransactionOperation {
+    /// Operation name as given in `op`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CreateRecord(_) => "create_record",
+            Self::UpdateRecord { .. } => "update_record",
+            Self::DeleteRecord { .. } => "delete_record",
+            Self::CreateRewriteRule(_) => "create_rewrite_rule",
+            Self::UpdateRewriteRule { .. } => "update_rewrite_rule",
+            Self::DeleteRewriteRule { .. } => "delete_rewrite_rule",
+            Self::CreateUpstream(_) => "create_upstream",
+            Self::UpdateUpstream { .. } => "update_upstream",
+            Self::DeleteUpstream { .. } => "delete_upstream",
+            Self::SetProtocolRules { .. } => "set_protocol_rules",
+        }
+    }
+}
+
+/// Validation error details
+#[derive(Debug, Serialize)]
+pub struct ValidationErrors {
+    pub errors: Vec<ValidationError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidationError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Outcome of one operation
+#[derive(Debug, Serialize)]
+pub struct OperationResult {
+    pub index: usize,
+    pub op: &'static str,
+    pub action: ChangeAction,
+    /// Id of the created, updated or deleted entity
+    pub id: Option<i64>,
+}
+
+/// Operation counts
+#[derive(Debug, Default, Serialize)]
+pub struct TransactionSummary {
+    pub create: usize,
+    pub update: usize,
+    pub delete: usize,
+}
+
+/// Transaction response
+#[derive(Debug, Serialize)]
+pub struct TransactionResponse {
+    pub applied: bool,
+    pub summary: TransactionSummary,
+    pub results: Vec<OperationResult>,
+}
+
+/// Validated operation, with the entity it replaces or removes
+#[derive(Debug)]
+enum Step {
+    CreateRecord(CreateDnsRecord),
+    UpdateRecord(DnsRecord, UpdateDnsRecord),
+    DeleteRecord(DnsRecord),
+    CreateRule(CreateRewriteRule),
+    UpdateRule(RewriteRule, UpdateRewriteRule),
+    DeleteRule(RewriteRule),
+    CreateUpstream(CreateUpstreamServer),
+    UpdateUpstream(UpstreamServer, UpdateUpstreamServer),
+    DeleteUpstream(UpstreamServer),
+    SetProtocolRules(Vec<ProtocolRule>),
+}
+
+impl Step {
+    fn action(&self) -> ChangeAction {
+        match self {
+            Self::CreateRecord(_) | Self::CreateRule(_) | Self::CreateUpstream(_) => ChangeAction::Create,
+            Self::DeleteRecord(_) | Self::DeleteRule(_) | Self::DeleteUpstream(_) => ChangeAction::Delete,
+            _ => ChangeAction::Update,
+        }
+    }
+}
+
+/// What changed, to reload the live components after the commit
+#[derive(Debug, Default)]
+struct Changed {
+    records: bool,
+    rules: bool,
+    upstreams: bool,
+    protocol_rules: Option<Vec<ProtocolRule>>,
+    /// Names whose cached answers are stale
+    names: Vec<String>,
+}
+
+/// Prefix validation errors of one operation with its position
+fn push_errors<I>(errors: &mut Vec<ValidationError>, index: usize, item_errors: I)
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    errors.extend(item_errors.into_iter().map(|(field, message)| ValidationError {
+        field: format!("operations[{}].{}", index, field),
+        message,
+    }));
+}
+
+fn not_found(errors: &mut Vec<ValidationError>, index: usize, message: String) {
+    errors.push(ValidationError {
+        field: format!("operations[{}].id", index),
+        message,
+    });
+}
+
+/// Validate an upstream address against our own listeners
+async fn check_upstream_address(
+    db: &Database,
+    errors: &mut Vec<ValidationError>,
+    index: usize,
+    address: &str,
+    protocol: &str,
+) -> Result<(), ApiError> {
+    let error = own_listener_error(db, address, protocol).await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to get listeners: {}", e),
+        details: None,
+    })?;
+    if let Some(e) = error {
+        push_errors(errors, index, e.errors.into_iter().map(|e| (e.field, e.message)));
+    }
+    Ok(())
+}
+
+/// Validate every operation and resolve the entities they refer to
+///
+/// Returns the steps to run, or all validation errors at once.
+async fn plan(
+    db: &Database,
+    operations: Vec<TransactionOperation>,
+    ttl_bounds: &TtlBounds,
+) -> Result<Result<Vec<Step>, ValidationErrors>, ApiError> {
+    let internal = |what: &str, e: anyhow::Error| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to get {}: {}", what, e),
+        details: None,
+    };
+
+    let mut errors = Vec::new();
+    let mut steps = Vec::with_capacity(operations.len());
+    for (i, operation) in operations.into_iter().enumerate() {
+        match operation {
+            TransactionOperation::CreateRecord(request) => {
+                if let Err(e) = request.validate(ttl_bounds) {
+                    push_errors(&mut errors, i, e.errors.into_iter().map(|e| (e.field, e.message)));
+                }
+                if let Err(e) = ensure_tenant_exists(db, request.tenant_id).await {
+                    push_errors(&mut errors, i, [("tenant_id".to_string(), e.message)]);
+                }
+                steps.push(Step::CreateRecord(request.into_create_dns_record()));
+            }
+            TransactionOperation::UpdateRecord { id, changes } => {
+                let Some(existing) = db.dns_records().get_by_id(id).await.map_err(|e| internal("record", e))? else {
+                    not_found(&mut errors, i, format!("Record with id {} not found", id));
+                    continue;
+                };
+                if let Err(e) = changes.validate(&existing.record_type, ttl_bounds) {
+                    push_errors(&mut errors, i, e.errors.into_iter().map(|e| (e.field, e.message)));
+                }
+                steps.push(Step::UpdateRecord(existing, changes.into_update_dns_record()));
+            }
+            TransactionOperation::DeleteRecord { id } => {
+                match db.dns_records().get_by_id(id).await.map_err(|e| internal("record", e))? {
+                    Some(existing) => steps.push(Step::DeleteRecord(existing)),
+                    None => not_found(&mut errors, i, format!("Record with id {} not found", id)),
+                }
+            }
+            TransactionOperation::CreateRewriteRule(request) => {
+                if let Err(e) = request.validate() {
+                    push_errors(&mut errors, i, e.errors.into_iter().map(|e| (e.field, e.message)));
+                }
+                if let Err(e) = ensure_tenant_exists(db, request.tenant_id).await {
+                    push_errors(&mut errors, i, [("tenant_id".to_string(), e.message)]);
+                }
+                steps.push(Step::CreateRule(request.into_create_rewrite_rule()));
+            }
+            TransactionOperation::UpdateRewriteRule { id, changes } => {
+                let Some(existing) = db.rewrite_rules().get_by_id(id).await.map_err(|e| internal("rewrite rule", e))? else {
+                    not_found(&mut errors, i, format!("Rewrite rule with id {} not found", id));
+                    continue;
+                };
+                if let Err(e) = changes.validate(&existing) {
+                    push_errors(&mut errors, i, e.errors.into_iter().map(|e| (e.field, e.message)));
+                }
+                let update = changes.into_update_rewrite_rule(&existing);
+                steps.push(Step::UpdateRule(existing, update));
+            }
+            TransactionOperation::DeleteRewriteRule { id } => {
+                match db.rewrite_rules().get_by_id(id).await.map_err(|e| internal("rewrite rule", e))? {
+                    Some(existing) => steps.push(Step::DeleteRule(existing)),
+                    None => not_found(&mut errors, i, format!("Rewrite rule with id {} not found", id)),
+                }
+            }
+            TransactionOperation::CreateUpstream(request) => {
+                match request.validate() {
+                    Ok(()) => check_upstream_address(db, &mut errors, i, &request.address, &request.protocol).await?,
+                    Err(e) => push_errors(&mut errors, i, e.errors.into_iter().map(|e| (e.field, e.message))),
+                }
+                steps.push(Step::CreateUpstream(request.into_create_upstream_server()));
+            }
+            TransactionOperation::UpdateUpstream { id, changes } => {
+                let Some(existing) = db
+                    .upstream_servers()
+                    .get_by_id(id)
+                    .await
+                    .map_err(|e| internal("upstream server", e))?
+                else {
+                    not_found(&mut errors, i, format!("Upstream server with id {} not found", id));
+                    continue;
+                };
+                match changes.validate(&existing) {
+                    Ok(()) if changes.address.is_some() || changes.protocol.is_some() => {
+                        let address = changes.address.as_deref().unwrap_or(&existing.address);
+                        let protocol = changes.protocol.as_deref().unwrap_or(&existing.protocol);
+                        check_upstream_address(db, &mut errors, i, address, protocol).await?;
+                    }
+                    Ok(()) => {}
+                    Err(e) => push_errors(&mut errors, i, e.errors.into_iter().map(|e| (e.field, e.message))),
+                }
+                steps.push(Step::UpdateUpstream(existing, changes.into_update_upstream_server()));
+            }
+            TransactionOperation::DeleteUpstream { id } => {
+                match db.upstream_servers().get_by_id(id).await.map_err(|e| internal("upstream server", e))? {
+                    Some(existing) => steps.push(Step::DeleteUpstream(existing)),
+                    None => not_found(&mut errors, i, format!("Upstream server with id {} not found", id)),
+                }
+            }
+            TransactionOperation::SetProtocolRules { rules } => match validate_protocol_rules(rules) {
+                Ok(rules) => steps.push(Step::SetProtocolRules(rules)),
+                Err(e) => push_errors(&mut errors, i, e.errors.into_iter().map(|e| (e.field, e.message))),
+            },
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(Ok(steps))
+    } else {
+        Ok(Err(ValidationErrors { errors }))
+    }
+}
+
+/// Run one step on the transaction
+///
+/// Returns the id of the entity it touched, or an error message when the
+/// entity no longer exists.
+async fn run_step(conn: &mut SqliteConnection, step: &Step, changed: &mut Changed) -> anyhow::Result<Option<i64>> {
+    match step {
+        Step::CreateRecord(create) => {
+            let record = DnsRecordRepository::create_on(conn, create.clone()).await?;
+            changed.records = true;
+            changed.names.push(record.name);
+            Ok(Some(record.id))
+        }
+        Step::UpdateRecord(existing, update) => {
+            let record = DnsRecordRepository::update_on(conn, existing.id, update.clone(), None)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Record with id {} not found", existing.id))?;
+            changed.records = true;
+            changed.names.extend([existing.name.clone(), record.name]);
+            Ok(Some(record.id))
+        }
+        Step::DeleteRecord(existing) => {
+            if !DnsRecordRepository::delete_on(conn, existing.id).await? {
+                anyhow::bail!("Record with id {} not found", existing.id);
+            }
+            changed.records = true;
+            changed.names.push(existing.name.clone());
+            Ok(Some(existing.id))
+        }
+        Step::CreateRule(create) => {
+            let rule = RewriteRuleRepository::create_on(conn, create.clone()).await?;
+            changed.rules = true;
+            changed.names.extend(rule_cache_name(&rule.pattern, &rule.match_type, rule.shadow).map(String::from));
+            Ok(Some(rule.id))
+        }
+        Step::UpdateRule(existing, update) => {
+            let rule = RewriteRuleRepository::update_on(conn, existing.id, update.clone(), None)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Rewrite rule with id {} not found", existing.id))?;
+            changed.rules = true;
+            changed.names.extend(
+                rule_cache_name(&existing.pattern, &existing.match_type, existing.shadow).map(String::from),
+            );
+            changed.names.extend(rule_cache_name(&rule.pattern, &rule.match_type, rule.shadow).map(String::from));
+            Ok(Some(rule.id))
+        }
+        Step::DeleteRule(existing) => {
+            if !RewriteRuleRepository::delete_on(conn, existing.id).await? {
+                anyhow::bail!("Rewrite rule with id {} not found", existing.id);
+            }
+            changed.rules = true;
+            changed.names.extend(
+                rule_cache_name(&existing.pattern, &existing.match_type, existing.shadow).map(String::from),
+            );
+            Ok(Some(existing.id))
+        }
+        Step::CreateUpstream(create) => {
+            let server = UpstreamServerRepository::create_on(conn, create.clone()).await?;
+            changed.upstreams = true;
+            Ok(Some(server.id))
+        }
+        Step::UpdateUpstream(existing, update) => {
+            let server = UpstreamServerRepository::update_on(conn, existing.id, update.clone(), None)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Upstream server with id {} not found", existing.id))?;
+            changed.upstreams = true;
+            Ok(Some(server.id))
+        }
+        Step::DeleteUpstream(existing) => {
+            if !UpstreamServerRepository::delete_on(conn, existing.id).await? {
+                anyhow::bail!("Upstream server with id {} not found", existing.id);
+            }
+            changed.upstreams = true;
+            Ok(Some(existing.id))
+        }
+        Step::SetProtocolRules(rules) => {
+            let stored = serde_json::to_string(rules)?;
+            SystemConfigRepository::set_on(conn, CONFIG_KEY_UPSTREAM_PROTOCOL_RULES, &stored).await?;
+            changed.protocol_rules = Some(rules.clone());
+            Ok(None)
+        }
+    }
+}
+
+/// Apply operations atomically
+///
+/// POST /api/transactions
+///
+/// Nothing is written unless every operation validates and runs; the
+/// response lists the outcome of each operation in order.
+pub async fn run_transaction(
+    State(state): State<TransactionsState>,
+    Json(request): Json<TransactionRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    if request.operations.is_empty() || request.operations.len() > MAX_TRANSACTION_OPERATIONS {
+        return Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: format!("Provide between 1 and {} operations", MAX_TRANSACTION_OPERATIONS),
+            details: None,
+        });
+    }
+
+    let names: Vec<&'static str> = request.operations.iter().map(TransactionOperation::name).collect();
+    let ttl_bounds = ttl_bounds(&state.db).await;
+    let steps = plan(&state.db, request.operations, &ttl_bounds).await?.map_err(|validation_errors| ApiError {
+        code: "BAD_REQUEST".to_string(),
+        message: "Validation failed".to_string(),
+        details: Some(serde_json::to_value(validation_errors).unwrap()),
+    })?;
+
+    let begin_failed = |e: sqlx::Error| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to start transaction: {}", e),
+        details: None,
+    };
+    let mut tx = state.db.pool().begin().await.map_err(begin_failed)?;
+    let mut changed = Changed::default();
+    let mut summary = TransactionSummary::default();
+    let mut results = Vec::with_capacity(steps.len());
+    for (index, step) in steps.iter().enumerate() {
+        let id = match run_step(&mut tx, step, &mut changed).await {
+            Ok(id) => id,
+            Err(e) => {
+                if let Err(e) = tx.rollback().await {
+                    tracing::warn!("Failed to roll back configuration transaction: {}", e);
+                }
+                return Err(ApiError {
+                    code: "CONFLICT".to_string(),
+                    message: format!("Operation {} ({}) failed, no changes were applied: {}", index, names[index], e),
+                    details: Some(serde_json::json!({ "index": index, "op": names[index] })),
+                });
+            }
+        };
+        let action = step.action();
+        match action {
+            ChangeAction::Create => summary.create += 1,
+            ChangeAction::Update => summary.update += 1,
+            ChangeAction::Delete => summary.delete += 1,
+        }
+        results.push(OperationResult {
+            index,
+            op: names[index],
+            action,
+            id,
+        });
+    }
+    tx.commit().await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to commit transaction: {}", e),
+        details: None,
+    })?;
+
+    tracing::info!(
+        "Configuration transaction applied: {} created, {} updated, {} deleted",
+        summary.create,
+        summary.update,
+        summary.delete
+    );
+
+    // Hot reload the affected components
+    if changed.records {
+        reload_local_records(&state.local_records).await;
+    }
+    if changed.rules {
+        if let Err(e) = state.rewrite_engine.reload_rules().await {
+            tracing::warn!("Failed to reload rewrite rules: {}", e);
+        }
+    }
+    if changed.upstreams {
+        if let Err(e) = state.upstream_manager.reload_from_db(&state.db).await {
+            tracing::warn!("Failed to reload upstream servers: {}", e);
+        }
+    }
+    if let Some(rules) = changed.protocol_rules {
+        state.proxy_manager.protocol_policy().set_rules(rules);
+    }
+    if !changed.names.is_empty() {
+        state.cache.purge_names(changed.names).await;
+    }
+
+    Ok(Json(TransactionResponse {
+        applied: true,
+        summary,
+        results,
+    }))
+}
+
+/// Build the configuration transactions API router
+pub fn transactions_router(state: TransactionsState) -> axum::Router {
+    use axum::routing::post;
+
+    axum::Router::new()
+        .route("/", post(run_transaction))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn parse(json: &str) -> Vec<TransactionOperation> {
+        serde_json::from_str::<TransactionRequest>(json).unwrap().operations
+    }
+
+    #[test]
+    fn test_parse_operations() {
+        let operations = parse(
+            r#"{"operations": [
+                {"op": "create_upstream", "name": "Corp", "address": "10.0.0.53:853", "protocol": "dot"},
+                {"op": "update_record", "id": 7, "ttl": 60},
+                {"op": "delete_rewrite_rule", "id": 3},
+                {"op": "set_protocol_rules", "rules": []}
+            ]}"#,
+        );
+        let names: Vec<_> = operations.iter().map(TransactionOperation::name).collect();
+        assert_eq!(names, ["create_upstream", "update_record", "delete_rewrite_rule", "set_protocol_rules"]);
+        match &operations[1] {
+            TransactionOperation::UpdateRecord { id, changes } => {
+                assert_eq!(*id, 7);
+                assert_eq!(changes.ttl, Some(60));
+            }
+            other => panic!("unexpected operation {:?}", other),
+        }
+
+        assert!(serde_json::from_str::<TransactionRequest>(r#"{"operations": [{"op": "drop_table"}]}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_all_or_nothing() {
+        let dir = tempdir().unwrap();
+        let db_url = format!("sqlite:{}?mode=rwc", dir.path().join("test.db").display());
+        let db = Database::new(&db_url).await.unwrap();
+        let bounds = TtlBounds::default();
+
+        // Validation reports every failing operation by position
+        let operations = parse(
+            r#"{"operations": [
+                {"op": "create_record", "name": "a.example", "record_type": "A", "value": "not-an-ip"},
+                {"op": "delete_upstream", "id": 9999}
+            ]}"#,
+        );
+        let errors = plan(&db, operations, &bounds).await.unwrap().unwrap_err().errors;
+        let fields: Vec<_> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["operations[0].value", "operations[1].id"]);
+
+        // A step failing mid-way leaves nothing behind once rolled back
+        let operations = parse(
+            r#"{"operations": [
+                {"op": "create_record", "name": "a.example", "record_type": "A", "value": "10.0.0.1"},
+                {"op": "create_upstream", "name": "Corp", "address": "10.0.0.53:853", "protocol": "dot"}
+            ]}"#,
+        );
+        let mut steps = plan(&db, operations, &bounds).await.unwrap().unwrap();
+        let records = db.dns_records();
+        let gone = records.create(CreateDnsRecord {
+            name: "gone.example".to_string(),
+            record_type: "A".to_string(),
+            value: "10.0.0.2".to_string(),
+            ttl: 300,
+            priority: 0,
+            enabled: true,
+            tenant_id: None,
+            description: None,
+            tags: Default::default(),
+            expires_at: None,
+        }).await.unwrap();
+        records.delete(gone.id).await.unwrap();
+        steps.push(Step::DeleteRecord(gone));
+
+        let mut tx = db.pool().begin().await.unwrap();
+        let mut changed = Changed::default();
+        assert!(run_step(&mut tx, &steps[0], &mut changed).await.unwrap().is_some());
+        assert!(run_step(&mut tx, &steps[1], &mut changed).await.unwrap().is_some());
+        assert!(run_step(&mut tx, &steps[2], &mut changed).await.is_err());
+        tx.rollback().await.unwrap();
+
+        assert!(db.dns_records().list().await.unwrap().is_empty());
+        assert!(db.upstream_servers().list().await.unwrap().iter().all(|s| s.name != "Corp"));
+    }
+}
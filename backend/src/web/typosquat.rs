@@ -0,0 +1,272 @@
+//! Typo-squatting protection API module
+//!
+//! Manage the reference domains protected against lookalikes, switch
+//! between flag and block mode, and review recorded detections.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{Database, TyposquatDomain, TyposquatEvent};
+use crate::dns::{
+    parse_domain_list, Lookalike, TyposquatGuard, TyposquatSettings, CONFIG_KEY_TYPOSQUAT_BLOCK,
+    CONFIG_KEY_TYPOSQUAT_ENABLED, CONFIG_KEY_TYPOSQUAT_MAX_DISTANCE, MAX_TYPOSQUAT_DISTANCE,
+};
+use crate::web::{bad_request, internal_error, not_found, ApiError};
+
+/// Application state for typo-squatting API
+#[derive(Clone)]
+pub struct TyposquatState {
+    pub db: Arc<Database>,
+    pub guard: Arc<TyposquatGuard>,
+}
+
+/// Update settings request
+#[derive(Debug, Deserialize)]
+pub struct UpdateTyposquatSettingsRequest {
+    pub enabled: Option<bool>,
+    pub block: Option<bool>,
+    pub max_distance: Option<usize>,
+}
+
+/// Reference domains response
+#[derive(Debug, Serialize)]
+pub struct TyposquatDomainsResponse {
+    pub data: Vec<TyposquatDomain>,
+    pub total: usize,
+}
+
+/// Add reference domains request
+#[derive(Debug, Deserialize)]
+pub struct AddTyposquatDomainsRequest {
+    pub domains: Vec<String>,
+}
+
+/// Detections query parameters
+#[derive(Debug, Deserialize)]
+pub struct EventsParams {
+    pub limit: Option<i64>,
+}
+
+/// Detections response
+#[derive(Debug, Serialize)]
+pub struct TyposquatEventsResponse {
+    pub data: Vec<TyposquatEvent>,
+    pub total: usize,
+}
+
+/// Check request
+#[derive(Debug, Deserialize)]
+pub struct CheckDomainRequest {
+    pub domain: String,
+    /// Defaults to the configured maximum distance
+    pub max_distance: Option<usize>,
+}
+
+/// Check response
+#[derive(Debug, Serialize)]
+pub struct CheckDomainResponse {
+    pub domain: String,
+    pub lookalike: Option<Lookalike>,
+}
+
+fn validate_distance(distance: usize) -> Result<(), ApiError> {
+    if (1..=MAX_TYPOSQUAT_DISTANCE).contains(&distance) {
+        Ok(())
+    } else {
+        Err(bad_request(format!("max_distance must be between 1 and {}", MAX_TYPOSQUAT_DISTANCE)))
+    }
+}
+
+/// Refresh the resolver's guard after a change
+async fn reload_guard(state: &TyposquatState) {
+    if let Err(e) = state.guard.reload().await {
+        tracing::warn!("Failed to reload typo-squatting settings: {}", e);
+    }
+}
+
+/// Get typo-squatting settings
+///
+/// GET /api/typosquat/settings
+pub async fn get_settings(
+    State(state): State<TyposquatState>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(state.guard.settings()))
+}
+
+/// Update typo-squatting settings
+///
+/// PUT /api/typosquat/settings
+pub async fn update_settings(
+    State(state): State<TyposquatState>,
+    Json(request): Json<UpdateTyposquatSettingsRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    if let Some(distance) = request.max_distance {
+        validate_distance(distance)?;
+    }
+
+    let current = state.guard.settings();
+    let settings = TyposquatSettings {
+        enabled: request.enabled.unwrap_or(current.enabled),
+        block: request.block.unwrap_or(current.block),
+        max_distance: request.max_distance.unwrap_or(current.max_distance),
+    };
+
+    let repo = state.db.system_config();
+    let bool_str = |b: bool| if b { "true" } else { "false" };
+    let saves = [
+        (CONFIG_KEY_TYPOSQUAT_ENABLED, bool_str(settings.enabled).to_string()),
+        (CONFIG_KEY_TYPOSQUAT_BLOCK, bool_str(settings.block).to_string()),
+        (CONFIG_KEY_TYPOSQUAT_MAX_DISTANCE, settings.max_distance.to_string()),
+    ];
+    for (key, value) in saves {
+        repo.set(key, &value)
+            .await
+            .map_err(|e| internal_error("Failed to save typo-squatting settings", e))?;
+    }
+
+    state.guard.set_settings(settings);
+
+    Ok(Json(settings))
+}
+
+/// List reference domains
+///
+/// GET /api/typosquat/domains
+pub async fn list_domains(
+    State(state): State<TyposquatState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let domains = state
+        .db
+        .typosquat()
+        .list_domains()
+        .await
+        .map_err(|e| internal_error("Failed to list reference domains", e))?;
+
+    Ok(Json(TyposquatDomainsResponse {
+        total: domains.len(),
+        data: domains,
+    }))
+}
+
+/// Add reference domains
+///
+/// POST /api/typosquat/domains
+pub async fn add_domains(
+    State(state): State<TyposquatState>,
+    Json(request): Json<AddTyposquatDomainsRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let domains = parse_domain_list(&request.domains.join("\n"));
+    if domains.is_empty() {
+        return Err(bad_request("No valid domains given"));
+    }
+
+    let added = state
+        .db
+        .typosquat()
+        .add_domains(&domains)
+        .await
+        .map_err(|e| internal_error("Failed to add reference domains", e))?;
+
+    reload_guard(&state).await;
+
+    Ok((StatusCode::CREATED, Json(serde_json::json!({ "added": added }))))
+}
+
+/// Delete a reference domain
+///
+/// DELETE /api/typosquat/domains/:id
+pub async fn delete_domain(
+    State(state): State<TyposquatState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let deleted = state
+        .db
+        .typosquat()
+        .delete_domain(id)
+        .await
+        .map_err(|e| internal_error("Failed to delete reference domain", e))?;
+
+    if !deleted {
+        return Err(not_found("Reference domain", id));
+    }
+
+    reload_guard(&state).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List recent detections
+///
+/// GET /api/typosquat/events
+pub async fn list_events(
+    State(state): State<TyposquatState>,
+    Query(params): Query<EventsParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    let events = state
+        .db
+        .typosquat()
+        .list_events(limit)
+        .await
+        .map_err(|e| internal_error("Failed to list typo-squatting events", e))?;
+
+    Ok(Json(TyposquatEventsResponse {
+        total: events.len(),
+        data: events,
+    }))
+}
+
+/// Delete all detections
+///
+/// DELETE /api/typosquat/events
+pub async fn clear_events(
+    State(state): State<TyposquatState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let deleted = state
+        .db
+        .typosquat()
+        .clear_events()
+        .await
+        .map_err(|e| internal_error("Failed to delete typo-squatting events", e))?;
+
+    Ok(Json(serde_json::json!({ "deleted_count": deleted })))
+}
+
+/// Check a domain against the reference list
+///
+/// POST /api/typosquat/check
+pub async fn check_domain(
+    State(state): State<TyposquatState>,
+    Json(request): Json<CheckDomainRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let max_distance = request
+        .max_distance
+        .unwrap_or_else(|| state.guard.settings().max_distance);
+    validate_distance(max_distance)?;
+
+    let lookalike = state.guard.check(&request.domain, max_distance);
+    Ok(Json(CheckDomainResponse {
+        domain: request.domain,
+        lookalike,
+    }))
+}
+
+/// Build the typo-squatting API router
+pub fn typosquat_router(state: TyposquatState) -> axum::Router {
+    use axum::routing::{delete, get, post};
+
+    axum::Router::new()
+        .route("/settings", get(get_settings).put(update_settings))
+        .route("/domains", get(list_domains).post(add_domains))
+        .route("/domains/:id", delete(delete_domain))
+        .route("/events", get(list_events).delete(clear_events))
+        .route("/check", post(check_domain))
+        .with_state(state)
+}
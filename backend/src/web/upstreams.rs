@@ -6,18 +6,25 @@
 //!
 //! - 4.4: Provide upstream server configuration functionality
 
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 
 use axum::{
     extract::{Path, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
     Json,
 };
 use serde::{Deserialize, Serialize};
 
 use crate::db::{CreateUpstreamServer, Database, UpdateUpstreamServer, UpstreamServer};
-use crate::dns::proxy::UpstreamManager;
+use crate::dns::proxy::{
+    doh_stats, upstream_traffic, DohHttpVersion, DohMethod, DohStats, ProtocolRule, ProxyManager,
+    TrafficStats, UpstreamCapabilities, UpstreamManager, UpstreamProtocol, UpstreamStats,
+    CONFIG_KEY_UPSTREAM_PROTOCOL_RULES,
+};
+use crate::dns::{listener_protocol, name_to_ascii, reaches_listener, upstream_target, validate_interface};
+use crate::web::etag::{check_if_match, etag_header, modified_error};
 use crate::web::ApiError;
 
 /// Application state for upstream servers API
@@ -25,10 +32,11 @@ use crate::web::ApiError;
 pub struct UpstreamsState {
     pub db: Arc<Database>,
     pub upstream_manager: Arc<UpstreamManager>,
+    pub proxy_manager: Arc<ProxyManager>,
 }
 
 /// Valid protocol types
-const VALID_PROTOCOLS: &[&str] = &["udp", "dot", "doh", "doq", "doh3"];
+const VALID_PROTOCOLS: &[&str] = &["udp", "tcp", "dot", "doh", "doq", "doh3"];
 
 /// Validation error details
 #[derive(Debug, Serialize)]
@@ -52,6 +60,24 @@ pub struct CreateUpstreamServerRequest {
     pub timeout: i32,
     #[serde(default = "default_enabled")]
     pub enabled: bool,
+    /// Local source IP for outbound queries
+    #[serde(default)]
+    pub source_ip: Option<String>,
+    /// Local interface for outbound queries
+    #[serde(default)]
+    pub source_interface: Option<String>,
+    /// TLS server name (SNI) when it differs from the address host
+    #[serde(default)]
+    pub tls_server_name: Option<String>,
+    /// Verify the server certificate; defaults to on for DoT and DoH
+    #[serde(default)]
+    pub verify_hostname: Option<bool>,
+    /// DoH request method: post (default) or get
+    #[serde(default)]
+    pub doh_method: Option<String>,
+    /// DoH HTTP version: auto (default), http1 or http2
+    #[serde(default)]
+    pub doh_http_version: Option<String>,
 }
 
 fn default_timeout() -> i32 {
@@ -70,18 +96,44 @@ pub struct UpdateUpstreamServerRequest {
     pub protocol: Option<String>,
     pub timeout: Option<i32>,
     pub enabled: Option<bool>,
+    /// Empty string clears the source IP
+    pub source_ip: Option<String>,
+    /// Empty string clears the source interface
+    pub source_interface: Option<String>,
+    /// Empty string clears the TLS server name
+    pub tls_server_name: Option<String>,
+    pub verify_hostname: Option<bool>,
+    /// Empty string resets the DoH method to post
+    pub doh_method: Option<String>,
+    /// Empty string resets the DoH HTTP version to auto
+    pub doh_http_version: Option<String>,
+}
+
+/// Upstream server with its detected capability profile
+#[derive(Debug, Serialize)]
+pub struct UpstreamServerView {
+    #[serde(flatten)]
+    pub server: UpstreamServer,
+    pub capabilities: Option<UpstreamCapabilities>,
+}
+
+impl From<UpstreamServer> for UpstreamServerView {
+    fn from(server: UpstreamServer) -> Self {
+        let capabilities = UpstreamCapabilities::from_json(server.capabilities.as_deref());
+        Self { server, capabilities }
+    }
 }
 
 /// API response wrapper for single server
 #[derive(Debug, Serialize)]
 pub struct UpstreamServerResponse {
-    pub data: UpstreamServer,
+    pub data: UpstreamServerView,
 }
 
 /// API response wrapper for multiple servers
 #[derive(Debug, Serialize)]
 pub struct UpstreamServersListResponse {
-    pub data: Vec<UpstreamServer>,
+    pub data: Vec<UpstreamServerView>,
     pub total: i64,
     pub page: i64,
     pub page_size: i64,
@@ -112,6 +164,7 @@ pub struct ServerStatus {
     pub address: String,
     pub protocol: String,
     pub enabled: bool,
+    pub drained: bool,
     pub healthy: bool,
     pub queries: u64,
     pub successes: u64,
@@ -120,6 +173,12 @@ pub struct ServerStatus {
     pub avg_response_time_ms: u64,
     pub suspended: bool,
     pub suspension_remaining_secs: Option<u64>,
+    /// Bytes exchanged with the server and the current rates
+    #[serde(flatten)]
+    pub traffic: TrafficStats,
+    /// Queries per DoH method and fallbacks, for DoH servers that sent any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doh: Option<DohStats>,
 }
 
 /// API response for server status
@@ -149,7 +208,7 @@ fn validate_address(address: &str, protocol: &str) -> Result<(), String> {
     }
 
     match protocol.to_lowercase().as_str() {
-        "udp" | "dot" | "doq" => {
+        "udp" | "tcp" | "dot" | "doq" => {
             // Should be host:port format or just IP
             // Basic validation - check if it looks like a valid address
             if !address.contains(':') && !address.contains('.') {
@@ -190,6 +249,177 @@ fn validate_timeout(timeout: i32) -> Result<(), String> {
     Ok(())
 }
 
+/// Validate outbound source IP (empty means unset)
+fn validate_source_ip(ip: &str) -> Result<(), String> {
+    let ip = ip.trim();
+    if !ip.is_empty() && ip.parse::<std::net::IpAddr>().is_err() {
+        return Err(format!("Invalid source IP address '{}'", ip));
+    }
+    Ok(())
+}
+
+/// Validate outbound source interface (empty means unset)
+fn validate_source_interface(interface: &str) -> Result<(), String> {
+    let interface = interface.trim();
+    if interface.is_empty() {
+        return Ok(());
+    }
+    validate_interface(interface).map_err(|e| e.to_string())
+}
+
+/// Collect source binding validation errors
+fn validate_source(
+    source_ip: Option<&str>,
+    source_interface: Option<&str>,
+    errors: &mut Vec<ValidationError>,
+) {
+    if let Some(Err(e)) = source_ip.map(validate_source_ip) {
+        errors.push(ValidationError {
+            field: "source_ip".to_string(),
+            message: e,
+        });
+    }
+    if let Some(Err(e)) = source_interface.map(validate_source_interface) {
+        errors.push(ValidationError {
+            field: "source_interface".to_string(),
+            message: e,
+        });
+    }
+}
+
+/// Validate a TLS server name (empty means unset)
+fn validate_tls_server_name(name: &str) -> Result<(), String> {
+    use rustls::pki_types::ServerName;
+
+    let name = name.trim();
+    if name.is_empty() {
+        return Ok(());
+    }
+    let ascii = name_to_ascii(name)?;
+    match ServerName::try_from(ascii) {
+        Ok(ServerName::DnsName(_)) => Ok(()),
+        _ => Err(format!("Invalid TLS server name '{}': must be a DNS name", name)),
+    }
+}
+
+/// Normalize a TLS server name for storage, keeping empty strings
+fn normalize_tls_server_name(name: String) -> String {
+    let name = name.trim();
+    name_to_ascii(name).unwrap_or_else(|_| name.to_string())
+}
+
+/// Collect TLS option errors for the effective protocol and address
+fn validate_tls(
+    protocol: &str,
+    address: &str,
+    tls_server_name: Option<&str>,
+    verify_hostname: Option<bool>,
+    errors: &mut Vec<ValidationError>,
+) {
+    let Some(protocol) = UpstreamProtocol::from_str(protocol) else {
+        return;
+    };
+    let tls_server_name = tls_server_name.map(str::trim).filter(|s| !s.is_empty());
+
+    if !protocol.is_encrypted() {
+        for (field, set) in [
+            ("tls_server_name", tls_server_name.is_some()),
+            ("verify_hostname", verify_hostname.is_some()),
+        ] {
+            if set {
+                errors.push(ValidationError {
+                    field: field.to_string(),
+                    message: format!("Only applies to encrypted protocols, not {}", protocol),
+                });
+            }
+        }
+        return;
+    }
+
+    let Some(name) = tls_server_name else {
+        return;
+    };
+    if let Err(e) = validate_tls_server_name(name) {
+        errors.push(ValidationError {
+            field: "tls_server_name".to_string(),
+            message: e,
+        });
+    } else if protocol == UpstreamProtocol::Doh && !doh_host_is_ip(address) {
+        errors.push(ValidationError {
+            field: "tls_server_name".to_string(),
+            message: "DoH upstreams only accept a TLS server name with an IP address URL (e.g., https://1.1.1.1/dns-query)".to_string(),
+        });
+    }
+}
+
+/// Collect DoH option errors for the effective protocol
+///
+/// Empty strings select the defaults and are accepted for any protocol.
+fn validate_doh(
+    protocol: &str,
+    doh_method: Option<&str>,
+    doh_http_version: Option<&str>,
+    errors: &mut Vec<ValidationError>,
+) {
+    let Some(protocol) = UpstreamProtocol::from_str(protocol) else {
+        return;
+    };
+    let doh_method = doh_method.map(str::trim).filter(|s| !s.is_empty());
+    let doh_http_version = doh_http_version.map(str::trim).filter(|s| !s.is_empty());
+
+    if protocol != UpstreamProtocol::Doh {
+        for (field, set) in [
+            ("doh_method", doh_method.is_some()),
+            ("doh_http_version", doh_http_version.is_some()),
+        ] {
+            if set {
+                errors.push(ValidationError {
+                    field: field.to_string(),
+                    message: format!("Only applies to DoH upstreams, not {}", protocol),
+                });
+            }
+        }
+        return;
+    }
+
+    if let Some(method) = doh_method.filter(|m| DohMethod::from_str(m).is_none()) {
+        errors.push(ValidationError {
+            field: "doh_method".to_string(),
+            message: format!("Invalid DoH method '{}': must be get or post", method),
+        });
+    }
+    if let Some(version) = doh_http_version.filter(|v| DohHttpVersion::from_str(v).is_none()) {
+        errors.push(ValidationError {
+            field: "doh_http_version".to_string(),
+            message: format!("Invalid HTTP version '{}': must be auto, http1 or http2", version),
+        });
+    }
+}
+
+/// Normalize a DoH method for storage, keeping empty strings
+fn normalize_doh_method(method: String) -> String {
+    DohMethod::from_str(method.trim()).map_or_else(String::new, |m| m.as_str().to_string())
+}
+
+/// Normalize a DoH HTTP version for storage, keeping empty strings
+fn normalize_doh_http_version(version: String) -> String {
+    DohHttpVersion::from_str(version.trim()).map_or_else(String::new, |v| v.as_str().to_string())
+}
+
+/// Whether a DoH address connects to an IP address
+fn doh_host_is_ip(address: &str) -> bool {
+    let rest = address
+        .strip_prefix("https://")
+        .or_else(|| address.strip_prefix("http://"))
+        .unwrap_or(address);
+    let authority = rest.split('/').next().unwrap_or_default();
+    let host = match authority.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    host.parse::<std::net::IpAddr>().is_ok()
+}
+
 impl CreateUpstreamServerRequest {
     /// Validate the create request
     pub fn validate(&self) -> Result<(), ValidationErrors> {
@@ -231,6 +461,21 @@ impl CreateUpstreamServerRequest {
             });
         }
 
+        validate_source(self.source_ip.as_deref(), self.source_interface.as_deref(), &mut errors);
+        validate_tls(
+            &self.protocol,
+            &self.address,
+            self.tls_server_name.as_deref(),
+            self.verify_hostname,
+            &mut errors,
+        );
+        validate_doh(
+            &self.protocol,
+            self.doh_method.as_deref(),
+            self.doh_http_version.as_deref(),
+            &mut errors,
+        );
+
         if errors.is_empty() {
             Ok(())
         } else {
@@ -246,6 +491,12 @@ impl CreateUpstreamServerRequest {
             protocol: self.protocol.to_lowercase(),
             timeout: self.timeout,
             enabled: self.enabled,
+            source_ip: self.source_ip.map(|s| s.trim().to_string()),
+            source_interface: self.source_interface.map(|s| s.trim().to_string()),
+            tls_server_name: self.tls_server_name.map(normalize_tls_server_name),
+            verify_hostname: self.verify_hostname,
+            doh_method: self.doh_method.map(normalize_doh_method),
+            doh_http_version: self.doh_http_version.map(normalize_doh_http_version),
         }
     }
 }
@@ -295,6 +546,29 @@ impl UpdateUpstreamServerRequest {
             }
         }
 
+        validate_source(self.source_ip.as_deref(), self.source_interface.as_deref(), &mut errors);
+
+        // TLS options are checked against the server as it will be after the update
+        let tls_server_name = self.tls_server_name.as_deref().or(existing.tls_server_name.as_deref());
+        let verify_hostname = self.verify_hostname.or(existing.verify_hostname);
+        if tls_server_name.is_some() || verify_hostname.is_some() {
+            validate_tls(
+                self.protocol.as_deref().unwrap_or(&existing.protocol),
+                self.address.as_deref().unwrap_or(&existing.address),
+                tls_server_name,
+                verify_hostname,
+                &mut errors,
+            );
+        }
+
+        // Likewise the DoH options, so a protocol change cannot strand them
+        validate_doh(
+            self.protocol.as_deref().unwrap_or(&existing.protocol),
+            self.doh_method.as_deref().or(existing.doh_method.as_deref()),
+            self.doh_http_version.as_deref().or(existing.doh_http_version.as_deref()),
+            &mut errors,
+        );
+
         if errors.is_empty() {
             Ok(())
         } else {
@@ -310,10 +584,68 @@ impl UpdateUpstreamServerRequest {
             protocol: self.protocol.map(|p| p.to_lowercase()),
             timeout: self.timeout,
             enabled: self.enabled,
+            source_ip: self.source_ip.map(|s| s.trim().to_string()),
+            source_interface: self.source_interface.map(|s| s.trim().to_string()),
+            tls_server_name: self.tls_server_name.map(normalize_tls_server_name),
+            verify_hostname: self.verify_hostname,
+            doh_method: self.doh_method.map(normalize_doh_method),
+            doh_http_version: self.doh_http_version.map(normalize_doh_http_version),
         }
     }
 }
 
+/// Validation error for an upstream address that reaches one of our own
+/// enabled listeners
+///
+/// Forwarding to ourselves would send every query round in a loop.
+pub async fn own_listener_error(
+    db: &Database,
+    address: &str,
+    protocol: &str,
+) -> anyhow::Result<Option<ValidationErrors>> {
+    let Some(protocol) = UpstreamProtocol::from_str(protocol) else {
+        return Ok(None);
+    };
+    let (Some(target), Some(listener)) = (upstream_target(address, protocol), listener_protocol(protocol)) else {
+        return Ok(None);
+    };
+
+    let own = db
+        .server_listeners()
+        .list_enabled()
+        .await?
+        .iter()
+        .filter(|l| l.protocol == listener)
+        .filter_map(|l| Some(SocketAddr::new(l.bind_address.parse::<IpAddr>().ok()?, u16::try_from(l.port).ok()?)))
+        .find(|bind| reaches_listener(target, *bind));
+
+    Ok(own.map(|bind| ValidationErrors {
+        errors: vec![ValidationError {
+            field: "address".to_string(),
+            message: format!(
+                "Address reaches this server's own {} listener on {}; forwarding to it would loop",
+                listener, bind
+            ),
+        }],
+    }))
+}
+
+async fn check_not_self(db: &Database, address: &str, protocol: &str) -> Result<(), ApiError> {
+    let error = own_listener_error(db, address, protocol).await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to get listeners: {}", e),
+        details: None,
+    })?;
+    match error {
+        Some(validation_errors) => Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: "Validation failed".to_string(),
+            details: Some(serde_json::to_value(validation_errors).unwrap()),
+        }),
+        None => Ok(()),
+    }
+}
+
 /// List all upstream servers with pagination
 ///
 /// GET /api/upstreams?page=1&page_size=20
@@ -337,7 +669,7 @@ pub async fn list_upstreams(
         total,
         page,
         page_size,
-        data: servers,
+        data: servers.into_iter().map(UpstreamServerView::from).collect(),
     }))
 }
 
@@ -357,7 +689,7 @@ pub async fn get_upstream(
     })?;
 
     match server {
-        Some(s) => Ok(Json(UpstreamServerResponse { data: s })),
+        Some(s) => Ok((etag_header(s.id, &s.updated_at), Json(UpstreamServerResponse { data: s.into() }))),
         None => Err(ApiError {
             code: "NOT_FOUND".to_string(),
             message: format!("Upstream server with id {} not found", id),
@@ -381,6 +713,7 @@ pub async fn create_upstream(
             details: Some(serde_json::to_value(validation_errors).unwrap()),
         });
     }
+    check_not_self(&state.db, &request.address, &request.protocol).await?;
 
     let repo = state.db.upstream_servers();
     let create_server = request.into_create_upstream_server();
@@ -396,7 +729,7 @@ pub async fn create_upstream(
         tracing::warn!("Failed to reload upstream servers: {}", e);
     }
 
-    Ok((StatusCode::CREATED, Json(UpstreamServerResponse { data: server })))
+    Ok((StatusCode::CREATED, Json(UpstreamServerResponse { data: server.into() })))
 }
 
 /// Update an upstream server
@@ -405,6 +738,7 @@ pub async fn create_upstream(
 pub async fn update_upstream(
     State(state): State<UpstreamsState>,
     Path(id): Path<i64>,
+    headers: HeaderMap,
     Json(request): Json<UpdateUpstreamServerRequest>,
 ) -> Result<impl IntoResponse, ApiError> {
     let repo = state.db.upstream_servers();
@@ -422,6 +756,8 @@ pub async fn update_upstream(
         details: None,
     })?;
 
+    let version = check_if_match(&state.db, &headers, existing.id, &existing.updated_at).await?;
+
     // Validate request against existing server
     if let Err(validation_errors) = request.validate(&existing) {
         return Err(ApiError {
@@ -430,10 +766,18 @@ pub async fn update_upstream(
             details: Some(serde_json::to_value(validation_errors).unwrap()),
         });
     }
+    if request.address.is_some() || request.protocol.is_some() {
+        check_not_self(
+            &state.db,
+            request.address.as_deref().unwrap_or(&existing.address),
+            request.protocol.as_deref().unwrap_or(&existing.protocol),
+        )
+        .await?;
+    }
 
     let update_server = request.into_update_upstream_server();
 
-    let server = repo.update(id, update_server).await.map_err(|e| ApiError {
+    let server = repo.update_at_version(id, update_server, version).await.map_err(|e| ApiError {
         code: "INTERNAL_ERROR".to_string(),
         message: format!("Failed to update upstream server: {}", e),
         details: None,
@@ -445,7 +789,8 @@ pub async fn update_upstream(
     }
 
     match server {
-        Some(s) => Ok(Json(UpstreamServerResponse { data: s })),
+        Some(s) => Ok((etag_header(s.id, &s.updated_at), Json(UpstreamServerResponse { data: s.into() }))),
+        None if version.is_some() => Err(modified_error(None)),
         None => Err(ApiError {
             code: "NOT_FOUND".to_string(),
             message: format!("Upstream server with id {} not found", id),
@@ -460,10 +805,24 @@ pub async fn update_upstream(
 pub async fn delete_upstream(
     State(state): State<UpstreamsState>,
     Path(id): Path<i64>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, ApiError> {
     let repo = state.db.upstream_servers();
 
-    let deleted = repo.delete(id).await.map_err(|e| ApiError {
+    let existing = repo.get_by_id(id).await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to get upstream server: {}", e),
+        details: None,
+    })?;
+    let existing = existing.ok_or_else(|| ApiError {
+        code: "NOT_FOUND".to_string(),
+        message: format!("Upstream server with id {} not found", id),
+        details: None,
+    })?;
+
+    let version = check_if_match(&state.db, &headers, existing.id, &existing.updated_at).await?;
+
+    let deleted = repo.delete_at_version(id, version).await.map_err(|e| ApiError {
         code: "INTERNAL_ERROR".to_string(),
         message: format!("Failed to delete upstream server: {}", e),
         details: None,
@@ -474,7 +833,11 @@ pub async fn delete_upstream(
         if let Err(e) = state.upstream_manager.reload_from_db(&state.db).await {
             tracing::warn!("Failed to reload upstream servers: {}", e);
         }
+        upstream_traffic().remove(id);
+        doh_stats().remove(id);
         Ok(StatusCode::NO_CONTENT)
+    } else if version.is_some() {
+        Err(modified_error(None))
     } else {
         Err(ApiError {
             code: "NOT_FOUND".to_string(),
@@ -484,6 +847,93 @@ pub async fn delete_upstream(
     }
 }
 
+/// Request body for reordering upstream servers
+#[derive(Debug, Deserialize)]
+pub struct ReorderUpstreamsRequest {
+    /// Every upstream server ID, first to last
+    pub ids: Vec<i64>,
+}
+
+/// Check that `ids` lists every ID in `existing` exactly once
+fn validate_order(ids: &[i64], existing: &[i64]) -> Result<(), ValidationErrors> {
+    let mut seen = std::collections::HashSet::new();
+    let mut errors = Vec::new();
+    for id in ids {
+        if !existing.contains(id) {
+            errors.push(ValidationError {
+                field: "ids".to_string(),
+                message: format!("Upstream server with id {} not found", id),
+            });
+        } else if !seen.insert(*id) {
+            errors.push(ValidationError {
+                field: "ids".to_string(),
+                message: format!("Upstream server {} is listed more than once", id),
+            });
+        }
+    }
+    for id in existing {
+        if !ids.contains(id) {
+            errors.push(ValidationError {
+                field: "ids".to_string(),
+                message: format!("Upstream server {} is missing from the order", id),
+            });
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationErrors { errors })
+    }
+}
+
+/// Set the order of the upstream servers
+///
+/// PUT /api/upstreams/order
+///
+/// The body lists every upstream ID; round-robin and failover follow it.
+pub async fn reorder_upstreams(
+    State(state): State<UpstreamsState>,
+    Json(request): Json<ReorderUpstreamsRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let repo = state.db.upstream_servers();
+
+    let existing = repo.list().await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to list upstream servers: {}", e),
+        details: None,
+    })?;
+
+    let existing_ids: Vec<i64> = existing.iter().map(|s| s.id).collect();
+    if let Err(validation_errors) = validate_order(&request.ids, &existing_ids) {
+        return Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: "Validation failed".to_string(),
+            details: Some(serde_json::to_value(validation_errors).unwrap()),
+        });
+    }
+
+    repo.reorder(&request.ids).await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to reorder upstream servers: {}", e),
+        details: None,
+    })?;
+
+    // Reload upstream servers in the manager
+    if let Err(e) = state.upstream_manager.reload_from_db(&state.db).await {
+        tracing::warn!("Failed to reload upstream servers: {}", e);
+    }
+
+    let servers = repo.list().await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to list upstream servers: {}", e),
+        details: None,
+    })?;
+    let data: Vec<UpstreamServerView> = servers.into_iter().map(UpstreamServerView::from).collect();
+
+    Ok(Json(serde_json::json!({ "data": data })))
+}
+
 /// Get upstream server status
 ///
 /// GET /api/upstreams/status
@@ -510,6 +960,7 @@ pub async fn get_status(
                 address: s.address,
                 protocol: s.protocol,
                 enabled: s.enabled,
+                drained: s.drained,
                 healthy: server_stats.map(|st| st.is_healthy()).unwrap_or(s.enabled),
                 queries: server_stats.map(|st| st.queries).unwrap_or(0),
                 successes: server_stats.map(|st| st.successes).unwrap_or(0),
@@ -518,6 +969,8 @@ pub async fn get_status(
                 avg_response_time_ms: server_stats.map(|st| st.avg_response_time_ms()).unwrap_or(0),
                 suspended: server_stats.map(|st| st.is_suspended()).unwrap_or(false),
                 suspension_remaining_secs: server_stats.and_then(|st| st.suspension_remaining_secs()),
+                traffic: upstream_traffic().stats(s.id),
+                doh: doh_stats().stats(s.id),
             }
         })
         .collect();
@@ -525,6 +978,73 @@ pub async fn get_status(
     Ok(Json(ServerStatusResponse { data: status }))
 }
 
+/// Get upstream server metrics in the Prometheus text format
+///
+/// GET /api/upstreams/metrics
+pub async fn get_metrics(
+    State(state): State<UpstreamsState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let servers = state.db.upstream_servers().list().await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to list upstream servers: {}", e),
+        details: None,
+    })?;
+
+    let stats = state.upstream_manager.get_all_stats().await;
+
+    let rows: Vec<(String, Option<&UpstreamStats>, TrafficStats, bool)> = servers
+        .iter()
+        .map(|s| {
+            let labels = format!(
+                "id=\"{}\",name=\"{}\",protocol=\"{}\"",
+                s.id,
+                escape_label(&s.name),
+                escape_label(&s.protocol)
+            );
+            (labels, stats.get(&s.id), upstream_traffic().stats(s.id), s.enabled)
+        })
+        .collect();
+
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: &dyn Fn(Option<&UpstreamStats>, &TrafficStats, bool) -> f64| {
+        out.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+        for (labels, server_stats, traffic, enabled) in &rows {
+            out.push_str(&format!("{}{{{}}} {}\n", name, labels, value(*server_stats, traffic, *enabled)));
+        }
+    };
+    metric("fluxdns_upstream_queries_total", "counter", "Queries sent to the upstream", &|st, _, _| {
+        st.map_or(0, |st| st.queries) as f64
+    });
+    metric("fluxdns_upstream_successes_total", "counter", "Queries the upstream answered", &|st, _, _| {
+        st.map_or(0, |st| st.successes) as f64
+    });
+    metric("fluxdns_upstream_failures_total", "counter", "Queries that failed at the upstream", &|st, _, _| {
+        st.map_or(0, |st| st.failures) as f64
+    });
+    metric("fluxdns_upstream_response_time_seconds", "gauge", "Average upstream response time", &|st, _, _| {
+        st.map_or(0, |st| st.avg_response_time_ms()) as f64 / 1000.0
+    });
+    metric("fluxdns_upstream_healthy", "gauge", "Whether the upstream is healthy", &|st, _, enabled| {
+        if st.map_or(enabled, |st| st.is_healthy()) { 1.0 } else { 0.0 }
+    });
+    metric("fluxdns_upstream_sent_bytes_total", "counter", "DNS message bytes sent to the upstream", &|_, t, _| {
+        t.bytes_sent as f64
+    });
+    metric("fluxdns_upstream_received_bytes_total", "counter", "DNS message bytes received from the upstream", &|_, t, _| {
+        t.bytes_received as f64
+    });
+
+    Ok(([(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")], out))
+}
+
+/// Escape a Prometheus label value
+pub(crate) fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
 /// Reset upstream server health status
 ///
 /// POST /api/upstreams/:id/reset-health
@@ -559,17 +1079,233 @@ pub async fn reset_health(
     })))
 }
 
+/// Take an upstream server out of production for maintenance
+///
+/// POST /api/upstreams/:id/drain
+///
+/// The server stays configured and is still health-checked, but no
+/// production queries are sent to it until it is undrained.
+pub async fn drain_upstream(
+    State(state): State<UpstreamsState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    set_drained(&state, id, true).await
+}
+
+/// Return a drained upstream server to service
+///
+/// POST /api/upstreams/:id/undrain
+pub async fn undrain_upstream(
+    State(state): State<UpstreamsState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    set_drained(&state, id, false).await
+}
+
+async fn set_drained(
+    state: &UpstreamsState,
+    id: i64,
+    drained: bool,
+) -> Result<impl IntoResponse, ApiError> {
+    let server = state
+        .db
+        .upstream_servers()
+        .set_drained(id, drained)
+        .await
+        .map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to update upstream server: {}", e),
+            details: None,
+        })?
+        .ok_or_else(|| ApiError {
+            code: "NOT_FOUND".to_string(),
+            message: format!("Upstream server with id {} not found", id),
+            details: None,
+        })?;
+
+    if let Err(e) = state.upstream_manager.reload_from_db(&state.db).await {
+        tracing::warn!("Failed to reload upstream servers: {}", e);
+    }
+    tracing::info!(
+        "Upstream server {} {}",
+        server.name,
+        if drained { "drained for maintenance" } else { "returned to service" }
+    );
+
+    Ok((etag_header(server.id, &server.updated_at), Json(UpstreamServerResponse { data: server.into() })))
+}
+
+/// Probe an upstream server's capabilities
+///
+/// POST /api/upstreams/:id/probe
+///
+/// Detects EDNS support and payload size, cookies, TCP fallback and DNSSEC
+/// answers, stores them on the server and applies them to the proxy
+/// clients. A failed probe is stored too and leaves the clients on their
+/// defaults.
+pub async fn probe_upstream(
+    State(state): State<UpstreamsState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let repo = state.db.upstream_servers();
+    let server = repo.get_by_id(id).await.map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to get upstream server: {}", e),
+        details: None,
+    })?;
+
+    let server = server.ok_or_else(|| ApiError {
+        code: "NOT_FOUND".to_string(),
+        message: format!("Upstream server with id {} not found", id),
+        details: None,
+    })?;
+
+    if UpstreamProtocol::from_str(&server.protocol) != Some(UpstreamProtocol::Udp) {
+        return Err(ApiError {
+            code: "BAD_REQUEST".to_string(),
+            message: "Capability probing only applies to UDP upstreams".to_string(),
+            details: None,
+        });
+    }
+
+    let capabilities = state
+        .upstream_manager
+        .probe_capabilities(&state.db, &server)
+        .await
+        .map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to probe upstream server: {}", e),
+            details: None,
+        })?;
+
+    Ok(Json(serde_json::json!({ "data": capabilities })))
+}
+
+/// Protocol rule with the number of enabled upstreams it can use
+#[derive(Debug, Serialize)]
+pub struct ProtocolRuleView {
+    #[serde(flatten)]
+    pub rule: ProtocolRule,
+    pub enabled_upstreams: usize,
+    /// Set when no enabled upstream speaks an allowed protocol
+    pub warning: Option<String>,
+}
+
+/// Replace protocol rules request
+#[derive(Debug, Deserialize)]
+pub struct UpdateProtocolRulesRequest {
+    pub rules: Vec<ProtocolRule>,
+}
+
+async fn protocol_rule_views(state: &UpstreamsState) -> Vec<ProtocolRuleView> {
+    let servers = state.upstream_manager.get_servers().await;
+    state
+        .proxy_manager
+        .protocol_policy()
+        .rules()
+        .into_iter()
+        .map(|rule| {
+            let enabled_upstreams = servers.iter().filter(|s| rule.allows(s)).count();
+            let warning = (enabled_upstreams == 0).then(|| {
+                format!(
+                    "No enabled {} upstream: queries for {} will fail",
+                    rule.protocol_list(),
+                    rule.pattern
+                )
+            });
+            ProtocolRuleView { rule, enabled_upstreams, warning }
+        })
+        .collect()
+}
+
+/// List per-domain protocol rules
+///
+/// GET /api/upstreams/protocol-rules
+pub async fn get_protocol_rules(
+    State(state): State<UpstreamsState>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(serde_json::json!({ "data": protocol_rule_views(&state).await })))
+}
+
+/// Trim and validate protocol rules, with errors on fields such as `rules[2]`
+pub(crate) fn validate_protocol_rules(rules: Vec<ProtocolRule>) -> Result<Vec<ProtocolRule>, ValidationErrors> {
+    let mut errors = Vec::new();
+    let mut valid = Vec::with_capacity(rules.len());
+    for (i, mut rule) in rules.into_iter().enumerate() {
+        rule.pattern = rule.pattern.trim().to_string();
+        let name = rule.pattern.strip_prefix("*.").unwrap_or(&rule.pattern);
+        let checked = rule
+            .validate()
+            .map_err(|e| e.to_string())
+            .and_then(|_| name_to_ascii(name).map(|_| ()));
+        match checked {
+            Ok(()) => valid.push(rule),
+            Err(message) => errors.push(ValidationError {
+                field: format!("rules[{}]", i),
+                message,
+            }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(valid)
+    } else {
+        Err(ValidationErrors { errors })
+    }
+}
+
+/// Replace the per-domain protocol rules
+///
+/// PUT /api/upstreams/protocol-rules
+///
+/// Rules whose protocols no enabled upstream speaks are accepted, so they
+/// can be set up before the upstream, but come back with a warning.
+pub async fn update_protocol_rules(
+    State(state): State<UpstreamsState>,
+    Json(request): Json<UpdateProtocolRulesRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let rules = validate_protocol_rules(request.rules).map_err(|validation_errors| ApiError {
+        code: "BAD_REQUEST".to_string(),
+        message: "Validation failed".to_string(),
+        details: Some(serde_json::to_value(validation_errors).unwrap()),
+    })?;
+
+    let stored = serde_json::to_string(&rules).map_err(|e| ApiError {
+        code: "INTERNAL_ERROR".to_string(),
+        message: format!("Failed to encode protocol rules: {}", e),
+        details: None,
+    })?;
+    state
+        .db
+        .system_config()
+        .set(CONFIG_KEY_UPSTREAM_PROTOCOL_RULES, &stored)
+        .await
+        .map_err(|e| ApiError {
+            code: "INTERNAL_ERROR".to_string(),
+            message: format!("Failed to save protocol rules: {}", e),
+            details: None,
+        })?;
+    state.proxy_manager.protocol_policy().set_rules(rules);
+
+    Ok(Json(serde_json::json!({ "data": protocol_rule_views(&state).await })))
+}
+
 /// Build the upstream servers API router
 pub fn upstreams_router(state: UpstreamsState) -> axum::Router {
-    use axum::routing::{get, post};
+    use axum::routing::{get, post, put};
 
     // Note: More specific routes must come before parameterized routes
     // /status must be before /:id to avoid being matched as an id
     axum::Router::new()
         .route("/status", get(get_status))
+        .route("/metrics", get(get_metrics))
+        .route("/protocol-rules", get(get_protocol_rules).put(update_protocol_rules))
+        .route("/order", put(reorder_upstreams))
         .route("/", get(list_upstreams).post(create_upstream))
         .route("/:id", get(get_upstream).put(update_upstream).delete(delete_upstream))
         .route("/:id/reset-health", post(reset_health))
+        .route("/:id/probe", post(probe_upstream))
+        .route("/:id/drain", post(drain_upstream))
+        .route("/:id/undrain", post(undrain_upstream))
         .with_state(state)
 }
 
@@ -594,6 +1330,7 @@ mod tests {
     fn test_validate_protocol_valid() {
         assert!(validate_protocol("udp").is_ok());
         assert!(validate_protocol("UDP").is_ok());
+        assert!(validate_protocol("tcp").is_ok());
         assert!(validate_protocol("dot").is_ok());
         assert!(validate_protocol("doh").is_ok());
         assert!(validate_protocol("doq").is_ok());
@@ -634,6 +1371,12 @@ mod tests {
             protocol: "udp".to_string(),
             timeout: 5000,
             enabled: true,
+            source_ip: None,
+            source_interface: None,
+            tls_server_name: None,
+            verify_hostname: None,
+            doh_method: None,
+            doh_http_version: None,
         };
         assert!(valid_request.validate().is_ok());
 
@@ -643,9 +1386,16 @@ mod tests {
             protocol: "invalid".to_string(),
             timeout: 50,
             enabled: true,
+            source_ip: Some("not-an-ip".to_string()),
+            source_interface: None,
+            tls_server_name: None,
+            verify_hostname: None,
+            doh_method: None,
+            doh_http_version: None,
         };
         let result = invalid_request.validate();
         assert!(result.is_err());
+        assert!(result.unwrap_err().errors.iter().any(|e| e.field == "source_ip"));
     }
 
     #[test]
@@ -656,8 +1406,108 @@ mod tests {
             protocol: "UDP".to_string(),
             timeout: 5000,
             enabled: true,
+            source_ip: None,
+            source_interface: None,
+            tls_server_name: None,
+            verify_hostname: None,
+            doh_method: None,
+            doh_http_version: None,
         };
         let create_server = request.into_create_upstream_server();
         assert_eq!(create_server.protocol, "udp");
     }
+
+    #[test]
+    fn test_tls_validation() {
+        let request = |protocol: &str, address: &str, name: Option<&str>, verify: Option<bool>| {
+            CreateUpstreamServerRequest {
+                name: "Test".to_string(),
+                address: address.to_string(),
+                protocol: protocol.to_string(),
+                timeout: 5000,
+                enabled: true,
+                source_ip: None,
+                source_interface: None,
+                tls_server_name: name.map(str::to_string),
+                verify_hostname: verify,
+                doh_method: None,
+                doh_http_version: None,
+            }
+        };
+        let tls_errors = |r: CreateUpstreamServerRequest| -> Vec<String> {
+            r.validate()
+                .err()
+                .map(|e| e.errors.into_iter().map(|e| e.field).collect())
+                .unwrap_or_default()
+        };
+
+        assert!(tls_errors(request("dot", "1.1.1.1:853", Some("one.one.one.one"), Some(true))).is_empty());
+        assert!(tls_errors(request("doq", "94.140.14.14", Some("dns.adguard-dns.com"), None)).is_empty());
+        assert!(tls_errors(request("doh", "https://1.1.1.1/dns-query", Some("cloudflare-dns.com"), None)).is_empty());
+
+        assert_eq!(tls_errors(request("dot", "1.1.1.1:853", Some("1.1.1.1"), None)), vec!["tls_server_name"]);
+        assert_eq!(tls_errors(request("dot", "1.1.1.1:853", Some("bad name"), None)), vec!["tls_server_name"]);
+        assert_eq!(
+            tls_errors(request("udp", "1.1.1.1:53", Some("one.one.one.one"), Some(false))),
+            vec!["tls_server_name", "verify_hostname"]
+        );
+        // DoH can only pin the name to an IP address URL
+        assert_eq!(
+            tls_errors(request("doh", "https://dns.google/dns-query", Some("dns.google"), None)),
+            vec!["tls_server_name"]
+        );
+
+        let create = request("dot", "1.1.1.1:853", Some(" One.One.One.One "), None).into_create_upstream_server();
+        assert_eq!(create.tls_server_name.as_deref(), Some("one.one.one.one"));
+    }
+
+    #[test]
+    fn test_doh_option_validation() {
+        let doh_errors = |protocol: &str, method: Option<&str>, version: Option<&str>| -> Vec<String> {
+            let mut errors = Vec::new();
+            validate_doh(protocol, method, version, &mut errors);
+            errors.into_iter().map(|e| e.field).collect()
+        };
+
+        assert!(doh_errors("doh", Some("GET"), Some("http1")).is_empty());
+        assert!(doh_errors("udp", Some(""), Some("")).is_empty());
+        assert_eq!(doh_errors("doh", Some("put"), Some("http3")), vec!["doh_method", "doh_http_version"]);
+        assert_eq!(doh_errors("dot", Some("get"), None), vec!["doh_method"]);
+
+        assert_eq!(normalize_doh_method(" GET ".to_string()), "get");
+        assert_eq!(normalize_doh_http_version("h2".to_string()), "http2");
+        assert_eq!(normalize_doh_http_version(String::new()), "");
+    }
+
+    #[test]
+    fn test_doh_host_is_ip() {
+        assert!(doh_host_is_ip("https://1.1.1.1/dns-query"));
+        assert!(doh_host_is_ip("https://[2606:4700:4700::1111]:443/dns-query"));
+        assert!(doh_host_is_ip("https://8.8.8.8:8443/dns-query"));
+        assert!(!doh_host_is_ip("https://dns.google/dns-query"));
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("Cloudflare"), "Cloudflare");
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn test_validate_order() {
+        assert!(validate_order(&[3, 1, 2], &[1, 2, 3]).is_ok());
+        assert!(validate_order(&[], &[]).is_ok());
+
+        let errors = validate_order(&[3, 3, 9], &[1, 3]).unwrap_err().errors;
+        let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Upstream server 3 is listed more than once",
+                "Upstream server with id 9 not found",
+                "Upstream server 1 is missing from the order",
+            ]
+        );
+        assert!(errors.iter().all(|e| e.field == "ids"));
+    }
 }